@@ -0,0 +1,865 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::{self, Channel};
+use crate::storage_commands::{format_bytes, parse_size, VolumeKindArg};
+use crate::vm_commands::wait_for_vm_state;
+use anyhow::{Context, Result};
+use clap::Args;
+use feos_proto::container_service::{
+    container_service_client::ContainerServiceClient, ContainerConfig, ContainerInfo,
+    ContainerState, ContainerStateChangedEvent, CreateContainerRequest, DeleteContainerRequest,
+    ListContainersRequest, StartContainerRequest, StopContainerRequest,
+    StreamContainerEventsRequest,
+};
+use feos_proto::storage_service::{
+    storage_service_client::StorageServiceClient, CreateVolumeRequest, ListVolumesRequest,
+    ResizeVolumeRequest, VolumeInfo, VolumeKind,
+};
+use feos_proto::vm_service::{
+    net_config, vm_service_client::VmServiceClient, CpuConfig, CreateVmRequest, DeleteVmRequest,
+    ListVmsRequest, MemoryConfig, NetConfig, ShutdownVmRequest, StartVmRequest, VfioPciConfig,
+    VmConfig, VmInfo, VmState,
+};
+use prost::Message;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::Read;
+use std::time::Duration;
+use tokio_stream::StreamExt;
+
+const CONTAINER_CREATE_TIMEOUT: Duration = Duration::from_secs(300);
+/// The namespace of the volumes of a manifest.
+const VOLUME_NAMESPACE: &str = "default";
+
+#[derive(Args, Debug)]
+pub struct ApplyArgs {
+    #[arg(
+        short,
+        long,
+        env = "FEOS_ADDRESS",
+        help = "FeOS API address, overriding the address of the selected context"
+    )]
+    pub address: Option<String>,
+
+    #[arg(
+        short = 'f',
+        long = "filename",
+        required = true,
+        help = "Path to the YAML manifest, or '-' to read it from stdin"
+    )]
+    filename: String,
+
+    #[arg(long, help = "Print the planned changes without applying them")]
+    dry_run: bool,
+
+    #[arg(
+        long,
+        help = "Delete VMs and containers which are not part of the manifest. Volumes are never deleted"
+    )]
+    prune: bool,
+}
+
+/// A single document of a manifest. Documents are separated by `---`.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "kind")]
+enum Resource {
+    Vm(VmResource),
+    Container(ContainerResource),
+    Volume(VolumeResource),
+    Pod,
+    Network,
+}
+
+/// The resources of a manifest, by kind.
+#[derive(Debug, Default)]
+struct Manifest {
+    vms: Vec<VmResource>,
+    containers: Vec<ContainerResource>,
+    volumes: Vec<VolumeResource>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct VmResource {
+    id: String,
+    spec: VmSpec,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct VmSpec {
+    image_ref: String,
+    #[serde(default = "default_vcpus")]
+    vcpus: u32,
+    #[serde(default = "default_memory")]
+    memory: u64,
+    #[serde(default)]
+    hugepages: bool,
+    #[serde(default)]
+    pci_devices: Vec<String>,
+    #[serde(default)]
+    ignition: Option<String>,
+    #[serde(default = "default_running")]
+    running: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct ContainerResource {
+    id: String,
+    spec: ContainerSpec,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct ContainerSpec {
+    image_ref: String,
+    #[serde(default)]
+    command: Vec<String>,
+    #[serde(default)]
+    env: HashMap<String, String>,
+    #[serde(default)]
+    disk_limit: Option<String>,
+    #[serde(default = "default_running")]
+    running: bool,
+}
+
+/// A volume of the default namespace. The storage service assigns volume IDs,
+/// so volumes are identified by their name within their pool.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct VolumeResource {
+    name: String,
+    spec: VolumeSpec,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct VolumeSpec {
+    pool: String,
+    size: String,
+    #[serde(default = "default_volume_kind")]
+    kind: VolumeKindArg,
+}
+
+fn default_vcpus() -> u32 {
+    1
+}
+
+fn default_memory() -> u64 {
+    1024
+}
+
+fn default_running() -> bool {
+    true
+}
+
+fn default_volume_kind() -> VolumeKindArg {
+    VolumeKindArg::VmDisk
+}
+
+#[derive(Debug, PartialEq)]
+enum Action {
+    CreateVm(VmResource),
+    RecreateVm(VmResource),
+    StartVm(String),
+    ShutdownVm(String),
+    DeleteVm(String),
+    CreateContainer(ContainerResource),
+    RecreateContainer(ContainerResource),
+    StartContainer(String),
+    StopContainer(String),
+    DeleteContainer(String),
+    CreateVolume(VolumeResource, u64),
+    /// Grows the volume with the given ID.
+    ResizeVolume(VolumeResource, String, u64),
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::CreateVm(vm) => write!(f, "+ vm/{} (create)", vm.id),
+            Action::RecreateVm(vm) => write!(f, "~ vm/{} (recreate, spec changed)", vm.id),
+            Action::StartVm(id) => write!(f, "~ vm/{id} (start)"),
+            Action::ShutdownVm(id) => write!(f, "~ vm/{id} (shutdown)"),
+            Action::DeleteVm(id) => write!(f, "- vm/{id} (delete)"),
+            Action::CreateContainer(c) => write!(f, "+ container/{} (create)", c.id),
+            Action::RecreateContainer(c) => {
+                write!(f, "~ container/{} (recreate, spec changed)", c.id)
+            }
+            Action::StartContainer(id) => write!(f, "~ container/{id} (start)"),
+            Action::StopContainer(id) => write!(f, "~ container/{id} (stop)"),
+            Action::DeleteContainer(id) => write!(f, "- container/{id} (delete)"),
+            Action::CreateVolume(v, size_bytes) => write!(
+                f,
+                "+ volume/{}/{} (create, {})",
+                v.spec.pool,
+                v.name,
+                format_bytes(*size_bytes)
+            ),
+            Action::ResizeVolume(v, _, size_bytes) => write!(
+                f,
+                "~ volume/{}/{} (resize to {})",
+                v.spec.pool,
+                v.name,
+                format_bytes(*size_bytes)
+            ),
+        }
+    }
+}
+
+pub async fn handle_apply_command(args: ApplyArgs, context: Option<&str>) -> Result<()> {
+    let manifest = if args.filename == "-" {
+        let mut contents = String::new();
+        std::io::stdin()
+            .read_to_string(&mut contents)
+            .context("Failed to read manifest from stdin")?;
+        contents
+    } else {
+        tokio::fs::read_to_string(&args.filename)
+            .await
+            .with_context(|| format!("Failed to read manifest {}", args.filename))?
+    };
+    let Manifest {
+        vms,
+        containers,
+        volumes,
+    } = parse_manifest(&manifest)?;
+
+    let channel = config::connect(args.address.as_deref(), context)
+        .await
+        .context("Failed to connect to FeOS")?;
+    let mut vm_client = VmServiceClient::new(channel.clone());
+    let mut container_client = ContainerServiceClient::new(channel.clone());
+    let mut storage_client = StorageServiceClient::new(channel);
+
+    let current_vms = vm_client
+        .list_vms(ListVmsRequest::default())
+        .await?
+        .into_inner()
+        .vms;
+    let current_containers = container_client
+        .list_containers(ListContainersRequest::default())
+        .await?
+        .into_inner()
+        .containers;
+
+    // Volumes are listed only if the manifest has some, so manifests without
+    // volumes also apply to hosts without the storage service.
+    let current_volumes = if volumes.is_empty() {
+        Vec::new()
+    } else {
+        storage_client
+            .list_volumes(ListVolumesRequest {
+                pool_id: None,
+                namespace: Some(VOLUME_NAMESPACE.to_string()),
+            })
+            .await?
+            .into_inner()
+            .volumes
+    };
+
+    // Volumes come first, so they exist before the VMs and containers that
+    // use them.
+    let mut actions = plan_volumes(&volumes, &current_volumes)?;
+    actions.extend(plan_vms(&vms, &current_vms, args.prune));
+    actions.extend(plan_containers(
+        &containers,
+        &current_containers,
+        args.prune,
+    ));
+
+    if actions.is_empty() {
+        println!("Everything is up to date.");
+        return Ok(());
+    }
+
+    for action in &actions {
+        println!("{action}");
+    }
+    if args.dry_run {
+        println!("Dry run: {} change(s) not applied.", actions.len());
+        return Ok(());
+    }
+
+    for action in actions {
+        apply_action(
+            &mut vm_client,
+            &mut container_client,
+            &mut storage_client,
+            action,
+        )
+        .await?;
+    }
+    println!("Manifest applied successfully.");
+    Ok(())
+}
+
+fn parse_manifest(manifest: &str) -> Result<Manifest> {
+    let mut parsed = Manifest::default();
+    let mut seen_ids = HashSet::new();
+    let mut seen_volumes = HashSet::new();
+
+    for (index, document) in serde_yaml::Deserializer::from_str(manifest).enumerate() {
+        let resource = Resource::deserialize(document)
+            .with_context(|| format!("Invalid resource in manifest document {}", index + 1))?;
+        let id = match resource {
+            Resource::Vm(vm) => {
+                let id = vm.id.clone();
+                parsed.vms.push(vm);
+                id
+            }
+            Resource::Container(container) => {
+                let id = container.id.clone();
+                parsed.containers.push(container);
+                id
+            }
+            Resource::Volume(volume) => {
+                if !seen_volumes.insert((volume.spec.pool.clone(), volume.name.clone())) {
+                    anyhow::bail!(
+                        "Manifest contains volume '{}' of pool '{}' more than once",
+                        volume.name,
+                        volume.spec.pool
+                    );
+                }
+                parsed.volumes.push(volume);
+                continue;
+            }
+            Resource::Pod | Resource::Network => {
+                anyhow::bail!(
+                    "Manifest document {}: kind {resource:?} is not supported by this FeOS host",
+                    index + 1
+                );
+            }
+        };
+        if !seen_ids.insert(id.clone()) {
+            anyhow::bail!("Manifest contains resource ID '{id}' more than once");
+        }
+    }
+
+    Ok(parsed)
+}
+
+fn build_vm_config(spec: &VmSpec) -> VmConfig {
+    VmConfig {
+        cpus: Some(CpuConfig {
+            boot_vcpus: spec.vcpus,
+            max_vcpus: spec.vcpus,
+        }),
+        memory: Some(MemoryConfig {
+            size_mib: spec.memory,
+            hugepages: spec.hugepages,
+            swap_max_bytes: None,
+            ..Default::default()
+        }),
+        image_ref: spec.image_ref.clone(),
+        net: spec
+            .pci_devices
+            .iter()
+            .map(|bdf| NetConfig {
+                backend: Some(net_config::Backend::VfioPci(VfioPciConfig {
+                    bdf: bdf.clone(),
+                })),
+                ..Default::default()
+            })
+            .collect(),
+        ignition: spec.ignition.clone(),
+        clock: None,
+        ..Default::default()
+    }
+}
+
+fn vm_matches_spec(spec: &VmSpec, config: &VmConfig) -> bool {
+    let pci_devices: Vec<&str> = config
+        .net
+        .iter()
+        .filter_map(|net| match &net.backend {
+            Some(net_config::Backend::VfioPci(pci)) => Some(pci.bdf.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    config.image_ref == spec.image_ref
+        && config.cpus.as_ref().map(|c| c.boot_vcpus) == Some(spec.vcpus)
+        && config.memory.as_ref().map(|m| m.size_mib) == Some(spec.memory)
+        && config.memory.as_ref().is_some_and(|m| m.hugepages) == spec.hugepages
+        && pci_devices == spec.pci_devices
+        && config.ignition == spec.ignition
+}
+
+fn container_matches_spec(spec: &ContainerSpec, config: &ContainerConfig) -> bool {
+    config.image_ref == spec.image_ref && config.command == spec.command && config.env == spec.env
+}
+
+/// Creates missing volumes and grows those smaller than their spec. Volumes
+/// are never shrunk or recreated, since that would lose their data.
+fn plan_volumes(desired: &[VolumeResource], current: &[VolumeInfo]) -> Result<Vec<Action>> {
+    let mut actions = Vec::new();
+
+    for volume in desired {
+        let size_bytes = parse_size(&volume.spec.size)
+            .map_err(anyhow::Error::msg)
+            .with_context(|| format!("Invalid size of volume '{}'", volume.name))?;
+        let Some(info) = current
+            .iter()
+            .find(|info| info.pool_id == volume.spec.pool && info.name == volume.name)
+        else {
+            actions.push(Action::CreateVolume(volume.clone(), size_bytes));
+            continue;
+        };
+
+        if info.kind != VolumeKind::from(volume.spec.kind) as i32 {
+            anyhow::bail!(
+                "Volume '{}' of pool '{}' exists with another kind, delete it to recreate it",
+                volume.name,
+                volume.spec.pool
+            );
+        }
+        if size_bytes < info.size_bytes {
+            anyhow::bail!(
+                "Volume '{}' of pool '{}' is {}, volumes cannot be shrunk to {}",
+                volume.name,
+                volume.spec.pool,
+                format_bytes(info.size_bytes),
+                format_bytes(size_bytes)
+            );
+        }
+        if size_bytes > info.size_bytes {
+            actions.push(Action::ResizeVolume(
+                volume.clone(),
+                info.volume_id.clone(),
+                size_bytes,
+            ));
+        }
+    }
+
+    Ok(actions)
+}
+
+fn plan_vms(desired: &[VmResource], current: &[VmInfo], prune: bool) -> Vec<Action> {
+    let mut actions = Vec::new();
+
+    for vm in desired {
+        let Some(info) = current.iter().find(|info| info.vm_id == vm.id) else {
+            actions.push(Action::CreateVm(vm.clone()));
+            continue;
+        };
+
+        let matches = info
+            .config
+            .as_ref()
+            .is_some_and(|config| vm_matches_spec(&vm.spec, config));
+        if !matches {
+            actions.push(Action::RecreateVm(vm.clone()));
+            continue;
+        }
+
+        let state = VmState::try_from(info.state).unwrap_or(VmState::Unspecified);
+        match (vm.spec.running, state) {
+            (true, VmState::Created | VmState::Stopped) => {
+                actions.push(Action::StartVm(vm.id.clone()))
+            }
+            (false, VmState::Running) => actions.push(Action::ShutdownVm(vm.id.clone())),
+            _ => {}
+        }
+    }
+
+    if prune {
+        for info in current {
+            if !desired.iter().any(|vm| vm.id == info.vm_id) {
+                actions.push(Action::DeleteVm(info.vm_id.clone()));
+            }
+        }
+    }
+
+    actions
+}
+
+fn plan_containers(
+    desired: &[ContainerResource],
+    current: &[ContainerInfo],
+    prune: bool,
+) -> Vec<Action> {
+    let mut actions = Vec::new();
+
+    for container in desired {
+        let Some(info) = current
+            .iter()
+            .find(|info| info.container_id == container.id)
+        else {
+            actions.push(Action::CreateContainer(container.clone()));
+            continue;
+        };
+
+        let matches = info
+            .config
+            .as_ref()
+            .is_some_and(|config| container_matches_spec(&container.spec, config));
+        let state = ContainerState::try_from(info.state).unwrap_or(ContainerState::Unspecified);
+
+        // A stopped container cannot be started again, it has to be recreated.
+        if !matches || (container.spec.running && state == ContainerState::Stopped) {
+            actions.push(Action::RecreateContainer(container.clone()));
+            continue;
+        }
+
+        match (container.spec.running, state) {
+            (true, ContainerState::Created) => {
+                actions.push(Action::StartContainer(container.id.clone()))
+            }
+            (false, ContainerState::Running) => {
+                actions.push(Action::StopContainer(container.id.clone()))
+            }
+            _ => {}
+        }
+    }
+
+    if prune {
+        for info in current {
+            if !desired.iter().any(|c| c.id == info.container_id) {
+                actions.push(Action::DeleteContainer(info.container_id.clone()));
+            }
+        }
+    }
+
+    actions
+}
+
+async fn apply_action(
+    vm_client: &mut VmServiceClient<Channel>,
+    container_client: &mut ContainerServiceClient<Channel>,
+    storage_client: &mut StorageServiceClient<Channel>,
+    action: Action,
+) -> Result<()> {
+    println!("Applying: {action}");
+    match action {
+        Action::CreateVm(vm) => create_vm(vm_client, vm).await?,
+        Action::RecreateVm(vm) => {
+            delete_vm(vm_client, &vm.id).await?;
+            create_vm(vm_client, vm).await?;
+        }
+        Action::StartVm(id) => {
+            vm_client.start_vm(StartVmRequest { vm_id: id }).await?;
+        }
+        Action::ShutdownVm(id) => {
+            vm_client
+                .shutdown_vm(ShutdownVmRequest {
+                    vm_id: id,
+                    graceful: false,
+                    grace_period_seconds: None,
+                })
+                .await?;
+        }
+        Action::DeleteVm(id) => delete_vm(vm_client, &id).await?,
+        Action::CreateContainer(container) => create_container(container_client, container).await?,
+        Action::RecreateContainer(container) => {
+            delete_container(container_client, &container.id).await?;
+            create_container(container_client, container).await?;
+        }
+        Action::StartContainer(id) => {
+            container_client
+                .start_container(StartContainerRequest { container_id: id })
+                .await?;
+        }
+        Action::StopContainer(id) => {
+            container_client
+                .stop_container(StopContainerRequest {
+                    container_id: id,
+                    ..Default::default()
+                })
+                .await?;
+        }
+        Action::DeleteContainer(id) => delete_container(container_client, &id).await?,
+        Action::CreateVolume(volume, size_bytes) => {
+            storage_client
+                .create_volume(CreateVolumeRequest {
+                    pool_id: volume.spec.pool,
+                    name: volume.name,
+                    kind: VolumeKind::from(volume.spec.kind) as i32,
+                    size_bytes,
+                    encryption: None,
+                    namespace: VOLUME_NAMESPACE.to_string(),
+                })
+                .await?;
+        }
+        Action::ResizeVolume(_, volume_id, size_bytes) => {
+            storage_client
+                .resize_volume(ResizeVolumeRequest {
+                    volume_id,
+                    size_bytes,
+                })
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+async fn create_vm(client: &mut VmServiceClient<Channel>, vm: VmResource) -> Result<()> {
+    let request = CreateVmRequest {
+        config: Some(build_vm_config(&vm.spec)),
+        vm_id: Some(vm.id.clone()),
+        ..Default::default()
+    };
+    client.create_vm(request).await?;
+
+    if vm.spec.running {
+        wait_for_vm_state(client, &vm.id, VmState::Created).await?;
+        client
+            .start_vm(StartVmRequest {
+                vm_id: vm.id.clone(),
+            })
+            .await?;
+    }
+    Ok(())
+}
+
+async fn delete_vm(client: &mut VmServiceClient<Channel>, id: &str) -> Result<()> {
+    client
+        .delete_vm(DeleteVmRequest {
+            vm_id: id.to_string(),
+        })
+        .await?;
+    Ok(())
+}
+
+async fn create_container(
+    client: &mut ContainerServiceClient<Channel>,
+    container: ContainerResource,
+) -> Result<()> {
+    let ContainerSpec {
+        image_ref,
+        command,
+        env,
+        disk_limit,
+        running,
+    } = container.spec;
+    let disk_limit_bytes = disk_limit
+        .as_deref()
+        .map(parse_size)
+        .transpose()
+        .map_err(anyhow::Error::msg)
+        .with_context(|| format!("Invalid disk_limit of container '{}'", container.id))?
+        .unwrap_or(0);
+    let request = CreateContainerRequest {
+        config: Some(ContainerConfig {
+            image_ref,
+            command,
+            env,
+            disk_limit_bytes,
+            swap_max_bytes: None,
+            memory_limit_bytes: 0,
+            milli_cpus: 0,
+            ..Default::default()
+        }),
+        container_id: Some(container.id.clone()),
+        ..Default::default()
+    };
+    client.create_container(request).await?;
+
+    if running {
+        tokio::time::timeout(
+            CONTAINER_CREATE_TIMEOUT,
+            wait_for_container_state(client, &container.id, ContainerState::Created),
+        )
+        .await
+        .with_context(|| {
+            format!(
+                "Timed out waiting for container {} to be created",
+                container.id
+            )
+        })??;
+        client
+            .start_container(StartContainerRequest {
+                container_id: container.id,
+            })
+            .await?;
+    }
+    Ok(())
+}
+
+async fn delete_container(client: &mut ContainerServiceClient<Channel>, id: &str) -> Result<()> {
+    let state = client
+        .list_containers(ListContainersRequest::default())
+        .await?
+        .into_inner()
+        .containers
+        .into_iter()
+        .find(|c| c.container_id == id)
+        .map(|c| ContainerState::try_from(c.state).unwrap_or(ContainerState::Unspecified));
+
+    if state == Some(ContainerState::Running) {
+        client
+            .stop_container(StopContainerRequest {
+                container_id: id.to_string(),
+                ..Default::default()
+            })
+            .await?;
+    }
+    client
+        .delete_container(DeleteContainerRequest {
+            container_id: id.to_string(),
+        })
+        .await?;
+    Ok(())
+}
+
+async fn wait_for_container_state(
+    client: &mut ContainerServiceClient<Channel>,
+    id: &str,
+    target_state: ContainerState,
+) -> Result<()> {
+    let request = StreamContainerEventsRequest {
+        container_id: Some(id.to_string()),
+        ..Default::default()
+    };
+    let mut stream = client.stream_container_events(request).await?.into_inner();
+
+    while let Some(event) = stream.next().await {
+        let Some(data) = event?.data else {
+            continue;
+        };
+        if !data
+            .type_url
+            .contains("feos.container.v1.ContainerStateChangedEvent")
+        {
+            continue;
+        }
+        let state_change = ContainerStateChangedEvent::decode(&*data.value)?;
+        if state_change.new_state == target_state as i32 {
+            return Ok(());
+        }
+    }
+
+    anyhow::bail!("Event stream ended before container {id} reached state {target_state:?}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r#"
+kind: Vm
+id: 6a3a3c4e-8f0b-4d53-9a0e-2b1c1f3e9d10
+spec:
+  image_ref: ghcr.io/ironcore-dev/os-images/gardenlinux:latest
+  vcpus: 2
+  memory: 2048
+---
+kind: Container
+id: 0b8a9f3c-1d2e-4f5a-8b6c-7d8e9f0a1b2c
+spec:
+  image_ref: docker.io/library/alpine:latest
+  command: ["sleep", "infinity"]
+---
+kind: Volume
+name: data
+spec:
+  pool: 9c1f6a2e-3b4d-4e5f-8a6b-7c8d9e0f1a2b
+  size: 10G
+  kind: container-volume
+"#;
+
+    fn vm_info(resource: &VmResource, state: VmState) -> VmInfo {
+        VmInfo {
+            vm_id: resource.id.clone(),
+            state: state as i32,
+            config: Some(build_vm_config(&resource.spec)),
+            boot_timings: None,
+            guest_addresses: Vec::new(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_manifest() {
+        let Manifest {
+            vms,
+            containers,
+            volumes,
+        } = parse_manifest(MANIFEST).unwrap();
+
+        assert_eq!(vms.len(), 1);
+        assert_eq!(vms[0].spec.vcpus, 2);
+        assert!(vms[0].spec.running);
+        assert_eq!(containers.len(), 1);
+        assert_eq!(containers[0].spec.command, vec!["sleep", "infinity"]);
+        assert_eq!(volumes.len(), 1);
+        assert_eq!(volumes[0].spec.kind, VolumeKindArg::ContainerVolume);
+    }
+
+    #[test]
+    fn test_parse_manifest_rejects_unsupported_kind() {
+        let err = parse_manifest("kind: Pod\nid: foo\n").unwrap_err();
+        assert!(err.to_string().contains("Pod"));
+    }
+
+    #[test]
+    fn test_plan_vms() {
+        let vms = parse_manifest(MANIFEST).unwrap().vms;
+
+        let actions = plan_vms(&vms, &[], false);
+        assert_eq!(actions, vec![Action::CreateVm(vms[0].clone())]);
+
+        let running = vm_info(&vms[0], VmState::Running);
+        assert!(plan_vms(&vms, &[running.clone()], false).is_empty());
+
+        let stopped = vm_info(&vms[0], VmState::Stopped);
+        assert_eq!(
+            plan_vms(&vms, &[stopped], false),
+            vec![Action::StartVm(vms[0].id.clone())]
+        );
+
+        let mut changed = vms[0].clone();
+        changed.spec.memory = 4096;
+        assert_eq!(
+            plan_vms(&[changed.clone()], &[running.clone()], false),
+            vec![Action::RecreateVm(changed)]
+        );
+
+        assert_eq!(
+            plan_vms(&[], &[running], true),
+            vec![Action::DeleteVm(vms[0].id.clone())]
+        );
+    }
+
+    #[test]
+    fn test_plan_volumes() {
+        let volumes = parse_manifest(MANIFEST).unwrap().volumes;
+        let size_bytes = parse_size("10G").unwrap();
+
+        assert_eq!(
+            plan_volumes(&volumes, &[]).unwrap(),
+            vec![Action::CreateVolume(volumes[0].clone(), size_bytes)]
+        );
+
+        let mut info = VolumeInfo {
+            volume_id: "f3e2d1c0-b9a8-4765-8432-10fedcba9876".to_string(),
+            pool_id: volumes[0].spec.pool.clone(),
+            name: volumes[0].name.clone(),
+            kind: VolumeKind::ContainerVolume as i32,
+            size_bytes,
+            namespace: VOLUME_NAMESPACE.to_string(),
+            ..Default::default()
+        };
+        assert!(plan_volumes(&volumes, &[info.clone()]).unwrap().is_empty());
+
+        info.size_bytes = size_bytes / 2;
+        assert_eq!(
+            plan_volumes(&volumes, &[info.clone()]).unwrap(),
+            vec![Action::ResizeVolume(
+                volumes[0].clone(),
+                info.volume_id.clone(),
+                size_bytes
+            )]
+        );
+
+        info.size_bytes = size_bytes * 2;
+        assert!(plan_volumes(&volumes, &[info.clone()]).is_err());
+
+        info.size_bytes = size_bytes;
+        info.kind = VolumeKind::VmDisk as i32;
+        assert!(plan_volumes(&volumes, &[info]).is_err());
+    }
+}
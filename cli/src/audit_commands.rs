@@ -0,0 +1,135 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config;
+use crate::host_commands::{parse_time, to_timestamp};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use clap::{Args, ValueEnum};
+use feos_proto::audit_service::{
+    audit_service_client::AuditServiceClient, AuditRecord, StreamAuditLogRequest,
+};
+use serde::Serialize;
+use tokio_stream::StreamExt;
+use tonic::Code;
+
+#[derive(Args, Debug)]
+pub struct AuditArgs {
+    #[arg(
+        short,
+        long,
+        env = "FEOS_ADDRESS",
+        help = "FeOS API address, overriding the address of the selected context"
+    )]
+    pub address: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        help = "Keep streaming new records instead of exiting after the recorded ones"
+    )]
+    follow: bool,
+
+    #[arg(
+        long,
+        default_value_t = 0,
+        help = "Only show the records after the one with this ID"
+    )]
+    after_id: u64,
+
+    #[arg(
+        long,
+        value_parser = parse_time,
+        help = "Only show the calls made at or after this time (RFC 3339, e.g. 2025-01-01T12:00:00Z)"
+    )]
+    since: Option<DateTime<Utc>>,
+
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Pretty)]
+    output: OutputFormat,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum OutputFormat {
+    Pretty,
+    Json,
+}
+
+/// An audit record in the shape it is printed.
+#[derive(Serialize, Debug)]
+struct Record {
+    id: u64,
+    time: String,
+    method: String,
+    caller: String,
+    credential: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    summary: String,
+    status: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    message: String,
+    duration_ms: u64,
+    node_id: String,
+}
+
+impl From<AuditRecord> for Record {
+    fn from(record: AuditRecord) -> Self {
+        let time = record
+            .timestamp
+            .and_then(|time| DateTime::from_timestamp(time.seconds, time.nanos as u32))
+            .map(|time| time.with_timezone(&chrono::Local).to_rfc3339())
+            .unwrap_or_default();
+        Self {
+            id: record.id,
+            time,
+            method: record.method,
+            caller: record.caller,
+            credential: record.credential,
+            summary: record.summary,
+            status: format!("{:?}", Code::from_i32(record.code)),
+            message: record.message,
+            duration_ms: record.duration_ms,
+            node_id: record.node_id,
+        }
+    }
+}
+
+pub async fn handle_audit_command(args: AuditArgs, context: Option<&str>) -> Result<()> {
+    let channel = config::connect(args.address.as_deref(), context)
+        .await
+        .context("Failed to connect to audit service")?;
+    let mut records = AuditServiceClient::new(channel)
+        .stream_audit_log(StreamAuditLogRequest {
+            after_id: args.after_id,
+            since: args.since.map(to_timestamp),
+            follow: args.follow,
+        })
+        .await
+        .context("Failed to read the audit log")?
+        .into_inner();
+
+    if matches!(args.output, OutputFormat::Pretty) {
+        println!(
+            "{:<8} {:<26} {:<22} {:<42} {:<18} SUMMARY",
+            "ID", "TIME", "METHOD", "CALLER", "STATUS"
+        );
+    }
+    while let Some(record) = records.next().await {
+        let record = Record::from(record.context("Audit log stream failed")?);
+        match args.output {
+            OutputFormat::Json => println!("{}", serde_json::to_string(&record)?),
+            OutputFormat::Pretty => {
+                let method = record.method.rsplit('/').next().unwrap_or(&record.method);
+                let status = if record.message.is_empty() {
+                    record.status.clone()
+                } else {
+                    format!("{}: {}", record.status, record.message)
+                };
+                println!(
+                    "{:<8} {:<26} {:<22} {:<42} {:<18} {}",
+                    record.id, record.time, method, record.caller, status, record.summary
+                );
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,23 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{Context, Result};
+use feos_proto::auth::SigningChannel;
+
+use feos_client_config::resolve;
+
+/// The channel to a FeOS host, which signs the requests if the host is
+/// protected with an API token.
+pub type Channel = SigningChannel<tonic::transport::Channel>;
+
+/// Opens a gRPC channel to the endpoint selected by `--address`/`--context`.
+pub async fn connect(address: Option<&str>, context: Option<&str>) -> Result<Channel> {
+    let target = resolve(address, context)?;
+    let token = target.token()?;
+    let channel = target
+        .endpoint()?
+        .connect()
+        .await
+        .with_context(|| format!("Failed to connect to {}", target.address))?;
+    Ok(SigningChannel::new(channel, token))
+}
@@ -1,14 +1,35 @@
 // SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::config::{self, Channel};
+use crate::operation_commands::{print_async, wait_for_operation, Operation, OperationKind};
+use crate::secret_commands::parse_secret_file;
+use crate::storage_commands::{format_bytes, parse_size};
+use crate::vm_commands::{DrainPolicyArg, StartupAfterArg};
 use anyhow::{Context, Result};
-use clap::{Args, Subcommand};
+use clap::{Args, Subcommand, ValueEnum};
+use crossterm::cursor::MoveTo;
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, Clear, ClearType};
+use crossterm::tty::IsTty;
 use feos_proto::container_service::{
-    container_service_client::ContainerServiceClient, ContainerConfig, ContainerState,
-    CreateContainerRequest, DeleteContainerRequest, GetContainerRequest, ListContainersRequest,
-    StartContainerRequest, StopContainerRequest,
+    container_secret, container_service_client::ContainerServiceClient,
+    exec_container_request as exec_input, exec_container_response as exec_output,
+    startup_dependency, ContainerConfig, ContainerInfo, ContainerSecret, ContainerState,
+    CpuScheduling, CreateContainerRequest, DeleteContainerRequest, DrainPolicy,
+    ExecContainerRequest, ExecStart, GetContainerRequest, ListContainersRequest,
+    ReplayContainerStateJournalRequest, SchedulingClass, StartContainerRequest, StartupConfig,
+    StartupDependency, StopContainerRequest, StreamContainerEventsRequest, TerminalSize,
 };
-use tonic::transport::Channel;
+use prost_types::Timestamp;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(100);
+const WATCH_REFRESH_INTERVAL: Duration = Duration::from_secs(2);
 
 #[derive(Args, Debug)]
 pub struct ContainerArgs {
@@ -17,9 +38,9 @@ pub struct ContainerArgs {
         long,
         global = true,
         env = "FEOS_ADDRESS",
-        default_value = "http://[::1]:1337"
+        help = "FeOS API address, overriding the address of the selected context"
     )]
-    pub address: String,
+    pub address: Option<String>,
 
     #[command(subcommand)]
     command: ContainerCommand,
@@ -39,6 +60,16 @@ pub enum ContainerCommand {
         #[arg(long, help = "Optional custom container identifier (UUID)")]
         id: Option<String>,
 
+        #[arg(
+            long,
+            env = "FEOS_NAMESPACE",
+            help = "Namespace to create the container in [default: default]"
+        )]
+        namespace: Option<String>,
+
+        #[arg(long, help = "Name of the container, unique within its namespace")]
+        name: Option<String>,
+
         #[arg(
             long,
             help = "Override the default command of the image",
@@ -53,16 +84,164 @@ pub enum ContainerCommand {
             value_parser = parse_key_val
         )]
         env: Vec<(String, String)>,
+
+        #[arg(
+            long,
+            value_name = "VAR=SECRET",
+            value_parser = parse_key_val,
+            help = "Set an environment variable to the value of a secret of the namespace (repeatable)"
+        )]
+        secret_env: Vec<(String, String)>,
+
+        #[arg(
+            long,
+            value_name = "SECRET:PATH",
+            value_parser = parse_secret_file,
+            help = "Mount the value of a secret of the namespace as a read-only file (repeatable)"
+        )]
+        secret_file: Vec<(String, String)>,
+
+        #[arg(
+            long,
+            value_parser = parse_size,
+            help = "Limit the size of the container's writable layer (e.g., 2G)"
+        )]
+        disk_limit: Option<u64>,
+
+        #[arg(
+            long,
+            value_parser = parse_size,
+            help = "Limit how much memory of the container the host may swap out, 0 keeps it in RAM (e.g., 512M)"
+        )]
+        swap_max: Option<u64>,
+
+        #[arg(
+            long,
+            value_parser = parse_size,
+            help = "Limit the memory of the container, committed against the host (e.g., 512M)"
+        )]
+        memory: Option<u64>,
+
+        #[arg(
+            long,
+            value_parser = parse_cpus,
+            help = "Limit the CPU time of the container, committed against the host (e.g., 1.5)"
+        )]
+        cpus: Option<u32>,
+
+        #[arg(
+            long,
+            help = "Share of CPU time the container gets under contention, 1 to 10000 [default: 100]"
+        )]
+        cpu_weight: Option<u32>,
+
+        #[arg(long, help = "CPUs the container may run on (e.g., 0-3,8)")]
+        cpuset: Option<String>,
+
+        #[arg(
+            long,
+            value_enum,
+            help = "How the processes of the container are scheduled [default: normal]"
+        )]
+        scheduling_class: Option<SchedulingClassArg>,
+
+        #[arg(
+            long,
+            allow_hyphen_values = true,
+            help = "Niceness of the container's processes, -20 to 19, for normal and batch scheduling"
+        )]
+        nice: Option<i32>,
+
+        #[arg(
+            long,
+            help = "Priority of the container's processes for realtime scheduling, 1 to 99"
+        )]
+        realtime_priority: Option<u32>,
+
+        #[arg(
+            long,
+            value_name = "PATH",
+            help = "Serial port of the host the container may use at the same path, e.g. /dev/ttyUSB0 (repeatable)"
+        )]
+        serial_port: Vec<String>,
+
+        #[arg(
+            long,
+            value_enum,
+            help = "What happens to the container when the host is drained [default: shutdown]"
+        )]
+        drain_policy: Option<DrainPolicyArg>,
+
+        #[arg(
+            long,
+            alias = "autostart",
+            help = "Bring the container back up once the network is up when FeOS finds its process gone, e.g. after a reboot"
+        )]
+        auto_start: bool,
+
+        #[arg(
+            long,
+            value_name = "vm:<id>|container:<id>",
+            help = "Workload that must be running before FeOS starts the container by itself (repeatable)"
+        )]
+        after: Vec<StartupAfterArg>,
+
+        #[arg(
+            long,
+            value_name = "SECONDS",
+            help = "How long FeOS waits for --after workloads [default: 300]"
+        )]
+        dependency_timeout: Option<u32>,
+
+        #[arg(
+            long,
+            value_parser = parse_signal,
+            help = "Signal that asks the container to stop, by name or number [default: SIGTERM]"
+        )]
+        stop_signal: Option<u32>,
+
+        #[arg(
+            long,
+            value_name = "SECONDS",
+            help = "How long stopping waits for the container to exit before killing it [default: 10]"
+        )]
+        stop_timeout: Option<u32>,
+
+        #[arg(
+            long = "async",
+            help = "Print the operation ID and return instead of waiting for completion"
+        )]
+        run_async: bool,
     },
     /// Start a created container
     Start {
         #[arg(required = true, help = "Container identifier")]
         id: String,
+
+        #[arg(
+            long = "async",
+            help = "Print the operation ID and return instead of waiting for completion"
+        )]
+        run_async: bool,
     },
     /// Stop a running container
     Stop {
         #[arg(required = true, help = "Container identifier")]
         id: String,
+
+        #[arg(
+            long,
+            value_parser = parse_signal,
+            help = "Signal to send instead of the stop signal of the container"
+        )]
+        signal: Option<u32>,
+
+        #[arg(
+            long,
+            value_name = "SECONDS",
+            help = "How long to wait for the container to exit before killing it, instead of its stop timeout"
+        )]
+        timeout: Option<u32>,
     },
     /// Get detailed information about a container
     Info {
@@ -70,12 +249,71 @@ pub enum ContainerCommand {
         id: String,
     },
     /// List all containers
-    List,
+    List {
+        #[arg(
+            long,
+            help = "Keep watching and update the table on every container event and every 2s"
+        )]
+        watch: bool,
+        #[arg(
+            long,
+            env = "FEOS_NAMESPACE",
+            help = "Only list containers in this namespace (lists all namespaces if not provided)"
+        )]
+        namespace: Option<String>,
+    },
     /// Delete a container
     Delete {
         #[arg(required = true, help = "Container identifier")]
         id: String,
     },
+    /// Replay the journal of container state transitions, oldest first
+    Journal {
+        #[arg(long, help = "Only show the transitions of this container")]
+        container_id: Option<String>,
+        #[arg(
+            long,
+            default_value_t = 0,
+            help = "Only show entries after this sequence number"
+        )]
+        after: u64,
+        #[arg(long, help = "Maximum number of entries to show [default: 1000]")]
+        limit: Option<u32>,
+    },
+    /// Execute a command inside a running container
+    Exec {
+        #[arg(short, long, help = "Keep stdin open and forward it to the process")]
+        interactive: bool,
+
+        #[arg(short, long, help = "Allocate a pseudo-terminal for the process")]
+        tty: bool,
+
+        #[arg(required = true, help = "Container identifier")]
+        id: String,
+
+        #[arg(
+            required = true,
+            trailing_var_arg = true,
+            allow_hyphen_values = true,
+            help = "Command and arguments to execute"
+        )]
+        command: Vec<String>,
+    },
+}
+
+fn container_secrets(
+    env: Vec<(String, String)>,
+    files: Vec<(String, String)>,
+) -> Vec<ContainerSecret> {
+    let env = env.into_iter().map(|(var, name)| ContainerSecret {
+        name,
+        target: Some(container_secret::Target::Env(var)),
+    });
+    let files = files.into_iter().map(|(name, path)| ContainerSecret {
+        name,
+        target: Some(container_secret::Target::Path(path)),
+    });
+    env.chain(files).collect()
 }
 
 fn parse_key_val(s: &str) -> Result<(String, String), String> {
@@ -84,23 +322,205 @@ fn parse_key_val(s: &str) -> Result<(String, String), String> {
         .ok_or_else(|| format!("invalid KEY=value format: {s}"))
 }
 
-pub async fn handle_container_command(args: ContainerArgs) -> Result<()> {
-    let mut client = ContainerServiceClient::connect(args.address)
+impl From<DrainPolicyArg> for DrainPolicy {
+    fn from(policy: DrainPolicyArg) -> Self {
+        match policy {
+            DrainPolicyArg::Shutdown => DrainPolicy::Shutdown,
+            DrainPolicyArg::Stop => DrainPolicy::Stop,
+            DrainPolicyArg::Keep => DrainPolicy::Keep,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SchedulingClassArg {
+    Normal,
+    Batch,
+    Idle,
+    Realtime,
+}
+
+impl From<SchedulingClassArg> for SchedulingClass {
+    fn from(class: SchedulingClassArg) -> Self {
+        match class {
+            SchedulingClassArg::Normal => SchedulingClass::Normal,
+            SchedulingClassArg::Batch => SchedulingClass::Batch,
+            SchedulingClassArg::Idle => SchedulingClass::Idle,
+            SchedulingClassArg::Realtime => SchedulingClass::Realtime,
+        }
+    }
+}
+
+impl From<StartupAfterArg> for StartupDependency {
+    fn from(after: StartupAfterArg) -> Self {
+        let workload = match after {
+            StartupAfterArg::Vm(id) => startup_dependency::Workload::VmId(id),
+            StartupAfterArg::Container(id) => startup_dependency::Workload::ContainerId(id),
+        };
+        StartupDependency {
+            workload: Some(workload),
+        }
+    }
+}
+
+/// Linux signal numbers of the signals processes are commonly stopped with.
+const SIGNALS: &[(&str, u32)] = &[
+    ("HUP", 1),
+    ("INT", 2),
+    ("QUIT", 3),
+    ("KILL", 9),
+    ("USR1", 10),
+    ("USR2", 12),
+    ("TERM", 15),
+    ("PWR", 30),
+];
+
+/// Parses a signal given by number or name, e.g. `15`, `TERM` or `SIGTERM`.
+fn parse_signal(s: &str) -> Result<u32, String> {
+    if let Ok(number) = s.parse::<u32>() {
+        return Ok(number);
+    }
+    let name = s.to_ascii_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+    SIGNALS
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|(_, number)| *number)
+        .ok_or_else(|| format!("unknown signal: {s}"))
+}
+
+fn scheduling_summary(scheduling: &CpuScheduling) -> String {
+    match scheduling.class() {
+        SchedulingClass::Realtime => format!("realtime, priority {}", scheduling.realtime_priority),
+        SchedulingClass::Idle => "idle".to_string(),
+        SchedulingClass::Batch => format!("batch, nice {}", scheduling.nice),
+        SchedulingClass::Unspecified | SchedulingClass::Normal => {
+            format!("normal, nice {}", scheduling.nice)
+        }
+    }
+}
+
+/// Parses a number of CPUs, e.g. `1.5`, into thousandths of a CPU.
+fn parse_cpus(s: &str) -> Result<u32, String> {
+    let cpus: f64 = s
+        .parse()
+        .map_err(|_| format!("invalid number of CPUs: {s}"))?;
+    let milli_cpus = (cpus * 1000.0).round();
+    if !(1.0..=f64::from(u32::MAX)).contains(&milli_cpus) {
+        return Err(format!("number of CPUs out of range: {s}"));
+    }
+    Ok(milli_cpus as u32)
+}
+
+pub async fn handle_container_command(args: ContainerArgs, context: Option<&str>) -> Result<()> {
+    let channel = config::connect(args.address.as_deref(), context)
         .await
         .context("Failed to connect to container service")?;
+    let mut client = ContainerServiceClient::new(channel.clone());
 
     match args.command {
         ContainerCommand::Create {
             image_ref,
             id,
+            namespace,
+            name,
             cmd,
             env,
-        } => create_container(&mut client, image_ref, id, cmd, env).await?,
-        ContainerCommand::Start { id } => start_container(&mut client, id).await?,
-        ContainerCommand::Stop { id } => stop_container(&mut client, id).await?,
+            secret_env,
+            secret_file,
+            disk_limit,
+            swap_max,
+            memory,
+            cpus,
+            cpu_weight,
+            cpuset,
+            scheduling_class,
+            nice,
+            realtime_priority,
+            serial_port,
+            drain_policy,
+            auto_start,
+            after,
+            dependency_timeout,
+            stop_signal,
+            stop_timeout,
+            run_async,
+        } => {
+            let startup =
+                (auto_start || !after.is_empty() || dependency_timeout.is_some()).then(|| {
+                    StartupConfig {
+                        auto_start,
+                        after: after.into_iter().map(Into::into).collect(),
+                        dependency_timeout_seconds: dependency_timeout,
+                    }
+                });
+            let scheduling = (scheduling_class.is_some()
+                || nice.is_some()
+                || realtime_priority.is_some())
+            .then(|| CpuScheduling {
+                class: scheduling_class.map_or(SchedulingClass::Unspecified, SchedulingClass::from)
+                    as i32,
+                nice: nice.unwrap_or(0),
+                realtime_priority: realtime_priority.unwrap_or(0),
+            });
+            let config = ContainerConfig {
+                image_ref,
+                command: cmd,
+                env: env.into_iter().collect(),
+                disk_limit_bytes: disk_limit.unwrap_or(0),
+                swap_max_bytes: swap_max,
+                memory_limit_bytes: memory.unwrap_or(0),
+                milli_cpus: cpus.unwrap_or(0),
+                drain_policy: drain_policy.map_or(DrainPolicy::Unspecified, DrainPolicy::from)
+                    as i32,
+                startup,
+                stop_signal,
+                stop_timeout_seconds: stop_timeout,
+                cpu_weight,
+                cpuset: cpuset.unwrap_or_default(),
+                scheduling,
+                serial_ports: serial_port,
+                secrets: container_secrets(secret_env, secret_file),
+            };
+            let request = CreateContainerRequest {
+                config: Some(config),
+                container_id: id,
+                namespace: namespace.unwrap_or_default(),
+                name,
+            };
+            create_container(&mut client, &channel, request, run_async).await?
+        }
+        ContainerCommand::Start { id, run_async } => {
+            start_container(&mut client, &channel, id, run_async).await?
+        }
+        ContainerCommand::Stop {
+            id,
+            signal,
+            timeout,
+        } => stop_container(&mut client, id, signal, timeout).await?,
         ContainerCommand::Info { id } => get_container_info(&mut client, id).await?,
-        ContainerCommand::List => list_containers(&mut client).await?,
+        ContainerCommand::List { watch, namespace } => {
+            if watch {
+                watch_containers(&mut client, namespace).await?
+            } else {
+                list_containers(&mut client, namespace).await?
+            }
+        }
         ContainerCommand::Delete { id } => delete_container(&mut client, id).await?,
+        ContainerCommand::Journal {
+            container_id,
+            after,
+            limit,
+        } => replay_state_journal(&mut client, container_id, after, limit).await?,
+        ContainerCommand::Exec {
+            interactive,
+            tty,
+            id,
+            command,
+        } => {
+            let exit_code = exec_container(&mut client, id, command, interactive, tty).await?;
+            std::process::exit(exit_code);
+        }
     }
 
     Ok(())
@@ -108,55 +528,64 @@ pub async fn handle_container_command(args: ContainerArgs) -> Result<()> {
 
 async fn create_container(
     client: &mut ContainerServiceClient<Channel>,
-    image_ref: String,
-    id: Option<String>,
-    cmd: Vec<String>,
-    env: Vec<(String, String)>,
+    channel: &Channel,
+    request: CreateContainerRequest,
+    run_async: bool,
 ) -> Result<()> {
-    println!("Requesting container creation with image: {image_ref}...");
-
-    let config = ContainerConfig {
-        image_ref,
-        command: cmd,
-        env: env.into_iter().collect(),
-    };
-
-    let request = CreateContainerRequest {
-        config: Some(config),
-        container_id: id,
-    };
-
     let response = client.create_container(request).await?.into_inner();
+    let operation = Operation::new(OperationKind::ContainerCreate, &response.container_id);
+    if run_async {
+        print_async(&operation);
+        return Ok(());
+    }
+
     println!(
         "Container creation initiated. Container ID: {}",
         response.container_id
     );
+    wait_for_operation(channel, &operation).await?;
     println!(
-        "Use 'feos-cli container list' to check its status and 'feos-cli container start {}' to run it.",
+        "Use 'feos-cli container start {}' to run it.",
         response.container_id
     );
 
     Ok(())
 }
 
-async fn start_container(client: &mut ContainerServiceClient<Channel>, id: String) -> Result<()> {
-    println!("Requesting to start container: {id}...");
+async fn start_container(
+    client: &mut ContainerServiceClient<Channel>,
+    channel: &Channel,
+    id: String,
+    run_async: bool,
+) -> Result<()> {
     let request = StartContainerRequest {
         container_id: id.clone(),
     };
     client.start_container(request).await?;
+    let operation = Operation::new(OperationKind::ContainerStart, &id);
+    if run_async {
+        print_async(&operation);
+        return Ok(());
+    }
+
     println!("Start request sent for container: {id}");
-    Ok(())
+    wait_for_operation(channel, &operation).await
 }
 
-async fn stop_container(client: &mut ContainerServiceClient<Channel>, id: String) -> Result<()> {
+async fn stop_container(
+    client: &mut ContainerServiceClient<Channel>,
+    id: String,
+    signal: Option<u32>,
+    timeout_seconds: Option<u32>,
+) -> Result<()> {
     println!("Requesting to stop container: {id}...");
     let request = StopContainerRequest {
         container_id: id.clone(),
-        ..Default::default()
+        signal,
+        timeout_seconds,
     };
     client.stop_container(request).await?;
-    println!("Stop request sent for container: {id}");
+    println!("Stopped container: {id}");
     Ok(())
 }
 
@@ -170,6 +599,10 @@ async fn get_container_info(
     let response = client.get_container(request).await?.into_inner();
 
     println!("Container Info for: {id}");
+    println!("  Namespace: {}", response.namespace);
+    if let Some(name) = &response.name {
+        println!("  Name: {name}");
+    }
     println!(
         "  State: {:?}",
         ContainerState::try_from(response.state).unwrap_or(ContainerState::Unspecified)
@@ -177,9 +610,24 @@ async fn get_container_info(
     if let Some(pid) = response.pid {
         println!("  PID: {pid}");
     }
+    if let Some(started_at) = &response.started_at {
+        println!("  Started At: {}", format_timestamp(started_at));
+    }
+    if let Some(finished_at) = &response.finished_at {
+        println!("  Finished At: {}", format_timestamp(finished_at));
+    }
     if let Some(exit_code) = response.exit_code {
         println!("  Exit Code: {exit_code}");
     }
+    if let Some(signal) = response.exit_signal {
+        println!("  Exit Signal: {signal}");
+    }
+    if response.oom_killed {
+        println!("  OOM Killed: yes");
+    }
+    if response.oom_kill_count > 0 {
+        println!("  OOM Kills: {}", response.oom_kill_count);
+    }
     if let Some(config) = response.config {
         println!("  Config:");
         println!("    Image Ref: {}", config.image_ref);
@@ -189,36 +637,189 @@ async fn get_container_info(
         if !config.env.is_empty() {
             println!("    Env: {:?}", config.env);
         }
+        if config.disk_limit_bytes > 0 {
+            println!("    Disk Limit: {}", format_bytes(config.disk_limit_bytes));
+        }
+        if let Some(swap_max) = config.swap_max_bytes {
+            println!("    Swap Limit: {}", format_bytes(swap_max));
+        }
+        if config.memory_limit_bytes > 0 {
+            println!(
+                "    Memory Limit: {}",
+                format_bytes(config.memory_limit_bytes)
+            );
+        }
+        if config.milli_cpus > 0 {
+            println!("    CPU Limit: {}", f64::from(config.milli_cpus) / 1000.0);
+        }
+        if let Some(weight) = config.cpu_weight {
+            println!("    CPU Weight: {weight}");
+        }
+        if !config.cpuset.is_empty() {
+            println!("    CPU Set: {}", config.cpuset);
+        }
+        if let Some(scheduling) = &config.scheduling {
+            println!("    Scheduling: {}", scheduling_summary(scheduling));
+        }
+        if !config.serial_ports.is_empty() {
+            println!("    Serial Ports: {}", config.serial_ports.join(", "));
+        }
+        if let Some(signal) = config.stop_signal {
+            println!("    Stop Signal: {signal}");
+        }
+        if let Some(timeout) = config.stop_timeout_seconds {
+            println!("    Stop Timeout: {timeout}s");
+        }
     }
 
     Ok(())
 }
 
-async fn list_containers(client: &mut ContainerServiceClient<Channel>) -> Result<()> {
-    let request = ListContainersRequest {};
+async fn list_containers(
+    client: &mut ContainerServiceClient<Channel>,
+    namespace: Option<String>,
+) -> Result<()> {
+    let request = ListContainersRequest { namespace };
     let response = client.list_containers(request).await?.into_inner();
+    print_container_table(&response.containers);
+    Ok(())
+}
 
-    if response.containers.is_empty() {
+fn format_timestamp(timestamp: &Timestamp) -> String {
+    chrono::DateTime::from_timestamp(timestamp.seconds, timestamp.nanos.max(0) as u32)
+        .map(|t| t.with_timezone(&chrono::Local).to_rfc3339())
+        .unwrap_or_default()
+}
+
+/// How the container process ended, e.g. `0` or `signal 9`.
+fn exit_status(container: &ContainerInfo) -> String {
+    match (container.exit_signal, container.exit_code) {
+        (Some(signal), _) => format!("signal {signal}"),
+        (None, Some(code)) => code.to_string(),
+        (None, None) => "-".to_string(),
+    }
+}
+
+fn print_container_table(containers: &[ContainerInfo]) {
+    if containers.is_empty() {
         println!("No containers found.");
-        return Ok(());
+        return;
     }
 
-    println!("{:<38} {:<15} IMAGE_REF", "CONTAINER_ID", "STATE");
-    println!("{:-<38} {:-<15} {:-<40}", "", "", "");
-    for container in response.containers {
+    println!(
+        "{:<38} {:<16} {:<20} {:<15} {:<10} IMAGE_REF",
+        "CONTAINER_ID", "NAMESPACE", "NAME", "STATE", "EXIT"
+    );
+    println!(
+        "{:-<38} {:-<16} {:-<20} {:-<15} {:-<10} {:-<40}",
+        "", "", "", "", "", ""
+    );
+    for container in containers {
         let state =
             ContainerState::try_from(container.state).unwrap_or(ContainerState::Unspecified);
         let image_ref = container
             .config
-            .map(|c| c.image_ref)
-            .unwrap_or_else(|| "N/A".to_string());
+            .as_ref()
+            .map(|c| c.image_ref.as_str())
+            .unwrap_or("N/A");
         println!(
-            "{:<38} {:<15} {}",
+            "{:<38} {:<16} {:<20} {:<15} {:<10} {}",
             container.container_id,
-            format!("{:?}", state),
+            container.namespace,
+            container.name.as_deref().unwrap_or("-"),
+            format!("{state:?}"),
+            exit_status(container),
             image_ref
         );
     }
+}
+
+async fn watch_containers(
+    client: &mut ContainerServiceClient<Channel>,
+    namespace: Option<String>,
+) -> Result<()> {
+    let request = StreamContainerEventsRequest {
+        namespace: namespace.clone(),
+        ..Default::default()
+    };
+    let mut events = client.stream_container_events(request).await?.into_inner();
+
+    loop {
+        let containers = client
+            .list_containers(ListContainersRequest {
+                namespace: namespace.clone(),
+            })
+            .await?
+            .into_inner()
+            .containers;
+        execute!(std::io::stdout(), Clear(ClearType::All), MoveTo(0, 0))?;
+        println!(
+            "Watching containers (last update {}). Press Ctrl+C to stop.\n",
+            chrono::Local::now().format("%H:%M:%S")
+        );
+        print_container_table(&containers);
+
+        // Deletions send no event, so the table is also refreshed
+        // periodically to drop deleted containers.
+        match tokio::time::timeout(WATCH_REFRESH_INTERVAL, events.next()).await {
+            Err(_) => continue,
+            Ok(Some(Ok(_))) => {}
+            Ok(Some(Err(status))) => anyhow::bail!("Container event stream failed: {status}"),
+            Ok(None) => return Ok(()),
+        }
+        // Coalesce bursts of events, e.g. the initial state of every container,
+        // into a single refresh of the table.
+        while let Ok(Some(event)) = tokio::time::timeout(WATCH_DEBOUNCE, events.next()).await {
+            event?;
+        }
+    }
+}
+
+async fn replay_state_journal(
+    client: &mut ContainerServiceClient<Channel>,
+    container_id: Option<String>,
+    after_sequence: u64,
+    limit: Option<u32>,
+) -> Result<()> {
+    let response = client
+        .replay_container_state_journal(ReplayContainerStateJournalRequest {
+            container_id,
+            after_sequence,
+            limit: limit.unwrap_or_default(),
+        })
+        .await?
+        .into_inner();
+
+    if response.entries.is_empty() {
+        println!("No journal entries found.");
+        return Ok(());
+    }
+    let format_state = |state: Option<i32>| match state {
+        Some(state) => format!(
+            "{:?}",
+            ContainerState::try_from(state).unwrap_or(ContainerState::Unspecified)
+        ),
+        None => "-".to_string(),
+    };
+    println!(
+        "{:>8} {:<32} {:<38} {:<15} TO",
+        "SEQUENCE", "TIME", "CONTAINER_ID", "FROM"
+    );
+    println!("{:->8} {:-<32} {:-<38} {:-<15} {:-<15}", "", "", "", "", "");
+    for entry in &response.entries {
+        println!(
+            "{:>8} {:<32} {:<38} {:<15} {}",
+            entry.sequence,
+            entry
+                .recorded_at
+                .as_ref()
+                .map(format_timestamp)
+                .unwrap_or_default(),
+            entry.container_id,
+            format_state(entry.old_state),
+            format_state(entry.new_state),
+        );
+    }
     Ok(())
 }
 
@@ -231,3 +832,125 @@ async fn delete_container(client: &mut ContainerServiceClient<Channel>, id: Stri
     println!("Successfully deleted container: {id}");
     Ok(())
 }
+
+fn current_terminal_size() -> Option<TerminalSize> {
+    crossterm::terminal::size()
+        .ok()
+        .map(|(cols, rows)| TerminalSize {
+            rows: rows.into(),
+            cols: cols.into(),
+        })
+}
+
+async fn exec_container(
+    client: &mut ContainerServiceClient<Channel>,
+    id: String,
+    command: Vec<String>,
+    interactive: bool,
+    tty: bool,
+) -> Result<i32> {
+    if tty && !std::io::stdin().is_tty() {
+        anyhow::bail!("Cannot allocate a pseudo-terminal without a TTY on stdin.");
+    }
+
+    struct RawModeGuard;
+    impl Drop for RawModeGuard {
+        fn drop(&mut self) {
+            if let Err(e) = disable_raw_mode() {
+                eprintln!("\r\nFailed to disable raw mode: {e}. Please reset your terminal.\r\n");
+            }
+        }
+    }
+
+    let _guard = if tty {
+        enable_raw_mode().context("Failed to enable terminal raw mode")?;
+        Some(RawModeGuard)
+    } else {
+        None
+    };
+
+    let (input_tx, input_rx) = mpsc::channel(10);
+    let start = ExecStart {
+        container_id: id,
+        command,
+        tty,
+        terminal_size: if tty { current_terminal_size() } else { None },
+    };
+    input_tx
+        .send(ExecContainerRequest {
+            payload: Some(exec_input::Payload::Start(start)),
+        })
+        .await
+        .context("Failed to send exec start message")?;
+
+    let response = client.exec_container(ReceiverStream::new(input_rx)).await?;
+    let mut output_stream = response.into_inner();
+
+    if tty {
+        let resize_tx = input_tx.clone();
+        tokio::spawn(async move {
+            let Ok(mut window_changes) = signal(SignalKind::window_change()) else {
+                return;
+            };
+            while window_changes.recv().await.is_some() {
+                let Some(size) = current_terminal_size() else {
+                    continue;
+                };
+                let resize_input = ExecContainerRequest {
+                    payload: Some(exec_input::Payload::Resize(size)),
+                };
+                if resize_tx.send(resize_input).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    if interactive {
+        tokio::spawn(async move {
+            let mut stdin = tokio::io::stdin();
+            let mut buffer = vec![0; 1024];
+            loop {
+                match stdin.read(&mut buffer).await {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        let data_input = ExecContainerRequest {
+                            payload: Some(exec_input::Payload::Stdin(buffer[..n].to_vec())),
+                        };
+                        if input_tx.send(data_input).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("\r\nError reading from stdin: {e}\r\n");
+                        break;
+                    }
+                }
+            }
+        });
+    } else {
+        // Closing the input side signals EOF on the stdin of the remote process.
+        drop(input_tx);
+    }
+
+    let mut stdout = tokio::io::stdout();
+    let mut stderr = tokio::io::stderr();
+    let mut exit_code = None;
+    while let Some(result) = output_stream.next().await {
+        let msg = result.context("Exec stream returned an error")?;
+        match msg.payload {
+            Some(exec_output::Payload::Stdout(data)) => {
+                stdout.write_all(&data).await?;
+                stdout.flush().await?;
+            }
+            Some(exec_output::Payload::Stderr(data)) => {
+                stderr.write_all(&data).await?;
+                stderr.flush().await?;
+            }
+            Some(exec_output::Payload::ExitCode(code)) => exit_code = Some(code),
+            None => {}
+        }
+    }
+
+    exit_code.context("Exec stream ended without reporting an exit code")
+}
@@ -0,0 +1,169 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{bail, Result};
+use clap::{Args, Subcommand};
+use feos_client_config::{ClientConfig, HostContext, TlsConfig};
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+pub struct ContextArgs {
+    #[command(subcommand)]
+    command: ContextCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ContextCommand {
+    /// List all configured contexts
+    List,
+    /// Show the name of the current context
+    Current,
+    /// Switch the current context
+    Use {
+        #[arg(required = true, help = "Name of the context to use")]
+        name: String,
+    },
+    /// Create a context or update an existing one
+    Set {
+        #[arg(required = true, help = "Name of the context")]
+        name: String,
+
+        #[arg(
+            long,
+            required = true,
+            help = "FeOS API address (e.g., https://node1.example.com:1337)"
+        )]
+        address: String,
+
+        #[arg(long, help = "CA certificate (PEM) used to verify the server")]
+        ca_cert: Option<PathBuf>,
+
+        #[arg(long, help = "Client certificate (PEM) for mutual TLS")]
+        client_cert: Option<PathBuf>,
+
+        #[arg(long, help = "Client private key (PEM) for mutual TLS")]
+        client_key: Option<PathBuf>,
+
+        #[arg(long, help = "Server name to verify instead of the address host")]
+        tls_domain: Option<String>,
+
+        #[arg(long, help = "Connect with TLS even if no certificate is given")]
+        tls: bool,
+
+        #[arg(
+            long,
+            help = "File with the API token to sign requests with, for hosts started with FEOS_API_TOKEN_FILE"
+        )]
+        token_file: Option<PathBuf>,
+    },
+    /// Delete a context
+    Delete {
+        #[arg(required = true, help = "Name of the context to delete")]
+        name: String,
+    },
+}
+
+pub async fn handle_context_command(args: ContextArgs) -> Result<()> {
+    let mut config = ClientConfig::load()?;
+
+    match args.command {
+        ContextCommand::List => list_contexts(&config),
+        ContextCommand::Current => match &config.current_context {
+            Some(name) => println!("{name}"),
+            None => println!("No current context set."),
+        },
+        ContextCommand::Use { name } => {
+            if config.get(&name).is_none() {
+                bail!("Context '{name}' not found");
+            }
+            config.current_context = Some(name.clone());
+            config.save()?;
+            println!("Switched to context '{name}'.");
+        }
+        ContextCommand::Set {
+            name,
+            address,
+            ca_cert,
+            client_cert,
+            client_key,
+            tls_domain,
+            tls,
+            token_file,
+        } => {
+            if client_cert.is_some() != client_key.is_some() {
+                bail!("--client-cert and --client-key must be given together");
+            }
+            let use_tls = tls
+                || ca_cert.is_some()
+                || client_cert.is_some()
+                || tls_domain.is_some()
+                || address.starts_with("https://");
+            let tls = use_tls.then(|| TlsConfig {
+                ca_cert,
+                client_cert,
+                client_key,
+                domain_name: tls_domain,
+            });
+
+            let is_new = config.get(&name).is_none();
+            config.upsert(HostContext {
+                name: name.clone(),
+                address,
+                tls,
+                token_file,
+            });
+            if config.current_context.is_none() {
+                config.current_context = Some(name.clone());
+            }
+            config.save()?;
+            if is_new {
+                println!("Context '{name}' created.");
+            } else {
+                println!("Context '{name}' updated.");
+            }
+        }
+        ContextCommand::Delete { name } => {
+            if config.remove(&name).is_none() {
+                bail!("Context '{name}' not found");
+            }
+            config.save()?;
+            println!("Context '{name}' deleted.");
+        }
+    }
+
+    Ok(())
+}
+
+fn list_contexts(config: &ClientConfig) {
+    if config.contexts.is_empty() {
+        println!("No contexts configured. Use 'feos-cli context set' to add one.");
+        return;
+    }
+
+    println!(
+        "{:<8} {:<20} {:<40} {:<5} TOKEN",
+        "CURRENT", "NAME", "ADDRESS", "TLS"
+    );
+    println!("{:-<8} {:-<20} {:-<40} {:-<5} {:-<5}", "", "", "", "", "");
+    for context in &config.contexts {
+        let current = if config.current_context.as_deref() == Some(context.name.as_str()) {
+            "*"
+        } else {
+            ""
+        };
+        let tls = match &context.tls {
+            Some(tls) if tls.client_cert.is_some() => "mTLS",
+            Some(_) => "yes",
+            None => "no",
+        };
+        let token = if context.token_file.is_some() {
+            "yes"
+        } else {
+            "no"
+        };
+        println!(
+            "{:<8} {:<20} {:<40} {:<5} {}",
+            current, context.name, context.address, tls, token
+        );
+    }
+}
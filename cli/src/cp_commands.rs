@@ -0,0 +1,657 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::{self, Channel};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::Args;
+use feos_proto::container_service::{
+    container_service_client::ContainerServiceClient, exec_container_request,
+    exec_container_response, ExecContainerRequest, ExecStart,
+};
+use feos_proto::vm_service::{
+    push_guest_file_request, vm_service_client::VmServiceClient, PullGuestFileRequest,
+    PushGuestFileRequest, PushGuestFileStart,
+};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+
+const STDIN_CHUNK_SIZE: usize = 64 * 1024;
+/// How many chunks of an archive are in flight between tar and the exec.
+const ARCHIVE_CHUNKS_IN_FLIGHT: usize = 4;
+
+#[derive(Args, Debug)]
+pub struct CpArgs {
+    #[arg(
+        short,
+        long,
+        env = "FEOS_ADDRESS",
+        help = "FeOS API address, overriding the address of the selected context"
+    )]
+    pub address: Option<String>,
+
+    #[arg(help = "Source: a local path or [container/|vm/]<id>:<path>")]
+    source: String,
+
+    #[arg(
+        help = "Destination: a local path or [container/|vm/]<id>:<path>. A trailing '/' copies into that directory"
+    )]
+    destination: String,
+}
+
+/// Splits `<id>:<path>` with a non-empty ID.
+fn parse_remote(s: &str) -> Option<(&str, &str)> {
+    s.split_once(':').filter(|(id, _)| !id.is_empty())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Location {
+    Local(PathBuf),
+    Container { id: String, path: String },
+    Vm { id: String, path: String },
+}
+
+impl Location {
+    /// Parses `container/<id>:<path>`, `vm/<id>:<path>` or `<id>:<path>` (a
+    /// container). Anything else is a local path; use `./a:b` for local paths
+    /// containing a colon.
+    fn parse(spec: &str) -> Self {
+        if let Some((id, path)) = spec.strip_prefix("container/").and_then(parse_remote) {
+            return Location::Container {
+                id: id.to_string(),
+                path: path.to_string(),
+            };
+        }
+        if let Some((id, path)) = spec.strip_prefix("vm/").and_then(parse_remote) {
+            return Location::Vm {
+                id: id.to_string(),
+                path: path.to_string(),
+            };
+        }
+        match parse_remote(spec) {
+            Some((id, path)) if !id.contains('/') => Location::Container {
+                id: id.to_string(),
+                path: path.to_string(),
+            },
+            _ => Location::Local(PathBuf::from(spec)),
+        }
+    }
+}
+
+pub async fn handle_cp_command(args: CpArgs, context: Option<&str>) -> Result<()> {
+    let source = Location::parse(&args.source);
+    let destination = Location::parse(&args.destination);
+
+    match (source, destination) {
+        (Location::Local(src), Location::Container { id, path }) => {
+            let mut client = connect(&args.address, context).await?;
+            upload_to_container(&mut client, &src, &id, &path).await
+        }
+        (Location::Container { id, path }, Location::Local(dst)) => {
+            let mut client = connect(&args.address, context).await?;
+            download_from_container(&mut client, &id, &path, &dst).await
+        }
+        (Location::Local(src), Location::Vm { id, path }) => {
+            let mut client = connect_vm(&args.address, context).await?;
+            upload_to_vm(&mut client, &src, &id, &path).await
+        }
+        (Location::Vm { id, path }, Location::Local(dst)) => {
+            let mut client = connect_vm(&args.address, context).await?;
+            download_from_vm(&mut client, &id, &path, &dst).await
+        }
+        (Location::Local(_), Location::Local(_)) => {
+            bail!("One of source or destination must be a container or VM path")
+        }
+        _ => bail!("Copying directly between two workloads is not supported"),
+    }
+}
+
+async fn connect(
+    address: &Option<String>,
+    context: Option<&str>,
+) -> Result<ContainerServiceClient<Channel>> {
+    let channel = config::connect(address.as_deref(), context)
+        .await
+        .context("Failed to connect to container service")?;
+    Ok(ContainerServiceClient::new(channel))
+}
+
+async fn connect_vm(
+    address: &Option<String>,
+    context: Option<&str>,
+) -> Result<VmServiceClient<Channel>> {
+    let channel = config::connect(address.as_deref(), context)
+        .await
+        .context("Failed to connect to VM service")?;
+    Ok(VmServiceClient::new(channel))
+}
+
+/// Splits a container path into the directory `tar` runs in and the entry name.
+/// A trailing '/' means "into this directory", keeping `default_name`.
+fn split_remote_path(path: &str, default_name: &str) -> Result<(String, String)> {
+    if path.is_empty() {
+        bail!("Container path must not be empty");
+    }
+    if path.ends_with('/') {
+        return Ok((path.to_string(), default_name.to_string()));
+    }
+    let path = Path::new(path);
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow!("Invalid container path '{}'", path.display()))?;
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_string_lossy().into_owned(),
+        _ => ".".to_string(),
+    };
+    Ok((dir, name.to_string()))
+}
+
+async fn upload_to_container(
+    client: &mut ContainerServiceClient<Channel>,
+    src: &Path,
+    container_id: &str,
+    dst: &str,
+) -> Result<()> {
+    let src_name = src
+        .canonicalize()
+        .with_context(|| format!("Failed to access {}", src.display()))?
+        .file_name()
+        .and_then(|name| name.to_str())
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("Cannot determine the name of {}", src.display()))?;
+    let (dir, name) = split_remote_path(dst, &src_name)?;
+
+    // The archive is streamed into the exec as it is written.
+    let (archive_tx, archive_rx) = mpsc::channel(ARCHIVE_CHUNKS_IN_FLIGHT);
+    let src_path = src.to_path_buf();
+    let archiver = tokio::task::spawn_blocking(move || -> Result<()> {
+        let mut builder = tar::Builder::new(ChunkWriter::new(archive_tx));
+        builder.follow_symlinks(false);
+        if src_path.is_dir() {
+            builder.append_dir_all(&name, &src_path)?;
+        } else {
+            builder.append_path_with_name(&src_path, &name)?;
+        }
+        builder.into_inner()?.flush()?;
+        Ok(())
+    });
+
+    let command = ["tar", "-x", "-f", "-", "-C", dir.as_str()].map(String::from);
+    let output = exec_in_container(client, container_id, command.to_vec(), archive_rx, None).await;
+    let archived = archiver.await?;
+    // A failing tar in the container stops reading the archive, so its
+    // error comes before the one of writing the archive.
+    let output = output?;
+    match output.exit_code {
+        Some(0) => archived?,
+        Some(code) => bail!(
+            "Extracting in container {container_id} failed with exit code {code}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+        None => {
+            archived?;
+            bail!("The exec in container {container_id} ended without an exit code");
+        }
+    }
+    println!("Copied {} to {container_id}:{dst}", src.display());
+    Ok(())
+}
+
+async fn download_from_container(
+    client: &mut ContainerServiceClient<Channel>,
+    container_id: &str,
+    src: &str,
+    dst: &Path,
+) -> Result<()> {
+    let (dir, name) = split_remote_path(src.trim_end_matches('/'), "")?;
+
+    // Like `cp`, copy into an existing directory, otherwise create `dst` itself.
+    let (target_dir, target_name) = if dst.is_dir() || dst.to_string_lossy().ends_with('/') {
+        (dst.to_path_buf(), name.clone())
+    } else {
+        let target_name = dst
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| anyhow!("Invalid destination {}", dst.display()))?
+            .to_string();
+        let parent = match dst.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        (parent, target_name)
+    };
+
+    // The archive is unpacked as it comes out of the exec.
+    let (archive_tx, archive_rx) = mpsc::channel(ARCHIVE_CHUNKS_IN_FLIGHT);
+    let archive_name = name.clone();
+    let unpacker = tokio::task::spawn_blocking(move || {
+        unpack_renamed(
+            ChunkReader::new(archive_rx),
+            &archive_name,
+            &target_dir,
+            &target_name,
+        )
+    });
+    let command = ["tar", "-c", "-f", "-", "-C", dir.as_str(), name.as_str()].map(String::from);
+    let (_, no_stdin) = mpsc::channel(1);
+    let output = exec_in_container(
+        client,
+        container_id,
+        command.to_vec(),
+        no_stdin,
+        Some(archive_tx),
+    )
+    .await;
+    let unpacked = unpacker.await?;
+    let output = output?;
+    match output.exit_code {
+        Some(0) => unpacked?,
+        Some(code) => bail!(
+            "Archiving in container {container_id} failed with exit code {code}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+        // The exec is cut short when unpacking fails.
+        None => {
+            unpacked?;
+            bail!("The exec in container {container_id} ended without an exit code");
+        }
+    }
+    println!("Copied {container_id}:{src} to {}", dst.display());
+    Ok(())
+}
+
+/// Fails unless `path`, or the part of it that exists, resolves to a path
+/// inside `root`, which is canonical. Symlinks unpacked before cannot
+/// redirect later entries out of the target directory that way.
+fn check_inside(root: &Path, path: &Path) -> Result<()> {
+    let existing = path
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or(root);
+    let resolved = existing
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {}", existing.display()))?;
+    if !resolved.starts_with(root) {
+        bail!(
+            "Refusing to unpack through {}, which leads out of the target directory",
+            existing.display()
+        );
+    }
+    Ok(())
+}
+
+/// Unpacks `archive` into `target_dir`, renaming the top-level entry `name`
+/// to `target_name`. Entries escaping the target directory, also through
+/// symlinks in the archive, are rejected, as are hard links.
+fn unpack_renamed(
+    archive: impl Read,
+    name: &str,
+    target_dir: &Path,
+    target_name: &str,
+) -> Result<()> {
+    std::fs::create_dir_all(target_dir)
+        .with_context(|| format!("Failed to create {}", target_dir.display()))?;
+    let root = target_dir
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {}", target_dir.display()))?;
+    let mut archive = tar::Archive::new(archive);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        if entry.header().entry_type().is_hard_link() {
+            bail!("Refusing to unpack hard link '{}'", path.display());
+        }
+        let relative = path
+            .strip_prefix(name)
+            .with_context(|| format!("Unexpected entry '{}' in archive", path.display()))?;
+        if relative
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)))
+        {
+            bail!("Refusing to unpack unsafe path '{}'", path.display());
+        }
+        let destination = root.join(target_name).join(relative);
+        let parent = destination.parent().unwrap_or(root.as_path());
+        check_inside(&root, parent)?;
+        std::fs::create_dir_all(parent)?;
+        check_inside(&root, parent)?;
+        entry
+            .unpack(&destination)
+            .with_context(|| format!("Failed to write {}", destination.display()))?;
+    }
+    // Reads the padding after the last entry, so the exec is not cut short.
+    io::copy(&mut archive.into_inner(), &mut io::sink())?;
+    Ok(())
+}
+
+/// The path in the guest a local file is copied to. The guest agent needs
+/// an absolute path, and a trailing '/' copies into that directory.
+fn guest_file_path(path: &str, src_name: &str) -> Result<String> {
+    if !path.starts_with('/') {
+        bail!("VM path '{path}' must be absolute");
+    }
+    if path.ends_with('/') {
+        return Ok(format!("{path}{src_name}"));
+    }
+    Ok(path.to_string())
+}
+
+/// The local path a file of the guest is copied to: into `dst` if it is a
+/// directory, else `dst` itself.
+fn local_file_path(dst: &Path, src: &str) -> Result<PathBuf> {
+    if dst.is_dir() || dst.to_string_lossy().ends_with('/') {
+        let name = Path::new(src)
+            .file_name()
+            .ok_or_else(|| anyhow!("Invalid VM path '{src}'"))?;
+        return Ok(dst.join(name));
+    }
+    Ok(dst.to_path_buf())
+}
+
+/// Copies a regular file into the guest, keeping its permission bits.
+async fn upload_to_vm(
+    client: &mut VmServiceClient<Channel>,
+    src: &Path,
+    vm_id: &str,
+    dst: &str,
+) -> Result<()> {
+    let metadata = tokio::fs::metadata(src)
+        .await
+        .with_context(|| format!("Failed to access {}", src.display()))?;
+    if !metadata.is_file() {
+        bail!("Only regular files can be copied to or from VMs");
+    }
+    let src_name = src
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow!("Cannot determine the name of {}", src.display()))?;
+    let path = guest_file_path(dst, src_name)?;
+    let content = tokio::fs::read(src)
+        .await
+        .with_context(|| format!("Failed to read {}", src.display()))?;
+
+    let start = PushGuestFileRequest {
+        payload: Some(push_guest_file_request::Payload::Start(
+            PushGuestFileStart {
+                vm_id: vm_id.to_string(),
+                path: path.clone(),
+                mode: metadata.permissions().mode() & 0o7777,
+                create_parents: false,
+            },
+        )),
+    };
+    let chunks: Vec<PushGuestFileRequest> = content
+        .chunks(STDIN_CHUNK_SIZE)
+        .map(|chunk| PushGuestFileRequest {
+            payload: Some(push_guest_file_request::Payload::Data(chunk.to_vec())),
+        })
+        .collect();
+    let requests = tokio_stream::once(start).chain(tokio_stream::iter(chunks));
+    client.push_guest_file(requests).await?;
+    println!("Copied {} to vm/{vm_id}:{path}", src.display());
+    Ok(())
+}
+
+async fn download_from_vm(
+    client: &mut VmServiceClient<Channel>,
+    vm_id: &str,
+    src: &str,
+    dst: &Path,
+) -> Result<()> {
+    let target = local_file_path(dst, src)?;
+    let request = PullGuestFileRequest {
+        vm_id: vm_id.to_string(),
+        path: src.to_string(),
+    };
+    let mut chunks = client.pull_guest_file(request).await?.into_inner();
+    // The first chunk tells whether the guest could open the file, so a
+    // failed copy does not leave an empty file behind.
+    let first = chunks.next().await.transpose()?;
+    let mut file = tokio::fs::File::create(&target)
+        .await
+        .with_context(|| format!("Failed to create {}", target.display()))?;
+    if let Some(chunk) = first {
+        file.write_all(&chunk.data).await?;
+    }
+    while let Some(chunk) = chunks.next().await {
+        file.write_all(&chunk?.data).await?;
+    }
+    file.flush().await?;
+    println!("Copied vm/{vm_id}:{src} to {}", target.display());
+    Ok(())
+}
+
+/// Sends what is written to it in chunks, for streaming archives into an
+/// exec from a blocking task.
+struct ChunkWriter {
+    tx: mpsc::Sender<Vec<u8>>,
+    buffer: Vec<u8>,
+}
+
+impl ChunkWriter {
+    fn new(tx: mpsc::Sender<Vec<u8>>) -> Self {
+        Self {
+            tx,
+            buffer: Vec::with_capacity(STDIN_CHUNK_SIZE),
+        }
+    }
+
+    fn send_buffer(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buffer, Vec::with_capacity(STDIN_CHUNK_SIZE));
+        self.tx
+            .blocking_send(chunk)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the exec has ended"))
+    }
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let len = data.len().min(STDIN_CHUNK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&data[..len]);
+        if self.buffer.len() == STDIN_CHUNK_SIZE {
+            self.send_buffer()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_buffer()
+    }
+}
+
+/// Reads the chunks an exec streams out, from a blocking task.
+struct ChunkReader {
+    rx: mpsc::Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+impl ChunkReader {
+    fn new(rx: mpsc::Receiver<Vec<u8>>) -> Self {
+        Self {
+            rx,
+            chunk: Vec::new(),
+            position: 0,
+        }
+    }
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.chunk.len() {
+            match self.rx.blocking_recv() {
+                Some(chunk) => {
+                    self.chunk = chunk;
+                    self.position = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.chunk.len() - self.position);
+        buf[..len].copy_from_slice(&self.chunk[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+struct ExecOutput {
+    stderr: Vec<u8>,
+    /// None if the exec ended before the command exited.
+    exit_code: Option<i32>,
+}
+
+/// Runs a non-interactive command in the container, streaming `stdin` into
+/// it and its standard output to `stdout`. The end of `stdin` closes the
+/// standard input of the command. The exec is cut short if `stdout` is
+/// closed.
+async fn exec_in_container(
+    client: &mut ContainerServiceClient<Channel>,
+    container_id: &str,
+    command: Vec<String>,
+    stdin: mpsc::Receiver<Vec<u8>>,
+    stdout: Option<mpsc::Sender<Vec<u8>>>,
+) -> Result<ExecOutput> {
+    let start = ExecContainerRequest {
+        payload: Some(exec_container_request::Payload::Start(ExecStart {
+            container_id: container_id.to_string(),
+            command,
+            tty: false,
+            terminal_size: None,
+        })),
+    };
+    let stdin_chunks = ReceiverStream::new(stdin).map(|chunk| ExecContainerRequest {
+        payload: Some(exec_container_request::Payload::Stdin(chunk)),
+    });
+    let requests = tokio_stream::once(start).chain(stdin_chunks);
+
+    let mut responses = client.exec_container(requests).await?.into_inner();
+    let mut output = ExecOutput {
+        stderr: Vec::new(),
+        exit_code: None,
+    };
+    while let Some(response) = responses.next().await {
+        match response?.payload {
+            Some(exec_container_response::Payload::Stdout(data)) => {
+                if let Some(stdout) = &stdout {
+                    if stdout.send(data).await.is_err() {
+                        break;
+                    }
+                }
+            }
+            Some(exec_container_response::Payload::Stderr(data)) => {
+                output.stderr.extend_from_slice(&data)
+            }
+            Some(exec_container_response::Payload::ExitCode(code)) => output.exit_code = Some(code),
+            None => {}
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_locations() {
+        assert_eq!(
+            Location::parse("container/web:/etc/nginx"),
+            Location::Container {
+                id: "web".to_string(),
+                path: "/etc/nginx".to_string()
+            }
+        );
+        assert_eq!(
+            Location::parse("web:/tmp/"),
+            Location::Container {
+                id: "web".to_string(),
+                path: "/tmp/".to_string()
+            }
+        );
+        assert_eq!(
+            Location::parse("vm/abc:/root"),
+            Location::Vm {
+                id: "abc".to_string(),
+                path: "/root".to_string()
+            }
+        );
+        assert_eq!(
+            Location::parse("./a:b"),
+            Location::Local(PathBuf::from("./a:b"))
+        );
+        assert_eq!(
+            Location::parse("/tmp/file"),
+            Location::Local(PathBuf::from("/tmp/file"))
+        );
+    }
+
+    #[test]
+    fn resolves_vm_file_paths() {
+        assert_eq!(
+            guest_file_path("/etc/app.conf", "local.conf").unwrap(),
+            "/etc/app.conf"
+        );
+        assert_eq!(
+            guest_file_path("/tmp/", "local.conf").unwrap(),
+            "/tmp/local.conf"
+        );
+        assert!(guest_file_path("tmp/app.conf", "local.conf").is_err());
+
+        let dir = std::env::temp_dir();
+        assert_eq!(
+            local_file_path(&dir, "/var/log/syslog").unwrap(),
+            dir.join("syslog")
+        );
+        assert_eq!(
+            local_file_path(Path::new("out.log"), "/var/log/syslog").unwrap(),
+            PathBuf::from("out.log")
+        );
+    }
+
+    #[test]
+    fn archives_cannot_write_through_their_symlinks() {
+        let outside = tempfile::tempdir().unwrap();
+        let target = tempfile::tempdir().unwrap();
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        builder
+            .append_link(&mut header, "app/escape", outside.path())
+            .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "app/escape/owned", &b"evil"[..])
+            .unwrap();
+        let archive = builder.into_inner().unwrap();
+
+        assert!(unpack_renamed(&archive[..], "app", target.path(), "copy").is_err());
+        assert!(!outside.path().join("owned").exists());
+        assert!(target.path().join("copy/escape").is_symlink());
+    }
+
+    #[test]
+    fn splits_remote_paths() {
+        assert_eq!(
+            split_remote_path("/etc/hosts", "x").unwrap(),
+            ("/etc".to_string(), "hosts".to_string())
+        );
+        assert_eq!(
+            split_remote_path("/tmp/", "file.txt").unwrap(),
+            ("/tmp/".to_string(), "file.txt".to_string())
+        );
+        assert_eq!(
+            split_remote_path("hosts", "x").unwrap(),
+            (".".to_string(), "hosts".to_string())
+        );
+    }
+}
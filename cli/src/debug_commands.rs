@@ -0,0 +1,279 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::{self, Channel};
+use crate::host_commands::{parse_time, to_timestamp};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use clap::{Args, Subcommand, ValueEnum};
+use feos_proto::container_service::{
+    container_service_client::ContainerServiceClient, ListContainersRequest,
+};
+use feos_proto::host_service::{
+    host_service_client::HostServiceClient, CreateDebugBundleRequest, ExportLogsRequest,
+    LogArchiveCompression,
+};
+use feos_proto::vm_service::{vm_service_client::VmServiceClient, ListVmsRequest};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tokio_stream::StreamExt;
+
+#[derive(Args, Debug)]
+pub struct DebugArgs {
+    #[arg(
+        short,
+        long,
+        global = true,
+        env = "FEOS_ADDRESS",
+        help = "FeOS API address, overriding the address of the selected context"
+    )]
+    pub address: Option<String>,
+
+    #[command(subcommand)]
+    command: DebugCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DebugCommand {
+    /// Collect logs, workload state, network config and versions into a tarball
+    Bundle {
+        #[arg(
+            short,
+            long,
+            help = "Output file (default: feos-debug-<timestamp>.tar.gz in the current directory)"
+        )]
+        output: Option<PathBuf>,
+        #[arg(
+            long,
+            value_parser = parse_time,
+            help = "Include logs from this time on (RFC 3339), all kept logs if omitted"
+        )]
+        since: Option<DateTime<Utc>>,
+        #[arg(
+            long,
+            value_parser = parse_time,
+            help = "Include logs up to this time (RFC 3339)"
+        )]
+        until: Option<DateTime<Utc>>,
+    },
+    /// Export FeOS, kernel and per-workload logs of a time window into an archive
+    Logs {
+        #[arg(
+            short,
+            long,
+            help = "Output file (default: feos-logs-<timestamp>.tar.gz or .tar.zst in the current directory)"
+        )]
+        output: Option<PathBuf>,
+        #[arg(long, value_parser = parse_time, help = "Start of the window (RFC 3339)")]
+        since: Option<DateTime<Utc>>,
+        #[arg(long, value_parser = parse_time, help = "End of the window (RFC 3339)")]
+        until: Option<DateTime<Utc>>,
+        #[arg(long, value_enum, default_value_t = ArchiveCompression::Gzip)]
+        compression: ArchiveCompression,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ArchiveCompression {
+    Gzip,
+    Zstd,
+}
+
+impl ArchiveCompression {
+    fn extension(self) -> &'static str {
+        match self {
+            ArchiveCompression::Gzip => "tar.gz",
+            ArchiveCompression::Zstd => "tar.zst",
+        }
+    }
+}
+
+impl From<ArchiveCompression> for LogArchiveCompression {
+    fn from(compression: ArchiveCompression) -> Self {
+        match compression {
+            ArchiveCompression::Gzip => LogArchiveCompression::Gzip,
+            ArchiveCompression::Zstd => LogArchiveCompression::Zstd,
+        }
+    }
+}
+
+pub async fn handle_debug_command(args: DebugArgs, context: Option<&str>) -> Result<()> {
+    let channel = config::connect(args.address.as_deref(), context)
+        .await
+        .context("Failed to connect to FeOS")?;
+
+    match args.command {
+        DebugCommand::Bundle {
+            output,
+            since,
+            until,
+        } => create_bundle(channel, output, since, until).await,
+        DebugCommand::Logs {
+            output,
+            since,
+            until,
+            compression,
+        } => export_logs(channel, output, since, until, compression).await,
+    }
+}
+
+/// Downloads the log archive of a time window as compressed by the daemon.
+async fn fetch_log_archive(
+    channel: Channel,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    compression: ArchiveCompression,
+) -> Result<Vec<u8>> {
+    let request = ExportLogsRequest {
+        start_time: since.map(to_timestamp),
+        end_time: until.map(to_timestamp),
+        compression: LogArchiveCompression::from(compression) as i32,
+    };
+    let mut stream = HostServiceClient::new(channel)
+        .export_logs(request)
+        .await
+        .context("Failed to request log archive from host service")?
+        .into_inner();
+    let mut archive = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        archive.extend_from_slice(&chunk.context("Log archive stream failed")?.data);
+    }
+    Ok(archive)
+}
+
+async fn export_logs(
+    channel: Channel,
+    output: Option<PathBuf>,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    compression: ArchiveCompression,
+) -> Result<()> {
+    let output = output.unwrap_or_else(|| {
+        PathBuf::from(format!(
+            "feos-logs-{}.{}",
+            chrono::Local::now().format("%Y%m%d-%H%M%S"),
+            compression.extension()
+        ))
+    });
+
+    println!("Exporting logs...");
+    let archive = fetch_log_archive(channel, since, until, compression).await?;
+    tokio::fs::write(&output, &archive)
+        .await
+        .with_context(|| format!("Failed to write {}", output.display()))?;
+
+    println!("Log archive written to {}", output.display());
+    Ok(())
+}
+
+async fn create_bundle(
+    channel: Channel,
+    output: Option<PathBuf>,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+) -> Result<()> {
+    let name = format!(
+        "feos-debug-{}",
+        chrono::Local::now().format("%Y%m%d-%H%M%S")
+    );
+    let output = output.unwrap_or_else(|| PathBuf::from(format!("{name}.tar.gz")));
+
+    println!("Collecting host diagnostics...");
+    let mut host_archive = Vec::new();
+    let mut stream = HostServiceClient::new(channel.clone())
+        .create_debug_bundle(CreateDebugBundleRequest {})
+        .await
+        .context("Failed to request debug bundle from host service")?
+        .into_inner();
+    while let Some(chunk) = stream.next().await {
+        host_archive.extend_from_slice(&chunk.context("Debug bundle stream failed")?.data);
+    }
+
+    println!("Exporting logs...");
+    let log_archive =
+        match fetch_log_archive(channel.clone(), since, until, ArchiveCompression::Gzip).await {
+            Ok(archive) => {
+                let mut decompressed = Vec::new();
+                GzDecoder::new(archive.as_slice())
+                    .read_to_end(&mut decompressed)
+                    .context("Invalid log archive from host service")?;
+                decompressed
+            }
+            Err(e) => {
+                eprintln!("Warning: {e:#}, the bundle will only contain the buffered logs");
+                Vec::new()
+            }
+        };
+
+    println!("Collecting VM and container state...");
+    let vms = match VmServiceClient::new(channel.clone())
+        .list_vms(ListVmsRequest::default())
+        .await
+    {
+        Ok(response) => format!("{:#?}\n", response.into_inner().vms),
+        Err(status) => format!("Failed to list VMs: {status}\n"),
+    };
+    let containers = match ContainerServiceClient::new(channel)
+        .list_containers(ListContainersRequest::default())
+        .await
+    {
+        Ok(response) => format!("{:#?}\n", response.into_inner().containers),
+        Err(status) => format!("Failed to list containers: {status}\n"),
+    };
+
+    let extra_files = vec![
+        ("state/vms.txt".to_string(), vms.into_bytes()),
+        ("state/containers.txt".to_string(), containers.into_bytes()),
+    ];
+    let output_path = output.clone();
+    tokio::task::spawn_blocking(move || {
+        let archives = [("", host_archive), ("log-archive/", log_archive)];
+        write_bundle(&output_path, &name, &archives, extra_files)
+    })
+    .await??;
+
+    println!("Debug bundle written to {}", output.display());
+    Ok(())
+}
+
+/// Writes a gzipped tarball with all entries below a `name/` directory: the
+/// tar archives returned by the daemon, each below its directory prefix, plus
+/// the client-collected `extra_files`.
+fn write_bundle(
+    output: &Path,
+    name: &str,
+    archives: &[(&str, Vec<u8>)],
+    extra_files: Vec<(String, Vec<u8>)>,
+) -> Result<()> {
+    let file = std::fs::File::create(output)
+        .with_context(|| format!("Failed to create {}", output.display()))?;
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+    for (prefix, archive) in archives {
+        let mut archive = tar::Archive::new(archive.as_slice());
+        for entry in archive
+            .entries()
+            .context("Invalid archive from host service")?
+        {
+            let mut entry = entry?;
+            let path = format!("{name}/{prefix}{}", entry.path()?.display());
+            let mut header = entry.header().clone();
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content)?;
+            builder.append_data(&mut header, path, content.as_slice())?;
+        }
+    }
+
+    let mtime = chrono::Utc::now().timestamp().max(0) as u64;
+    for (path, content) in extra_files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        builder.append_data(&mut header, format!("{name}/{path}"), content.as_slice())?;
+    }
+
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
@@ -0,0 +1,385 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::{self, Channel};
+use crate::host_commands::{parse_time, to_timestamp};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use clap::{Args, ValueEnum};
+use feos_proto::container_service::{
+    container_service_client::ContainerServiceClient, ContainerEvent, ContainerOomEvent,
+    ContainerState, ContainerStateChangedEvent, ListContainerEventsRequest, ListContainersRequest,
+    StreamContainerEventsRequest,
+};
+use feos_proto::vm_service::{
+    vm_service_client::VmServiceClient, ListVmEventsRequest, ListVmsRequest, StreamVmEventsRequest,
+    VmEvent, VmState, VmStateChangedEvent,
+};
+use prost::Message;
+use prost_types::Timestamp;
+use serde::Serialize;
+use std::pin::Pin;
+use tokio_stream::{Stream, StreamExt};
+
+type EventStream = Pin<Box<dyn Stream<Item = Result<EventRecord>> + Send>>;
+
+#[derive(Args, Debug)]
+pub struct EventsArgs {
+    #[arg(
+        short,
+        long,
+        env = "FEOS_ADDRESS",
+        help = "FeOS API address, overriding the address of the selected context"
+    )]
+    pub address: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        help = "Keep streaming new events instead of printing the current state and exiting"
+    )]
+    follow: bool,
+
+    #[arg(
+        long = "type",
+        value_enum,
+        help = "Only show events of these resource types (repeatable, default: all)"
+    )]
+    types: Vec<ResourceType>,
+
+    #[arg(long, help = "Only show events of the resource with this ID")]
+    id: Option<String>,
+
+    #[arg(
+        long,
+        value_parser = parse_time,
+        help = "Show the events recorded at or after this time instead of the current state (RFC 3339, e.g. 2025-01-01T12:00:00Z)"
+    )]
+    since: Option<DateTime<Utc>>,
+
+    #[arg(
+        long,
+        value_parser = parse_time,
+        conflicts_with = "follow",
+        help = "Show the events recorded before this time instead of the current state (RFC 3339)"
+    )]
+    until: Option<DateTime<Utc>>,
+
+    #[arg(
+        long,
+        help = "Show at most this many of the newest recorded events per resource type [default: 1000]"
+    )]
+    limit: Option<u32>,
+
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Pretty)]
+    output: OutputFormat,
+}
+
+#[derive(ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum ResourceType {
+    Vm,
+    Container,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum OutputFormat {
+    Pretty,
+    Json,
+}
+
+/// A VM or container event normalized into one shape for printing.
+#[derive(Serialize, Debug)]
+struct EventRecord {
+    time: String,
+    resource: ResourceType,
+    id: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    event_id: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    component: String,
+    kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    state: Option<String>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    reason: String,
+}
+
+impl EventRecord {
+    fn new(resource: ResourceType, id: String, kind: &str) -> Self {
+        Self {
+            time: chrono::Local::now().to_rfc3339(),
+            resource,
+            id,
+            event_id: String::new(),
+            component: String::new(),
+            kind: kind.to_string(),
+            state: None,
+            reason: String::new(),
+        }
+    }
+
+    fn from_vm_event(event: VmEvent) -> Self {
+        let mut record = Self::new(ResourceType::Vm, event.vm_id, "Unknown");
+        record.event_id = event.id;
+        record.component = event.component_id;
+        if let Some(data) = event.data {
+            record.kind = event_kind(&data.type_url).to_string();
+            if record.kind == "VmStateChangedEvent" {
+                if let Ok(change) = VmStateChangedEvent::decode(&*data.value) {
+                    let state = VmState::try_from(change.new_state).unwrap_or(VmState::Unspecified);
+                    record.state = Some(format!("{state:?}"));
+                    record.reason = change.reason;
+                }
+            }
+        }
+        record
+    }
+
+    /// Dates a recorded event with the time it was recorded instead of now.
+    fn recorded_at(mut self, recorded_at: Option<Timestamp>) -> Self {
+        if let Some(time) =
+            recorded_at.and_then(|time| DateTime::from_timestamp(time.seconds, time.nanos as u32))
+        {
+            self.time = time.with_timezone(&chrono::Local).to_rfc3339();
+        }
+        self
+    }
+
+    fn from_container_event(event: ContainerEvent) -> Self {
+        let mut record = Self::new(ResourceType::Container, event.container_id, "Unknown");
+        record.event_id = event.id;
+        if let Some(data) = event.data {
+            record.kind = event_kind(&data.type_url).to_string();
+            if record.kind == "ContainerStateChangedEvent" {
+                if let Ok(change) = ContainerStateChangedEvent::decode(&*data.value) {
+                    let state = ContainerState::try_from(change.new_state)
+                        .unwrap_or(ContainerState::Unspecified);
+                    record.state = Some(format!("{state:?}"));
+                    record.reason = change.reason;
+                }
+            } else if record.kind == "ContainerOomEvent" {
+                if let Ok(oom) = ContainerOomEvent::decode(&*data.value) {
+                    record.reason = format!("OOM kills: {}", oom.oom_kill_count);
+                }
+            }
+        }
+        record
+    }
+}
+
+/// Strips the package from a type URL, e.g.
+/// `type.googleapis.com/feos.vm.vmm.api.v1.VmStateChangedEvent` becomes `VmStateChangedEvent`.
+fn event_kind(type_url: &str) -> &str {
+    type_url.rsplit(['/', '.']).next().unwrap_or(type_url)
+}
+
+pub async fn handle_events_command(args: EventsArgs, context: Option<&str>) -> Result<()> {
+    let channel = config::connect(args.address.as_deref(), context)
+        .await
+        .context("Failed to connect to FeOS")?;
+    let wants = |resource: ResourceType| args.types.is_empty() || args.types.contains(&resource);
+    let matches_id = |record: &EventRecord| args.id.as_ref().is_none_or(|id| &record.id == id);
+
+    if matches!(args.output, OutputFormat::Pretty) {
+        print_header();
+    }
+
+    let history = args.since.is_some() || args.until.is_some();
+    if history {
+        let mut records = Vec::new();
+        if wants(ResourceType::Vm) {
+            records.extend(recorded_vm_events(&channel, &args).await?);
+        }
+        if wants(ResourceType::Container) {
+            records.extend(recorded_container_events(&channel, &args).await?);
+        }
+        records.sort_by_key(|record| DateTime::parse_from_rfc3339(&record.time).ok());
+        for record in &records {
+            print_record(record, args.output)?;
+        }
+    }
+
+    if !args.follow {
+        if history {
+            return Ok(());
+        }
+        let mut records = Vec::new();
+        if wants(ResourceType::Vm) {
+            records.extend(current_vm_states(&channel).await?);
+        }
+        if wants(ResourceType::Container) {
+            records.extend(current_container_states(&channel).await?);
+        }
+        for record in records.iter().filter(|record| matches_id(record)) {
+            print_record(record, args.output)?;
+        }
+        return Ok(());
+    }
+
+    let mut streams: Vec<EventStream> = Vec::new();
+    if wants(ResourceType::Vm) {
+        streams.push(vm_event_stream(&channel).await?);
+    }
+    if wants(ResourceType::Container) {
+        streams.push(container_event_stream(&channel).await?);
+    }
+    let Some(mut events) = streams
+        .into_iter()
+        .reduce(|merged, stream| Box::pin(merged.merge(stream)))
+    else {
+        return Ok(());
+    };
+
+    while let Some(record) = events.next().await {
+        let record = record?;
+        if matches_id(&record) {
+            print_record(&record, args.output)?;
+        }
+    }
+    Ok(())
+}
+
+async fn vm_event_stream(channel: &Channel) -> Result<EventStream> {
+    let stream = VmServiceClient::new(channel.clone())
+        .stream_vm_events(StreamVmEventsRequest::default())
+        .await
+        .context("Failed to subscribe to VM events")?
+        .into_inner();
+    Ok(Box::pin(stream.map(|event| {
+        event
+            .map(EventRecord::from_vm_event)
+            .context("VM event stream failed")
+    })))
+}
+
+async fn container_event_stream(channel: &Channel) -> Result<EventStream> {
+    let stream = ContainerServiceClient::new(channel.clone())
+        .stream_container_events(StreamContainerEventsRequest::default())
+        .await
+        .context("Failed to subscribe to container events")?
+        .into_inner();
+    Ok(Box::pin(stream.map(|event| {
+        event
+            .map(EventRecord::from_container_event)
+            .context("Container event stream failed")
+    })))
+}
+
+async fn recorded_vm_events(channel: &Channel, args: &EventsArgs) -> Result<Vec<EventRecord>> {
+    let events = VmServiceClient::new(channel.clone())
+        .list_vm_events(ListVmEventsRequest {
+            vm_id: args.id.clone(),
+            namespace: None,
+            since: args.since.map(to_timestamp),
+            until: args.until.map(to_timestamp),
+            limit: args.limit.unwrap_or_default(),
+        })
+        .await
+        .context("Failed to list recorded VM events")?
+        .into_inner()
+        .events;
+    Ok(events
+        .into_iter()
+        .filter_map(|recorded| {
+            let record = EventRecord::from_vm_event(recorded.event?);
+            Some(record.recorded_at(recorded.recorded_at))
+        })
+        .collect())
+}
+
+async fn recorded_container_events(
+    channel: &Channel,
+    args: &EventsArgs,
+) -> Result<Vec<EventRecord>> {
+    let events = ContainerServiceClient::new(channel.clone())
+        .list_container_events(ListContainerEventsRequest {
+            container_id: args.id.clone(),
+            namespace: None,
+            since: args.since.map(to_timestamp),
+            until: args.until.map(to_timestamp),
+            limit: args.limit.unwrap_or_default(),
+        })
+        .await
+        .context("Failed to list recorded container events")?
+        .into_inner()
+        .events;
+    Ok(events
+        .into_iter()
+        .filter_map(|recorded| {
+            let record = EventRecord::from_container_event(recorded.event?);
+            Some(record.recorded_at(recorded.recorded_at))
+        })
+        .collect())
+}
+
+async fn current_vm_states(channel: &Channel) -> Result<Vec<EventRecord>> {
+    let vms = VmServiceClient::new(channel.clone())
+        .list_vms(ListVmsRequest::default())
+        .await?
+        .into_inner()
+        .vms;
+    Ok(vms
+        .into_iter()
+        .map(|vm| {
+            let state = VmState::try_from(vm.state).unwrap_or(VmState::Unspecified);
+            let mut record = EventRecord::new(ResourceType::Vm, vm.vm_id, "CurrentState");
+            record.state = Some(format!("{state:?}"));
+            record
+        })
+        .collect())
+}
+
+async fn current_container_states(channel: &Channel) -> Result<Vec<EventRecord>> {
+    let containers = ContainerServiceClient::new(channel.clone())
+        .list_containers(ListContainersRequest::default())
+        .await?
+        .into_inner()
+        .containers;
+    Ok(containers
+        .into_iter()
+        .map(|container| {
+            let state =
+                ContainerState::try_from(container.state).unwrap_or(ContainerState::Unspecified);
+            let mut record = EventRecord::new(
+                ResourceType::Container,
+                container.container_id,
+                "CurrentState",
+            );
+            record.state = Some(format!("{state:?}"));
+            record
+        })
+        .collect())
+}
+
+fn print_header() {
+    println!(
+        "{:<10} {:<10} {:<38} {:<28} {:<14} REASON",
+        "TIME", "RESOURCE", "ID", "EVENT", "STATE"
+    );
+}
+
+fn print_record(record: &EventRecord, output: OutputFormat) -> Result<()> {
+    match output {
+        OutputFormat::Json => println!("{}", serde_json::to_string(record)?),
+        OutputFormat::Pretty => {
+            let time = chrono::DateTime::parse_from_rfc3339(&record.time)
+                .map(|time| time.format("%H:%M:%S").to_string())
+                .unwrap_or_else(|_| record.time.clone());
+            let resource = match record.resource {
+                ResourceType::Vm => "vm",
+                ResourceType::Container => "container",
+            };
+            println!(
+                "{:<10} {:<10} {:<38} {:<28} {:<14} {}",
+                time,
+                resource,
+                record.id,
+                record.kind,
+                record.state.as_deref().unwrap_or("-"),
+                record.reason
+            );
+        }
+    }
+    Ok(())
+}
@@ -1,18 +1,57 @@
 // SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
 // SPDX-License-Identifier: Apache-2.0
+mod firewall;
+mod gpu;
 mod kernel_stats;
-
-use anyhow::{Context, Result};
-use clap::{Args, Subcommand};
+mod mdev;
+mod nic;
+mod nvme;
+mod swap;
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use clap::{Args, Subcommand, ValueEnum};
 use feos_proto::host_service::{
-    host_service_client::HostServiceClient, GetCpuInfoRequest, GetNetworkInfoRequest,
-    GetVersionInfoRequest, HostnameRequest, MemoryRequest, RebootRequest, ShutdownRequest,
-    StreamFeosLogsRequest, StreamKernelLogsRequest, UpgradeFeosBinaryRequest,
+    host_service_client::HostServiceClient, DrainHostRequest, DrainOutcome, FeosLogEntry,
+    GetCapabilitiesRequest, GetClockInfoRequest, GetCpuInfoRequest, GetLogLevelsRequest,
+    GetNetworkInfoRequest, GetNodeIdentityRequest, GetVersionInfoRequest, HostnameRequest,
+    LogComponent, MemoryRequest, ReadFeosLogsRequest, RebootRequest, SetClocksourceRequest,
+    SetLogLevelRequest, ShutdownRequest, StreamFeosLogsRequest, StreamKernelLogsRequest,
+    UncordonHostRequest, UpgradeFeosBinaryRequest, WorkloadKind,
 };
+use prost_types::Timestamp;
 use tokio_stream::StreamExt;
-use tonic::transport::Channel;
+use tonic::{Code, Status};
 
+use crate::config::{self, Channel};
+use crate::host_commands::firewall::{handle_firewall_command, FirewallCommand};
+use crate::host_commands::gpu::{handle_gpu_command, GpuCommand};
 use crate::host_commands::kernel_stats::get_kernel_stats;
+use crate::host_commands::mdev::{handle_mdev_command, MdevCommand};
+use crate::host_commands::nic::{handle_nic_command, NicCommand};
+use crate::host_commands::nvme::{handle_nvme_command, NvmeCommand};
+use crate::host_commands::swap::{handle_swap_command, SwapCommand};
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ComponentArg {
+    Vm,
+    Container,
+    Network,
+    Api,
+    System,
+}
+
+impl From<ComponentArg> for LogComponent {
+    fn from(component: ComponentArg) -> Self {
+        match component {
+            ComponentArg::Vm => LogComponent::Vm,
+            ComponentArg::Container => LogComponent::Container,
+            ComponentArg::Network => LogComponent::Network,
+            ComponentArg::Api => LogComponent::Api,
+            ComponentArg::System => LogComponent::System,
+        }
+    }
+}
 
 #[derive(Args, Debug)]
 pub struct HostArgs {
@@ -21,9 +60,9 @@ pub struct HostArgs {
         long,
         global = true,
         env = "FEOS_ADDRESS",
-        default_value = "http://[::1]:1337"
+        help = "FeOS API address, overriding the address of the selected context"
     )]
-    pub address: String,
+    pub address: Option<String>,
 
     #[command(subcommand)]
     command: HostCommand,
@@ -55,19 +94,132 @@ pub enum HostCommand {
     /// Stream kernel logs from /dev/kmsg
     Klogs,
     /// Stream logs from the internal FeOS logger
-    Flogs,
+    Flogs {
+        #[arg(
+            long,
+            help = "Only show entries of this level or more severe (error, warn, info, debug, trace)"
+        )]
+        level: Option<String>,
+        #[arg(
+            long,
+            help = "Only show entries whose target starts with this prefix (e.g., vm_service::worker)"
+        )]
+        target: Option<String>,
+        #[arg(
+            long,
+            help = "Only show entries whose message matches this regular expression"
+        )]
+        grep: Option<String>,
+        #[arg(
+            long,
+            value_parser = parse_time,
+            help = "Skip buffered entries older than this time (RFC 3339)"
+        )]
+        since: Option<DateTime<Utc>>,
+        #[arg(long, value_enum, help = "Only show entries of this component")]
+        component: Option<ComponentArg>,
+    },
+    /// Read past FeOS logs, including those from before the last restart
+    LogHistory {
+        #[arg(long, help = "First sequence number to read")]
+        start_seq: Option<u64>,
+        #[arg(long, help = "Last sequence number to read")]
+        end_seq: Option<u64>,
+        #[arg(
+            long,
+            value_parser = parse_time,
+            help = "Only show entries at or after this time (RFC 3339, e.g. 2025-01-01T12:00:00Z)"
+        )]
+        since: Option<DateTime<Utc>>,
+        #[arg(
+            long,
+            value_parser = parse_time,
+            help = "Only show entries at or before this time (RFC 3339)"
+        )]
+        until: Option<DateTime<Utc>>,
+        #[arg(
+            long,
+            default_value_t = 0,
+            help = "Maximum number of entries, 0 for all"
+        )]
+        limit: u32,
+        #[arg(long, value_enum, help = "Only show entries of this component")]
+        component: Option<ComponentArg>,
+    },
+    /// Show the log level of each FeOS component, or change it
+    LogLevel {
+        #[arg(help = "New level: off, error, warn, info, debug or trace")]
+        level: Option<String>,
+        #[arg(
+            long,
+            value_enum,
+            requires = "level",
+            help = "Component to change, all components if omitted"
+        )]
+        component: Option<ComponentArg>,
+    },
     /// Shutdown the host machine
     Shutdown,
     /// Reboot the host machine
     Reboot,
     /// Get kernel and FeOS version information
     VersionInfo,
+    /// List the services this FeOS host was built with
+    Capabilities,
+    /// Show the node ID and public key of the host
+    Identity,
+    /// Manage swapfiles, zram devices and swappiness
+    Swap {
+        #[command(subcommand)]
+        command: SwapCommand,
+    },
+    /// Manage vGPU partitions of the host's GPUs
+    Gpu {
+        #[command(subcommand)]
+        command: GpuCommand,
+    },
+    /// Manage mediated devices, e.g. vGPUs or vfio-ap devices
+    Mdev {
+        #[command(subcommand)]
+        command: MdevCommand,
+    },
+    /// Tune RSS queues and IRQ affinity of the host's NICs
+    Nic {
+        #[command(subcommand)]
+        command: NicCommand,
+    },
+    /// Manage the namespaces of the host's NVMe drives
+    Nvme {
+        #[command(subcommand)]
+        command: NvmeCommand,
+    },
+    /// Manage the exceptions of the host firewall
+    Firewall {
+        #[command(subcommand)]
+        command: FirewallCommand,
+    },
+    /// Show how well the host clock is synchronized, or switch the clocksource
+    Clock {
+        #[arg(help = "Clocksource to switch to, e.g. tsc so guests can use ptp_kvm")]
+        clocksource: Option<String>,
+    },
+    /// Stop accepting new workloads and drain the running ones, e.g. before a reboot
+    Drain {
+        #[arg(
+            long,
+            help = "Seconds workloads may take to shut down before they are stopped forcibly [default: 60]"
+        )]
+        grace_period: Option<u32>,
+    },
+    /// Accept new workloads again after a drain
+    Uncordon,
 }
 
-pub async fn handle_host_command(args: HostArgs) -> Result<()> {
-    let mut client = HostServiceClient::connect(args.address)
+pub async fn handle_host_command(args: HostArgs, context: Option<&str>) -> Result<()> {
+    let channel = config::connect(args.address.as_deref(), context)
         .await
         .context("Failed to connect to host service")?;
+    let mut client = HostServiceClient::new(channel);
 
     match args.command {
         HostCommand::Hostname => get_hostname(&mut client).await?,
@@ -79,10 +231,61 @@ pub async fn handle_host_command(args: HostArgs) -> Result<()> {
             upgrade_feos(&mut client, url, sha256_sum).await?
         }
         HostCommand::Klogs => stream_klogs(&mut client).await?,
-        HostCommand::Flogs => stream_flogs(&mut client).await?,
+        HostCommand::Flogs {
+            level,
+            target,
+            grep,
+            since,
+            component,
+        } => {
+            let request = StreamFeosLogsRequest {
+                level: level.unwrap_or_default(),
+                target_prefix: target.unwrap_or_default(),
+                message_regex: grep.unwrap_or_default(),
+                since: since.map(to_timestamp),
+                component: component.map(|c| LogComponent::from(c) as i32),
+            };
+            stream_flogs(&mut client, request).await?
+        }
+        HostCommand::LogHistory {
+            start_seq,
+            end_seq,
+            since,
+            until,
+            limit,
+            component,
+        } => {
+            let request = ReadFeosLogsRequest {
+                start_seq,
+                end_seq,
+                start_time: since.map(to_timestamp),
+                end_time: until.map(to_timestamp),
+                limit,
+                component: component.map(|c| LogComponent::from(c) as i32),
+            };
+            read_log_history(&mut client, request).await?
+        }
+        HostCommand::LogLevel { level, component } => match level {
+            Some(level) => set_log_level(&mut client, level, component).await?,
+            None => get_log_levels(&mut client).await?,
+        },
         HostCommand::Shutdown => shutdown_host(&mut client).await?,
         HostCommand::Reboot => reboot_host(&mut client).await?,
         HostCommand::VersionInfo => get_version_info(&mut client).await?,
+        HostCommand::Capabilities => get_capabilities(&mut client).await?,
+        HostCommand::Identity => get_node_identity(&mut client).await?,
+        HostCommand::Swap { command } => handle_swap_command(&mut client, command).await?,
+        HostCommand::Gpu { command } => handle_gpu_command(&mut client, command).await?,
+        HostCommand::Mdev { command } => handle_mdev_command(&mut client, command).await?,
+        HostCommand::Nic { command } => handle_nic_command(&mut client, command).await?,
+        HostCommand::Nvme { command } => handle_nvme_command(&mut client, command).await?,
+        HostCommand::Firewall { command } => handle_firewall_command(&mut client, command).await?,
+        HostCommand::Clock { clocksource } => match clocksource {
+            Some(clocksource) => set_clocksource(&mut client, clocksource).await?,
+            None => get_clock_info(&mut client).await?,
+        },
+        HostCommand::Drain { grace_period } => drain_host(&mut client, grace_period).await?,
+        HostCommand::Uncordon => uncordon_host(&mut client).await?,
     }
 
     Ok(())
@@ -256,27 +459,16 @@ async fn stream_klogs(client: &mut HostServiceClient<Channel>) -> Result<()> {
     Ok(())
 }
 
-async fn stream_flogs(client: &mut HostServiceClient<Channel>) -> Result<()> {
+async fn stream_flogs(
+    client: &mut HostServiceClient<Channel>,
+    request: StreamFeosLogsRequest,
+) -> Result<()> {
     println!("Streaming FeOS logs... Press Ctrl+C to stop.");
-    let request = StreamFeosLogsRequest {};
     let mut stream = client.stream_fe_os_logs(request).await?.into_inner();
 
     while let Some(entry_res) = stream.next().await {
         match entry_res {
-            Ok(entry) => {
-                let ts = entry
-                    .timestamp
-                    .map(|t| {
-                        chrono::DateTime::from_timestamp(t.seconds, t.nanos as u32)
-                            .unwrap_or_default()
-                            .to_rfc3339()
-                    })
-                    .unwrap_or_default();
-                println!(
-                    "[{ts} {:<5} {}] {}",
-                    entry.level, entry.target, entry.message
-                );
-            }
+            Ok(entry) => print_feos_log_entry(&entry),
             Err(status) => {
                 eprintln!("Error in FeOS log stream: {status}");
                 break;
@@ -286,6 +478,136 @@ async fn stream_flogs(client: &mut HostServiceClient<Channel>) -> Result<()> {
     Ok(())
 }
 
+async fn read_log_history(
+    client: &mut HostServiceClient<Channel>,
+    request: ReadFeosLogsRequest,
+) -> Result<()> {
+    let mut stream = client.read_fe_os_logs(request).await?.into_inner();
+    let mut count = 0;
+    while let Some(entry) = stream.next().await {
+        let entry = entry.context("Error while reading FeOS log history")?;
+        print_feos_log_entry(&entry);
+        count += 1;
+    }
+    if count == 0 {
+        println!("No log entries matched.");
+    }
+    Ok(())
+}
+
+async fn set_log_level(
+    client: &mut HostServiceClient<Channel>,
+    level: String,
+    component: Option<ComponentArg>,
+) -> Result<()> {
+    let request = SetLogLevelRequest {
+        component: component.map_or(LogComponent::Unspecified, LogComponent::from) as i32,
+        level: level.clone(),
+    };
+    client.set_log_level(request).await?;
+    match component {
+        Some(component) => println!(
+            "Set log level of {} to {level}",
+            component_name(LogComponent::from(component))
+        ),
+        None => println!("Set log level of all components to {level}"),
+    }
+    Ok(())
+}
+
+async fn get_log_levels(client: &mut HostServiceClient<Channel>) -> Result<()> {
+    let response = client
+        .get_log_levels(GetLogLevelsRequest {})
+        .await?
+        .into_inner();
+    println!("{:<12} LEVEL", "COMPONENT");
+    for level in response.levels {
+        let component = LogComponent::try_from(level.component).unwrap_or_default();
+        println!("{:<12} {}", component_name(component), level.level);
+    }
+    Ok(())
+}
+
+async fn get_clock_info(client: &mut HostServiceClient<Channel>) -> Result<()> {
+    let info = client
+        .get_clock_info(GetClockInfoRequest {})
+        .await?
+        .into_inner();
+    println!("{:<20}: {}", "Clocksource", info.clocksource);
+    println!(
+        "{:<20}: {}",
+        "Available",
+        info.available_clocksources.join(" ")
+    );
+    println!("{:<20}: {}", "Synchronized", info.synchronized);
+    println!("{:<20}: {} ns", "Offset", info.offset_ns);
+    println!("{:<20}: {} us", "Estimated Error", info.estimated_error_us);
+    println!("{:<20}: {} us", "Maximum Error", info.max_error_us);
+    println!("{:<20}: {}", "Guest ptp_kvm", info.ptp_kvm_supported);
+    if info.ptp_clocks.is_empty() {
+        println!("{:<20}: none", "PTP Clocks");
+    } else {
+        println!("PTP Clocks:");
+        for ptp in info.ptp_clocks {
+            println!("  /dev/{:<12} {}", ptp.device, ptp.name);
+        }
+    }
+    Ok(())
+}
+
+async fn set_clocksource(
+    client: &mut HostServiceClient<Channel>,
+    clocksource: String,
+) -> Result<()> {
+    client
+        .set_clocksource(SetClocksourceRequest {
+            clocksource: clocksource.clone(),
+        })
+        .await?;
+    println!("Switched host clocksource to {clocksource}");
+    Ok(())
+}
+
+fn component_name(component: LogComponent) -> &'static str {
+    match component {
+        LogComponent::Vm => "vm",
+        LogComponent::Container => "container",
+        LogComponent::Network => "network",
+        LogComponent::Api => "api",
+        LogComponent::System => "system",
+        LogComponent::Unspecified => "unknown",
+    }
+}
+
+fn print_feos_log_entry(entry: &FeosLogEntry) {
+    let ts = entry
+        .timestamp
+        .as_ref()
+        .map(|t| {
+            DateTime::from_timestamp(t.seconds, t.nanos as u32)
+                .unwrap_or_default()
+                .to_rfc3339()
+        })
+        .unwrap_or_default();
+    println!(
+        "{:>8} [{ts} {:<5} {}] {}",
+        entry.seq, entry.level, entry.target, entry.message
+    );
+}
+
+pub(crate) fn parse_time(s: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(s)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|e| format!("invalid RFC 3339 time '{s}': {e}"))
+}
+
+pub(crate) fn to_timestamp(time: DateTime<Utc>) -> Timestamp {
+    Timestamp {
+        seconds: time.timestamp(),
+        nanos: time.timestamp_subsec_nanos() as i32,
+    }
+}
+
 async fn upgrade_feos(
     client: &mut HostServiceClient<Channel>,
     url: String,
@@ -309,9 +631,76 @@ async fn get_version_info(client: &mut HostServiceClient<Channel>) -> Result<()>
     let response = client.get_version_info(request).await?.into_inner();
     println!("FeOS Version:    {}", response.feos_version);
     println!("Kernel Version:  {}", response.kernel_version);
+    println!("Architecture:    {}", response.architecture);
+    Ok(())
+}
+
+async fn get_capabilities(client: &mut HostServiceClient<Channel>) -> Result<()> {
+    let response = client
+        .get_capabilities(GetCapabilitiesRequest {})
+        .await?
+        .into_inner();
+    println!("Services:");
+    for service in &response.services {
+        println!("  {service}");
+    }
+    Ok(())
+}
+
+async fn get_node_identity(client: &mut HostServiceClient<Channel>) -> Result<()> {
+    let response = client
+        .get_node_identity(GetNodeIdentityRequest {})
+        .await?
+        .into_inner();
+    println!("Node ID:    {}", response.node_id);
+    println!("Algorithm:  {}", response.algorithm);
+    print!("{}", response.public_key_pem);
     Ok(())
 }
 
+/// FeOS can be built without some services. Their RPCs then fail with
+/// `Unimplemented`, which this turns into an error saying so if the host's
+/// capabilities confirm that the service is missing.
+pub async fn explain_missing_service(
+    result: Result<()>,
+    address: Option<&str>,
+    context: Option<&str>,
+    service_name: &str,
+    support: &str,
+) -> Result<()> {
+    let Err(e) = result else {
+        return Ok(());
+    };
+    let unimplemented = e
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<Status>())
+        .any(|status| status.code() == Code::Unimplemented);
+    if !unimplemented {
+        return Err(e);
+    }
+    let Ok(channel) = config::connect(address, context).await else {
+        return Err(e);
+    };
+    // Hosts from before GetCapabilities existed serve all services.
+    let Ok(response) = HostServiceClient::new(channel)
+        .get_capabilities(GetCapabilitiesRequest {})
+        .await
+    else {
+        return Err(e);
+    };
+    if response
+        .into_inner()
+        .services
+        .iter()
+        .any(|service| service == service_name)
+    {
+        return Err(e);
+    }
+    Err(e.context(format!(
+        "This FeOS host was built without {support} support"
+    )))
+}
+
 async fn shutdown_host(client: &mut HostServiceClient<Channel>) -> Result<()> {
     println!("Requesting host shutdown...");
     let request = ShutdownRequest {};
@@ -327,3 +716,56 @@ async fn reboot_host(client: &mut HostServiceClient<Channel>) -> Result<()> {
     println!("Reboot command sent successfully. Connection will be lost.");
     Ok(())
 }
+
+async fn drain_host(
+    client: &mut HostServiceClient<Channel>,
+    grace_period: Option<u32>,
+) -> Result<()> {
+    println!("Cordoning the host and draining its workloads...");
+    let request = DrainHostRequest {
+        grace_period_seconds: grace_period,
+    };
+    let mut stream = client.drain_host(request).await?.into_inner();
+
+    let mut failed = 0;
+    while let Some(progress) = stream.next().await {
+        let progress = progress?;
+        if let Some(workload) = &progress.workload {
+            let kind = match workload.kind() {
+                WorkloadKind::Vm => "VM",
+                WorkloadKind::Container => "container",
+                WorkloadKind::Unspecified => "workload",
+            };
+            let outcome = match workload.outcome() {
+                DrainOutcome::Stopped => "stopped".to_string(),
+                DrainOutcome::ForceStopped => "stopped forcibly".to_string(),
+                DrainOutcome::Kept => "kept running".to_string(),
+                DrainOutcome::Failed => {
+                    failed += 1;
+                    format!("failed: {}", workload.message)
+                }
+                DrainOutcome::Unspecified => "unknown".to_string(),
+            };
+            println!(
+                "[{}/{}] {kind} {} {outcome}",
+                progress.drained, progress.total, workload.id
+            );
+        }
+        if progress.done {
+            println!(
+                "Host drained, {} workloads handled. It stays cordoned until 'host uncordon'.",
+                progress.total
+            );
+        }
+    }
+    if failed > 0 {
+        bail!("{failed} workloads could not be drained");
+    }
+    Ok(())
+}
+
+async fn uncordon_host(client: &mut HostServiceClient<Channel>) -> Result<()> {
+    client.uncordon_host(UncordonHostRequest {}).await?;
+    println!("Host uncordoned, it accepts new workloads again.");
+    Ok(())
+}
@@ -0,0 +1,136 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::Channel;
+use anyhow::Result;
+use clap::{Subcommand, ValueEnum};
+use feos_proto::host_service::{
+    host_service_client::HostServiceClient, AddFirewallExceptionRequest, FirewallException,
+    FirewallProtocol, GetFirewallRequest, RemoveFirewallExceptionRequest,
+};
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ProtocolArg {
+    Tcp,
+    Udp,
+}
+
+impl From<ProtocolArg> for FirewallProtocol {
+    fn from(protocol: ProtocolArg) -> Self {
+        match protocol {
+            ProtocolArg::Tcp => FirewallProtocol::Tcp,
+            ProtocolArg::Udp => FirewallProtocol::Udp,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum FirewallCommand {
+    /// Show the host firewall and its exceptions
+    Show,
+    /// Let traffic to a port of the host through the firewall
+    Allow {
+        #[arg(required = true, help = "Name of the exception (e.g., metrics)")]
+        name: String,
+        #[arg(required = true, help = "Port of the host")]
+        port: u16,
+        #[arg(long, value_enum, default_value = "tcp")]
+        protocol: ProtocolArg,
+        #[arg(
+            long = "source",
+            help = "Address or network allowed to connect, can be repeated [default: any]"
+        )]
+        sources: Vec<String>,
+    },
+    /// Remove an exception from the firewall
+    Remove {
+        #[arg(required = true)]
+        name: String,
+    },
+}
+
+pub async fn handle_firewall_command(
+    client: &mut HostServiceClient<Channel>,
+    command: FirewallCommand,
+) -> Result<()> {
+    match command {
+        FirewallCommand::Show => {
+            let response = client
+                .get_firewall(GetFirewallRequest {})
+                .await?
+                .into_inner();
+            if response.enabled {
+                println!("Firewall on: {}", response.interfaces.join(", "));
+            } else {
+                println!("Firewall is disabled, the host accepts traffic on every port.");
+            }
+            println!("API port:    {}", response.api_port);
+            println!(
+                "SSH:         {}",
+                if response.ssh_allowed {
+                    "allowed"
+                } else {
+                    "blocked"
+                }
+            );
+            print_exceptions(&response.exceptions);
+            Ok(())
+        }
+        FirewallCommand::Allow {
+            name,
+            port,
+            protocol,
+            sources,
+        } => {
+            let exception = FirewallException {
+                name,
+                protocol: FirewallProtocol::from(protocol) as i32,
+                port: port.into(),
+                sources,
+            };
+            let response = client
+                .add_firewall_exception(AddFirewallExceptionRequest {
+                    exception: Some(exception),
+                })
+                .await?
+                .into_inner();
+            print_exceptions(&response.exceptions);
+            Ok(())
+        }
+        FirewallCommand::Remove { name } => {
+            let response = client
+                .remove_firewall_exception(RemoveFirewallExceptionRequest { name })
+                .await?
+                .into_inner();
+            print_exceptions(&response.exceptions);
+            Ok(())
+        }
+    }
+}
+
+fn print_exceptions(exceptions: &[FirewallException]) {
+    if exceptions.is_empty() {
+        println!("\nNo exceptions.");
+        return;
+    }
+    println!(
+        "\n{:<32} {:<8} {:>5}  SOURCES",
+        "EXCEPTION", "PROTOCOL", "PORT"
+    );
+    for exception in exceptions {
+        let protocol = match exception.protocol() {
+            FirewallProtocol::Tcp => "tcp",
+            FirewallProtocol::Udp => "udp",
+            FirewallProtocol::Unspecified => "-",
+        };
+        let sources = if exception.sources.is_empty() {
+            "any".to_string()
+        } else {
+            exception.sources.join(", ")
+        };
+        println!(
+            "{:<32} {:<8} {:>5}  {sources}",
+            exception.name, protocol, exception.port
+        );
+    }
+}
@@ -0,0 +1,105 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::Channel;
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use feos_proto::host_service::{
+    host_service_client::HostServiceClient, CreateGpuPartitionRequest, DestroyGpuPartitionRequest,
+    ListGpuPartitionsRequest,
+};
+
+#[derive(Subcommand, Debug)]
+pub enum GpuCommand {
+    /// List partitionable GPUs and their partitions
+    List,
+    /// Create a vGPU partition that VMs can get with 'vm create --gpu <profile>'
+    Create {
+        #[arg(required = true, help = "PCI address of the GPU (e.g., 0000:3b:00.0)")]
+        gpu_address: String,
+        #[arg(
+            long,
+            required = true,
+            help = "vGPU profile (e.g., \"NVIDIA A100-4C\")"
+        )]
+        profile: String,
+    },
+    /// Destroy a vGPU partition that no VM uses
+    Destroy {
+        #[arg(required = true, help = "PCI address of the partition")]
+        pci_address: String,
+    },
+}
+
+pub async fn handle_gpu_command(
+    client: &mut HostServiceClient<Channel>,
+    command: GpuCommand,
+) -> Result<()> {
+    match command {
+        GpuCommand::List => list_gpu_partitions(client).await,
+        GpuCommand::Create {
+            gpu_address,
+            profile,
+        } => {
+            let partition = client
+                .create_gpu_partition(CreateGpuPartitionRequest {
+                    gpu_address,
+                    profile,
+                })
+                .await?
+                .into_inner()
+                .partition
+                .context("No GPU partition in response")?;
+            println!(
+                "Created GPU partition {} of profile '{}' on {}",
+                partition.pci_address, partition.profile, partition.gpu_address
+            );
+            Ok(())
+        }
+        GpuCommand::Destroy { pci_address } => {
+            client
+                .destroy_gpu_partition(DestroyGpuPartitionRequest {
+                    pci_address: pci_address.clone(),
+                })
+                .await?;
+            println!("Destroyed GPU partition {pci_address}");
+            Ok(())
+        }
+    }
+}
+
+async fn list_gpu_partitions(client: &mut HostServiceClient<Channel>) -> Result<()> {
+    let response = client
+        .list_gpu_partitions(ListGpuPartitionsRequest {})
+        .await?
+        .into_inner();
+    if response.gpus.is_empty() {
+        println!("No partitionable GPUs found.");
+        return Ok(());
+    }
+    println!("{:<16} {:>10}  CREATABLE PROFILES", "GPU", "PARTITIONS");
+    for gpu in &response.gpus {
+        let used = response
+            .partitions
+            .iter()
+            .filter(|partition| partition.gpu_address == gpu.pci_address)
+            .count();
+        println!(
+            "{:<16} {:>10}  {}",
+            gpu.pci_address,
+            format!("{used}/{}", gpu.max_partitions),
+            gpu.creatable_profiles.join(", ")
+        );
+    }
+    if !response.partitions.is_empty() {
+        println!();
+        println!("{:<16} {:<16} PROFILE", "PARTITION", "GPU");
+        for partition in response.partitions {
+            println!(
+                "{:<16} {:<16} {}",
+                partition.pci_address, partition.gpu_address, partition.profile
+            );
+        }
+    }
+    Ok(())
+}
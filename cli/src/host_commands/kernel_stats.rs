@@ -1,10 +1,10 @@
 // SPDX-FileCopyrightText: 2025 SAP SE or an SAP affiliate company and IronCore contributors
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::config::Channel;
 use anyhow::{Context, Result};
 use feos_proto::host_service::host_service_client::HostServiceClient;
 use feos_proto::host_service::GetKernelStatsRequest;
-use tonic::transport::Channel;
 
 pub async fn get_kernel_stats(client: &mut HostServiceClient<Channel>) -> Result<()> {
     use std::time::Duration;
@@ -0,0 +1,96 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::Channel;
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use feos_proto::host_service::{
+    host_service_client::HostServiceClient, CreateMdevRequest, ListMdevsRequest, RemoveMdevRequest,
+};
+
+#[derive(Subcommand, Debug)]
+pub enum MdevCommand {
+    /// List the mediated device types of the host and the existing devices
+    List,
+    /// Create a mediated device that VMs can get with 'vm create --mdev <uuid>'
+    Create {
+        #[arg(required = true, help = "Parent device (e.g., 0000:3b:00.0)")]
+        parent: String,
+        #[arg(required = true, help = "Type of the device (e.g., nvidia-63)")]
+        type_id: String,
+        #[arg(long, help = "UUID of the new device [default: generated]")]
+        uuid: Option<String>,
+    },
+    /// Remove a mediated device that no VM uses
+    Remove {
+        #[arg(required = true)]
+        uuid: String,
+    },
+}
+
+pub async fn handle_mdev_command(
+    client: &mut HostServiceClient<Channel>,
+    command: MdevCommand,
+) -> Result<()> {
+    match command {
+        MdevCommand::List => list_mdevs(client).await,
+        MdevCommand::Create {
+            parent,
+            type_id,
+            uuid,
+        } => {
+            let mdev = client
+                .create_mdev(CreateMdevRequest {
+                    parent,
+                    type_id,
+                    uuid,
+                })
+                .await?
+                .into_inner()
+                .mdev
+                .context("No mediated device in response")?;
+            println!(
+                "Created mediated device {} of type {} on {}",
+                mdev.uuid, mdev.type_id, mdev.parent
+            );
+            Ok(())
+        }
+        MdevCommand::Remove { uuid } => {
+            client
+                .remove_mdev(RemoveMdevRequest { uuid: uuid.clone() })
+                .await?;
+            println!("Removed mediated device {uuid}");
+            Ok(())
+        }
+    }
+}
+
+async fn list_mdevs(client: &mut HostServiceClient<Channel>) -> Result<()> {
+    let response = client.list_mdevs(ListMdevsRequest {}).await?.into_inner();
+    if response.types.is_empty() {
+        println!("No mediated device types found.");
+    } else {
+        println!(
+            "{:<16} {:<20} {:<10} {:>9}  NAME",
+            "PARENT", "TYPE", "API", "AVAILABLE"
+        );
+        for mdev_type in response.types {
+            println!(
+                "{:<16} {:<20} {:<10} {:>9}  {}",
+                mdev_type.parent,
+                mdev_type.type_id,
+                mdev_type.device_api,
+                mdev_type.available_instances,
+                mdev_type.name
+            );
+        }
+    }
+    if !response.mdevs.is_empty() {
+        println!();
+        println!("{:<38} {:<16} TYPE", "UUID", "PARENT");
+        for mdev in response.mdevs {
+            println!("{:<38} {:<16} {}", mdev.uuid, mdev.parent, mdev.type_id);
+        }
+    }
+    Ok(())
+}
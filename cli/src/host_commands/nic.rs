@@ -0,0 +1,103 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::Channel;
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use feos_proto::host_service::{
+    host_service_client::HostServiceClient, GetNicTuningRequest, NicQueueCounts, NicTuning,
+    SetNicTuningRequest,
+};
+
+#[derive(Subcommand, Debug)]
+pub enum NicCommand {
+    /// Show the RSS queues and IRQ affinity of the host's NICs
+    Show {
+        #[arg(help = "Interface to show [default: all NICs backed by a device]")]
+        interface: Option<String>,
+    },
+    /// Set the RSS queues of a NIC and move its IRQs off isolated CPUs
+    Tune {
+        #[arg(required = true, help = "Interface to tune (e.g., eth0)")]
+        interface: String,
+        #[arg(long, help = "Number of combined RSS queues [default: unchanged]")]
+        queues: Option<u32>,
+        #[arg(
+            long,
+            help = "CPUs to spread the IRQs over (e.g., 0-3,8) [default: CPUs that are not isolated, on the NIC's NUMA node if possible]"
+        )]
+        irq_cpus: Option<String>,
+    },
+}
+
+pub async fn handle_nic_command(
+    client: &mut HostServiceClient<Channel>,
+    command: NicCommand,
+) -> Result<()> {
+    match command {
+        NicCommand::Show { interface } => {
+            let response = client
+                .get_nic_tuning(GetNicTuningRequest {
+                    interface: interface.unwrap_or_default(),
+                })
+                .await?
+                .into_inner();
+            let isolated_cpus = if response.isolated_cpus.is_empty() {
+                "none"
+            } else {
+                &response.isolated_cpus
+            };
+            println!("Isolated CPUs: {isolated_cpus}");
+            if response.nics.is_empty() {
+                println!("No NICs found.");
+            }
+            for nic in &response.nics {
+                print_nic(nic);
+            }
+            Ok(())
+        }
+        NicCommand::Tune {
+            interface,
+            queues,
+            irq_cpus,
+        } => {
+            let nic = client
+                .set_nic_tuning(SetNicTuningRequest {
+                    interface,
+                    combined_queues: queues.unwrap_or_default(),
+                    irq_cpus: irq_cpus.unwrap_or_default(),
+                })
+                .await?
+                .into_inner()
+                .nic
+                .context("No NIC in response")?;
+            print_nic(&nic);
+            Ok(())
+        }
+    }
+}
+
+fn format_queues(queues: Option<&NicQueueCounts>) -> String {
+    match queues {
+        Some(queues) => format!(
+            "rx {}, tx {}, other {}, combined {}",
+            queues.rx, queues.tx, queues.other, queues.combined
+        ),
+        None => "not reported by the driver".to_string(),
+    }
+}
+
+fn print_nic(nic: &NicTuning) {
+    println!("\n{}:", nic.interface);
+    println!("  Queues:     {}", format_queues(nic.queues.as_ref()));
+    println!("  Max queues: {}", format_queues(nic.max_queues.as_ref()));
+    println!("  Local CPUs: {}", nic.local_cpus);
+    if nic.irqs.is_empty() {
+        println!("  No MSI IRQs");
+        return;
+    }
+    println!("  {:>6}  CPUS", "IRQ");
+    for irq in &nic.irqs {
+        println!("  {:>6}  {}", irq.irq, irq.cpus);
+    }
+}
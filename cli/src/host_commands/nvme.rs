@@ -0,0 +1,202 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::Channel;
+use anyhow::{Context, Result};
+use clap::{Subcommand, ValueEnum};
+use feos_proto::host_service::{
+    host_service_client::HostServiceClient, CreateNvmeNamespaceRequest, DeleteNvmeNamespaceRequest,
+    FormatNvmeNamespaceRequest, ListNvmeControllersRequest, NvmeNamespace, NvmeSecureErase,
+};
+
+use crate::storage_commands::{format_bytes, parse_size};
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum SecureEraseArg {
+    None,
+    UserData,
+    Crypto,
+}
+
+impl From<SecureEraseArg> for NvmeSecureErase {
+    fn from(erase: SecureEraseArg) -> Self {
+        match erase {
+            SecureEraseArg::None => NvmeSecureErase::None,
+            SecureEraseArg::UserData => NvmeSecureErase::UserData,
+            SecureEraseArg::Crypto => NvmeSecureErase::Cryptographic,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum NvmeCommand {
+    /// List the NVMe controllers of the host and their namespaces
+    List,
+    /// Create a namespace and attach it to its controller
+    CreateNamespace {
+        #[arg(required = true, help = "Controller (e.g., nvme0)")]
+        controller: String,
+        #[arg(required = true, value_parser = parse_size, help = "Size of the namespace (e.g., 500G)")]
+        size: u64,
+        #[arg(
+            long,
+            help = "Block size in bytes, e.g. 512 or 4096 [default: the fastest of the controller]"
+        )]
+        block_size: Option<u32>,
+    },
+    /// Detach and delete a namespace, losing its data
+    DeleteNamespace {
+        #[arg(required = true, help = "Controller (e.g., nvme0)")]
+        controller: String,
+        #[arg(required = true, help = "Namespace ID")]
+        nsid: u32,
+    },
+    /// Format a namespace, losing its data
+    Format {
+        #[arg(required = true, help = "Controller (e.g., nvme0)")]
+        controller: String,
+        #[arg(required = true, help = "Namespace ID")]
+        nsid: u32,
+        #[arg(long, help = "Block size in bytes [default: the current one]")]
+        block_size: Option<u32>,
+        #[arg(long, value_enum, default_value_t = SecureEraseArg::None, help = "How the data is erased")]
+        secure_erase: SecureEraseArg,
+    },
+}
+
+pub async fn handle_nvme_command(
+    client: &mut HostServiceClient<Channel>,
+    command: NvmeCommand,
+) -> Result<()> {
+    match command {
+        NvmeCommand::List => list_nvme_controllers(client).await,
+        NvmeCommand::CreateNamespace {
+            controller,
+            size,
+            block_size,
+        } => {
+            let namespace = client
+                .create_nvme_namespace(CreateNvmeNamespaceRequest {
+                    controller: controller.clone(),
+                    size_bytes: size,
+                    block_size: block_size.unwrap_or_default(),
+                })
+                .await?
+                .into_inner()
+                .namespace
+                .context("No namespace in response")?;
+            println!(
+                "Created namespace {} on {controller}: {}",
+                namespace.nsid,
+                describe_namespace(&namespace)
+            );
+            Ok(())
+        }
+        NvmeCommand::DeleteNamespace { controller, nsid } => {
+            client
+                .delete_nvme_namespace(DeleteNvmeNamespaceRequest {
+                    controller: controller.clone(),
+                    nsid,
+                })
+                .await?;
+            println!("Deleted namespace {nsid} of {controller}");
+            Ok(())
+        }
+        NvmeCommand::Format {
+            controller,
+            nsid,
+            block_size,
+            secure_erase,
+        } => {
+            let namespace = client
+                .format_nvme_namespace(FormatNvmeNamespaceRequest {
+                    controller: controller.clone(),
+                    nsid,
+                    block_size: block_size.unwrap_or_default(),
+                    secure_erase: NvmeSecureErase::from(secure_erase) as i32,
+                })
+                .await?
+                .into_inner()
+                .namespace
+                .context("No namespace in response")?;
+            println!(
+                "Formatted namespace {nsid} of {controller}: {}",
+                describe_namespace(&namespace)
+            );
+            Ok(())
+        }
+    }
+}
+
+fn describe_namespace(namespace: &NvmeNamespace) -> String {
+    let device = if namespace.device.is_empty() {
+        "not attached"
+    } else {
+        namespace.device.as_str()
+    };
+    format!(
+        "{device}, {} with {} byte blocks",
+        format_bytes(namespace.size_bytes),
+        namespace.block_size
+    )
+}
+
+async fn list_nvme_controllers(client: &mut HostServiceClient<Channel>) -> Result<()> {
+    let response = client
+        .list_nvme_controllers(ListNvmeControllersRequest {})
+        .await?
+        .into_inner();
+    if response.controllers.is_empty() {
+        println!("No NVMe controllers found.");
+        return Ok(());
+    }
+    for controller in response.controllers {
+        println!(
+            "{} ({}, {}) {} {}, firmware {}",
+            controller.name,
+            controller.transport,
+            controller.address,
+            controller.model,
+            controller.serial,
+            controller.firmware
+        );
+        if controller.namespace_management {
+            let block_sizes: Vec<String> = controller
+                .lba_formats
+                .iter()
+                .filter(|format| format.metadata_size == 0)
+                .map(|format| format.block_size.to_string())
+                .collect();
+            println!(
+                "  Capacity: {} of {} unallocated, up to {} namespaces, block sizes {}",
+                format_bytes(controller.unallocated_capacity_bytes),
+                format_bytes(controller.total_capacity_bytes),
+                controller.max_namespaces,
+                block_sizes.join(", ")
+            );
+        } else {
+            println!("  No namespace management");
+        }
+        if controller.namespaces.is_empty() {
+            continue;
+        }
+        println!(
+            "  {:>6} {:<14} {:>12} {:>10}",
+            "NSID", "DEVICE", "SIZE", "BLOCK"
+        );
+        for namespace in controller.namespaces {
+            println!(
+                "  {:>6} {:<14} {:>12} {:>10}",
+                namespace.nsid,
+                if namespace.device.is_empty() {
+                    "-"
+                } else {
+                    &namespace.device
+                },
+                format_bytes(namespace.size_bytes),
+                namespace.block_size
+            );
+        }
+    }
+    Ok(())
+}
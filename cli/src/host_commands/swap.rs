@@ -0,0 +1,160 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::Channel;
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use feos_proto::host_service::{
+    add_swap_request::Backend, host_service_client::HostServiceClient, AddSwapRequest,
+    ListSwapRequest, RemoveSwapRequest, SetSwappinessRequest, SwapFileConfig, SwapType, ZramConfig,
+};
+
+use crate::storage_commands::{format_bytes, parse_size};
+
+#[derive(Subcommand, Debug)]
+pub enum SwapCommand {
+    /// List active swap areas and the host swappiness
+    List,
+    /// Create a swapfile and enable it
+    AddFile {
+        #[arg(long, required = true, help = "Absolute path of the new swapfile")]
+        path: String,
+        #[arg(long, required = true, value_parser = parse_size, help = "Size of the swapfile (e.g., 4G)")]
+        size: u64,
+        #[arg(
+            long,
+            help = "Priority between 0 and 32767, higher priorities are used first"
+        )]
+        priority: Option<i32>,
+    },
+    /// Create a compressed swap device in RAM and enable it
+    AddZram {
+        #[arg(long, required = true, value_parser = parse_size, help = "Uncompressed size of the device (e.g., 2G)")]
+        size: u64,
+        #[arg(long, help = "Compression algorithm (e.g., zstd, lz4)")]
+        algorithm: Option<String>,
+        #[arg(
+            long,
+            help = "Priority between 0 and 32767, higher priorities are used first"
+        )]
+        priority: Option<i32>,
+    },
+    /// Disable a swap area, removing zram devices
+    Remove {
+        #[arg(
+            required = true,
+            help = "Path of the swap area as shown by 'swap list'"
+        )]
+        path: String,
+        #[arg(long, help = "Also delete the swapfile")]
+        delete_file: bool,
+    },
+    /// Set the host-wide swappiness (0-200)
+    Swappiness {
+        #[arg(required = true)]
+        value: u32,
+    },
+}
+
+pub async fn handle_swap_command(
+    client: &mut HostServiceClient<Channel>,
+    command: SwapCommand,
+) -> Result<()> {
+    match command {
+        SwapCommand::List => list_swap(client).await,
+        SwapCommand::AddFile {
+            path,
+            size,
+            priority,
+        } => {
+            let backend = Backend::File(SwapFileConfig {
+                path,
+                size_bytes: size,
+            });
+            add_swap(client, backend, priority).await
+        }
+        SwapCommand::AddZram {
+            size,
+            algorithm,
+            priority,
+        } => {
+            let backend = Backend::Zram(ZramConfig {
+                size_bytes: size,
+                compression_algorithm: algorithm.unwrap_or_default(),
+            });
+            add_swap(client, backend, priority).await
+        }
+        SwapCommand::Remove { path, delete_file } => {
+            client
+                .remove_swap(RemoveSwapRequest {
+                    path: path.clone(),
+                    delete_file,
+                })
+                .await?;
+            println!("Disabled swap on {path}");
+            Ok(())
+        }
+        SwapCommand::Swappiness { value } => {
+            client
+                .set_swappiness(SetSwappinessRequest { swappiness: value })
+                .await?;
+            println!("Set swappiness to {value}");
+            Ok(())
+        }
+    }
+}
+
+async fn add_swap(
+    client: &mut HostServiceClient<Channel>,
+    backend: Backend,
+    priority: Option<i32>,
+) -> Result<()> {
+    let request = AddSwapRequest {
+        backend: Some(backend),
+        priority,
+    };
+    let device = client
+        .add_swap(request)
+        .await?
+        .into_inner()
+        .device
+        .context("No swap device in response")?;
+    println!(
+        "Enabled {} of swap on {} with priority {}",
+        format_bytes(device.size_bytes),
+        device.path,
+        device.priority
+    );
+    Ok(())
+}
+
+async fn list_swap(client: &mut HostServiceClient<Channel>) -> Result<()> {
+    let response = client.list_swap(ListSwapRequest {}).await?.into_inner();
+    println!("Swappiness: {}", response.swappiness);
+    if response.devices.is_empty() {
+        println!("No swap areas are active.");
+        return Ok(());
+    }
+    println!();
+    println!(
+        "{:<40} {:<10} {:>12} {:>12} {:>9}",
+        "PATH", "TYPE", "SIZE", "USED", "PRIORITY"
+    );
+    for device in response.devices {
+        let swap_type = match SwapType::try_from(device.r#type).unwrap_or_default() {
+            SwapType::File => "file",
+            SwapType::Partition => "partition",
+            SwapType::Zram => "zram",
+            SwapType::Unspecified => "unknown",
+        };
+        println!(
+            "{:<40} {:<10} {:>12} {:>12} {:>9}",
+            device.path,
+            swap_type,
+            format_bytes(device.size_bytes),
+            format_bytes(device.used_bytes),
+            device.priority
+        );
+    }
+    Ok(())
+}
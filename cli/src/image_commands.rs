@@ -1,16 +1,25 @@
 // SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use clap::{Args, Subcommand};
+use crossterm::{
+    cursor, queue,
+    terminal::{Clear, ClearType},
+};
 use feos_proto::image_service::{
-    image_service_client::ImageServiceClient, DeleteImageRequest, ImageState, ListImagesRequest,
-    PullImageRequest, WatchImageStatusRequest,
+    image_service_client::ImageServiceClient, load_image_request, DeleteImageRequest, ImageInfo,
+    ImageState, InspectImageRequest, ListImagesRequest, ListPinnedImagesRequest, LoadImageRequest,
+    LoadImageStart, PinImageRequest, PruneImagesRequest, PullImageRequest, UnpinImageRequest,
+    WatchImageStatusRequest,
 };
 use hyper_util::rt::TokioIo;
-use std::path::PathBuf;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncRead, AsyncReadExt};
 use tokio::net::UnixStream;
-use tokio_stream::StreamExt;
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
 use tonic::transport::{Channel, Endpoint, Uri};
 use tower::service_fn;
 
@@ -38,19 +47,73 @@ pub enum ImageCommand {
             help = "Container image reference to pull (e.g., docker.io/library/ubuntu:latest)"
         )]
         image_ref: String,
+        #[arg(
+            long,
+            visible_alias = "async",
+            help = "Return right after the pull is initiated instead of showing its progress"
+        )]
+        detach: bool,
+    },
+    /// Load an image from an archive written by `docker save`
+    Load {
+        #[arg(
+            required = true,
+            help = "Path of the image archive, or - to read it from stdin"
+        )]
+        archive: PathBuf,
+        #[arg(
+            long = "ref",
+            help = "Reference to list the image under, picks it from archives holding several [default: its tag]"
+        )]
+        image_ref: Option<String>,
+        #[arg(
+            long,
+            visible_alias = "async",
+            help = "Return right after the archive is uploaded instead of showing the progress"
+        )]
+        detach: bool,
     },
     /// List all local container images
     List,
+    /// Show details and the OCI configuration of a local image
+    Inspect {
+        #[arg(required = true, help = "UUID of the image to inspect")]
+        image_uuid: String,
+    },
     /// Watch the status of an image pull operation
     Watch {
         #[arg(required = true, help = "UUID of the image to watch")]
         image_uuid: String,
     },
-    /// Delete a local container image
-    Delete {
-        #[arg(required = true, help = "UUID of the image to delete")]
-        image_uuid: String,
+    /// Delete one or more local container images
+    #[command(visible_alias = "delete")]
+    Rm {
+        #[arg(required = true, help = "UUIDs of the images to delete")]
+        image_uuids: Vec<String>,
     },
+    /// Delete images no VM or container uses and report the reclaimed disk space
+    Prune {
+        #[arg(long, help = "Only show what would be deleted")]
+        dry_run: bool,
+    },
+    /// Pull images if needed and keep them until they are unpinned
+    Pin {
+        #[arg(required = true, help = "Container image references to pin")]
+        image_refs: Vec<String>,
+        #[arg(
+            long,
+            visible_alias = "async",
+            help = "Return right after the images are pinned instead of showing the progress of their pulls"
+        )]
+        detach: bool,
+    },
+    /// Unpin images, they are kept until they are deleted
+    Unpin {
+        #[arg(required = true, help = "Container image references to unpin")]
+        image_refs: Vec<String>,
+    },
+    /// List the pinned images and the status of their pulls
+    Pins,
 }
 
 async fn get_image_client(socket: PathBuf) -> Result<ImageServiceClient<Channel>> {
@@ -69,27 +132,196 @@ pub async fn handle_image_command(args: ImageArgs) -> Result<()> {
     let mut client = get_image_client(args.socket).await?;
 
     match args.command {
-        ImageCommand::Pull { image_ref } => pull_image(&mut client, image_ref).await?,
+        ImageCommand::Pull { image_ref, detach } => {
+            pull_image(&mut client, image_ref, detach).await?
+        }
+        ImageCommand::Load {
+            archive,
+            image_ref,
+            detach,
+        } => load_image(&mut client, &archive, image_ref, detach).await?,
         ImageCommand::List => list_images(&mut client).await?,
+        ImageCommand::Inspect { image_uuid } => inspect_image(&mut client, image_uuid).await?,
         ImageCommand::Watch { image_uuid } => watch_image(&mut client, image_uuid).await?,
-        ImageCommand::Delete { image_uuid } => delete_image(&mut client, image_uuid).await?,
+        ImageCommand::Rm { image_uuids } => delete_images(&mut client, image_uuids).await?,
+        ImageCommand::Prune { dry_run } => prune_images(&mut client, dry_run).await?,
+        ImageCommand::Pin { image_refs, detach } => {
+            pin_images(&mut client, image_refs, detach).await?
+        }
+        ImageCommand::Unpin { image_refs } => unpin_images(&mut client, image_refs).await?,
+        ImageCommand::Pins => list_pinned_images(&mut client).await?,
     }
 
     Ok(())
 }
 
-async fn pull_image(client: &mut ImageServiceClient<Channel>, image_ref: String) -> Result<()> {
+async fn pull_image(
+    client: &mut ImageServiceClient<Channel>,
+    image_ref: String,
+    detach: bool,
+) -> Result<()> {
     println!("Requesting image pull for: {image_ref}...");
     let request = PullImageRequest { image_ref };
-    let response = client.pull_image(request).await?.into_inner();
-    println!("Image pull initiated. UUID: {}", response.image_uuid);
+    let image_uuid = client.pull_image(request).await?.into_inner().image_uuid;
+    println!("Image pull initiated. UUID: {image_uuid}");
+
+    if detach {
+        println!("Use 'feos-cli image watch {image_uuid}' to see progress.");
+        return Ok(());
+    }
+    follow_image(client, image_uuid, "pull", "Pulled").await
+}
+
+const UPLOAD_CHUNK_SIZE: usize = 1 << 20;
+
+/// Sends the archive in chunks into `chunk_tx` until it is read completely
+/// or the upload stops.
+async fn read_archive(
+    mut archive: impl AsyncRead + Unpin,
+    chunk_tx: mpsc::Sender<LoadImageRequest>,
+) -> io::Result<()> {
+    loop {
+        let mut chunk = vec![0u8; UPLOAD_CHUNK_SIZE];
+        let n = archive.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        chunk.truncate(n);
+        let request = LoadImageRequest {
+            payload: Some(load_image_request::Payload::Data(chunk)),
+        };
+        if chunk_tx.send(request).await.is_err() {
+            return Ok(());
+        }
+    }
+}
+
+async fn load_image(
+    client: &mut ImageServiceClient<Channel>,
+    archive: &Path,
+    image_ref: Option<String>,
+    detach: bool,
+) -> Result<()> {
+    let (chunk_tx, chunk_rx) = mpsc::channel(4);
+    let start = LoadImageRequest {
+        payload: Some(load_image_request::Payload::Start(LoadImageStart {
+            image_ref: image_ref.unwrap_or_default(),
+        })),
+    };
+    chunk_tx.send(start).await?;
+    let reader = if archive == Path::new("-") {
+        println!("Uploading image archive from stdin...");
+        tokio::spawn(read_archive(tokio::io::stdin(), chunk_tx))
+    } else {
+        let file = tokio::fs::File::open(archive)
+            .await
+            .with_context(|| format!("Failed to open {}", archive.display()))?;
+        println!("Uploading image archive {}...", archive.display());
+        tokio::spawn(read_archive(file, chunk_tx))
+    };
+
+    let response = client.load_image(ReceiverStream::new(chunk_rx)).await;
+    reader
+        .await?
+        .with_context(|| format!("Failed to read {}", archive.display()))?;
+    let response = response?.into_inner();
+    let image_uuid = response.image_uuid;
     println!(
-        "Use 'feos-cli image watch {}' to see progress.",
-        response.image_uuid
+        "Image archive uploaded as {}. UUID: {image_uuid}",
+        response.image_ref
+    );
+
+    if detach {
+        println!("Use 'feos-cli image watch {image_uuid}' to see progress.");
+        return Ok(());
+    }
+    follow_image(client, image_uuid, "load", "Loaded").await
+}
+
+/// Shows the progress of an image pull or load until the image is ready.
+async fn follow_image(
+    client: &mut ImageServiceClient<Channel>,
+    image_uuid: String,
+    operation: &str,
+    done: &str,
+) -> Result<()> {
+    let request = WatchImageStatusRequest {
+        image_uuid: image_uuid.clone(),
+    };
+    let mut stream = client.watch_image_status(request).await?.into_inner();
+
+    while let Some(status) = stream.next().await {
+        let status = status.context("Error in watch stream")?;
+        let state = ImageState::try_from(status.state).unwrap_or_default();
+        match state {
+            ImageState::Ready => {
+                draw_progress_bar(100, "Image is ready")?;
+                println!();
+                let request = InspectImageRequest {
+                    image_uuid: image_uuid.clone(),
+                };
+                let size = client
+                    .inspect_image(request)
+                    .await
+                    .ok()
+                    .and_then(|response| response.into_inner().image)
+                    .map(|image| format_bytes(image.size_bytes));
+                match size {
+                    Some(size) => println!("{done} image {image_uuid} ({size})."),
+                    None => println!("{done} image {image_uuid}."),
+                }
+                return Ok(());
+            }
+            ImageState::PullFailed => {
+                println!();
+                bail!("Image {operation} failed: {}", status.message);
+            }
+            ImageState::NotFound => {
+                println!();
+                bail!("Image {image_uuid} was deleted during the {operation}");
+            }
+            _ => draw_progress_bar(status.progress_percent, &status.message)?,
+        }
+    }
+
+    println!();
+    bail!("Watch stream for {image_uuid} ended before the {operation} completed")
+}
+
+fn draw_progress_bar(percent: u32, message: &str) -> Result<()> {
+    const WIDTH: usize = 30;
+    let percent = percent.min(100);
+    let filled = WIDTH * percent as usize / 100;
+    let mut stdout = io::stdout();
+    queue!(
+        stdout,
+        cursor::MoveToColumn(0),
+        Clear(ClearType::CurrentLine)
+    )?;
+    print!(
+        "[{}{}] {percent:>3}% {message}",
+        "#".repeat(filled),
+        " ".repeat(WIDTH - filled)
     );
+    stdout.flush()?;
     Ok(())
 }
 
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
 async fn list_images(client: &mut ImageServiceClient<Channel>) -> Result<()> {
     let request = ListImagesRequest {};
     let response = client.list_images(request).await?.into_inner();
@@ -98,20 +330,55 @@ async fn list_images(client: &mut ImageServiceClient<Channel>) -> Result<()> {
         return Ok(());
     }
 
-    println!("{:<38} {:<12} REFERENCE", "UUID", "STATE");
-    println!("{:-<38} {:-<12} {:-<40}", "", "", "");
+    println!(
+        "{:<38} {:<12} {:>10} {:<6} REFERENCE",
+        "UUID", "STATE", "SIZE", "PINNED"
+    );
+    println!("{:-<38} {:-<12} {:->10} {:-<6} {:-<40}", "", "", "", "", "");
     for image in response.images {
         let state = ImageState::try_from(image.state).unwrap_or_default();
         println!(
-            "{:<38} {:<12} {}",
+            "{:<38} {:<12} {:>10} {:<6} {}",
             image.image_uuid,
             format!("{state:?}"),
+            format_bytes(image.size_bytes),
+            if image.pinned { "yes" } else { "-" },
             image.image_ref
         );
     }
     Ok(())
 }
 
+async fn inspect_image(client: &mut ImageServiceClient<Channel>, image_uuid: String) -> Result<()> {
+    let request = InspectImageRequest { image_uuid };
+    let response = client.inspect_image(request).await?.into_inner();
+    let image = response
+        .image
+        .context("Image service returned no image information")?;
+    let state = ImageState::try_from(image.state).unwrap_or_default();
+
+    println!("UUID:      {}", image.image_uuid);
+    println!("Reference: {}", image.image_ref);
+    println!("State:     {state:?}");
+    println!(
+        "Size:      {} ({} bytes)",
+        format_bytes(image.size_bytes),
+        image.size_bytes
+    );
+    if !image.architecture.is_empty() {
+        println!("Arch:      {}", image.architecture);
+    }
+
+    if !response.config_json.is_empty() {
+        let config = serde_json::from_str::<serde_json::Value>(&response.config_json)
+            .and_then(|value| serde_json::to_string_pretty(&value))
+            .unwrap_or(response.config_json);
+        println!("Config:");
+        println!("{config}");
+    }
+    Ok(())
+}
+
 async fn watch_image(client: &mut ImageServiceClient<Channel>, image_uuid: String) -> Result<()> {
     println!("Watching status for image: {image_uuid}. Press Ctrl+C to stop.");
     let request = WatchImageStatusRequest {
@@ -143,11 +410,144 @@ async fn watch_image(client: &mut ImageServiceClient<Channel>, image_uuid: Strin
     Ok(())
 }
 
-async fn delete_image(client: &mut ImageServiceClient<Channel>, image_uuid: String) -> Result<()> {
-    let request = DeleteImageRequest {
-        image_uuid: image_uuid.clone(),
-    };
-    client.delete_image(request).await?;
-    println!("Successfully deleted image: {image_uuid}");
+async fn delete_images(
+    client: &mut ImageServiceClient<Channel>,
+    image_uuids: Vec<String>,
+) -> Result<()> {
+    for image_uuid in image_uuids {
+        let request = DeleteImageRequest {
+            image_uuid: image_uuid.clone(),
+        };
+        client.delete_image(request).await?;
+        println!("Successfully deleted image: {image_uuid}");
+    }
+    Ok(())
+}
+
+async fn prune_images(client: &mut ImageServiceClient<Channel>, dry_run: bool) -> Result<()> {
+    let pruned = client
+        .prune_images(PruneImagesRequest { dry_run })
+        .await?
+        .into_inner();
+    for image in &pruned.images {
+        println!(
+            "{} {} ({}, {})",
+            if dry_run { "Would delete" } else { "Deleted" },
+            image.image_uuid,
+            image.image_ref,
+            format_bytes(image.size_bytes)
+        );
+    }
+
+    let kept: Vec<ImageInfo> = client
+        .list_images(ListImagesRequest {})
+        .await?
+        .into_inner()
+        .images
+        .into_iter()
+        .filter(|image| {
+            !dry_run
+                || !pruned
+                    .images
+                    .iter()
+                    .any(|pruned| pruned.image_uuid == image.image_uuid)
+        })
+        .collect();
+    let kept_bytes: u64 = kept.iter().map(|image| image.size_bytes).sum();
+    println!();
+    println!(
+        "{} space: {}",
+        if dry_run { "Reclaimable" } else { "Reclaimed" },
+        format_bytes(pruned.reclaimed_bytes)
+    );
+    println!(
+        "Remaining: {} images using {}",
+        kept.len(),
+        format_bytes(kept_bytes)
+    );
+    Ok(())
+}
+
+async fn pin_images(
+    client: &mut ImageServiceClient<Channel>,
+    image_refs: Vec<String>,
+    detach: bool,
+) -> Result<()> {
+    let mut pulls = Vec::new();
+    for image_ref in image_refs {
+        let request = PinImageRequest {
+            image_ref: image_ref.clone(),
+        };
+        let pinned = client
+            .pin_image(request)
+            .await?
+            .into_inner()
+            .pinned_image
+            .unwrap_or_default();
+        println!("Pinned {image_ref} to image {}", pinned.image_uuid);
+        if pinned.state == ImageState::Downloading as i32 {
+            pulls.push(pinned.image_uuid);
+        }
+    }
+
+    if detach {
+        if !pulls.is_empty() {
+            println!("Use 'feos-cli image pins' to see the progress of the pulls.");
+        }
+        return Ok(());
+    }
+    for image_uuid in pulls {
+        follow_image(client, image_uuid, "pull", "Pulled").await?;
+    }
+    Ok(())
+}
+
+async fn unpin_images(
+    client: &mut ImageServiceClient<Channel>,
+    image_refs: Vec<String>,
+) -> Result<()> {
+    for image_ref in image_refs {
+        let request = UnpinImageRequest {
+            image_ref: image_ref.clone(),
+        };
+        client.unpin_image(request).await?;
+        println!("Unpinned {image_ref}");
+    }
+    Ok(())
+}
+
+async fn list_pinned_images(client: &mut ImageServiceClient<Channel>) -> Result<()> {
+    let response = client
+        .list_pinned_images(ListPinnedImagesRequest {})
+        .await?
+        .into_inner();
+    if response.pinned_images.is_empty() {
+        println!("No pinned images found.");
+        return Ok(());
+    }
+
+    println!(
+        "{:<40} {:<38} {:<12} {:>10} MESSAGE",
+        "REFERENCE", "UUID", "STATE", "SIZE"
+    );
+    println!(
+        "{:-<40} {:-<38} {:-<12} {:->10} {:-<20}",
+        "", "", "", "", ""
+    );
+    for pinned in response.pinned_images {
+        let state = ImageState::try_from(pinned.state).unwrap_or_default();
+        let state = match state {
+            ImageState::Downloading => format!("{state:?} {}%", pinned.progress_percent),
+            state => format!("{state:?}"),
+        };
+        println!(
+            "{:<40} {:<38} {:<12} {:>10} {}",
+            pinned.image_ref,
+            pinned.image_uuid,
+            state,
+            format_bytes(pinned.size_bytes),
+            pinned.message
+        );
+    }
     Ok(())
 }
@@ -3,15 +3,36 @@
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
+use feos_proto::{container_service::container_service_server, vm_service::vm_service_server};
 
+mod apply_commands;
+mod audit_commands;
+mod config;
 mod container_commands;
+mod context_commands;
+mod cp_commands;
+mod debug_commands;
+mod events_commands;
 mod host_commands;
 mod image_commands;
+mod operation_commands;
+mod port_forward_commands;
+mod schedule_commands;
+mod secret_commands;
+mod storage_commands;
 mod vm_commands;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
+    #[arg(
+        long,
+        global = true,
+        env = "FEOS_CONTEXT",
+        help = "Name of the client config context to use instead of the current one"
+    )]
+    context: Option<String>,
+
     #[command(subcommand)]
     service: Service,
 }
@@ -22,6 +43,26 @@ enum Service {
     Host(host_commands::HostArgs),
     Image(image_commands::ImageArgs),
     Container(container_commands::ContainerArgs),
+    Apply(apply_commands::ApplyArgs),
+    Context(context_commands::ContextArgs),
+    /// Copy files and directories between the local machine and a container, or files to and from a VM
+    Cp(cp_commands::CpArgs),
+    /// Collect diagnostics for support cases
+    Debug(debug_commands::DebugArgs),
+    /// Show VM and container lifecycle events
+    Events(events_commands::EventsArgs),
+    /// Track long-running operations started with --async
+    Operation(operation_commands::OperationArgs),
+    /// Forward local TCP ports to a VM or container
+    PortForward(port_forward_commands::PortForwardArgs),
+    /// Manage storage pools and volumes
+    Storage(storage_commands::StorageArgs),
+    /// Run maintenance actions on a cron schedule
+    Schedule(schedule_commands::ScheduleArgs),
+    /// Manage secrets of workloads
+    Secret(secret_commands::SecretArgs),
+    /// Show the state-changing API calls recorded in the audit log
+    Audit(audit_commands::AuditArgs),
 }
 
 #[tokio::main]
@@ -32,12 +73,52 @@ async fn main() -> Result<()> {
         .init();
 
     let cli = Cli::parse();
+    let context = cli.context.as_deref();
 
     match cli.service {
-        Service::Vm(args) => vm_commands::handle_vm_command(args).await?,
-        Service::Host(args) => host_commands::handle_host_command(args).await?,
+        Service::Vm(args) => {
+            let address = args.address.clone();
+            let result = vm_commands::handle_vm_command(args, context).await;
+            host_commands::explain_missing_service(
+                result,
+                address.as_deref(),
+                context,
+                vm_service_server::SERVICE_NAME,
+                "VM",
+            )
+            .await?
+        }
+        Service::Host(args) => host_commands::handle_host_command(args, context).await?,
         Service::Image(args) => image_commands::handle_image_command(args).await?,
-        Service::Container(args) => container_commands::handle_container_command(args).await?,
+        Service::Container(args) => {
+            let address = args.address.clone();
+            let result = container_commands::handle_container_command(args, context).await;
+            host_commands::explain_missing_service(
+                result,
+                address.as_deref(),
+                context,
+                container_service_server::SERVICE_NAME,
+                "container",
+            )
+            .await?
+        }
+        Service::Apply(args) => apply_commands::handle_apply_command(args, context).await?,
+        Service::Context(args) => context_commands::handle_context_command(args).await?,
+        Service::Cp(args) => cp_commands::handle_cp_command(args, context).await?,
+        Service::Debug(args) => debug_commands::handle_debug_command(args, context).await?,
+        Service::Events(args) => events_commands::handle_events_command(args, context).await?,
+        Service::Operation(args) => {
+            operation_commands::handle_operation_command(args, context).await?
+        }
+        Service::PortForward(args) => {
+            port_forward_commands::handle_port_forward_command(args, context).await?
+        }
+        Service::Storage(args) => storage_commands::handle_storage_command(args, context).await?,
+        Service::Schedule(args) => {
+            schedule_commands::handle_schedule_command(args, context).await?
+        }
+        Service::Secret(args) => secret_commands::handle_secret_command(args, context).await?,
+        Service::Audit(args) => audit_commands::handle_audit_command(args, context).await?,
     }
 
     Ok(())
@@ -0,0 +1,523 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::{self, Channel};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Args, Subcommand};
+use crossterm::tty::IsTty;
+use crossterm::{
+    cursor, queue,
+    terminal::{Clear, ClearType},
+};
+use feos_proto::container_service::{
+    container_service_client::ContainerServiceClient, ContainerState, ContainerStateChangedEvent,
+    GetContainerRequest, StreamContainerEventsRequest,
+};
+use feos_proto::vm_service::{
+    vm_service_client::VmServiceClient, GetVmRequest, StreamVmEventsRequest, VmState,
+    VmStateChangedEvent,
+};
+use prost::Message;
+use std::fmt;
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Code, Status};
+
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+const RECHECK_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_RETRIES: u32 = 5;
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+type StateStream = Pin<Box<dyn Stream<Item = Result<StateUpdate, Status>> + Send>>;
+
+#[derive(Args, Debug)]
+pub struct OperationArgs {
+    #[arg(
+        short,
+        long,
+        global = true,
+        env = "FEOS_ADDRESS",
+        help = "FeOS API address, overriding the address of the selected context"
+    )]
+    pub address: Option<String>,
+
+    #[command(subcommand)]
+    command: OperationCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum OperationCommand {
+    /// Resume tracking an operation started with --async until it finishes
+    Wait {
+        #[arg(
+            required = true,
+            help = "Operation ID printed by a command run with --async"
+        )]
+        operation_id: String,
+
+        #[arg(long, help = "Give up after this many seconds")]
+        timeout: Option<u64>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperationKind {
+    VmCreate,
+    VmStart,
+    ContainerCreate,
+    ContainerStart,
+}
+
+impl OperationKind {
+    const ALL: [OperationKind; 4] = [
+        OperationKind::VmCreate,
+        OperationKind::VmStart,
+        OperationKind::ContainerCreate,
+        OperationKind::ContainerStart,
+    ];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            OperationKind::VmCreate => "vm-create",
+            OperationKind::VmStart => "vm-start",
+            OperationKind::ContainerCreate => "container-create",
+            OperationKind::ContainerStart => "container-start",
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            OperationKind::VmCreate => "Creating VM",
+            OperationKind::VmStart => "Starting VM",
+            OperationKind::ContainerCreate => "Creating container",
+            OperationKind::ContainerStart => "Starting container",
+        }
+    }
+}
+
+/// A long-running request such as creating or starting a workload.
+///
+/// These RPCs return as soon as the request is accepted and the resource then moves
+/// through its states, so an operation is tracked by following the resource's events
+/// until it reaches the state the operation waits for. Its ID is `<kind>/<resource id>`,
+/// e.g. `vm-start/0b6b2c8e-...`, which `feos-cli operation wait` uses to resume tracking.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operation {
+    pub kind: OperationKind,
+    pub resource_id: String,
+}
+
+impl Operation {
+    pub fn new(kind: OperationKind, resource_id: impl Into<String>) -> Self {
+        Self {
+            kind,
+            resource_id: resource_id.into(),
+        }
+    }
+
+    pub fn parse(id: &str) -> Result<Self> {
+        let (kind, resource_id) = id
+            .split_once('/')
+            .ok_or_else(|| anyhow!("Invalid operation ID '{id}', expected <kind>/<id>"))?;
+        let kind = OperationKind::ALL
+            .into_iter()
+            .find(|candidate| candidate.as_str() == kind)
+            .ok_or_else(|| anyhow!("Unknown operation kind '{kind}'"))?;
+        if resource_id.is_empty() {
+            bail!("Invalid operation ID '{id}', the resource ID is missing");
+        }
+        Ok(Self::new(kind, resource_id))
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.kind.as_str(), self.resource_id)
+    }
+}
+
+/// Prints the operation ID of a command run with `--async`. Only the ID goes to
+/// stdout so scripts can capture it.
+pub fn print_async(operation: &Operation) {
+    println!("{operation}");
+    eprintln!("Use 'feos-cli operation wait {operation}' to track its progress.");
+}
+
+pub async fn handle_operation_command(args: OperationArgs, context: Option<&str>) -> Result<()> {
+    let channel = config::connect(args.address.as_deref(), context)
+        .await
+        .context("Failed to connect to FeOS")?;
+
+    match args.command {
+        OperationCommand::Wait {
+            operation_id,
+            timeout,
+        } => {
+            let operation = Operation::parse(&operation_id)?;
+            let wait = wait_for_operation(&channel, &operation);
+            match timeout {
+                Some(seconds) => tokio::time::timeout(Duration::from_secs(seconds), wait)
+                    .await
+                    .map_err(|_| anyhow!("Timed out waiting for operation {operation}"))?,
+                None => wait.await,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Pending,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Debug)]
+struct StateUpdate {
+    state: String,
+    reason: String,
+    outcome: Outcome,
+}
+
+impl OperationKind {
+    fn vm_outcome(self, state: VmState) -> Outcome {
+        match (self, state) {
+            (_, VmState::Crashed) => Outcome::Failed,
+            (
+                OperationKind::VmCreate,
+                VmState::Created | VmState::Running | VmState::Paused | VmState::Stopped,
+            ) => Outcome::Succeeded,
+            (OperationKind::VmStart, VmState::Running | VmState::Paused) => Outcome::Succeeded,
+            (OperationKind::VmStart, VmState::Stopped) => Outcome::Failed,
+            _ => Outcome::Pending,
+        }
+    }
+
+    fn container_outcome(self, state: ContainerState) -> Outcome {
+        match (self, state) {
+            (
+                OperationKind::ContainerCreate,
+                ContainerState::Created | ContainerState::Running | ContainerState::Stopped,
+            ) => Outcome::Succeeded,
+            (_, ContainerState::Failed) => Outcome::Failed,
+            // A short-lived container may already have exited again.
+            (OperationKind::ContainerStart, ContainerState::Running | ContainerState::Stopped) => {
+                Outcome::Succeeded
+            }
+            _ => Outcome::Pending,
+        }
+    }
+}
+
+/// Follows an operation with a progress line until it succeeds or fails.
+/// Dropped connections are retried with exponential backoff.
+pub async fn wait_for_operation(channel: &Channel, operation: &Operation) -> Result<()> {
+    let mut progress = Progress::new(operation);
+    let mut retries = 0;
+    loop {
+        match follow_once(channel, operation, &mut progress).await {
+            Ok(update) if update.outcome == Outcome::Succeeded => {
+                progress.finish(&format!("{operation} finished: {}", update.state))?;
+                return Ok(());
+            }
+            Ok(update) => {
+                progress.finish(&format!("{operation} failed: {}", update.state))?;
+                if update.reason.is_empty() {
+                    bail!("Operation {operation} failed in state {}", update.state);
+                }
+                bail!(
+                    "Operation {operation} failed in state {}: {}",
+                    update.state,
+                    update.reason
+                );
+            }
+            Err(status) if is_retryable(&status) && retries < MAX_RETRIES => {
+                retries += 1;
+                let delay = RETRY_BASE_DELAY * 2u32.pow(retries - 1);
+                progress.update(
+                    "Reconnecting",
+                    &format!("{} (attempt {retries}/{MAX_RETRIES})", status.message()),
+                )?;
+                tokio::time::sleep(delay).await;
+            }
+            Err(status) if status.code() == Code::NotFound => {
+                progress.finish(&format!("{operation} failed"))?;
+                bail!(
+                    "Operation {operation} failed: {} no longer exists",
+                    operation.resource_id
+                );
+            }
+            Err(status) => {
+                progress.finish(&format!("{operation} failed"))?;
+                return Err(status)
+                    .with_context(|| format!("Failed to track operation {operation}"));
+            }
+        }
+    }
+}
+
+fn is_retryable(status: &Status) -> bool {
+    matches!(status.code(), Code::Unavailable | Code::Unknown)
+}
+
+/// Subscribes to the resource's events and returns once the operation is done.
+/// The current state is re-read periodically as well, so a resource that is
+/// removed without a final event fails the wait with `NotFound`.
+async fn follow_once(
+    channel: &Channel,
+    operation: &Operation,
+    progress: &mut Progress,
+) -> Result<StateUpdate, Status> {
+    // Subscribe before reading the current state so no transition is missed.
+    let mut updates = match operation.kind {
+        OperationKind::VmCreate | OperationKind::VmStart => vm_updates(channel, operation).await?,
+        OperationKind::ContainerCreate | OperationKind::ContainerStart => {
+            container_updates(channel, operation).await?
+        }
+    };
+
+    let mut update = current_state(channel, operation).await?;
+    let mut redraw = tokio::time::interval(REDRAW_INTERVAL);
+    let mut recheck = tokio::time::interval(RECHECK_INTERVAL);
+    recheck.reset();
+    loop {
+        progress
+            .update(&update.state, &update.reason)
+            .map_err(|e| Status::internal(e.to_string()))?;
+        if update.outcome != Outcome::Pending {
+            return Ok(update);
+        }
+        tokio::select! {
+            next = updates.next() => match next {
+                Some(next) => update = next?,
+                None => return Err(Status::unavailable("event stream closed")),
+            },
+            _ = recheck.tick() => {
+                let current = current_state(channel, operation).await?;
+                // Keep the reason of the last event while the state is unchanged.
+                if current.state != update.state || current.outcome != Outcome::Pending {
+                    update = current;
+                }
+            }
+            _ = redraw.tick() => {}
+        }
+    }
+}
+
+async fn current_state(channel: &Channel, operation: &Operation) -> Result<StateUpdate, Status> {
+    let kind = operation.kind;
+    match kind {
+        OperationKind::VmCreate | OperationKind::VmStart => {
+            let vm = VmServiceClient::new(channel.clone())
+                .get_vm(GetVmRequest {
+                    vm_id: operation.resource_id.clone(),
+                })
+                .await?
+                .into_inner();
+            let state = VmState::try_from(vm.state).unwrap_or(VmState::Unspecified);
+            Ok(StateUpdate {
+                state: format!("{state:?}"),
+                reason: String::new(),
+                outcome: kind.vm_outcome(state),
+            })
+        }
+        OperationKind::ContainerCreate | OperationKind::ContainerStart => {
+            let container = ContainerServiceClient::new(channel.clone())
+                .get_container(GetContainerRequest {
+                    container_id: operation.resource_id.clone(),
+                })
+                .await?
+                .into_inner();
+            let state =
+                ContainerState::try_from(container.state).unwrap_or(ContainerState::Unspecified);
+            Ok(StateUpdate {
+                state: format!("{state:?}"),
+                reason: String::new(),
+                outcome: kind.container_outcome(state),
+            })
+        }
+    }
+}
+
+async fn vm_updates(channel: &Channel, operation: &Operation) -> Result<StateStream, Status> {
+    let mut client = VmServiceClient::new(channel.clone());
+    let kind = operation.kind;
+    let events = client
+        .stream_vm_events(StreamVmEventsRequest {
+            vm_id: Some(operation.resource_id.clone()),
+            ..Default::default()
+        })
+        .await?
+        .into_inner();
+    let updates = events.filter_map(move |event| {
+        let data = match event {
+            Ok(event) => event.data?,
+            Err(status) => return Some(Err(status)),
+        };
+        if !data.type_url.ends_with("VmStateChangedEvent") {
+            return None;
+        }
+        let change = VmStateChangedEvent::decode(&*data.value).ok()?;
+        let state = VmState::try_from(change.new_state).unwrap_or(VmState::Unspecified);
+        Some(Ok(StateUpdate {
+            state: format!("{state:?}"),
+            reason: change.reason,
+            outcome: kind.vm_outcome(state),
+        }))
+    });
+    Ok(Box::pin(updates))
+}
+
+async fn container_updates(
+    channel: &Channel,
+    operation: &Operation,
+) -> Result<StateStream, Status> {
+    let mut client = ContainerServiceClient::new(channel.clone());
+    let kind = operation.kind;
+    let events = client
+        .stream_container_events(StreamContainerEventsRequest {
+            container_id: Some(operation.resource_id.clone()),
+            ..Default::default()
+        })
+        .await?
+        .into_inner();
+    let updates = events.filter_map(move |event| {
+        let data = match event {
+            Ok(event) => event.data?,
+            Err(status) => return Some(Err(status)),
+        };
+        if !data.type_url.ends_with("ContainerStateChangedEvent") {
+            return None;
+        }
+        let change = ContainerStateChangedEvent::decode(&*data.value).ok()?;
+        let state =
+            ContainerState::try_from(change.new_state).unwrap_or(ContainerState::Unspecified);
+        Some(Ok(StateUpdate {
+            state: format!("{state:?}"),
+            reason: change.reason,
+            outcome: kind.container_outcome(state),
+        }))
+    });
+    Ok(Box::pin(updates))
+}
+
+/// A single status line with a spinner on terminals, plain state changes otherwise.
+struct Progress {
+    label: String,
+    started: Instant,
+    frame: usize,
+    state: String,
+    reason: String,
+    interactive: bool,
+}
+
+impl Progress {
+    fn new(operation: &Operation) -> Self {
+        Self {
+            label: format!("{} {}", operation.kind.description(), operation.resource_id),
+            started: Instant::now(),
+            frame: 0,
+            state: String::new(),
+            reason: String::new(),
+            interactive: io::stdout().is_tty(),
+        }
+    }
+
+    fn update(&mut self, state: &str, reason: &str) -> Result<()> {
+        if state != self.state || reason != self.reason {
+            self.state = state.to_string();
+            self.reason = reason.to_string();
+            if !self.interactive {
+                match reason {
+                    "" => println!("{}: {state}", self.label),
+                    _ => println!("{}: {state} ({reason})", self.label),
+                }
+            }
+        }
+        if !self.interactive {
+            return Ok(());
+        }
+
+        self.frame = (self.frame + 1) % SPINNER.len();
+        let mut stdout = io::stdout();
+        queue!(
+            stdout,
+            cursor::MoveToColumn(0),
+            Clear(ClearType::CurrentLine)
+        )?;
+        let mut line = format!(
+            "{} {} [{:>4}s] {}",
+            SPINNER[self.frame],
+            self.label,
+            self.started.elapsed().as_secs(),
+            self.state
+        );
+        if !self.reason.is_empty() {
+            line.push_str(&format!(" - {}", self.reason));
+        }
+        write!(stdout, "{line}")?;
+        stdout.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self, message: &str) -> Result<()> {
+        if self.interactive {
+            let mut stdout = io::stdout();
+            queue!(
+                stdout,
+                cursor::MoveToColumn(0),
+                Clear(ClearType::CurrentLine)
+            )?;
+            stdout.flush()?;
+        }
+        println!("{message} ({:.1}s)", self.started.elapsed().as_secs_f32());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_operation_id_roundtrip() {
+        let operation = Operation::new(
+            OperationKind::ContainerStart,
+            "0b8a9f3c-1d2e-4f5a-8b6c-7d8e9f0a1b2c",
+        );
+        let id = operation.to_string();
+        assert_eq!(id, "container-start/0b8a9f3c-1d2e-4f5a-8b6c-7d8e9f0a1b2c");
+        assert_eq!(Operation::parse(&id).unwrap(), operation);
+
+        assert!(Operation::parse("vm-create").is_err());
+        assert!(Operation::parse("vm-create/").is_err());
+        assert!(Operation::parse("vm-migrate/abc").is_err());
+    }
+
+    #[test]
+    fn test_outcomes() {
+        assert_eq!(
+            OperationKind::VmCreate.vm_outcome(VmState::Creating),
+            Outcome::Pending
+        );
+        assert_eq!(
+            OperationKind::VmCreate.vm_outcome(VmState::Created),
+            Outcome::Succeeded
+        );
+        assert_eq!(
+            OperationKind::VmStart.vm_outcome(VmState::Crashed),
+            Outcome::Failed
+        );
+        assert_eq!(
+            OperationKind::ContainerStart.container_outcome(ContainerState::Created),
+            Outcome::Pending
+        );
+        assert_eq!(
+            OperationKind::ContainerCreate.container_outcome(ContainerState::Failed),
+            Outcome::Failed
+        );
+    }
+}
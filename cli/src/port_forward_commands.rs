@@ -0,0 +1,257 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::{self, Channel};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::Args;
+use feos_proto::container_service::{
+    container_service_client::ContainerServiceClient, port_forward_request as container_pf,
+    GetContainerRequest, PortForwardRequest as ContainerPortForwardRequest,
+    PortForwardStart as ContainerPortForwardStart,
+};
+use feos_proto::vm_service::{
+    port_forward_request as vm_pf, vm_service_client::VmServiceClient, GetVmRequest,
+    PortForwardRequest as VmPortForwardRequest, PortForwardStart as VmPortForwardStart,
+};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+use tonic::{Code, Status};
+
+type TunnelOutput = Pin<Box<dyn Stream<Item = Result<Vec<u8>, Status>> + Send>>;
+
+#[derive(Args, Debug)]
+pub struct PortForwardArgs {
+    #[arg(
+        short,
+        long,
+        env = "FEOS_ADDRESS",
+        help = "FeOS API address, overriding the address of the selected context"
+    )]
+    pub address: Option<String>,
+
+    #[arg(help = "Workload to forward to: vm/<id>, container/<id> or a bare ID")]
+    workload: String,
+
+    #[arg(
+        required = true,
+        help = "Port mappings as <local>:<remote>, or <port> to use the same port on both sides"
+    )]
+    ports: Vec<String>,
+
+    #[arg(long, default_value = "127.0.0.1", help = "Local address to listen on")]
+    bind_address: IpAddr,
+}
+
+#[derive(Debug, Clone)]
+enum Workload {
+    Vm(String),
+    Container(String),
+}
+
+impl fmt::Display for Workload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Workload::Vm(id) => write!(f, "vm/{id}"),
+            Workload::Container(id) => write!(f, "container/{id}"),
+        }
+    }
+}
+
+pub async fn handle_port_forward_command(
+    args: PortForwardArgs,
+    context: Option<&str>,
+) -> Result<()> {
+    let mappings = args
+        .ports
+        .iter()
+        .map(|mapping| parse_port_mapping(mapping))
+        .collect::<Result<Vec<_>>>()?;
+
+    let channel = config::connect(args.address.as_deref(), context)
+        .await
+        .context("Failed to connect to FeOS")?;
+    let workload = resolve_workload(&channel, &args.workload).await?;
+
+    for (local_port, remote_port) in mappings {
+        let listen_addr = SocketAddr::new(args.bind_address, local_port);
+        let listener = TcpListener::bind(listen_addr)
+            .await
+            .with_context(|| format!("Failed to listen on {listen_addr}"))?;
+        println!("Forwarding from {listen_addr} -> {workload}:{remote_port}");
+        tokio::spawn(accept_loop(
+            listener,
+            channel.clone(),
+            workload.clone(),
+            remote_port,
+        ));
+    }
+
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
+
+fn parse_port_mapping(mapping: &str) -> Result<(u16, u16)> {
+    let parse = |port: &str| {
+        port.parse::<u16>()
+            .ok()
+            .filter(|port| *port != 0)
+            .ok_or_else(|| anyhow!("Invalid port '{port}' in mapping '{mapping}'"))
+    };
+    match mapping.split_once(':') {
+        Some((local, remote)) => Ok((parse(local)?, parse(remote)?)),
+        None => {
+            let port = parse(mapping)?;
+            Ok((port, port))
+        }
+    }
+}
+
+async fn resolve_workload(channel: &Channel, workload: &str) -> Result<Workload> {
+    if let Some(id) = workload.strip_prefix("vm/") {
+        return Ok(Workload::Vm(id.to_string()));
+    }
+    if let Some(id) = workload.strip_prefix("container/") {
+        return Ok(Workload::Container(id.to_string()));
+    }
+
+    let is_missing =
+        |status: &Status| matches!(status.code(), Code::NotFound | Code::InvalidArgument);
+
+    let vm_request = GetVmRequest {
+        vm_id: workload.to_string(),
+    };
+    match VmServiceClient::new(channel.clone())
+        .get_vm(vm_request)
+        .await
+    {
+        Ok(_) => return Ok(Workload::Vm(workload.to_string())),
+        Err(status) if is_missing(&status) => {}
+        Err(status) => return Err(status.into()),
+    }
+
+    let container_request = GetContainerRequest {
+        container_id: workload.to_string(),
+    };
+    match ContainerServiceClient::new(channel.clone())
+        .get_container(container_request)
+        .await
+    {
+        Ok(_) => Ok(Workload::Container(workload.to_string())),
+        Err(status) if is_missing(&status) => {
+            bail!("No VM or container with ID '{workload}' found")
+        }
+        Err(status) => Err(status.into()),
+    }
+}
+
+async fn accept_loop(listener: TcpListener, channel: Channel, workload: Workload, port: u16) {
+    loop {
+        let (socket, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                eprintln!("Failed to accept connection: {e}");
+                continue;
+            }
+        };
+        println!("Handling connection from {peer} for {workload}:{port}");
+        let channel = channel.clone();
+        let workload = workload.clone();
+        tokio::spawn(async move {
+            if let Err(e) = forward_connection(channel, &workload, port, socket).await {
+                eprintln!("Connection from {peer} to {workload}:{port} failed: {e:#}");
+            }
+        });
+    }
+}
+
+/// Opens a PortForward stream for one connection. Data sent to the returned
+/// sender is forwarded to the workload; dropping it half-closes the tunnel.
+async fn open_tunnel(
+    channel: Channel,
+    workload: &Workload,
+    port: u16,
+) -> Result<(mpsc::Sender<Vec<u8>>, TunnelOutput)> {
+    let (data_tx, data_rx) = mpsc::channel::<Vec<u8>>(32);
+    let output: TunnelOutput = match workload {
+        Workload::Vm(vm_id) => {
+            let start = VmPortForwardRequest {
+                payload: Some(vm_pf::Payload::Start(VmPortForwardStart {
+                    vm_id: vm_id.clone(),
+                    port: port.into(),
+                })),
+            };
+            let requests =
+                tokio_stream::once(start).chain(ReceiverStream::new(data_rx).map(|data| {
+                    VmPortForwardRequest {
+                        payload: Some(vm_pf::Payload::Data(data)),
+                    }
+                }));
+            let responses = VmServiceClient::new(channel)
+                .port_forward(requests)
+                .await?
+                .into_inner();
+            Box::pin(responses.map(|res| res.map(|msg| msg.data)))
+        }
+        Workload::Container(container_id) => {
+            let start = ContainerPortForwardRequest {
+                payload: Some(container_pf::Payload::Start(ContainerPortForwardStart {
+                    container_id: container_id.clone(),
+                    port: port.into(),
+                })),
+            };
+            let requests =
+                tokio_stream::once(start).chain(ReceiverStream::new(data_rx).map(|data| {
+                    ContainerPortForwardRequest {
+                        payload: Some(container_pf::Payload::Data(data)),
+                    }
+                }));
+            let responses = ContainerServiceClient::new(channel)
+                .port_forward(requests)
+                .await?
+                .into_inner();
+            Box::pin(responses.map(|res| res.map(|msg| msg.data)))
+        }
+    };
+    Ok((data_tx, output))
+}
+
+async fn forward_connection(
+    channel: Channel,
+    workload: &Workload,
+    port: u16,
+    socket: TcpStream,
+) -> Result<()> {
+    let (data_tx, mut output) = open_tunnel(channel, workload, port).await?;
+    let (mut reader, mut writer) = socket.into_split();
+
+    let upload = tokio::spawn(async move {
+        let mut buf = vec![0; 16384];
+        loop {
+            match reader.read(&mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if data_tx.send(buf[..n].to_vec()).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+
+    let result = async {
+        while let Some(data) = output.next().await {
+            writer.write_all(&data?).await?;
+        }
+        writer.shutdown().await?;
+        Ok::<(), anyhow::Error>(())
+    }
+    .await;
+
+    upload.abort();
+    result
+}
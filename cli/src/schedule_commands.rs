@@ -0,0 +1,400 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::{self, Channel};
+use anyhow::{Context, Result};
+use clap::{ArgGroup, Args, Subcommand};
+use feos_proto::schedule_service::{
+    schedule_action::Action, schedule_service_client::ScheduleServiceClient, CreateScheduleRequest,
+    DatabaseBackupAction, DeleteScheduleRequest, DeleteVmSnapshotPolicyRequest, ImageGcAction,
+    ListSchedulesRequest, LogExportAction, Schedule, ScheduleAction, SetVmSnapshotPolicyRequest,
+    VmDiskReplicationAction, VmSnapshotAction,
+};
+use prost_types::Timestamp;
+
+#[derive(Args, Debug)]
+pub struct ScheduleArgs {
+    #[arg(
+        short,
+        long,
+        global = true,
+        env = "FEOS_ADDRESS",
+        help = "FeOS API address, overriding the address of the selected context"
+    )]
+    pub address: Option<String>,
+
+    #[command(subcommand)]
+    command: ScheduleCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ScheduleCommand {
+    /// Create a schedule that runs one maintenance action
+    #[command(group(ArgGroup::new("action").required(true)))]
+    Create {
+        #[arg(required = true, help = "Schedule name")]
+        name: String,
+
+        #[arg(
+            long,
+            required_unless_present = "every",
+            conflicts_with = "every",
+            help = "When to run, as a cron expression in UTC (e.g., \"30 2 * * *\" or @daily)"
+        )]
+        cron: Option<String>,
+
+        #[arg(
+            long,
+            value_parser = parse_interval,
+            help = "Run at a fixed interval instead (e.g., 30m, 6h or 1d)"
+        )]
+        every: Option<u64>,
+
+        #[arg(long, group = "action", value_name = "VM_ID", help = "Snapshot a VM")]
+        vm_snapshot: Option<String>,
+
+        #[arg(long, help = "With --vm-snapshot, include the memory state")]
+        include_memory: bool,
+
+        #[arg(
+            long,
+            group = "action",
+            value_name = "VM_ID",
+            requires = "peer",
+            help = "Replicate the disks of a VM to a peer node"
+        )]
+        replicate_vm: Option<String>,
+
+        #[arg(
+            long,
+            requires = "replicate_vm",
+            help = "With --replicate-vm, the public API of the peer, e.g. https://[2001:db8::2]:1337"
+        )]
+        peer: Option<String>,
+
+        #[arg(long, group = "action", help = "Remove unused image blobs and layers")]
+        image_gc: bool,
+
+        #[arg(
+            long,
+            group = "action",
+            value_name = "DIR",
+            help = "Back up the FeOS databases into a directory on the host"
+        )]
+        backup_dir: Option<String>,
+
+        #[arg(
+            long,
+            group = "action",
+            value_name = "DIR",
+            help = "Export the FeOS logs into a directory on the host"
+        )]
+        export_logs_dir: Option<String>,
+
+        #[arg(
+            long,
+            default_value = "0",
+            help = "With --export-logs-dir, only export the logs of this many hours before each run. 0 exports all"
+        )]
+        window_hours: u64,
+
+        #[arg(
+            long,
+            default_value = "0",
+            help = "How many snapshots, backups or log exports to keep. 0 keeps all"
+        )]
+        keep: u32,
+    },
+    /// List all schedules with their next and last run
+    List,
+    /// Delete a schedule
+    Delete {
+        #[arg(required = true, help = "Schedule identifier")]
+        id: String,
+    },
+    /// Manage the periodic snapshots of VMs
+    #[command(subcommand)]
+    SnapshotPolicy(SnapshotPolicyCommand),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SnapshotPolicyCommand {
+    /// Snapshot a VM periodically, replacing its previous policy
+    Set {
+        #[arg(required = true, help = "VM identifier")]
+        vm_id: String,
+
+        #[arg(
+            long,
+            required = true,
+            value_parser = parse_interval,
+            help = "How often to take a snapshot (e.g., 30m, 6h or 1d)"
+        )]
+        every: u64,
+
+        #[arg(long, required = true, help = "How many snapshots to keep")]
+        keep: u32,
+
+        #[arg(long, help = "Include the memory state")]
+        include_memory: bool,
+    },
+    /// Stop snapshotting a VM periodically. Its snapshots are kept
+    Delete {
+        #[arg(required = true, help = "VM identifier")]
+        vm_id: String,
+    },
+}
+
+/// Parses an interval like `90s`, `30m`, `6h` or `1d` into seconds.
+fn parse_interval(s: &str) -> Result<u64, String> {
+    let (number, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let factor = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(format!("invalid interval '{s}', use e.g. 30m, 6h or 1d")),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(factor))
+        .ok_or_else(|| format!("invalid interval '{s}', use e.g. 30m, 6h or 1d"))
+}
+
+/// Formats an interval in seconds the way `parse_interval` accepts it.
+fn format_interval(seconds: u64) -> String {
+    match seconds {
+        s if s % 86400 == 0 => format!("{}d", s / 86400),
+        s if s % 3600 == 0 => format!("{}h", s / 3600),
+        s if s % 60 == 0 => format!("{}m", s / 60),
+        s => format!("{s}s"),
+    }
+}
+
+pub async fn handle_schedule_command(args: ScheduleArgs, context: Option<&str>) -> Result<()> {
+    let channel = config::connect(args.address.as_deref(), context)
+        .await
+        .context("Failed to connect to schedule service")?;
+    let mut client = ScheduleServiceClient::new(channel);
+
+    match args.command {
+        ScheduleCommand::Create {
+            name,
+            cron,
+            every,
+            vm_snapshot,
+            include_memory,
+            replicate_vm,
+            peer,
+            image_gc,
+            backup_dir,
+            export_logs_dir,
+            window_hours,
+            keep,
+        } => {
+            let action = match (
+                vm_snapshot,
+                replicate_vm,
+                image_gc,
+                backup_dir,
+                export_logs_dir,
+            ) {
+                (Some(vm_id), ..) => Action::VmSnapshot(VmSnapshotAction {
+                    vm_id,
+                    include_memory,
+                    keep,
+                }),
+                (_, Some(vm_id), ..) => Action::VmDiskReplication(VmDiskReplicationAction {
+                    vm_id,
+                    peer: peer.unwrap_or_default(),
+                }),
+                (_, _, true, ..) => Action::ImageGc(ImageGcAction {}),
+                (_, _, _, Some(directory), _) => {
+                    Action::DatabaseBackup(DatabaseBackupAction { directory, keep })
+                }
+                (_, _, _, _, Some(directory)) => Action::LogExport(LogExportAction {
+                    directory,
+                    window_seconds: window_hours * 3600,
+                    keep,
+                }),
+                _ => unreachable!("clap requires exactly one action"),
+            };
+            let request = CreateScheduleRequest {
+                name,
+                cron: cron.unwrap_or_default(),
+                action: Some(ScheduleAction {
+                    action: Some(action),
+                }),
+                interval_seconds: every.unwrap_or_default(),
+            };
+            create_schedule(&mut client, request).await?
+        }
+        ScheduleCommand::List => list_schedules(&mut client).await?,
+        ScheduleCommand::Delete { id } => delete_schedule(&mut client, id).await?,
+        ScheduleCommand::SnapshotPolicy(command) => match command {
+            SnapshotPolicyCommand::Set {
+                vm_id,
+                every,
+                keep,
+                include_memory,
+            } => {
+                let request = SetVmSnapshotPolicyRequest {
+                    vm_id,
+                    interval_seconds: every,
+                    keep,
+                    include_memory,
+                };
+                set_vm_snapshot_policy(&mut client, request).await?
+            }
+            SnapshotPolicyCommand::Delete { vm_id } => {
+                delete_vm_snapshot_policy(&mut client, vm_id).await?
+            }
+        },
+    }
+
+    Ok(())
+}
+
+fn format_timestamp(timestamp: Option<&Timestamp>) -> String {
+    timestamp
+        .and_then(|t| chrono::DateTime::from_timestamp(t.seconds, t.nanos.max(0) as u32))
+        .map(|t| {
+            t.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// The cron expression of a schedule, or its interval.
+fn describe_trigger(schedule: &Schedule) -> String {
+    if schedule.interval_seconds > 0 {
+        format!("every {}", format_interval(schedule.interval_seconds))
+    } else {
+        schedule.cron.clone()
+    }
+}
+
+fn describe_action(action: Option<&Action>) -> String {
+    match action {
+        Some(Action::VmSnapshot(snapshot)) => {
+            format!("vm-snapshot {} keep {}", snapshot.vm_id, snapshot.keep)
+        }
+        Some(Action::ImageGc(_)) => "image-gc".to_string(),
+        Some(Action::DatabaseBackup(backup)) => format!("db-backup {}", backup.directory),
+        Some(Action::LogExport(export)) => format!("log-export {}", export.directory),
+        Some(Action::VmDiskReplication(replication)) => {
+            format!("vm-replicate {} to {}", replication.vm_id, replication.peer)
+        }
+        None => "unknown".to_string(),
+    }
+}
+
+async fn create_schedule(
+    client: &mut ScheduleServiceClient<Channel>,
+    request: CreateScheduleRequest,
+) -> Result<()> {
+    let response = client.create_schedule(request).await?.into_inner();
+    let schedule = response.schedule.unwrap_or_default();
+    println!("{}", schedule.schedule_id);
+    println!("Next run: {}", format_timestamp(schedule.next_run.as_ref()));
+    Ok(())
+}
+
+async fn list_schedules(client: &mut ScheduleServiceClient<Channel>) -> Result<()> {
+    let response = client
+        .list_schedules(ListSchedulesRequest {})
+        .await?
+        .into_inner();
+    if response.schedules.is_empty() {
+        println!("No schedules found.");
+        return Ok(());
+    }
+
+    println!(
+        "{:<38} {:<16} {:<16} {:<16} {:<24} ACTION",
+        "SCHEDULE_ID", "NAME", "WHEN", "NEXT_RUN", "LAST_RUN"
+    );
+    println!(
+        "{:-<38} {:-<16} {:-<16} {:-<16} {:-<24} {:-<6}",
+        "", "", "", "", "", ""
+    );
+    for schedule in response.schedules {
+        let last_run = match &schedule.last_run {
+            Some(run) => format!(
+                "{} {}",
+                format_timestamp(run.started_at.as_ref()),
+                if run.succeeded { "ok" } else { "failed" }
+            ),
+            None => "-".to_string(),
+        };
+        let action = schedule.action.as_ref().and_then(|a| a.action.as_ref());
+        println!(
+            "{:<38} {:<16} {:<16} {:<16} {:<24} {}",
+            schedule.schedule_id,
+            schedule.name,
+            describe_trigger(&schedule),
+            format_timestamp(schedule.next_run.as_ref()),
+            last_run,
+            describe_action(action)
+        );
+        if let Some(run) = schedule.last_run.filter(|run| !run.succeeded) {
+            println!("  Last error: {}", run.message);
+        }
+    }
+    Ok(())
+}
+
+async fn delete_schedule(
+    client: &mut ScheduleServiceClient<Channel>,
+    schedule_id: String,
+) -> Result<()> {
+    let request = DeleteScheduleRequest {
+        schedule_id: schedule_id.clone(),
+    };
+    client.delete_schedule(request).await?;
+    println!("Deleted schedule {schedule_id}");
+    Ok(())
+}
+
+async fn set_vm_snapshot_policy(
+    client: &mut ScheduleServiceClient<Channel>,
+    request: SetVmSnapshotPolicyRequest,
+) -> Result<()> {
+    let response = client.set_vm_snapshot_policy(request).await?.into_inner();
+    let schedule = response.schedule.unwrap_or_default();
+    println!("{}", schedule.schedule_id);
+    println!("Next run: {}", format_timestamp(schedule.next_run.as_ref()));
+    Ok(())
+}
+
+async fn delete_vm_snapshot_policy(
+    client: &mut ScheduleServiceClient<Channel>,
+    vm_id: String,
+) -> Result<()> {
+    let request = DeleteVmSnapshotPolicyRequest {
+        vm_id: vm_id.clone(),
+    };
+    client.delete_vm_snapshot_policy(request).await?;
+    println!("Deleted the snapshot policy of VM {vm_id}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intervals_round_trip() {
+        assert_eq!(parse_interval("90s"), Ok(90));
+        assert_eq!(parse_interval("30m"), Ok(1800));
+        assert_eq!(parse_interval("6h"), Ok(21600));
+        assert_eq!(parse_interval("1d"), Ok(86400));
+        assert!(parse_interval("30").is_err());
+        assert!(parse_interval("h").is_err());
+        assert!(parse_interval("1.5h").is_err());
+        assert_eq!(format_interval(21600), "6h");
+        assert_eq!(format_interval(90), "90s");
+    }
+}
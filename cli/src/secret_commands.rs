@@ -0,0 +1,214 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::{self, Channel};
+use crate::storage_commands::format_bytes;
+use anyhow::{Context, Result};
+use clap::{ArgGroup, Args, Subcommand};
+use feos_proto::secret_service::{
+    secret_service_client::SecretServiceClient, DeleteSecretRequest, ListSecretsRequest,
+    PutSecretRequest,
+};
+use prost_types::Timestamp;
+use std::io::Read;
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+pub struct SecretArgs {
+    #[arg(
+        short,
+        long,
+        global = true,
+        env = "FEOS_ADDRESS",
+        help = "FeOS API address, overriding the address of the selected context"
+    )]
+    pub address: Option<String>,
+
+    #[command(subcommand)]
+    command: SecretCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SecretCommand {
+    /// Create a secret or replace its value
+    #[command(group(ArgGroup::new("value_source").required(true)))]
+    Put {
+        #[arg(required = true, help = "Secret name")]
+        name: String,
+
+        #[arg(
+            long,
+            env = "FEOS_NAMESPACE",
+            help = "Namespace of the secret [default: default]"
+        )]
+        namespace: Option<String>,
+
+        #[arg(
+            long,
+            group = "value_source",
+            value_name = "PATH",
+            help = "Read the value from a file, - for stdin"
+        )]
+        from_file: Option<PathBuf>,
+
+        #[arg(
+            long,
+            group = "value_source",
+            help = "The value, which ends up in the shell history"
+        )]
+        value: Option<String>,
+    },
+    /// List secrets without their values
+    List {
+        #[arg(
+            long,
+            env = "FEOS_NAMESPACE",
+            help = "Only list secrets in this namespace (lists all namespaces if not provided)"
+        )]
+        namespace: Option<String>,
+    },
+    /// Delete a secret
+    Delete {
+        #[arg(required = true, help = "Secret name")]
+        name: String,
+
+        #[arg(
+            long,
+            env = "FEOS_NAMESPACE",
+            help = "Namespace of the secret [default: default]"
+        )]
+        namespace: Option<String>,
+    },
+}
+
+/// Parses `SECRET:/path`, a secret written to a file of a workload.
+pub(crate) fn parse_secret_file(s: &str) -> Result<(String, String), String> {
+    s.split_once(':')
+        .filter(|(name, path)| !name.is_empty() && path.starts_with('/'))
+        .map(|(name, path)| (name.to_string(), path.to_string()))
+        .ok_or_else(|| format!("invalid SECRET:/path format: {s}"))
+}
+
+pub async fn handle_secret_command(args: SecretArgs, context: Option<&str>) -> Result<()> {
+    let channel = config::connect(args.address.as_deref(), context)
+        .await
+        .context("Failed to connect to secret service")?;
+    let mut client = SecretServiceClient::new(channel);
+
+    match args.command {
+        SecretCommand::Put {
+            name,
+            namespace,
+            from_file,
+            value,
+        } => {
+            let value = match (from_file, value) {
+                (Some(path), _) if path.as_os_str() == "-" => {
+                    let mut value = Vec::new();
+                    std::io::stdin()
+                        .read_to_end(&mut value)
+                        .context("Failed to read the value from stdin")?;
+                    value
+                }
+                (Some(path), _) => std::fs::read(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?,
+                (None, Some(value)) => value.into_bytes(),
+                (None, None) => unreachable!("clap requires a value source"),
+            };
+            let request = PutSecretRequest {
+                namespace: namespace.unwrap_or_default(),
+                name,
+                value,
+            };
+            put_secret(&mut client, request).await?
+        }
+        SecretCommand::List { namespace } => list_secrets(&mut client, namespace).await?,
+        SecretCommand::Delete { name, namespace } => {
+            let request = DeleteSecretRequest {
+                namespace: namespace.unwrap_or_default(),
+                name,
+            };
+            delete_secret(&mut client, request).await?
+        }
+    }
+
+    Ok(())
+}
+
+fn format_timestamp(timestamp: Option<&Timestamp>) -> String {
+    timestamp
+        .and_then(|t| chrono::DateTime::from_timestamp(t.seconds, t.nanos.max(0) as u32))
+        .map(|t| {
+            t.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_else(|| "-".to_string())
+}
+
+async fn put_secret(
+    client: &mut SecretServiceClient<Channel>,
+    request: PutSecretRequest,
+) -> Result<()> {
+    let name = request.name.clone();
+    let response = client.put_secret(request).await?.into_inner();
+    if response.created {
+        println!("Created secret {name}");
+    } else {
+        println!("Replaced the value of secret {name}");
+    }
+    Ok(())
+}
+
+async fn list_secrets(
+    client: &mut SecretServiceClient<Channel>,
+    namespace: Option<String>,
+) -> Result<()> {
+    let response = client
+        .list_secrets(ListSecretsRequest { namespace })
+        .await?
+        .into_inner();
+    if response.secrets.is_empty() {
+        println!("No secrets found.");
+        return Ok(());
+    }
+
+    println!("{:<20} {:<32} {:<10} UPDATED", "NAMESPACE", "NAME", "SIZE");
+    println!("{:-<20} {:-<32} {:-<10} {:-<16}", "", "", "", "");
+    for secret in response.secrets {
+        println!(
+            "{:<20} {:<32} {:<10} {}",
+            secret.namespace,
+            secret.name,
+            format_bytes(secret.size_bytes),
+            format_timestamp(secret.updated_at.as_ref())
+        );
+    }
+    Ok(())
+}
+
+async fn delete_secret(
+    client: &mut SecretServiceClient<Channel>,
+    request: DeleteSecretRequest,
+) -> Result<()> {
+    let name = request.name.clone();
+    client.delete_secret(request).await?;
+    println!("Deleted secret {name}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secret_files_are_names_with_absolute_paths() {
+        assert_eq!(
+            parse_secret_file("tls-key:/etc/tls/key.pem"),
+            Ok(("tls-key".to_string(), "/etc/tls/key.pem".to_string()))
+        );
+        assert!(parse_secret_file("tls-key").is_err());
+        assert!(parse_secret_file("tls-key:etc/key.pem").is_err());
+        assert!(parse_secret_file(":/etc/key.pem").is_err());
+    }
+}
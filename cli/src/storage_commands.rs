@@ -0,0 +1,625 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::{self, Channel};
+use anyhow::{Context, Result};
+use clap::{Args, Subcommand, ValueEnum};
+use feos_proto::storage_service::{
+    encryption_config::KeySource, pool_config, storage_service_client::StorageServiceClient,
+    CloseVolumeRequest, CreatePoolRequest, CreateVolumeRequest, DeletePoolRequest,
+    DeleteVolumeRequest, DirectoryPool, EncryptionConfig, FilesystemUsage, GetDiskUsageRequest,
+    ListPoolsRequest, ListVolumesRequest, LvmThinPool, OpenVolumeRequest, PoolConfig,
+    ResizePoolRequest, ResizeVolumeRequest, TpmKeySource, UsageCategory, UsageLevel,
+    VolumeEncryption, VolumeKind, WatchDiskUsageAlertsRequest,
+};
+use serde::Deserialize;
+use std::io::Read;
+
+#[derive(Args, Debug)]
+pub struct StorageArgs {
+    #[arg(
+        short,
+        long,
+        global = true,
+        env = "FEOS_ADDRESS",
+        help = "FeOS API address, overriding the address of the selected context"
+    )]
+    pub address: Option<String>,
+
+    #[command(subcommand)]
+    command: StorageCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum StorageCommand {
+    /// Manage storage pools
+    #[command(subcommand)]
+    Pool(PoolCommand),
+    /// Manage volumes provisioned from storage pools
+    #[command(subcommand)]
+    Volume(VolumeCommand),
+    /// Show disk usage of the host filesystems holding FeOS data
+    Usage {
+        #[arg(
+            long,
+            help = "Keep running and print an alert whenever the usage level of a filesystem changes"
+        )]
+        watch: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PoolCommand {
+    /// Create a pool backed by an LVM thin pool or a directory
+    Create {
+        #[arg(required = true, help = "Pool name")]
+        name: String,
+
+        #[arg(
+            long,
+            requires = "thin_pool",
+            conflicts_with = "directory",
+            help = "LVM volume group holding the thin pool"
+        )]
+        volume_group: Option<String>,
+
+        #[arg(long, help = "Name of the LVM thin pool, created if it does not exist")]
+        thin_pool: Option<String>,
+
+        #[arg(
+            long,
+            required_unless_present = "volume_group",
+            help = "Absolute path of the directory backing the pool"
+        )]
+        directory: Option<String>,
+
+        #[arg(
+            long,
+            default_value = "0",
+            value_parser = parse_size,
+            help = "Pool size (e.g., 100G). Size of a new thin pool, or the allocation limit of a directory pool"
+        )]
+        size: u64,
+    },
+    /// List all pools with their capacity and usage
+    List,
+    /// Delete an empty pool
+    Delete {
+        #[arg(required = true, help = "Pool identifier")]
+        id: String,
+    },
+    /// Grow a pool
+    Resize {
+        #[arg(required = true, help = "Pool identifier")]
+        id: String,
+
+        #[arg(required = true, value_parser = parse_size, help = "New pool size (e.g., 200G)")]
+        size: u64,
+    },
+}
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum VolumeKindArg {
+    VmDisk,
+    ContainerVolume,
+}
+
+impl From<VolumeKindArg> for VolumeKind {
+    fn from(kind: VolumeKindArg) -> Self {
+        match kind {
+            VolumeKindArg::VmDisk => VolumeKind::VmDisk,
+            VolumeKindArg::ContainerVolume => VolumeKind::ContainerVolume,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum VolumeCommand {
+    /// Provision a new volume in a pool
+    Create {
+        #[arg(
+            required = true,
+            help = "Volume name, unique within the pool and namespace"
+        )]
+        name: String,
+
+        #[arg(long, required = true, help = "Pool identifier")]
+        pool: String,
+
+        #[arg(
+            long,
+            env = "FEOS_NAMESPACE",
+            help = "Namespace to create the volume in [default: default]"
+        )]
+        namespace: Option<String>,
+
+        #[arg(long, required = true, value_parser = parse_size, help = "Volume size (e.g., 20G)")]
+        size: u64,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value = "vm-disk",
+            help = "What the volume is used for"
+        )]
+        kind: VolumeKindArg,
+
+        #[arg(
+            long,
+            conflicts_with = "key_file",
+            help = "Encrypt the volume with LUKS2 using a random key sealed to the host TPM"
+        )]
+        encrypt_tpm: bool,
+
+        #[arg(
+            long,
+            help = "Encrypt the volume with LUKS2 using the key in this file ('-' for stdin)"
+        )]
+        key_file: Option<String>,
+    },
+    /// Unlock an encrypted volume and print the path of its decrypted device
+    Open {
+        #[arg(required = true, help = "Volume identifier")]
+        id: String,
+
+        #[arg(
+            long,
+            help = "File holding the key ('-' for stdin). Not needed for TPM-sealed keys"
+        )]
+        key_file: Option<String>,
+    },
+    /// Remove the decrypted device of an encrypted volume
+    Close {
+        #[arg(required = true, help = "Volume identifier")]
+        id: String,
+    },
+    /// List volumes, optionally only those of one pool
+    List {
+        #[arg(long, help = "Only list volumes of this pool")]
+        pool: Option<String>,
+        #[arg(
+            long,
+            env = "FEOS_NAMESPACE",
+            help = "Only list volumes of this namespace"
+        )]
+        namespace: Option<String>,
+    },
+    /// Delete a volume and its data
+    Delete {
+        #[arg(required = true, help = "Volume identifier")]
+        id: String,
+    },
+    /// Grow a volume
+    Resize {
+        #[arg(required = true, help = "Volume identifier")]
+        id: String,
+
+        #[arg(required = true, value_parser = parse_size, help = "New volume size (e.g., 40G)")]
+        size: u64,
+    },
+}
+
+/// Parses a size in bytes with an optional binary suffix (K, M, G or T).
+pub(crate) fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (number, shift) = match s.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&s[..i], 10),
+        Some((i, 'M' | 'm')) => (&s[..i], 20),
+        Some((i, 'G' | 'g')) => (&s[..i], 30),
+        Some((i, 'T' | 't')) => (&s[..i], 40),
+        _ => (s, 0),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .ok_or_else(|| format!("invalid size: {s}"))
+}
+
+fn read_key(path: &str) -> Result<Vec<u8>> {
+    let mut key = Vec::new();
+    if path == "-" {
+        std::io::stdin()
+            .read_to_end(&mut key)
+            .context("Failed to read key from stdin")?;
+    } else {
+        key = std::fs::read(path).with_context(|| format!("Failed to read key file {path}"))?;
+    }
+    Ok(key)
+}
+
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+pub async fn handle_storage_command(args: StorageArgs, context: Option<&str>) -> Result<()> {
+    let channel = config::connect(args.address.as_deref(), context)
+        .await
+        .context("Failed to connect to storage service")?;
+    let mut client = StorageServiceClient::new(channel);
+
+    match args.command {
+        StorageCommand::Pool(command) => match command {
+            PoolCommand::Create {
+                name,
+                volume_group,
+                thin_pool,
+                directory,
+                size,
+            } => {
+                let backend = match (volume_group, thin_pool, directory) {
+                    (Some(volume_group), Some(thin_pool), _) => {
+                        pool_config::Backend::LvmThin(LvmThinPool {
+                            volume_group,
+                            thin_pool,
+                        })
+                    }
+                    (_, _, Some(path)) => pool_config::Backend::Directory(DirectoryPool { path }),
+                    _ => unreachable!("clap requires either an LVM thin pool or a directory"),
+                };
+                let config = PoolConfig {
+                    name,
+                    backend: Some(backend),
+                    size_bytes: size,
+                };
+                create_pool(&mut client, config).await?
+            }
+            PoolCommand::List => list_pools(&mut client).await?,
+            PoolCommand::Delete { id } => delete_pool(&mut client, id).await?,
+            PoolCommand::Resize { id, size } => resize_pool(&mut client, id, size).await?,
+        },
+        StorageCommand::Volume(command) => match command {
+            VolumeCommand::Create {
+                name,
+                pool,
+                namespace,
+                size,
+                kind,
+                encrypt_tpm,
+                key_file,
+            } => {
+                let key_source = match key_file {
+                    Some(path) => Some(KeySource::Key(read_key(&path)?)),
+                    None if encrypt_tpm => Some(KeySource::Tpm(TpmKeySource {})),
+                    None => None,
+                };
+                let encryption = key_source.map(|key_source| EncryptionConfig {
+                    key_source: Some(key_source),
+                });
+                let request = CreateVolumeRequest {
+                    pool_id: pool,
+                    name,
+                    kind: VolumeKind::from(kind) as i32,
+                    size_bytes: size,
+                    encryption,
+                    namespace: namespace.unwrap_or_default(),
+                };
+                create_volume(&mut client, request).await?
+            }
+            VolumeCommand::Open { id, key_file } => {
+                let key = match key_file {
+                    Some(path) => read_key(&path)?,
+                    None => Vec::new(),
+                };
+                open_volume(&mut client, id, key).await?
+            }
+            VolumeCommand::Close { id } => close_volume(&mut client, id).await?,
+            VolumeCommand::List { pool, namespace } => {
+                list_volumes(&mut client, pool, namespace).await?
+            }
+            VolumeCommand::Delete { id } => delete_volume(&mut client, id).await?,
+            VolumeCommand::Resize { id, size } => resize_volume(&mut client, id, size).await?,
+        },
+        StorageCommand::Usage { watch } => {
+            if watch {
+                watch_disk_usage_alerts(&mut client).await?
+            } else {
+                get_disk_usage(&mut client).await?
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn create_pool(client: &mut StorageServiceClient<Channel>, config: PoolConfig) -> Result<()> {
+    let request = CreatePoolRequest {
+        config: Some(config),
+    };
+    let response = client.create_pool(request).await?.into_inner();
+    println!("{}", response.pool_id);
+    Ok(())
+}
+
+async fn list_pools(client: &mut StorageServiceClient<Channel>) -> Result<()> {
+    let response = client.list_pools(ListPoolsRequest {}).await?.into_inner();
+    if response.pools.is_empty() {
+        println!("No storage pools found.");
+        return Ok(());
+    }
+
+    println!(
+        "{:<38} {:<16} {:<9} {:>11} {:>11} {:>11} VOLUMES",
+        "POOL_ID", "NAME", "BACKEND", "CAPACITY", "USED", "ALLOCATED"
+    );
+    println!(
+        "{:-<38} {:-<16} {:-<9} {:->11} {:->11} {:->11} {:-<7}",
+        "", "", "", "", "", "", ""
+    );
+    for pool in response.pools {
+        let config = pool.config.unwrap_or_default();
+        let backend = match config.backend {
+            Some(pool_config::Backend::LvmThin(_)) => "lvm-thin",
+            Some(pool_config::Backend::Directory(_)) => "directory",
+            None => "unknown",
+        };
+        println!(
+            "{:<38} {:<16} {:<9} {:>11} {:>11} {:>11} {}",
+            pool.pool_id,
+            config.name,
+            backend,
+            format_bytes(pool.capacity_bytes),
+            format_bytes(pool.used_bytes),
+            format_bytes(pool.allocated_bytes),
+            pool.volume_count
+        );
+    }
+    Ok(())
+}
+
+fn category_name(category: UsageCategory) -> &'static str {
+    match category {
+        UsageCategory::ImageCache => "image cache",
+        UsageCategory::VmDisks => "VM disks",
+        UsageCategory::ContainerLayers => "container layers",
+        UsageCategory::ContainerVolumes => "container volumes",
+        UsageCategory::Databases => "databases",
+        UsageCategory::Unspecified => "other",
+    }
+}
+
+fn level_name(level: UsageLevel) -> &'static str {
+    match level {
+        UsageLevel::Ok => "ok",
+        UsageLevel::Warning => "warning",
+        UsageLevel::Critical => "critical",
+    }
+}
+
+fn print_filesystem_usage(filesystem: &FilesystemUsage) {
+    let total = filesystem.used_bytes + filesystem.available_bytes;
+    let percent = if total > 0 {
+        filesystem.used_bytes * 100 / total
+    } else {
+        0
+    };
+    println!(
+        "{:<24} {:<8} {:>11} {:>11} {:>11} {:>3}%",
+        filesystem.mount_point,
+        level_name(filesystem.level()),
+        format_bytes(filesystem.capacity_bytes),
+        format_bytes(filesystem.used_bytes),
+        format_bytes(filesystem.available_bytes),
+        percent
+    );
+    for usage in &filesystem.categories {
+        println!(
+            "  {:<31} {:>11}",
+            category_name(usage.category()),
+            format_bytes(usage.used_bytes)
+        );
+    }
+}
+
+async fn get_disk_usage(client: &mut StorageServiceClient<Channel>) -> Result<()> {
+    let response = client
+        .get_disk_usage(GetDiskUsageRequest {})
+        .await?
+        .into_inner();
+    if response.filesystems.is_empty() {
+        println!("No FeOS data found on any filesystem.");
+        return Ok(());
+    }
+
+    println!(
+        "{:<24} {:<8} {:>11} {:>11} {:>11} USE%",
+        "MOUNT_POINT", "LEVEL", "CAPACITY", "USED", "AVAILABLE"
+    );
+    println!(
+        "{:-<24} {:-<8} {:->11} {:->11} {:->11} {:-<4}",
+        "", "", "", "", "", ""
+    );
+    for filesystem in &response.filesystems {
+        print_filesystem_usage(filesystem);
+    }
+    println!(
+        "\nThresholds: warning at {}%, critical at {}%",
+        response.warning_percent, response.critical_percent
+    );
+    Ok(())
+}
+
+async fn watch_disk_usage_alerts(client: &mut StorageServiceClient<Channel>) -> Result<()> {
+    let mut alerts = client
+        .watch_disk_usage_alerts(WatchDiskUsageAlertsRequest {})
+        .await?
+        .into_inner();
+    println!("Watching disk usage alerts. Press Ctrl+C to stop.");
+    while let Some(alert) = alerts.message().await? {
+        let Some(filesystem) = alert.filesystem.as_ref() else {
+            continue;
+        };
+        println!(
+            "{} changed from {} to {}{}",
+            filesystem.mount_point,
+            level_name(alert.previous_level()),
+            level_name(filesystem.level()),
+            if alert.images_collected {
+                " after collecting unused image data"
+            } else {
+                ""
+            }
+        );
+        print_filesystem_usage(filesystem);
+    }
+    Ok(())
+}
+
+async fn delete_pool(client: &mut StorageServiceClient<Channel>, pool_id: String) -> Result<()> {
+    let request = DeletePoolRequest {
+        pool_id: pool_id.clone(),
+    };
+    client.delete_pool(request).await?;
+    println!("Deleted pool {pool_id}");
+    Ok(())
+}
+
+async fn resize_pool(
+    client: &mut StorageServiceClient<Channel>,
+    pool_id: String,
+    size_bytes: u64,
+) -> Result<()> {
+    let request = ResizePoolRequest {
+        pool_id: pool_id.clone(),
+        size_bytes,
+    };
+    client.resize_pool(request).await?;
+    println!("Resized pool {pool_id} to {}", format_bytes(size_bytes));
+    Ok(())
+}
+
+async fn create_volume(
+    client: &mut StorageServiceClient<Channel>,
+    request: CreateVolumeRequest,
+) -> Result<()> {
+    let response = client.create_volume(request).await?.into_inner();
+    println!("{}", response.volume_id);
+    eprintln!("Volume is available at {}", response.path);
+    Ok(())
+}
+
+async fn list_volumes(
+    client: &mut StorageServiceClient<Channel>,
+    pool_id: Option<String>,
+    namespace: Option<String>,
+) -> Result<()> {
+    let request = ListVolumesRequest { pool_id, namespace };
+    let response = client.list_volumes(request).await?.into_inner();
+    if response.volumes.is_empty() {
+        println!("No volumes found.");
+        return Ok(());
+    }
+
+    println!(
+        "{:<38} {:<16} {:<20} {:<17} {:>11} {:>11} {:<10} PATH",
+        "VOLUME_ID", "NAMESPACE", "NAME", "KIND", "SIZE", "USED", "ENCRYPTION"
+    );
+    println!(
+        "{:-<38} {:-<16} {:-<20} {:-<17} {:->11} {:->11} {:-<10} {:-<40}",
+        "", "", "", "", "", "", "", ""
+    );
+    for volume in response.volumes {
+        let kind = match VolumeKind::try_from(volume.kind).unwrap_or(VolumeKind::Unspecified) {
+            VolumeKind::VmDisk => "vm-disk",
+            VolumeKind::ContainerVolume => "container-volume",
+            VolumeKind::Unspecified => "unknown",
+        };
+        let encryption =
+            match VolumeEncryption::try_from(volume.encryption).unwrap_or(VolumeEncryption::None) {
+                VolumeEncryption::None => "none",
+                VolumeEncryption::Tpm => "tpm",
+                VolumeEncryption::Key => "key",
+            };
+        // Open encrypted volumes are attached through their decrypted device.
+        let path = if volume.mapped_path.is_empty() {
+            volume.path
+        } else {
+            volume.mapped_path
+        };
+        println!(
+            "{:<38} {:<16} {:<20} {:<17} {:>11} {:>11} {:<10} {}",
+            volume.volume_id,
+            volume.namespace,
+            volume.name,
+            kind,
+            format_bytes(volume.size_bytes),
+            format_bytes(volume.used_bytes),
+            encryption,
+            path
+        );
+    }
+    Ok(())
+}
+
+async fn delete_volume(
+    client: &mut StorageServiceClient<Channel>,
+    volume_id: String,
+) -> Result<()> {
+    let request = DeleteVolumeRequest {
+        volume_id: volume_id.clone(),
+    };
+    client.delete_volume(request).await?;
+    println!("Deleted volume {volume_id}");
+    Ok(())
+}
+
+async fn resize_volume(
+    client: &mut StorageServiceClient<Channel>,
+    volume_id: String,
+    size_bytes: u64,
+) -> Result<()> {
+    let request = ResizeVolumeRequest {
+        volume_id: volume_id.clone(),
+        size_bytes,
+    };
+    client.resize_volume(request).await?;
+    println!("Resized volume {volume_id} to {}", format_bytes(size_bytes));
+    Ok(())
+}
+
+async fn open_volume(
+    client: &mut StorageServiceClient<Channel>,
+    volume_id: String,
+    key: Vec<u8>,
+) -> Result<()> {
+    let request = OpenVolumeRequest { volume_id, key };
+    let response = client.open_volume(request).await?.into_inner();
+    println!("{}", response.mapped_path);
+    Ok(())
+}
+
+async fn close_volume(client: &mut StorageServiceClient<Channel>, volume_id: String) -> Result<()> {
+    let request = CloseVolumeRequest {
+        volume_id: volume_id.clone(),
+    };
+    client.close_volume(request).await?;
+    println!("Closed volume {volume_id}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_accept_binary_suffixes() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("512M"), Ok(512 << 20));
+        assert_eq!(parse_size("20g"), Ok(20 << 30));
+        assert!(parse_size("G").is_err());
+        assert!(parse_size("1.5G").is_err());
+        assert!(parse_size("99999999T").is_err());
+    }
+}
@@ -1,23 +1,45 @@
 // SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
 // SPDX-License-Identifier: Apache-2.0
+mod create;
+mod replica;
+mod snapshot;
 
+use crate::config::{self, Channel};
+use crate::operation_commands::{print_async, wait_for_operation, Operation, OperationKind};
+use crate::storage_commands::{format_bytes, parse_size};
 use anyhow::{Context, Result};
-use clap::{Args, Subcommand};
-use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+use clap::{Args, Subcommand, ValueEnum};
+use create::CreateVmFlags;
+use crossterm::cursor::MoveTo;
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, Clear, ClearType};
 use crossterm::tty::IsTty;
 use feos_proto::vm_service::{
-    net_config, stream_vm_console_request as console_input, vm_service_client::VmServiceClient,
-    AttachConsoleMessage, AttachDiskRequest, AttachNicRequest, ConsoleData, CpuConfig,
-    CreateVmRequest, DeleteVmRequest, DetachDiskRequest, DetachNicRequest, DiskConfig,
-    GetVmRequest, ListVmsRequest, MemoryConfig, NetConfig, PauseVmRequest, PingVmRequest,
-    ResumeVmRequest, ShutdownVmRequest, StartVmRequest, StreamVmConsoleRequest,
-    StreamVmEventsRequest, TapConfig, VfioPciConfig, VmConfig, VmState, VmStateChangedEvent,
+    disk_config, net_config, startup_dependency, stream_vm_console_request as console_input,
+    vm_service_client::VmServiceClient, AttachConsoleMessage, AttachDiskRequest, AttachNicRequest,
+    AttachPciDeviceRequest, BootDurationHistogram, ConsoleData, CreateVmRequest, DeleteVmRequest,
+    DetachDiskRequest, DetachNicRequest, DetachPciDeviceRequest, DiskBus, DiskConfig, DiskFormat,
+    DrainPolicy, EphemeralDiskConfig, EvacuationAction, EvacuationTarget, ExecInGuestRequest,
+    GetGuestInfoRequest, GetVmBootMetricsRequest, GetVmRequest, GetVmStatsRequest,
+    IscsiChapCredentials, IscsiConfig, ListHostPciDevicesRequest, ListVmsRequest, MacvtapConfig,
+    MacvtapMode, MigrationBlockerKind, NetConfig, NvmeofConfig, PauseVmRequest, PciDeviceConfig,
+    PingVmRequest, PlacementPolicy, PlanEvacuationRequest, RbdConfig, ReplayVmStateJournalRequest,
+    ResizeVmRequest, ResumeVmRequest, ShutdownVmRequest, StartVmRequest, StartupDependency,
+    StreamVmConsoleRequest, StreamVmEventsRequest, StreamVmFlowsRequest, StreamVmMetricsRequest,
+    TapConfig, VfioPciConfig, VhostUserNetConfig, VmBootTimings, VmInfo, VmMetrics, VmState,
+    VmStateChangedEvent,
 };
 use prost::Message;
+use prost_types::Timestamp;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::sync::mpsc;
 use tokio_stream::StreamExt;
-use tonic::transport::Channel;
+
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(100);
+const WATCH_REFRESH_INTERVAL: Duration = Duration::from_secs(2);
 
 #[derive(Args, Debug)]
 pub struct VmArgs {
@@ -26,50 +48,171 @@ pub struct VmArgs {
         long,
         global = true,
         env = "FEOS_ADDRESS",
-        default_value = "http://[::1]:1337"
+        help = "FeOS API address, overriding the address of the selected context"
     )]
-    pub address: String,
+    pub address: Option<String>,
 
     #[command(subcommand)]
     command: VmCommand,
 }
 
-#[derive(Subcommand, Debug)]
-pub enum VmCommand {
-    /// Create a new virtual machine with specified configuration
-    Create {
-        #[arg(
-            long,
-            required = true,
-            help = "Container image reference to use for the VM"
-        )]
-        image_ref: String,
+#[derive(ValueEnum, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiskBusArg {
+    VirtioBlk,
+    VirtioScsi,
+}
 
-        #[arg(long, default_value_t = 1, help = "Number of virtual CPUs to allocate")]
-        vcpus: u32,
+impl From<DiskBusArg> for DiskBus {
+    fn from(bus: DiskBusArg) -> Self {
+        match bus {
+            DiskBusArg::VirtioBlk => DiskBus::VirtioBlk,
+            DiskBusArg::VirtioScsi => DiskBus::VirtioScsi,
+        }
+    }
+}
 
-        #[arg(long, default_value_t = 1024, help = "Memory size in MiB")]
-        memory: u64,
+#[derive(ValueEnum, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiskFormatArg {
+    Raw,
+    Qcow2,
+}
 
-        #[arg(long, help = "Optional custom VM identifier")]
-        vm_id: Option<String>,
+impl From<DiskFormatArg> for DiskFormat {
+    fn from(format: DiskFormatArg) -> Self {
+        match format {
+            DiskFormatArg::Raw => DiskFormat::Raw,
+            DiskFormatArg::Qcow2 => DiskFormat::Qcow2,
+        }
+    }
+}
 
-        #[arg(
-            long,
-            help = "PCI device BDF to passthrough for networking (e.g., 0000:03:00.0)"
-        )]
-        pci_device: Vec<String>,
+#[derive(ValueEnum, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum MacvtapModeArg {
+    Bridge,
+    Vepa,
+    Private,
+    Passthru,
+}
+
+impl From<MacvtapModeArg> for MacvtapMode {
+    fn from(mode: MacvtapModeArg) -> Self {
+        match mode {
+            MacvtapModeArg::Bridge => MacvtapMode::Bridge,
+            MacvtapModeArg::Vepa => MacvtapMode::Vepa,
+            MacvtapModeArg::Private => MacvtapMode::Private,
+            MacvtapModeArg::Passthru => MacvtapMode::Passthru,
+        }
+    }
+}
+
+#[derive(ValueEnum, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DrainPolicyArg {
+    Shutdown,
+    Stop,
+    Keep,
+}
+
+impl From<DrainPolicyArg> for DrainPolicy {
+    fn from(policy: DrainPolicyArg) -> Self {
+        match policy {
+            DrainPolicyArg::Shutdown => DrainPolicy::Shutdown,
+            DrainPolicyArg::Stop => DrainPolicy::Stop,
+            DrainPolicyArg::Keep => DrainPolicy::Keep,
+        }
+    }
+}
+
+#[derive(ValueEnum, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PlacementPolicyArg {
+    Preferred,
+    Required,
+    None,
+}
+
+impl From<PlacementPolicyArg> for PlacementPolicy {
+    fn from(policy: PlacementPolicyArg) -> Self {
+        match policy {
+            PlacementPolicyArg::Preferred => PlacementPolicy::Preferred,
+            PlacementPolicyArg::Required => PlacementPolicy::Required,
+            PlacementPolicyArg::None => PlacementPolicy::None,
+        }
+    }
+}
+
+/// A workload to start after, given as `vm:<id>` or `container:<id>`.
+#[derive(serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub enum StartupAfterArg {
+    Vm(String),
+    Container(String),
+}
+
+impl FromStr for StartupAfterArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, id) = s.split_once(':').ok_or_else(|| {
+            format!("Invalid dependency '{s}', expected vm:<id> or container:<id>")
+        })?;
+        uuid::Uuid::parse_str(id).map_err(|_| format!("Dependency ID '{id}' is not a UUID"))?;
+        match kind {
+            "vm" => Ok(StartupAfterArg::Vm(id.to_string())),
+            "container" => Ok(StartupAfterArg::Container(id.to_string())),
+            _ => Err(format!(
+                "Invalid dependency kind '{kind}', expected vm or container"
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for StartupAfterArg {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
 
-        #[arg(long, help = "Enable hugepages for memory allocation")]
-        hugepages: bool,
+impl From<StartupAfterArg> for StartupDependency {
+    fn from(after: StartupAfterArg) -> Self {
+        let workload = match after {
+            StartupAfterArg::Vm(id) => startup_dependency::Workload::VmId(id),
+            StartupAfterArg::Container(id) => startup_dependency::Workload::ContainerId(id),
+        };
+        StartupDependency {
+            workload: Some(workload),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum VmCommand {
+    /// Create a new virtual machine from flags and/or a template
+    Create {
+        #[command(flatten)]
+        flags: CreateVmFlags,
 
-        #[arg(long, help = "Path to ignition file or the content itself")]
-        ignition: Option<String>,
+        #[arg(
+            long = "async",
+            help = "Print the operation ID and return instead of waiting for completion"
+        )]
+        run_async: bool,
     },
     /// Start an existing virtual machine
     Start {
         #[arg(required = true, help = "VM identifier")]
         vm_id: String,
+
+        #[arg(
+            long = "async",
+            help = "Print the operation ID and return instead of waiting for completion"
+        )]
+        run_async: bool,
     },
     /// Get detailed information about a virtual machine
     Info {
@@ -77,16 +220,66 @@ pub enum VmCommand {
         vm_id: String,
     },
     /// List all virtual machines
-    List,
+    List {
+        #[arg(
+            long,
+            help = "Keep watching and update the table on every VM event and every 2s"
+        )]
+        watch: bool,
+        #[arg(
+            long,
+            env = "FEOS_NAMESPACE",
+            help = "Only list VMs in this namespace (lists all namespaces if not provided)"
+        )]
+        namespace: Option<String>,
+    },
+    /// Show how long creating and booting VMs took since the VM service started
+    BootMetrics,
+    /// Show the usage of the GPUs assigned to virtual machines
+    Stats {
+        #[arg(help = "Only show these VMs (optional, if not provided shows all VMs)")]
+        vm_ids: Vec<String>,
+    },
+    /// Watch the connections of virtual machines, by the traffic they carry
+    Flows {
+        #[arg(help = "Only show these VMs (optional, if not provided shows all VMs)")]
+        vm_ids: Vec<String>,
+        #[arg(long, default_value_t = 5, help = "Seconds between updates")]
+        interval: u32,
+        #[arg(
+            long,
+            default_value_t = 20,
+            help = "Show at most this many connections"
+        )]
+        top: usize,
+    },
+    /// Watch the CPU, memory, disk and network usage of running virtual machines
+    Metrics {
+        #[arg(help = "Only show these VMs (optional, if not provided shows all VMs)")]
+        vm_ids: Vec<String>,
+        #[arg(long, default_value_t = 5, help = "Seconds between updates")]
+        interval: u32,
+    },
     /// Ping a virtual machine's VMM to check status
     Ping {
         #[arg(required = true, help = "VM identifier")]
         vm_id: String,
     },
-    /// Gracefully shutdown a virtual machine
+    /// Shut down a virtual machine
     Shutdown {
         #[arg(required = true, help = "VM identifier")]
         vm_id: String,
+        #[arg(
+            long,
+            help = "Ask the guest to power off, through its guest agent or the ACPI power button, before stopping it"
+        )]
+        graceful: bool,
+        #[arg(
+            long,
+            requires = "graceful",
+            help = "Seconds to wait for the guest to power off [default: 60]"
+        )]
+        grace_period: Option<u32>,
     },
     /// Pause a running virtual machine
     Pause {
@@ -98,6 +291,19 @@ pub enum VmCommand {
         #[arg(required = true, help = "VM identifier")]
         vm_id: String,
     },
+    /// Change the vCPUs or memory of a running virtual machine
+    Resize {
+        #[arg(required = true, help = "VM identifier")]
+        vm_id: String,
+        #[arg(
+            long,
+            required_unless_present = "memory",
+            help = "Number of virtual CPUs, up to the VM's maximum"
+        )]
+        vcpus: Option<u32>,
+        #[arg(long, help = "Memory size in MiB, up to the VM's maximum")]
+        memory: Option<u64>,
+    },
     /// Delete a virtual machine
     Delete {
         #[arg(required = true, help = "VM identifier")]
@@ -105,53 +311,159 @@ pub enum VmCommand {
     },
     /// Create and start a virtual machine in one operation
     CreateAndStart {
+        #[command(flatten)]
+        flags: CreateVmFlags,
+    },
+    /// Watch virtual machine state change events
+    Events {
         #[arg(
             long,
-            required = true,
-            help = "Container image reference to use for the VM"
+            help = "VM identifier (optional, if not provided watches all VMs)"
         )]
-        image_ref: String,
-
-        #[arg(long, default_value_t = 1, help = "Number of virtual CPUs to allocate")]
-        vcpus: u32,
-
-        #[arg(long, default_value_t = 1024, help = "Memory size in MiB")]
-        memory: u64,
-
-        #[arg(long, help = "Optional custom VM identifier")]
         vm_id: Option<String>,
-
         #[arg(
             long,
-            help = "PCI device BDF to passthrough for networking (e.g., 0000:03:00.0)"
+            env = "FEOS_NAMESPACE",
+            help = "Only watch VMs in this namespace"
         )]
-        pci_device: Vec<String>,
-
-        #[arg(long, help = "Enable hugepages for memory allocation")]
-        hugepages: bool,
-
-        #[arg(long, help = "Path to ignition file or the content itself")]
-        ignition: Option<String>,
+        namespace: Option<String>,
     },
-    /// Watch virtual machine state change events
-    Events {
+    /// Replay the journal of VM state transitions, oldest first
+    Journal {
+        #[arg(long, help = "Only show the transitions of this VM")]
+        vm_id: Option<String>,
         #[arg(
             long,
-            help = "VM identifier (optional, if not provided watches all VMs)"
+            default_value_t = 0,
+            help = "Only show entries after this sequence number"
         )]
-        vm_id: Option<String>,
+        after: u64,
+        #[arg(long, help = "Maximum number of entries to show [default: 1000]")]
+        limit: Option<u32>,
+    },
+    /// Show which VMs would be live-migrated or stopped to drain the host
+    PlanEvacuation {
+        #[arg(help = "Only plan for these VMs (optional, if not provided plans for all VMs)")]
+        vm_ids: Vec<String>,
+        #[arg(
+            long,
+            help = "Plan for emptying the host, including stopped VMs and those kept by their drain policy"
+        )]
+        all: bool,
     },
     /// Connect to a virtual machine's console
     Console {
         #[arg(required = true, help = "VM identifier")]
         vm_id: String,
+        #[arg(long, help = "Only watch the console, never type into it")]
+        read_only: bool,
+        #[arg(
+            long,
+            conflicts_with = "read_only",
+            help = "Take the write access away from the client holding it"
+        )]
+        takeover: bool,
     },
-    /// Attach a disk to a running virtual machine
+    /// Attach a disk image, a block device, an iSCSI LUN, an NVMe-oF namespace, a Ceph RBD image or a scratch disk to a running virtual machine
     AttachDisk {
         #[arg(long, required = true, help = "VM identifier")]
         vm_id: String,
-        #[arg(long, required = true, help = "Path to the disk image file")]
-        path: String,
+        #[arg(
+            long,
+            required_unless_present_any = ["iscsi_target", "nvmeof", "rbd_image", "ephemeral"],
+            conflicts_with_all = ["iscsi_target", "nvmeof", "rbd_image", "ephemeral"],
+            help = "Path to a raw or qcow2 disk image file or a host block device"
+        )]
+        path: Option<String>,
+        #[arg(
+            long,
+            value_enum,
+            requires = "path",
+            help = "Format of the disk image [default: detected from its header]"
+        )]
+        format: Option<DiskFormatArg>,
+        #[arg(
+            long,
+            requires = "iscsi_portals",
+            conflicts_with = "rbd_image",
+            help = "IQN of the iSCSI target"
+        )]
+        iscsi_target: Option<String>,
+        #[arg(
+            long = "iscsi-portal",
+            help = "Portal of the iSCSI target as host[:port]. Repeat to use the LUN over multiple paths"
+        )]
+        iscsi_portals: Vec<String>,
+        #[arg(long, default_value_t = 0, help = "LUN of the iSCSI target")]
+        iscsi_lun: u32,
+        #[arg(
+            long,
+            value_name = "[TRANSPORT://]ADDRESS[:PORT]/NQN[/NSID]",
+            value_parser = create::parse_nvmeof,
+            conflicts_with_all = ["iscsi_target", "rbd_image", "ephemeral"],
+            help = "Namespace of an NVMe-oF subsystem, e.g. tcp://10.0.0.1/nqn.2014-08.org.example:vol1/1"
+        )]
+        nvmeof: Option<NvmeofConfig>,
+        #[arg(
+            long,
+            requires = "nvmeof",
+            help = "NQN to connect to the NVMe-oF subsystem as [default: the NQN of the host]"
+        )]
+        host_nqn: Option<String>,
+        #[arg(
+            long,
+            requires_all = ["iscsi_target", "chap_password"],
+            help = "CHAP username for the iSCSI target"
+        )]
+        chap_username: Option<String>,
+        #[arg(
+            long,
+            env = "FEOS_ISCSI_CHAP_PASSWORD",
+            hide_env_values = true,
+            help = "CHAP password. Prefer the environment variable over the flag"
+        )]
+        chap_password: Option<String>,
+        #[arg(
+            long,
+            requires_all = ["ceph_mons", "keyring_file"],
+            help = "Ceph RBD image as pool/image or pool/namespace/image"
+        )]
+        rbd_image: Option<String>,
+        #[arg(
+            long = "ceph-mon",
+            help = "Ceph monitor as host[:port]. Can be repeated"
+        )]
+        ceph_mons: Vec<String>,
+        #[arg(
+            long,
+            requires = "rbd_image",
+            help = "Ceph user without the 'client.' prefix [default: admin]"
+        )]
+        ceph_user: Option<String>,
+        #[arg(long, help = "Path to the Ceph keyring of the user")]
+        keyring_file: Option<String>,
+        #[arg(
+            long,
+            value_parser = parse_size,
+            conflicts_with_all = ["iscsi_target", "rbd_image"],
+            help = "Size of a scratch disk in host RAM that is wiped at shutdown (e.g., 4G)"
+        )]
+        ephemeral: Option<u64>,
+        #[arg(long, help = "Attach the disk read-only")]
+        readonly: bool,
+        #[arg(
+            long,
+            value_enum,
+            help = "Device model the guest sees [default: virtio-blk]"
+        )]
+        bus: Option<DiskBusArg>,
+        #[arg(
+            long,
+            help = "Pass guest discards (TRIM) through, so thin and sparse images release space"
+        )]
+        discard: bool,
+        #[arg(long, help = "Device identifier for the disk")]
+        device_id: Option<String>,
     },
     /// Detach a disk from a virtual machine
     DetachDisk {
@@ -171,15 +483,57 @@ pub enum VmCommand {
         #[arg(
             long,
             help = "Name of the TAP device to attach",
-            conflicts_with = "pci_device"
+            conflicts_with_all = ["pci_device", "vhost_user_socket", "macvtap"]
         )]
         tap_name: Option<String>,
+        #[arg(
+            long,
+            help = "Address the guest by SLAAC in the prefix delegated to the host",
+            requires = "tap_name"
+        )]
+        slaac: bool,
         #[arg(
             long,
             help = "PCI device BDF to passthrough for networking (e.g., 0000:03:00.0)",
-            conflicts_with = "tap_name"
+            conflicts_with_all = ["tap_name", "vhost_user_socket", "macvtap"]
         )]
         pci_device: Option<String>,
+        #[arg(
+            long,
+            help = "vhost-user socket of a userspace dataplane (OVS-DPDK, VPP, dpservice)",
+            conflicts_with_all = ["tap_name", "pci_device", "macvtap"]
+        )]
+        vhost_user_socket: Option<String>,
+        #[arg(
+            long,
+            help = "Let the VM create the vhost-user socket for the dataplane to connect to",
+            requires = "vhost_user_socket"
+        )]
+        vhost_user_server: bool,
+        #[arg(
+            long,
+            value_name = "PARENT",
+            help = "Host interface to put the NIC on with a macvtap device (e.g., eth0)",
+            conflicts_with_all = ["tap_name", "pci_device", "vhost_user_socket"]
+        )]
+        macvtap: Option<String>,
+        #[arg(
+            long,
+            help = "Name of the macvtap device [default: generated]",
+            requires = "macvtap"
+        )]
+        macvtap_name: Option<String>,
+        #[arg(
+            long,
+            value_enum,
+            help = "Mode of the macvtap device [default: bridge]",
+            requires = "macvtap"
+        )]
+        macvtap_mode: Option<MacvtapModeArg>,
+        #[arg(long, value_parser = parse_size, help = "Bandwidth limit in bytes per second (e.g., 125M)")]
+        bandwidth: Option<u64>,
+        #[arg(long, help = "Packet rate limit in packets per second")]
+        packets_per_second: Option<u64>,
         #[arg(long, help = "MAC address for the new interface")]
         mac_address: Option<String>,
         #[arg(long, help = "Custom device identifier for the new interface")]
@@ -192,99 +546,312 @@ pub enum VmCommand {
         #[arg(long, required = true, help = "Device identifier of the NIC to detach")]
         device_id: String,
     },
+    /// List the PCI devices of the host and the VMs they are passed through to
+    ListPciDevices {
+        #[arg(long, help = "Only list devices that can be passed through")]
+        vfio_capable_only: bool,
+    },
+    /// Pass a PCI device of the host through to a VM
+    AttachPciDevice {
+        #[arg(long, required = true, help = "VM identifier")]
+        vm_id: String,
+        #[arg(
+            long,
+            required = true,
+            help = "PCI address of the device, e.g. 0000:81:00.0"
+        )]
+        pci_address: String,
+        #[arg(long, help = "Custom device identifier for the device")]
+        device_id: Option<String>,
+    },
+    /// Detach a PCI device from a VM and give it back to the host
+    DetachPciDevice {
+        #[arg(long, required = true, help = "VM identifier")]
+        vm_id: String,
+        #[arg(
+            long,
+            required = true,
+            help = "Device identifier of the PCI device to detach"
+        )]
+        device_id: String,
+    },
+    /// Run a command in a running virtual machine through its guest agent
+    Exec {
+        #[arg(short, long, help = "Read stdin to the end and pass it to the command")]
+        interactive: bool,
+        #[arg(
+            short,
+            long = "env",
+            value_name = "KEY=VALUE",
+            help = "Set an environment variable for the command (can be repeated)"
+        )]
+        env: Vec<String>,
+        #[arg(long, help = "Working directory of the command in the guest")]
+        workdir: Option<String>,
+        #[arg(long, help = "Kill the command after this many seconds")]
+        timeout: Option<u32>,
+        #[arg(required = true, help = "VM identifier")]
+        vm_id: String,
+        #[arg(
+            required = true,
+            trailing_var_arg = true,
+            allow_hyphen_values = true,
+            help = "Command and arguments to execute"
+        )]
+        command: Vec<String>,
+    },
+    /// Show the hostname and network interfaces reported by the guest agent of a VM
+    GuestInfo {
+        #[arg(required = true, help = "VM identifier")]
+        vm_id: String,
+    },
+    /// Manage disk and memory snapshots of a VM
+    #[command(subcommand)]
+    Snapshot(snapshot::SnapshotCommand),
+    /// Replicate the disks of a VM to a peer node and manage the replicas kept here
+    #[command(subcommand)]
+    Replica(replica::ReplicaCommand),
 }
 
-#[derive(Debug, Clone)]
-struct CreateVmOptions {
-    image_ref: String,
-    vcpus: u32,
-    memory: u64,
-    vm_id: Option<String>,
-    pci_devices: Vec<String>,
-    hugepages: bool,
-    ignition: Option<String>,
-}
+pub async fn handle_vm_command(args: VmArgs, context: Option<&str>) -> Result<()> {
+    if let VmCommand::Create { flags, .. } | VmCommand::CreateAndStart { flags } = &args.command {
+        if flags.dry_run {
+            let request = create::build_create_request(flags).await?;
+            return create::print_request(&request);
+        }
+    }
 
-pub async fn handle_vm_command(args: VmArgs) -> Result<()> {
-    let mut client = VmServiceClient::connect(args.address)
+    let channel = config::connect(args.address.as_deref(), context)
         .await
         .context("Failed to connect to VM service")?;
+    let mut client = VmServiceClient::new(channel.clone());
 
     match args.command {
-        VmCommand::Create {
-            image_ref,
-            vcpus,
-            memory,
-            vm_id,
-            pci_device,
-            hugepages,
-            ignition,
-        } => {
-            let opts = CreateVmOptions {
-                image_ref,
-                vcpus,
-                memory,
-                vm_id,
-                pci_devices: pci_device,
-                hugepages,
-                ignition,
-            };
-            create_vm(&mut client, opts).await?
+        VmCommand::Create { flags, run_async } => {
+            let request = create::build_create_request(&flags).await?;
+            create_vm(&mut client, &channel, request, run_async).await?
+        }
+        VmCommand::Start { vm_id, run_async } => {
+            start_vm(&mut client, &channel, vm_id, run_async).await?
         }
-        VmCommand::Start { vm_id } => start_vm(&mut client, vm_id).await?,
         VmCommand::Info { vm_id } => get_vm_info(&mut client, vm_id).await?,
-        VmCommand::List => list_vms(&mut client).await?,
+        VmCommand::List { watch, namespace } => {
+            if watch {
+                watch_vms(&mut client, namespace).await?
+            } else {
+                list_vms(&mut client, namespace).await?
+            }
+        }
+        VmCommand::BootMetrics => get_boot_metrics(&mut client).await?,
+        VmCommand::Stats { vm_ids } => get_vm_stats(&mut client, vm_ids).await?,
+        VmCommand::Flows {
+            vm_ids,
+            interval,
+            top,
+        } => watch_vm_flows(&mut client, vm_ids, interval, top).await?,
+        VmCommand::Metrics { vm_ids, interval } => {
+            watch_vm_metrics(&mut client, vm_ids, interval).await?
+        }
         VmCommand::Ping { vm_id } => ping_vm(&mut client, vm_id).await?,
-        VmCommand::Shutdown { vm_id } => shutdown_vm(&mut client, vm_id).await?,
+        VmCommand::Shutdown {
+            vm_id,
+            graceful,
+            grace_period,
+        } => shutdown_vm(&mut client, vm_id, graceful, grace_period).await?,
         VmCommand::Pause { vm_id } => pause_vm(&mut client, vm_id).await?,
         VmCommand::Resume { vm_id } => resume_vm(&mut client, vm_id).await?,
-        VmCommand::Delete { vm_id } => delete_vm(&mut client, vm_id).await?,
-        VmCommand::CreateAndStart {
-            image_ref,
+        VmCommand::Resize {
+            vm_id,
             vcpus,
             memory,
+        } => resize_vm(&mut client, vm_id, vcpus, memory).await?,
+        VmCommand::Delete { vm_id } => delete_vm(&mut client, vm_id).await?,
+        VmCommand::Exec {
+            interactive,
+            env,
+            workdir,
+            timeout,
             vm_id,
-            pci_device,
-            hugepages,
-            ignition,
+            command,
+        } => {
+            let request = ExecInGuestRequest {
+                vm_id,
+                command,
+                stdin: Vec::new(),
+                env,
+                working_dir: workdir.unwrap_or_default(),
+                timeout_seconds: timeout.unwrap_or(0),
+            };
+            let exit_code = exec_in_guest(&mut client, request, interactive).await?;
+            std::process::exit(exit_code);
+        }
+        VmCommand::GuestInfo { vm_id } => get_guest_info(&mut client, vm_id).await?,
+        VmCommand::CreateAndStart { flags } => {
+            let request = create::build_create_request(&flags).await?;
+            create_and_start_vm(&mut client, &channel, request).await?
+        }
+        VmCommand::Events { vm_id, namespace } => {
+            watch_events(&mut client, vm_id, namespace).await?
+        }
+        VmCommand::Journal {
+            vm_id,
+            after,
+            limit,
+        } => replay_state_journal(&mut client, vm_id, after, limit).await?,
+        VmCommand::PlanEvacuation { vm_ids, all } => {
+            plan_evacuation(&mut client, vm_ids, all).await?
+        }
+        VmCommand::Console {
+            vm_id,
+            read_only,
+            takeover,
         } => {
-            let opts = CreateVmOptions {
-                image_ref,
-                vcpus,
-                memory,
+            let attach = AttachConsoleMessage {
                 vm_id,
-                pci_devices: pci_device,
-                hugepages,
-                ignition,
+                read_only,
+                takeover,
+            };
+            console_vm(&mut client, attach).await?
+        }
+        VmCommand::AttachDisk {
+            vm_id,
+            path,
+            format,
+            iscsi_target,
+            iscsi_portals,
+            iscsi_lun,
+            nvmeof,
+            host_nqn,
+            chap_username,
+            chap_password,
+            rbd_image,
+            ceph_mons,
+            ceph_user,
+            keyring_file,
+            ephemeral,
+            readonly,
+            bus,
+            discard,
+            device_id,
+        } => {
+            let backend = match (path, nvmeof, iscsi_target, rbd_image, ephemeral) {
+                (Some(path), ..) => disk_config::Backend::Path(path),
+                (None, Some(subsystem), ..) => disk_config::Backend::Nvmeof(NvmeofConfig {
+                    host_nqn: host_nqn.unwrap_or_default(),
+                    ..subsystem
+                }),
+                (None, None, None, None, Some(size_bytes)) => {
+                    disk_config::Backend::Ephemeral(EphemeralDiskConfig { size_bytes })
+                }
+                (None, None, None, Some(image_spec), _) => {
+                    let keyring_file = keyring_file.unwrap_or_default();
+                    let keyring = std::fs::read_to_string(&keyring_file)
+                        .with_context(|| format!("Failed to read keyring {keyring_file}"))?;
+                    disk_config::Backend::Rbd(RbdConfig {
+                        image_spec,
+                        mon_hosts: ceph_mons,
+                        user: ceph_user.unwrap_or_default(),
+                        keyring,
+                    })
+                }
+                (None, None, Some(target_iqn), _, _) => disk_config::Backend::Iscsi(IscsiConfig {
+                    portals: iscsi_portals,
+                    target_iqn,
+                    lun: iscsi_lun,
+                    chap: chap_username.map(|username| IscsiChapCredentials {
+                        username,
+                        password: chap_password.unwrap_or_default(),
+                    }),
+                }),
+                (None, None, None, None, None) => unreachable!(
+                    "clap requires a path, an iSCSI target, an NVMe-oF namespace, an RBD image or an ephemeral size"
+                ),
             };
-            create_and_start_vm(&mut client, opts).await?
+            let disk = DiskConfig {
+                device_id: device_id.unwrap_or_default(),
+                backend: Some(backend),
+                readonly,
+                bus: bus.map(DiskBus::from).unwrap_or_default() as i32,
+                discard,
+                format: format.map(DiskFormat::from).unwrap_or_default() as i32,
+            };
+            attach_disk(&mut client, vm_id, disk).await?
         }
-        VmCommand::Events { vm_id } => watch_events(&mut client, vm_id).await?,
-        VmCommand::Console { vm_id } => console_vm(&mut client, vm_id).await?,
-        VmCommand::AttachDisk { vm_id, path } => attach_disk(&mut client, vm_id, path).await?,
         VmCommand::DetachDisk { vm_id, device_id } => {
             detach_disk(&mut client, vm_id, device_id).await?
         }
         VmCommand::AttachNic {
             vm_id,
             tap_name,
+            slaac,
             pci_device,
+            vhost_user_socket,
+            vhost_user_server,
+            macvtap,
+            macvtap_name,
+            macvtap_mode,
+            bandwidth,
+            packets_per_second,
             mac_address,
             device_id,
         } => {
-            attach_nic(
-                &mut client,
-                vm_id,
-                tap_name,
-                pci_device,
-                mac_address,
-                device_id,
-            )
-            .await?
+            let backend = if let Some(tap) = tap_name {
+                net_config::Backend::Tap(TapConfig {
+                    tap_name: tap,
+                    slaac,
+                })
+            } else if let Some(bdf) = pci_device {
+                net_config::Backend::VfioPci(VfioPciConfig { bdf })
+            } else if let Some(socket_path) = vhost_user_socket {
+                net_config::Backend::VhostUser(VhostUserNetConfig {
+                    socket_path,
+                    server: vhost_user_server,
+                })
+            } else if let Some(parent) = macvtap {
+                net_config::Backend::Macvtap(MacvtapConfig {
+                    parent,
+                    name: macvtap_name.unwrap_or_default(),
+                    mode: macvtap_mode.map(MacvtapMode::from).unwrap_or_default() as i32,
+                })
+            } else {
+                anyhow::bail!(
+                    "One of --tap-name, --pci-device, --vhost-user-socket or --macvtap must be specified."
+                );
+            };
+            let nic = NetConfig {
+                device_id: device_id.unwrap_or_default(),
+                backend: Some(backend),
+                mac_address: mac_address.unwrap_or_default(),
+                rate_limit: create::net_rate_limit(bandwidth, packets_per_second),
+            };
+            attach_nic(&mut client, vm_id, nic).await?
         }
         VmCommand::DetachNic { vm_id, device_id } => {
             detach_nic(&mut client, vm_id, device_id).await?
         }
+        VmCommand::ListPciDevices { vfio_capable_only } => {
+            list_pci_devices(&mut client, vfio_capable_only).await?
+        }
+        VmCommand::AttachPciDevice {
+            vm_id,
+            pci_address,
+            device_id,
+        } => {
+            let device = PciDeviceConfig {
+                device_id: device_id.unwrap_or_default(),
+                pci_address,
+            };
+            attach_pci_device(&mut client, vm_id, device).await?
+        }
+        VmCommand::DetachPciDevice { vm_id, device_id } => {
+            detach_pci_device(&mut client, vm_id, device_id).await?
+        }
+        VmCommand::Snapshot(command) => {
+            snapshot::handle_snapshot_command(&mut client, command).await?
+        }
+        VmCommand::Replica(command) => {
+            replica::handle_replica_command(&mut client, command).await?
+        }
     }
 
     Ok(())
@@ -292,71 +859,26 @@ pub async fn handle_vm_command(args: VmArgs) -> Result<()> {
 
 async fn create_and_start_vm(
     client: &mut VmServiceClient<Channel>,
-    opts: CreateVmOptions,
+    channel: &Channel,
+    request: CreateVmRequest,
 ) -> Result<()> {
-    let CreateVmOptions {
-        image_ref,
-        vcpus,
-        memory,
-        vm_id,
-        pci_devices,
-        hugepages,
-        ignition,
-    } = opts;
-
+    let image_ref = request
+        .config
+        .as_ref()
+        .map(|config| config.image_ref.clone())
+        .unwrap_or_default();
     println!("� Starting create and start operation for VM with image: {image_ref}");
 
     // Step 1: Create the VM
     println!("� Step 1: Creating VM...");
 
-    let ignition_data = if let Some(ignition_str) = ignition {
-        if tokio::fs::metadata(&ignition_str).await.is_ok() {
-            Some(tokio::fs::read_to_string(ignition_str).await?)
-        } else {
-            Some(ignition_str)
-        }
-    } else {
-        None
-    };
-
-    let net_configs = pci_devices
-        .iter()
-        .map(|bdf| {
-            println!("   Adding PCI device: {bdf}");
-            NetConfig {
-                backend: Some(net_config::Backend::VfioPci(VfioPciConfig {
-                    bdf: bdf.clone(),
-                })),
-                ..Default::default()
-            }
-        })
-        .collect();
-
-    let request = CreateVmRequest {
-        config: Some(VmConfig {
-            cpus: Some(CpuConfig {
-                boot_vcpus: vcpus,
-                max_vcpus: vcpus,
-            }),
-            memory: Some(MemoryConfig {
-                size_mib: memory,
-                hugepages,
-            }),
-            image_ref: image_ref.clone(),
-            net: net_configs,
-            ignition: ignition_data,
-            ..Default::default()
-        }),
-        vm_id: vm_id.clone(),
-    };
-
     let response = client.create_vm(request).await?.into_inner();
     let vm_id = response.vm_id;
     println!("✅ VM created successfully with ID: {vm_id}");
 
     // Step 2: Wait for VM to be in 'Created' state
     println!("⏳ Step 2: Waiting for VM to reach 'Created' state...");
-    wait_for_vm_state(client, &vm_id, VmState::Created).await?;
+    wait_for_operation(channel, &Operation::new(OperationKind::VmCreate, &vm_id)).await?;
     println!("✅ VM is now in 'Created' state");
 
     // Step 3: Start the VM
@@ -369,7 +891,7 @@ async fn create_and_start_vm(
 
     // Step 4: Wait for VM to be in 'Running' state
     println!("⏳ Step 4: Waiting for VM to reach 'Running' state...");
-    wait_for_vm_state(client, &vm_id, VmState::Running).await?;
+    wait_for_operation(channel, &Operation::new(OperationKind::VmStart, &vm_id)).await?;
     println!("🎉 VM '{vm_id}' is now running successfully!");
 
     println!("Use 'feos-cli vm console {vm_id}' to connect to the VM console.");
@@ -377,7 +899,7 @@ async fn create_and_start_vm(
     Ok(())
 }
 
-async fn wait_for_vm_state(
+pub(crate) async fn wait_for_vm_state(
     client: &mut VmServiceClient<Channel>,
     vm_id: &str,
     target_state: VmState,
@@ -440,76 +962,44 @@ async fn wait_for_vm_state(
     anyhow::bail!("Event stream ended before reaching target state: {target_state:?}")
 }
 
-async fn create_vm(client: &mut VmServiceClient<Channel>, opts: CreateVmOptions) -> Result<()> {
-    let CreateVmOptions {
-        image_ref,
-        vcpus,
-        memory,
-        vm_id,
-        pci_devices,
-        hugepages,
-        ignition,
-    } = opts;
-
-    println!("Requesting VM creation with image: {image_ref}...");
-
-    let ignition_data = if let Some(ignition_str) = ignition {
-        if tokio::fs::metadata(&ignition_str).await.is_ok() {
-            Some(tokio::fs::read_to_string(ignition_str).await?)
-        } else {
-            Some(ignition_str)
-        }
-    } else {
-        None
-    };
-
-    let net_configs = pci_devices
-        .into_iter()
-        .map(|bdf| {
-            println!("Adding PCI device: {bdf}");
-            NetConfig {
-                backend: Some(net_config::Backend::VfioPci(VfioPciConfig { bdf })),
-                ..Default::default()
-            }
-        })
-        .collect();
-
-    let request = CreateVmRequest {
-        config: Some(VmConfig {
-            cpus: Some(CpuConfig {
-                boot_vcpus: vcpus,
-                max_vcpus: vcpus,
-            }),
-            memory: Some(MemoryConfig {
-                size_mib: memory,
-                hugepages,
-            }),
-            image_ref,
-            net: net_configs,
-            ignition: ignition_data,
-            ..Default::default()
-        }),
-        vm_id,
-    };
-
+async fn create_vm(
+    client: &mut VmServiceClient<Channel>,
+    channel: &Channel,
+    request: CreateVmRequest,
+    run_async: bool,
+) -> Result<()> {
     let response = client.create_vm(request).await?.into_inner();
+    let operation = Operation::new(OperationKind::VmCreate, &response.vm_id);
+    if run_async {
+        print_async(&operation);
+        return Ok(());
+    }
+
     println!("VM creation initiated. VM ID: {}", response.vm_id);
-    println!(
-        "Use 'feos-cli vm events {}' to watch its progress.",
-        response.vm_id
-    );
-    println!("Then 'feos-cli vm start {}' to start it.", response.vm_id);
+    wait_for_operation(channel, &operation).await?;
+    println!("Use 'feos-cli vm start {}' to start it.", response.vm_id);
 
     Ok(())
 }
 
-async fn start_vm(client: &mut VmServiceClient<Channel>, vm_id: String) -> Result<()> {
+async fn start_vm(
+    client: &mut VmServiceClient<Channel>,
+    channel: &Channel,
+    vm_id: String,
+    run_async: bool,
+) -> Result<()> {
     let request = StartVmRequest {
         vm_id: vm_id.clone(),
     };
     client.start_vm(request).await?;
+    let operation = Operation::new(OperationKind::VmStart, &vm_id);
+    if run_async {
+        print_async(&operation);
+        return Ok(());
+    }
+
     println!("Start request sent for VM: {vm_id}");
-    Ok(())
+    wait_for_operation(channel, &operation).await
 }
 
 async fn get_vm_info(client: &mut VmServiceClient<Channel>, vm_id: String) -> Result<()> {
@@ -519,18 +1009,47 @@ async fn get_vm_info(client: &mut VmServiceClient<Channel>, vm_id: String) -> Re
     let response = client.get_vm(request).await?.into_inner();
 
     println!("VM Info for: {vm_id}");
+    println!("  Namespace: {}", response.namespace);
+    if let Some(name) = &response.name {
+        println!("  Name: {name}");
+    }
     println!(
         "  State: {:?}",
         VmState::try_from(response.state).unwrap_or(VmState::Unspecified)
     );
+    if let Some(timings) = &response.boot_timings {
+        print_boot_timings(timings);
+    }
+    if !response.guest_addresses.is_empty() {
+        println!("  Guest Addresses:");
+        for nic in &response.guest_addresses {
+            let ips = if nic.ip_addresses.is_empty() {
+                "-".to_string()
+            } else {
+                nic.ip_addresses.join(", ")
+            };
+            println!("    {} ({}): {ips}", nic.device_id, nic.mac_address);
+        }
+    }
     if let Some(config) = response.config {
         println!("  Config:");
         println!("    Image Ref: {}", config.image_ref);
         if let Some(cpus) = config.cpus {
-            println!("    vCPUs: {}", cpus.boot_vcpus);
+            println!("    vCPUs: {} (max {})", cpus.boot_vcpus, cpus.max_vcpus);
         }
         if let Some(mem) = config.memory {
-            println!("    Memory: {} MiB", mem.size_mib);
+            println!(
+                "    Memory: {} MiB (max {} MiB)",
+                mem.size_mib + mem.hotplugged_size_mib,
+                mem.size_mib + mem.hotplug_size_mib
+            );
+        }
+        if let Some(node) = config
+            .placement
+            .as_ref()
+            .and_then(|placement| placement.assigned_numa_node)
+        {
+            println!("    NUMA Node: {node}");
         }
         if !config.net.is_empty() {
             println!("    Network Devices:");
@@ -541,38 +1060,594 @@ async fn get_vm_info(client: &mut VmServiceClient<Channel>, vm_id: String) -> Re
                             println!("      Device {}: PCI Passthrough - {}", i, pci.bdf);
                         }
                         net_config::Backend::Tap(tap) => {
-                            println!("      Device {}: TAP - {}", i, tap.tap_name);
+                            let slaac = if tap.slaac { " (SLAAC)" } else { "" };
+                            println!("      Device {}: TAP - {}{slaac}", i, tap.tap_name);
+                        }
+                        net_config::Backend::VhostUser(vhost_user) => {
+                            println!(
+                                "      Device {}: vhost-user - {}",
+                                i, vhost_user.socket_path
+                            );
                         }
+                        net_config::Backend::Macvtap(macvtap) => {
+                            println!(
+                                "      Device {}: macvtap - {} on {} ({})",
+                                i,
+                                macvtap.name,
+                                macvtap.parent,
+                                macvtap.mode().as_str_name()
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        if !config.disks.is_empty() {
+            println!("    Disks:");
+            for (i, disk) in config.disks.iter().enumerate() {
+                let mut mode = if disk.readonly { "ro" } else { "rw" }.to_string();
+                if disk.bus() == DiskBus::VirtioScsi {
+                    mode.push_str(", virtio-scsi");
+                }
+                if disk.discard {
+                    mode.push_str(", discard");
+                }
+                if disk.format() == DiskFormat::Qcow2 {
+                    mode.push_str(", qcow2");
+                }
+                match &disk.backend {
+                    Some(disk_config::Backend::Path(path)) => {
+                        println!("      Disk {i}: {path} ({mode})");
+                    }
+                    Some(disk_config::Backend::VfioPci(pci)) => {
+                        println!("      Disk {i}: PCI Passthrough - {}", pci.bdf);
+                    }
+                    Some(disk_config::Backend::Iscsi(target)) => {
+                        println!(
+                            "      Disk {i}: iSCSI {} LUN {} via {} ({mode})",
+                            target.target_iqn,
+                            target.lun,
+                            target.portals.join(", ")
+                        );
+                    }
+                    Some(disk_config::Backend::Nvmeof(subsystem)) => {
+                        println!(
+                            "      Disk {i}: NVMe-oF {} namespace {} via {} ({mode})",
+                            subsystem.subsystem_nqn, subsystem.nsid, subsystem.address
+                        );
+                    }
+                    Some(disk_config::Backend::Rbd(image)) => {
+                        println!(
+                            "      Disk {i}: RBD {} via {} ({mode})",
+                            image.image_spec,
+                            image.mon_hosts.join(", ")
+                        );
+                    }
+                    Some(disk_config::Backend::VhostUserBlk(vhost_user_blk)) => {
+                        println!(
+                            "      Disk {i}: vhost-user-blk {} ({mode})",
+                            vhost_user_blk.socket_path
+                        );
+                    }
+                    Some(disk_config::Backend::Ephemeral(config)) => {
+                        println!(
+                            "      Disk {i}: ephemeral {} ({mode})",
+                            format_bytes(config.size_bytes)
+                        );
+                    }
+                    Some(disk_config::Backend::Overlay(overlay)) => {
+                        let path = if overlay.overlay_path.is_empty() {
+                            "not created yet"
+                        } else {
+                            &overlay.overlay_path
+                        };
+                        println!(
+                            "      Disk {i}: overlay {path} of {} ({mode})",
+                            overlay.base_path
+                        );
                     }
+                    None => {}
                 }
             }
         }
+        if !config.pci_devices.is_empty() {
+            println!("    PCI Devices:");
+            for device in &config.pci_devices {
+                println!("      {}: {}", device.device_id, device.pci_address);
+            }
+        }
     }
     Ok(())
 }
 
-async fn list_vms(client: &mut VmServiceClient<Channel>) -> Result<()> {
-    let request = ListVmsRequest {};
-    let response = client.list_vms(request).await?.into_inner();
+fn seconds_since(start: &Timestamp, end: &Timestamp) -> f64 {
+    (end.seconds - start.seconds) as f64 + f64::from(end.nanos - start.nanos) / 1e9
+}
+
+fn print_boot_timings(timings: &VmBootTimings) {
+    let phases = [
+        ("Create Requested", &timings.create_requested_at),
+        ("Image Ready", &timings.image_ready_at),
+        ("VMM Spawned", &timings.vmm_spawned_at),
+        ("Created", &timings.created_at),
+        ("Boot Requested", &timings.boot_requested_at),
+        ("Booted", &timings.booted_at),
+    ];
+    println!("  Boot Timings:");
+    let mut previous: Option<&Timestamp> = None;
+    for (name, at) in phases {
+        let Some(at) = at else {
+            println!("    {name:<17} -");
+            continue;
+        };
+        let time = chrono::DateTime::from_timestamp(at.seconds, at.nanos as u32)
+            .map(|t| t.with_timezone(&chrono::Local).to_rfc3339())
+            .unwrap_or_default();
+        match previous {
+            Some(previous) => println!(
+                "    {name:<17} {time} (+{:.3}s)",
+                seconds_since(previous, at)
+            ),
+            None => println!("    {name:<17} {time}"),
+        }
+        previous = Some(at);
+    }
+}
+
+/// Estimates a quantile as the upper bound of the bucket it falls into.
+fn bucket_quantile(histogram: &BootDurationHistogram, quantile: f64) -> String {
+    if histogram.count == 0 {
+        return "-".to_string();
+    }
+    let rank = (quantile * histogram.count as f64).ceil() as u64;
+    match histogram
+        .bucket_counts
+        .iter()
+        .position(|count| *count >= rank)
+        .and_then(|bucket| histogram.bucket_bounds_seconds.get(bucket))
+    {
+        Some(bound) => format!("<={bound}s"),
+        None => format!(
+            ">{}s",
+            histogram.bucket_bounds_seconds.last().unwrap_or(&0.0)
+        ),
+    }
+}
+
+async fn get_boot_metrics(client: &mut VmServiceClient<Channel>) -> Result<()> {
+    let response = client
+        .get_vm_boot_metrics(GetVmBootMetricsRequest {})
+        .await?
+        .into_inner();
+
+    println!(
+        "{:<12} {:>6} {:>10} {:>10} {:>10}",
+        "PHASE", "COUNT", "AVG", "P50", "P90"
+    );
+    println!("{:-<12} {:->6} {:->10} {:->10} {:->10}", "", "", "", "", "");
+    for histogram in &response.histograms {
+        let average = if histogram.count == 0 {
+            "-".to_string()
+        } else {
+            format!("{:.3}s", histogram.sum_seconds / histogram.count as f64)
+        };
+        println!(
+            "{:<12} {:>6} {:>10} {:>10} {:>10}",
+            histogram.phase,
+            histogram.count,
+            average,
+            bucket_quantile(histogram, 0.5),
+            bucket_quantile(histogram, 0.9)
+        );
+    }
+    Ok(())
+}
+
+async fn get_vm_stats(client: &mut VmServiceClient<Channel>, vm_ids: Vec<String>) -> Result<()> {
+    let response = client
+        .get_vm_stats(GetVmStatsRequest { vm_ids })
+        .await?
+        .into_inner();
+
+    if response.stats.iter().all(|vm| vm.gpus.is_empty()) {
+        println!("No GPUs assigned to the VMs.");
+        return Ok(());
+    }
+    let or_dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    println!(
+        "{:<38} {:<14} {:<20} {:>5} {:>21} {:>5}",
+        "VM_ID", "PCI", "PROFILE", "UTIL", "MEMORY", "TEMP"
+    );
+    println!(
+        "{:-<38} {:-<14} {:-<20} {:->5} {:->21} {:->5}",
+        "", "", "", "", "", ""
+    );
+    for vm in &response.stats {
+        for gpu in &vm.gpus {
+            let memory = match (gpu.memory_used_bytes, gpu.memory_total_bytes) {
+                (Some(used), Some(total)) => {
+                    Some(format!("{}/{}", format_bytes(used), format_bytes(total)))
+                }
+                (Some(used), None) => Some(format_bytes(used)),
+                _ => None,
+            };
+            println!(
+                "{:<38} {:<14} {:<20} {:>5} {:>21} {:>5}",
+                vm.vm_id,
+                gpu.pci_address,
+                gpu.profile,
+                or_dash(gpu.utilization_percent.map(|util| format!("{util}%"))),
+                or_dash(memory),
+                or_dash(gpu.temperature_celsius.map(|temp| format!("{temp}C")))
+            );
+        }
+    }
+    Ok(())
+}
+
+fn format_endpoint(address: &str, port: u32) -> String {
+    let address = if address.contains(':') {
+        format!("[{address}]")
+    } else {
+        address.to_string()
+    };
+    match port {
+        0 => address,
+        port => format!("{address}:{port}"),
+    }
+}
+
+async fn watch_vm_flows(
+    client: &mut VmServiceClient<Channel>,
+    vm_ids: Vec<String>,
+    interval: u32,
+    top: usize,
+) -> Result<()> {
+    let request = StreamVmFlowsRequest {
+        vm_ids,
+        interval_seconds: interval,
+    };
+    let mut samples = client.stream_vm_flows(request).await?.into_inner();
+
+    while let Some(sample) = samples.next().await {
+        let sample = match sample {
+            Ok(sample) => sample,
+            Err(status) => anyhow::bail!("VM flow stream failed: {status}"),
+        };
+        let mut flows: Vec<_> = sample
+            .vms
+            .iter()
+            .flat_map(|vm| vm.flows.iter().map(move |flow| (vm, flow)))
+            .collect();
+        flows.sort_by_key(|(_, flow)| std::cmp::Reverse(flow.tx_bytes + flow.rx_bytes));
+
+        execute!(std::io::stdout(), Clear(ClearType::All), MoveTo(0, 0))?;
+        println!(
+            "Watching VM connections (last update {}). Press Ctrl+C to stop.\n",
+            chrono::Local::now().format("%H:%M:%S")
+        );
+        println!(
+            "{:<38} {:<8} {:<6} {:<3} {:<45} {:<45} {:<12} {:>10} {:>10}",
+            "VM_ID", "NIC", "PROTO", "DIR", "GUEST", "REMOTE", "STATE", "TX", "RX"
+        );
+        for (vm, flow) in flows.into_iter().take(top) {
+            println!(
+                "{:<38} {:<8} {:<6} {:<3} {:<45} {:<45} {:<12} {:>10} {:>10}",
+                vm.vm_id,
+                flow.device_id,
+                flow.protocol,
+                if flow.inbound { "in" } else { "out" },
+                format_endpoint(&flow.guest_address, flow.guest_port),
+                format_endpoint(&flow.remote_address, flow.remote_port),
+                flow.state,
+                format_bytes(flow.tx_bytes),
+                format_bytes(flow.rx_bytes)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The disk and network traffic of a VM so far, summed over its devices:
+/// bytes read, written, received and sent.
+fn vm_traffic(vm: &VmMetrics) -> [u64; 4] {
+    let (mut read, mut written) = (0, 0);
+    for disk in &vm.disks {
+        read += disk.read_bytes;
+        written += disk.write_bytes;
+    }
+    let (mut received, mut sent) = (0, 0);
+    for nic in &vm.nics {
+        received += nic.rx_bytes;
+        sent += nic.tx_bytes;
+    }
+    [read, written, received, sent]
+}
+
+async fn watch_vm_metrics(
+    client: &mut VmServiceClient<Channel>,
+    vm_ids: Vec<String>,
+    interval: u32,
+) -> Result<()> {
+    let request = StreamVmMetricsRequest {
+        vm_ids,
+        interval_seconds: interval,
+    };
+    let mut samples = client.stream_vm_metrics(request).await?.into_inner();
+    let or_dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    let mut previous: HashMap<String, [u64; 4]> = HashMap::new();
+    let mut last_sample: Option<Instant> = None;
+
+    while let Some(sample) = samples.next().await {
+        let sample = match sample {
+            Ok(sample) => sample,
+            Err(status) => anyhow::bail!("VM metrics stream failed: {status}"),
+        };
+        let now = Instant::now();
+        let elapsed = last_sample.map(|last| now.duration_since(last).as_secs_f64());
+        last_sample = Some(now);
+
+        execute!(std::io::stdout(), Clear(ClearType::All), MoveTo(0, 0))?;
+        println!(
+            "Watching VM usage (last update {}). Press Ctrl+C to stop.\n",
+            chrono::Local::now().format("%H:%M:%S")
+        );
+        println!(
+            "{:<38} {:>7} {:>10} {:>12} {:>12} {:>12} {:>12}",
+            "VM_ID", "CPU", "MEMORY", "DISK_READ/S", "DISK_WRITE/S", "NET_RX/S", "NET_TX/S"
+        );
+        let mut current = HashMap::new();
+        for vm in &sample.vms {
+            let traffic = vm_traffic(vm);
+            // A rate needs the traffic of the previous sample, and a VM whose
+            // hypervisor was restarted counts from zero again.
+            let rate = |index: usize| {
+                let before = previous.get(&vm.vm_id)?[index];
+                let delta = traffic[index].checked_sub(before)?;
+                let elapsed = elapsed.filter(|elapsed| *elapsed > 0.0)?;
+                Some(format_bytes((delta as f64 / elapsed) as u64))
+            };
+            println!(
+                "{:<38} {:>7} {:>10} {:>12} {:>12} {:>12} {:>12}",
+                vm.vm_id,
+                or_dash(vm.cpu_percent.map(|cpu| format!("{cpu:.1}%"))),
+                format_bytes(vm.memory_rss_bytes),
+                or_dash(rate(0)),
+                or_dash(rate(1)),
+                or_dash(rate(2)),
+                or_dash(rate(3))
+            );
+            current.insert(vm.vm_id.clone(), traffic);
+        }
+        previous = current;
+    }
+    Ok(())
+}
+
+async fn replay_state_journal(
+    client: &mut VmServiceClient<Channel>,
+    vm_id: Option<String>,
+    after_sequence: u64,
+    limit: Option<u32>,
+) -> Result<()> {
+    let response = client
+        .replay_vm_state_journal(ReplayVmStateJournalRequest {
+            vm_id,
+            after_sequence,
+            limit: limit.unwrap_or_default(),
+        })
+        .await?
+        .into_inner();
+
+    if response.entries.is_empty() {
+        println!("No journal entries found.");
+        return Ok(());
+    }
+    let format_state = |state: Option<i32>| match state {
+        Some(state) => format!(
+            "{:?}",
+            VmState::try_from(state).unwrap_or(VmState::Unspecified)
+        ),
+        None => "-".to_string(),
+    };
+    println!(
+        "{:>8} {:<32} {:<38} {:<12} {:<12} MESSAGE",
+        "SEQUENCE", "TIME", "VM_ID", "FROM", "TO"
+    );
+    println!(
+        "{:->8} {:-<32} {:-<38} {:-<12} {:-<12} {:-<20}",
+        "", "", "", "", "", ""
+    );
+    for entry in &response.entries {
+        let time = entry
+            .recorded_at
+            .as_ref()
+            .and_then(|at| chrono::DateTime::from_timestamp(at.seconds, at.nanos as u32))
+            .map(|t| t.with_timezone(&chrono::Local).to_rfc3339())
+            .unwrap_or_default();
+        println!(
+            "{:>8} {:<32} {:<38} {:<12} {:<12} {}",
+            entry.sequence,
+            time,
+            entry.vm_id,
+            format_state(entry.old_state),
+            format_state(entry.new_state),
+            entry.message
+        );
+    }
+    Ok(())
+}
+
+async fn plan_evacuation(
+    client: &mut VmServiceClient<Channel>,
+    vm_ids: Vec<String>,
+    all: bool,
+) -> Result<()> {
+    let target = if all {
+        EvacuationTarget::EmptyHost
+    } else {
+        EvacuationTarget::DrainHost
+    };
+    let response = client
+        .plan_evacuation(PlanEvacuationRequest {
+            target: target as i32,
+            vm_ids,
+        })
+        .await?
+        .into_inner();
 
-    if response.vms.is_empty() {
+    if response.steps.is_empty() {
         println!("No VMs found.");
         return Ok(());
     }
+    println!("{:<38} {:<10} {:<14} BLOCKERS", "VM_ID", "STATE", "ACTION");
+    println!("{:-<38} {:-<10} {:-<14} {:-<8}", "", "", "", "");
+    for step in &response.steps {
+        let blockers: Vec<String> = step
+            .blockers
+            .iter()
+            .map(|blocker| {
+                let kind = match blocker.kind() {
+                    MigrationBlockerKind::PciDevice => "pci",
+                    MigrationBlockerKind::GpuPartition => "gpu",
+                    MigrationBlockerKind::Mdev => "mdev",
+                    MigrationBlockerKind::SerialPort => "serial",
+                    MigrationBlockerKind::EphemeralDisk => "ephemeral",
+                    MigrationBlockerKind::Unspecified => "unknown",
+                };
+                format!("{kind}:{}", blocker.device)
+            })
+            .collect();
+        let action = match step.action() {
+            EvacuationAction::Keep => "keep",
+            EvacuationAction::LiveMigrate => "live-migrate",
+            EvacuationAction::Stop => "stop",
+            EvacuationAction::Recreate => "recreate",
+            EvacuationAction::Unspecified => "unknown",
+        };
+        let state = format!("{:?}", step.state());
+        println!(
+            "{:<38} {:<10} {:<14} {}",
+            step.vm_id,
+            state,
+            action,
+            if blockers.is_empty() {
+                "-".to_string()
+            } else {
+                blockers.join(", ")
+            }
+        );
+        for path in &step.host_paths {
+            println!("  Needs on the new host: {path}");
+        }
+    }
+
+    let required = response.required_capacity.unwrap_or_default();
+    println!();
+    println!(
+        "Required elsewhere: {} vCPUs, {} memory ({} hugepages), {} scratch disks",
+        required.vcpus,
+        format_bytes(required.memory_bytes),
+        format_bytes(required.hugepage_memory_bytes),
+        format_bytes(required.ephemeral_disk_bytes)
+    );
+    let mut gpu_partitions: Vec<_> = required.gpu_partitions.iter().collect();
+    gpu_partitions.sort();
+    for (profile, count) in gpu_partitions {
+        println!("  {count} GPU partitions of {profile}");
+    }
+    if required.mdevs > 0 {
+        println!("  {} mediated devices", required.mdevs);
+    }
+    Ok(())
+}
+
+async fn list_vms(client: &mut VmServiceClient<Channel>, namespace: Option<String>) -> Result<()> {
+    let request = ListVmsRequest { namespace };
+    let response = client.list_vms(request).await?.into_inner();
+    print_vm_table(&response.vms);
+    Ok(())
+}
+
+fn print_vm_table(vms: &[VmInfo]) {
+    if vms.is_empty() {
+        println!("No VMs found.");
+        return;
+    }
 
-    println!("{:<38} {:<12} IMAGE_REF", "VM_ID", "STATE");
-    println!("{:-<38} {:-<12} {:-<40}", "", "", "");
-    for vm in response.vms {
+    println!(
+        "{:<38} {:<16} {:<20} {:<12} {:<40} ADDRESSES",
+        "VM_ID", "NAMESPACE", "NAME", "STATE", "IMAGE_REF"
+    );
+    println!(
+        "{:-<38} {:-<16} {:-<20} {:-<12} {:-<40} {:-<20}",
+        "", "", "", "", "", ""
+    );
+    for vm in vms {
         let state = VmState::try_from(vm.state).unwrap_or(VmState::Unspecified);
-        let image_ref = vm.config.map(|c| c.image_ref).unwrap_or_default();
+        let image_ref = vm
+            .config
+            .as_ref()
+            .map(|c| c.image_ref.as_str())
+            .unwrap_or_default();
+        let addresses: Vec<&str> = vm
+            .guest_addresses
+            .iter()
+            .flat_map(|nic| nic.ip_addresses.iter().map(String::as_str))
+            .collect();
+        let addresses = if addresses.is_empty() {
+            "-".to_string()
+        } else {
+            addresses.join(",")
+        };
         println!(
-            "{:<38} {:<12} {}",
+            "{:<38} {:<16} {:<20} {:<12} {:<40} {}",
             vm.vm_id,
+            vm.namespace,
+            vm.name.as_deref().unwrap_or("-"),
             format!("{state:?}"),
-            image_ref
+            image_ref,
+            addresses
         );
     }
-    Ok(())
+}
+
+async fn watch_vms(client: &mut VmServiceClient<Channel>, namespace: Option<String>) -> Result<()> {
+    let request = StreamVmEventsRequest {
+        namespace: namespace.clone(),
+        ..Default::default()
+    };
+    let mut events = client.stream_vm_events(request).await?.into_inner();
+
+    loop {
+        let vms = client
+            .list_vms(ListVmsRequest {
+                namespace: namespace.clone(),
+            })
+            .await?
+            .into_inner()
+            .vms;
+        execute!(std::io::stdout(), Clear(ClearType::All), MoveTo(0, 0))?;
+        println!(
+            "Watching VMs (last update {}). Press Ctrl+C to stop.\n",
+            chrono::Local::now().format("%H:%M:%S")
+        );
+        print_vm_table(&vms);
+
+        // Deletions send no event, so the table is also refreshed
+        // periodically to drop deleted VMs.
+        match tokio::time::timeout(WATCH_REFRESH_INTERVAL, events.next()).await {
+            Err(_) => continue,
+            Ok(Some(Ok(_))) => {}
+            Ok(Some(Err(status))) => anyhow::bail!("VM event stream failed: {status}"),
+            Ok(None) => return Ok(()),
+        }
+        // Coalesce bursts of events, e.g. the initial state of every VM, into
+        // a single refresh of the table.
+        while let Ok(Some(event)) = tokio::time::timeout(WATCH_DEBOUNCE, events.next()).await {
+            event?;
+        }
+    }
 }
 
 async fn ping_vm(client: &mut VmServiceClient<Channel>, vm_id: String) -> Result<()> {
@@ -589,15 +1664,75 @@ async fn ping_vm(client: &mut VmServiceClient<Channel>, vm_id: String) -> Result
     Ok(())
 }
 
-async fn shutdown_vm(client: &mut VmServiceClient<Channel>, vm_id: String) -> Result<()> {
+async fn shutdown_vm(
+    client: &mut VmServiceClient<Channel>,
+    vm_id: String,
+    graceful: bool,
+    grace_period: Option<u32>,
+) -> Result<()> {
     let request = ShutdownVmRequest {
         vm_id: vm_id.clone(),
+        graceful,
+        grace_period_seconds: grace_period,
     };
     client.shutdown_vm(request).await?;
     println!("Shutdown request sent for VM: {vm_id}");
     Ok(())
 }
 
+/// Exit code for commands killed after `--timeout`, as timeout(1) uses.
+const EXEC_TIMEOUT_EXIT_CODE: i32 = 124;
+
+async fn exec_in_guest(
+    client: &mut VmServiceClient<Channel>,
+    mut request: ExecInGuestRequest,
+    interactive: bool,
+) -> Result<i32> {
+    if interactive {
+        tokio::io::stdin()
+            .read_to_end(&mut request.stdin)
+            .await
+            .context("Failed to read stdin")?;
+    }
+    let timeout_seconds = request.timeout_seconds;
+    let response = client.exec_in_guest(request).await?.into_inner();
+
+    tokio::io::stdout().write_all(&response.stdout).await?;
+    tokio::io::stderr().write_all(&response.stderr).await?;
+    if response.timed_out {
+        eprintln!("Command was killed after {timeout_seconds}s");
+        return Ok(EXEC_TIMEOUT_EXIT_CODE);
+    }
+    Ok(response.exit_code)
+}
+
+async fn get_guest_info(client: &mut VmServiceClient<Channel>, vm_id: String) -> Result<()> {
+    let request = GetGuestInfoRequest {
+        vm_id: vm_id.clone(),
+    };
+    let response = client.get_guest_info(request).await?.into_inner();
+
+    println!("Guest Info for: {vm_id}");
+    println!("  Agent Version: {}", response.agent_version);
+    println!("  Hostname: {}", response.hostname);
+    println!("  Interfaces:");
+    for interface in response.interfaces {
+        let nic = if interface.device_id.is_empty() {
+            String::new()
+        } else {
+            format!(" (NIC {})", interface.device_id)
+        };
+        println!("    {}{nic}", interface.name);
+        if !interface.mac_address.is_empty() {
+            println!("      MAC Address: {}", interface.mac_address);
+        }
+        for address in &interface.ip_addresses {
+            println!("      IP Address: {address}");
+        }
+    }
+    Ok(())
+}
+
 async fn pause_vm(client: &mut VmServiceClient<Channel>, vm_id: String) -> Result<()> {
     let request = PauseVmRequest {
         vm_id: vm_id.clone(),
@@ -616,6 +1751,22 @@ async fn resume_vm(client: &mut VmServiceClient<Channel>, vm_id: String) -> Resu
     Ok(())
 }
 
+async fn resize_vm(
+    client: &mut VmServiceClient<Channel>,
+    vm_id: String,
+    vcpus: Option<u32>,
+    memory_mib: Option<u64>,
+) -> Result<()> {
+    let request = ResizeVmRequest {
+        vm_id: vm_id.clone(),
+        vcpus,
+        memory_mib,
+    };
+    client.resize_vm(request).await?;
+    println!("Resized VM: {vm_id}");
+    Ok(())
+}
+
 async fn delete_vm(client: &mut VmServiceClient<Channel>, vm_id: String) -> Result<()> {
     let request = DeleteVmRequest {
         vm_id: vm_id.clone(),
@@ -625,7 +1776,11 @@ async fn delete_vm(client: &mut VmServiceClient<Channel>, vm_id: String) -> Resu
     Ok(())
 }
 
-async fn watch_events(client: &mut VmServiceClient<Channel>, vm_id: Option<String>) -> Result<()> {
+async fn watch_events(
+    client: &mut VmServiceClient<Channel>,
+    vm_id: Option<String>,
+    namespace: Option<String>,
+) -> Result<()> {
     if let Some(id) = &vm_id {
         println!("Watching events for VM: {id}. Press Ctrl+C to stop.");
     } else {
@@ -634,6 +1789,7 @@ async fn watch_events(client: &mut VmServiceClient<Channel>, vm_id: Option<Strin
 
     let request = StreamVmEventsRequest {
         vm_id,
+        namespace,
         ..Default::default()
     };
     let mut stream = client.stream_vm_events(request).await?.into_inner();
@@ -669,12 +1825,18 @@ async fn watch_events(client: &mut VmServiceClient<Channel>, vm_id: Option<Strin
     Ok(())
 }
 
-async fn console_vm(client: &mut VmServiceClient<Channel>, vm_id: String) -> Result<()> {
+async fn console_vm(
+    client: &mut VmServiceClient<Channel>,
+    attach: AttachConsoleMessage,
+) -> Result<()> {
     if !std::io::stdin().is_tty() {
         anyhow::bail!("Cannot enter interactive console mode without a TTY.");
     }
 
-    println!("Connecting to console for VM: {vm_id}. Press Ctrl+] to exit.");
+    println!(
+        "Connecting to console for VM: {}. Press Ctrl+] to exit.",
+        attach.vm_id
+    );
 
     struct RawModeGuard;
     impl Drop for RawModeGuard {
@@ -691,25 +1853,28 @@ async fn console_vm(client: &mut VmServiceClient<Channel>, vm_id: String) -> Res
     let (input_tx, input_rx) = mpsc::channel(10);
     let input_stream = tokio_stream::wrappers::ReceiverStream::new(input_rx);
 
-    let response = client.stream_vm_console(input_stream).await?;
-    let mut output_stream = response.into_inner();
-
-    let attach_payload = console_input::Payload::Attach(AttachConsoleMessage {
-        vm_id: vm_id.clone(),
-    });
+    // The attach message is queued first, signed requests wait for it.
     let attach_input = StreamVmConsoleRequest {
-        payload: Some(attach_payload),
+        payload: Some(console_input::Payload::Attach(attach)),
     };
     input_tx
         .send(attach_input)
         .await
         .context("Failed to send attach message")?;
 
+    let response = client.stream_vm_console(input_stream).await?;
+    let mut output_stream = response.into_inner();
+
     let output_task = tokio::spawn(async move {
         let mut stdout = tokio::io::stdout();
         while let Some(result) = output_stream.next().await {
             match result {
                 Ok(msg) => {
+                    match msg.writable {
+                        Some(true) => eprint!("\r\n[Console is writable]\r\n"),
+                        Some(false) => eprint!("\r\n[Console is read-only]\r\n"),
+                        None => {}
+                    }
                     if let Err(e) = stdout.write_all(&msg.output).await {
                         eprintln!("\r\nError writing to stdout: {e}\r\n");
                         break;
@@ -766,14 +1931,11 @@ async fn console_vm(client: &mut VmServiceClient<Channel>, vm_id: String) -> Res
 async fn attach_disk(
     client: &mut VmServiceClient<Channel>,
     vm_id: String,
-    path: String,
+    disk: DiskConfig,
 ) -> Result<()> {
     let request = AttachDiskRequest {
         vm_id: vm_id.clone(),
-        disk: Some(DiskConfig {
-            backend: Some(feos_proto::vm_service::disk_config::Backend::Path(path)),
-            ..Default::default()
-        }),
+        disk: Some(disk),
     };
     let response = client.attach_disk(request).await?.into_inner();
     println!(
@@ -800,25 +1962,8 @@ async fn detach_disk(
 async fn attach_nic(
     client: &mut VmServiceClient<Channel>,
     vm_id: String,
-    tap_name: Option<String>,
-    pci_device: Option<String>,
-    mac_address: Option<String>,
-    device_id: Option<String>,
+    nic: NetConfig,
 ) -> Result<()> {
-    let backend = if let Some(tap) = tap_name {
-        Some(net_config::Backend::Tap(TapConfig { tap_name: tap }))
-    } else if let Some(bdf) = pci_device {
-        Some(net_config::Backend::VfioPci(VfioPciConfig { bdf }))
-    } else {
-        anyhow::bail!("Either --tap-name or --pci-device must be specified.");
-    };
-
-    let nic = NetConfig {
-        device_id: device_id.unwrap_or_default(),
-        mac_address: mac_address.unwrap_or_default(),
-        backend,
-    };
-
     let request = AttachNicRequest {
         vm_id: vm_id.clone(),
         nic: Some(nic),
@@ -846,3 +1991,78 @@ async fn detach_nic(
     println!("NIC detach request sent for device {device_id} on VM {vm_id}");
     Ok(())
 }
+
+async fn list_pci_devices(
+    client: &mut VmServiceClient<Channel>,
+    vfio_capable_only: bool,
+) -> Result<()> {
+    let request = ListHostPciDevicesRequest { vfio_capable_only };
+    let response = client.list_host_pci_devices(request).await?.into_inner();
+    if response.devices.is_empty() {
+        println!("No PCI devices found.");
+        return Ok(());
+    }
+
+    println!(
+        "{:<12} {:<9} {:<6} {:<14} {:<5} {:<4} {:<8} VM",
+        "ADDRESS", "ID", "CLASS", "DRIVER", "GROUP", "NODE", "CAPABLE"
+    );
+    println!(
+        "{:-<12} {:-<9} {:-<6} {:-<14} {:-<5} {:-<4} {:-<8} {:-<36}",
+        "", "", "", "", "", "", "", ""
+    );
+    let optional = |value: Option<u32>| value.map_or("-".to_string(), |v| v.to_string());
+    for device in response.devices {
+        let id = format!("{:04x}:{:04x}", device.vendor_id, device.device_id);
+        let class = format!("{:06x}", device.class_code);
+        println!(
+            "{:<12} {:<9} {:<6} {:<14} {:<5} {:<4} {:<8} {}",
+            device.pci_address,
+            id,
+            class,
+            if device.driver.is_empty() {
+                "-"
+            } else {
+                &device.driver
+            },
+            optional(device.iommu_group),
+            optional(device.numa_node),
+            if device.vfio_capable { "yes" } else { "no" },
+            device.vm_id.as_deref().unwrap_or("-")
+        );
+    }
+    Ok(())
+}
+
+async fn attach_pci_device(
+    client: &mut VmServiceClient<Channel>,
+    vm_id: String,
+    device: PciDeviceConfig,
+) -> Result<()> {
+    let request = AttachPciDeviceRequest {
+        vm_id: vm_id.clone(),
+        device: Some(device),
+    };
+
+    let response = client.attach_pci_device(request).await?.into_inner();
+    println!(
+        "PCI device attach request sent for VM: {vm_id}. Assigned device_id: {}",
+        response.device_id
+    );
+
+    Ok(())
+}
+
+async fn detach_pci_device(
+    client: &mut VmServiceClient<Channel>,
+    vm_id: String,
+    device_id: String,
+) -> Result<()> {
+    let request = DetachPciDeviceRequest {
+        vm_id: vm_id.clone(),
+        device_id: device_id.clone(),
+    };
+    client.detach_pci_device(request).await?;
+    println!("PCI device detach request sent for device {device_id} on VM {vm_id}");
+    Ok(())
+}
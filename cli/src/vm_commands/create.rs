@@ -0,0 +1,1358 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{
+    DiskBusArg, DiskFormatArg, DrainPolicyArg, MacvtapModeArg, PlacementPolicyArg, StartupAfterArg,
+};
+use crate::secret_commands::parse_secret_file;
+use crate::storage_commands::parse_size;
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Args, ValueEnum};
+use feos_proto::vm_service::{
+    disk_config, net_config, startup_dependency, ClockConfig, CloudInitConfig, CloudInitSecret,
+    CpuConfig, CreateVmRequest, DiskBus, DiskConfig, DiskFormat, DrainPolicy, EphemeralDiskConfig,
+    GpuConfig, MacvtapConfig, MacvtapMode, MdevConfig, MemoryConfig, NetConfig, NetRateLimit,
+    NetbootConfig, NvmeofConfig, NvmeofTransport, OverlayDiskConfig, PciDeviceConfig,
+    PlacementConfig, PlacementPolicy, SerialPortConfig, StartupConfig, TapConfig, VfioPciConfig,
+    VhostUserNetConfig, VmConfig,
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashSet;
+use std::net::Ipv6Addr;
+use std::path::PathBuf;
+
+const DEFAULT_VCPUS: u32 = 1;
+const DEFAULT_MEMORY_MIB: u64 = 1024;
+
+#[derive(Args, Debug, Clone)]
+pub struct CreateVmFlags {
+    #[arg(
+        long,
+        help = "YAML template with VM settings; flags override scalar values and add devices"
+    )]
+    template: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Container image reference to use for the VM (required unless set by the template)"
+    )]
+    image_ref: Option<String>,
+
+    #[arg(long, help = "Number of virtual CPUs to allocate [default: 1]")]
+    vcpus: Option<u32>,
+
+    #[arg(long, help = "Maximum number of virtual CPUs [default: --vcpus]")]
+    max_vcpus: Option<u32>,
+
+    #[arg(long, help = "Memory size in MiB [default: 1024]")]
+    memory: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Maximum memory in MiB the VM can be resized to [default: --memory]"
+    )]
+    max_memory: Option<u64>,
+
+    #[arg(long, help = "Optional custom VM identifier (UUID)")]
+    vm_id: Option<String>,
+
+    #[arg(
+        long,
+        env = "FEOS_NAMESPACE",
+        help = "Namespace to create the VM in [default: default]"
+    )]
+    namespace: Option<String>,
+
+    #[arg(long, help = "Name of the VM, unique within its namespace")]
+    name: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "SPEC",
+        help = "Data disk as path=<file-or-device>[,format=raw|qcow2]|overlay=<base-image>|pci=<bdf>|ephemeral=<size>|nvmeof=[tcp|rdma://]<address>[:port]/<nqn>[/nsid][,host-nqn=<nqn>][,id=<device-id>][,readonly][,bus=virtio-blk|virtio-scsi] (repeatable)"
+    )]
+    disk: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "SPEC",
+        help = "Network interface as tap=<name>[,slaac]|pci=<bdf>|vhost-user=<socket>[,server]|macvtap=<parent>[,mode=<mode>][,mac=<mac>][,id=<device-id>][,bandwidth=<bytes/s>][,pps=<packets/s>] (repeatable)"
+    )]
+    nic: Vec<String>,
+
+    #[arg(
+        long,
+        help = "PCI device BDF to passthrough for networking (e.g., 0000:03:00.0), same as --nic pci=<bdf>"
+    )]
+    pci_device: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "PROFILE",
+        help = "Pass through a free GPU partition of the vGPU profile, e.g. \"NVIDIA A100-4C\" (repeatable)"
+    )]
+    gpu: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "UUID",
+        help = "Pass through a mediated device created with 'host mdev create' (repeatable)"
+    )]
+    mdev: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "BDF",
+        help = "Pass through a PCI device of the host, e.g. 0000:81:00.0, see 'vm list-pci-devices' (repeatable)"
+    )]
+    passthrough: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Serial port of the host the guest sees as its virtio console, e.g. /dev/ttyUSB0"
+    )]
+    serial_port: Option<String>,
+
+    #[arg(
+        long,
+        requires = "serial_port",
+        help = "Line speed to set on --serial-port, e.g. 115200 [default: keep the port's speed]"
+    )]
+    serial_baud_rate: Option<u32>,
+
+    #[arg(long, help = "Enable hugepages for memory allocation")]
+    hugepages: bool,
+
+    #[arg(
+        long,
+        value_parser = parse_size,
+        help = "Limit how much guest memory the host may swap out, 0 keeps it in RAM (e.g., 512M)"
+    )]
+    swap_max: Option<u64>,
+
+    #[arg(long, help = "Path to ignition file or the content itself")]
+    ignition: Option<String>,
+
+    #[arg(
+        long,
+        help = "cloud-init user-data, e.g. a #cloud-config file, or the content itself"
+    )]
+    user_data: Option<String>,
+
+    #[arg(
+        long,
+        help = "cloud-init meta-data file or the content itself [default: the VM ID as instance-id and the name as hostname]"
+    )]
+    meta_data: Option<String>,
+
+    #[arg(
+        long,
+        help = "cloud-init network-config (version 1 or 2) file or the content itself"
+    )]
+    network_config: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "SECRET:PATH",
+        value_parser = parse_secret_file,
+        help = "Have cloud-init write the value of a secret of the namespace to a file readable by root only (repeatable)"
+    )]
+    secret_file: Vec<(String, String)>,
+
+    #[arg(
+        long,
+        value_name = "URL",
+        requires = "netboot_address",
+        help = "Boot from the network, fetching this http, https or tftp URL, e.g. http://[2001:db8::1]/ipxe.efi"
+    )]
+    netboot_url: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "IPV6",
+        requires = "netboot_url",
+        help = "IPv6 address the guest firmware gets over DHCPv6 while netbooting"
+    )]
+    netboot_address: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "DEVICE_ID",
+        requires = "netboot_url",
+        help = "Tap NIC to netboot through [default: the first tap NIC]"
+    )]
+    netboot_nic: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "PARAM",
+        requires = "netboot_url",
+        help = "Parameter passed to the boot file, e.g. an iPXE script URL (repeatable)"
+    )]
+    netboot_param: Vec<String>,
+
+    #[arg(
+        long,
+        help = "Expose the Hyper-V reference TSC page clocksource, e.g. for Windows guests"
+    )]
+    hyperv_clock: bool,
+
+    #[arg(
+        long,
+        help = "Require that the guest can synchronize its clock to the host with ptp_kvm"
+    )]
+    ptp_kvm: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "What happens to the VM when the host is drained [default: shutdown]"
+    )]
+    drain_policy: Option<DrainPolicyArg>,
+
+    #[arg(
+        long,
+        value_name = "NODE",
+        help = "NUMA node to bind the VM's vCPUs and memory to [default: the node of its devices]"
+    )]
+    numa_node: Option<u32>,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Whether the VM may run when its placement cannot be met [default: preferred]"
+    )]
+    placement_policy: Option<PlacementPolicyArg>,
+
+    #[arg(
+        long,
+        value_name = "VM_ID",
+        help = "VM whose NUMA node the VM should avoid, e.g. a replica (repeatable)"
+    )]
+    anti_affinity: Vec<String>,
+
+    #[arg(
+        long,
+        alias = "autostart",
+        help = "Bring the VM back up once the network is up when FeOS finds its hypervisor gone, e.g. after a reboot"
+    )]
+    auto_start: bool,
+
+    #[arg(
+        long,
+        value_name = "vm:<id>|container:<id>",
+        help = "Workload that must be running before FeOS starts the VM by itself (repeatable)"
+    )]
+    after: Vec<StartupAfterArg>,
+
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        help = "How long FeOS waits for --after workloads [default: 300]"
+    )]
+    dependency_timeout: Option<u32>,
+
+    #[arg(
+        long,
+        help = "Validate and print the resulting request without sending it"
+    )]
+    pub dry_run: bool,
+}
+
+/// VM settings read from a `--template` file.
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct VmTemplate {
+    image_ref: Option<String>,
+    vcpus: Option<u32>,
+    max_vcpus: Option<u32>,
+    memory: Option<u64>,
+    max_memory: Option<u64>,
+    #[serde(default)]
+    hugepages: bool,
+    #[serde(default)]
+    disks: Vec<DiskSpec>,
+    #[serde(default)]
+    nics: Vec<NicSpec>,
+    /// vGPU profiles of the GPU partitions to pass through.
+    #[serde(default)]
+    gpus: Vec<String>,
+    /// UUIDs of the mediated devices to pass through.
+    #[serde(default)]
+    mdevs: Vec<String>,
+    /// Addresses of the PCI devices of the host to pass through.
+    #[serde(default)]
+    passthrough: Vec<String>,
+    serial_port: Option<String>,
+    serial_baud_rate: Option<u32>,
+    ignition: Option<String>,
+    /// cloud-init data, each a file or the content itself.
+    user_data: Option<String>,
+    meta_data: Option<String>,
+    network_config: Option<String>,
+    netboot: Option<NetbootSpec>,
+    #[serde(default)]
+    hyperv_clock: bool,
+    #[serde(default)]
+    ptp_kvm: bool,
+    drain_policy: Option<DrainPolicyArg>,
+    numa_node: Option<u32>,
+    placement_policy: Option<PlacementPolicyArg>,
+    /// IDs of the VMs whose NUMA nodes to avoid.
+    #[serde(default)]
+    anti_affinity: Vec<String>,
+    #[serde(default, alias = "autostart")]
+    auto_start: bool,
+    #[serde(default)]
+    after: Vec<StartupAfterArg>,
+    dependency_timeout: Option<u32>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct DiskSpec {
+    path: Option<String>,
+    /// Format of the image at `path`, detected by the host if not given.
+    format: Option<DiskFormatArg>,
+    /// Base image of a copy-on-write overlay the host creates for the VM.
+    overlay: Option<String>,
+    pci: Option<String>,
+    /// Size of a scratch disk in host RAM, e.g. "4G".
+    ephemeral: Option<String>,
+    /// Namespace of an NVMe-oF subsystem, as `parse_nvmeof` takes it.
+    nvmeof: Option<String>,
+    host_nqn: Option<String>,
+    device_id: Option<String>,
+    #[serde(default)]
+    readonly: bool,
+    bus: Option<DiskBusArg>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct NetbootSpec {
+    url: String,
+    address: String,
+    /// The device ID of the tap NIC to boot through.
+    nic: Option<String>,
+    #[serde(default)]
+    params: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct NicSpec {
+    tap: Option<String>,
+    /// The guest of a tap NIC is addressed by SLAAC.
+    #[serde(default)]
+    slaac: bool,
+    pci: Option<String>,
+    /// The vhost-user socket of a userspace dataplane.
+    vhost_user: Option<String>,
+    /// Cloud Hypervisor listens on the vhost-user socket.
+    #[serde(default)]
+    vhost_user_server: bool,
+    /// The host interface of a macvtap NIC.
+    macvtap: Option<String>,
+    macvtap_mode: Option<MacvtapModeArg>,
+    mac_address: Option<String>,
+    device_id: Option<String>,
+    /// Bytes per second, e.g. "125M".
+    bandwidth: Option<String>,
+    packets_per_second: Option<u64>,
+}
+
+/// Builds and validates a `CreateVmRequest` from the template and flags.
+pub async fn build_create_request(flags: &CreateVmFlags) -> Result<CreateVmRequest> {
+    let template = match &flags.template {
+        Some(path) => {
+            let content = tokio::fs::read_to_string(path)
+                .await
+                .with_context(|| format!("Failed to read template {}", path.display()))?;
+            serde_yaml::from_str(&content)
+                .with_context(|| format!("Invalid template {}", path.display()))?
+        }
+        None => VmTemplate::default(),
+    };
+
+    let mut disks = template.disks;
+    for spec in &flags.disk {
+        disks.push(parse_disk_spec(spec)?);
+    }
+    let mut nics = template.nics;
+    for spec in &flags.nic {
+        nics.push(parse_nic_spec(spec)?);
+    }
+    nics.extend(flags.pci_device.iter().map(|bdf| NicSpec {
+        pci: Some(bdf.clone()),
+        ..Default::default()
+    }));
+    let mut gpus = template.gpus;
+    gpus.extend(flags.gpu.iter().cloned());
+    if gpus.iter().any(|profile| profile.trim().is_empty()) {
+        bail!("--gpu needs the vGPU profile of the partition");
+    }
+    let mut mdevs = template.mdevs;
+    mdevs.extend(flags.mdev.iter().cloned());
+    for uuid in &mdevs {
+        uuid::Uuid::parse_str(uuid).with_context(|| format!("--mdev '{uuid}' is not a UUID"))?;
+    }
+    let mut passthrough = template.passthrough;
+    passthrough.extend(flags.passthrough.iter().cloned());
+
+    let image_ref = flags
+        .image_ref
+        .clone()
+        .or(template.image_ref)
+        .ok_or_else(|| anyhow!("An image reference is required (--image-ref or template)"))?;
+    let vcpus = flags.vcpus.or(template.vcpus).unwrap_or(DEFAULT_VCPUS);
+    let max_vcpus = flags.max_vcpus.or(template.max_vcpus).unwrap_or(vcpus);
+    let memory = flags
+        .memory
+        .or(template.memory)
+        .unwrap_or(DEFAULT_MEMORY_MIB);
+    let max_memory = flags.max_memory.or(template.max_memory).unwrap_or(memory);
+
+    if vcpus == 0 {
+        bail!("--vcpus must be at least 1");
+    }
+    if max_vcpus < vcpus {
+        bail!("--max-vcpus ({max_vcpus}) must not be lower than --vcpus ({vcpus})");
+    }
+    if memory == 0 {
+        bail!("--memory must be greater than 0");
+    }
+    if max_memory < memory {
+        bail!("--max-memory ({max_memory}) must not be lower than --memory ({memory})");
+    }
+    if let Some(vm_id) = &flags.vm_id {
+        uuid::Uuid::parse_str(vm_id).with_context(|| format!("--vm-id '{vm_id}' is not a UUID"))?;
+    }
+
+    let mut after = template.after;
+    after.extend(flags.after.iter().cloned());
+    let auto_start = flags.auto_start || template.auto_start;
+    let dependency_timeout_seconds = flags.dependency_timeout.or(template.dependency_timeout);
+    let startup =
+        (auto_start || !after.is_empty() || dependency_timeout_seconds.is_some()).then(|| {
+            StartupConfig {
+                auto_start,
+                after: after.into_iter().map(Into::into).collect(),
+                dependency_timeout_seconds,
+            }
+        });
+
+    let mut anti_affinity = template.anti_affinity;
+    anti_affinity.extend(flags.anti_affinity.iter().cloned());
+    for vm_id in &anti_affinity {
+        uuid::Uuid::parse_str(vm_id)
+            .with_context(|| format!("--anti-affinity '{vm_id}' is not a UUID"))?;
+    }
+    let numa_node = flags.numa_node.or(template.numa_node);
+    let placement_policy = flags.placement_policy.or(template.placement_policy);
+    let placement = (numa_node.is_some()
+        || placement_policy.is_some()
+        || !anti_affinity.is_empty())
+    .then(|| PlacementConfig {
+        policy: placement_policy.map_or(PlacementPolicy::Unspecified, PlacementPolicy::from) as i32,
+        numa_node,
+        anti_affinity,
+        assigned_numa_node: None,
+    });
+
+    let ignition = match flags.ignition.clone().or(template.ignition) {
+        Some(ignition) => Some(read_file_or_content(ignition).await?),
+        None => None,
+    };
+
+    let cloud_init = CloudInitConfig {
+        user_data: read_cloud_init_data(flags.user_data.clone().or(template.user_data)).await?,
+        meta_data: read_cloud_init_data(flags.meta_data.clone().or(template.meta_data)).await?,
+        network_config: read_cloud_init_data(
+            flags.network_config.clone().or(template.network_config),
+        )
+        .await?,
+        secrets: flags
+            .secret_file
+            .iter()
+            .map(|(name, path)| CloudInitSecret {
+                name: name.clone(),
+                path: path.clone(),
+            })
+            .collect(),
+    };
+    let cloud_init = (cloud_init != CloudInitConfig::default()).then_some(cloud_init);
+
+    let netboot = match (&flags.netboot_url, &flags.netboot_address) {
+        (Some(url), Some(address)) => Some(NetbootSpec {
+            url: url.clone(),
+            address: address.clone(),
+            nic: flags.netboot_nic.clone(),
+            params: flags.netboot_param.clone(),
+        }),
+        _ => template.netboot,
+    };
+    if let Some(netboot) = &netboot {
+        netboot
+            .address
+            .parse::<Ipv6Addr>()
+            .with_context(|| format!("Netboot address '{}' is not IPv6", netboot.address))?;
+    }
+
+    let config = VmConfig {
+        cpus: Some(CpuConfig {
+            boot_vcpus: vcpus,
+            max_vcpus,
+        }),
+        memory: Some(MemoryConfig {
+            size_mib: memory,
+            hugepages: flags.hugepages || template.hugepages,
+            swap_max_bytes: flags.swap_max,
+            hotplug_size_mib: max_memory - memory,
+            hotplugged_size_mib: 0,
+        }),
+        image_ref,
+        disks: disks
+            .iter()
+            .map(disk_config_from_spec)
+            .collect::<Result<_>>()?,
+        net: nics
+            .iter()
+            .map(net_config_from_spec)
+            .collect::<Result<_>>()?,
+        ignition,
+        clock: Some(ClockConfig {
+            hyperv: flags.hyperv_clock || template.hyperv_clock,
+            ptp_kvm: flags.ptp_kvm || template.ptp_kvm,
+        }),
+        drain_policy: flags
+            .drain_policy
+            .or(template.drain_policy)
+            .map_or(DrainPolicy::Unspecified, DrainPolicy::from) as i32,
+        startup,
+        gpus: gpus
+            .into_iter()
+            .map(|profile| GpuConfig {
+                profile,
+                ..Default::default()
+            })
+            .collect(),
+        mdevs: mdevs.into_iter().map(|uuid| MdevConfig { uuid }).collect(),
+        serial_port: flags
+            .serial_port
+            .clone()
+            .or(template.serial_port)
+            .map(|path| SerialPortConfig {
+                path,
+                baud_rate: flags
+                    .serial_baud_rate
+                    .or(template.serial_baud_rate)
+                    .unwrap_or(0),
+            }),
+        netboot: netboot.map(|netboot| NetbootConfig {
+            boot_url: netboot.url,
+            device_id: netboot.nic.unwrap_or_default(),
+            address: netboot.address,
+            boot_params: netboot.params,
+        }),
+        placement,
+        pci_devices: passthrough
+            .into_iter()
+            .map(|pci_address| PciDeviceConfig {
+                pci_address,
+                ..Default::default()
+            })
+            .collect(),
+        cloud_init,
+    };
+    validate_devices(&config)?;
+
+    Ok(CreateVmRequest {
+        config: Some(config),
+        vm_id: flags.vm_id.clone(),
+        namespace: flags.namespace.clone().unwrap_or_default(),
+        name: flags.name.clone(),
+    })
+}
+
+async fn read_file_or_content(value: String) -> Result<String> {
+    if tokio::fs::metadata(&value).await.is_ok() {
+        tokio::fs::read_to_string(&value)
+            .await
+            .with_context(|| format!("Failed to read {value}"))
+    } else {
+        Ok(value)
+    }
+}
+
+/// A cloud-init file or its content, empty if not given.
+async fn read_cloud_init_data(value: Option<String>) -> Result<String> {
+    match value {
+        Some(value) => read_file_or_content(value).await,
+        None => Ok(String::new()),
+    }
+}
+
+/// Splits `key=value,flag,...` into pairs. Bare words get an empty value.
+fn parse_spec_pairs(spec: &str) -> Vec<(&str, &str)> {
+    spec.split(',')
+        .filter(|part| !part.is_empty())
+        .map(|part| part.split_once('=').unwrap_or((part, "")))
+        .collect()
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool> {
+    match value {
+        "" | "true" => Ok(true),
+        "false" => Ok(false),
+        _ => bail!("Invalid value '{value}' for '{key}', expected true or false"),
+    }
+}
+
+fn parse_disk_spec(spec: &str) -> Result<DiskSpec> {
+    let mut disk = DiskSpec::default();
+    for (key, value) in parse_spec_pairs(spec) {
+        match key {
+            "path" => disk.path = Some(value.to_string()),
+            "format" => {
+                disk.format = Some(
+                    DiskFormatArg::from_str(value, false)
+                        .map_err(|_| anyhow!("Invalid format '{value}', expected raw or qcow2"))?,
+                )
+            }
+            "overlay" => disk.overlay = Some(value.to_string()),
+            "pci" => disk.pci = Some(value.to_string()),
+            "ephemeral" => disk.ephemeral = Some(value.to_string()),
+            "nvmeof" => disk.nvmeof = Some(value.to_string()),
+            "host-nqn" => disk.host_nqn = Some(value.to_string()),
+            "id" => disk.device_id = Some(value.to_string()),
+            "readonly" => disk.readonly = parse_bool(key, value)?,
+            "bus" => {
+                disk.bus = Some(DiskBusArg::from_str(value, false).map_err(|_| {
+                    anyhow!("Invalid bus '{value}', expected virtio-blk or virtio-scsi")
+                })?)
+            }
+            _ => bail!("Unknown key '{key}' in --disk '{spec}'"),
+        }
+    }
+    Ok(disk)
+}
+
+fn parse_nic_spec(spec: &str) -> Result<NicSpec> {
+    let mut nic = NicSpec::default();
+    for (key, value) in parse_spec_pairs(spec) {
+        match key {
+            "tap" => nic.tap = Some(value.to_string()),
+            "slaac" => nic.slaac = parse_bool(key, value)?,
+            "pci" => nic.pci = Some(value.to_string()),
+            "vhost-user" => nic.vhost_user = Some(value.to_string()),
+            "server" => nic.vhost_user_server = parse_bool(key, value)?,
+            "macvtap" => nic.macvtap = Some(value.to_string()),
+            "mode" => {
+                nic.macvtap_mode = Some(MacvtapModeArg::from_str(value, false).map_err(|_| {
+                    anyhow!("Invalid mode '{value}', expected bridge, vepa, private or passthru")
+                })?)
+            }
+            "mac" => nic.mac_address = Some(value.to_string()),
+            "id" => nic.device_id = Some(value.to_string()),
+            "bandwidth" => nic.bandwidth = Some(value.to_string()),
+            "pps" => {
+                nic.packets_per_second = Some(
+                    value
+                        .parse()
+                        .map_err(|_| anyhow!("Invalid packet rate '{value}'"))?,
+                )
+            }
+            _ => bail!("Unknown key '{key}' in --nic '{spec}'"),
+        }
+    }
+    Ok(nic)
+}
+
+/// Parses `[tcp|rdma://]address[:port]/nqn[/nsid]`, a namespace of an NVMe-oF
+/// subsystem. IPv6 addresses with a port go in brackets, the namespace
+/// defaults to 1.
+pub(super) fn parse_nvmeof(s: &str) -> Result<NvmeofConfig, String> {
+    let invalid = || format!("invalid [tcp|rdma://]address[:port]/nqn[/nsid] format: {s}");
+    let (transport, rest) = match s.split_once("://") {
+        Some(("tcp", rest)) => (NvmeofTransport::Tcp, rest),
+        Some(("rdma", rest)) => (NvmeofTransport::Rdma, rest),
+        Some(_) => return Err(invalid()),
+        None => (NvmeofTransport::Tcp, s),
+    };
+    let (host, rest) = rest.split_once('/').ok_or_else(invalid)?;
+    let (subsystem_nqn, nsid) = match rest.split_once('/') {
+        Some((nqn, nsid)) => (nqn, nsid.parse().map_err(|_| invalid())?),
+        None => (rest, 1),
+    };
+    let (address, port) = if let Some(bracketed) = host.strip_prefix('[') {
+        match bracketed.split_once(']') {
+            Some((address, "")) => (address, None),
+            Some((address, port)) => (address, Some(port.strip_prefix(':').ok_or_else(invalid)?)),
+            None => return Err(invalid()),
+        }
+    } else if host.matches(':').count() > 1 {
+        (host, None)
+    } else {
+        match host.split_once(':') {
+            Some((address, port)) => (address, Some(port)),
+            None => (host, None),
+        }
+    };
+    let port = match port {
+        Some(port) => port.parse::<u16>().map_err(|_| invalid())?.into(),
+        None => 0,
+    };
+    if address.is_empty() || subsystem_nqn.is_empty() {
+        return Err(invalid());
+    }
+    Ok(NvmeofConfig {
+        transport: transport as i32,
+        address: address.to_string(),
+        port,
+        subsystem_nqn: subsystem_nqn.to_string(),
+        nsid,
+        host_nqn: String::new(),
+    })
+}
+
+fn disk_config_from_spec(spec: &DiskSpec) -> Result<DiskConfig> {
+    if spec.format.is_some() && spec.path.is_none() {
+        bail!("Only disks given by path have a format");
+    }
+    if spec.host_nqn.is_some() && spec.nvmeof.is_none() {
+        bail!("host-nqn only applies to NVMe-oF disks");
+    }
+    let backend = match (
+        &spec.path,
+        &spec.overlay,
+        &spec.pci,
+        &spec.ephemeral,
+        &spec.nvmeof,
+    ) {
+        (Some(path), None, None, None, None) if !path.is_empty() => {
+            disk_config::Backend::Path(path.clone())
+        }
+        (None, Some(base_path), None, None, None) if !base_path.is_empty() => {
+            disk_config::Backend::Overlay(OverlayDiskConfig {
+                base_path: base_path.clone(),
+                overlay_path: String::new(),
+            })
+        }
+        (None, None, None, None, Some(nvmeof)) => {
+            let subsystem = parse_nvmeof(nvmeof).map_err(|e| anyhow!(e))?;
+            disk_config::Backend::Nvmeof(NvmeofConfig {
+                host_nqn: spec.host_nqn.clone().unwrap_or_default(),
+                ..subsystem
+            })
+        }
+        (None, None, Some(bdf), None, None) => {
+            validate_bdf(bdf)?;
+            if spec.readonly {
+                bail!("PCI passthrough disk {bdf} cannot be read-only");
+            }
+            disk_config::Backend::VfioPci(VfioPciConfig { bdf: bdf.clone() })
+        }
+        (None, None, None, Some(size), None) => {
+            let size_bytes = parse_size(size).map_err(|e| anyhow!(e))?;
+            if spec.readonly {
+                bail!("Ephemeral disk cannot be read-only");
+            }
+            disk_config::Backend::Ephemeral(EphemeralDiskConfig { size_bytes })
+        }
+        _ => bail!(
+            "Each disk needs exactly one of a non-empty path, overlay, pci, ephemeral or nvmeof"
+        ),
+    };
+    Ok(DiskConfig {
+        device_id: spec.device_id.clone().unwrap_or_default(),
+        backend: Some(backend),
+        readonly: spec.readonly,
+        bus: spec.bus.map(DiskBus::from).unwrap_or_default() as i32,
+        format: spec.format.map(DiskFormat::from).unwrap_or_default() as i32,
+        ..Default::default()
+    })
+}
+
+/// The limits of a NIC, none if it is not limited.
+pub(super) fn net_rate_limit(
+    bandwidth: Option<u64>,
+    packets_per_second: Option<u64>,
+) -> Option<NetRateLimit> {
+    (bandwidth.is_some() || packets_per_second.is_some()).then(|| NetRateLimit {
+        bandwidth_bytes_per_second: bandwidth.unwrap_or_default(),
+        packets_per_second: packets_per_second.unwrap_or_default(),
+        ..Default::default()
+    })
+}
+
+fn net_config_from_spec(spec: &NicSpec) -> Result<NetConfig> {
+    let backend = match (&spec.tap, &spec.pci, &spec.vhost_user, &spec.macvtap) {
+        (Some(tap), None, None, None) if !tap.is_empty() => net_config::Backend::Tap(TapConfig {
+            tap_name: tap.clone(),
+            slaac: spec.slaac,
+        }),
+        (None, Some(bdf), None, None) => {
+            validate_bdf(bdf)?;
+            net_config::Backend::VfioPci(VfioPciConfig { bdf: bdf.clone() })
+        }
+        (None, None, Some(socket_path), None) if !socket_path.is_empty() => {
+            net_config::Backend::VhostUser(VhostUserNetConfig {
+                socket_path: socket_path.clone(),
+                server: spec.vhost_user_server,
+            })
+        }
+        (None, None, None, Some(parent)) if !parent.is_empty() => {
+            net_config::Backend::Macvtap(MacvtapConfig {
+                parent: parent.clone(),
+                name: String::new(),
+                mode: spec.macvtap_mode.map(MacvtapMode::from).unwrap_or_default() as i32,
+            })
+        }
+        _ => bail!("Each NIC needs exactly one of a non-empty tap, pci, vhost-user or macvtap"),
+    };
+    if spec.vhost_user_server && spec.vhost_user.is_none() {
+        bail!("Only vhost-user NICs can be a server");
+    }
+    if spec.macvtap_mode.is_some() && spec.macvtap.is_none() {
+        bail!("Only macvtap NICs have a mode");
+    }
+    if spec.slaac && spec.tap.is_none() {
+        bail!("Only tap NICs can use SLAAC");
+    }
+    if let Some(mac) = &spec.mac_address {
+        validate_mac(mac)?;
+    }
+    let bandwidth = spec
+        .bandwidth
+        .as_deref()
+        .map(parse_size)
+        .transpose()
+        .map_err(|e| anyhow!(e))?;
+    let rate_limit = net_rate_limit(bandwidth, spec.packets_per_second);
+    if rate_limit.is_some() && spec.tap.is_none() && spec.macvtap.is_none() {
+        bail!("Only tap and macvtap NICs can be rate limited");
+    }
+    Ok(NetConfig {
+        device_id: spec.device_id.clone().unwrap_or_default(),
+        backend: Some(backend),
+        mac_address: spec.mac_address.clone().unwrap_or_default(),
+        rate_limit,
+    })
+}
+
+/// Rejects device IDs and PCI devices that are used more than once.
+fn validate_devices(config: &VmConfig) -> Result<()> {
+    let mut device_ids = HashSet::new();
+    let mut bdfs = HashSet::new();
+    let disks = config.disks.iter().map(|disk| {
+        let bdf = match &disk.backend {
+            Some(disk_config::Backend::VfioPci(pci)) => Some(pci.bdf.as_str()),
+            _ => None,
+        };
+        (disk.device_id.as_str(), bdf)
+    });
+    let nics = config.net.iter().map(|nic| {
+        let bdf = match &nic.backend {
+            Some(net_config::Backend::VfioPci(pci)) => Some(pci.bdf.as_str()),
+            _ => None,
+        };
+        (nic.device_id.as_str(), bdf)
+    });
+    let passthrough = config
+        .pci_devices
+        .iter()
+        .map(|device| (device.device_id.as_str(), Some(device.pci_address.as_str())));
+
+    for (device_id, bdf) in disks.chain(nics).chain(passthrough) {
+        if !device_id.is_empty() && !device_ids.insert(device_id) {
+            bail!("Device ID '{device_id}' is used more than once");
+        }
+        if let Some(bdf) = bdf {
+            if !bdfs.insert(bdf) {
+                bail!("PCI device {bdf} is assigned more than once");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks for the `DDDD:BB:DD.F` form, e.g. `0000:03:00.0`.
+fn validate_bdf(bdf: &str) -> Result<()> {
+    let hex = |s: &str, len: usize| s.len() == len && s.chars().all(|c| c.is_ascii_hexdigit());
+    let valid = match bdf.split(':').collect::<Vec<_>>()[..] {
+        [domain, bus, device_function] => match device_function.split_once('.') {
+            Some((device, function)) => {
+                hex(domain, 4)
+                    && hex(bus, 2)
+                    && hex(device, 2)
+                    && function.len() == 1
+                    && matches!(function.as_bytes()[0], b'0'..=b'7')
+            }
+            None => false,
+        },
+        _ => false,
+    };
+    if !valid {
+        bail!("Invalid PCI address '{bdf}', expected the form 0000:03:00.0");
+    }
+    Ok(())
+}
+
+fn validate_mac(mac: &str) -> Result<()> {
+    let octets: Vec<&str> = mac.split(':').collect();
+    let valid = octets.len() == 6
+        && octets
+            .iter()
+            .all(|octet| octet.len() == 2 && octet.chars().all(|c| c.is_ascii_hexdigit()));
+    if !valid {
+        bail!("Invalid MAC address '{mac}', expected the form 52:54:00:12:34:56");
+    }
+    Ok(())
+}
+
+/// Prints the request as JSON, using the proto field names.
+pub fn print_request(request: &CreateVmRequest) -> Result<()> {
+    let config = request.config.clone().unwrap_or_default();
+    let disks: Vec<_> = config
+        .disks
+        .iter()
+        .map(|disk| {
+            let backend = match &disk.backend {
+                Some(disk_config::Backend::Path(path)) => json!({ "path": path }),
+                Some(disk_config::Backend::VfioPci(pci)) => vfio_pci_json(pci),
+                Some(disk_config::Backend::Iscsi(target)) => json!({
+                    "iscsi": {
+                        "portals": target.portals,
+                        "target_iqn": target.target_iqn,
+                        "lun": target.lun,
+                    }
+                }),
+                Some(disk_config::Backend::Nvmeof(subsystem)) => json!({
+                    "nvmeof": {
+                        "transport": subsystem.transport().as_str_name(),
+                        "address": subsystem.address,
+                        "port": subsystem.port,
+                        "subsystem_nqn": subsystem.subsystem_nqn,
+                        "nsid": subsystem.nsid,
+                        "host_nqn": subsystem.host_nqn,
+                    }
+                }),
+                Some(disk_config::Backend::Rbd(image)) => json!({
+                    "rbd": {
+                        "image_spec": image.image_spec,
+                        "mon_hosts": image.mon_hosts,
+                        "user": image.user,
+                    }
+                }),
+                Some(disk_config::Backend::VhostUserBlk(vhost_user_blk)) => json!({
+                    "vhost_user_blk": { "socket_path": vhost_user_blk.socket_path }
+                }),
+                Some(disk_config::Backend::Ephemeral(config)) => json!({
+                    "ephemeral": { "size_bytes": config.size_bytes }
+                }),
+                Some(disk_config::Backend::Overlay(config)) => json!({
+                    "overlay": { "base_path": config.base_path }
+                }),
+                None => json!(null),
+            };
+            json!({
+                "device_id": disk.device_id,
+                "backend": backend,
+                "readonly": disk.readonly,
+                "bus": disk.bus().as_str_name(),
+                "discard": disk.discard,
+                "format": disk.format().as_str_name(),
+            })
+        })
+        .collect();
+    let nics: Vec<_> = config
+        .net
+        .iter()
+        .map(|nic| {
+            let backend = match &nic.backend {
+                Some(net_config::Backend::Tap(tap)) => {
+                    json!({ "tap": { "tap_name": tap.tap_name, "slaac": tap.slaac } })
+                }
+                Some(net_config::Backend::VfioPci(pci)) => vfio_pci_json(pci),
+                Some(net_config::Backend::VhostUser(vhost_user)) => json!({
+                    "vhost_user": {
+                        "socket_path": vhost_user.socket_path,
+                        "server": vhost_user.server,
+                    }
+                }),
+                Some(net_config::Backend::Macvtap(macvtap)) => json!({
+                    "macvtap": {
+                        "parent": macvtap.parent,
+                        "name": macvtap.name,
+                        "mode": macvtap.mode().as_str_name(),
+                    }
+                }),
+                None => json!(null),
+            };
+            let rate_limit = nic.rate_limit.as_ref().map(|limit| {
+                json!({
+                    "bandwidth_bytes_per_second": limit.bandwidth_bytes_per_second,
+                    "packets_per_second": limit.packets_per_second,
+                })
+            });
+            json!({
+                "device_id": nic.device_id,
+                "backend": backend,
+                "mac_address": nic.mac_address,
+                "rate_limit": rate_limit,
+            })
+        })
+        .collect();
+    let gpus: Vec<_> = config
+        .gpus
+        .iter()
+        .map(|gpu| json!({ "profile": gpu.profile, "pci_address": gpu.pci_address }))
+        .collect();
+
+    let drain_policy = config.drain_policy();
+    let output = json!({
+        "vm_id": request.vm_id,
+        "namespace": request.namespace,
+        "name": request.name,
+        "config": {
+            "cpus": config.cpus.map(|cpus| json!({
+                "boot_vcpus": cpus.boot_vcpus,
+                "max_vcpus": cpus.max_vcpus,
+            })),
+            "memory": config.memory.map(|memory| json!({
+                "size_mib": memory.size_mib,
+                "hugepages": memory.hugepages,
+                "swap_max_bytes": memory.swap_max_bytes,
+                "hotplug_size_mib": memory.hotplug_size_mib,
+            })),
+            "image_ref": config.image_ref,
+            "disks": disks,
+            "net": nics,
+            "gpus": gpus,
+            "mdevs": config.mdevs.iter().map(|mdev| &mdev.uuid).collect::<Vec<_>>(),
+            "pci_devices": config
+                .pci_devices
+                .iter()
+                .map(|device| &device.pci_address)
+                .collect::<Vec<_>>(),
+            "serial_port": config.serial_port.map(|port| json!({
+                "path": port.path,
+                "baud_rate": port.baud_rate,
+            })),
+            "ignition": config.ignition,
+            "cloud_init": config.cloud_init.map(|cloud_init| json!({
+                "user_data": cloud_init.user_data,
+                "meta_data": cloud_init.meta_data,
+                "network_config": cloud_init.network_config,
+                "secrets": cloud_init.secrets.iter().map(|secret| json!({
+                    "name": secret.name,
+                    "path": secret.path,
+                })).collect::<Vec<_>>(),
+            })),
+            "netboot": config.netboot.map(|netboot| json!({
+                "boot_url": netboot.boot_url,
+                "device_id": netboot.device_id,
+                "address": netboot.address,
+                "boot_params": netboot.boot_params,
+            })),
+            "drain_policy": drain_policy.as_str_name(),
+            "startup": config.startup.map(startup_json),
+            "placement": config.placement.map(|placement| json!({
+                "policy": placement.policy().as_str_name(),
+                "numa_node": placement.numa_node,
+                "anti_affinity": placement.anti_affinity,
+            })),
+        },
+    });
+    println!("{}", serde_json::to_string_pretty(&output)?);
+    Ok(())
+}
+
+fn vfio_pci_json(pci: &VfioPciConfig) -> serde_json::Value {
+    json!({ "vfio_pci": { "bdf": pci.bdf } })
+}
+
+fn startup_json(startup: StartupConfig) -> serde_json::Value {
+    let after: Vec<_> = startup
+        .after
+        .iter()
+        .map(|dependency| match &dependency.workload {
+            Some(startup_dependency::Workload::VmId(id)) => json!({ "vm_id": id }),
+            Some(startup_dependency::Workload::ContainerId(id)) => json!({ "container_id": id }),
+            None => json!(null),
+        })
+        .collect();
+    json!({
+        "auto_start": startup.auto_start,
+        "after": after,
+        "dependency_timeout_seconds": startup.dependency_timeout_seconds,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_device_specs() {
+        assert_eq!(
+            parse_disk_spec("path=/var/lib/feos/data.img,id=data0,readonly").unwrap(),
+            DiskSpec {
+                path: Some("/var/lib/feos/data.img".to_string()),
+                format: None,
+                overlay: None,
+                pci: None,
+                ephemeral: None,
+                nvmeof: None,
+                host_nqn: None,
+                device_id: Some("data0".to_string()),
+                readonly: true,
+                bus: None,
+            }
+        );
+        let qcow2 = parse_disk_spec("path=/var/lib/feos/data.qcow2,format=qcow2").unwrap();
+        assert_eq!(
+            disk_config_from_spec(&qcow2).unwrap().format(),
+            DiskFormat::Qcow2
+        );
+        assert!(parse_disk_spec("ephemeral=2G,format=qcow2")
+            .and_then(|spec| disk_config_from_spec(&spec))
+            .is_err());
+        let scratch = parse_disk_spec("ephemeral=2G,id=scratch0").unwrap();
+        assert_eq!(
+            disk_config_from_spec(&scratch).unwrap().backend,
+            Some(disk_config::Backend::Ephemeral(EphemeralDiskConfig {
+                size_bytes: 2 << 30
+            }))
+        );
+        assert!(parse_disk_spec("ephemeral=2G,readonly")
+            .and_then(|spec| disk_config_from_spec(&spec))
+            .is_err());
+        let overlay = parse_disk_spec("overlay=/var/lib/feos/base.img,id=root").unwrap();
+        assert_eq!(
+            disk_config_from_spec(&overlay).unwrap().backend,
+            Some(disk_config::Backend::Overlay(OverlayDiskConfig {
+                base_path: "/var/lib/feos/base.img".to_string(),
+                overlay_path: String::new(),
+            }))
+        );
+        assert!(parse_disk_spec("overlay=/a.img,path=/b.img")
+            .and_then(|spec| disk_config_from_spec(&spec))
+            .is_err());
+        assert_eq!(
+            parse_disk_spec("path=/a.img,bus=virtio-scsi").unwrap().bus,
+            Some(DiskBusArg::VirtioScsi)
+        );
+        assert_eq!(
+            parse_nic_spec("tap=tap0,mac=52:54:00:12:34:56").unwrap(),
+            NicSpec {
+                tap: Some("tap0".to_string()),
+                slaac: false,
+                pci: None,
+                vhost_user: None,
+                vhost_user_server: false,
+                macvtap: None,
+                macvtap_mode: None,
+                mac_address: Some("52:54:00:12:34:56".to_string()),
+                device_id: None,
+                bandwidth: None,
+                packets_per_second: None,
+            }
+        );
+        let macvtap = parse_nic_spec("macvtap=eth0,mode=private,bandwidth=125M,pps=20000")
+            .and_then(|spec| net_config_from_spec(&spec))
+            .unwrap();
+        assert_eq!(
+            macvtap.backend,
+            Some(net_config::Backend::Macvtap(MacvtapConfig {
+                parent: "eth0".to_string(),
+                name: String::new(),
+                mode: MacvtapMode::Private as i32,
+            }))
+        );
+        assert_eq!(
+            macvtap.rate_limit,
+            Some(NetRateLimit {
+                bandwidth_bytes_per_second: 125 << 20,
+                packets_per_second: 20000,
+                ..Default::default()
+            })
+        );
+        assert!(parse_nic_spec("vhost-user=/run/dp/vm1.sock,pps=100")
+            .and_then(|spec| net_config_from_spec(&spec))
+            .is_err());
+        assert!(parse_nic_spec("tap=tap0,mode=vepa")
+            .and_then(|spec| net_config_from_spec(&spec))
+            .is_err());
+        let vhost_user = parse_nic_spec("vhost-user=/run/dp/vm1.sock,server")
+            .and_then(|spec| net_config_from_spec(&spec))
+            .unwrap();
+        assert_eq!(
+            vhost_user.backend,
+            Some(net_config::Backend::VhostUser(VhostUserNetConfig {
+                socket_path: "/run/dp/vm1.sock".to_string(),
+                server: true,
+            }))
+        );
+        assert!(parse_nic_spec("tap=tap0,server")
+            .and_then(|spec| net_config_from_spec(&spec))
+            .is_err());
+        assert_eq!(
+            parse_nic_spec("tap=tap0,slaac")
+                .and_then(|spec| net_config_from_spec(&spec))
+                .unwrap()
+                .backend,
+            Some(net_config::Backend::Tap(TapConfig {
+                tap_name: "tap0".to_string(),
+                slaac: true,
+            }))
+        );
+        assert!(parse_nic_spec("macvtap=eth0,slaac")
+            .and_then(|spec| net_config_from_spec(&spec))
+            .is_err());
+        assert!(parse_disk_spec("path=/a.img,size=10G").is_err());
+        assert!(parse_disk_spec("path=/a.img,readonly=maybe").is_err());
+        assert!(parse_disk_spec("path=/a.img,bus=ide").is_err());
+    }
+
+    #[test]
+    fn nvmeof_namespaces_default_to_tcp_and_the_first_namespace() {
+        assert_eq!(
+            parse_nvmeof("10.0.0.1/nqn.2014-08.org.example:vol1"),
+            Ok(NvmeofConfig {
+                transport: NvmeofTransport::Tcp as i32,
+                address: "10.0.0.1".to_string(),
+                subsystem_nqn: "nqn.2014-08.org.example:vol1".to_string(),
+                nsid: 1,
+                ..Default::default()
+            })
+        );
+        let rdma = parse_nvmeof("rdma://[fd00::1]:4421/nqn.2014-08.org.example:vol1/3").unwrap();
+        assert_eq!(rdma.transport(), NvmeofTransport::Rdma);
+        assert_eq!(
+            (rdma.address.as_str(), rdma.port, rdma.nsid),
+            ("fd00::1", 4421, 3)
+        );
+        assert_eq!(
+            parse_nvmeof("fd00::1/nqn.2014-08.org.example:vol1")
+                .unwrap()
+                .address,
+            "fd00::1"
+        );
+        for invalid in [
+            "10.0.0.1",
+            "fc://10.0.0.1/nqn.2014-08.org.example:vol1",
+            "10.0.0.1:port/nqn.2014-08.org.example:vol1",
+            "10.0.0.1/nqn.2014-08.org.example:vol1/first",
+            "/nqn.2014-08.org.example:vol1",
+        ] {
+            assert!(parse_nvmeof(invalid).is_err(), "{invalid} was accepted");
+        }
+    }
+
+    #[test]
+    fn test_validate_addresses() {
+        assert!(validate_bdf("0000:03:00.0").is_ok());
+        assert!(validate_bdf("0000:af:1f.7").is_ok());
+        assert!(validate_bdf("03:00.0").is_err());
+        assert!(validate_bdf("0000:03:00.8").is_err());
+        assert!(validate_mac("52:54:00:ab:CD:ef").is_ok());
+        assert!(validate_mac("52:54:00:ab:cd").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_build_request_rejects_duplicate_devices() {
+        let flags = CreateVmFlags {
+            template: None,
+            image_ref: Some("ghcr.io/ironcore-dev/os-images/gardenlinux:latest".to_string()),
+            vcpus: Some(2),
+            max_vcpus: None,
+            memory: None,
+            max_memory: None,
+            vm_id: None,
+            namespace: None,
+            name: None,
+            disk: vec!["pci=0000:03:00.0".to_string()],
+            nic: vec![],
+            pci_device: vec!["0000:03:00.0".to_string()],
+            gpu: vec![],
+            mdev: vec![],
+            passthrough: vec![],
+            serial_port: None,
+            serial_baud_rate: None,
+            hugepages: false,
+            swap_max: None,
+            ignition: None,
+            user_data: None,
+            meta_data: None,
+            network_config: None,
+            secret_file: vec![],
+            netboot_url: None,
+            netboot_address: None,
+            netboot_nic: None,
+            netboot_param: vec![],
+            hyperv_clock: false,
+            ptp_kvm: false,
+            drain_policy: None,
+            numa_node: None,
+            placement_policy: None,
+            anti_affinity: vec![],
+            auto_start: false,
+            after: vec![],
+            dependency_timeout: None,
+            dry_run: true,
+        };
+        let err = build_create_request(&flags).await.unwrap_err();
+        assert!(err.to_string().contains("assigned more than once"));
+
+        let flags = CreateVmFlags {
+            pci_device: vec![],
+            ..flags
+        };
+        let request = build_create_request(&flags).await.unwrap();
+        let config = request.config.unwrap();
+        assert_eq!(config.cpus.unwrap().max_vcpus, 2);
+        assert_eq!(config.memory.unwrap().size_mib, DEFAULT_MEMORY_MIB);
+        assert_eq!(config.disks.len(), 1);
+        assert_eq!(config.startup, None);
+        assert_eq!(config.cloud_init, None);
+
+        let flags = CreateVmFlags {
+            user_data: Some("#cloud-config\nhostname: web-1\n".to_string()),
+            ..flags
+        };
+        let cloud_init = build_create_request(&flags)
+            .await
+            .unwrap()
+            .config
+            .unwrap()
+            .cloud_init
+            .unwrap();
+        assert_eq!(cloud_init.user_data, "#cloud-config\nhostname: web-1\n");
+        assert_eq!(cloud_init.meta_data, "");
+
+        let db = "0b5c1f0e-3f43-4b0e-9c1e-7d0b8f4a2e11";
+        let flags = CreateVmFlags {
+            auto_start: true,
+            after: vec![format!("container:{db}").parse().unwrap()],
+            ..flags
+        };
+        let startup = build_create_request(&flags)
+            .await
+            .unwrap()
+            .config
+            .unwrap()
+            .startup
+            .unwrap();
+        assert!(startup.auto_start);
+        assert_eq!(
+            startup.after[0].workload,
+            Some(startup_dependency::Workload::ContainerId(db.to_string()))
+        );
+        assert!("pod:x".parse::<StartupAfterArg>().is_err());
+        assert!("vm:not-a-uuid".parse::<StartupAfterArg>().is_err());
+    }
+}
@@ -0,0 +1,113 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::Channel;
+use anyhow::Result;
+use clap::Subcommand;
+use feos_proto::vm_service::{
+    vm_service_client::VmServiceClient, DeleteDiskReplicasRequest, ListDiskReplicasRequest,
+    ReplicateVmDisksRequest,
+};
+
+#[derive(Subcommand, Debug)]
+pub enum ReplicaCommand {
+    /// Replicate the disks of a VM to a peer node, sending the blocks changed since the last time
+    Send {
+        #[arg(long, required = true, help = "VM identifier")]
+        vm_id: String,
+
+        #[arg(
+            long,
+            required = true,
+            help = "Public API of the peer, e.g. https://[2001:db8::2]:1337"
+        )]
+        peer: String,
+
+        #[arg(
+            long = "disk",
+            help = "Only replicate this disk (device ID, 'rootfs' for the root filesystem). Can be repeated"
+        )]
+        disks: Vec<String>,
+    },
+    /// List the replicas this node keeps for other nodes
+    List {
+        #[arg(long, help = "Only list the replicas of the disks of this VM")]
+        vm_id: Option<String>,
+    },
+    /// Delete the replicas of the disks of a VM
+    Delete {
+        #[arg(long, required = true, help = "VM identifier")]
+        vm_id: String,
+    },
+}
+
+pub async fn handle_replica_command(
+    client: &mut VmServiceClient<Channel>,
+    command: ReplicaCommand,
+) -> Result<()> {
+    match command {
+        ReplicaCommand::Send { vm_id, peer, disks } => {
+            let request = ReplicateVmDisksRequest {
+                vm_id,
+                peer: peer.clone(),
+                device_ids: disks,
+            };
+            let response = client.replicate_vm_disks(request).await?.into_inner();
+            for disk in response.disks {
+                println!(
+                    "Replicated disk '{}' to {peer}: {} of {} bytes sent{}",
+                    disk.device_id,
+                    disk.bytes_sent,
+                    disk.size_bytes,
+                    if disk.full { " (full)" } else { "" }
+                );
+            }
+        }
+        ReplicaCommand::List { vm_id } => {
+            let request = ListDiskReplicasRequest { vm_id };
+            let response = client.list_disk_replicas(request).await?.into_inner();
+            if response.replicas.is_empty() {
+                println!("No replicas found.");
+                return Ok(());
+            }
+
+            println!(
+                "{:<38} {:<20} {:<14} {:<20} {:<16} PATH",
+                "VM_ID", "DISK", "SIZE", "UPDATED", "SOURCE_NODE"
+            );
+            println!(
+                "{:-<38} {:-<20} {:-<14} {:-<20} {:-<16} {:-<20}",
+                "", "", "", "", "", ""
+            );
+            for replica in response.replicas {
+                let updated = replica
+                    .updated_at
+                    .and_then(|t| chrono::DateTime::from_timestamp(t.seconds, 0))
+                    .map(|t| {
+                        t.with_timezone(&chrono::Local)
+                            .format("%Y-%m-%d %H:%M:%S")
+                            .to_string()
+                    })
+                    .unwrap_or_default();
+                let source: String = replica.source_node_id.chars().take(16).collect();
+                println!(
+                    "{:<38} {:<20} {:<14} {:<20} {:<16} {}",
+                    replica.vm_id,
+                    replica.device_id,
+                    replica.size_bytes,
+                    updated,
+                    source,
+                    replica.path
+                );
+            }
+        }
+        ReplicaCommand::Delete { vm_id } => {
+            let request = DeleteDiskReplicasRequest {
+                vm_id: vm_id.clone(),
+            };
+            client.delete_disk_replicas(request).await?;
+            println!("Deleted the replicas of VM {vm_id}");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,147 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::Channel;
+use anyhow::Result;
+use clap::Subcommand;
+use feos_proto::vm_service::{
+    vm_service_client::VmServiceClient, CreateVmSnapshotRequest, DeleteVmSnapshotRequest,
+    ListVmSnapshotsRequest, RevertVmSnapshotRequest,
+};
+
+#[derive(Subcommand, Debug)]
+pub enum SnapshotCommand {
+    /// Snapshot the disks and, optionally, the memory of a VM
+    Create {
+        #[arg(long, required = true, help = "VM identifier")]
+        vm_id: String,
+
+        #[arg(long, default_value = "", help = "Name of the snapshot")]
+        name: String,
+
+        #[arg(
+            long = "disk",
+            help = "Only snapshot this disk (device ID, 'rootfs' for the root filesystem). Can be repeated"
+        )]
+        disks: Vec<String>,
+
+        #[arg(
+            long,
+            conflicts_with = "disks",
+            help = "Also save memory and device state, so reverting resumes the guest where it was"
+        )]
+        memory: bool,
+    },
+    /// List the snapshots of a VM
+    List {
+        #[arg(long, required = true, help = "VM identifier")]
+        vm_id: String,
+    },
+    /// Revert a VM to a snapshot
+    Revert {
+        #[arg(long, required = true, help = "VM identifier")]
+        vm_id: String,
+
+        #[arg(required = true, help = "Snapshot identifier")]
+        snapshot_id: String,
+
+        #[arg(
+            long = "disk",
+            help = "Only revert this disk and leave memory untouched. Can be repeated"
+        )]
+        disks: Vec<String>,
+    },
+    /// Delete a snapshot
+    Delete {
+        #[arg(long, required = true, help = "VM identifier")]
+        vm_id: String,
+
+        #[arg(required = true, help = "Snapshot identifier")]
+        snapshot_id: String,
+    },
+}
+
+pub async fn handle_snapshot_command(
+    client: &mut VmServiceClient<Channel>,
+    command: SnapshotCommand,
+) -> Result<()> {
+    match command {
+        SnapshotCommand::Create {
+            vm_id,
+            name,
+            disks,
+            memory,
+        } => {
+            let request = CreateVmSnapshotRequest {
+                vm_id,
+                name,
+                device_ids: disks,
+                include_memory: memory,
+            };
+            let response = client.create_vm_snapshot(request).await?.into_inner();
+            println!("{}", response.snapshot_id);
+        }
+        SnapshotCommand::List { vm_id } => {
+            let request = ListVmSnapshotsRequest { vm_id };
+            let response = client.list_vm_snapshots(request).await?.into_inner();
+            if response.snapshots.is_empty() {
+                println!("No snapshots found.");
+                return Ok(());
+            }
+
+            println!(
+                "{:<38} {:<20} {:<20} {:<7} DISKS",
+                "SNAPSHOT_ID", "NAME", "CREATED", "MEMORY"
+            );
+            println!("{:-<38} {:-<20} {:-<20} {:-<7} {:-<20}", "", "", "", "", "");
+            for snapshot in response.snapshots {
+                let created = chrono::DateTime::from_timestamp(snapshot.created_at, 0)
+                    .map(|t| {
+                        t.with_timezone(&chrono::Local)
+                            .format("%Y-%m-%d %H:%M:%S")
+                            .to_string()
+                    })
+                    .unwrap_or_default();
+                let disks: Vec<_> = snapshot
+                    .disks
+                    .iter()
+                    .map(|disk| disk.device_id.as_str())
+                    .collect();
+                println!(
+                    "{:<38} {:<20} {:<20} {:<7} {}",
+                    snapshot.snapshot_id,
+                    snapshot.name,
+                    created,
+                    if snapshot.includes_memory {
+                        "yes"
+                    } else {
+                        "no"
+                    },
+                    disks.join(",")
+                );
+            }
+        }
+        SnapshotCommand::Revert {
+            vm_id,
+            snapshot_id,
+            disks,
+        } => {
+            let request = RevertVmSnapshotRequest {
+                vm_id: vm_id.clone(),
+                snapshot_id: snapshot_id.clone(),
+                device_ids: disks,
+            };
+            client.revert_vm_snapshot(request).await?;
+            println!("Reverted VM {vm_id} to snapshot {snapshot_id}");
+        }
+        SnapshotCommand::Delete { vm_id, snapshot_id } => {
+            let request = DeleteVmSnapshotRequest {
+                vm_id,
+                snapshot_id: snapshot_id.clone(),
+            };
+            client.delete_vm_snapshot(request).await?;
+            println!("Deleted snapshot {snapshot_id}");
+        }
+    }
+    Ok(())
+}
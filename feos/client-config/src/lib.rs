@@ -0,0 +1,202 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! The contexts file of feos-cli and feos-tui, which names the FeOS hosts
+//! they connect to.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tonic::transport::{Certificate, ClientTlsConfig, Endpoint, Identity};
+
+pub const DEFAULT_ADDRESS: &str = "http://[::1]:1337";
+
+/// Client configuration stored in `$FEOS_CONFIG`, `$XDG_CONFIG_HOME/feos/config.yaml`
+/// or `~/.config/feos/config.yaml`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ClientConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_context: Option<String>,
+    #[serde(default)]
+    pub contexts: Vec<HostContext>,
+}
+
+/// A named FeOS endpoint together with the TLS settings and API token needed
+/// to reach it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct HostContext {
+    pub name: String,
+    pub address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+    /// File with the token requests are signed with, for hosts started with
+    /// FEOS_API_TOKEN_FILE.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TlsConfig {
+    /// CA bundle used to verify the server. The system roots are used if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_cert: Option<PathBuf>,
+    /// Client certificate for mutual TLS. Requires `client_key`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_cert: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_key: Option<PathBuf>,
+    /// Overrides the server name checked against the certificate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain_name: Option<String>,
+}
+
+impl ClientConfig {
+    pub fn path() -> Result<PathBuf> {
+        if let Some(path) = std::env::var_os("FEOS_CONFIG") {
+            return Ok(PathBuf::from(path));
+        }
+        let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => std::env::var_os("HOME")
+                .map(|home| PathBuf::from(home).join(".config"))
+                .ok_or_else(|| anyhow!("Neither FEOS_CONFIG, XDG_CONFIG_HOME nor HOME is set"))?,
+        };
+        Ok(config_dir.join("feos").join("config.yaml"))
+    }
+
+    /// Loads the client configuration. A missing file yields an empty configuration.
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+        Self::load_from(&path)
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => serde_yaml::from_str(&content)
+                .with_context(|| format!("Failed to parse client config {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => {
+                Err(e).with_context(|| format!("Failed to read client config {}", path.display()))
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let content = serde_yaml::to_string(self).context("Failed to serialize client config")?;
+        std::fs::write(&path, content)
+            .with_context(|| format!("Failed to write client config {}", path.display()))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&HostContext> {
+        self.contexts.iter().find(|ctx| ctx.name == name)
+    }
+
+    /// Inserts a context or replaces the one with the same name.
+    pub fn upsert(&mut self, context: HostContext) {
+        match self
+            .contexts
+            .iter_mut()
+            .find(|ctx| ctx.name == context.name)
+        {
+            Some(existing) => *existing = context,
+            None => self.contexts.push(context),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<HostContext> {
+        let index = self.contexts.iter().position(|ctx| ctx.name == name)?;
+        if self.current_context.as_deref() == Some(name) {
+            self.current_context = None;
+        }
+        Some(self.contexts.remove(index))
+    }
+}
+
+/// Resolves the endpoint a command talks to.
+///
+/// An explicit `--address` (or `FEOS_ADDRESS`) wins over the context's address but keeps
+/// its TLS settings and token. Without a `--context`, the config's current context is used,
+/// falling back to the local daemon.
+pub fn resolve(address: Option<&str>, context: Option<&str>) -> Result<HostContext> {
+    let config = ClientConfig::load()?;
+    let selected = match context {
+        Some(name) => Some(config.get(name).cloned().ok_or_else(|| {
+            anyhow!("Context '{name}' not found. Use 'feos-cli context list' to see contexts")
+        })?),
+        None => config
+            .current_context
+            .as_deref()
+            .and_then(|name| config.get(name).cloned()),
+    };
+
+    let mut target = selected.unwrap_or_else(|| HostContext {
+        name: "default".to_string(),
+        address: DEFAULT_ADDRESS.to_string(),
+        tls: None,
+        token_file: None,
+    });
+    if let Some(address) = address {
+        target.address = address.to_string();
+    }
+    Ok(target)
+}
+
+impl HostContext {
+    pub fn endpoint(&self) -> Result<Endpoint> {
+        let mut endpoint = Endpoint::from_shared(self.address.clone())
+            .with_context(|| format!("Invalid address '{}'", self.address))?;
+        if let Some(tls) = &self.tls {
+            endpoint = endpoint
+                .tls_config(tls.client_tls_config()?)
+                .context("Failed to apply TLS configuration")?;
+        }
+        Ok(endpoint)
+    }
+
+    /// The API token requests are signed with, if the host needs one.
+    pub fn token(&self) -> Result<Option<Vec<u8>>> {
+        let Some(path) = &self.token_file else {
+            return Ok(None);
+        };
+        let token = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read API token {}", path.display()))?;
+        let token = token.trim();
+        if token.is_empty() {
+            bail!("API token file {} is empty", path.display());
+        }
+        Ok(Some(token.as_bytes().to_vec()))
+    }
+}
+
+impl TlsConfig {
+    fn client_tls_config(&self) -> Result<ClientTlsConfig> {
+        let mut tls = ClientTlsConfig::new();
+        tls = match &self.ca_cert {
+            Some(path) => tls.ca_certificate(Certificate::from_pem(read_pem(path)?)),
+            None => tls.with_native_roots(),
+        };
+        match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => {
+                tls = tls.identity(Identity::from_pem(read_pem(cert)?, read_pem(key)?));
+            }
+            (None, None) => {}
+            _ => bail!("TLS client-cert and client-key must be set together"),
+        }
+        if let Some(domain_name) = &self.domain_name {
+            tls = tls.domain_name(domain_name.clone());
+        }
+        Ok(tls)
+    }
+}
+
+fn read_pem(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))
+}
@@ -0,0 +1,436 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! The GuestAgentService: what the host can do in the guest.
+
+use feos_proto::guest_agent::{
+    guest_agent_service_server::GuestAgentService, write_file_request::Payload, ExecRequest,
+    ExecResponse, FileChunk, FileHeader, GetInfoRequest, GetInfoResponse, GuestInterface,
+    ReadFileRequest, ShutdownRequest, ShutdownResponse, WriteFileRequest, WriteFileResponse,
+};
+use futures::{Stream, StreamExt};
+use log::{info, warn};
+use nix::ifaddrs::getifaddrs;
+use nix::net::if_::InterfaceFlags;
+use std::collections::BTreeMap;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::fs::{self, File};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status, Streaming};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const DEFAULT_FILE_MODE: u32 = 0o644;
+
+fn io_status(context: &str, e: io::Error) -> Status {
+    let message = format!("{context}: {e}");
+    match e.kind() {
+        io::ErrorKind::NotFound => Status::not_found(message),
+        io::ErrorKind::PermissionDenied => Status::permission_denied(message),
+        io::ErrorKind::AlreadyExists => Status::already_exists(message),
+        io::ErrorKind::InvalidInput => Status::invalid_argument(message),
+        _ => Status::internal(message),
+    }
+}
+
+fn absolute_path(path: &str) -> io::Result<&Path> {
+    let path = Path::new(path);
+    if !path.is_absolute() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "not an absolute path",
+        ));
+    }
+    Ok(path)
+}
+
+async fn exec(req: ExecRequest) -> Result<ExecResponse, Status> {
+    let (program, args) = req
+        .command
+        .split_first()
+        .ok_or_else(|| Status::invalid_argument("No command given"))?;
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    for var in &req.env {
+        let (key, value) = var.split_once('=').ok_or_else(|| {
+            Status::invalid_argument(format!("Environment variable '{var}' is not KEY=VALUE"))
+        })?;
+        command.env(key, value);
+    }
+    if !req.working_dir.is_empty() {
+        command.current_dir(&req.working_dir);
+    }
+
+    let mut child = command
+        .spawn()
+        .map_err(|e| io_status(&format!("Failed to run {program}"), e))?;
+    // Written while the output is read, so a command that writes a lot
+    // before it reads all of its input does not block.
+    let stdin = child.stdin.take();
+    let input = req.stdin;
+    let writer = tokio::spawn(async move {
+        if let Some(mut stdin) = stdin {
+            let _ = stdin.write_all(&input).await;
+        }
+    });
+    let output = child.wait_with_output();
+    let output = if req.timeout_seconds == 0 {
+        Some(output.await)
+    } else {
+        let timeout = Duration::from_secs(u64::from(req.timeout_seconds));
+        // Dropping the future kills the command.
+        tokio::time::timeout(timeout, output).await.ok()
+    };
+    writer.abort();
+
+    let Some(output) = output else {
+        warn!(
+            "GuestAgent: {program} timed out after {}s",
+            req.timeout_seconds
+        );
+        return Ok(ExecResponse {
+            exit_code: -1,
+            timed_out: true,
+            ..Default::default()
+        });
+    };
+    let output = output.map_err(|e| io_status(&format!("Failed to wait for {program}"), e))?;
+    Ok(ExecResponse {
+        exit_code: output.status.code().unwrap_or(-1),
+        stdout: output.stdout,
+        stderr: output.stderr,
+        timed_out: false,
+    })
+}
+
+/// Where a file is written before it replaces `path`, so a transfer that
+/// breaks off leaves the old file as it was.
+fn partial_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{name}.feos-partial"))
+}
+
+async fn write_chunks(
+    file: &mut File,
+    mut stream: impl Stream<Item = Result<WriteFileRequest, Status>> + Unpin,
+) -> Result<u64, Status> {
+    let mut written = 0;
+    while let Some(msg) = stream.next().await {
+        let Some(Payload::Data(chunk)) = msg?.payload else {
+            return Err(Status::invalid_argument(
+                "Only the first message may be a FileHeader message.",
+            ));
+        };
+        file.write_all(&chunk)
+            .await
+            .map_err(|e| io_status("Failed to write the file", e))?;
+        written += chunk.len() as u64;
+    }
+    file.sync_all()
+        .await
+        .map_err(|e| io_status("Failed to write the file", e))?;
+    Ok(written)
+}
+
+/// Writes the data messages of `stream` to the file of `header` and returns
+/// how many bytes were written.
+async fn write_file(
+    header: &FileHeader,
+    stream: impl Stream<Item = Result<WriteFileRequest, Status>> + Unpin,
+) -> Result<u64, Status> {
+    let path = absolute_path(&header.path)
+        .map_err(|e| io_status(&format!("Cannot write '{}'", header.path), e))?;
+    if path.file_name().is_none() {
+        return Err(Status::invalid_argument(format!(
+            "'{}' is not a file path",
+            path.display()
+        )));
+    }
+    if header.create_parents {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(|e| io_status("Failed to create the parent directories", e))?;
+        }
+    }
+    let mode = if header.mode == 0 {
+        DEFAULT_FILE_MODE
+    } else {
+        header.mode
+    };
+
+    let partial = partial_path(path);
+    let mut file = File::create(&partial)
+        .await
+        .map_err(|e| io_status(&format!("Failed to create {}", path.display()), e))?;
+    let result = async {
+        let written = write_chunks(&mut file, stream).await?;
+        fs::set_permissions(&partial, std::fs::Permissions::from_mode(mode))
+            .await
+            .map_err(|e| io_status("Failed to set the file mode", e))?;
+        fs::rename(&partial, path)
+            .await
+            .map_err(|e| io_status(&format!("Failed to replace {}", path.display()), e))?;
+        Ok(written)
+    }
+    .await;
+    if result.is_err() {
+        let _ = fs::remove_file(&partial).await;
+    }
+    result
+}
+
+/// The content of a file in chunks. Errors opening the file are returned
+/// right away, errors reading it end the stream.
+async fn read_file(path: &str) -> Result<ReceiverStream<Result<FileChunk, Status>>, Status> {
+    let path = absolute_path(path).map_err(|e| io_status(&format!("Cannot read '{path}'"), e))?;
+    let mut file = File::open(path)
+        .await
+        .map_err(|e| io_status(&format!("Failed to open {}", path.display()), e))?;
+    let (tx, rx) = mpsc::channel(4);
+    tokio::spawn(async move {
+        let mut buf = vec![0; CHUNK_SIZE];
+        loop {
+            let chunk = match file.read(&mut buf).await {
+                Ok(0) => break,
+                Ok(read) => Ok(FileChunk {
+                    data: buf[..read].to_vec(),
+                }),
+                Err(e) => Err(io_status("Failed to read the file", e)),
+            };
+            let failed = chunk.is_err();
+            if tx.send(chunk).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+    Ok(ReceiverStream::new(rx))
+}
+
+fn format_mac(mac: [u8; 6]) -> String {
+    mac.iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// The network interfaces of the guest except loopback, by name.
+fn interfaces() -> nix::Result<Vec<GuestInterface>> {
+    let mut interfaces = BTreeMap::new();
+    for ifaddr in getifaddrs()? {
+        if ifaddr.flags.contains(InterfaceFlags::IFF_LOOPBACK) {
+            continue;
+        }
+        let interface = interfaces
+            .entry(ifaddr.interface_name.clone())
+            .or_insert_with(|| GuestInterface {
+                name: ifaddr.interface_name,
+                ..Default::default()
+            });
+        let Some(address) = ifaddr.address else {
+            continue;
+        };
+        if let Some(mac) = address.as_link_addr().and_then(|link| link.addr()) {
+            interface.mac_address = format_mac(mac);
+        } else if let Some(ip) = address.as_sockaddr_in() {
+            interface.ip_addresses.push(ip.ip().to_string());
+        } else if let Some(ip) = address.as_sockaddr_in6() {
+            interface.ip_addresses.push(ip.ip().to_string());
+        }
+    }
+    Ok(interfaces.into_values().collect())
+}
+
+/// Shuts the guest down through its init system. Waits a moment first, so
+/// the response of the agent reaches the host.
+fn shut_down(reboot: bool) {
+    let program = if reboot { "reboot" } else { "poweroff" };
+    info!("GuestAgent: Running {program} as asked by the host");
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        match Command::new(program).status().await {
+            Ok(status) if status.success() => {}
+            Ok(status) => warn!("GuestAgent: {program} failed with {status}"),
+            Err(e) => warn!("GuestAgent: Failed to run {program}: {e}"),
+        }
+    });
+}
+
+async fn get_file_header(
+    stream: &mut (impl Stream<Item = Result<WriteFileRequest, Status>> + Unpin),
+) -> Result<FileHeader, Status> {
+    match stream.next().await {
+        Some(Ok(msg)) => match msg.payload {
+            Some(Payload::Header(header)) => Ok(header),
+            _ => Err(Status::invalid_argument(
+                "First message must be a FileHeader message.",
+            )),
+        },
+        Some(Err(e)) => Err(e),
+        None => Err(Status::invalid_argument(
+            "Client disconnected before sending FileHeader message.",
+        )),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct GuestAgent;
+
+#[tonic::async_trait]
+impl GuestAgentService for GuestAgent {
+    type ReadFileStream = ReceiverStream<Result<FileChunk, Status>>;
+
+    async fn get_info(
+        &self,
+        _request: Request<GetInfoRequest>,
+    ) -> Result<Response<GetInfoResponse>, Status> {
+        info!("GuestAgent: Received GetInfo request.");
+        let hostname = nix::unistd::gethostname()
+            .map(|hostname| hostname.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let interfaces = interfaces()
+            .map_err(|e| Status::internal(format!("Failed to list the network interfaces: {e}")))?;
+        Ok(Response::new(GetInfoResponse {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            hostname,
+            interfaces,
+        }))
+    }
+
+    async fn exec(&self, request: Request<ExecRequest>) -> Result<Response<ExecResponse>, Status> {
+        let req = request.into_inner();
+        info!("GuestAgent: Received Exec request for {:?}.", req.command);
+        exec(req).await.map(Response::new)
+    }
+
+    async fn write_file(
+        &self,
+        request: Request<Streaming<WriteFileRequest>>,
+    ) -> Result<Response<WriteFileResponse>, Status> {
+        let mut chunks = request.into_inner();
+        let header = get_file_header(&mut chunks).await?;
+        info!(
+            "GuestAgent: Received WriteFile request for {}.",
+            header.path
+        );
+        let bytes_written = write_file(&header, chunks).await?;
+        Ok(Response::new(WriteFileResponse { bytes_written }))
+    }
+
+    async fn read_file(
+        &self,
+        request: Request<ReadFileRequest>,
+    ) -> Result<Response<Self::ReadFileStream>, Status> {
+        let req = request.into_inner();
+        info!("GuestAgent: Received ReadFile request for {}.", req.path);
+        read_file(&req.path).await.map(Response::new)
+    }
+
+    async fn shutdown(
+        &self,
+        request: Request<ShutdownRequest>,
+    ) -> Result<Response<ShutdownResponse>, Status> {
+        info!("GuestAgent: Received Shutdown request.");
+        shut_down(request.into_inner().reboot);
+        Ok(Response::new(ShutdownResponse {}))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn data(chunk: &[u8]) -> WriteFileRequest {
+        WriteFileRequest {
+            payload: Some(Payload::Data(chunk.to_vec())),
+        }
+    }
+
+    #[tokio::test]
+    async fn files_are_written_in_full_and_read_back() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("etc/app/config");
+        let header = FileHeader {
+            path: path.to_string_lossy().into_owned(),
+            mode: 0o600,
+            create_parents: true,
+        };
+        let content = vec![7u8; CHUNK_SIZE + 10];
+        let mut messages = vec![WriteFileRequest {
+            payload: Some(Payload::Header(header.clone())),
+        }];
+        messages.extend(content.chunks(1000).map(data));
+        let mut stream = futures::stream::iter(messages).map(Ok);
+        assert_eq!(get_file_header(&mut stream).await.unwrap(), header);
+        let written = write_file(&header, stream).await.unwrap();
+        assert_eq!(written, content.len() as u64);
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let chunks: Vec<_> = read_file(&header.path).await.unwrap().collect().await;
+        assert_eq!(chunks.len(), 2);
+        let read: Vec<u8> = chunks
+            .into_iter()
+            .flat_map(|chunk| chunk.unwrap().data)
+            .collect();
+        assert_eq!(read, content);
+
+        // A transfer that breaks off leaves the file as it was.
+        let broken = futures::stream::iter(vec![
+            Ok(data(&[1, 2, 3])),
+            Err(Status::cancelled("client went away")),
+        ]);
+        assert!(write_file(&header, broken).await.is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), content);
+        assert!(!partial_path(&path).exists());
+        assert_eq!(
+            read_file("relative/path").await.unwrap_err().code(),
+            tonic::Code::InvalidArgument
+        );
+    }
+
+    #[tokio::test]
+    async fn exec_returns_the_output_and_kills_commands_that_time_out() {
+        let response = exec(ExecRequest {
+            command: vec![
+                "sh".into(),
+                "-c".into(),
+                "cat; echo $GREETING >&2; exit 3".into(),
+            ],
+            stdin: b"input".to_vec(),
+            env: vec!["GREETING=hello".into()],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert_eq!(response.exit_code, 3);
+        assert_eq!(response.stdout, b"input");
+        assert_eq!(response.stderr, b"hello\n");
+        assert!(!response.timed_out);
+
+        let response = exec(ExecRequest {
+            command: vec!["sleep".into(), "10".into()],
+            timeout_seconds: 1,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert!(response.timed_out);
+        assert_eq!(
+            exec(ExecRequest::default()).await.unwrap_err().code(),
+            tonic::Code::InvalidArgument
+        );
+    }
+}
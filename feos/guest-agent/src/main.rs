@@ -0,0 +1,46 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! `feos-guest-agent` runs inside VMs and serves the GuestAgentService to
+//! the FeOS host on a vsock port. The vm-service uses it for exec in the
+//! guest, file transfers, the guest's IP addresses and graceful shutdowns.
+
+mod agent;
+mod vsock;
+
+use agent::GuestAgent;
+use anyhow::{Context, Result};
+use clap::Parser;
+use feos_proto::guest_agent::{
+    guest_agent_service_server::GuestAgentServiceServer, GUEST_AGENT_PORT,
+};
+use log::info;
+use tonic::transport::Server;
+use vsock::VsockListener;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct AgentArgs {
+    /// The vsock port to listen on. The host expects the default.
+    #[arg(long, default_value_t = GUEST_AGENT_PORT)]
+    port: u32,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::Builder::new()
+        .filter_level(log::LevelFilter::Info)
+        .parse_default_env()
+        .init();
+
+    let args = AgentArgs::parse();
+    let listener = VsockListener::bind(args.port)
+        .with_context(|| format!("Failed to listen on vsock port {}", args.port))?;
+    info!("Main: Guest agent listening on vsock port {}", args.port);
+
+    Server::builder()
+        .add_service(GuestAgentServiceServer::new(GuestAgent))
+        .serve_with_incoming(listener.incoming())
+        .await
+        .context("Guest agent server failed")
+}
@@ -0,0 +1,119 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! A vsock listener for tokio. The host reaches the agent through the vsock
+//! device of the VM, which Cloud Hypervisor connects to a unix socket on the
+//! host.
+
+use futures::Stream;
+use nix::sys::socket::{
+    accept4, bind, listen, shutdown, socket, AddressFamily, Backlog, Shutdown, SockFlag, SockType,
+    VsockAddr,
+};
+use nix::unistd;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::unix::AsyncFd;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tonic::transport::server::Connected;
+
+const SOCKET_FLAGS: SockFlag = SockFlag::SOCK_NONBLOCK.union(SockFlag::SOCK_CLOEXEC);
+
+pub struct VsockListener {
+    fd: AsyncFd<OwnedFd>,
+}
+
+impl VsockListener {
+    /// Listens on `port` for connections from the host and other guests.
+    pub fn bind(port: u32) -> io::Result<Self> {
+        let fd = socket(AddressFamily::Vsock, SockType::Stream, SOCKET_FLAGS, None)?;
+        bind(
+            fd.as_raw_fd(),
+            &VsockAddr::new(nix::libc::VMADDR_CID_ANY, port),
+        )?;
+        listen(&fd, Backlog::new(128)?)?;
+        Ok(Self {
+            fd: AsyncFd::new(fd)?,
+        })
+    }
+
+    pub async fn accept(&self) -> io::Result<VsockStream> {
+        loop {
+            let mut guard = self.fd.readable().await?;
+            if let Ok(result) = guard.try_io(|fd| Ok(accept4(fd.as_raw_fd(), SOCKET_FLAGS)?)) {
+                // SAFETY: accept4 returned a new descriptor that nothing else owns.
+                let fd = unsafe { OwnedFd::from_raw_fd(result?) };
+                return VsockStream::new(fd);
+            }
+        }
+    }
+
+    /// The connections to the listener, for `serve_with_incoming`.
+    pub fn incoming(self) -> impl Stream<Item = io::Result<VsockStream>> {
+        futures::stream::unfold(self, |listener| async move {
+            let stream = listener.accept().await;
+            Some((stream, listener))
+        })
+    }
+}
+
+pub struct VsockStream {
+    fd: AsyncFd<OwnedFd>,
+}
+
+impl VsockStream {
+    fn new(fd: OwnedFd) -> io::Result<Self> {
+        Ok(Self {
+            fd: AsyncFd::new(fd)?,
+        })
+    }
+}
+
+impl AsyncRead for VsockStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            let mut guard = ready!(self.fd.poll_read_ready(cx))?;
+            let unfilled = buf.initialize_unfilled();
+            if let Ok(result) = guard.try_io(|fd| Ok(unistd::read(fd.get_ref(), unfilled)?)) {
+                let read = result?;
+                buf.advance(read);
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+impl AsyncWrite for VsockStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            let mut guard = ready!(self.fd.poll_write_ready(cx))?;
+            if let Ok(result) = guard.try_io(|fd| Ok(unistd::write(fd.get_ref(), buf)?)) {
+                return Poll::Ready(result);
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(shutdown(self.fd.as_raw_fd(), Shutdown::Write)?))
+    }
+}
+
+impl Connected for VsockStream {
+    type ConnectInfo = ();
+
+    fn connect_info(&self) -> Self::ConnectInfo {}
+}
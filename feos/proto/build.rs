@@ -13,6 +13,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 format!("{proto_dir}/image.proto"),
                 format!("{proto_dir}/container.proto"),
                 format!("{proto_dir}/task.proto"),
+                format!("{proto_dir}/storage.proto"),
+                format!("{proto_dir}/schedule.proto"),
+                format!("{proto_dir}/secret.proto"),
+                format!("{proto_dir}/guest_agent.proto"),
+                format!("{proto_dir}/ca.proto"),
+                format!("{proto_dir}/audit.proto"),
             ],
             &[proto_dir],
         )?;
@@ -0,0 +1,300 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Signed requests for FeOS hosts whose API is protected with a shared token.
+//! The token itself is never sent. Each request carries a timestamp, a nonce
+//! and an HMAC-SHA256 with the token over them, the method and the request
+//! body, so captured requests can neither be replayed nor altered. Streamed
+//! requests are signed over their first message, which says what the stream
+//! is for, so their headers cannot be reused with another stream.
+
+use hmac::{Hmac, Mac};
+use http_body::Frame;
+use http_body_util::{BodyExt, Full};
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tonic::body::Body;
+use tonic::codegen::http::{HeaderValue, Request};
+use tonic::codegen::Bytes;
+use tonic::Status;
+use tower::Service;
+
+pub const TIMESTAMP_HEADER: &str = "x-feos-timestamp";
+pub const NONCE_HEADER: &str = "x-feos-nonce";
+pub const SIGNATURE_HEADER: &str = "x-feos-signature";
+/// The digest of the first message of a streamed request.
+pub const FIRST_MESSAGE_HEADER: &str = "x-feos-first-message";
+
+/// The public RPCs that take a stream of requests, which cannot be buffered
+/// to be signed. Their signature covers the first message instead.
+const STREAMED_REQUEST_METHODS: &[&str] = &[
+    "/feos.vm.vmm.api.v1.VMService/StreamVmConsole",
+    "/feos.vm.vmm.api.v1.VMService/PortForward",
+    "/feos.vm.vmm.api.v1.VMService/PushGuestFile",
+    "/feos.vm.vmm.api.v1.VMService/ReceiveDiskReplica",
+    "/feos.container.v1.ContainerService/ExecContainer",
+    "/feos.container.v1.ContainerService/PortForward",
+];
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Whether the signature of a call to `path` covers its body.
+pub fn signs_body(path: &str) -> bool {
+    !STREAMED_REQUEST_METHODS.contains(&path)
+}
+
+/// The digest of a request body that the signature covers.
+pub fn body_digest(body: &[u8]) -> String {
+    hex::encode(Sha256::digest(body))
+}
+
+fn mac(token: &[u8], path: &str, timestamp: u64, nonce: &str, body_digest: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(token).expect("HMAC takes keys of any length");
+    for part in [path, &timestamp.to_string(), nonce, body_digest] {
+        mac.update(part.as_bytes());
+        mac.update(b"\n");
+    }
+    mac
+}
+
+/// The signature of a request. `body_digest` is the digest of the first
+/// message for streamed requests.
+pub fn sign(token: &[u8], path: &str, timestamp: u64, nonce: &str, body_digest: &str) -> String {
+    hex::encode(
+        mac(token, path, timestamp, nonce, body_digest)
+            .finalize()
+            .into_bytes(),
+    )
+}
+
+/// Checks a signature in constant time.
+pub fn verify(
+    token: &[u8],
+    path: &str,
+    timestamp: u64,
+    nonce: &str,
+    body_digest: &str,
+    signature: &str,
+) -> bool {
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+    mac(token, path, timestamp, nonce, body_digest)
+        .verify_slice(&signature)
+        .is_ok()
+}
+
+/// A channel that signs the requests sent through it with `token`. Without a
+/// token, requests are sent as they are.
+#[derive(Clone)]
+pub struct SigningChannel<S> {
+    inner: S,
+    token: Option<Arc<[u8]>>,
+}
+
+impl<S> SigningChannel<S> {
+    pub fn new(inner: S, token: Option<Vec<u8>>) -> Self {
+        Self {
+            inner,
+            token: token.map(Arc::from),
+        }
+    }
+}
+
+impl<S> Service<Request<Body>> for SigningChannel<S>
+where
+    S: Service<Request<Body>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: Into<BoxError>,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        // The inner service was made ready by poll_ready, the clone takes
+        // its place for the next call.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let token = self.token.clone();
+        Box::pin(async move {
+            let request = match token {
+                Some(token) => sign_request(&token, request).await?,
+                None => request,
+            };
+            inner.call(request).await.map_err(Into::into)
+        })
+    }
+}
+
+/// Reads the first message of a streamed request, in its gRPC framing, up to
+/// `limit` bytes. Returns it with a body that sends everything read so far
+/// again, followed by the rest of the stream. The message is empty if the
+/// stream ends without one.
+pub async fn read_first_message(mut body: Body, limit: usize) -> Result<(Bytes, Body), BoxError> {
+    let mut frames = VecDeque::new();
+    let mut message = Vec::new();
+    loop {
+        // A message is a compression flag, its length and its bytes.
+        if let Some(length) = message.get(1..5) {
+            let length = 5 + u32::from_be_bytes(length.try_into()?) as usize;
+            if length > limit {
+                return Err(format!("the first message is larger than {limit} bytes").into());
+            }
+            if message.len() >= length {
+                message.truncate(length);
+                break;
+            }
+        }
+        let Some(frame) = body.frame().await else {
+            break;
+        };
+        let frame = frame?;
+        if let Some(data) = frame.data_ref() {
+            message.extend_from_slice(data);
+        }
+        frames.push_back(frame);
+    }
+    let body = Body::new(Replayed { frames, rest: body });
+    Ok((Bytes::from(message), body))
+}
+
+/// A body that sends the frames read from it again before the rest.
+struct Replayed {
+    frames: VecDeque<Frame<Bytes>>,
+    rest: Body,
+}
+
+impl http_body::Body for Replayed {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Status>>> {
+        if let Some(frame) = self.frames.pop_front() {
+            return Poll::Ready(Some(Ok(frame)));
+        }
+        Pin::new(&mut self.rest).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.frames.is_empty() && self.rest.is_end_stream()
+    }
+}
+
+async fn sign_request(token: &[u8], request: Request<Body>) -> Result<Request<Body>, BoxError> {
+    let (mut parts, body) = request.into_parts();
+    let path = parts.uri.path().to_string();
+    let (body, digest) = if signs_body(&path) {
+        let body = body.collect().await?.to_bytes();
+        let digest = body_digest(&body);
+        (Body::new(Full::new(body)), digest)
+    } else {
+        let (message, body) = read_first_message(body, usize::MAX).await?;
+        let digest = body_digest(&message);
+        parts
+            .headers
+            .insert(FIRST_MESSAGE_HEADER, HeaderValue::from_str(&digest)?);
+        (body, digest)
+    };
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let nonce = uuid::Uuid::new_v4().simple().to_string();
+    let signature = sign(token, &path, timestamp, &nonce, &digest);
+    parts
+        .headers
+        .insert(TIMESTAMP_HEADER, HeaderValue::from(timestamp));
+    parts
+        .headers
+        .insert(NONCE_HEADER, HeaderValue::from_str(&nonce)?);
+    parts
+        .headers
+        .insert(SIGNATURE_HEADER, HeaderValue::from_str(&signature)?);
+    Ok(Request::from_parts(parts, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::codegen::Bytes;
+
+    #[tokio::test]
+    async fn requests_are_signed_over_their_method_and_body() {
+        let token = b"secret".to_vec();
+        let path = "/feos.vm.vmm.api.v1.VMService/DeleteVm";
+        let request = Request::builder()
+            .uri(format!("http://[::1]:1337{path}"))
+            .body(Body::new(Full::new(Bytes::from_static(b"vm-1"))))
+            .unwrap();
+        let (parts, body) = sign_request(&token, request).await.unwrap().into_parts();
+        let header = |name| parts.headers[name].to_str().unwrap().to_string();
+        let timestamp: u64 = header(TIMESTAMP_HEADER).parse().unwrap();
+        let nonce = header(NONCE_HEADER);
+        let signature = header(SIGNATURE_HEADER);
+
+        // The body is sent as it was.
+        let body = body.collect().await.unwrap().to_bytes();
+        assert_eq!(body.as_ref(), b"vm-1");
+        let digest = body_digest(&body);
+        assert!(verify(&token, path, timestamp, &nonce, &digest, &signature));
+        // Another body, method, nonce or token does not match.
+        let other = body_digest(b"vm-2");
+        assert!(!verify(&token, path, timestamp, &nonce, &other, &signature));
+        let other_path = "/feos.vm.vmm.api.v1.VMService/StartVm";
+        assert!(!verify(
+            &token, other_path, timestamp, &nonce, &digest, &signature
+        ));
+        assert!(!verify(&token, path, timestamp, "0", &digest, &signature));
+        assert!(!verify(
+            b"guess", path, timestamp, &nonce, &digest, &signature
+        ));
+        assert!(!verify(&token, path, timestamp, &nonce, &digest, "not hex"));
+
+        assert!(!signs_body("/feos.vm.vmm.api.v1.VMService/StreamVmConsole"));
+    }
+
+    #[tokio::test]
+    async fn streamed_requests_are_signed_over_their_first_message() {
+        let token = b"secret".to_vec();
+        let path = "/feos.container.v1.ContainerService/ExecContainer";
+        let first = b"\0\0\0\0\x05start";
+        let stream: Vec<Result<Frame<Bytes>, Status>> = vec![
+            Ok(Frame::data(Bytes::from_static(&first[..3]))),
+            Ok(Frame::data(Bytes::from_static(&first[3..]))),
+            Ok(Frame::data(Bytes::from_static(b"\0\0\0\0\x05stdin"))),
+        ];
+        let request = Request::builder()
+            .uri(format!("http://[::1]:1337{path}"))
+            .body(Body::new(http_body_util::StreamBody::new(
+                tokio_stream::iter(stream),
+            )))
+            .unwrap();
+        let (parts, body) = sign_request(&token, request).await.unwrap().into_parts();
+        let header = |name| parts.headers[name].to_str().unwrap().to_string();
+        let timestamp: u64 = header(TIMESTAMP_HEADER).parse().unwrap();
+        let digest = header(FIRST_MESSAGE_HEADER);
+        assert_eq!(digest, body_digest(first));
+        assert!(verify(
+            &token,
+            path,
+            timestamp,
+            &header(NONCE_HEADER),
+            &digest,
+            &header(SIGNATURE_HEADER)
+        ));
+
+        // The whole stream is still sent.
+        let body = body.collect().await.unwrap().to_bytes();
+        assert_eq!(body.as_ref(), b"\0\0\0\0\x05start\0\0\0\0\x05stdin");
+    }
+}
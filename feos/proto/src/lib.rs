@@ -1,6 +1,8 @@
 // SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
 // SPDX-License-Identifier: Apache-2.0
 
+pub mod auth;
+
 pub mod vm_service {
     tonic::include_proto!("feos.vm.vmm.api.v1");
 }
@@ -16,3 +18,24 @@ pub mod task_service {
 pub mod container_service {
     tonic::include_proto!("feos.container.v1");
 }
+pub mod storage_service {
+    tonic::include_proto!("feos.storage.v1");
+}
+pub mod schedule_service {
+    tonic::include_proto!("feos.schedule.v1");
+}
+pub mod secret_service {
+    tonic::include_proto!("feos.secret.v1");
+}
+pub mod audit_service {
+    tonic::include_proto!("feos.audit.v1");
+}
+pub mod ca {
+    tonic::include_proto!("feos.ca.v1");
+}
+pub mod guest_agent {
+    tonic::include_proto!("feos.guest_agent.v1");
+
+    /// The vsock port `feos-guest-agent` listens on inside VMs.
+    pub const GUEST_AGENT_PORT: u32 = 1025;
+}
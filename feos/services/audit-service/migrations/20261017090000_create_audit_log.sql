@@ -0,0 +1,29 @@
+-- SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+-- SPDX-License-Identifier: Apache-2.0
+
+CREATE TABLE IF NOT EXISTS audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- When the call was made, in milliseconds since the Unix epoch.
+    timestamp_ms INTEGER NOT NULL,
+    method TEXT NOT NULL,
+    caller TEXT NOT NULL,
+    credential TEXT NOT NULL,
+    summary TEXT NOT NULL,
+    -- The gRPC status code the call ended with.
+    code INTEGER NOT NULL,
+    message TEXT NOT NULL,
+    duration_ms INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_timestamp ON audit_log (timestamp_ms);
+
+-- The log is append-only, records can neither be changed nor removed.
+CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'the audit log is append-only');
+END;
+
+CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'the audit log is append-only');
+END;
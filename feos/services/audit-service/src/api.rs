@@ -0,0 +1,41 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::Command;
+use feos_proto::audit_service::{
+    audit_service_server::AuditService, AuditRecord, StreamAuditLogRequest,
+};
+use log::info;
+use std::pin::Pin;
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+use tonic::{Request, Response, Status};
+
+pub struct AuditApiHandler {
+    dispatcher_tx: mpsc::Sender<Command>,
+}
+
+impl AuditApiHandler {
+    pub fn new(dispatcher_tx: mpsc::Sender<Command>) -> Self {
+        Self { dispatcher_tx }
+    }
+}
+
+#[tonic::async_trait]
+impl AuditService for AuditApiHandler {
+    type StreamAuditLogStream = Pin<Box<dyn Stream<Item = Result<AuditRecord, Status>> + Send>>;
+
+    async fn stream_audit_log(
+        &self,
+        request: Request<StreamAuditLogRequest>,
+    ) -> Result<Response<Self::StreamAuditLogStream>, Status> {
+        info!("AuditApi: Received StreamAuditLog stream request.");
+        let (stream_tx, stream_rx) = mpsc::channel(16);
+        let cmd = Command::StreamAuditLog(request.into_inner(), stream_tx);
+        self.dispatcher_tx
+            .send(cmd)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to send command to dispatcher: {e}")))?;
+        Ok(Response::new(Box::pin(ReceiverStream::new(stream_rx))))
+    }
+}
@@ -0,0 +1,197 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    error::AuditServiceError,
+    persistence::{repository::AuditRepository, AuditEntry},
+    Command,
+};
+use feos_proto::audit_service::{AuditRecord, StreamAuditLogRequest};
+use log::{error, info, warn};
+use prost_types::Timestamp;
+use tokio::sync::{broadcast, mpsc};
+use tonic::Status;
+
+/// How many stored records are read from the database at a time.
+const PAGE_SIZE: i64 = 500;
+
+pub struct Dispatcher {
+    rx: mpsc::Receiver<Command>,
+    entries_rx: mpsc::UnboundedReceiver<AuditEntry>,
+    repository: AuditRepository,
+    /// The records appended since the start, for streams that follow the log.
+    live_tx: broadcast::Sender<AuditRecord>,
+    /// Stamped on the records, see HostService.GetNodeIdentity.
+    node_id: String,
+}
+
+fn timestamp(ms: i64) -> Timestamp {
+    Timestamp {
+        seconds: ms.div_euclid(1000),
+        nanos: (ms.rem_euclid(1000) * 1_000_000) as i32,
+    }
+}
+
+fn timestamp_to_ms(timestamp: &Timestamp) -> i64 {
+    timestamp
+        .seconds
+        .saturating_mul(1000)
+        .saturating_add(i64::from(timestamp.nanos / 1_000_000))
+}
+
+fn record_to_proto(id: i64, entry: AuditEntry, node_id: &str) -> AuditRecord {
+    AuditRecord {
+        id: id as u64,
+        timestamp: Some(timestamp(entry.timestamp_ms)),
+        method: entry.method,
+        caller: entry.caller,
+        credential: entry.credential,
+        summary: entry.summary,
+        code: entry.code,
+        message: entry.message,
+        duration_ms: entry.duration_ms,
+        node_id: node_id.to_string(),
+    }
+}
+
+impl Dispatcher {
+    /// `entries_rx` receives the calls to record from the `AuditRecorder`.
+    pub async fn new(
+        rx: mpsc::Receiver<Command>,
+        entries_rx: mpsc::UnboundedReceiver<AuditEntry>,
+        db_url: &str,
+        node_id: String,
+    ) -> Result<Self, AuditServiceError> {
+        info!("Dispatcher: Connecting to persistence layer at {db_url}...");
+        let repository = AuditRepository::connect(db_url).await?;
+        info!("Dispatcher: Persistence layer connected successfully.");
+        let (live_tx, _) = broadcast::channel(256);
+        Ok(Self {
+            rx,
+            entries_rx,
+            repository,
+            live_tx,
+            node_id,
+        })
+    }
+
+    pub async fn run(mut self) {
+        info!("Dispatcher: Running and waiting for commands and records.");
+        loop {
+            tokio::select! {
+                Some(entry) = self.entries_rx.recv() => self.append(entry).await,
+                Some(cmd) = self.rx.recv() => self.handle_command(cmd),
+                else => break,
+            }
+        }
+        info!("Dispatcher: Channels closed, shutting down.");
+    }
+
+    async fn append(&self, entry: AuditEntry) {
+        match self.repository.append(&entry).await {
+            Ok(id) => {
+                // Only streams that follow the log receive it.
+                let _ = self.live_tx.send(record_to_proto(id, entry, &self.node_id));
+            }
+            Err(e) => error!(
+                "Dispatcher: Failed to record the call to {} by {}: {e}",
+                entry.method, entry.caller
+            ),
+        }
+    }
+
+    fn handle_command(&self, cmd: Command) {
+        match cmd {
+            Command::StreamAuditLog(req, stream_tx) => {
+                // Subscribed before the stored records are read, so no
+                // record falls in between.
+                let live_rx = req.follow.then(|| self.live_tx.subscribe());
+                tokio::spawn(stream_records(
+                    self.repository.clone(),
+                    req,
+                    live_rx,
+                    stream_tx,
+                    self.node_id.clone(),
+                ));
+            }
+        }
+    }
+}
+
+/// Sends the stored records after `after_id` and advances it. Returns
+/// whether the client is still there.
+async fn send_stored(
+    repository: &AuditRepository,
+    after_id: &mut i64,
+    since_ms: Option<i64>,
+    stream_tx: &mpsc::Sender<Result<AuditRecord, Status>>,
+    node_id: &str,
+) -> Result<bool, AuditServiceError> {
+    loop {
+        let records = repository
+            .list_records(*after_id, since_ms, PAGE_SIZE)
+            .await?;
+        let last_page = (records.len() as i64) < PAGE_SIZE;
+        for (id, entry) in records {
+            *after_id = id;
+            if stream_tx
+                .send(Ok(record_to_proto(id, entry, node_id)))
+                .await
+                .is_err()
+            {
+                return Ok(false);
+            }
+        }
+        if last_page {
+            return Ok(true);
+        }
+    }
+}
+
+async fn stream_records(
+    repository: AuditRepository,
+    req: StreamAuditLogRequest,
+    mut live_rx: Option<broadcast::Receiver<AuditRecord>>,
+    stream_tx: mpsc::Sender<Result<AuditRecord, Status>>,
+    node_id: String,
+) {
+    let since_ms = req.since.as_ref().map(timestamp_to_ms);
+    let mut after_id = i64::try_from(req.after_id).unwrap_or(i64::MAX);
+    loop {
+        match send_stored(&repository, &mut after_id, since_ms, &stream_tx, &node_id).await {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => {
+                let _ = stream_tx.send(Err(e.into())).await;
+                return;
+            }
+        }
+        let Some(live_rx) = live_rx.as_mut() else {
+            return;
+        };
+        loop {
+            match live_rx.recv().await {
+                Ok(record) => {
+                    let id = record.id as i64;
+                    let before_since =
+                        record.timestamp.as_ref().zip(since_ms).is_some_and(
+                            |(timestamp, since_ms)| timestamp_to_ms(timestamp) < since_ms,
+                        );
+                    if id <= after_id || before_since {
+                        continue;
+                    }
+                    after_id = id;
+                    if stream_tx.send(Ok(record)).await.is_err() {
+                        return;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    // The missed records are read from the database.
+                    warn!("Dispatcher: An audit log stream fell {skipped} records behind, catching up.");
+                    break;
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    }
+}
@@ -0,0 +1,20 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::persistence::PersistenceError;
+use tonic::Status;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuditServiceError {
+    #[error("Persistence Error: {0}")]
+    Persistence(#[from] PersistenceError),
+}
+
+impl From<AuditServiceError> for Status {
+    fn from(err: AuditServiceError) -> Self {
+        log::error!("AuditServiceError: {err}");
+        match err {
+            AuditServiceError::Persistence(_) => Status::internal("A database error occurred"),
+        }
+    }
+}
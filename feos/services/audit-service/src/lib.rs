@@ -0,0 +1,43 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::persistence::AuditEntry;
+use feos_proto::audit_service::{AuditRecord, StreamAuditLogRequest};
+use log::error;
+use tokio::sync::mpsc;
+use tonic::Status;
+
+pub mod api;
+pub mod dispatcher;
+pub mod error;
+pub mod persistence;
+
+pub const DEFAULT_AUDIT_DB_URL: &str = "sqlite:/var/lib/feos/audit.db";
+
+#[derive(Debug)]
+pub enum Command {
+    StreamAuditLog(
+        StreamAuditLogRequest,
+        mpsc::Sender<Result<AuditRecord, Status>>,
+    ),
+}
+
+/// Hands the calls to record to the dispatcher, which appends them to the
+/// audit log in the order they complete.
+#[derive(Clone)]
+pub struct AuditRecorder {
+    tx: mpsc::UnboundedSender<AuditEntry>,
+}
+
+impl AuditRecorder {
+    pub fn channel() -> (Self, mpsc::UnboundedReceiver<AuditEntry>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (Self { tx }, rx)
+    }
+
+    pub fn record(&self, entry: AuditEntry) {
+        if self.tx.send(entry).is_err() {
+            error!("AuditRecorder: The audit log is closed, a call was not recorded.");
+        }
+    }
+}
@@ -0,0 +1,28 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+pub mod repository;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PersistenceError {
+    #[error("A database error occurred")]
+    Database(#[from] sqlx::Error),
+
+    #[error("Database migration failed")]
+    Migration(#[from] sqlx::migrate::MigrateError),
+}
+
+/// A call to the public API as recorded in the audit log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    /// When the call was made, in milliseconds since the Unix epoch.
+    pub timestamp_ms: i64,
+    pub method: String,
+    pub caller: String,
+    pub credential: String,
+    pub summary: String,
+    /// The gRPC status code the call ended with.
+    pub code: i32,
+    pub message: String,
+    pub duration_ms: u64,
+}
@@ -0,0 +1,138 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::persistence::{AuditEntry, PersistenceError};
+use log::info;
+use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+
+#[derive(Clone)]
+pub struct AuditRepository {
+    pool: SqlitePool,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct DbAuditRow {
+    id: i64,
+    timestamp_ms: i64,
+    method: String,
+    caller: String,
+    credential: String,
+    summary: String,
+    code: i32,
+    message: String,
+    duration_ms: i64,
+}
+
+impl From<DbAuditRow> for (i64, AuditEntry) {
+    fn from(row: DbAuditRow) -> Self {
+        (
+            row.id,
+            AuditEntry {
+                timestamp_ms: row.timestamp_ms,
+                method: row.method,
+                caller: row.caller,
+                credential: row.credential,
+                summary: row.summary,
+                code: row.code,
+                message: row.message,
+                duration_ms: row.duration_ms as u64,
+            },
+        )
+    }
+}
+
+impl AuditRepository {
+    pub async fn connect(db_url: &str) -> Result<Self, PersistenceError> {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(db_url)
+            .await?;
+
+        info!("Persistence: Running audit-service database migrations...");
+        sqlx::migrate!("./migrations").run(&pool).await?;
+        info!("Persistence: Database migrations completed for audit-service.");
+
+        Ok(Self { pool })
+    }
+
+    /// Appends a record to the audit log and returns its ID.
+    pub async fn append(&self, entry: &AuditEntry) -> Result<i64, PersistenceError> {
+        let id = sqlx::query_scalar(
+            "INSERT INTO audit_log (timestamp_ms, method, caller, credential, summary, code, message, duration_ms) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8) RETURNING id",
+        )
+        .bind(entry.timestamp_ms)
+        .bind(&entry.method)
+        .bind(&entry.caller)
+        .bind(&entry.credential)
+        .bind(&entry.summary)
+        .bind(entry.code)
+        .bind(&entry.message)
+        .bind(entry.duration_ms as i64)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(id)
+    }
+
+    /// Up to `limit` records after the one with `after_id`, oldest first,
+    /// optionally only those of calls made at or after `since_ms`.
+    pub async fn list_records(
+        &self,
+        after_id: i64,
+        since_ms: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<(i64, AuditEntry)>, PersistenceError> {
+        let rows = sqlx::query_as::<_, DbAuditRow>(
+            "SELECT id, timestamp_ms, method, caller, credential, summary, code, message, duration_ms FROM audit_log WHERE id > ?1 AND (?2 IS NULL OR timestamp_ms >= ?2) ORDER BY id LIMIT ?3",
+        )
+        .bind(after_id)
+        .bind(since_ms)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(method: &str) -> AuditEntry {
+        AuditEntry {
+            timestamp_ms: 1_700_000_000_000,
+            method: method.to_string(),
+            caller: "[2001:db8::1]:52000".to_string(),
+            credential: "api-token".to_string(),
+            summary: "vm_id=vm-1".to_string(),
+            code: 0,
+            message: String::new(),
+            duration_ms: 12,
+        }
+    }
+
+    #[tokio::test]
+    async fn the_audit_log_is_append_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("audit.db");
+        std::fs::File::create(&db_path).unwrap();
+        let repository = AuditRepository::connect(&format!("sqlite:{}", db_path.display()))
+            .await
+            .unwrap();
+
+        let first = repository.append(&entry("CreateVm")).await.unwrap();
+        let second = repository.append(&entry("DeleteVm")).await.unwrap();
+        assert!(second > first);
+        let records = repository.list_records(first, None, 10).await.unwrap();
+        assert_eq!(records, vec![(second, entry("DeleteVm"))]);
+
+        assert!(sqlx::query("UPDATE audit_log SET code = 0")
+            .execute(&repository.pool)
+            .await
+            .is_err());
+        assert!(sqlx::query("DELETE FROM audit_log")
+            .execute(&repository.pool)
+            .await
+            .is_err());
+        assert_eq!(repository.list_records(0, None, 10).await.unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,10 @@
+-- SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+-- SPDX-License-Identifier: Apache-2.0
+
+-- Containers from before namespaces existed belong to the default one.
+ALTER TABLE containers ADD COLUMN namespace TEXT NOT NULL DEFAULT 'default';
+-- An optional human-readable name, unique within the namespace.
+ALTER TABLE containers ADD COLUMN name TEXT;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_containers_namespace_name ON containers (namespace, name) WHERE name IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_containers_namespace ON containers (namespace);
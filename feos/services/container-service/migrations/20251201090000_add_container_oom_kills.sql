@@ -0,0 +1,7 @@
+-- SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+-- SPDX-License-Identifier: Apache-2.0
+
+-- How often the OOM killer hit a process of the container's cgroup.
+ALTER TABLE containers ADD COLUMN oom_kill_count INTEGER NOT NULL DEFAULT 0;
+-- Whether the container stopped because its own process was OOM-killed.
+ALTER TABLE containers ADD COLUMN oom_killed BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,10 @@
+-- SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+-- SPDX-License-Identifier: Apache-2.0
+
+-- When the container was last started and its process last exited, as Unix
+-- time in milliseconds.
+ALTER TABLE containers ADD COLUMN started_at INTEGER;
+ALTER TABLE containers ADD COLUMN finished_at INTEGER;
+-- How the process exited, reset when the container is started again.
+ALTER TABLE containers ADD COLUMN exit_code INTEGER;
+ALTER TABLE containers ADD COLUMN exit_signal INTEGER;
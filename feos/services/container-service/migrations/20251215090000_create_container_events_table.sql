@@ -0,0 +1,20 @@
+-- SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+-- SPDX-License-Identifier: Apache-2.0
+
+CREATE TABLE IF NOT EXISTS container_events (
+    -- The ID of the event, unique among the events of all containers.
+    event_id TEXT PRIMARY KEY NOT NULL,
+    -- The container the event is about. Its events are kept after it is
+    -- deleted.
+    container_id TEXT NOT NULL,
+    -- The namespace the container was in.
+    namespace TEXT NOT NULL,
+    -- When the event was recorded, as Unix time in milliseconds.
+    recorded_at INTEGER NOT NULL,
+    -- A binary blob containing the serialized ContainerEvent protobuf message.
+    event_blob BLOB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_container_events_container_id
+    ON container_events (container_id, recorded_at);
+CREATE INDEX IF NOT EXISTS idx_container_events_recorded_at ON container_events (recorded_at);
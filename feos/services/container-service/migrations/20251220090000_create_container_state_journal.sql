@@ -0,0 +1,65 @@
+-- SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+-- SPDX-License-Identifier: Apache-2.0
+
+CREATE TABLE IF NOT EXISTS container_state_journal (
+    -- The position of the entry in the journal. Later entries have higher ones.
+    sequence INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- The container whose state changed. Its entries are kept after it is
+    -- deleted.
+    container_id TEXT NOT NULL,
+    -- The namespace the container was in.
+    namespace TEXT NOT NULL,
+    -- When the state changed, in milliseconds since the Unix epoch.
+    recorded_at_ms INTEGER NOT NULL DEFAULT (CAST(unixepoch('subsec') * 1000 AS INTEGER)),
+    -- The state before, NULL when the container was created.
+    old_state TEXT,
+    -- The state after, NULL when the container was deleted.
+    new_state TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_container_state_journal_container_id
+    ON container_state_journal (container_id, sequence);
+
+-- Containers from before the journal existed start it with their current
+-- state.
+INSERT INTO container_state_journal (container_id, namespace, old_state, new_state)
+SELECT container_id, namespace, NULL, state FROM containers ORDER BY created_at;
+
+-- The journal is written by these triggers, in the same transaction as the
+-- change of the container, so no transition is missing from it.
+CREATE TRIGGER IF NOT EXISTS trigger_containers_journal_insert
+AFTER INSERT ON containers
+FOR EACH ROW
+BEGIN
+    INSERT INTO container_state_journal (container_id, namespace, old_state, new_state)
+    VALUES (NEW.container_id, NEW.namespace, NULL, NEW.state);
+END;
+
+CREATE TRIGGER IF NOT EXISTS trigger_containers_journal_update
+AFTER UPDATE OF state ON containers
+FOR EACH ROW WHEN OLD.state IS NOT NEW.state
+BEGIN
+    INSERT INTO container_state_journal (container_id, namespace, old_state, new_state)
+    VALUES (NEW.container_id, NEW.namespace, OLD.state, NEW.state);
+END;
+
+CREATE TRIGGER IF NOT EXISTS trigger_containers_journal_delete
+AFTER DELETE ON containers
+FOR EACH ROW
+BEGIN
+    INSERT INTO container_state_journal (container_id, namespace, old_state, new_state)
+    VALUES (OLD.container_id, OLD.namespace, OLD.state, NULL);
+END;
+
+-- Entries are never changed or removed.
+CREATE TRIGGER IF NOT EXISTS trigger_container_state_journal_no_update
+BEFORE UPDATE ON container_state_journal
+BEGIN
+    SELECT RAISE(ABORT, 'container_state_journal is append-only');
+END;
+
+CREATE TRIGGER IF NOT EXISTS trigger_container_state_journal_no_delete
+BEFORE DELETE ON container_state_journal
+BEGIN
+    SELECT RAISE(ABORT, 'container_state_journal is append-only');
+END;
@@ -5,23 +5,31 @@ use crate::Command;
 use feos_proto::container_service::{
     container_service_server::ContainerService, ContainerEvent, ContainerInfo,
     CreateContainerRequest, CreateContainerResponse, DeleteContainerRequest,
-    DeleteContainerResponse, GetContainerRequest, ListContainersRequest, ListContainersResponse,
-    LogEntry, StartContainerRequest, StartContainerResponse, StopContainerRequest,
-    StopContainerResponse, StreamContainerEventsRequest, StreamContainerLogsRequest,
+    DeleteContainerResponse, ExecContainerRequest, ExecContainerResponse, GetContainerRequest,
+    ListContainerEventsRequest, ListContainerEventsResponse, ListContainersRequest,
+    ListContainersResponse, LogEntry, PortForwardRequest, PortForwardResponse,
+    ReplayContainerStateJournalRequest, ReplayContainerStateJournalResponse, StartContainerRequest,
+    StartContainerResponse, StopContainerRequest, StopContainerResponse,
+    StreamContainerEventsRequest, StreamContainerLogsRequest,
 };
 use log::info;
 use std::pin::Pin;
 use tokio::sync::{mpsc, oneshot};
-use tokio_stream::Stream;
-use tonic::{Request, Response, Status};
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+use tonic::{Request, Response, Status, Streaming};
 
 pub struct ContainerApiHandler {
     dispatcher_tx: mpsc::Sender<Command>,
+    /// The ID of the node, which events are stamped with.
+    node_id: String,
 }
 
 impl ContainerApiHandler {
-    pub fn new(dispatcher_tx: mpsc::Sender<Command>) -> Self {
-        Self { dispatcher_tx }
+    pub fn new(dispatcher_tx: mpsc::Sender<Command>, node_id: String) -> Self {
+        Self {
+            dispatcher_tx,
+            node_id,
+        }
     }
 }
 
@@ -54,6 +62,10 @@ impl ContainerService for ContainerApiHandler {
     type StreamContainerLogsStream = Pin<Box<dyn Stream<Item = Result<LogEntry, Status>> + Send>>;
     type StreamContainerEventsStream =
         Pin<Box<dyn Stream<Item = Result<ContainerEvent, Status>> + Send>>;
+    type ExecContainerStream =
+        Pin<Box<dyn Stream<Item = Result<ExecContainerResponse, Status>> + Send>>;
+    type PortForwardStream =
+        Pin<Box<dyn Stream<Item = Result<PortForwardResponse, Status>> + Send>>;
 
     async fn create_container(
         &self,
@@ -130,8 +142,82 @@ impl ContainerService for ContainerApiHandler {
 
     async fn stream_container_events(
         &self,
-        _request: Request<StreamContainerEventsRequest>,
+        request: Request<StreamContainerEventsRequest>,
     ) -> Result<Response<Self::StreamContainerEventsStream>, Status> {
-        Err(Status::unimplemented("Not yet implemented"))
+        info!("ContainerApi: Received StreamContainerEvents stream request.");
+        let (stream_tx, stream_rx) = mpsc::channel(16);
+        let cmd = Command::StreamContainerEvents(request.into_inner(), stream_tx);
+        self.dispatcher_tx
+            .send(cmd)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to send command to dispatcher: {e}")))?;
+        let node_id = self.node_id.clone();
+        let output_stream = ReceiverStream::new(stream_rx).map(move |event| {
+            event.map(|event| ContainerEvent {
+                node_id: node_id.clone(),
+                ..event
+            })
+        });
+        Ok(Response::new(Box::pin(output_stream)))
+    }
+
+    async fn list_container_events(
+        &self,
+        request: Request<ListContainerEventsRequest>,
+    ) -> Result<Response<ListContainerEventsResponse>, Status> {
+        info!("ContainerApi: Received ListContainerEvents request.");
+        let mut response = dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::ListContainerEvents(request.into_inner(), resp_tx)
+        })
+        .await?;
+        for recorded in &mut response.get_mut().events {
+            if let Some(event) = recorded.event.as_mut() {
+                event.node_id.clone_from(&self.node_id);
+            }
+        }
+        Ok(response)
+    }
+
+    async fn replay_container_state_journal(
+        &self,
+        request: Request<ReplayContainerStateJournalRequest>,
+    ) -> Result<Response<ReplayContainerStateJournalResponse>, Status> {
+        info!("ContainerApi: Received ReplayContainerStateJournal request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::ReplayContainerStateJournal(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn exec_container(
+        &self,
+        request: Request<Streaming<ExecContainerRequest>>,
+    ) -> Result<Response<Self::ExecContainerStream>, Status> {
+        info!("ContainerApi: Received ExecContainer stream request.");
+        let grpc_input_stream = request.into_inner();
+        let (grpc_output_tx, grpc_output_rx) = mpsc::channel(32);
+        let cmd = Command::ExecContainer(Box::new(grpc_input_stream), grpc_output_tx);
+        self.dispatcher_tx
+            .send(cmd)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to send command to dispatcher: {e}")))?;
+        let output_stream = ReceiverStream::new(grpc_output_rx);
+        Ok(Response::new(Box::pin(output_stream)))
+    }
+
+    async fn port_forward(
+        &self,
+        request: Request<Streaming<PortForwardRequest>>,
+    ) -> Result<Response<Self::PortForwardStream>, Status> {
+        info!("ContainerApi: Received PortForward stream request.");
+        let grpc_input_stream = request.into_inner();
+        let (grpc_output_tx, grpc_output_rx) = mpsc::channel(32);
+        let cmd = Command::PortForward(Box::new(grpc_input_stream), grpc_output_tx);
+        self.dispatcher_tx
+            .send(cmd)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to send command to dispatcher: {e}")))?;
+        let output_stream = ReceiverStream::new(grpc_output_rx);
+        Ok(Response::new(Box::pin(output_stream)))
     }
 }
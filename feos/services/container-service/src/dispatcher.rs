@@ -2,28 +2,157 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
+    drain::drain_containers,
     error::ContainerServiceError,
-    persistence::{repository::ContainerRepository, ContainerRecord},
-    runtime::adapter::ContainerAdapter,
+    oom,
+    persistence::{
+        repository::{ContainerEventFilter, ContainerJournalEntry, ContainerRepository},
+        ContainerRecord, PersistenceError,
+    },
+    runtime::{
+        adapter::{self, ContainerAdapter},
+        secrets,
+        snapshotter::{self, Snapshotter},
+    },
     worker, Command,
 };
 use feos_proto::{
-    container_service::{ContainerInfo, ContainerState, ListContainersResponse},
+    container_service::{
+        exec_container_request, port_forward_request, startup_dependency, ContainerConfig,
+        ContainerEvent, ContainerInfo, ContainerState, ContainerStateJournalEntry,
+        CreateContainerRequest, ExecContainerRequest, ExecStart, ListContainerEventsRequest,
+        ListContainerEventsResponse, ListContainersResponse, PortForwardRequest, PortForwardStart,
+        RecordedContainerEvent, ReplayContainerStateJournalRequest,
+        ReplayContainerStateJournalResponse, StreamContainerEventsRequest,
+    },
     image_service::{image_service_client::ImageServiceClient, PullImageRequest},
 };
+use feos_utils::host::admission::{AdmissionController, Resources, WorkloadKind};
+use feos_utils::host::maintenance::{DrainJob, Maintenance};
+use feos_utils::host::startup::{StartupOrder, WorkloadRef};
+use feos_utils::namespace::{namespace_or_default, validate_name};
 use hyper_util::rt::TokioIo;
 use image_service::IMAGE_SERVICE_SOCKET;
 use log::{info, warn};
-use std::{path::PathBuf, sync::Arc};
-use tokio::sync::mpsc;
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+use prost_types::Timestamp;
+use secret_service::store::SecretStore;
+use std::{path::PathBuf, sync::Arc, time::SystemTime};
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::StreamExt;
 use tonic::transport::{Channel, Endpoint, Uri};
+use tonic::{Status, Streaming};
 use tower::service_fn;
 use uuid::Uuid;
 
+/// The number of events ListContainerEvents returns if the request sets no
+/// limit.
+const DEFAULT_CONTAINER_EVENTS_LIMIT: u32 = 1000;
+
+/// The number of entries ReplayContainerStateJournal returns if the request
+/// sets no limit.
+const DEFAULT_JOURNAL_ENTRIES_LIMIT: u32 = 1000;
+
 pub struct Dispatcher {
     rx: mpsc::Receiver<Command>,
     repository: ContainerRepository,
     adapter: Arc<ContainerAdapter>,
+    event_tx: broadcast::Sender<ContainerEvent>,
+    admission: AdmissionController,
+    maintenance: Maintenance,
+    startup: StartupOrder,
+    drain_rx: mpsc::Receiver<DrainJob>,
+}
+
+fn journal_entry_to_proto(entry: ContainerJournalEntry) -> ContainerStateJournalEntry {
+    ContainerStateJournalEntry {
+        sequence: entry.sequence as u64,
+        container_id: entry.container_id.to_string(),
+        namespace: entry.namespace,
+        recorded_at: Some(entry.recorded_at.into()),
+        old_state: entry.old_state.map(Into::into),
+        new_state: entry.new_state.map(Into::into),
+    }
+}
+
+/// The host CPU and memory a container is limited to. Containers without
+/// limits do not count against the host.
+pub(crate) fn container_resources(config: &ContainerConfig) -> Resources {
+    Resources {
+        milli_cpus: u64::from(config.milli_cpus),
+        memory_bytes: config.memory_limit_bytes,
+    }
+}
+
+/// The workloads FeOS starts before the container when it starts the
+/// container by itself.
+fn startup_dependencies(
+    config: &ContainerConfig,
+) -> Result<Vec<WorkloadRef>, ContainerServiceError> {
+    let Some(startup) = &config.startup else {
+        return Ok(Vec::new());
+    };
+    startup
+        .after
+        .iter()
+        .map(|dependency| {
+            let (kind, id) = match &dependency.workload {
+                Some(startup_dependency::Workload::VmId(id)) => (WorkloadKind::Vm, id),
+                Some(startup_dependency::Workload::ContainerId(id)) => {
+                    (WorkloadKind::Container, id)
+                }
+                None => {
+                    return Err(ContainerServiceError::InvalidArgument(
+                        "A startup dependency must name a VM or a container.".to_string(),
+                    ))
+                }
+            };
+            let id = Uuid::parse_str(id).map_err(|_| {
+                ContainerServiceError::InvalidArgument(format!(
+                    "Startup dependency {kind} '{id}' is not a valid UUID."
+                ))
+            })?;
+            Ok(WorkloadRef::new(kind, id.to_string()))
+        })
+        .collect()
+}
+
+fn container_info(record: ContainerRecord) -> ContainerInfo {
+    let status = record.status;
+    ContainerInfo {
+        container_id: record.container_id.to_string(),
+        state: status.state as i32,
+        config: Some(record.config),
+        pid: status.process_id,
+        exit_code: status.exit_code,
+        namespace: record.namespace,
+        name: record.name,
+        oom_kill_count: status.oom_kill_count,
+        oom_killed: status.oom_killed,
+        exit_signal: status.exit_signal,
+        started_at: status.started_at.map(Timestamp::from),
+        finished_at: status.finished_at.map(Timestamp::from),
+    }
+}
+
+fn validate_config(config: &ContainerConfig) -> Result<(), ContainerServiceError> {
+    if let Some(signal) = config.stop_signal {
+        if !matches!(i32::try_from(signal).map(Signal::try_from), Ok(Ok(_))) {
+            return Err(ContainerServiceError::InvalidArgument(format!(
+                "Invalid stop signal {signal}."
+            )));
+        }
+    }
+    adapter::validate_cpu_options(config).map_err(ContainerServiceError::InvalidArgument)?;
+    adapter::validate_serial_ports(config).map_err(ContainerServiceError::InvalidArgument)?;
+    secrets::validate(config).map_err(ContainerServiceError::InvalidArgument)
+}
+
+pub(crate) fn process_alive(process_id: Option<i64>) -> bool {
+    process_id
+        .and_then(|pid| i32::try_from(pid).ok())
+        .is_some_and(|pid| kill(Pid::from_raw(pid), None).is_ok())
 }
 
 async fn get_image_service_client() -> Result<ImageServiceClient<Channel>, ContainerServiceError> {
@@ -63,36 +192,171 @@ async fn initiate_image_pull(image_ref: &str) -> Result<String, ContainerService
     Ok(image_uuid)
 }
 
+async fn get_exec_start(stream: &mut Streaming<ExecContainerRequest>) -> Result<ExecStart, Status> {
+    match stream.next().await {
+        Some(Ok(msg)) => match msg.payload {
+            Some(exec_container_request::Payload::Start(start)) => Ok(start),
+            _ => Err(Status::invalid_argument(
+                "First message must be an ExecStart message.",
+            )),
+        },
+        Some(Err(e)) => Err(e),
+        None => Err(Status::invalid_argument(
+            "Client disconnected before sending ExecStart message.",
+        )),
+    }
+}
+
+async fn get_port_forward_start(
+    stream: &mut Streaming<PortForwardRequest>,
+) -> Result<PortForwardStart, Status> {
+    match stream.next().await {
+        Some(Ok(msg)) => match msg.payload {
+            Some(port_forward_request::Payload::Start(start)) => Ok(start),
+            _ => Err(Status::invalid_argument(
+                "First message must be a PortForwardStart message.",
+            )),
+        },
+        Some(Err(e)) => Err(e),
+        None => Err(Status::invalid_argument(
+            "Client disconnected before sending PortForwardStart message.",
+        )),
+    }
+}
+
 impl Dispatcher {
+    /// `admission` tracks the host resources committed to containers and
+    /// other workloads, `maintenance` whether the host takes new ones and
+    /// when to drain the containers, `startup` what containers wait for when
+    /// they are started by FeOS itself. `secrets` holds the secrets
+    /// containers reference.
     pub async fn new(
         rx: mpsc::Receiver<Command>,
         db_url: &str,
+        snapshotter: Arc<dyn Snapshotter>,
+        admission: AdmissionController,
+        maintenance: Maintenance,
+        startup: StartupOrder,
+        secrets: SecretStore,
     ) -> Result<Self, ContainerServiceError> {
         info!("Dispatcher: Connecting to persistence layer at {db_url}...");
         let repository = ContainerRepository::connect(db_url).await?;
         info!("Dispatcher: Persistence layer connected successfully.");
-        let adapter = Arc::new(ContainerAdapter::new());
+        let adapter = Arc::new(ContainerAdapter::new(snapshotter, secrets));
+        let (event_tx, _) = broadcast::channel(32);
         Ok(Self {
             rx,
             repository,
             adapter,
+            event_tx,
+            admission,
+            drain_rx: maintenance.register_drainer(),
+            maintenance,
+            startup,
         })
     }
 
     pub async fn run(mut self) {
+        tokio::spawn(worker::track_running(
+            self.startup.clone(),
+            self.event_tx.subscribe(),
+        ));
+        tokio::spawn(worker::record_events(
+            self.repository.clone(),
+            self.event_tx.subscribe(),
+        ));
+        tokio::spawn(oom::watch_oom_kills(
+            self.repository.clone(),
+            self.event_tx.clone(),
+        ));
+        match self.repository.list_all_containers().await {
+            Ok(records) => {
+                for record in &records {
+                    self.admission.restore(
+                        WorkloadKind::Container,
+                        &record.container_id.to_string(),
+                        container_resources(&record.config),
+                    );
+                    // Dependencies were checked when the container was
+                    // created.
+                    self.startup.restore(
+                        WorkloadRef::new(WorkloadKind::Container, record.container_id.to_string()),
+                        startup_dependencies(&record.config).unwrap_or_default(),
+                    );
+                }
+                for record in records {
+                    self.restart_if_gone(record);
+                }
+            }
+            Err(e) => warn!("Dispatcher: Failed to restore committed container resources: {e}"),
+        }
+
         info!("Dispatcher: Running and waiting for commands.");
-        while let Some(cmd) = self.rx.recv().await {
-            let repo = self.repository.clone();
-            let adapter = self.adapter.clone();
-            tokio::spawn(async move {
-                if let Err(e) = Self::handle_command(cmd, repo, adapter).await {
-                    warn!("Dispatcher: Error handling command: {e}");
+        loop {
+            tokio::select! {
+                cmd = self.rx.recv() => {
+                    let Some(cmd) = cmd else {
+                        break;
+                    };
+                    let repo = self.repository.clone();
+                    let adapter = self.adapter.clone();
+                    let event_tx = self.event_tx.clone();
+                    let admission = self.admission.clone();
+                    let maintenance = self.maintenance.clone();
+                    let startup = self.startup.clone();
+                    tokio::spawn(async move {
+                        let result = Self::handle_command(
+                            cmd,
+                            repo,
+                            adapter,
+                            event_tx,
+                            admission,
+                            maintenance,
+                            startup,
+                        )
+                        .await;
+                        if let Err(e) = result {
+                            warn!("Dispatcher: Error handling command: {e}");
+                        }
+                    });
+                }
+                Some(job) = self.drain_rx.recv() => {
+                    tokio::spawn(drain_containers(
+                        job,
+                        self.repository.clone(),
+                        self.adapter.clone(),
+                        self.event_tx.clone(),
+                    ));
                 }
-            });
+            }
         }
         info!("Dispatcher: Channel closed, shutting down.");
     }
 
+    /// Marks a container from before FeOS started as running if its process
+    /// is still there, or starts it again if it asked for that.
+    fn restart_if_gone(&self, record: ContainerRecord) {
+        if record.status.state != ContainerState::Running {
+            return;
+        }
+        let workload = WorkloadRef::new(WorkloadKind::Container, record.container_id.to_string());
+        if process_alive(record.status.process_id) {
+            self.startup.set_running(&workload, true);
+        } else if record.config.startup.as_ref().is_some_and(|s| s.auto_start) {
+            info!(
+                "Dispatcher: Process of container {} is gone, starting it again.",
+                record.container_id
+            );
+            tokio::spawn(worker::recover_container(
+                record,
+                self.startup.clone(),
+                self.repository.clone(),
+                self.adapter.clone(),
+                self.event_tx.clone(),
+            ));
+        }
+    }
+
     async fn get_container_record(
         repo: &ContainerRepository,
         id_str: &str,
@@ -106,13 +370,170 @@ impl Dispatcher {
         })
     }
 
+    async fn list_container_events(
+        repo: &ContainerRepository,
+        req: ListContainerEventsRequest,
+    ) -> Result<ListContainerEventsResponse, ContainerServiceError> {
+        let container_id = req
+            .container_id
+            .as_deref()
+            .map(Uuid::parse_str)
+            .transpose()
+            .map_err(|_| {
+                ContainerServiceError::InvalidArgument("Invalid UUID format".to_string())
+            })?;
+        let to_system_time = |timestamp: Timestamp| {
+            SystemTime::try_from(timestamp)
+                .map_err(|e| ContainerServiceError::InvalidArgument(format!("Invalid time: {e}")))
+        };
+        let filter = ContainerEventFilter {
+            container_id,
+            namespace: req.namespace,
+            since: req.since.map(to_system_time).transpose()?,
+            until: req.until.map(to_system_time).transpose()?,
+            limit: match req.limit {
+                0 => DEFAULT_CONTAINER_EVENTS_LIMIT,
+                limit => limit,
+            },
+        };
+        let events = repo
+            .list_container_events(&filter)
+            .await?
+            .into_iter()
+            .map(|(event, recorded_at)| RecordedContainerEvent {
+                event: Some(event),
+                recorded_at: Some(recorded_at.into()),
+            })
+            .collect();
+        Ok(ListContainerEventsResponse { events })
+    }
+
+    async fn replay_container_state_journal(
+        repo: &ContainerRepository,
+        req: ReplayContainerStateJournalRequest,
+    ) -> Result<ReplayContainerStateJournalResponse, ContainerServiceError> {
+        let container_id = req
+            .container_id
+            .as_deref()
+            .map(Uuid::parse_str)
+            .transpose()
+            .map_err(|_| {
+                ContainerServiceError::InvalidArgument("Invalid UUID format".to_string())
+            })?;
+        let after_sequence = i64::try_from(req.after_sequence).map_err(|_| {
+            ContainerServiceError::InvalidArgument("after_sequence is out of range".to_string())
+        })?;
+        let limit = match req.limit {
+            0 => DEFAULT_JOURNAL_ENTRIES_LIMIT,
+            limit => limit,
+        };
+        let entries = repo
+            .replay_container_state_journal(container_id, after_sequence, i64::from(limit))
+            .await?
+            .into_iter()
+            .map(journal_entry_to_proto)
+            .collect();
+        Ok(ReplayContainerStateJournalResponse { entries })
+    }
+
+    /// The namespace a new container is created in, after checking that its
+    /// name is not taken there.
+    async fn container_namespace(
+        repo: &ContainerRepository,
+        req: &CreateContainerRequest,
+    ) -> Result<String, ContainerServiceError> {
+        let namespace = namespace_or_default(&req.namespace)
+            .map_err(|e| ContainerServiceError::InvalidArgument(e.to_string()))?;
+        if let Some(name) = req.name.as_deref() {
+            validate_name(name)
+                .map_err(|e| ContainerServiceError::InvalidArgument(e.to_string()))?;
+            if repo.container_name_taken(&namespace, name).await? {
+                return Err(PersistenceError::NameTaken(format!(
+                    "A container named '{name}' already exists in namespace '{namespace}'"
+                ))
+                .into());
+            }
+        }
+        Ok(namespace)
+    }
+
+    async fn handle_stream_container_events(
+        repository: &ContainerRepository,
+        req: StreamContainerEventsRequest,
+        stream_tx: mpsc::Sender<Result<ContainerEvent, Status>>,
+        event_tx: broadcast::Sender<ContainerEvent>,
+    ) {
+        // Subscribe before reading the current states, so that no state change
+        // happening in between is lost.
+        let event_rx = event_tx.subscribe();
+
+        let records = match (&req.container_id, &req.namespace) {
+            (Some(id_str), namespace) => Self::get_container_record(repository, id_str)
+                .await
+                .and_then(|rec| match namespace {
+                    Some(namespace) if rec.namespace != *namespace => {
+                        Err(ContainerServiceError::InvalidArgument(format!(
+                            "Container '{id_str}' not found"
+                        )))
+                    }
+                    _ => Ok(vec![rec]),
+                }),
+            (None, Some(namespace)) => repository
+                .list_containers_in_namespace(namespace)
+                .await
+                .map_err(ContainerServiceError::Persistence),
+            (None, None) => repository
+                .list_all_containers()
+                .await
+                .map_err(ContainerServiceError::Persistence),
+        };
+
+        let records = match records {
+            Ok(records) => records,
+            Err(e) => {
+                if stream_tx.send(Err(e.into())).await.is_err() {
+                    warn!("StreamEvents: Client disconnected before error could be sent.");
+                }
+                return;
+            }
+        };
+
+        for record in records {
+            let initial_event = worker::state_change_event(
+                &record.container_id.to_string(),
+                record.status.state,
+                "Initial state from DB",
+            );
+            if stream_tx.send(Ok(initial_event)).await.is_err() {
+                info!("StreamEvents: Client disconnected while sending initial states.");
+                return;
+            }
+        }
+
+        tokio::spawn(worker::handle_stream_container_events(
+            req,
+            stream_tx,
+            event_rx,
+            repository.clone(),
+        ));
+    }
+
     async fn handle_command(
         cmd: Command,
         repository: ContainerRepository,
         adapter: Arc<ContainerAdapter>,
+        event_tx: broadcast::Sender<ContainerEvent>,
+        admission: AdmissionController,
+        maintenance: Maintenance,
+        startup: StartupOrder,
     ) -> Result<(), ContainerServiceError> {
         match cmd {
-            Command::CreateContainer(req, responder) => {
+            Command::CreateContainer(req, mut responder) => {
+                if let Err(e) = maintenance.check_schedulable(WorkloadKind::Container) {
+                    let _ = responder.send(Err(e.into()));
+                    return Ok(());
+                }
+
                 let container_id =
                     if let Some(id_str) = req.container_id.as_deref().filter(|s| !s.is_empty()) {
                         Uuid::parse_str(id_str).map_err(|_| {
@@ -131,44 +552,122 @@ impl Dispatcher {
                     return Ok(());
                 }
 
+                let namespace = match Self::container_namespace(&repository, &req).await {
+                    Ok(namespace) => namespace,
+                    Err(e) => {
+                        let _ = responder.send(Err(e));
+                        return Ok(());
+                    }
+                };
+
                 let config = req.config.clone().ok_or_else(|| {
                     ContainerServiceError::InvalidArgument(
                         "ContainerConfig is required".to_string(),
                     )
                 })?;
-                let image_ref = config.image_ref.clone();
+                snapshotter::validate_disk_limit(config.disk_limit_bytes)
+                    .map_err(ContainerServiceError::InvalidArgument)?;
+                if let Err(e) = validate_config(&config) {
+                    let _ = responder.send(Err(e));
+                    return Ok(());
+                }
+                let after = match startup_dependencies(&config) {
+                    Ok(after) => after,
+                    Err(e) => {
+                        let _ = responder.send(Err(e));
+                        return Ok(());
+                    }
+                };
+                let admitted = match admission.admit(
+                    WorkloadKind::Container,
+                    &container_id.to_string(),
+                    container_resources(&config),
+                ) {
+                    Ok(admitted) => admitted,
+                    Err(e) => {
+                        let _ = responder.send(Err(e.into()));
+                        return Ok(());
+                    }
+                };
+                let workload = WorkloadRef::new(WorkloadKind::Container, container_id.to_string());
+                if let Err(e) = startup.register(workload.clone(), after) {
+                    let err = ContainerServiceError::InvalidArgument(e.to_string());
+                    let _ = responder.send(Err(err));
+                    return Ok(());
+                }
 
-                let image_uuid_str = initiate_image_pull(&image_ref).await?;
-                let image_uuid = Uuid::parse_str(&image_uuid_str).map_err(|e| {
-                    ContainerServiceError::ImageService(format!("Invalid image UUID: {e}"))
-                })?;
+                // Deleting the container cancels its creation from when it
+                // is registered.
+                let cancelled = adapter.cancelled_creations();
+                // The client going away, e.g. when its deadline passes,
+                // cancels the creation up to the point it is told the ID of
+                // the container.
+                let registration = async {
+                    let image_uuid_str = initiate_image_pull(&config.image_ref).await?;
+                    let image_uuid = Uuid::parse_str(&image_uuid_str).map_err(|e| {
+                        ContainerServiceError::ImageService(format!("Invalid image UUID: {e}"))
+                    })?;
 
-                let record = crate::persistence::ContainerRecord {
-                    container_id,
-                    image_uuid,
-                    status: crate::persistence::ContainerStatus {
-                        state: ContainerState::PullingImage,
-                        process_id: None,
-                    },
-                    config,
+                    let record = crate::persistence::ContainerRecord {
+                        container_id,
+                        namespace,
+                        name: req.name.clone(),
+                        image_uuid,
+                        status: crate::persistence::ContainerStatus {
+                            state: ContainerState::PullingImage,
+                            ..Default::default()
+                        },
+                        config,
+                    };
+                    repository.save_container(&record).await?;
+                    Ok::<_, ContainerServiceError>(record)
+                };
+                let registered = tokio::select! {
+                    registered = registration => Some(registered),
+                    () = responder.closed() => None,
+                };
+                let record = match registered {
+                    Some(Ok(record)) => record,
+                    Some(Err(e)) => {
+                        startup.remove(&workload);
+                        return Err(e);
+                    }
+                    None => {
+                        warn!("Dispatcher: CreateContainer for {container_id} was cancelled by the client.");
+                        // The record may have been saved just before.
+                        if let Err(e) = repository.delete_container(container_id).await {
+                            warn!("Failed to cleanup initial DB record for cancelled creation of {container_id}: {e}");
+                        }
+                        startup.remove(&workload);
+                        return Ok(());
+                    }
                 };
-                repository.save_container(&record).await?;
-
-                tokio::spawn(worker::handle_create_container(
-                    container_id,
-                    image_uuid,
-                    image_ref,
-                    responder,
-                    repository.clone(),
-                    adapter.clone(),
-                ));
+                worker::broadcast_state_change(
+                    &event_tx,
+                    &container_id.to_string(),
+                    ContainerState::PullingImage,
+                    "Image pull initiated",
+                );
+
+                tokio::spawn(async move {
+                    let created = worker::handle_create_container(
+                        record, responder, repository, adapter, event_tx, cancelled,
+                    )
+                    .await;
+                    // Failed creations remove the container again.
+                    if created {
+                        admitted.keep();
+                    } else {
+                        startup.remove(&workload);
+                    }
+                });
             }
             Command::StartContainer(req, responder) => {
                 let record = Self::get_container_record(&repository, &req.container_id).await;
                 match record {
                     Ok(rec) if rec.status.state == ContainerState::Created => {
                         tokio::spawn(worker::handle_start_container(
-                            req, responder, repository, adapter,
+                            req, responder, repository, adapter, event_tx,
                         ));
                     }
                     Ok(rec) => {
@@ -187,7 +686,7 @@ impl Dispatcher {
                 match record {
                     Ok(rec) if rec.status.state == ContainerState::Running => {
                         tokio::spawn(worker::handle_stop_container(
-                            req, responder, repository, adapter,
+                            req, rec, responder, repository, adapter, event_tx,
                         ));
                     }
                     Ok(rec) => {
@@ -206,7 +705,7 @@ impl Dispatcher {
                 match record {
                     Ok(rec) if rec.status.state != ContainerState::Running => {
                         tokio::spawn(worker::handle_delete_container(
-                            req, responder, repository, adapter,
+                            req, rec, responder, repository, adapter, admission, startup,
                         ));
                     }
                     Ok(rec) => {
@@ -223,35 +722,86 @@ impl Dispatcher {
             Command::GetContainer(req, responder) => {
                 let result = Self::get_container_record(&repository, &req.container_id)
                     .await
-                    .map(|rec| ContainerInfo {
-                        container_id: rec.container_id.to_string(),
-                        state: rec.status.state as i32,
-                        config: Some(rec.config),
-                        pid: rec.status.process_id,
-                        exit_code: None, // This would require waiting for the process
-                    });
+                    .map(container_info);
                 let _ = responder.send(result);
             }
-            Command::ListContainers(_req, responder) => {
-                let result = repository
-                    .list_all_containers()
-                    .await
-                    .map(|records| {
-                        let containers = records
-                            .into_iter()
-                            .map(|rec| ContainerInfo {
-                                container_id: rec.container_id.to_string(),
-                                state: rec.status.state as i32,
-                                config: Some(rec.config),
-                                pid: rec.status.process_id,
-                                exit_code: None,
-                            })
-                            .collect();
-                        ListContainersResponse { containers }
+            Command::ListContainers(req, responder) => {
+                let records = match req.namespace.as_deref() {
+                    Some(namespace) => repository.list_containers_in_namespace(namespace).await,
+                    None => repository.list_all_containers().await,
+                };
+                let result = records
+                    .map(|records| ListContainersResponse {
+                        containers: records.into_iter().map(container_info).collect(),
                     })
                     .map_err(ContainerServiceError::Persistence);
                 let _ = responder.send(result);
             }
+            Command::ListContainerEvents(req, responder) => {
+                let _ = responder.send(Self::list_container_events(&repository, req).await);
+            }
+            Command::ReplayContainerStateJournal(req, responder) => {
+                let _ =
+                    responder.send(Self::replay_container_state_journal(&repository, req).await);
+            }
+            Command::StreamContainerEvents(req, stream_tx) => {
+                Self::handle_stream_container_events(&repository, req, stream_tx, event_tx).await;
+            }
+            Command::ExecContainer(mut input_stream, output_tx) => {
+                let start = match get_exec_start(&mut input_stream).await {
+                    Ok(start) => start,
+                    Err(status) => {
+                        let _ = output_tx.send(Err(status)).await;
+                        return Ok(());
+                    }
+                };
+                let record = Self::get_container_record(&repository, &start.container_id).await;
+                match record {
+                    Ok(rec) if rec.status.state == ContainerState::Running => {
+                        tokio::spawn(worker::handle_exec_container(
+                            start,
+                            *input_stream,
+                            output_tx,
+                            adapter,
+                        ));
+                    }
+                    Ok(rec) => {
+                        let err = ContainerServiceError::InvalidState(format!(
+                            "Cannot exec in container in state {:?}. Must be in Running.",
+                            rec.status.state
+                        ));
+                        let _ = output_tx.send(Err(err.into())).await;
+                    }
+                    Err(e) => {
+                        let _ = output_tx.send(Err(e.into())).await;
+                    }
+                }
+            }
+            Command::PortForward(mut input_stream, output_tx) => {
+                let start = match get_port_forward_start(&mut input_stream).await {
+                    Ok(start) => start,
+                    Err(status) => {
+                        let _ = output_tx.send(Err(status)).await;
+                        return Ok(());
+                    }
+                };
+                let record = Self::get_container_record(&repository, &start.container_id).await;
+                match record {
+                    Ok(rec) if rec.status.state == ContainerState::Running => {
+                        tokio::spawn(worker::handle_port_forward(start, *input_stream, output_tx));
+                    }
+                    Ok(rec) => {
+                        let err = ContainerServiceError::InvalidState(format!(
+                            "Cannot forward ports to container in state {:?}. Must be in Running.",
+                            rec.status.state
+                        ));
+                        let _ = output_tx.send(Err(err.into())).await;
+                    }
+                    Err(e) => {
+                        let _ = output_tx.send(Err(e.into())).await;
+                    }
+                }
+            }
         }
         Ok(())
     }
@@ -0,0 +1,126 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    persistence::{repository::ContainerRepository, ContainerRecord},
+    runtime::adapter::ContainerAdapter,
+    worker,
+};
+use feos_proto::container_service::{ContainerEvent, ContainerState, DrainPolicy};
+use feos_utils::host::admission::WorkloadKind;
+use feos_utils::host::maintenance::{DrainEvent, DrainJob, DrainOutcome, DrainedWorkload};
+use log::{error, info, warn};
+use nix::sys::signal::Signal;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::task::JoinSet;
+
+/// Stops all running containers according to their drain policy,
+/// concurrently, and reports each of them on the job.
+pub(crate) async fn drain_containers(
+    job: DrainJob,
+    repository: ContainerRepository,
+    adapter: Arc<ContainerAdapter>,
+    event_tx: broadcast::Sender<ContainerEvent>,
+) {
+    let records = match repository.list_all_containers().await {
+        Ok(records) => records,
+        Err(e) => {
+            error!("ContainerDrain: Failed to list containers to drain: {e}");
+            return;
+        }
+    };
+    let containers: Vec<ContainerRecord> = records
+        .into_iter()
+        .filter(|record| record.status.state == ContainerState::Running)
+        .collect();
+    info!("ContainerDrain: Draining {} containers.", containers.len());
+    if job
+        .events
+        .send(DrainEvent::Planned(containers.len()))
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    let mut drains = JoinSet::new();
+    for record in containers {
+        drains.spawn(drain_container(
+            record,
+            job.grace_period,
+            repository.clone(),
+            adapter.clone(),
+            event_tx.clone(),
+        ));
+    }
+    while let Some(drained) = drains.join_next().await {
+        match drained {
+            Ok(drained) => {
+                let _ = job.events.send(DrainEvent::Drained(drained)).await;
+            }
+            Err(e) => error!("ContainerDrain: A drain task failed: {e}"),
+        }
+    }
+}
+
+async fn drain_container(
+    record: ContainerRecord,
+    grace_period: Duration,
+    repository: ContainerRepository,
+    adapter: Arc<ContainerAdapter>,
+    event_tx: broadcast::Sender<ContainerEvent>,
+) -> DrainedWorkload {
+    let id = record.container_id.to_string();
+    let drained = |outcome, message: String| DrainedWorkload {
+        kind: WorkloadKind::Container,
+        id: id.clone(),
+        outcome,
+        message,
+    };
+    let outcome = match record.config.drain_policy() {
+        DrainPolicy::Keep => return drained(DrainOutcome::Kept, String::new()),
+        DrainPolicy::Stop => adapter
+            .stop_container(&id, Signal::SIGKILL as u32)
+            .await
+            .map(|()| DrainOutcome::Stopped),
+        DrainPolicy::Unspecified | DrainPolicy::Shutdown => worker::stop_with_timeout(
+            &id,
+            record.status.process_id,
+            worker::stop_signal(&record.config),
+            grace_period,
+            &adapter,
+        )
+        .await
+        .map(|killed| {
+            if killed {
+                DrainOutcome::ForceStopped
+            } else {
+                DrainOutcome::Stopped
+            }
+        }),
+    };
+    let outcome = match outcome {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            warn!("ContainerDrain ({id}): Failed to stop container: {e}");
+            return drained(DrainOutcome::Failed, e.to_string());
+        }
+    };
+
+    if let Err(e) = repository
+        .update_container_state(record.container_id, ContainerState::Stopped)
+        .await
+    {
+        error!("ContainerDrain ({id}): Failed to save stopped state: {e}");
+        return drained(DrainOutcome::Failed, e.to_string());
+    }
+    worker::broadcast_state_change(
+        &event_tx,
+        &id,
+        ContainerState::Stopped,
+        "Stopped to drain the host",
+    );
+    drained(outcome, String::new())
+}
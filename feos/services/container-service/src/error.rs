@@ -2,6 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::persistence::PersistenceError;
+use feos_utils::host::admission::AdmissionError;
+use feos_utils::host::maintenance::Cordoned;
 use tonic::Status;
 
 #[derive(Debug, thiserror::Error)]
@@ -26,6 +28,12 @@ pub enum ContainerServiceError {
 
     #[error("Invalid container state for operation: {0}")]
     InvalidState(String),
+
+    #[error("Host overcommit limit reached: {0}")]
+    AdmissionRejected(#[from] AdmissionError),
+
+    #[error("{0}")]
+    Cordoned(#[from] Cordoned),
 }
 
 impl From<ContainerServiceError> for Status {
@@ -37,6 +45,9 @@ impl From<ContainerServiceError> for Status {
             {
                 Status::not_found("Record not found in database")
             }
+            ContainerServiceError::Persistence(PersistenceError::NameTaken(msg)) => {
+                Status::already_exists(msg)
+            }
             ContainerServiceError::Persistence(_) => Status::internal("A database error occurred"),
             ContainerServiceError::ImageService(msg) => {
                 Status::unavailable(format!("Image service unavailable: {msg}"))
@@ -50,6 +61,10 @@ impl From<ContainerServiceError> for Status {
             ContainerServiceError::InvalidArgument(msg) => Status::invalid_argument(msg),
             ContainerServiceError::AlreadyExists(msg) => Status::already_exists(msg),
             ContainerServiceError::InvalidState(msg) => Status::failed_precondition(msg),
+            ContainerServiceError::AdmissionRejected(e) => {
+                Status::resource_exhausted(format!("Host overcommit limit reached: {e}"))
+            }
+            ContainerServiceError::Cordoned(e) => Status::unavailable(e.to_string()),
         }
     }
 }
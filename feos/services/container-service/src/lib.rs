@@ -3,20 +3,30 @@
 
 use crate::error::ContainerServiceError;
 use feos_proto::container_service::{
-    ContainerInfo, CreateContainerRequest, CreateContainerResponse, DeleteContainerRequest,
-    DeleteContainerResponse, GetContainerRequest, ListContainersRequest, ListContainersResponse,
-    StartContainerRequest, StartContainerResponse, StopContainerRequest, StopContainerResponse,
+    ContainerEvent, ContainerInfo, CreateContainerRequest, CreateContainerResponse,
+    DeleteContainerRequest, DeleteContainerResponse, ExecContainerRequest, ExecContainerResponse,
+    GetContainerRequest, ListContainerEventsRequest, ListContainerEventsResponse,
+    ListContainersRequest, ListContainersResponse, PortForwardRequest, PortForwardResponse,
+    ReplayContainerStateJournalRequest, ReplayContainerStateJournalResponse, StartContainerRequest,
+    StartContainerResponse, StopContainerRequest, StopContainerResponse,
+    StreamContainerEventsRequest,
 };
-use tokio::sync::oneshot;
+use tokio::sync::{mpsc, oneshot};
+use tonic::{Status, Streaming};
 
 pub mod api;
 pub mod dispatcher;
+pub mod drain;
 pub mod error;
+pub mod oom;
 pub mod persistence;
 pub mod runtime;
 pub mod worker;
 
 pub const DEFAULT_CONTAINER_DB_URL: &str = "sqlite:/var/lib/feos/containers.db";
+pub const CONTAINER_DIR: &str = "/var/lib/feos/containers";
+/// The cgroup holding the cgroups of all containers, below the cgroup root.
+pub const CONTAINER_CGROUP: &str = "feos-containers";
 
 pub enum Command {
     CreateContainer(
@@ -43,6 +53,26 @@ pub enum Command {
         DeleteContainerRequest,
         oneshot::Sender<Result<DeleteContainerResponse, ContainerServiceError>>,
     ),
+    StreamContainerEvents(
+        StreamContainerEventsRequest,
+        mpsc::Sender<Result<ContainerEvent, Status>>,
+    ),
+    ListContainerEvents(
+        ListContainerEventsRequest,
+        oneshot::Sender<Result<ListContainerEventsResponse, ContainerServiceError>>,
+    ),
+    ReplayContainerStateJournal(
+        ReplayContainerStateJournalRequest,
+        oneshot::Sender<Result<ReplayContainerStateJournalResponse, ContainerServiceError>>,
+    ),
+    ExecContainer(
+        Box<Streaming<ExecContainerRequest>>,
+        mpsc::Sender<Result<ExecContainerResponse, Status>>,
+    ),
+    PortForward(
+        Box<Streaming<PortForwardRequest>>,
+        mpsc::Sender<Result<PortForwardResponse, Status>>,
+    ),
 }
 
 impl std::fmt::Debug for Command {
@@ -58,6 +88,20 @@ impl std::fmt::Debug for Command {
             Command::DeleteContainer(req, _) => {
                 f.debug_tuple("DeleteContainer").field(req).finish()
             }
+            Command::StreamContainerEvents(req, _) => {
+                f.debug_tuple("StreamContainerEvents").field(req).finish()
+            }
+            Command::ListContainerEvents(req, _) => {
+                f.debug_tuple("ListContainerEvents").field(req).finish()
+            }
+            Command::ReplayContainerStateJournal(req, _) => f
+                .debug_tuple("ReplayContainerStateJournal")
+                .field(req)
+                .finish(),
+            Command::ExecContainer(_, _) => {
+                f.write_str("ExecContainer(<gRPC Stream>, <mpsc::Sender>)")
+            }
+            Command::PortForward(_, _) => f.write_str("PortForward(<gRPC Stream>, <mpsc::Sender>)"),
         }
     }
 }
@@ -0,0 +1,200 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    dispatcher::process_alive,
+    persistence::{repository::ContainerRepository, ContainerRecord},
+    worker, CONTAINER_CGROUP,
+};
+use feos_proto::container_service::{ContainerEvent, ContainerOomEvent, ContainerState};
+use log::{debug, error, info, warn};
+use prost::Message;
+use prost_types::Any;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::fs;
+use tokio::sync::broadcast;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+const OOM_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long after an OOM kill the exit of a container's process is put down
+/// to it.
+const OOM_EXIT_GRACE: Duration = Duration::from_secs(3);
+
+fn memory_events_path(container_id: &str) -> PathBuf {
+    Path::new(CGROUP_ROOT)
+        .join(CONTAINER_CGROUP)
+        .join(container_id)
+        .join("memory.events")
+}
+
+/// The `oom_kill` counter of a cgroup's `memory.events`.
+fn parse_oom_kills(events: &str) -> Option<u32> {
+    events
+        .lines()
+        .find_map(|line| line.strip_prefix("oom_kill ")?.trim().parse().ok())
+}
+
+fn oom_event(container_id: &str, oom_kill_count: u32) -> ContainerEvent {
+    let data = ContainerOomEvent { oom_kill_count };
+    ContainerEvent {
+        container_id: container_id.to_string(),
+        id: Uuid::new_v4().to_string(),
+        data: Some(Any {
+            type_url: "type.googleapis.com/feos.container.v1.ContainerOomEvent".to_string(),
+            value: data.encode_to_vec(),
+        }),
+        node_id: String::new(),
+    }
+}
+
+/// The OOM kills in the cgroup of a container, `None` if it has none.
+async fn read_oom_kills(container_id: &str) -> Option<u32> {
+    match fs::read_to_string(memory_events_path(container_id)).await {
+        Ok(events) => parse_oom_kills(&events),
+        // Containers created before they got a cgroup of their own cannot be
+        // watched.
+        Err(e) if e.kind() == ErrorKind::NotFound => None,
+        Err(e) => {
+            warn!("ContainerOom ({container_id}): Failed to read memory events: {e}");
+            None
+        }
+    }
+}
+
+/// Whether the OOM killer killed the process of a container that just exited,
+/// which is then recorded.
+pub(crate) async fn record_oom_exit(
+    record: &ContainerRecord,
+    repository: &ContainerRepository,
+    event_tx: &broadcast::Sender<ContainerEvent>,
+) -> bool {
+    let id = record.container_id.to_string();
+    let Some(count) = read_oom_kills(&id).await else {
+        return false;
+    };
+    if count <= record.status.oom_kill_count {
+        return false;
+    }
+    if let Err(e) = repository
+        .update_container_oom_kills(record.container_id, count, true)
+        .await
+    {
+        error!("ContainerOom ({id}): Failed to save OOM kills: {e}");
+    }
+    if event_tx.send(oom_event(&id, count)).is_err() {
+        debug!("ContainerOom: No active event subscribers for container {id}.");
+    }
+    warn!("ContainerOom ({id}): Container process was OOM-killed.");
+    true
+}
+
+/// Watches the cgroups of running containers for OOM kills, records them and
+/// stops the containers whose own process was killed.
+pub(crate) async fn watch_oom_kills(
+    repository: ContainerRepository,
+    event_tx: broadcast::Sender<ContainerEvent>,
+) {
+    // Containers with new OOM kills whose process was still there, with the
+    // time until its exit counts as OOM-killed.
+    let mut suspects: HashMap<Uuid, Instant> = HashMap::new();
+    let mut interval = tokio::time::interval(OOM_POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        let records = match repository.list_all_containers().await {
+            Ok(records) => records,
+            Err(e) => {
+                warn!("ContainerOom: Failed to list containers: {e}");
+                continue;
+            }
+        };
+        let now = Instant::now();
+        suspects.retain(|_, deadline| *deadline > now);
+        // Containers that just exited may have been killed after the last
+        // look at their cgroup.
+        let recently = SystemTime::now() - OOM_EXIT_GRACE;
+        for record in records.into_iter().filter(|record| {
+            record.status.state == ContainerState::Running
+                || record.status.finished_at.is_some_and(|at| at > recently)
+        }) {
+            check_container(record, &mut suspects, &repository, &event_tx).await;
+        }
+    }
+}
+
+async fn check_container(
+    record: ContainerRecord,
+    suspects: &mut HashMap<Uuid, Instant>,
+    repository: &ContainerRepository,
+    event_tx: &broadcast::Sender<ContainerEvent>,
+) {
+    let id = record.container_id.to_string();
+    let Some(count) = read_oom_kills(&id).await else {
+        return;
+    };
+
+    let known = record.status.oom_kill_count;
+    // The cgroup counts anew when the container is created again.
+    let new_kills = if count >= known { count - known } else { count };
+    if new_kills > 0 {
+        info!("ContainerOom ({id}): OOM killer killed {new_kills} processes, {count} in total.");
+        suspects.insert(record.container_id, Instant::now() + OOM_EXIT_GRACE);
+    }
+    let killed =
+        suspects.contains_key(&record.container_id) && !process_alive(record.status.process_id);
+    if count == known && !killed {
+        return;
+    }
+
+    if let Err(e) = repository
+        .update_container_oom_kills(record.container_id, count, killed)
+        .await
+    {
+        error!("ContainerOom ({id}): Failed to save OOM kills: {e}");
+        return;
+    }
+    if new_kills > 0 && event_tx.send(oom_event(&id, count)).is_err() {
+        debug!("ContainerOom: No active event subscribers for container {id}.");
+    }
+    if !killed {
+        return;
+    }
+
+    suspects.remove(&record.container_id);
+    warn!("ContainerOom ({id}): Container process was OOM-killed.");
+    // The container may have been marked stopped when its process exited.
+    if record.status.state != ContainerState::Running {
+        return;
+    }
+    if let Err(e) = repository
+        .update_container_state(record.container_id, ContainerState::Stopped)
+        .await
+    {
+        error!("ContainerOom ({id}): Failed to save stopped state: {e}");
+        return;
+    }
+    worker::broadcast_state_change(
+        event_tx,
+        &id,
+        ContainerState::Stopped,
+        "Killed by the OOM killer",
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oom_kills_are_read_from_memory_events() {
+        let events = "low 0\nhigh 0\nmax 12\noom 3\noom_kill 2\noom_group_kill 0\n";
+        assert_eq!(parse_oom_kills(events), Some(2));
+        assert_eq!(parse_oom_kills("low 0\nhigh 0\n"), None);
+    }
+}
@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use feos_proto::container_service::{ContainerConfig, ContainerState};
+use std::time::SystemTime;
 use uuid::Uuid;
 
 pub mod repository;
@@ -22,17 +23,32 @@ pub enum PersistenceError {
 
     #[error("Invalid state string '{0}' in database")]
     InvalidStateString(String),
+
+    #[error("{0}")]
+    NameTaken(String),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct ContainerStatus {
     pub state: ContainerState,
     pub process_id: Option<i64>,
+    /// How often the OOM killer hit a process of the container.
+    pub oom_kill_count: u32,
+    /// Whether the container stopped because its process was OOM-killed.
+    pub oom_killed: bool,
+    pub started_at: Option<SystemTime>,
+    /// When the process exited, `None` while it runs.
+    pub finished_at: Option<SystemTime>,
+    pub exit_code: Option<i32>,
+    /// The signal that killed the process, if any.
+    pub exit_signal: Option<i32>,
 }
 
 #[derive(Debug, Clone)]
 pub struct ContainerRecord {
     pub container_id: Uuid,
+    pub namespace: String,
+    pub name: Option<String>,
     pub image_uuid: Uuid,
     pub status: ContainerStatus,
     pub config: ContainerConfig,
@@ -2,10 +2,12 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::persistence::{ContainerRecord, ContainerStatus, PersistenceError};
-use feos_proto::container_service::{ContainerConfig, ContainerState};
+use feos_proto::container_service::{ContainerConfig, ContainerEvent, ContainerState};
+use feos_utils::sqlite::{connect_pool, retry_busy};
 use log::info;
 use prost::Message;
-use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+use sqlx::sqlite::SqlitePool;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 
 #[derive(Clone)]
@@ -16,10 +18,117 @@ pub struct ContainerRepository {
 #[derive(sqlx::FromRow, Debug)]
 struct DbContainerRow {
     container_id: String,
+    namespace: String,
+    name: Option<String>,
     image_uuid: String,
     state: String,
     pid: Option<i64>,
     config_blob: Vec<u8>,
+    oom_kill_count: i64,
+    oom_killed: bool,
+    started_at: Option<i64>,
+    finished_at: Option<i64>,
+    exit_code: Option<i32>,
+    exit_signal: Option<i32>,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct DbEventRow {
+    recorded_at: i64,
+    event_blob: Vec<u8>,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct DbJournalRow {
+    sequence: i64,
+    container_id: String,
+    namespace: String,
+    recorded_at_ms: i64,
+    old_state: Option<String>,
+    new_state: Option<String>,
+}
+
+/// An entry of the journal of container state transitions.
+#[derive(Debug, Clone)]
+pub struct ContainerJournalEntry {
+    pub sequence: i64,
+    pub container_id: Uuid,
+    pub namespace: String,
+    pub recorded_at: SystemTime,
+    /// The state before, `None` when the container was created.
+    pub old_state: Option<ContainerState>,
+    /// The state after, `None` when the container was deleted.
+    pub new_state: Option<ContainerState>,
+}
+
+/// The most events kept per container. Older ones are dropped first.
+const MAX_EVENTS_PER_CONTAINER: i64 = 1000;
+/// How long events are kept, 30 days.
+const EVENT_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Selects recorded container events.
+#[derive(Debug, Clone)]
+pub struct ContainerEventFilter {
+    pub container_id: Option<Uuid>,
+    pub namespace: Option<String>,
+    /// Only events recorded at or after this time.
+    pub since: Option<SystemTime>,
+    /// Only events recorded before this time.
+    pub until: Option<SystemTime>,
+    /// The most events returned, the newest of those selected.
+    pub limit: u32,
+}
+
+const CONTAINER_COLUMNS: &str = "container_id, namespace, name, image_uuid, state, pid, \
+    config_blob, oom_kill_count, oom_killed, started_at, finished_at, exit_code, exit_signal";
+
+fn to_unix_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |since| {
+        i64::try_from(since.as_millis()).unwrap_or(i64::MAX)
+    })
+}
+
+fn from_unix_millis(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(u64::try_from(millis).unwrap_or_default())
+}
+
+fn container_record_from_row(row: DbContainerRow) -> Result<ContainerRecord, PersistenceError> {
+    Ok(ContainerRecord {
+        container_id: Uuid::parse_str(&row.container_id).unwrap(),
+        namespace: row.namespace,
+        name: row.name,
+        image_uuid: Uuid::parse_str(&row.image_uuid).unwrap(),
+        status: ContainerStatus {
+            state: string_to_container_state(&row.state)?,
+            process_id: row.pid,
+            oom_kill_count: u32::try_from(row.oom_kill_count).unwrap_or(u32::MAX),
+            oom_killed: row.oom_killed,
+            started_at: row.started_at.map(from_unix_millis),
+            finished_at: row.finished_at.map(from_unix_millis),
+            exit_code: row.exit_code,
+            exit_signal: row.exit_signal,
+        },
+        config: ContainerConfig::decode(&*row.config_blob)?,
+    })
+}
+
+fn journal_entry_from_row(row: DbJournalRow) -> Result<ContainerJournalEntry, PersistenceError> {
+    Ok(ContainerJournalEntry {
+        sequence: row.sequence,
+        container_id: Uuid::parse_str(&row.container_id).unwrap(),
+        namespace: row.namespace,
+        recorded_at: from_unix_millis(row.recorded_at_ms),
+        old_state: row
+            .old_state
+            .as_deref()
+            .map(string_to_container_state)
+            .transpose()?,
+        new_state: row
+            .new_state
+            .as_deref()
+            .map(string_to_container_state)
+            .transpose()?,
+    })
 }
 
 fn string_to_container_state(s: &str) -> Result<ContainerState, PersistenceError> {
@@ -28,6 +137,7 @@ fn string_to_container_state(s: &str) -> Result<ContainerState, PersistenceError
         "CREATED" => Ok(ContainerState::Created),
         "RUNNING" => Ok(ContainerState::Running),
         "STOPPED" => Ok(ContainerState::Stopped),
+        "FAILED" => Ok(ContainerState::Failed),
         "CONTAINER_STATE_UNSPECIFIED" => Ok(ContainerState::Unspecified),
         _ => Err(PersistenceError::InvalidStateString(s.to_string())),
     }
@@ -39,16 +149,14 @@ fn container_state_to_string(state: ContainerState) -> &'static str {
         ContainerState::Created => "CREATED",
         ContainerState::Running => "RUNNING",
         ContainerState::Stopped => "STOPPED",
+        ContainerState::Failed => "FAILED",
         ContainerState::Unspecified => "CONTAINER_STATE_UNSPECIFIED",
     }
 }
 
 impl ContainerRepository {
     pub async fn connect(db_url: &str) -> Result<Self, PersistenceError> {
-        let pool = SqlitePoolOptions::new()
-            .max_connections(1)
-            .connect(db_url)
-            .await?;
+        let pool = connect_pool(db_url).await?;
 
         info!("Persistence: Running container-service database migrations...");
         sqlx::migrate!("./migrations").run(&pool).await?;
@@ -61,59 +169,58 @@ impl ContainerRepository {
         &self,
         container_id: Uuid,
     ) -> Result<Option<ContainerRecord>, PersistenceError> {
-        let row_opt = sqlx::query_as::<_, DbContainerRow>(
-            "SELECT container_id, image_uuid, state, pid, config_blob FROM containers WHERE container_id = ?1",
-        )
+        let row_opt = sqlx::query_as::<_, DbContainerRow>(&format!(
+            "SELECT {CONTAINER_COLUMNS} FROM containers WHERE container_id = ?1"
+        ))
         .bind(container_id.to_string())
         .fetch_optional(&self.pool)
         .await?;
 
-        if let Some(row) = row_opt {
-            let config = ContainerConfig::decode(&*row.config_blob)?;
-            let state = string_to_container_state(&row.state)?;
-
-            let record = ContainerRecord {
-                container_id: Uuid::parse_str(&row.container_id).unwrap(),
-                image_uuid: Uuid::parse_str(&row.image_uuid).unwrap(),
-                status: ContainerStatus {
-                    state,
-                    process_id: row.pid,
-                },
-                config,
-            };
-            Ok(Some(record))
-        } else {
-            Ok(None)
-        }
+        row_opt.map(container_record_from_row).transpose()
     }
 
     pub async fn list_all_containers(&self) -> Result<Vec<ContainerRecord>, PersistenceError> {
-        let rows = sqlx::query_as::<_, DbContainerRow>(
-            "SELECT container_id, image_uuid, state, pid, config_blob FROM containers",
-        )
+        let rows = sqlx::query_as::<_, DbContainerRow>(&format!(
+            "SELECT {CONTAINER_COLUMNS} FROM containers"
+        ))
         .fetch_all(&self.pool)
         .await?;
 
-        let mut records = Vec::with_capacity(rows.len());
-        for row in rows {
-            let config = ContainerConfig::decode(&*row.config_blob)?;
-            let state = string_to_container_state(&row.state)?;
-
-            let record = ContainerRecord {
-                container_id: Uuid::parse_str(&row.container_id).unwrap(),
-                image_uuid: Uuid::parse_str(&row.image_uuid).unwrap(),
-                status: ContainerStatus {
-                    state,
-                    process_id: row.pid,
-                },
-                config,
-            };
-            records.push(record);
-        }
+        rows.into_iter().map(container_record_from_row).collect()
+    }
 
-        Ok(records)
+    pub async fn list_containers_in_namespace(
+        &self,
+        namespace: &str,
+    ) -> Result<Vec<ContainerRecord>, PersistenceError> {
+        let rows = sqlx::query_as::<_, DbContainerRow>(&format!(
+            "SELECT {CONTAINER_COLUMNS} FROM containers WHERE namespace = ?1"
+        ))
+        .bind(namespace)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(container_record_from_row).collect()
+    }
+
+    /// Whether a container in `namespace` already has the name `name`.
+    pub async fn container_name_taken(
+        &self,
+        namespace: &str,
+        name: &str,
+    ) -> Result<bool, PersistenceError> {
+        let taken: Option<i64> =
+            sqlx::query_scalar("SELECT 1 FROM containers WHERE namespace = ?1 AND name = ?2")
+                .bind(namespace)
+                .bind(name)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(taken.is_some())
     }
 
+    /// Inserts or updates the record of a container. Fails with `NameTaken`
+    /// if another container of the namespace has the name, instead of
+    /// replacing it.
     pub async fn save_container(
         &self,
         container: &ContainerRecord,
@@ -123,19 +230,38 @@ impl ContainerRepository {
 
         let state_str = container_state_to_string(container.status.state);
 
-        sqlx::query(
-            r#"
-            INSERT OR REPLACE INTO containers (container_id, image_uuid, state, pid, config_blob)
-            VALUES (?1, ?2, ?3, ?4, ?5)
+        retry_busy(|| {
+            sqlx::query(
+                r#"
+            INSERT INTO containers (container_id, namespace, name, image_uuid, state, pid, config_blob)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            ON CONFLICT (container_id) DO UPDATE SET
+                namespace = excluded.namespace,
+                name = excluded.name,
+                image_uuid = excluded.image_uuid,
+                state = excluded.state,
+                pid = excluded.pid,
+                config_blob = excluded.config_blob
             "#,
-        )
-        .bind(container.container_id.to_string())
-        .bind(container.image_uuid.to_string())
-        .bind(state_str)
-        .bind(container.status.process_id)
-        .bind(config_blob)
-        .execute(&self.pool)
-        .await?;
+            )
+            .bind(container.container_id.to_string())
+            .bind(&container.namespace)
+            .bind(&container.name)
+            .bind(container.image_uuid.to_string())
+            .bind(state_str)
+            .bind(container.status.process_id)
+            .bind(&config_blob)
+            .execute(&self.pool)
+        })
+        .await
+        .map_err(|e| match e.as_database_error() {
+            Some(db_err) if db_err.is_unique_violation() => PersistenceError::NameTaken(format!(
+                "A container named '{}' already exists in namespace '{}'",
+                container.name.as_deref().unwrap_or_default(),
+                container.namespace
+            )),
+            _ => e.into(),
+        })?;
 
         Ok(())
     }
@@ -147,16 +273,18 @@ impl ContainerRepository {
     ) -> Result<bool, PersistenceError> {
         let state_str = container_state_to_string(new_state);
 
-        let result = sqlx::query(
-            r#"
+        let result = retry_busy(|| {
+            sqlx::query(
+                r#"
             UPDATE containers
             SET state = ?1
             WHERE container_id = ?2
             "#,
-        )
-        .bind(state_str)
-        .bind(container_id.to_string())
-        .execute(&self.pool)
+            )
+            .bind(state_str)
+            .bind(container_id.to_string())
+            .execute(&self.pool)
+        })
         .await?;
 
         Ok(result.rows_affected() > 0)
@@ -167,19 +295,93 @@ impl ContainerRepository {
         container_id: Uuid,
         pid: i64,
     ) -> Result<(), PersistenceError> {
-        sqlx::query("UPDATE containers SET pid = ?1 WHERE container_id = ?2")
-            .bind(pid)
+        retry_busy(|| {
+            sqlx::query("UPDATE containers SET pid = ?1 WHERE container_id = ?2")
+                .bind(pid)
+                .bind(container_id.to_string())
+                .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Records that a container was started, forgetting how its process
+    /// exited before.
+    pub async fn update_container_started(
+        &self,
+        container_id: Uuid,
+        started_at: SystemTime,
+    ) -> Result<(), PersistenceError> {
+        retry_busy(|| {
+            sqlx::query(
+                r#"
+            UPDATE containers
+            SET started_at = ?1, finished_at = NULL, exit_code = NULL, exit_signal = NULL
+            WHERE container_id = ?2
+            "#,
+            )
+            .bind(to_unix_millis(started_at))
+            .bind(container_id.to_string())
+            .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Records how the process of a container exited.
+    pub async fn update_container_exit(
+        &self,
+        container_id: Uuid,
+        finished_at: SystemTime,
+        exit_code: i32,
+        exit_signal: Option<i32>,
+    ) -> Result<(), PersistenceError> {
+        retry_busy(|| {
+            sqlx::query(
+                r#"
+            UPDATE containers
+            SET finished_at = ?1, exit_code = ?2, exit_signal = ?3
+            WHERE container_id = ?4
+            "#,
+            )
+            .bind(to_unix_millis(finished_at))
+            .bind(exit_code)
+            .bind(exit_signal)
             .bind(container_id.to_string())
             .execute(&self.pool)
-            .await?;
+        })
+        .await?;
         Ok(())
     }
 
-    pub async fn delete_container(&self, container_id: Uuid) -> Result<(), PersistenceError> {
-        let result = sqlx::query("DELETE FROM containers WHERE container_id = ?1")
+    /// Records the OOM kills of a container and, if its process was one of
+    /// them, that it stopped because of that.
+    pub async fn update_container_oom_kills(
+        &self,
+        container_id: Uuid,
+        oom_kill_count: u32,
+        oom_killed: bool,
+    ) -> Result<(), PersistenceError> {
+        retry_busy(|| {
+            sqlx::query(
+                "UPDATE containers SET oom_kill_count = ?1, oom_killed = oom_killed OR ?2 WHERE container_id = ?3",
+            )
+            .bind(i64::from(oom_kill_count))
+            .bind(oom_killed)
             .bind(container_id.to_string())
             .execute(&self.pool)
-            .await?;
+        })
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete_container(&self, container_id: Uuid) -> Result<(), PersistenceError> {
+        let result = retry_busy(|| {
+            sqlx::query("DELETE FROM containers WHERE container_id = ?1")
+                .bind(container_id.to_string())
+                .execute(&self.pool)
+        })
+        .await?;
 
         if result.rows_affected() == 0 {
             log::warn!(
@@ -189,4 +391,126 @@ impl ContainerRepository {
 
         Ok(())
     }
+
+    /// Records an event of a container and drops its events beyond the
+    /// retention limits. Without a namespace, that of the container's earlier
+    /// events is used.
+    pub async fn save_container_event(
+        &self,
+        event: &ContainerEvent,
+        namespace: Option<&str>,
+        recorded_at: SystemTime,
+    ) -> Result<(), PersistenceError> {
+        let mut event_blob = Vec::new();
+        event.encode(&mut event_blob)?;
+
+        retry_busy(|| {
+            sqlx::query(
+                r#"
+            INSERT OR REPLACE INTO container_events
+                (event_id, container_id, namespace, recorded_at, event_blob)
+            VALUES (
+                ?1, ?2,
+                COALESCE(
+                    ?3,
+                    (SELECT namespace FROM container_events WHERE container_id = ?2 LIMIT 1),
+                    ''
+                ),
+                ?4, ?5
+            )
+            "#,
+            )
+            .bind(&event.id)
+            .bind(&event.container_id)
+            .bind(namespace)
+            .bind(to_unix_millis(recorded_at))
+            .bind(&event_blob)
+            .execute(&self.pool)
+        })
+        .await?;
+
+        let expired_before = recorded_at
+            .checked_sub(EVENT_RETENTION)
+            .unwrap_or(UNIX_EPOCH);
+        retry_busy(|| {
+            sqlx::query(
+                r#"
+            DELETE FROM container_events
+            WHERE recorded_at < ?2
+               OR (container_id = ?1 AND rowid NOT IN (
+                   SELECT rowid FROM container_events WHERE container_id = ?1
+                   ORDER BY recorded_at DESC, rowid DESC LIMIT ?3
+               ))
+            "#,
+            )
+            .bind(&event.container_id)
+            .bind(to_unix_millis(expired_before))
+            .bind(MAX_EVENTS_PER_CONTAINER)
+            .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Lists recorded events, oldest first, with the time each was recorded.
+    pub async fn list_container_events(
+        &self,
+        filter: &ContainerEventFilter,
+    ) -> Result<Vec<(ContainerEvent, SystemTime)>, PersistenceError> {
+        let rows = sqlx::query_as::<_, DbEventRow>(
+            r#"
+            SELECT recorded_at, event_blob FROM container_events
+            WHERE (?1 IS NULL OR container_id = ?1)
+              AND (?2 IS NULL OR namespace = ?2)
+              AND recorded_at >= ?3 AND recorded_at < ?4
+            ORDER BY recorded_at DESC, rowid DESC
+            LIMIT ?5
+            "#,
+        )
+        .bind(filter.container_id.map(|id| id.to_string()))
+        .bind(&filter.namespace)
+        .bind(filter.since.map_or(i64::MIN, to_unix_millis))
+        .bind(filter.until.map_or(i64::MAX, to_unix_millis))
+        .bind(i64::from(filter.limit))
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .rev()
+            .map(|row| {
+                Ok((
+                    ContainerEvent::decode(&*row.event_blob)?,
+                    from_unix_millis(row.recorded_at),
+                ))
+            })
+            .collect()
+    }
+
+    /// Replays the journal of container state transitions, which the
+    /// database appends to whenever a container is created, changes state or
+    /// is deleted. Returns up to `limit` entries after `after_sequence`,
+    /// oldest first.
+    pub async fn replay_container_state_journal(
+        &self,
+        container_id: Option<Uuid>,
+        after_sequence: i64,
+        limit: i64,
+    ) -> Result<Vec<ContainerJournalEntry>, PersistenceError> {
+        let rows = sqlx::query_as::<_, DbJournalRow>(
+            r#"
+            SELECT sequence, container_id, namespace, recorded_at_ms, old_state, new_state
+            FROM container_state_journal
+            WHERE (?1 IS NULL OR container_id = ?1) AND sequence > ?2
+            ORDER BY sequence
+            LIMIT ?3
+            "#,
+        )
+        .bind(container_id.map(|id| id.to_string()))
+        .bind(after_sequence)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(journal_entry_from_row).collect()
+    }
 }
@@ -1,16 +1,29 @@
 // SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
 // SPDX-License-Identifier: Apache-2.0
 
+use super::secrets::{self, SecretValues};
+use super::snapshotter::{ImageLayers, Snapshotter, SnapshotterError};
+use crate::{CONTAINER_CGROUP, CONTAINER_DIR};
+use feos_proto::container_service::{ContainerConfig, CpuScheduling, SchedulingClass};
 use feos_proto::task_service::{
-    task_service_client::TaskServiceClient, CreateRequest, DeleteRequest, KillRequest, StartRequest,
+    task_service_client::TaskServiceClient, CreateRequest, DeleteRequest, ExecRequest,
+    ExecResponse, KillRequest, StartRequest, WaitRequest, WaitResponse,
 };
+use feos_utils::host::serial;
 use hyper_util::rt::TokioIo;
-use log::info;
+use log::{info, warn};
+use secret_service::error::SecretServiceError;
+use secret_service::store::SecretStore;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use task_service::TASK_SERVICE_SOCKET;
 use tokio::fs;
+use tokio::sync::broadcast;
+use tokio_stream::Stream;
 use tonic::transport::{Channel, Endpoint, Uri};
+use tonic::Streaming;
 use tower::service_fn;
 
 #[derive(Debug, thiserror::Error)]
@@ -21,6 +34,10 @@ pub enum AdapterError {
     Io(#[from] std::io::Error),
     #[error("Task service communication failed: {0}")]
     TaskService(#[from] tonic::Status),
+    #[error("Snapshotter error: {0}")]
+    Snapshotter(#[from] SnapshotterError),
+    #[error("{0}")]
+    Secret(#[from] SecretServiceError),
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -55,6 +72,15 @@ struct OciProcess {
     args: Vec<String>,
     env: Vec<String>,
     cwd: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scheduler: Option<OciScheduler>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct OciScheduler {
+    policy: String,
+    nice: i32,
+    priority: i32,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -79,8 +105,48 @@ struct OciMount {
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 struct OciLinux {
+    /// Relative to the cgroup root.
+    cgroups_path: String,
     namespaces: Vec<OciLinuxNamespace>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resources: Option<OciResources>,
+    /// Device nodes created in the container.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    devices: Vec<OciDevice>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct OciResources {
+    /// cgroup v2 interface files written as given.
+    unified: HashMap<String, String>,
+    /// Devices the container may use besides the runtime's defaults.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    devices: Vec<OciDeviceRule>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct OciDevice {
+    path: String,
+    #[serde(rename = "type")]
+    typ: String,
+    major: u64,
+    minor: u64,
+    file_mode: u32,
+    uid: u32,
+    gid: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct OciDeviceRule {
+    allow: bool,
+    #[serde(rename = "type")]
+    typ: String,
+    major: u64,
+    minor: u64,
+    access: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -90,17 +156,217 @@ struct OciLinuxNamespace {
     typ: String,
 }
 
-pub struct ContainerAdapter;
+/// The cgroup limits of a container and how its processes are scheduled.
+/// Unset limits leave the container unrestricted.
+#[derive(Clone, Debug, Default)]
+pub struct ResourceLimits {
+    pub swap_max: Option<u64>,
+    /// 0 means unlimited.
+    pub memory_max: u64,
+    /// CPU time in thousandths of a CPU, 0 means unlimited.
+    pub milli_cpus: u32,
+    pub cpu_weight: Option<u32>,
+    /// Empty allows all CPUs.
+    pub cpuset: String,
+    pub scheduling: Option<CpuScheduling>,
+    /// Serial ports of the host the container may use, at the same paths.
+    pub serial_ports: Vec<String>,
+}
 
-impl Default for ContainerAdapter {
-    fn default() -> Self {
-        Self::new()
+impl From<&ContainerConfig> for ResourceLimits {
+    fn from(config: &ContainerConfig) -> Self {
+        Self {
+            swap_max: config.swap_max_bytes,
+            memory_max: config.memory_limit_bytes,
+            milli_cpus: config.milli_cpus,
+            cpu_weight: config.cpu_weight,
+            cpuset: config.cpuset.clone(),
+            scheduling: config.scheduling,
+            serial_ports: config.serial_ports.clone(),
+        }
+    }
+}
+
+/// Whether `cpuset` is a list of CPUs and ranges, e.g. `0-3,8`.
+fn valid_cpuset(cpuset: &str) -> bool {
+    cpuset.split(',').all(|part| {
+        let (first, last) = part.split_once('-').unwrap_or((part, part));
+        matches!(
+            (first.parse::<u32>(), last.parse::<u32>()),
+            (Ok(first), Ok(last)) if first <= last
+        )
+    })
+}
+
+/// Checks the CPU options of a container before it is created.
+pub fn validate_cpu_options(config: &ContainerConfig) -> Result<(), String> {
+    if let Some(weight) = config.cpu_weight {
+        if !(1..=10_000).contains(&weight) {
+            return Err(format!("CPU weight {weight} is not between 1 and 10000."));
+        }
+    }
+    if !config.cpuset.is_empty() && !valid_cpuset(&config.cpuset) {
+        return Err(format!(
+            "Invalid cpuset '{}', expected CPUs and ranges like 0-3,8.",
+            config.cpuset
+        ));
+    }
+    let Some(scheduling) = &config.scheduling else {
+        return Ok(());
+    };
+    if !(-20..=19).contains(&scheduling.nice) {
+        return Err(format!(
+            "Niceness {} is not between -20 and 19.",
+            scheduling.nice
+        ));
+    }
+    let realtime = scheduling.class() == SchedulingClass::Realtime;
+    if realtime && !(1..=99).contains(&scheduling.realtime_priority) {
+        return Err("Realtime scheduling needs a priority between 1 and 99.".to_string());
+    }
+    if !realtime && scheduling.realtime_priority != 0 {
+        return Err("Only realtime scheduling takes a priority.".to_string());
+    }
+    Ok(())
+}
+
+/// The period `cpu.max` quotas are given for, in microseconds.
+const CPU_PERIOD_US: u64 = 100_000;
+
+/// Checks the serial ports of a container before it is created.
+pub fn validate_serial_ports(config: &ContainerConfig) -> Result<(), String> {
+    for path in &config.serial_ports {
+        serial::serial_port(path).map_err(|e| format!("Invalid serial port '{path}': {e}"))?;
+    }
+    Ok(())
+}
+
+impl ResourceLimits {
+    /// The device nodes of the serial ports, looked up again every time the
+    /// container is created as the numbers may change, e.g. for USB adapters.
+    fn serial_devices(&self) -> Result<Vec<OciDevice>, AdapterError> {
+        self.serial_ports
+            .iter()
+            .map(|path| {
+                let port = serial::serial_port(path).map_err(|e| {
+                    AdapterError::Internal(format!("Serial port {path} is unavailable: {e}"))
+                })?;
+                Ok(OciDevice {
+                    path: path.clone(),
+                    typ: "c".to_string(),
+                    major: port.major,
+                    minor: port.minor,
+                    file_mode: 0o660,
+                    uid: 0,
+                    gid: 0,
+                })
+            })
+            .collect()
+    }
+
+    fn to_oci(&self, devices: &[OciDevice]) -> Option<OciResources> {
+        let mut unified = HashMap::new();
+        // cgroup v2 has no per-group swappiness, the swap limit is the only
+        // per-container control.
+        if let Some(swap_max) = self.swap_max {
+            unified.insert("memory.swap.max".to_string(), swap_max.to_string());
+        }
+        if self.memory_max > 0 {
+            unified.insert("memory.max".to_string(), self.memory_max.to_string());
+        }
+        if self.milli_cpus > 0 {
+            let quota = u64::from(self.milli_cpus) * CPU_PERIOD_US / 1000;
+            unified.insert("cpu.max".to_string(), format!("{quota} {CPU_PERIOD_US}"));
+        }
+        if let Some(weight) = self.cpu_weight {
+            unified.insert("cpu.weight".to_string(), weight.to_string());
+        }
+        if !self.cpuset.is_empty() {
+            unified.insert("cpuset.cpus".to_string(), self.cpuset.clone());
+        }
+        let devices: Vec<_> = devices
+            .iter()
+            .map(|device| OciDeviceRule {
+                allow: true,
+                typ: device.typ.clone(),
+                major: device.major,
+                minor: device.minor,
+                access: "rwm".to_string(),
+            })
+            .collect();
+        (!unified.is_empty() || !devices.is_empty()).then_some(OciResources { unified, devices })
+    }
+
+    fn scheduler(&self) -> Option<OciScheduler> {
+        let scheduling = self.scheduling.as_ref()?;
+        let policy = match scheduling.class() {
+            SchedulingClass::Unspecified | SchedulingClass::Normal => "SCHED_OTHER",
+            SchedulingClass::Batch => "SCHED_BATCH",
+            SchedulingClass::Idle => "SCHED_IDLE",
+            SchedulingClass::Realtime => "SCHED_FIFO",
+        };
+        Some(OciScheduler {
+            policy: policy.to_string(),
+            nice: scheduling.nice,
+            priority: scheduling.realtime_priority as i32,
+        })
+    }
+}
+
+pub struct ContainerAdapter {
+    snapshotter: Arc<dyn Snapshotter>,
+    secrets: SecretStore,
+    /// The IDs of the containers whose creation is cancelled.
+    cancel_tx: broadcast::Sender<String>,
+}
+
+/// The OCI bundle of a container, holding its runtime spec and root
+/// filesystem.
+fn bundle_dir(container_id: &str) -> PathBuf {
+    Path::new(CONTAINER_DIR).join(container_id)
+}
+
+/// Sets the variables of `overrides` in `env`, a list of `NAME=value`,
+/// replacing earlier values of the same name.
+fn override_env(env: &mut Vec<String>, overrides: impl IntoIterator<Item = (String, String)>) {
+    for (name, value) in overrides {
+        env.retain(|var| var.split_once('=').map_or(var.as_str(), |(n, _)| n) != name);
+        env.push(format!("{name}={value}"));
+    }
+}
+
+fn secret_mount(source: &Path, destination: String) -> OciMount {
+    OciMount {
+        destination,
+        typ: "bind".to_string(),
+        source: source.to_string_lossy().into_owned(),
+        options: ["bind", "ro", "nosuid", "nodev", "noexec"]
+            .map(str::to_string)
+            .to_vec(),
     }
 }
 
 impl ContainerAdapter {
-    pub fn new() -> Self {
-        Self
+    /// `secrets` holds the secrets containers get when they are created.
+    pub fn new(snapshotter: Arc<dyn Snapshotter>, secrets: SecretStore) -> Self {
+        info!("Adapter: Using {} snapshotter", snapshotter.name());
+        let (cancel_tx, _) = broadcast::channel(32);
+        Self {
+            snapshotter,
+            secrets,
+            cancel_tx,
+        }
+    }
+
+    /// Cancels the creation of a container, e.g. because it was deleted
+    /// while its image was pulled. The creation cleans up what it set up.
+    pub fn cancel_creation(&self, container_id: &str) {
+        let _ = self.cancel_tx.send(container_id.to_string());
+    }
+
+    /// The containers whose creation is cancelled from now on.
+    pub fn cancelled_creations(&self) -> broadcast::Receiver<String> {
+        self.cancel_tx.subscribe()
     }
 
     async fn get_task_service_client() -> Result<TaskServiceClient<Channel>, AdapterError> {
@@ -120,9 +386,17 @@ impl ContainerAdapter {
             .map_err(|e| AdapterError::TaskService(tonic::Status::unavailable(e.to_string())))
     }
 
-    async fn generate_runtime_spec(bundle_path: &Path) -> Result<(), AdapterError> {
-        let image_config_path = bundle_path.join("config.json");
-        let image_spec_json = fs::read_to_string(&image_config_path).await?;
+    /// The environment of the container is that of the image with the
+    /// variables of `env` set on top. The spec is written to `spec_path`.
+    async fn generate_runtime_spec(
+        container_id: &str,
+        image_dir: &Path,
+        spec_path: &Path,
+        limits: &ResourceLimits,
+        env: Vec<(String, String)>,
+        extra_mounts: Vec<OciMount>,
+    ) -> Result<(), AdapterError> {
+        let image_spec_json = fs::read_to_string(image_dir.join("config.json")).await?;
         let image_spec: OciImageSpec = serde_json::from_str(&image_spec_json)
             .map_err(|e| AdapterError::Internal(e.to_string()))?;
 
@@ -134,14 +408,19 @@ impl ContainerAdapter {
             args.extend(cmd);
         }
 
-        let runtime_spec = OciRuntimeSpec {
+        let mut process_env = image_spec.config.env.unwrap_or_default();
+        override_env(&mut process_env, env);
+
+        let devices = limits.serial_devices()?;
+        let mut runtime_spec = OciRuntimeSpec {
             oci_version: "1.0.2".to_string(),
             process: OciProcess {
                 terminal: false,
                 user: OciUser { uid: 0, gid: 0 },
                 args,
-                env: image_spec.config.env.unwrap_or_default(),
+                env: process_env,
                 cwd: "/".to_string(),
+                scheduler: limits.scheduler(),
             },
             root: OciRoot {
                 path: "rootfs".to_string(),
@@ -190,6 +469,8 @@ impl ContainerAdapter {
                 },
             ],
             linux: OciLinux {
+                // A cgroup of its own, so that its OOM kills can be watched.
+                cgroups_path: format!("/{CONTAINER_CGROUP}/{container_id}"),
                 namespaces: vec![
                     OciLinuxNamespace {
                         typ: "pid".to_string(),
@@ -207,25 +488,115 @@ impl ContainerAdapter {
                     //     typ: "network".to_string(),
                     // },
                 ],
+                resources: limits.to_oci(&devices),
+                devices,
             },
         };
+        runtime_spec.mounts.extend(extra_mounts);
 
         let runtime_spec_json = serde_json::to_string(&runtime_spec)
             .map_err(|e| AdapterError::Internal(e.to_string()))?;
-        fs::write(&image_config_path, runtime_spec_json).await?;
-        info!("Generated and overwrote runtime config.json in bundle");
+        fs::write(spec_path, runtime_spec_json).await?;
+        info!("Generated runtime config.json in bundle");
+
+        Ok(())
+    }
+
+    async fn prepare_bundle(
+        &self,
+        container_id: &str,
+        image_dir: &Path,
+        config: &ContainerConfig,
+        secrets: SecretValues,
+    ) -> Result<PathBuf, AdapterError> {
+        let disk_limit = config.disk_limit_bytes;
+        let bundle_path = bundle_dir(container_id);
+        let image_id = image_dir
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| AdapterError::Internal("Invalid image directory".to_string()))?;
+        let layers = image_service::filestore::rootfs_layers(image_dir).await?;
+        let image = ImageLayers {
+            image_id,
+            layers: &layers,
+        };
+
+        info!("Adapter: Preparing root filesystem for container {container_id}");
+        self.snapshotter
+            .prepare(&bundle_path, &image, disk_limit)
+            .await?;
+        if disk_limit > 0 {
+            info!(
+                "Adapter: Limited writable layer of container {container_id} to {disk_limit} bytes"
+            );
+        }
+
+        let secret_mounts = secrets::write_files(&bundle_path, &secrets)
+            .await?
+            .into_iter()
+            .map(|(source, destination)| secret_mount(&source, destination))
+            .collect();
+        let spec_path = secrets::spec_path(&bundle_path, &secrets).await?;
+        let env = config
+            .env
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .chain(secrets.env)
+            .collect();
+
+        info!("Adapter: Generating OCI spec for container {container_id}");
+        Self::generate_runtime_spec(
+            container_id,
+            image_dir,
+            &spec_path,
+            &ResourceLimits::from(config),
+            env,
+            secret_mounts,
+        )
+        .await?;
+        Ok(bundle_path)
+    }
 
+    /// Releases the root filesystem of a container and removes its bundle.
+    /// The bundle stays in place if anything is still mounted in it.
+    pub async fn remove_bundle(&self, container_id: &str) -> Result<(), AdapterError> {
+        let bundle_path = bundle_dir(container_id);
+        if !fs::try_exists(&bundle_path).await? {
+            return Ok(());
+        }
+        secrets::remove_files(&bundle_path)?;
+        self.snapshotter.remove(&bundle_path).await?;
+        fs::remove_dir_all(&bundle_path).await?;
+        info!("Adapter: Removed bundle of container {container_id}");
         Ok(())
     }
 
+    /// Creates a container of `namespace` with the current values of the
+    /// secrets it references.
     pub async fn create_container(
         &self,
         container_id: &str,
-        bundle_path: &Path,
+        namespace: &str,
+        image_dir: &Path,
+        config: &ContainerConfig,
     ) -> Result<i64, AdapterError> {
-        info!("Adapter: Rewriting OCI spec for container {container_id}");
-        Self::generate_runtime_spec(bundle_path).await?;
+        let result = async {
+            let secrets = secrets::resolve(&self.secrets, namespace, &config.secrets).await?;
+            let bundle_path = self
+                .prepare_bundle(container_id, image_dir, config, secrets)
+                .await?;
+            Self::create_task(container_id, &bundle_path).await
+        }
+        .await;
+        if result.is_err() {
+            if let Err(e) = self.remove_bundle(container_id).await {
+                warn!("Adapter: Failed to clean up bundle of container {container_id}: {e}");
+            }
+        }
+        result
+    }
 
+    async fn create_task(container_id: &str, bundle_path: &Path) -> Result<i64, AdapterError> {
         info!("Adapter: Connecting to TaskService for container {container_id}");
         let mut task_client = Self::get_task_service_client().await?;
 
@@ -274,12 +645,97 @@ impl ContainerAdapter {
         Ok(())
     }
 
+    /// Waits until the process of a started container exits.
+    pub async fn wait_container(&self, container_id: &str) -> Result<WaitResponse, AdapterError> {
+        let mut task_client = Self::get_task_service_client().await?;
+        let request = WaitRequest {
+            container_id: container_id.to_string(),
+        };
+        Ok(task_client.wait(request).await?.into_inner())
+    }
+
     pub async fn delete_container(&self, container_id: &str) -> Result<(), AdapterError> {
         let mut task_client = Self::get_task_service_client().await?;
         let request = DeleteRequest {
             container_id: container_id.to_string(),
         };
         task_client.delete(request).await?;
+        if let Err(e) = self.remove_bundle(container_id).await {
+            warn!("Adapter: Failed to remove bundle of container {container_id}: {e}");
+        }
         Ok(())
     }
+
+    pub async fn exec_container(
+        &self,
+        requests: impl Stream<Item = ExecRequest> + Send + 'static,
+    ) -> Result<Streaming<ExecResponse>, AdapterError> {
+        let mut task_client = Self::get_task_service_client().await?;
+        let response = task_client.exec(requests).await?;
+        Ok(response.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpusets_are_lists_of_cpus_and_ranges() {
+        assert!(valid_cpuset("0"));
+        assert!(valid_cpuset("0-3,8,10-11"));
+        assert!(!valid_cpuset("3-0"));
+        assert!(!valid_cpuset("0,,1"));
+        assert!(!valid_cpuset("all"));
+    }
+
+    #[test]
+    fn serial_ports_are_allowed_in_the_device_cgroup() {
+        let limits = ResourceLimits::default();
+        assert!(limits.to_oci(&[]).is_none());
+
+        let device = OciDevice {
+            path: "/dev/ttyUSB0".to_string(),
+            typ: "c".to_string(),
+            major: 188,
+            minor: 0,
+            file_mode: 0o660,
+            uid: 0,
+            gid: 0,
+        };
+        let resources = limits.to_oci(&[device]).unwrap();
+        assert!(resources.unified.is_empty());
+        assert_eq!(resources.devices.len(), 1);
+        assert!(resources.devices[0].allow);
+        assert_eq!(
+            (resources.devices[0].major, resources.devices[0].minor),
+            (188, 0)
+        );
+        assert_eq!(resources.devices[0].access, "rwm");
+    }
+
+    #[test]
+    fn overridden_variables_replace_those_of_the_image() {
+        let mut env = vec![
+            "PATH=/usr/bin".to_string(),
+            "DB_PASSWORD=".to_string(),
+            "EMPTY".to_string(),
+        ];
+        override_env(
+            &mut env,
+            [
+                ("DB_PASSWORD".to_string(), "hunter2=x".to_string()),
+                ("TOKEN".to_string(), "abc".to_string()),
+            ],
+        );
+        assert_eq!(
+            env,
+            [
+                "PATH=/usr/bin",
+                "EMPTY",
+                "DB_PASSWORD=hunter2=x",
+                "TOKEN=abc"
+            ]
+        );
+    }
 }
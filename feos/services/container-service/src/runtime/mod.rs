@@ -2,3 +2,5 @@
 // SPDX-License-Identifier: Apache-2.0
 
 pub mod adapter;
+pub mod secrets;
+pub mod snapshotter;
@@ -0,0 +1,199 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Secrets of containers. They are kept on a tmpfs in the bundle, so their
+//! values never reach a disk of the host. Files are bind mounted into the
+//! container read-only. Environment variables are part of the runtime spec,
+//! which is then written to the tmpfs as well and linked from the bundle.
+
+use super::adapter::AdapterError;
+use super::snapshotter::{unmount, SnapshotterError};
+use feos_proto::container_service::{container_secret::Target, ContainerConfig, ContainerSecret};
+use feos_utils::namespace::validate_name;
+use nix::mount::{mount, MsFlags};
+use secret_service::error::SecretServiceError;
+use secret_service::store::{SecretStore, MAX_SECRET_BYTES};
+use std::collections::HashSet;
+use std::fs::Permissions;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/// The values of the secrets of a container.
+#[derive(Debug, Default)]
+pub struct SecretValues {
+    /// Environment variables by name.
+    pub env: Vec<(String, String)>,
+    /// File contents by their path in the container.
+    pub files: Vec<(String, Vec<u8>)>,
+}
+
+/// Checks the secrets of a container before it is created. Whether they
+/// exist is only known when it is created, as they may be put until then.
+pub fn validate(config: &ContainerConfig) -> Result<(), String> {
+    let mut env = HashSet::new();
+    let mut paths = HashSet::new();
+    for secret in &config.secrets {
+        validate_name(&secret.name).map_err(|e| format!("Invalid secret: {e}"))?;
+        match &secret.target {
+            Some(Target::Env(var)) => {
+                if var.is_empty() || var.contains(['=', '\0']) {
+                    return Err(format!(
+                        "Invalid environment variable '{var}' for secret '{}'.",
+                        secret.name
+                    ));
+                }
+                if !env.insert(var.as_str()) {
+                    return Err(format!("More than one secret is set as {var}."));
+                }
+            }
+            Some(Target::Path(path)) => {
+                if !path.starts_with('/') || path.contains('\0') {
+                    return Err(format!(
+                        "The path of secret '{}' must be absolute, got '{path}'.",
+                        secret.name
+                    ));
+                }
+                if !paths.insert(path.as_str()) {
+                    return Err(format!("More than one secret is mounted at {path}."));
+                }
+            }
+            None => {
+                return Err(format!(
+                    "Secret '{}' needs an environment variable or a path.",
+                    secret.name
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads the current values of the secrets of `namespace` a container
+/// references.
+pub async fn resolve(
+    store: &SecretStore,
+    namespace: &str,
+    secrets: &[ContainerSecret],
+) -> Result<SecretValues, SecretServiceError> {
+    let mut values = SecretValues::default();
+    for secret in secrets {
+        let value = store.get(namespace, &secret.name).await?;
+        match &secret.target {
+            Some(Target::Env(var)) => {
+                let value = String::from_utf8(value)
+                    .ok()
+                    .filter(|value| !value.contains('\0'))
+                    .ok_or_else(|| {
+                        SecretServiceError::InvalidArgument(format!(
+                            "Secret '{}' is not text and cannot be set as {var}",
+                            secret.name
+                        ))
+                    })?;
+                values.env.push((var.clone(), value));
+            }
+            Some(Target::Path(path)) => values.files.push((path.clone(), value)),
+            None => {}
+        }
+    }
+    Ok(values)
+}
+
+/// The tmpfs holding the secrets of a container.
+fn files_dir(bundle: &Path) -> PathBuf {
+    bundle.join("secrets")
+}
+
+/// Room on the tmpfs for the runtime spec next to the values it holds.
+const SPEC_BYTES: usize = 1024 * 1024;
+
+/// Writes the secrets of a container to a tmpfs in its bundle, readable by
+/// root only. Returns the files to bind mount by the path in the container.
+pub async fn write_files(
+    bundle: &Path,
+    secrets: &SecretValues,
+) -> Result<Vec<(PathBuf, String)>, AdapterError> {
+    if secrets.env.is_empty() && secrets.files.is_empty() {
+        return Ok(Vec::new());
+    }
+    let dir = files_dir(bundle);
+    fs::create_dir_all(&dir).await?;
+    let size = (secrets.env.len() + secrets.files.len()) * MAX_SECRET_BYTES + SPEC_BYTES;
+    let options = format!("mode=0700,size={size}");
+    mount(
+        Some("tmpfs"),
+        &dir,
+        Some("tmpfs"),
+        MsFlags::MS_NOSUID | MsFlags::MS_NODEV | MsFlags::MS_NOEXEC,
+        Some(options.as_str()),
+    )
+    .map_err(|e| AdapterError::Internal(format!("Failed to mount tmpfs for secrets: {e}")))?;
+
+    let mut mounts = Vec::with_capacity(secrets.files.len());
+    for (index, (path, value)) in secrets.files.iter().enumerate() {
+        // Paths in the container may have any name, the files are numbered.
+        let source = dir.join(index.to_string());
+        fs::write(&source, value).await?;
+        fs::set_permissions(&source, Permissions::from_mode(0o400)).await?;
+        mounts.push((source, path.clone()));
+    }
+    Ok(mounts)
+}
+
+/// Where the runtime spec of a container is written. A spec with secret
+/// environment variables goes to the tmpfs, the bundle only links to it.
+pub async fn spec_path(bundle: &Path, secrets: &SecretValues) -> Result<PathBuf, AdapterError> {
+    let path = bundle.join("config.json");
+    if secrets.env.is_empty() {
+        return Ok(path);
+    }
+    fs::symlink(Path::new("secrets").join("config.json"), &path).await?;
+    Ok(files_dir(bundle).join("config.json"))
+}
+
+/// Unmounts the tmpfs with the secrets of a container, if it has one.
+pub fn remove_files(bundle: &Path) -> Result<(), SnapshotterError> {
+    unmount(&files_dir(bundle))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secret(name: &str, target: Target) -> ContainerSecret {
+        ContainerSecret {
+            name: name.to_string(),
+            target: Some(target),
+        }
+    }
+
+    #[test]
+    fn secrets_need_distinct_variables_and_absolute_paths() {
+        let mut config = ContainerConfig {
+            secrets: vec![
+                secret("db-password", Target::Env("DB_PASSWORD".to_string())),
+                secret("tls-key", Target::Path("/etc/tls/key.pem".to_string())),
+            ],
+            ..Default::default()
+        };
+        assert!(validate(&config).is_ok());
+
+        let invalid = [
+            secret("api-token", Target::Env("DB_PASSWORD".to_string())),
+            secret("api-token", Target::Env("A=B".to_string())),
+            secret("api-token", Target::Env(String::new())),
+            secret("api-token", Target::Path("/etc/tls/key.pem".to_string())),
+            secret("api-token", Target::Path("token".to_string())),
+            secret("Not Valid", Target::Env("TOKEN".to_string())),
+            ContainerSecret {
+                name: "api-token".to_string(),
+                target: None,
+            },
+        ];
+        for secret in invalid {
+            config.secrets.push(secret.clone());
+            assert!(validate(&config).is_err(), "{secret:?} was accepted");
+            config.secrets.pop();
+        }
+    }
+}
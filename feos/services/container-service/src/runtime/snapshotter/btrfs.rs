@@ -0,0 +1,195 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{run, unmount, ImageLayers, Snapshotter, SnapshotterError};
+use crate::CONTAINER_DIR;
+use image_service::IMAGE_DIR;
+use log::{info, warn};
+use nix::mount::{mount, MsFlags};
+use nix::sys::statfs::{statfs, BTRFS_SUPER_MAGIC};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use uuid::Uuid;
+
+/// Flattens every image once into a read-only subvolume and gives each
+/// container a writable snapshot of it. Disk limits are btrfs qgroup limits
+/// on the data exclusive to the snapshot. Needs `CONTAINER_DIR` on btrfs.
+pub struct BtrfsSnapshotter {
+    base_dir: PathBuf,
+}
+
+impl BtrfsSnapshotter {
+    pub fn new() -> Result<Self, SnapshotterError> {
+        std::fs::create_dir_all(CONTAINER_DIR)?;
+        let stat = statfs(CONTAINER_DIR).map_err(std::io::Error::from)?;
+        if stat.filesystem_type() != BTRFS_SUPER_MAGIC {
+            return Err(SnapshotterError::InvalidConfig(format!(
+                "{CONTAINER_DIR} is not on a btrfs filesystem"
+            )));
+        }
+        Ok(Self {
+            base_dir: Path::new(CONTAINER_DIR).join(".btrfs"),
+        })
+    }
+
+    /// Copies the merged view of the layers into a new subvolume. Extents are
+    /// reflinked where the layers live on the same filesystem.
+    async fn flatten(
+        &self,
+        image: &ImageLayers<'_>,
+        target: &Path,
+    ) -> Result<(), SnapshotterError> {
+        let target_str = target.to_string_lossy();
+        run("btrfs", &["subvolume", "create", &target_str]).await?;
+
+        let (source, mountpoint) = match image.layers {
+            [layer] => (layer.clone(), None),
+            layers => {
+                // A read-only overlay applies the whiteouts of upper layers.
+                let mountpoint = self.base_dir.join(format!("mnt-{}", Uuid::new_v4()));
+                fs::create_dir_all(&mountpoint).await?;
+                let lowerdir = layers
+                    .iter()
+                    .rev()
+                    .map(|layer| layer.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(":");
+                let options = format!("lowerdir={lowerdir}");
+                mount(
+                    Some("overlay"),
+                    &mountpoint,
+                    Some("overlay"),
+                    MsFlags::MS_RDONLY,
+                    Some(options.as_str()),
+                )
+                .map_err(|e| {
+                    SnapshotterError::CommandFailed(format!("Failed to mount image layers: {e}"))
+                })?;
+                (mountpoint.clone(), Some(mountpoint))
+            }
+        };
+
+        let source = format!("{}/.", source.display());
+        let result = run("cp", &["-a", "--reflink=auto", &source, &target_str]).await;
+        if let Some(mountpoint) = mountpoint {
+            unmount(&mountpoint)?;
+            let _ = fs::remove_dir(&mountpoint).await;
+        }
+        result?;
+        run(
+            "btrfs",
+            &["property", "set", "-ts", &target_str, "ro", "true"],
+        )
+        .await
+    }
+
+    /// Returns the read-only subvolume holding the flattened image, creating
+    /// it on first use.
+    async fn base_subvolume(&self, image: &ImageLayers<'_>) -> Result<PathBuf, SnapshotterError> {
+        let base = self.base_dir.join(image.image_id);
+        if fs::try_exists(&base).await? {
+            return Ok(base);
+        }
+        fs::create_dir_all(&self.base_dir).await?;
+        self.prune_bases().await;
+
+        let tmp = self.base_dir.join(format!("tmp-{}", Uuid::new_v4()));
+        if let Err(e) = self.flatten(image, &tmp).await {
+            delete_subvolume(&tmp).await;
+            return Err(e);
+        }
+        if let Err(e) = fs::rename(&tmp, &base).await {
+            // Another container of the same image was faster.
+            delete_subvolume(&tmp).await;
+            if !fs::try_exists(&base).await? {
+                return Err(e.into());
+            }
+        }
+        info!("Btrfs: Created base subvolume for image {}", image.image_id);
+        Ok(base)
+    }
+
+    /// Deletes the base subvolumes of images that were removed. Snapshots of
+    /// running containers are independent of them.
+    async fn prune_bases(&self) {
+        let Ok(mut entries) = fs::read_dir(&self.base_dir).await else {
+            return;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let is_leftover = name.starts_with("tmp-") || name.starts_with("mnt-");
+            if is_leftover || Path::new(IMAGE_DIR).join(&name).exists() {
+                continue;
+            }
+            delete_subvolume(&entry.path()).await;
+        }
+    }
+}
+
+async fn delete_subvolume(path: &Path) {
+    if !path.exists() {
+        return;
+    }
+    let path_str = path.to_string_lossy();
+    let _ = run(
+        "btrfs",
+        &["property", "set", "-ts", &path_str, "ro", "false"],
+    )
+    .await;
+    if let Err(e) = run("btrfs", &["subvolume", "delete", &path_str]).await {
+        warn!("Btrfs: Failed to delete subvolume {path_str}: {e}");
+    }
+}
+
+#[tonic::async_trait]
+impl Snapshotter for BtrfsSnapshotter {
+    fn name(&self) -> &'static str {
+        "btrfs"
+    }
+
+    async fn prepare(
+        &self,
+        bundle: &Path,
+        image: &ImageLayers<'_>,
+        disk_limit: u64,
+    ) -> Result<(), SnapshotterError> {
+        let base = self.base_subvolume(image).await?;
+        fs::create_dir_all(bundle).await?;
+        let rootfs = bundle.join("rootfs");
+        let rootfs_str = rootfs.to_string_lossy();
+        run(
+            "btrfs",
+            &[
+                "subvolume",
+                "snapshot",
+                &base.to_string_lossy(),
+                &rootfs_str,
+            ],
+        )
+        .await?;
+        if disk_limit > 0 {
+            // Enabling quotas on a filesystem that has them is a no-op.
+            run("btrfs", &["quota", "enable", CONTAINER_DIR]).await?;
+            run(
+                "btrfs",
+                &[
+                    "qgroup",
+                    "limit",
+                    "-e",
+                    &disk_limit.to_string(),
+                    &rootfs_str,
+                ],
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn remove(&self, bundle: &Path) -> Result<(), SnapshotterError> {
+        let rootfs = bundle.join("rootfs");
+        if fs::try_exists(&rootfs).await? {
+            run("btrfs", &["subvolume", "delete", &rootfs.to_string_lossy()]).await?;
+        }
+        Ok(())
+    }
+}
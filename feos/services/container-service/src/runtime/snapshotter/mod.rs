@@ -0,0 +1,118 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use nix::errno::Errno;
+use nix::mount::{umount2, MntFlags};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::process::Command as TokioCommand;
+
+pub mod btrfs;
+pub mod overlay;
+
+pub const DEFAULT_SNAPSHOTTER: &str = "overlayfs";
+
+/// Smallest writable layer that still fits a filesystem journal and leaves
+/// room for the container to write anything at all.
+pub const MIN_DISK_LIMIT: u64 = 16 << 20;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SnapshotterError {
+    #[error("Invalid snapshotter configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("Snapshotter command failed: {0}")]
+    CommandFailed(String),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// The read-only layers of an image a container is created from.
+pub struct ImageLayers<'a> {
+    pub image_id: &'a str,
+    /// Bottom layer first.
+    pub layers: &'a [PathBuf],
+}
+
+#[tonic::async_trait]
+pub trait Snapshotter: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Creates the root filesystem of a container in `<bundle>/rootfs`. The
+    /// image layers are shared with other containers and never written to.
+    /// Unless `disk_limit` is 0, the container can write at most that many
+    /// bytes.
+    async fn prepare(
+        &self,
+        bundle: &Path,
+        image: &ImageLayers<'_>,
+        disk_limit: u64,
+    ) -> Result<(), SnapshotterError>;
+
+    /// Releases the root filesystem of a container, including everything it
+    /// wrote. Must also clean up after a `prepare` that failed halfway. The
+    /// bundle is only removed afterwards if this succeeds.
+    async fn remove(&self, bundle: &Path) -> Result<(), SnapshotterError>;
+}
+
+/// Looks up a snapshotter by the name used in the `CONTAINER_SNAPSHOTTER`
+/// setting.
+pub fn from_name(name: &str) -> Result<Arc<dyn Snapshotter>, SnapshotterError> {
+    match name {
+        "overlayfs" => Ok(Arc::new(overlay::OverlaySnapshotter)),
+        "btrfs" => Ok(Arc::new(btrfs::BtrfsSnapshotter::new()?)),
+        other => Err(SnapshotterError::InvalidConfig(format!(
+            "Unknown snapshotter '{other}', expected 'overlayfs' or 'btrfs'"
+        ))),
+    }
+}
+
+pub fn validate_disk_limit(disk_limit: u64) -> Result<(), String> {
+    if disk_limit != 0 && disk_limit < MIN_DISK_LIMIT {
+        return Err(format!(
+            "Disk limit of {disk_limit} bytes is below the minimum of {MIN_DISK_LIMIT} bytes"
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) async fn run(program: &str, args: &[&str]) -> Result<(), SnapshotterError> {
+    let output = TokioCommand::new(program)
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| SnapshotterError::CommandFailed(format!("Failed to run {program}: {e}")))?;
+    if !output.status.success() {
+        return Err(SnapshotterError::CommandFailed(format!(
+            "{program} {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Lazily unmounts `path`. Succeeds if nothing is mounted there.
+pub(crate) fn unmount(path: &Path) -> Result<(), SnapshotterError> {
+    match umount2(path, MntFlags::MNT_DETACH) {
+        Ok(()) | Err(Errno::EINVAL) | Err(Errno::ENOENT) => Ok(()),
+        Err(e) => Err(SnapshotterError::CommandFailed(format!(
+            "Failed to unmount {}: {e}",
+            path.display()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disk_limits_below_minimum_are_rejected() {
+        assert!(validate_disk_limit(0).is_ok());
+        assert!(validate_disk_limit(MIN_DISK_LIMIT).is_ok());
+        assert!(validate_disk_limit(MIN_DISK_LIMIT - 1).is_err());
+        assert!(validate_disk_limit(1 << 30).is_ok());
+    }
+}
@@ -0,0 +1,106 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{run, unmount, ImageLayers, Snapshotter, SnapshotterError};
+use nix::mount::{mount, MsFlags};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/// The kernel copies mount options into a single page.
+const MAX_MOUNT_OPTIONS_LEN: usize = 4095;
+
+/// Stacks the image layers as overlayfs lower directories with a writable
+/// layer in `<bundle>/layer` on top. With a disk limit, the writable layer is
+/// a sparse ext4 image of that size.
+pub struct OverlaySnapshotter;
+
+fn overlay_options(layers: &[PathBuf], layer: &Path) -> Result<String, SnapshotterError> {
+    let lowerdir = layers
+        .iter()
+        .rev()
+        .map(|layer| layer.display().to_string())
+        .collect::<Vec<_>>()
+        .join(":");
+    let options = format!(
+        "lowerdir={lowerdir},upperdir={},workdir={}",
+        layer.join("upper").display(),
+        layer.join("work").display()
+    );
+    if options.len() > MAX_MOUNT_OPTIONS_LEN {
+        return Err(SnapshotterError::InvalidConfig(format!(
+            "Image has too many layers ({}) for overlayfs",
+            layers.len()
+        )));
+    }
+    Ok(options)
+}
+
+/// Backs the writable layer with a sparse ext4 image of `disk_limit` bytes,
+/// so a container that fills it gets ENOSPC while the host filesystem only
+/// holds the blocks actually written.
+async fn mount_layer_volume(bundle: &Path, disk_limit: u64) -> Result<(), SnapshotterError> {
+    let image = bundle.join("layer.img");
+    let layer = bundle.join("layer");
+    fs::File::create(&image).await?.set_len(disk_limit).await?;
+
+    let image = image.to_string_lossy();
+    let layer = layer.to_string_lossy();
+    run("mkfs.ext4", &["-q", "-F", "-m", "0", &image]).await?;
+    run("mount", &["-o", "loop,nodev,nosuid", &image, &layer]).await
+}
+
+#[tonic::async_trait]
+impl Snapshotter for OverlaySnapshotter {
+    fn name(&self) -> &'static str {
+        "overlayfs"
+    }
+
+    async fn prepare(
+        &self,
+        bundle: &Path,
+        image: &ImageLayers<'_>,
+        disk_limit: u64,
+    ) -> Result<(), SnapshotterError> {
+        let layer = bundle.join("layer");
+        let options = overlay_options(image.layers, &layer)?;
+        fs::create_dir_all(bundle.join("rootfs")).await?;
+        fs::create_dir_all(&layer).await?;
+        if disk_limit > 0 {
+            mount_layer_volume(bundle, disk_limit).await?;
+        }
+        fs::create_dir_all(layer.join("upper")).await?;
+        fs::create_dir_all(layer.join("work")).await?;
+        mount(
+            Some("overlay"),
+            &bundle.join("rootfs"),
+            Some("overlay"),
+            MsFlags::empty(),
+            Some(options.as_str()),
+        )
+        .map_err(|e| {
+            SnapshotterError::CommandFailed(format!("Failed to mount overlay rootfs: {e}"))
+        })
+    }
+
+    async fn remove(&self, bundle: &Path) -> Result<(), SnapshotterError> {
+        unmount(&bundle.join("rootfs"))?;
+        unmount(&bundle.join("layer"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lower_directories_are_listed_top_first() {
+        let layers = [PathBuf::from("/layers/base"), PathBuf::from("/layers/app")];
+        assert_eq!(
+            overlay_options(&layers, Path::new("/bundle/layer")).unwrap(),
+            "lowerdir=/layers/app:/layers/base,upperdir=/bundle/layer/upper,workdir=/bundle/layer/work"
+        );
+
+        let many = vec![PathBuf::from(format!("/{}", "x".repeat(100))); 50];
+        assert!(overlay_options(&many, Path::new("/bundle/layer")).is_err());
+    }
+}
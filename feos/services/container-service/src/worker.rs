@@ -2,29 +2,60 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    error::ContainerServiceError, persistence::repository::ContainerRepository,
-    runtime::adapter::ContainerAdapter,
+    error::ContainerServiceError,
+    oom,
+    persistence::{repository::ContainerRepository, ContainerRecord},
+    runtime::adapter::{AdapterError, ContainerAdapter},
 };
 use feos_proto::{
     container_service::{
-        ContainerState, CreateContainerResponse, DeleteContainerRequest, DeleteContainerResponse,
-        StartContainerRequest, StartContainerResponse, StopContainerRequest, StopContainerResponse,
+        exec_container_request, exec_container_response, port_forward_request, ContainerConfig,
+        ContainerEvent, ContainerState, ContainerStateChangedEvent, CreateContainerResponse,
+        DeleteContainerRequest, DeleteContainerResponse, ExecContainerRequest,
+        ExecContainerResponse, ExecStart, PortForwardRequest, PortForwardResponse,
+        PortForwardStart, StartContainerRequest, StartContainerResponse, StopContainerRequest,
+        StopContainerResponse, StreamContainerEventsRequest, TerminalSize,
     },
     image_service::{
         image_service_client::ImageServiceClient, ImageState as OciImageState,
         WatchImageStatusRequest,
     },
+    task_service::{
+        exec_request as task_exec_request, exec_response as task_exec_response,
+        ExecRequest as TaskExecRequest, ExecStart as TaskExecStart,
+        TerminalSize as TaskTerminalSize,
+    },
 };
+use feos_utils::host::admission::{AdmissionController, WorkloadKind};
+use feos_utils::host::startup::{StartupOrder, WorkloadRef, DEFAULT_DEPENDENCY_TIMEOUT};
+use feos_utils::network::happy_eyeballs;
 use hyper_util::rt::TokioIo;
 use image_service::IMAGE_SERVICE_SOCKET;
-use log::{error, info, warn};
-use std::{path::PathBuf, sync::Arc};
-use tokio::sync::oneshot;
-use tokio_stream::StreamExt;
+use log::{debug, error, info, warn};
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+use prost::Message;
+use prost_types::Any;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::time::Instant;
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
 use tonic::transport::{Channel, Endpoint, Uri};
+use tonic::{Status, Streaming};
 use tower::service_fn;
 use uuid::Uuid;
 
+/// How long StopContainer waits for a container to exit if neither the
+/// request nor the container say.
+const DEFAULT_STOP_TIMEOUT: Duration = Duration::from_secs(10);
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
 async fn get_image_service_client() -> Result<ImageServiceClient<Channel>, ContainerServiceError> {
     let socket_path = PathBuf::from(IMAGE_SERVICE_SOCKET);
     Endpoint::try_from("http://[::1]:50051")
@@ -83,14 +114,260 @@ async fn wait_for_image_ready(
     )))
 }
 
-pub async fn handle_create_container(
+pub fn state_change_event(
+    container_id: &str,
+    new_state: ContainerState,
+    reason: &str,
+) -> ContainerEvent {
+    let data = ContainerStateChangedEvent {
+        new_state: new_state as i32,
+        reason: reason.to_string(),
+    };
+    ContainerEvent {
+        container_id: container_id.to_string(),
+        id: Uuid::new_v4().to_string(),
+        data: Some(Any {
+            type_url: "type.googleapis.com/feos.container.v1.ContainerStateChangedEvent"
+                .to_string(),
+            value: data.encode_to_vec(),
+        }),
+        node_id: String::new(),
+    }
+}
+
+pub fn broadcast_state_change(
+    event_tx: &broadcast::Sender<ContainerEvent>,
+    container_id: &str,
+    new_state: ContainerState,
+    reason: &str,
+) {
+    let event = state_change_event(container_id, new_state, reason);
+    if event_tx.send(event).is_err() {
+        debug!("Worker: No active event subscribers for container {container_id}.");
+    }
+}
+
+/// Keeps track of the running containers that other workloads are started
+/// after.
+pub async fn track_running(
+    startup: StartupOrder,
+    mut event_rx: broadcast::Receiver<ContainerEvent>,
+) {
+    loop {
+        let event = match event_rx.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                warn!("Worker (Startup): Missed {n} container events.");
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+        let Some(data) = event
+            .data
+            .filter(|data| data.type_url.contains("ContainerStateChangedEvent"))
+        else {
+            continue;
+        };
+        match ContainerStateChangedEvent::decode(&*data.value) {
+            Ok(change) => startup.set_running(
+                &WorkloadRef::new(WorkloadKind::Container, event.container_id),
+                change.new_state == ContainerState::Running as i32,
+            ),
+            Err(e) => warn!("Worker (Startup): Failed to decode state change: {e}"),
+        }
+    }
+}
+
+/// Keeps the container events for ListContainerEvents, also after the
+/// containers are deleted.
+pub async fn record_events(
+    repository: ContainerRepository,
+    mut event_rx: broadcast::Receiver<ContainerEvent>,
+) {
+    loop {
+        let event = match event_rx.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                warn!("Worker (History): Missed {n} container events.");
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+        let record = match Uuid::parse_str(&event.container_id) {
+            Ok(id) => repository.get_container(id).await,
+            Err(_) => Ok(None),
+        };
+        let namespace = match record {
+            Ok(record) => record.map(|record| record.namespace),
+            Err(e) => {
+                warn!(
+                    "Worker (History): Failed to look up the namespace of container {}: {e}",
+                    event.container_id
+                );
+                None
+            }
+        };
+        if let Err(e) = repository
+            .save_container_event(&event, namespace.as_deref(), SystemTime::now())
+            .await
+        {
+            error!(
+                "Worker (History): Failed to record event {} of container {}: {e}",
+                event.id, event.container_id
+            );
+        }
+    }
+}
+
+/// Whether the containers events are about belong to a namespace. The
+/// namespace of a container never changes, so it is looked up once per
+/// container.
+struct NamespaceFilter {
+    namespace: String,
+    repository: ContainerRepository,
+    members: HashMap<String, bool>,
+}
+
+impl NamespaceFilter {
+    async fn matches(&mut self, container_id: &str) -> bool {
+        if let Some(member) = self.members.get(container_id) {
+            return *member;
+        }
+        let record = match Uuid::parse_str(container_id) {
+            Ok(id) => self.repository.get_container(id).await,
+            Err(_) => Ok(None),
+        };
+        match record {
+            Ok(Some(record)) => {
+                let member = record.namespace == self.namespace;
+                self.members.insert(container_id.to_string(), member);
+                member
+            }
+            // Without a record the container is gone, so are further events
+            // of it.
+            Ok(None) => false,
+            Err(e) => {
+                warn!("Worker (Stream): Failed to look up the namespace of container {container_id}: {e}");
+                false
+            }
+        }
+    }
+}
+
+pub async fn handle_stream_container_events(
+    req: StreamContainerEventsRequest,
+    stream_tx: mpsc::Sender<Result<ContainerEvent, Status>>,
+    mut event_rx: broadcast::Receiver<ContainerEvent>,
+    repository: ContainerRepository,
+) {
+    let container_id_to_watch = req.container_id;
+    let mut namespace_filter = req.namespace.map(|namespace| NamespaceFilter {
+        namespace,
+        repository,
+        members: HashMap::new(),
+    });
+
+    let watcher_desc = container_id_to_watch
+        .clone()
+        .unwrap_or_else(|| "all containers".to_string());
+
+    loop {
+        match event_rx.recv().await {
+            Ok(event) => {
+                if !container_id_to_watch
+                    .as_ref()
+                    .is_none_or(|id| event.container_id == *id)
+                {
+                    continue;
+                }
+                if let Some(filter) = namespace_filter.as_mut() {
+                    if !filter.matches(&event.container_id).await {
+                        continue;
+                    }
+                }
+                if stream_tx.send(Ok(event)).await.is_err() {
+                    info!("Worker (Stream): Client for '{watcher_desc}' disconnected.");
+                    break;
+                }
+            }
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                warn!("Worker (Stream): Event stream for '{watcher_desc}' lagged by {n} messages.");
+            }
+            Err(broadcast::error::RecvError::Closed) => {
+                info!(
+                    "Worker (Stream): Event channel closed. Shutting down stream for '{watcher_desc}'."
+                );
+                break;
+            }
+        }
+    }
+}
+
+/// Resolves once the creation of `container_id` is cancelled.
+async fn creation_cancelled(container_id: &str, cancelled: &mut broadcast::Receiver<String>) {
+    loop {
+        match cancelled.recv().await {
+            Ok(cancelled_id) if cancelled_id == container_id => return,
+            Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+            Err(broadcast::error::RecvError::Closed) => std::future::pending().await,
+        }
+    }
+}
+
+/// Undoes what the creation of a container set up when it was cancelled,
+/// wherever that was. The runtime may have created the task already.
+async fn cancel_container_creation(
     container_id: Uuid,
-    image_uuid: Uuid,
-    image_ref: String,
+    repository: &ContainerRepository,
+    adapter: &ContainerAdapter,
+) {
+    let id_str = container_id.to_string();
+    warn!("ContainerWorker ({id_str}): The container was deleted while it was being created. Cancelling the creation.");
+    if adapter.delete_container(&id_str).await.is_err() {
+        if let Err(e) = adapter.remove_bundle(&id_str).await {
+            warn!("ContainerWorker ({id_str}): Failed to remove the bundle of the cancelled creation: {e}");
+        }
+    }
+    if let Err(e) = repository.delete_container(container_id).await {
+        warn!("Failed to cleanup DB record for cancelled creation of {container_id}: {e}");
+    }
+}
+
+/// Tells watchers that the creation failed before its record is removed, so
+/// they don't keep waiting for a container that no longer exists.
+async fn fail_container_creation(
+    container_id: Uuid,
+    error_msg: &str,
+    repository: &ContainerRepository,
+    event_tx: &broadcast::Sender<ContainerEvent>,
+) {
+    error!("ContainerWorker ({container_id}): {error_msg}");
+    broadcast_state_change(
+        event_tx,
+        &container_id.to_string(),
+        ContainerState::Failed,
+        &format!("{error_msg}. The container was removed."),
+    );
+    if let Err(e) = repository.delete_container(container_id).await {
+        warn!("Failed to cleanup DB record for failed creation of {container_id}: {e}");
+    }
+}
+
+/// Creates a container after its client was told its ID. Deleting the
+/// container meanwhile cancels the creation, while it waits for the image or
+/// prepares the bundle.
+pub async fn handle_create_container(
+    record: ContainerRecord,
     responder: oneshot::Sender<Result<CreateContainerResponse, ContainerServiceError>>,
     repository: ContainerRepository,
     adapter: Arc<ContainerAdapter>,
-) {
+    event_tx: broadcast::Sender<ContainerEvent>,
+    mut cancelled: broadcast::Receiver<String>,
+) -> bool {
+    let container_id = record.container_id;
+    let id_str = container_id.to_string();
+    let image_uuid = record.image_uuid;
+    let config = &record.config;
     if responder
         .send(Ok(CreateContainerResponse {
             container_id: container_id.to_string(),
@@ -103,28 +380,39 @@ pub async fn handle_create_container(
                 "Failed to cleanup initial DB record for aborted creation of {container_id}: {e}"
             );
         }
-        return;
+        return false;
     }
 
+    let image_ref = &config.image_ref;
     info!("ContainerWorker ({container_id}): Waiting for image '{image_ref}' (uuid: {image_uuid}) to be ready...");
 
-    if let Err(e) = wait_for_image_ready(&image_uuid.to_string(), &image_ref).await {
-        let error_msg = e.to_string();
-        error!("ContainerWorker ({container_id}): {error_msg}");
-        if let Err(e) = repository.delete_container(container_id).await {
-            warn!("Failed to cleanup DB record for failed creation of {container_id}: {e}");
+    let ready = tokio::select! {
+        ready = wait_for_image_ready(&image_uuid.to_string(), image_ref) => Some(ready),
+        () = creation_cancelled(&id_str, &mut cancelled) => None,
+    };
+    match ready {
+        Some(Ok(())) => {}
+        Some(Err(e)) => {
+            fail_container_creation(container_id, &e.to_string(), &repository, &event_tx).await;
+            return false;
+        }
+        None => {
+            cancel_container_creation(container_id, &repository, &adapter).await;
+            return false;
         }
-        return;
     }
     info!("ContainerWorker ({container_id}): Image is ready.");
 
-    let bundle_path = PathBuf::from(image_service::IMAGE_DIR).join(image_uuid.to_string());
+    let image_dir = PathBuf::from(image_service::IMAGE_DIR).join(image_uuid.to_string());
 
-    match adapter
-        .create_container(&container_id.to_string(), &bundle_path)
-        .await
-    {
-        Ok(pid) => {
+    let created = tokio::select! {
+        created = adapter.create_container(&id_str, &record.namespace, &image_dir, config) => {
+            Some(created)
+        }
+        () = creation_cancelled(&id_str, &mut cancelled) => None,
+    };
+    match created {
+        Some(Ok(pid)) => {
             info!("ContainerWorker ({container_id}): Container created successfully by runtime with PID {pid}.");
             if let Err(e) = repository.update_container_pid(container_id, pid).await {
                 error!("ContainerWorker ({container_id}): Failed to update PID in DB: {e}");
@@ -135,15 +423,159 @@ pub async fn handle_create_container(
             {
                 error!("ContainerWorker ({container_id}): Failed to update state to CREATED in DB: {e}");
             }
+            broadcast_state_change(
+                &event_tx,
+                &container_id.to_string(),
+                ContainerState::Created,
+                "Container created by runtime",
+            );
+            true
         }
-        Err(e) => {
+        Some(Err(e)) => {
             let error_msg = format!("Adapter failed to create container: {e}");
-            error!("ContainerWorker ({container_id}): {error_msg}");
-            if let Err(e) = repository.delete_container(container_id).await {
-                warn!("Failed to cleanup DB record for failed creation of {container_id}: {e}");
-            }
+            fail_container_creation(container_id, &error_msg, &repository, &event_tx).await;
+            false
+        }
+        None => {
+            cancel_container_creation(container_id, &repository, &adapter).await;
+            false
+        }
+    }
+}
+
+/// Creates and starts a container again whose process is gone, e.g. after a
+/// reboot of the host, once the network is up and the workloads it is
+/// started after are running. Failures leave it stopped with the reason.
+pub async fn recover_container(
+    record: ContainerRecord,
+    startup: StartupOrder,
+    repository: ContainerRepository,
+    adapter: Arc<ContainerAdapter>,
+    event_tx: broadcast::Sender<ContainerEvent>,
+) {
+    let container_id = record.container_id;
+    let id_str = container_id.to_string();
+    let config = record.config;
+    let timeout = config
+        .startup
+        .as_ref()
+        .and_then(|startup| startup.dependency_timeout_seconds)
+        .map_or(DEFAULT_DEPENDENCY_TIMEOUT, |secs| {
+            Duration::from_secs(secs.into())
+        });
+
+    let result = async {
+        startup.wait_for_network().await;
+        startup
+            .wait_for_dependencies(&WorkloadRef::new(WorkloadKind::Container, &id_str), timeout)
+            .await
+            .map_err(|e| ContainerServiceError::InvalidState(e.to_string()))?;
+        info!(
+            "ContainerWorker ({id_str}): Startup dependencies are running, recreating container."
+        );
+
+        // The runtime may still know the container if only FeOS restarted.
+        if adapter.delete_container(&id_str).await.is_err() {
+            adapter
+                .remove_bundle(&id_str)
+                .await
+                .map_err(adapter_error)?;
+        }
+        let image_dir = PathBuf::from(image_service::IMAGE_DIR).join(record.image_uuid.to_string());
+        let pid = adapter
+            .create_container(&id_str, &record.namespace, &image_dir, &config)
+            .await
+            .map_err(adapter_error)?;
+        repository.update_container_pid(container_id, pid).await?;
+        adapter
+            .start_container(&id_str)
+            .await
+            .map_err(adapter_error)?;
+        repository
+            .update_container_started(container_id, SystemTime::now())
+            .await?;
+        Ok::<_, ContainerServiceError>(())
+    }
+    .await;
+
+    let (state, reason) = match result {
+        Ok(()) => {
+            tokio::spawn(watch_exit(
+                container_id,
+                repository.clone(),
+                adapter,
+                event_tx.clone(),
+            ));
+            (ContainerState::Running, "Auto-started".to_string())
+        }
+        Err(e) => {
+            error!("ContainerWorker ({id_str}): Auto-start failed: {e}");
+            (ContainerState::Stopped, format!("Auto-start failed: {e}"))
+        }
+    };
+    if let Err(e) = repository.update_container_state(container_id, state).await {
+        error!("ContainerWorker ({id_str}): Failed to update state to {state:?} in DB: {e}");
+    }
+    broadcast_state_change(&event_tx, &id_str, state, &reason);
+}
+
+/// Waits for the process of a started container to exit, records how it
+/// ended and marks the container stopped unless that happened already.
+pub async fn watch_exit(
+    container_id: Uuid,
+    repository: ContainerRepository,
+    adapter: Arc<ContainerAdapter>,
+    event_tx: broadcast::Sender<ContainerEvent>,
+) {
+    let id_str = container_id.to_string();
+    let exit = match adapter.wait_container(&id_str).await {
+        Ok(exit) => exit,
+        Err(e) => {
+            warn!("ContainerWorker ({id_str}): Failed to wait for the container to exit: {e}");
+            return;
+        }
+    };
+    info!(
+        "ContainerWorker ({id_str}): Container process exited with code {}.",
+        exit.exit_code
+    );
+    if let Err(e) = repository
+        .update_container_exit(container_id, SystemTime::now(), exit.exit_code, exit.signal)
+        .await
+    {
+        error!("ContainerWorker ({id_str}): Failed to save exit status: {e}");
+    }
+
+    let record = match repository.get_container(container_id).await {
+        Ok(Some(record)) => record,
+        Ok(None) => return,
+        Err(e) => {
+            error!("ContainerWorker ({id_str}): Failed to get container after exit: {e}");
+            return;
         }
+    };
+    // Stopping the container marked it stopped already.
+    if record.status.state != ContainerState::Running {
+        return;
+    }
+    let oom_killed = exit.signal == Some(Signal::SIGKILL as i32)
+        && oom::record_oom_exit(&record, &repository, &event_tx).await;
+    let reason = match exit.signal {
+        _ if oom_killed => "Killed by the OOM killer".to_string(),
+        Some(signal) => format!("Killed by signal {signal}"),
+        None => format!("Exited with code {}", exit.exit_code),
+    };
+    if let Err(e) = repository
+        .update_container_state(container_id, ContainerState::Stopped)
+        .await
+    {
+        error!("ContainerWorker ({id_str}): Failed to update state to STOPPED in DB: {e}");
     }
+    broadcast_state_change(&event_tx, &id_str, ContainerState::Stopped, &reason);
+}
+
+fn adapter_error(e: AdapterError) -> ContainerServiceError {
+    ContainerServiceError::Adapter(e.to_string())
 }
 
 pub async fn handle_start_container(
@@ -151,6 +583,7 @@ pub async fn handle_start_container(
     responder: oneshot::Sender<Result<StartContainerResponse, ContainerServiceError>>,
     repository: ContainerRepository,
     adapter: Arc<ContainerAdapter>,
+    event_tx: broadcast::Sender<ContainerEvent>,
 ) {
     let id_str = req.container_id.clone();
     let result = adapter.start_container(&id_str).await;
@@ -168,6 +601,19 @@ pub async fn handle_start_container(
                 let _ = responder.send(Err(err));
                 return;
             }
+            if let Err(e) = repository
+                .update_container_started(container_id, SystemTime::now())
+                .await
+            {
+                error!("Worker: Failed to save start time of container {id_str}: {e}");
+            }
+            broadcast_state_change(
+                &event_tx,
+                &id_str,
+                ContainerState::Running,
+                "Start command successful",
+            );
+            tokio::spawn(watch_exit(container_id, repository, adapter, event_tx));
             let _ = responder.send(Ok(StartContainerResponse {}));
         }
         Err(e) => {
@@ -178,19 +624,70 @@ pub async fn handle_start_container(
     }
 }
 
+/// The signal that asks a container to stop.
+pub(crate) fn stop_signal(config: &ContainerConfig) -> u32 {
+    config.stop_signal.unwrap_or(Signal::SIGTERM as u32)
+}
+
+/// Sends `signal` and, if the container is still running after `timeout`,
+/// SIGKILL. Returns whether it had to be killed.
+pub(crate) async fn stop_with_timeout(
+    id: &str,
+    process_id: Option<i64>,
+    signal: u32,
+    timeout: Duration,
+    adapter: &ContainerAdapter,
+) -> Result<bool, AdapterError> {
+    adapter.stop_container(id, signal).await?;
+    if signal == Signal::SIGKILL as u32 {
+        return Ok(false);
+    }
+    let Some(pid) = process_id.and_then(|pid| i32::try_from(pid).ok()) else {
+        // Without a process to watch, give it the whole timeout.
+        tokio::time::sleep(timeout).await;
+        adapter.stop_container(id, Signal::SIGKILL as u32).await?;
+        return Ok(true);
+    };
+
+    let deadline = Instant::now() + timeout;
+    while kill(Pid::from_raw(pid), None).is_ok() {
+        if Instant::now() + EXIT_POLL_INTERVAL > deadline {
+            info!("ContainerWorker ({id}): Container did not exit within {timeout:?}, killing it.");
+            adapter.stop_container(id, Signal::SIGKILL as u32).await?;
+            return Ok(true);
+        }
+        tokio::time::sleep(EXIT_POLL_INTERVAL).await;
+    }
+    Ok(false)
+}
+
 pub async fn handle_stop_container(
     req: StopContainerRequest,
+    record: ContainerRecord,
     responder: oneshot::Sender<Result<StopContainerResponse, ContainerServiceError>>,
     repository: ContainerRepository,
     adapter: Arc<ContainerAdapter>,
+    event_tx: broadcast::Sender<ContainerEvent>,
 ) {
     let id_str = req.container_id.clone();
-    let signal = req.signal.unwrap_or(9);
-    let result = adapter.stop_container(&id_str, signal).await;
+    let signal = req.signal.unwrap_or_else(|| stop_signal(&record.config));
+    let timeout = req
+        .timeout_seconds
+        .or(record.config.stop_timeout_seconds)
+        .map_or(DEFAULT_STOP_TIMEOUT, |secs| {
+            Duration::from_secs(secs.into())
+        });
+    let result =
+        stop_with_timeout(&id_str, record.status.process_id, signal, timeout, &adapter).await;
 
     match result {
-        Ok(_) => {
-            info!("Worker: Stop command sent for container {id_str}");
+        Ok(killed) => {
+            info!("Worker: Stopped container {id_str}");
+            let reason = if killed {
+                format!("Stop command successful, killed after {timeout:?}")
+            } else {
+                "Stop command successful".to_string()
+            };
             let container_id = Uuid::parse_str(&id_str).unwrap();
             if let Err(e) = repository
                 .update_container_state(container_id, ContainerState::Stopped)
@@ -201,6 +698,7 @@ pub async fn handle_stop_container(
                 let _ = responder.send(Err(err));
                 return;
             }
+            broadcast_state_change(&event_tx, &id_str, ContainerState::Stopped, &reason);
             let _ = responder.send(Ok(StopContainerResponse {}));
         }
         Err(e) => {
@@ -213,12 +711,27 @@ pub async fn handle_stop_container(
 
 pub async fn handle_delete_container(
     req: DeleteContainerRequest,
+    record: ContainerRecord,
     responder: oneshot::Sender<Result<DeleteContainerResponse, ContainerServiceError>>,
     repository: ContainerRepository,
     adapter: Arc<ContainerAdapter>,
+    admission: AdmissionController,
+    startup: StartupOrder,
 ) {
     let id_str = req.container_id.clone();
-    let result = adapter.delete_container(&id_str).await;
+    let creating = record.status.state == ContainerState::PullingImage;
+    if creating {
+        adapter.cancel_creation(&id_str);
+    }
+    let result = match adapter.delete_container(&id_str).await {
+        // The runtime may not know the container yet, its creation cleans
+        // up after itself.
+        Err(e) if creating => {
+            debug!("Worker: Container {id_str} is not created by the runtime yet: {e}");
+            Ok(())
+        }
+        result => result,
+    };
 
     match result {
         Ok(_) => {
@@ -230,6 +743,8 @@ pub async fn handle_delete_container(
                 let _ = responder.send(Err(err));
                 return;
             }
+            admission.release(WorkloadKind::Container, &id_str);
+            startup.remove(&WorkloadRef::new(WorkloadKind::Container, &id_str));
             let _ = responder.send(Ok(DeleteContainerResponse {}));
         }
         Err(e) => {
@@ -239,3 +754,161 @@ pub async fn handle_delete_container(
         }
     }
 }
+
+fn to_task_terminal_size(size: TerminalSize) -> TaskTerminalSize {
+    TaskTerminalSize {
+        rows: size.rows,
+        cols: size.cols,
+    }
+}
+
+pub async fn handle_exec_container(
+    start: ExecStart,
+    mut input_stream: Streaming<ExecContainerRequest>,
+    output_tx: mpsc::Sender<Result<ExecContainerResponse, Status>>,
+    adapter: Arc<ContainerAdapter>,
+) {
+    let id_str = start.container_id.clone();
+    let (task_input_tx, task_input_rx) = mpsc::channel(32);
+
+    let task_start = TaskExecRequest {
+        payload: Some(task_exec_request::Payload::Start(TaskExecStart {
+            container_id: start.container_id,
+            args: start.command,
+            tty: start.tty,
+            terminal_size: start.terminal_size.map(to_task_terminal_size),
+        })),
+    };
+    if task_input_tx.send(task_start).await.is_err() {
+        error!("Worker: Failed to queue exec start for container {id_str}");
+        return;
+    }
+
+    let mut task_output = match adapter
+        .exec_container(ReceiverStream::new(task_input_rx))
+        .await
+    {
+        Ok(stream) => stream,
+        Err(e) => {
+            let err = ContainerServiceError::Adapter(e.to_string());
+            error!("Worker: {err}");
+            let _ = output_tx.send(Err(err.into())).await;
+            return;
+        }
+    };
+    info!("Worker: Exec session started for container {id_str}");
+
+    let input_task = tokio::spawn(async move {
+        while let Some(Ok(msg)) = input_stream.next().await {
+            let payload = match msg.payload {
+                Some(exec_container_request::Payload::Stdin(data)) => {
+                    task_exec_request::Payload::Stdin(data)
+                }
+                Some(exec_container_request::Payload::Resize(size)) => {
+                    task_exec_request::Payload::Resize(to_task_terminal_size(size))
+                }
+                Some(exec_container_request::Payload::Start(_)) | None => continue,
+            };
+            let request = TaskExecRequest {
+                payload: Some(payload),
+            };
+            if task_input_tx.send(request).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    while let Some(result) = task_output.next().await {
+        let response = result.map(|msg| ExecContainerResponse {
+            payload: msg.payload.map(|payload| match payload {
+                task_exec_response::Payload::Stdout(data) => {
+                    exec_container_response::Payload::Stdout(data)
+                }
+                task_exec_response::Payload::Stderr(data) => {
+                    exec_container_response::Payload::Stderr(data)
+                }
+                task_exec_response::Payload::ExitCode(code) => {
+                    exec_container_response::Payload::ExitCode(code)
+                }
+            }),
+        });
+        if output_tx.send(response).await.is_err() {
+            warn!("Worker: Exec client for container {id_str} disconnected.");
+            break;
+        }
+    }
+
+    input_task.abort();
+    info!("Worker: Exec session for container {id_str} finished.");
+}
+
+/// Containers share the host network namespace, so a port inside the
+/// container is reachable on the host's loopback interface.
+pub async fn handle_port_forward(
+    start: PortForwardStart,
+    mut input_stream: Streaming<PortForwardRequest>,
+    output_tx: mpsc::Sender<Result<PortForwardResponse, Status>>,
+) {
+    let id_str = start.container_id;
+    let port = match u16::try_from(start.port) {
+        Ok(port) if port != 0 => port,
+        _ => {
+            let err =
+                ContainerServiceError::InvalidArgument(format!("Invalid port {}", start.port));
+            let _ = output_tx.send(Err(err.into())).await;
+            return;
+        }
+    };
+
+    let stream = match happy_eyeballs::connect("localhost", port).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            let msg = format!("Failed to connect to port {port} of container {id_str}: {e}");
+            warn!("Worker: {msg}");
+            let _ = output_tx.send(Err(Status::unavailable(msg))).await;
+            return;
+        }
+    };
+    info!("Worker: Port forward to port {port} of container {id_str} established.");
+    let (mut tcp_reader, mut tcp_writer) = stream.into_split();
+
+    let input_task = tokio::spawn(async move {
+        while let Some(Ok(msg)) = input_stream.next().await {
+            match msg.payload {
+                Some(port_forward_request::Payload::Data(data)) => {
+                    if tcp_writer.write_all(&data).await.is_err() {
+                        break;
+                    }
+                }
+                Some(port_forward_request::Payload::Start(_)) | None => continue,
+            }
+        }
+        let _ = tcp_writer.shutdown().await;
+    });
+
+    let mut buf = vec![0; 16384];
+    loop {
+        tokio::select! {
+            biased;
+            _ = output_tx.closed() => break,
+            read_result = tcp_reader.read(&mut buf) => match read_result {
+                Ok(0) => break,
+                Ok(n) => {
+                    let response = PortForwardResponse { data: buf[..n].to_vec() };
+                    if output_tx.send(Ok(response)).await.is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    let _ = output_tx
+                        .send(Err(Status::internal(format!("Error reading from port {port}: {e}"))))
+                        .await;
+                    break;
+                }
+            },
+        }
+    }
+
+    input_task.abort();
+    info!("Worker: Port forward to port {port} of container {id_str} closed.");
+}
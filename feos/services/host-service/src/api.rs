@@ -3,11 +3,27 @@
 
 use crate::Command;
 use feos_proto::host_service::{
-    host_service_server::HostService, FeosLogEntry, GetCpuInfoRequest, GetCpuInfoResponse,
-    GetKernelStatsRequest, GetKernelStatsResponse, GetNetworkInfoRequest, GetNetworkInfoResponse,
-    GetVersionInfoRequest, GetVersionInfoResponse, HostnameRequest, HostnameResponse,
-    KernelLogEntry, MemoryRequest, MemoryResponse, RebootRequest, RebootResponse, ShutdownRequest,
-    ShutdownResponse, StreamFeosLogsRequest, StreamKernelLogsRequest, UpgradeFeosBinaryRequest,
+    host_service_server::HostService, AddFirewallExceptionRequest, AddFirewallExceptionResponse,
+    AddSwapRequest, AddSwapResponse, CreateDebugBundleRequest, CreateGpuPartitionRequest,
+    CreateGpuPartitionResponse, CreateMdevRequest, CreateMdevResponse, CreateNvmeNamespaceRequest,
+    CreateNvmeNamespaceResponse, DebugBundleChunk, DeleteNvmeNamespaceRequest,
+    DeleteNvmeNamespaceResponse, DestroyGpuPartitionRequest, DestroyGpuPartitionResponse,
+    DrainHostProgress, DrainHostRequest, ExportLogsRequest, FeosLogEntry,
+    FormatNvmeNamespaceRequest, FormatNvmeNamespaceResponse, GetCapabilitiesRequest,
+    GetCapabilitiesResponse, GetClockInfoRequest, GetClockInfoResponse, GetCpuInfoRequest,
+    GetCpuInfoResponse, GetFirewallRequest, GetFirewallResponse, GetKernelStatsRequest,
+    GetKernelStatsResponse, GetLogLevelsRequest, GetLogLevelsResponse, GetNetworkInfoRequest,
+    GetNetworkInfoResponse, GetNicTuningRequest, GetNicTuningResponse, GetNodeIdentityRequest,
+    GetNodeIdentityResponse, GetVersionInfoRequest, GetVersionInfoResponse, HostnameRequest,
+    HostnameResponse, KernelLogEntry, ListGpuPartitionsRequest, ListGpuPartitionsResponse,
+    ListMdevsRequest, ListMdevsResponse, ListNvmeControllersRequest, ListNvmeControllersResponse,
+    ListSwapRequest, ListSwapResponse, LogArchiveChunk, MemoryRequest, MemoryResponse,
+    ReadFeosLogsRequest, RebootRequest, RebootResponse, RemoveFirewallExceptionRequest,
+    RemoveFirewallExceptionResponse, RemoveMdevRequest, RemoveMdevResponse, RemoveSwapRequest,
+    RemoveSwapResponse, SetClocksourceRequest, SetClocksourceResponse, SetLogLevelRequest,
+    SetLogLevelResponse, SetNicTuningRequest, SetNicTuningResponse, SetSwappinessRequest,
+    SetSwappinessResponse, ShutdownRequest, ShutdownResponse, StreamFeosLogsRequest,
+    StreamKernelLogsRequest, UncordonHostRequest, UncordonHostResponse, UpgradeFeosBinaryRequest,
     UpgradeFeosBinaryResponse,
 };
 use log::info;
@@ -18,11 +34,16 @@ use tonic::{Request, Response, Status};
 
 pub struct HostApiHandler {
     dispatcher_tx: mpsc::Sender<Command>,
+    /// The ID of the node, which stats are stamped with.
+    node_id: String,
 }
 
 impl HostApiHandler {
-    pub fn new(dispatcher_tx: mpsc::Sender<Command>) -> Self {
-        Self { dispatcher_tx }
+    pub fn new(dispatcher_tx: mpsc::Sender<Command>, node_id: String) -> Self {
+        Self {
+            dispatcher_tx,
+            node_id,
+        }
     }
 }
 
@@ -55,6 +76,11 @@ impl HostService for HostApiHandler {
     type StreamKernelLogsStream =
         Pin<Box<dyn Stream<Item = Result<KernelLogEntry, Status>> + Send>>;
     type StreamFeOSLogsStream = Pin<Box<dyn Stream<Item = Result<FeosLogEntry, Status>> + Send>>;
+    type ReadFeOSLogsStream = Pin<Box<dyn Stream<Item = Result<FeosLogEntry, Status>> + Send>>;
+    type ExportLogsStream = Pin<Box<dyn Stream<Item = Result<LogArchiveChunk, Status>> + Send>>;
+    type CreateDebugBundleStream =
+        Pin<Box<dyn Stream<Item = Result<DebugBundleChunk, Status>> + Send>>;
+    type DrainHostStream = Pin<Box<dyn Stream<Item = Result<DrainHostProgress, Status>> + Send>>;
 
     async fn hostname(
         &self,
@@ -85,7 +111,9 @@ impl HostService for HostApiHandler {
         _request: Request<GetKernelStatsRequest>,
     ) -> Result<Response<GetKernelStatsResponse>, Status> {
         info!("HostApi: Received GetKernelStats request.");
-        dispatch_and_wait(&self.dispatcher_tx, Command::GetKernelStats).await
+        let mut response = dispatch_and_wait(&self.dispatcher_tx, Command::GetKernelStats).await?;
+        response.get_mut().node_id.clone_from(&self.node_id);
+        Ok(response)
     }
 
     async fn get_network_info(
@@ -146,11 +174,11 @@ impl HostService for HostApiHandler {
 
     async fn stream_fe_os_logs(
         &self,
-        _request: Request<StreamFeosLogsRequest>,
+        request: Request<StreamFeosLogsRequest>,
     ) -> Result<Response<Self::StreamFeOSLogsStream>, Status> {
         info!("HostApi: Received StreamFeOSLogs request.");
         let (stream_tx, stream_rx) = mpsc::channel(128);
-        let cmd = Command::StreamFeOSLogs(stream_tx);
+        let cmd = Command::StreamFeOSLogs(request.into_inner(), stream_tx);
         self.dispatcher_tx
             .send(cmd)
             .await
@@ -159,6 +187,40 @@ impl HostService for HostApiHandler {
         Ok(Response::new(Box::pin(output_stream)))
     }
 
+    async fn read_fe_os_logs(
+        &self,
+        request: Request<ReadFeosLogsRequest>,
+    ) -> Result<Response<Self::ReadFeOSLogsStream>, Status> {
+        info!("HostApi: Received ReadFeOSLogs request.");
+        let (stream_tx, stream_rx) = mpsc::channel(128);
+        let cmd = Command::ReadFeOSLogs(request.into_inner(), stream_tx);
+        self.dispatcher_tx
+            .send(cmd)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to send command to dispatcher: {e}")))?;
+        let output_stream = ReceiverStream::new(stream_rx);
+        Ok(Response::new(Box::pin(output_stream)))
+    }
+
+    async fn set_log_level(
+        &self,
+        request: Request<SetLogLevelRequest>,
+    ) -> Result<Response<SetLogLevelResponse>, Status> {
+        info!("HostApi: Received SetLogLevel request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::SetLogLevel(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn get_log_levels(
+        &self,
+        _request: Request<GetLogLevelsRequest>,
+    ) -> Result<Response<GetLogLevelsResponse>, Status> {
+        info!("HostApi: Received GetLogLevels request.");
+        dispatch_and_wait(&self.dispatcher_tx, Command::GetLogLevels).await
+    }
+
     async fn get_version_info(
         &self,
         _request: Request<GetVersionInfoRequest>,
@@ -166,4 +228,285 @@ impl HostService for HostApiHandler {
         info!("HostApi: Received GetVersionInfo request.");
         dispatch_and_wait(&self.dispatcher_tx, Command::GetVersionInfo).await
     }
+
+    async fn create_debug_bundle(
+        &self,
+        _request: Request<CreateDebugBundleRequest>,
+    ) -> Result<Response<Self::CreateDebugBundleStream>, Status> {
+        info!("HostApi: Received CreateDebugBundle request.");
+        let (stream_tx, stream_rx) = mpsc::channel(16);
+        let cmd = Command::CreateDebugBundle(stream_tx);
+        self.dispatcher_tx
+            .send(cmd)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to send command to dispatcher: {e}")))?;
+        let output_stream = ReceiverStream::new(stream_rx);
+        Ok(Response::new(Box::pin(output_stream)))
+    }
+
+    async fn export_logs(
+        &self,
+        request: Request<ExportLogsRequest>,
+    ) -> Result<Response<Self::ExportLogsStream>, Status> {
+        info!("HostApi: Received ExportLogs request.");
+        let (stream_tx, stream_rx) = mpsc::channel(16);
+        let cmd = Command::ExportLogs(request.into_inner(), stream_tx);
+        self.dispatcher_tx
+            .send(cmd)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to send command to dispatcher: {e}")))?;
+        let output_stream = ReceiverStream::new(stream_rx);
+        Ok(Response::new(Box::pin(output_stream)))
+    }
+
+    async fn add_swap(
+        &self,
+        request: Request<AddSwapRequest>,
+    ) -> Result<Response<AddSwapResponse>, Status> {
+        info!("HostApi: Received AddSwap request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::AddSwap(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn list_swap(
+        &self,
+        _request: Request<ListSwapRequest>,
+    ) -> Result<Response<ListSwapResponse>, Status> {
+        info!("HostApi: Received ListSwap request.");
+        dispatch_and_wait(&self.dispatcher_tx, Command::ListSwap).await
+    }
+
+    async fn remove_swap(
+        &self,
+        request: Request<RemoveSwapRequest>,
+    ) -> Result<Response<RemoveSwapResponse>, Status> {
+        info!("HostApi: Received RemoveSwap request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::RemoveSwap(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn set_swappiness(
+        &self,
+        request: Request<SetSwappinessRequest>,
+    ) -> Result<Response<SetSwappinessResponse>, Status> {
+        info!("HostApi: Received SetSwappiness request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::SetSwappiness(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn get_clock_info(
+        &self,
+        _request: Request<GetClockInfoRequest>,
+    ) -> Result<Response<GetClockInfoResponse>, Status> {
+        info!("HostApi: Received GetClockInfo request.");
+        dispatch_and_wait(&self.dispatcher_tx, Command::GetClockInfo).await
+    }
+
+    async fn set_clocksource(
+        &self,
+        request: Request<SetClocksourceRequest>,
+    ) -> Result<Response<SetClocksourceResponse>, Status> {
+        info!("HostApi: Received SetClocksource request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::SetClocksource(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn drain_host(
+        &self,
+        request: Request<DrainHostRequest>,
+    ) -> Result<Response<Self::DrainHostStream>, Status> {
+        info!("HostApi: Received DrainHost request.");
+        let (stream_tx, stream_rx) = mpsc::channel(16);
+        let cmd = Command::DrainHost(request.into_inner(), stream_tx);
+        self.dispatcher_tx
+            .send(cmd)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to send command to dispatcher: {e}")))?;
+        let output_stream = ReceiverStream::new(stream_rx);
+        Ok(Response::new(Box::pin(output_stream)))
+    }
+
+    async fn uncordon_host(
+        &self,
+        _request: Request<UncordonHostRequest>,
+    ) -> Result<Response<UncordonHostResponse>, Status> {
+        info!("HostApi: Received UncordonHost request.");
+        dispatch_and_wait(&self.dispatcher_tx, Command::UncordonHost).await
+    }
+
+    async fn list_gpu_partitions(
+        &self,
+        _request: Request<ListGpuPartitionsRequest>,
+    ) -> Result<Response<ListGpuPartitionsResponse>, Status> {
+        info!("HostApi: Received ListGpuPartitions request.");
+        dispatch_and_wait(&self.dispatcher_tx, Command::ListGpuPartitions).await
+    }
+
+    async fn create_gpu_partition(
+        &self,
+        request: Request<CreateGpuPartitionRequest>,
+    ) -> Result<Response<CreateGpuPartitionResponse>, Status> {
+        info!("HostApi: Received CreateGpuPartition request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::CreateGpuPartition(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn destroy_gpu_partition(
+        &self,
+        request: Request<DestroyGpuPartitionRequest>,
+    ) -> Result<Response<DestroyGpuPartitionResponse>, Status> {
+        info!("HostApi: Received DestroyGpuPartition request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::DestroyGpuPartition(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn list_mdevs(
+        &self,
+        _request: Request<ListMdevsRequest>,
+    ) -> Result<Response<ListMdevsResponse>, Status> {
+        info!("HostApi: Received ListMdevs request.");
+        dispatch_and_wait(&self.dispatcher_tx, Command::ListMdevs).await
+    }
+
+    async fn create_mdev(
+        &self,
+        request: Request<CreateMdevRequest>,
+    ) -> Result<Response<CreateMdevResponse>, Status> {
+        info!("HostApi: Received CreateMdev request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::CreateMdev(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn remove_mdev(
+        &self,
+        request: Request<RemoveMdevRequest>,
+    ) -> Result<Response<RemoveMdevResponse>, Status> {
+        info!("HostApi: Received RemoveMdev request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::RemoveMdev(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn list_nvme_controllers(
+        &self,
+        _request: Request<ListNvmeControllersRequest>,
+    ) -> Result<Response<ListNvmeControllersResponse>, Status> {
+        info!("HostApi: Received ListNvmeControllers request.");
+        dispatch_and_wait(&self.dispatcher_tx, Command::ListNvmeControllers).await
+    }
+
+    async fn create_nvme_namespace(
+        &self,
+        request: Request<CreateNvmeNamespaceRequest>,
+    ) -> Result<Response<CreateNvmeNamespaceResponse>, Status> {
+        info!("HostApi: Received CreateNvmeNamespace request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::CreateNvmeNamespace(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn delete_nvme_namespace(
+        &self,
+        request: Request<DeleteNvmeNamespaceRequest>,
+    ) -> Result<Response<DeleteNvmeNamespaceResponse>, Status> {
+        info!("HostApi: Received DeleteNvmeNamespace request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::DeleteNvmeNamespace(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn format_nvme_namespace(
+        &self,
+        request: Request<FormatNvmeNamespaceRequest>,
+    ) -> Result<Response<FormatNvmeNamespaceResponse>, Status> {
+        info!("HostApi: Received FormatNvmeNamespace request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::FormatNvmeNamespace(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn get_nic_tuning(
+        &self,
+        request: Request<GetNicTuningRequest>,
+    ) -> Result<Response<GetNicTuningResponse>, Status> {
+        info!("HostApi: Received GetNicTuning request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::GetNicTuning(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn set_nic_tuning(
+        &self,
+        request: Request<SetNicTuningRequest>,
+    ) -> Result<Response<SetNicTuningResponse>, Status> {
+        info!("HostApi: Received SetNicTuning request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::SetNicTuning(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn get_capabilities(
+        &self,
+        _request: Request<GetCapabilitiesRequest>,
+    ) -> Result<Response<GetCapabilitiesResponse>, Status> {
+        info!("HostApi: Received GetCapabilities request.");
+        dispatch_and_wait(&self.dispatcher_tx, Command::GetCapabilities).await
+    }
+    async fn get_firewall(
+        &self,
+        _request: Request<GetFirewallRequest>,
+    ) -> Result<Response<GetFirewallResponse>, Status> {
+        info!("HostApi: Received GetFirewall request.");
+        dispatch_and_wait(&self.dispatcher_tx, Command::GetFirewall).await
+    }
+
+    async fn add_firewall_exception(
+        &self,
+        request: Request<AddFirewallExceptionRequest>,
+    ) -> Result<Response<AddFirewallExceptionResponse>, Status> {
+        info!("HostApi: Received AddFirewallException request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::AddFirewallException(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn remove_firewall_exception(
+        &self,
+        request: Request<RemoveFirewallExceptionRequest>,
+    ) -> Result<Response<RemoveFirewallExceptionResponse>, Status> {
+        info!("HostApi: Received RemoveFirewallException request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::RemoveFirewallException(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn get_node_identity(
+        &self,
+        _request: Request<GetNodeIdentityRequest>,
+    ) -> Result<Response<GetNodeIdentityResponse>, Status> {
+        info!("HostApi: Received GetNodeIdentity request.");
+        dispatch_and_wait(&self.dispatcher_tx, Command::GetNodeIdentity).await
+    }
 }
@@ -2,14 +2,21 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{worker, Command, RestartSignal};
+use feos_proto::host_service::{GetCapabilitiesResponse, GetNodeIdentityResponse};
 use feos_utils::feos_logger::LogHandle;
-use log::info;
+use feos_utils::host::maintenance::Maintenance;
+use feos_utils::network::firewall::Profile as FirewallProfile;
+use log::{error, info};
 use tokio::sync::mpsc;
 
 pub struct HostServiceDispatcher {
     rx: mpsc::Receiver<Command>,
     restart_tx: mpsc::Sender<RestartSignal>,
     log_handle: LogHandle,
+    maintenance: Maintenance,
+    capabilities: GetCapabilitiesResponse,
+    firewall: FirewallProfile,
+    identity: GetNodeIdentityResponse,
 }
 
 impl HostServiceDispatcher {
@@ -17,11 +24,19 @@ impl HostServiceDispatcher {
         rx: mpsc::Receiver<Command>,
         restart_tx: mpsc::Sender<RestartSignal>,
         log_handle: LogHandle,
+        maintenance: Maintenance,
+        capabilities: GetCapabilitiesResponse,
+        firewall: FirewallProfile,
+        identity: GetNodeIdentityResponse,
     ) -> Self {
         Self {
             rx,
             restart_tx,
             log_handle,
+            maintenance,
+            capabilities,
+            firewall,
+            identity,
         }
     }
 
@@ -54,9 +69,29 @@ impl HostServiceDispatcher {
                 Command::StreamKernelLogs(stream_tx) => {
                     tokio::spawn(worker::handle_stream_kernel_logs(stream_tx));
                 }
-                Command::StreamFeOSLogs(stream_tx) => {
+                Command::StreamFeOSLogs(req, stream_tx) => {
                     let log_handle = self.log_handle.clone();
-                    tokio::spawn(worker::handle_stream_feos_logs(log_handle, stream_tx));
+                    tokio::spawn(worker::handle_stream_feos_logs(log_handle, req, stream_tx));
+                }
+                Command::ReadFeOSLogs(req, stream_tx) => {
+                    let log_handle = self.log_handle.clone();
+                    tokio::spawn(worker::handle_read_feos_logs(log_handle, req, stream_tx));
+                }
+                Command::SetLogLevel(req, responder) => {
+                    let log_handle = self.log_handle.clone();
+                    tokio::spawn(worker::handle_set_log_level(log_handle, req, responder));
+                }
+                Command::GetLogLevels(responder) => {
+                    let log_handle = self.log_handle.clone();
+                    tokio::spawn(worker::handle_get_log_levels(log_handle, responder));
+                }
+                Command::CreateDebugBundle(stream_tx) => {
+                    let log_handle = self.log_handle.clone();
+                    tokio::spawn(worker::handle_create_debug_bundle(log_handle, stream_tx));
+                }
+                Command::ExportLogs(req, stream_tx) => {
+                    let log_handle = self.log_handle.clone();
+                    tokio::spawn(worker::handle_export_logs(log_handle, req, stream_tx));
                 }
                 Command::Shutdown(req, responder) => {
                     tokio::spawn(worker::handle_shutdown(req, responder));
@@ -64,6 +99,88 @@ impl HostServiceDispatcher {
                 Command::Reboot(req, responder) => {
                     tokio::spawn(worker::handle_reboot(req, responder));
                 }
+                Command::AddSwap(req, responder) => {
+                    tokio::spawn(worker::handle_add_swap(req, responder));
+                }
+                Command::ListSwap(responder) => {
+                    tokio::spawn(worker::handle_list_swap(responder));
+                }
+                Command::RemoveSwap(req, responder) => {
+                    tokio::spawn(worker::handle_remove_swap(req, responder));
+                }
+                Command::SetSwappiness(req, responder) => {
+                    tokio::spawn(worker::handle_set_swappiness(req, responder));
+                }
+                Command::GetClockInfo(responder) => {
+                    tokio::spawn(worker::handle_get_clock_info(responder));
+                }
+                Command::SetClocksource(req, responder) => {
+                    tokio::spawn(worker::handle_set_clocksource(req, responder));
+                }
+                Command::DrainHost(req, stream_tx) => {
+                    let maintenance = self.maintenance.clone();
+                    tokio::spawn(worker::handle_drain_host(maintenance, req, stream_tx));
+                }
+                Command::UncordonHost(responder) => {
+                    let maintenance = self.maintenance.clone();
+                    tokio::spawn(worker::handle_uncordon_host(maintenance, responder));
+                }
+                Command::ListGpuPartitions(responder) => {
+                    tokio::spawn(worker::handle_list_gpu_partitions(responder));
+                }
+                Command::CreateGpuPartition(req, responder) => {
+                    tokio::spawn(worker::handle_create_gpu_partition(req, responder));
+                }
+                Command::DestroyGpuPartition(req, responder) => {
+                    tokio::spawn(worker::handle_destroy_gpu_partition(req, responder));
+                }
+                Command::ListMdevs(responder) => {
+                    tokio::spawn(worker::handle_list_mdevs(responder));
+                }
+                Command::CreateMdev(req, responder) => {
+                    tokio::spawn(worker::handle_create_mdev(req, responder));
+                }
+                Command::RemoveMdev(req, responder) => {
+                    tokio::spawn(worker::handle_remove_mdev(req, responder));
+                }
+                Command::ListNvmeControllers(responder) => {
+                    tokio::spawn(worker::handle_list_nvme_controllers(responder));
+                }
+                Command::CreateNvmeNamespace(req, responder) => {
+                    tokio::spawn(worker::handle_create_nvme_namespace(req, responder));
+                }
+                Command::DeleteNvmeNamespace(req, responder) => {
+                    tokio::spawn(worker::handle_delete_nvme_namespace(req, responder));
+                }
+                Command::FormatNvmeNamespace(req, responder) => {
+                    tokio::spawn(worker::handle_format_nvme_namespace(req, responder));
+                }
+                Command::GetNicTuning(req, responder) => {
+                    tokio::spawn(worker::handle_get_nic_tuning(req, responder));
+                }
+                Command::SetNicTuning(req, responder) => {
+                    tokio::spawn(worker::handle_set_nic_tuning(req, responder));
+                }
+                Command::GetCapabilities(responder) => {
+                    if responder.send(Ok(self.capabilities.clone())).is_err() {
+                        error!("HostDispatcher: Failed to send response for GetCapabilities.");
+                    }
+                }
+                Command::GetFirewall(responder) => {
+                    let profile = self.firewall.clone();
+                    tokio::spawn(worker::handle_get_firewall(profile, responder));
+                }
+                Command::AddFirewallException(req, responder) => {
+                    tokio::spawn(worker::handle_add_firewall_exception(req, responder));
+                }
+                Command::RemoveFirewallException(req, responder) => {
+                    tokio::spawn(worker::handle_remove_firewall_exception(req, responder));
+                }
+                Command::GetNodeIdentity(responder) => {
+                    if responder.send(Ok(self.identity.clone())).is_err() {
+                        error!("HostDispatcher: Failed to send response for GetNodeIdentity.");
+                    }
+                }
             }
         }
         info!("HostDispatcher: Channel closed, shutting down.");
@@ -20,6 +20,45 @@ pub enum HostError {
 
     #[error("Failed to create log reader: {0}")]
     LogReader(String),
+
+    #[error("Failed to create debug bundle: {0}")]
+    DebugBundle(String),
+
+    #[error("Failed to export logs: {0}")]
+    LogArchive(String),
+
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Swap operation failed: {0}")]
+    Swap(String),
+
+    #[error("Clock operation failed: {0}")]
+    Clock(String),
+
+    #[error("GPU operation failed: {0}")]
+    Gpu(String),
+
+    #[error("Mediated device operation failed: {0}")]
+    Mdev(String),
+
+    #[error("NIC tuning failed: {0}")]
+    Nic(String),
+
+    #[error("NVMe operation failed: {0}")]
+    Nvme(String),
+
+    #[error("Firewall operation failed: {0}")]
+    Firewall(String),
+
+    #[error("In use: {0}")]
+    InUse(String),
+
+    #[error("Already exists: {0}")]
+    AlreadyExists(String),
 }
 
 impl From<HostError> for Status {
@@ -32,7 +71,20 @@ impl From<HostError> for Status {
             HostError::Hostname(_) | HostError::PowerOperation(_) => {
                 Status::internal("An internal host error occurred")
             }
-            HostError::LogReader(msg) => Status::internal(msg),
+            HostError::LogReader(msg)
+            | HostError::DebugBundle(msg)
+            | HostError::LogArchive(msg)
+            | HostError::Swap(msg)
+            | HostError::Clock(msg)
+            | HostError::Gpu(msg)
+            | HostError::Mdev(msg)
+            | HostError::Nic(msg)
+            | HostError::Nvme(msg)
+            | HostError::Firewall(msg) => Status::internal(msg),
+            HostError::InvalidArgument(msg) => Status::invalid_argument(msg),
+            HostError::NotFound(msg) => Status::not_found(msg),
+            HostError::AlreadyExists(msg) => Status::already_exists(msg),
+            HostError::InUse(msg) => Status::failed_precondition(msg),
         }
     }
 }
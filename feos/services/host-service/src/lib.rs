@@ -3,9 +3,22 @@
 
 use crate::error::HostError;
 use feos_proto::host_service::{
-    FeosLogEntry, GetCpuInfoResponse, GetKernelStatsResponse, GetNetworkInfoResponse,
-    GetVersionInfoResponse, HostnameResponse, KernelLogEntry, MemoryResponse, RebootRequest,
-    RebootResponse, ShutdownRequest, ShutdownResponse, UpgradeFeosBinaryRequest,
+    AddFirewallExceptionRequest, AddFirewallExceptionResponse, AddSwapRequest, AddSwapResponse,
+    CreateGpuPartitionRequest, CreateGpuPartitionResponse, CreateMdevRequest, CreateMdevResponse,
+    CreateNvmeNamespaceRequest, CreateNvmeNamespaceResponse, DebugBundleChunk,
+    DeleteNvmeNamespaceRequest, DeleteNvmeNamespaceResponse, DestroyGpuPartitionRequest,
+    DestroyGpuPartitionResponse, DrainHostProgress, DrainHostRequest, ExportLogsRequest,
+    FeosLogEntry, FormatNvmeNamespaceRequest, FormatNvmeNamespaceResponse, GetCapabilitiesResponse,
+    GetClockInfoResponse, GetCpuInfoResponse, GetFirewallResponse, GetKernelStatsResponse,
+    GetLogLevelsResponse, GetNetworkInfoResponse, GetNicTuningRequest, GetNicTuningResponse,
+    GetNodeIdentityResponse, GetVersionInfoResponse, HostnameResponse, KernelLogEntry,
+    ListGpuPartitionsResponse, ListMdevsResponse, ListNvmeControllersResponse, ListSwapResponse,
+    LogArchiveChunk, MemoryResponse, ReadFeosLogsRequest, RebootRequest, RebootResponse,
+    RemoveFirewallExceptionRequest, RemoveFirewallExceptionResponse, RemoveMdevRequest,
+    RemoveMdevResponse, RemoveSwapRequest, RemoveSwapResponse, SetClocksourceRequest,
+    SetClocksourceResponse, SetLogLevelRequest, SetLogLevelResponse, SetNicTuningRequest,
+    SetNicTuningResponse, SetSwappinessRequest, SetSwappinessResponse, ShutdownRequest,
+    ShutdownResponse, StreamFeosLogsRequest, UncordonHostResponse, UpgradeFeosBinaryRequest,
     UpgradeFeosBinaryResponse,
 };
 use std::path::PathBuf;
@@ -30,7 +43,24 @@ pub enum Command {
         oneshot::Sender<Result<UpgradeFeosBinaryResponse, Status>>,
     ),
     StreamKernelLogs(mpsc::Sender<Result<KernelLogEntry, Status>>),
-    StreamFeOSLogs(mpsc::Sender<Result<FeosLogEntry, Status>>),
+    StreamFeOSLogs(
+        StreamFeosLogsRequest,
+        mpsc::Sender<Result<FeosLogEntry, Status>>,
+    ),
+    ReadFeOSLogs(
+        ReadFeosLogsRequest,
+        mpsc::Sender<Result<FeosLogEntry, Status>>,
+    ),
+    SetLogLevel(
+        SetLogLevelRequest,
+        oneshot::Sender<Result<SetLogLevelResponse, HostError>>,
+    ),
+    GetLogLevels(oneshot::Sender<Result<GetLogLevelsResponse, HostError>>),
+    CreateDebugBundle(mpsc::Sender<Result<DebugBundleChunk, Status>>),
+    ExportLogs(
+        ExportLogsRequest,
+        mpsc::Sender<Result<LogArchiveChunk, Status>>,
+    ),
     Shutdown(
         ShutdownRequest,
         oneshot::Sender<Result<ShutdownResponse, HostError>>,
@@ -39,6 +69,79 @@ pub enum Command {
         RebootRequest,
         oneshot::Sender<Result<RebootResponse, HostError>>,
     ),
+    AddSwap(
+        AddSwapRequest,
+        oneshot::Sender<Result<AddSwapResponse, HostError>>,
+    ),
+    ListSwap(oneshot::Sender<Result<ListSwapResponse, HostError>>),
+    RemoveSwap(
+        RemoveSwapRequest,
+        oneshot::Sender<Result<RemoveSwapResponse, HostError>>,
+    ),
+    SetSwappiness(
+        SetSwappinessRequest,
+        oneshot::Sender<Result<SetSwappinessResponse, HostError>>,
+    ),
+    GetClockInfo(oneshot::Sender<Result<GetClockInfoResponse, HostError>>),
+    SetClocksource(
+        SetClocksourceRequest,
+        oneshot::Sender<Result<SetClocksourceResponse, HostError>>,
+    ),
+    DrainHost(
+        DrainHostRequest,
+        mpsc::Sender<Result<DrainHostProgress, Status>>,
+    ),
+    UncordonHost(oneshot::Sender<Result<UncordonHostResponse, HostError>>),
+    ListGpuPartitions(oneshot::Sender<Result<ListGpuPartitionsResponse, HostError>>),
+    CreateGpuPartition(
+        CreateGpuPartitionRequest,
+        oneshot::Sender<Result<CreateGpuPartitionResponse, HostError>>,
+    ),
+    DestroyGpuPartition(
+        DestroyGpuPartitionRequest,
+        oneshot::Sender<Result<DestroyGpuPartitionResponse, HostError>>,
+    ),
+    ListMdevs(oneshot::Sender<Result<ListMdevsResponse, HostError>>),
+    CreateMdev(
+        CreateMdevRequest,
+        oneshot::Sender<Result<CreateMdevResponse, HostError>>,
+    ),
+    RemoveMdev(
+        RemoveMdevRequest,
+        oneshot::Sender<Result<RemoveMdevResponse, HostError>>,
+    ),
+    ListNvmeControllers(oneshot::Sender<Result<ListNvmeControllersResponse, HostError>>),
+    CreateNvmeNamespace(
+        CreateNvmeNamespaceRequest,
+        oneshot::Sender<Result<CreateNvmeNamespaceResponse, HostError>>,
+    ),
+    DeleteNvmeNamespace(
+        DeleteNvmeNamespaceRequest,
+        oneshot::Sender<Result<DeleteNvmeNamespaceResponse, HostError>>,
+    ),
+    FormatNvmeNamespace(
+        FormatNvmeNamespaceRequest,
+        oneshot::Sender<Result<FormatNvmeNamespaceResponse, HostError>>,
+    ),
+    GetNicTuning(
+        GetNicTuningRequest,
+        oneshot::Sender<Result<GetNicTuningResponse, HostError>>,
+    ),
+    SetNicTuning(
+        SetNicTuningRequest,
+        oneshot::Sender<Result<SetNicTuningResponse, HostError>>,
+    ),
+    GetCapabilities(oneshot::Sender<Result<GetCapabilitiesResponse, HostError>>),
+    GetFirewall(oneshot::Sender<Result<GetFirewallResponse, HostError>>),
+    AddFirewallException(
+        AddFirewallExceptionRequest,
+        oneshot::Sender<Result<AddFirewallExceptionResponse, HostError>>,
+    ),
+    RemoveFirewallException(
+        RemoveFirewallExceptionRequest,
+        oneshot::Sender<Result<RemoveFirewallExceptionResponse, HostError>>,
+    ),
+    GetNodeIdentity(oneshot::Sender<Result<GetNodeIdentityResponse, HostError>>),
 }
 
 #[derive(Debug)]
@@ -0,0 +1,74 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::error::HostError;
+use feos_proto::host_service::{
+    GetClockInfoResponse, PtpClock, SetClocksourceRequest, SetClocksourceResponse,
+};
+use feos_utils::host::clock;
+use log::{error, info};
+use std::io;
+use tokio::sync::oneshot;
+
+fn get_clock_info() -> Result<GetClockInfoResponse, HostError> {
+    let clock_err =
+        |what: &str, e: io::Error| HostError::Clock(format!("Failed to read {what}: {e}"));
+    let status = clock::kernel_clock_status().map_err(|e| clock_err("clock status", e))?;
+    Ok(GetClockInfoResponse {
+        clocksource: clock::current_clocksource().map_err(|e| clock_err("clocksource", e))?,
+        available_clocksources: clock::available_clocksources()
+            .map_err(|e| clock_err("available clocksources", e))?,
+        synchronized: status.synchronized,
+        offset_ns: status.offset_ns,
+        estimated_error_us: status.estimated_error_us,
+        max_error_us: status.max_error_us,
+        ptp_clocks: clock::ptp_clocks()
+            .map_err(|e| clock_err("PTP clocks", e))?
+            .into_iter()
+            .map(|ptp| PtpClock {
+                device: ptp.device,
+                name: ptp.name,
+            })
+            .collect(),
+        ptp_kvm_supported: clock::ptp_kvm_supported(),
+    })
+}
+
+pub async fn handle_get_clock_info(
+    responder: oneshot::Sender<Result<GetClockInfoResponse, HostError>>,
+) {
+    info!("HostWorker: Processing GetClockInfo request.");
+    if responder.send(get_clock_info()).is_err() {
+        error!(
+            "HostWorker: Failed to send response for GetClockInfo. The client may have disconnected."
+        );
+    }
+}
+
+pub async fn handle_set_clocksource(
+    req: SetClocksourceRequest,
+    responder: oneshot::Sender<Result<SetClocksourceResponse, HostError>>,
+) {
+    info!(
+        "HostWorker: Processing SetClocksource request for {}.",
+        req.clocksource
+    );
+    let result = match clock::set_clocksource(&req.clocksource) {
+        Ok(()) => {
+            info!("HostWorker: Switched clocksource to {}", req.clocksource);
+            Ok(SetClocksourceResponse {})
+        }
+        Err(e) if e.kind() == io::ErrorKind::InvalidInput => {
+            Err(HostError::InvalidArgument(e.to_string()))
+        }
+        Err(e) => Err(HostError::Clock(format!(
+            "Failed to switch clocksource to {}: {e}",
+            req.clocksource
+        ))),
+    };
+    if responder.send(result).is_err() {
+        error!(
+            "HostWorker: Failed to send response for SetClocksource. The client may have disconnected."
+        );
+    }
+}
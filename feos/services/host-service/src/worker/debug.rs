@@ -0,0 +1,197 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::ops::KMSG_PATH;
+use crate::error::HostError;
+use feos_proto::host_service::DebugBundleChunk;
+use feos_utils::feos_logger::LogHandle;
+use log::{error, info, warn};
+use nix::unistd;
+use std::io::{BufRead, ErrorKind};
+use std::os::unix::fs::OpenOptionsExt;
+use tokio::fs;
+use tokio::sync::mpsc;
+use tonic::Status;
+
+pub(super) const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Host files copied verbatim into the bundle, as (archive path, source path).
+const BUNDLE_FILES: &[(&str, &str)] = &[
+    ("host/cmdline", "/proc/cmdline"),
+    ("host/cpuinfo", "/proc/cpuinfo"),
+    ("host/meminfo", "/proc/meminfo"),
+    ("host/loadavg", "/proc/loadavg"),
+    ("host/uptime", "/proc/uptime"),
+    ("host/mounts", "/proc/mounts"),
+    ("network/dev", "/proc/net/dev"),
+    ("network/fib_trie", "/proc/net/fib_trie"),
+    ("network/route", "/proc/net/route"),
+    ("network/if_inet6", "/proc/net/if_inet6"),
+    ("network/ipv6_route", "/proc/net/ipv6_route"),
+    ("network/resolv.conf", "/etc/resolv.conf"),
+    ("network/hosts", "/etc/hosts"),
+];
+
+pub async fn handle_create_debug_bundle(
+    log_handle: LogHandle,
+    grpc_tx: mpsc::Sender<Result<DebugBundleChunk, Status>>,
+) {
+    info!("HostWorker: Creating debug bundle.");
+    let archive = match build_debug_bundle(&log_handle).await {
+        Ok(archive) => archive,
+        Err(err) => {
+            error!("HostWorker: {err}");
+            if grpc_tx.send(Err(err.into())).await.is_err() {
+                warn!("HostWorker: gRPC client for debug bundle disconnected before error could be sent.");
+            }
+            return;
+        }
+    };
+
+    info!(
+        "HostWorker: Debug bundle created ({} bytes), streaming to client.",
+        archive.len()
+    );
+    for chunk in archive.chunks(CHUNK_SIZE) {
+        let chunk = DebugBundleChunk {
+            data: chunk.to_vec(),
+        };
+        if grpc_tx.send(Ok(chunk)).await.is_err() {
+            warn!("HostWorker: gRPC client for debug bundle disconnected. Stopping stream.");
+            return;
+        }
+    }
+}
+
+async fn build_debug_bundle(log_handle: &LogHandle) -> Result<Vec<u8>, HostError> {
+    let mut files: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut missing: Vec<String> = Vec::new();
+
+    files.push((
+        "version.txt".to_string(),
+        version_report().await.into_bytes(),
+    ));
+
+    match log_handle.history().await {
+        Ok(entries) => {
+            let log: String = entries.iter().map(|entry| format!("{entry}\n")).collect();
+            files.push(("logs/feos.log".to_string(), log.into_bytes()));
+        }
+        Err(e) => missing.push(format!("logs/feos.log: {e}")),
+    }
+
+    match read_kernel_log().await {
+        Ok(log) => files.push(("logs/kernel.log".to_string(), log)),
+        Err(e) => missing.push(format!("logs/kernel.log: {e}")),
+    }
+
+    for (name, path) in BUNDLE_FILES {
+        match fs::read(path).await {
+            Ok(content) => files.push((name.to_string(), content)),
+            Err(e) => missing.push(format!("{name}: failed to read {path}: {e}")),
+        }
+    }
+
+    files.push((
+        "network/interfaces.txt".to_string(),
+        interfaces_report().await.into_bytes(),
+    ));
+
+    if !missing.is_empty() {
+        warn!(
+            "HostWorker: {} item(s) could not be collected for the debug bundle.",
+            missing.len()
+        );
+        let report = missing.join("\n") + "\n";
+        files.push(("missing.txt".to_string(), report.into_bytes()));
+    }
+
+    tokio::task::spawn_blocking(move || write_archive(files))
+        .await
+        .map_err(|e| HostError::DebugBundle(e.to_string()))?
+        .map_err(|e| HostError::DebugBundle(e.to_string()))
+}
+
+pub(super) fn write_archive(files: Vec<(String, Vec<u8>)>) -> std::io::Result<Vec<u8>> {
+    let mtime = chrono::Utc::now().timestamp().max(0) as u64;
+    let mut builder = tar::Builder::new(Vec::new());
+    for (name, content) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        builder.append_data(&mut header, name, content.as_slice())?;
+    }
+    builder.into_inner()
+}
+
+async fn version_report() -> String {
+    let kernel_version = fs::read_to_string("/proc/version")
+        .await
+        .map(|version| version.trim().to_string())
+        .unwrap_or_else(|e| format!("unknown ({e})"));
+    let hostname = unistd::gethostname()
+        .map(|host| host.to_string_lossy().into_owned())
+        .unwrap_or_else(|e| format!("unknown ({e})"));
+    format!(
+        "feos: {}\nkernel: {kernel_version}\narchitecture: {}\nhostname: {hostname}\ncollected: {}\n",
+        feos_utils::version::full_version_string(),
+        feos_utils::host::info::oci_architecture(),
+        chrono::Utc::now().to_rfc3339()
+    )
+}
+
+/// Reads the records currently in the kernel ring buffer without waiting for new ones.
+pub(super) async fn read_kernel_log() -> std::io::Result<Vec<u8>> {
+    tokio::task::spawn_blocking(|| {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(KMSG_PATH)?;
+        let mut reader = std::io::BufReader::new(file);
+        let mut log = Vec::new();
+        let mut record = Vec::new();
+        loop {
+            record.clear();
+            match reader.read_until(b'\n', &mut record) {
+                Ok(0) => break,
+                Ok(_) => log.extend_from_slice(&record),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                // Records overwritten while reading are reported as EPIPE.
+                Err(e) if e.kind() == ErrorKind::BrokenPipe => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(log)
+    })
+    .await
+    .map_err(std::io::Error::other)?
+}
+
+async fn interfaces_report() -> String {
+    let mut names = Vec::new();
+    if let Ok(mut entries) = fs::read_dir("/sys/class/net").await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    names.sort();
+
+    let mut report = String::new();
+    for name in names {
+        let address = read_interface_attr(&name, "address").await;
+        let mtu = read_interface_attr(&name, "mtu").await;
+        let operstate = read_interface_attr(&name, "operstate").await;
+        report.push_str(&format!(
+            "{name}: address={address} mtu={mtu} operstate={operstate}\n"
+        ));
+    }
+    report
+}
+
+async fn read_interface_attr(interface: &str, attr: &str) -> String {
+    fs::read_to_string(format!("/sys/class/net/{interface}/{attr}"))
+        .await
+        .map(|value| value.trim().to_string())
+        .unwrap_or_else(|_| "-".to_string())
+}
@@ -0,0 +1,136 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::error::HostError;
+use feos_proto::host_service::{
+    AddFirewallExceptionRequest, AddFirewallExceptionResponse, FirewallException, FirewallProtocol,
+    GetFirewallResponse, RemoveFirewallExceptionRequest, RemoveFirewallExceptionResponse,
+};
+use feos_utils::network::firewall::{self, Exception, Profile, Protocol};
+use log::{error, info};
+use std::io;
+use tokio::sync::oneshot;
+
+fn exception_to_proto(exception: Exception) -> FirewallException {
+    let protocol = match exception.protocol {
+        Protocol::Tcp => FirewallProtocol::Tcp,
+        Protocol::Udp => FirewallProtocol::Udp,
+    };
+    FirewallException {
+        name: exception.name,
+        protocol: protocol as i32,
+        port: exception.port.into(),
+        sources: exception.sources,
+    }
+}
+
+fn exception_from_proto(exception: FirewallException) -> Result<Exception, HostError> {
+    let protocol = match exception.protocol() {
+        FirewallProtocol::Tcp => Protocol::Tcp,
+        FirewallProtocol::Udp => Protocol::Udp,
+        FirewallProtocol::Unspecified => {
+            return Err(HostError::InvalidArgument(format!(
+                "Firewall exception '{}' has no protocol",
+                exception.name
+            )))
+        }
+    };
+    let port = u16::try_from(exception.port)
+        .map_err(|_| HostError::InvalidArgument(format!("Invalid port {}", exception.port)))?;
+    Ok(Exception {
+        name: exception.name,
+        protocol,
+        port,
+        sources: exception.sources,
+    })
+}
+
+fn firewall_error(e: io::Error) -> HostError {
+    match e.kind() {
+        io::ErrorKind::NotFound => HostError::NotFound(e.to_string()),
+        io::ErrorKind::InvalidInput => HostError::InvalidArgument(e.to_string()),
+        _ => HostError::Firewall(e.to_string()),
+    }
+}
+
+fn exceptions_to_proto(exceptions: Vec<Exception>) -> Vec<FirewallException> {
+    exceptions.into_iter().map(exception_to_proto).collect()
+}
+
+async fn get_firewall(profile: Profile) -> Result<GetFirewallResponse, HostError> {
+    let exceptions = firewall::exceptions().map_err(firewall_error)?;
+    Ok(GetFirewallResponse {
+        enabled: firewall::is_applied().await,
+        interfaces: profile.interfaces,
+        api_port: profile.api_port.into(),
+        ssh_allowed: profile.ssh,
+        exceptions: exceptions_to_proto(exceptions),
+    })
+}
+
+pub async fn handle_get_firewall(
+    profile: Profile,
+    responder: oneshot::Sender<Result<GetFirewallResponse, HostError>>,
+) {
+    info!("HostWorker: Processing GetFirewall request.");
+    let result = get_firewall(profile).await;
+    if responder.send(result).is_err() {
+        error!(
+            "HostWorker: Failed to send response for GetFirewall. The client may have disconnected."
+        );
+    }
+}
+
+async fn add_firewall_exception(
+    req: AddFirewallExceptionRequest,
+) -> Result<AddFirewallExceptionResponse, HostError> {
+    let exception = req
+        .exception
+        .ok_or_else(|| HostError::InvalidArgument("No firewall exception given".to_string()))?;
+    let exception = exception_from_proto(exception)?;
+    let name = exception.name.clone();
+    let exceptions = firewall::add_exception(exception)
+        .await
+        .map_err(firewall_error)?;
+    info!("HostWorker: Added firewall exception '{name}'");
+    Ok(AddFirewallExceptionResponse {
+        exceptions: exceptions_to_proto(exceptions),
+    })
+}
+
+pub async fn handle_add_firewall_exception(
+    req: AddFirewallExceptionRequest,
+    responder: oneshot::Sender<Result<AddFirewallExceptionResponse, HostError>>,
+) {
+    info!("HostWorker: Processing AddFirewallException request.");
+    let result = add_firewall_exception(req).await;
+    if responder.send(result).is_err() {
+        error!(
+            "HostWorker: Failed to send response for AddFirewallException. The client may have disconnected."
+        );
+    }
+}
+
+pub async fn handle_remove_firewall_exception(
+    req: RemoveFirewallExceptionRequest,
+    responder: oneshot::Sender<Result<RemoveFirewallExceptionResponse, HostError>>,
+) {
+    info!(
+        "HostWorker: Processing RemoveFirewallException request for '{}'.",
+        req.name
+    );
+    let result = firewall::remove_exception(&req.name)
+        .await
+        .map_err(firewall_error)
+        .map(|exceptions| {
+            info!("HostWorker: Removed firewall exception '{}'", req.name);
+            RemoveFirewallExceptionResponse {
+                exceptions: exceptions_to_proto(exceptions),
+            }
+        });
+    if responder.send(result).is_err() {
+        error!(
+            "HostWorker: Failed to send response for RemoveFirewallException. The client may have disconnected."
+        );
+    }
+}
@@ -0,0 +1,119 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::error::HostError;
+use feos_proto::host_service::{
+    CreateGpuPartitionRequest, CreateGpuPartitionResponse, DestroyGpuPartitionRequest,
+    DestroyGpuPartitionResponse, Gpu, GpuPartition, ListGpuPartitionsResponse,
+};
+use feos_utils::host::gpu;
+use log::{error, info};
+use std::io;
+use tokio::sync::oneshot;
+
+fn partition_to_proto(partition: gpu::GpuPartition) -> GpuPartition {
+    GpuPartition {
+        pci_address: partition.pci_address,
+        gpu_address: partition.gpu_address,
+        profile: partition.profile,
+    }
+}
+
+fn gpu_error(e: io::Error) -> HostError {
+    match e.kind() {
+        io::ErrorKind::NotFound => HostError::NotFound(e.to_string()),
+        io::ErrorKind::InvalidInput => HostError::InvalidArgument(e.to_string()),
+        _ => HostError::Gpu(e.to_string()),
+    }
+}
+
+fn list_gpu_partitions() -> Result<ListGpuPartitionsResponse, HostError> {
+    let list_err = |what: &str, e: io::Error| HostError::Gpu(format!("Failed to list {what}: {e}"));
+    Ok(ListGpuPartitionsResponse {
+        gpus: gpu::gpus()
+            .map_err(|e| list_err("GPUs", e))?
+            .into_iter()
+            .map(|gpu| Gpu {
+                pci_address: gpu.pci_address,
+                max_partitions: gpu.max_partitions,
+                creatable_profiles: gpu.creatable_profiles,
+            })
+            .collect(),
+        partitions: gpu::partitions()
+            .map_err(|e| list_err("GPU partitions", e))?
+            .into_iter()
+            .map(partition_to_proto)
+            .collect(),
+    })
+}
+
+pub async fn handle_list_gpu_partitions(
+    responder: oneshot::Sender<Result<ListGpuPartitionsResponse, HostError>>,
+) {
+    info!("HostWorker: Processing ListGpuPartitions request.");
+    let result = tokio::task::spawn_blocking(list_gpu_partitions)
+        .await
+        .unwrap_or_else(|e| Err(HostError::Gpu(e.to_string())));
+    if responder.send(result).is_err() {
+        error!(
+            "HostWorker: Failed to send response for ListGpuPartitions. The client may have disconnected."
+        );
+    }
+}
+
+pub async fn handle_create_gpu_partition(
+    req: CreateGpuPartitionRequest,
+    responder: oneshot::Sender<Result<CreateGpuPartitionResponse, HostError>>,
+) {
+    info!(
+        "HostWorker: Processing CreateGpuPartition request for profile '{}' on {}.",
+        req.profile, req.gpu_address
+    );
+    let result = if req.gpu_address.is_empty() || req.profile.is_empty() {
+        Err(HostError::InvalidArgument(
+            "A GPU address and a profile are required".to_string(),
+        ))
+    } else {
+        // Enabling the virtual functions of a GPU takes a while.
+        tokio::task::spawn_blocking(move || gpu::create_partition(&req.gpu_address, &req.profile))
+            .await
+            .map_err(|e| HostError::Gpu(e.to_string()))
+            .and_then(|result| result.map_err(gpu_error))
+            .map(|partition| {
+                info!(
+                    "HostWorker: Created GPU partition {} of profile '{}'",
+                    partition.pci_address, partition.profile
+                );
+                CreateGpuPartitionResponse {
+                    partition: Some(partition_to_proto(partition)),
+                }
+            })
+    };
+    if responder.send(result).is_err() {
+        error!(
+            "HostWorker: Failed to send response for CreateGpuPartition. The client may have disconnected."
+        );
+    }
+}
+
+pub async fn handle_destroy_gpu_partition(
+    req: DestroyGpuPartitionRequest,
+    responder: oneshot::Sender<Result<DestroyGpuPartitionResponse, HostError>>,
+) {
+    info!(
+        "HostWorker: Processing DestroyGpuPartition request for {}.",
+        req.pci_address
+    );
+    let result = match gpu::destroy_partition(&req.pci_address) {
+        Ok(()) => {
+            info!("HostWorker: Destroyed GPU partition {}", req.pci_address);
+            Ok(DestroyGpuPartitionResponse {})
+        }
+        Err(e) => Err(gpu_error(e)),
+    };
+    if responder.send(result).is_err() {
+        error!(
+            "HostWorker: Failed to send response for DestroyGpuPartition. The client may have disconnected."
+        );
+    }
+}
@@ -329,6 +329,7 @@ pub async fn handle_get_version_info(
             GetVersionInfoResponse {
                 kernel_version: kernel_version.trim().to_string(),
                 feos_version,
+                architecture: feos_utils::host::info::oci_architecture().to_string(),
             }
         })
         .map_err(|e| HostError::SystemInfoRead {
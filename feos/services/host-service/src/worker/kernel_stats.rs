@@ -142,7 +142,10 @@ pub async fn handle_get_kernel_stats(
     info!("HostWorker: Processing GetKernelStats request.");
     let result = read_and_parse_proc_stat()
         .await
-        .map(|stats| GetKernelStatsResponse { stats: Some(stats) });
+        .map(|stats| GetKernelStatsResponse {
+            stats: Some(stats),
+            node_id: String::new(),
+        });
 
     if responder.send(result).is_err() {
         error!(
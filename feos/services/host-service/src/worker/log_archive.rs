@@ -0,0 +1,251 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::debug::{read_kernel_log, write_archive, CHUNK_SIZE};
+use super::ops::to_datetime;
+use crate::error::HostError;
+use chrono::{DateTime, Duration, Utc};
+use feos_proto::host_service::{ExportLogsRequest, LogArchiveChunk, LogArchiveCompression};
+use feos_utils::feos_logger::{Component, LogEntry, LogHandle, LogQuery};
+use flate2::{write::GzEncoder, Compression};
+use log::{error, info, warn};
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use tokio::sync::mpsc;
+use tonic::Status;
+
+/// Workload ids are UUIDs, and every VM and container log line names the
+/// workload it is about.
+const WORKLOAD_ID_PATTERN: &str =
+    r"[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}";
+
+/// The inclusive time range of an export. Unset bounds are open.
+struct Window {
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+}
+
+impl Window {
+    fn contains(&self, time: DateTime<Utc>) -> bool {
+        self.start.is_none_or(|start| time >= start) && self.end.is_none_or(|end| time <= end)
+    }
+}
+
+fn describe_bound(bound: Option<DateTime<Utc>>) -> String {
+    bound.map_or_else(|| "unbounded".to_string(), |time| time.to_rfc3339())
+}
+
+pub async fn handle_export_logs(
+    log_handle: LogHandle,
+    req: ExportLogsRequest,
+    grpc_tx: mpsc::Sender<Result<LogArchiveChunk, Status>>,
+) {
+    info!("HostWorker: Exporting log archive.");
+    let archive = match build_log_archive(&log_handle, req).await {
+        Ok(archive) => archive,
+        Err(err) => {
+            error!("HostWorker: {err}");
+            if grpc_tx.send(Err(err.into())).await.is_err() {
+                warn!("HostWorker: gRPC client for log export disconnected before error could be sent.");
+            }
+            return;
+        }
+    };
+
+    info!(
+        "HostWorker: Log archive created ({} bytes), streaming to client.",
+        archive.len()
+    );
+    for chunk in archive.chunks(CHUNK_SIZE) {
+        let chunk = LogArchiveChunk {
+            data: chunk.to_vec(),
+        };
+        if grpc_tx.send(Ok(chunk)).await.is_err() {
+            warn!("HostWorker: gRPC client for log export disconnected. Stopping stream.");
+            return;
+        }
+    }
+}
+
+async fn build_log_archive(
+    log_handle: &LogHandle,
+    req: ExportLogsRequest,
+) -> Result<Vec<u8>, HostError> {
+    let window = Window {
+        start: to_datetime(req.start_time),
+        end: to_datetime(req.end_time),
+    };
+    if let (Some(start), Some(end)) = (window.start, window.end) {
+        if start > end {
+            return Err(HostError::InvalidArgument(
+                "start_time must not be after end_time".to_string(),
+            ));
+        }
+    }
+    let compression = LogArchiveCompression::try_from(req.compression).map_err(|_| {
+        HostError::InvalidArgument(format!("unknown compression {}", req.compression))
+    })?;
+
+    let query = LogQuery {
+        start_time: window.start,
+        end_time: window.end,
+        ..Default::default()
+    };
+    let entries = log_handle
+        .query(query)
+        .await
+        .map_err(|e| HostError::LogArchive(format!("failed to read FeOS logs: {e}")))?;
+
+    let mut files = feos_log_files(&entries);
+    let mut missing = Vec::new();
+    match read_kernel_log().await {
+        Ok(log) => files.push(("kernel.log".to_string(), kernel_log_in(&log, &window))),
+        Err(e) => missing.push(format!("kernel.log: {e}")),
+    }
+
+    let mut summary = format!(
+        "start: {}\nend: {}\nfeos entries: {}\ncollected: {}\n",
+        describe_bound(window.start),
+        describe_bound(window.end),
+        entries.len(),
+        Utc::now().to_rfc3339()
+    );
+    if !missing.is_empty() {
+        summary.push_str("\nnot collected:\n");
+        summary.push_str(&(missing.join("\n") + "\n"));
+    }
+    files.insert(0, ("summary.txt".to_string(), summary.into_bytes()));
+
+    tokio::task::spawn_blocking(move || {
+        let archive = write_archive(files)?;
+        compress(&archive, compression)
+    })
+    .await
+    .map_err(|e| HostError::LogArchive(e.to_string()))?
+    .map_err(|e| HostError::LogArchive(e.to_string()))
+}
+
+/// Lays out the FeOS log entries as one combined log, one log per component
+/// and one log per VM or container.
+fn feos_log_files(entries: &[LogEntry]) -> Vec<(String, Vec<u8>)> {
+    let workload_id = Regex::new(WORKLOAD_ID_PATTERN).expect("workload id pattern is valid");
+    let mut combined = String::new();
+    let mut components: BTreeMap<&'static str, String> = BTreeMap::new();
+    let mut workloads: BTreeMap<String, String> = BTreeMap::new();
+
+    for entry in entries {
+        let line = format!("{entry}\n");
+        combined.push_str(&line);
+        let component = entry.component();
+        components
+            .entry(component.as_str())
+            .or_default()
+            .push_str(&line);
+
+        if matches!(component, Component::Vm | Component::Container) {
+            if let Some(id) = workload_id.find(&entry.message) {
+                let name = format!("{component}-{}", id.as_str().to_lowercase());
+                workloads.entry(name).or_default().push_str(&line);
+            }
+        }
+    }
+
+    let mut files = vec![("feos/feos.log".to_string(), combined.into_bytes())];
+    files.extend(
+        components
+            .into_iter()
+            .map(|(component, log)| (format!("feos/components/{component}.log"), log.into_bytes())),
+    );
+    files.extend(
+        workloads
+            .into_iter()
+            .map(|(name, log)| (format!("workloads/{name}.log"), log.into_bytes())),
+    );
+    files
+}
+
+/// The wall clock time the system booted, which kernel log timestamps are
+/// relative to.
+fn boot_time() -> DateTime<Utc> {
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    let since_boot = Duration::seconds(now.tv_sec) + Duration::nanoseconds(now.tv_nsec);
+    Utc::now() - since_boot
+}
+
+/// Keeps the /dev/kmsg records inside the window, each prefixed with its
+/// wall clock time. Records look like `prio,seq,usec,flags;message`.
+fn kernel_log_in(kmsg: &[u8], window: &Window) -> Vec<u8> {
+    let boot = boot_time();
+    let mut log = String::new();
+    for record in String::from_utf8_lossy(kmsg).lines() {
+        // Continuation lines carry key=value metadata of the record.
+        if record.starts_with(' ') {
+            continue;
+        }
+        let Some((prefix, message)) = record.split_once(';') else {
+            continue;
+        };
+        let Some(usec) = prefix.split(',').nth(2).and_then(|f| f.parse::<i64>().ok()) else {
+            continue;
+        };
+        let time = boot + Duration::microseconds(usec);
+        if window.contains(time) {
+            log.push_str(&format!("[{}] {message}\n", time.to_rfc3339()));
+        }
+    }
+    log.into_bytes()
+}
+
+fn compress(archive: &[u8], compression: LogArchiveCompression) -> io::Result<Vec<u8>> {
+    match compression {
+        LogArchiveCompression::Unspecified | LogArchiveCompression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(archive)?;
+            encoder.finish()
+        }
+        LogArchiveCompression::Zstd => zstd::encode_all(archive, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+
+    #[test]
+    fn feos_logs_are_split_by_component_and_workload() {
+        let entry = |target: &str, message: &str| LogEntry {
+            seq: 1,
+            timestamp: Utc::now(),
+            level: Level::Info,
+            target: target.to_string(),
+            message: message.to_string(),
+        };
+        let entries = [
+            entry(
+                "vm_service::worker",
+                "VM 3F2504E0-4F89-11D3-9A0C-0305E82C3301 started",
+            ),
+            entry("container_service::worker", "Pulling image"),
+            entry("feos::setup", "Configured 4 hugepages"),
+        ];
+
+        let files = feos_log_files(&entries);
+        let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "feos/feos.log",
+                "feos/components/container.log",
+                "feos/components/system.log",
+                "feos/components/vm.log",
+                "workloads/vm-3f2504e0-4f89-11d3-9a0c-0305e82c3301.log",
+            ]
+        );
+    }
+}
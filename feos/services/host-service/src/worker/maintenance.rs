@@ -0,0 +1,102 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::error::HostError;
+use feos_proto::host_service::{
+    DrainHostProgress, DrainHostRequest, DrainOutcome, DrainedWorkload, UncordonHostResponse,
+    WorkloadKind,
+};
+use feos_utils::host::admission;
+use feos_utils::host::maintenance::{self, DrainEvent, Maintenance};
+use log::{error, info, warn};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tonic::Status;
+
+const DEFAULT_GRACE_PERIOD_SECS: u32 = 60;
+
+fn drained_workload_to_proto(workload: maintenance::DrainedWorkload) -> DrainedWorkload {
+    let kind = match workload.kind {
+        admission::WorkloadKind::Vm => WorkloadKind::Vm,
+        admission::WorkloadKind::Container => WorkloadKind::Container,
+    };
+    let outcome = match workload.outcome {
+        maintenance::DrainOutcome::Stopped => DrainOutcome::Stopped,
+        maintenance::DrainOutcome::ForceStopped => DrainOutcome::ForceStopped,
+        maintenance::DrainOutcome::Kept => DrainOutcome::Kept,
+        maintenance::DrainOutcome::Failed => DrainOutcome::Failed,
+    };
+    DrainedWorkload {
+        kind: kind as i32,
+        id: workload.id,
+        outcome: outcome as i32,
+        message: workload.message,
+    }
+}
+
+pub async fn handle_drain_host(
+    maintenance: Maintenance,
+    req: DrainHostRequest,
+    stream_tx: mpsc::Sender<Result<DrainHostProgress, Status>>,
+) {
+    let grace_period = Duration::from_secs(
+        req.grace_period_seconds
+            .unwrap_or(DEFAULT_GRACE_PERIOD_SECS)
+            .into(),
+    );
+    info!("HostWorker: Draining the host with a grace period of {grace_period:?}.");
+    let mut drain = match maintenance.drain(grace_period) {
+        Ok(drain) => drain,
+        Err(e) => {
+            let _ = stream_tx
+                .send(Err(Status::failed_precondition(e.to_string())))
+                .await;
+            return;
+        }
+    };
+
+    let mut progress = DrainHostProgress::default();
+    while let Some(event) = drain.next_event().await {
+        match event {
+            DrainEvent::Planned(count) => {
+                progress.total += count as u32;
+                progress.workload = None;
+            }
+            DrainEvent::Drained(workload) => {
+                if workload.outcome == maintenance::DrainOutcome::Failed {
+                    warn!(
+                        "HostWorker: Failed to drain {} {}: {}",
+                        workload.kind, workload.id, workload.message
+                    );
+                }
+                progress.drained += 1;
+                progress.workload = Some(drained_workload_to_proto(workload));
+            }
+        }
+        // The drain goes on if the client disconnects, the host stays
+        // cordoned either way.
+        let _ = stream_tx.send(Ok(progress.clone())).await;
+    }
+
+    info!(
+        "HostWorker: Host drained, {} of {} workloads handled.",
+        progress.drained, progress.total
+    );
+    progress.workload = None;
+    progress.done = true;
+    if stream_tx.send(Ok(progress)).await.is_err() {
+        error!("HostWorker: Failed to send final DrainHost progress. The client may have disconnected.");
+    }
+}
+
+pub async fn handle_uncordon_host(
+    maintenance: Maintenance,
+    responder: oneshot::Sender<Result<UncordonHostResponse, HostError>>,
+) {
+    maintenance.uncordon();
+    if responder.send(Ok(UncordonHostResponse {})).is_err() {
+        error!(
+            "HostWorker: Failed to send response for UncordonHost. The client may have disconnected."
+        );
+    }
+}
@@ -0,0 +1,134 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::error::HostError;
+use feos_proto::host_service::{
+    CreateMdevRequest, CreateMdevResponse, ListMdevsResponse, Mdev, MdevType, RemoveMdevRequest,
+    RemoveMdevResponse,
+};
+use feos_utils::host::mdev;
+use log::{error, info};
+use std::io;
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+fn mdev_to_proto(mdev: mdev::Mdev) -> Mdev {
+    Mdev {
+        uuid: mdev.uuid,
+        parent: mdev.parent,
+        type_id: mdev.type_id,
+    }
+}
+
+fn mdev_error(e: io::Error) -> HostError {
+    match e.kind() {
+        io::ErrorKind::NotFound => HostError::NotFound(e.to_string()),
+        io::ErrorKind::InvalidInput => HostError::InvalidArgument(e.to_string()),
+        io::ErrorKind::AlreadyExists => HostError::AlreadyExists(e.to_string()),
+        _ => HostError::Mdev(e.to_string()),
+    }
+}
+
+fn list_mdevs() -> Result<ListMdevsResponse, HostError> {
+    let types = mdev::mdev_types()
+        .map_err(|e| HostError::Mdev(format!("Failed to list mdev types: {e}")))?;
+    let mdevs = mdev::mdevs().map_err(|e| HostError::Mdev(format!("Failed to list mdevs: {e}")))?;
+    Ok(ListMdevsResponse {
+        types: types
+            .into_iter()
+            .map(|mdev_type| MdevType {
+                parent: mdev_type.parent,
+                type_id: mdev_type.type_id,
+                name: mdev_type.name,
+                device_api: mdev_type.device_api,
+                available_instances: mdev_type.available_instances,
+                description: mdev_type.description,
+            })
+            .collect(),
+        mdevs: mdevs.into_iter().map(mdev_to_proto).collect(),
+    })
+}
+
+pub async fn handle_list_mdevs(responder: oneshot::Sender<Result<ListMdevsResponse, HostError>>) {
+    info!("HostWorker: Processing ListMdevs request.");
+    if responder.send(list_mdevs()).is_err() {
+        error!(
+            "HostWorker: Failed to send response for ListMdevs. The client may have disconnected."
+        );
+    }
+}
+
+pub async fn handle_create_mdev(
+    req: CreateMdevRequest,
+    responder: oneshot::Sender<Result<CreateMdevResponse, HostError>>,
+) {
+    info!(
+        "HostWorker: Processing CreateMdev request for type {} of {}.",
+        req.type_id, req.parent
+    );
+    let uuid = req
+        .uuid
+        .filter(|uuid| !uuid.is_empty())
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    let result = if req.parent.is_empty() || req.type_id.is_empty() {
+        Err(HostError::InvalidArgument(
+            "A parent device and an mdev type are required".to_string(),
+        ))
+    } else {
+        match mdev::create_mdev(&req.parent, &req.type_id, &uuid) {
+            Ok(mdev) => {
+                info!(
+                    "HostWorker: Created mdev {} of type {} on {}",
+                    mdev.uuid, mdev.type_id, mdev.parent
+                );
+                Ok(CreateMdevResponse {
+                    mdev: Some(mdev_to_proto(mdev)),
+                })
+            }
+            Err(e) => Err(mdev_error(e)),
+        }
+    };
+    if responder.send(result).is_err() {
+        error!(
+            "HostWorker: Failed to send response for CreateMdev. The client may have disconnected."
+        );
+    }
+}
+
+pub async fn handle_remove_mdev(
+    req: RemoveMdevRequest,
+    responder: oneshot::Sender<Result<RemoveMdevResponse, HostError>>,
+) {
+    info!(
+        "HostWorker: Processing RemoveMdev request for {}.",
+        req.uuid
+    );
+    let result = match mdev::remove_mdev(&req.uuid) {
+        Ok(()) => {
+            info!("HostWorker: Removed mdev {}", req.uuid);
+            Ok(RemoveMdevResponse {})
+        }
+        Err(e) => Err(mdev_error(e)),
+    };
+    if responder.send(result).is_err() {
+        error!(
+            "HostWorker: Failed to send response for RemoveMdev. The client may have disconnected."
+        );
+    }
+}
+
+/// Recreates the mdevs created through FeOS that are gone, e.g. after a
+/// reboot of the host, so VMs using them can start.
+pub fn recreate_mdevs() {
+    match mdev::recreate_mdevs() {
+        Ok(failed) => {
+            for (mdev, e) in failed {
+                error!(
+                    "HostWorker: Failed to recreate mdev {} of type {} on {}: {e}",
+                    mdev.uuid, mdev.type_id, mdev.parent
+                );
+            }
+        }
+        Err(e) => error!("HostWorker: Failed to read the recorded mdevs: {e}"),
+    }
+}
@@ -1,17 +1,47 @@
 // SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
 // SPDX-License-Identifier: Apache-2.0
 
+pub mod clock;
+pub mod debug;
+pub mod firewall;
+pub mod gpu;
 pub mod info;
 pub mod kernel_stats;
+pub mod log_archive;
+pub mod maintenance;
+pub mod mdev;
+pub mod nic;
+pub mod nvme;
 pub mod ops;
 pub mod power;
+pub mod swap;
 pub mod time;
 
+pub use clock::{handle_get_clock_info, handle_set_clocksource};
+pub use debug::handle_create_debug_bundle;
+pub use firewall::{
+    handle_add_firewall_exception, handle_get_firewall, handle_remove_firewall_exception,
+};
+pub use gpu::{
+    handle_create_gpu_partition, handle_destroy_gpu_partition, handle_list_gpu_partitions,
+};
 pub use info::{
     handle_get_cpu_info, handle_get_memory, handle_get_network_info, handle_get_version_info,
     handle_hostname,
 };
 pub use kernel_stats::*;
-pub use ops::{handle_stream_feos_logs, handle_stream_kernel_logs, handle_upgrade};
+pub use log_archive::handle_export_logs;
+pub use maintenance::{handle_drain_host, handle_uncordon_host};
+pub use mdev::{handle_create_mdev, handle_list_mdevs, handle_remove_mdev, recreate_mdevs};
+pub use nic::{handle_get_nic_tuning, handle_set_nic_tuning};
+pub use nvme::{
+    handle_create_nvme_namespace, handle_delete_nvme_namespace, handle_format_nvme_namespace,
+    handle_list_nvme_controllers,
+};
+pub use ops::{
+    handle_get_log_levels, handle_read_feos_logs, handle_set_log_level, handle_stream_feos_logs,
+    handle_stream_kernel_logs, handle_upgrade,
+};
 pub use power::{handle_reboot, handle_shutdown};
+pub use swap::{handle_add_swap, handle_list_swap, handle_remove_swap, handle_set_swappiness};
 pub use time::TimeSyncWorker;
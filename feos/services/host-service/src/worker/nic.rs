@@ -0,0 +1,111 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::error::HostError;
+use feos_proto::host_service::{
+    GetNicTuningRequest, GetNicTuningResponse, NicIrq, NicQueueCounts, NicTuning,
+    SetNicTuningRequest, SetNicTuningResponse,
+};
+use feos_utils::host::nic;
+use log::{error, info};
+use std::io;
+use tokio::sync::oneshot;
+
+fn queue_counts_to_proto(counts: nic::QueueCounts) -> NicQueueCounts {
+    NicQueueCounts {
+        rx: counts.rx,
+        tx: counts.tx,
+        other: counts.other,
+        combined: counts.combined,
+    }
+}
+
+fn tuning_to_proto(tuning: nic::NicTuning) -> NicTuning {
+    NicTuning {
+        interface: tuning.interface,
+        queues: tuning
+            .queues
+            .map(|queues| queue_counts_to_proto(queues.current)),
+        max_queues: tuning
+            .queues
+            .map(|queues| queue_counts_to_proto(queues.max)),
+        irqs: tuning
+            .irqs
+            .into_iter()
+            .map(|irq| NicIrq {
+                irq: irq.irq,
+                cpus: nic::format_cpu_list(&irq.cpus),
+            })
+            .collect(),
+        local_cpus: nic::format_cpu_list(&tuning.local_cpus),
+    }
+}
+
+fn nic_error(e: io::Error) -> HostError {
+    match e.kind() {
+        io::ErrorKind::NotFound => HostError::NotFound(e.to_string()),
+        io::ErrorKind::InvalidInput => HostError::InvalidArgument(e.to_string()),
+        _ => HostError::Nic(e.to_string()),
+    }
+}
+
+fn get_nic_tuning(req: GetNicTuningRequest) -> Result<GetNicTuningResponse, HostError> {
+    let tunings = if req.interface.is_empty() {
+        nic::nic_tunings()
+    } else {
+        nic::nic_tuning(&req.interface).map(|tuning| vec![tuning])
+    }
+    .map_err(nic_error)?;
+    let isolated_cpus = nic::isolated_cpus().map_err(nic_error)?;
+    Ok(GetNicTuningResponse {
+        nics: tunings.into_iter().map(tuning_to_proto).collect(),
+        isolated_cpus: nic::format_cpu_list(&isolated_cpus),
+    })
+}
+
+pub async fn handle_get_nic_tuning(
+    req: GetNicTuningRequest,
+    responder: oneshot::Sender<Result<GetNicTuningResponse, HostError>>,
+) {
+    info!("HostWorker: Processing GetNicTuning request.");
+    let result = tokio::task::spawn_blocking(move || get_nic_tuning(req))
+        .await
+        .unwrap_or_else(|e| Err(HostError::Nic(e.to_string())));
+    if responder.send(result).is_err() {
+        error!(
+            "HostWorker: Failed to send response for GetNicTuning. The client may have disconnected."
+        );
+    }
+}
+
+fn set_nic_tuning(req: SetNicTuningRequest) -> Result<SetNicTuningResponse, HostError> {
+    let irq_cpus = nic::parse_cpu_list(&req.irq_cpus).map_err(nic_error)?;
+    let tuning =
+        nic::tune_nic(&req.interface, req.combined_queues, &irq_cpus).map_err(nic_error)?;
+    info!(
+        "HostWorker: Tuned NIC {} with {} IRQs",
+        tuning.interface,
+        tuning.irqs.len()
+    );
+    Ok(SetNicTuningResponse {
+        nic: Some(tuning_to_proto(tuning)),
+    })
+}
+
+pub async fn handle_set_nic_tuning(
+    req: SetNicTuningRequest,
+    responder: oneshot::Sender<Result<SetNicTuningResponse, HostError>>,
+) {
+    info!(
+        "HostWorker: Processing SetNicTuning request for {}.",
+        req.interface
+    );
+    let result = tokio::task::spawn_blocking(move || set_nic_tuning(req))
+        .await
+        .unwrap_or_else(|e| Err(HostError::Nic(e.to_string())));
+    if responder.send(result).is_err() {
+        error!(
+            "HostWorker: Failed to send response for SetNicTuning. The client may have disconnected."
+        );
+    }
+}
@@ -0,0 +1,174 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::error::HostError;
+use feos_proto::host_service::{
+    CreateNvmeNamespaceRequest, CreateNvmeNamespaceResponse, DeleteNvmeNamespaceRequest,
+    DeleteNvmeNamespaceResponse, FormatNvmeNamespaceRequest, FormatNvmeNamespaceResponse,
+    ListNvmeControllersResponse, NvmeController, NvmeLbaFormat, NvmeNamespace, NvmeSecureErase,
+};
+use feos_utils::host::nvme;
+use log::{error, info};
+use std::io;
+use tokio::sync::oneshot;
+
+fn namespace_to_proto(namespace: nvme::NvmeNamespace) -> NvmeNamespace {
+    NvmeNamespace {
+        nsid: namespace.nsid,
+        device: namespace.device,
+        size_bytes: namespace.size_bytes,
+        block_size: namespace.block_size,
+    }
+}
+
+fn controller_to_proto(controller: nvme::NvmeController) -> NvmeController {
+    NvmeController {
+        name: controller.name,
+        address: controller.address,
+        transport: controller.transport,
+        model: controller.model,
+        serial: controller.serial,
+        firmware: controller.firmware,
+        namespace_management: controller.namespace_management,
+        total_capacity_bytes: controller.total_capacity_bytes,
+        unallocated_capacity_bytes: controller.unallocated_capacity_bytes,
+        max_namespaces: controller.max_namespaces,
+        lba_formats: controller
+            .lba_formats
+            .into_iter()
+            .map(|format| NvmeLbaFormat {
+                index: u32::from(format.index),
+                block_size: format.block_size,
+                metadata_size: u32::from(format.metadata_size),
+                relative_performance: u32::from(format.relative_performance),
+            })
+            .collect(),
+        namespaces: controller
+            .namespaces
+            .into_iter()
+            .map(namespace_to_proto)
+            .collect(),
+    }
+}
+
+fn nvme_error(e: io::Error) -> HostError {
+    match e.kind() {
+        io::ErrorKind::NotFound => HostError::NotFound(e.to_string()),
+        io::ErrorKind::InvalidInput | io::ErrorKind::Unsupported => {
+            HostError::InvalidArgument(e.to_string())
+        }
+        io::ErrorKind::ResourceBusy => HostError::InUse(e.to_string()),
+        _ => HostError::Nvme(e.to_string()),
+    }
+}
+
+async fn run_blocking<T: Send + 'static>(
+    f: impl FnOnce() -> io::Result<T> + Send + 'static,
+) -> Result<T, HostError> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| HostError::Nvme(e.to_string()))?
+        .map_err(nvme_error)
+}
+
+pub async fn handle_list_nvme_controllers(
+    responder: oneshot::Sender<Result<ListNvmeControllersResponse, HostError>>,
+) {
+    info!("HostWorker: Processing ListNvmeControllers request.");
+    let result =
+        run_blocking(nvme::controllers)
+            .await
+            .map(|controllers| ListNvmeControllersResponse {
+                controllers: controllers.into_iter().map(controller_to_proto).collect(),
+            });
+    if responder.send(result).is_err() {
+        error!(
+            "HostWorker: Failed to send response for ListNvmeControllers. The client may have disconnected."
+        );
+    }
+}
+
+pub async fn handle_create_nvme_namespace(
+    req: CreateNvmeNamespaceRequest,
+    responder: oneshot::Sender<Result<CreateNvmeNamespaceResponse, HostError>>,
+) {
+    info!(
+        "HostWorker: Processing CreateNvmeNamespace request for {} bytes on {}.",
+        req.size_bytes, req.controller
+    );
+    let controller = req.controller.clone();
+    let result = run_blocking(move || {
+        nvme::create_namespace(&req.controller, req.size_bytes, req.block_size)
+    })
+    .await
+    .map(|namespace| {
+        info!(
+            "HostWorker: Created NVMe namespace {} on {controller} as {}",
+            namespace.nsid, namespace.device
+        );
+        CreateNvmeNamespaceResponse {
+            namespace: Some(namespace_to_proto(namespace)),
+        }
+    });
+    if responder.send(result).is_err() {
+        error!(
+            "HostWorker: Failed to send response for CreateNvmeNamespace. The client may have disconnected."
+        );
+    }
+}
+
+pub async fn handle_delete_nvme_namespace(
+    req: DeleteNvmeNamespaceRequest,
+    responder: oneshot::Sender<Result<DeleteNvmeNamespaceResponse, HostError>>,
+) {
+    info!(
+        "HostWorker: Processing DeleteNvmeNamespace request for namespace {} of {}.",
+        req.nsid, req.controller
+    );
+    let (controller, nsid) = (req.controller.clone(), req.nsid);
+    let result = run_blocking(move || nvme::delete_namespace(&req.controller, req.nsid))
+        .await
+        .map(|()| {
+            info!("HostWorker: Deleted NVMe namespace {nsid} of {controller}");
+            DeleteNvmeNamespaceResponse {}
+        });
+    if responder.send(result).is_err() {
+        error!(
+            "HostWorker: Failed to send response for DeleteNvmeNamespace. The client may have disconnected."
+        );
+    }
+}
+
+pub async fn handle_format_nvme_namespace(
+    req: FormatNvmeNamespaceRequest,
+    responder: oneshot::Sender<Result<FormatNvmeNamespaceResponse, HostError>>,
+) {
+    info!(
+        "HostWorker: Processing FormatNvmeNamespace request for namespace {} of {}.",
+        req.nsid, req.controller
+    );
+    let erase = match req.secure_erase() {
+        NvmeSecureErase::None => nvme::SecureErase::None,
+        NvmeSecureErase::UserData => nvme::SecureErase::UserData,
+        NvmeSecureErase::Cryptographic => nvme::SecureErase::Cryptographic,
+    };
+    let controller = req.controller.clone();
+    let result = run_blocking(move || {
+        nvme::format_namespace(&req.controller, req.nsid, req.block_size, erase)
+    })
+    .await
+    .map(|namespace| {
+        info!(
+            "HostWorker: Formatted NVMe namespace {} of {controller} with {} byte blocks",
+            namespace.nsid, namespace.block_size
+        );
+        FormatNvmeNamespaceResponse {
+            namespace: Some(namespace_to_proto(namespace)),
+        }
+    });
+    if responder.send(result).is_err() {
+        error!(
+            "HostWorker: Failed to send response for FormatNvmeNamespace. The client may have disconnected."
+        );
+    }
+}
@@ -4,15 +4,18 @@
 use crate::{error::HostError, RestartSignal};
 use digest::Digest;
 use feos_proto::host_service::{
-    FeosLogEntry, KernelLogEntry, UpgradeFeosBinaryRequest, UpgradeFeosBinaryResponse,
+    ComponentLogLevel, FeosLogEntry, GetLogLevelsResponse, KernelLogEntry, LogComponent,
+    ReadFeosLogsRequest, SetLogLevelRequest, SetLogLevelResponse, StreamFeosLogsRequest,
+    UpgradeFeosBinaryRequest, UpgradeFeosBinaryResponse,
 };
-use feos_utils::feos_logger::LogHandle;
+use feos_utils::feos_logger::{Component, LogEntry, LogHandle, LogQuery};
 use http_body_util::{BodyExt, Empty};
 use hyper::body::Bytes;
 use hyper_rustls::HttpsConnectorBuilder;
 use hyper_util::{client::legacy::Client, rt::TokioExecutor};
-use log::{error, info, warn};
+use log::{error, info, warn, Level, LevelFilter};
 use prost_types::Timestamp;
+use regex::{Regex, RegexBuilder};
 use sha2::Sha256;
 use std::fs::Permissions;
 use std::io::Write;
@@ -26,13 +29,78 @@ use tonic::Status;
 
 const UPGRADE_DIR: &str = "/var/lib/feos/upgrade";
 const ELF_MAGIC: [u8; 4] = [0x7f, 0x45, 0x4c, 0x46];
-const KMSG_PATH: &str = "/dev/kmsg";
+/// Keeps client supplied patterns from compiling into huge automata.
+const MESSAGE_REGEX_SIZE_LIMIT: usize = 1 << 20;
+pub(crate) const KMSG_PATH: &str = "/dev/kmsg";
+
+/// The filters of a StreamFeOSLogs request. An entry is sent only if it
+/// matches all of them.
+struct LogStreamFilter {
+    level: Option<Level>,
+    target_prefix: String,
+    message: Option<Regex>,
+    since: Option<chrono::DateTime<chrono::Utc>>,
+    component: Option<Component>,
+}
+
+impl LogStreamFilter {
+    fn new(req: StreamFeosLogsRequest) -> Result<Self, HostError> {
+        let level = match req.level.as_str() {
+            "" => None,
+            level => Some(level.parse().map_err(|_| {
+                HostError::InvalidArgument(format!(
+                    "invalid log level '{level}', expected error, warn, info, debug or trace"
+                ))
+            })?),
+        };
+        let message = match req.message_regex.as_str() {
+            "" => None,
+            pattern => Some(
+                RegexBuilder::new(pattern)
+                    .size_limit(MESSAGE_REGEX_SIZE_LIMIT)
+                    .build()
+                    .map_err(|e| {
+                        HostError::InvalidArgument(format!("invalid message regex: {e}"))
+                    })?,
+            ),
+        };
+        let component = req.component.map(from_log_component).transpose()?.flatten();
+        Ok(Self {
+            level,
+            target_prefix: req.target_prefix,
+            message,
+            since: to_datetime(req.since),
+            component,
+        })
+    }
+
+    fn matches(&self, entry: &LogEntry) -> bool {
+        self.level.is_none_or(|level| entry.level <= level)
+            && entry.target.starts_with(&self.target_prefix)
+            && self.since.is_none_or(|since| entry.timestamp >= since)
+            && self
+                .component
+                .is_none_or(|component| entry.component() == component)
+            && self
+                .message
+                .as_ref()
+                .is_none_or(|regex| regex.is_match(&entry.message))
+    }
+}
 
 pub async fn handle_stream_feos_logs(
     log_handle: LogHandle,
+    req: StreamFeosLogsRequest,
     grpc_tx: mpsc::Sender<Result<FeosLogEntry, Status>>,
 ) {
     info!("HostWorker: Starting new FeOS log stream.");
+    let filter = match LogStreamFilter::new(req) {
+        Ok(filter) => filter,
+        Err(err) => {
+            let _ = grpc_tx.send(Err(err.into())).await;
+            return;
+        }
+    };
     let mut reader = match log_handle.new_reader().await {
         Ok(r) => r,
         Err(e) => {
@@ -45,19 +113,20 @@ pub async fn handle_stream_feos_logs(
         }
     };
 
-    while let Some(entry) = reader.next().await {
-        let feos_log_entry = FeosLogEntry {
-            seq: entry.seq,
-            timestamp: Some(Timestamp {
-                seconds: entry.timestamp.timestamp(),
-                nanos: entry.timestamp.timestamp_subsec_nanos() as i32,
-            }),
-            level: entry.level.to_string(),
-            target: entry.target,
-            message: entry.message,
+    loop {
+        // Filtered streams can stay quiet for long, so notice clients that
+        // go away in the meantime.
+        let entry = tokio::select! {
+            entry = reader.next() => entry,
+            _ = grpc_tx.closed() => None,
         };
-
-        if grpc_tx.send(Ok(feos_log_entry)).await.is_err() {
+        let Some(entry) = entry else {
+            break;
+        };
+        if !filter.matches(&entry) {
+            continue;
+        }
+        if grpc_tx.send(Ok(to_feos_log_entry(entry))).await.is_err() {
             info!("HostWorker: Log stream client disconnected.");
             break;
         }
@@ -65,6 +134,135 @@ pub async fn handle_stream_feos_logs(
     info!("HostWorker: FeOS log stream finished.");
 }
 
+fn to_feos_log_entry(entry: LogEntry) -> FeosLogEntry {
+    let component = to_log_component(entry.component()) as i32;
+    FeosLogEntry {
+        seq: entry.seq,
+        timestamp: Some(Timestamp {
+            seconds: entry.timestamp.timestamp(),
+            nanos: entry.timestamp.timestamp_subsec_nanos() as i32,
+        }),
+        level: entry.level.to_string(),
+        target: entry.target,
+        message: entry.message,
+        component,
+    }
+}
+
+fn to_log_component(component: Component) -> LogComponent {
+    match component {
+        Component::Vm => LogComponent::Vm,
+        Component::Container => LogComponent::Container,
+        Component::Network => LogComponent::Network,
+        Component::Api => LogComponent::Api,
+        Component::System => LogComponent::System,
+    }
+}
+
+/// Maps a requested component, `None` standing for all components.
+fn from_log_component(component: i32) -> Result<Option<Component>, HostError> {
+    match LogComponent::try_from(component) {
+        Ok(LogComponent::Unspecified) => Ok(None),
+        Ok(LogComponent::Vm) => Ok(Some(Component::Vm)),
+        Ok(LogComponent::Container) => Ok(Some(Component::Container)),
+        Ok(LogComponent::Network) => Ok(Some(Component::Network)),
+        Ok(LogComponent::Api) => Ok(Some(Component::Api)),
+        Ok(LogComponent::System) => Ok(Some(Component::System)),
+        Err(_) => Err(HostError::InvalidArgument(format!(
+            "unknown log component {component}"
+        ))),
+    }
+}
+
+pub(super) fn to_datetime(timestamp: Option<Timestamp>) -> Option<chrono::DateTime<chrono::Utc>> {
+    timestamp.and_then(|ts| chrono::DateTime::from_timestamp(ts.seconds, ts.nanos.max(0) as u32))
+}
+
+pub async fn handle_read_feos_logs(
+    log_handle: LogHandle,
+    req: ReadFeosLogsRequest,
+    grpc_tx: mpsc::Sender<Result<FeosLogEntry, Status>>,
+) {
+    let component = match req.component.map(from_log_component).transpose() {
+        Ok(component) => component.flatten(),
+        Err(e) => {
+            let _ = grpc_tx.send(Err(e.into())).await;
+            return;
+        }
+    };
+    let query = LogQuery {
+        start_seq: req.start_seq,
+        end_seq: req.end_seq,
+        start_time: to_datetime(req.start_time),
+        end_time: to_datetime(req.end_time),
+        limit: req.limit as usize,
+        component,
+    };
+    let entries = match log_handle.query(query).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            let err = HostError::LogReader(e.to_string());
+            error!("HostWorker: {err}");
+            let _ = grpc_tx.send(Err(err.into())).await;
+            return;
+        }
+    };
+
+    info!("HostWorker: Sending {} FeOS log entries.", entries.len());
+    for entry in entries {
+        if grpc_tx.send(Ok(to_feos_log_entry(entry))).await.is_err() {
+            info!("HostWorker: Log history client disconnected.");
+            break;
+        }
+    }
+}
+
+fn set_log_level(log_handle: &LogHandle, req: &SetLogLevelRequest) -> Result<(), HostError> {
+    let level: LevelFilter = req.level.parse().map_err(|_| {
+        HostError::InvalidArgument(format!(
+            "invalid log level '{}', expected off, error, warn, info, debug or trace",
+            req.level
+        ))
+    })?;
+    let components = match from_log_component(req.component)? {
+        Some(component) => vec![component],
+        None => Component::ALL.to_vec(),
+    };
+    for component in components {
+        log_handle.set_level(component, level);
+        info!("HostWorker: Set log level of component '{component}' to {level}.");
+    }
+    Ok(())
+}
+
+pub async fn handle_set_log_level(
+    log_handle: LogHandle,
+    req: SetLogLevelRequest,
+    responder: oneshot::Sender<Result<SetLogLevelResponse, HostError>>,
+) {
+    let result = set_log_level(&log_handle, &req).map(|()| SetLogLevelResponse {});
+    if responder.send(result).is_err() {
+        error!("HostWorker: Failed to send response for SetLogLevel.");
+    }
+}
+
+pub async fn handle_get_log_levels(
+    log_handle: LogHandle,
+    responder: oneshot::Sender<Result<GetLogLevelsResponse, HostError>>,
+) {
+    let levels = Component::ALL
+        .into_iter()
+        .map(|component| ComponentLogLevel {
+            component: to_log_component(component) as i32,
+            level: log_handle.level(component).to_string().to_lowercase(),
+        })
+        .collect();
+
+    if responder.send(Ok(GetLogLevelsResponse { levels })).is_err() {
+        error!("HostWorker: Failed to send response for GetLogLevels.");
+    }
+}
+
 pub async fn handle_stream_kernel_logs(grpc_tx: mpsc::Sender<Result<KernelLogEntry, Status>>) {
     info!("HostWorker: Opening {KMSG_PATH} for streaming kernel logs.");
 
@@ -254,3 +452,47 @@ pub async fn handle_upgrade(
 
     info!("HostWorker: Restart signal sent.");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(level: Level, target: &str, message: &str) -> LogEntry {
+        LogEntry {
+            seq: 1,
+            timestamp: chrono::Utc::now(),
+            level,
+            target: target.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn log_stream_filter_matches_all_criteria() {
+        let filter = LogStreamFilter::new(StreamFeosLogsRequest {
+            level: "info".to_string(),
+            target_prefix: "vm_service".to_string(),
+            message_regex: "^VM [0-9a-f-]+ started$".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert!(filter.matches(&entry(
+            Level::Warn,
+            "vm_service::worker",
+            "VM 1f-2a started"
+        )));
+        assert!(!filter.matches(&entry(Level::Debug, "vm_service::worker", "VM 1f started")));
+        assert!(!filter.matches(&entry(Level::Info, "host_service::worker", "VM 1f started")));
+        assert!(!filter.matches(&entry(Level::Info, "vm_service::worker", "VM 1f stopped")));
+
+        let invalid = StreamFeosLogsRequest {
+            message_regex: "(".to_string(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            LogStreamFilter::new(invalid),
+            Err(HostError::InvalidArgument(_))
+        ));
+    }
+}
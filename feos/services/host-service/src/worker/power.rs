@@ -7,6 +7,12 @@ use log::{error, info};
 use nix::sys::reboot::{reboot, RebootMode};
 use tokio::sync::oneshot;
 
+/// reboot(2) does not write back dirty pages, which would lose the tail of the
+/// log journal among others.
+fn sync_filesystems() {
+    unsafe { libc::sync() };
+}
+
 pub async fn handle_shutdown(
     _req: ShutdownRequest,
     responder: oneshot::Sender<Result<ShutdownResponse, HostError>>,
@@ -22,6 +28,7 @@ pub async fn handle_shutdown(
     tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
 
     info!("HostWorker: Executing system shutdown.");
+    sync_filesystems();
     match reboot(RebootMode::RB_POWER_OFF) {
         Ok(infallible) => match infallible {},
         Err(e) => {
@@ -43,6 +50,7 @@ pub async fn handle_reboot(
     tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
 
     info!("HostWorker: Executing system reboot.");
+    sync_filesystems();
     match reboot(RebootMode::RB_AUTOBOOT) {
         Ok(infallible) => match infallible {},
         Err(e) => {
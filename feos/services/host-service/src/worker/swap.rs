@@ -0,0 +1,375 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::error::HostError;
+use feos_proto::host_service::{
+    add_swap_request::Backend, AddSwapRequest, AddSwapResponse, ListSwapResponse,
+    RemoveSwapRequest, RemoveSwapResponse, SetSwappinessRequest, SetSwappinessResponse, SwapDevice,
+    SwapFileConfig, SwapType, ZramConfig,
+};
+use log::{error, info, warn};
+use std::ffi::CString;
+use std::fs::OpenOptions;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::sync::oneshot;
+
+const PROC_SWAPS: &str = "/proc/swaps";
+const SWAPPINESS_PATH: &str = "/proc/sys/vm/swappiness";
+const ZRAM_CONTROL_DIR: &str = "/sys/class/zram-control";
+const MAX_SWAPPINESS: u32 = 200;
+/// mkswap refuses smaller swap areas.
+const MIN_SWAP_PAGES: u64 = 10;
+
+// From <sys/swap.h>.
+const SWAP_FLAG_PREFER: libc::c_int = 0x8000;
+const SWAP_FLAG_PRIO_MASK: libc::c_int = 0x7fff;
+
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+/// Builds the first page of a swap area the way mkswap writes it: a version 1
+/// header followed by the `SWAPSPACE2` signature at the end of the page.
+fn swap_header(size_bytes: u64, page_size: usize) -> Result<Vec<u8>, HostError> {
+    let pages = size_bytes / page_size as u64;
+    if pages < MIN_SWAP_PAGES {
+        return Err(HostError::InvalidArgument(format!(
+            "A swap area needs at least {} bytes, got {size_bytes}",
+            MIN_SWAP_PAGES * page_size as u64
+        )));
+    }
+    let last_page = u32::try_from(pages - 1).map_err(|_| {
+        HostError::InvalidArgument(format!("Swap area of {size_bytes} bytes is too large"))
+    })?;
+
+    let mut header = vec![0u8; page_size];
+    header[1024..1028].copy_from_slice(&1u32.to_ne_bytes());
+    header[1028..1032].copy_from_slice(&last_page.to_ne_bytes());
+    header[page_size - 10..].copy_from_slice(b"SWAPSPACE2");
+    Ok(header)
+}
+
+fn swap_on(path: &Path, priority: Option<i32>) -> io::Result<()> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let flags = priority.map_or(0, |priority| {
+        SWAP_FLAG_PREFER | (priority & SWAP_FLAG_PRIO_MASK)
+    });
+    if unsafe { libc::swapon(path.as_ptr(), flags) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn swap_off(path: &Path) -> io::Result<()> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    if unsafe { libc::swapoff(path.as_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Returns the zram device number of a path like `/dev/zram0`.
+fn zram_id(path: &str) -> Option<&str> {
+    path.strip_prefix("/dev/zram")
+        .filter(|id| !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()))
+}
+
+fn create_swapfile(config: &SwapFileConfig, priority: Option<i32>) -> Result<PathBuf, HostError> {
+    let header = swap_header(config.size_bytes, page_size())?;
+    let path = Path::new(&config.path);
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .map_err(|e| HostError::Swap(format!("Failed to create {}: {e}", path.display())))?;
+
+    let result = (|| -> io::Result<PathBuf> {
+        // The kernel refuses swapfiles with holes.
+        let ret = unsafe { libc::fallocate(file.as_raw_fd(), 0, 0, config.size_bytes as _) };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        file.write_all_at(&header, 0)?;
+        file.sync_all()?;
+        let path = std::fs::canonicalize(path)?;
+        swap_on(&path, priority)?;
+        Ok(path)
+    })();
+    result.map_err(|e| {
+        let _ = std::fs::remove_file(path);
+        HostError::Swap(format!("Failed to set up swapfile {}: {e}", path.display()))
+    })
+}
+
+fn create_zram(config: &ZramConfig, priority: Option<i32>) -> Result<PathBuf, HostError> {
+    let header = swap_header(config.size_bytes, page_size())?;
+    let id = std::fs::read_to_string(Path::new(ZRAM_CONTROL_DIR).join("hot_add")).map_err(|e| {
+        HostError::Swap(format!(
+            "Failed to add a zram device, is the zram module loaded? {e}"
+        ))
+    })?;
+    let id = id.trim();
+
+    let result = (|| -> io::Result<PathBuf> {
+        let sysfs = Path::new("/sys/block").join(format!("zram{id}"));
+        // The algorithm can only be changed before the size is set.
+        if !config.compression_algorithm.is_empty() {
+            std::fs::write(sysfs.join("comp_algorithm"), &config.compression_algorithm)?;
+        }
+        std::fs::write(sysfs.join("disksize"), config.size_bytes.to_string())?;
+
+        let device = PathBuf::from(format!("/dev/zram{id}"));
+        let file = OpenOptions::new().write(true).open(&device)?;
+        file.write_all_at(&header, 0)?;
+        file.sync_all()?;
+        swap_on(&device, priority)?;
+        Ok(device)
+    })();
+    result.map_err(|e| {
+        remove_zram(id);
+        HostError::Swap(format!("Failed to set up zram{id}: {e}"))
+    })
+}
+
+/// Frees the memory of a zram device and removes it.
+fn remove_zram(id: &str) {
+    let reset = Path::new("/sys/block")
+        .join(format!("zram{id}"))
+        .join("reset");
+    if let Err(e) = std::fs::write(reset, "1") {
+        warn!("HostWorker: Failed to reset zram{id}: {e}");
+    }
+    if let Err(e) = std::fs::write(Path::new(ZRAM_CONTROL_DIR).join("hot_remove"), id) {
+        warn!("HostWorker: Failed to remove zram{id}: {e}");
+    }
+}
+
+fn parse_swaps(swaps: &str) -> Vec<SwapDevice> {
+    swaps
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [path, kind, size_kib, used_kib, priority] = fields[..] else {
+                return None;
+            };
+            let swap_type = match kind {
+                _ if zram_id(path).is_some() => SwapType::Zram,
+                "file" => SwapType::File,
+                "partition" => SwapType::Partition,
+                _ => SwapType::Unspecified,
+            };
+            Some(SwapDevice {
+                path: path.to_string(),
+                r#type: swap_type as i32,
+                size_bytes: size_kib.parse::<u64>().ok()? * 1024,
+                used_bytes: used_kib.parse::<u64>().ok()? * 1024,
+                priority: priority.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+async fn read_swap_devices() -> Result<Vec<SwapDevice>, HostError> {
+    let swaps = fs::read_to_string(PROC_SWAPS)
+        .await
+        .map_err(|e| HostError::SystemInfoRead {
+            source: e,
+            path: PROC_SWAPS.to_string(),
+        })?;
+    Ok(parse_swaps(&swaps))
+}
+
+async fn add_swap(req: AddSwapRequest) -> Result<AddSwapResponse, HostError> {
+    let priority = req.priority;
+    if let Some(priority) = priority {
+        if !(0..=SWAP_FLAG_PRIO_MASK).contains(&priority) {
+            return Err(HostError::InvalidArgument(format!(
+                "Swap priority must be between 0 and {SWAP_FLAG_PRIO_MASK}, got {priority}"
+            )));
+        }
+    }
+
+    let setup = match req.backend {
+        Some(Backend::File(config)) => {
+            let path = Path::new(&config.path);
+            // /proc/swaps separates its columns with whitespace.
+            if !path.is_absolute() || config.path.contains(char::is_whitespace) {
+                return Err(HostError::InvalidArgument(format!(
+                    "Swapfile path must be absolute and free of whitespace, got '{}'",
+                    config.path
+                )));
+            }
+            tokio::task::spawn_blocking(move || create_swapfile(&config, priority)).await
+        }
+        Some(Backend::Zram(config)) => {
+            tokio::task::spawn_blocking(move || create_zram(&config, priority)).await
+        }
+        None => {
+            return Err(HostError::InvalidArgument(
+                "A swapfile or zram backend is required".to_string(),
+            ))
+        }
+    };
+    let path = setup.map_err(|e| HostError::Swap(e.to_string()))??;
+    let path = path.to_string_lossy().into_owned();
+    info!("HostWorker: Enabled swap on {path}");
+
+    let device = read_swap_devices()
+        .await?
+        .into_iter()
+        .find(|device| device.path == path)
+        .ok_or_else(|| HostError::Swap(format!("{path} is missing from {PROC_SWAPS}")))?;
+    Ok(AddSwapResponse {
+        device: Some(device),
+    })
+}
+
+async fn remove_swap(req: RemoveSwapRequest) -> Result<RemoveSwapResponse, HostError> {
+    let device = read_swap_devices()
+        .await?
+        .into_iter()
+        .find(|device| device.path == req.path)
+        .ok_or_else(|| HostError::NotFound(format!("{} is not an active swap area", req.path)))?;
+
+    // Swapping everything back in can take a while.
+    tokio::task::spawn_blocking(move || {
+        let path = Path::new(&device.path);
+        swap_off(path).map_err(|e| {
+            HostError::Swap(format!("Failed to disable swap on {}: {e}", device.path))
+        })?;
+        if let Some(id) = zram_id(&device.path) {
+            remove_zram(id);
+        } else if req.delete_file && device.r#type == SwapType::File as i32 {
+            std::fs::remove_file(path)
+                .map_err(|e| HostError::Swap(format!("Failed to delete {}: {e}", device.path)))?;
+        }
+        Ok::<(), HostError>(())
+    })
+    .await
+    .map_err(|e| HostError::Swap(e.to_string()))??;
+
+    info!("HostWorker: Disabled swap on {}", req.path);
+    Ok(RemoveSwapResponse {})
+}
+
+async fn read_swappiness() -> Result<u32, HostError> {
+    let read_error = |e| HostError::SystemInfoRead {
+        source: e,
+        path: SWAPPINESS_PATH.to_string(),
+    };
+    fs::read_to_string(SWAPPINESS_PATH)
+        .await
+        .map_err(read_error)?
+        .trim()
+        .parse()
+        .map_err(|e| read_error(io::Error::new(io::ErrorKind::InvalidData, e)))
+}
+
+pub async fn handle_add_swap(
+    req: AddSwapRequest,
+    responder: oneshot::Sender<Result<AddSwapResponse, HostError>>,
+) {
+    info!("HostWorker: Processing AddSwap request.");
+    if responder.send(add_swap(req).await).is_err() {
+        error!(
+            "HostWorker: Failed to send response for AddSwap. The client may have disconnected."
+        );
+    }
+}
+
+pub async fn handle_list_swap(responder: oneshot::Sender<Result<ListSwapResponse, HostError>>) {
+    info!("HostWorker: Processing ListSwap request.");
+    let result = async {
+        Ok(ListSwapResponse {
+            devices: read_swap_devices().await?,
+            swappiness: read_swappiness().await?,
+        })
+    }
+    .await;
+    if responder.send(result).is_err() {
+        error!(
+            "HostWorker: Failed to send response for ListSwap. The client may have disconnected."
+        );
+    }
+}
+
+pub async fn handle_remove_swap(
+    req: RemoveSwapRequest,
+    responder: oneshot::Sender<Result<RemoveSwapResponse, HostError>>,
+) {
+    info!(
+        "HostWorker: Processing RemoveSwap request for {}.",
+        req.path
+    );
+    if responder.send(remove_swap(req).await).is_err() {
+        error!(
+            "HostWorker: Failed to send response for RemoveSwap. The client may have disconnected."
+        );
+    }
+}
+
+pub async fn handle_set_swappiness(
+    req: SetSwappinessRequest,
+    responder: oneshot::Sender<Result<SetSwappinessResponse, HostError>>,
+) {
+    info!("HostWorker: Processing SetSwappiness request.");
+    let result = async {
+        if req.swappiness > MAX_SWAPPINESS {
+            return Err(HostError::InvalidArgument(format!(
+                "Swappiness must be between 0 and {MAX_SWAPPINESS}, got {}",
+                req.swappiness
+            )));
+        }
+        fs::write(SWAPPINESS_PATH, req.swappiness.to_string())
+            .await
+            .map_err(|e| HostError::Swap(format!("Failed to write {SWAPPINESS_PATH}: {e}")))?;
+        info!("HostWorker: Set swappiness to {}", req.swappiness);
+        Ok(SetSwappinessResponse {})
+    }
+    .await;
+    if responder.send(result).is_err() {
+        error!(
+            "HostWorker: Failed to send response for SetSwappiness. The client may have disconnected."
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swap_header_matches_mkswap_layout() {
+        let header = swap_header(64 * 4096 + 100, 4096).unwrap();
+        assert_eq!(header.len(), 4096);
+        assert_eq!(&header[1024..1028], &1u32.to_ne_bytes());
+        assert_eq!(&header[1028..1032], &63u32.to_ne_bytes());
+        assert_eq!(&header[4086..], b"SWAPSPACE2");
+        assert!(header[..1024].iter().all(|&b| b == 0));
+
+        assert!(swap_header(9 * 4096, 4096).is_err());
+    }
+
+    #[test]
+    fn swaps_are_parsed_with_their_type() {
+        let swaps = "Filename\t\t\t\tType\t\tSize\t\tUsed\t\tPriority\n\
+                     /dev/zram0                              partition\t8388604\t\t1024\t\t100\n\
+                     /var/lib/feos/swapfile                  file\t\t2097148\t\t0\t\t-2\n";
+        let devices = parse_swaps(swaps);
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].path, "/dev/zram0");
+        assert_eq!(devices[0].r#type, SwapType::Zram as i32);
+        assert_eq!(devices[0].used_bytes, 1024 * 1024);
+        assert_eq!(devices[0].priority, 100);
+        assert_eq!(devices[1].r#type, SwapType::File as i32);
+        assert_eq!(devices[1].size_bytes, 2097148 * 1024);
+        assert_eq!(devices[1].priority, -2);
+    }
+}
@@ -75,14 +75,15 @@ impl TimeSyncWorker {
     }
 
     async fn resolve_and_sync(&self, socket: &UdpSocket, hostname: &str) -> Result<(), String> {
-        use tokio::net::lookup_host;
+        use feos_utils::network::happy_eyeballs;
 
-        let server_with_port = format!("{hostname}:{NTP_PORT}");
-        let mut addrs = lookup_host(&server_with_port)
+        // IPv4-only servers are reached through NAT64 on IPv6-only hosts.
+        let addrs = happy_eyeballs::resolve(hostname, NTP_PORT)
             .await
             .map_err(|e| format!("Failed to resolve {hostname}: {e}"))?;
 
         let target = addrs
+            .into_iter()
             .find(|addr| addr.is_ipv6())
             .or_else(|| {
                 warn!("TimeSyncWorker: No IPv6 address found for {hostname}, trying IPv4");
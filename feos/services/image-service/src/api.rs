@@ -4,14 +4,17 @@
 use crate::Command;
 use feos_proto::image_service::{
     image_service_server::ImageService, DeleteImageRequest, DeleteImageResponse,
-    ImageStatusResponse, ListImagesRequest, ListImagesResponse, PullImageRequest,
-    PullImageResponse, WatchImageStatusRequest,
+    ImageStatusResponse, InspectImageRequest, InspectImageResponse, ListImagesRequest,
+    ListImagesResponse, ListPinnedImagesRequest, ListPinnedImagesResponse, LoadImageRequest,
+    LoadImageResponse, PinImageRequest, PinImageResponse, PruneImagesRequest, PruneImagesResponse,
+    PullImageRequest, PullImageResponse, UnpinImageRequest, UnpinImageResponse,
+    WatchImageStatusRequest,
 };
 use log::info;
 use std::pin::Pin;
 use tokio::sync::{mpsc, oneshot};
 use tokio_stream::{wrappers::ReceiverStream, Stream};
-use tonic::{Request, Response, Status};
+use tonic::{Request, Response, Status, Streaming};
 
 pub struct ImageApiHandler {
     dispatcher_tx: mpsc::Sender<Command>,
@@ -99,4 +102,70 @@ impl ImageService for ImageApiHandler {
         })
         .await
     }
+
+    async fn inspect_image(
+        &self,
+        request: Request<InspectImageRequest>,
+    ) -> Result<Response<InspectImageResponse>, Status> {
+        info!("ImageApi: Received InspectImage request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::InspectImage(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn load_image(
+        &self,
+        request: Request<Streaming<LoadImageRequest>>,
+    ) -> Result<Response<LoadImageResponse>, Status> {
+        info!("ImageApi: Received LoadImage stream.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::LoadImage(Box::new(request.into_inner()), resp_tx)
+        })
+        .await
+    }
+
+    async fn pin_image(
+        &self,
+        request: Request<PinImageRequest>,
+    ) -> Result<Response<PinImageResponse>, Status> {
+        info!("ImageApi: Received PinImage request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::PinImage(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn unpin_image(
+        &self,
+        request: Request<UnpinImageRequest>,
+    ) -> Result<Response<UnpinImageResponse>, Status> {
+        info!("ImageApi: Received UnpinImage request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::UnpinImage(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn list_pinned_images(
+        &self,
+        request: Request<ListPinnedImagesRequest>,
+    ) -> Result<Response<ListPinnedImagesResponse>, Status> {
+        info!("ImageApi: Received ListPinnedImages request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::ListPinnedImages(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn prune_images(
+        &self,
+        request: Request<PruneImagesRequest>,
+    ) -> Result<Response<PruneImagesResponse>, Status> {
+        info!("ImageApi: Received PruneImages request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::PruneImages(request.into_inner(), resp_tx)
+        })
+        .await
+    }
 }
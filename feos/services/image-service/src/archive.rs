@@ -0,0 +1,241 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{error::ImageServiceError, layerstore::GZIP_MAGIC, PulledImageData, PulledLayer};
+use oci_distribution::manifest;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use tar::{Archive, EntryType};
+
+/// An image listed in the `manifest.json` of a `docker save` archive.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ArchivedImage {
+    config: String,
+    #[serde(default)]
+    repo_tags: Option<Vec<String>>,
+    layers: Vec<String>,
+}
+
+fn invalid(message: impl Into<String>) -> ImageServiceError {
+    ImageServiceError::InvalidArchive(message.into())
+}
+
+/// Resolves `.` and `..` in a path within the archive, `None` if it leads
+/// out of the archive.
+fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(normalized)
+}
+
+/// The regular files of an archive by path, as ranges of the archive.
+/// Symlinks, which `docker save` uses for layers shared by several images,
+/// point to the range of their target.
+fn index_files(archive: &[u8]) -> Result<HashMap<PathBuf, Range<usize>>, ImageServiceError> {
+    let mut files = HashMap::new();
+    let mut links = Vec::new();
+    let mut reader = Archive::new(archive);
+    let entries = reader
+        .entries()
+        .map_err(|e| invalid(format!("Not a tar archive: {e}")))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| invalid(format!("Corrupt tar archive: {e}")))?;
+        let Some(path) = entry.path().ok().and_then(|path| normalize(&path)) else {
+            continue;
+        };
+        match entry.header().entry_type() {
+            EntryType::Regular => {
+                let start = entry.raw_file_position() as usize;
+                files.insert(path, start..start + entry.size() as usize);
+            }
+            EntryType::Symlink => {
+                let target = entry.link_name().ok().flatten().and_then(|target| {
+                    normalize(&path.parent().unwrap_or(Path::new("")).join(target))
+                });
+                if let Some(target) = target {
+                    links.push((path, target));
+                }
+            }
+            _ => {}
+        }
+    }
+    for (path, target) in links {
+        if let Some(range) = files.get(&target).cloned() {
+            files.insert(path, range);
+        }
+    }
+    Ok(files)
+}
+
+fn sha256_digest(data: &[u8]) -> String {
+    format!("sha256:{}", hex::encode(Sha256::digest(data)))
+}
+
+/// Picks the image listed under `image_ref`, or the only image of the
+/// archive, which is then listed under `image_ref` or else its first tag.
+fn select_image(
+    mut images: Vec<ArchivedImage>,
+    image_ref: &str,
+) -> Result<(ArchivedImage, String), ImageServiceError> {
+    if image_ref.is_empty() {
+        if images.len() != 1 {
+            return Err(invalid(format!(
+                "Archive holds {} images, name the one to load",
+                images.len()
+            )));
+        }
+        let image = images.remove(0);
+        let image_ref = image
+            .repo_tags
+            .as_ref()
+            .and_then(|tags| tags.first().cloned())
+            .ok_or_else(|| invalid("Image in archive has no tag, name it"))?;
+        return Ok((image, image_ref));
+    }
+
+    let tagged = images
+        .iter()
+        .position(|image| image.repo_tags.iter().flatten().any(|tag| tag == image_ref));
+    match tagged {
+        Some(index) => Ok((images.swap_remove(index), image_ref.to_string())),
+        None if images.len() == 1 => Ok((images.remove(0), image_ref.to_string())),
+        None => Err(invalid(format!("Archive holds no image {image_ref}"))),
+    }
+}
+
+/// Reads an image out of an archive written by `docker save`, see
+/// `select_image`. Returns the reference the image is listed under along
+/// with its config and layers.
+pub fn read_docker_archive(
+    archive: &[u8],
+    image_ref: &str,
+) -> Result<(String, PulledImageData), ImageServiceError> {
+    let files = index_files(archive)?;
+    let file = |name: &str| {
+        normalize(Path::new(name))
+            .and_then(|path| files.get(&path))
+            .and_then(|range| archive.get(range.clone()))
+            .ok_or_else(|| invalid(format!("Archive lacks {name}")))
+    };
+
+    let images: Vec<ArchivedImage> = serde_json::from_slice(file("manifest.json")?)
+        .map_err(|e| invalid(format!("Invalid manifest.json: {e}")))?;
+    let (image, image_ref) = select_image(images, image_ref)?;
+
+    let config = file(&image.config)?;
+    let mut layers = Vec::new();
+    for name in &image.layers {
+        let data = file(name)?;
+        // Layers are saved as plain tarballs, but OCI layouts may hold
+        // compressed ones.
+        let media_type = if data.starts_with(GZIP_MAGIC) {
+            manifest::IMAGE_LAYER_GZIP_MEDIA_TYPE
+        } else {
+            manifest::IMAGE_LAYER_MEDIA_TYPE
+        };
+        layers.push(PulledLayer {
+            media_type: media_type.to_string(),
+            digest: sha256_digest(data),
+            data: Some(data.to_vec()),
+        });
+    }
+    if layers.is_empty() {
+        return Err(ImageServiceError::MissingLayer(
+            "No layers found in archive".to_string(),
+        ));
+    }
+
+    Ok((
+        image_ref,
+        PulledImageData {
+            config_digest: sha256_digest(config),
+            config: config.to_vec(),
+            layers,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tar::{Builder, Header};
+
+    fn append_file(builder: &mut Builder<Vec<u8>>, path: &str, data: &[u8]) {
+        let mut header = Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, path, data).unwrap();
+    }
+
+    fn saved_archive() -> Vec<u8> {
+        let manifest = r#"[
+            {"Config": "c0ffee.json", "RepoTags": ["app:1.0"], "Layers": ["base/layer.tar", "app/layer.tar"]},
+            {"Config": "c0ffee.json", "RepoTags": null, "Layers": ["shared/layer.tar"]}
+        ]"#;
+        let mut builder = Builder::new(Vec::new());
+        append_file(&mut builder, "manifest.json", manifest.as_bytes());
+        append_file(&mut builder, "c0ffee.json", b"{\"architecture\":\"amd64\"}");
+        append_file(&mut builder, "base/layer.tar", b"base layer");
+        append_file(&mut builder, "app/layer.tar", b"\x1f\x8bapp layer");
+
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Symlink);
+        header.set_size(0);
+        header.set_cksum();
+        builder
+            .append_link(&mut header, "shared/layer.tar", "../base/layer.tar")
+            .unwrap();
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn images_are_read_from_saved_archives() {
+        let archive = saved_archive();
+        let (image_ref, image) = read_docker_archive(&archive, "app:1.0").unwrap();
+        assert_eq!(image_ref, "app:1.0");
+        assert_eq!(image.config, b"{\"architecture\":\"amd64\"}");
+        assert_eq!(image.config_digest, sha256_digest(&image.config));
+        assert_eq!(image.layers.len(), 2);
+        assert_eq!(image.layers[0].data.as_deref(), Some(&b"base layer"[..]));
+        assert_eq!(image.layers[0].media_type, manifest::IMAGE_LAYER_MEDIA_TYPE);
+        assert_eq!(
+            image.layers[1].media_type,
+            manifest::IMAGE_LAYER_GZIP_MEDIA_TYPE
+        );
+
+        // Without a reference it is unclear which of the two images to load.
+        assert!(matches!(
+            read_docker_archive(&archive, ""),
+            Err(ImageServiceError::InvalidArchive(_))
+        ));
+        assert!(read_docker_archive(&archive, "other:latest").is_err());
+    }
+
+    #[test]
+    fn shared_layers_resolve_to_their_target() {
+        let archive = saved_archive();
+        let files = index_files(&archive).unwrap();
+        assert_eq!(
+            files[Path::new("shared/layer.tar")],
+            files[Path::new("base/layer.tar")]
+        );
+        assert_eq!(normalize(Path::new("../etc/passwd")), None);
+        assert_eq!(normalize(Path::new("/etc/passwd")), None);
+    }
+}
@@ -0,0 +1,126 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Credentials of private registries. They are read from a file in the
+//! format of Docker's `config.json`, which `docker login` and most CI systems
+//! write:
+//!
+//! ```json
+//! {"auths": {"ghcr.io": {"auth": "<base64 of user:password>"}}}
+//! ```
+//!
+//! The file is read on every pull, so credentials can be rotated without
+//! restarting FeOS.
+
+use crate::error::ImageServiceError;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use oci_distribution::{secrets::RegistryAuth, Reference};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use tokio::fs;
+
+const DOCKER_HUB: &str = "index.docker.io";
+
+#[derive(Deserialize)]
+struct AuthFile {
+    #[serde(default)]
+    auths: HashMap<String, AuthEntry>,
+}
+
+#[derive(Deserialize)]
+struct AuthEntry {
+    auth: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+/// The registry host a key of `auths` stands for. Keys may be URLs, and
+/// Docker Hub goes by several names.
+fn registry_host(key: &str) -> &str {
+    let host = key
+        .strip_prefix("https://")
+        .or_else(|| key.strip_prefix("http://"))
+        .unwrap_or(key);
+    let host = host.split('/').next().unwrap_or(host);
+    match host {
+        "docker.io" | "registry-1.docker.io" => DOCKER_HUB,
+        host => host,
+    }
+}
+
+/// The username and password for `registry`, if the auth file has any.
+fn find_credentials(content: &str, registry: &str) -> Result<Option<(String, String)>, String> {
+    let file: AuthFile = serde_json::from_str(content).map_err(|e| e.to_string())?;
+    let registry = registry_host(registry);
+    let Some((key, entry)) = file
+        .auths
+        .iter()
+        .find(|(key, _)| registry_host(key) == registry)
+    else {
+        return Ok(None);
+    };
+    if let (Some(username), Some(password)) = (&entry.username, &entry.password) {
+        return Ok(Some((username.clone(), password.clone())));
+    }
+    let auth = entry
+        .auth
+        .as_deref()
+        .ok_or_else(|| format!("The entry of {key} has no credentials"))?;
+    let decoded = STANDARD
+        .decode(auth.trim())
+        .ok()
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .ok_or_else(|| format!("The auth of {key} is not base64 encoded text"))?;
+    let (username, password) = decoded
+        .split_once(':')
+        .ok_or_else(|| format!("The auth of {key} is not of the form user:password"))?;
+    Ok(Some((username.to_string(), password.to_string())))
+}
+
+/// The credentials to pull `reference` with. Registries without an entry in
+/// the auth file are pulled from anonymously.
+pub async fn registry_auth(
+    auth_file: Option<&Path>,
+    reference: &Reference,
+) -> Result<RegistryAuth, ImageServiceError> {
+    let Some(auth_file) = auth_file else {
+        return Ok(RegistryAuth::Anonymous);
+    };
+    let content = fs::read_to_string(auth_file).await.map_err(|e| {
+        ImageServiceError::RegistryAuth(format!("Cannot read {}: {e}", auth_file.display()))
+    })?;
+    let credentials = find_credentials(&content, reference.resolve_registry())
+        .map_err(|e| ImageServiceError::RegistryAuth(format!("{}: {e}", auth_file.display())))?;
+    Ok(match credentials {
+        Some((username, password)) => RegistryAuth::Basic(username, password),
+        None => RegistryAuth::Anonymous,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credentials_are_found_by_registry_host() {
+        let content = r#"{
+            "auths": {
+                "https://index.docker.io/v1/": {"auth": "dXNlcjpzM2NyZXQ6MQ=="},
+                "ghcr.io": {"username": "bot", "password": "token"},
+                "quay.io": {"auth": "not base64"}
+            }
+        }"#;
+        assert_eq!(
+            find_credentials(content, "docker.io"),
+            Ok(Some(("user".to_string(), "s3cret:1".to_string())))
+        );
+        assert_eq!(
+            find_credentials(content, "ghcr.io"),
+            Ok(Some(("bot".to_string(), "token".to_string())))
+        );
+        assert_eq!(find_credentials(content, "registry.example.com"), Ok(None));
+        assert!(find_credentials(content, "quay.io").is_err());
+        assert!(find_credentials("{", "ghcr.io").is_err());
+    }
+}
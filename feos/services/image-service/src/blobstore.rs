@@ -0,0 +1,244 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use log::{info, warn};
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::HashSet;
+use std::io;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::process::Command as TokioCommand;
+use uuid::Uuid;
+
+pub(crate) const SUPPORTED_ALGORITHMS: &[&str] = &["sha256", "sha512"];
+const COPY_CHUNK_SIZE: usize = 4 << 20;
+
+/// Maps a digest like `sha256:<hex>` to `<root>/<algorithm>/<hex>`, rejecting
+/// anything that could escape `root`.
+pub(crate) fn digest_path(root: &Path, digest: &str) -> io::Result<PathBuf> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid blob digest '{digest}'"),
+        )
+    };
+    let (algorithm, hex) = digest.split_once(':').ok_or_else(invalid)?;
+    if !SUPPORTED_ALGORITHMS.contains(&algorithm)
+        || hex.is_empty()
+        || !hex.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f'))
+    {
+        return Err(invalid());
+    }
+    Ok(root.join(algorithm).join(hex))
+}
+
+/// Whether `data` has the digest `digest`.
+pub(crate) fn matches_digest(digest: &str, data: &[u8]) -> bool {
+    match digest.split_once(':') {
+        Some(("sha256", hex)) => hex::encode(Sha256::digest(data)) == hex,
+        Some(("sha512", hex)) => hex::encode(Sha512::digest(data)) == hex,
+        _ => false,
+    }
+}
+
+/// Content-addressed storage for the blobs of pulled images. Each blob is
+/// kept once under `<root>/<algorithm>/<hex>`, no matter how many images use
+/// it. Image directories get hardlinks to immutable blobs and reflinked or
+/// sparse copies of the ones VMs and containers write to.
+pub struct BlobStore {
+    root: PathBuf,
+}
+
+impl BlobStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Maps a digest like `sha256:<hex>` to the path of its blob.
+    pub fn blob_path(&self, digest: &str) -> io::Result<PathBuf> {
+        digest_path(&self.root, digest)
+    }
+
+    fn tmp_dir(&self) -> PathBuf {
+        self.root.join("tmp")
+    }
+
+    /// Stores a blob unless it is already present and returns its path.
+    pub async fn put(&self, digest: &str, data: &[u8]) -> io::Result<PathBuf> {
+        let path = self.blob_path(digest)?;
+        if fs::try_exists(&path).await? {
+            return Ok(path);
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::create_dir_all(self.tmp_dir()).await?;
+        // Written next to the store and renamed, so a blob is either complete
+        // or absent.
+        let tmp_path = self.tmp_dir().join(Uuid::new_v4().to_string());
+        fs::write(&tmp_path, data).await?;
+        if let Err(e) = fs::rename(&tmp_path, &path).await {
+            let _ = fs::remove_file(&tmp_path).await;
+            return Err(e);
+        }
+        Ok(path)
+    }
+
+    /// Hardlinks a blob into an image. Only for files nobody writes to.
+    pub async fn link(&self, blob: &Path, destination: &Path) -> io::Result<()> {
+        match fs::hard_link(blob, destination).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                fs::copy(blob, destination).await.map(|_| ())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Gives an image its own copy of a blob that shares the data blocks
+    /// until either side is written. Returns false if the filesystem has no
+    /// reflinks (btrfs and XFS have them), leaving the copy to `copy_sparse`.
+    pub async fn reflink(&self, blob: &Path, destination: &Path) -> io::Result<bool> {
+        let output = TokioCommand::new("cp")
+            .arg("--reflink=always")
+            .arg(blob)
+            .arg(destination)
+            .output()
+            .await?;
+        if output.status.success() {
+            return Ok(true);
+        }
+        info!(
+            "BlobStore: Cannot reflink {}: {}",
+            blob.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        match fs::remove_file(destination).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(false),
+        }
+    }
+
+    /// Removes all blobs whose digest is not in `referenced`, along with
+    /// leftovers of interrupted writes. Returns the number of bytes freed.
+    pub async fn collect_garbage(&self, referenced: &HashSet<String>) -> u64 {
+        let mut freed = 0;
+        if let Err(e) = fs::remove_dir_all(self.tmp_dir()).await {
+            if e.kind() != io::ErrorKind::NotFound {
+                warn!(
+                    "BlobStore: Failed to clean up {}: {e}",
+                    self.tmp_dir().display()
+                );
+            }
+        }
+
+        for algorithm in SUPPORTED_ALGORITHMS {
+            let mut entries = match fs::read_dir(self.root.join(algorithm)).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    warn!("BlobStore: Failed to list {algorithm} blobs: {e}");
+                    continue;
+                }
+            };
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                let digest = format!("{algorithm}:{}", entry.file_name().to_string_lossy());
+                if referenced.contains(&digest) {
+                    continue;
+                }
+                let size = entry.metadata().await.map(|m| m.len()).unwrap_or(0);
+                match fs::remove_file(entry.path()).await {
+                    Ok(()) => {
+                        info!("BlobStore: Removed unused blob {digest}");
+                        freed += size;
+                    }
+                    Err(e) => warn!("BlobStore: Failed to remove blob {digest}: {e}"),
+                }
+            }
+        }
+        freed
+    }
+}
+
+/// Copies `source` to `destination` without writing the blocks that are all
+/// zeros, so the copy of a sparse disk image stays sparse. `progress` is
+/// called with the bytes copied so far and the total after every chunk.
+pub async fn copy_sparse(
+    source: &Path,
+    destination: &Path,
+    mut progress: impl FnMut(u64, u64),
+) -> io::Result<()> {
+    let mut input = fs::File::open(source).await?;
+    let total = input.metadata().await?.len();
+    let mut output = fs::File::create(destination).await?;
+    let mut chunk = vec![0u8; COPY_CHUNK_SIZE];
+    let mut copied = 0;
+    loop {
+        let mut filled = 0;
+        while filled < chunk.len() {
+            match input.read(&mut chunk[filled..]).await? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        if filled == 0 {
+            break;
+        }
+        let data = &chunk[..filled];
+        if data.iter().all(|byte| *byte == 0) {
+            output.seek(SeekFrom::Current(filled as i64)).await?;
+        } else {
+            output.write_all(data).await?;
+        }
+        copied += filled as u64;
+        progress(copied, total);
+    }
+    // A trailing hole is only part of the file once the size is set.
+    output.set_len(copied).await?;
+    output.sync_all().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blob_paths_are_derived_from_digests() {
+        let store = BlobStore::new("/var/lib/feos/images/blobs");
+        assert_eq!(
+            store.blob_path("sha256:0a1b2c").unwrap(),
+            Path::new("/var/lib/feos/images/blobs/sha256/0a1b2c")
+        );
+        assert!(store.blob_path("sha256:../../etc").is_err());
+        assert!(store.blob_path("md5:0a1b2c").is_err());
+        assert!(store.blob_path("0a1b2c").is_err());
+        assert!(matches_digest(
+            "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            b""
+        ));
+        assert!(!matches_digest("md5:d41d8cd98f00b204e9800998ecf8427e", b""));
+    }
+
+    #[tokio::test]
+    async fn sparse_copies_keep_their_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("disk.raw");
+        let mut content = vec![0u8; COPY_CHUNK_SIZE * 2 + 17];
+        content[COPY_CHUNK_SIZE + 3] = 0xaa;
+        std::fs::write(&source, &content).unwrap();
+
+        let destination = dir.path().join("disk.image");
+        let mut reported = 0;
+        copy_sparse(&source, &destination, |copied, total| {
+            assert_eq!(total, content.len() as u64);
+            reported = copied;
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(reported, content.len() as u64);
+        assert_eq!(std::fs::read(&destination).unwrap(), content);
+    }
+}
@@ -44,6 +44,28 @@ impl ImageServiceDispatcher {
                 image_uuid: req.image_uuid,
                 responder,
             },
+            Command::InspectImage(req, responder) => OrchestratorCommand::InspectImage {
+                image_uuid: req.image_uuid,
+                responder,
+            },
+            Command::LoadImage(stream, responder) => {
+                OrchestratorCommand::LoadImage { stream, responder }
+            }
+            Command::PinImage(req, responder) => OrchestratorCommand::PinImage {
+                image_ref: req.image_ref,
+                responder,
+            },
+            Command::UnpinImage(req, responder) => OrchestratorCommand::UnpinImage {
+                image_ref: req.image_ref,
+                responder,
+            },
+            Command::ListPinnedImages(_req, responder) => {
+                OrchestratorCommand::ListPinnedImages { responder }
+            }
+            Command::PruneImages(req, responder) => OrchestratorCommand::PruneImages {
+                dry_run: req.dry_run,
+                responder,
+            },
             Command::WatchImageStatus(req, stream_sender) => {
                 OrchestratorCommand::WatchImageStatus {
                     image_uuid: req.image_uuid,
@@ -18,9 +18,36 @@ pub enum ImageServiceError {
     #[error("A file storage error occurred")]
     Storage(#[from] std::io::Error),
 
+    #[error("Invalid image archive: {0}")]
+    InvalidArchive(String),
+
+    #[error("Failed to receive image archive: {0}")]
+    Upload(String),
+
+    #[error("Image is built for {image}, but this host is {host}")]
+    WrongArchitecture { image: String, host: String },
+
     #[error("Image with ID '{0}' not found")]
     NotFound(String),
 
+    #[error("Image is pinned as '{0}', unpin it first")]
+    Pinned(String),
+
+    #[error("Image '{0}' is not pinned")]
+    NotPinned(String),
+
+    #[error("Image '{0}' is the base of disk overlays, delete their VMs first")]
+    OverlayBase(String),
+
+    #[error("Invalid registry credentials: {0}")]
+    RegistryAuth(String),
+
+    #[error("{0}")]
+    DigestMismatch(String),
+
+    #[error("Cannot tell which images are in use: {0}")]
+    References(String),
+
     #[error("An internal orchestrator error occurred: {0}")]
     Internal(String),
 }
@@ -32,10 +59,21 @@ impl From<ImageServiceError> for Status {
             ImageServiceError::NotFound(id) => {
                 Status::not_found(format!("Image with ID '{id}' not found"))
             }
-            ImageServiceError::OciParse(_) => Status::invalid_argument(err.to_string()),
-            ImageServiceError::OciPull(_) | ImageServiceError::MissingLayer(_) => {
-                Status::unavailable(err.to_string())
+            ImageServiceError::OciParse(_) | ImageServiceError::InvalidArchive(_) => {
+                Status::invalid_argument(err.to_string())
+            }
+            ImageServiceError::NotPinned(_) => Status::not_found(err.to_string()),
+            ImageServiceError::Pinned(_)
+            | ImageServiceError::OverlayBase(_)
+            | ImageServiceError::RegistryAuth(_) => Status::failed_precondition(err.to_string()),
+            ImageServiceError::DigestMismatch(_) => Status::data_loss(err.to_string()),
+            ImageServiceError::Upload(_) => Status::aborted(err.to_string()),
+            ImageServiceError::WrongArchitecture { .. } => {
+                Status::failed_precondition(err.to_string())
             }
+            ImageServiceError::OciPull(_)
+            | ImageServiceError::MissingLayer(_)
+            | ImageServiceError::References(_) => Status::unavailable(err.to_string()),
             ImageServiceError::Storage(_) | ImageServiceError::Internal(_) => {
                 Status::internal(err.to_string())
             }
@@ -1,30 +1,133 @@
 // SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{FileCommand, ImageInfo, PulledImageData, IMAGE_DIR};
+use crate::{
+    blobstore::{self, BlobStore},
+    layerstore::LayerStore,
+    platform, FileCommand, ImageInfo, OrchestratorCommand, PulledImageData, IMAGE_BLOB_DIR,
+    IMAGE_DIR, IMAGE_LAYER_DIR,
+};
 use feos_proto::image_service::ImageState;
-use flate2::read::GzDecoder;
 use log::{error, info, warn};
 use oci_distribution::manifest;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::io::Cursor;
-use std::path::Path;
-use tar::Archive;
-use tokio::{fs, sync::mpsc};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use tokio::{
+    fs,
+    sync::{mpsc, oneshot},
+};
+use uuid::Uuid;
 
 const INITRAMFS_MEDIA_TYPE: &str = "application/vnd.ironcore.image.initramfs.v1alpha1.initramfs";
 const VMLINUZ_MEDIA_TYPE: &str = "application/vnd.ironcore.image.vmlinuz.v1alpha1.vmlinuz";
 const ROOTFS_MEDIA_TYPE: &str = "application/vnd.ironcore.image.rootfs.v1alpha1.rootfs";
+/// Where the root disk of a VM image is copied to before it is renamed to
+/// `disk.image`.
+const PARTIAL_DISK_IMAGE: &str = "disk.image.partial";
+/// Disk copy progress is reported in steps of this many percent.
+const COPY_PROGRESS_STEP: u64 = 5;
+
+/// The root disk of a VM image that could not be reflinked from its blob.
+struct PendingDisk {
+    blob: PathBuf,
+}
 
 #[derive(Serialize, Deserialize)]
 struct ImageMetadata {
     image_ref: String,
+    /// Digests of the blobs the image was built from. Images stored before
+    /// the blob store existed have none.
+    #[serde(default)]
+    blobs: Vec<String>,
+    /// Digests of the unpacked layers of a container image, bottom layer
+    /// first. Images stored before the layer store existed have a flattened
+    /// `rootfs` directory instead.
+    #[serde(default)]
+    layers: Vec<String>,
+    /// The architecture the image is built for, if its configuration names
+    /// one.
+    #[serde(default)]
+    architecture: Option<String>,
+}
+
+impl ImageMetadata {
+    async fn read(image_dir: &Path) -> Result<Self, std::io::Error> {
+        let path = image_dir.join("metadata.json");
+        let content = fs::read_to_string(&path).await?;
+        serde_json::from_str(&content).map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Could not parse {}: {e}", path.display()),
+            )
+        })
+    }
+}
+
+/// The directories making up the root filesystem of a container image,
+/// bottom layer first. They are shared with other images and must not be
+/// written to.
+pub async fn rootfs_layers(image_dir: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+    let metadata = ImageMetadata::read(image_dir).await?;
+    if metadata.layers.is_empty() {
+        let rootfs = image_dir.join("rootfs");
+        if !fs::try_exists(&rootfs).await? {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} is not a container image", image_dir.display()),
+            ));
+        }
+        return Ok(vec![rootfs]);
+    }
+    let layers = LayerStore::new(IMAGE_LAYER_DIR);
+    metadata
+        .layers
+        .iter()
+        .map(|digest| layers.layer_path(digest))
+        .collect()
+}
+
+fn is_container_layer(media_type: &str) -> bool {
+    matches!(
+        media_type,
+        manifest::IMAGE_LAYER_GZIP_MEDIA_TYPE
+            | manifest::IMAGE_DOCKER_LAYER_GZIP_MEDIA_TYPE
+            | manifest::IMAGE_LAYER_MEDIA_TYPE
+    )
+}
+
+/// Whether a layer of an image is already stored, as an unpacked layer of a
+/// container or as a blob, and need not be downloaded again.
+pub async fn is_stored(media_type: &str, digest: &str) -> bool {
+    let path = if is_container_layer(media_type) {
+        LayerStore::new(IMAGE_LAYER_DIR).layer_path(digest)
+    } else {
+        BlobStore::new(IMAGE_BLOB_DIR).blob_path(digest)
+    };
+    match path {
+        Ok(path) => fs::try_exists(path).await.unwrap_or(false),
+        Err(_) => false,
+    }
+}
+
+/// Fails if a layer that was not downloaded, as it was stored, is gone,
+/// e.g. because the last image using it was deleted during the pull.
+async fn check_stored(path: std::io::Result<PathBuf>, digest: &str) -> std::io::Result<PathBuf> {
+    let path = path?;
+    if !fs::try_exists(&path).await? {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("Layer {digest} was removed from the store during the pull"),
+        ));
+    }
+    Ok(path)
 }
 
 pub struct FileStore {
     command_rx: mpsc::Receiver<FileCommand>,
     command_tx: mpsc::Sender<FileCommand>,
+    blobs: BlobStore,
+    layers: LayerStore,
 }
 
 impl Default for FileStore {
@@ -39,6 +142,8 @@ impl FileStore {
         Self {
             command_rx,
             command_tx,
+            blobs: BlobStore::new(IMAGE_BLOB_DIR),
+            layers: LayerStore::new(IMAGE_LAYER_DIR),
         }
     }
 
@@ -60,12 +165,38 @@ impl FileStore {
                 image_uuid,
                 image_ref,
                 image_data,
+                progress_tx,
                 responder,
             } => {
                 info!("FileStore: Storing image {image_uuid}");
                 let final_dir = Path::new(IMAGE_DIR).join(&image_uuid);
-                let result = Self::store_image_impl(&final_dir, image_data, &image_ref).await;
-                let _ = responder.send(result);
+                match self
+                    .store_image_impl(&final_dir, image_data, &image_ref)
+                    .await
+                {
+                    Ok(None) => {
+                        let _ = responder.send(self.image_size(&final_dir).await);
+                    }
+                    // The copy runs in the background, so other images can
+                    // be stored and deleted in the meantime.
+                    Ok(Some(disk)) => {
+                        tokio::spawn(copy_disk(
+                            disk,
+                            image_uuid,
+                            final_dir,
+                            progress_tx,
+                            self.command_tx.clone(),
+                            responder,
+                        ));
+                    }
+                    Err(e) => {
+                        if let Err(cleanup_err) = fs::remove_dir_all(&final_dir).await {
+                            warn!("FileStore: Failed to clean up {image_uuid}: {cleanup_err}");
+                        }
+                        self.collect_garbage().await;
+                        let _ = responder.send(Err(e));
+                    }
+                }
             }
             FileCommand::DeleteImage {
                 image_uuid,
@@ -74,69 +205,166 @@ impl FileStore {
                 info!("FileStore: Deleting image {image_uuid}");
                 let image_dir = Path::new(IMAGE_DIR).join(&image_uuid);
                 let result = fs::remove_dir_all(&image_dir).await;
+                self.collect_garbage().await;
+                let _ = responder.send(result);
+            }
+            FileCommand::ReadImageConfig {
+                image_uuid,
+                responder,
+            } => {
+                let config_path = Path::new(IMAGE_DIR).join(&image_uuid).join("config.json");
+                let result = fs::read_to_string(&config_path).await;
                 let _ = responder.send(result);
             }
             FileCommand::ScanExistingImages { responder } => {
                 info!("FileStore: Scanning for existing images...");
-                let store = Self::scan_images_impl().await;
+                let store = self.scan_images_impl().await;
+                self.collect_garbage().await;
                 let _ = responder.send(store);
             }
+            FileCommand::CollectGarbage { responder } => {
+                info!("FileStore: Collecting garbage on request");
+                self.remove_incomplete_images().await;
+                self.collect_garbage().await;
+                let _ = responder.send(());
+            }
         }
     }
 
+    /// Stores everything of an image but the root disk of a VM that could
+    /// not be reflinked, which is returned for `copy_disk`. The metadata is
+    /// written either way, so the blob of that disk is not collected.
     async fn store_image_impl(
+        &self,
         final_dir: &Path,
         image_data: PulledImageData,
         image_ref: &str,
-    ) -> Result<(), std::io::Error> {
+    ) -> Result<Option<PendingDisk>, std::io::Error> {
         fs::create_dir_all(final_dir).await?;
+        let mut blobs = Vec::new();
+        let mut layers = Vec::new();
+        let mut pending_disk = None;
 
         for layer in image_data.layers {
-            match layer.media_type.as_str() {
-                manifest::IMAGE_LAYER_GZIP_MEDIA_TYPE
-                | manifest::IMAGE_DOCKER_LAYER_GZIP_MEDIA_TYPE => {
-                    let rootfs_path = final_dir.join("rootfs");
-                    if !rootfs_path.exists() {
-                        fs::create_dir_all(&rootfs_path).await?;
+            // Containers stack the unpacked layers, so the tarballs
+            // themselves are not kept.
+            if is_container_layer(&layer.media_type) {
+                match layer.data {
+                    Some(data) => self.layers.unpack(&layer.digest, data).await?,
+                    None => {
+                        check_stored(self.layers.layer_path(&layer.digest), &layer.digest).await?
                     }
-                    let cursor = Cursor::new(layer.data);
-                    let decoder = GzDecoder::new(cursor);
-                    let mut archive = Archive::new(decoder);
-                    tokio::task::block_in_place(move || archive.unpack(&rootfs_path))?;
-                }
-                ROOTFS_MEDIA_TYPE => {
-                    let path = final_dir.join("disk.image");
-                    fs::write(path, layer.data).await?;
-                }
-                INITRAMFS_MEDIA_TYPE => {
-                    let path = final_dir.join("initramfs");
-                    fs::write(path, layer.data).await?;
-                }
-                VMLINUZ_MEDIA_TYPE => {
-                    let path = final_dir.join("vmlinuz");
-                    fs::write(path, layer.data).await?;
-                }
+                };
+                layers.push(layer.digest);
+                continue;
+            }
+            let destination = match layer.media_type.as_str() {
+                ROOTFS_MEDIA_TYPE => final_dir.join("disk.image"),
+                INITRAMFS_MEDIA_TYPE => final_dir.join("initramfs"),
+                VMLINUZ_MEDIA_TYPE => final_dir.join("vmlinuz"),
                 _ => {
                     warn!(
                         "FileStore: Skipping layer with unsupported media type: {}",
                         layer.media_type
                     );
+                    continue;
+                }
+            };
+            let blob = match layer.data {
+                Some(data) => self.blobs.put(&layer.digest, &data).await?,
+                None => check_stored(self.blobs.blob_path(&layer.digest), &layer.digest).await?,
+            };
+            blobs.push(layer.digest);
+
+            match layer.media_type.as_str() {
+                // VMs write to their root disk.
+                ROOTFS_MEDIA_TYPE => {
+                    if !self.blobs.reflink(&blob, &destination).await? {
+                        pending_disk = Some(PendingDisk { blob });
+                    }
                 }
+                _ => self.blobs.link(&blob, &destination).await?,
             }
         }
 
-        fs::write(final_dir.join("config.json"), image_data.config).await?;
+        let config_blob = self
+            .blobs
+            .put(&image_data.config_digest, &image_data.config)
+            .await?;
+        self.blobs
+            .link(&config_blob, &final_dir.join("config.json"))
+            .await?;
+        blobs.push(image_data.config_digest);
 
         let metadata = ImageMetadata {
             image_ref: image_ref.to_string(),
+            blobs,
+            layers,
+            architecture: platform::image_architecture(&image_data.config),
         };
         let metadata_json =
             serde_json::to_string_pretty(&metadata).map_err(std::io::Error::other)?;
         fs::write(final_dir.join("metadata.json"), metadata_json).await?;
-        Ok(())
+        Ok(pending_disk)
+    }
+
+    /// Removes the blobs and layers no stored image refers to anymore.
+    async fn collect_garbage(&self) {
+        let (referenced_blobs, referenced_layers) = match referenced_digests().await {
+            Ok(referenced) => referenced,
+            Err(e) => {
+                warn!("FileStore: Skipping blob cleanup, cannot list images: {e}");
+                return;
+            }
+        };
+        let freed = self.blobs.collect_garbage(&referenced_blobs).await;
+        if freed > 0 {
+            info!("FileStore: Freed {freed} bytes of unused blobs");
+        }
+        let removed = self.layers.collect_garbage(&referenced_layers).await;
+        if removed > 0 {
+            info!("FileStore: Removed {removed} unused layers");
+        }
+    }
+
+    /// Removes image directories without metadata. Images are stored within
+    /// a single command, so these are left over from pulls interrupted by a
+    /// crash.
+    async fn remove_incomplete_images(&self) {
+        let mut entries = match fs::read_dir(IMAGE_DIR).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("FileStore: Failed to read image directory {IMAGE_DIR}: {e}");
+                return;
+            }
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            let is_image_dir = entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| Uuid::parse_str(name).is_ok());
+            if !is_image_dir || !path.is_dir() || path.join("metadata.json").exists() {
+                continue;
+            }
+            match fs::remove_dir_all(&path).await {
+                Ok(()) => info!("FileStore: Removed incomplete image {}", path.display()),
+                Err(e) => warn!("FileStore: Failed to remove {}: {e}", path.display()),
+            }
+        }
     }
 
-    async fn scan_images_impl() -> HashMap<String, ImageInfo> {
+    /// The size of an image including its layers, even those it shares with
+    /// other images.
+    async fn image_size(&self, image_dir: &Path) -> Result<u64, std::io::Error> {
+        let mut size = dir_size(image_dir).await?;
+        for digest in ImageMetadata::read(image_dir).await?.layers {
+            size += dir_size(&self.layers.layer_path(&digest)?).await?;
+        }
+        Ok(size)
+    }
+
+    async fn scan_images_impl(&self) -> HashMap<String, ImageInfo> {
         let mut store = HashMap::new();
         let mut entries = match fs::read_dir(IMAGE_DIR).await {
             Ok(entries) => entries,
@@ -153,25 +381,42 @@ impl FileStore {
             }
 
             if let Some(uuid) = path.file_name().and_then(|s| s.to_str()) {
-                let metadata_path = path.join("metadata.json");
-                let disk_image_path = path.join("disk.image");
-                let rootfs_path = path.join("rootfs");
-
-                if metadata_path.exists() && (disk_image_path.exists() || rootfs_path.exists()) {
-                    if let Ok(content) = fs::read_to_string(&metadata_path).await {
-                        if let Ok(metadata) = serde_json::from_str::<ImageMetadata>(&content) {
-                            let image_info = ImageInfo {
-                                image_uuid: uuid.to_string(),
-                                image_ref: metadata.image_ref,
-                                state: ImageState::Ready as i32,
-                            };
-                            store.insert(uuid.to_string(), image_info);
-                        } else {
-                            warn!("FileStore: Could not parse metadata for {uuid}");
-                        }
-                    } else {
-                        warn!("FileStore: Could not read metadata for {uuid}");
+                if !path.join("metadata.json").exists() {
+                    continue;
+                }
+                // Nothing is stored yet while scanning, so this is a disk copy
+                // interrupted by a crash.
+                if path.join(PARTIAL_DISK_IMAGE).exists() {
+                    match fs::remove_dir_all(&path).await {
+                        Ok(()) => info!("FileStore: Removed incomplete image {uuid}"),
+                        Err(e) => warn!("FileStore: Failed to remove {}: {e}", path.display()),
+                    }
+                    continue;
+                }
+                let metadata = match ImageMetadata::read(&path).await {
+                    Ok(metadata) => metadata,
+                    Err(e) => {
+                        warn!("FileStore: Could not read metadata for {uuid}: {e}");
+                        continue;
                     }
+                };
+                let is_complete = path.join("disk.image").exists()
+                    || path.join("rootfs").exists()
+                    || !metadata.layers.is_empty();
+                if is_complete {
+                    let size_bytes = self.image_size(&path).await.unwrap_or_else(|e| {
+                        warn!("FileStore: Could not determine size of {uuid}: {e}");
+                        0
+                    });
+                    let image_info = ImageInfo {
+                        image_uuid: uuid.to_string(),
+                        image_ref: metadata.image_ref,
+                        state: ImageState::Ready as i32,
+                        size_bytes,
+                        architecture: metadata.architecture.unwrap_or_default(),
+                        pinned: false,
+                    };
+                    store.insert(uuid.to_string(), image_info);
                 }
             }
         }
@@ -182,3 +427,91 @@ impl FileStore {
         store
     }
 }
+
+/// Copies the root disk of a VM image from its blob and reports the progress
+/// to the orchestrator. Answers `responder` once the image is complete.
+async fn copy_disk(
+    disk: PendingDisk,
+    image_uuid: String,
+    final_dir: PathBuf,
+    progress_tx: mpsc::Sender<OrchestratorCommand>,
+    filestore_tx: mpsc::Sender<FileCommand>,
+    responder: oneshot::Sender<Result<u64, std::io::Error>>,
+) {
+    info!("FileStore: Copying disk of {image_uuid}, the filesystem has no reflinks");
+    let partial = final_dir.join(PARTIAL_DISK_IMAGE);
+    let mut reported = 0;
+    let result = async {
+        blobstore::copy_sparse(&disk.blob, &partial, |copied, total| {
+            let percent = (copied * 100).checked_div(total).unwrap_or(100);
+            if percent >= reported + COPY_PROGRESS_STEP || percent == 100 {
+                reported = percent;
+                // The download is done, but the image is not ready yet.
+                let progress = OrchestratorCommand::UpdatePullProgress {
+                    image_uuid: image_uuid.clone(),
+                    progress_percent: 99,
+                    message: format!("Copying disk image: {percent}%"),
+                };
+                // Progress updates are dropped rather than slowing down the copy.
+                let _ = progress_tx.try_send(progress);
+            }
+        })
+        .await?;
+        fs::rename(&partial, final_dir.join("disk.image")).await?;
+        dir_size(&final_dir).await
+    }
+    .await;
+
+    if let Err(e) = &result {
+        warn!("FileStore: Copying disk of {image_uuid} failed: {e}");
+        if let Err(cleanup_err) = fs::remove_dir_all(&final_dir).await {
+            if cleanup_err.kind() != std::io::ErrorKind::NotFound {
+                warn!("FileStore: Failed to clean up {image_uuid}: {cleanup_err}");
+            }
+        }
+        let (gc_responder, _) = oneshot::channel();
+        let _ = filestore_tx
+            .send(FileCommand::CollectGarbage {
+                responder: gc_responder,
+            })
+            .await;
+    }
+    let _ = responder.send(result);
+}
+
+/// The digests of all blobs and layers used by images in `IMAGE_DIR`. Only
+/// call this from the FileStore actor, so no image is half stored.
+async fn referenced_digests() -> Result<(HashSet<String>, HashSet<String>), std::io::Error> {
+    let mut blobs = HashSet::new();
+    let mut layers = HashSet::new();
+    let mut entries = fs::read_dir(IMAGE_DIR).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if !entry.path().join("metadata.json").exists() {
+            continue;
+        }
+        let metadata = ImageMetadata::read(&entry.path()).await?;
+        blobs.extend(metadata.blobs);
+        layers.extend(metadata.layers);
+    }
+    Ok((blobs, layers))
+}
+
+/// Returns the total size in bytes of all regular files below `root`.
+/// Symlinks are not followed, so unpacked rootfs links are not double counted.
+async fn dir_size(root: &Path) -> Result<u64, std::io::Error> {
+    let mut total = 0;
+    let mut pending: Vec<PathBuf> = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let mut entries = fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = fs::symlink_metadata(entry.path()).await?;
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else if metadata.is_file() {
+                total += metadata.len();
+            }
+        }
+    }
+    Ok(total)
+}
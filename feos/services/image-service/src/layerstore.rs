@@ -0,0 +1,196 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::blobstore::{digest_path, SUPPORTED_ALGORITHMS};
+use flate2::read::GzDecoder;
+use log::{info, warn};
+use nix::libc;
+use nix::sys::stat::{makedev, mknod, Mode, SFlag};
+use std::collections::HashSet;
+use std::ffi::{CStr, CString};
+use std::io::{self, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+use tar::Archive;
+use tokio::fs;
+use uuid::Uuid;
+
+const WHITEOUT_PREFIX: &str = ".wh.";
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+const OPAQUE_XATTR: &CStr = c"trusted.overlay.opaque";
+pub(crate) const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// Unpacked container image layers, each kept once under
+/// `<root>/<algorithm>/<hex>` of its blob digest and shared read-only by all
+/// images and containers using it. Whiteouts are stored the way overlayfs
+/// expects them, so layers can be stacked without flattening.
+pub struct LayerStore {
+    root: PathBuf,
+}
+
+impl LayerStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn layer_path(&self, digest: &str) -> io::Result<PathBuf> {
+        digest_path(&self.root, digest)
+    }
+
+    fn tmp_dir(&self) -> PathBuf {
+        self.root.join("tmp")
+    }
+
+    /// Unpacks a layer tarball, gzipped or not, unless the layer is already present
+    /// and returns the path of its directory.
+    pub async fn unpack(&self, digest: &str, data: Vec<u8>) -> io::Result<PathBuf> {
+        let path = self.layer_path(digest)?;
+        if fs::try_exists(&path).await? {
+            return Ok(path);
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let tmp_path = self.tmp_dir().join(Uuid::new_v4().to_string());
+        fs::create_dir_all(&tmp_path).await?;
+
+        let destination = tmp_path.clone();
+        let result = tokio::task::block_in_place(move || unpack_layer(&data, &destination));
+        let result = match result {
+            Ok(()) => fs::rename(&tmp_path, &path).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            let _ = fs::remove_dir_all(&tmp_path).await;
+            return Err(e);
+        }
+        info!("LayerStore: Unpacked layer {digest}");
+        Ok(path)
+    }
+
+    /// Removes all layers whose digest is not in `referenced`, along with
+    /// leftovers of interrupted unpacks. Returns the number of layers removed.
+    pub async fn collect_garbage(&self, referenced: &HashSet<String>) -> usize {
+        let mut removed = 0;
+        if let Err(e) = fs::remove_dir_all(self.tmp_dir()).await {
+            if e.kind() != io::ErrorKind::NotFound {
+                warn!(
+                    "LayerStore: Failed to clean up {}: {e}",
+                    self.tmp_dir().display()
+                );
+            }
+        }
+
+        for algorithm in SUPPORTED_ALGORITHMS {
+            let mut entries = match fs::read_dir(self.root.join(algorithm)).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    warn!("LayerStore: Failed to list {algorithm} layers: {e}");
+                    continue;
+                }
+            };
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                let digest = format!("{algorithm}:{}", entry.file_name().to_string_lossy());
+                if referenced.contains(&digest) {
+                    continue;
+                }
+                match fs::remove_dir_all(entry.path()).await {
+                    Ok(()) => {
+                        info!("LayerStore: Removed unused layer {digest}");
+                        removed += 1;
+                    }
+                    Err(e) => warn!("LayerStore: Failed to remove layer {digest}: {e}"),
+                }
+            }
+        }
+        removed
+    }
+}
+
+/// Joins a directory from a tarball onto `root`, refusing anything but plain
+/// path components.
+fn join_within(root: &Path, dir: &Path) -> io::Result<PathBuf> {
+    let mut path = root.to_path_buf();
+    for component in dir.components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Layer contains invalid path '{}'", dir.display()),
+                ))
+            }
+        }
+    }
+    Ok(path)
+}
+
+fn set_opaque(dir: &Path) -> io::Result<()> {
+    let path = CString::new(dir.as_os_str().as_bytes())?;
+    let value = b"y";
+    let ret = unsafe {
+        libc::setxattr(
+            path.as_ptr(),
+            OPAQUE_XATTR.as_ptr(),
+            value.as_ptr().cast(),
+            value.len(),
+            0,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Unpacks a layer, turning OCI whiteout files into overlayfs whiteouts:
+/// `.wh.<name>` becomes a 0/0 character device named `<name>`, and
+/// `.wh..wh..opq` marks its directory opaque.
+fn unpack_layer(data: &[u8], destination: &Path) -> io::Result<()> {
+    let reader: Box<dyn Read + '_> = if data.starts_with(GZIP_MAGIC) {
+        Box::new(GzDecoder::new(data))
+    } else {
+        Box::new(data)
+    };
+    let mut archive = Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let whiteout = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix(WHITEOUT_PREFIX).map(|_| name));
+        let Some(name) = whiteout else {
+            entry.unpack_in(destination)?;
+            continue;
+        };
+
+        let dir = join_within(destination, path.parent().unwrap_or(Path::new("")))?;
+        std::fs::create_dir_all(&dir)?;
+        if name == OPAQUE_WHITEOUT {
+            set_opaque(&dir)?;
+        } else {
+            let hidden = dir.join(&name[WHITEOUT_PREFIX.len()..]);
+            mknod(&hidden, SFlag::S_IFCHR, Mode::empty(), makedev(0, 0))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whiteout_directories_stay_within_the_layer() {
+        let root = Path::new("/var/lib/feos/images/layers/sha256/0a1b2c");
+        assert_eq!(
+            join_within(root, Path::new("./etc/apt")).unwrap(),
+            root.join("etc/apt")
+        );
+        assert!(join_within(root, Path::new("../../etc")).is_err());
+        assert!(join_within(root, Path::new("/etc")).is_err());
+    }
+}
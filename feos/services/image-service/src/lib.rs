@@ -4,25 +4,40 @@
 use crate::error::ImageServiceError;
 use feos_proto::image_service::{
     DeleteImageRequest, DeleteImageResponse, ImageInfo, ImageState, ImageStatusResponse,
-    ListImagesRequest, ListImagesResponse, PullImageRequest, PullImageResponse,
-    WatchImageStatusRequest,
+    InspectImageRequest, InspectImageResponse, ListImagesRequest, ListImagesResponse,
+    ListPinnedImagesRequest, ListPinnedImagesResponse, LoadImageRequest, LoadImageResponse,
+    PinImageRequest, PinImageResponse, PruneImagesRequest, PruneImagesResponse, PullImageRequest,
+    PullImageResponse, UnpinImageRequest, UnpinImageResponse, WatchImageStatusRequest,
 };
 use std::collections::HashMap;
 use tokio::sync::{mpsc, oneshot};
-use tonic::Status;
+use tonic::{Status, Streaming};
 pub mod api;
+pub mod archive;
+pub mod auth;
+pub mod blobstore;
 pub mod dispatcher;
 pub mod error;
 pub mod filestore;
+pub mod layerstore;
+pub mod mirror;
+pub mod pins;
+pub mod platform;
+pub mod reclaimer;
 pub mod worker;
 
 pub const IMAGE_DIR: &str = "/var/lib/feos/images";
+pub const IMAGE_BLOB_DIR: &str = "/var/lib/feos/images/blobs";
+pub const IMAGE_LAYER_DIR: &str = "/var/lib/feos/images/layers";
+pub const IMAGE_MIRROR_DIR: &str = "/var/lib/feos/images/mirror";
+pub const PINNED_IMAGES_FILE: &str = "/var/lib/feos/images/pinned.json";
 pub const IMAGE_SERVICE_SOCKET: &str = "/var/lib/feos/image_service.sock";
 
 #[derive(Debug, Clone)]
 pub struct ImageStateEvent {
     pub image_uuid: String,
     pub state: ImageState,
+    pub progress_percent: u32,
     pub message: String,
 }
 
@@ -44,16 +59,44 @@ pub enum Command {
         DeleteImageRequest,
         oneshot::Sender<Result<DeleteImageResponse, ImageServiceError>>,
     ),
+    InspectImage(
+        InspectImageRequest,
+        oneshot::Sender<Result<InspectImageResponse, ImageServiceError>>,
+    ),
+    LoadImage(
+        Box<Streaming<LoadImageRequest>>,
+        oneshot::Sender<Result<LoadImageResponse, ImageServiceError>>,
+    ),
+    PinImage(
+        PinImageRequest,
+        oneshot::Sender<Result<PinImageResponse, ImageServiceError>>,
+    ),
+    UnpinImage(
+        UnpinImageRequest,
+        oneshot::Sender<Result<UnpinImageResponse, ImageServiceError>>,
+    ),
+    ListPinnedImages(
+        ListPinnedImagesRequest,
+        oneshot::Sender<Result<ListPinnedImagesResponse, ImageServiceError>>,
+    ),
+    PruneImages(
+        PruneImagesRequest,
+        oneshot::Sender<Result<PruneImagesResponse, ImageServiceError>>,
+    ),
 }
 
 #[derive(Debug)]
 pub struct PulledLayer {
     pub media_type: String,
-    pub data: Vec<u8>,
+    pub digest: String,
+    /// The content of the layer, or `None` if the store already has a blob
+    /// or unpacked layer of this digest and it was not downloaded again.
+    pub data: Option<Vec<u8>>,
 }
 
 #[derive(Debug)]
 pub struct PulledImageData {
+    pub config_digest: String,
     pub config: Vec<u8>,
     pub layers: Vec<PulledLayer>,
 }
@@ -69,10 +112,31 @@ pub enum OrchestratorCommand {
         image_ref: String,
         image_data: PulledImageData,
     },
+    /// Sent once the FileStore has stored a pulled image, including the
+    /// copy of a VM disk done in the background.
+    FinishStore {
+        image_uuid: String,
+        result: Result<u64, std::io::Error>,
+    },
     FailPull {
         image_uuid: String,
         error: ImageServiceError,
     },
+    LoadImage {
+        stream: Box<Streaming<LoadImageRequest>>,
+        responder: oneshot::Sender<Result<LoadImageResponse, ImageServiceError>>,
+    },
+    /// Sent once the archive of a loaded image is received and read.
+    FinalizeLoad {
+        image_ref: String,
+        image_data: PulledImageData,
+        responder: oneshot::Sender<Result<LoadImageResponse, ImageServiceError>>,
+    },
+    UpdatePullProgress {
+        image_uuid: String,
+        progress_percent: u32,
+        message: String,
+    },
     WatchImageStatus {
         image_uuid: String,
         stream_sender: mpsc::Sender<Result<ImageStatusResponse, Status>>,
@@ -84,6 +148,25 @@ pub enum OrchestratorCommand {
         image_uuid: String,
         responder: oneshot::Sender<Result<DeleteImageResponse, ImageServiceError>>,
     },
+    InspectImage {
+        image_uuid: String,
+        responder: oneshot::Sender<Result<InspectImageResponse, ImageServiceError>>,
+    },
+    PinImage {
+        image_ref: String,
+        responder: oneshot::Sender<Result<PinImageResponse, ImageServiceError>>,
+    },
+    UnpinImage {
+        image_ref: String,
+        responder: oneshot::Sender<Result<UnpinImageResponse, ImageServiceError>>,
+    },
+    ListPinnedImages {
+        responder: oneshot::Sender<Result<ListPinnedImagesResponse, ImageServiceError>>,
+    },
+    PruneImages {
+        dry_run: bool,
+        responder: oneshot::Sender<Result<PruneImagesResponse, ImageServiceError>>,
+    },
 }
 
 #[derive(Debug)]
@@ -92,13 +175,22 @@ pub enum FileCommand {
         image_uuid: String,
         image_ref: String,
         image_data: PulledImageData,
-        responder: oneshot::Sender<Result<(), std::io::Error>>,
+        /// Receives `UpdatePullProgress` while a VM disk is copied.
+        progress_tx: mpsc::Sender<OrchestratorCommand>,
+        responder: oneshot::Sender<Result<u64, std::io::Error>>,
     },
     DeleteImage {
         image_uuid: String,
         responder: oneshot::Sender<Result<(), std::io::Error>>,
     },
+    ReadImageConfig {
+        image_uuid: String,
+        responder: oneshot::Sender<Result<String, std::io::Error>>,
+    },
     ScanExistingImages {
         responder: oneshot::Sender<HashMap<String, ImageInfo>>,
     },
+    /// Removes leftovers of interrupted pulls along with unused blobs and
+    /// layers. Used to free space when the host filesystem runs full.
+    CollectGarbage { responder: oneshot::Sender<()> },
 }
@@ -0,0 +1,409 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    blobstore::{matches_digest, BlobStore},
+    error::ImageServiceError,
+};
+use futures::stream;
+use http_body_util::{combinators::UnsyncBoxBody, BodyExt, Empty, Full, StreamBody};
+use hyper::body::{Bytes, Frame, Incoming};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use log::{debug, info, warn};
+use oci_distribution::{
+    client::ClientConfig, manifest, manifest::OciDescriptor, secrets::RegistryAuth, Client,
+    Reference, RegistryOperation,
+};
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::fs;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpListener;
+
+const MANIFEST_MEDIA_TYPES: &[&str] = &[
+    manifest::OCI_IMAGE_MEDIA_TYPE,
+    manifest::OCI_IMAGE_INDEX_MEDIA_TYPE,
+    manifest::IMAGE_MANIFEST_MEDIA_TYPE,
+    manifest::IMAGE_MANIFEST_LIST_MEDIA_TYPE,
+];
+const DIGEST_HEADER: &str = "Docker-Content-Digest";
+const READ_CHUNK_SIZE: usize = 1 << 20;
+
+type Body = UnsyncBoxBody<Bytes, io::Error>;
+
+/// The reference of an image on a registry mirror, which serves the
+/// repositories of all registries below the registry's host name, e.g.
+/// `docker.io/library/alpine`.
+pub fn mirror_reference(mirror: &str, reference: &Reference) -> Reference {
+    let repository = format!("{}/{}", reference.registry(), reference.repository());
+    match reference.digest() {
+        Some(digest) => Reference::with_digest(mirror.to_string(), repository, digest.to_string()),
+        None => Reference::with_tag(
+            mirror.to_string(),
+            repository,
+            reference.tag().unwrap_or("latest").to_string(),
+        ),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Route {
+    /// The version check clients start with.
+    Base,
+    Manifest {
+        name: String,
+        reference: String,
+    },
+    Blob {
+        name: String,
+        digest: String,
+    },
+}
+
+fn parse_route(path: &str) -> Option<Route> {
+    let path = path.strip_prefix("/v2/")?;
+    if path.is_empty() {
+        return Some(Route::Base);
+    }
+    if let Some((name, reference)) = path.rsplit_once("/manifests/") {
+        return Some(Route::Manifest {
+            name: name.to_string(),
+            reference: reference.to_string(),
+        });
+    }
+    let (name, digest) = path.rsplit_once("/blobs/")?;
+    Some(Route::Blob {
+        name: name.to_string(),
+        digest: digest.to_string(),
+    })
+}
+
+fn is_digest(reference: &str) -> bool {
+    reference.contains(':')
+}
+
+/// The image on its registry a mirrored repository stands for.
+fn upstream_reference(name: &str, reference: &str) -> Result<Reference, ImageServiceError> {
+    let separator = if is_digest(reference) { '@' } else { ':' };
+    Ok(Reference::try_from(format!(
+        "{name}{separator}{reference}"
+    ))?)
+}
+
+/// The media type of a manifest, which registries return along with it.
+fn manifest_media_type(data: &[u8]) -> String {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Probe {
+        media_type: Option<String>,
+        manifests: Option<serde_json::Value>,
+    }
+    match serde_json::from_slice::<Probe>(data) {
+        Ok(Probe {
+            media_type: Some(media_type),
+            ..
+        }) => media_type,
+        Ok(Probe {
+            manifests: Some(_), ..
+        }) => manifest::OCI_IMAGE_INDEX_MEDIA_TYPE.to_string(),
+        _ => manifest::OCI_IMAGE_MEDIA_TYPE.to_string(),
+    }
+}
+
+fn full(data: impl Into<Bytes>) -> Body {
+    Full::new(data.into())
+        .map_err(|never| match never {})
+        .boxed_unsync()
+}
+
+fn empty() -> Body {
+    Empty::new().map_err(|never| match never {}).boxed_unsync()
+}
+
+fn error_response(status: StatusCode, code: &str, message: &str) -> Response<Body> {
+    let body = serde_json::json!({ "errors": [{ "code": code, "message": message }] });
+    let mut response = Response::new(full(body.to_string()));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, "application/json".parse().unwrap());
+    response
+}
+
+fn failure_response(err: &ImageServiceError) -> Response<Body> {
+    let (status, code) = match err {
+        ImageServiceError::OciParse(_) => (StatusCode::BAD_REQUEST, "NAME_INVALID"),
+        ImageServiceError::OciPull(_) => (StatusCode::BAD_GATEWAY, "UNKNOWN"),
+        _ => (StatusCode::INTERNAL_SERVER_ERROR, "UNKNOWN"),
+    };
+    error_response(status, code, &err.to_string())
+}
+
+/// A pull-through cache serving the images of container registries to other
+/// FeOS hosts, e.g. of the same rack, so each image is pulled over the WAN
+/// once. Blobs are kept by digest apart from the images of this host, so
+/// deleting those does not empty the cache. Tags are looked up on the
+/// registry on every pull and served from the cache while it is unreachable.
+pub struct RegistryMirror {
+    root: PathBuf,
+    blobs: BlobStore,
+    client: Client,
+}
+
+impl RegistryMirror {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        Self {
+            blobs: BlobStore::new(root.join("blobs")),
+            root,
+            client: Client::new(ClientConfig::default()),
+        }
+    }
+
+    fn tag_path(&self, reference: &Reference) -> PathBuf {
+        self.root
+            .join("tags")
+            .join(reference.registry())
+            .join(reference.repository())
+            .join(reference.tag().unwrap_or("latest"))
+    }
+
+    async fn cached_manifest(&self, digest: &str) -> Option<Vec<u8>> {
+        let path = self.blobs.blob_path(digest).ok()?;
+        fs::read(path).await.ok()
+    }
+
+    /// The manifest `reference` of a repository and its digest.
+    async fn manifest(
+        &self,
+        name: &str,
+        reference: &str,
+    ) -> Result<(Vec<u8>, String), ImageServiceError> {
+        let upstream = upstream_reference(name, reference)?;
+        if is_digest(reference) {
+            if let Some(data) = self.cached_manifest(reference).await {
+                return Ok((data, reference.to_string()));
+            }
+        }
+
+        let pulled = self
+            .client
+            .pull_manifest_raw(&upstream, &RegistryAuth::Anonymous, MANIFEST_MEDIA_TYPES)
+            .await;
+        let (data, digest) = match pulled {
+            Ok(pulled) => pulled,
+            Err(e) if !is_digest(reference) => {
+                let cached = match fs::read_to_string(self.tag_path(&upstream)).await {
+                    Ok(digest) => self
+                        .cached_manifest(&digest)
+                        .await
+                        .map(|data| (data, digest)),
+                    Err(_) => None,
+                };
+                let Some(cached) = cached else {
+                    return Err(e.into());
+                };
+                warn!("RegistryMirror: Serving cached {upstream}, the registry failed: {e}");
+                return Ok(cached);
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if !matches_digest(&digest, &data) {
+            return Err(ImageServiceError::DigestMismatch(format!(
+                "Manifest of {upstream} does not match its digest {digest}"
+            )));
+        }
+
+        self.blobs.put(&digest, &data).await?;
+        if !is_digest(reference) {
+            let tag_path = self.tag_path(&upstream);
+            if let Some(parent) = tag_path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            fs::write(&tag_path, &digest).await?;
+        }
+        Ok((data, digest))
+    }
+
+    /// The path of a blob of a repository, which is pulled first unless
+    /// cached.
+    async fn blob(&self, name: &str, digest: &str) -> Result<PathBuf, ImageServiceError> {
+        let path = self.blobs.blob_path(digest)?;
+        if fs::try_exists(&path).await? {
+            return Ok(path);
+        }
+
+        let upstream = upstream_reference(name, digest)?;
+        info!("RegistryMirror: Pulling blob {digest} of {upstream}");
+        self.client
+            .auth(&upstream, &RegistryAuth::Anonymous, RegistryOperation::Pull)
+            .await?;
+        let descriptor = OciDescriptor {
+            digest: digest.to_string(),
+            ..Default::default()
+        };
+        let mut data = Vec::new();
+        self.client
+            .pull_blob(&upstream, &descriptor, &mut data)
+            .await?;
+        if !matches_digest(digest, &data) {
+            return Err(ImageServiceError::DigestMismatch(format!(
+                "Blob of {upstream} does not match its digest {digest}"
+            )));
+        }
+        Ok(self.blobs.put(digest, &data).await?)
+    }
+
+    async fn handle(&self, request: Request<Incoming>) -> Response<Body> {
+        let head = request.method() == Method::HEAD;
+        if request.method() != Method::GET && !head {
+            return error_response(
+                StatusCode::METHOD_NOT_ALLOWED,
+                "UNSUPPORTED",
+                "The mirror is read-only",
+            );
+        }
+
+        let response = match parse_route(request.uri().path()) {
+            None => {
+                return error_response(StatusCode::NOT_FOUND, "NAME_UNKNOWN", "Unknown path");
+            }
+            Some(Route::Base) => return Response::new(full("{}")),
+            Some(Route::Manifest { name, reference }) => self
+                .manifest(&name, &reference)
+                .await
+                .and_then(|(data, digest)| {
+                    let response = Response::builder()
+                        .header(CONTENT_TYPE, manifest_media_type(&data))
+                        .header(CONTENT_LENGTH, data.len())
+                        .header(DIGEST_HEADER, digest);
+                    let body = if head { empty() } else { full(data) };
+                    response.body(body).map_err(invalid_response)
+                }),
+            Some(Route::Blob { name, digest }) => match self.blob(&name, &digest).await {
+                Ok(path) => blob_response(path, &digest, head).await,
+                Err(e) => Err(e),
+            },
+        };
+        response.unwrap_or_else(|e| {
+            warn!("RegistryMirror: Failed to serve {}: {e}", request.uri());
+            failure_response(&e)
+        })
+    }
+}
+
+fn invalid_response(err: hyper::http::Error) -> ImageServiceError {
+    ImageServiceError::Internal(format!("Invalid response: {err}"))
+}
+
+/// Streams a blob, which may be a disk image too large to be read at once.
+async fn blob_response(
+    path: PathBuf,
+    digest: &str,
+    head: bool,
+) -> Result<Response<Body>, ImageServiceError> {
+    let file = fs::File::open(&path).await?;
+    let size = file.metadata().await?.len();
+    let response = Response::builder()
+        .header(CONTENT_TYPE, "application/octet-stream")
+        .header(CONTENT_LENGTH, size)
+        .header(DIGEST_HEADER, digest);
+    if head {
+        return response.body(empty()).map_err(invalid_response);
+    }
+    let chunks = stream::try_unfold(file, |mut file| async move {
+        let mut chunk = vec![0u8; READ_CHUNK_SIZE];
+        let n = file.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        chunk.truncate(n);
+        Ok(Some((Frame::data(Bytes::from(chunk)), file)))
+    });
+    response
+        .body(StreamBody::new(chunks).boxed_unsync())
+        .map_err(invalid_response)
+}
+
+/// Serves the mirror over plain HTTP on `addr` until listening fails.
+pub async fn serve(addr: SocketAddr, mirror: RegistryMirror) -> io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("RegistryMirror: Listening on {addr}");
+    let mirror = Arc::new(mirror);
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("RegistryMirror: Failed to accept connection: {e}");
+                continue;
+            }
+        };
+        let mirror = mirror.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |request| {
+                let mirror = mirror.clone();
+                async move { Ok::<_, Infallible>(mirror.handle(request).await) }
+            });
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                debug!("RegistryMirror: Connection from {peer} failed: {e}");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mirrored_repositories_map_to_their_registry() {
+        let reference = Reference::try_from("alpine:3.20".to_string()).unwrap();
+        let mirrored = mirror_reference("[fd00::1]:5000", &reference);
+        assert_eq!(
+            mirrored.whole(),
+            "[fd00::1]:5000/docker.io/library/alpine:3.20"
+        );
+
+        let route = parse_route("/v2/docker.io/library/alpine/manifests/3.20").unwrap();
+        let Route::Manifest { name, reference } = route else {
+            panic!("not a manifest route: {route:?}");
+        };
+        let upstream = upstream_reference(&name, &reference).unwrap();
+        assert_eq!(upstream.whole(), "docker.io/library/alpine:3.20");
+
+        assert_eq!(parse_route("/v2/"), Some(Route::Base));
+        assert_eq!(
+            parse_route("/v2/ghcr.io/ironcore-dev/os/blobs/sha256:0a1b"),
+            Some(Route::Blob {
+                name: "ghcr.io/ironcore-dev/os".to_string(),
+                digest: "sha256:0a1b".to_string(),
+            })
+        );
+        assert_eq!(parse_route("/v1/_catalog"), None);
+    }
+
+    #[test]
+    fn manifests_are_served_with_their_media_type() {
+        let docker = br#"{"schemaVersion":2,"mediaType":"application/vnd.docker.distribution.manifest.v2+json"}"#;
+        assert_eq!(
+            manifest_media_type(docker),
+            manifest::IMAGE_MANIFEST_MEDIA_TYPE
+        );
+        let index = br#"{"schemaVersion":2,"manifests":[]}"#;
+        assert_eq!(
+            manifest_media_type(index),
+            manifest::OCI_IMAGE_INDEX_MEDIA_TYPE
+        );
+    }
+}
@@ -0,0 +1,93 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/// A pinned image reference and the image kept for it. Only the reference
+/// and the UUID are kept across restarts, the status is that of the current
+/// pull.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pin {
+    pub image_ref: String,
+    pub image_uuid: String,
+    #[serde(skip)]
+    pub progress_percent: u32,
+    #[serde(skip)]
+    pub message: String,
+}
+
+/// The file listing the pinned image references, next to the image
+/// directories.
+pub struct PinFile {
+    path: PathBuf,
+}
+
+impl PinFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The pins, none if the file does not exist yet.
+    pub async fn load(&self) -> io::Result<Vec<Pin>> {
+        let content = match fs::read_to_string(&self.path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        serde_json::from_str(&content).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Could not parse {}: {e}", self.path.display()),
+            )
+        })
+    }
+
+    /// Replaces the pins in the file. The file is renamed into place, so a
+    /// crash leaves either the old or the new pins.
+    pub async fn save(&self, pins: &[Pin]) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(pins).map_err(io::Error::other)?;
+        let partial = partial_path(&self.path);
+        fs::write(&partial, json).await?;
+        fs::rename(&partial, &self.path).await
+    }
+}
+
+fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".partial");
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn pins_survive_a_reload_without_their_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = PinFile::new(dir.path().join("pinned.json"));
+        assert!(file.load().await.unwrap().is_empty());
+
+        let pin = Pin {
+            image_ref: "ghcr.io/ironcore-dev/os-images/gardenlinux:1877.0".to_string(),
+            image_uuid: "0b6c5b6e-7d3c-4c49-9d3e-0d4c4f3e8a11".to_string(),
+            progress_percent: 40,
+            message: "Pulled layer 1/3".to_string(),
+        };
+        file.save(std::slice::from_ref(&pin)).await.unwrap();
+
+        let loaded = file.load().await.unwrap();
+        assert_eq!(
+            loaded,
+            [Pin {
+                progress_percent: 0,
+                message: String::new(),
+                ..pin
+            }]
+        );
+        assert!(!dir.path().join("pinned.json.partial").exists());
+    }
+}
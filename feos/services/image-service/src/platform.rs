@@ -0,0 +1,59 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::error::ImageServiceError;
+use feos_utils::host::info::oci_architecture;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct ImagePlatform {
+    #[serde(default)]
+    architecture: String,
+}
+
+/// The architecture an image is built for, as named by the `architecture`
+/// field of its configuration. VM images may not name one.
+pub fn image_architecture(config: &[u8]) -> Option<String> {
+    serde_json::from_slice::<ImagePlatform>(config)
+        .ok()
+        .map(|platform| platform.architecture)
+        .filter(|architecture| !architecture.is_empty())
+}
+
+/// Rejects an image built for another architecture than the host's, before
+/// its layers are fetched or stored.
+pub fn check_architecture(config: &[u8]) -> Result<(), ImageServiceError> {
+    match image_architecture(config) {
+        Some(image) if image != oci_architecture() => Err(ImageServiceError::WrongArchitecture {
+            image,
+            host: oci_architecture().to_string(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn images_of_other_architectures_are_rejected() {
+        let host = oci_architecture();
+        let config = format!(r#"{{"architecture":"{host}","os":"linux"}}"#);
+        assert_eq!(image_architecture(config.as_bytes()).as_deref(), Some(host));
+        assert!(check_architecture(config.as_bytes()).is_ok());
+
+        let other = if host == "arm64" { "amd64" } else { "arm64" };
+        let config = format!(r#"{{"architecture":"{other}","os":"linux"}}"#);
+        assert!(matches!(
+            check_architecture(config.as_bytes()),
+            Err(ImageServiceError::WrongArchitecture { image, .. }) if image == other
+        ));
+
+        assert_eq!(
+            image_architecture(br#"{"commandLine":"console=ttyS0"}"#),
+            None
+        );
+        assert!(check_architecture(b"not json").is_ok());
+    }
+}
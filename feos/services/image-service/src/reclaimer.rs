@@ -0,0 +1,182 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Reclaiming the images no workload uses. Every VM and container gets an
+//! image of its own, which keeps its root disk, so images are left behind
+//! when a workload is not cleaned up fully, e.g. after a crash. Ready images
+//! are only deleted once no workload has used them for a grace period, so an
+//! image pulled for a workload that is being created, or ahead of one, is
+//! not deleted before the workload is recorded.
+
+use crate::OrchestratorCommand;
+use feos_proto::image_service::{ImageInfo, ImageState};
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{interval, Duration, Instant, MissedTickBehavior};
+
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(3600);
+pub const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(24 * 3600);
+
+/// Tells which images the workloads of the host use. Implemented by the
+/// daemon, which has the services of the workloads.
+#[tonic::async_trait]
+pub trait ImageReferences: Send + Sync {
+    /// The UUIDs of the images VMs and containers use.
+    async fn referenced_images(&self) -> Result<HashSet<String>, String>;
+
+    /// The UUIDs of the images disks of workloads are overlays of, which
+    /// cannot be deleted while the overlays exist.
+    async fn base_images(&self) -> Result<HashSet<String>, String>;
+}
+
+/// Which images are unused, and since when.
+pub struct Reclaimer {
+    references: Arc<dyn ImageReferences>,
+    grace_period: Duration,
+    unused_since: HashMap<String, Instant>,
+}
+
+impl Reclaimer {
+    pub fn new(references: Arc<dyn ImageReferences>, grace_period: Duration) -> Self {
+        Self {
+            references,
+            grace_period,
+            unused_since: HashMap::new(),
+        }
+    }
+
+    pub async fn referenced_images(&self) -> Result<HashSet<String>, String> {
+        self.references.referenced_images().await
+    }
+
+    pub async fn base_images(&self) -> Result<HashSet<String>, String> {
+        self.references.base_images().await
+    }
+
+    /// The images of `store` to delete at `now`. Images are unused from the
+    /// first time they are seen unreferenced, so after a restart the grace
+    /// period starts over.
+    pub fn reclaimable(
+        &mut self,
+        store: &HashMap<String, ImageInfo>,
+        referenced: &HashSet<String>,
+        pinned: &HashSet<String>,
+        now: Instant,
+    ) -> Vec<String> {
+        let unused: Vec<&ImageInfo> = store
+            .values()
+            .filter(|image| {
+                !referenced.contains(&image.image_uuid) && !pinned.contains(&image.image_uuid)
+            })
+            .collect();
+        self.unused_since
+            .retain(|image_uuid, _| unused.iter().any(|image| image.image_uuid == *image_uuid));
+
+        let mut reclaimable = Vec::new();
+        for image in unused {
+            let since = *self
+                .unused_since
+                .entry(image.image_uuid.clone())
+                .or_insert(now);
+            let reclaim = match image.state() {
+                ImageState::PullFailed => true,
+                ImageState::Ready => now.duration_since(since) >= self.grace_period,
+                _ => false,
+            };
+            if reclaim {
+                reclaimable.push(image.image_uuid.clone());
+            }
+        }
+        reclaimable.sort();
+        reclaimable
+    }
+}
+
+/// Prunes the images every `period`.
+pub async fn run(orchestrator_tx: mpsc::Sender<OrchestratorCommand>, period: Duration) {
+    let mut ticks = interval(period);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticks.tick().await;
+        let (responder, response) = oneshot::channel();
+        let cmd = OrchestratorCommand::PruneImages {
+            dry_run: false,
+            responder,
+        };
+        if orchestrator_tx.send(cmd).await.is_err() {
+            return;
+        }
+        match response.await {
+            Ok(Ok(pruned)) if !pruned.images.is_empty() => info!(
+                "Reclaimer: Deleted {} unused images, freeing {} bytes",
+                pruned.images.len(),
+                pruned.reclaimed_bytes
+            ),
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => warn!("Reclaimer: Failed to prune images: {e}"),
+            Err(_) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NoReferences;
+
+    #[tonic::async_trait]
+    impl ImageReferences for NoReferences {
+        async fn referenced_images(&self) -> Result<HashSet<String>, String> {
+            Ok(HashSet::new())
+        }
+
+        async fn base_images(&self) -> Result<HashSet<String>, String> {
+            Ok(HashSet::new())
+        }
+    }
+
+    fn image(image_uuid: &str, state: ImageState) -> (String, ImageInfo) {
+        let info = ImageInfo {
+            image_uuid: image_uuid.to_string(),
+            state: state as i32,
+            ..Default::default()
+        };
+        (image_uuid.to_string(), info)
+    }
+
+    #[test]
+    fn unused_images_are_reclaimed_after_the_grace_period() {
+        let grace_period = Duration::from_secs(60);
+        let mut reclaimer = Reclaimer::new(Arc::new(NoReferences), grace_period);
+        let store = HashMap::from([
+            image("used", ImageState::Ready),
+            image("pinned", ImageState::Ready),
+            image("unused", ImageState::Ready),
+            image("failed", ImageState::PullFailed),
+            image("pulling", ImageState::Downloading),
+        ]);
+        let referenced = HashSet::from(["used".to_string()]);
+        let pinned = HashSet::from(["pinned".to_string()]);
+        let start = Instant::now();
+
+        assert_eq!(
+            reclaimer.reclaimable(&store, &referenced, &pinned, start),
+            ["failed"]
+        );
+        assert_eq!(
+            reclaimer.reclaimable(&store, &referenced, &pinned, start + grace_period),
+            ["failed", "unused"]
+        );
+
+        // Used again in the meantime, the grace period starts over.
+        let used = HashSet::from(["used".to_string(), "unused".to_string()]);
+        reclaimer.reclaimable(&store, &used, &pinned, start + grace_period);
+        assert_eq!(
+            reclaimer.reclaimable(&store, &referenced, &pinned, start + 2 * grace_period),
+            ["failed"]
+        );
+    }
+}
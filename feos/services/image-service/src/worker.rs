@@ -2,18 +2,34 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    error::ImageServiceError, FileCommand, ImageStateEvent, OrchestratorCommand, PulledImageData,
-    PulledLayer,
+    archive, auth,
+    blobstore::matches_digest,
+    error::ImageServiceError,
+    filestore, mirror,
+    pins::{Pin, PinFile},
+    platform,
+    reclaimer::Reclaimer,
+    FileCommand, ImageStateEvent, OrchestratorCommand, PulledImageData, PulledLayer,
+    PINNED_IMAGES_FILE,
 };
 use feos_proto::image_service::{
-    DeleteImageResponse, ImageInfo, ImageState, ImageStatusResponse, ListImagesResponse,
-    PullImageResponse,
+    load_image_request, DeleteImageResponse, ImageInfo, ImageState, ImageStatusResponse,
+    InspectImageResponse, ListImagesResponse, ListPinnedImagesResponse, LoadImageRequest,
+    LoadImageResponse, PinImageResponse, PinnedImage, PruneImagesResponse, PullImageResponse,
+    UnpinImageResponse,
 };
+use feos_utils::network::nat64;
 use log::{error, info, warn};
-use oci_distribution::{client::ClientConfig, manifest, secrets::RegistryAuth, Client, Reference};
-use std::collections::HashMap;
+use oci_distribution::{
+    client::{ClientConfig, ClientProtocol},
+    manifest::{self, OciDescriptor},
+    secrets::RegistryAuth,
+    Client, Reference,
+};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use tokio::sync::{broadcast, mpsc, oneshot};
-use tonic::Status;
+use tonic::{Status, Streaming};
 use uuid::Uuid;
 
 const SQUASHFS_MEDIA_TYPE: &str = "application/vnd.ironcore.image.squashfs.v1alpha1.squashfs";
@@ -27,10 +43,23 @@ pub struct Orchestrator {
     broadcast_tx: broadcast::Sender<ImageStateEvent>,
     filestore_tx: mpsc::Sender<FileCommand>,
     store: HashMap<String, ImageInfo>,
+    /// The `host:port` of a registry mirror images are pulled through.
+    mirror: Option<String>,
+    /// The file with credentials of private registries, see `auth`.
+    auth_file: Option<PathBuf>,
+    pins: Vec<Pin>,
+    pin_file: PinFile,
+    /// Tells which images are unused.
+    reclaimer: Reclaimer,
 }
 
 impl Orchestrator {
-    pub fn new(filestore_tx: mpsc::Sender<FileCommand>) -> Self {
+    pub fn new(
+        filestore_tx: mpsc::Sender<FileCommand>,
+        mirror: Option<String>,
+        auth_file: Option<PathBuf>,
+        reclaimer: Reclaimer,
+    ) -> Self {
         let (command_tx, command_rx) = mpsc::channel(32);
         let (broadcast_tx, _) = broadcast::channel(32);
         Self {
@@ -39,6 +68,11 @@ impl Orchestrator {
             broadcast_tx,
             filestore_tx,
             store: HashMap::new(),
+            mirror,
+            auth_file,
+            pins: Vec::new(),
+            pin_file: PinFile::new(PINNED_IMAGES_FILE),
+            reclaimer,
         }
     }
 
@@ -58,6 +92,7 @@ impl Orchestrator {
                 self.store = initial_store;
             }
         }
+        self.restore_pins().await;
 
         info!("Orchestrator: Running and waiting for commands.");
         while let Some(cmd) = self.command_rx.recv().await {
@@ -71,69 +106,74 @@ impl Orchestrator {
             OrchestratorCommand::PullImage {
                 image_ref,
                 responder,
+            } => {
+                let image_uuid = self.start_pull(image_ref);
+                let _ = responder.send(Ok(PullImageResponse { image_uuid }));
+            }
+            OrchestratorCommand::FinalizePull {
+                image_uuid,
+                image_ref,
+                image_data,
+            } => {
+                info!("Orchestrator: Finalizing pull for {image_uuid}");
+                self.store_image(image_uuid, image_ref, image_data).await;
+            }
+            OrchestratorCommand::LoadImage { stream, responder } => {
+                tokio::spawn(receive_image_archive(
+                    self.command_tx.clone(),
+                    stream,
+                    responder,
+                ));
+            }
+            OrchestratorCommand::FinalizeLoad {
+                image_ref,
+                image_data,
+                responder,
             } => {
                 let image_uuid = Uuid::new_v4().to_string();
-                info!("Orchestrator: Start pull for '{image_ref}', assigned UUID {image_uuid}");
-
+                info!(
+                    "Orchestrator: Loading '{image_ref}' from archive, assigned UUID {image_uuid}"
+                );
                 self.store.insert(
                     image_uuid.clone(),
                     ImageInfo {
                         image_uuid: image_uuid.clone(),
                         image_ref: image_ref.clone(),
                         state: ImageState::Downloading as i32,
+                        size_bytes: 0,
+                        architecture: String::new(),
+                        pinned: false,
                     },
                 );
                 self.broadcast_state_change(
                     image_uuid.clone(),
                     ImageState::Downloading,
-                    "Pull initiated".to_string(),
+                    "Archive received".to_string(),
                 );
-
-                let _ = responder.send(Ok(PullImageResponse {
+                let _ = responder.send(Ok(LoadImageResponse {
                     image_uuid: image_uuid.clone(),
+                    image_ref: image_ref.clone(),
                 }));
-
-                tokio::spawn(pull_oci_image(
-                    self.command_tx.clone(),
-                    image_uuid,
-                    image_ref,
-                ));
+                self.store_image(image_uuid, image_ref, image_data).await;
             }
-            OrchestratorCommand::FinalizePull {
-                image_uuid,
-                image_ref,
-                image_data,
-            } => {
-                info!("Orchestrator: Finalizing pull for {image_uuid}");
-                let (responder, resp_rx) = oneshot::channel();
-                let file_cmd = FileCommand::StoreImage {
-                    image_uuid: image_uuid.clone(),
-                    image_ref,
-                    image_data,
-                    responder,
-                };
-
-                if self.filestore_tx.send(file_cmd).await.is_err() {
-                    let err_msg = "Failed to send StoreImage command to FileStore.";
-                    error!("Orchestrator: {err_msg}");
-                    self.update_and_broadcast_state(
-                        image_uuid,
-                        ImageState::PullFailed,
-                        err_msg.to_string(),
-                    );
+            OrchestratorCommand::FinishStore { image_uuid, result } => {
+                if !self.store.contains_key(&image_uuid) {
+                    info!("Orchestrator: Image {image_uuid} was deleted while it was stored");
                     return;
                 }
-
-                match resp_rx.await {
-                    Ok(Ok(())) => {
-                        info!("Orchestrator: FileStore successfully stored image {image_uuid}");
+                match result {
+                    Ok(size_bytes) => {
+                        info!("Orchestrator: FileStore successfully stored image {image_uuid} ({size_bytes} bytes)");
+                        if let Some(info) = self.store.get_mut(&image_uuid) {
+                            info.size_bytes = size_bytes;
+                        }
                         self.update_and_broadcast_state(
                             image_uuid,
                             ImageState::Ready,
                             "Image is ready".to_string(),
                         );
                     }
-                    Ok(Err(e)) => {
+                    Err(e) => {
                         let err_msg = format!("FileStore failed to store image: {e}");
                         error!("Orchestrator: {err_msg} ({image_uuid})");
                         self.update_and_broadcast_state(
@@ -142,15 +182,6 @@ impl Orchestrator {
                             err_msg,
                         );
                     }
-                    Err(_) => {
-                        let err_msg = "FileStore actor dropped response channel.";
-                        error!("Orchestrator: {err_msg} ({image_uuid})");
-                        self.update_and_broadcast_state(
-                            image_uuid,
-                            ImageState::PullFailed,
-                            err_msg.to_string(),
-                        );
-                    }
                 }
             }
             OrchestratorCommand::FailPull { image_uuid, error } => {
@@ -158,37 +189,122 @@ impl Orchestrator {
                 error!("Orchestrator: {err_msg} ({image_uuid})");
                 self.update_and_broadcast_state(image_uuid, ImageState::PullFailed, err_msg);
             }
+            OrchestratorCommand::UpdatePullProgress {
+                image_uuid,
+                progress_percent,
+                message,
+            } => {
+                let is_downloading = self
+                    .store
+                    .get(&image_uuid)
+                    .is_some_and(|info| info.state == ImageState::Downloading as i32);
+                if is_downloading {
+                    self.broadcast_event(ImageStateEvent {
+                        image_uuid,
+                        state: ImageState::Downloading,
+                        progress_percent,
+                        message,
+                    });
+                }
+            }
             OrchestratorCommand::ListImages { responder } => {
-                let images = self.store.values().cloned().collect();
+                let images = self
+                    .store
+                    .values()
+                    .map(|image| self.with_pinned(image.clone()))
+                    .collect();
                 let _ = responder.send(Ok(ListImagesResponse { images }));
             }
             OrchestratorCommand::DeleteImage {
                 image_uuid,
                 responder,
             } => {
-                info!("Orchestrator: Deleting image {image_uuid}");
-                self.store.remove(&image_uuid);
-
-                let (file_resp_tx, file_resp_rx) = oneshot::channel();
-                let file_cmd = FileCommand::DeleteImage {
-                    image_uuid: image_uuid.clone(),
-                    responder: file_resp_tx,
+                if let Some(pin) = self.pin_of(&image_uuid) {
+                    let _ = responder.send(Err(ImageServiceError::Pinned(pin.image_ref.clone())));
+                    return;
+                }
+                match self.reclaimer.base_images().await {
+                    Ok(bases) if bases.contains(&image_uuid) => {
+                        let _ = responder.send(Err(ImageServiceError::OverlayBase(image_uuid)));
+                        return;
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        let _ = responder.send(Err(ImageServiceError::References(e)));
+                        return;
+                    }
+                }
+                self.delete_image(image_uuid).await;
+                let _ = responder.send(Ok(DeleteImageResponse {}));
+            }
+            OrchestratorCommand::InspectImage {
+                image_uuid,
+                responder,
+            } => {
+                let Some(image) = self
+                    .store
+                    .get(&image_uuid)
+                    .cloned()
+                    .map(|image| self.with_pinned(image))
+                else {
+                    let _ = responder.send(Err(ImageServiceError::NotFound(image_uuid)));
+                    return;
                 };
 
-                if self.filestore_tx.send(file_cmd).await.is_err() {
-                    error!("Orchestrator: Failed to send DeleteImage command to FileStore.");
-                } else if let Ok(Err(e)) = file_resp_rx.await {
-                    if e.kind() != std::io::ErrorKind::NotFound {
-                        warn!("Orchestrator: FileStore failed to delete {image_uuid}: {e}");
+                let mut config_json = String::new();
+                if image.state == ImageState::Ready as i32 {
+                    let (file_resp_tx, file_resp_rx) = oneshot::channel();
+                    let file_cmd = FileCommand::ReadImageConfig {
+                        image_uuid: image_uuid.clone(),
+                        responder: file_resp_tx,
+                    };
+                    if self.filestore_tx.send(file_cmd).await.is_err() {
+                        let _ = responder.send(Err(ImageServiceError::Internal(
+                            "Failed to send ReadImageConfig command to FileStore.".to_string(),
+                        )));
+                        return;
+                    }
+                    match file_resp_rx.await {
+                        Ok(Ok(config)) => config_json = config,
+                        Ok(Err(e)) => {
+                            warn!("Orchestrator: Could not read config for {image_uuid}: {e}");
+                        }
+                        Err(_) => {
+                            let _ = responder.send(Err(ImageServiceError::Internal(
+                                "FileStore actor dropped response channel.".to_string(),
+                            )));
+                            return;
+                        }
                     }
                 }
 
-                self.broadcast_state_change(
-                    image_uuid,
-                    ImageState::NotFound,
-                    "Image deleted".to_string(),
-                );
-                let _ = responder.send(Ok(DeleteImageResponse {}));
+                let _ = responder.send(Ok(InspectImageResponse {
+                    image: Some(image),
+                    config_json,
+                }));
+            }
+            OrchestratorCommand::PinImage {
+                image_ref,
+                responder,
+            } => {
+                let result = self.pin_image(image_ref).await;
+                let _ = responder.send(result.map(|pinned_image| PinImageResponse {
+                    pinned_image: Some(pinned_image),
+                }));
+            }
+            OrchestratorCommand::UnpinImage {
+                image_ref,
+                responder,
+            } => {
+                let result = self.unpin_image(image_ref).await;
+                let _ = responder.send(result.map(|()| UnpinImageResponse {}));
+            }
+            OrchestratorCommand::ListPinnedImages { responder } => {
+                let pinned_images = self.pins.iter().map(|pin| self.pinned_image(pin)).collect();
+                let _ = responder.send(Ok(ListPinnedImagesResponse { pinned_images }));
+            }
+            OrchestratorCommand::PruneImages { dry_run, responder } => {
+                let _ = responder.send(self.prune_images(dry_run).await);
             }
             OrchestratorCommand::WatchImageStatus {
                 image_uuid,
@@ -210,6 +326,273 @@ impl Orchestrator {
         }
     }
 
+    async fn delete_image(&mut self, image_uuid: String) {
+        info!("Orchestrator: Deleting image {image_uuid}");
+        self.store.remove(&image_uuid);
+
+        let (file_resp_tx, file_resp_rx) = oneshot::channel();
+        let file_cmd = FileCommand::DeleteImage {
+            image_uuid: image_uuid.clone(),
+            responder: file_resp_tx,
+        };
+
+        if self.filestore_tx.send(file_cmd).await.is_err() {
+            error!("Orchestrator: Failed to send DeleteImage command to FileStore.");
+        } else if let Ok(Err(e)) = file_resp_rx.await {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("Orchestrator: FileStore failed to delete {image_uuid}: {e}");
+            }
+        }
+
+        self.broadcast_state_change(
+            image_uuid,
+            ImageState::NotFound,
+            "Image deleted".to_string(),
+        );
+    }
+
+    /// Deletes the images no workload uses, see `Reclaimer`.
+    async fn prune_images(
+        &mut self,
+        dry_run: bool,
+    ) -> Result<PruneImagesResponse, ImageServiceError> {
+        let mut referenced = self
+            .reclaimer
+            .referenced_images()
+            .await
+            .map_err(ImageServiceError::References)?;
+        referenced.extend(
+            self.reclaimer
+                .base_images()
+                .await
+                .map_err(ImageServiceError::References)?,
+        );
+        let pinned: HashSet<String> = self.pins.iter().map(|pin| pin.image_uuid.clone()).collect();
+        let reclaimable = self.reclaimer.reclaimable(
+            &self.store,
+            &referenced,
+            &pinned,
+            tokio::time::Instant::now(),
+        );
+
+        let mut response = PruneImagesResponse::default();
+        for image_uuid in reclaimable {
+            let Some(image) = self.store.get(&image_uuid).cloned() else {
+                continue;
+            };
+            if !dry_run {
+                self.delete_image(image_uuid).await;
+            }
+            response.reclaimed_bytes += image.size_bytes;
+            response.images.push(image);
+        }
+        Ok(response)
+    }
+
+    /// Adds an image to the store and pulls it in the background.
+    fn start_pull(&mut self, image_ref: String) -> String {
+        let image_uuid = Uuid::new_v4().to_string();
+        info!("Orchestrator: Start pull for '{image_ref}', assigned UUID {image_uuid}");
+
+        self.store.insert(
+            image_uuid.clone(),
+            ImageInfo {
+                image_uuid: image_uuid.clone(),
+                image_ref: image_ref.clone(),
+                state: ImageState::Downloading as i32,
+                size_bytes: 0,
+                architecture: String::new(),
+                pinned: false,
+            },
+        );
+        self.broadcast_state_change(
+            image_uuid.clone(),
+            ImageState::Downloading,
+            "Pull initiated".to_string(),
+        );
+
+        tokio::spawn(pull_oci_image(
+            self.command_tx.clone(),
+            image_uuid.clone(),
+            image_ref,
+            self.mirror.clone(),
+            self.auth_file.clone(),
+        ));
+        image_uuid
+    }
+
+    /// Loads the pins and pulls the pinned images that are missing, e.g.
+    /// because FeOS stopped during their pull.
+    async fn restore_pins(&mut self) {
+        self.pins = match self.pin_file.load().await {
+            Ok(pins) => pins,
+            Err(e) => {
+                error!("Orchestrator: Failed to load pinned images: {e}");
+                return;
+            }
+        };
+        let missing: Vec<String> = self
+            .pins
+            .iter()
+            .filter(|pin| !self.store.contains_key(&pin.image_uuid))
+            .map(|pin| pin.image_ref.clone())
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+        for image_ref in missing {
+            info!("Orchestrator: Pinned image '{image_ref}' is missing, pulling it again");
+            let image_uuid = self.start_pull(image_ref.clone());
+            self.set_pin(image_ref, image_uuid);
+        }
+        self.save_pins().await;
+    }
+
+    /// Keeps the image of `image_ref` that is ready or being pulled, or
+    /// pulls it.
+    async fn pin_image(&mut self, image_ref: String) -> Result<PinnedImage, ImageServiceError> {
+        Reference::try_from(image_ref.clone())?;
+        let usable = |image: &ImageInfo| {
+            image.state == ImageState::Ready as i32 || image.state == ImageState::Downloading as i32
+        };
+        let pinned = self
+            .pins
+            .iter()
+            .find(|pin| pin.image_ref == image_ref)
+            .and_then(|pin| self.store.get(&pin.image_uuid))
+            .filter(|image| usable(image));
+        let existing = pinned.or_else(|| {
+            self.store
+                .values()
+                .filter(|image| image.image_ref == image_ref && usable(image))
+                .max_by_key(|image| image.state == ImageState::Ready as i32)
+        });
+        let image_uuid = match existing {
+            Some(image) => image.image_uuid.clone(),
+            None => self.start_pull(image_ref.clone()),
+        };
+        info!("Orchestrator: Pinning '{image_ref}' to image {image_uuid}");
+        self.set_pin(image_ref.clone(), image_uuid);
+        self.pin_file.save(&self.pins).await?;
+        let pin = self
+            .pins
+            .iter()
+            .find(|pin| pin.image_ref == image_ref)
+            .expect("pin was just set");
+        Ok(self.pinned_image(pin))
+    }
+
+    async fn unpin_image(&mut self, image_ref: String) -> Result<(), ImageServiceError> {
+        let Some(index) = self.pins.iter().position(|pin| pin.image_ref == image_ref) else {
+            return Err(ImageServiceError::NotPinned(image_ref));
+        };
+        info!("Orchestrator: Unpinning '{image_ref}'");
+        let pin = self.pins.remove(index);
+        if let Err(e) = self.pin_file.save(&self.pins).await {
+            self.pins.insert(index, pin);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    fn set_pin(&mut self, image_ref: String, image_uuid: String) {
+        let pin = Pin {
+            image_ref,
+            image_uuid,
+            progress_percent: 0,
+            message: String::new(),
+        };
+        match self
+            .pins
+            .iter_mut()
+            .find(|existing| existing.image_ref == pin.image_ref)
+        {
+            Some(existing) if existing.image_uuid == pin.image_uuid => {}
+            Some(existing) => *existing = pin,
+            None => self.pins.push(pin),
+        }
+    }
+
+    async fn save_pins(&self) {
+        if let Err(e) = self.pin_file.save(&self.pins).await {
+            error!("Orchestrator: Failed to save pinned images: {e}");
+        }
+    }
+
+    fn pin_of(&self, image_uuid: &str) -> Option<&Pin> {
+        self.pins.iter().find(|pin| pin.image_uuid == image_uuid)
+    }
+
+    fn with_pinned(&self, mut image: ImageInfo) -> ImageInfo {
+        image.pinned = self.pin_of(&image.image_uuid).is_some();
+        image
+    }
+
+    fn pinned_image(&self, pin: &Pin) -> PinnedImage {
+        let image = self.store.get(&pin.image_uuid);
+        let state = image.map_or(ImageState::NotFound as i32, |image| image.state);
+        PinnedImage {
+            image_ref: pin.image_ref.clone(),
+            image_uuid: pin.image_uuid.clone(),
+            state,
+            progress_percent: if state == ImageState::Ready as i32 {
+                100
+            } else {
+                pin.progress_percent
+            },
+            message: pin.message.clone(),
+            size_bytes: image.map_or(0, |image| image.size_bytes),
+        }
+    }
+
+    /// Has the FileStore store a pulled or loaded image and marks it ready
+    /// once it is done.
+    async fn store_image(
+        &mut self,
+        image_uuid: String,
+        image_ref: String,
+        image_data: PulledImageData,
+    ) {
+        if let Some(info) = self.store.get_mut(&image_uuid) {
+            info.architecture =
+                platform::image_architecture(&image_data.config).unwrap_or_default();
+        }
+        let (responder, resp_rx) = oneshot::channel();
+        let file_cmd = FileCommand::StoreImage {
+            image_uuid: image_uuid.clone(),
+            image_ref,
+            image_data,
+            progress_tx: self.command_tx.clone(),
+            responder,
+        };
+
+        if self.filestore_tx.send(file_cmd).await.is_err() {
+            let err_msg = "Failed to send StoreImage command to FileStore.";
+            error!("Orchestrator: {err_msg}");
+            self.update_and_broadcast_state(
+                image_uuid,
+                ImageState::PullFailed,
+                err_msg.to_string(),
+            );
+            return;
+        }
+
+        // Copying a VM disk can take a while, other images are served
+        // in the meantime.
+        let command_tx = self.command_tx.clone();
+        tokio::spawn(async move {
+            let result = resp_rx.await.unwrap_or_else(|_| {
+                Err(std::io::Error::other(
+                    "FileStore actor dropped response channel.",
+                ))
+            });
+            let cmd = OrchestratorCommand::FinishStore { image_uuid, result };
+            if command_tx.send(cmd).await.is_err() {
+                error!("Orchestrator: Failed to send FinishStore command. Actor may be down.");
+            }
+        });
+    }
+
     fn update_and_broadcast_state(
         &mut self,
         image_uuid: String,
@@ -222,21 +605,104 @@ impl Orchestrator {
         self.broadcast_state_change(image_uuid, new_state, message);
     }
 
-    fn broadcast_state_change(&self, image_uuid: String, state: ImageState, message: String) {
-        let event = ImageStateEvent {
+    fn broadcast_state_change(&mut self, image_uuid: String, state: ImageState, message: String) {
+        self.broadcast_event(ImageStateEvent {
             image_uuid,
             state,
+            progress_percent: if state == ImageState::Ready { 100 } else { 0 },
             message,
-        };
+        });
+    }
+
+    fn broadcast_event(&mut self, event: ImageStateEvent) {
+        if let Some(pin) = self
+            .pins
+            .iter_mut()
+            .find(|pin| pin.image_uuid == event.image_uuid)
+        {
+            pin.progress_percent = event.progress_percent;
+            pin.message = event.message.clone();
+        }
         if self.broadcast_tx.send(event).is_err() {
             info!("Orchestrator: Broadcast failed, no active listeners.");
         }
     }
 }
 
-async fn pull_oci_data(image_ref: &str) -> Result<PulledImageData, ImageServiceError> {
+async fn pull_oci_data(
+    command_tx: &mpsc::Sender<OrchestratorCommand>,
+    image_uuid: &str,
+    image_ref: &str,
+    mirror: Option<&str>,
+    auth_file: Option<&Path>,
+) -> Result<PulledImageData, ImageServiceError> {
     info!("ImagePuller: fetching image: {image_ref}");
     let reference = Reference::try_from(image_ref.to_string())?;
+    // Mirrors only serve public images, so they are pulled from anonymously
+    // and the credentials are only sent to the registry itself.
+    if let Some(mirror) = mirror {
+        let mirror = &nat64::reachable_host(mirror).await;
+        let config = ClientConfig {
+            // Mirrors are run by FeOS hosts nearby, which serve plain HTTP.
+            protocol: ClientProtocol::HttpsExcept(vec![mirror.to_string()]),
+            ..Default::default()
+        };
+        let mirrored = mirror::mirror_reference(mirror, &reference);
+        let client = Client::new(config);
+        match pull_reference(command_tx, image_uuid, &client, &mirrored, &RegistryAuth::Anonymous)
+            .await
+        {
+            Ok(image_data) => return Ok(image_data),
+            Err(e) => warn!("ImagePuller: Pull of {image_ref} through mirror {mirror} failed, pulling from the registry: {e}"),
+        }
+    }
+    let auth = auth::registry_auth(auth_file, &reference).await?;
+    pull_reference(
+        command_tx,
+        image_uuid,
+        &Client::new(ClientConfig::default()),
+        &reference,
+        &auth,
+    )
+    .await
+}
+
+/// Pulls a blob and checks it against its descriptor, as registries and
+/// mirrors in between are not trusted to serve what was asked for.
+async fn pull_verified_blob(
+    client: &Client,
+    reference: &Reference,
+    descriptor: &OciDescriptor,
+) -> Result<Vec<u8>, ImageServiceError> {
+    let mut data = Vec::new();
+    client.pull_blob(reference, descriptor, &mut data).await?;
+    if descriptor.size >= 0 && data.len() as u64 != descriptor.size as u64 {
+        return Err(ImageServiceError::DigestMismatch(format!(
+            "Blob {} of {} has {} bytes instead of the {} of its descriptor",
+            descriptor.digest,
+            reference.whole(),
+            data.len(),
+            descriptor.size
+        )));
+    }
+    if !matches_digest(&descriptor.digest, &data) {
+        return Err(ImageServiceError::DigestMismatch(format!(
+            "Blob of {} does not match its digest {}",
+            reference.whole(),
+            descriptor.digest
+        )));
+    }
+    Ok(data)
+}
+
+async fn pull_reference(
+    command_tx: &mpsc::Sender<OrchestratorCommand>,
+    image_uuid: &str,
+    client: &Client,
+    reference: &Reference,
+    auth: &RegistryAuth,
+) -> Result<PulledImageData, ImageServiceError> {
+    let image_ref = reference.whole();
 
     let accepted_media_types = [
         ROOTFS_MEDIA_TYPE,
@@ -247,48 +713,69 @@ async fn pull_oci_data(image_ref: &str) -> Result<PulledImageData, ImageServiceE
         manifest::IMAGE_DOCKER_LAYER_GZIP_MEDIA_TYPE,
     ];
 
-    let config = ClientConfig {
-        ..Default::default()
-    };
-    let client = Client::new(config);
-    let auth = &RegistryAuth::Anonymous;
-
     info!("ImagePuller: pulling manifest and config for {image_ref}");
-    let (manifest, _, _) = client.pull_manifest_and_config(&reference, auth).await?;
+    let (manifest, _, _) = client.pull_manifest_and_config(reference, auth).await?;
 
-    let mut config_data = Vec::new();
-    client
-        .pull_blob(&reference, &manifest.config, &mut config_data)
-        .await?;
+    let config_data = pull_verified_blob(client, reference, &manifest.config).await?;
     info!(
         "ImagePuller: pulled config blob {} bytes",
         config_data.len()
     );
+    platform::check_architecture(&config_data)?;
+
+    let (accepted_layers, skipped_layers): (Vec<_>, Vec<_>) = manifest
+        .layers
+        .into_iter()
+        .partition(|layer| accepted_media_types.contains(&layer.media_type.as_str()));
+    for layer in &skipped_layers {
+        warn!(
+            "ImagePuller: skipping layer with unsupported media type: {}",
+            layer.media_type
+        );
+    }
+
+    let total_bytes: u64 = accepted_layers
+        .iter()
+        .map(|layer| layer.size.max(0) as u64)
+        .sum();
+    let layer_count = accepted_layers.len();
+    let mut pulled_bytes: u64 = 0;
 
     let mut layers = Vec::new();
-    for layer in manifest.layers {
-        if !accepted_media_types.contains(&layer.media_type.as_str()) {
-            warn!(
-                "ImagePuller: skipping layer with unsupported media type: {}",
-                layer.media_type
+    for (index, layer) in accepted_layers.into_iter().enumerate() {
+        let data = if filestore::is_stored(&layer.media_type, &layer.digest).await {
+            info!("ImagePuller: layer {} is already stored", layer.digest);
+            pulled_bytes += layer.size.max(0) as u64;
+            None
+        } else {
+            info!(
+                "ImagePuller: pulling layer {} ({})",
+                layer.digest, layer.media_type
             );
-            continue;
-        }
-
-        info!(
-            "ImagePuller: pulling layer {} ({})",
-            layer.digest, layer.media_type
-        );
-
-        let mut layer_data = Vec::new();
-        client
-            .pull_blob(&reference, &layer, &mut layer_data)
-            .await?;
-        info!("ImagePuller: pulled layer blob {} bytes", layer_data.len());
+            let layer_data = pull_verified_blob(client, reference, &layer).await?;
+            info!("ImagePuller: pulled layer blob {} bytes", layer_data.len());
+            pulled_bytes += layer_data.len() as u64;
+            Some(layer_data)
+        };
         layers.push(PulledLayer {
             media_type: layer.media_type.clone(),
-            data: layer_data,
+            digest: layer.digest.clone(),
+            data,
         });
+
+        // Unpacking still follows the download, so READY is the only state reporting 100%.
+        let progress_percent = match total_bytes {
+            0 => 0,
+            total => (pulled_bytes.min(total) * 99 / total) as u32,
+        };
+        let progress = OrchestratorCommand::UpdatePullProgress {
+            image_uuid: image_uuid.to_string(),
+            progress_percent,
+            message: format!("Pulled layer {}/{layer_count}", index + 1),
+        };
+        if command_tx.send(progress).await.is_err() {
+            warn!("ImagePuller: Failed to send progress update for {image_uuid}");
+        }
     }
 
     if layers.is_empty() {
@@ -298,6 +785,7 @@ async fn pull_oci_data(image_ref: &str) -> Result<PulledImageData, ImageServiceE
     }
 
     Ok(PulledImageData {
+        config_digest: manifest.config.digest,
         config: config_data,
         layers,
     })
@@ -307,8 +795,18 @@ pub async fn pull_oci_image(
     command_tx: mpsc::Sender<OrchestratorCommand>,
     image_uuid: String,
     image_ref: String,
+    mirror: Option<String>,
+    auth_file: Option<PathBuf>,
 ) {
-    match pull_oci_data(&image_ref).await {
+    match pull_oci_data(
+        &command_tx,
+        &image_uuid,
+        &image_ref,
+        mirror.as_deref(),
+        auth_file.as_deref(),
+    )
+    .await
+    {
         Ok(image_data) => {
             let cmd = OrchestratorCommand::FinalizePull {
                 image_uuid,
@@ -331,6 +829,73 @@ pub async fn pull_oci_image(
     }
 }
 
+async fn read_image_archive(
+    stream: &mut Streaming<LoadImageRequest>,
+) -> Result<(String, PulledImageData), ImageServiceError> {
+    let upload_error = |e: Status| ImageServiceError::Upload(e.message().to_string());
+    let image_ref = match stream.message().await.map_err(upload_error)? {
+        Some(LoadImageRequest {
+            payload: Some(load_image_request::Payload::Start(start)),
+        }) => start.image_ref,
+        _ => {
+            return Err(ImageServiceError::InvalidArchive(
+                "First message must be a LoadImageStart message.".to_string(),
+            ))
+        }
+    };
+
+    let mut data = Vec::new();
+    while let Some(request) = stream.message().await.map_err(upload_error)? {
+        match request.payload {
+            Some(load_image_request::Payload::Data(chunk)) => data.extend_from_slice(&chunk),
+            _ => {
+                return Err(ImageServiceError::InvalidArchive(
+                    "Only the first message may be a LoadImageStart message.".to_string(),
+                ))
+            }
+        }
+    }
+    info!(
+        "ImageLoader: Received archive of {} bytes for '{image_ref}'",
+        data.len()
+    );
+
+    let (image_ref, image_data) =
+        tokio::task::spawn_blocking(move || archive::read_docker_archive(&data, &image_ref))
+            .await
+            .map_err(|e| {
+                ImageServiceError::Internal(format!("Reading the archive failed: {e}"))
+            })??;
+    platform::check_architecture(&image_data.config)?;
+    Ok((image_ref, image_data))
+}
+
+/// Receives the archive of an image streamed by a client and hands the image
+/// to the orchestrator to be stored. Errors are returned to the client right
+/// away, as no image exists yet.
+pub async fn receive_image_archive(
+    command_tx: mpsc::Sender<OrchestratorCommand>,
+    mut stream: Box<Streaming<LoadImageRequest>>,
+    responder: oneshot::Sender<Result<LoadImageResponse, ImageServiceError>>,
+) {
+    match read_image_archive(&mut stream).await {
+        Ok((image_ref, image_data)) => {
+            let cmd = OrchestratorCommand::FinalizeLoad {
+                image_ref,
+                image_data,
+                responder,
+            };
+            if command_tx.send(cmd).await.is_err() {
+                error!("ImageLoader: Failed to send FinalizeLoad command. Actor may be down.");
+            }
+        }
+        Err(e) => {
+            warn!("ImageLoader: Failed to load image: {e}");
+            let _ = responder.send(Err(e));
+        }
+    }
+}
+
 pub async fn watch_image_status_stream(
     image_uuid_to_watch: String,
     initial_state: ImageState,
@@ -375,11 +940,7 @@ pub async fn watch_image_status_stream(
                     );
                     let response = ImageStatusResponse {
                         state: event.state as i32,
-                        progress_percent: if event.state == ImageState::Ready {
-                            100
-                        } else {
-                            0
-                        },
+                        progress_percent: event.progress_percent,
                         message: event.message,
                     };
 
@@ -0,0 +1,16 @@
+-- SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+-- SPDX-License-Identifier: Apache-2.0
+
+CREATE TABLE IF NOT EXISTS schedules (
+    schedule_id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    -- The cron expression, evaluated in UTC.
+    cron TEXT NOT NULL,
+    -- The ScheduleAction to run, protobuf encoded.
+    action_blob BLOB NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    -- The last run, NULL until the action ran for the first time.
+    last_run_at_ms INTEGER,
+    last_run_succeeded INTEGER,
+    last_run_message TEXT
+);
@@ -0,0 +1,11 @@
+-- SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+-- SPDX-License-Identifier: Apache-2.0
+
+-- Set instead of cron for schedules that run at a fixed interval, counted
+-- from created_at.
+ALTER TABLE schedules ADD COLUMN interval_seconds INTEGER;
+
+-- The VM whose snapshot policy the schedule is, NULL for other schedules.
+ALTER TABLE schedules ADD COLUMN policy_vm_id TEXT;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_schedules_policy_vm_id ON schedules (policy_vm_id);
@@ -0,0 +1,105 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::Command;
+use feos_proto::schedule_service::{
+    schedule_service_server::ScheduleService, CreateScheduleRequest, CreateScheduleResponse,
+    DeleteScheduleRequest, DeleteScheduleResponse, DeleteVmSnapshotPolicyRequest,
+    DeleteVmSnapshotPolicyResponse, ListSchedulesRequest, ListSchedulesResponse,
+    SetVmSnapshotPolicyRequest, SetVmSnapshotPolicyResponse,
+};
+use log::info;
+use tokio::sync::{mpsc, oneshot};
+use tonic::{Request, Response, Status};
+
+pub struct ScheduleApiHandler {
+    dispatcher_tx: mpsc::Sender<Command>,
+}
+
+impl ScheduleApiHandler {
+    pub fn new(dispatcher_tx: mpsc::Sender<Command>) -> Self {
+        Self { dispatcher_tx }
+    }
+}
+
+async fn dispatch_and_wait<T, E>(
+    dispatcher: &mpsc::Sender<Command>,
+    command_constructor: impl FnOnce(oneshot::Sender<Result<T, E>>) -> Command,
+) -> Result<Response<T>, Status>
+where
+    E: Into<Status>,
+{
+    let (resp_tx, resp_rx) = oneshot::channel();
+    let cmd = command_constructor(resp_tx);
+
+    dispatcher
+        .send(cmd)
+        .await
+        .map_err(|e| Status::internal(format!("Failed to send command to dispatcher: {e}")))?;
+
+    match resp_rx.await {
+        Ok(Ok(result)) => Ok(Response::new(result)),
+        Ok(Err(e)) => Err(e.into()),
+        Err(_) => Err(Status::internal(
+            "Dispatcher task dropped response channel.",
+        )),
+    }
+}
+
+#[tonic::async_trait]
+impl ScheduleService for ScheduleApiHandler {
+    async fn create_schedule(
+        &self,
+        request: Request<CreateScheduleRequest>,
+    ) -> Result<Response<CreateScheduleResponse>, Status> {
+        info!("ScheduleApi: Received CreateSchedule request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::CreateSchedule(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn list_schedules(
+        &self,
+        request: Request<ListSchedulesRequest>,
+    ) -> Result<Response<ListSchedulesResponse>, Status> {
+        info!("ScheduleApi: Received ListSchedules request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::ListSchedules(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn delete_schedule(
+        &self,
+        request: Request<DeleteScheduleRequest>,
+    ) -> Result<Response<DeleteScheduleResponse>, Status> {
+        info!("ScheduleApi: Received DeleteSchedule request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::DeleteSchedule(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn set_vm_snapshot_policy(
+        &self,
+        request: Request<SetVmSnapshotPolicyRequest>,
+    ) -> Result<Response<SetVmSnapshotPolicyResponse>, Status> {
+        info!("ScheduleApi: Received SetVmSnapshotPolicy request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::SetVmSnapshotPolicy(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn delete_vm_snapshot_policy(
+        &self,
+        request: Request<DeleteVmSnapshotPolicyRequest>,
+    ) -> Result<Response<DeleteVmSnapshotPolicyResponse>, Status> {
+        info!("ScheduleApi: Received DeleteVmSnapshotPolicy request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::DeleteVmSnapshotPolicy(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+}
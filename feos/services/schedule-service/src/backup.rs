@@ -0,0 +1,130 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use chrono::{DateTime, Utc};
+use sqlx::{sqlite::SqliteConnection, Connection};
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/// The prefix of the directories database backups are written to.
+pub const DATABASE_BACKUP_PREFIX: &str = "feos-db-";
+
+/// The time a scheduled job started at, as used in the names of the files it
+/// writes. They sort in the order they were written.
+pub fn file_timestamp(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Copies the SQLite databases behind `database_urls` into a new directory
+/// in `directory`. The copies are consistent even while the databases are
+/// written to. Only the newest `keep` backups are kept, all if it is 0.
+pub async fn backup_databases(
+    database_urls: &[String],
+    directory: &Path,
+    started_at: DateTime<Utc>,
+    keep: u32,
+) -> io::Result<PathBuf> {
+    let name = format!("{DATABASE_BACKUP_PREFIX}{}", file_timestamp(started_at));
+    let backup_dir = directory.join(&name);
+    let partial_dir = directory.join(format!(".{name}.partial"));
+    // Left over if FeOS stopped during a backup.
+    let _ = fs::remove_dir_all(&partial_dir).await;
+    fs::create_dir_all(&partial_dir).await?;
+
+    let result = async {
+        for db_url in database_urls {
+            let Some(file_name) = db_url
+                .strip_prefix("sqlite:")
+                .and_then(|path| Path::new(path).file_name())
+            else {
+                continue;
+            };
+            let target = partial_dir.join(file_name);
+            let mut connection = SqliteConnection::connect(db_url)
+                .await
+                .map_err(io::Error::other)?;
+            sqlx::query("VACUUM INTO ?1")
+                .bind(target.to_string_lossy().into_owned())
+                .execute(&mut connection)
+                .await
+                .map_err(|e| io::Error::other(format!("Failed to back up {db_url}: {e}")))?;
+            connection.close().await.map_err(io::Error::other)?;
+        }
+        fs::rename(&partial_dir, &backup_dir).await
+    }
+    .await;
+    if let Err(e) = result {
+        let _ = fs::remove_dir_all(&partial_dir).await;
+        return Err(e);
+    }
+
+    prune(directory, DATABASE_BACKUP_PREFIX, keep).await?;
+    Ok(backup_dir)
+}
+
+/// Removes all but the newest `keep` files or directories in `directory`
+/// whose name starts with `prefix`. Keeps all if `keep` is 0.
+pub async fn prune(directory: &Path, prefix: &str, keep: u32) -> io::Result<()> {
+    if keep == 0 {
+        return Ok(());
+    }
+    let mut entries = Vec::new();
+    let mut dir = fs::read_dir(directory).await?;
+    while let Some(entry) = dir.next_entry().await? {
+        if entry.file_name().to_string_lossy().starts_with(prefix) {
+            entries.push(entry.path());
+        }
+    }
+    entries.sort();
+    let excess = entries.len().saturating_sub(keep as usize);
+    for path in &entries[..excess] {
+        if fs::metadata(path).await?.is_dir() {
+            fs::remove_dir_all(path).await?;
+        } else {
+            fs::remove_file(path).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use sqlx::Row;
+
+    #[tokio::test]
+    async fn backups_are_consistent_copies_and_pruned() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("vms.db");
+        let db_url = format!("sqlite:{}", db_path.display());
+        std::fs::File::create(&db_path).unwrap();
+        let mut connection = SqliteConnection::connect(&db_url).await.unwrap();
+        sqlx::query("CREATE TABLE vms (name TEXT); INSERT INTO vms VALUES ('a')")
+            .execute(&mut connection)
+            .await
+            .unwrap();
+
+        let backups = dir.path().join("backups");
+        let mut written = Vec::new();
+        for hour in 1..=3 {
+            let started_at = Utc.with_ymd_and_hms(2026, 10, 16, hour, 0, 0).unwrap();
+            let backup = backup_databases(&[db_url.clone()], &backups, started_at, 2)
+                .await
+                .unwrap();
+            written.push(backup);
+        }
+
+        assert!(!written[0].exists());
+        assert!(written[1].exists());
+        assert_eq!(written[2], backups.join("feos-db-20261016T030000Z"),);
+        let copy_url = format!("sqlite:{}", written[2].join("vms.db").display());
+        let mut copy = SqliteConnection::connect(&copy_url).await.unwrap();
+        let row = sqlx::query("SELECT name FROM vms")
+            .fetch_one(&mut copy)
+            .await
+            .unwrap();
+        assert_eq!(row.get::<String, _>("name"), "a");
+    }
+}
@@ -0,0 +1,274 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use chrono::{DateTime, Datelike, NaiveDate, TimeDelta, TimeZone, Timelike, Utc};
+use std::str::FromStr;
+
+/// How far ahead the next run of a schedule is searched. Expressions that
+/// match no time within it, e.g. the 30th of February, never run.
+const MAX_SEARCH_YEARS: i32 = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Invalid cron expression '{expression}': {reason}")]
+pub struct CronError {
+    expression: String,
+    reason: String,
+}
+
+struct Field {
+    name: &'static str,
+    min: u32,
+    max: u32,
+}
+
+const MINUTE: Field = Field {
+    name: "minute",
+    min: 0,
+    max: 59,
+};
+const HOUR: Field = Field {
+    name: "hour",
+    min: 0,
+    max: 23,
+};
+const DAY_OF_MONTH: Field = Field {
+    name: "day of month",
+    min: 1,
+    max: 31,
+};
+const MONTH: Field = Field {
+    name: "month",
+    min: 1,
+    max: 12,
+};
+/// Sunday is both 0 and 7.
+const DAY_OF_WEEK: Field = Field {
+    name: "day of week",
+    min: 0,
+    max: 7,
+};
+
+/// A cron expression with the fields minute, hour, day of month, month and
+/// day of week, e.g. `30 2 * * 1-5`, evaluated in UTC. Fields are `*`,
+/// values, ranges like `1-5` and steps like `*/15` or `0-30/10`, separated
+/// by commas. `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly` are
+/// accepted as well.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    /// As in other crons, a day matches if it matches either day field,
+    /// unless one of them starts with `*`.
+    any_day_of_month: bool,
+    any_day_of_week: bool,
+}
+
+fn parse_value(field: &Field, text: &str) -> Result<u32, String> {
+    match text.parse::<u32>() {
+        Ok(value) if (field.min..=field.max).contains(&value) => Ok(value),
+        _ => Err(format!(
+            "'{text}' is not a {} between {} and {}",
+            field.name, field.min, field.max
+        )),
+    }
+}
+
+/// Parses a field into a bit set of the values it matches.
+fn parse_field(field: &Field, text: &str) -> Result<u64, String> {
+    let mut values = 0;
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<usize>() {
+                Ok(step) if step > 0 => (range, Some(step)),
+                _ => return Err(format!("invalid step '{step}' in the {} field", field.name)),
+            },
+            None => (part, None),
+        };
+        let (start, end) = if range == "*" {
+            (field.min, field.max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (parse_value(field, start)?, parse_value(field, end)?)
+        } else {
+            let start = parse_value(field, range)?;
+            // A step after a single value runs to the end of the field.
+            (start, if step.is_some() { field.max } else { start })
+        };
+        if start > end {
+            return Err(format!(
+                "range '{range}' of the {} field ends before it starts",
+                field.name
+            ));
+        }
+        for value in (start..=end).step_by(step.unwrap_or(1)) {
+            values |= 1 << value;
+        }
+    }
+    Ok(values)
+}
+
+fn contains(values: u64, value: u32) -> bool {
+    values & (1 << value) != 0
+}
+
+impl FromStr for CronSchedule {
+    type Err = CronError;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let error = |reason: String| CronError {
+            expression: expression.to_string(),
+            reason,
+        };
+        let expanded = match expression.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day_of_month, month, day_of_week] = fields[..] else {
+            return Err(error(format!(
+                "expected 5 fields (minute hour day-of-month month day-of-week), got {}",
+                fields.len()
+            )));
+        };
+        let mut days_of_week = parse_field(&DAY_OF_WEEK, day_of_week).map_err(error)?;
+        if contains(days_of_week, 7) {
+            days_of_week = (days_of_week | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            minutes: parse_field(&MINUTE, minute).map_err(error)?,
+            hours: parse_field(&HOUR, hour).map_err(error)?,
+            days_of_month: parse_field(&DAY_OF_MONTH, day_of_month).map_err(error)?,
+            months: parse_field(&MONTH, month).map_err(error)?,
+            days_of_week,
+            any_day_of_month: day_of_month.starts_with('*'),
+            any_day_of_week: day_of_week.starts_with('*'),
+        })
+    }
+}
+
+impl CronSchedule {
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day_of_month = contains(self.days_of_month, date.day());
+        let day_of_week = contains(self.days_of_week, date.weekday().num_days_from_sunday());
+        if self.any_day_of_month || self.any_day_of_week {
+            day_of_month && day_of_week
+        } else {
+            day_of_month || day_of_week
+        }
+    }
+
+    /// The first minute after `after` the expression matches, if there is
+    /// one within the next years.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let limit = after.year() + MAX_SEARCH_YEARS;
+        let mut time = after.with_second(0)?.with_nanosecond(0)? + TimeDelta::minutes(1);
+        while time.year() <= limit {
+            if !contains(self.months, time.month()) {
+                let (year, month) = match time.month() {
+                    12 => (time.year() + 1, 1),
+                    month => (time.year(), month + 1),
+                };
+                time = Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).single()?;
+            } else if !self.matches_day(time.date_naive()) {
+                time = time
+                    .date_naive()
+                    .succ_opt()?
+                    .and_hms_opt(0, 0, 0)?
+                    .and_utc();
+            } else if !contains(self.hours, time.hour()) {
+                time = time.with_minute(0)? + TimeDelta::hours(1);
+            } else if !contains(self.minutes, time.minute()) {
+                time += TimeDelta::minutes(1);
+            } else {
+                return Some(time);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().to_utc()
+    }
+
+    fn next(expression: &str, after: &str) -> Option<DateTime<Utc>> {
+        expression
+            .parse::<CronSchedule>()
+            .unwrap()
+            .next_after(at(after))
+    }
+
+    #[test]
+    fn next_runs_follow_the_expression() {
+        let after = "2026-10-16T10:17:42Z";
+        assert_eq!(next("* * * * *", after), Some(at("2026-10-16T10:18:00Z")));
+        assert_eq!(
+            next("*/15 * * * *", after),
+            Some(at("2026-10-16T10:30:00Z"))
+        );
+        assert_eq!(
+            next("5/20 * * * *", after),
+            Some(at("2026-10-16T10:25:00Z"))
+        );
+        assert_eq!(next("0 3 * * *", after), Some(at("2026-10-17T03:00:00Z")));
+        assert_eq!(next("@hourly", after), Some(at("2026-10-16T11:00:00Z")));
+        assert_eq!(next("@monthly", after), Some(at("2026-11-01T00:00:00Z")));
+        assert_eq!(next("0 0 1 1 *", after), Some(at("2027-01-01T00:00:00Z")));
+        assert_eq!(next("30 2 29 2 *", after), Some(at("2028-02-29T02:30:00Z")));
+        assert_eq!(next("0 0 30 2 *", after), None);
+    }
+
+    #[test]
+    fn days_match_either_restricted_day_field() {
+        // 2026-10-16 is a Friday.
+        let after = "2026-10-16T10:17:42Z";
+        assert_eq!(
+            next("0 12 * * 1-5", after),
+            Some(at("2026-10-16T12:00:00Z"))
+        );
+        assert_eq!(next("0 0 * * 7", after), Some(at("2026-10-18T00:00:00Z")));
+        assert_eq!(next("0 0 * * 0", after), Some(at("2026-10-18T00:00:00Z")));
+        assert_eq!(next("0 0 20 * 0", after), Some(at("2026-10-18T00:00:00Z")));
+        assert_eq!(
+            next("0 0 17,20 * 1", after),
+            Some(at("2026-10-17T00:00:00Z"))
+        );
+        assert_eq!(
+            next("0 0 */10 * 1", after),
+            Some(at("2026-12-21T00:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn invalid_expressions_are_rejected() {
+        for expression in [
+            "",
+            "* * * *",
+            "* * * * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "10-5 * * * *",
+            "a * * * *",
+            "1,,2 * * * *",
+        ] {
+            assert!(
+                expression.parse::<CronSchedule>().is_err(),
+                "'{expression}' was accepted"
+            );
+        }
+    }
+}
@@ -0,0 +1,532 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    cron::CronSchedule,
+    error::ScheduleServiceError,
+    persistence::{repository::ScheduleRepository, LastRun, ScheduleRecord},
+    trigger::{Trigger, MIN_INTERVAL_SECONDS},
+    Command, JobRun, JobRunner,
+};
+use chrono::{DateTime, SubsecRound, TimeDelta, Utc};
+use feos_proto::schedule_service::{
+    schedule_action::Action, CreateScheduleRequest, CreateScheduleResponse, DeleteScheduleRequest,
+    DeleteScheduleResponse, DeleteVmSnapshotPolicyRequest, DeleteVmSnapshotPolicyResponse,
+    ListSchedulesResponse, Schedule, ScheduleAction, ScheduleRun, SetVmSnapshotPolicyRequest,
+    SetVmSnapshotPolicyResponse, VmSnapshotAction,
+};
+use log::{error, info, warn};
+use prost_types::Timestamp;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+/// The longest the dispatcher sleeps before it looks at the clock again, so
+/// that runs are not late by much after the clock of the host was set.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+struct ScheduledJob {
+    trigger: Trigger,
+    action: Action,
+    vm_snapshot_policy: bool,
+    next_run: Option<DateTime<Utc>>,
+}
+
+struct FinishedRun {
+    schedule_id: Uuid,
+    run: LastRun,
+}
+
+pub struct Dispatcher {
+    rx: mpsc::Receiver<Command>,
+    repository: ScheduleRepository,
+    runner: Arc<dyn JobRunner>,
+    jobs: HashMap<Uuid, ScheduledJob>,
+    running: HashSet<Uuid>,
+    finished_tx: mpsc::Sender<FinishedRun>,
+    finished_rx: mpsc::Receiver<FinishedRun>,
+}
+
+fn timestamp(time: DateTime<Utc>) -> Timestamp {
+    Timestamp {
+        seconds: time.timestamp(),
+        nanos: time.timestamp_subsec_nanos() as i32,
+    }
+}
+
+fn schedule_to_proto(record: ScheduleRecord, next_run: Option<DateTime<Utc>>) -> Schedule {
+    Schedule {
+        schedule_id: record.schedule_id.to_string(),
+        name: record.name,
+        cron: record.cron,
+        action: Some(record.action),
+        next_run: next_run.map(timestamp),
+        last_run: record.last_run.map(|run| ScheduleRun {
+            started_at: DateTime::from_timestamp_millis(run.started_at_ms).map(timestamp),
+            succeeded: run.succeeded,
+            message: run.message,
+        }),
+        interval_seconds: record.interval_seconds.unwrap_or_default(),
+        vm_snapshot_policy: record.policy_vm_id.is_some(),
+    }
+}
+
+fn interval_trigger(seconds: u64, anchor: DateTime<Utc>) -> Result<Trigger, ScheduleServiceError> {
+    if seconds < MIN_INTERVAL_SECONDS {
+        return Err(ScheduleServiceError::InvalidArgument(format!(
+            "interval_seconds must be at least {MIN_INTERVAL_SECONDS}, got {seconds}"
+        )));
+    }
+    let every = TimeDelta::try_seconds(seconds as i64).ok_or_else(|| {
+        ScheduleServiceError::InvalidArgument(format!("interval_seconds {seconds} is too large"))
+    })?;
+    Ok(Trigger::Interval { every, anchor })
+}
+
+/// The trigger of a schedule loaded from the database.
+fn record_trigger(record: &ScheduleRecord) -> Result<Trigger, ScheduleServiceError> {
+    match record.interval_seconds {
+        Some(seconds) => {
+            let anchor = DateTime::from_timestamp_millis(record.created_at_ms).unwrap_or_default();
+            interval_trigger(seconds, anchor)
+        }
+        None => record
+            .cron
+            .parse()
+            .map(Trigger::Cron)
+            .map_err(|e| ScheduleServiceError::InvalidArgument(e.to_string())),
+    }
+}
+
+fn ensure_absolute(field: &str, directory: &str) -> Result<(), ScheduleServiceError> {
+    if !Path::new(directory).is_absolute() {
+        return Err(ScheduleServiceError::InvalidArgument(format!(
+            "{field} must be an absolute path, got '{directory}'"
+        )));
+    }
+    Ok(())
+}
+
+fn validate_action(action: &Action) -> Result<(), ScheduleServiceError> {
+    match action {
+        Action::VmSnapshot(snapshot) => {
+            Uuid::parse_str(&snapshot.vm_id).map_err(|_| {
+                ScheduleServiceError::InvalidArgument("Invalid VM UUID format".to_string())
+            })?;
+        }
+        Action::ImageGc(_) => {}
+        Action::DatabaseBackup(backup) => ensure_absolute("directory", &backup.directory)?,
+        Action::LogExport(export) => ensure_absolute("directory", &export.directory)?,
+        Action::VmDiskReplication(replication) => {
+            Uuid::parse_str(&replication.vm_id).map_err(|_| {
+                ScheduleServiceError::InvalidArgument("Invalid VM UUID format".to_string())
+            })?;
+            if !["http://", "https://"]
+                .iter()
+                .any(|scheme| replication.peer.starts_with(scheme))
+            {
+                return Err(ScheduleServiceError::InvalidArgument(format!(
+                    "peer must be an http:// or https:// address, got '{}'",
+                    replication.peer
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn run_job(runner: Arc<dyn JobRunner>, run: JobRun, finished_tx: mpsc::Sender<FinishedRun>) {
+    info!(
+        "Dispatcher: Running schedule {} started at {}",
+        run.schedule_id, run.started_at
+    );
+    let (succeeded, message) = match runner.run(&run).await {
+        Ok(message) => {
+            info!(
+                "Dispatcher: Schedule {} succeeded: {message}",
+                run.schedule_id
+            );
+            (true, message)
+        }
+        Err(message) => {
+            warn!("Dispatcher: Schedule {} failed: {message}", run.schedule_id);
+            (false, message)
+        }
+    };
+    let _ = finished_tx
+        .send(FinishedRun {
+            schedule_id: run.schedule_id,
+            run: LastRun {
+                started_at_ms: run.started_at.timestamp_millis(),
+                succeeded,
+                message,
+            },
+        })
+        .await;
+}
+
+impl Dispatcher {
+    pub async fn new(
+        rx: mpsc::Receiver<Command>,
+        db_url: &str,
+        runner: Arc<dyn JobRunner>,
+    ) -> Result<Self, ScheduleServiceError> {
+        info!("Dispatcher: Connecting to persistence layer at {db_url}...");
+        let repository = ScheduleRepository::connect(db_url).await?;
+        info!("Dispatcher: Persistence layer connected successfully.");
+
+        let now = Utc::now();
+        let mut jobs = HashMap::new();
+        for record in repository.list_schedules().await? {
+            // Before the action is taken out of the record.
+            let trigger = record_trigger(&record);
+            let Some(action) = record.action.action else {
+                warn!(
+                    "Dispatcher: Schedule {} has no action, skipping",
+                    record.schedule_id
+                );
+                continue;
+            };
+            match trigger {
+                Ok(trigger) => {
+                    let next_run = trigger.next_after(now);
+                    jobs.insert(
+                        record.schedule_id,
+                        ScheduledJob {
+                            trigger,
+                            action,
+                            vm_snapshot_policy: record.policy_vm_id.is_some(),
+                            next_run,
+                        },
+                    );
+                }
+                Err(e) => warn!("Dispatcher: Schedule {} skipped: {e}", record.schedule_id),
+            }
+        }
+        info!("Dispatcher: Loaded {} schedules.", jobs.len());
+
+        let (finished_tx, finished_rx) = mpsc::channel(32);
+        Ok(Self {
+            rx,
+            repository,
+            runner,
+            jobs,
+            running: HashSet::new(),
+            finished_tx,
+            finished_rx,
+        })
+    }
+
+    /// Starts the actions of schedules when they are due. Actions run in
+    /// their own tasks, so a slow one neither delays the others nor the
+    /// commands.
+    pub async fn run(mut self) {
+        info!("Dispatcher: Running and waiting for commands.");
+        loop {
+            let sleep = self.time_until_next_run();
+            tokio::select! {
+                cmd = self.rx.recv() => {
+                    let Some(cmd) = cmd else { break };
+                    self.handle_command(cmd).await;
+                }
+                Some(finished) = self.finished_rx.recv() => {
+                    self.record_run(finished).await;
+                }
+                _ = tokio::time::sleep(sleep) => {
+                    self.start_due_runs();
+                }
+            }
+        }
+        info!("Dispatcher: Channel closed, shutting down.");
+    }
+
+    fn time_until_next_run(&self) -> Duration {
+        let now = Utc::now();
+        self.jobs
+            .values()
+            .filter_map(|job| job.next_run)
+            .min()
+            .map(|next_run| (next_run - now).to_std().unwrap_or(Duration::ZERO))
+            .unwrap_or(MAX_SLEEP)
+            .min(MAX_SLEEP)
+    }
+
+    fn start_due_runs(&mut self) {
+        let now = Utc::now();
+        for (schedule_id, job) in &mut self.jobs {
+            let Some(started_at) = job.next_run.filter(|next_run| *next_run <= now) else {
+                continue;
+            };
+            job.next_run = job.trigger.next_after(now);
+            if !self.running.insert(*schedule_id) {
+                warn!("Dispatcher: Schedule {schedule_id} is still running, skipping this run");
+                continue;
+            }
+            tokio::spawn(run_job(
+                self.runner.clone(),
+                JobRun {
+                    schedule_id: *schedule_id,
+                    action: job.action.clone(),
+                    vm_snapshot_policy: job.vm_snapshot_policy,
+                    started_at,
+                },
+                self.finished_tx.clone(),
+            ));
+        }
+    }
+
+    async fn record_run(&mut self, finished: FinishedRun) {
+        self.running.remove(&finished.schedule_id);
+        if let Err(e) = self
+            .repository
+            .record_run(finished.schedule_id, &finished.run)
+            .await
+        {
+            error!(
+                "Dispatcher: Failed to record the run of schedule {}: {e}",
+                finished.schedule_id
+            );
+        }
+    }
+
+    async fn handle_command(&mut self, cmd: Command) {
+        match cmd {
+            Command::CreateSchedule(req, responder) => {
+                let _ = responder.send(self.create_schedule(req).await);
+            }
+            Command::ListSchedules(_req, responder) => {
+                let _ = responder.send(self.list_schedules().await);
+            }
+            Command::DeleteSchedule(req, responder) => {
+                let _ = responder.send(self.delete_schedule(req).await);
+            }
+            Command::SetVmSnapshotPolicy(req, responder) => {
+                let _ = responder.send(self.set_vm_snapshot_policy(req).await);
+            }
+            Command::DeleteVmSnapshotPolicy(req, responder) => {
+                let _ = responder.send(self.delete_vm_snapshot_policy(req).await);
+            }
+        }
+    }
+
+    /// Starts running a saved schedule. Returns its next run.
+    fn add_job(
+        &mut self,
+        record: &ScheduleRecord,
+        trigger: Trigger,
+        action: Action,
+    ) -> Option<DateTime<Utc>> {
+        let next_run = trigger.next_after(Utc::now());
+        self.jobs.insert(
+            record.schedule_id,
+            ScheduledJob {
+                trigger,
+                action,
+                vm_snapshot_policy: record.policy_vm_id.is_some(),
+                next_run,
+            },
+        );
+        next_run
+    }
+
+    async fn create_schedule(
+        &mut self,
+        req: CreateScheduleRequest,
+    ) -> Result<CreateScheduleResponse, ScheduleServiceError> {
+        let Some(action) = req.action.as_ref().and_then(|a| a.action.clone()) else {
+            return Err(ScheduleServiceError::InvalidArgument(
+                "An action is required".to_string(),
+            ));
+        };
+        validate_action(&action)?;
+        self.runner
+            .check(&action)
+            .map_err(ScheduleServiceError::InvalidArgument)?;
+        let created_at = Utc::now().trunc_subsecs(0);
+        let cron = req.cron.trim();
+        let trigger = match (cron.is_empty(), req.interval_seconds) {
+            (false, 0) => Trigger::Cron(
+                cron.parse::<CronSchedule>()
+                    .map_err(|e| ScheduleServiceError::InvalidArgument(e.to_string()))?,
+            ),
+            (true, seconds) if seconds > 0 => interval_trigger(seconds, created_at)?,
+            _ => {
+                return Err(ScheduleServiceError::InvalidArgument(
+                    "Exactly one of cron and interval_seconds must be set".to_string(),
+                ))
+            }
+        };
+        if trigger.next_after(created_at).is_none() {
+            return Err(ScheduleServiceError::InvalidArgument(format!(
+                "Cron expression '{cron}' never matches"
+            )));
+        }
+
+        let record = ScheduleRecord {
+            schedule_id: Uuid::new_v4(),
+            name: req.name,
+            cron: cron.to_string(),
+            action: ScheduleAction {
+                action: Some(action.clone()),
+            },
+            interval_seconds: (req.interval_seconds > 0).then_some(req.interval_seconds),
+            policy_vm_id: None,
+            created_at_ms: created_at.timestamp_millis(),
+            last_run: None,
+        };
+        self.repository.save_schedule(&record).await?;
+        let next_run = self.add_job(&record, trigger, action);
+        info!(
+            "Dispatcher: Created schedule {}, next run at {next_run:?}",
+            record.schedule_id
+        );
+        Ok(CreateScheduleResponse {
+            schedule: Some(schedule_to_proto(record, next_run)),
+        })
+    }
+
+    async fn list_schedules(&self) -> Result<ListSchedulesResponse, ScheduleServiceError> {
+        let schedules = self
+            .repository
+            .list_schedules()
+            .await?
+            .into_iter()
+            .map(|record| {
+                let next_run = self
+                    .jobs
+                    .get(&record.schedule_id)
+                    .and_then(|job| job.next_run);
+                schedule_to_proto(record, next_run)
+            })
+            .collect();
+        Ok(ListSchedulesResponse { schedules })
+    }
+
+    async fn delete_schedule(
+        &mut self,
+        req: DeleteScheduleRequest,
+    ) -> Result<DeleteScheduleResponse, ScheduleServiceError> {
+        let schedule_id = Uuid::parse_str(&req.schedule_id).map_err(|_| {
+            ScheduleServiceError::InvalidArgument("Invalid schedule UUID format".to_string())
+        })?;
+        if !self.repository.delete_schedule(schedule_id).await? {
+            return Err(ScheduleServiceError::NotFound(format!(
+                "Schedule '{schedule_id}' not found"
+            )));
+        }
+        self.jobs.remove(&schedule_id);
+        info!("Dispatcher: Deleted schedule {schedule_id}");
+        Ok(DeleteScheduleResponse {})
+    }
+
+    async fn set_vm_snapshot_policy(
+        &mut self,
+        req: SetVmSnapshotPolicyRequest,
+    ) -> Result<SetVmSnapshotPolicyResponse, ScheduleServiceError> {
+        if req.keep == 0 {
+            return Err(ScheduleServiceError::InvalidArgument(
+                "keep must be at least 1".to_string(),
+            ));
+        }
+        let action = Action::VmSnapshot(VmSnapshotAction {
+            vm_id: req.vm_id.clone(),
+            include_memory: req.include_memory,
+            keep: req.keep,
+        });
+        validate_action(&action)?;
+        self.runner
+            .check(&action)
+            .map_err(ScheduleServiceError::InvalidArgument)?;
+        let created_at = Utc::now().trunc_subsecs(0);
+        let trigger = interval_trigger(req.interval_seconds, created_at)?;
+
+        let record = ScheduleRecord {
+            schedule_id: Uuid::new_v4(),
+            name: format!("snapshot-policy-{}", req.vm_id),
+            cron: String::new(),
+            action: ScheduleAction {
+                action: Some(action.clone()),
+            },
+            interval_seconds: Some(req.interval_seconds),
+            policy_vm_id: Some(req.vm_id),
+            created_at_ms: created_at.timestamp_millis(),
+            last_run: None,
+        };
+        if let Some(replaced) = self.repository.replace_vm_snapshot_policy(&record).await? {
+            self.jobs.remove(&replaced);
+        }
+        let next_run = self.add_job(&record, trigger, action);
+        info!(
+            "Dispatcher: Set snapshot policy {} of VM {}, next run at {next_run:?}",
+            record.schedule_id,
+            record.policy_vm_id.as_deref().unwrap_or_default()
+        );
+        Ok(SetVmSnapshotPolicyResponse {
+            schedule: Some(schedule_to_proto(record, next_run)),
+        })
+    }
+
+    async fn delete_vm_snapshot_policy(
+        &mut self,
+        req: DeleteVmSnapshotPolicyRequest,
+    ) -> Result<DeleteVmSnapshotPolicyResponse, ScheduleServiceError> {
+        let vm_id = Uuid::parse_str(&req.vm_id).map_err(|_| {
+            ScheduleServiceError::InvalidArgument("Invalid VM UUID format".to_string())
+        })?;
+        let Some(schedule_id) = self.repository.delete_vm_snapshot_policy(vm_id).await? else {
+            return Err(ScheduleServiceError::NotFound(format!(
+                "VM '{vm_id}' has no snapshot policy"
+            )));
+        };
+        self.jobs.remove(&schedule_id);
+        info!("Dispatcher: Deleted snapshot policy {schedule_id} of VM {vm_id}");
+        Ok(DeleteVmSnapshotPolicyResponse {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feos_proto::schedule_service::{
+        DatabaseBackupAction, ImageGcAction, VmDiskReplicationAction, VmSnapshotAction,
+    };
+
+    #[test]
+    fn actions_are_validated() {
+        assert!(validate_action(&Action::ImageGc(ImageGcAction {})).is_ok());
+        assert!(validate_action(&Action::VmSnapshot(VmSnapshotAction {
+            vm_id: "not-a-uuid".to_string(),
+            ..Default::default()
+        }))
+        .is_err());
+        assert!(
+            validate_action(&Action::DatabaseBackup(DatabaseBackupAction {
+                directory: "backups".to_string(),
+                keep: 3,
+            }))
+            .is_err()
+        );
+        assert!(
+            validate_action(&Action::DatabaseBackup(DatabaseBackupAction {
+                directory: "/var/backups/feos".to_string(),
+                keep: 3,
+            }))
+            .is_ok()
+        );
+        let replication = VmDiskReplicationAction {
+            vm_id: Uuid::new_v4().to_string(),
+            peer: "https://[2001:db8::2]:1337".to_string(),
+        };
+        assert!(validate_action(&Action::VmDiskReplication(replication.clone())).is_ok());
+        assert!(
+            validate_action(&Action::VmDiskReplication(VmDiskReplicationAction {
+                peer: "[2001:db8::2]:1337".to_string(),
+                ..replication
+            }))
+            .is_err()
+        );
+    }
+}
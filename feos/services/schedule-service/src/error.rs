@@ -0,0 +1,28 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::persistence::PersistenceError;
+use tonic::Status;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ScheduleServiceError {
+    #[error("Persistence Error: {0}")]
+    Persistence(#[from] PersistenceError),
+
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+}
+
+impl From<ScheduleServiceError> for Status {
+    fn from(err: ScheduleServiceError) -> Self {
+        log::error!("ScheduleServiceError: {err}");
+        match err {
+            ScheduleServiceError::Persistence(_) => Status::internal("A database error occurred"),
+            ScheduleServiceError::InvalidArgument(msg) => Status::invalid_argument(msg),
+            ScheduleServiceError::NotFound(msg) => Status::not_found(msg),
+        }
+    }
+}
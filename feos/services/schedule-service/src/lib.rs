@@ -0,0 +1,72 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::error::ScheduleServiceError;
+use chrono::{DateTime, Utc};
+use feos_proto::schedule_service::{
+    schedule_action, CreateScheduleRequest, CreateScheduleResponse, DeleteScheduleRequest,
+    DeleteScheduleResponse, DeleteVmSnapshotPolicyRequest, DeleteVmSnapshotPolicyResponse,
+    ListSchedulesRequest, ListSchedulesResponse, SetVmSnapshotPolicyRequest,
+    SetVmSnapshotPolicyResponse,
+};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+pub mod api;
+pub mod backup;
+pub mod cron;
+pub mod dispatcher;
+pub mod error;
+pub mod persistence;
+pub mod trigger;
+
+pub const DEFAULT_SCHEDULE_DB_URL: &str = "sqlite:/var/lib/feos/schedules.db";
+
+#[derive(Debug)]
+pub enum Command {
+    CreateSchedule(
+        CreateScheduleRequest,
+        oneshot::Sender<Result<CreateScheduleResponse, ScheduleServiceError>>,
+    ),
+    ListSchedules(
+        ListSchedulesRequest,
+        oneshot::Sender<Result<ListSchedulesResponse, ScheduleServiceError>>,
+    ),
+    DeleteSchedule(
+        DeleteScheduleRequest,
+        oneshot::Sender<Result<DeleteScheduleResponse, ScheduleServiceError>>,
+    ),
+    SetVmSnapshotPolicy(
+        SetVmSnapshotPolicyRequest,
+        oneshot::Sender<Result<SetVmSnapshotPolicyResponse, ScheduleServiceError>>,
+    ),
+    DeleteVmSnapshotPolicy(
+        DeleteVmSnapshotPolicyRequest,
+        oneshot::Sender<Result<DeleteVmSnapshotPolicyResponse, ScheduleServiceError>>,
+    ),
+}
+
+/// A run of the action of a schedule.
+#[derive(Debug, Clone)]
+pub struct JobRun {
+    pub schedule_id: Uuid,
+    pub action: schedule_action::Action,
+    /// Whether the schedule is the snapshot policy of the VM of its action.
+    /// The snapshots of a policy are pruned along with those of the policies
+    /// it replaced.
+    pub vm_snapshot_policy: bool,
+    pub started_at: DateTime<Utc>,
+}
+
+/// Runs the actions of schedules, which are carried out by the other
+/// services.
+#[tonic::async_trait]
+pub trait JobRunner: Send + Sync {
+    /// Rejects actions this host can never run, before a schedule is created
+    /// for them.
+    fn check(&self, action: &schedule_action::Action) -> Result<(), String>;
+
+    /// Returns what the action did or why it failed, which is kept as the
+    /// last run of the schedule.
+    async fn run(&self, run: &JobRun) -> Result<String, String>;
+}
@@ -0,0 +1,45 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use feos_proto::schedule_service::ScheduleAction;
+use uuid::Uuid;
+
+pub mod repository;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PersistenceError {
+    #[error("A database error occurred")]
+    Database(#[from] sqlx::Error),
+
+    #[error("Database migration failed")]
+    Migration(#[from] sqlx::migrate::MigrateError),
+
+    #[error("Failed to decode ScheduleAction blob")]
+    Decode(#[from] prost::DecodeError),
+
+    #[error("Invalid UUID '{0}' in database")]
+    InvalidUuid(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct LastRun {
+    /// Milliseconds since the Unix epoch.
+    pub started_at_ms: i64,
+    pub succeeded: bool,
+    pub message: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScheduleRecord {
+    pub schedule_id: Uuid,
+    pub name: String,
+    pub cron: String,
+    pub action: ScheduleAction,
+    /// Set instead of `cron` for schedules that run at a fixed interval.
+    pub interval_seconds: Option<u64>,
+    /// The VM whose snapshot policy the schedule is.
+    pub policy_vm_id: Option<String>,
+    /// Milliseconds since the Unix epoch, which intervals are counted from.
+    pub created_at_ms: i64,
+    pub last_run: Option<LastRun>,
+}
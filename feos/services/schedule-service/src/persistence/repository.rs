@@ -0,0 +1,168 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::persistence::{LastRun, PersistenceError, ScheduleRecord};
+use chrono::DateTime;
+use feos_proto::schedule_service::ScheduleAction;
+use log::info;
+use prost::Message;
+use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+use sqlx::SqliteExecutor;
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub struct ScheduleRepository {
+    pool: SqlitePool,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct DbScheduleRow {
+    schedule_id: String,
+    name: String,
+    cron: String,
+    action_blob: Vec<u8>,
+    interval_seconds: Option<i64>,
+    policy_vm_id: Option<String>,
+    created_at_ms: i64,
+    last_run_at_ms: Option<i64>,
+    last_run_succeeded: Option<bool>,
+    last_run_message: Option<String>,
+}
+
+fn parse_uuid(id: String) -> Result<Uuid, PersistenceError> {
+    Uuid::parse_str(&id).map_err(|_| PersistenceError::InvalidUuid(id))
+}
+
+async fn insert_schedule(
+    executor: impl SqliteExecutor<'_>,
+    record: &ScheduleRecord,
+) -> Result<(), PersistenceError> {
+    // In the format of CURRENT_TIMESTAMP, so intervals count from the same
+    // second after a restart.
+    let created_at = DateTime::from_timestamp_millis(record.created_at_ms)
+        .unwrap_or_default()
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string();
+    sqlx::query(
+        "INSERT INTO schedules (schedule_id, name, cron, action_blob, interval_seconds, policy_vm_id, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+    )
+    .bind(record.schedule_id.to_string())
+    .bind(&record.name)
+    .bind(&record.cron)
+    .bind(record.action.encode_to_vec())
+    .bind(record.interval_seconds.map(|seconds| seconds as i64))
+    .bind(&record.policy_vm_id)
+    .bind(created_at)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+impl TryFrom<DbScheduleRow> for ScheduleRecord {
+    type Error = PersistenceError;
+
+    fn try_from(row: DbScheduleRow) -> Result<Self, Self::Error> {
+        Ok(ScheduleRecord {
+            schedule_id: parse_uuid(row.schedule_id)?,
+            name: row.name,
+            cron: row.cron,
+            action: ScheduleAction::decode(&*row.action_blob)?,
+            interval_seconds: row.interval_seconds.map(|seconds| seconds as u64),
+            policy_vm_id: row.policy_vm_id,
+            created_at_ms: row.created_at_ms,
+            last_run: row.last_run_at_ms.map(|started_at_ms| LastRun {
+                started_at_ms,
+                succeeded: row.last_run_succeeded.unwrap_or_default(),
+                message: row.last_run_message.unwrap_or_default(),
+            }),
+        })
+    }
+}
+
+impl ScheduleRepository {
+    pub async fn connect(db_url: &str) -> Result<Self, PersistenceError> {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(db_url)
+            .await?;
+
+        info!("Persistence: Running schedule-service database migrations...");
+        sqlx::migrate!("./migrations").run(&pool).await?;
+        info!("Persistence: Database migrations completed for schedule-service.");
+
+        Ok(Self { pool })
+    }
+
+    pub async fn list_schedules(&self) -> Result<Vec<ScheduleRecord>, PersistenceError> {
+        sqlx::query_as::<_, DbScheduleRow>(
+            "SELECT schedule_id, name, cron, action_blob, interval_seconds, policy_vm_id, CAST(unixepoch(created_at) AS INTEGER) * 1000 AS created_at_ms, last_run_at_ms, last_run_succeeded, last_run_message FROM schedules ORDER BY created_at, schedule_id",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(ScheduleRecord::try_from)
+        .collect()
+    }
+
+    pub async fn save_schedule(&self, record: &ScheduleRecord) -> Result<(), PersistenceError> {
+        insert_schedule(&self.pool, record).await
+    }
+
+    /// Saves the snapshot policy of a VM in place of its previous one, whose
+    /// ID is returned.
+    pub async fn replace_vm_snapshot_policy(
+        &self,
+        record: &ScheduleRecord,
+    ) -> Result<Option<Uuid>, PersistenceError> {
+        let mut tx = self.pool.begin().await?;
+        let replaced: Option<String> = sqlx::query_scalar(
+            "DELETE FROM schedules WHERE policy_vm_id = ?1 RETURNING schedule_id",
+        )
+        .bind(&record.policy_vm_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        insert_schedule(&mut *tx, record).await?;
+        tx.commit().await?;
+        replaced.map(parse_uuid).transpose()
+    }
+
+    /// Returns the ID of the deleted policy, if the VM had one.
+    pub async fn delete_vm_snapshot_policy(
+        &self,
+        vm_id: Uuid,
+    ) -> Result<Option<Uuid>, PersistenceError> {
+        let deleted: Option<String> = sqlx::query_scalar(
+            "DELETE FROM schedules WHERE policy_vm_id = ?1 RETURNING schedule_id",
+        )
+        .bind(vm_id.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+        deleted.map(parse_uuid).transpose()
+    }
+
+    /// Returns whether the schedule existed.
+    pub async fn delete_schedule(&self, schedule_id: Uuid) -> Result<bool, PersistenceError> {
+        let result = sqlx::query("DELETE FROM schedules WHERE schedule_id = ?1")
+            .bind(schedule_id.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn record_run(
+        &self,
+        schedule_id: Uuid,
+        run: &LastRun,
+    ) -> Result<(), PersistenceError> {
+        sqlx::query(
+            "UPDATE schedules SET last_run_at_ms = ?1, last_run_succeeded = ?2, last_run_message = ?3 WHERE schedule_id = ?4",
+        )
+        .bind(run.started_at_ms)
+        .bind(run.succeeded)
+        .bind(&run.message)
+        .bind(schedule_id.to_string())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::cron::CronSchedule;
+use chrono::{DateTime, TimeDelta, Utc};
+
+/// The shortest interval a schedule runs at. The dispatcher looks at the
+/// clock at least this often.
+pub const MIN_INTERVAL_SECONDS: u64 = 60;
+
+/// When the action of a schedule runs.
+#[derive(Debug, Clone)]
+pub enum Trigger {
+    Cron(CronSchedule),
+    /// Every `every`, counted from `anchor`.
+    Interval {
+        every: TimeDelta,
+        anchor: DateTime<Utc>,
+    },
+}
+
+impl Trigger {
+    /// The first time after `after` the action runs, if it runs again.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Trigger::Cron(cron) => cron.next_after(after),
+            Trigger::Interval { every, anchor } => {
+                let every_ms = every.num_milliseconds();
+                let periods = (after - *anchor).num_milliseconds().div_euclid(every_ms) + 1;
+                let elapsed = TimeDelta::try_milliseconds(every_ms.checked_mul(periods)?)?;
+                anchor.checked_add_signed(elapsed)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().to_utc()
+    }
+
+    #[test]
+    fn intervals_count_from_the_anchor() {
+        let trigger = Trigger::Interval {
+            every: TimeDelta::hours(6),
+            anchor: at("2026-10-16T10:17:42Z"),
+        };
+        assert_eq!(
+            trigger.next_after(at("2026-10-16T10:17:42Z")),
+            Some(at("2026-10-16T16:17:42Z"))
+        );
+        assert_eq!(
+            trigger.next_after(at("2026-10-18T03:00:00Z")),
+            Some(at("2026-10-18T04:17:42Z"))
+        );
+        assert_eq!(
+            trigger.next_after(at("2026-10-16T09:00:00Z")),
+            Some(at("2026-10-16T10:17:42Z"))
+        );
+    }
+}
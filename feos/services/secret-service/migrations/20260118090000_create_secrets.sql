@@ -0,0 +1,22 @@
+-- SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+-- SPDX-License-Identifier: Apache-2.0
+
+CREATE TABLE IF NOT EXISTS secrets (
+    namespace TEXT NOT NULL,
+    name TEXT NOT NULL,
+    -- The AES-256-GCM nonce and the encrypted value followed by its tag.
+    nonce BLOB NOT NULL,
+    ciphertext BLOB NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    created_at_ms INTEGER NOT NULL,
+    updated_at_ms INTEGER NOT NULL,
+    PRIMARY KEY (namespace, name)
+);
+
+-- The key the secrets are encrypted with, sealed to the host TPM. Only used
+-- without a key file, at most one row.
+CREATE TABLE IF NOT EXISTS sealed_key (
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    public BLOB NOT NULL,
+    private BLOB NOT NULL
+);
@@ -0,0 +1,81 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::Command;
+use feos_proto::secret_service::{
+    secret_service_server::SecretService, DeleteSecretRequest, DeleteSecretResponse,
+    ListSecretsRequest, ListSecretsResponse, PutSecretRequest, PutSecretResponse,
+};
+use log::info;
+use tokio::sync::{mpsc, oneshot};
+use tonic::{Request, Response, Status};
+
+pub struct SecretApiHandler {
+    dispatcher_tx: mpsc::Sender<Command>,
+}
+
+impl SecretApiHandler {
+    pub fn new(dispatcher_tx: mpsc::Sender<Command>) -> Self {
+        Self { dispatcher_tx }
+    }
+}
+
+async fn dispatch_and_wait<T, E>(
+    dispatcher: &mpsc::Sender<Command>,
+    command_constructor: impl FnOnce(oneshot::Sender<Result<T, E>>) -> Command,
+) -> Result<Response<T>, Status>
+where
+    E: Into<Status>,
+{
+    let (resp_tx, resp_rx) = oneshot::channel();
+    let cmd = command_constructor(resp_tx);
+
+    dispatcher
+        .send(cmd)
+        .await
+        .map_err(|e| Status::internal(format!("Failed to send command to dispatcher: {e}")))?;
+
+    match resp_rx.await {
+        Ok(Ok(result)) => Ok(Response::new(result)),
+        Ok(Err(e)) => Err(e.into()),
+        Err(_) => Err(Status::internal(
+            "Dispatcher task dropped response channel.",
+        )),
+    }
+}
+
+#[tonic::async_trait]
+impl SecretService for SecretApiHandler {
+    async fn put_secret(
+        &self,
+        request: Request<PutSecretRequest>,
+    ) -> Result<Response<PutSecretResponse>, Status> {
+        info!("SecretApi: Received PutSecret request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::PutSecret(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn list_secrets(
+        &self,
+        request: Request<ListSecretsRequest>,
+    ) -> Result<Response<ListSecretsResponse>, Status> {
+        info!("SecretApi: Received ListSecrets request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::ListSecrets(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn delete_secret(
+        &self,
+        request: Request<DeleteSecretRequest>,
+    ) -> Result<Response<DeleteSecretResponse>, Status> {
+        info!("SecretApi: Received DeleteSecret request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::DeleteSecret(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+}
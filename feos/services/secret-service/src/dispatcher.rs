@@ -0,0 +1,111 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{error::SecretServiceError, persistence::SecretRecord, store::SecretStore, Command};
+use feos_proto::secret_service::{
+    DeleteSecretRequest, DeleteSecretResponse, ListSecretsRequest, ListSecretsResponse,
+    PutSecretRequest, PutSecretResponse, SecretInfo,
+};
+use feos_utils::namespace::{namespace_or_default, validate_name};
+use log::info;
+use prost_types::Timestamp;
+use tokio::sync::mpsc;
+
+pub struct Dispatcher {
+    rx: mpsc::Receiver<Command>,
+    store: SecretStore,
+}
+
+fn timestamp(ms: i64) -> Timestamp {
+    Timestamp {
+        seconds: ms.div_euclid(1000),
+        nanos: (ms.rem_euclid(1000) * 1_000_000) as i32,
+    }
+}
+
+fn secret_to_proto(record: SecretRecord) -> SecretInfo {
+    SecretInfo {
+        namespace: record.namespace,
+        name: record.name,
+        size_bytes: record.size_bytes,
+        created_at: Some(timestamp(record.created_at_ms)),
+        updated_at: Some(timestamp(record.updated_at_ms)),
+    }
+}
+
+/// The namespace and name of the secret a request refers to.
+fn secret_ref(namespace: &str, name: &str) -> Result<(String, String), SecretServiceError> {
+    let namespace = namespace_or_default(namespace)
+        .map_err(|e| SecretServiceError::InvalidArgument(e.to_string()))?;
+    validate_name(name).map_err(|e| SecretServiceError::InvalidArgument(e.to_string()))?;
+    Ok((namespace, name.to_string()))
+}
+
+impl Dispatcher {
+    pub fn new(rx: mpsc::Receiver<Command>, store: SecretStore) -> Self {
+        Self { rx, store }
+    }
+
+    pub async fn run(mut self) {
+        info!("Dispatcher: Running and waiting for commands.");
+        while let Some(cmd) = self.rx.recv().await {
+            self.handle_command(cmd).await;
+        }
+        info!("Dispatcher: Channel closed, shutting down.");
+    }
+
+    async fn handle_command(&self, cmd: Command) {
+        match cmd {
+            Command::PutSecret(req, responder) => {
+                let _ = responder.send(self.put_secret(req).await);
+            }
+            Command::ListSecrets(req, responder) => {
+                let _ = responder.send(self.list_secrets(req).await);
+            }
+            Command::DeleteSecret(req, responder) => {
+                let _ = responder.send(self.delete_secret(req).await);
+            }
+        }
+    }
+
+    async fn put_secret(
+        &self,
+        req: PutSecretRequest,
+    ) -> Result<PutSecretResponse, SecretServiceError> {
+        let (namespace, name) = secret_ref(&req.namespace, &req.name)?;
+        let created = self.store.put(&namespace, &name, &req.value).await?;
+        info!(
+            "Dispatcher: {} secret '{name}' in namespace '{namespace}'",
+            if created { "Created" } else { "Replaced" }
+        );
+        Ok(PutSecretResponse { created })
+    }
+
+    async fn list_secrets(
+        &self,
+        req: ListSecretsRequest,
+    ) -> Result<ListSecretsResponse, SecretServiceError> {
+        let secrets = self
+            .store
+            .list(req.namespace.as_deref())
+            .await?
+            .into_iter()
+            .map(secret_to_proto)
+            .collect();
+        Ok(ListSecretsResponse { secrets })
+    }
+
+    async fn delete_secret(
+        &self,
+        req: DeleteSecretRequest,
+    ) -> Result<DeleteSecretResponse, SecretServiceError> {
+        let (namespace, name) = secret_ref(&req.namespace, &req.name)?;
+        if !self.store.delete(&namespace, &name).await? {
+            return Err(SecretServiceError::NotFound(format!(
+                "Secret '{name}' not found in namespace '{namespace}'"
+            )));
+        }
+        info!("Dispatcher: Deleted secret '{name}' in namespace '{namespace}'");
+        Ok(DeleteSecretResponse {})
+    }
+}
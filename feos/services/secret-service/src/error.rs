@@ -0,0 +1,40 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::persistence::PersistenceError;
+use tonic::Status;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SecretServiceError {
+    #[error("Persistence Error: {0}")]
+    Persistence(#[from] PersistenceError),
+
+    #[error("Secret key error: {0}")]
+    Key(String),
+
+    #[error("Secrets are unavailable: {0}")]
+    Unavailable(String),
+
+    #[error("Failed to decrypt secret: {0}")]
+    Decrypt(String),
+
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+}
+
+impl From<SecretServiceError> for Status {
+    fn from(err: SecretServiceError) -> Self {
+        log::error!("SecretServiceError: {err}");
+        match err {
+            SecretServiceError::Persistence(_) => Status::internal("A database error occurred"),
+            SecretServiceError::Key(msg) => Status::internal(msg),
+            SecretServiceError::Unavailable(msg) => Status::failed_precondition(msg),
+            SecretServiceError::Decrypt(msg) => Status::internal(msg),
+            SecretServiceError::InvalidArgument(msg) => Status::invalid_argument(msg),
+            SecretServiceError::NotFound(msg) => Status::not_found(msg),
+        }
+    }
+}
@@ -0,0 +1,161 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! The key secrets are encrypted with at rest. It is derived from a key file,
+//! which hosts can share, or generated on the host and sealed to its TPM.
+
+use crate::error::SecretServiceError;
+use crate::persistence::{repository::SecretRepository, EncryptedValue};
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use log::info;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use storage_service::encryption::tpm;
+
+/// Key files with less content are rejected, the output of
+/// `openssl rand -hex 32` is long enough.
+const MIN_KEY_FILE_BYTES: usize = 32;
+const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 12;
+
+pub struct SecretKey {
+    cipher: Aes256Gcm,
+}
+
+/// Binds a value to its secret, so values cannot be swapped between secrets
+/// in the database.
+fn associated_data(namespace: &str, name: &str) -> String {
+    format!("{namespace}/{name}")
+}
+
+impl SecretKey {
+    fn new(key: &[u8]) -> Self {
+        Self {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)),
+        }
+    }
+
+    /// Derives the key from the content of a key file. Whitespace around it
+    /// is ignored, so a trailing newline does not change the key.
+    pub fn from_key_file(content: &[u8]) -> Result<Self, SecretServiceError> {
+        let content = content.trim_ascii();
+        if content.len() < MIN_KEY_FILE_BYTES {
+            return Err(SecretServiceError::Key(format!(
+                "The key file must hold at least {MIN_KEY_FILE_BYTES} bytes, got {}",
+                content.len()
+            )));
+        }
+        Ok(Self::new(&Sha256::digest(content)))
+    }
+
+    pub fn encrypt(&self, namespace: &str, name: &str, value: &[u8]) -> EncryptedValue {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let aad = associated_data(namespace, name);
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: value,
+                    aad: aad.as_bytes(),
+                },
+            )
+            .expect("AES-GCM encrypts values of any size this small");
+        EncryptedValue {
+            nonce: nonce.to_vec(),
+            ciphertext,
+        }
+    }
+
+    pub fn decrypt(
+        &self,
+        namespace: &str,
+        name: &str,
+        value: &EncryptedValue,
+    ) -> Result<Vec<u8>, SecretServiceError> {
+        let failed = || {
+            SecretServiceError::Decrypt(format!(
+                "Secret '{name}' in namespace '{namespace}' cannot be decrypted, was it put with another key?"
+            ))
+        };
+        if value.nonce.len() != NONCE_SIZE {
+            return Err(failed());
+        }
+        let aad = associated_data(namespace, name);
+        self.cipher
+            .decrypt(
+                Nonce::from_slice(&value.nonce),
+                Payload {
+                    msg: &value.ciphertext,
+                    aad: aad.as_bytes(),
+                },
+            )
+            .map_err(|_| failed())
+    }
+}
+
+/// Loads the key from `key_file` or, without one, unseals the key of the
+/// host. The key of the host is generated and sealed to its TPM the first
+/// time.
+pub async fn load(
+    repository: &SecretRepository,
+    key_file: Option<&Path>,
+) -> Result<SecretKey, SecretServiceError> {
+    if let Some(key_file) = key_file {
+        let content = tokio::fs::read(key_file).await.map_err(|e| {
+            SecretServiceError::Key(format!("Failed to read {}: {e}", key_file.display()))
+        })?;
+        return SecretKey::from_key_file(&content);
+    }
+
+    let tpm_error =
+        |e: storage_service::backend::BackendError| SecretServiceError::Key(format!("TPM: {e}"));
+    if let Some(sealed_key) = repository.get_sealed_key().await? {
+        let key = tpm::unseal(&sealed_key).await.map_err(tpm_error)?;
+        if key.len() != KEY_SIZE {
+            return Err(SecretServiceError::Key(format!(
+                "The sealed key has {} bytes instead of {KEY_SIZE}",
+                key.len()
+            )));
+        }
+        return Ok(SecretKey::new(&key));
+    }
+    let key = Aes256Gcm::generate_key(OsRng);
+    let sealed_key = tpm::seal(&key).await.map_err(tpm_error)?;
+    repository.save_sealed_key(&sealed_key).await?;
+    info!("SecretKey: Generated a key for secrets and sealed it to the TPM.");
+    Ok(SecretKey::new(&key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_only_decrypt_for_their_secret_and_key() {
+        let key = SecretKey::from_key_file(b"0123456789abcdef0123456789abcdef\n").unwrap();
+        let value = key.encrypt("default", "db-password", b"hunter2");
+        assert_ne!(value.ciphertext, b"hunter2");
+        assert_eq!(
+            key.decrypt("default", "db-password", &value).unwrap(),
+            b"hunter2"
+        );
+        // The same value is encrypted differently each time.
+        let again = key.encrypt("default", "db-password", b"hunter2");
+        assert_ne!(again.nonce, value.nonce);
+        assert_ne!(again.ciphertext, value.ciphertext);
+
+        // Values moved to another secret or namespace do not decrypt.
+        assert!(key.decrypt("default", "api-token", &value).is_err());
+        assert!(key.decrypt("tenant-a", "db-password", &value).is_err());
+        // Nor do they with another key, while whitespace around the key
+        // file content does not matter.
+        let same = SecretKey::from_key_file(b"  0123456789abcdef0123456789abcdef").unwrap();
+        assert!(same.decrypt("default", "db-password", &value).is_ok());
+        let other = SecretKey::from_key_file(b"0123456789abcdef0123456789abcdeF").unwrap();
+        assert!(other.decrypt("default", "db-password", &value).is_err());
+
+        assert!(SecretKey::from_key_file(b"too short\n").is_err());
+    }
+}
@@ -0,0 +1,34 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::error::SecretServiceError;
+use feos_proto::secret_service::{
+    DeleteSecretRequest, DeleteSecretResponse, ListSecretsRequest, ListSecretsResponse,
+    PutSecretRequest, PutSecretResponse,
+};
+use tokio::sync::oneshot;
+
+pub mod api;
+pub mod dispatcher;
+pub mod error;
+pub mod key;
+pub mod persistence;
+pub mod store;
+
+pub const DEFAULT_SECRET_DB_URL: &str = "sqlite:/var/lib/feos/secrets.db";
+
+#[derive(Debug)]
+pub enum Command {
+    PutSecret(
+        PutSecretRequest,
+        oneshot::Sender<Result<PutSecretResponse, SecretServiceError>>,
+    ),
+    ListSecrets(
+        ListSecretsRequest,
+        oneshot::Sender<Result<ListSecretsResponse, SecretServiceError>>,
+    ),
+    DeleteSecret(
+        DeleteSecretRequest,
+        oneshot::Sender<Result<DeleteSecretResponse, SecretServiceError>>,
+    ),
+}
@@ -0,0 +1,30 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+pub mod repository;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PersistenceError {
+    #[error("A database error occurred")]
+    Database(#[from] sqlx::Error),
+
+    #[error("Database migration failed")]
+    Migration(#[from] sqlx::migrate::MigrateError),
+}
+
+/// A secret value as stored, encrypted by `SecretKey::encrypt`.
+#[derive(Debug, Clone)]
+pub struct EncryptedValue {
+    pub nonce: Vec<u8>,
+    pub ciphertext: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SecretRecord {
+    pub namespace: String,
+    pub name: String,
+    pub size_bytes: u64,
+    /// Milliseconds since the Unix epoch.
+    pub created_at_ms: i64,
+    pub updated_at_ms: i64,
+}
@@ -0,0 +1,152 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::persistence::{EncryptedValue, PersistenceError, SecretRecord};
+use log::info;
+use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+use storage_service::encryption::tpm::SealedKey;
+
+#[derive(Clone)]
+pub struct SecretRepository {
+    pool: SqlitePool,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct DbSecretRow {
+    namespace: String,
+    name: String,
+    size_bytes: i64,
+    created_at_ms: i64,
+    updated_at_ms: i64,
+}
+
+impl From<DbSecretRow> for SecretRecord {
+    fn from(row: DbSecretRow) -> Self {
+        SecretRecord {
+            namespace: row.namespace,
+            name: row.name,
+            size_bytes: row.size_bytes as u64,
+            created_at_ms: row.created_at_ms,
+            updated_at_ms: row.updated_at_ms,
+        }
+    }
+}
+
+const SECRET_COLUMNS: &str = "namespace, name, size_bytes, created_at_ms, updated_at_ms";
+
+impl SecretRepository {
+    pub async fn connect(db_url: &str) -> Result<Self, PersistenceError> {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(db_url)
+            .await?;
+
+        info!("Persistence: Running secret-service database migrations...");
+        sqlx::migrate!("./migrations").run(&pool).await?;
+        info!("Persistence: Database migrations completed for secret-service.");
+
+        Ok(Self { pool })
+    }
+
+    pub async fn list_secrets(
+        &self,
+        namespace: Option<&str>,
+    ) -> Result<Vec<SecretRecord>, PersistenceError> {
+        let rows = match namespace {
+            Some(namespace) => {
+                sqlx::query_as::<_, DbSecretRow>(&format!(
+                    "SELECT {SECRET_COLUMNS} FROM secrets WHERE namespace = ?1 ORDER BY name"
+                ))
+                .bind(namespace)
+                .fetch_all(&self.pool)
+                .await?
+            }
+            None => {
+                sqlx::query_as::<_, DbSecretRow>(&format!(
+                    "SELECT {SECRET_COLUMNS} FROM secrets ORDER BY namespace, name"
+                ))
+                .fetch_all(&self.pool)
+                .await?
+            }
+        };
+        Ok(rows.into_iter().map(SecretRecord::from).collect())
+    }
+
+    pub async fn get_value(
+        &self,
+        namespace: &str,
+        name: &str,
+    ) -> Result<Option<EncryptedValue>, PersistenceError> {
+        let row: Option<(Vec<u8>, Vec<u8>)> = sqlx::query_as(
+            "SELECT nonce, ciphertext FROM secrets WHERE namespace = ?1 AND name = ?2",
+        )
+        .bind(namespace)
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(|(nonce, ciphertext)| EncryptedValue { nonce, ciphertext }))
+    }
+
+    /// Saves the value of a secret, replacing its previous one. Returns
+    /// whether the secret was created.
+    pub async fn put_secret(
+        &self,
+        namespace: &str,
+        name: &str,
+        value: &EncryptedValue,
+        size_bytes: u64,
+        now_ms: i64,
+    ) -> Result<bool, PersistenceError> {
+        let mut tx = self.pool.begin().await?;
+        let exists: Option<i64> =
+            sqlx::query_scalar("SELECT 1 FROM secrets WHERE namespace = ?1 AND name = ?2")
+                .bind(namespace)
+                .bind(name)
+                .fetch_optional(&mut *tx)
+                .await?;
+        sqlx::query(
+            "INSERT INTO secrets (namespace, name, nonce, ciphertext, size_bytes, created_at_ms, updated_at_ms) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6) ON CONFLICT (namespace, name) DO UPDATE SET nonce = excluded.nonce, ciphertext = excluded.ciphertext, size_bytes = excluded.size_bytes, updated_at_ms = excluded.updated_at_ms",
+        )
+        .bind(namespace)
+        .bind(name)
+        .bind(&value.nonce)
+        .bind(&value.ciphertext)
+        .bind(size_bytes as i64)
+        .bind(now_ms)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(exists.is_none())
+    }
+
+    /// Returns whether the secret existed.
+    pub async fn delete_secret(
+        &self,
+        namespace: &str,
+        name: &str,
+    ) -> Result<bool, PersistenceError> {
+        let result = sqlx::query("DELETE FROM secrets WHERE namespace = ?1 AND name = ?2")
+            .bind(namespace)
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn get_sealed_key(&self) -> Result<Option<SealedKey>, PersistenceError> {
+        let row: Option<(Vec<u8>, Vec<u8>)> =
+            sqlx::query_as("SELECT public, private FROM sealed_key WHERE id = 0")
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(row.map(|(public, private)| SealedKey { public, private }))
+    }
+
+    pub async fn save_sealed_key(&self, sealed_key: &SealedKey) -> Result<(), PersistenceError> {
+        sqlx::query("INSERT INTO sealed_key (id, public, private) VALUES (0, ?1, ?2)")
+            .bind(&sealed_key.public)
+            .bind(&sealed_key.private)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
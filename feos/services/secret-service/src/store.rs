@@ -0,0 +1,94 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! The secrets of the host. The store is shared by the secret service and
+//! the workload services, which read the secrets containers and VMs
+//! reference.
+
+use crate::error::SecretServiceError;
+use crate::key::SecretKey;
+use crate::persistence::{repository::SecretRepository, SecretRecord};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Secrets end up in environment variables and cloud-init seeds, which are
+/// not meant for large values.
+pub const MAX_SECRET_BYTES: usize = 64 * 1024;
+
+#[derive(Clone)]
+pub struct SecretStore {
+    repository: SecretRepository,
+    /// Why secrets cannot be put or read if there is no key.
+    key: Result<Arc<SecretKey>, String>,
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as i64)
+}
+
+impl SecretStore {
+    /// Without a key, secrets can still be listed and deleted.
+    pub fn new(repository: SecretRepository, key: Result<SecretKey, String>) -> Self {
+        Self {
+            repository,
+            key: key.map(Arc::new),
+        }
+    }
+
+    fn key(&self) -> Result<&SecretKey, SecretServiceError> {
+        self.key
+            .as_deref()
+            .map_err(|reason| SecretServiceError::Unavailable(reason.clone()))
+    }
+
+    /// Creates a secret or replaces its value. Returns whether it was
+    /// created.
+    pub async fn put(
+        &self,
+        namespace: &str,
+        name: &str,
+        value: &[u8],
+    ) -> Result<bool, SecretServiceError> {
+        if value.len() > MAX_SECRET_BYTES {
+            return Err(SecretServiceError::InvalidArgument(format!(
+                "Secret values must not exceed {MAX_SECRET_BYTES} bytes, got {}",
+                value.len()
+            )));
+        }
+        let encrypted = self.key()?.encrypt(namespace, name, value);
+        Ok(self
+            .repository
+            .put_secret(namespace, name, &encrypted, value.len() as u64, now_ms())
+            .await?)
+    }
+
+    /// The value of a secret of `namespace`, for a workload of the
+    /// namespace referencing it.
+    pub async fn get(&self, namespace: &str, name: &str) -> Result<Vec<u8>, SecretServiceError> {
+        let key = self.key()?;
+        let encrypted = self
+            .repository
+            .get_value(namespace, name)
+            .await?
+            .ok_or_else(|| {
+                SecretServiceError::NotFound(format!(
+                    "Secret '{name}' not found in namespace '{namespace}'"
+                ))
+            })?;
+        key.decrypt(namespace, name, &encrypted)
+    }
+
+    pub async fn list(
+        &self,
+        namespace: Option<&str>,
+    ) -> Result<Vec<SecretRecord>, SecretServiceError> {
+        Ok(self.repository.list_secrets(namespace).await?)
+    }
+
+    /// Returns whether the secret existed.
+    pub async fn delete(&self, namespace: &str, name: &str) -> Result<bool, SecretServiceError> {
+        Ok(self.repository.delete_secret(namespace, name).await?)
+    }
+}
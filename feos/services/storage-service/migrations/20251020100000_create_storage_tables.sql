@@ -0,0 +1,31 @@
+-- SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+-- SPDX-License-Identifier: Apache-2.0
+
+CREATE TABLE IF NOT EXISTS pools (
+    pool_id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    config_blob BLOB NOT NULL,
+    -- Whether the thin pool or directory was created by FeOS and may be
+    -- removed together with the pool.
+    owned INTEGER NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS volumes (
+    volume_id TEXT PRIMARY KEY NOT NULL,
+    pool_id TEXT NOT NULL REFERENCES pools(pool_id),
+    name TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    path TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    UNIQUE (pool_id, name)
+);
+
+CREATE TRIGGER IF NOT EXISTS trigger_volumes_updated_at
+AFTER UPDATE ON volumes
+FOR EACH ROW
+BEGIN
+    UPDATE volumes SET updated_at = CURRENT_TIMESTAMP WHERE volume_id = OLD.volume_id;
+END;
@@ -0,0 +1,9 @@
+-- SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+-- SPDX-License-Identifier: Apache-2.0
+
+ALTER TABLE volumes ADD COLUMN encryption TEXT NOT NULL DEFAULT 'VOLUME_ENCRYPTION_NONE';
+
+-- The key of a TPM-encrypted volume, sealed to the host TPM. NULL for volumes
+-- that are not encrypted or whose key is held by the caller.
+ALTER TABLE volumes ADD COLUMN sealed_key_public BLOB;
+ALTER TABLE volumes ADD COLUMN sealed_key_private BLOB;
@@ -0,0 +1,38 @@
+-- SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+-- SPDX-License-Identifier: Apache-2.0
+
+-- Volume names only have to be unique within the namespace of their tenant.
+-- SQLite cannot drop the old UNIQUE (pool_id, name) constraint, so the table
+-- is rebuilt. Volumes from before namespaces existed belong to the default one.
+CREATE TABLE volumes_new (
+    volume_id TEXT PRIMARY KEY NOT NULL,
+    pool_id TEXT NOT NULL REFERENCES pools(pool_id),
+    namespace TEXT NOT NULL DEFAULT 'default',
+    name TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    path TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    encryption TEXT NOT NULL DEFAULT 'VOLUME_ENCRYPTION_NONE',
+    sealed_key_public BLOB,
+    sealed_key_private BLOB,
+    UNIQUE (pool_id, namespace, name)
+);
+
+INSERT INTO volumes_new (volume_id, pool_id, name, kind, size_bytes, path, created_at,
+    updated_at, encryption, sealed_key_public, sealed_key_private)
+SELECT volume_id, pool_id, name, kind, size_bytes, path, created_at, updated_at, encryption,
+    sealed_key_public, sealed_key_private
+FROM volumes;
+
+DROP TRIGGER IF EXISTS trigger_volumes_updated_at;
+DROP TABLE volumes;
+ALTER TABLE volumes_new RENAME TO volumes;
+
+CREATE TRIGGER IF NOT EXISTS trigger_volumes_updated_at
+AFTER UPDATE ON volumes
+FOR EACH ROW
+BEGIN
+    UPDATE volumes SET updated_at = CURRENT_TIMESTAMP WHERE volume_id = OLD.volume_id;
+END;
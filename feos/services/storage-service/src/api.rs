@@ -0,0 +1,193 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::Command;
+use feos_proto::storage_service::{
+    storage_service_server::StorageService, CloseVolumeRequest, CloseVolumeResponse,
+    CreatePoolRequest, CreatePoolResponse, CreateVolumeRequest, CreateVolumeResponse,
+    DeletePoolRequest, DeletePoolResponse, DeleteVolumeRequest, DeleteVolumeResponse,
+    DiskUsageAlert, GetDiskUsageRequest, GetDiskUsageResponse, ListPoolsRequest, ListPoolsResponse,
+    ListVolumesRequest, ListVolumesResponse, OpenVolumeRequest, OpenVolumeResponse,
+    ResizePoolRequest, ResizePoolResponse, ResizeVolumeRequest, ResizeVolumeResponse,
+    WatchDiskUsageAlertsRequest,
+};
+use log::info;
+use std::pin::Pin;
+use tokio::sync::{mpsc, oneshot};
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+use tonic::{Request, Response, Status};
+
+pub struct StorageApiHandler {
+    dispatcher_tx: mpsc::Sender<Command>,
+}
+
+impl StorageApiHandler {
+    pub fn new(dispatcher_tx: mpsc::Sender<Command>) -> Self {
+        Self { dispatcher_tx }
+    }
+}
+
+async fn dispatch_and_wait<T, E>(
+    dispatcher: &mpsc::Sender<Command>,
+    command_constructor: impl FnOnce(oneshot::Sender<Result<T, E>>) -> Command,
+) -> Result<Response<T>, Status>
+where
+    E: Into<Status>,
+{
+    let (resp_tx, resp_rx) = oneshot::channel();
+    let cmd = command_constructor(resp_tx);
+
+    dispatcher
+        .send(cmd)
+        .await
+        .map_err(|e| Status::internal(format!("Failed to send command to dispatcher: {e}")))?;
+
+    match resp_rx.await {
+        Ok(Ok(result)) => Ok(Response::new(result)),
+        Ok(Err(e)) => Err(e.into()),
+        Err(_) => Err(Status::internal(
+            "Dispatcher task dropped response channel.",
+        )),
+    }
+}
+
+#[tonic::async_trait]
+impl StorageService for StorageApiHandler {
+    type WatchDiskUsageAlertsStream =
+        Pin<Box<dyn Stream<Item = Result<DiskUsageAlert, Status>> + Send>>;
+
+    async fn create_pool(
+        &self,
+        request: Request<CreatePoolRequest>,
+    ) -> Result<Response<CreatePoolResponse>, Status> {
+        info!("StorageApi: Received CreatePool request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::CreatePool(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn list_pools(
+        &self,
+        request: Request<ListPoolsRequest>,
+    ) -> Result<Response<ListPoolsResponse>, Status> {
+        info!("StorageApi: Received ListPools request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::ListPools(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn delete_pool(
+        &self,
+        request: Request<DeletePoolRequest>,
+    ) -> Result<Response<DeletePoolResponse>, Status> {
+        info!("StorageApi: Received DeletePool request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::DeletePool(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn resize_pool(
+        &self,
+        request: Request<ResizePoolRequest>,
+    ) -> Result<Response<ResizePoolResponse>, Status> {
+        info!("StorageApi: Received ResizePool request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::ResizePool(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn create_volume(
+        &self,
+        request: Request<CreateVolumeRequest>,
+    ) -> Result<Response<CreateVolumeResponse>, Status> {
+        info!("StorageApi: Received CreateVolume request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::CreateVolume(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn list_volumes(
+        &self,
+        request: Request<ListVolumesRequest>,
+    ) -> Result<Response<ListVolumesResponse>, Status> {
+        info!("StorageApi: Received ListVolumes request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::ListVolumes(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn delete_volume(
+        &self,
+        request: Request<DeleteVolumeRequest>,
+    ) -> Result<Response<DeleteVolumeResponse>, Status> {
+        info!("StorageApi: Received DeleteVolume request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::DeleteVolume(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn resize_volume(
+        &self,
+        request: Request<ResizeVolumeRequest>,
+    ) -> Result<Response<ResizeVolumeResponse>, Status> {
+        info!("StorageApi: Received ResizeVolume request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::ResizeVolume(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn open_volume(
+        &self,
+        request: Request<OpenVolumeRequest>,
+    ) -> Result<Response<OpenVolumeResponse>, Status> {
+        info!("StorageApi: Received OpenVolume request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::OpenVolume(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn close_volume(
+        &self,
+        request: Request<CloseVolumeRequest>,
+    ) -> Result<Response<CloseVolumeResponse>, Status> {
+        info!("StorageApi: Received CloseVolume request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::CloseVolume(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn get_disk_usage(
+        &self,
+        request: Request<GetDiskUsageRequest>,
+    ) -> Result<Response<GetDiskUsageResponse>, Status> {
+        info!("StorageApi: Received GetDiskUsage request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::GetDiskUsage(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn watch_disk_usage_alerts(
+        &self,
+        request: Request<WatchDiskUsageAlertsRequest>,
+    ) -> Result<Response<Self::WatchDiskUsageAlertsStream>, Status> {
+        info!("StorageApi: Received WatchDiskUsageAlerts stream request.");
+        let (stream_tx, stream_rx) = mpsc::channel(16);
+        let cmd = Command::WatchDiskUsageAlerts(request.into_inner(), stream_tx);
+        self.dispatcher_tx
+            .send(cmd)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to send command to dispatcher: {e}")))?;
+        Ok(Response::new(Box::pin(ReceiverStream::new(stream_rx))))
+    }
+}
@@ -0,0 +1,210 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{BackendError, PoolBackend, PoolUsage};
+use feos_proto::storage_service::VolumeKind;
+use log::info;
+use nix::sys::statvfs::statvfs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use uuid::Uuid;
+
+/// A pool backed by a plain directory. VM disks are sparse raw image files and
+/// container volumes are subdirectories.
+pub struct DirectoryBackend {
+    path: PathBuf,
+    size_bytes: u64,
+}
+
+/// Bytes allocated on disk for `path` and, for directories, everything below
+/// it. Sparse regions of files are not counted.
+fn disk_usage(path: &Path) -> std::io::Result<u64> {
+    let metadata = std::fs::symlink_metadata(path)?;
+    let mut total = metadata.blocks() * 512;
+    if metadata.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            total += disk_usage(&entry?.path())?;
+        }
+    }
+    Ok(total)
+}
+
+impl DirectoryBackend {
+    pub fn new(path: &str, size_bytes: u64) -> Result<Self, BackendError> {
+        let path = PathBuf::from(path);
+        if !path.is_absolute() {
+            return Err(BackendError::InvalidConfig(format!(
+                "Pool directory '{}' must be an absolute path",
+                path.display()
+            )));
+        }
+        Ok(Self { path, size_bytes })
+    }
+
+    /// Rejects paths outside the pool directory, so a corrupted record can
+    /// never make the backend delete unrelated files.
+    fn check_volume_path(&self, path: &Path) -> Result<(), BackendError> {
+        if path.parent() != Some(self.path.as_path()) {
+            return Err(BackendError::InvalidConfig(format!(
+                "'{}' is not a volume of pool directory '{}'",
+                path.display(),
+                self.path.display()
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[tonic::async_trait]
+impl PoolBackend for DirectoryBackend {
+    async fn create(&self) -> Result<bool, BackendError> {
+        if fs::try_exists(&self.path).await? {
+            if !fs::metadata(&self.path).await?.is_dir() {
+                return Err(BackendError::InvalidConfig(format!(
+                    "'{}' exists but is not a directory",
+                    self.path.display()
+                )));
+            }
+            info!(
+                "Directory: Adopting existing pool directory {}",
+                self.path.display()
+            );
+            return Ok(false);
+        }
+        info!("Directory: Creating pool directory {}", self.path.display());
+        fs::create_dir_all(&self.path).await?;
+        Ok(true)
+    }
+
+    async fn destroy(&self) -> Result<(), BackendError> {
+        // Fails instead of deleting anything that was put there behind our back.
+        fs::remove_dir(&self.path).await?;
+        Ok(())
+    }
+
+    async fn resize(&self, _size_bytes: u64) -> Result<(), BackendError> {
+        // The limit is only enforced when provisioning volumes.
+        Ok(())
+    }
+
+    async fn usage(&self) -> Result<PoolUsage, BackendError> {
+        let path = self.path.clone();
+        let used_bytes = tokio::task::spawn_blocking(move || disk_usage(&path))
+            .await
+            .map_err(|e| BackendError::CommandFailed(e.to_string()))??;
+        let capacity_bytes = if self.size_bytes > 0 {
+            self.size_bytes
+        } else {
+            let stat = statvfs(&self.path).map_err(std::io::Error::from)?;
+            stat.blocks() as u64 * stat.fragment_size() as u64
+        };
+        Ok(PoolUsage {
+            capacity_bytes,
+            used_bytes,
+        })
+    }
+
+    async fn create_volume(
+        &self,
+        volume_id: Uuid,
+        kind: VolumeKind,
+        size_bytes: u64,
+    ) -> Result<PathBuf, BackendError> {
+        match kind {
+            VolumeKind::ContainerVolume => {
+                let path = self.path.join(volume_id.to_string());
+                fs::create_dir(&path).await?;
+                Ok(path)
+            }
+            _ => {
+                let path = self.path.join(format!("{volume_id}.img"));
+                let file = fs::File::create_new(&path).await?;
+                file.set_len(size_bytes).await?;
+                Ok(path)
+            }
+        }
+    }
+
+    async fn delete_volume(&self, path: &Path, kind: VolumeKind) -> Result<(), BackendError> {
+        self.check_volume_path(path)?;
+        match kind {
+            VolumeKind::ContainerVolume => fs::remove_dir_all(path).await?,
+            _ => fs::remove_file(path).await?,
+        }
+        Ok(())
+    }
+
+    async fn resize_volume(
+        &self,
+        path: &Path,
+        kind: VolumeKind,
+        size_bytes: u64,
+    ) -> Result<(), BackendError> {
+        self.check_volume_path(path)?;
+        if kind != VolumeKind::ContainerVolume {
+            let file = fs::OpenOptions::new().write(true).open(path).await?;
+            file.set_len(size_bytes).await?;
+        }
+        Ok(())
+    }
+
+    async fn volume_usage(&self, path: &Path, _kind: VolumeKind) -> Result<u64, BackendError> {
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || disk_usage(&path))
+            .await
+            .map_err(|e| BackendError::CommandFailed(e.to_string()))?
+            .map_err(BackendError::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn vm_disks_are_sparse_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let pool_path = dir.path().join("pool");
+        let backend = DirectoryBackend::new(pool_path.to_str().unwrap(), 0).unwrap();
+        assert!(backend.create().await.unwrap());
+        assert!(!backend.create().await.unwrap());
+
+        let path = backend
+            .create_volume(Uuid::new_v4(), VolumeKind::VmDisk, 64 << 20)
+            .await
+            .unwrap();
+        assert_eq!(fs::metadata(&path).await.unwrap().len(), 64 << 20);
+        assert!(
+            backend
+                .volume_usage(&path, VolumeKind::VmDisk)
+                .await
+                .unwrap()
+                < 64 << 20
+        );
+
+        backend
+            .resize_volume(&path, VolumeKind::VmDisk, 128 << 20)
+            .await
+            .unwrap();
+        assert_eq!(fs::metadata(&path).await.unwrap().len(), 128 << 20);
+
+        backend
+            .delete_volume(&path, VolumeKind::VmDisk)
+            .await
+            .unwrap();
+        backend.destroy().await.unwrap();
+        assert!(!pool_path.exists());
+    }
+
+    #[tokio::test]
+    async fn volumes_outside_the_pool_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = DirectoryBackend::new(dir.path().to_str().unwrap(), 0).unwrap();
+        let outside = dir.path().parent().unwrap().join("other.img");
+        assert!(backend
+            .delete_volume(&outside, VolumeKind::VmDisk)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,275 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{run, BackendError, PoolBackend, PoolUsage};
+use feos_proto::storage_service::VolumeKind;
+use log::{info, warn};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+const VOLUME_PREFIX: &str = "feos-";
+
+/// A pool backed by an LVM thin pool. Every volume is a thin logical volume;
+/// container volumes additionally get an ext4 filesystem.
+pub struct LvmThinBackend {
+    volume_group: String,
+    thin_pool: String,
+    size_bytes: u64,
+}
+
+/// One line of `lvs -o lv_size,data_percent,segtype` output.
+#[derive(Debug, PartialEq)]
+struct LvReport {
+    size_bytes: u64,
+    data_percent: f64,
+    segtype: String,
+}
+
+fn validate_lvm_name(kind: &str, name: &str) -> Result<(), BackendError> {
+    let valid_chars = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '_' | '.' | '-'));
+    if name.is_empty() || name.starts_with('-') || !valid_chars {
+        return Err(BackendError::InvalidConfig(format!(
+            "Invalid LVM {kind} name '{name}'"
+        )));
+    }
+    Ok(())
+}
+
+fn parse_lv_report(output: &str) -> Result<LvReport, BackendError> {
+    let line = output
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .ok_or_else(|| BackendError::CommandFailed("lvs returned no output".to_string()))?;
+    let mut fields = line.split_whitespace();
+    let size_bytes = fields
+        .next()
+        .and_then(|size| size.parse().ok())
+        .ok_or_else(|| BackendError::CommandFailed(format!("Unexpected lvs output: {line}")))?;
+    let (data_percent, segtype) = match (fields.next(), fields.next()) {
+        (Some(percent), Some(segtype)) => (percent.parse().unwrap_or(0.0), segtype),
+        // data_percent is empty for logical volumes without thin data.
+        (Some(segtype), None) => (0.0, segtype),
+        _ => {
+            return Err(BackendError::CommandFailed(format!(
+                "Unexpected lvs output: {line}"
+            )))
+        }
+    };
+    Ok(LvReport {
+        size_bytes,
+        data_percent,
+        segtype: segtype.to_string(),
+    })
+}
+
+impl LvmThinBackend {
+    pub fn new(volume_group: &str, thin_pool: &str, size_bytes: u64) -> Result<Self, BackendError> {
+        validate_lvm_name("volume group", volume_group)?;
+        validate_lvm_name("thin pool", thin_pool)?;
+        Ok(Self {
+            volume_group: volume_group.to_string(),
+            thin_pool: thin_pool.to_string(),
+            size_bytes,
+        })
+    }
+
+    fn pool_lv(&self) -> String {
+        format!("{}/{}", self.volume_group, self.thin_pool)
+    }
+
+    /// The `vg/lv` name of the volume behind a device path like `/dev/vg/lv`.
+    fn volume_lv(&self, path: &Path) -> Result<String, BackendError> {
+        let lv = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .filter(|name| name.starts_with(VOLUME_PREFIX))
+            .ok_or_else(|| {
+                BackendError::InvalidConfig(format!(
+                    "'{}' is not a FeOS thin volume",
+                    path.display()
+                ))
+            })?;
+        Ok(format!("{}/{lv}", self.volume_group))
+    }
+
+    async fn report(&self, lv: &str) -> Result<LvReport, BackendError> {
+        let output = run(
+            "lvs",
+            &[
+                "--noheadings",
+                "--nosuffix",
+                "--units",
+                "b",
+                "-o",
+                "lv_size,data_percent,segtype",
+                lv,
+            ],
+        )
+        .await?;
+        parse_lv_report(&output)
+    }
+}
+
+#[tonic::async_trait]
+impl PoolBackend for LvmThinBackend {
+    async fn create(&self) -> Result<bool, BackendError> {
+        let pool_lv = self.pool_lv();
+        if let Ok(report) = self.report(&pool_lv).await {
+            if report.segtype != "thin-pool" {
+                return Err(BackendError::InvalidConfig(format!(
+                    "Logical volume {pool_lv} exists but is a {} volume, not a thin pool",
+                    report.segtype
+                )));
+            }
+            info!("LVM: Adopting existing thin pool {pool_lv}");
+            return Ok(false);
+        }
+        if self.size_bytes == 0 {
+            return Err(BackendError::InvalidConfig(format!(
+                "Thin pool {pool_lv} does not exist and no size was given to create it"
+            )));
+        }
+        info!(
+            "LVM: Creating thin pool {pool_lv} with {} bytes",
+            self.size_bytes
+        );
+        let size = format!("{}b", self.size_bytes);
+        run(
+            "lvcreate",
+            &[
+                "--yes",
+                "--type",
+                "thin-pool",
+                "-L",
+                &size,
+                "-n",
+                &self.thin_pool,
+                &self.volume_group,
+            ],
+        )
+        .await?;
+        Ok(true)
+    }
+
+    async fn destroy(&self) -> Result<(), BackendError> {
+        info!("LVM: Removing thin pool {}", self.pool_lv());
+        run("lvremove", &["--yes", &self.pool_lv()]).await?;
+        Ok(())
+    }
+
+    async fn resize(&self, size_bytes: u64) -> Result<(), BackendError> {
+        let size = format!("{size_bytes}b");
+        run("lvextend", &["-L", &size, &self.pool_lv()]).await?;
+        Ok(())
+    }
+
+    async fn usage(&self) -> Result<PoolUsage, BackendError> {
+        let report = self.report(&self.pool_lv()).await?;
+        Ok(PoolUsage {
+            capacity_bytes: report.size_bytes,
+            used_bytes: (report.size_bytes as f64 * report.data_percent / 100.0) as u64,
+        })
+    }
+
+    async fn create_volume(
+        &self,
+        volume_id: Uuid,
+        kind: VolumeKind,
+        size_bytes: u64,
+    ) -> Result<PathBuf, BackendError> {
+        let lv_name = format!("{VOLUME_PREFIX}{volume_id}");
+        let size = format!("{size_bytes}b");
+        run(
+            "lvcreate",
+            &[
+                "--yes",
+                "--type",
+                "thin",
+                "-V",
+                &size,
+                "-n",
+                &lv_name,
+                "--thinpool",
+                &self.thin_pool,
+                &self.volume_group,
+            ],
+        )
+        .await?;
+        let path = PathBuf::from("/dev")
+            .join(&self.volume_group)
+            .join(&lv_name);
+
+        if kind == VolumeKind::ContainerVolume {
+            let device = path.to_string_lossy();
+            if let Err(e) = run("mkfs.ext4", &["-q", "-F", &device]).await {
+                let lv = format!("{}/{lv_name}", self.volume_group);
+                if let Err(cleanup) = run("lvremove", &["--yes", &lv]).await {
+                    warn!("LVM: Failed to remove {lv} after mkfs failed: {cleanup}");
+                }
+                return Err(e);
+            }
+        }
+        Ok(path)
+    }
+
+    async fn delete_volume(&self, path: &Path, _kind: VolumeKind) -> Result<(), BackendError> {
+        run("lvremove", &["--yes", &self.volume_lv(path)?]).await?;
+        Ok(())
+    }
+
+    async fn resize_volume(
+        &self,
+        path: &Path,
+        kind: VolumeKind,
+        size_bytes: u64,
+    ) -> Result<(), BackendError> {
+        let size = format!("{size_bytes}b");
+        run("lvextend", &["-L", &size, &self.volume_lv(path)?]).await?;
+        if kind == VolumeKind::ContainerVolume {
+            run("resize2fs", &[&path.to_string_lossy()]).await?;
+        }
+        Ok(())
+    }
+
+    async fn volume_usage(&self, path: &Path, _kind: VolumeKind) -> Result<u64, BackendError> {
+        let report = self.report(&self.volume_lv(path)?).await?;
+        Ok((report.size_bytes as f64 * report.data_percent / 100.0) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_thin_pool_report() {
+        let report = parse_lv_report("  10737418240 12.50 thin-pool\n").unwrap();
+        assert_eq!(
+            report,
+            LvReport {
+                size_bytes: 10737418240,
+                data_percent: 12.5,
+                segtype: "thin-pool".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_report_without_data_percent() {
+        let report = parse_lv_report("  4194304   linear").unwrap();
+        assert_eq!(report.size_bytes, 4194304);
+        assert_eq!(report.data_percent, 0.0);
+        assert_eq!(report.segtype, "linear");
+    }
+
+    #[test]
+    fn lvm_names_are_validated() {
+        assert!(LvmThinBackend::new("vg0", "feos-pool", 0).is_ok());
+        assert!(LvmThinBackend::new("vg0", "-pool", 0).is_err());
+        assert!(LvmThinBackend::new("vg/0", "pool", 0).is_err());
+        assert!(LvmThinBackend::new("", "pool", 0).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use feos_proto::storage_service::{pool_config, PoolConfig, VolumeKind};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command as TokioCommand;
+use uuid::Uuid;
+
+pub mod directory;
+pub mod lvm;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BackendError {
+    #[error("The provided pool configuration is invalid: {0}")]
+    InvalidConfig(String),
+
+    #[error("Storage command failed: {0}")]
+    CommandFailed(String),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Capacity and actual usage of a pool in bytes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PoolUsage {
+    pub capacity_bytes: u64,
+    pub used_bytes: u64,
+}
+
+#[tonic::async_trait]
+pub trait PoolBackend: Send + Sync {
+    /// Prepares the backing thin pool or directory. Returns `true` if it was
+    /// created, or `false` if an existing one was adopted.
+    async fn create(&self) -> Result<bool, BackendError>;
+
+    /// Removes the backing thin pool or directory. Only called for pools whose
+    /// backing storage was created by `create`.
+    async fn destroy(&self) -> Result<(), BackendError>;
+
+    async fn resize(&self, size_bytes: u64) -> Result<(), BackendError>;
+
+    async fn usage(&self) -> Result<PoolUsage, BackendError>;
+
+    /// Provisions a volume and returns the host path backing it.
+    async fn create_volume(
+        &self,
+        volume_id: Uuid,
+        kind: VolumeKind,
+        size_bytes: u64,
+    ) -> Result<PathBuf, BackendError>;
+
+    async fn delete_volume(&self, path: &Path, kind: VolumeKind) -> Result<(), BackendError>;
+
+    async fn resize_volume(
+        &self,
+        path: &Path,
+        kind: VolumeKind,
+        size_bytes: u64,
+    ) -> Result<(), BackendError>;
+
+    /// Space actually consumed by the volume in bytes.
+    async fn volume_usage(&self, path: &Path, kind: VolumeKind) -> Result<u64, BackendError>;
+}
+
+pub fn for_pool(config: &PoolConfig) -> Result<Box<dyn PoolBackend>, BackendError> {
+    match &config.backend {
+        Some(pool_config::Backend::LvmThin(lvm)) => Ok(Box::new(lvm::LvmThinBackend::new(
+            &lvm.volume_group,
+            &lvm.thin_pool,
+            config.size_bytes,
+        )?)),
+        Some(pool_config::Backend::Directory(dir)) => Ok(Box::new(
+            directory::DirectoryBackend::new(&dir.path, config.size_bytes)?,
+        )),
+        None => Err(BackendError::InvalidConfig(
+            "A pool backend must be specified".to_string(),
+        )),
+    }
+}
+
+/// Runs a storage tool and returns its stdout, turning a non-zero exit status
+/// into an error carrying its stderr.
+pub(crate) async fn run(program: &str, args: &[&str]) -> Result<String, BackendError> {
+    let stdout = run_with_input(program, args, &[]).await?;
+    Ok(String::from_utf8_lossy(&stdout).into_owned())
+}
+
+/// Like [`run`], but writes `input` to the tool's stdin and returns its raw
+/// stdout. Used to hand keys to tools without putting them on the command
+/// line or on disk.
+pub(crate) async fn run_with_input(
+    program: &str,
+    args: &[&str],
+    input: &[u8],
+) -> Result<Vec<u8>, BackendError> {
+    let mut child = TokioCommand::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| BackendError::CommandFailed(format!("Failed to run {program}: {e}")))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input).await?;
+    }
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        return Err(BackendError::CommandFailed(format!(
+            "{program} {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(output.stdout)
+}
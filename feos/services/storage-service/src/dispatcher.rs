@@ -0,0 +1,627 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    backend::{self, PoolBackend},
+    encryption::{self, tpm, tpm::SealedKey},
+    error::StorageServiceError,
+    persistence::{repository::StorageRepository, PoolRecord, VolumeRecord},
+    usage::{DiskUsage, UsageConfig},
+    Command,
+};
+use feos_proto::storage_service::{
+    encryption_config::KeySource, pool_config, CloseVolumeRequest, CloseVolumeResponse,
+    CreatePoolRequest, CreatePoolResponse, CreateVolumeRequest, CreateVolumeResponse,
+    DeletePoolRequest, DeletePoolResponse, DeleteVolumeRequest, DeleteVolumeResponse,
+    DiskUsageAlert, ListPoolsResponse, ListVolumesRequest, ListVolumesResponse, OpenVolumeRequest,
+    OpenVolumeResponse, PoolInfo, ResizePoolRequest, ResizePoolResponse, ResizeVolumeRequest,
+    ResizeVolumeResponse, VolumeEncryption, VolumeInfo, VolumeKind,
+};
+use feos_utils::namespace::namespace_or_default;
+use log::{info, warn};
+use std::path::Path;
+use tokio::sync::{broadcast, mpsc};
+use tonic::Status;
+use uuid::Uuid;
+
+pub struct Dispatcher {
+    rx: mpsc::Receiver<Command>,
+    repository: StorageRepository,
+    disk_usage: DiskUsage,
+}
+
+fn parse_id(kind: &str, id_str: &str) -> Result<Uuid, StorageServiceError> {
+    Uuid::parse_str(id_str)
+        .map_err(|_| StorageServiceError::InvalidArgument(format!("Invalid {kind} UUID format")))
+}
+
+fn is_directory_pool(pool: &PoolRecord) -> bool {
+    matches!(
+        pool.config.backend,
+        Some(pool_config::Backend::Directory(_))
+    )
+}
+
+/// Formats a new volume as LUKS2 if encryption was requested. TPM keys are
+/// sealed before formatting, so a host without a usable TPM fails early.
+async fn encrypt_volume(
+    path: &Path,
+    key_source: Option<KeySource>,
+) -> Result<(VolumeEncryption, Option<SealedKey>), StorageServiceError> {
+    match key_source {
+        None => Ok((VolumeEncryption::None, None)),
+        Some(KeySource::Tpm(_)) => {
+            let key = encryption::generate_key().await?;
+            let sealed_key = tpm::seal(&key).await?;
+            encryption::format(path, &key).await?;
+            Ok((VolumeEncryption::Tpm, Some(sealed_key)))
+        }
+        Some(KeySource::Key(key)) => {
+            encryption::format(path, &key).await?;
+            Ok((VolumeEncryption::Key, None))
+        }
+    }
+}
+
+async fn cleanup_volume(backend: &dyn PoolBackend, path: &Path, kind: VolumeKind, volume_id: Uuid) {
+    if let Err(e) = backend.delete_volume(path, kind).await {
+        warn!("Dispatcher: Failed to clean up volume {volume_id}: {e}");
+    }
+}
+
+async fn forward_disk_usage_alerts(
+    mut alerts: broadcast::Receiver<DiskUsageAlert>,
+    stream_tx: mpsc::Sender<Result<DiskUsageAlert, Status>>,
+) {
+    loop {
+        let alert = match alerts.recv().await {
+            Ok(alert) => alert,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                warn!("Dispatcher: Disk usage alert stream lagged, skipped {skipped} alerts");
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+        if stream_tx.send(Ok(alert)).await.is_err() {
+            info!("Dispatcher: Disk usage alert client disconnected.");
+            break;
+        }
+    }
+}
+
+fn ensure_closed(volume: &VolumeRecord) -> Result<(), StorageServiceError> {
+    if encryption::is_open(volume.volume_id) {
+        return Err(StorageServiceError::InvalidState(format!(
+            "Volume '{}' is open, close it first",
+            volume.volume_id
+        )));
+    }
+    Ok(())
+}
+
+impl Dispatcher {
+    pub async fn new(
+        rx: mpsc::Receiver<Command>,
+        db_url: &str,
+        usage_config: UsageConfig,
+    ) -> Result<Self, StorageServiceError> {
+        info!("Dispatcher: Connecting to persistence layer at {db_url}...");
+        let repository = StorageRepository::connect(db_url).await?;
+        info!("Dispatcher: Persistence layer connected successfully.");
+
+        let disk_usage = DiskUsage::new(
+            repository.clone(),
+            usage_config.sources,
+            usage_config.thresholds,
+        );
+        tokio::spawn(disk_usage.clone().monitor(usage_config.image_gc));
+        Ok(Self {
+            rx,
+            repository,
+            disk_usage,
+        })
+    }
+
+    /// Handles commands one at a time, so that capacity checks and changes to
+    /// the backing storage never race with each other.
+    pub async fn run(mut self) {
+        info!("Dispatcher: Running and waiting for commands.");
+        while let Some(cmd) = self.rx.recv().await {
+            self.handle_command(cmd).await;
+        }
+        info!("Dispatcher: Channel closed, shutting down.");
+    }
+
+    async fn handle_command(&self, cmd: Command) {
+        match cmd {
+            Command::CreatePool(req, responder) => {
+                let _ = responder.send(self.create_pool(req).await);
+            }
+            Command::ListPools(_req, responder) => {
+                let _ = responder.send(self.list_pools().await);
+            }
+            Command::DeletePool(req, responder) => {
+                let _ = responder.send(self.delete_pool(req).await);
+            }
+            Command::ResizePool(req, responder) => {
+                let _ = responder.send(self.resize_pool(req).await);
+            }
+            Command::CreateVolume(req, responder) => {
+                let _ = responder.send(self.create_volume(req).await);
+            }
+            Command::ListVolumes(req, responder) => {
+                let _ = responder.send(self.list_volumes(req).await);
+            }
+            Command::DeleteVolume(req, responder) => {
+                let _ = responder.send(self.delete_volume(req).await);
+            }
+            Command::ResizeVolume(req, responder) => {
+                let _ = responder.send(self.resize_volume(req).await);
+            }
+            Command::OpenVolume(req, responder) => {
+                let _ = responder.send(self.open_volume(req).await);
+            }
+            Command::CloseVolume(req, responder) => {
+                let _ = responder.send(self.close_volume(req).await);
+            }
+            // Walking the data directories takes a while and changes nothing,
+            // so other commands need not wait for it.
+            Command::GetDiskUsage(_req, responder) => {
+                let disk_usage = self.disk_usage.clone();
+                tokio::spawn(async move {
+                    let _ = responder.send(disk_usage.report().await);
+                });
+            }
+            Command::WatchDiskUsageAlerts(_req, stream_tx) => {
+                tokio::spawn(forward_disk_usage_alerts(
+                    self.disk_usage.subscribe(),
+                    stream_tx,
+                ));
+            }
+        }
+    }
+
+    async fn get_pool_record(&self, id_str: &str) -> Result<PoolRecord, StorageServiceError> {
+        let pool_id = parse_id("pool", id_str)?;
+        self.repository
+            .get_pool(pool_id)
+            .await?
+            .ok_or_else(|| StorageServiceError::NotFound(format!("Pool '{id_str}' not found")))
+    }
+
+    async fn get_volume_record(&self, id_str: &str) -> Result<VolumeRecord, StorageServiceError> {
+        let volume_id = parse_id("volume", id_str)?;
+        self.repository
+            .get_volume(volume_id)
+            .await?
+            .ok_or_else(|| StorageServiceError::NotFound(format!("Volume '{id_str}' not found")))
+    }
+
+    async fn create_pool(
+        &self,
+        req: CreatePoolRequest,
+    ) -> Result<CreatePoolResponse, StorageServiceError> {
+        let config = req.config.ok_or_else(|| {
+            StorageServiceError::InvalidArgument("PoolConfig is required".to_string())
+        })?;
+        if config.name.is_empty() {
+            return Err(StorageServiceError::InvalidArgument(
+                "Pool name must not be empty".to_string(),
+            ));
+        }
+        if self.repository.pool_name_exists(&config.name).await? {
+            return Err(StorageServiceError::AlreadyExists(format!(
+                "Pool '{}' already exists",
+                config.name
+            )));
+        }
+
+        let backend = backend::for_pool(&config)?;
+        let owned = backend.create().await?;
+        let record = PoolRecord {
+            pool_id: Uuid::new_v4(),
+            config,
+            owned,
+        };
+        if let Err(e) = self.repository.save_pool(&record).await {
+            if owned {
+                if let Err(cleanup) = backend.destroy().await {
+                    warn!("Dispatcher: Failed to clean up pool storage: {cleanup}");
+                }
+            }
+            return Err(e.into());
+        }
+        info!(
+            "Dispatcher: Created pool '{}' ({})",
+            record.config.name, record.pool_id
+        );
+        Ok(CreatePoolResponse {
+            pool_id: record.pool_id.to_string(),
+        })
+    }
+
+    async fn list_pools(&self) -> Result<ListPoolsResponse, StorageServiceError> {
+        let volumes = self.repository.list_volumes(None).await?;
+        let mut pools = Vec::new();
+        for record in self.repository.list_pools().await? {
+            let pool_volumes = volumes.iter().filter(|v| v.pool_id == record.pool_id);
+            let allocated_bytes = pool_volumes.clone().map(|v| v.size_bytes).sum();
+            let volume_count = pool_volumes.count() as u32;
+            // An unavailable pool is still listed, so it can be inspected and deleted.
+            let usage = match backend::for_pool(&record.config) {
+                Ok(backend) => backend.usage().await,
+                Err(e) => Err(e),
+            }
+            .unwrap_or_else(|e| {
+                warn!(
+                    "Dispatcher: Failed to get usage of pool {}: {e}",
+                    record.pool_id
+                );
+                Default::default()
+            });
+            pools.push(PoolInfo {
+                pool_id: record.pool_id.to_string(),
+                config: Some(record.config),
+                capacity_bytes: usage.capacity_bytes,
+                used_bytes: usage.used_bytes,
+                allocated_bytes,
+                volume_count,
+            });
+        }
+        Ok(ListPoolsResponse { pools })
+    }
+
+    async fn delete_pool(
+        &self,
+        req: DeletePoolRequest,
+    ) -> Result<DeletePoolResponse, StorageServiceError> {
+        let record = self.get_pool_record(&req.pool_id).await?;
+        let volumes = self.repository.list_volumes(Some(record.pool_id)).await?;
+        if !volumes.is_empty() {
+            return Err(StorageServiceError::InvalidState(format!(
+                "Pool '{}' still contains {} volume(s)",
+                record.config.name,
+                volumes.len()
+            )));
+        }
+        if record.owned {
+            backend::for_pool(&record.config)?.destroy().await?;
+        }
+        self.repository.delete_pool(record.pool_id).await?;
+        info!("Dispatcher: Deleted pool {}", record.pool_id);
+        Ok(DeletePoolResponse {})
+    }
+
+    async fn resize_pool(
+        &self,
+        req: ResizePoolRequest,
+    ) -> Result<ResizePoolResponse, StorageServiceError> {
+        let mut record = self.get_pool_record(&req.pool_id).await?;
+        let backend = backend::for_pool(&record.config)?;
+        let current = if is_directory_pool(&record) {
+            // Limiting an unlimited directory pool only needs room for its volumes.
+            match record.config.size_bytes {
+                0 => self
+                    .allocated_bytes(record.pool_id)
+                    .await?
+                    .saturating_sub(1),
+                limit => limit,
+            }
+        } else {
+            backend.usage().await?.capacity_bytes
+        };
+        if req.size_bytes <= current {
+            return Err(StorageServiceError::InvalidArgument(format!(
+                "New size {} must be larger than the current size {current}",
+                req.size_bytes
+            )));
+        }
+        backend.resize(req.size_bytes).await?;
+        record.config.size_bytes = req.size_bytes;
+        self.repository.save_pool(&record).await?;
+        info!(
+            "Dispatcher: Resized pool {} to {} bytes",
+            record.pool_id, req.size_bytes
+        );
+        Ok(ResizePoolResponse {})
+    }
+
+    /// Sum of the provisioned sizes of all volumes in a pool.
+    async fn allocated_bytes(&self, pool_id: Uuid) -> Result<u64, StorageServiceError> {
+        Ok(self
+            .repository
+            .list_volumes(Some(pool_id))
+            .await?
+            .iter()
+            .map(|v| v.size_bytes)
+            .sum())
+    }
+
+    /// Rejects growing a directory pool's volumes beyond its size limit. Thin
+    /// pools may be overcommitted.
+    async fn check_pool_limit(
+        &self,
+        pool: &PoolRecord,
+        additional_bytes: u64,
+    ) -> Result<(), StorageServiceError> {
+        if !is_directory_pool(pool) || pool.config.size_bytes == 0 {
+            return Ok(());
+        }
+        let allocated = self.allocated_bytes(pool.pool_id).await?;
+        if allocated + additional_bytes > pool.config.size_bytes {
+            return Err(StorageServiceError::InvalidState(format!(
+                "Pool '{}' has {} of {} bytes allocated, {additional_bytes} more do not fit",
+                pool.config.name, allocated, pool.config.size_bytes
+            )));
+        }
+        Ok(())
+    }
+
+    async fn create_volume(
+        &self,
+        req: CreateVolumeRequest,
+    ) -> Result<CreateVolumeResponse, StorageServiceError> {
+        let pool = self.get_pool_record(&req.pool_id).await?;
+        let namespace = namespace_or_default(&req.namespace)
+            .map_err(|e| StorageServiceError::InvalidArgument(e.to_string()))?;
+        if req.name.is_empty() {
+            return Err(StorageServiceError::InvalidArgument(
+                "Volume name must not be empty".to_string(),
+            ));
+        }
+        if req.size_bytes == 0 {
+            return Err(StorageServiceError::InvalidArgument(
+                "Volume size must be greater than zero".to_string(),
+            ));
+        }
+        let kind = VolumeKind::try_from(req.kind).unwrap_or(VolumeKind::Unspecified);
+        if kind == VolumeKind::Unspecified {
+            return Err(StorageServiceError::InvalidArgument(
+                "Volume kind must be specified".to_string(),
+            ));
+        }
+        let key_source = req.encryption.and_then(|config| config.key_source);
+        if key_source.is_some() && kind != VolumeKind::VmDisk {
+            return Err(StorageServiceError::InvalidArgument(
+                "Only VM disks can be encrypted".to_string(),
+            ));
+        }
+        if matches!(&key_source, Some(KeySource::Key(key)) if key.is_empty()) {
+            return Err(StorageServiceError::InvalidArgument(
+                "Encryption key must not be empty".to_string(),
+            ));
+        }
+        let volumes = self.repository.list_volumes(Some(pool.pool_id)).await?;
+        if volumes
+            .iter()
+            .any(|v| v.namespace == namespace && v.name == req.name)
+        {
+            return Err(StorageServiceError::AlreadyExists(format!(
+                "Volume '{}' already exists in pool '{}' and namespace '{namespace}'",
+                req.name, pool.config.name
+            )));
+        }
+        self.check_pool_limit(&pool, req.size_bytes).await?;
+
+        let backend = backend::for_pool(&pool.config)?;
+        let volume_id = Uuid::new_v4();
+        let path = backend
+            .create_volume(volume_id, kind, req.size_bytes)
+            .await?;
+        let (encryption, sealed_key) = match encrypt_volume(&path, key_source).await {
+            Ok(encryption) => encryption,
+            Err(e) => {
+                cleanup_volume(&*backend, &path, kind, volume_id).await;
+                return Err(e);
+            }
+        };
+        let record = VolumeRecord {
+            volume_id,
+            pool_id: pool.pool_id,
+            namespace,
+            name: req.name,
+            kind,
+            size_bytes: req.size_bytes,
+            path: path.to_string_lossy().into_owned(),
+            encryption,
+            sealed_key,
+        };
+        if let Err(e) = self.repository.save_volume(&record).await {
+            cleanup_volume(&*backend, &path, kind, volume_id).await;
+            return Err(e.into());
+        }
+        info!(
+            "Dispatcher: Created volume '{}' ({volume_id}) at {}",
+            record.name, record.path
+        );
+        Ok(CreateVolumeResponse {
+            volume_id: volume_id.to_string(),
+            path: record.path,
+        })
+    }
+
+    async fn list_volumes(
+        &self,
+        req: ListVolumesRequest,
+    ) -> Result<ListVolumesResponse, StorageServiceError> {
+        let pool_filter = match req.pool_id.as_deref().filter(|id| !id.is_empty()) {
+            Some(id_str) => Some(self.get_pool_record(id_str).await?.pool_id),
+            None => None,
+        };
+        let pools = self.repository.list_pools().await?;
+        let mut volumes = Vec::new();
+        let records = self.repository.list_volumes(pool_filter).await?;
+        let records = records.into_iter().filter(|record| {
+            req.namespace
+                .as_deref()
+                .is_none_or(|namespace| record.namespace == namespace)
+        });
+        for record in records {
+            let backend = pools
+                .iter()
+                .find(|pool| pool.pool_id == record.pool_id)
+                .and_then(|pool| backend::for_pool(&pool.config).ok());
+            let used_bytes = match backend {
+                Some(backend) => backend
+                    .volume_usage(Path::new(&record.path), record.kind)
+                    .await
+                    .unwrap_or_else(|e| {
+                        warn!(
+                            "Dispatcher: Failed to get usage of volume {}: {e}",
+                            record.volume_id
+                        );
+                        0
+                    }),
+                None => 0,
+            };
+            let mapped_path = if record.encryption != VolumeEncryption::None
+                && encryption::is_open(record.volume_id)
+            {
+                encryption::mapped_path(record.volume_id)
+                    .to_string_lossy()
+                    .into_owned()
+            } else {
+                String::new()
+            };
+            volumes.push(VolumeInfo {
+                volume_id: record.volume_id.to_string(),
+                pool_id: record.pool_id.to_string(),
+                namespace: record.namespace,
+                name: record.name,
+                kind: record.kind as i32,
+                size_bytes: record.size_bytes,
+                used_bytes,
+                path: record.path,
+                encryption: record.encryption as i32,
+                mapped_path,
+            });
+        }
+        Ok(ListVolumesResponse { volumes })
+    }
+
+    async fn volume_backend(
+        &self,
+        volume: &VolumeRecord,
+    ) -> Result<(PoolRecord, Box<dyn PoolBackend>), StorageServiceError> {
+        let pool = self
+            .repository
+            .get_pool(volume.pool_id)
+            .await?
+            .ok_or_else(|| {
+                StorageServiceError::NotFound(format!(
+                    "Pool '{}' of volume '{}' not found",
+                    volume.pool_id, volume.volume_id
+                ))
+            })?;
+        let backend = backend::for_pool(&pool.config)?;
+        Ok((pool, backend))
+    }
+
+    async fn delete_volume(
+        &self,
+        req: DeleteVolumeRequest,
+    ) -> Result<DeleteVolumeResponse, StorageServiceError> {
+        let record = self.get_volume_record(&req.volume_id).await?;
+        ensure_closed(&record)?;
+        let (_, backend) = self.volume_backend(&record).await?;
+        backend
+            .delete_volume(Path::new(&record.path), record.kind)
+            .await?;
+        self.repository.delete_volume(record.volume_id).await?;
+        info!("Dispatcher: Deleted volume {}", record.volume_id);
+        Ok(DeleteVolumeResponse {})
+    }
+
+    async fn resize_volume(
+        &self,
+        req: ResizeVolumeRequest,
+    ) -> Result<ResizeVolumeResponse, StorageServiceError> {
+        let mut record = self.get_volume_record(&req.volume_id).await?;
+        if req.size_bytes <= record.size_bytes {
+            return Err(StorageServiceError::InvalidArgument(format!(
+                "New size {} must be larger than the current size {}",
+                req.size_bytes, record.size_bytes
+            )));
+        }
+        // The mapping keeps its size until the volume is opened again.
+        ensure_closed(&record)?;
+        let (pool, backend) = self.volume_backend(&record).await?;
+        self.check_pool_limit(&pool, req.size_bytes - record.size_bytes)
+            .await?;
+        backend
+            .resize_volume(Path::new(&record.path), record.kind, req.size_bytes)
+            .await?;
+        record.size_bytes = req.size_bytes;
+        self.repository.save_volume(&record).await?;
+        info!(
+            "Dispatcher: Resized volume {} to {} bytes",
+            record.volume_id, req.size_bytes
+        );
+        Ok(ResizeVolumeResponse {})
+    }
+
+    async fn open_volume(
+        &self,
+        req: OpenVolumeRequest,
+    ) -> Result<OpenVolumeResponse, StorageServiceError> {
+        let record = self.get_volume_record(&req.volume_id).await?;
+        if record.encryption == VolumeEncryption::None {
+            return Err(StorageServiceError::InvalidArgument(format!(
+                "Volume '{}' is not encrypted",
+                record.volume_id
+            )));
+        }
+        let mapped_path = encryption::mapped_path(record.volume_id);
+        if !encryption::is_open(record.volume_id) {
+            let key = match &record.sealed_key {
+                Some(sealed_key) if req.key.is_empty() => tpm::unseal(sealed_key).await?,
+                Some(_) => {
+                    return Err(StorageServiceError::InvalidArgument(format!(
+                        "Volume '{}' has a TPM-sealed key, no key must be given",
+                        record.volume_id
+                    )))
+                }
+                None if record.encryption == VolumeEncryption::Tpm => {
+                    return Err(StorageServiceError::InvalidState(format!(
+                        "Sealed key of volume '{}' is missing",
+                        record.volume_id
+                    )))
+                }
+                None if req.key.is_empty() => {
+                    return Err(StorageServiceError::InvalidArgument(format!(
+                        "A key is required to open volume '{}'",
+                        record.volume_id
+                    )))
+                }
+                None => req.key,
+            };
+            encryption::open(Path::new(&record.path), record.volume_id, &key).await?;
+            info!(
+                "Dispatcher: Opened volume {} at {}",
+                record.volume_id,
+                mapped_path.display()
+            );
+        }
+        Ok(OpenVolumeResponse {
+            mapped_path: mapped_path.to_string_lossy().into_owned(),
+        })
+    }
+
+    async fn close_volume(
+        &self,
+        req: CloseVolumeRequest,
+    ) -> Result<CloseVolumeResponse, StorageServiceError> {
+        let record = self.get_volume_record(&req.volume_id).await?;
+        if record.encryption == VolumeEncryption::None {
+            return Err(StorageServiceError::InvalidArgument(format!(
+                "Volume '{}' is not encrypted",
+                record.volume_id
+            )));
+        }
+        if encryption::is_open(record.volume_id) {
+            encryption::close(record.volume_id).await?;
+            info!("Dispatcher: Closed volume {}", record.volume_id);
+        }
+        Ok(CloseVolumeResponse {})
+    }
+}
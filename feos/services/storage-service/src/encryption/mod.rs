@@ -0,0 +1,103 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::backend::{run, run_with_input, BackendError};
+use log::info;
+use std::path::{Path, PathBuf};
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+use uuid::Uuid;
+
+pub mod tpm;
+
+/// Size of the random keys generated for TPM-sealed volumes.
+pub const KEY_SIZE: usize = 64;
+
+const MAPPER_DIR: &str = "/dev/mapper";
+
+fn mapper_name(volume_id: Uuid) -> String {
+    format!("feos-{volume_id}")
+}
+
+/// Device with the decrypted contents of a volume while it is open.
+pub fn mapped_path(volume_id: Uuid) -> PathBuf {
+    Path::new(MAPPER_DIR).join(mapper_name(volume_id))
+}
+
+pub fn is_open(volume_id: Uuid) -> bool {
+    mapped_path(volume_id).exists()
+}
+
+pub async fn generate_key() -> Result<Vec<u8>, BackendError> {
+    let mut key = vec![0; KEY_SIZE];
+    File::open("/dev/urandom")
+        .await?
+        .read_exact(&mut key)
+        .await?;
+    Ok(key)
+}
+
+/// Formats a volume as LUKS2. Keys are passed on stdin so they never show up
+/// in the process list.
+pub async fn format(device: &Path, key: &[u8]) -> Result<(), BackendError> {
+    info!("LUKS: Formatting {}", device.display());
+    run_with_input(
+        "cryptsetup",
+        &[
+            "luksFormat",
+            "--batch-mode",
+            "--type",
+            "luks2",
+            "--key-file",
+            "-",
+            &device.to_string_lossy(),
+        ],
+        key,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Unlocks a LUKS2 volume and returns the path of the decrypted device.
+pub async fn open(device: &Path, volume_id: Uuid, key: &[u8]) -> Result<PathBuf, BackendError> {
+    info!(
+        "LUKS: Opening {} as {}",
+        device.display(),
+        mapper_name(volume_id)
+    );
+    run_with_input(
+        "cryptsetup",
+        &[
+            "open",
+            "--type",
+            "luks2",
+            "--key-file",
+            "-",
+            &device.to_string_lossy(),
+            &mapper_name(volume_id),
+        ],
+        key,
+    )
+    .await?;
+    Ok(mapped_path(volume_id))
+}
+
+pub async fn close(volume_id: Uuid) -> Result<(), BackendError> {
+    info!("LUKS: Closing {}", mapper_name(volume_id));
+    run("cryptsetup", &["close", &mapper_name(volume_id)]).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mapped_path_is_unique_per_volume() {
+        let volume_id = Uuid::parse_str("6f1b0a0e-6d5c-4e39-9b8e-0d7c4f4b2a11").unwrap();
+        assert_eq!(
+            mapped_path(volume_id),
+            PathBuf::from("/dev/mapper/feos-6f1b0a0e-6d5c-4e39-9b8e-0d7c4f4b2a11")
+        );
+    }
+}
@@ -0,0 +1,117 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::backend::{run, run_with_input, BackendError};
+use log::warn;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use uuid::Uuid;
+
+/// FeOS runs without a TPM resource manager daemon, so the tools talk to the
+/// kernel's in-kernel resource manager directly.
+const TCTI: &str = "--tcti=device:/dev/tpmrm0";
+
+/// Keys are bound to PCR 7, which measures the Secure Boot policy. Booting
+/// the disks on another host or with Secure Boot disabled leaves them locked.
+const PCR_SELECTION: &str = "sha256:7";
+
+/// A key sealed to the host TPM. Both blobs are encrypted by the TPM's
+/// storage root key and are useless without it, so they can be stored next
+/// to the volume.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SealedKey {
+    pub public: Vec<u8>,
+    pub private: Vec<u8>,
+}
+
+async fn work_dir() -> Result<PathBuf, BackendError> {
+    let dir = std::env::temp_dir().join(format!("feos-tpm-{}", Uuid::new_v4()));
+    fs::create_dir(&dir).await?;
+    Ok(dir)
+}
+
+async fn remove_work_dir(dir: &Path) {
+    if let Err(e) = fs::remove_dir_all(dir).await {
+        warn!("TPM: Failed to remove {}: {e}", dir.display());
+    }
+}
+
+fn path_arg(dir: &Path, file: &str) -> String {
+    dir.join(file).to_string_lossy().into_owned()
+}
+
+/// Recreates the primary key under the owner hierarchy. It is derived from
+/// the TPM's seed, so it is the same every time and never has to be stored.
+async fn create_primary(dir: &Path) -> Result<String, BackendError> {
+    let primary = path_arg(dir, "primary.ctx");
+    run(
+        "tpm2_createprimary",
+        &[TCTI, "-Q", "-C", "o", "-c", &primary],
+    )
+    .await?;
+    Ok(primary)
+}
+
+pub async fn seal(key: &[u8]) -> Result<SealedKey, BackendError> {
+    let dir = work_dir().await?;
+    let result = seal_in(&dir, key).await;
+    remove_work_dir(&dir).await;
+    result
+}
+
+async fn seal_in(dir: &Path, key: &[u8]) -> Result<SealedKey, BackendError> {
+    let primary = create_primary(dir).await?;
+    let policy = path_arg(dir, "pcr.policy");
+    run(
+        "tpm2_createpolicy",
+        &[
+            TCTI,
+            "-Q",
+            "--policy-pcr",
+            "-l",
+            PCR_SELECTION,
+            "-L",
+            &policy,
+        ],
+    )
+    .await?;
+    let public = path_arg(dir, "key.pub");
+    let private = path_arg(dir, "key.priv");
+    run_with_input(
+        "tpm2_create",
+        &[
+            TCTI, "-Q", "-C", &primary, "-L", &policy, "-i", "-", "-u", &public, "-r", &private,
+        ],
+        key,
+    )
+    .await?;
+    Ok(SealedKey {
+        public: fs::read(&public).await?,
+        private: fs::read(&private).await?,
+    })
+}
+
+pub async fn unseal(sealed: &SealedKey) -> Result<Vec<u8>, BackendError> {
+    let dir = work_dir().await?;
+    let result = unseal_in(&dir, sealed).await;
+    remove_work_dir(&dir).await;
+    result
+}
+
+async fn unseal_in(dir: &Path, sealed: &SealedKey) -> Result<Vec<u8>, BackendError> {
+    let primary = create_primary(dir).await?;
+    let public = path_arg(dir, "key.pub");
+    let private = path_arg(dir, "key.priv");
+    fs::write(&public, &sealed.public).await?;
+    fs::write(&private, &sealed.private).await?;
+    let context = path_arg(dir, "key.ctx");
+    run(
+        "tpm2_load",
+        &[
+            TCTI, "-Q", "-C", &primary, "-u", &public, "-r", &private, "-c", &context,
+        ],
+    )
+    .await?;
+    let auth = format!("pcr:{PCR_SELECTION}");
+    run_with_input("tpm2_unseal", &[TCTI, "-c", &context, "-p", &auth], &[]).await
+}
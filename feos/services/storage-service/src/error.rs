@@ -0,0 +1,48 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::backend::BackendError;
+use crate::persistence::PersistenceError;
+use tonic::Status;
+
+#[derive(Debug, thiserror::Error)]
+pub enum StorageServiceError {
+    #[error("Persistence Error: {0}")]
+    Persistence(#[from] PersistenceError),
+
+    #[error("Storage backend error: {0}")]
+    Backend(#[from] BackendError),
+
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Already exists: {0}")]
+    AlreadyExists(String),
+
+    #[error("Invalid state for operation: {0}")]
+    InvalidState(String),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl From<StorageServiceError> for Status {
+    fn from(err: StorageServiceError) -> Self {
+        log::error!("StorageServiceError: {err}");
+        match err {
+            StorageServiceError::Persistence(_) => Status::internal("A database error occurred"),
+            StorageServiceError::Backend(BackendError::InvalidConfig(msg)) => {
+                Status::invalid_argument(msg)
+            }
+            StorageServiceError::Backend(e) => Status::internal(e.to_string()),
+            StorageServiceError::InvalidArgument(msg) => Status::invalid_argument(msg),
+            StorageServiceError::NotFound(msg) => Status::not_found(msg),
+            StorageServiceError::AlreadyExists(msg) => Status::already_exists(msg),
+            StorageServiceError::InvalidState(msg) => Status::failed_precondition(msg),
+            StorageServiceError::Io(e) => Status::internal(e.to_string()),
+        }
+    }
+}
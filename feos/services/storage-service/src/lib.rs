@@ -0,0 +1,76 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::error::StorageServiceError;
+use feos_proto::storage_service::{
+    CloseVolumeRequest, CloseVolumeResponse, CreatePoolRequest, CreatePoolResponse,
+    CreateVolumeRequest, CreateVolumeResponse, DeletePoolRequest, DeletePoolResponse,
+    DeleteVolumeRequest, DeleteVolumeResponse, DiskUsageAlert, GetDiskUsageRequest,
+    GetDiskUsageResponse, ListPoolsRequest, ListPoolsResponse, ListVolumesRequest,
+    ListVolumesResponse, OpenVolumeRequest, OpenVolumeResponse, ResizePoolRequest,
+    ResizePoolResponse, ResizeVolumeRequest, ResizeVolumeResponse, WatchDiskUsageAlertsRequest,
+};
+use tokio::sync::{mpsc, oneshot};
+use tonic::Status;
+
+pub mod api;
+pub mod backend;
+pub mod dispatcher;
+pub mod encryption;
+pub mod error;
+pub mod persistence;
+pub mod usage;
+
+pub const DEFAULT_STORAGE_DB_URL: &str = "sqlite:/var/lib/feos/storage.db";
+
+#[derive(Debug)]
+pub enum Command {
+    CreatePool(
+        CreatePoolRequest,
+        oneshot::Sender<Result<CreatePoolResponse, StorageServiceError>>,
+    ),
+    ListPools(
+        ListPoolsRequest,
+        oneshot::Sender<Result<ListPoolsResponse, StorageServiceError>>,
+    ),
+    DeletePool(
+        DeletePoolRequest,
+        oneshot::Sender<Result<DeletePoolResponse, StorageServiceError>>,
+    ),
+    ResizePool(
+        ResizePoolRequest,
+        oneshot::Sender<Result<ResizePoolResponse, StorageServiceError>>,
+    ),
+    CreateVolume(
+        CreateVolumeRequest,
+        oneshot::Sender<Result<CreateVolumeResponse, StorageServiceError>>,
+    ),
+    ListVolumes(
+        ListVolumesRequest,
+        oneshot::Sender<Result<ListVolumesResponse, StorageServiceError>>,
+    ),
+    DeleteVolume(
+        DeleteVolumeRequest,
+        oneshot::Sender<Result<DeleteVolumeResponse, StorageServiceError>>,
+    ),
+    ResizeVolume(
+        ResizeVolumeRequest,
+        oneshot::Sender<Result<ResizeVolumeResponse, StorageServiceError>>,
+    ),
+    OpenVolume(
+        OpenVolumeRequest,
+        oneshot::Sender<Result<OpenVolumeResponse, StorageServiceError>>,
+    ),
+    CloseVolume(
+        CloseVolumeRequest,
+        oneshot::Sender<Result<CloseVolumeResponse, StorageServiceError>>,
+    ),
+    GetDiskUsage(
+        GetDiskUsageRequest,
+        oneshot::Sender<Result<GetDiskUsageResponse, StorageServiceError>>,
+    ),
+    WatchDiskUsageAlerts(
+        WatchDiskUsageAlertsRequest,
+        mpsc::Sender<Result<DiskUsageAlert, Status>>,
+    ),
+}
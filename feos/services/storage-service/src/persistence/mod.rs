@@ -0,0 +1,55 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::encryption::tpm::SealedKey;
+use feos_proto::storage_service::{PoolConfig, VolumeEncryption, VolumeKind};
+use uuid::Uuid;
+
+pub mod repository;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PersistenceError {
+    #[error("A database error occurred")]
+    Database(#[from] sqlx::Error),
+
+    #[error("Database migration failed")]
+    Migration(#[from] sqlx::migrate::MigrateError),
+
+    #[error("Failed to decode PoolConfig blob")]
+    Decode(#[from] prost::DecodeError),
+
+    #[error("Failed to encode PoolConfig blob")]
+    Encode(#[from] prost::EncodeError),
+
+    #[error("Invalid volume kind string '{0}' in database")]
+    InvalidKindString(String),
+
+    #[error("Invalid volume encryption string '{0}' in database")]
+    InvalidEncryptionString(String),
+
+    #[error("Invalid UUID '{0}' in database")]
+    InvalidUuid(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct PoolRecord {
+    pub pool_id: Uuid,
+    pub config: PoolConfig,
+    /// Whether the backing thin pool or directory was created by FeOS, as
+    /// opposed to an existing one being adopted.
+    pub owned: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct VolumeRecord {
+    pub volume_id: Uuid,
+    pub pool_id: Uuid,
+    pub namespace: String,
+    pub name: String,
+    pub kind: VolumeKind,
+    pub size_bytes: u64,
+    pub path: String,
+    pub encryption: VolumeEncryption,
+    /// The LUKS key of a `VolumeEncryption::Tpm` volume.
+    pub sealed_key: Option<SealedKey>,
+}
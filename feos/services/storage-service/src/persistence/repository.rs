@@ -0,0 +1,248 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::encryption::tpm::SealedKey;
+use crate::persistence::{PersistenceError, PoolRecord, VolumeRecord};
+use feos_proto::storage_service::{PoolConfig, VolumeEncryption, VolumeKind};
+use log::info;
+use prost::Message;
+use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub struct StorageRepository {
+    pool: SqlitePool,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct DbPoolRow {
+    pool_id: String,
+    config_blob: Vec<u8>,
+    owned: bool,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct DbVolumeRow {
+    volume_id: String,
+    pool_id: String,
+    namespace: String,
+    name: String,
+    kind: String,
+    size_bytes: i64,
+    path: String,
+    encryption: String,
+    sealed_key_public: Option<Vec<u8>>,
+    sealed_key_private: Option<Vec<u8>>,
+}
+
+const VOLUME_COLUMNS: &str = "volume_id, pool_id, namespace, name, kind, size_bytes, path, encryption, sealed_key_public, sealed_key_private";
+
+fn string_to_volume_kind(s: &str) -> Result<VolumeKind, PersistenceError> {
+    match s {
+        "VM_DISK" => Ok(VolumeKind::VmDisk),
+        "CONTAINER_VOLUME" => Ok(VolumeKind::ContainerVolume),
+        "VOLUME_KIND_UNSPECIFIED" => Ok(VolumeKind::Unspecified),
+        _ => Err(PersistenceError::InvalidKindString(s.to_string())),
+    }
+}
+
+fn volume_kind_to_string(kind: VolumeKind) -> &'static str {
+    match kind {
+        VolumeKind::VmDisk => "VM_DISK",
+        VolumeKind::ContainerVolume => "CONTAINER_VOLUME",
+        VolumeKind::Unspecified => "VOLUME_KIND_UNSPECIFIED",
+    }
+}
+
+fn string_to_volume_encryption(s: &str) -> Result<VolumeEncryption, PersistenceError> {
+    match s {
+        "VOLUME_ENCRYPTION_NONE" => Ok(VolumeEncryption::None),
+        "VOLUME_ENCRYPTION_TPM" => Ok(VolumeEncryption::Tpm),
+        "VOLUME_ENCRYPTION_KEY" => Ok(VolumeEncryption::Key),
+        _ => Err(PersistenceError::InvalidEncryptionString(s.to_string())),
+    }
+}
+
+fn volume_encryption_to_string(encryption: VolumeEncryption) -> &'static str {
+    match encryption {
+        VolumeEncryption::None => "VOLUME_ENCRYPTION_NONE",
+        VolumeEncryption::Tpm => "VOLUME_ENCRYPTION_TPM",
+        VolumeEncryption::Key => "VOLUME_ENCRYPTION_KEY",
+    }
+}
+
+fn parse_uuid(s: &str) -> Result<Uuid, PersistenceError> {
+    Uuid::parse_str(s).map_err(|_| PersistenceError::InvalidUuid(s.to_string()))
+}
+
+impl TryFrom<DbPoolRow> for PoolRecord {
+    type Error = PersistenceError;
+
+    fn try_from(row: DbPoolRow) -> Result<Self, Self::Error> {
+        Ok(PoolRecord {
+            pool_id: parse_uuid(&row.pool_id)?,
+            config: PoolConfig::decode(&*row.config_blob)?,
+            owned: row.owned,
+        })
+    }
+}
+
+impl TryFrom<DbVolumeRow> for VolumeRecord {
+    type Error = PersistenceError;
+
+    fn try_from(row: DbVolumeRow) -> Result<Self, Self::Error> {
+        Ok(VolumeRecord {
+            volume_id: parse_uuid(&row.volume_id)?,
+            pool_id: parse_uuid(&row.pool_id)?,
+            namespace: row.namespace,
+            name: row.name,
+            kind: string_to_volume_kind(&row.kind)?,
+            size_bytes: row.size_bytes as u64,
+            path: row.path,
+            encryption: string_to_volume_encryption(&row.encryption)?,
+            sealed_key: match (row.sealed_key_public, row.sealed_key_private) {
+                (Some(public), Some(private)) => Some(SealedKey { public, private }),
+                _ => None,
+            },
+        })
+    }
+}
+
+impl StorageRepository {
+    pub async fn connect(db_url: &str) -> Result<Self, PersistenceError> {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(db_url)
+            .await?;
+
+        info!("Persistence: Running storage-service database migrations...");
+        sqlx::migrate!("./migrations").run(&pool).await?;
+        info!("Persistence: Database migrations completed for storage-service.");
+
+        Ok(Self { pool })
+    }
+
+    pub async fn get_pool(&self, pool_id: Uuid) -> Result<Option<PoolRecord>, PersistenceError> {
+        sqlx::query_as::<_, DbPoolRow>(
+            "SELECT pool_id, config_blob, owned FROM pools WHERE pool_id = ?1",
+        )
+        .bind(pool_id.to_string())
+        .fetch_optional(&self.pool)
+        .await?
+        .map(PoolRecord::try_from)
+        .transpose()
+    }
+
+    pub async fn pool_name_exists(&self, name: &str) -> Result<bool, PersistenceError> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pools WHERE name = ?1")
+            .bind(name)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count > 0)
+    }
+
+    pub async fn list_pools(&self) -> Result<Vec<PoolRecord>, PersistenceError> {
+        sqlx::query_as::<_, DbPoolRow>("SELECT pool_id, config_blob, owned FROM pools")
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(PoolRecord::try_from)
+            .collect()
+    }
+
+    pub async fn save_pool(&self, pool: &PoolRecord) -> Result<(), PersistenceError> {
+        let mut config_blob = Vec::new();
+        pool.config.encode(&mut config_blob)?;
+
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO pools (pool_id, name, config_blob, owned)
+            VALUES (?1, ?2, ?3, ?4)
+            "#,
+        )
+        .bind(pool.pool_id.to_string())
+        .bind(&pool.config.name)
+        .bind(config_blob)
+        .bind(pool.owned)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_pool(&self, pool_id: Uuid) -> Result<bool, PersistenceError> {
+        let result = sqlx::query("DELETE FROM pools WHERE pool_id = ?1")
+            .bind(pool_id.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn get_volume(
+        &self,
+        volume_id: Uuid,
+    ) -> Result<Option<VolumeRecord>, PersistenceError> {
+        sqlx::query_as::<_, DbVolumeRow>(&format!(
+            "SELECT {VOLUME_COLUMNS} FROM volumes WHERE volume_id = ?1"
+        ))
+        .bind(volume_id.to_string())
+        .fetch_optional(&self.pool)
+        .await?
+        .map(VolumeRecord::try_from)
+        .transpose()
+    }
+
+    pub async fn list_volumes(
+        &self,
+        pool_id: Option<Uuid>,
+    ) -> Result<Vec<VolumeRecord>, PersistenceError> {
+        let rows = match pool_id {
+            Some(pool_id) => {
+                sqlx::query_as::<_, DbVolumeRow>(&format!(
+                    "SELECT {VOLUME_COLUMNS} FROM volumes WHERE pool_id = ?1"
+                ))
+                .bind(pool_id.to_string())
+                .fetch_all(&self.pool)
+                .await?
+            }
+            None => {
+                sqlx::query_as::<_, DbVolumeRow>(&format!("SELECT {VOLUME_COLUMNS} FROM volumes"))
+                    .fetch_all(&self.pool)
+                    .await?
+            }
+        };
+        rows.into_iter().map(VolumeRecord::try_from).collect()
+    }
+
+    pub async fn save_volume(&self, volume: &VolumeRecord) -> Result<(), PersistenceError> {
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO volumes (volume_id, pool_id, namespace, name, kind, size_bytes,
+                path, encryption, sealed_key_public, sealed_key_private)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+            "#,
+        )
+        .bind(volume.volume_id.to_string())
+        .bind(volume.pool_id.to_string())
+        .bind(&volume.namespace)
+        .bind(&volume.name)
+        .bind(volume_kind_to_string(volume.kind))
+        .bind(volume.size_bytes as i64)
+        .bind(&volume.path)
+        .bind(volume_encryption_to_string(volume.encryption))
+        .bind(volume.sealed_key.as_ref().map(|key| key.public.clone()))
+        .bind(volume.sealed_key.as_ref().map(|key| key.private.clone()))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_volume(&self, volume_id: Uuid) -> Result<bool, PersistenceError> {
+        let result = sqlx::query("DELETE FROM volumes WHERE volume_id = ?1")
+            .bind(volume_id.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,421 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{error::StorageServiceError, persistence::repository::StorageRepository};
+use feos_proto::storage_service::{
+    pool_config, CategoryUsage, DiskUsageAlert, FilesystemUsage, GetDiskUsageResponse,
+    UsageCategory, UsageLevel, VolumeKind,
+};
+use image_service::FileCommand;
+use log::{info, warn};
+use nix::sys::statvfs::statvfs;
+use std::collections::{btree_map::Entry, BTreeMap, HashMap, HashSet};
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::time::{interval, Duration, MissedTickBehavior};
+
+pub const DEFAULT_WARNING_PERCENT: u32 = 80;
+pub const DEFAULT_CRITICAL_PERCENT: u32 = 90;
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A file or directory tree whose space counts towards `category`.
+#[derive(Debug, Clone)]
+pub struct UsageSource {
+    pub category: UsageCategory,
+    pub path: PathBuf,
+    /// Directories below `path` that are accounted for by another source.
+    pub excluded: Vec<PathBuf>,
+}
+
+impl UsageSource {
+    pub fn new(category: UsageCategory, path: impl Into<PathBuf>) -> Self {
+        Self {
+            category,
+            path: path.into(),
+            excluded: Vec::new(),
+        }
+    }
+
+    pub fn excluding(mut self, path: impl Into<PathBuf>) -> Self {
+        self.excluded.push(path.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct UsageThresholds {
+    pub warning_percent: u32,
+    pub critical_percent: u32,
+}
+
+impl Default for UsageThresholds {
+    fn default() -> Self {
+        Self {
+            warning_percent: DEFAULT_WARNING_PERCENT,
+            critical_percent: DEFAULT_CRITICAL_PERCENT,
+        }
+    }
+}
+
+impl UsageThresholds {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.warning_percent == 0
+            || self.warning_percent > self.critical_percent
+            || self.critical_percent > 100
+        {
+            return Err(format!(
+                "Usage thresholds must satisfy 0 < warning ({}) <= critical ({}) <= 100",
+                self.warning_percent, self.critical_percent
+            ));
+        }
+        Ok(())
+    }
+
+    /// Rates usage the way `df` does, so the space reserved for root counts
+    /// as used.
+    fn level(&self, used_bytes: u64, available_bytes: u64) -> UsageLevel {
+        let total = used_bytes as u128 + available_bytes as u128;
+        if total == 0 {
+            return UsageLevel::Ok;
+        }
+        let percent = used_bytes as u128 * 100 / total;
+        if percent >= self.critical_percent as u128 {
+            UsageLevel::Critical
+        } else if percent >= self.warning_percent as u128 {
+            UsageLevel::Warning
+        } else {
+            UsageLevel::Ok
+        }
+    }
+}
+
+pub struct UsageConfig {
+    /// Locations of FeOS data besides the volumes of directory pools, which
+    /// are added from the database.
+    pub sources: Vec<UsageSource>,
+    pub thresholds: UsageThresholds,
+    /// Where to request garbage collection of images when a filesystem with
+    /// image data becomes critical. `None` leaves the images alone.
+    pub image_gc: Option<mpsc::Sender<FileCommand>>,
+}
+
+struct Filesystem {
+    usage: FilesystemUsage,
+    categories: BTreeMap<i32, u64>,
+    /// Inodes with several links that were already counted.
+    seen: HashSet<u64>,
+}
+
+impl Filesystem {
+    fn new(path: &Path, dev: u64, thresholds: UsageThresholds) -> io::Result<Self> {
+        let stat = statvfs(path).map_err(io::Error::from)?;
+        let fragment_size = stat.fragment_size() as u64;
+        let capacity_bytes = stat.blocks() as u64 * fragment_size;
+        let used_bytes = capacity_bytes - stat.blocks_free() as u64 * fragment_size;
+        let available_bytes = stat.blocks_available() as u64 * fragment_size;
+        let mut usage = FilesystemUsage {
+            mount_point: mount_point(path, dev)?.display().to_string(),
+            capacity_bytes,
+            used_bytes,
+            available_bytes,
+            ..Default::default()
+        };
+        usage.set_level(thresholds.level(used_bytes, available_bytes));
+        Ok(Self {
+            usage,
+            categories: BTreeMap::new(),
+            seen: HashSet::new(),
+        })
+    }
+}
+
+/// Walks up from `path` to the root of the filesystem it is on.
+fn mount_point(path: &Path, dev: u64) -> io::Result<PathBuf> {
+    let mut mount_point = path.canonicalize()?;
+    while let Some(parent) = mount_point.parent() {
+        if std::fs::metadata(parent)?.dev() != dev {
+            break;
+        }
+        mount_point = parent.to_path_buf();
+    }
+    Ok(mount_point)
+}
+
+/// Space allocated to a file or directory tree on device `dev`. Mounts below
+/// `path`, such as container root filesystems, are skipped.
+fn allocated_bytes(
+    path: &Path,
+    dev: u64,
+    excluded: &[PathBuf],
+    seen: &mut HashSet<u64>,
+) -> io::Result<u64> {
+    let metadata = std::fs::symlink_metadata(path)?;
+    if metadata.dev() != dev || excluded.iter().any(|excluded| excluded == path) {
+        return Ok(0);
+    }
+    if metadata.nlink() > 1 && !metadata.is_dir() && !seen.insert(metadata.ino()) {
+        return Ok(0);
+    }
+    let mut total = metadata.blocks() * 512;
+    if metadata.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            match allocated_bytes(&entry?.path(), dev, excluded, seen) {
+                Ok(bytes) => total += bytes,
+                // Removed while walking.
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    Ok(total)
+}
+
+fn measure(
+    sources: &[UsageSource],
+    thresholds: UsageThresholds,
+) -> io::Result<Vec<FilesystemUsage>> {
+    let mut filesystems: BTreeMap<u64, Filesystem> = BTreeMap::new();
+    for source in sources {
+        let dev = match std::fs::metadata(&source.path) {
+            Ok(metadata) => metadata.dev(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        let filesystem = match filesystems.entry(dev) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(Filesystem::new(&source.path, dev, thresholds)?),
+        };
+        let bytes = allocated_bytes(&source.path, dev, &source.excluded, &mut filesystem.seen)?;
+        *filesystem
+            .categories
+            .entry(source.category as i32)
+            .or_default() += bytes;
+    }
+
+    Ok(filesystems
+        .into_values()
+        .map(|mut filesystem| {
+            filesystem.usage.categories = filesystem
+                .categories
+                .into_iter()
+                .map(|(category, used_bytes)| CategoryUsage {
+                    category,
+                    used_bytes,
+                })
+                .collect();
+            filesystem.usage
+        })
+        .collect())
+}
+
+fn holds_images(filesystem: &FilesystemUsage) -> bool {
+    filesystem.categories.iter().any(|usage| {
+        matches!(
+            usage.category(),
+            UsageCategory::ImageCache | UsageCategory::ContainerLayers
+        )
+    })
+}
+
+/// Measures disk usage on request and watches it in the background.
+#[derive(Clone)]
+pub struct DiskUsage {
+    repository: StorageRepository,
+    sources: Arc<Vec<UsageSource>>,
+    thresholds: UsageThresholds,
+    alerts: broadcast::Sender<DiskUsageAlert>,
+}
+
+impl DiskUsage {
+    pub fn new(
+        repository: StorageRepository,
+        sources: Vec<UsageSource>,
+        thresholds: UsageThresholds,
+    ) -> Self {
+        let (alerts, _) = broadcast::channel(16);
+        Self {
+            repository,
+            sources: Arc::new(sources),
+            thresholds,
+            alerts,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<DiskUsageAlert> {
+        self.alerts.subscribe()
+    }
+
+    /// The configured sources plus the volumes of directory pools. Volumes of
+    /// LVM pools are block devices and show up in `ListPools` instead.
+    async fn all_sources(&self) -> Result<Vec<UsageSource>, StorageServiceError> {
+        let directory_pools: HashSet<_> = self
+            .repository
+            .list_pools()
+            .await?
+            .into_iter()
+            .filter(|pool| {
+                matches!(
+                    pool.config.backend,
+                    Some(pool_config::Backend::Directory(_))
+                )
+            })
+            .map(|pool| pool.pool_id)
+            .collect();
+
+        let mut sources = self.sources.as_ref().clone();
+        for volume in self.repository.list_volumes(None).await? {
+            if !directory_pools.contains(&volume.pool_id) {
+                continue;
+            }
+            let category = match volume.kind {
+                VolumeKind::ContainerVolume => UsageCategory::ContainerVolumes,
+                _ => UsageCategory::VmDisks,
+            };
+            sources.push(UsageSource::new(category, volume.path));
+        }
+        Ok(sources)
+    }
+
+    pub async fn report(&self) -> Result<GetDiskUsageResponse, StorageServiceError> {
+        let sources = self.all_sources().await?;
+        let thresholds = self.thresholds;
+        let filesystems = tokio::task::spawn_blocking(move || measure(&sources, thresholds))
+            .await
+            .map_err(io::Error::other)??;
+        Ok(GetDiskUsageResponse {
+            filesystems,
+            warning_percent: self.thresholds.warning_percent,
+            critical_percent: self.thresholds.critical_percent,
+        })
+    }
+
+    async fn collect_images(image_gc: &mpsc::Sender<FileCommand>) -> bool {
+        let (responder, done) = oneshot::channel();
+        if image_gc
+            .send(FileCommand::CollectGarbage { responder })
+            .await
+            .is_err()
+        {
+            warn!("DiskUsage: Image service is not running, cannot collect garbage");
+            return false;
+        }
+        done.await.is_ok()
+    }
+
+    /// Checks the usage periodically and sends an alert whenever the level of
+    /// a filesystem changes. When a filesystem holding image data becomes
+    /// critical, unused image data is garbage collected first.
+    pub async fn monitor(self, image_gc: Option<mpsc::Sender<FileCommand>>) {
+        let mut levels: HashMap<String, UsageLevel> = HashMap::new();
+        let mut ticker = interval(CHECK_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            let report = match self.report().await {
+                Ok(report) => report,
+                Err(e) => {
+                    warn!("DiskUsage: Failed to measure disk usage: {e}");
+                    continue;
+                }
+            };
+
+            for mut filesystem in report.filesystems {
+                let previous = levels
+                    .get(&filesystem.mount_point)
+                    .copied()
+                    .unwrap_or(UsageLevel::Ok);
+                let mut images_collected = false;
+                if filesystem.level() == UsageLevel::Critical
+                    && previous != UsageLevel::Critical
+                    && holds_images(&filesystem)
+                {
+                    if let Some(image_gc) = &image_gc {
+                        warn!(
+                            "DiskUsage: {} is critical, collecting unused image data",
+                            filesystem.mount_point
+                        );
+                        images_collected = Self::collect_images(image_gc).await;
+                        if let Ok(after) = self.report().await {
+                            if let Some(usage) = after
+                                .filesystems
+                                .into_iter()
+                                .find(|usage| usage.mount_point == filesystem.mount_point)
+                            {
+                                filesystem = usage;
+                            }
+                        }
+                    }
+                }
+
+                let level = filesystem.level();
+                levels.insert(filesystem.mount_point.clone(), level);
+                if level == previous && !images_collected {
+                    continue;
+                }
+                let message = format!(
+                    "DiskUsage: {} changed from {previous:?} to {level:?}, {} of {} bytes used",
+                    filesystem.mount_point, filesystem.used_bytes, filesystem.capacity_bytes
+                );
+                if level > previous {
+                    warn!("{message}");
+                } else {
+                    info!("{message}");
+                }
+                // Nobody may be listening.
+                let _ = self.alerts.send(DiskUsageAlert {
+                    filesystem: Some(filesystem),
+                    previous_level: previous as i32,
+                    images_collected,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_follow_thresholds() {
+        let thresholds = UsageThresholds::default();
+        assert_eq!(thresholds.level(0, 0), UsageLevel::Ok);
+        assert_eq!(thresholds.level(79, 21), UsageLevel::Ok);
+        assert_eq!(thresholds.level(80, 20), UsageLevel::Warning);
+        assert_eq!(thresholds.level(90, 10), UsageLevel::Critical);
+        assert!(UsageThresholds {
+            warning_percent: 95,
+            critical_percent: 90
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn usage_is_split_by_category_and_hardlinks_count_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let images = dir.path().join("images");
+        let layers = images.join("layers");
+        std::fs::create_dir_all(&layers).unwrap();
+        std::fs::write(images.join("blob"), vec![1u8; 64 << 10]).unwrap();
+        std::fs::hard_link(images.join("blob"), images.join("config.json")).unwrap();
+        std::fs::write(layers.join("file"), vec![1u8; 64 << 10]).unwrap();
+
+        let sources = [
+            UsageSource::new(UsageCategory::ImageCache, &images).excluding(&layers),
+            UsageSource::new(UsageCategory::ContainerLayers, &layers),
+            UsageSource::new(UsageCategory::Databases, dir.path().join("missing.db")),
+        ];
+        let filesystems = measure(&sources, UsageThresholds::default()).unwrap();
+        assert_eq!(filesystems.len(), 1);
+        let categories = &filesystems[0].categories;
+        assert_eq!(categories.len(), 2);
+        let image_cache = categories[0].used_bytes;
+        let container_layers = categories[1].used_bytes;
+        assert!((64 << 10..2 * (64 << 10)).contains(&image_cache));
+        assert!(container_layers >= 64 << 10);
+    }
+}
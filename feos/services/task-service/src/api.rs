@@ -1,12 +1,15 @@
 use crate::error::TaskError;
 use crate::Command;
 use feos_proto::task_service::{
-    task_service_server::TaskService, CreateRequest, CreateResponse, DeleteRequest, DeleteResponse,
-    KillRequest, KillResponse, StartRequest, StartResponse, WaitRequest, WaitResponse,
+    exec_request, task_service_server::TaskService, CreateRequest, CreateResponse, DeleteRequest,
+    DeleteResponse, ExecRequest, ExecResponse, KillRequest, KillResponse, StartRequest,
+    StartResponse, WaitRequest, WaitResponse,
 };
 use log::info;
+use std::pin::Pin;
 use tokio::sync::{mpsc, oneshot};
-use tonic::{Request, Response, Status};
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+use tonic::{Request, Response, Status, Streaming};
 
 pub struct TaskApiHandler {
     dispatcher_tx: mpsc::Sender<Command>,
@@ -45,6 +48,8 @@ where
 
 #[tonic::async_trait]
 impl TaskService for TaskApiHandler {
+    type ExecStream = Pin<Box<dyn Stream<Item = Result<ExecResponse, Status>> + Send>>;
+
     async fn create(
         &self,
         request: Request<CreateRequest>,
@@ -113,4 +118,39 @@ impl TaskService for TaskApiHandler {
         })
         .await
     }
+
+    async fn exec(
+        &self,
+        request: Request<Streaming<ExecRequest>>,
+    ) -> Result<Response<Self::ExecStream>, Status> {
+        let mut input = request.into_inner();
+        let start = match input.next().await {
+            Some(Ok(ExecRequest {
+                payload: Some(exec_request::Payload::Start(start)),
+            })) => start,
+            Some(Ok(_)) => {
+                return Err(Status::invalid_argument(
+                    "First message must be an ExecStart message.",
+                ))
+            }
+            Some(Err(e)) => return Err(e),
+            None => {
+                return Err(Status::invalid_argument(
+                    "Client disconnected before sending ExecStart message.",
+                ))
+            }
+        };
+        info!("API: Received Exec request for {}", start.container_id);
+
+        let (output, output_rx) = mpsc::channel(32);
+        self.dispatcher_tx
+            .send(Command::Exec {
+                start,
+                input: Box::new(input),
+                output,
+            })
+            .await
+            .map_err(|e| Status::internal(format!("Failed to send command to dispatcher: {e}")))?;
+        Ok(Response::new(Box::pin(ReceiverStream::new(output_rx))))
+    }
 }
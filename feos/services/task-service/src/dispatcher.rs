@@ -57,6 +57,7 @@ impl Dispatcher {
                         pid: None,
                         bundle_path: req.bundle_path.clone(),
                         exit_code: None,
+                        signal: None,
                         wait_responder: None,
                     },
                 );
@@ -131,7 +132,10 @@ impl Dispatcher {
                         // Container has already stopped, respond immediately.
                         let exit_code = container.exit_code.unwrap_or(255);
                         info!("Dispatcher: Responding to Wait for already stopped container {id} with code {exit_code}");
-                        let _ = responder.send(Ok(WaitResponse { exit_code }));
+                        let _ = responder.send(Ok(WaitResponse {
+                            exit_code,
+                            signal: container.signal,
+                        }));
                     }
                     Some(container) if container.status == Status::Running => {
                         // Container is running, store the responder to be used when the stop event arrives.
@@ -157,6 +161,31 @@ impl Dispatcher {
                     }
                 }
             }
+            Command::Exec {
+                start,
+                input,
+                output,
+            } => {
+                let id = start.container_id.clone();
+                match self.containers.get(&id) {
+                    Some(container) if container.status == Status::Running => {
+                        tokio::spawn(worker::handle_exec(start, *input, output));
+                    }
+                    Some(container) => {
+                        let err = TaskError::InvalidState {
+                            id,
+                            current_state: container.status,
+                            required_states: vec![Status::Running],
+                        };
+                        let _ = output.send(Err(err.into())).await;
+                    }
+                    None => {
+                        let _ = output
+                            .send(Err(TaskError::ContainerNotFound(id).into()))
+                            .await;
+                    }
+                }
+            }
         }
     }
 
@@ -183,15 +212,23 @@ impl Dispatcher {
                     container.status = Status::Created;
                 }
             }
-            Event::ContainerStopped { id, exit_code } => {
+            Event::ContainerStopped {
+                id,
+                exit_code,
+                signal,
+            } => {
                 if let Some(container) = self.containers.get_mut(&id) {
                     container.status = Status::Stopped;
                     container.exit_code = Some(exit_code);
+                    container.signal = signal;
                     if let Some(responder) = container.wait_responder.take() {
                         info!(
                             "Dispatcher: Fulfilling pending Wait request for {id} with exit code {exit_code}"
                         );
-                        if responder.send(Ok(WaitResponse { exit_code })).is_err() {
+                        if responder
+                            .send(Ok(WaitResponse { exit_code, signal }))
+                            .is_err()
+                        {
                             warn!("Dispatcher: Client waiting on container {id} disconnected");
                         }
                     }
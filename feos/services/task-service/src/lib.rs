@@ -1,5 +1,6 @@
 use crate::error::TaskError;
-use tokio::sync::oneshot;
+use tokio::sync::{mpsc, oneshot};
+use tonic::Streaming;
 
 pub mod api;
 pub mod dispatcher;
@@ -7,8 +8,8 @@ pub mod error;
 pub mod worker;
 
 pub use feos_proto::task_service::{
-    CreateRequest, CreateResponse, DeleteRequest, DeleteResponse, KillRequest, KillResponse,
-    StartRequest, StartResponse, WaitRequest, WaitResponse,
+    CreateRequest, CreateResponse, DeleteRequest, DeleteResponse, ExecRequest, ExecResponse,
+    ExecStart, KillRequest, KillResponse, StartRequest, StartResponse, WaitRequest, WaitResponse,
 };
 
 pub const TASK_SERVICE_SOCKET: &str = "/tmp/feos/task_service.sock";
@@ -19,6 +20,7 @@ pub struct Container {
     pub pid: Option<i32>,
     pub bundle_path: String,
     pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
     pub wait_responder: Option<oneshot::Sender<Result<WaitResponse, TaskError>>>,
 }
 
@@ -52,14 +54,36 @@ pub enum Command {
         req: WaitRequest,
         responder: oneshot::Sender<Result<WaitResponse, TaskError>>,
     },
+    Exec {
+        start: ExecStart,
+        input: Box<Streaming<ExecRequest>>,
+        output: mpsc::Sender<Result<ExecResponse, tonic::Status>>,
+    },
 }
 
 #[derive(Debug)]
 pub enum Event {
-    ContainerCreated { id: String, pid: i32 },
-    ContainerCreateFailed { id: String, error: TaskError },
-    ContainerStarted { id: String },
-    ContainerStartFailed { id: String, error: TaskError },
-    ContainerStopped { id: String, exit_code: i32 },
-    ContainerDeleted { id: String },
+    ContainerCreated {
+        id: String,
+        pid: i32,
+    },
+    ContainerCreateFailed {
+        id: String,
+        error: TaskError,
+    },
+    ContainerStarted {
+        id: String,
+    },
+    ContainerStartFailed {
+        id: String,
+        error: TaskError,
+    },
+    ContainerStopped {
+        id: String,
+        exit_code: i32,
+        signal: Option<i32>,
+    },
+    ContainerDeleted {
+        id: String,
+    },
 }
@@ -1,15 +1,22 @@
 use crate::error::TaskError;
 use crate::Event;
 use feos_proto::task_service::{
-    CreateRequest, CreateResponse, DeleteRequest, DeleteResponse, KillRequest, KillResponse,
-    StartRequest, StartResponse,
+    exec_request, exec_response, CreateRequest, CreateResponse, DeleteRequest, DeleteResponse,
+    ExecRequest, ExecResponse, ExecStart, KillRequest, KillResponse, StartRequest, StartResponse,
+    TerminalSize,
 };
 use log::{debug, error, info, warn};
+use nix::pty::{openpty, Winsize};
 use nix::sys::wait::{waitpid, WaitStatus};
 use nix::unistd::Pid;
-use std::process::Stdio;
+use std::os::fd::AsRawFd;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{ExitStatus, Stdio};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
 use tokio::process::Command;
 use tokio::sync::{mpsc, oneshot};
+use tokio_stream::StreamExt;
+use tonic::Streaming;
 
 const YOUKI_BIN: &str = "youki";
 
@@ -219,26 +226,232 @@ pub async fn wait_for_process_exit(id: String, pid: i32, event_tx: mpsc::Sender<
         }
     };
 
-    let exit_code = match status {
+    let (exit_code, signal) = match status {
         WaitStatus::Exited(_, code) => {
             info!("Worker: Process {pid} ({id}) exited with code {code}");
-            code
+            (code, None)
         }
         WaitStatus::Signaled(_, signal, _) => {
             info!("Worker: Process {pid} ({id}) was terminated by signal {signal}");
-            128 + (signal as i32)
+            (128 + (signal as i32), Some(signal as i32))
         }
         _ => {
             warn!("Worker: Process {pid} ({id}) ended with unexpected status: {status:?}");
-            255
+            (255, None)
         }
     };
 
     if event_tx
-        .send(Event::ContainerStopped { id, exit_code })
+        .send(Event::ContainerStopped {
+            id,
+            exit_code,
+            signal,
+        })
         .await
         .is_err()
     {
         error!("Worker: Failed to send ContainerStopped event. Dispatcher may be down.");
     }
 }
+
+pub async fn handle_exec(
+    start: ExecStart,
+    input: Streaming<ExecRequest>,
+    output: mpsc::Sender<Result<ExecResponse, tonic::Status>>,
+) {
+    let id = start.container_id.clone();
+    let mut args = vec!["exec".to_string(), id.clone(), "--".to_string()];
+    args.extend(start.args);
+
+    info!(
+        "Worker: Spawning youki exec command: {} {}",
+        YOUKI_BIN,
+        args.join(" ")
+    );
+
+    let result = if start.tty {
+        exec_with_tty(&args, start.terminal_size, input, &output).await
+    } else {
+        exec_with_pipes(&args, input, &output).await
+    };
+
+    let response = match result {
+        Ok(exit_code) => {
+            info!("Worker: Exec process in '{id}' exited with code {exit_code}");
+            Ok(ExecResponse {
+                payload: Some(exec_response::Payload::ExitCode(exit_code)),
+            })
+        }
+        Err(e) => {
+            error!("Worker: Exec in '{id}' failed: {e}");
+            Err(e.into())
+        }
+    };
+
+    if output.send(response).await.is_err() {
+        warn!("Worker: Exec client for '{id}' disconnected before exit code could be sent.");
+    }
+}
+
+async fn exec_with_pipes(
+    args: &[String],
+    mut input: Streaming<ExecRequest>,
+    output: &mpsc::Sender<Result<ExecResponse, tonic::Status>>,
+) -> Result<i32, TaskError> {
+    let mut child = Command::new(YOUKI_BIN)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| TaskError::YoukiCommand(format!("Failed to spawn youki exec: {e}")))?;
+
+    let stdout_task = tokio::spawn(forward_exec_output(
+        child.stdout.take(),
+        output.clone(),
+        exec_response::Payload::Stdout,
+    ));
+    let stderr_task = tokio::spawn(forward_exec_output(
+        child.stderr.take(),
+        output.clone(),
+        exec_response::Payload::Stderr,
+    ));
+
+    let mut stdin = child.stdin.take();
+    let input_task = tokio::spawn(async move {
+        while let Some(Ok(msg)) = input.next().await {
+            if let Some(exec_request::Payload::Stdin(data)) = msg.payload {
+                let Some(writer) = stdin.as_mut() else {
+                    break;
+                };
+                if writer.write_all(&data).await.is_err() {
+                    break;
+                }
+            }
+        }
+        // Dropping stdin here closes the pipe, so the process sees EOF once
+        // the client has finished sending input.
+    });
+
+    let status = child
+        .wait()
+        .await
+        .map_err(|e| TaskError::YoukiCommand(format!("Failed to wait for youki exec: {e}")))?;
+
+    input_task.abort();
+    let _ = stdout_task.await;
+    let _ = stderr_task.await;
+
+    Ok(exit_code_from_status(status))
+}
+
+async fn exec_with_tty(
+    args: &[String],
+    terminal_size: Option<TerminalSize>,
+    mut input: Streaming<ExecRequest>,
+    output: &mpsc::Sender<Result<ExecResponse, tonic::Status>>,
+) -> Result<i32, TaskError> {
+    let winsize = terminal_size.as_ref().map(to_winsize);
+    let pty = openpty(winsize.as_ref(), None)
+        .map_err(|e| TaskError::Internal(format!("Failed to allocate pseudo-terminal: {e}")))?;
+
+    // The command holds the only copies of the slave side, so it must be
+    // dropped after spawning. Otherwise reads from the master never see EOF.
+    let mut child = Command::new(YOUKI_BIN)
+        .args(args)
+        .stdin(Stdio::from(pty.slave.try_clone()?))
+        .stdout(Stdio::from(pty.slave.try_clone()?))
+        .stderr(Stdio::from(pty.slave))
+        .spawn()
+        .map_err(|e| TaskError::YoukiCommand(format!("Failed to spawn youki exec: {e}")))?;
+
+    let master = std::fs::File::from(pty.master);
+    let reader = tokio::fs::File::from_std(master.try_clone()?);
+    let mut writer = tokio::fs::File::from_std(master);
+
+    let output_task = tokio::spawn(forward_exec_output(
+        Some(reader),
+        output.clone(),
+        exec_response::Payload::Stdout,
+    ));
+
+    let input_task = tokio::spawn(async move {
+        while let Some(Ok(msg)) = input.next().await {
+            match msg.payload {
+                Some(exec_request::Payload::Stdin(data)) => {
+                    if writer.write_all(&data).await.is_err() || writer.flush().await.is_err() {
+                        break;
+                    }
+                }
+                Some(exec_request::Payload::Resize(size)) => {
+                    if let Err(e) = set_winsize(&writer, &size) {
+                        warn!("Worker: Failed to resize exec pseudo-terminal: {e}");
+                    }
+                }
+                Some(exec_request::Payload::Start(_)) | None => {
+                    warn!("Worker: Ignoring unexpected message on exec input stream.");
+                }
+            }
+        }
+    });
+
+    let status = child
+        .wait()
+        .await
+        .map_err(|e| TaskError::YoukiCommand(format!("Failed to wait for youki exec: {e}")))?;
+
+    input_task.abort();
+    let _ = output_task.await;
+
+    Ok(exit_code_from_status(status))
+}
+
+async fn forward_exec_output<R: AsyncRead + Unpin>(
+    reader: Option<R>,
+    output: mpsc::Sender<Result<ExecResponse, tonic::Status>>,
+    wrap: fn(Vec<u8>) -> exec_response::Payload,
+) {
+    let Some(mut reader) = reader else {
+        return;
+    };
+    let mut buf = vec![0; 4096];
+    loop {
+        match reader.read(&mut buf).await {
+            // A pty master returns EIO once the slave side has been closed,
+            // which is treated the same as EOF.
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                let msg = ExecResponse {
+                    payload: Some(wrap(buf[..n].to_vec())),
+                };
+                if output.send(Ok(msg)).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn to_winsize(size: &TerminalSize) -> Winsize {
+    Winsize {
+        ws_row: size.rows as u16,
+        ws_col: size.cols as u16,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    }
+}
+
+fn set_winsize(fd: &impl AsRawFd, size: &TerminalSize) -> std::io::Result<()> {
+    let winsize = to_winsize(size);
+    let ret = unsafe { libc::ioctl(fd.as_raw_fd(), libc::TIOCSWINSZ, &winsize) };
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn exit_code_from_status(status: ExitStatus) -> i32 {
+    status
+        .code()
+        .unwrap_or_else(|| 128 + status.signal().unwrap_or(0))
+}
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS vm_snapshots (
+    -- The primary key for the snapshot, generated by the vm-service.
+    snapshot_id TEXT PRIMARY KEY NOT NULL,
+    -- The VM the snapshot was taken of.
+    vm_id TEXT NOT NULL,
+    -- A binary blob containing the serialized VmSnapshotInfo protobuf message.
+    info_blob BLOB NOT NULL,
+    -- Timestamp of when the snapshot was taken.
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_vm_snapshots_vm_id ON vm_snapshots (vm_id);
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS vm_boot_timings (
+    -- The VM the timings belong to.
+    vm_id TEXT PRIMARY KEY NOT NULL,
+    -- A binary blob containing the serialized VmBootTimings protobuf message.
+    timings_blob BLOB NOT NULL
+);
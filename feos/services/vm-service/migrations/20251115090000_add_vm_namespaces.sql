@@ -0,0 +1,8 @@
+-- The namespace of the tenant a VM belongs to. VMs from before namespaces
+-- existed belong to the default one.
+ALTER TABLE vms ADD COLUMN namespace TEXT NOT NULL DEFAULT 'default';
+-- An optional human-readable name, unique within the namespace.
+ALTER TABLE vms ADD COLUMN name TEXT;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_vms_namespace_name ON vms (namespace, name) WHERE name IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_vms_namespace ON vms (namespace);
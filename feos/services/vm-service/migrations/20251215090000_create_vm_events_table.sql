@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS vm_events (
+    -- The ID of the event, unique among the events of all VMs.
+    event_id TEXT PRIMARY KEY NOT NULL,
+    -- The VM the event is about. Its events are kept after it is deleted.
+    vm_id TEXT NOT NULL,
+    -- The namespace the VM was in.
+    namespace TEXT NOT NULL,
+    -- When the event was recorded, in milliseconds since the Unix epoch.
+    recorded_at_ms INTEGER NOT NULL,
+    -- A binary blob containing the serialized VmEvent protobuf message.
+    event_blob BLOB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_vm_events_vm_id ON vm_events (vm_id, recorded_at_ms);
+CREATE INDEX IF NOT EXISTS idx_vm_events_recorded_at ON vm_events (recorded_at_ms);
@@ -0,0 +1,61 @@
+CREATE TABLE IF NOT EXISTS vm_state_journal (
+    -- The position of the entry in the journal. Later entries have higher ones.
+    sequence INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- The VM whose state changed. Its entries are kept after it is deleted.
+    vm_id TEXT NOT NULL,
+    -- The namespace the VM was in.
+    namespace TEXT NOT NULL,
+    -- When the state changed, in milliseconds since the Unix epoch.
+    recorded_at_ms INTEGER NOT NULL DEFAULT (CAST(unixepoch('subsec') * 1000 AS INTEGER)),
+    -- The state before, NULL when the VM was created.
+    old_state TEXT,
+    -- The state after, NULL when the VM was deleted.
+    new_state TEXT,
+    -- The status message the VM had after the change.
+    message TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_vm_state_journal_vm_id ON vm_state_journal (vm_id, sequence);
+
+-- VMs from before the journal existed start it with their current state.
+INSERT INTO vm_state_journal (vm_id, namespace, old_state, new_state, message)
+SELECT vm_id, namespace, NULL, state, COALESCE(last_msg, '') FROM vms ORDER BY created_at;
+
+-- The journal is written by these triggers, in the same transaction as the
+-- change of the VM, so no transition is missing from it.
+CREATE TRIGGER IF NOT EXISTS trigger_vms_journal_insert
+AFTER INSERT ON vms
+FOR EACH ROW
+BEGIN
+    INSERT INTO vm_state_journal (vm_id, namespace, old_state, new_state, message)
+    VALUES (NEW.vm_id, NEW.namespace, NULL, NEW.state, COALESCE(NEW.last_msg, ''));
+END;
+
+CREATE TRIGGER IF NOT EXISTS trigger_vms_journal_update
+AFTER UPDATE OF state ON vms
+FOR EACH ROW WHEN OLD.state IS NOT NEW.state
+BEGIN
+    INSERT INTO vm_state_journal (vm_id, namespace, old_state, new_state, message)
+    VALUES (NEW.vm_id, NEW.namespace, OLD.state, NEW.state, COALESCE(NEW.last_msg, ''));
+END;
+
+CREATE TRIGGER IF NOT EXISTS trigger_vms_journal_delete
+AFTER DELETE ON vms
+FOR EACH ROW
+BEGIN
+    INSERT INTO vm_state_journal (vm_id, namespace, old_state, new_state, message)
+    VALUES (OLD.vm_id, OLD.namespace, OLD.state, NULL, 'VM deleted');
+END;
+
+-- Entries are never changed or removed.
+CREATE TRIGGER IF NOT EXISTS trigger_vm_state_journal_no_update
+BEFORE UPDATE ON vm_state_journal
+BEGIN
+    SELECT RAISE(ABORT, 'vm_state_journal is append-only');
+END;
+
+CREATE TRIGGER IF NOT EXISTS trigger_vm_state_journal_no_delete
+BEFORE DELETE ON vm_state_journal
+BEGIN
+    SELECT RAISE(ABORT, 'vm_state_journal is append-only');
+END;
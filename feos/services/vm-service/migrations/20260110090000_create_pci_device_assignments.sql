@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS pci_device_assignments (
+    -- The PCI address of the device, e.g. 0000:41:00.0. A device is passed
+    -- through to one VM at a time.
+    pci_address TEXT PRIMARY KEY NOT NULL,
+    -- The VM the device is passed through to.
+    vm_id TEXT NOT NULL,
+    -- The driver the device was bound to before FeOS bound it to vfio-pci,
+    -- NULL if it was bound to vfio-pci already or unbound.
+    original_driver TEXT,
+    -- When the device was passed through.
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_pci_device_assignments_vm_id ON pci_device_assignments (vm_id);
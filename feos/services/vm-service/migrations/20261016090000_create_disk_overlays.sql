@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS disk_overlays (
+    -- The VM and disk written to the overlay.
+    vm_id TEXT NOT NULL,
+    device_id TEXT NOT NULL,
+    -- The canonical path of the image the overlay is based on. It must not
+    -- change or go away while the overlay exists.
+    base_path TEXT NOT NULL,
+    overlay_path TEXT NOT NULL,
+    -- The format the overlay is opened in, e.g. DISK_FORMAT_QCOW2 for one
+    -- with the base as its backing file.
+    format TEXT NOT NULL,
+    -- When the overlay was created.
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (vm_id, device_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_disk_overlays_base_path ON disk_overlays (base_path);
@@ -4,26 +4,44 @@
 use crate::Command;
 use feos_proto::vm_service::{
     vm_service_server::VmService, AttachDiskRequest, AttachDiskResponse, AttachNicRequest,
-    AttachNicResponse, CreateVmRequest, CreateVmResponse, DeleteVmRequest, DeleteVmResponse,
-    DetachDiskRequest, DetachDiskResponse, DetachNicRequest, DetachNicResponse, GetVmRequest,
-    ListVmsRequest, ListVmsResponse, PauseVmRequest, PauseVmResponse, PingVmRequest,
-    PingVmResponse, ResumeVmRequest, ResumeVmResponse, ShutdownVmRequest, ShutdownVmResponse,
+    AttachNicResponse, AttachPciDeviceRequest, AttachPciDeviceResponse, CreateVmRequest,
+    CreateVmResponse, CreateVmSnapshotRequest, CreateVmSnapshotResponse, DeleteDiskReplicasRequest,
+    DeleteDiskReplicasResponse, DeleteVmRequest, DeleteVmResponse, DeleteVmSnapshotRequest,
+    DeleteVmSnapshotResponse, DetachDiskRequest, DetachDiskResponse, DetachNicRequest,
+    DetachNicResponse, DetachPciDeviceRequest, DetachPciDeviceResponse, ExecInGuestRequest,
+    ExecInGuestResponse, GetGuestInfoRequest, GetGuestInfoResponse, GetVmBootMetricsRequest,
+    GetVmBootMetricsResponse, GetVmRequest, GetVmStatsRequest, GetVmStatsResponse, GuestFileChunk,
+    ListDiskReplicasRequest, ListDiskReplicasResponse, ListHostPciDevicesRequest,
+    ListHostPciDevicesResponse, ListVmEventsRequest, ListVmEventsResponse, ListVmSnapshotsRequest,
+    ListVmSnapshotsResponse, ListVmsRequest, ListVmsResponse, PauseVmRequest, PauseVmResponse,
+    PingVmRequest, PingVmResponse, PlanEvacuationRequest, PlanEvacuationResponse,
+    PortForwardRequest, PortForwardResponse, PullGuestFileRequest, PushGuestFileRequest,
+    PushGuestFileResponse, ReceiveDiskReplicaRequest, ReceiveDiskReplicaResponse,
+    ReplayVmStateJournalRequest, ReplayVmStateJournalResponse, ReplicateVmDisksRequest,
+    ReplicateVmDisksResponse, ResizeVmRequest, ResizeVmResponse, ResumeVmRequest, ResumeVmResponse,
+    RevertVmSnapshotRequest, RevertVmSnapshotResponse, ShutdownVmRequest, ShutdownVmResponse,
     StartVmRequest, StartVmResponse, StreamVmConsoleRequest, StreamVmConsoleResponse,
-    StreamVmEventsRequest, VmEvent, VmInfo,
+    StreamVmEventsRequest, StreamVmFlowsRequest, StreamVmMetricsRequest, VmEvent, VmFlowsSample,
+    VmInfo, VmMetricsSample,
 };
 use log::info;
 use std::pin::Pin;
 use tokio::sync::{mpsc, oneshot};
-use tokio_stream::{wrappers::ReceiverStream, Stream};
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
 use tonic::{Request, Response, Status, Streaming};
 
 pub struct VmApiHandler {
     dispatcher_tx: mpsc::Sender<Command>,
+    /// The ID of the node, which events, stats and samples are stamped with.
+    node_id: String,
 }
 
 impl VmApiHandler {
-    pub fn new(dispatcher_tx: mpsc::Sender<Command>) -> Self {
-        Self { dispatcher_tx }
+    pub fn new(dispatcher_tx: mpsc::Sender<Command>, node_id: String) -> Self {
+        Self {
+            dispatcher_tx,
+            node_id,
+        }
     }
 }
 
@@ -56,6 +74,12 @@ impl VmService for VmApiHandler {
     type StreamVmEventsStream = Pin<Box<dyn Stream<Item = Result<VmEvent, Status>> + Send>>;
     type StreamVmConsoleStream =
         Pin<Box<dyn Stream<Item = Result<StreamVmConsoleResponse, Status>> + Send>>;
+    type PortForwardStream =
+        Pin<Box<dyn Stream<Item = Result<PortForwardResponse, Status>> + Send>>;
+    type PullGuestFileStream = Pin<Box<dyn Stream<Item = Result<GuestFileChunk, Status>> + Send>>;
+    type StreamVmFlowsStream = Pin<Box<dyn Stream<Item = Result<VmFlowsSample, Status>> + Send>>;
+    type StreamVmMetricsStream =
+        Pin<Box<dyn Stream<Item = Result<VmMetricsSample, Status>> + Send>>;
 
     async fn create_vm(
         &self,
@@ -98,7 +122,13 @@ impl VmService for VmApiHandler {
             .send(cmd)
             .await
             .map_err(|e| Status::internal(format!("Failed to send command to dispatcher: {e}")))?;
-        let output_stream = ReceiverStream::new(stream_rx);
+        let node_id = self.node_id.clone();
+        let output_stream = ReceiverStream::new(stream_rx).map(move |event| {
+            event.map(|event| VmEvent {
+                node_id: node_id.clone(),
+                ..event
+            })
+        });
         Ok(Response::new(Box::pin(output_stream)))
     }
 
@@ -227,4 +257,307 @@ impl VmService for VmApiHandler {
         })
         .await
     }
+
+    async fn list_host_pci_devices(
+        &self,
+        request: Request<ListHostPciDevicesRequest>,
+    ) -> Result<Response<ListHostPciDevicesResponse>, Status> {
+        info!("VmApi: Received ListHostPciDevices request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::ListHostPciDevices(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn attach_pci_device(
+        &self,
+        request: Request<AttachPciDeviceRequest>,
+    ) -> Result<Response<AttachPciDeviceResponse>, Status> {
+        info!("VmApi: Received AttachPciDevice request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::AttachPciDevice(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn detach_pci_device(
+        &self,
+        request: Request<DetachPciDeviceRequest>,
+    ) -> Result<Response<DetachPciDeviceResponse>, Status> {
+        info!("VmApi: Received DetachPciDevice request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::DetachPciDevice(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn resize_vm(
+        &self,
+        request: Request<ResizeVmRequest>,
+    ) -> Result<Response<ResizeVmResponse>, Status> {
+        info!("VmApi: Received ResizeVm request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::ResizeVm(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn port_forward(
+        &self,
+        request: Request<Streaming<PortForwardRequest>>,
+    ) -> Result<Response<Self::PortForwardStream>, Status> {
+        info!("VmApi: Received PortForward stream request.");
+        let grpc_input_stream = request.into_inner();
+        let (grpc_output_tx, grpc_output_rx) = mpsc::channel(32);
+        let cmd = Command::PortForward(Box::new(grpc_input_stream), grpc_output_tx);
+        self.dispatcher_tx
+            .send(cmd)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to send command to dispatcher: {e}")))?;
+        let output_stream = ReceiverStream::new(grpc_output_rx);
+        Ok(Response::new(Box::pin(output_stream)))
+    }
+
+    async fn exec_in_guest(
+        &self,
+        request: Request<ExecInGuestRequest>,
+    ) -> Result<Response<ExecInGuestResponse>, Status> {
+        info!("VmApi: Received ExecInGuest request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::ExecInGuest(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn push_guest_file(
+        &self,
+        request: Request<Streaming<PushGuestFileRequest>>,
+    ) -> Result<Response<PushGuestFileResponse>, Status> {
+        info!("VmApi: Received PushGuestFile stream request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::PushGuestFile(Box::new(request.into_inner()), resp_tx)
+        })
+        .await
+    }
+
+    async fn pull_guest_file(
+        &self,
+        request: Request<PullGuestFileRequest>,
+    ) -> Result<Response<Self::PullGuestFileStream>, Status> {
+        info!("VmApi: Received PullGuestFile request.");
+        let (output_tx, output_rx) = mpsc::channel(4);
+        let cmd = Command::PullGuestFile(request.into_inner(), output_tx);
+        self.dispatcher_tx
+            .send(cmd)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to send command to dispatcher: {e}")))?;
+        let output_stream = ReceiverStream::new(output_rx);
+        Ok(Response::new(Box::pin(output_stream)))
+    }
+
+    async fn get_guest_info(
+        &self,
+        request: Request<GetGuestInfoRequest>,
+    ) -> Result<Response<GetGuestInfoResponse>, Status> {
+        info!("VmApi: Received GetGuestInfo request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::GetGuestInfo(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn create_vm_snapshot(
+        &self,
+        request: Request<CreateVmSnapshotRequest>,
+    ) -> Result<Response<CreateVmSnapshotResponse>, Status> {
+        info!("VmApi: Received CreateVmSnapshot request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::CreateVmSnapshot(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn list_vm_snapshots(
+        &self,
+        request: Request<ListVmSnapshotsRequest>,
+    ) -> Result<Response<ListVmSnapshotsResponse>, Status> {
+        info!("VmApi: Received ListVmSnapshots request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::ListVmSnapshots(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn revert_vm_snapshot(
+        &self,
+        request: Request<RevertVmSnapshotRequest>,
+    ) -> Result<Response<RevertVmSnapshotResponse>, Status> {
+        info!("VmApi: Received RevertVmSnapshot request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::RevertVmSnapshot(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn delete_vm_snapshot(
+        &self,
+        request: Request<DeleteVmSnapshotRequest>,
+    ) -> Result<Response<DeleteVmSnapshotResponse>, Status> {
+        info!("VmApi: Received DeleteVmSnapshot request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::DeleteVmSnapshot(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn get_vm_boot_metrics(
+        &self,
+        request: Request<GetVmBootMetricsRequest>,
+    ) -> Result<Response<GetVmBootMetricsResponse>, Status> {
+        info!("VmApi: Received GetVmBootMetrics request.");
+        let mut response = dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::GetVmBootMetrics(request.into_inner(), resp_tx)
+        })
+        .await?;
+        response.get_mut().node_id.clone_from(&self.node_id);
+        Ok(response)
+    }
+
+    async fn list_vm_events(
+        &self,
+        request: Request<ListVmEventsRequest>,
+    ) -> Result<Response<ListVmEventsResponse>, Status> {
+        info!("VmApi: Received ListVmEvents request.");
+        let mut response = dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::ListVmEvents(request.into_inner(), resp_tx)
+        })
+        .await?;
+        for recorded in &mut response.get_mut().events {
+            if let Some(event) = recorded.event.as_mut() {
+                event.node_id.clone_from(&self.node_id);
+            }
+        }
+        Ok(response)
+    }
+
+    async fn replay_vm_state_journal(
+        &self,
+        request: Request<ReplayVmStateJournalRequest>,
+    ) -> Result<Response<ReplayVmStateJournalResponse>, Status> {
+        info!("VmApi: Received ReplayVmStateJournal request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::ReplayVmStateJournal(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn plan_evacuation(
+        &self,
+        request: Request<PlanEvacuationRequest>,
+    ) -> Result<Response<PlanEvacuationResponse>, Status> {
+        info!("VmApi: Received PlanEvacuation request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::PlanEvacuation(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn get_vm_stats(
+        &self,
+        request: Request<GetVmStatsRequest>,
+    ) -> Result<Response<GetVmStatsResponse>, Status> {
+        info!("VmApi: Received GetVmStats request.");
+        let mut response = dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::GetVmStats(request.into_inner(), resp_tx)
+        })
+        .await?;
+        response.get_mut().node_id.clone_from(&self.node_id);
+        Ok(response)
+    }
+
+    async fn stream_vm_flows(
+        &self,
+        request: Request<StreamVmFlowsRequest>,
+    ) -> Result<Response<Self::StreamVmFlowsStream>, Status> {
+        info!("VmApi: Received StreamVmFlows stream request.");
+        let (stream_tx, stream_rx) = mpsc::channel(4);
+        let cmd = Command::StreamVmFlows(request.into_inner(), stream_tx);
+        self.dispatcher_tx
+            .send(cmd)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to send command to dispatcher: {e}")))?;
+        let node_id = self.node_id.clone();
+        let output_stream = ReceiverStream::new(stream_rx).map(move |sample| {
+            sample.map(|sample| VmFlowsSample {
+                node_id: node_id.clone(),
+                ..sample
+            })
+        });
+        Ok(Response::new(Box::pin(output_stream)))
+    }
+
+    async fn stream_vm_metrics(
+        &self,
+        request: Request<StreamVmMetricsRequest>,
+    ) -> Result<Response<Self::StreamVmMetricsStream>, Status> {
+        info!("VmApi: Received StreamVmMetrics stream request.");
+        let (stream_tx, stream_rx) = mpsc::channel(4);
+        let cmd = Command::StreamVmMetrics(request.into_inner(), stream_tx);
+        self.dispatcher_tx
+            .send(cmd)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to send command to dispatcher: {e}")))?;
+        let node_id = self.node_id.clone();
+        let output_stream = ReceiverStream::new(stream_rx).map(move |sample| {
+            sample.map(|sample| VmMetricsSample {
+                node_id: node_id.clone(),
+                ..sample
+            })
+        });
+        Ok(Response::new(Box::pin(output_stream)))
+    }
+
+    async fn replicate_vm_disks(
+        &self,
+        request: Request<ReplicateVmDisksRequest>,
+    ) -> Result<Response<ReplicateVmDisksResponse>, Status> {
+        info!("VmApi: Received ReplicateVmDisks request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::ReplicateVmDisks(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn receive_disk_replica(
+        &self,
+        request: Request<Streaming<ReceiveDiskReplicaRequest>>,
+    ) -> Result<Response<ReceiveDiskReplicaResponse>, Status> {
+        info!("VmApi: Received ReceiveDiskReplica stream request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::ReceiveDiskReplica(Box::new(request.into_inner()), resp_tx)
+        })
+        .await
+    }
+
+    async fn list_disk_replicas(
+        &self,
+        request: Request<ListDiskReplicasRequest>,
+    ) -> Result<Response<ListDiskReplicasResponse>, Status> {
+        info!("VmApi: Received ListDiskReplicas request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::ListDiskReplicas(request.into_inner(), resp_tx)
+        })
+        .await
+    }
+
+    async fn delete_disk_replicas(
+        &self,
+        request: Request<DeleteDiskReplicasRequest>,
+    ) -> Result<Response<DeleteDiskReplicasResponse>, Status> {
+        info!("VmApi: Received DeleteDiskReplicas request.");
+        dispatch_and_wait(&self.dispatcher_tx, |resp_tx| {
+            Command::DeleteDiskReplicas(request.into_inner(), resp_tx)
+        })
+        .await
+    }
 }
@@ -0,0 +1,223 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use feos_proto::vm_service::{
+    BootDurationHistogram, GetVmBootMetricsResponse, VmBootPhase, VmBootTimings,
+};
+use prost_types::Timestamp;
+
+/// Upper bounds of the histogram buckets in seconds. Booting from a cached
+/// image takes well under a second, pulling a large one can take minutes.
+const BUCKET_BOUNDS_SECONDS: [f64; 12] = [
+    0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0,
+];
+
+/// The measured intervals between two boot phases.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Interval {
+    ImageWait,
+    VmmSpawn,
+    VmCreate,
+    Boot,
+    Total,
+}
+
+impl Interval {
+    const ALL: [Interval; 5] = [
+        Interval::ImageWait,
+        Interval::VmmSpawn,
+        Interval::VmCreate,
+        Interval::Boot,
+        Interval::Total,
+    ];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Interval::ImageWait => "image_wait",
+            Interval::VmmSpawn => "vmm_spawn",
+            Interval::VmCreate => "vm_create",
+            Interval::Boot => "boot",
+            Interval::Total => "total",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Histogram {
+    /// Observations per bucket, the last one counting those above every bound.
+    buckets: [u64; BUCKET_BOUNDS_SECONDS.len() + 1],
+    count: u64,
+    sum_seconds: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        let bucket = BUCKET_BOUNDS_SECONDS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(BUCKET_BOUNDS_SECONDS.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum_seconds += seconds;
+    }
+
+    fn to_proto(&self, interval: Interval) -> BootDurationHistogram {
+        let bucket_counts = self
+            .buckets
+            .iter()
+            .scan(0, |total, count| {
+                *total += count;
+                Some(*total)
+            })
+            .collect();
+        BootDurationHistogram {
+            phase: interval.as_str().to_string(),
+            bucket_bounds_seconds: BUCKET_BOUNDS_SECONDS.to_vec(),
+            bucket_counts,
+            count: self.count,
+            sum_seconds: self.sum_seconds,
+        }
+    }
+}
+
+fn seconds_between(start: Option<&Timestamp>, end: Option<&Timestamp>) -> Option<f64> {
+    let (start, end) = (start?, end?);
+    let seconds = (end.seconds - start.seconds) as f64 + f64::from(end.nanos - start.nanos) / 1e9;
+    // The wall clock may have been stepped between the two phases.
+    (seconds >= 0.0).then_some(seconds)
+}
+
+/// Boot phase durations of all VMs since the vm-service started.
+#[derive(Default)]
+pub struct BootMetrics {
+    histograms: [Histogram; Interval::ALL.len()],
+}
+
+impl BootMetrics {
+    /// Records in `timings` that a VM reached `phase` at `at`, and observes
+    /// the duration of the interval the phase completes.
+    pub fn record(&mut self, timings: &mut VmBootTimings, phase: VmBootPhase, at: Timestamp) {
+        let at = Some(at);
+        match phase {
+            VmBootPhase::Unspecified => {}
+            VmBootPhase::CreateRequested => timings.create_requested_at = at,
+            VmBootPhase::ImageReady => {
+                timings.image_ready_at = at;
+                self.observe(
+                    Interval::ImageWait,
+                    &timings.create_requested_at,
+                    &timings.image_ready_at,
+                );
+            }
+            VmBootPhase::VmmSpawned => {
+                timings.vmm_spawned_at = at;
+                self.observe(
+                    Interval::VmmSpawn,
+                    &timings.image_ready_at,
+                    &timings.vmm_spawned_at,
+                );
+            }
+            VmBootPhase::Created => {
+                timings.created_at = at;
+                self.observe(
+                    Interval::VmCreate,
+                    &timings.vmm_spawned_at,
+                    &timings.created_at,
+                );
+            }
+            VmBootPhase::BootRequested => timings.boot_requested_at = at,
+            VmBootPhase::Booted => {
+                let first_boot = timings.booted_at.is_none();
+                timings.booted_at = at;
+                self.observe(
+                    Interval::Boot,
+                    &timings.boot_requested_at,
+                    &timings.booted_at,
+                );
+                if first_boot {
+                    self.observe(
+                        Interval::Total,
+                        &timings.create_requested_at,
+                        &timings.booted_at,
+                    );
+                }
+            }
+        }
+    }
+
+    fn observe(&mut self, interval: Interval, start: &Option<Timestamp>, end: &Option<Timestamp>) {
+        if let Some(seconds) = seconds_between(start.as_ref(), end.as_ref()) {
+            self.histograms[interval as usize].observe(seconds);
+        }
+    }
+
+    pub fn to_proto(&self) -> GetVmBootMetricsResponse {
+        GetVmBootMetricsResponse {
+            histograms: Interval::ALL
+                .into_iter()
+                .map(|interval| self.histograms[interval as usize].to_proto(interval))
+                .collect(),
+            node_id: String::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(seconds: i64, nanos: i32) -> Timestamp {
+        Timestamp { seconds, nanos }
+    }
+
+    #[test]
+    fn phases_fill_timings_and_histograms() {
+        let mut metrics = BootMetrics::default();
+        let mut timings = VmBootTimings::default();
+        metrics.record(&mut timings, VmBootPhase::CreateRequested, at(100, 0));
+        metrics.record(&mut timings, VmBootPhase::ImageReady, at(102, 500_000_000));
+        metrics.record(&mut timings, VmBootPhase::VmmSpawned, at(102, 600_000_000));
+        metrics.record(&mut timings, VmBootPhase::Created, at(102, 700_000_000));
+        metrics.record(&mut timings, VmBootPhase::BootRequested, at(110, 0));
+        metrics.record(&mut timings, VmBootPhase::Booted, at(110, 200_000_000));
+        // Starting the VM again only counts as another boot.
+        metrics.record(&mut timings, VmBootPhase::BootRequested, at(200, 0));
+        metrics.record(&mut timings, VmBootPhase::Booted, at(200, 100_000_000));
+
+        assert_eq!(timings.created_at, Some(at(102, 700_000_000)));
+        assert_eq!(timings.booted_at, Some(at(200, 100_000_000)));
+
+        let response = metrics.to_proto();
+        let histogram = |phase: &str| {
+            response
+                .histograms
+                .iter()
+                .find(|h| h.phase == phase)
+                .unwrap()
+        };
+        let image_wait = histogram("image_wait");
+        assert_eq!(image_wait.count, 1);
+        assert!((image_wait.sum_seconds - 2.5).abs() < 1e-9);
+        // 2.5 s falls into the bucket bounded by 2.5 and all above it.
+        assert_eq!(image_wait.bucket_counts[4], 0);
+        assert_eq!(image_wait.bucket_counts[5], 1);
+        assert_eq!(
+            image_wait.bucket_counts.len(),
+            image_wait.bucket_bounds_seconds.len() + 1
+        );
+
+        assert_eq!(histogram("boot").count, 2);
+        let total = histogram("total");
+        assert_eq!(total.count, 1);
+        assert!((total.sum_seconds - 10.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn backwards_clock_steps_are_not_observed() {
+        let mut metrics = BootMetrics::default();
+        let mut timings = VmBootTimings::default();
+        metrics.record(&mut timings, VmBootPhase::BootRequested, at(100, 0));
+        metrics.record(&mut timings, VmBootPhase::Booted, at(99, 0));
+        assert_eq!(metrics.histograms[Interval::Boot as usize].count, 0);
+    }
+}
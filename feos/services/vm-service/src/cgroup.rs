@@ -0,0 +1,94 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::VM_CGROUP_DIR;
+use feos_utils::host::nic::format_cpu_list;
+use feos_utils::host::numa;
+use log::{info, warn};
+use nix::errno::Errno;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::fs;
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// How long a killed hypervisor process may take to leave its cgroup.
+const REMOVE_ATTEMPTS: u32 = 10;
+const REMOVE_INTERVAL: Duration = Duration::from_millis(100);
+
+fn cgroup_dir(vm_id: &str) -> PathBuf {
+    Path::new(VM_CGROUP_DIR).join(vm_id)
+}
+
+/// Makes a controller available to the VM cgroups. cgroup v2 only hands
+/// controllers down one level at a time.
+async fn enable_controller(controller: &str) -> io::Result<()> {
+    fs::create_dir_all(VM_CGROUP_DIR).await?;
+    for dir in [CGROUP_ROOT, VM_CGROUP_DIR] {
+        fs::write(
+            Path::new(dir).join("cgroup.subtree_control"),
+            format!("+{controller}"),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Moves the hypervisor process of a VM into a cgroup of its own that lets
+/// the host swap out at most `swap_max` bytes of it. cgroup v2 has no
+/// per-group swappiness, so the limit is the only per-VM control.
+pub async fn limit_swap(vm_id: &str, pid: i64, swap_max: u64) -> io::Result<()> {
+    enable_controller("memory").await?;
+    let dir = cgroup_dir(vm_id);
+    fs::create_dir_all(&dir).await?;
+    fs::write(dir.join("memory.swap.max"), swap_max.to_string()).await?;
+    fs::write(dir.join("cgroup.procs"), pid.to_string()).await?;
+    info!("Cgroup: Limited swap of VM {vm_id} to {swap_max} bytes");
+    Ok(())
+}
+
+/// Binds the hypervisor process of a VM to the CPUs and memory of a NUMA
+/// node. Has to happen before the guest memory is allocated, memory that
+/// is already allocated stays where it is.
+pub async fn bind_to_node(vm_id: &str, pid: i64, node: u32) -> io::Result<()> {
+    let cpus = numa::nodes()?
+        .into_iter()
+        .find(|n| n.id == node)
+        .map(|n| format_cpu_list(&n.cpus))
+        .ok_or_else(|| {
+            io::Error::new(ErrorKind::NotFound, format!("NUMA node {node} has no CPUs"))
+        })?;
+    enable_controller("cpuset").await?;
+    let dir = cgroup_dir(vm_id);
+    fs::create_dir_all(&dir).await?;
+    fs::write(dir.join("cpuset.cpus"), &cpus).await?;
+    fs::write(dir.join("cpuset.mems"), node.to_string()).await?;
+    fs::write(dir.join("cgroup.procs"), pid.to_string()).await?;
+    info!("Cgroup: Bound VM {vm_id} to NUMA node {node} (CPUs {cpus})");
+    Ok(())
+}
+
+/// Removes the cgroup of a VM once its hypervisor process is gone. Does
+/// nothing for VMs that never had one.
+pub async fn remove(vm_id: &str) {
+    let dir = cgroup_dir(vm_id);
+    for _ in 0..REMOVE_ATTEMPTS {
+        match fs::remove_dir(&dir).await {
+            Ok(()) => return,
+            Err(e) if e.kind() == ErrorKind::NotFound => return,
+            // The cgroup is busy until the killed process has exited.
+            Err(e) if e.raw_os_error() == Some(Errno::EBUSY as i32) => {
+                tokio::time::sleep(REMOVE_INTERVAL).await;
+            }
+            Err(e) => {
+                warn!("Cgroup: Failed to remove {}: {e}", dir.display());
+                return;
+            }
+        }
+    }
+    warn!(
+        "Cgroup: {} is still in use, leaving it in place",
+        dir.display()
+    );
+}
@@ -0,0 +1,290 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! NoCloud seeds for cloud-init. The user-data, meta-data and network-config
+//! of a VM are written to a small FAT image labeled CIDATA, which is attached
+//! to the VM read-only and found there by cloud-init on boot. Secrets of the
+//! VM are passed as vendor-data, which has cloud-init write them to files.
+
+use crate::{error::VmServiceError, VM_CLOUD_INIT_DIR};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use feos_proto::vm_service::{disk_config, CloudInitConfig, DiskConfig, VmConfig};
+use feos_utils::namespace::validate_name;
+use log::{info, warn};
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+/// The device ID of the seed disk, which disks of the VM cannot use.
+pub const SEED_DEVICE_ID: &str = "cloud-init";
+const VOLUME_LABEL: [u8; 11] = *b"CIDATA     ";
+/// What cloud-init is given in total, which keeps the seeds small.
+const MAX_DATA_BYTES: usize = 4 * 1024 * 1024;
+/// Room for the FAT and directory next to the files, and the smallest
+/// volume a FAT file system is formatted with.
+const SEED_OVERHEAD_BYTES: u64 = 1024 * 1024;
+const SEED_ALIGNMENT: u64 = 1024 * 1024;
+
+fn seed_path(vm_id: &str) -> PathBuf {
+    Path::new(VM_CLOUD_INIT_DIR).join(format!("{vm_id}.img"))
+}
+
+fn data_bytes(config: &CloudInitConfig) -> usize {
+    config.user_data.len() + config.meta_data.len() + config.network_config.len()
+}
+
+/// Checks the cloud-init data of a VM, if it has any.
+pub fn validate(config: &VmConfig) -> Result<(), VmServiceError> {
+    let Some(cloud_init) = &config.cloud_init else {
+        return Ok(());
+    };
+    if data_bytes(cloud_init) > MAX_DATA_BYTES {
+        return Err(VmServiceError::InvalidArgument(format!(
+            "Cloud-init data must not exceed {MAX_DATA_BYTES} bytes in total, got {}",
+            data_bytes(cloud_init)
+        )));
+    }
+    if config
+        .disks
+        .iter()
+        .any(|disk| disk.device_id == SEED_DEVICE_ID)
+    {
+        return Err(VmServiceError::InvalidArgument(format!(
+            "Disk ID '{SEED_DEVICE_ID}' is taken by the cloud-init seed"
+        )));
+    }
+    let mut paths = HashSet::new();
+    for secret in &cloud_init.secrets {
+        validate_name(&secret.name).map_err(|e| {
+            VmServiceError::InvalidArgument(format!("Invalid cloud-init secret: {e}"))
+        })?;
+        if !secret.path.starts_with('/') {
+            return Err(VmServiceError::InvalidArgument(format!(
+                "The path of secret '{}' must be absolute, got '{}'",
+                secret.name, secret.path
+            )));
+        }
+        if !paths.insert(secret.path.as_str()) {
+            return Err(VmServiceError::InvalidArgument(format!(
+                "More than one secret is written to '{}'",
+                secret.path
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The meta-data of a VM. Without one given, the VM ID is the instance ID,
+/// which makes cloud-init treat a recreated VM as the same instance, and
+/// the name of the VM its hostname.
+fn meta_data(config: &CloudInitConfig, vm_id: &str, name: Option<&str>) -> String {
+    if !config.meta_data.is_empty() {
+        return config.meta_data.clone();
+    }
+    let mut meta_data = format!("instance-id: {vm_id}\n");
+    if let Some(name) = name {
+        meta_data.push_str(&format!("local-hostname: {name}\n"));
+    }
+    meta_data
+}
+
+/// The vendor-data writing the secrets of a VM, given by the path they are
+/// written to and their value, to files of the guest. Vendor-data is merged
+/// with the user-data, so it does not take the place of user-given files.
+fn vendor_data(secrets: &[(String, Vec<u8>)]) -> String {
+    let mut vendor_data = "#cloud-config\nwrite_files:\n".to_string();
+    for (path, value) in secrets {
+        // A JSON string is a YAML string, whatever the path contains.
+        let path = serde_json::Value::from(path.as_str());
+        vendor_data.push_str(&format!(
+            "  - path: {path}\n    encoding: b64\n    content: {}\n    owner: root:root\n    permissions: '0600'\n",
+            STANDARD.encode(value)
+        ));
+    }
+    vendor_data
+}
+
+/// The files of the seed, by name. network-config is left out without one,
+/// so the guest configures its network as it would without cloud-init, and
+/// vendor-data without secrets.
+fn seed_files(
+    config: &CloudInitConfig,
+    vm_id: &str,
+    name: Option<&str>,
+    secrets: &[(String, Vec<u8>)],
+) -> Vec<(&'static str, Vec<u8>)> {
+    let mut files = vec![
+        ("user-data", config.user_data.clone().into_bytes()),
+        ("meta-data", meta_data(config, vm_id, name).into_bytes()),
+    ];
+    if !config.network_config.is_empty() {
+        files.push(("network-config", config.network_config.clone().into_bytes()));
+    }
+    if !secrets.is_empty() {
+        files.push(("vendor-data", vendor_data(secrets).into_bytes()));
+    }
+    files
+}
+
+fn write_seed(path: &Path, files: &[(&str, Vec<u8>)]) -> io::Result<()> {
+    let content: u64 = files.iter().map(|(_, data)| data.len() as u64).sum();
+    let size = (content + SEED_OVERHEAD_BYTES).div_ceil(SEED_ALIGNMENT) * SEED_ALIGNMENT;
+    let mut image = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    image.set_len(size)?;
+    fatfs::format_volume(
+        &mut image,
+        fatfs::FormatVolumeOptions::new().volume_label(VOLUME_LABEL),
+    )?;
+    let fs = fatfs::FileSystem::new(&mut image, fatfs::FsOptions::new())?;
+    for (name, data) in files {
+        fs.root_dir().create_file(name)?.write_all(data)?;
+    }
+    fs.unmount()?;
+    image.sync_all()
+}
+
+/// Writes the NoCloud seed of a VM and returns the read-only disk it is
+/// attached as. `secrets` are the values of the secrets of `config` by the
+/// path they are written to. The seed holds them in plain text, so only
+/// root may read it.
+pub async fn create_seed(
+    vm_id: &str,
+    name: Option<&str>,
+    config: &CloudInitConfig,
+    secrets: &[(String, Vec<u8>)],
+) -> Result<DiskConfig, VmServiceError> {
+    let path = seed_path(vm_id);
+    let files = seed_files(config, vm_id, name, secrets);
+    let result = {
+        let path = path.clone();
+        tokio::task::spawn_blocking(move || {
+            std::fs::create_dir_all(VM_CLOUD_INIT_DIR)?;
+            write_seed(&path, &files)
+        })
+        .await
+        .map_err(io::Error::other)
+        .and_then(|result| result)
+    };
+    if let Err(e) = result {
+        remove_seed(vm_id).await;
+        return Err(VmServiceError::CloudInit(format!(
+            "Failed to write {}: {e}",
+            path.display()
+        )));
+    }
+    info!(
+        "CloudInit: Wrote the NoCloud seed of VM {vm_id} to {}",
+        path.display()
+    );
+    Ok(DiskConfig {
+        device_id: SEED_DEVICE_ID.to_string(),
+        backend: Some(disk_config::Backend::Path(
+            path.to_string_lossy().into_owned(),
+        )),
+        readonly: true,
+        ..Default::default()
+    })
+}
+
+pub async fn remove_seed(vm_id: &str) {
+    let path = seed_path(vm_id);
+    if let Err(e) = tokio::fs::remove_file(&path).await {
+        if e.kind() != io::ErrorKind::NotFound {
+            warn!("CloudInit: Failed to remove {}: {e}", path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use tempfile::TempDir;
+
+    #[test]
+    fn seeds_are_fat_volumes_labeled_cidata_with_the_nocloud_files() {
+        let config = CloudInitConfig {
+            user_data: "#cloud-config\nssh_authorized_keys:\n  - ssh-ed25519 AAAA\n".to_string(),
+            network_config: "version: 2\n".to_string(),
+            ..Default::default()
+        };
+        let files = seed_files(&config, "0b5e", Some("web-1"), &[]);
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("seed.img");
+        write_seed(&path, &files).unwrap();
+
+        let image = std::fs::File::open(&path).unwrap();
+        assert_eq!(image.metadata().unwrap().len() % SEED_ALIGNMENT, 0);
+        let fs = fatfs::FileSystem::new(image, fatfs::FsOptions::new()).unwrap();
+        assert_eq!(fs.volume_label(), "CIDATA");
+        let read = |name: &str| {
+            let mut content = String::new();
+            fs.root_dir()
+                .open_file(name)
+                .unwrap()
+                .read_to_string(&mut content)
+                .unwrap();
+            content
+        };
+        assert_eq!(read("user-data"), config.user_data);
+        assert_eq!(
+            read("meta-data"),
+            "instance-id: 0b5e\nlocal-hostname: web-1\n"
+        );
+        assert_eq!(read("network-config"), "version: 2\n");
+        let names: Vec<String> = fs
+            .root_dir()
+            .iter()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["user-data", "meta-data", "network-config"]);
+
+        // Given meta-data is used as it is, and without network-config the
+        // guest keeps its own network configuration.
+        let config = CloudInitConfig {
+            meta_data: "instance-id: custom\n".to_string(),
+            ..Default::default()
+        };
+        let files = seed_files(&config, "0b5e", None, &[]);
+        let names: Vec<&str> = files.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, ["user-data", "meta-data"]);
+        assert_eq!(files[1].1, b"instance-id: custom\n");
+    }
+
+    #[test]
+    fn secrets_are_written_by_vendor_data_for_root_only() {
+        let secrets = [
+            ("/etc/app/token".to_string(), b"s3cr3t".to_vec()),
+            ("/root/key: \"x\"".to_string(), vec![0, 255]),
+        ];
+        let files = seed_files(&CloudInitConfig::default(), "0b5e", None, &secrets);
+        let (name, vendor_data) = files.last().unwrap();
+        assert_eq!(*name, "vendor-data");
+        let expected = [
+            "#cloud-config",
+            "write_files:",
+            "  - path: \"/etc/app/token\"",
+            "    encoding: b64",
+            "    content: czNjcjN0",
+            "    owner: root:root",
+            "    permissions: '0600'",
+            "  - path: \"/root/key: \\\"x\\\"\"",
+            "    encoding: b64",
+            "    content: AP8=",
+            "    owner: root:root",
+            "    permissions: '0600'",
+        ];
+        assert_eq!(
+            String::from_utf8(vendor_data.clone()).unwrap(),
+            expected.map(|line| format!("{line}\n")).concat()
+        );
+    }
+}
@@ -0,0 +1,355 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use log::{info, warn};
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+use tokio::sync::{broadcast, mpsc, watch};
+
+/// Console output kept for clients attaching later, so they see the prompt
+/// and recent boot messages.
+const BACKLOG_BYTES: usize = 64 * 1024;
+const OUTPUT_CHANNEL_CAPACITY: usize = 256;
+const INPUT_CHANNEL_CAPACITY: usize = 64;
+/// No client holds the write access.
+const NO_WRITER: u64 = 0;
+
+/// The most recent console output, up to a fixed number of bytes.
+struct Backlog {
+    bytes: VecDeque<u8>,
+    capacity: usize,
+}
+
+impl Backlog {
+    fn new(capacity: usize) -> Self {
+        Self {
+            bytes: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    fn push(&mut self, output: &[u8]) {
+        let output = &output[output.len().saturating_sub(self.capacity)..];
+        let overflow = (self.bytes.len() + output.len()).saturating_sub(self.capacity);
+        self.bytes.drain(..overflow);
+        self.bytes.extend(output);
+    }
+
+    fn to_vec(&self) -> Vec<u8> {
+        self.bytes.iter().copied().collect()
+    }
+}
+
+/// The single connection to the console socket of a VM, shared by all
+/// clients attached to it.
+struct ConsoleSession {
+    output: Mutex<SessionOutput>,
+    input: mpsc::Sender<Vec<u8>>,
+    writer: watch::Sender<u64>,
+    next_client: AtomicU64,
+}
+
+/// The output is buffered and broadcast under the same lock, so clients
+/// attaching meanwhile neither miss nor repeat any of it.
+struct SessionOutput {
+    backlog: Backlog,
+    /// Dropped once the console socket is closed, which ends the output of
+    /// all clients.
+    sender: Option<broadcast::Sender<Vec<u8>>>,
+}
+
+impl ConsoleSession {
+    fn output(&self) -> std::sync::MutexGuard<'_, SessionOutput> {
+        self.output.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Multiplexes the console of each VM to any number of watching clients.
+/// The console socket of a VM only takes one connection, so the manager
+/// holds it for as long as the VM runs and buffers the output.
+#[derive(Clone, Default)]
+pub struct ConsoleManager {
+    sessions: Arc<tokio::sync::Mutex<HashMap<String, Arc<ConsoleSession>>>>,
+}
+
+impl ConsoleManager {
+    /// Attaches a client to the console of `vm_id`, connecting to
+    /// `socket_path` if no client is attached yet. Unless `read_only`, the
+    /// client gets the write access if it is free or if it takes it over.
+    pub async fn attach(
+        &self,
+        vm_id: &str,
+        socket_path: &Path,
+        read_only: bool,
+        takeover: bool,
+    ) -> io::Result<ConsoleAttachment> {
+        let session = {
+            let mut sessions = self.sessions.lock().await;
+            match sessions.get(vm_id) {
+                Some(session) if !session.input.is_closed() => session.clone(),
+                _ => {
+                    let socket = UnixStream::connect(socket_path).await?;
+                    let session = self.start_session(vm_id, socket);
+                    sessions.insert(vm_id.to_string(), session.clone());
+                    session
+                }
+            }
+        };
+        Ok(ConsoleAttachment::new(session, read_only, takeover))
+    }
+
+    fn start_session(&self, vm_id: &str, socket: UnixStream) -> Arc<ConsoleSession> {
+        let (sender, _) = broadcast::channel(OUTPUT_CHANNEL_CAPACITY);
+        let (input, input_rx) = mpsc::channel(INPUT_CHANNEL_CAPACITY);
+        let (writer, _) = watch::channel(NO_WRITER);
+        let session = Arc::new(ConsoleSession {
+            output: Mutex::new(SessionOutput {
+                backlog: Backlog::new(BACKLOG_BYTES),
+                sender: Some(sender),
+            }),
+            input,
+            writer,
+            next_client: AtomicU64::new(NO_WRITER + 1),
+        });
+        tokio::spawn(run_session(
+            vm_id.to_string(),
+            socket,
+            session.clone(),
+            input_rx,
+            self.clone(),
+        ));
+        session
+    }
+
+    async fn remove_session(&self, vm_id: &str, session: &Arc<ConsoleSession>) {
+        let mut sessions = self.sessions.lock().await;
+        if sessions
+            .get(vm_id)
+            .is_some_and(|current| Arc::ptr_eq(current, session))
+        {
+            sessions.remove(vm_id);
+        }
+    }
+}
+
+async fn run_session(
+    vm_id: String,
+    socket: UnixStream,
+    session: Arc<ConsoleSession>,
+    mut input_rx: mpsc::Receiver<Vec<u8>>,
+    manager: ConsoleManager,
+) {
+    let (mut reader, mut writer) = socket.into_split();
+    let mut buf = vec![0; 4096];
+    loop {
+        tokio::select! {
+            read = reader.read(&mut buf) => match read {
+                Ok(0) => {
+                    info!("ConsoleManager ({vm_id}): Console socket closed (EOF).");
+                    break;
+                }
+                Ok(n) => {
+                    let mut output = session.output();
+                    output.backlog.push(&buf[..n]);
+                    if let Some(sender) = &output.sender {
+                        let _ = sender.send(buf[..n].to_vec());
+                    }
+                }
+                Err(e) => {
+                    warn!("ConsoleManager ({vm_id}): Error reading from console socket: {e}");
+                    break;
+                }
+            },
+            Some(input) = input_rx.recv() => {
+                if let Err(e) = writer.write_all(&input).await {
+                    warn!("ConsoleManager ({vm_id}): Failed to write to console socket: {e}. VM may have shut down.");
+                    break;
+                }
+            }
+        }
+    }
+    input_rx.close();
+    session.output().sender = None;
+    manager.remove_session(&vm_id, &session).await;
+}
+
+pub enum ConsoleEvent {
+    Output(Vec<u8>),
+    /// The write access changed hands, and whether the client holds it now.
+    Writable(bool),
+}
+
+/// A client attached to the console of a VM. Dropping it detaches the
+/// client and gives up its write access.
+pub struct ConsoleAttachment {
+    session: Arc<ConsoleSession>,
+    id: u64,
+    backlog: Vec<u8>,
+    output: broadcast::Receiver<Vec<u8>>,
+    writer: watch::Receiver<u64>,
+}
+
+impl ConsoleAttachment {
+    fn new(session: Arc<ConsoleSession>, read_only: bool, takeover: bool) -> Self {
+        let id = session.next_client.fetch_add(1, Ordering::Relaxed);
+        if !read_only {
+            session.writer.send_if_modified(|writer| {
+                let take = *writer == NO_WRITER || takeover;
+                if take {
+                    *writer = id;
+                }
+                take
+            });
+        }
+        let (backlog, output) = {
+            let output = session.output();
+            let receiver = match &output.sender {
+                Some(sender) => sender.subscribe(),
+                None => broadcast::channel(1).1,
+            };
+            (output.backlog.to_vec(), receiver)
+        };
+        let writer = session.writer.subscribe();
+        Self {
+            session,
+            id,
+            backlog,
+            output,
+            writer,
+        }
+    }
+
+    /// The output from before the client attached.
+    pub fn take_backlog(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.backlog)
+    }
+
+    /// Waits for the next console output or change of the write access.
+    /// Returns `None` once the console is closed. Output the client was too
+    /// slow to receive is skipped.
+    pub async fn next_event(&mut self) -> Option<ConsoleEvent> {
+        loop {
+            tokio::select! {
+                output = self.output.recv() => match output {
+                    Ok(output) => return Some(ConsoleEvent::Output(output)),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("ConsoleManager: Client fell behind, skipped {skipped} console outputs.");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                },
+                // The session holds the sender, so this never fails.
+                Ok(()) = self.writer.changed() => {
+                    let writable = *self.writer.borrow_and_update() == self.id;
+                    return Some(ConsoleEvent::Writable(writable));
+                }
+            }
+        }
+    }
+
+    pub fn is_writer(&self) -> bool {
+        *self.writer.borrow() == self.id
+    }
+
+    /// Sends input to the console if the client holds the write access.
+    /// Returns whether it was sent.
+    pub async fn write(&self, input: Vec<u8>) -> io::Result<bool> {
+        if !self.is_writer() {
+            return Ok(false);
+        }
+        self.session
+            .input
+            .send(input)
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "console socket is closed"))?;
+        Ok(true)
+    }
+}
+
+impl Drop for ConsoleAttachment {
+    fn drop(&mut self) {
+        self.session.writer.send_if_modified(|writer| {
+            let release = *writer == self.id;
+            if release {
+                *writer = NO_WRITER;
+            }
+            release
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backlog_keeps_the_most_recent_output() {
+        let mut backlog = Backlog::new(8);
+        backlog.push(b"login");
+        backlog.push(b": root");
+        assert_eq!(backlog.to_vec(), b"n: root");
+        backlog.push(b"0123456789");
+        assert_eq!(backlog.to_vec(), b"23456789");
+    }
+
+    async fn output(console: &mut ConsoleAttachment) -> Vec<u8> {
+        match console.next_event().await {
+            Some(ConsoleEvent::Output(output)) => output,
+            _ => panic!("expected console output"),
+        }
+    }
+
+    #[tokio::test]
+    async fn watchers_share_output_and_take_over_the_write_access() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("vm.console");
+        let listener = tokio::net::UnixListener::bind(&socket_path).unwrap();
+        let manager = ConsoleManager::default();
+
+        let mut first = manager
+            .attach("vm", &socket_path, false, false)
+            .await
+            .unwrap();
+        let (mut vm_side, _) = listener.accept().await.unwrap();
+        vm_side.write_all(b"boot\n").await.unwrap();
+        assert_eq!(output(&mut first).await, b"boot\n");
+
+        // The second client shares the connection and starts read-only.
+        let mut second = manager
+            .attach("vm", &socket_path, false, false)
+            .await
+            .unwrap();
+        assert_eq!(second.take_backlog(), b"boot\n");
+        assert!(first.is_writer());
+        assert!(!second.write(b"ignored".to_vec()).await.unwrap());
+
+        let third = manager
+            .attach("vm", &socket_path, false, true)
+            .await
+            .unwrap();
+        assert!(matches!(
+            first.next_event().await,
+            Some(ConsoleEvent::Writable(false))
+        ));
+        assert!(third.write(b"root\n".to_vec()).await.unwrap());
+        let mut input = [0; 5];
+        vm_side.read_exact(&mut input).await.unwrap();
+        assert_eq!(&input, b"root\n");
+
+        vm_side.write_all(b"# ").await.unwrap();
+        assert_eq!(output(&mut first).await, b"# ");
+        assert_eq!(output(&mut second).await, b"# ");
+
+        // Once the writer detaches, the write access is free again.
+        drop(third);
+        let fourth = manager
+            .attach("vm", &socket_path, false, false)
+            .await
+            .unwrap();
+        assert!(fourth.is_writer());
+    }
+}
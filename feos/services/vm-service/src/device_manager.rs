@@ -0,0 +1,283 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Passes PCI devices of the host through to VMs. A device is bound to
+//! vfio-pci while a VM has it and recorded as the VM's in the database, so it
+//! is never passed through to two VMs at once.
+
+use crate::{
+    error::VmServiceError,
+    persistence::{repository::VmRepository, PciDeviceAssignment, VmRecord},
+};
+use feos_proto::vm_service::{disk_config, net_config, HostPciDevice, VmConfig};
+use feos_utils::host::pci::{self, PciDevice};
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use uuid::Uuid;
+
+/// The PCI devices of the host a VM is configured with: NICs and disks with
+/// a vfio_pci backend and its pci_devices. GPU partitions and mediated
+/// devices are set up by the host service and are not tracked here.
+pub fn vm_pci_devices(config: &VmConfig) -> Vec<String> {
+    let disks = config.disks.iter().filter_map(|disk| match &disk.backend {
+        Some(disk_config::Backend::VfioPci(pci)) => Some(pci.bdf.clone()),
+        _ => None,
+    });
+    let nics = config.net.iter().filter_map(|nic| match &nic.backend {
+        Some(net_config::Backend::VfioPci(pci)) => Some(pci.bdf.clone()),
+        _ => None,
+    });
+    let devices = config
+        .pci_devices
+        .iter()
+        .map(|device| device.pci_address.clone());
+    disks.chain(nics).chain(devices).collect()
+}
+
+fn pci_error(address: &str, e: io::Error) -> VmServiceError {
+    match e.kind() {
+        io::ErrorKind::NotFound => VmServiceError::InvalidArgument(format!(
+            "PCI device {address} does not exist on the host"
+        )),
+        io::ErrorKind::Unsupported => VmServiceError::InvalidArgument(e.to_string()),
+        _ => VmServiceError::PciDevice(format!("PCI device {address}: {e}")),
+    }
+}
+
+fn in_use(address: &str, owner: Option<Uuid>) -> VmServiceError {
+    VmServiceError::InvalidState(match owner {
+        Some(vm_id) => format!("PCI device {address} is already passed through to VM {vm_id}"),
+        None => format!("PCI device {address} is being passed through to another VM"),
+    })
+}
+
+/// Checks that the devices exist, can be passed through and are not passed
+/// through to a VM other than `vm_id`. Nothing is changed.
+pub async fn check(
+    repository: &VmRepository,
+    vm_id: Uuid,
+    addresses: &[String],
+) -> Result<(), VmServiceError> {
+    let mut seen = HashSet::new();
+    for address in addresses {
+        if !seen.insert(address) {
+            return Err(VmServiceError::InvalidArgument(format!(
+                "PCI device {address} is passed through more than once"
+            )));
+        }
+        let device = pci::device(address).map_err(|e| pci_error(address, e))?;
+        if !device.vfio_capable() {
+            return Err(VmServiceError::InvalidArgument(format!(
+                "PCI device {address} cannot be passed through, it is a bridge or has no IOMMU group"
+            )));
+        }
+        let owner = repository
+            .get_pci_device_assignment(address)
+            .await?
+            .map(|assignment| assignment.vm_id);
+        if owner.is_some_and(|owner| owner != vm_id) {
+            return Err(in_use(address, owner));
+        }
+    }
+    Ok(())
+}
+
+/// Passes a device through to `vm_id`: records it as the VM's and binds it
+/// to vfio-pci. Fails if another VM has the device.
+pub async fn claim(
+    repository: &VmRepository,
+    vm_id: Uuid,
+    address: &str,
+) -> Result<(), VmServiceError> {
+    let device = pci::device(address).map_err(|e| pci_error(address, e))?;
+    let assignment = PciDeviceAssignment {
+        pci_address: address.to_string(),
+        vm_id,
+        original_driver: device
+            .driver
+            .filter(|driver| driver != pci::VFIO_PCI_DRIVER),
+    };
+    // The record comes first, so of two VMs claiming a device at once only
+    // one binds it.
+    let assigned = repository.assign_pci_device(&assignment).await?;
+    if !assigned {
+        let owner = repository
+            .get_pci_device_assignment(address)
+            .await?
+            .map(|assignment| assignment.vm_id);
+        if owner != Some(vm_id) {
+            return Err(in_use(address, owner));
+        }
+    }
+    if let Err(e) = pci::bind_vfio(address) {
+        if assigned {
+            if let Err(e) = repository.unassign_pci_device(address, vm_id).await {
+                warn!("DeviceManager: Failed to remove the record of PCI device {address}: {e}");
+            }
+        }
+        return Err(pci_error(address, e));
+    }
+    info!("DeviceManager: Passed PCI device {address} through to VM {vm_id}");
+    Ok(())
+}
+
+/// Gives a device of `vm_id` back to the host once the VM no longer uses it.
+/// It is bound to the driver it had before, devices that were on vfio-pci
+/// already stay there.
+pub async fn release(repository: &VmRepository, vm_id: Uuid, address: &str) {
+    let assignment = match repository.get_pci_device_assignment(address).await {
+        Ok(Some(assignment)) if assignment.vm_id == vm_id => assignment,
+        Ok(_) => return,
+        Err(e) => {
+            warn!("DeviceManager: Failed to look up PCI device {address} of VM {vm_id}: {e}");
+            return;
+        }
+    };
+    if let Some(driver) = &assignment.original_driver {
+        if let Err(e) = pci::unbind_vfio(address, driver) {
+            warn!("DeviceManager: Failed to bind PCI device {address} to {driver} again: {e}");
+        }
+    }
+    match repository.unassign_pci_device(address, vm_id).await {
+        Ok(()) => info!("DeviceManager: Released PCI device {address} of VM {vm_id}"),
+        Err(e) => warn!("DeviceManager: Failed to remove the record of PCI device {address}: {e}"),
+    }
+}
+
+/// Releases all devices of a VM.
+pub async fn release_all(repository: &VmRepository, vm_id: Uuid) {
+    match repository.list_pci_device_assignments(Some(vm_id)).await {
+        Ok(assignments) => {
+            for assignment in assignments {
+                release(repository, vm_id, &assignment.pci_address).await;
+            }
+        }
+        Err(e) => warn!("DeviceManager: Failed to list the PCI devices of VM {vm_id}: {e}"),
+    }
+}
+
+/// Brings the records in line with the VMs after a restart: devices of VMs
+/// from before the records existed are recorded, devices of VMs that are
+/// gone are released.
+pub async fn reconcile(repository: &VmRepository, vms: &[VmRecord]) {
+    for vm in vms {
+        for address in vm_pci_devices(&vm.config) {
+            let assignment = PciDeviceAssignment {
+                pci_address: address.clone(),
+                vm_id: vm.vm_id,
+                original_driver: None,
+            };
+            match repository.assign_pci_device(&assignment).await {
+                Ok(true) => info!(
+                    "DeviceManager: Recorded PCI device {address} of VM {}",
+                    vm.vm_id
+                ),
+                Ok(false) => {}
+                Err(e) => warn!("DeviceManager: Failed to record PCI device {address}: {e}"),
+            }
+        }
+    }
+    let vm_ids: HashSet<Uuid> = vms.iter().map(|vm| vm.vm_id).collect();
+    match repository.list_pci_device_assignments(None).await {
+        Ok(assignments) => {
+            for assignment in assignments {
+                if !vm_ids.contains(&assignment.vm_id) {
+                    release(repository, assignment.vm_id, &assignment.pci_address).await;
+                }
+            }
+        }
+        Err(e) => warn!("DeviceManager: Failed to list the PCI devices passed through: {e}"),
+    }
+}
+
+fn host_pci_device(device: PciDevice, owners: &HashMap<String, Uuid>) -> HostPciDevice {
+    HostPciDevice {
+        vfio_capable: device.vfio_capable(),
+        vm_id: owners.get(&device.address).map(Uuid::to_string),
+        pci_address: device.address,
+        vendor_id: u32::from(device.vendor_id),
+        device_id: u32::from(device.device_id),
+        class_code: device.class,
+        driver: device.driver.unwrap_or_default(),
+        iommu_group: device.iommu_group,
+        numa_node: device.numa_node,
+    }
+}
+
+/// The PCI devices of the host and the VMs they are passed through to.
+pub async fn host_pci_devices(
+    repository: &VmRepository,
+    vfio_capable_only: bool,
+) -> Result<Vec<HostPciDevice>, VmServiceError> {
+    let devices = pci::devices().map_err(|e| {
+        VmServiceError::PciDevice(format!("Failed to list the PCI devices of the host: {e}"))
+    })?;
+    let owners: HashMap<String, Uuid> = repository
+        .list_pci_device_assignments(None)
+        .await?
+        .into_iter()
+        .map(|assignment| (assignment.pci_address, assignment.vm_id))
+        .collect();
+    Ok(devices
+        .into_iter()
+        .filter(|device| !vfio_capable_only || device.vfio_capable())
+        .map(|device| host_pci_device(device, &owners))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feos_proto::vm_service::{
+        DiskConfig, NetConfig, PciDeviceConfig, TapConfig, VfioPciConfig,
+    };
+
+    #[test]
+    fn vfio_nics_disks_and_pci_devices_are_tracked() {
+        let vfio = |bdf: &str| VfioPciConfig {
+            bdf: bdf.to_string(),
+        };
+        let config = VmConfig {
+            disks: vec![DiskConfig {
+                backend: Some(disk_config::Backend::VfioPci(vfio("0000:03:00.0"))),
+                ..Default::default()
+            }],
+            net: vec![
+                NetConfig {
+                    backend: Some(net_config::Backend::Tap(TapConfig::default())),
+                    ..Default::default()
+                },
+                NetConfig {
+                    backend: Some(net_config::Backend::VfioPci(vfio("0000:41:00.1"))),
+                    ..Default::default()
+                },
+            ],
+            pci_devices: vec![PciDeviceConfig {
+                device_id: "pci0".to_string(),
+                pci_address: "0000:81:00.0".to_string(),
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            vm_pci_devices(&config),
+            ["0000:03:00.0", "0000:41:00.1", "0000:81:00.0"]
+        );
+
+        let vm_id = Uuid::new_v4();
+        let owners = HashMap::from([("0000:41:00.1".to_string(), vm_id)]);
+        let device = PciDevice {
+            address: "0000:41:00.1".to_string(),
+            vendor_id: 0x8086,
+            device_id: 0x159b,
+            class: 0x020000,
+            driver: Some(pci::VFIO_PCI_DRIVER.to_string()),
+            iommu_group: Some(37),
+            numa_node: Some(1),
+        };
+        let host_device = host_pci_device(device, &owners);
+        assert!(host_device.vfio_capable);
+        assert_eq!(host_device.vm_id, Some(vm_id.to_string()));
+        assert_eq!(host_device.vendor_id, 0x8086);
+    }
+}
@@ -0,0 +1,234 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Image files and block devices handed to the hypervisor by their path on
+//! the host. Cloud Hypervisor picks the format of an image by its header, so
+//! the format is detected when the disk is added and kept with the VM. Before
+//! the disk is opened again, the header has to still match it.
+
+use crate::error::VmServiceError;
+use feos_proto::vm_service::{disk_config, DiskConfig, DiskFormat};
+use nix::libc;
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::AsyncReadExt;
+
+const QCOW2_MAGIC: &[u8; 4] = b"QFI\xfb";
+const QCOW2_V2_HEADER_LEN: usize = 72;
+/// The version 3 header up to the end of the incompatible feature bits.
+const QCOW2_HEADER_LEN: usize = 80;
+const QCOW2_INCOMPATIBLE_DIRTY: u64 = 1 << 0;
+const QCOW2_INCOMPATIBLE_CORRUPT: u64 = 1 << 1;
+
+/// What is found at the path of a disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskImage {
+    pub format: DiskFormat,
+    pub block_device: bool,
+}
+
+fn be_u32(header: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(header[offset..offset + 4].try_into().unwrap())
+}
+
+fn be_u64(header: &[u8], offset: usize) -> u64 {
+    u64::from_be_bytes(header[offset..offset + 8].try_into().unwrap())
+}
+
+/// The format of an image by its first bytes. qcow2 images Cloud Hypervisor
+/// cannot open, or that would make it read other files of the host, are
+/// rejected.
+pub fn detect_format(header: &[u8]) -> Result<DiskFormat, String> {
+    if !header.starts_with(QCOW2_MAGIC) {
+        return Ok(DiskFormat::Raw);
+    }
+    if header.len() < QCOW2_V2_HEADER_LEN {
+        return Err("the qcow2 header is truncated".to_string());
+    }
+    let version = be_u32(header, 4);
+    if !matches!(version, 2 | 3) {
+        return Err(format!("qcow2 version {version} is not supported"));
+    }
+    if be_u64(header, 8) != 0 {
+        return Err("qcow2 images with a backing file are not supported".to_string());
+    }
+    if be_u32(header, 32) != 0 {
+        return Err("encrypted qcow2 images are not supported".to_string());
+    }
+    if version == 3 {
+        if header.len() < QCOW2_HEADER_LEN {
+            return Err("the qcow2 header is truncated".to_string());
+        }
+        let incompatible = be_u64(header, 72);
+        if incompatible & QCOW2_INCOMPATIBLE_CORRUPT != 0 {
+            return Err("the qcow2 image is marked corrupt".to_string());
+        }
+        if incompatible & !QCOW2_INCOMPATIBLE_DIRTY != 0 {
+            return Err(format!(
+                "the qcow2 image uses unsupported features {incompatible:#x}"
+            ));
+        }
+    }
+    Ok(DiskFormat::Qcow2)
+}
+
+/// Inspects the image file or block device at `path`.
+pub async fn inspect(path: &Path) -> Result<DiskImage, VmServiceError> {
+    if !path.is_absolute() {
+        return Err(VmServiceError::InvalidArgument(format!(
+            "Disk path '{}' must be absolute",
+            path.display()
+        )));
+    }
+    let metadata = fs::metadata(path).await.map_err(|e| {
+        VmServiceError::InvalidArgument(format!("Cannot access disk {}: {e}", path.display()))
+    })?;
+    let block_device = metadata.file_type().is_block_device();
+    if !block_device && !metadata.is_file() {
+        return Err(VmServiceError::InvalidArgument(format!(
+            "Disk {} is neither an image file nor a block device",
+            path.display()
+        )));
+    }
+
+    let mut header = Vec::with_capacity(QCOW2_HEADER_LEN);
+    File::open(path)
+        .await
+        .map_err(|e| {
+            VmServiceError::InvalidArgument(format!("Cannot open disk {}: {e}", path.display()))
+        })?
+        .take(QCOW2_HEADER_LEN as u64)
+        .read_to_end(&mut header)
+        .await
+        .map_err(|e| {
+            VmServiceError::InvalidArgument(format!("Cannot read disk {}: {e}", path.display()))
+        })?;
+    let format = detect_format(&header)
+        .map_err(|e| VmServiceError::InvalidArgument(format!("Disk {}: {e}", path.display())))?;
+    Ok(DiskImage {
+        format,
+        block_device,
+    })
+}
+
+/// Fails if the host uses the block device, e.g. for a mounted filesystem or
+/// as a physical volume of device mapper.
+async fn check_unused(path: &Path) -> Result<(), VmServiceError> {
+    match OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_EXCL)
+        .open(path)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) if e.raw_os_error() == Some(libc::EBUSY) => Err(VmServiceError::InvalidState(
+            format!("Block device {} is in use by the host", path.display()),
+        )),
+        Err(e) => Err(VmServiceError::InvalidArgument(format!(
+            "Cannot open disk {}: {e}",
+            path.display()
+        ))),
+    }
+}
+
+/// Checks the image file or block device of a disk given by path and sets
+/// its format, if it was not given. Other disks cannot be qcow2.
+pub async fn resolve_format(disk: &mut DiskConfig) -> Result<(), VmServiceError> {
+    let Some(disk_config::Backend::Path(path)) = &disk.backend else {
+        if disk.format() == DiskFormat::Qcow2 {
+            return Err(VmServiceError::InvalidArgument(format!(
+                "Disk '{}' is not given by path and cannot be qcow2",
+                disk.device_id
+            )));
+        }
+        return Ok(());
+    };
+    let path = Path::new(path);
+    let image = inspect(path).await?;
+    if image.block_device {
+        check_unused(path).await?;
+    }
+    match disk.format() {
+        DiskFormat::Unspecified => disk.set_format(image.format),
+        format if format != image.format => {
+            return Err(VmServiceError::InvalidArgument(format!(
+                "Disk {} is {}, not {}",
+                path.display(),
+                format_name(image.format),
+                format_name(format)
+            )))
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Fails if the image at `path` no longer has the format kept with the VM.
+/// Disks added before formats were kept are opened as they are.
+pub async fn check_format(path: &Path, format: DiskFormat) -> Result<(), VmServiceError> {
+    let image = inspect(path).await?;
+    if format != DiskFormat::Unspecified && image.format != format {
+        return Err(VmServiceError::InvalidState(format!(
+            "Disk {} was added as {}, but is now {}",
+            path.display(),
+            format_name(format),
+            format_name(image.format)
+        )));
+    }
+    Ok(())
+}
+
+pub fn format_name(format: DiskFormat) -> &'static str {
+    match format {
+        DiskFormat::Unspecified => "unknown",
+        DiskFormat::Raw => "raw",
+        DiskFormat::Qcow2 => "qcow2",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn qcow2_header(version: u32) -> Vec<u8> {
+        let mut header = vec![0u8; QCOW2_HEADER_LEN];
+        header[..4].copy_from_slice(QCOW2_MAGIC);
+        header[4..8].copy_from_slice(&version.to_be_bytes());
+        header
+    }
+
+    #[test]
+    fn qcow2_is_detected_by_its_header() {
+        assert_eq!(detect_format(&[0u8; 512]), Ok(DiskFormat::Raw));
+        assert_eq!(detect_format(&[]), Ok(DiskFormat::Raw));
+        assert_eq!(detect_format(&qcow2_header(2)), Ok(DiskFormat::Qcow2));
+
+        let mut dirty = qcow2_header(3);
+        dirty[79] = QCOW2_INCOMPATIBLE_DIRTY as u8;
+        assert_eq!(detect_format(&dirty), Ok(DiskFormat::Qcow2));
+    }
+
+    #[test]
+    fn unsupported_qcow2_images_are_rejected() {
+        let mut backing_file = qcow2_header(3);
+        backing_file[15] = 0x68;
+        let mut encrypted = qcow2_header(2);
+        encrypted[35] = 1;
+        let mut corrupt = qcow2_header(3);
+        corrupt[79] = QCOW2_INCOMPATIBLE_CORRUPT as u8;
+        let mut external_data = qcow2_header(3);
+        external_data[79] = 1 << 2;
+
+        for header in [
+            qcow2_header(1),
+            qcow2_header(3)[..40].to_vec(),
+            backing_file,
+            encrypted,
+            corrupt,
+            external_data,
+        ] {
+            assert!(detect_format(&header).is_err(), "{header:?} was accepted");
+        }
+    }
+}
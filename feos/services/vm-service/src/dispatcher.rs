@@ -2,24 +2,47 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
+    boot_metrics::BootMetrics,
+    console::ConsoleManager,
     dispatcher_handlers::{
-        handle_attach_disk_command, handle_attach_nic_command, handle_create_vm_command,
-        handle_delete_vm_command, handle_detach_disk_command, handle_detach_nic_command,
-        handle_get_vm_command, handle_list_vms_command, handle_pause_vm_command,
-        handle_resume_vm_command, handle_shutdown_vm_command, handle_start_vm_command,
+        handle_attach_disk_command, handle_attach_nic_command, handle_attach_pci_device_command,
+        handle_create_vm_command, handle_create_vm_snapshot_command,
+        handle_delete_disk_replicas_command, handle_delete_vm_command,
+        handle_delete_vm_snapshot_command, handle_detach_disk_command, handle_detach_nic_command,
+        handle_detach_pci_device_command, handle_exec_in_guest_command,
+        handle_get_guest_info_command, handle_get_vm_command, handle_get_vm_stats_command,
+        handle_list_disk_replicas_command, handle_list_host_pci_devices_command,
+        handle_list_vm_events_command, handle_list_vm_snapshots_command, handle_list_vms_command,
+        handle_pause_vm_command, handle_plan_evacuation_command, handle_port_forward_command,
+        handle_pull_guest_file_command, handle_push_guest_file_command,
+        handle_receive_disk_replica_command, handle_replay_vm_state_journal_command,
+        handle_replicate_vm_disks_command, handle_resize_vm_command, handle_resume_vm_command,
+        handle_revert_vm_snapshot_command, handle_shutdown_vm_command, handle_start_vm_command,
         handle_stream_vm_console_command, handle_stream_vm_events_command,
-        perform_startup_sanity_check,
+        handle_stream_vm_flows_command, handle_stream_vm_metrics_command,
+        perform_startup_sanity_check, CreateVmLimits, PendingVmIds,
     },
+    drain::drain_vms,
     error::VmServiceError,
+    netboot::NetbootServers,
     persistence::repository::VmRepository,
+    replication::{Replication, ReplicationConfig},
+    slaac::SlaacAdvertisers,
     vmm::{factory, Hypervisor, VmmType},
     worker, Command, VmEventWrapper,
 };
-use feos_proto::vm_service::{VmState, VmStateChangedEvent};
-use log::{debug, error, info};
+use feos_proto::vm_service::{
+    VmBootPhase, VmBootPhaseEvent, VmEvent, VmState, VmStateChangedEvent,
+};
+use feos_utils::host::admission::{AdmissionController, WorkloadKind};
+use feos_utils::host::maintenance::{DrainJob, Maintenance};
+use feos_utils::host::startup::{StartupOrder, WorkloadRef};
+use log::{debug, error, info, warn};
 use prost::Message;
+use secret_service::store::SecretStore;
 use std::sync::Arc;
-use tokio::sync::{broadcast, mpsc};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::{broadcast, mpsc, Semaphore};
 use uuid::Uuid;
 
 pub struct VmServiceDispatcher {
@@ -29,11 +52,34 @@ pub struct VmServiceDispatcher {
     status_channel_tx: broadcast::Sender<VmEventWrapper>,
     hypervisor: Arc<dyn Hypervisor>,
     repository: VmRepository,
+    /// The IDs of deleted VMs, which stops their healthchecks and cancels
+    /// their creation.
     healthcheck_cancel_bus: broadcast::Sender<Uuid>,
+    boot_metrics: BootMetrics,
+    create_vm_limits: CreateVmLimits,
+    consoles: ConsoleManager,
+    netboot: NetbootServers,
+    slaac: SlaacAdvertisers,
+    drain_rx: mpsc::Receiver<DrainJob>,
+    replication: Replication,
 }
 
 impl VmServiceDispatcher {
-    pub async fn new(rx: mpsc::Receiver<Command>, db_url: &str) -> Result<Self, VmServiceError> {
+    /// `create_concurrency` is the number of VMs whose hypervisor may be
+    /// spawned and configured at the same time. `admission` tracks the host
+    /// resources committed to VMs and other workloads, `maintenance` whether
+    /// the host takes new ones and when to drain the VMs, `startup` what VMs
+    /// wait for when they are started by FeOS itself. `secrets` holds the
+    /// secrets VMs reference in their cloud-init data.
+    pub async fn new(
+        rx: mpsc::Receiver<Command>,
+        db_url: &str,
+        create_concurrency: usize,
+        admission: AdmissionController,
+        maintenance: Maintenance,
+        startup: StartupOrder,
+        secrets: SecretStore,
+    ) -> Result<Self, VmServiceError> {
         let (event_bus_tx, event_bus_rx_for_dispatcher) = mpsc::channel(32);
         let (status_channel_tx, _) = broadcast::channel(32);
         let (healthcheck_cancel_bus, _) = broadcast::channel::<Uuid>(32);
@@ -49,12 +95,36 @@ impl VmServiceDispatcher {
             hypervisor,
             repository,
             healthcheck_cancel_bus,
+            boot_metrics: BootMetrics::default(),
+            create_vm_limits: CreateVmLimits {
+                pending_vm_ids: PendingVmIds::default(),
+                create_permits: Arc::new(Semaphore::new(create_concurrency.max(1))),
+                admission,
+                maintenance: maintenance.clone(),
+                startup,
+                secrets,
+            },
+            consoles: ConsoleManager::default(),
+            netboot: NetbootServers::default(),
+            slaac: SlaacAdvertisers::default(),
+            drain_rx: maintenance.register_drainer(),
+            replication: Replication::new(ReplicationConfig::default()),
         })
     }
 
+    /// Sets how disks are replicated to peers: the ID this node stamps on
+    /// the replicas, and the API token and CA certificate to reach peers
+    /// with. Without it, only peers without authentication and TLS can be
+    /// replicated to.
+    pub fn with_replication(mut self, config: ReplicationConfig) -> Self {
+        self.replication = Replication::new(config);
+        self
+    }
+
     pub async fn run(mut self) {
         perform_startup_sanity_check(
             &self.repository,
+            &self.create_vm_limits,
             self.hypervisor.clone(),
             self.event_bus_tx.clone(),
             &self.healthcheck_cancel_bus,
@@ -72,7 +142,7 @@ impl VmServiceDispatcher {
 
                     match cmd {
                         Command::CreateVm(req, responder) => {
-                            handle_create_vm_command(&self.repository, req, responder, hypervisor, event_bus_tx).await;
+                            handle_create_vm_command(&self.repository, &self.create_vm_limits, req, responder, hypervisor, event_bus_tx, self.healthcheck_cancel_bus.subscribe()).await;
                         }
                         Command::StartVm(req, responder) => {
                             handle_start_vm_command(&self.repository, req, responder, hypervisor, event_bus_tx, &self.healthcheck_cancel_bus).await;
@@ -84,10 +154,12 @@ impl VmServiceDispatcher {
                             handle_stream_vm_events_command(&self.repository, req, stream_tx, status_channel_tx).await;
                         }
                         Command::DeleteVm(req, responder) => {
-                            handle_delete_vm_command(&self.repository, &self.healthcheck_cancel_bus, req, responder, hypervisor, event_bus_tx).await;
+                            self.netboot.stop(&req.vm_id);
+                            self.slaac.stop(&req.vm_id);
+                            handle_delete_vm_command(&self.repository, &self.create_vm_limits, &self.healthcheck_cancel_bus, req, responder, hypervisor, event_bus_tx).await;
                         }
                         Command::StreamVmConsole(input_stream, output_tx) => {
-                            handle_stream_vm_console_command(&self.repository, *input_stream, output_tx, hypervisor).await;
+                            handle_stream_vm_console_command(&self.repository, *input_stream, output_tx, hypervisor, self.consoles.clone()).await;
                         }
                         Command::ListVms(req, responder) => {
                             handle_list_vms_command(&self.repository, req, responder).await;
@@ -111,16 +183,95 @@ impl VmServiceDispatcher {
                             handle_detach_disk_command(&self.repository, req, responder, hypervisor).await;
                         }
                         Command::AttachNic(req, responder) => {
+                            let vm_id = req.vm_id.clone();
                             handle_attach_nic_command(&self.repository, req, responder, hypervisor).await;
+                            self.advertise_to_attached_nics(&vm_id).await;
                         }
                         Command::DetachNic(req, responder) => {
                             handle_detach_nic_command(&self.repository, req, responder, hypervisor).await;
                         }
+                        Command::ListHostPciDevices(req, responder) => {
+                            handle_list_host_pci_devices_command(&self.repository, req, responder).await;
+                        }
+                        Command::AttachPciDevice(req, responder) => {
+                            handle_attach_pci_device_command(&self.repository, req, responder, hypervisor).await;
+                        }
+                        Command::DetachPciDevice(req, responder) => {
+                            handle_detach_pci_device_command(&self.repository, req, responder, hypervisor).await;
+                        }
+                        Command::ResizeVm(req, responder) => {
+                            handle_resize_vm_command(&self.repository, &self.create_vm_limits.admission, req, responder, hypervisor).await;
+                        }
+                        Command::PortForward(input_stream, output_tx) => {
+                            handle_port_forward_command(&self.repository, *input_stream, output_tx, hypervisor).await;
+                        }
+                        Command::ExecInGuest(req, responder) => {
+                            handle_exec_in_guest_command(&self.repository, req, responder, hypervisor).await;
+                        }
+                        Command::PushGuestFile(input_stream, responder) => {
+                            handle_push_guest_file_command(&self.repository, *input_stream, responder, hypervisor).await;
+                        }
+                        Command::PullGuestFile(req, output_tx) => {
+                            handle_pull_guest_file_command(&self.repository, req, output_tx, hypervisor).await;
+                        }
+                        Command::GetGuestInfo(req, responder) => {
+                            handle_get_guest_info_command(&self.repository, req, responder, hypervisor).await;
+                        }
+                        Command::CreateVmSnapshot(req, responder) => {
+                            handle_create_vm_snapshot_command(&self.repository, req, responder, hypervisor).await;
+                        }
+                        Command::ListVmSnapshots(req, responder) => {
+                            handle_list_vm_snapshots_command(&self.repository, req, responder).await;
+                        }
+                        Command::RevertVmSnapshot(req, responder) => {
+                            handle_revert_vm_snapshot_command(&self.repository, req, responder, hypervisor, event_bus_tx).await;
+                        }
+                        Command::DeleteVmSnapshot(req, responder) => {
+                            handle_delete_vm_snapshot_command(&self.repository, req, responder).await;
+                        }
+                        Command::GetVmBootMetrics(_req, responder) => {
+                            if responder.send(Ok(self.boot_metrics.to_proto())).is_err() {
+                                error!("VmDispatcher: Failed to send response for GetVmBootMetrics.");
+                            }
+                        }
+                        Command::ListVmEvents(req, responder) => {
+                            handle_list_vm_events_command(&self.repository, req, responder).await;
+                        }
+                        Command::ReplayVmStateJournal(req, responder) => {
+                            handle_replay_vm_state_journal_command(&self.repository, req, responder).await;
+                        }
+                        Command::PlanEvacuation(req, responder) => {
+                            handle_plan_evacuation_command(&self.repository, req, responder).await;
+                        }
+                        Command::GetVmStats(req, responder) => {
+                            tokio::spawn(handle_get_vm_stats_command(self.repository.clone(), req, responder));
+                        }
+                        Command::StreamVmFlows(req, stream_tx) => {
+                            handle_stream_vm_flows_command(&self.repository, req, stream_tx).await;
+                        }
+                        Command::StreamVmMetrics(req, stream_tx) => {
+                            handle_stream_vm_metrics_command(&self.repository, req, stream_tx, hypervisor).await;
+                        }
+                        Command::ReplicateVmDisks(req, responder) => {
+                            handle_replicate_vm_disks_command(&self.repository, &self.replication, req, responder, hypervisor).await;
+                        }
+                        Command::ReceiveDiskReplica(input_stream, responder) => {
+                            handle_receive_disk_replica_command(&self.replication, *input_stream, responder);
+                        }
+                        Command::ListDiskReplicas(req, responder) => {
+                            handle_list_disk_replicas_command(&self.replication, req, responder).await;
+                        }
+                        Command::DeleteDiskReplicas(req, responder) => {
+                            handle_delete_disk_replicas_command(&self.replication, req, responder).await;
+                        }
                     }
                 },
                 Some(event) = self.event_bus_rx_for_dispatcher.recv() => {
                     self.handle_vm_event(event).await;
                 }
+                Some(job) = self.drain_rx.recv() => {
+                    tokio::spawn(drain_vms(job, self.repository.clone(), self.hypervisor.clone(), self.event_bus_tx.clone()));
+                }
                 else => {
                     info!("VmDispatcher: A channel closed, shutting down.");
                     break;
@@ -152,6 +303,8 @@ impl VmServiceDispatcher {
             }
         };
 
+        self.record_vm_event(&event, vm_id_uuid).await;
+
         if let Some(pid) = event_wrapper.process_id {
             info!("DatabaseUpdate: Updating pid for VM {vm_id_uuid} to {pid}");
             if let Err(e) = self.repository.update_vm_pid(vm_id_uuid, pid).await {
@@ -168,8 +321,120 @@ impl VmServiceDispatcher {
                     event_to_forward,
                 )
                 .await;
+            } else if data.type_url.contains("VmBootPhaseEvent") {
+                self.handle_vm_boot_phase_event(data, vm_id_uuid).await;
+            }
+        }
+    }
+
+    /// Keeps the event for ListVmEvents, also after the VM is deleted.
+    async fn record_vm_event(&self, event: &VmEvent, vm_id_uuid: Uuid) {
+        let namespace = match self.repository.get_vm(vm_id_uuid).await {
+            Ok(record) => record.map(|record| record.namespace),
+            Err(e) => {
+                warn!("DatabaseUpdate: Failed to look up VM {vm_id_uuid} for its event: {e}");
+                None
+            }
+        };
+        let recorded_at_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_millis() as i64);
+        if let Err(e) = self
+            .repository
+            .save_vm_event(event, namespace.as_deref(), recorded_at_ms)
+            .await
+        {
+            error!(
+                "DatabaseUpdate: Failed to record event {} of VM {vm_id_uuid}: {e}",
+                event.id
+            );
+        }
+    }
+
+    async fn handle_vm_boot_phase_event(&mut self, data: &prost_types::Any, vm_id_uuid: Uuid) {
+        let event = match VmBootPhaseEvent::decode(&*data.value) {
+            Ok(event) => event,
+            Err(e) => {
+                error!(
+                    "DatabaseUpdate: Failed to decode VmBootPhaseEvent for VM {vm_id_uuid}: {e}"
+                );
+                return;
+            }
+        };
+        let Ok(phase) = VmBootPhase::try_from(event.phase) else {
+            error!(
+                "DatabaseUpdate: Invalid VmBootPhase value '{}' in event for VM {vm_id_uuid}",
+                event.phase
+            );
+            return;
+        };
+        let Some(timestamp) = event.timestamp else {
+            error!("DatabaseUpdate: Boot phase {phase:?} of VM {vm_id_uuid} has no timestamp");
+            return;
+        };
+
+        match self.repository.get_vm(vm_id_uuid).await {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                debug!("DatabaseUpdate: VM {vm_id_uuid} is gone, dropping boot phase {phase:?}.");
+                return;
+            }
+            Err(e) => {
+                error!("DatabaseUpdate: Failed to look up VM {vm_id_uuid}: {e}");
+                return;
             }
         }
+
+        let mut timings = match self.repository.get_boot_timings(vm_id_uuid).await {
+            Ok(timings) => timings.unwrap_or_default(),
+            Err(e) => {
+                warn!("DatabaseUpdate: Failed to read boot timings of VM {vm_id_uuid}, starting over: {e}");
+                Default::default()
+            }
+        };
+        self.boot_metrics.record(&mut timings, phase, timestamp);
+        if let Err(e) = self
+            .repository
+            .save_boot_timings(vm_id_uuid, &timings)
+            .await
+        {
+            error!("DatabaseUpdate: Failed to save boot timings of VM {vm_id_uuid}: {e}");
+        }
+    }
+
+    /// Serves the boot file to VMs booting from the network and router
+    /// advertisements to SLAAC NICs while the VMs run.
+    async fn update_tap_servers(&self, vm_id_uuid: Uuid, vm_id: &str, new_state: VmState) {
+        if matches!(new_state, VmState::Running | VmState::Paused) {
+            match self.repository.get_vm(vm_id_uuid).await {
+                Ok(Some(record)) => {
+                    self.netboot.start(vm_id, &record.config);
+                    self.slaac.start(vm_id, &record.config);
+                }
+                Ok(None) => {}
+                Err(e) => error!("DatabaseUpdate: Failed to look up VM {vm_id_uuid}: {e}"),
+            }
+        } else {
+            self.netboot.stop(vm_id);
+            self.slaac.stop(vm_id);
+        }
+    }
+
+    /// Advertises to a SLAAC NIC hotplugged into a running VM. The NIC is
+    /// saved with the VM before it is attached.
+    async fn advertise_to_attached_nics(&self, vm_id: &str) {
+        let Ok(vm_id_uuid) = Uuid::parse_str(vm_id) else {
+            return;
+        };
+        match self.repository.get_vm(vm_id_uuid).await {
+            Ok(Some(record))
+                if matches!(record.status.state, VmState::Running | VmState::Paused) =>
+            {
+                self.slaac.start(vm_id, &record.config);
+            }
+            Ok(_) => {}
+            Err(e) => error!("VmDispatcher: Failed to look up VM {vm_id_uuid}: {e}"),
+        }
     }
 
     async fn handle_vm_state_changed_event(
@@ -196,12 +461,17 @@ impl VmServiceDispatcher {
                     "DatabaseUpdate: Updating status for VM {vm_id_uuid} to {new_state:?} with message: '{}'",
                     state_change.reason
                 );
+                self.update_tap_servers(vm_id_uuid, vm_id, new_state).await;
                 match self
                     .repository
                     .update_vm_status(vm_id_uuid, new_state, &state_change.reason)
                     .await
                 {
                     Ok(true) => {
+                        self.create_vm_limits.startup.set_running(
+                            &WorkloadRef::new(WorkloadKind::Vm, vm_id_uuid.to_string()),
+                            new_state == VmState::Running,
+                        );
                         if let Err(e) = self.status_channel_tx.send(event_to_forward) {
                             debug!(
                                 "VmDispatcher: Failed to forward successful VM status event for {vm_id}: {e}"
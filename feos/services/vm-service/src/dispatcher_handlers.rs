@@ -2,32 +2,77 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
+    cloud_init,
+    console::ConsoleManager,
+    device_manager, disk_image,
     error::VmServiceError,
-    persistence::{repository::VmRepository, VmRecord, VmStatus},
-    vmm::Hypervisor,
-    worker, VmEventWrapper,
+    evacuation, flows, iscsi, metrics, netboot, nvmeof, overlay,
+    persistence::{
+        repository::{VmEventFilter, VmJournalEntry, VmRepository},
+        DiskOverlay, PersistenceError, VmRecord, VmStatus,
+    },
+    placement, rbd,
+    replication::Replication,
+    scratch, slaac, snapshot, stats, storage_daemon,
+    vmm::{arch, Hypervisor},
+    worker::{self, DiskRelease},
+    VmEventWrapper,
 };
 use feos_proto::{
     image_service::{image_service_client::ImageServiceClient, PullImageRequest},
     vm_service::{
-        net_config, stream_vm_console_request as console_input, AttachConsoleMessage,
-        AttachDiskRequest, AttachDiskResponse, AttachNicRequest, AttachNicResponse,
-        CreateVmRequest, CreateVmResponse, DeleteVmRequest, DeleteVmResponse, DetachDiskRequest,
-        DetachDiskResponse, DetachNicRequest, DetachNicResponse, GetVmRequest, ListVmsRequest,
-        ListVmsResponse, PauseVmRequest, PauseVmResponse, ResumeVmRequest, ResumeVmResponse,
-        ShutdownVmRequest, ShutdownVmResponse, StartVmRequest, StartVmResponse,
-        StreamVmConsoleRequest, StreamVmConsoleResponse, StreamVmEventsRequest, VmEvent, VmInfo,
-        VmState, VmStateChangedEvent,
+        disk_config, net_config, port_forward_request, push_guest_file_request, startup_dependency,
+        stream_vm_console_request as console_input, AttachConsoleMessage, AttachDiskRequest,
+        AttachDiskResponse, AttachNicRequest, AttachNicResponse, AttachPciDeviceRequest,
+        AttachPciDeviceResponse, CreateVmRequest, CreateVmResponse, CreateVmSnapshotRequest,
+        CreateVmSnapshotResponse, DeleteDiskReplicasRequest, DeleteDiskReplicasResponse,
+        DeleteVmRequest, DeleteVmResponse, DeleteVmSnapshotRequest, DeleteVmSnapshotResponse,
+        DetachDiskRequest, DetachDiskResponse, DetachNicRequest, DetachNicResponse,
+        DetachPciDeviceRequest, DetachPciDeviceResponse, DiskBus, DiskConfig, DiskSnapshot,
+        ExecInGuestRequest, ExecInGuestResponse, GetGuestInfoRequest, GetGuestInfoResponse,
+        GetVmRequest, GetVmStatsRequest, GetVmStatsResponse, GpuConfig, GuestFileChunk,
+        GuestNicAddresses, ListDiskReplicasRequest, ListDiskReplicasResponse,
+        ListHostPciDevicesRequest, ListHostPciDevicesResponse, ListVmEventsRequest,
+        ListVmEventsResponse, ListVmSnapshotsRequest, ListVmSnapshotsResponse, ListVmsRequest,
+        ListVmsResponse, MdevConfig, PauseVmRequest, PauseVmResponse, PciDeviceConfig,
+        PlanEvacuationRequest, PlanEvacuationResponse, PortForwardRequest, PortForwardResponse,
+        PortForwardStart, PullGuestFileRequest, PushGuestFileRequest, PushGuestFileResponse,
+        PushGuestFileStart, ReceiveDiskReplicaRequest, ReceiveDiskReplicaResponse, RecordedVmEvent,
+        ReplayVmStateJournalRequest, ReplayVmStateJournalResponse, ReplicateVmDisksRequest,
+        ReplicateVmDisksResponse, ResizeVmRequest, ResizeVmResponse, ResumeVmRequest,
+        ResumeVmResponse, RevertVmSnapshotRequest, RevertVmSnapshotResponse, ShutdownVmRequest,
+        ShutdownVmResponse, StartVmRequest, StartVmResponse, StreamVmConsoleRequest,
+        StreamVmConsoleResponse, StreamVmEventsRequest, StreamVmFlowsRequest,
+        StreamVmMetricsRequest, VmConfig, VmEvent, VmFlowsSample, VmInfo, VmMetricsSample,
+        VmSnapshotInfo, VmState, VmStateChangedEvent, VmStateJournalEntry,
     },
 };
+use feos_utils::host::admission::{Admission, AdmissionController, Resources, WorkloadKind};
+use feos_utils::host::clock;
+use feos_utils::host::gpu;
+use feos_utils::host::gpu_metrics;
+use feos_utils::host::maintenance::Maintenance;
+use feos_utils::host::mdev;
+use feos_utils::host::numa;
+use feos_utils::host::serial;
+use feos_utils::host::startup::{StartupOrder, WorkloadRef};
+use feos_utils::namespace::{namespace_or_default, validate_name};
+use feos_utils::network::neighbours::{format_mac, neighbour_addresses};
 use hyper_util::rt::TokioIo;
 use image_service::IMAGE_SERVICE_SOCKET;
 use log::{error, info, warn};
 use nix::unistd::Pid;
 use prost::Message;
 use prost_types::Any;
-use std::{path::PathBuf, sync::Arc};
-use tokio::sync::{broadcast, mpsc, oneshot};
+use secret_service::store::SecretStore;
+use std::{
+    collections::{HashMap, HashSet},
+    net::IpAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::{broadcast, mpsc, oneshot, Semaphore};
 use tokio_stream::StreamExt;
 use tonic::{
     transport::{Channel, Endpoint, Error as TonicTransportError, Uri},
@@ -36,7 +81,34 @@ use tonic::{
 use tower::service_fn;
 use uuid::Uuid;
 
+/// The name of the macvtap device of a NIC, which is left to FeOS if the
+/// client does not pick one. Interface names have at most 15 characters.
+fn ensure_macvtap_name(net_config: &mut feos_proto::vm_service::NetConfig) {
+    if let Some(net_config::Backend::Macvtap(macvtap)) = &mut net_config.backend {
+        if macvtap.name.is_empty() {
+            macvtap.name = format!("mvt{}", &Uuid::new_v4().simple().to_string()[..8]);
+        }
+    }
+}
+
+/// The macvtap device FeOS created for a NIC, if any.
+fn macvtap_name(net_config: &feos_proto::vm_service::NetConfig) -> Option<String> {
+    match &net_config.backend {
+        Some(net_config::Backend::Macvtap(macvtap)) if !macvtap.name.is_empty() => {
+            Some(macvtap.name.clone())
+        }
+        _ => None,
+    }
+}
+
+fn ensure_pci_device_config_device_id(device: &mut PciDeviceConfig) {
+    if device.device_id.is_empty() {
+        device.device_id = format!("pci-{}", device.pci_address);
+    }
+}
+
 fn ensure_net_config_device_id(net_config: &mut feos_proto::vm_service::NetConfig) {
+    ensure_macvtap_name(net_config);
     if net_config.device_id.is_empty() {
         if let Some(backend) = &net_config.backend {
             match backend {
@@ -46,9 +118,183 @@ fn ensure_net_config_device_id(net_config: &mut feos_proto::vm_service::NetConfi
                 net_config::Backend::VfioPci(pci) => {
                     net_config.device_id = format!("/sys/bus/pci/devices/{}", pci.bdf);
                 }
+                net_config::Backend::VhostUser(vhost_user) => {
+                    net_config.device_id = vhost_user.socket_path.clone();
+                }
+                net_config::Backend::Macvtap(macvtap) => {
+                    net_config.device_id = macvtap.name.clone();
+                }
+            }
+        }
+    }
+}
+
+/// Gives TAP, vhost-user and macvtap NICs without a MAC address a random,
+/// locally administered one. Knowing the MAC of every guest NIC lets us find
+/// its IP addresses, userspace dataplanes match guest traffic by it and a
+/// macvtap device only receives the frames sent to it.
+fn ensure_net_config_mac_address(net_config: &mut feos_proto::vm_service::NetConfig) {
+    if net_config.mac_address.is_empty()
+        && matches!(
+            net_config.backend,
+            Some(
+                net_config::Backend::Tap(_)
+                    | net_config::Backend::VhostUser(_)
+                    | net_config::Backend::Macvtap(_)
+            )
+        )
+    {
+        let mut mac = [0u8; 6];
+        mac.copy_from_slice(&Uuid::new_v4().as_bytes()[..6]);
+        mac[0] = (mac[0] & 0xfe) | 0x02;
+        net_config.mac_address = format_mac(&mac);
+    }
+}
+
+/// The IP addresses the host has seen each NIC of a VM use.
+fn guest_addresses(
+    config: &VmConfig,
+    neighbours: &HashMap<String, Vec<IpAddr>>,
+) -> Vec<GuestNicAddresses> {
+    config
+        .net
+        .iter()
+        .filter(|nic| !nic.mac_address.is_empty())
+        .map(|nic| {
+            let mac_address = nic.mac_address.to_lowercase();
+            let ip_addresses = neighbours
+                .get(&mac_address)
+                .map(|ips| ips.iter().map(ToString::to_string).collect())
+                .unwrap_or_default();
+            GuestNicAddresses {
+                device_id: nic.device_id.clone(),
+                mac_address,
+                ip_addresses,
+            }
+        })
+        .collect()
+}
+
+async fn load_neighbour_addresses() -> HashMap<String, Vec<IpAddr>> {
+    neighbour_addresses().await.unwrap_or_else(|e| {
+        warn!("VmDispatcher: Failed to read the neighbour table for guest addresses: {e}");
+        HashMap::new()
+    })
+}
+
+fn ensure_disk_config_device_id(disk_config: &mut DiskConfig) {
+    if disk_config.device_id.is_empty() {
+        if let Some(backend) = &disk_config.backend {
+            disk_config.device_id = match backend {
+                disk_config::Backend::Path(path) => path.clone(),
+                disk_config::Backend::VfioPci(pci) => format!("/sys/bus/pci/devices/{}", pci.bdf),
+                disk_config::Backend::Iscsi(target) => {
+                    format!("{}-lun-{}", target.target_iqn, target.lun)
+                }
+                disk_config::Backend::Nvmeof(subsystem) => {
+                    format!("{}-ns-{}", subsystem.subsystem_nqn, subsystem.nsid)
+                }
+                disk_config::Backend::Rbd(image) => format!("rbd:{}", image.image_spec),
+                disk_config::Backend::VhostUserBlk(vhost_user_blk) => {
+                    vhost_user_blk.socket_path.clone()
+                }
+                disk_config::Backend::Ephemeral(_) => {
+                    format!("scratch-{}", &Uuid::new_v4().simple().to_string()[..8])
+                }
+                disk_config::Backend::Overlay(_) => {
+                    format!("overlay-{}", &Uuid::new_v4().simple().to_string()[..8])
+                }
+            };
+        }
+    }
+}
+
+/// Whether two disks reach their backends through the same iSCSI session or
+/// NVMe-oF connection of the host.
+fn shares_connection(a: &disk_config::Backend, b: &disk_config::Backend) -> bool {
+    match (a, b) {
+        (disk_config::Backend::Iscsi(a), disk_config::Backend::Iscsi(b)) => {
+            iscsi::same_target(a, b)
+        }
+        (disk_config::Backend::Nvmeof(a), disk_config::Backend::Nvmeof(b)) => {
+            nvmeof::same_subsystem(a, b)
+        }
+        _ => false,
+    }
+}
+
+/// Whether a disk other than `device_id` of VM `vm_id` uses the connection
+/// of `backend`, so the host has to stay connected.
+async fn connection_in_use(
+    repository: &VmRepository,
+    vm_id: Uuid,
+    device_id: &str,
+    backend: &disk_config::Backend,
+) -> Result<bool, VmServiceError> {
+    Ok(repository.list_all_vms().await?.iter().any(|vm| {
+        vm.config.disks.iter().any(|disk| {
+            !(vm.vm_id == vm_id && disk.device_id == device_id)
+                && disk
+                    .backend
+                    .as_ref()
+                    .is_some_and(|other| shares_connection(backend, other))
+        })
+    }))
+}
+
+/// What to tear down on the host once `disk` is detached from VM `vm_id`.
+/// An iSCSI session or NVMe-oF connection stays up while other disks use
+/// it. Scratch disks and overlays are always torn down.
+async fn disk_release(
+    repository: &VmRepository,
+    vm_id: Uuid,
+    disk: &DiskConfig,
+) -> Result<Option<DiskRelease>, VmServiceError> {
+    let disconnect = match &disk.backend {
+        Some(backend @ (disk_config::Backend::Iscsi(_) | disk_config::Backend::Nvmeof(_))) => {
+            !connection_in_use(repository, vm_id, &disk.device_id, backend).await?
+        }
+        _ => false,
+    };
+    let is_local = matches!(
+        disk.backend,
+        Some(disk_config::Backend::Ephemeral(_) | disk_config::Backend::Overlay(_))
+    );
+    if !disconnect && !is_local && !storage_daemon::is_exported(disk) {
+        return Ok(None);
+    }
+    Ok(Some(DiskRelease {
+        disk: disk.clone(),
+        disconnect,
+    }))
+}
+
+fn validate_disk_config(disk: &DiskConfig) -> Result<(), VmServiceError> {
+    match &disk.backend {
+        Some(disk_config::Backend::Iscsi(target)) => iscsi::validate(target)?,
+        Some(disk_config::Backend::Nvmeof(subsystem)) => nvmeof::validate(subsystem)?,
+        Some(disk_config::Backend::Rbd(image)) => rbd::validate(image)?,
+        Some(disk_config::Backend::Overlay(config)) => overlay::validate(config)?,
+        Some(disk_config::Backend::Ephemeral(config)) => {
+            scratch::validate(config)?;
+            if disk.readonly {
+                return Err(VmServiceError::InvalidArgument(format!(
+                    "Ephemeral disk '{}' cannot be read-only",
+                    disk.device_id
+                )));
+            }
+        }
+        Some(disk_config::Backend::VfioPci(_)) => {
+            if disk.discard || disk.bus() == DiskBus::VirtioScsi {
+                return Err(VmServiceError::InvalidArgument(format!(
+                    "Disk '{}' is passed through, bus and discard do not apply",
+                    disk.device_id
+                )));
             }
         }
+        _ => {}
     }
+    Ok(())
 }
 
 pub(crate) async fn get_image_service_client(
@@ -97,10 +343,254 @@ async fn initiate_image_pull_for_vm(req: &CreateVmRequest) -> Result<String, VmS
     Ok(image_uuid)
 }
 
-async fn prepare_vm_creation(
+/// Host memory needed for the scratch disks of a VM.
+pub(crate) fn ephemeral_disk_bytes<'a>(disks: impl IntoIterator<Item = &'a DiskConfig>) -> u64 {
+    disks
+        .into_iter()
+        .filter_map(|disk| match &disk.backend {
+            Some(disk_config::Backend::Ephemeral(config)) => Some(config.size_bytes),
+            _ => None,
+        })
+        .sum()
+}
+
+/// IDs of VMs whose creation was accepted but whose record is not saved yet,
+/// with the host devices passed through to them, so that concurrent requests
+/// cannot claim the same ID or device.
+#[derive(Clone, Default)]
+pub(crate) struct PendingVmIds(Arc<Mutex<HashMap<Uuid, Vec<String>>>>);
+
+impl PendingVmIds {
+    fn reserve(&self, vm_id: Uuid, devices: Vec<String>) -> Option<PendingVmId> {
+        let mut ids = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if ids.contains_key(&vm_id) {
+            return None;
+        }
+        ids.insert(vm_id, devices);
+        Some(PendingVmId {
+            vm_id,
+            ids: self.clone(),
+        })
+    }
+
+    fn claimed_devices(&self) -> HashSet<String> {
+        let ids = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        ids.values().flatten().cloned().collect()
+    }
+}
+
+/// Holds a VM ID reserved until it is dropped.
+pub(crate) struct PendingVmId {
+    vm_id: Uuid,
+    ids: PendingVmIds,
+}
+
+impl Drop for PendingVmId {
+    fn drop(&mut self) {
+        let mut ids = self.ids.0.lock().unwrap_or_else(PoisonError::into_inner);
+        ids.remove(&self.vm_id);
+    }
+}
+
+/// State shared by all CreateVm requests, which keeps them from claiming
+/// the same ID or overloading the host, rejects them while the host is
+/// cordoned and keeps their startup dependencies free of cycles. Deleting a
+/// VM releases what it holds here. The secrets are those VMs get when they
+/// are created.
+#[derive(Clone)]
+pub(crate) struct CreateVmLimits {
+    pub(crate) pending_vm_ids: PendingVmIds,
+    pub(crate) create_permits: Arc<Semaphore>,
+    pub(crate) admission: AdmissionController,
+    pub(crate) maintenance: Maintenance,
+    pub(crate) startup: StartupOrder,
+    pub(crate) secrets: SecretStore,
+}
+
+/// The GPU partitions, mediated devices and serial port passed through to
+/// a VM.
+fn passthrough_devices(config: &VmConfig) -> impl Iterator<Item = String> + '_ {
+    let gpus = config.gpus.iter().map(|gpu| gpu.pci_address.clone());
+    gpus.chain(config.mdevs.iter().map(|mdev| mdev.uuid.clone()))
+        .chain(config.serial_port.iter().map(|port| port.path.clone()))
+}
+
+/// Binds a VM to the NUMA node of its devices or its placement hints. The
+/// node is persisted with the config, so the VM keeps it across restarts.
+async fn place_vm(
     repository: &VmRepository,
-    req: &CreateVmRequest,
-) -> Result<(Uuid, String), VmServiceError> {
+    vm_config: &mut VmConfig,
+) -> Result<(), VmServiceError> {
+    let nodes = numa::nodes().map_err(|e| {
+        VmServiceError::InvalidState(format!("Failed to read the NUMA nodes of the host: {e}"))
+    })?;
+    let mdevs = if vm_config.mdevs.is_empty() {
+        Vec::new()
+    } else {
+        mdev::mdevs()
+            .map_err(|e| VmServiceError::Mdev(format!("Failed to list mediated devices: {e}")))?
+    };
+    let devices = placement::device_nodes(vm_config, &mdevs);
+    let others = repository.list_all_vms().await?;
+    let node = placement::place(vm_config, &nodes, &devices, &others)?;
+    if node.is_some() || vm_config.placement.is_some() {
+        vm_config
+            .placement
+            .get_or_insert_with(Default::default)
+            .assigned_numa_node = node;
+    }
+    Ok(())
+}
+
+/// Checks that the mediated devices of a VM exist and are not in `taken`
+/// by other VMs.
+fn check_mdevs(
+    mdevs: &[MdevConfig],
+    existing: &[mdev::Mdev],
+    taken: &HashSet<String>,
+) -> Result<(), VmServiceError> {
+    let mut seen = HashSet::new();
+    for config in mdevs {
+        if !existing.iter().any(|mdev| mdev.uuid == config.uuid) {
+            return Err(VmServiceError::InvalidArgument(format!(
+                "Mediated device '{}' does not exist on the host",
+                config.uuid
+            )));
+        }
+        if taken.contains(&config.uuid) || !seen.insert(&config.uuid) {
+            return Err(VmServiceError::InvalidState(format!(
+                "Mediated device {} is already passed through to a VM",
+                config.uuid
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Assigns each GPU of a VM a free partition of its profile. Partitions
+/// in `taken` belong to other VMs.
+fn assign_gpu_partitions(
+    gpus: &mut [GpuConfig],
+    partitions: &[gpu::GpuPartition],
+    taken: &HashSet<String>,
+) -> Result<(), VmServiceError> {
+    let mut assigned = HashSet::new();
+    for config in gpus.iter_mut() {
+        if config.profile.is_empty() {
+            return Err(VmServiceError::InvalidArgument(
+                "A GPU must name the profile of its partition".to_string(),
+            ));
+        }
+        let partition = partitions
+            .iter()
+            .find(|partition| {
+                partition.profile == config.profile
+                    && !taken.contains(&partition.pci_address)
+                    && !assigned.contains(&partition.pci_address)
+            })
+            .ok_or_else(|| {
+                VmServiceError::InvalidState(format!(
+                    "No free GPU partition of profile '{}' on the host",
+                    config.profile
+                ))
+            })?;
+        assigned.insert(partition.pci_address.clone());
+        config.pci_address = partition.pci_address.clone();
+    }
+    Ok(())
+}
+
+/// The host CPU and memory a VM is sized for.
+pub(crate) fn vm_resources(config: &VmConfig) -> Resources {
+    Resources {
+        milli_cpus: config
+            .cpus
+            .as_ref()
+            .map_or(0, |cpus| u64::from(cpus.boot_vcpus) * 1000),
+        memory_bytes: config.memory.as_ref().map_or(0, |memory| {
+            (memory.size_mib + memory.hotplugged_size_mib) << 20
+        }),
+    }
+}
+
+/// The config of a VM with the vCPUs and memory asked for by `req`.
+fn resized_config(config: &VmConfig, req: &ResizeVmRequest) -> Result<VmConfig, VmServiceError> {
+    if req.vcpus.is_none() && req.memory_mib.is_none() {
+        return Err(VmServiceError::InvalidArgument(
+            "Nothing to resize, set vcpus or memory_mib".to_string(),
+        ));
+    }
+    let mut config = config.clone();
+    if let Some(vcpus) = req.vcpus {
+        let cpus = config.cpus.get_or_insert_with(Default::default);
+        if vcpus == 0 || vcpus > cpus.max_vcpus {
+            return Err(VmServiceError::InvalidArgument(format!(
+                "vcpus must be between 1 and the VM's max_vcpus of {}",
+                cpus.max_vcpus
+            )));
+        }
+        cpus.boot_vcpus = vcpus;
+    }
+    if let Some(memory_mib) = req.memory_mib {
+        let memory = config.memory.get_or_insert_with(Default::default);
+        if memory.hotplug_size_mib == 0 {
+            return Err(VmServiceError::InvalidArgument(
+                "The VM was created without hotplug memory, its memory cannot be resized"
+                    .to_string(),
+            ));
+        }
+        let max_mib = memory.size_mib + memory.hotplug_size_mib;
+        if !(memory.size_mib..=max_mib).contains(&memory_mib) {
+            return Err(VmServiceError::InvalidArgument(format!(
+                "memory_mib must be between {} and {max_mib}",
+                memory.size_mib
+            )));
+        }
+        memory.hotplugged_size_mib = memory_mib - memory.size_mib;
+    }
+    Ok(config)
+}
+
+/// The workloads FeOS starts before the VM when it starts the VM by itself.
+fn startup_dependencies(config: &VmConfig) -> Result<Vec<WorkloadRef>, VmServiceError> {
+    let Some(startup) = &config.startup else {
+        return Ok(Vec::new());
+    };
+    startup
+        .after
+        .iter()
+        .map(|dependency| {
+            let (kind, id) = match &dependency.workload {
+                Some(startup_dependency::Workload::VmId(id)) => (WorkloadKind::Vm, id),
+                Some(startup_dependency::Workload::ContainerId(id)) => {
+                    (WorkloadKind::Container, id)
+                }
+                None => {
+                    return Err(VmServiceError::InvalidArgument(
+                        "A startup dependency must name a VM or a container.".to_string(),
+                    ))
+                }
+            };
+            let id = Uuid::parse_str(id).map_err(|_| {
+                VmServiceError::InvalidArgument(format!(
+                    "Startup dependency {kind} '{id}' is not a valid UUID."
+                ))
+            })?;
+            Ok(WorkloadRef::new(kind, id.to_string()))
+        })
+        .collect()
+}
+
+/// Checks a CreateVm request, reserves the ID of the new VM and commits the
+/// host resources it needs. This runs on the dispatcher, so it must not wait
+/// for other services.
+async fn validate_vm_creation(
+    repository: &VmRepository,
+    limits: &CreateVmLimits,
+    req: &mut CreateVmRequest,
+) -> Result<(PendingVmId, Admission), VmServiceError> {
+    limits.maintenance.check_schedulable(WorkloadKind::Vm)?;
+
     let vm_id_res: Result<(Uuid, bool), VmServiceError> =
         if let Some(id_str) = req.vm_id.as_deref().filter(|s| !s.is_empty()) {
             match Uuid::parse_str(id_str) {
@@ -124,21 +614,178 @@ async fn prepare_vm_creation(
         )));
     }
 
-    let image_uuid_str = initiate_image_pull_for_vm(req).await?;
-    let image_uuid = Uuid::parse_str(&image_uuid_str)
-        .map_err(|e| VmServiceError::ImageService(format!("Failed to parse image UUID: {e}")))?;
+    req.namespace = namespace_or_default(&req.namespace)
+        .map_err(|e| VmServiceError::InvalidArgument(e.to_string()))?;
+    if let Some(name) = req.name.as_deref() {
+        validate_name(name).map_err(|e| VmServiceError::InvalidArgument(e.to_string()))?;
+        if repository.vm_name_taken(&req.namespace, name).await? {
+            return Err(PersistenceError::NameTaken(format!(
+                "A VM named '{name}' already exists in namespace '{}'",
+                req.namespace
+            ))
+            .into());
+        }
+    }
 
     let mut vm_config = req.config.clone().ok_or(VmServiceError::InvalidArgument(
         "VmConfig is required in CreateVmRequest".to_string(),
     ))?;
 
+    if vm_config.disks.iter().any(|disk| {
+        disk.discard
+            || matches!(
+                disk.backend,
+                Some(disk_config::Backend::Iscsi(_) | disk_config::Backend::Rbd(_))
+            )
+    }) {
+        return Err(VmServiceError::InvalidArgument(
+            "iSCSI, RBD and discarding disks must be attached with AttachDisk after the VM is created"
+                .to_string(),
+        ));
+    }
+    for disk in &mut vm_config.disks {
+        validate_disk_config(disk)?;
+        disk_image::resolve_format(disk).await?;
+    }
+    arch::check_config(&vm_config)?;
+    netboot::validate(&vm_config)?;
+    slaac::validate(&vm_config)?;
+    cloud_init::validate(&vm_config)?;
+    if vm_config
+        .memory
+        .as_ref()
+        .is_some_and(|memory| memory.hugepages && memory.swap_max_bytes.is_some())
+    {
+        return Err(VmServiceError::InvalidArgument(
+            "Hugepages are never swapped, swap_max_bytes cannot be set with hugepages".to_string(),
+        ));
+    }
+    if vm_config
+        .memory
+        .as_ref()
+        .is_some_and(|memory| memory.hotplugged_size_mib > memory.hotplug_size_mib)
+    {
+        return Err(VmServiceError::InvalidArgument(
+            "hotplugged_size_mib cannot exceed hotplug_size_mib".to_string(),
+        ));
+    }
+
+    if vm_config.clock.as_ref().is_some_and(|clock| clock.ptp_kvm) && !clock::ptp_kvm_supported() {
+        return Err(VmServiceError::InvalidState(format!(
+            "The host cannot serve ptp_kvm to guests, it needs KVM and the {} clocksource",
+            clock::PTP_KVM_CLOCKSOURCE
+        )));
+    }
+
+    let scratch_bytes = ephemeral_disk_bytes(&vm_config.disks);
+    if scratch_bytes > 0 {
+        let memory_bytes = vm_config
+            .memory
+            .as_ref()
+            .map_or(0, |memory| memory.size_mib << 20);
+        scratch::check_memory(memory_bytes + scratch_bytes).await?;
+    }
+
+    vm_config
+        .disks
+        .iter_mut()
+        .for_each(ensure_disk_config_device_id);
     vm_config
         .net
         .iter_mut()
         .for_each(ensure_net_config_device_id);
+    vm_config
+        .net
+        .iter_mut()
+        .for_each(ensure_net_config_mac_address);
+    if let Some(config) = vm_config.serial_port.as_mut() {
+        let port = serial::serial_port(&config.path)
+            .and_then(|port| serial::validate_baud_rate(config.baud_rate).map(|()| port))
+            .map_err(|e| {
+                VmServiceError::InvalidArgument(format!(
+                    "Invalid serial port '{}': {e}",
+                    config.path
+                ))
+            })?;
+        config.path = port.path.to_string_lossy().into_owned();
+    }
+    if passthrough_devices(&vm_config).next().is_some() {
+        let mut taken = limits.pending_vm_ids.claimed_devices();
+        for record in repository.list_all_vms().await? {
+            taken.extend(passthrough_devices(&record.config));
+        }
+        if !vm_config.gpus.is_empty() {
+            let partitions = gpu::partitions()
+                .map_err(|e| VmServiceError::Gpu(format!("Failed to list GPU partitions: {e}")))?;
+            assign_gpu_partitions(&mut vm_config.gpus, &partitions, &taken)?;
+        }
+        if !vm_config.mdevs.is_empty() {
+            let existing = mdev::mdevs().map_err(|e| {
+                VmServiceError::Mdev(format!("Failed to list mediated devices: {e}"))
+            })?;
+            check_mdevs(&vm_config.mdevs, &existing, &taken)?;
+        }
+        if let Some(port) = vm_config
+            .serial_port
+            .as_ref()
+            .filter(|port| taken.contains(&port.path))
+        {
+            return Err(VmServiceError::InvalidState(format!(
+                "Serial port {} is already passed through to a VM",
+                port.path
+            )));
+        }
+    }
+    vm_config
+        .pci_devices
+        .iter_mut()
+        .for_each(ensure_pci_device_config_device_id);
+    device_manager::check(
+        repository,
+        vm_id,
+        &device_manager::vm_pci_devices(&vm_config),
+    )
+    .await?;
+    place_vm(repository, &mut vm_config).await?;
+    let devices = passthrough_devices(&vm_config).collect();
+    let resources = vm_resources(&vm_config);
+    let after = startup_dependencies(&vm_config)?;
+    // The worker sets up scratch disks under the IDs that are persisted.
+    req.config = Some(vm_config);
+
+    let pending_vm_id = limits
+        .pending_vm_ids
+        .reserve(vm_id, devices)
+        .ok_or_else(|| {
+            VmServiceError::AlreadyExists(format!("VM with ID {vm_id} is already being created."))
+        })?;
+    let admission = limits
+        .admission
+        .admit(WorkloadKind::Vm, &vm_id.to_string(), resources)?;
+    limits
+        .startup
+        .register(WorkloadRef::new(WorkloadKind::Vm, vm_id.to_string()), after)
+        .map_err(|e| VmServiceError::InvalidArgument(e.to_string()))?;
+    Ok((pending_vm_id, admission))
+}
+
+/// Starts the pull of the VM's image and saves the initial record of the VM.
+async fn register_vm_creation(
+    repository: &VmRepository,
+    vm_id: Uuid,
+    req: &CreateVmRequest,
+) -> Result<String, VmServiceError> {
+    let image_uuid_str = initiate_image_pull_for_vm(req).await?;
+    let image_uuid = Uuid::parse_str(&image_uuid_str)
+        .map_err(|e| VmServiceError::ImageService(format!("Failed to parse image UUID: {e}")))?;
+    let vm_config = req.config.clone().ok_or(VmServiceError::InvalidArgument(
+        "VmConfig is required in CreateVmRequest".to_string(),
+    ))?;
 
     let record = VmRecord {
         vm_id,
+        namespace: req.namespace.clone(),
+        name: req.name.clone(),
         image_uuid,
         status: VmStatus {
             state: VmState::Creating,
@@ -150,7 +797,21 @@ async fn prepare_vm_creation(
 
     repository.save_vm(&record).await?;
     info!("VmDispatcher: Saved initial record for VM {vm_id}");
-    Ok((vm_id, image_uuid_str))
+    Ok(image_uuid_str)
+}
+
+/// Tells the client where the overlays of the disks of a VM are kept.
+fn fill_overlay_paths(config: &mut VmConfig, overlays: &[DiskOverlay]) {
+    for disk in &mut config.disks {
+        if let Some(disk_config::Backend::Overlay(overlay)) = &mut disk.backend {
+            if let Some(kept) = overlays
+                .iter()
+                .find(|kept| kept.device_id == disk.device_id)
+            {
+                overlay.overlay_path = kept.overlay_path.clone();
+            }
+        }
+    }
 }
 
 async fn get_vm_info(
@@ -161,11 +822,19 @@ async fn get_vm_info(
         .map_err(|_| VmServiceError::InvalidArgument("Invalid VM ID format.".to_string()))?;
 
     match repository.get_vm(vm_id).await? {
-        Some(record) => Ok(VmInfo {
-            vm_id: record.vm_id.to_string(),
-            state: record.status.state as i32,
-            config: Some(record.config),
-        }),
+        Some(mut record) => {
+            let overlays = repository.list_disk_overlays(Some(vm_id)).await?;
+            fill_overlay_paths(&mut record.config, &overlays);
+            Ok(VmInfo {
+                vm_id: record.vm_id.to_string(),
+                state: record.status.state as i32,
+                guest_addresses: guest_addresses(&record.config, &load_neighbour_addresses().await),
+                config: Some(record.config),
+                boot_timings: repository.get_boot_timings(vm_id).await?,
+                namespace: record.namespace,
+                name: record.name,
+            })
+        }
         None => Err(VmServiceError::Vmm(crate::vmm::VmmError::VmNotFound(
             vm_id.to_string(),
         ))),
@@ -187,34 +856,207 @@ async fn parse_vm_id_and_get_record(
     }
 }
 
+fn send_create_vm_error(
+    responder: oneshot::Sender<Result<CreateVmResponse, VmServiceError>>,
+    e: VmServiceError,
+) {
+    error!("VmDispatcher: Failed to handle CreateVm command: {e}");
+    if responder.send(Err(e)).is_err() {
+        error!("VmDispatcher: Failed to send error response for CreateVm. Responder closed.");
+    }
+}
+
 pub(crate) async fn handle_create_vm_command(
     repository: &VmRepository,
-    req: CreateVmRequest,
-    responder: oneshot::Sender<Result<CreateVmResponse, VmServiceError>>,
+    limits: &CreateVmLimits,
+    mut req: CreateVmRequest,
+    mut responder: oneshot::Sender<Result<CreateVmResponse, VmServiceError>>,
     hypervisor: Arc<dyn Hypervisor>,
     event_bus_tx: mpsc::Sender<VmEventWrapper>,
+    cancel_bus: broadcast::Receiver<Uuid>,
 ) {
-    let result = prepare_vm_creation(repository, &req).await;
+    let (pending_vm_id, admission) = match validate_vm_creation(repository, limits, &mut req).await
+    {
+        Ok(reserved) => reserved,
+        Err(e) => {
+            send_create_vm_error(responder, e);
+            return;
+        }
+    };
 
-    match result {
-        Ok((vm_id, image_uuid_str)) => {
-            tokio::spawn(worker::handle_create_vm(
-                vm_id.to_string(),
-                req,
-                image_uuid_str,
-                responder,
-                hypervisor,
-                event_bus_tx,
-            ));
+    // Everything from here on waits for other services or processes, so it
+    // runs alongside the creation of other VMs.
+    let repository = repository.clone();
+    let limits = limits.clone();
+    let startup = limits.startup.clone();
+    tokio::spawn(async move {
+        let vm_id = pending_vm_id.vm_id;
+        let saga = CreateVmSaga::new(vm_id, repository.clone(), admission, startup.clone());
+        // The client going away, e.g. when its deadline passes, cancels the
+        // creation up to the point it is told the ID of the VM.
+        let registered = tokio::select! {
+            registered = register_vm_creation(&repository, vm_id, &req) => Some(registered),
+            () = responder.closed() => None,
+        };
+        let image_uuid_str = match registered {
+            Some(Ok(image_uuid_str)) => image_uuid_str,
+            Some(Err(e)) => {
+                startup.remove(&WorkloadRef::new(WorkloadKind::Vm, vm_id.to_string()));
+                send_create_vm_error(responder, e);
+                return;
+            }
+            None => {
+                warn!("VmDispatcher: CreateVm for {vm_id} was cancelled by the client.");
+                saga.roll_back(hypervisor.as_ref()).await;
+                return;
+            }
+        };
+        drop(pending_vm_id);
+
+        if responder
+            .send(Ok(CreateVmResponse {
+                vm_id: vm_id.to_string(),
+            }))
+            .is_err()
+        {
+            warn!("VmDispatcher: Client of CreateVm for {vm_id} disconnected before the response could be sent. Rolling back.");
+            saga.roll_back(hypervisor.as_ref()).await;
+            return;
         }
-        Err(e) => {
-            error!("VmDispatcher: Failed to handle CreateVm command: {e}");
-            if responder.send(Err(e)).is_err() {
-                error!(
-                    "VmDispatcher: Failed to send error response for CreateVm. Responder closed."
-                );
+        worker::handle_create_vm(
+            req,
+            image_uuid_str,
+            hypervisor,
+            event_bus_tx,
+            limits,
+            saga,
+            cancel_bus,
+        )
+        .await;
+    });
+}
+
+/// A step of creating a VM that is undone if a later step fails.
+enum CreateVmStep {
+    /// The record of the VM is saved, or about to be, and its resources are
+    /// admitted.
+    Registered,
+    /// The host side of the disk, as requested, is set up, possibly only
+    /// partly.
+    DiskBackend(DiskConfig),
+    /// A PCI device of the host is passed through to the VM, or about to be.
+    PciDevice(String),
+    /// The hypervisor was asked to create the VM. Cloud Hypervisor creates
+    /// the TAPs of the VM itself, they go away with its process.
+    Hypervisor,
+}
+
+/// Creates a VM as a saga: every step records how it is undone, and if a
+/// later step fails, the steps taken are undone newest first. A failed
+/// creation leaves no hypervisor process, disk backend or record behind.
+pub(crate) struct CreateVmSaga {
+    vm_id: Uuid,
+    repository: VmRepository,
+    admission: Admission,
+    startup: StartupOrder,
+    steps: Vec<CreateVmStep>,
+}
+
+impl CreateVmSaga {
+    fn new(
+        vm_id: Uuid,
+        repository: VmRepository,
+        admission: Admission,
+        startup: StartupOrder,
+    ) -> Self {
+        Self {
+            vm_id,
+            repository,
+            admission,
+            startup,
+            steps: vec![CreateVmStep::Registered],
+        }
+    }
+
+    pub(crate) fn vm_id(&self) -> Uuid {
+        self.vm_id
+    }
+
+    pub(crate) fn repository(&self) -> &VmRepository {
+        &self.repository
+    }
+
+    /// Records that the host side of `disk` is about to be set up.
+    pub(crate) fn disk_backend(&mut self, disk: &DiskConfig) {
+        self.steps.push(CreateVmStep::DiskBackend(disk.clone()));
+    }
+
+    /// Passes the PCI device at `address` through to the VM.
+    pub(crate) async fn claim_pci_device(&mut self, address: &str) -> Result<(), VmServiceError> {
+        self.steps
+            .push(CreateVmStep::PciDevice(address.to_string()));
+        device_manager::claim(&self.repository, self.vm_id, address).await
+    }
+
+    /// Records that the hypervisor is about to create the VM.
+    pub(crate) fn hypervisor(&mut self) {
+        self.steps.push(CreateVmStep::Hypervisor);
+    }
+
+    /// Ends the saga with the VM created. It holds its resources until it is
+    /// deleted.
+    pub(crate) fn complete(self) {
+        self.admission.keep();
+    }
+
+    /// Undoes the steps taken so far, newest first.
+    pub(crate) async fn roll_back(self, hypervisor: &dyn Hypervisor) {
+        let vm_id = self.vm_id;
+        info!("VmDispatcher: Rolling back the creation of VM {vm_id}.");
+        // The disk backends are torn down after the record is gone, so the
+        // VM's own disks do not keep an iSCSI session in use.
+        let mut disks = Vec::new();
+        for step in self.steps.into_iter().rev() {
+            match step {
+                CreateVmStep::Hypervisor => {
+                    let req = DeleteVmRequest {
+                        vm_id: vm_id.to_string(),
+                    };
+                    if let Err(e) = hypervisor.delete_vm(req, None).await {
+                        warn!("VmDispatcher: Failed to clean up the hypervisor of VM {vm_id}: {e}");
+                    }
+                }
+                CreateVmStep::DiskBackend(disk) => disks.push(disk),
+                CreateVmStep::PciDevice(address) => {
+                    device_manager::release(&self.repository, vm_id, &address).await
+                }
+                CreateVmStep::Registered => {
+                    if let Err(e) = self.repository.delete_vm(vm_id).await {
+                        error!("VmDispatcher: Failed to remove the record of VM {vm_id}: {e}");
+                    }
+                    if let Err(e) = self.repository.delete_boot_timings(vm_id).await {
+                        warn!("VmDispatcher: Failed to delete boot timings of VM {vm_id}: {e}");
+                    }
+                    self.startup
+                        .remove(&WorkloadRef::new(WorkloadKind::Vm, vm_id.to_string()));
+                }
+            }
+        }
+        for disk in &disks {
+            match disk_release(&self.repository, vm_id, disk).await {
+                Ok(Some(release)) => {
+                    worker::release_disk_backend(&self.repository, &vm_id.to_string(), &release)
+                        .await
+                }
+                Ok(None) => {}
+                Err(e) => warn!(
+                    "VmDispatcher: Cannot tell whether disk '{}' of VM {vm_id} shares its backend, keeping it: {e}",
+                    disk.device_id
+                ),
             }
         }
+        // Dropping the admission releases the resources of the VM.
+        drop(self.admission);
     }
 }
 
@@ -248,8 +1090,13 @@ pub(crate) async fn handle_stream_vm_events_command(
             }
         };
 
+        let in_namespace = |record: &VmRecord| {
+            req.namespace
+                .as_deref()
+                .is_none_or(|namespace| record.namespace == namespace)
+        };
         match repository.get_vm(vm_id).await {
-            Ok(Some(record)) => {
+            Ok(Some(record)) if in_namespace(&record) => {
                 info!(
                     "StreamEvents: Sending initial state for VM {vm_id_str}: {:?}",
                     record.status.state
@@ -267,6 +1114,7 @@ pub(crate) async fn handle_stream_vm_events_command(
                             .to_string(),
                         value: state_change_event.encode_to_vec(),
                     }),
+                    node_id: String::new(),
                 };
 
                 if stream_tx.send(Ok(initial_event)).await.is_err() {
@@ -280,9 +1128,10 @@ pub(crate) async fn handle_stream_vm_events_command(
                     req,
                     stream_tx,
                     status_channel_tx,
+                    repository.clone(),
                 ));
             }
-            Ok(None) => {
+            Ok(_) => {
                 warn!("VM with ID {vm_id} not found");
                 if stream_tx
                     .send(Err(Status::not_found(format!(
@@ -311,7 +1160,11 @@ pub(crate) async fn handle_stream_vm_events_command(
         }
     } else {
         info!("StreamEvents: Request to stream events for all VMs received.");
-        match repository.list_all_vms().await {
+        let records = match req.namespace.as_deref() {
+            Some(namespace) => repository.list_vms_in_namespace(namespace).await,
+            None => repository.list_all_vms().await,
+        };
+        match records {
             Ok(records) => {
                 info!(
                     "StreamEvents: Found {} existing VMs to send initial state for.",
@@ -331,6 +1184,7 @@ pub(crate) async fn handle_stream_vm_events_command(
                                 .to_string(),
                             value: state_change_event.encode_to_vec(),
                         }),
+                        node_id: String::new(),
                     };
 
                     if stream_tx.send(Ok(initial_event)).await.is_err() {
@@ -359,12 +1213,14 @@ pub(crate) async fn handle_stream_vm_events_command(
             req,
             stream_tx,
             status_channel_tx,
+            repository.clone(),
         ));
     }
 }
 
 pub(crate) async fn handle_delete_vm_command(
     repository: &VmRepository,
+    limits: &CreateVmLimits,
     healthcheck_cancel_bus: &broadcast::Sender<Uuid>,
     req: DeleteVmRequest,
     responder: oneshot::Sender<Result<DeleteVmResponse, VmServiceError>>,
@@ -383,6 +1239,10 @@ pub(crate) async fn handle_delete_vm_command(
 
     match repository.get_vm(vm_id).await {
         Ok(Some(record)) => {
+            if let Err(e) = overlay::check_not_base(repository, &record).await {
+                let _ = responder.send(Err(e));
+                return;
+            }
             let image_uuid_to_delete = record.image_uuid.to_string();
             let process_id_to_kill = record.status.process_id;
 
@@ -392,15 +1252,49 @@ pub(crate) async fn handle_delete_vm_command(
                 return;
             }
             info!("VmDispatcher: Deleted record for VM {vm_id} from database.");
+            limits
+                .admission
+                .release(WorkloadKind::Vm, &vm_id.to_string());
+            limits
+                .startup
+                .remove(&WorkloadRef::new(WorkloadKind::Vm, vm_id.to_string()));
+
+            if let Err(e) = repository.delete_vm_snapshots(vm_id).await {
+                warn!("VmDispatcher: Failed to delete snapshot records of VM {vm_id}: {e}");
+            }
+            if let Err(e) = repository.delete_boot_timings(vm_id).await {
+                warn!("VmDispatcher: Failed to delete boot timings of VM {vm_id}: {e}");
+            }
+            let snapshots_dir = PathBuf::from(crate::VM_SNAPSHOT_DIR).join(vm_id.to_string());
+            tokio::spawn(async move { snapshot::remove_snapshot_dir(&snapshots_dir).await });
+
+            let mut released_disks = Vec::new();
+            for disk in &record.config.disks {
+                match disk_release(repository, vm_id, disk).await {
+                    Ok(Some(release)) => released_disks.push(release),
+                    Ok(None) => {}
+                    Err(e) => warn!(
+                        "VmDispatcher: Cannot tell whether disk '{}' of VM {vm_id} shares its backend, keeping it: {e}",
+                        disk.device_id
+                    ),
+                }
+            }
 
             if let Err(e) = healthcheck_cancel_bus.send(vm_id) {
                 warn!("VmDispatcher: Failed to send healthcheck cancellation for {vm_id}: {e}");
             }
 
+            let macvtaps: Vec<String> = record.config.net.iter().filter_map(macvtap_name).collect();
+            if !macvtaps.is_empty() {
+                tokio::spawn(worker::remove_macvtaps(vm_id, macvtaps));
+            }
+
             tokio::spawn(worker::handle_delete_vm(
                 req,
                 image_uuid_to_delete,
                 process_id_to_kill,
+                released_disks,
+                repository.clone(),
                 responder,
                 hypervisor,
                 event_bus_tx,
@@ -418,6 +1312,8 @@ pub(crate) async fn handle_delete_vm_command(
                 req,
                 String::new(),
                 None,
+                Vec::new(),
+                repository.clone(),
                 responder,
                 hypervisor,
                 event_bus_tx,
@@ -432,10 +1328,10 @@ pub(crate) async fn handle_delete_vm_command(
 
 async fn get_attach_message(
     stream: &mut Streaming<StreamVmConsoleRequest>,
-) -> Result<String, Status> {
+) -> Result<AttachConsoleMessage, Status> {
     match stream.next().await {
         Some(Ok(msg)) => match msg.payload {
-            Some(console_input::Payload::Attach(AttachConsoleMessage { vm_id })) => Ok(vm_id),
+            Some(console_input::Payload::Attach(attach)) => Ok(attach),
             _ => Err(Status::invalid_argument(
                 "First message must be an Attach message.",
             )),
@@ -452,14 +1348,16 @@ pub(crate) async fn handle_stream_vm_console_command(
     mut input_stream: Streaming<StreamVmConsoleRequest>,
     output_tx: mpsc::Sender<Result<StreamVmConsoleResponse, Status>>,
     hypervisor: Arc<dyn Hypervisor>,
+    consoles: ConsoleManager,
 ) {
-    let vm_id_str = match get_attach_message(&mut input_stream).await {
-        Ok(id) => id,
+    let attach = match get_attach_message(&mut input_stream).await {
+        Ok(attach) => attach,
         Err(status) => {
             let _ = output_tx.send(Err(status)).await;
             return;
         }
     };
+    let vm_id_str = attach.vm_id.clone();
 
     let (_vm_id, record) = match parse_vm_id_and_get_record(&vm_id_str, repository).await {
         Ok(result) => result,
@@ -488,31 +1386,231 @@ pub(crate) async fn handle_stream_vm_console_command(
     }
 
     tokio::spawn(worker::spawn_console_bridge(
-        vm_id_str,
+        attach,
         input_stream,
         output_tx,
         hypervisor,
+        consoles,
     ));
 }
 
-pub(crate) async fn handle_list_vms_command(
+async fn get_port_forward_start(
+    stream: &mut Streaming<PortForwardRequest>,
+) -> Result<PortForwardStart, Status> {
+    match stream.next().await {
+        Some(Ok(msg)) => match msg.payload {
+            Some(port_forward_request::Payload::Start(start)) => Ok(start),
+            _ => Err(Status::invalid_argument(
+                "First message must be a Start message.",
+            )),
+        },
+        Some(Err(e)) => Err(e),
+        None => Err(Status::invalid_argument(
+            "Client disconnected before sending Start message.",
+        )),
+    }
+}
+
+pub(crate) async fn handle_port_forward_command(
     repository: &VmRepository,
-    _req: ListVmsRequest,
-    responder: oneshot::Sender<Result<ListVmsResponse, VmServiceError>>,
+    mut input_stream: Streaming<PortForwardRequest>,
+    output_tx: mpsc::Sender<Result<PortForwardResponse, Status>>,
+    hypervisor: Arc<dyn Hypervisor>,
+) {
+    let start = match get_port_forward_start(&mut input_stream).await {
+        Ok(start) => start,
+        Err(status) => {
+            let _ = output_tx.send(Err(status)).await;
+            return;
+        }
+    };
+
+    let (_vm_id, record) = match parse_vm_id_and_get_record(&start.vm_id, repository).await {
+        Ok(result) => result,
+        Err(e) => {
+            if output_tx.send(Err(e.into())).await.is_err() {
+                warn!(
+                    "PortForward: Client for {} disconnected before error could be sent.",
+                    start.vm_id
+                );
+            }
+            return;
+        }
+    };
+
+    if record.status.state != VmState::Running {
+        let status = VmServiceError::InvalidState(format!(
+            "Cannot forward ports to VM in {:?} state. Must be in Running.",
+            record.status.state
+        ))
+        .into();
+        if output_tx.send(Err(status)).await.is_err() {
+            warn!(
+                "PortForward: Client for {} disconnected before precondition error could be sent.",
+                start.vm_id
+            );
+        }
+        return;
+    }
+
+    tokio::spawn(worker::spawn_port_forward_bridge(
+        start,
+        input_stream,
+        output_tx,
+        hypervisor,
+    ));
+}
+
+/// The guest agent answers only while the guest runs.
+async fn get_running_vm_record(
+    vm_id: &str,
+    repository: &VmRepository,
+) -> Result<VmRecord, VmServiceError> {
+    let (_vm_id, record) = parse_vm_id_and_get_record(vm_id, repository).await?;
+    if record.status.state != VmState::Running {
+        return Err(VmServiceError::InvalidState(format!(
+            "Cannot reach the guest agent of VM in {:?} state. Must be in Running.",
+            record.status.state
+        )));
+    }
+    Ok(record)
+}
+
+pub(crate) async fn handle_exec_in_guest_command(
+    repository: &VmRepository,
+    req: ExecInGuestRequest,
+    responder: oneshot::Sender<Result<ExecInGuestResponse, VmServiceError>>,
+    hypervisor: Arc<dyn Hypervisor>,
+) {
+    if let Err(e) = get_running_vm_record(&req.vm_id, repository).await {
+        let _ = responder.send(Err(e));
+        return;
+    }
+    if req.command.is_empty() {
+        let _ = responder.send(Err(VmServiceError::InvalidArgument(
+            "command must not be empty".to_string(),
+        )));
+        return;
+    }
+
+    tokio::spawn(worker::handle_exec_in_guest(req, responder, hypervisor));
+}
+
+async fn get_push_guest_file_start(
+    stream: &mut Streaming<PushGuestFileRequest>,
+) -> Result<PushGuestFileStart, VmServiceError> {
+    match stream.next().await {
+        Some(Ok(msg)) => match msg.payload {
+            Some(push_guest_file_request::Payload::Start(start)) => Ok(start),
+            _ => Err(VmServiceError::InvalidArgument(
+                "First message must be a Start message.".to_string(),
+            )),
+        },
+        Some(Err(e)) => Err(VmServiceError::InvalidArgument(format!(
+            "Failed to receive the Start message: {}",
+            e.message()
+        ))),
+        None => Err(VmServiceError::InvalidArgument(
+            "Client disconnected before sending Start message.".to_string(),
+        )),
+    }
+}
+
+pub(crate) async fn handle_push_guest_file_command(
+    repository: &VmRepository,
+    mut input_stream: Streaming<PushGuestFileRequest>,
+    responder: oneshot::Sender<Result<PushGuestFileResponse, VmServiceError>>,
+    hypervisor: Arc<dyn Hypervisor>,
+) {
+    let start = match get_push_guest_file_start(&mut input_stream).await {
+        Ok(start) => start,
+        Err(e) => {
+            let _ = responder.send(Err(e));
+            return;
+        }
+    };
+    if let Err(e) = get_running_vm_record(&start.vm_id, repository).await {
+        let _ = responder.send(Err(e));
+        return;
+    }
+
+    tokio::spawn(worker::handle_push_guest_file(
+        start,
+        input_stream,
+        responder,
+        hypervisor,
+    ));
+}
+
+pub(crate) async fn handle_pull_guest_file_command(
+    repository: &VmRepository,
+    req: PullGuestFileRequest,
+    output_tx: mpsc::Sender<Result<GuestFileChunk, Status>>,
+    hypervisor: Arc<dyn Hypervisor>,
+) {
+    if let Err(e) = get_running_vm_record(&req.vm_id, repository).await {
+        if output_tx.send(Err(e.into())).await.is_err() {
+            warn!(
+                "PullGuestFile: Client for {} disconnected before error could be sent.",
+                req.vm_id
+            );
+        }
+        return;
+    }
+
+    tokio::spawn(worker::handle_pull_guest_file(req, output_tx, hypervisor));
+}
+
+pub(crate) async fn handle_get_guest_info_command(
+    repository: &VmRepository,
+    req: GetGuestInfoRequest,
+    responder: oneshot::Sender<Result<GetGuestInfoResponse, VmServiceError>>,
+    hypervisor: Arc<dyn Hypervisor>,
+) {
+    let record = match get_running_vm_record(&req.vm_id, repository).await {
+        Ok(record) => record,
+        Err(e) => {
+            let _ = responder.send(Err(e));
+            return;
+        }
+    };
+
+    tokio::spawn(worker::handle_get_guest_info(
+        req,
+        record.config.net,
+        responder,
+        hypervisor,
+    ));
+}
+
+pub(crate) async fn handle_list_vms_command(
+    repository: &VmRepository,
+    req: ListVmsRequest,
+    responder: oneshot::Sender<Result<ListVmsResponse, VmServiceError>>,
 ) {
-    let result = repository.list_all_vms().await.map(|records| {
+    let neighbours = load_neighbour_addresses().await;
+    let result = async {
+        let records = match req.namespace.as_deref() {
+            Some(namespace) => repository.list_vms_in_namespace(namespace).await?,
+            None => repository.list_all_vms().await?,
+        };
         let vms = records
             .into_iter()
             .map(|record| VmInfo {
                 vm_id: record.vm_id.to_string(),
                 state: record.status.state as i32,
+                guest_addresses: guest_addresses(&record.config, &neighbours),
                 config: Some(record.config),
+                boot_timings: None,
+                namespace: record.namespace,
+                name: record.name,
             })
             .collect();
-        ListVmsResponse { vms }
-    });
+        Ok(ListVmsResponse { vms })
+    }
+    .await;
 
-    if responder.send(result.map_err(Into::into)).is_err() {
+    if responder.send(result).is_err() {
         error!("VmDispatcher: Failed to send response for ListVms.");
     }
 }
@@ -579,8 +1677,16 @@ pub(crate) async fn handle_shutdown_vm_command(
         return;
     }
 
+    let ephemeral_disks = record
+        .config
+        .disks
+        .into_iter()
+        .filter(|disk| matches!(disk.backend, Some(disk_config::Backend::Ephemeral(_))))
+        .collect();
+
     tokio::spawn(worker::handle_shutdown_vm(
         req,
+        ephemeral_disks,
         responder,
         hypervisor,
         event_bus_tx,
@@ -651,11 +1757,11 @@ pub(crate) async fn handle_resume_vm_command(
 
 pub(crate) async fn handle_attach_disk_command(
     repository: &VmRepository,
-    req: AttachDiskRequest,
+    mut req: AttachDiskRequest,
     responder: oneshot::Sender<Result<AttachDiskResponse, VmServiceError>>,
     hypervisor: Arc<dyn Hypervisor>,
 ) {
-    let (_vm_id, record) = match parse_vm_id_and_get_record(&req.vm_id, repository).await {
+    let (vm_id, mut record) = match parse_vm_id_and_get_record(&req.vm_id, repository).await {
         Ok(result) => result,
         Err(e) => {
             let _ = responder.send(Err(e));
@@ -674,7 +1780,78 @@ pub(crate) async fn handle_attach_disk_command(
         return;
     }
 
-    tokio::spawn(worker::handle_attach_disk(req, responder, hypervisor));
+    let mut new_disk_config = match req.disk.take() {
+        Some(disk) => disk,
+        None => {
+            let _ = responder.send(Err(VmServiceError::InvalidArgument(
+                "DiskConfig is required in AttachDiskRequest".to_string(),
+            )));
+            return;
+        }
+    };
+
+    ensure_disk_config_device_id(&mut new_disk_config);
+
+    if record
+        .config
+        .disks
+        .iter()
+        .any(|disk| disk.device_id == new_disk_config.device_id)
+    {
+        let _ = responder.send(Err(VmServiceError::InvalidArgument(format!(
+            "Disk with device_id '{}' is already attached.",
+            new_disk_config.device_id
+        ))));
+        return;
+    }
+
+    if let Err(e) = validate_disk_config(&new_disk_config) {
+        let _ = responder.send(Err(e));
+        return;
+    }
+    if let Err(e) = disk_image::resolve_format(&mut new_disk_config).await {
+        let _ = responder.send(Err(e));
+        return;
+    }
+
+    let scratch_bytes = ephemeral_disk_bytes([&new_disk_config]);
+    if scratch_bytes > 0 {
+        if let Err(e) = scratch::check_memory(scratch_bytes).await {
+            let _ = responder.send(Err(e));
+            return;
+        }
+    }
+
+    let release_on_failure = match disk_release(repository, vm_id, &new_disk_config).await {
+        Ok(release) => release,
+        Err(e) => {
+            let _ = responder.send(Err(e));
+            return;
+        }
+    };
+
+    let mut persisted_disk_config = new_disk_config.clone();
+    match &mut persisted_disk_config.backend {
+        Some(disk_config::Backend::Iscsi(target)) => iscsi::strip_secrets(target),
+        Some(disk_config::Backend::Rbd(image)) => rbd::strip_secrets(image),
+        _ => {}
+    }
+
+    record.config.disks.push(persisted_disk_config);
+
+    if let Err(e) = repository.save_vm(&record).await {
+        let _ = responder.send(Err(e.into()));
+        return;
+    }
+
+    req.disk = Some(new_disk_config);
+    tokio::spawn(worker::handle_attach_disk(
+        req,
+        release_on_failure,
+        repository.clone(),
+        responder,
+        hypervisor,
+    ));
 }
 
 pub(crate) async fn handle_detach_disk_command(
@@ -683,7 +1860,7 @@ pub(crate) async fn handle_detach_disk_command(
     responder: oneshot::Sender<Result<DetachDiskResponse, VmServiceError>>,
     hypervisor: Arc<dyn Hypervisor>,
 ) {
-    let (_vm_id, record) = match parse_vm_id_and_get_record(&req.vm_id, repository).await {
+    let (vm_id, mut record) = match parse_vm_id_and_get_record(&req.vm_id, repository).await {
         Ok(result) => result,
         Err(e) => {
             let _ = responder.send(Err(e));
@@ -702,12 +1879,39 @@ pub(crate) async fn handle_detach_disk_command(
         return;
     }
 
-    tokio::spawn(worker::handle_detach_disk(req, responder, hypervisor));
+    let mut release = None;
+    if let Some(index) = record
+        .config
+        .disks
+        .iter()
+        .position(|disk| disk.device_id == req.device_id)
+    {
+        let removed = record.config.disks.remove(index);
+        release = match disk_release(repository, vm_id, &removed).await {
+            Ok(release) => release,
+            Err(e) => {
+                let _ = responder.send(Err(e));
+                return;
+            }
+        };
+        if let Err(e) = repository.save_vm(&record).await {
+            let _ = responder.send(Err(e.into()));
+            return;
+        }
+    }
+
+    tokio::spawn(worker::handle_detach_disk(
+        req,
+        release,
+        repository.clone(),
+        responder,
+        hypervisor,
+    ));
 }
 
 pub(crate) async fn handle_attach_nic_command(
     repository: &VmRepository,
-    req: AttachNicRequest,
+    mut req: AttachNicRequest,
     responder: oneshot::Sender<Result<AttachNicResponse, VmServiceError>>,
     hypervisor: Arc<dyn Hypervisor>,
 ) {
@@ -737,7 +1941,14 @@ pub(crate) async fn handle_attach_nic_command(
         }
     };
 
+    if let Err(e) = slaac::validate_nic(&new_nic_config) {
+        let _ = responder.send(Err(e));
+        return;
+    }
     ensure_net_config_device_id(&mut new_nic_config);
+    ensure_net_config_mac_address(&mut new_nic_config);
+    // The hypervisor gets the device under the ID and MAC that are persisted.
+    req.nic = Some(new_nic_config.clone());
 
     record.config.net.push(new_nic_config);
 
@@ -771,86 +1982,893 @@ pub(crate) async fn handle_detach_nic_command(
         return;
     }
 
-    let initial_len = record.config.net.len();
-    record
+    let Some(position) = record
         .config
         .net
-        .retain(|nic| nic.device_id != req.device_id);
-
-    if record.config.net.len() == initial_len {
+        .iter()
+        .position(|nic| nic.device_id == req.device_id)
+    else {
         let _ = responder.send(Err(VmServiceError::InvalidArgument(format!(
             "NIC with device_id '{}' not found in VM configuration.",
             req.device_id
         ))));
         return;
-    }
+    };
+    let macvtap = macvtap_name(&record.config.net.remove(position));
 
     if let Err(e) = repository.save_vm(&record).await {
         let _ = responder.send(Err(e.into()));
         return;
     }
 
-    tokio::spawn(worker::handle_detach_nic(req, responder, hypervisor));
+    tokio::spawn(worker::handle_detach_nic(
+        req, macvtap, responder, hypervisor,
+    ));
 }
 
-pub(crate) async fn check_and_cleanup_vms(
+pub(crate) async fn handle_list_host_pci_devices_command(
     repository: &VmRepository,
-    hypervisor: Arc<dyn Hypervisor>,
-    event_bus_tx: mpsc::Sender<VmEventWrapper>,
-    healthcheck_cancel_bus: &broadcast::Sender<Uuid>,
-    vms: Vec<VmRecord>,
+    req: ListHostPciDevicesRequest,
+    responder: oneshot::Sender<Result<ListHostPciDevicesResponse, VmServiceError>>,
 ) {
-    for vm in vms {
-        if let Some(pid) = vm.status.process_id {
-            let pid_obj = Pid::from_raw(pid as i32);
-            let process_exists = nix::sys::signal::kill(pid_obj, None).is_ok();
-
-            if process_exists {
-                info!("VmDispatcher (Sanity Check): Found running VM {} (PID: {}) from previous session. Starting health monitor.", vm.vm_id, pid);
-                let cancel_bus = healthcheck_cancel_bus.subscribe();
-                worker::start_healthcheck_monitor(
-                    vm.vm_id.to_string(),
-                    hypervisor.clone(),
-                    event_bus_tx.clone(),
-                    cancel_bus,
-                );
-            } else {
-                warn!("VmDispatcher (Sanity Check): Found VM {} in DB with PID {}, but process does not exist. Cleaning up.", vm.vm_id, pid);
-                let (resp_tx, resp_rx) = oneshot::channel();
-                let req = DeleteVmRequest {
-                    vm_id: vm.vm_id.to_string(),
-                };
-                let vm_id_for_log = vm.vm_id;
-
-                handle_delete_vm_command(
-                    repository,
-                    healthcheck_cancel_bus,
-                    req,
-                    resp_tx,
-                    hypervisor.clone(),
-                    event_bus_tx.clone(),
-                )
-                .await;
-
-                match resp_rx.await {
-                    Ok(Ok(_)) => info!("VmDispatcher (Sanity Check): Successfully cleaned up zombie VM {vm_id_for_log}."),
-                    Ok(Err(status)) => error!("VmDispatcher (Sanity Check): Failed to clean up zombie VM {vm_id_for_log}: {status}"),
-                    Err(_) => error!("VmDispatcher (Sanity Check): Cleanup task for zombie VM {vm_id_for_log} did not return a response."),
-                }
-            }
-        }
+    let result = device_manager::host_pci_devices(repository, req.vfio_capable_only)
+        .await
+        .map(|devices| ListHostPciDevicesResponse { devices });
+    if responder.send(result).is_err() {
+        error!("VmDispatcher: Failed to send response for ListHostPciDevices.");
     }
 }
 
-pub(crate) async fn perform_startup_sanity_check(
+pub(crate) async fn handle_attach_pci_device_command(
     repository: &VmRepository,
+    mut req: AttachPciDeviceRequest,
+    responder: oneshot::Sender<Result<AttachPciDeviceResponse, VmServiceError>>,
     hypervisor: Arc<dyn Hypervisor>,
-    event_bus_tx: mpsc::Sender<VmEventWrapper>,
-    healthcheck_cancel_bus: &broadcast::Sender<Uuid>,
 ) {
-    info!("VmDispatcher: Running initial sanity check...");
-    match repository.list_all_vms().await {
-        Ok(vms) => {
+    let (vm_id, mut record) = match parse_vm_id_and_get_record(&req.vm_id, repository).await {
+        Ok(result) => result,
+        Err(e) => {
+            let _ = responder.send(Err(e));
+            return;
+        }
+    };
+
+    let current_state = record.status.state;
+    if matches!(current_state, VmState::Creating | VmState::Crashed) {
+        let _ = responder.send(Err(VmServiceError::InvalidState(format!(
+            "Cannot attach PCI device to VM in {current_state:?} state."
+        ))));
+        return;
+    }
+
+    let mut device = match req.device.clone() {
+        Some(device) if !device.pci_address.is_empty() => device,
+        _ => {
+            let _ = responder.send(Err(VmServiceError::InvalidArgument(
+                "A PciDeviceConfig with a pci_address is required in AttachPciDeviceRequest"
+                    .to_string(),
+            )));
+            return;
+        }
+    };
+    ensure_pci_device_config_device_id(&mut device);
+    if record
+        .config
+        .pci_devices
+        .iter()
+        .any(|existing| existing.device_id == device.device_id)
+    {
+        let _ = responder.send(Err(VmServiceError::AlreadyExists(format!(
+            "A PCI device with device_id '{}' is already attached to the VM.",
+            device.device_id
+        ))));
+        return;
+    }
+    let mut addresses = device_manager::vm_pci_devices(&record.config);
+    addresses.push(device.pci_address.clone());
+    if let Err(e) = device_manager::check(repository, vm_id, &addresses).await {
+        let _ = responder.send(Err(e));
+        return;
+    }
+    if let Err(e) = device_manager::claim(repository, vm_id, &device.pci_address).await {
+        let _ = responder.send(Err(e));
+        return;
+    }
+    // The hypervisor gets the device under the ID that is persisted.
+    req.device = Some(device.clone());
+    let address = device.pci_address.clone();
+    record.config.pci_devices.push(device);
+
+    if let Err(e) = repository.save_vm(&record).await {
+        device_manager::release(repository, vm_id, &address).await;
+        let _ = responder.send(Err(e.into()));
+        return;
+    }
+
+    tokio::spawn(worker::handle_attach_pci_device(
+        req,
+        repository.clone(),
+        vm_id,
+        address,
+        responder,
+        hypervisor,
+    ));
+}
+
+pub(crate) async fn handle_detach_pci_device_command(
+    repository: &VmRepository,
+    req: DetachPciDeviceRequest,
+    responder: oneshot::Sender<Result<DetachPciDeviceResponse, VmServiceError>>,
+    hypervisor: Arc<dyn Hypervisor>,
+) {
+    let (vm_id, mut record) = match parse_vm_id_and_get_record(&req.vm_id, repository).await {
+        Ok(result) => result,
+        Err(e) => {
+            let _ = responder.send(Err(e));
+            return;
+        }
+    };
+
+    let current_state = record.status.state;
+    if matches!(current_state, VmState::Creating | VmState::Crashed) {
+        let _ = responder.send(Err(VmServiceError::InvalidState(format!(
+            "Cannot detach PCI device from VM in {current_state:?} state."
+        ))));
+        return;
+    }
+
+    let Some(position) = record
+        .config
+        .pci_devices
+        .iter()
+        .position(|device| device.device_id == req.device_id)
+    else {
+        let _ = responder.send(Err(VmServiceError::InvalidArgument(format!(
+            "PCI device with device_id '{}' not found in VM configuration.",
+            req.device_id
+        ))));
+        return;
+    };
+    let address = record.config.pci_devices.remove(position).pci_address;
+
+    if let Err(e) = repository.save_vm(&record).await {
+        let _ = responder.send(Err(e.into()));
+        return;
+    }
+
+    tokio::spawn(worker::handle_detach_pci_device(
+        req,
+        repository.clone(),
+        vm_id,
+        address,
+        responder,
+        hypervisor,
+    ));
+}
+
+pub(crate) async fn handle_resize_vm_command(
+    repository: &VmRepository,
+    admission: &AdmissionController,
+    mut req: ResizeVmRequest,
+    responder: oneshot::Sender<Result<ResizeVmResponse, VmServiceError>>,
+    hypervisor: Arc<dyn Hypervisor>,
+) {
+    let (vm_id, record) = match parse_vm_id_and_get_record(&req.vm_id, repository).await {
+        Ok(result) => result,
+        Err(e) => {
+            let _ = responder.send(Err(e));
+            return;
+        }
+    };
+
+    let current_state = record.status.state;
+    if current_state != VmState::Running {
+        let _ = responder.send(Err(VmServiceError::InvalidState(format!(
+            "Cannot resize VM in {current_state:?} state. Must be in Running."
+        ))));
+        return;
+    }
+
+    let config = match resized_config(&record.config, &req) {
+        Ok(config) => config,
+        Err(e) => {
+            let _ = responder.send(Err(e));
+            return;
+        }
+    };
+
+    let previous =
+        match admission.resize(WorkloadKind::Vm, &vm_id.to_string(), vm_resources(&config)) {
+            Ok(previous) => previous,
+            Err(e) => {
+                let _ = responder.send(Err(e.into()));
+                return;
+            }
+        };
+
+    // The commitment is restored under the same ID if resizing fails.
+    req.vm_id = vm_id.to_string();
+    tokio::spawn(worker::handle_resize_vm(
+        req,
+        config,
+        previous,
+        repository.clone(),
+        admission.clone(),
+        responder,
+        hypervisor,
+    ));
+}
+
+async fn get_snapshot_of_vm(
+    repository: &VmRepository,
+    vm_id: Uuid,
+    snapshot_id_str: &str,
+) -> Result<(Uuid, VmSnapshotInfo), VmServiceError> {
+    let snapshot_id = Uuid::parse_str(snapshot_id_str)
+        .map_err(|_| VmServiceError::InvalidArgument("Invalid snapshot ID format.".to_string()))?;
+
+    match repository.get_vm_snapshot(snapshot_id).await? {
+        Some(snapshot) if snapshot.vm_id == vm_id.to_string() => Ok((snapshot_id, snapshot)),
+        _ => Err(VmServiceError::SnapshotNotFound(snapshot_id.to_string())),
+    }
+}
+
+fn prepare_vm_snapshot(
+    record: &VmRecord,
+    req: CreateVmSnapshotRequest,
+) -> Result<(VmSnapshotInfo, bool), VmServiceError> {
+    let current_state = record.status.state;
+    if req.include_memory {
+        if !matches!(current_state, VmState::Running | VmState::Paused) {
+            return Err(VmServiceError::InvalidState(format!(
+                "Cannot snapshot the memory of a VM in {current_state:?} state. Must be in Running or Paused."
+            )));
+        }
+        if !req.device_ids.is_empty() {
+            return Err(VmServiceError::InvalidArgument(
+                "A snapshot including memory always covers all disks.".to_string(),
+            ));
+        }
+    } else if !matches!(
+        current_state,
+        VmState::Created | VmState::Running | VmState::Paused | VmState::Stopped
+    ) {
+        return Err(VmServiceError::InvalidState(format!(
+            "Cannot snapshot VM in {current_state:?} state."
+        )));
+    }
+
+    let disks = snapshot::select_disks(
+        &snapshot::vm_disks(record),
+        &req.device_ids,
+        |(device_id, _)| device_id.as_str(),
+    )?;
+
+    let vm_id = record.vm_id.to_string();
+    let snapshot_id = Uuid::new_v4().to_string();
+    let dir = snapshot::snapshot_dir(&vm_id, &snapshot_id);
+    let disks = disks
+        .into_iter()
+        .enumerate()
+        .map(|(index, (device_id, path))| DiskSnapshot {
+            device_id,
+            source_path: path.to_string_lossy().into_owned(),
+            snapshot_path: dir
+                .join(format!("disk{index}.img"))
+                .to_string_lossy()
+                .into_owned(),
+            size_bytes: 0,
+        })
+        .collect();
+    let created_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+
+    let snapshot = VmSnapshotInfo {
+        snapshot_id,
+        vm_id,
+        name: req.name,
+        created_at,
+        includes_memory: req.include_memory,
+        disks,
+    };
+    // Pausing keeps the guest from writing to its disks while they are copied.
+    Ok((snapshot, current_state == VmState::Running))
+}
+
+pub(crate) async fn handle_create_vm_snapshot_command(
+    repository: &VmRepository,
+    req: CreateVmSnapshotRequest,
+    responder: oneshot::Sender<Result<CreateVmSnapshotResponse, VmServiceError>>,
+    hypervisor: Arc<dyn Hypervisor>,
+) {
+    let (_vm_id, record) = match parse_vm_id_and_get_record(&req.vm_id, repository).await {
+        Ok(result) => result,
+        Err(e) => {
+            let _ = responder.send(Err(e));
+            return;
+        }
+    };
+
+    match prepare_vm_snapshot(&record, req) {
+        Ok((snapshot, pause)) => {
+            tokio::spawn(worker::handle_create_vm_snapshot(
+                snapshot,
+                pause,
+                responder,
+                hypervisor,
+                repository.clone(),
+            ));
+        }
+        Err(e) => {
+            let _ = responder.send(Err(e));
+        }
+    }
+}
+
+pub(crate) async fn handle_list_vm_snapshots_command(
+    repository: &VmRepository,
+    req: ListVmSnapshotsRequest,
+    responder: oneshot::Sender<Result<ListVmSnapshotsResponse, VmServiceError>>,
+) {
+    let result = async {
+        let (vm_id, _record) = parse_vm_id_and_get_record(&req.vm_id, repository).await?;
+        let snapshots = repository.list_vm_snapshots(vm_id).await?;
+        Ok::<_, VmServiceError>(ListVmSnapshotsResponse { snapshots })
+    }
+    .await;
+
+    if responder.send(result).is_err() {
+        error!("VmDispatcher: Failed to send response for ListVmSnapshots.");
+    }
+}
+
+/// The number of events ListVmEvents returns if the request sets no limit.
+const DEFAULT_VM_EVENTS_LIMIT: u32 = 1000;
+
+fn timestamp_to_ms(timestamp: &prost_types::Timestamp) -> i64 {
+    timestamp
+        .seconds
+        .saturating_mul(1000)
+        .saturating_add(i64::from(timestamp.nanos / 1_000_000))
+}
+
+fn ms_to_timestamp(ms: i64) -> prost_types::Timestamp {
+    prost_types::Timestamp {
+        seconds: ms.div_euclid(1000),
+        nanos: (ms.rem_euclid(1000) * 1_000_000) as i32,
+    }
+}
+
+pub(crate) async fn handle_list_vm_events_command(
+    repository: &VmRepository,
+    req: ListVmEventsRequest,
+    responder: oneshot::Sender<Result<ListVmEventsResponse, VmServiceError>>,
+) {
+    let result = async {
+        let vm_id = req
+            .vm_id
+            .as_deref()
+            .map(Uuid::parse_str)
+            .transpose()
+            .map_err(|_| VmServiceError::InvalidArgument("Invalid VM ID format.".to_string()))?;
+        let limit = match req.limit {
+            0 => DEFAULT_VM_EVENTS_LIMIT,
+            limit => limit,
+        };
+        let filter = VmEventFilter {
+            vm_id,
+            namespace: req.namespace,
+            since_ms: req.since.as_ref().map(timestamp_to_ms),
+            until_ms: req.until.as_ref().map(timestamp_to_ms),
+            limit: i64::from(limit),
+        };
+        let events = repository
+            .list_vm_events(&filter)
+            .await?
+            .into_iter()
+            .map(|(event, recorded_at_ms)| RecordedVmEvent {
+                event: Some(event),
+                recorded_at: Some(ms_to_timestamp(recorded_at_ms)),
+            })
+            .collect();
+        Ok::<_, VmServiceError>(ListVmEventsResponse { events })
+    }
+    .await;
+
+    if responder.send(result).is_err() {
+        error!("VmDispatcher: Failed to send response for ListVmEvents.");
+    }
+}
+
+/// The number of entries ReplayVmStateJournal returns if the request sets no
+/// limit.
+const DEFAULT_JOURNAL_ENTRIES_LIMIT: u32 = 1000;
+
+fn journal_entry_to_proto(entry: VmJournalEntry) -> VmStateJournalEntry {
+    VmStateJournalEntry {
+        sequence: entry.sequence as u64,
+        vm_id: entry.vm_id.to_string(),
+        namespace: entry.namespace,
+        recorded_at: Some(ms_to_timestamp(entry.recorded_at_ms)),
+        old_state: entry.old_state.map(Into::into),
+        new_state: entry.new_state.map(Into::into),
+        message: entry.message,
+    }
+}
+
+pub(crate) async fn handle_replay_vm_state_journal_command(
+    repository: &VmRepository,
+    req: ReplayVmStateJournalRequest,
+    responder: oneshot::Sender<Result<ReplayVmStateJournalResponse, VmServiceError>>,
+) {
+    let result = async {
+        let vm_id = req
+            .vm_id
+            .as_deref()
+            .map(Uuid::parse_str)
+            .transpose()
+            .map_err(|_| VmServiceError::InvalidArgument("Invalid VM ID format.".to_string()))?;
+        let after_sequence = i64::try_from(req.after_sequence).map_err(|_| {
+            VmServiceError::InvalidArgument("after_sequence is out of range.".to_string())
+        })?;
+        let limit = match req.limit {
+            0 => DEFAULT_JOURNAL_ENTRIES_LIMIT,
+            limit => limit,
+        };
+        let entries = repository
+            .replay_vm_state_journal(vm_id, after_sequence, i64::from(limit))
+            .await?
+            .into_iter()
+            .map(journal_entry_to_proto)
+            .collect();
+        Ok::<_, VmServiceError>(ReplayVmStateJournalResponse { entries })
+    }
+    .await;
+
+    if responder.send(result).is_err() {
+        error!("VmDispatcher: Failed to send response for ReplayVmStateJournal.");
+    }
+}
+
+pub(crate) async fn handle_plan_evacuation_command(
+    repository: &VmRepository,
+    req: PlanEvacuationRequest,
+    responder: oneshot::Sender<Result<PlanEvacuationResponse, VmServiceError>>,
+) {
+    let result = async {
+        let vm_ids = req
+            .vm_ids
+            .iter()
+            .map(|vm_id| Uuid::parse_str(vm_id))
+            .collect::<Result<HashSet<_>, _>>()
+            .map_err(|_| VmServiceError::InvalidArgument("Invalid VM ID format.".to_string()))?;
+        let records: Vec<VmRecord> = repository
+            .list_all_vms()
+            .await?
+            .into_iter()
+            .filter(|record| vm_ids.is_empty() || vm_ids.contains(&record.vm_id))
+            .collect();
+        if let Some(missing) = vm_ids
+            .iter()
+            .find(|vm_id| !records.iter().any(|record| record.vm_id == **vm_id))
+        {
+            return Err(VmServiceError::Vmm(crate::vmm::VmmError::VmNotFound(
+                missing.to_string(),
+            )));
+        }
+        let (steps, required_capacity) = evacuation::plan_evacuation(&records, req.target());
+        Ok(PlanEvacuationResponse {
+            steps,
+            required_capacity: Some(required_capacity),
+        })
+    }
+    .await;
+
+    if responder.send(result).is_err() {
+        error!("VmDispatcher: Failed to send response for PlanEvacuation.");
+    }
+}
+
+pub(crate) async fn handle_get_vm_stats_command(
+    repository: VmRepository,
+    req: GetVmStatsRequest,
+    responder: oneshot::Sender<Result<GetVmStatsResponse, VmServiceError>>,
+) {
+    let result = async {
+        let vm_ids = req
+            .vm_ids
+            .iter()
+            .map(|vm_id| Uuid::parse_str(vm_id))
+            .collect::<Result<HashSet<_>, _>>()
+            .map_err(|_| VmServiceError::InvalidArgument("Invalid VM ID format.".to_string()))?;
+        let records: Vec<VmRecord> = repository
+            .list_all_vms()
+            .await?
+            .into_iter()
+            .filter(|record| vm_ids.is_empty() || vm_ids.contains(&record.vm_id))
+            .collect();
+        if let Some(missing) = vm_ids
+            .iter()
+            .find(|vm_id| !records.iter().any(|record| record.vm_id == **vm_id))
+        {
+            return Err(VmServiceError::Vmm(crate::vmm::VmmError::VmNotFound(
+                missing.to_string(),
+            )));
+        }
+        if records.iter().all(|record| record.config.gpus.is_empty()) {
+            return Ok(GetVmStatsResponse {
+                stats: stats::vm_stats(&records, &[], &[]),
+                node_id: String::new(),
+            });
+        }
+        let partitions = gpu::partitions()
+            .map_err(|e| VmServiceError::Gpu(format!("Failed to list GPU partitions: {e}")))?;
+        let metrics = gpu_metrics::collect().await;
+        Ok(GetVmStatsResponse {
+            stats: stats::vm_stats(&records, &partitions, &metrics),
+            node_id: String::new(),
+        })
+    }
+    .await;
+
+    if responder.send(result).is_err() {
+        error!("VmDispatcher: Failed to send response for GetVmStats.");
+    }
+}
+
+/// The VMs a stream is for, which must exist. Empty streams all VMs.
+async fn stream_vm_ids(
+    repository: &VmRepository,
+    vm_ids: &[String],
+) -> Result<HashSet<Uuid>, VmServiceError> {
+    let vm_ids = vm_ids
+        .iter()
+        .map(|vm_id| Uuid::parse_str(vm_id))
+        .collect::<Result<HashSet<_>, _>>()
+        .map_err(|_| VmServiceError::InvalidArgument("Invalid VM ID format.".to_string()))?;
+    for vm_id in &vm_ids {
+        if repository.get_vm(*vm_id).await?.is_none() {
+            return Err(VmServiceError::Vmm(crate::vmm::VmmError::VmNotFound(
+                vm_id.to_string(),
+            )));
+        }
+    }
+    Ok(vm_ids)
+}
+
+pub(crate) async fn handle_stream_vm_flows_command(
+    repository: &VmRepository,
+    req: StreamVmFlowsRequest,
+    stream_tx: mpsc::Sender<Result<VmFlowsSample, Status>>,
+) {
+    match stream_vm_ids(repository, &req.vm_ids).await {
+        Ok(vm_ids) => {
+            let period = match req.interval_seconds {
+                0 => flows::DEFAULT_INTERVAL,
+                seconds => Duration::from_secs(seconds.into()),
+            };
+            tokio::spawn(flows::stream(repository.clone(), vm_ids, period, stream_tx));
+        }
+        Err(e) => {
+            if stream_tx.send(Err(e.into())).await.is_err() {
+                warn!("StreamVmFlows: Client disconnected before the error could be sent.");
+            }
+        }
+    }
+}
+
+pub(crate) async fn handle_stream_vm_metrics_command(
+    repository: &VmRepository,
+    req: StreamVmMetricsRequest,
+    stream_tx: mpsc::Sender<Result<VmMetricsSample, Status>>,
+    hypervisor: Arc<dyn Hypervisor>,
+) {
+    match stream_vm_ids(repository, &req.vm_ids).await {
+        Ok(vm_ids) => {
+            let period = match req.interval_seconds {
+                0 => metrics::DEFAULT_INTERVAL,
+                seconds => Duration::from_secs(seconds.into()),
+            };
+            tokio::spawn(metrics::stream(
+                repository.clone(),
+                hypervisor,
+                vm_ids,
+                period,
+                stream_tx,
+            ));
+        }
+        Err(e) => {
+            if stream_tx.send(Err(e.into())).await.is_err() {
+                warn!("StreamVmMetrics: Client disconnected before the error could be sent.");
+            }
+        }
+    }
+}
+
+pub(crate) async fn handle_replicate_vm_disks_command(
+    repository: &VmRepository,
+    replication: &Replication,
+    req: ReplicateVmDisksRequest,
+    responder: oneshot::Sender<Result<ReplicateVmDisksResponse, VmServiceError>>,
+    hypervisor: Arc<dyn Hypervisor>,
+) {
+    let prepared = async {
+        let (_vm_id, record) = parse_vm_id_and_get_record(&req.vm_id, repository).await?;
+        let current_state = record.status.state;
+        if !matches!(
+            current_state,
+            VmState::Created | VmState::Running | VmState::Paused | VmState::Stopped
+        ) {
+            return Err(VmServiceError::InvalidState(format!(
+                "Cannot replicate the disks of a VM in {current_state:?} state."
+            )));
+        }
+        if !(req.peer.starts_with("http://") || req.peer.starts_with("https://")) {
+            return Err(VmServiceError::InvalidArgument(format!(
+                "Peer '{}' must be an http:// or https:// address",
+                req.peer
+            )));
+        }
+        let disks = snapshot::select_disks(
+            &snapshot::vm_disks(&record),
+            &req.device_ids,
+            |(device_id, _)| device_id.as_str(),
+        )?;
+        if !req.device_ids.is_empty() {
+            return Ok((record, disks));
+        }
+        // Disks on block devices, e.g. iSCSI LUNs, are replicated by their
+        // storage, if at all, and only fail if named explicitly.
+        let mut files = Vec::with_capacity(disks.len());
+        for (device_id, path) in disks {
+            if tokio::fs::metadata(&path)
+                .await
+                .is_ok_and(|metadata| metadata.is_file())
+            {
+                files.push((device_id, path));
+            } else {
+                info!(
+                    "VmDispatcher: Not replicating disk '{device_id}' of VM {}, it is no image file.",
+                    record.vm_id
+                );
+            }
+        }
+        Ok((record, files))
+    }
+    .await;
+
+    match prepared {
+        Ok((record, disks)) => {
+            let replication = replication.clone();
+            tokio::spawn(async move {
+                let result = replication
+                    .replicate(record, req.peer, disks, hypervisor)
+                    .await;
+                if responder.send(result).is_err() {
+                    error!("VmDispatcher: Failed to send response for ReplicateVmDisks.");
+                }
+            });
+        }
+        Err(e) => {
+            let _ = responder.send(Err(e));
+        }
+    }
+}
+
+pub(crate) fn handle_receive_disk_replica_command(
+    replication: &Replication,
+    input_stream: Streaming<ReceiveDiskReplicaRequest>,
+    responder: oneshot::Sender<Result<ReceiveDiskReplicaResponse, VmServiceError>>,
+) {
+    let replication = replication.clone();
+    tokio::spawn(async move {
+        let result = replication.receive(input_stream).await;
+        if responder.send(result).is_err() {
+            error!("VmDispatcher: Failed to send response for ReceiveDiskReplica.");
+        }
+    });
+}
+
+pub(crate) async fn handle_list_disk_replicas_command(
+    replication: &Replication,
+    req: ListDiskReplicasRequest,
+    responder: oneshot::Sender<Result<ListDiskReplicasResponse, VmServiceError>>,
+) {
+    let result = replication
+        .list(req.vm_id.as_deref())
+        .await
+        .map(|replicas| ListDiskReplicasResponse { replicas });
+    if responder.send(result).is_err() {
+        error!("VmDispatcher: Failed to send response for ListDiskReplicas.");
+    }
+}
+
+pub(crate) async fn handle_delete_disk_replicas_command(
+    replication: &Replication,
+    req: DeleteDiskReplicasRequest,
+    responder: oneshot::Sender<Result<DeleteDiskReplicasResponse, VmServiceError>>,
+) {
+    let result = replication
+        .delete(&req.vm_id)
+        .await
+        .map(|()| DeleteDiskReplicasResponse {});
+    if responder.send(result).is_err() {
+        error!("VmDispatcher: Failed to send response for DeleteDiskReplicas.");
+    }
+}
+
+pub(crate) async fn handle_revert_vm_snapshot_command(
+    repository: &VmRepository,
+    req: RevertVmSnapshotRequest,
+    responder: oneshot::Sender<Result<RevertVmSnapshotResponse, VmServiceError>>,
+    hypervisor: Arc<dyn Hypervisor>,
+    event_bus_tx: mpsc::Sender<VmEventWrapper>,
+) {
+    let prepared = async {
+        let (vm_id, record) = parse_vm_id_and_get_record(&req.vm_id, repository).await?;
+        let (_snapshot_id, snapshot) =
+            get_snapshot_of_vm(repository, vm_id, &req.snapshot_id).await?;
+        let disks = snapshot::select_disks(&snapshot.disks, &req.device_ids, |disk| {
+            disk.device_id.as_str()
+        })?;
+        let restore_memory = snapshot.includes_memory && req.device_ids.is_empty();
+
+        let current_state = record.status.state;
+        if restore_memory {
+            if matches!(current_state, VmState::Creating | VmState::Crashed) {
+                return Err(VmServiceError::InvalidState(format!(
+                    "Cannot revert VM in {current_state:?} state."
+                )));
+            }
+        } else if !matches!(current_state, VmState::Created | VmState::Stopped) {
+            return Err(VmServiceError::InvalidState(format!(
+                "Cannot revert the disks of a VM in {current_state:?} state. Must be in Created or Stopped."
+            )));
+        }
+        Ok::<_, VmServiceError>((
+            snapshot,
+            disks,
+            restore_memory,
+            current_state == VmState::Running,
+        ))
+    }
+    .await;
+
+    match prepared {
+        Ok((snapshot, disks, restore_memory, pause)) => {
+            tokio::spawn(worker::handle_revert_vm_snapshot(
+                snapshot,
+                disks,
+                restore_memory,
+                pause,
+                responder,
+                hypervisor,
+                event_bus_tx,
+            ));
+        }
+        Err(e) => {
+            let _ = responder.send(Err(e));
+        }
+    }
+}
+
+pub(crate) async fn handle_delete_vm_snapshot_command(
+    repository: &VmRepository,
+    req: DeleteVmSnapshotRequest,
+    responder: oneshot::Sender<Result<DeleteVmSnapshotResponse, VmServiceError>>,
+) {
+    let result = async {
+        let (vm_id, _record) = parse_vm_id_and_get_record(&req.vm_id, repository).await?;
+        let (snapshot_id, snapshot) =
+            get_snapshot_of_vm(repository, vm_id, &req.snapshot_id).await?;
+        repository.delete_vm_snapshot(snapshot_id).await?;
+        info!(
+            "VmDispatcher: Deleted snapshot '{}' ({}) of VM {vm_id}",
+            snapshot.name, snapshot.snapshot_id
+        );
+
+        let dir = snapshot::snapshot_dir(&snapshot.vm_id, &snapshot.snapshot_id);
+        tokio::spawn(async move { snapshot::remove_snapshot_dir(&dir).await });
+        Ok::<_, VmServiceError>(DeleteVmSnapshotResponse {})
+    }
+    .await;
+
+    if responder.send(result).is_err() {
+        error!("VmDispatcher: Failed to send response for DeleteVmSnapshot.");
+    }
+}
+
+pub(crate) async fn check_and_cleanup_vms(
+    repository: &VmRepository,
+    limits: &CreateVmLimits,
+    hypervisor: Arc<dyn Hypervisor>,
+    event_bus_tx: mpsc::Sender<VmEventWrapper>,
+    healthcheck_cancel_bus: &broadcast::Sender<Uuid>,
+    vms: Vec<VmRecord>,
+) {
+    for vm in vms {
+        if let Some(pid) = vm.status.process_id {
+            let pid_obj = Pid::from_raw(pid as i32);
+            let process_exists = nix::sys::signal::kill(pid_obj, None).is_ok();
+
+            if process_exists {
+                info!("VmDispatcher (Sanity Check): Found running VM {} (PID: {}) from previous session. Starting health monitor.", vm.vm_id, pid);
+                limits.startup.set_running(
+                    &WorkloadRef::new(WorkloadKind::Vm, vm.vm_id.to_string()),
+                    vm.status.state == VmState::Running,
+                );
+                let cancel_bus = healthcheck_cancel_bus.subscribe();
+                worker::start_healthcheck_monitor(
+                    vm.vm_id.to_string(),
+                    hypervisor.clone(),
+                    event_bus_tx.clone(),
+                    cancel_bus,
+                );
+            } else if vm.config.startup.as_ref().is_some_and(|s| s.auto_start) {
+                info!("VmDispatcher (Sanity Check): Hypervisor of VM {} (PID: {}) is gone, starting it again.", vm.vm_id, pid);
+                tokio::spawn(worker::recover_vm(
+                    vm,
+                    repository.clone(),
+                    limits.startup.clone(),
+                    limits.secrets.clone(),
+                    hypervisor.clone(),
+                    event_bus_tx.clone(),
+                    healthcheck_cancel_bus.subscribe(),
+                ));
+            } else {
+                warn!("VmDispatcher (Sanity Check): Found VM {} in DB with PID {}, but process does not exist. Cleaning up.", vm.vm_id, pid);
+                let (resp_tx, resp_rx) = oneshot::channel();
+                let req = DeleteVmRequest {
+                    vm_id: vm.vm_id.to_string(),
+                };
+                let vm_id_for_log = vm.vm_id;
+
+                handle_delete_vm_command(
+                    repository,
+                    limits,
+                    healthcheck_cancel_bus,
+                    req,
+                    resp_tx,
+                    hypervisor.clone(),
+                    event_bus_tx.clone(),
+                )
+                .await;
+
+                match resp_rx.await {
+                    Ok(Ok(_)) => info!("VmDispatcher (Sanity Check): Successfully cleaned up zombie VM {vm_id_for_log}."),
+                    Ok(Err(status)) => error!("VmDispatcher (Sanity Check): Failed to clean up zombie VM {vm_id_for_log}: {status}"),
+                    Err(_) => error!("VmDispatcher (Sanity Check): Cleanup task for zombie VM {vm_id_for_log} did not return a response."),
+                }
+            }
+        }
+    }
+}
+
+pub(crate) async fn perform_startup_sanity_check(
+    repository: &VmRepository,
+    limits: &CreateVmLimits,
+    hypervisor: Arc<dyn Hypervisor>,
+    event_bus_tx: mpsc::Sender<VmEventWrapper>,
+    healthcheck_cancel_bus: &broadcast::Sender<Uuid>,
+) {
+    info!("VmDispatcher: Running initial sanity check...");
+    match repository.list_all_vms().await {
+        Ok(vms) => {
+            for vm in &vms {
+                limits.admission.restore(
+                    WorkloadKind::Vm,
+                    &vm.vm_id.to_string(),
+                    vm_resources(&vm.config),
+                );
+                // Dependencies were checked when the VM was created.
+                limits.startup.restore(
+                    WorkloadRef::new(WorkloadKind::Vm, vm.vm_id.to_string()),
+                    startup_dependencies(&vm.config).unwrap_or_default(),
+                );
+            }
+            device_manager::reconcile(repository, &vms).await;
             if vms.is_empty() {
                 info!("VmDispatcher (Sanity Check): No VMs found in persistence, check complete.");
             } else {
@@ -860,6 +2878,7 @@ pub(crate) async fn perform_startup_sanity_check(
                 );
                 check_and_cleanup_vms(
                     repository,
+                    limits,
                     hypervisor,
                     event_bus_tx,
                     healthcheck_cancel_bus,
@@ -0,0 +1,181 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    guest_agent::GuestAgent,
+    persistence::{repository::VmRepository, VmRecord},
+    vmm::{Hypervisor, VmmError},
+    worker, VmEventWrapper,
+};
+use feos_proto::vm_service::{
+    disk_config, DiskConfig, DrainPolicy, GetVmRequest, ShutdownVmRequest, VmState,
+};
+use feos_utils::host::admission::WorkloadKind;
+use feos_utils::host::maintenance::{DrainEvent, DrainJob, DrainOutcome, DrainedWorkload};
+use log::{error, info, warn};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+
+const POWER_OFF_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Shuts down all running and paused VMs according to their drain policy,
+/// concurrently, and reports each of them on the job.
+pub(crate) async fn drain_vms(
+    job: DrainJob,
+    repository: VmRepository,
+    hypervisor: Arc<dyn Hypervisor>,
+    event_bus_tx: mpsc::Sender<VmEventWrapper>,
+) {
+    let records = match repository.list_all_vms().await {
+        Ok(records) => records,
+        Err(e) => {
+            error!("VmDrain: Failed to list VMs to drain: {e}");
+            return;
+        }
+    };
+    let vms: Vec<VmRecord> = records
+        .into_iter()
+        .filter(|record| matches!(record.status.state, VmState::Running | VmState::Paused))
+        .collect();
+    info!("VmDrain: Draining {} VMs.", vms.len());
+    if job
+        .events
+        .send(DrainEvent::Planned(vms.len()))
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    let mut drains = JoinSet::new();
+    for record in vms {
+        drains.spawn(drain_vm(
+            record,
+            job.grace_period,
+            hypervisor.clone(),
+            event_bus_tx.clone(),
+        ));
+    }
+    while let Some(drained) = drains.join_next().await {
+        match drained {
+            Ok(drained) => {
+                let _ = job.events.send(DrainEvent::Drained(drained)).await;
+            }
+            Err(e) => error!("VmDrain: A drain task failed: {e}"),
+        }
+    }
+}
+
+async fn drain_vm(
+    record: VmRecord,
+    grace_period: Duration,
+    hypervisor: Arc<dyn Hypervisor>,
+    event_bus_tx: mpsc::Sender<VmEventWrapper>,
+) -> DrainedWorkload {
+    let vm_id = record.vm_id.to_string();
+    let policy = record.config.drain_policy();
+    let drained = |outcome, message: String| DrainedWorkload {
+        kind: WorkloadKind::Vm,
+        id: vm_id.clone(),
+        outcome,
+        message,
+    };
+    if policy == DrainPolicy::Keep {
+        return drained(DrainOutcome::Kept, String::new());
+    }
+
+    // A paused guest cannot react to the power button.
+    let graceful = policy != DrainPolicy::Stop && record.status.state == VmState::Running;
+    match stop_vm(&vm_id, graceful, grace_period, hypervisor.as_ref()).await {
+        Ok(outcome) => {
+            let ephemeral_disks: Vec<DiskConfig> = record
+                .config
+                .disks
+                .into_iter()
+                .filter(|disk| matches!(disk.backend, Some(disk_config::Backend::Ephemeral(_))))
+                .collect();
+            worker::finish_shutdown(
+                &vm_id,
+                &ephemeral_disks,
+                &event_bus_tx,
+                "Stopped to drain the host",
+            )
+            .await;
+            drained(outcome, String::new())
+        }
+        Err(e) => {
+            warn!("VmDrain ({vm_id}): Failed to stop VM: {e}");
+            drained(DrainOutcome::Failed, e.to_string())
+        }
+    }
+}
+
+/// Stops a VM. A graceful stop asks the guest to power off first and waits
+/// up to `grace_period` for it.
+pub(crate) async fn stop_vm(
+    vm_id: &str,
+    graceful: bool,
+    grace_period: Duration,
+    hypervisor: &dyn Hypervisor,
+) -> Result<DrainOutcome, VmmError> {
+    let mut outcome = DrainOutcome::Stopped;
+    if graceful {
+        ask_guest_to_power_off(vm_id, hypervisor).await?;
+        if wait_for_power_off(vm_id, grace_period, hypervisor).await {
+            return Ok(outcome);
+        }
+        info!("VmDrain ({vm_id}): Guest did not shut down within {grace_period:?}, stopping it.");
+        outcome = DrainOutcome::ForceStopped;
+    }
+    hypervisor
+        .shutdown_vm(ShutdownVmRequest {
+            vm_id: vm_id.to_string(),
+            graceful: false,
+            grace_period_seconds: None,
+        })
+        .await?;
+    Ok(outcome)
+}
+
+/// Asks the guest agent to power the guest off. Guests without an agent get
+/// the ACPI power button instead.
+async fn ask_guest_to_power_off(vm_id: &str, hypervisor: &dyn Hypervisor) -> Result<(), VmmError> {
+    let shutdown = match GuestAgent::connect(vm_id, hypervisor).await {
+        Ok(mut agent) => agent.shutdown().await,
+        Err(e) => Err(e),
+    };
+    match shutdown {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            info!("VmDrain ({vm_id}): Guest agent did not take the shutdown ({e}), pressing the power button.");
+            hypervisor.power_button_vm(vm_id).await
+        }
+    }
+}
+
+/// Whether the guest powered off within `grace_period`.
+async fn wait_for_power_off(
+    vm_id: &str,
+    grace_period: Duration,
+    hypervisor: &dyn Hypervisor,
+) -> bool {
+    let deadline = Instant::now() + grace_period;
+    loop {
+        let request = GetVmRequest {
+            vm_id: vm_id.to_string(),
+        };
+        match hypervisor.get_vm(request).await {
+            Ok(info) if info.state() == VmState::Running => {}
+            // Without its API the hypervisor is gone along with the guest.
+            Ok(_) | Err(VmmError::VmNotFound(_)) => return true,
+            Err(e) => warn!("VmDrain ({vm_id}): Failed to get VM state: {e}"),
+        }
+        if Instant::now() + POWER_OFF_POLL_INTERVAL > deadline {
+            return false;
+        }
+        tokio::time::sleep(POWER_OFF_POLL_INTERVAL).await;
+    }
+}
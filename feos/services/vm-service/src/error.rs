@@ -2,6 +2,9 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::persistence::PersistenceError;
+use feos_utils::host::admission::AdmissionError;
+use feos_utils::host::maintenance::Cordoned;
+use secret_service::error::SecretServiceError;
 use tonic::Status;
 
 #[derive(Debug, thiserror::Error)]
@@ -23,6 +26,60 @@ pub enum VmServiceError {
 
     #[error("Invalid VM state for operation: {0}")]
     InvalidState(String),
+
+    #[error("Snapshot {0} not found")]
+    SnapshotNotFound(String),
+
+    #[error("Snapshot Error: {0}")]
+    Snapshot(String),
+
+    #[error("Replication Error: {0}")]
+    Replication(String),
+
+    #[error("iSCSI Error: {0}")]
+    Iscsi(String),
+
+    #[error("NVMe-oF Error: {0}")]
+    Nvmeof(String),
+
+    #[error("Storage daemon Error: {0}")]
+    StorageDaemon(String),
+
+    #[error("Scratch disk Error: {0}")]
+    Scratch(String),
+
+    #[error("Disk overlay Error: {0}")]
+    Overlay(String),
+
+    #[error("Cloud-init Error: {0}")]
+    CloudInit(String),
+
+    #[error("GPU Error: {0}")]
+    Gpu(String),
+
+    #[error("Mediated device Error: {0}")]
+    Mdev(String),
+
+    #[error("PCI device Error: {0}")]
+    PciDevice(String),
+
+    #[error("{0}")]
+    GuestAgentUnavailable(String),
+
+    #[error("Guest agent: {}", .0.message())]
+    GuestAgent(Box<Status>),
+
+    #[error("Insufficient memory: {0}")]
+    InsufficientMemory(String),
+
+    #[error("Host overcommit limit reached: {0}")]
+    AdmissionRejected(#[from] AdmissionError),
+
+    #[error("{0}")]
+    Cordoned(#[from] Cordoned),
+
+    #[error("{0}")]
+    Secret(#[from] SecretServiceError),
 }
 
 impl From<VmServiceError> for Status {
@@ -35,6 +92,9 @@ impl From<VmServiceError> for Status {
             {
                 Status::not_found("Record not found in database")
             }
+            VmServiceError::Persistence(PersistenceError::NameTaken(msg)) => {
+                Status::already_exists(msg)
+            }
             VmServiceError::Persistence(_) => Status::internal("A database error occurred"),
             VmServiceError::ImageService(msg) => {
                 Status::unavailable(format!("Image service unavailable: {msg}"))
@@ -42,6 +102,34 @@ impl From<VmServiceError> for Status {
             VmServiceError::InvalidArgument(msg) => Status::invalid_argument(msg),
             VmServiceError::AlreadyExists(msg) => Status::already_exists(msg),
             VmServiceError::InvalidState(msg) => Status::failed_precondition(msg),
+            VmServiceError::SnapshotNotFound(id) => {
+                Status::not_found(format!("Snapshot {id} not found"))
+            }
+            VmServiceError::Snapshot(msg) => Status::internal(msg),
+            VmServiceError::Replication(msg) => Status::internal(msg),
+            VmServiceError::StorageDaemon(msg) => Status::internal(msg),
+            VmServiceError::Scratch(msg) => Status::internal(msg),
+            VmServiceError::Overlay(msg) => Status::internal(msg),
+            VmServiceError::CloudInit(msg) => Status::internal(msg),
+            VmServiceError::Gpu(msg) => Status::internal(msg),
+            VmServiceError::Mdev(msg) => Status::internal(msg),
+            VmServiceError::PciDevice(msg) => Status::internal(msg),
+            VmServiceError::GuestAgentUnavailable(msg) => Status::unavailable(msg),
+            VmServiceError::GuestAgent(status) => {
+                Status::new(status.code(), format!("Guest agent: {}", status.message()))
+            }
+            VmServiceError::InsufficientMemory(msg) => Status::resource_exhausted(msg),
+            VmServiceError::AdmissionRejected(e) => {
+                Status::resource_exhausted(format!("Host overcommit limit reached: {e}"))
+            }
+            VmServiceError::Cordoned(e) => Status::unavailable(e.to_string()),
+            VmServiceError::Secret(e) => e.into(),
+            VmServiceError::Iscsi(msg) => {
+                Status::unavailable(format!("iSCSI target unavailable: {msg}"))
+            }
+            VmServiceError::Nvmeof(msg) => {
+                Status::unavailable(format!("NVMe-oF subsystem unavailable: {msg}"))
+            }
         }
     }
 }
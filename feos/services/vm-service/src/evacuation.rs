@@ -0,0 +1,259 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    dispatcher_handlers::{ephemeral_disk_bytes, vm_resources},
+    persistence::VmRecord,
+};
+use feos_proto::vm_service::{
+    disk_config, net_config, DrainPolicy, EvacuationAction, EvacuationCapacity, EvacuationStep,
+    EvacuationTarget, MigrationBlocker, MigrationBlockerKind, VmConfig, VmState,
+};
+
+/// Plans what happens to each VM when the host is evacuated for `target`.
+/// Returns the steps in the order of `records` and the capacity all VMs
+/// leaving the host need elsewhere.
+pub fn plan_evacuation(
+    records: &[VmRecord],
+    target: EvacuationTarget,
+) -> (Vec<EvacuationStep>, EvacuationCapacity) {
+    let mut required = EvacuationCapacity::default();
+    let steps = records
+        .iter()
+        .map(|record| {
+            let step = plan_step(record, target);
+            if let Some(capacity) = &step.capacity {
+                add_capacity(&mut required, capacity);
+            }
+            step
+        })
+        .collect();
+    (steps, required)
+}
+
+fn plan_step(record: &VmRecord, target: EvacuationTarget) -> EvacuationStep {
+    let state = record.status.state;
+    let active = matches!(state, VmState::Running | VmState::Paused);
+    let blockers = if active {
+        migration_blockers(&record.config)
+    } else {
+        Vec::new()
+    };
+    let stays = target != EvacuationTarget::EmptyHost
+        && (!active || record.config.drain_policy() == DrainPolicy::Keep);
+    let action = match (stays, active) {
+        (true, _) => EvacuationAction::Keep,
+        (false, false) => EvacuationAction::Recreate,
+        (false, true) if blockers.is_empty() => EvacuationAction::LiveMigrate,
+        (false, true) => EvacuationAction::Stop,
+    };
+    EvacuationStep {
+        vm_id: record.vm_id.to_string(),
+        namespace: record.namespace.clone(),
+        state: state as i32,
+        action: action as i32,
+        blockers,
+        capacity: (!stays).then(|| required_capacity(&record.config)),
+        host_paths: host_paths(&record.config),
+    }
+}
+
+/// The devices of a VM that tie its guest to this host.
+fn migration_blockers(config: &VmConfig) -> Vec<MigrationBlocker> {
+    let blocker = |kind: MigrationBlockerKind, device: &str| MigrationBlocker {
+        kind: kind as i32,
+        device: device.to_string(),
+    };
+    let mut blockers = Vec::new();
+    for disk in &config.disks {
+        match &disk.backend {
+            Some(disk_config::Backend::VfioPci(_)) => {
+                blockers.push(blocker(MigrationBlockerKind::PciDevice, &disk.device_id))
+            }
+            Some(disk_config::Backend::Ephemeral(_)) => blockers.push(blocker(
+                MigrationBlockerKind::EphemeralDisk,
+                &disk.device_id,
+            )),
+            _ => {}
+        }
+    }
+    for net in &config.net {
+        if let Some(net_config::Backend::VfioPci(_)) = &net.backend {
+            blockers.push(blocker(MigrationBlockerKind::PciDevice, &net.device_id));
+        }
+    }
+    for gpu in &config.gpus {
+        blockers.push(blocker(
+            MigrationBlockerKind::GpuPartition,
+            &gpu.pci_address,
+        ));
+    }
+    for mdev in &config.mdevs {
+        blockers.push(blocker(MigrationBlockerKind::Mdev, &mdev.uuid));
+    }
+    if let Some(port) = &config.serial_port {
+        blockers.push(blocker(MigrationBlockerKind::SerialPort, &port.path));
+    }
+    blockers
+}
+
+fn required_capacity(config: &VmConfig) -> EvacuationCapacity {
+    let resources = vm_resources(config);
+    let hugepages = config
+        .memory
+        .as_ref()
+        .is_some_and(|memory| memory.hugepages);
+    let mut capacity = EvacuationCapacity {
+        vcpus: (resources.milli_cpus / 1000) as u32,
+        memory_bytes: resources.memory_bytes,
+        hugepage_memory_bytes: if hugepages { resources.memory_bytes } else { 0 },
+        ephemeral_disk_bytes: ephemeral_disk_bytes(&config.disks),
+        mdevs: config.mdevs.len() as u32,
+        ..Default::default()
+    };
+    for gpu in &config.gpus {
+        *capacity
+            .gpu_partitions
+            .entry(gpu.profile.clone())
+            .or_default() += 1;
+    }
+    capacity
+}
+
+fn add_capacity(total: &mut EvacuationCapacity, capacity: &EvacuationCapacity) {
+    total.vcpus += capacity.vcpus;
+    total.memory_bytes += capacity.memory_bytes;
+    total.hugepage_memory_bytes += capacity.hugepage_memory_bytes;
+    total.ephemeral_disk_bytes += capacity.ephemeral_disk_bytes;
+    total.mdevs += capacity.mdevs;
+    for (profile, count) in &capacity.gpu_partitions {
+        *total.gpu_partitions.entry(profile.clone()).or_default() += count;
+    }
+}
+
+/// Disk images, overlay bases and vhost-user sockets, which are addressed by
+/// their path on the host. iSCSI, NVMe-oF and Ceph disks are reached over the
+/// network.
+fn host_paths(config: &VmConfig) -> Vec<String> {
+    let disks = config.disks.iter().filter_map(|disk| match &disk.backend {
+        Some(disk_config::Backend::Path(path)) => Some(path.clone()),
+        Some(disk_config::Backend::Overlay(overlay)) => Some(overlay.base_path.clone()),
+        Some(disk_config::Backend::VhostUserBlk(vhost_user)) => {
+            Some(vhost_user.socket_path.clone())
+        }
+        _ => None,
+    });
+    let nets = config.net.iter().filter_map(|net| match &net.backend {
+        Some(net_config::Backend::VhostUser(vhost_user)) => Some(vhost_user.socket_path.clone()),
+        _ => None,
+    });
+    disks.chain(nets).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence::VmStatus;
+    use feos_proto::vm_service::{
+        CpuConfig, DiskConfig, EphemeralDiskConfig, GpuConfig, MemoryConfig,
+    };
+    use uuid::Uuid;
+
+    fn record(state: VmState, config: VmConfig) -> VmRecord {
+        VmRecord {
+            vm_id: Uuid::new_v4(),
+            image_uuid: Uuid::nil(),
+            status: VmStatus {
+                state,
+                last_msg: String::new(),
+                process_id: None,
+            },
+            config: VmConfig {
+                cpus: Some(CpuConfig {
+                    boot_vcpus: 2,
+                    max_vcpus: 4,
+                }),
+                memory: Some(MemoryConfig {
+                    size_mib: 1024,
+                    ..Default::default()
+                }),
+                ..config
+            },
+            namespace: "default".to_string(),
+            name: None,
+        }
+    }
+
+    #[test]
+    fn drain_plan_follows_state_devices_and_policy() {
+        let records = [
+            record(
+                VmState::Running,
+                VmConfig {
+                    disks: vec![DiskConfig {
+                        device_id: "data".to_string(),
+                        backend: Some(disk_config::Backend::Path("/srv/data.img".to_string())),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                },
+            ),
+            record(
+                VmState::Paused,
+                VmConfig {
+                    disks: vec![DiskConfig {
+                        device_id: "scratch".to_string(),
+                        backend: Some(disk_config::Backend::Ephemeral(EphemeralDiskConfig {
+                            size_bytes: 1 << 30,
+                        })),
+                        ..Default::default()
+                    }],
+                    gpus: vec![GpuConfig {
+                        profile: "NVIDIA A100-4C".to_string(),
+                        pci_address: "0000:41:00.4".to_string(),
+                    }],
+                    ..Default::default()
+                },
+            ),
+            record(
+                VmState::Running,
+                VmConfig {
+                    drain_policy: DrainPolicy::Keep as i32,
+                    ..Default::default()
+                },
+            ),
+            record(VmState::Stopped, VmConfig::default()),
+        ];
+
+        let (steps, required) = plan_evacuation(&records, EvacuationTarget::DrainHost);
+        let actions: Vec<_> = steps.iter().map(|step| step.action()).collect();
+        assert_eq!(
+            actions,
+            [
+                EvacuationAction::LiveMigrate,
+                EvacuationAction::Stop,
+                EvacuationAction::Keep,
+                EvacuationAction::Keep,
+            ]
+        );
+        assert_eq!(steps[0].host_paths, ["/srv/data.img"]);
+        let kinds: Vec<_> = steps[1].blockers.iter().map(|b| b.kind()).collect();
+        assert_eq!(
+            kinds,
+            [
+                MigrationBlockerKind::EphemeralDisk,
+                MigrationBlockerKind::GpuPartition
+            ]
+        );
+        assert!(steps[2].capacity.is_none());
+        assert_eq!(required.vcpus, 4);
+        assert_eq!(required.memory_bytes, 2 << 30);
+        assert_eq!(required.ephemeral_disk_bytes, 1 << 30);
+        assert_eq!(required.gpu_partitions["NVIDIA A100-4C"], 1);
+
+        let (steps, required) = plan_evacuation(&records, EvacuationTarget::EmptyHost);
+        assert_eq!(steps[2].action(), EvacuationAction::LiveMigrate);
+        assert_eq!(steps[3].action(), EvacuationAction::Recreate);
+        assert_eq!(required.vcpus, 8);
+    }
+}
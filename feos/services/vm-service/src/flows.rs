@@ -0,0 +1,284 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! The connections of guests, streamed by StreamVmFlows. The host tracks
+//! the connections it routes, and each is put down to the NIC whose address
+//! it was set up from or to. The addresses of a NIC are those the host has
+//! seen its MAC use, and the one it forms with SLAAC.
+
+use crate::error::VmServiceError;
+use crate::persistence::{repository::VmRepository, VmRecord};
+use crate::slaac;
+use feos_proto::vm_service::{VmFlow, VmFlows, VmFlowsSample};
+use feos_utils::network::conntrack::{self, Connection, Tuple};
+use feos_utils::network::neighbours::neighbour_addresses;
+use feos_utils::network::router_advert::first_subnet;
+use feos_utils::network::utils::delegated_prefix;
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv6Addr};
+use tokio::sync::mpsc;
+use tokio::time::{interval, Duration, MissedTickBehavior};
+use tonic::Status;
+use uuid::Uuid;
+
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The VM and NIC each guest address belongs to, by index into the records.
+fn guest_addresses(
+    records: &[VmRecord],
+    neighbours: &HashMap<String, Vec<IpAddr>>,
+    slaac_prefix: Option<Ipv6Addr>,
+) -> HashMap<IpAddr, (usize, String)> {
+    let mut addresses = HashMap::new();
+    for (index, record) in records.iter().enumerate() {
+        for nic in record.config.net.iter() {
+            let mut nic_addresses = neighbours
+                .get(&nic.mac_address.to_lowercase())
+                .cloned()
+                .unwrap_or_default();
+            if let Some(address) = slaac_prefix
+                .filter(|_| slaac::uses_slaac(nic))
+                .and_then(|prefix| slaac::guest_address(prefix, nic))
+            {
+                nic_addresses.push(IpAddr::V6(address));
+            }
+            for address in nic_addresses {
+                addresses.insert(address, (index, nic.device_id.clone()));
+            }
+        }
+    }
+    addresses
+}
+
+/// The flow of a connection from the view of the guest at the source of
+/// `from_guest`.
+fn flow(
+    connection: &Connection,
+    device_id: &str,
+    from_guest: &Tuple,
+    to_guest: &Tuple,
+    inbound: bool,
+) -> VmFlow {
+    VmFlow {
+        device_id: device_id.to_string(),
+        protocol: connection.protocol.clone(),
+        state: connection.state.clone(),
+        guest_address: from_guest.src.to_string(),
+        guest_port: from_guest.sport.into(),
+        remote_address: from_guest.dst.to_string(),
+        remote_port: from_guest.dport.into(),
+        inbound,
+        tx_bytes: from_guest.bytes,
+        tx_packets: from_guest.packets,
+        rx_bytes: to_guest.bytes,
+        rx_packets: to_guest.packets,
+    }
+}
+
+/// The flows of the VMs, with the connections put down to the guests at
+/// either end. Connections to a guest behind NAT are found by the address
+/// it answers from.
+pub fn vm_flows(
+    records: &[VmRecord],
+    neighbours: &HashMap<String, Vec<IpAddr>>,
+    slaac_prefix: Option<Ipv6Addr>,
+    connections: &[Connection],
+) -> Vec<VmFlows> {
+    let addresses = guest_addresses(records, neighbours, slaac_prefix);
+    let mut flows: Vec<Vec<VmFlow>> = vec![Vec::new(); records.len()];
+    for connection in connections {
+        let original = &connection.original;
+        let reply = &connection.reply;
+        let source = addresses.get(&original.src);
+        if let Some((index, device_id)) = source {
+            flows[*index].push(flow(connection, device_id, original, reply, false));
+        }
+        let destination = addresses
+            .get(&original.dst)
+            .or_else(|| addresses.get(&reply.src));
+        if let Some((index, device_id)) = destination.filter(|_| destination != source) {
+            let answer = Tuple {
+                dst: original.src,
+                dport: original.sport,
+                ..reply.clone()
+            };
+            flows[*index].push(flow(connection, device_id, &answer, original, true));
+        }
+    }
+    records
+        .iter()
+        .zip(flows)
+        .map(|(record, mut flows)| {
+            flows.sort_by_key(|flow| std::cmp::Reverse(flow.tx_bytes + flow.rx_bytes));
+            VmFlows {
+                vm_id: record.vm_id.to_string(),
+                namespace: record.namespace.clone(),
+                flows,
+            }
+        })
+        .collect()
+}
+
+async fn sample(
+    repository: &VmRepository,
+    vm_ids: &HashSet<Uuid>,
+) -> Result<VmFlowsSample, VmServiceError> {
+    let records: Vec<VmRecord> = repository
+        .list_all_vms()
+        .await?
+        .into_iter()
+        .filter(|record| vm_ids.is_empty() || vm_ids.contains(&record.vm_id))
+        .collect();
+    let connections = conntrack::connections().await.map_err(|e| {
+        VmServiceError::InvalidState(format!("Cannot read the tracked connections: {e}"))
+    })?;
+    let neighbours = neighbour_addresses().await.unwrap_or_else(|e| {
+        warn!("Flows: Failed to read the neighbour table for guest addresses: {e}");
+        HashMap::new()
+    });
+    let slaac_prefix = delegated_prefix()
+        .await
+        .map(|(prefix, _)| first_subnet(prefix));
+    Ok(VmFlowsSample {
+        vms: vm_flows(&records, &neighbours, slaac_prefix, &connections),
+        node_id: String::new(),
+    })
+}
+
+/// Sends a sample of the flows of the VMs every `period`, until the client
+/// goes away or sampling fails.
+pub async fn stream(
+    repository: VmRepository,
+    vm_ids: HashSet<Uuid>,
+    period: Duration,
+    stream_tx: mpsc::Sender<Result<VmFlowsSample, Status>>,
+) {
+    if let Err(e) = conntrack::enable_accounting().await {
+        warn!("Flows: Failed to enable accounting of tracked connections: {e}");
+    }
+    let mut ticks = interval(period);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {}
+            _ = stream_tx.closed() => break,
+        }
+        let result = sample(&repository, &vm_ids).await.map_err(Status::from);
+        let failed = result.is_err();
+        if stream_tx.send(result).await.is_err() || failed {
+            break;
+        }
+    }
+    info!("Flows: Stream ended.");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence::VmStatus;
+    use feos_proto::vm_service::{net_config, NetConfig, TapConfig, VmConfig, VmState};
+
+    fn record(mac_address: &str, slaac: bool) -> VmRecord {
+        VmRecord {
+            vm_id: Uuid::new_v4(),
+            namespace: "default".to_string(),
+            name: None,
+            image_uuid: Uuid::nil(),
+            status: VmStatus {
+                state: VmState::Running,
+                last_msg: String::new(),
+                process_id: None,
+            },
+            config: VmConfig {
+                net: vec![NetConfig {
+                    device_id: "net0".to_string(),
+                    mac_address: mac_address.to_string(),
+                    backend: Some(net_config::Backend::Tap(TapConfig {
+                        tap_name: "tap0".to_string(),
+                        slaac,
+                    })),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        }
+    }
+
+    fn tuple(src: &str, dst: &str, sport: u16, dport: u16, bytes: u64) -> Tuple {
+        Tuple {
+            src: src.parse().unwrap(),
+            dst: dst.parse().unwrap(),
+            sport,
+            dport,
+            packets: 1,
+            bytes,
+        }
+    }
+
+    fn connection(original: Tuple, reply: Tuple) -> Connection {
+        Connection {
+            protocol: "tcp".to_string(),
+            state: "ESTABLISHED".to_string(),
+            original,
+            reply,
+        }
+    }
+
+    #[test]
+    fn connections_are_put_down_to_the_guests_at_either_end() {
+        let records = [
+            record("52:54:00:00:00:01", false),
+            record("52:54:00:00:00:02", true),
+        ];
+        let neighbours = HashMap::from([(
+            "52:54:00:00:00:01".to_string(),
+            vec!["10.0.0.2".parse().unwrap()],
+        )]);
+        let prefix = "2001:db8:12::".parse().unwrap();
+        let second_guest = "2001:db8:12:0:5054:ff:fe00:2";
+        let connections = [
+            // The first guest downloads from outside, through NAT.
+            connection(
+                tuple("10.0.0.2", "192.0.2.1", 40000, 443, 100),
+                tuple("192.0.2.1", "198.51.100.1", 443, 40000, 5000),
+            ),
+            // Outside connects to the second guest.
+            connection(
+                tuple("2001:db8::1", second_guest, 50000, 22, 300),
+                tuple(second_guest, "2001:db8::1", 22, 50000, 200),
+            ),
+            // A port of the host is forwarded to the first guest.
+            connection(
+                tuple("192.0.2.1", "198.51.100.1", 50000, 8080, 10),
+                tuple("10.0.0.2", "192.0.2.1", 80, 50000, 20),
+            ),
+            // The host itself.
+            connection(
+                tuple("198.51.100.1", "192.0.2.1", 50001, 443, 1),
+                tuple("192.0.2.1", "198.51.100.1", 443, 50001, 1),
+            ),
+        ];
+
+        let flows = vm_flows(&records, &neighbours, Some(prefix), &connections);
+        assert_eq!(flows.len(), 2);
+        let first = &flows[0].flows;
+        assert_eq!(first.len(), 2);
+        assert_eq!(
+            (first[0].guest_port, first[0].remote_port, first[0].inbound),
+            (40000, 443, false)
+        );
+        assert_eq!((first[0].tx_bytes, first[0].rx_bytes), (100, 5000));
+        assert_eq!(first[1].guest_address, "10.0.0.2");
+        assert_eq!(first[1].remote_address, "192.0.2.1");
+        assert_eq!((first[1].guest_port, first[1].remote_port), (80, 50000));
+        assert_eq!((first[1].tx_bytes, first[1].rx_bytes), (20, 10));
+        assert!(first[1].inbound);
+
+        let second = &flows[1].flows;
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].guest_address, second_guest);
+        assert_eq!(second[0].remote_address, "2001:db8::1");
+        assert_eq!((second[0].tx_bytes, second[0].rx_bytes), (200, 300));
+    }
+}
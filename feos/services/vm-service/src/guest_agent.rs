@@ -0,0 +1,254 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! The host side of the FeOS guest agent. `feos-guest-agent` serves the
+//! GuestAgentService inside VMs on a vsock port, which the host reaches
+//! through the hybrid vsock socket of the VM.
+
+use crate::{error::VmServiceError, vmm::Hypervisor, vsock};
+use feos_proto::{
+    guest_agent::{
+        self, guest_agent_service_client::GuestAgentServiceClient, write_file_request, ExecRequest,
+        FileChunk, FileHeader, GetInfoRequest, ReadFileRequest, ShutdownRequest, WriteFileRequest,
+        GUEST_AGENT_PORT,
+    },
+    vm_service::{
+        push_guest_file_request, ExecInGuestRequest, ExecInGuestResponse, GetGuestInfoResponse,
+        GuestInterface, NetConfig, PushGuestFileRequest, PushGuestFileStart,
+    },
+};
+use hyper_util::rt::TokioIo;
+use std::error::Error;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+use tonic::{
+    transport::{Channel, Endpoint, Uri},
+    Status, Streaming,
+};
+use tower::service_fn;
+
+/// A guest that does not accept the connection by then has no agent running.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A connection to the guest agent of a VM.
+pub struct GuestAgent {
+    client: GuestAgentServiceClient<Channel>,
+}
+
+fn unavailable(vm_id: &str, reason: impl std::fmt::Display) -> VmServiceError {
+    VmServiceError::GuestAgentUnavailable(format!(
+        "The guest agent of VM {vm_id} is not reachable ({reason}), is feos-guest-agent running in the guest?"
+    ))
+}
+
+/// An error the agent answered with. It keeps its code, so e.g. a missing
+/// file is NotFound to the client too.
+pub(crate) fn agent_error(status: Status) -> VmServiceError {
+    VmServiceError::GuestAgent(Box::new(status))
+}
+
+impl GuestAgent {
+    pub async fn connect(vm_id: &str, hypervisor: &dyn Hypervisor) -> Result<Self, VmServiceError> {
+        let socket_path = hypervisor.get_vsock_socket_path(vm_id).await?;
+        let endpoint = Endpoint::try_from("http://[::1]:50051").unwrap();
+        let connect = endpoint.connect_with_connector(service_fn(move |_: Uri| {
+            let socket_path = socket_path.clone();
+            async move {
+                vsock::connect(&socket_path, GUEST_AGENT_PORT)
+                    .await
+                    .map(TokioIo::new)
+            }
+        }));
+        let channel = match tokio::time::timeout(CONNECT_TIMEOUT, connect).await {
+            Ok(Ok(channel)) => channel,
+            Ok(Err(e)) => match e.source() {
+                Some(source) => return Err(unavailable(vm_id, source)),
+                None => return Err(unavailable(vm_id, e)),
+            },
+            Err(_) => return Err(unavailable(vm_id, "timed out")),
+        };
+        Ok(Self {
+            client: GuestAgentServiceClient::new(channel),
+        })
+    }
+
+    pub async fn exec(
+        &mut self,
+        req: ExecInGuestRequest,
+    ) -> Result<ExecInGuestResponse, VmServiceError> {
+        let response = self
+            .client
+            .exec(ExecRequest {
+                command: req.command,
+                stdin: req.stdin,
+                env: req.env,
+                working_dir: req.working_dir,
+                timeout_seconds: req.timeout_seconds,
+            })
+            .await
+            .map_err(agent_error)?
+            .into_inner();
+        Ok(ExecInGuestResponse {
+            exit_code: response.exit_code,
+            stdout: response.stdout,
+            stderr: response.stderr,
+            timed_out: response.timed_out,
+        })
+    }
+
+    /// What the agent reports, with the interfaces matched to the NICs of
+    /// the VM by their MAC address.
+    pub async fn info(
+        &mut self,
+        nics: &[NetConfig],
+    ) -> Result<GetGuestInfoResponse, VmServiceError> {
+        let info = self
+            .client
+            .get_info(GetInfoRequest {})
+            .await
+            .map_err(agent_error)?
+            .into_inner();
+        Ok(GetGuestInfoResponse {
+            agent_version: info.version,
+            hostname: info.hostname,
+            interfaces: info
+                .interfaces
+                .into_iter()
+                .map(|interface| guest_interface(interface, nics))
+                .collect(),
+        })
+    }
+
+    /// Writes the data messages of `input` to a file in the guest. The agent
+    /// replaces the file only once all of it arrived, so a client that fails
+    /// midway leaves the guest's file as it was.
+    pub async fn write_file(
+        &mut self,
+        start: PushGuestFileStart,
+        mut input: impl Stream<Item = Result<PushGuestFileRequest, Status>> + Unpin,
+    ) -> Result<u64, VmServiceError> {
+        let (tx, rx) = mpsc::channel(4);
+        let header = WriteFileRequest {
+            payload: Some(write_file_request::Payload::Header(FileHeader {
+                path: start.path,
+                mode: start.mode,
+                create_parents: start.create_parents,
+            })),
+        };
+        // The channel has room for it, the call below takes it from there.
+        let _ = tx.send(header).await;
+        let call = self.client.write_file(ReceiverStream::new(rx));
+        tokio::pin!(call);
+
+        let forward = async move {
+            while let Some(msg) = input.next().await {
+                let msg = msg.map_err(|status| {
+                    VmServiceError::InvalidArgument(format!(
+                        "Failed to receive the file: {}",
+                        status.message()
+                    ))
+                })?;
+                let Some(push_guest_file_request::Payload::Data(data)) = msg.payload else {
+                    return Err(VmServiceError::InvalidArgument(
+                        "Only the first message may be a Start message.".to_string(),
+                    ));
+                };
+                let chunk = WriteFileRequest {
+                    payload: Some(write_file_request::Payload::Data(data)),
+                };
+                if tx.send(chunk).await.is_err() {
+                    break;
+                }
+            }
+            Ok(())
+        };
+
+        // Returning early drops the call, which aborts the transfer in the
+        // guest rather than ending it as if the file were complete.
+        tokio::select! {
+            response = &mut call => {
+                return response
+                    .map(|response| response.into_inner().bytes_written)
+                    .map_err(agent_error);
+            }
+            forwarded = forward => forwarded?,
+        }
+        call.await
+            .map(|response| response.into_inner().bytes_written)
+            .map_err(agent_error)
+    }
+
+    pub async fn read_file(
+        &mut self,
+        path: String,
+    ) -> Result<Streaming<FileChunk>, VmServiceError> {
+        self.client
+            .read_file(ReadFileRequest { path })
+            .await
+            .map(|response| response.into_inner())
+            .map_err(agent_error)
+    }
+
+    /// Asks the guest to power off. The guest is still up when this returns.
+    pub async fn shutdown(&mut self) -> Result<(), VmServiceError> {
+        self.client
+            .shutdown(ShutdownRequest { reboot: false })
+            .await
+            .map(|_| ())
+            .map_err(agent_error)
+    }
+}
+
+fn guest_interface(interface: guest_agent::GuestInterface, nics: &[NetConfig]) -> GuestInterface {
+    let device_id = nics
+        .iter()
+        .find(|nic| {
+            !interface.mac_address.is_empty()
+                && nic.mac_address.eq_ignore_ascii_case(&interface.mac_address)
+        })
+        .map(|nic| nic.device_id.clone())
+        .unwrap_or_default();
+    GuestInterface {
+        name: interface.name,
+        mac_address: interface.mac_address,
+        ip_addresses: interface.ip_addresses,
+        device_id,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guest_interfaces_are_matched_to_nics_by_mac_address() {
+        let nics = [
+            NetConfig {
+                device_id: "net0".to_string(),
+                mac_address: "52:54:00:AB:CD:01".to_string(),
+                ..Default::default()
+            },
+            NetConfig {
+                device_id: "net1".to_string(),
+                ..Default::default()
+            },
+        ];
+        let interface = |name: &str, mac: &str| guest_agent::GuestInterface {
+            name: name.to_string(),
+            mac_address: mac.to_string(),
+            ip_addresses: vec!["10.0.0.2".to_string()],
+        };
+
+        let eth0 = guest_interface(interface("eth0", "52:54:00:ab:cd:01"), &nics);
+        assert_eq!(eth0.device_id, "net0");
+        assert_eq!(eth0.ip_addresses, ["10.0.0.2"]);
+        // Interfaces of the guest only, and NICs without a configured MAC,
+        // match nothing.
+        assert_eq!(
+            guest_interface(interface("br0", "52:54:00:ff:00:01"), &nics).device_id,
+            ""
+        );
+        assert_eq!(guest_interface(interface("wg0", ""), &nics).device_id, "");
+    }
+}
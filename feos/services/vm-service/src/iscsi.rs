@@ -0,0 +1,305 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::error::VmServiceError;
+use feos_proto::vm_service::IscsiConfig;
+use log::{info, warn};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::process::Command as TokioCommand;
+use tokio::time::{sleep, Duration, Instant};
+
+const DEFAULT_PORT: u16 = 3260;
+const DEVICE_TIMEOUT: Duration = Duration::from_secs(15);
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// iscsiadm exit code for a login to a node that already has a session.
+const ISCSI_ERR_SESS_EXISTS: i32 = 15;
+/// iscsiadm exit code for a logout or delete of a node without a session or
+/// record.
+const ISCSI_ERR_NO_OBJS_FOUND: i32 = 21;
+
+/// Appends the default iSCSI port to a portal without one. IPv6 addresses are
+/// put in brackets, as iscsiadm expects.
+pub fn normalize_portal(portal: &str) -> String {
+    let portal = portal.trim();
+    if let Some(rest) = portal.strip_prefix('[') {
+        if rest.contains("]:") {
+            portal.to_string()
+        } else {
+            format!("{portal}:{DEFAULT_PORT}")
+        }
+    } else if portal.matches(':').count() > 1 {
+        format!("[{portal}]:{DEFAULT_PORT}")
+    } else if portal.contains(':') {
+        portal.to_string()
+    } else {
+        format!("{portal}:{DEFAULT_PORT}")
+    }
+}
+
+pub fn validate(config: &IscsiConfig) -> Result<(), VmServiceError> {
+    if config.portals.iter().all(|portal| portal.trim().is_empty()) {
+        return Err(VmServiceError::InvalidArgument(
+            "At least one iSCSI portal is required".to_string(),
+        ));
+    }
+    let iqn = config.target_iqn.as_str();
+    if !["iqn.", "eui.", "naa."]
+        .iter()
+        .any(|prefix| iqn.starts_with(prefix))
+        || iqn.contains(char::is_whitespace)
+    {
+        return Err(VmServiceError::InvalidArgument(format!(
+            "Invalid iSCSI target name '{iqn}'"
+        )));
+    }
+    if let Some(chap) = &config.chap {
+        if chap.username.is_empty() || chap.password.is_empty() {
+            return Err(VmServiceError::InvalidArgument(
+                "CHAP credentials need a username and a password".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Whether two disks use a session to the same target, which logging out of
+/// one of them would tear down for the other.
+pub fn same_target(a: &IscsiConfig, b: &IscsiConfig) -> bool {
+    a.target_iqn == b.target_iqn
+        && a.portals.iter().any(|portal| {
+            b.portals
+                .iter()
+                .any(|other| normalize_portal(portal) == normalize_portal(other))
+        })
+}
+
+/// Removes the CHAP password, which is kept in the initiator's node database
+/// only.
+pub fn strip_secrets(config: &mut IscsiConfig) {
+    if let Some(chap) = &mut config.chap {
+        chap.password.clear();
+    }
+}
+
+fn portals(config: &IscsiConfig) -> Vec<String> {
+    config
+        .portals
+        .iter()
+        .filter(|portal| !portal.trim().is_empty())
+        .map(|portal| normalize_portal(portal))
+        .collect()
+}
+
+fn by_path(portal: &str, config: &IscsiConfig) -> PathBuf {
+    PathBuf::from(format!(
+        "/dev/disk/by-path/ip-{portal}-iscsi-{}-lun-{}",
+        config.target_iqn, config.lun
+    ))
+}
+
+async fn iscsiadm(args: &[&str], accepted_codes: &[i32]) -> Result<(), VmServiceError> {
+    let output = TokioCommand::new("iscsiadm")
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| VmServiceError::Iscsi(format!("Failed to run iscsiadm: {e}")))?;
+    match output.status.code() {
+        Some(0) => Ok(()),
+        Some(code) if accepted_codes.contains(&code) => Ok(()),
+        _ => Err(VmServiceError::Iscsi(format!(
+            "iscsiadm failed for {}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))),
+    }
+}
+
+async fn update_node(
+    iqn: &str,
+    portal: &str,
+    name: &str,
+    value: &str,
+) -> Result<(), VmServiceError> {
+    iscsiadm(
+        &[
+            "-m", "node", "-T", iqn, "-p", portal, "-o", "update", "-n", name, "-v", value,
+        ],
+        &[],
+    )
+    .await
+}
+
+async fn login_portal(config: &IscsiConfig, portal: &str) -> Result<(), VmServiceError> {
+    let iqn = config.target_iqn.as_str();
+    iscsiadm(&["-m", "node", "-T", iqn, "-p", portal, "-o", "new"], &[]).await?;
+    if let Some(chap) = &config.chap {
+        update_node(iqn, portal, "node.session.auth.authmethod", "CHAP").await?;
+        update_node(iqn, portal, "node.session.auth.username", &chap.username).await?;
+        update_node(iqn, portal, "node.session.auth.password", &chap.password).await?;
+    }
+    iscsiadm(
+        &["-m", "node", "-T", iqn, "-p", portal, "--login"],
+        &[ISCSI_ERR_SESS_EXISTS],
+    )
+    .await
+}
+
+/// The dm-multipath device stacked on top of a SCSI disk, if any.
+async fn multipath_holder(disk: &Path) -> Option<PathBuf> {
+    let name = disk.file_name()?.to_str()?;
+    let mut holders = fs::read_dir(format!("/sys/block/{name}/holders"))
+        .await
+        .ok()?;
+    while let Ok(Some(holder)) = holders.next_entry().await {
+        let dm = holder.path();
+        let uuid = fs::read_to_string(dm.join("dm/uuid"))
+            .await
+            .unwrap_or_default();
+        if uuid.starts_with("mpath-") {
+            let map = fs::read_to_string(dm.join("dm/name")).await.ok()?;
+            return Some(Path::new("/dev/mapper").join(map.trim()));
+        }
+    }
+    None
+}
+
+/// Waits for the LUN to show up behind the given portals and returns the
+/// device to attach. With more than one path, multipathd gets until the
+/// timeout to assemble its map before a single path is used.
+async fn wait_for_device(
+    config: &IscsiConfig,
+    portals: &[String],
+) -> Result<PathBuf, VmServiceError> {
+    let deadline = Instant::now() + DEVICE_TIMEOUT;
+    loop {
+        let mut disks = Vec::new();
+        for portal in portals {
+            if let Ok(disk) = fs::canonicalize(by_path(portal, config)).await {
+                disks.push(disk);
+            }
+        }
+        if let Some(disk) = disks.first() {
+            if let Some(multipath) = multipath_holder(disk).await {
+                return Ok(multipath);
+            }
+            if portals.len() == 1 {
+                return Ok(disk.clone());
+            }
+            if Instant::now() >= deadline {
+                warn!(
+                    "iSCSI: No multipath device for {} LUN {}, using single path {}",
+                    config.target_iqn,
+                    config.lun,
+                    disk.display()
+                );
+                return Ok(disk.clone());
+            }
+        } else if Instant::now() >= deadline {
+            return Err(VmServiceError::Iscsi(format!(
+                "LUN {} of {} did not appear after login",
+                config.lun, config.target_iqn
+            )));
+        }
+        sleep(DEVICE_POLL_INTERVAL).await;
+    }
+}
+
+/// Logs in to the target through all of its portals and returns the host
+/// device of the LUN. Portals that fail are skipped as long as one works.
+pub async fn login(config: &IscsiConfig) -> Result<PathBuf, VmServiceError> {
+    let mut logged_in = Vec::new();
+    let mut last_error = None;
+    for portal in portals(config) {
+        match login_portal(config, &portal).await {
+            Ok(()) => {
+                info!("iSCSI: Logged in to {} at {portal}", config.target_iqn);
+                logged_in.push(portal);
+            }
+            Err(e) => {
+                warn!(
+                    "iSCSI: Login to {} at {portal} failed: {e}",
+                    config.target_iqn
+                );
+                last_error = Some(e);
+            }
+        }
+    }
+    if logged_in.is_empty() {
+        return Err(last_error
+            .unwrap_or_else(|| VmServiceError::Iscsi("No iSCSI portal to log in to".to_string())));
+    }
+    wait_for_device(config, &logged_in).await
+}
+
+/// Logs out of the target and removes its node records, including the CHAP
+/// credentials.
+pub async fn logout(config: &IscsiConfig) {
+    let iqn = config.target_iqn.as_str();
+    for portal in portals(config) {
+        let result = async {
+            iscsiadm(
+                &["-m", "node", "-T", iqn, "-p", &portal, "--logout"],
+                &[ISCSI_ERR_NO_OBJS_FOUND],
+            )
+            .await?;
+            iscsiadm(
+                &["-m", "node", "-T", iqn, "-p", &portal, "-o", "delete"],
+                &[ISCSI_ERR_NO_OBJS_FOUND],
+            )
+            .await
+        }
+        .await;
+        match result {
+            Ok(()) => info!("iSCSI: Logged out of {iqn} at {portal}"),
+            Err(e) => warn!("iSCSI: Logout of {iqn} at {portal} failed: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feos_proto::vm_service::IscsiChapCredentials;
+
+    fn target(portals: &[&str]) -> IscsiConfig {
+        IscsiConfig {
+            portals: portals.iter().map(|p| p.to_string()).collect(),
+            target_iqn: "iqn.2003-01.org.example:disk1".to_string(),
+            lun: 0,
+            chap: None,
+        }
+    }
+
+    #[test]
+    fn portals_get_default_port() {
+        assert_eq!(normalize_portal("10.0.0.1"), "10.0.0.1:3260");
+        assert_eq!(normalize_portal("10.0.0.1:3261"), "10.0.0.1:3261");
+        assert_eq!(normalize_portal("fd00::1"), "[fd00::1]:3260");
+        assert_eq!(normalize_portal("[fd00::1]"), "[fd00::1]:3260");
+        assert_eq!(normalize_portal("[fd00::1]:3261"), "[fd00::1]:3261");
+    }
+
+    #[test]
+    fn targets_share_sessions_through_common_portals() {
+        let a = target(&["10.0.0.1", "10.0.1.1"]);
+        assert!(same_target(&a, &target(&["10.0.1.1:3260"])));
+        assert!(!same_target(&a, &target(&["10.0.2.1"])));
+    }
+
+    #[test]
+    fn chap_credentials_are_validated_and_stripped() {
+        let mut config = target(&["10.0.0.1"]);
+        config.chap = Some(IscsiChapCredentials {
+            username: "feos".to_string(),
+            password: String::new(),
+        });
+        assert!(validate(&config).is_err());
+
+        config.chap.as_mut().unwrap().password = "secret".to_string();
+        assert!(validate(&config).is_ok());
+        strip_secrets(&mut config);
+        assert_eq!(config.chap.unwrap().password, "");
+    }
+}
@@ -3,22 +3,60 @@
 
 use crate::error::VmServiceError;
 use feos_proto::vm_service::{
-    AttachDiskRequest, AttachDiskResponse, AttachNicRequest, AttachNicResponse, CreateVmRequest,
-    CreateVmResponse, DeleteVmRequest, DeleteVmResponse, DetachDiskRequest, DetachDiskResponse,
-    DetachNicRequest, DetachNicResponse, GetVmRequest, ListVmsRequest, ListVmsResponse,
-    PauseVmRequest, PauseVmResponse, PingVmRequest, PingVmResponse, ResumeVmRequest,
-    ResumeVmResponse, ShutdownVmRequest, ShutdownVmResponse, StartVmRequest, StartVmResponse,
-    StreamVmConsoleRequest, StreamVmConsoleResponse, StreamVmEventsRequest, VmEvent, VmInfo,
+    AttachDiskRequest, AttachDiskResponse, AttachNicRequest, AttachNicResponse,
+    AttachPciDeviceRequest, AttachPciDeviceResponse, CreateVmRequest, CreateVmResponse,
+    CreateVmSnapshotRequest, CreateVmSnapshotResponse, DeleteDiskReplicasRequest,
+    DeleteDiskReplicasResponse, DeleteVmRequest, DeleteVmResponse, DeleteVmSnapshotRequest,
+    DeleteVmSnapshotResponse, DetachDiskRequest, DetachDiskResponse, DetachNicRequest,
+    DetachNicResponse, DetachPciDeviceRequest, DetachPciDeviceResponse, ExecInGuestRequest,
+    ExecInGuestResponse, GetGuestInfoRequest, GetGuestInfoResponse, GetVmBootMetricsRequest,
+    GetVmBootMetricsResponse, GetVmRequest, GetVmStatsRequest, GetVmStatsResponse, GuestFileChunk,
+    ListDiskReplicasRequest, ListDiskReplicasResponse, ListHostPciDevicesRequest,
+    ListHostPciDevicesResponse, ListVmEventsRequest, ListVmEventsResponse, ListVmSnapshotsRequest,
+    ListVmSnapshotsResponse, ListVmsRequest, ListVmsResponse, PauseVmRequest, PauseVmResponse,
+    PingVmRequest, PingVmResponse, PlanEvacuationRequest, PlanEvacuationResponse,
+    PortForwardRequest, PortForwardResponse, PullGuestFileRequest, PushGuestFileRequest,
+    PushGuestFileResponse, ReceiveDiskReplicaRequest, ReceiveDiskReplicaResponse,
+    ReplayVmStateJournalRequest, ReplayVmStateJournalResponse, ReplicateVmDisksRequest,
+    ReplicateVmDisksResponse, ResizeVmRequest, ResizeVmResponse, ResumeVmRequest, ResumeVmResponse,
+    RevertVmSnapshotRequest, RevertVmSnapshotResponse, ShutdownVmRequest, ShutdownVmResponse,
+    StartVmRequest, StartVmResponse, StreamVmConsoleRequest, StreamVmConsoleResponse,
+    StreamVmEventsRequest, StreamVmFlowsRequest, StreamVmMetricsRequest, VmEvent, VmFlowsSample,
+    VmInfo, VmMetricsSample,
 };
 use tokio::sync::{mpsc, oneshot};
 use tonic::{Status, Streaming};
 
 pub mod api;
+pub mod boot_metrics;
+pub mod cgroup;
+pub mod cloud_init;
+pub mod console;
+pub mod device_manager;
+pub mod disk_image;
 pub mod dispatcher;
 pub mod dispatcher_handlers;
+pub mod drain;
 pub mod error;
+pub mod evacuation;
+pub mod flows;
+pub mod guest_agent;
+pub mod iscsi;
+pub mod metrics;
+pub mod netboot;
+pub mod nvmeof;
+pub mod overlay;
 pub mod persistence;
+pub mod placement;
+pub mod rbd;
+pub mod replication;
+pub mod scratch;
+pub mod slaac;
+pub mod snapshot;
+pub mod stats;
+pub mod storage_daemon;
 pub mod vmm;
+pub mod vsock;
 pub mod worker;
 
 pub const DEFAULT_VM_DB_URL: &str = "sqlite:/var/lib/feos/vms.db";
@@ -27,6 +65,17 @@ pub const VM_CH_BIN: &str = "cloud-hypervisor";
 pub const CONT_YOUKI_BIN: &str = "youki";
 pub const IMAGE_DIR: &str = "/var/lib/feos/images";
 pub const VM_CONSOLE_DIR: &str = "/tmp/feos/consoles";
+pub const VM_VSOCK_DIR: &str = "/tmp/feos/vsock";
+pub const VM_SNAPSHOT_DIR: &str = "/var/lib/feos/snapshots";
+pub const VM_OVERLAY_DIR: &str = "/var/lib/feos/overlays";
+pub const VM_REPLICATION_DIR: &str = "/var/lib/feos/replication";
+pub const VM_REPLICA_DIR: &str = "/var/lib/feos/replicas";
+pub const VM_EXPORT_DIR: &str = "/tmp/feos/exports";
+pub const VM_SCRATCH_DIR: &str = "/tmp/feos/scratch";
+pub const VM_CLOUD_INIT_DIR: &str = "/tmp/feos/cloud-init";
+pub const VM_CGROUP_DIR: &str = "/sys/fs/cgroup/feos-vms";
+pub const VM_GUEST_CID: i64 = 3;
+pub const DEFAULT_VM_CREATE_CONCURRENCY: usize = 8;
 
 #[derive(Debug, Clone)]
 pub struct VmEventWrapper {
@@ -92,6 +141,102 @@ pub enum Command {
         DetachNicRequest,
         oneshot::Sender<Result<DetachNicResponse, VmServiceError>>,
     ),
+    ListHostPciDevices(
+        ListHostPciDevicesRequest,
+        oneshot::Sender<Result<ListHostPciDevicesResponse, VmServiceError>>,
+    ),
+    AttachPciDevice(
+        AttachPciDeviceRequest,
+        oneshot::Sender<Result<AttachPciDeviceResponse, VmServiceError>>,
+    ),
+    DetachPciDevice(
+        DetachPciDeviceRequest,
+        oneshot::Sender<Result<DetachPciDeviceResponse, VmServiceError>>,
+    ),
+    ResizeVm(
+        ResizeVmRequest,
+        oneshot::Sender<Result<ResizeVmResponse, VmServiceError>>,
+    ),
+    PortForward(
+        Box<Streaming<PortForwardRequest>>,
+        mpsc::Sender<Result<PortForwardResponse, Status>>,
+    ),
+    ExecInGuest(
+        ExecInGuestRequest,
+        oneshot::Sender<Result<ExecInGuestResponse, VmServiceError>>,
+    ),
+    PushGuestFile(
+        Box<Streaming<PushGuestFileRequest>>,
+        oneshot::Sender<Result<PushGuestFileResponse, VmServiceError>>,
+    ),
+    PullGuestFile(
+        PullGuestFileRequest,
+        mpsc::Sender<Result<GuestFileChunk, Status>>,
+    ),
+    GetGuestInfo(
+        GetGuestInfoRequest,
+        oneshot::Sender<Result<GetGuestInfoResponse, VmServiceError>>,
+    ),
+    CreateVmSnapshot(
+        CreateVmSnapshotRequest,
+        oneshot::Sender<Result<CreateVmSnapshotResponse, VmServiceError>>,
+    ),
+    ListVmSnapshots(
+        ListVmSnapshotsRequest,
+        oneshot::Sender<Result<ListVmSnapshotsResponse, VmServiceError>>,
+    ),
+    RevertVmSnapshot(
+        RevertVmSnapshotRequest,
+        oneshot::Sender<Result<RevertVmSnapshotResponse, VmServiceError>>,
+    ),
+    DeleteVmSnapshot(
+        DeleteVmSnapshotRequest,
+        oneshot::Sender<Result<DeleteVmSnapshotResponse, VmServiceError>>,
+    ),
+    GetVmBootMetrics(
+        GetVmBootMetricsRequest,
+        oneshot::Sender<Result<GetVmBootMetricsResponse, VmServiceError>>,
+    ),
+    ListVmEvents(
+        ListVmEventsRequest,
+        oneshot::Sender<Result<ListVmEventsResponse, VmServiceError>>,
+    ),
+    ReplayVmStateJournal(
+        ReplayVmStateJournalRequest,
+        oneshot::Sender<Result<ReplayVmStateJournalResponse, VmServiceError>>,
+    ),
+    PlanEvacuation(
+        PlanEvacuationRequest,
+        oneshot::Sender<Result<PlanEvacuationResponse, VmServiceError>>,
+    ),
+    GetVmStats(
+        GetVmStatsRequest,
+        oneshot::Sender<Result<GetVmStatsResponse, VmServiceError>>,
+    ),
+    StreamVmFlows(
+        StreamVmFlowsRequest,
+        mpsc::Sender<Result<VmFlowsSample, Status>>,
+    ),
+    StreamVmMetrics(
+        StreamVmMetricsRequest,
+        mpsc::Sender<Result<VmMetricsSample, Status>>,
+    ),
+    ReplicateVmDisks(
+        ReplicateVmDisksRequest,
+        oneshot::Sender<Result<ReplicateVmDisksResponse, VmServiceError>>,
+    ),
+    ReceiveDiskReplica(
+        Box<Streaming<ReceiveDiskReplicaRequest>>,
+        oneshot::Sender<Result<ReceiveDiskReplicaResponse, VmServiceError>>,
+    ),
+    ListDiskReplicas(
+        ListDiskReplicasRequest,
+        oneshot::Sender<Result<ListDiskReplicasResponse, VmServiceError>>,
+    ),
+    DeleteDiskReplicas(
+        DeleteDiskReplicasRequest,
+        oneshot::Sender<Result<DeleteDiskReplicasResponse, VmServiceError>>,
+    ),
 }
 
 impl std::fmt::Debug for Command {
@@ -114,6 +259,60 @@ impl std::fmt::Debug for Command {
             Command::DetachDisk(req, _) => f.debug_tuple("DetachDisk").field(req).finish(),
             Command::AttachNic(req, _) => f.debug_tuple("AttachNic").field(req).finish(),
             Command::DetachNic(req, _) => f.debug_tuple("DetachNic").field(req).finish(),
+            Command::ListHostPciDevices(req, _) => {
+                f.debug_tuple("ListHostPciDevices").field(req).finish()
+            }
+            Command::AttachPciDevice(req, _) => {
+                f.debug_tuple("AttachPciDevice").field(req).finish()
+            }
+            Command::DetachPciDevice(req, _) => {
+                f.debug_tuple("DetachPciDevice").field(req).finish()
+            }
+            Command::ResizeVm(req, _) => f.debug_tuple("ResizeVm").field(req).finish(),
+            Command::PortForward(_, _) => f.write_str("PortForward(<gRPC Stream>, <mpsc::Sender>)"),
+            Command::ExecInGuest(req, _) => f.debug_tuple("ExecInGuest").field(req).finish(),
+            Command::PushGuestFile(_, _) => {
+                f.write_str("PushGuestFile(<gRPC Stream>, <oneshot::Sender>)")
+            }
+            Command::PullGuestFile(req, _) => f.debug_tuple("PullGuestFile").field(req).finish(),
+            Command::GetGuestInfo(req, _) => f.debug_tuple("GetGuestInfo").field(req).finish(),
+            Command::CreateVmSnapshot(req, _) => {
+                f.debug_tuple("CreateVmSnapshot").field(req).finish()
+            }
+            Command::ListVmSnapshots(req, _) => {
+                f.debug_tuple("ListVmSnapshots").field(req).finish()
+            }
+            Command::RevertVmSnapshot(req, _) => {
+                f.debug_tuple("RevertVmSnapshot").field(req).finish()
+            }
+            Command::DeleteVmSnapshot(req, _) => {
+                f.debug_tuple("DeleteVmSnapshot").field(req).finish()
+            }
+            Command::GetVmBootMetrics(req, _) => {
+                f.debug_tuple("GetVmBootMetrics").field(req).finish()
+            }
+            Command::ListVmEvents(req, _) => f.debug_tuple("ListVmEvents").field(req).finish(),
+            Command::ReplayVmStateJournal(req, _) => {
+                f.debug_tuple("ReplayVmStateJournal").field(req).finish()
+            }
+            Command::PlanEvacuation(req, _) => f.debug_tuple("PlanEvacuation").field(req).finish(),
+            Command::GetVmStats(req, _) => f.debug_tuple("GetVmStats").field(req).finish(),
+            Command::StreamVmFlows(req, _) => f.debug_tuple("StreamVmFlows").field(req).finish(),
+            Command::StreamVmMetrics(req, _) => {
+                f.debug_tuple("StreamVmMetrics").field(req).finish()
+            }
+            Command::ReplicateVmDisks(req, _) => {
+                f.debug_tuple("ReplicateVmDisks").field(req).finish()
+            }
+            Command::ReceiveDiskReplica(_, _) => {
+                f.write_str("ReceiveDiskReplica(<gRPC Stream>, <oneshot::Sender>)")
+            }
+            Command::ListDiskReplicas(req, _) => {
+                f.debug_tuple("ListDiskReplicas").field(req).finish()
+            }
+            Command::DeleteDiskReplicas(req, _) => {
+                f.debug_tuple("DeleteDiskReplicas").field(req).finish()
+            }
         }
     }
 }
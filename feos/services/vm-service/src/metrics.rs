@@ -0,0 +1,243 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! The usage of VMs, streamed by StreamVmMetrics. The vCPUs of a VM are
+//! threads of its hypervisor process, so the CPU time and memory of the
+//! process are those of the VM plus the overhead of the hypervisor. The
+//! traffic of the disks and NICs is counted by the hypervisor, for the
+//! virtio devices it emulates.
+
+use crate::error::VmServiceError;
+use crate::persistence::{repository::VmRepository, VmRecord};
+use crate::vmm::Hypervisor;
+use feos_proto::vm_service::{VmDiskMetrics, VmMetrics, VmMetricsSample, VmNicMetrics, VmState};
+use feos_utils::host::process::{self, ProcessStats};
+use log::{debug, info};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::time::{interval, Duration, Instant, MissedTickBehavior};
+use tonic::Status;
+use uuid::Uuid;
+
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+
+type Counters = HashMap<String, HashMap<String, i64>>;
+
+fn counter(counters: &HashMap<String, i64>, name: &str) -> u64 {
+    counters
+        .get(name)
+        .and_then(|value| u64::try_from(*value).ok())
+        .unwrap_or_default()
+}
+
+/// The disks and NICs among the devices the hypervisor counts, told apart
+/// by their counters, ordered by device ID.
+fn device_metrics(counters: &Counters) -> (Vec<VmDiskMetrics>, Vec<VmNicMetrics>) {
+    let mut devices: Vec<_> = counters.iter().collect();
+    devices.sort_by_key(|(device_id, _)| device_id.as_str());
+    let mut disks = Vec::new();
+    let mut nics = Vec::new();
+    for (device_id, counters) in devices {
+        if counters.contains_key("read_bytes") {
+            disks.push(VmDiskMetrics {
+                device_id: device_id.clone(),
+                read_bytes: counter(counters, "read_bytes"),
+                write_bytes: counter(counters, "write_bytes"),
+                read_ops: counter(counters, "read_ops"),
+                write_ops: counter(counters, "write_ops"),
+            });
+        } else if counters.contains_key("rx_bytes") {
+            nics.push(VmNicMetrics {
+                device_id: device_id.clone(),
+                rx_bytes: counter(counters, "rx_bytes"),
+                rx_packets: counter(counters, "rx_frames"),
+                tx_bytes: counter(counters, "tx_bytes"),
+                tx_packets: counter(counters, "tx_frames"),
+            });
+        }
+    }
+    (disks, nics)
+}
+
+/// The CPU time used between two samples, in percent of one host CPU. None
+/// if the hypervisor was restarted in between.
+fn cpu_percent(previous: (Instant, Duration), current: (Instant, Duration)) -> Option<f64> {
+    let elapsed = current.0.checked_duration_since(previous.0)?;
+    let used = current.1.checked_sub(previous.1)?;
+    (!elapsed.is_zero()).then(|| used.as_secs_f64() * 100.0 / elapsed.as_secs_f64())
+}
+
+fn vm_metrics(
+    record: &VmRecord,
+    stats: ProcessStats,
+    cpu_percent: Option<f64>,
+    counters: &Counters,
+) -> VmMetrics {
+    let (disks, nics) = device_metrics(counters);
+    VmMetrics {
+        vm_id: record.vm_id.to_string(),
+        namespace: record.namespace.clone(),
+        cpu_time_ms: u64::try_from(stats.cpu_time.as_millis()).unwrap_or(u64::MAX),
+        cpu_percent,
+        memory_rss_bytes: stats.rss_bytes,
+        disks,
+        nics,
+    }
+}
+
+/// The CPU time of each VM at the previous sample, to tell the CPU usage
+/// since.
+type CpuTimes = HashMap<Uuid, (Instant, Duration)>;
+
+async fn sample(
+    repository: &VmRepository,
+    hypervisor: &dyn Hypervisor,
+    vm_ids: &HashSet<Uuid>,
+    cpu_times: &mut CpuTimes,
+) -> Result<VmMetricsSample, VmServiceError> {
+    let records: Vec<VmRecord> = repository
+        .list_all_vms()
+        .await?
+        .into_iter()
+        .filter(|record| vm_ids.is_empty() || vm_ids.contains(&record.vm_id))
+        .filter(|record| matches!(record.status.state, VmState::Running | VmState::Paused))
+        .collect();
+    let mut vms = Vec::new();
+    let mut sampled = CpuTimes::new();
+    for record in &records {
+        let Some(pid) = record.status.process_id else {
+            continue;
+        };
+        let stats = match process::stats(pid).await {
+            Ok(stats) => stats,
+            Err(e) => {
+                debug!(
+                    "Metrics: No usage of the hypervisor of VM {}: {e}",
+                    record.vm_id
+                );
+                continue;
+            }
+        };
+        let now = (Instant::now(), stats.cpu_time);
+        let cpu_percent = cpu_times
+            .get(&record.vm_id)
+            .and_then(|previous| cpu_percent(*previous, now));
+        sampled.insert(record.vm_id, now);
+        let counters = hypervisor
+            .vm_counters(&record.vm_id.to_string())
+            .await
+            .unwrap_or_else(|e| {
+                debug!("Metrics: No device counters of VM {}: {e}", record.vm_id);
+                Counters::new()
+            });
+        vms.push(vm_metrics(record, stats, cpu_percent, &counters));
+    }
+    *cpu_times = sampled;
+    Ok(VmMetricsSample {
+        vms,
+        node_id: String::new(),
+    })
+}
+
+/// Sends a sample of the usage of the VMs every `period`, until the client
+/// goes away or sampling fails.
+pub async fn stream(
+    repository: VmRepository,
+    hypervisor: Arc<dyn Hypervisor>,
+    vm_ids: HashSet<Uuid>,
+    period: Duration,
+    stream_tx: mpsc::Sender<Result<VmMetricsSample, Status>>,
+) {
+    let mut cpu_times = CpuTimes::new();
+    let mut ticks = interval(period);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {}
+            _ = stream_tx.closed() => break,
+        }
+        let result = sample(&repository, hypervisor.as_ref(), &vm_ids, &mut cpu_times)
+            .await
+            .map_err(Status::from);
+        let failed = result.is_err();
+        if stream_tx.send(result).await.is_err() || failed {
+            break;
+        }
+    }
+    info!("Metrics: Stream ended.");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn devices_are_told_apart_by_their_counters() {
+        let counters = Counters::from([
+            (
+                "net0".to_string(),
+                HashMap::from([
+                    ("rx_bytes".to_string(), 2048),
+                    ("rx_frames".to_string(), 4),
+                    ("tx_bytes".to_string(), 512),
+                    ("tx_frames".to_string(), 2),
+                ]),
+            ),
+            (
+                "disk1".to_string(),
+                HashMap::from([
+                    ("read_bytes".to_string(), 4096),
+                    ("read_ops".to_string(), 1),
+                ]),
+            ),
+            (
+                "disk0".to_string(),
+                HashMap::from([
+                    ("read_bytes".to_string(), 1 << 20),
+                    ("write_bytes".to_string(), -1),
+                ]),
+            ),
+            (
+                "_rng".to_string(),
+                HashMap::from([("entropy".to_string(), 64)]),
+            ),
+        ]);
+        let (disks, nics) = device_metrics(&counters);
+        assert_eq!(
+            disks
+                .iter()
+                .map(|disk| disk.device_id.as_str())
+                .collect::<Vec<_>>(),
+            ["disk0", "disk1"]
+        );
+        assert_eq!(disks[0].read_bytes, 1 << 20);
+        assert_eq!(disks[0].write_bytes, 0);
+        assert_eq!(disks[1].read_ops, 1);
+        assert_eq!(nics.len(), 1);
+        assert_eq!((nics[0].rx_bytes, nics[0].rx_packets), (2048, 4));
+        assert_eq!((nics[0].tx_bytes, nics[0].tx_packets), (512, 2));
+    }
+
+    #[test]
+    fn cpu_usage_is_relative_to_one_host_cpu() {
+        let start = Instant::now();
+        let later = start + Duration::from_secs(2);
+        let percent = cpu_percent(
+            (start, Duration::from_secs(10)),
+            (later, Duration::from_secs(13)),
+        );
+        assert_eq!(percent, Some(150.0));
+        assert_eq!(
+            cpu_percent(
+                (start, Duration::from_secs(10)),
+                (later, Duration::from_secs(1))
+            ),
+            None
+        );
+        assert_eq!(
+            cpu_percent((start, Duration::ZERO), (start, Duration::ZERO)),
+            None
+        );
+    }
+}
@@ -0,0 +1,176 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::error::VmServiceError;
+use feos_proto::vm_service::{net_config, NetbootConfig, VmConfig};
+use feos_utils::network::boot_server::{self, BootOffer};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::net::Ipv6Addr;
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::task::JoinHandle;
+
+const BOOT_URL_SCHEMES: [&str; 3] = ["http://", "https://", "tftp://"];
+
+/// The tap device the guest netboots through.
+fn boot_tap<'a>(config: &'a VmConfig, netboot: &NetbootConfig) -> Option<&'a str> {
+    config
+        .net
+        .iter()
+        .filter(|net| netboot.device_id.is_empty() || net.device_id == netboot.device_id)
+        .find_map(|net| match &net.backend {
+            Some(net_config::Backend::Tap(tap)) => Some(tap.tap_name.as_str()),
+            _ => None,
+        })
+}
+
+fn boot_offer(netboot: &NetbootConfig) -> Result<BootOffer, VmServiceError> {
+    if !BOOT_URL_SCHEMES
+        .iter()
+        .any(|scheme| netboot.boot_url.starts_with(scheme))
+    {
+        return Err(VmServiceError::InvalidArgument(format!(
+            "Netboot URL '{}' must be an http, https or tftp URL",
+            netboot.boot_url
+        )));
+    }
+    let address = netboot.address.parse::<Ipv6Addr>().map_err(|_| {
+        VmServiceError::InvalidArgument(format!(
+            "Netboot address '{}' is not an IPv6 address",
+            netboot.address
+        ))
+    })?;
+    Ok(BootOffer {
+        address,
+        boot_url: netboot.boot_url.clone(),
+        boot_params: netboot.boot_params.clone(),
+    })
+}
+
+/// Checks that a VM booting from the network can be served by FeOS.
+pub fn validate(config: &VmConfig) -> Result<(), VmServiceError> {
+    let Some(netboot) = &config.netboot else {
+        return Ok(());
+    };
+    boot_offer(netboot)?;
+    if boot_tap(config, netboot).is_none() {
+        let nic = if netboot.device_id.is_empty() {
+            "any NIC".to_string()
+        } else {
+            format!("NIC '{}'", netboot.device_id)
+        };
+        return Err(VmServiceError::InvalidArgument(format!(
+            "Netboot needs a tap backend, which {nic} does not have"
+        )));
+    }
+    Ok(())
+}
+
+/// The DHCPv6 servers of the VMs that boot from the network, one on the boot
+/// tap device of each running VM.
+#[derive(Clone, Default)]
+pub struct NetbootServers {
+    servers: Arc<Mutex<HashMap<String, JoinHandle<()>>>>,
+}
+
+impl NetbootServers {
+    fn servers(&self) -> std::sync::MutexGuard<'_, HashMap<String, JoinHandle<()>>> {
+        self.servers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Starts serving the VM if it boots from the network and is not served
+    /// yet.
+    pub fn start(&self, vm_id: &str, config: &VmConfig) {
+        let Some(netboot) = &config.netboot else {
+            return;
+        };
+        let (Some(tap), Ok(offer)) = (boot_tap(config, netboot), boot_offer(netboot)) else {
+            warn!("Netboot ({vm_id}): Config is invalid, not serving the boot file.");
+            return;
+        };
+        let mut servers = self.servers();
+        if servers
+            .get(vm_id)
+            .is_some_and(|server| !server.is_finished())
+        {
+            return;
+        }
+        let vm = vm_id.to_string();
+        let tap = tap.to_string();
+        let server = tokio::spawn(async move {
+            if let Err(e) = boot_server::serve(&tap, offer).await {
+                warn!("Netboot ({vm}): Boot server on {tap} stopped: {e}");
+            }
+        });
+        servers.insert(vm_id.to_string(), server);
+    }
+
+    /// Stops serving the VM, once it no longer runs.
+    pub fn stop(&self, vm_id: &str) {
+        if let Some(server) = self.servers().remove(vm_id) {
+            server.abort();
+            info!("Netboot ({vm_id}): Stopped the boot server.");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feos_proto::vm_service::{NetConfig, TapConfig, VhostUserNetConfig};
+
+    fn config(netboot: NetbootConfig) -> VmConfig {
+        VmConfig {
+            net: vec![
+                NetConfig {
+                    device_id: "fabric".to_string(),
+                    backend: Some(net_config::Backend::VhostUser(VhostUserNetConfig {
+                        socket_path: "/run/dpservice/vm.sock".to_string(),
+                        server: false,
+                    })),
+                    ..Default::default()
+                },
+                NetConfig {
+                    device_id: "provisioning".to_string(),
+                    backend: Some(net_config::Backend::Tap(TapConfig {
+                        tap_name: "tap-prov0".to_string(),
+                        ..Default::default()
+                    })),
+                    ..Default::default()
+                },
+            ],
+            netboot: Some(netboot),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn netboot_needs_a_tap_nic_and_a_valid_offer() {
+        let netboot = NetbootConfig {
+            boot_url: "http://[2001:db8::1]/ipxe.efi".to_string(),
+            address: "2001:db8::10".to_string(),
+            ..Default::default()
+        };
+        let vm = config(netboot.clone());
+        assert!(validate(&vm).is_ok());
+        assert_eq!(boot_tap(&vm, &netboot), Some("tap-prov0"));
+
+        let fabric = config(NetbootConfig {
+            device_id: "fabric".to_string(),
+            ..netboot.clone()
+        });
+        assert!(validate(&fabric).is_err());
+
+        let ftp = config(NetbootConfig {
+            boot_url: "ftp://[2001:db8::1]/ipxe.efi".to_string(),
+            ..netboot.clone()
+        });
+        assert!(validate(&ftp).is_err());
+
+        let ipv4 = config(NetbootConfig {
+            address: "192.0.2.10".to_string(),
+            ..netboot
+        });
+        assert!(validate(&ipv4).is_err());
+    }
+}
@@ -0,0 +1,256 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::error::VmServiceError;
+use feos_proto::vm_service::{NvmeofConfig, NvmeofTransport};
+use log::{info, warn};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::process::Command as TokioCommand;
+use tokio::time::{sleep, Duration, Instant};
+
+const DEFAULT_PORT: u32 = 4420;
+const DEVICE_TIMEOUT: Duration = Duration::from_secs(15);
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_millis(250);
+const SUBSYSTEM_CLASS_DIR: &str = "/sys/class/nvme-subsystem";
+/// NQNs are at most 223 bytes long.
+const MAX_NQN_LEN: usize = 223;
+
+fn validate_nqn(nqn: &str) -> Result<(), VmServiceError> {
+    if !nqn.starts_with("nqn.") || nqn.len() > MAX_NQN_LEN || nqn.contains(char::is_whitespace) {
+        return Err(VmServiceError::InvalidArgument(format!(
+            "Invalid NVMe qualified name '{nqn}'"
+        )));
+    }
+    Ok(())
+}
+
+pub fn validate(config: &NvmeofConfig) -> Result<(), VmServiceError> {
+    let address = config.address.trim();
+    if address.is_empty() || address.contains(char::is_whitespace) {
+        return Err(VmServiceError::InvalidArgument(format!(
+            "Invalid NVMe-oF address '{}'",
+            config.address
+        )));
+    }
+    if config.port > u16::MAX as u32 {
+        return Err(VmServiceError::InvalidArgument(format!(
+            "Invalid NVMe-oF port {}",
+            config.port
+        )));
+    }
+    validate_nqn(&config.subsystem_nqn)?;
+    if !config.host_nqn.is_empty() {
+        validate_nqn(&config.host_nqn)?;
+    }
+    if config.nsid == 0 {
+        return Err(VmServiceError::InvalidArgument(
+            "NVMe namespace IDs start at 1".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Whether two disks are namespaces of the same subsystem. Disconnecting
+/// from it for one of them would tear down all paths to the other.
+pub fn same_subsystem(a: &NvmeofConfig, b: &NvmeofConfig) -> bool {
+    a.subsystem_nqn == b.subsystem_nqn
+}
+
+fn transport(config: &NvmeofConfig) -> &'static str {
+    match config.transport() {
+        NvmeofTransport::Unspecified | NvmeofTransport::Tcp => "tcp",
+        NvmeofTransport::Rdma => "rdma",
+    }
+}
+
+async fn nvme(args: &[&str]) -> Result<(), VmServiceError> {
+    let output = TokioCommand::new("nvme")
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| VmServiceError::Nvmeof(format!("Failed to run nvme: {e}")))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(VmServiceError::Nvmeof(format!(
+            "nvme failed for {}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+/// Whether a sysfs entry is a namespace block device like `nvme0n1`, rather
+/// than a controller or the hidden path `nvme0c1n1` of native multipath.
+fn is_namespace_name(name: &str) -> bool {
+    let digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    name.strip_prefix("nvme")
+        .and_then(|rest| rest.split_once('n'))
+        .is_some_and(|(subsystem, namespace)| digits(subsystem) && digits(namespace))
+}
+
+fn is_controller_name(name: &str) -> bool {
+    name.strip_prefix("nvme")
+        .is_some_and(|rest| !rest.is_empty() && rest.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// The sysfs directory of the subsystem, if the host is connected to it.
+async fn subsystem_dir(config: &NvmeofConfig) -> Option<PathBuf> {
+    let mut subsystems = fs::read_dir(SUBSYSTEM_CLASS_DIR).await.ok()?;
+    while let Ok(Some(subsystem)) = subsystems.next_entry().await {
+        let dir = subsystem.path();
+        let nqn = fs::read_to_string(dir.join("subsysnqn"))
+            .await
+            .unwrap_or_default();
+        if nqn.trim() == config.subsystem_nqn {
+            return Some(dir);
+        }
+    }
+    None
+}
+
+async fn namespace_in(dir: &Path, nsid: u32) -> Option<PathBuf> {
+    let mut entries = fs::read_dir(dir).await.ok()?;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !is_namespace_name(&name) {
+            continue;
+        }
+        let id = fs::read_to_string(entry.path().join("nsid"))
+            .await
+            .unwrap_or_default();
+        if id.trim().parse() == Ok(nsid) {
+            return Some(Path::new("/dev").join(name));
+        }
+    }
+    None
+}
+
+/// The block device of the namespace. With native multipath it belongs to
+/// the subsystem, otherwise to one of its controllers.
+async fn find_namespace(subsystem: &Path, nsid: u32) -> Option<PathBuf> {
+    if let Some(device) = namespace_in(subsystem, nsid).await {
+        return Some(device);
+    }
+    let mut entries = fs::read_dir(subsystem).await.ok()?;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if is_controller_name(&name) {
+            if let Some(device) = namespace_in(&entry.path(), nsid).await {
+                return Some(device);
+            }
+        }
+    }
+    None
+}
+
+/// Connects to the subsystem, unless the host already is, and returns the
+/// block device of the namespace.
+pub async fn connect(config: &NvmeofConfig) -> Result<PathBuf, VmServiceError> {
+    let nqn = config.subsystem_nqn.as_str();
+    if subsystem_dir(config).await.is_none() {
+        let port = match config.port {
+            0 => DEFAULT_PORT,
+            port => port,
+        }
+        .to_string();
+        let mut args = vec![
+            "connect",
+            "-t",
+            transport(config),
+            "-a",
+            config.address.trim(),
+            "-s",
+            &port,
+            "-n",
+            nqn,
+        ];
+        if !config.host_nqn.is_empty() {
+            args.extend(["-q", config.host_nqn.as_str()]);
+        }
+        nvme(&args).await?;
+        info!("NVMe-oF: Connected to {nqn} at {}", config.address);
+    }
+
+    let deadline = Instant::now() + DEVICE_TIMEOUT;
+    loop {
+        if let Some(subsystem) = subsystem_dir(config).await {
+            if let Some(device) = find_namespace(&subsystem, config.nsid).await {
+                return Ok(device);
+            }
+        }
+        if Instant::now() >= deadline {
+            return Err(VmServiceError::Nvmeof(format!(
+                "Namespace {} of {nqn} did not appear after connecting",
+                config.nsid
+            )));
+        }
+        sleep(DEVICE_POLL_INTERVAL).await;
+    }
+}
+
+/// Disconnects all controllers of the subsystem.
+pub async fn disconnect(config: &NvmeofConfig) {
+    let nqn = config.subsystem_nqn.as_str();
+    match nvme(&["disconnect", "-n", nqn]).await {
+        Ok(()) => info!("NVMe-oF: Disconnected from {nqn}"),
+        Err(e) => warn!("NVMe-oF: Disconnecting from {nqn} failed: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subsystem() -> NvmeofConfig {
+        NvmeofConfig {
+            address: "10.0.0.1".to_string(),
+            subsystem_nqn: "nqn.2014-08.org.example:storage.vol1".to_string(),
+            nsid: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn subsystems_need_valid_names_and_namespaces() {
+        assert!(validate(&subsystem()).is_ok());
+
+        let invalid = [
+            NvmeofConfig {
+                address: " ".to_string(),
+                ..subsystem()
+            },
+            NvmeofConfig {
+                port: 70000,
+                ..subsystem()
+            },
+            NvmeofConfig {
+                subsystem_nqn: "iqn.2003-01.org.example:disk1".to_string(),
+                ..subsystem()
+            },
+            NvmeofConfig {
+                host_nqn: "host1".to_string(),
+                ..subsystem()
+            },
+            NvmeofConfig {
+                nsid: 0,
+                ..subsystem()
+            },
+        ];
+        for config in invalid {
+            assert!(validate(&config).is_err(), "{config:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn namespaces_are_told_apart_from_controllers_and_paths() {
+        assert!(is_namespace_name("nvme0n1"));
+        assert!(is_namespace_name("nvme12n3"));
+        assert!(!is_namespace_name("nvme0c1n1"));
+        assert!(!is_namespace_name("nvme0"));
+        assert!(!is_namespace_name("nvme0n"));
+        assert!(is_controller_name("nvme3"));
+        assert!(!is_controller_name("nvme-subsys0"));
+    }
+}
@@ -0,0 +1,279 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Disks written to a copy-on-write overlay of a base image, so VMs booting
+//! from the same image share its data instead of each getting a full copy.
+//! The overlay is a reflinked copy of the base where the filesystem supports
+//! reflinks (btrfs, XFS), elsewhere a qcow2 image with the base as its
+//! backing file. Overlays are recorded with their base in the database, and
+//! the VM or image a base belongs to is not deleted while overlays of it
+//! exist.
+
+use crate::{
+    disk_image,
+    error::VmServiceError,
+    persistence::{repository::VmRepository, DiskOverlay, VmRecord},
+    storage_daemon::flatten_device_id,
+    IMAGE_DIR, VM_OVERLAY_DIR,
+};
+use feos_proto::vm_service::{DiskFormat, OverlayDiskConfig};
+use log::{info, warn};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::process::Command as TokioCommand;
+use uuid::Uuid;
+
+const QEMU_IMG_BIN: &str = "qemu-img";
+
+pub fn overlay_path(vm_id: &str, device_id: &str) -> PathBuf {
+    Path::new(VM_OVERLAY_DIR).join(format!("{vm_id}-{}", flatten_device_id(device_id)))
+}
+
+pub fn validate(config: &OverlayDiskConfig) -> Result<(), VmServiceError> {
+    if !Path::new(&config.base_path).is_absolute() {
+        return Err(VmServiceError::InvalidArgument(format!(
+            "Overlay base path '{}' must be absolute",
+            config.base_path
+        )));
+    }
+    Ok(())
+}
+
+/// The image of the image service whose root disk is at `base_path`.
+pub fn base_image(base_path: &str) -> Option<Uuid> {
+    let relative = Path::new(base_path).strip_prefix(IMAGE_DIR).ok()?;
+    let image_uuid = relative.components().next()?.as_os_str().to_str()?;
+    Uuid::parse_str(image_uuid).ok()
+}
+
+/// Shares the data blocks of `base` with `overlay`. Fails on filesystems
+/// without reflinks and across filesystems.
+async fn reflink(base: &Path, overlay: &Path) -> bool {
+    match TokioCommand::new("cp")
+        .arg("--reflink=always")
+        .arg(base)
+        .arg(overlay)
+        .output()
+        .await
+    {
+        Ok(output) => output.status.success(),
+        Err(e) => {
+            warn!("Overlay: Failed to run cp: {e}");
+            false
+        }
+    }
+}
+
+async fn create_qcow2(
+    base: &Path,
+    base_format: DiskFormat,
+    overlay: &Path,
+) -> Result<(), VmServiceError> {
+    let backing_format = if base_format == DiskFormat::Qcow2 {
+        "qcow2"
+    } else {
+        "raw"
+    };
+    let output = TokioCommand::new(QEMU_IMG_BIN)
+        .args(["create", "-q", "-f", "qcow2", "-F", backing_format, "-b"])
+        .arg(base)
+        .arg(overlay)
+        .output()
+        .await
+        .map_err(|e| VmServiceError::Overlay(format!("Failed to run {QEMU_IMG_BIN}: {e}")))?;
+    if !output.status.success() {
+        return Err(VmServiceError::Overlay(format!(
+            "Creating an overlay of {} failed: {}",
+            base.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+async fn remove_file(path: &Path) {
+    if let Err(e) = fs::remove_file(path).await {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            warn!("Overlay: Failed to remove {}: {e}", path.display());
+        }
+    }
+}
+
+/// Creates the overlay of a disk of `vm_id` and records it, or returns the
+/// one created before, which keeps what the guest wrote.
+pub async fn create(
+    repository: &VmRepository,
+    vm_id: Uuid,
+    device_id: &str,
+    config: &OverlayDiskConfig,
+) -> Result<DiskOverlay, VmServiceError> {
+    if let Some(overlay) = repository.get_disk_overlay(vm_id, device_id).await? {
+        if !fs::try_exists(&overlay.overlay_path).await.unwrap_or(false) {
+            return Err(VmServiceError::InvalidState(format!(
+                "Overlay {} of disk '{device_id}' is gone",
+                overlay.overlay_path
+            )));
+        }
+        return Ok(overlay);
+    }
+
+    let base = fs::canonicalize(&config.base_path).await.map_err(|e| {
+        VmServiceError::InvalidArgument(format!(
+            "Cannot access overlay base {}: {e}",
+            config.base_path
+        ))
+    })?;
+    let image = disk_image::inspect(&base).await?;
+    fs::create_dir_all(VM_OVERLAY_DIR)
+        .await
+        .map_err(|e| VmServiceError::Overlay(format!("Failed to create {VM_OVERLAY_DIR}: {e}")))?;
+    let path = overlay_path(&vm_id.to_string(), device_id);
+    remove_file(&path).await;
+
+    let format = if !image.block_device && reflink(&base, &path).await {
+        info!(
+            "Overlay: Reflinked {} for disk '{device_id}' of VM {vm_id}",
+            base.display()
+        );
+        image.format
+    } else {
+        remove_file(&path).await;
+        create_qcow2(&base, image.format, &path).await?;
+        info!(
+            "Overlay: Created qcow2 overlay of {} for disk '{device_id}' of VM {vm_id}",
+            base.display()
+        );
+        DiskFormat::Qcow2
+    };
+
+    let overlay = DiskOverlay {
+        vm_id,
+        device_id: device_id.to_string(),
+        base_path: base.to_string_lossy().into_owned(),
+        overlay_path: path.to_string_lossy().into_owned(),
+        format,
+    };
+    if let Err(e) = repository.save_disk_overlay(&overlay).await {
+        remove_file(&path).await;
+        return Err(e.into());
+    }
+    Ok(overlay)
+}
+
+/// Deletes the overlay of a disk of `vm_id`, if it has one.
+pub async fn remove(repository: &VmRepository, vm_id: Uuid, device_id: &str) {
+    match repository.get_disk_overlay(vm_id, device_id).await {
+        Ok(Some(overlay)) => {
+            remove_file(Path::new(&overlay.overlay_path)).await;
+            if let Err(e) = repository.delete_disk_overlay(vm_id, device_id).await {
+                warn!(
+                    "Overlay: Failed to remove the record of disk '{device_id}' of VM {vm_id}: {e}"
+                );
+            }
+        }
+        Ok(None) => {}
+        Err(e) => {
+            warn!("Overlay: Failed to look up the overlay of disk '{device_id}' of VM {vm_id}: {e}")
+        }
+    }
+}
+
+/// Deletes the overlays of all disks of `vm_id`.
+pub async fn remove_all(repository: &VmRepository, vm_id: Uuid) {
+    match repository.list_disk_overlays(Some(vm_id)).await {
+        Ok(overlays) => {
+            for overlay in overlays {
+                remove(repository, vm_id, &overlay.device_id).await;
+            }
+        }
+        Err(e) => warn!("Overlay: Failed to list the overlays of VM {vm_id}: {e}"),
+    }
+}
+
+/// The VMs other than `vm_id` with overlays based on one of `paths`.
+fn dependents(overlays: &[DiskOverlay], vm_id: Uuid, paths: &HashSet<PathBuf>) -> Vec<Uuid> {
+    let mut vm_ids: Vec<Uuid> = overlays
+        .iter()
+        .filter(|overlay| overlay.vm_id != vm_id && paths.contains(Path::new(&overlay.base_path)))
+        .map(|overlay| overlay.vm_id)
+        .collect();
+    vm_ids.sort();
+    vm_ids.dedup();
+    vm_ids
+}
+
+/// Fails if disks of other VMs are overlays of the root disk of the VM or of
+/// its own overlays, which go away with it.
+pub async fn check_not_base(
+    repository: &VmRepository,
+    record: &VmRecord,
+) -> Result<(), VmServiceError> {
+    let overlays = repository.list_disk_overlays(None).await?;
+    let rootfs = Path::new(IMAGE_DIR)
+        .join(record.image_uuid.to_string())
+        .join("disk.image");
+    let own = overlays
+        .iter()
+        .filter(|overlay| overlay.vm_id == record.vm_id)
+        .map(|overlay| PathBuf::from(&overlay.overlay_path));
+    let mut paths = HashSet::new();
+    for path in std::iter::once(rootfs).chain(own) {
+        if let Ok(path) = fs::canonicalize(&path).await {
+            paths.insert(path);
+        }
+    }
+
+    let vm_ids = dependents(&overlays, record.vm_id, &paths);
+    if vm_ids.is_empty() {
+        return Ok(());
+    }
+    let vm_ids: Vec<String> = vm_ids.iter().map(Uuid::to_string).collect();
+    Err(VmServiceError::InvalidState(format!(
+        "Disks of VM {} are the base of overlays of VMs {}, delete those first",
+        record.vm_id,
+        vm_ids.join(", ")
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlay(vm_id: Uuid, base_path: &str) -> DiskOverlay {
+        DiskOverlay {
+            vm_id,
+            device_id: "data".to_string(),
+            base_path: base_path.to_string(),
+            overlay_path: overlay_path(&vm_id.to_string(), "data")
+                .to_string_lossy()
+                .into_owned(),
+            format: DiskFormat::Qcow2,
+        }
+    }
+
+    #[test]
+    fn vms_with_overlays_of_a_base_depend_on_it() {
+        let golden = Uuid::new_v4();
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let base = format!("{IMAGE_DIR}/{}/disk.image", Uuid::new_v4());
+        let overlays = [
+            overlay(golden, "/srv/base.raw"),
+            overlay(second, &base),
+            overlay(first, &base),
+            overlay(first, "/srv/other.raw"),
+        ];
+        let paths = HashSet::from([PathBuf::from(&base), PathBuf::from("/srv/base.raw")]);
+
+        let mut expected = vec![first, second];
+        expected.sort();
+        assert_eq!(dependents(&overlays, golden, &paths), expected);
+        assert!(dependents(&overlays, golden, &HashSet::new()).is_empty());
+
+        assert_eq!(
+            base_image(&base).map(|uuid| base.contains(&uuid.to_string())),
+            Some(true)
+        );
+        assert_eq!(base_image("/srv/base.raw"), None);
+    }
+}
@@ -1,7 +1,7 @@
 // SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use feos_proto::vm_service::{VmConfig, VmState};
+use feos_proto::vm_service::{DiskFormat, VmConfig, VmState};
 use uuid::Uuid;
 
 pub mod repository;
@@ -22,6 +22,12 @@ pub enum PersistenceError {
 
     #[error("Invalid state string '{0}' in database")]
     InvalidStateString(String),
+
+    #[error("Invalid disk format '{0}' in database")]
+    InvalidFormatString(String),
+
+    #[error("{0}")]
+    NameTaken(String),
 }
 
 #[derive(Debug, Clone)]
@@ -34,7 +40,30 @@ pub struct VmStatus {
 #[derive(Debug, Clone)]
 pub struct VmRecord {
     pub vm_id: Uuid,
+    pub namespace: String,
+    pub name: Option<String>,
     pub image_uuid: Uuid,
     pub status: VmStatus,
     pub config: VmConfig,
 }
+
+/// A PCI device of the host passed through to a VM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PciDeviceAssignment {
+    pub pci_address: String,
+    pub vm_id: Uuid,
+    /// The driver to bind the device to again once the VM lets go of it.
+    pub original_driver: Option<String>,
+}
+
+/// A disk of a VM written to a copy-on-write overlay of a base image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskOverlay {
+    pub vm_id: Uuid,
+    pub device_id: String,
+    pub base_path: String,
+    pub overlay_path: String,
+    /// The format of the base for a reflinked copy, qcow2 for an overlay
+    /// with the base as its backing file.
+    pub format: DiskFormat,
+}
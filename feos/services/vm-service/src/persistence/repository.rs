@@ -1,11 +1,14 @@
 // SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::persistence::{PersistenceError, VmRecord, VmStatus};
-use feos_proto::vm_service::{VmConfig, VmState};
+use crate::persistence::{DiskOverlay, PciDeviceAssignment, PersistenceError, VmRecord, VmStatus};
+use feos_proto::vm_service::{
+    DiskFormat, VmBootTimings, VmConfig, VmEvent, VmSnapshotInfo, VmState,
+};
+use feos_utils::sqlite::{connect_pool, retry_busy};
 use log::info;
 use prost::Message;
-use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+use sqlx::sqlite::SqlitePool;
 use uuid::Uuid;
 
 #[derive(Clone)]
@@ -16,6 +19,8 @@ pub struct VmRepository {
 #[derive(sqlx::FromRow, Debug)]
 struct DbVmRow {
     vm_id: Uuid,
+    namespace: String,
+    name: Option<String>,
     image_uuid: Uuid,
     state: String,
     last_msg: String,
@@ -23,6 +28,139 @@ struct DbVmRow {
     config_blob: Vec<u8>,
 }
 
+#[derive(sqlx::FromRow, Debug)]
+struct DbSnapshotRow {
+    info_blob: Vec<u8>,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct DbBootTimingsRow {
+    timings_blob: Vec<u8>,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct DbPciDeviceAssignmentRow {
+    pci_address: String,
+    vm_id: Uuid,
+    original_driver: Option<String>,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct DbDiskOverlayRow {
+    vm_id: Uuid,
+    device_id: String,
+    base_path: String,
+    overlay_path: String,
+    format: String,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct DbEventRow {
+    recorded_at_ms: i64,
+    event_blob: Vec<u8>,
+}
+
+/// The most events kept per VM. Older ones are dropped first.
+const MAX_EVENTS_PER_VM: i64 = 1000;
+/// How long events are kept, 30 days.
+const EVENT_RETENTION_MS: i64 = 30 * 24 * 60 * 60 * 1000;
+
+/// Selects recorded VM events. Times are milliseconds since the Unix epoch.
+#[derive(Debug, Clone, Default)]
+pub struct VmEventFilter {
+    pub vm_id: Option<Uuid>,
+    pub namespace: Option<String>,
+    /// Only events recorded at or after this time.
+    pub since_ms: Option<i64>,
+    /// Only events recorded before this time.
+    pub until_ms: Option<i64>,
+    /// The most events returned, the newest of those selected.
+    pub limit: i64,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct DbJournalRow {
+    sequence: i64,
+    vm_id: Uuid,
+    namespace: String,
+    recorded_at_ms: i64,
+    old_state: Option<String>,
+    new_state: Option<String>,
+    message: String,
+}
+
+/// An entry of the journal of VM state transitions. Times are milliseconds
+/// since the Unix epoch.
+#[derive(Debug, Clone)]
+pub struct VmJournalEntry {
+    pub sequence: i64,
+    pub vm_id: Uuid,
+    pub namespace: String,
+    pub recorded_at_ms: i64,
+    /// The state before, `None` when the VM was created.
+    pub old_state: Option<VmState>,
+    /// The state after, `None` when the VM was deleted.
+    pub new_state: Option<VmState>,
+    pub message: String,
+}
+
+fn journal_entry_from_row(row: DbJournalRow) -> Result<VmJournalEntry, PersistenceError> {
+    Ok(VmJournalEntry {
+        sequence: row.sequence,
+        vm_id: row.vm_id,
+        namespace: row.namespace,
+        recorded_at_ms: row.recorded_at_ms,
+        old_state: row
+            .old_state
+            .as_deref()
+            .map(string_to_vm_state)
+            .transpose()?,
+        new_state: row
+            .new_state
+            .as_deref()
+            .map(string_to_vm_state)
+            .transpose()?,
+        message: row.message,
+    })
+}
+
+const VM_COLUMNS: &str = "vm_id, namespace, name, image_uuid, state, last_msg, pid, config_blob";
+
+fn vm_record_from_row(row: DbVmRow) -> Result<VmRecord, PersistenceError> {
+    Ok(VmRecord {
+        vm_id: row.vm_id,
+        namespace: row.namespace,
+        name: row.name,
+        image_uuid: row.image_uuid,
+        status: VmStatus {
+            state: string_to_vm_state(&row.state)?,
+            last_msg: row.last_msg,
+            process_id: row.pid,
+        },
+        config: VmConfig::decode(&*row.config_blob)?,
+    })
+}
+
+fn pci_device_assignment_from_row(row: DbPciDeviceAssignmentRow) -> PciDeviceAssignment {
+    PciDeviceAssignment {
+        pci_address: row.pci_address,
+        vm_id: row.vm_id,
+        original_driver: row.original_driver,
+    }
+}
+
+fn disk_overlay_from_row(row: DbDiskOverlayRow) -> Result<DiskOverlay, PersistenceError> {
+    let format = DiskFormat::from_str_name(&row.format)
+        .ok_or_else(|| PersistenceError::InvalidFormatString(row.format.clone()))?;
+    Ok(DiskOverlay {
+        vm_id: row.vm_id,
+        device_id: row.device_id,
+        base_path: row.base_path,
+        overlay_path: row.overlay_path,
+        format,
+    })
+}
+
 fn string_to_vm_state(s: &str) -> Result<VmState, PersistenceError> {
     match s {
         "VM_STATE_CREATING" => Ok(VmState::Creating),
@@ -38,10 +176,7 @@ fn string_to_vm_state(s: &str) -> Result<VmState, PersistenceError> {
 
 impl VmRepository {
     pub async fn connect(db_url: &str) -> Result<Self, PersistenceError> {
-        let pool = SqlitePoolOptions::new()
-            .max_connections(1)
-            .connect(db_url)
-            .await?;
+        let pool = connect_pool(db_url).await?;
 
         info!("Persistence: Running database migrations...");
         sqlx::migrate!("./migrations").run(&pool).await?;
@@ -51,81 +186,94 @@ impl VmRepository {
     }
 
     pub async fn get_vm(&self, vm_id: Uuid) -> Result<Option<VmRecord>, PersistenceError> {
-        let row_opt = sqlx::query_as::<_, DbVmRow>(
-            "SELECT vm_id, image_uuid, state, last_msg, pid, config_blob FROM vms WHERE vm_id = ?1",
-        )
-        .bind(vm_id)
-        .fetch_optional(&self.pool)
-        .await?;
+        let row_opt =
+            sqlx::query_as::<_, DbVmRow>(&format!("SELECT {VM_COLUMNS} FROM vms WHERE vm_id = ?1"))
+                .bind(vm_id)
+                .fetch_optional(&self.pool)
+                .await?;
 
-        if let Some(row) = row_opt {
-            let config = VmConfig::decode(&*row.config_blob)?;
-            let state = string_to_vm_state(&row.state)?;
-
-            let record = VmRecord {
-                vm_id: row.vm_id,
-                image_uuid: row.image_uuid,
-                status: VmStatus {
-                    state,
-                    last_msg: row.last_msg,
-                    process_id: row.pid,
-                },
-                config,
-            };
-            Ok(Some(record))
-        } else {
-            Ok(None)
-        }
+        row_opt.map(vm_record_from_row).transpose()
     }
 
     pub async fn list_all_vms(&self) -> Result<Vec<VmRecord>, PersistenceError> {
-        let rows = sqlx::query_as::<_, DbVmRow>(
-            "SELECT vm_id, image_uuid, state, last_msg, pid, config_blob FROM vms",
-        )
+        let rows = sqlx::query_as::<_, DbVmRow>(&format!("SELECT {VM_COLUMNS} FROM vms"))
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.into_iter().map(vm_record_from_row).collect()
+    }
+
+    pub async fn list_vms_in_namespace(
+        &self,
+        namespace: &str,
+    ) -> Result<Vec<VmRecord>, PersistenceError> {
+        let rows = sqlx::query_as::<_, DbVmRow>(&format!(
+            "SELECT {VM_COLUMNS} FROM vms WHERE namespace = ?1"
+        ))
+        .bind(namespace)
         .fetch_all(&self.pool)
         .await?;
 
-        let mut records = Vec::with_capacity(rows.len());
-        for row in rows {
-            let config = VmConfig::decode(&*row.config_blob)?;
-            let state = string_to_vm_state(&row.state)?;
-
-            let record = VmRecord {
-                vm_id: row.vm_id,
-                image_uuid: row.image_uuid,
-                status: VmStatus {
-                    state,
-                    last_msg: row.last_msg,
-                    process_id: row.pid,
-                },
-                config,
-            };
-            records.push(record);
-        }
+        rows.into_iter().map(vm_record_from_row).collect()
+    }
 
-        Ok(records)
+    /// Whether a VM in `namespace` already has the name `name`.
+    pub async fn vm_name_taken(
+        &self,
+        namespace: &str,
+        name: &str,
+    ) -> Result<bool, PersistenceError> {
+        let taken: Option<i64> =
+            sqlx::query_scalar("SELECT 1 FROM vms WHERE namespace = ?1 AND name = ?2")
+                .bind(namespace)
+                .bind(name)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(taken.is_some())
     }
 
+    /// Inserts or updates the record of a VM. Fails with `NameTaken` if
+    /// another VM of the namespace has the name, instead of replacing it.
     pub async fn save_vm(&self, vm: &VmRecord) -> Result<(), PersistenceError> {
         let mut config_blob = Vec::new();
         vm.config.encode(&mut config_blob)?;
 
         let state_str = format!("VM_STATE_{:?}", vm.status.state).to_uppercase();
 
-        sqlx::query_unchecked!(
-            r#"
-            INSERT OR REPLACE INTO vms (vm_id, image_uuid, state, last_msg, pid, config_blob)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+        retry_busy(|| {
+            sqlx::query(
+                r#"
+            INSERT INTO vms (vm_id, namespace, name, image_uuid, state, last_msg, pid, config_blob)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            ON CONFLICT (vm_id) DO UPDATE SET
+                namespace = excluded.namespace,
+                name = excluded.name,
+                image_uuid = excluded.image_uuid,
+                state = excluded.state,
+                last_msg = excluded.last_msg,
+                pid = excluded.pid,
+                config_blob = excluded.config_blob
             "#,
-            vm.vm_id,
-            vm.image_uuid,
-            state_str,
-            vm.status.last_msg,
-            vm.status.process_id,
-            config_blob,
-        )
-        .execute(&self.pool)
-        .await?;
+            )
+            .bind(vm.vm_id)
+            .bind(&vm.namespace)
+            .bind(&vm.name)
+            .bind(vm.image_uuid)
+            .bind(&state_str)
+            .bind(&vm.status.last_msg)
+            .bind(vm.status.process_id)
+            .bind(&config_blob)
+            .execute(&self.pool)
+        })
+        .await
+        .map_err(|e| match e.as_database_error() {
+            Some(db_err) if db_err.is_unique_violation() => PersistenceError::NameTaken(format!(
+                "A VM named '{}' already exists in namespace '{}'",
+                vm.name.as_deref().unwrap_or_default(),
+                vm.namespace
+            )),
+            _ => e.into(),
+        })?;
 
         Ok(())
     }
@@ -138,33 +286,37 @@ impl VmRepository {
     ) -> Result<bool, PersistenceError> {
         let state_str = format!("VM_STATE_{new_state:?}").to_uppercase();
 
-        let result = sqlx::query!(
-            r#"
+        let result = retry_busy(|| {
+            sqlx::query!(
+                r#"
             UPDATE vms
             SET state = ?1, last_msg = ?2
             WHERE vm_id = ?3
             "#,
-            state_str,
-            message,
-            vm_id,
-        )
-        .execute(&self.pool)
+                state_str,
+                message,
+                vm_id,
+            )
+            .execute(&self.pool)
+        })
         .await?;
 
         Ok(result.rows_affected() > 0)
     }
 
     pub async fn update_vm_pid(&self, vm_id: Uuid, pid: i64) -> Result<(), PersistenceError> {
-        sqlx::query!("UPDATE vms SET pid = ?1 WHERE vm_id = ?2", pid, vm_id)
-            .execute(&self.pool)
-            .await?;
+        retry_busy(|| {
+            sqlx::query!("UPDATE vms SET pid = ?1 WHERE vm_id = ?2", pid, vm_id).execute(&self.pool)
+        })
+        .await?;
         Ok(())
     }
 
     pub async fn delete_vm(&self, vm_id: Uuid) -> Result<(), PersistenceError> {
-        let result = sqlx::query!("DELETE FROM vms WHERE vm_id = ?1", vm_id)
-            .execute(&self.pool)
-            .await?;
+        let result = retry_busy(|| {
+            sqlx::query!("DELETE FROM vms WHERE vm_id = ?1", vm_id).execute(&self.pool)
+        })
+        .await?;
 
         if result.rows_affected() == 0 {
             log::warn!("Attempted to delete VM {vm_id} from DB, but no record was found.");
@@ -172,4 +324,377 @@ impl VmRepository {
 
         Ok(())
     }
+
+    pub async fn save_vm_snapshot(
+        &self,
+        snapshot: &VmSnapshotInfo,
+    ) -> Result<(), PersistenceError> {
+        let mut info_blob = Vec::new();
+        snapshot.encode(&mut info_blob)?;
+
+        retry_busy(|| {
+            sqlx::query(
+                r#"
+            INSERT OR REPLACE INTO vm_snapshots (snapshot_id, vm_id, info_blob)
+            VALUES (?1, ?2, ?3)
+            "#,
+            )
+            .bind(&snapshot.snapshot_id)
+            .bind(&snapshot.vm_id)
+            .bind(&info_blob)
+            .execute(&self.pool)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_vm_snapshot(
+        &self,
+        snapshot_id: Uuid,
+    ) -> Result<Option<VmSnapshotInfo>, PersistenceError> {
+        let row_opt = sqlx::query_as::<_, DbSnapshotRow>(
+            "SELECT info_blob FROM vm_snapshots WHERE snapshot_id = ?1",
+        )
+        .bind(snapshot_id.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row_opt
+            .map(|row| VmSnapshotInfo::decode(&*row.info_blob).map_err(Into::into))
+            .transpose()
+    }
+
+    pub async fn list_vm_snapshots(
+        &self,
+        vm_id: Uuid,
+    ) -> Result<Vec<VmSnapshotInfo>, PersistenceError> {
+        let rows = sqlx::query_as::<_, DbSnapshotRow>(
+            "SELECT info_blob FROM vm_snapshots WHERE vm_id = ?1 ORDER BY created_at, rowid",
+        )
+        .bind(vm_id.to_string())
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| VmSnapshotInfo::decode(&*row.info_blob).map_err(Into::into))
+            .collect()
+    }
+
+    pub async fn delete_vm_snapshot(&self, snapshot_id: Uuid) -> Result<(), PersistenceError> {
+        retry_busy(|| {
+            sqlx::query("DELETE FROM vm_snapshots WHERE snapshot_id = ?1")
+                .bind(snapshot_id.to_string())
+                .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete_vm_snapshots(&self, vm_id: Uuid) -> Result<(), PersistenceError> {
+        retry_busy(|| {
+            sqlx::query("DELETE FROM vm_snapshots WHERE vm_id = ?1")
+                .bind(vm_id.to_string())
+                .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    pub async fn save_boot_timings(
+        &self,
+        vm_id: Uuid,
+        timings: &VmBootTimings,
+    ) -> Result<(), PersistenceError> {
+        let mut timings_blob = Vec::new();
+        timings.encode(&mut timings_blob)?;
+
+        retry_busy(|| {
+            sqlx::query(
+                "INSERT OR REPLACE INTO vm_boot_timings (vm_id, timings_blob) VALUES (?1, ?2)",
+            )
+            .bind(vm_id.to_string())
+            .bind(&timings_blob)
+            .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_boot_timings(
+        &self,
+        vm_id: Uuid,
+    ) -> Result<Option<VmBootTimings>, PersistenceError> {
+        let row_opt = sqlx::query_as::<_, DbBootTimingsRow>(
+            "SELECT timings_blob FROM vm_boot_timings WHERE vm_id = ?1",
+        )
+        .bind(vm_id.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row_opt
+            .map(|row| VmBootTimings::decode(&*row.timings_blob).map_err(Into::into))
+            .transpose()
+    }
+
+    pub async fn delete_boot_timings(&self, vm_id: Uuid) -> Result<(), PersistenceError> {
+        retry_busy(|| {
+            sqlx::query("DELETE FROM vm_boot_timings WHERE vm_id = ?1")
+                .bind(vm_id.to_string())
+                .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Records an event of a VM and drops its events beyond the retention
+    /// limits. Without a namespace, that of the VM's earlier events is used.
+    pub async fn save_vm_event(
+        &self,
+        event: &VmEvent,
+        namespace: Option<&str>,
+        recorded_at_ms: i64,
+    ) -> Result<(), PersistenceError> {
+        let mut event_blob = Vec::new();
+        event.encode(&mut event_blob)?;
+
+        retry_busy(|| {
+            sqlx::query(
+                r#"
+            INSERT OR REPLACE INTO vm_events (event_id, vm_id, namespace, recorded_at_ms, event_blob)
+            VALUES (
+                ?1, ?2,
+                COALESCE(?3, (SELECT namespace FROM vm_events WHERE vm_id = ?2 LIMIT 1), ''),
+                ?4, ?5
+            )
+            "#,
+            )
+            .bind(&event.id)
+            .bind(&event.vm_id)
+            .bind(namespace)
+            .bind(recorded_at_ms)
+            .bind(&event_blob)
+            .execute(&self.pool)
+        })
+        .await?;
+
+        retry_busy(|| {
+            sqlx::query(
+                r#"
+            DELETE FROM vm_events
+            WHERE recorded_at_ms < ?2
+               OR (vm_id = ?1 AND rowid NOT IN (
+                   SELECT rowid FROM vm_events WHERE vm_id = ?1
+                   ORDER BY recorded_at_ms DESC, rowid DESC LIMIT ?3
+               ))
+            "#,
+            )
+            .bind(&event.vm_id)
+            .bind(recorded_at_ms - EVENT_RETENTION_MS)
+            .bind(MAX_EVENTS_PER_VM)
+            .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Lists recorded events, oldest first, with the time each was recorded.
+    pub async fn list_vm_events(
+        &self,
+        filter: &VmEventFilter,
+    ) -> Result<Vec<(VmEvent, i64)>, PersistenceError> {
+        let rows = sqlx::query_as::<_, DbEventRow>(
+            r#"
+            SELECT recorded_at_ms, event_blob FROM vm_events
+            WHERE (?1 IS NULL OR vm_id = ?1)
+              AND (?2 IS NULL OR namespace = ?2)
+              AND recorded_at_ms >= ?3 AND recorded_at_ms < ?4
+            ORDER BY recorded_at_ms DESC, rowid DESC
+            LIMIT ?5
+            "#,
+        )
+        .bind(filter.vm_id.map(|id| id.to_string()))
+        .bind(&filter.namespace)
+        .bind(filter.since_ms.unwrap_or(i64::MIN))
+        .bind(filter.until_ms.unwrap_or(i64::MAX))
+        .bind(filter.limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .rev()
+            .map(|row| Ok((VmEvent::decode(&*row.event_blob)?, row.recorded_at_ms)))
+            .collect()
+    }
+
+    /// Replays the journal of VM state transitions, which the database
+    /// appends to whenever a VM is created, changes state or is deleted.
+    /// Returns up to `limit` entries after `after_sequence`, oldest first.
+    pub async fn replay_vm_state_journal(
+        &self,
+        vm_id: Option<Uuid>,
+        after_sequence: i64,
+        limit: i64,
+    ) -> Result<Vec<VmJournalEntry>, PersistenceError> {
+        let rows = sqlx::query_as::<_, DbJournalRow>(
+            r#"
+            SELECT sequence, vm_id, namespace, recorded_at_ms, old_state, new_state, message
+            FROM vm_state_journal
+            WHERE (?1 IS NULL OR vm_id = ?1) AND sequence > ?2
+            ORDER BY sequence
+            LIMIT ?3
+            "#,
+        )
+        .bind(vm_id)
+        .bind(after_sequence)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(journal_entry_from_row).collect()
+    }
+
+    /// Records that a device is passed through to a VM. Returns false if it
+    /// is passed through to a VM already.
+    pub async fn assign_pci_device(
+        &self,
+        assignment: &PciDeviceAssignment,
+    ) -> Result<bool, PersistenceError> {
+        let result = retry_busy(|| {
+            sqlx::query(
+                r#"
+            INSERT OR IGNORE INTO pci_device_assignments (pci_address, vm_id, original_driver)
+            VALUES (?1, ?2, ?3)
+            "#,
+            )
+            .bind(&assignment.pci_address)
+            .bind(assignment.vm_id)
+            .bind(&assignment.original_driver)
+            .execute(&self.pool)
+        })
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn get_pci_device_assignment(
+        &self,
+        pci_address: &str,
+    ) -> Result<Option<PciDeviceAssignment>, PersistenceError> {
+        let row = sqlx::query_as::<_, DbPciDeviceAssignmentRow>(
+            "SELECT pci_address, vm_id, original_driver FROM pci_device_assignments WHERE pci_address = ?1",
+        )
+        .bind(pci_address)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(pci_device_assignment_from_row))
+    }
+
+    /// Lists the devices passed through to `vm_id`, or to any VM.
+    pub async fn list_pci_device_assignments(
+        &self,
+        vm_id: Option<Uuid>,
+    ) -> Result<Vec<PciDeviceAssignment>, PersistenceError> {
+        let rows = sqlx::query_as::<_, DbPciDeviceAssignmentRow>(
+            r#"
+            SELECT pci_address, vm_id, original_driver FROM pci_device_assignments
+            WHERE ?1 IS NULL OR vm_id = ?1
+            ORDER BY pci_address
+            "#,
+        )
+        .bind(vm_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(pci_device_assignment_from_row)
+            .collect())
+    }
+
+    /// Removes the record of a device passed through to `vm_id`. Records of
+    /// other VMs are kept.
+    pub async fn unassign_pci_device(
+        &self,
+        pci_address: &str,
+        vm_id: Uuid,
+    ) -> Result<(), PersistenceError> {
+        retry_busy(|| {
+            sqlx::query("DELETE FROM pci_device_assignments WHERE pci_address = ?1 AND vm_id = ?2")
+                .bind(pci_address)
+                .bind(vm_id)
+                .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Records the overlay of a disk, replacing an earlier one of the disk.
+    pub async fn save_disk_overlay(&self, overlay: &DiskOverlay) -> Result<(), PersistenceError> {
+        retry_busy(|| {
+            sqlx::query(
+                r#"
+            INSERT OR REPLACE INTO disk_overlays (vm_id, device_id, base_path, overlay_path, format)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            "#,
+            )
+            .bind(overlay.vm_id)
+            .bind(&overlay.device_id)
+            .bind(&overlay.base_path)
+            .bind(&overlay.overlay_path)
+            .bind(overlay.format.as_str_name())
+            .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_disk_overlay(
+        &self,
+        vm_id: Uuid,
+        device_id: &str,
+    ) -> Result<Option<DiskOverlay>, PersistenceError> {
+        let row = sqlx::query_as::<_, DbDiskOverlayRow>(
+            r#"
+            SELECT vm_id, device_id, base_path, overlay_path, format FROM disk_overlays
+            WHERE vm_id = ?1 AND device_id = ?2
+            "#,
+        )
+        .bind(vm_id)
+        .bind(device_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(disk_overlay_from_row).transpose()
+    }
+
+    /// Lists the overlays of the disks of `vm_id`, or of any VM.
+    pub async fn list_disk_overlays(
+        &self,
+        vm_id: Option<Uuid>,
+    ) -> Result<Vec<DiskOverlay>, PersistenceError> {
+        let rows = sqlx::query_as::<_, DbDiskOverlayRow>(
+            r#"
+            SELECT vm_id, device_id, base_path, overlay_path, format FROM disk_overlays
+            WHERE ?1 IS NULL OR vm_id = ?1
+            ORDER BY vm_id, device_id
+            "#,
+        )
+        .bind(vm_id)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.into_iter().map(disk_overlay_from_row).collect()
+    }
+
+    pub async fn delete_disk_overlay(
+        &self,
+        vm_id: Uuid,
+        device_id: &str,
+    ) -> Result<(), PersistenceError> {
+        retry_busy(|| {
+            sqlx::query("DELETE FROM disk_overlays WHERE vm_id = ?1 AND device_id = ?2")
+                .bind(vm_id)
+                .bind(device_id)
+                .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
 }
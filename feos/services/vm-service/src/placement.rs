@@ -0,0 +1,355 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Placement of VMs on the NUMA nodes of the host, see PlacementConfig.
+
+use crate::{error::VmServiceError, persistence::VmRecord};
+use feos_proto::vm_service::{disk_config, net_config, PlacementPolicy, VmConfig};
+use feos_utils::host::mdev::Mdev;
+use feos_utils::host::numa::{self, NumaNode};
+use log::{info, warn};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use uuid::Uuid;
+
+/// A device of a VM and the node it is attached to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceNode {
+    pub device: String,
+    pub node: u32,
+}
+
+/// The devices of a VM that are attached to a node. Devices whose node
+/// cannot be read are left out, they fail the VM elsewhere if they are
+/// missing.
+pub fn device_nodes(config: &VmConfig, mdevs: &[Mdev]) -> Vec<DeviceNode> {
+    let mut lookups: Vec<(String, io::Result<Option<u32>>)> = Vec::new();
+    for disk in &config.disks {
+        if let Some(disk_config::Backend::VfioPci(pci)) = &disk.backend {
+            lookups.push((pci.bdf.clone(), numa::pci_device_node(&pci.bdf)));
+        }
+    }
+    for nic in &config.net {
+        match &nic.backend {
+            Some(net_config::Backend::VfioPci(pci)) => {
+                lookups.push((pci.bdf.clone(), numa::pci_device_node(&pci.bdf)));
+            }
+            Some(net_config::Backend::Macvtap(macvtap)) => {
+                lookups.push((
+                    macvtap.parent.clone(),
+                    numa::interface_node(&macvtap.parent),
+                ));
+            }
+            _ => {}
+        }
+    }
+    for gpu in &config.gpus {
+        lookups.push((
+            gpu.pci_address.clone(),
+            numa::pci_device_node(&gpu.pci_address),
+        ));
+    }
+    for device in &config.pci_devices {
+        lookups.push((
+            device.pci_address.clone(),
+            numa::pci_device_node(&device.pci_address),
+        ));
+    }
+    for config in &config.mdevs {
+        // Mediated devices share the node of their parent device, if that is
+        // a PCI device.
+        if let Some(mdev) = mdevs.iter().find(|mdev| mdev.uuid == config.uuid) {
+            lookups.push((config.uuid.clone(), numa::pci_device_node(&mdev.parent)));
+        }
+    }
+    lookups
+        .into_iter()
+        .filter_map(|(device, node)| match node {
+            Ok(node) => node.map(|node| DeviceNode { device, node }),
+            Err(e) => {
+                warn!("VmPlacement: Cannot tell the NUMA node of {device}: {e}");
+                None
+            }
+        })
+        .collect()
+}
+
+fn vcpus(config: &VmConfig) -> u32 {
+    config
+        .cpus
+        .as_ref()
+        .map_or(0, |cpus| cpus.max_vcpus.max(cpus.boot_vcpus))
+}
+
+/// The vCPUs of the VMs bound to `node`.
+fn node_load(others: &[VmRecord], node: u32) -> u32 {
+    others
+        .iter()
+        .filter(|record| {
+            record
+                .config
+                .placement
+                .as_ref()
+                .and_then(|placement| placement.assigned_numa_node)
+                == Some(node)
+        })
+        .map(|record| vcpus(&record.config))
+        .sum()
+}
+
+/// The node most devices are attached to, the lowest on a tie.
+fn device_node(devices: &[DeviceNode]) -> Option<u32> {
+    let mut counts: BTreeMap<u32, usize> = BTreeMap::new();
+    for device in devices {
+        *counts.entry(device.node).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .max_by_key(|&(node, count)| (count, Reverse(node)))
+        .map(|(node, _)| node)
+}
+
+/// Picks the node to bind a VM to, none if it is not bound to a node.
+/// `others` are the other VMs of the host.
+pub fn place(
+    config: &VmConfig,
+    nodes: &[NumaNode],
+    devices: &[DeviceNode],
+    others: &[VmRecord],
+) -> Result<Option<u32>, VmServiceError> {
+    let placement = config.placement.clone().unwrap_or_default();
+    let policy = placement.policy();
+    if policy == PlacementPolicy::None {
+        return Ok(None);
+    }
+    let anti_affinity = placement
+        .anti_affinity
+        .iter()
+        .map(|vm_id| {
+            Uuid::parse_str(vm_id).map_err(|_| {
+                VmServiceError::InvalidArgument(format!(
+                    "Anti-affinity VM '{vm_id}' is not a valid UUID"
+                ))
+            })
+        })
+        .collect::<Result<HashSet<Uuid>, _>>()?;
+    if let Some(node) = placement.numa_node {
+        if !nodes.iter().any(|n| n.id == node) {
+            return Err(VmServiceError::InvalidArgument(format!(
+                "NUMA node {node} does not exist on the host or has no CPUs"
+            )));
+        }
+    }
+    // Everything is local on hosts with a single node.
+    if nodes.len() < 2 {
+        return Ok(None);
+    }
+    let required = policy == PlacementPolicy::Required;
+    let unsatisfiable = |reason: String| {
+        if required {
+            Err(VmServiceError::InvalidState(format!(
+                "The required placement cannot be met: {reason}"
+            )))
+        } else {
+            warn!("VmPlacement: {reason}");
+            Ok(())
+        }
+    };
+
+    let device_nodes: HashSet<u32> = devices.iter().map(|device| device.node).collect();
+    if device_nodes.len() > 1 {
+        let devices: Vec<String> = devices
+            .iter()
+            .map(|device| format!("{} on node {}", device.device, device.node))
+            .collect();
+        unsatisfiable(format!(
+            "the devices are on different NUMA nodes: {}",
+            devices.join(", ")
+        ))?;
+    }
+    let avoided: HashSet<u32> = others
+        .iter()
+        .filter(|record| anti_affinity.contains(&record.vm_id))
+        .filter_map(|record| record.config.placement.as_ref()?.assigned_numa_node)
+        .collect();
+
+    let node = if let Some(node) = placement.numa_node {
+        if let Some(device_node) = device_node(devices).filter(|&device| device != node) {
+            unsatisfiable(format!(
+                "NUMA node {node} was requested, but the devices are on node {device_node}"
+            ))?;
+        }
+        node
+    } else if let Some(node) = device_node(devices) {
+        node
+    } else if !anti_affinity.is_empty() {
+        let allowed: Vec<&NumaNode> = nodes
+            .iter()
+            .filter(|node| !avoided.contains(&node.id))
+            .collect();
+        let candidates = if allowed.is_empty() {
+            unsatisfiable("the VMs of the anti-affinity use all NUMA nodes".to_string())?;
+            nodes.iter().collect()
+        } else {
+            allowed
+        };
+        candidates
+            .into_iter()
+            .min_by_key(|node| (node_load(others, node.id), node.id))
+            .map(|node| node.id)
+            .expect("hosts with several nodes have candidates")
+    } else {
+        return Ok(None);
+    };
+    if avoided.contains(&node) && (placement.numa_node.is_some() || !devices.is_empty()) {
+        unsatisfiable(format!(
+            "NUMA node {node} is used by a VM of the anti-affinity"
+        ))?;
+    }
+    info!(
+        "VmPlacement: Placing VM on NUMA node {node} with {} vCPUs placed on it",
+        node_load(others, node)
+    );
+    Ok(Some(node))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence::VmStatus;
+    use feos_proto::vm_service::{CpuConfig, PlacementConfig, VmState};
+
+    fn nodes() -> Vec<NumaNode> {
+        (0..2)
+            .map(|id| NumaNode {
+                id,
+                cpus: (id * 8..id * 8 + 8).collect(),
+            })
+            .collect()
+    }
+
+    fn config(placement: PlacementConfig) -> VmConfig {
+        VmConfig {
+            cpus: Some(CpuConfig {
+                boot_vcpus: 4,
+                max_vcpus: 4,
+            }),
+            placement: Some(placement),
+            ..Default::default()
+        }
+    }
+
+    fn record(vm_id: Uuid, node: u32) -> VmRecord {
+        VmRecord {
+            vm_id,
+            namespace: String::new(),
+            name: None,
+            image_uuid: Uuid::nil(),
+            status: VmStatus {
+                state: VmState::Running,
+                last_msg: String::new(),
+                process_id: None,
+            },
+            config: config(PlacementConfig {
+                assigned_numa_node: Some(node),
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn device(device: &str, node: u32) -> DeviceNode {
+        DeviceNode {
+            device: device.to_string(),
+            node,
+        }
+    }
+
+    #[test]
+    fn vms_follow_their_devices_and_hints() {
+        let preferred = config(PlacementConfig::default());
+        assert_eq!(place(&preferred, &nodes(), &[], &[]).unwrap(), None);
+        assert_eq!(
+            place(&preferred, &nodes(), &[device("0000:41:00.0", 1)], &[]).unwrap(),
+            Some(1)
+        );
+        assert_eq!(
+            place(&preferred, &nodes()[..1], &[device("gpu", 0)], &[]).unwrap(),
+            None
+        );
+
+        let split = [
+            device("0000:41:00.0", 1),
+            device("0000:03:00.0", 0),
+            device("eth1", 1),
+        ];
+        assert_eq!(place(&preferred, &nodes(), &split, &[]).unwrap(), Some(1));
+        let required = config(PlacementConfig {
+            policy: PlacementPolicy::Required as i32,
+            ..Default::default()
+        });
+        assert!(matches!(
+            place(&required, &nodes(), &split, &[]),
+            Err(VmServiceError::InvalidState(_))
+        ));
+
+        let explicit = config(PlacementConfig {
+            numa_node: Some(0),
+            ..Default::default()
+        });
+        assert_eq!(
+            place(&explicit, &nodes(), &[device("gpu", 1)], &[]).unwrap(),
+            Some(0)
+        );
+        let missing = config(PlacementConfig {
+            numa_node: Some(3),
+            ..Default::default()
+        });
+        assert!(matches!(
+            place(&missing, &nodes(), &[], &[]),
+            Err(VmServiceError::InvalidArgument(_))
+        ));
+        let unbound = config(PlacementConfig {
+            policy: PlacementPolicy::None as i32,
+            ..Default::default()
+        });
+        assert_eq!(
+            place(&unbound, &nodes(), &[device("gpu", 1)], &[]).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn anti_affinity_spreads_vms_over_nodes() {
+        let (replica, busy) = (Uuid::new_v4(), Uuid::new_v4());
+        let others = [
+            record(replica, 0),
+            record(busy, 1),
+            record(Uuid::new_v4(), 1),
+        ];
+        let spread = |policy: PlacementPolicy, anti_affinity: Vec<Uuid>| {
+            config(PlacementConfig {
+                policy: policy as i32,
+                anti_affinity: anti_affinity.iter().map(Uuid::to_string).collect(),
+                ..Default::default()
+            })
+        };
+
+        // Node 1 is busier, but node 0 has the replica.
+        let vm = spread(PlacementPolicy::Preferred, vec![replica]);
+        assert_eq!(place(&vm, &nodes(), &[], &others).unwrap(), Some(1));
+        // Without a free node, the least busy one is taken.
+        let vm = spread(PlacementPolicy::Preferred, vec![replica, busy]);
+        assert_eq!(place(&vm, &nodes(), &[], &others).unwrap(), Some(0));
+        let vm = spread(PlacementPolicy::Required, vec![replica, busy]);
+        assert!(place(&vm, &nodes(), &[], &others).is_err());
+        // Devices win over the anti-affinity unless it is required.
+        let vm = spread(PlacementPolicy::Preferred, vec![replica]);
+        assert_eq!(
+            place(&vm, &nodes(), &[device("gpu", 0)], &others).unwrap(),
+            Some(0)
+        );
+        let vm = spread(PlacementPolicy::Required, vec![replica]);
+        assert!(place(&vm, &nodes(), &[device("gpu", 0)], &others).is_err());
+    }
+}
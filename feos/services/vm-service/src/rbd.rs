@@ -0,0 +1,218 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    error::VmServiceError,
+    storage_daemon::{self, KEY_SECRET_ID},
+};
+use feos_proto::vm_service::RbdConfig;
+use log::info;
+use serde_json::json;
+use std::path::PathBuf;
+
+const DEFAULT_USER: &str = "admin";
+const DEFAULT_MON_PORT: &str = "6789";
+
+#[derive(Debug, PartialEq)]
+struct ImageSpec<'a> {
+    pool: &'a str,
+    namespace: Option<&'a str>,
+    image: &'a str,
+}
+
+fn parse_image_spec(spec: &str) -> Option<ImageSpec<'_>> {
+    let parts: Vec<&str> = spec.split('/').collect();
+    if parts.iter().any(|part| part.is_empty()) {
+        return None;
+    }
+    match parts[..] {
+        [pool, image] => Some(ImageSpec {
+            pool,
+            namespace: None,
+            image,
+        }),
+        [pool, namespace, image] => Some(ImageSpec {
+            pool,
+            namespace: Some(namespace),
+            image,
+        }),
+        _ => None,
+    }
+}
+
+/// Splits a monitor address into host and port. IPv6 addresses may be given
+/// with or without brackets.
+fn parse_mon_host(mon_host: &str) -> (String, String) {
+    let mon_host = mon_host.trim();
+    if let Some(rest) = mon_host.strip_prefix('[') {
+        if let Some((host, port)) = rest.split_once("]:") {
+            return (host.to_string(), port.to_string());
+        }
+        return (
+            rest.trim_end_matches(']').to_string(),
+            DEFAULT_MON_PORT.to_string(),
+        );
+    }
+    match mon_host.split_once(':') {
+        Some((host, port)) if !port.contains(':') => (host.to_string(), port.to_string()),
+        _ => (mon_host.to_string(), DEFAULT_MON_PORT.to_string()),
+    }
+}
+
+/// Extracts the base64 cephx key from a keyring. A bare key is returned as is.
+pub fn parse_key(keyring: &str) -> Option<String> {
+    let keyring = keyring.trim();
+    if keyring.is_empty() {
+        return None;
+    }
+    if !keyring.contains(char::is_whitespace) && !keyring.starts_with('[') {
+        return Some(keyring.to_string());
+    }
+    keyring.lines().find_map(|line| {
+        let value = line
+            .trim()
+            .strip_prefix("key")?
+            .trim_start()
+            .strip_prefix('=')?;
+        Some(value.trim().to_string()).filter(|key| !key.is_empty())
+    })
+}
+
+pub fn validate(config: &RbdConfig) -> Result<(), VmServiceError> {
+    if parse_image_spec(&config.image_spec).is_none() {
+        return Err(VmServiceError::InvalidArgument(format!(
+            "Invalid RBD image spec '{}', expected pool/image or pool/namespace/image",
+            config.image_spec
+        )));
+    }
+    if config.mon_hosts.iter().all(|host| host.trim().is_empty()) {
+        return Err(VmServiceError::InvalidArgument(
+            "At least one Ceph monitor is required".to_string(),
+        ));
+    }
+    if parse_key(&config.keyring).is_none() {
+        return Err(VmServiceError::InvalidArgument(
+            "The RBD keyring does not contain a key".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Removes the keyring, which is only handed to the storage daemon.
+pub fn strip_secrets(config: &mut RbdConfig) {
+    config.keyring.clear();
+}
+
+fn blockdev_options(config: &RbdConfig) -> Result<serde_json::Value, VmServiceError> {
+    let spec = parse_image_spec(&config.image_spec).ok_or_else(|| {
+        VmServiceError::InvalidArgument(format!("Invalid RBD image spec '{}'", config.image_spec))
+    })?;
+    let servers: Vec<_> = config
+        .mon_hosts
+        .iter()
+        .filter(|host| !host.trim().is_empty())
+        .map(|host| {
+            let (host, port) = parse_mon_host(host);
+            json!({ "host": host, "port": port })
+        })
+        .collect();
+    let user = if config.user.is_empty() {
+        DEFAULT_USER
+    } else {
+        config.user.as_str()
+    };
+    let mut options = json!({
+        "driver": "rbd",
+        "pool": spec.pool,
+        "image": spec.image,
+        "user": user,
+        "server": servers,
+        "key-secret": KEY_SECRET_ID,
+        "auth-client-required": ["cephx"],
+        "cache": { "direct": true },
+    });
+    if let Some(namespace) = spec.namespace {
+        options["namespace"] = json!(namespace);
+    }
+    Ok(options)
+}
+
+/// Starts a storage daemon serving the image over vhost-user-blk and returns
+/// the path of its socket.
+pub async fn start_export(
+    vm_id: &str,
+    device_id: &str,
+    config: &RbdConfig,
+    readonly: bool,
+    discard: bool,
+) -> Result<PathBuf, VmServiceError> {
+    let key = parse_key(&config.keyring).ok_or_else(|| {
+        VmServiceError::InvalidArgument("The RBD keyring does not contain a key".to_string())
+    })?;
+    let blockdev = blockdev_options(config)?;
+    let socket_path =
+        storage_daemon::start_export(vm_id, device_id, blockdev, Some(&key), readonly, discard)
+            .await?;
+    info!(
+        "RBD: Serving {} as disk '{device_id}' of VM {vm_id}",
+        config.image_spec
+    );
+    Ok(socket_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_specs_with_and_without_namespace() {
+        assert_eq!(
+            parse_image_spec("rbd/vm-disk"),
+            Some(ImageSpec {
+                pool: "rbd",
+                namespace: None,
+                image: "vm-disk"
+            })
+        );
+        assert_eq!(
+            parse_image_spec("rbd/tenant-a/vm-disk").map(|spec| spec.namespace),
+            Some(Some("tenant-a"))
+        );
+        assert_eq!(parse_image_spec("vm-disk"), None);
+        assert_eq!(parse_image_spec("rbd//vm-disk"), None);
+    }
+
+    #[test]
+    fn keys_are_read_from_keyrings() {
+        let keyring = "[client.feos]\n\tkey = AQBsYXJ0aGVrZXkAAAAAAAAAAAAAAAAAAAAAAA==\n\tcaps mon = \"profile rbd\"\n";
+        assert_eq!(
+            parse_key(keyring).as_deref(),
+            Some("AQBsYXJ0aGVrZXkAAAAAAAAAAAAAAAAAAAAAAA==")
+        );
+        assert_eq!(
+            parse_key(" AQBsYXJ0aGVrZXk= ").as_deref(),
+            Some("AQBsYXJ0aGVrZXk=")
+        );
+        assert_eq!(parse_key("[client.feos]\n"), None);
+    }
+
+    #[test]
+    fn blockdev_lists_all_monitors() {
+        let config = RbdConfig {
+            image_spec: "rbd/vm-disk".to_string(),
+            mon_hosts: vec!["10.0.0.1".to_string(), "[fd00::1]:3300".to_string()],
+            user: String::new(),
+            keyring: String::new(),
+        };
+        let options = blockdev_options(&config).unwrap();
+        assert_eq!(options["user"], "admin");
+        assert_eq!(
+            options["server"],
+            json!([
+                { "host": "10.0.0.1", "port": "6789" },
+                { "host": "fd00::1", "port": "3300" }
+            ])
+        );
+        assert!(options.get("namespace").is_none());
+    }
+}
@@ -0,0 +1,751 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Asynchronous replication of VM disks to a peer FeOS node, a cold standby
+//! for VMs that cannot be migrated. The sender copies the disks like for a
+//! snapshot, hashes them in blocks and sends the blocks whose hash changed
+//! since the last replication to the peer. The peer writes them into a copy
+//! of its replica, which replaces the replica once all blocks arrived.
+//!
+//! Overlays are replicated without their base image, which the peer needs to
+//! have pulled as well for a VM to be created from the replica.
+
+use crate::{
+    disk_image, error::VmServiceError, persistence::VmRecord, snapshot,
+    storage_daemon::flatten_device_id, vmm::Hypervisor, VM_REPLICATION_DIR, VM_REPLICA_DIR,
+};
+use feos_proto::auth::SigningChannel;
+use feos_proto::vm_service::{
+    receive_disk_replica_request::Payload, vm_service_client::VmServiceClient, DiskReplica,
+    DiskReplicaBlock, DiskReplicaHeader, DiskReplicationResult, PauseVmRequest,
+    ReceiveDiskReplicaRequest, ReceiveDiskReplicaResponse, ReplicateVmDisksResponse,
+    ResumeVmRequest, VmState,
+};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::io::{ErrorKind, Read, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity};
+use tonic::{Code, Status, Streaming};
+use uuid::Uuid;
+
+/// The size of the blocks disks are compared and sent in, well below the
+/// message size limit of gRPC.
+pub const BLOCK_SIZE: usize = 1024 * 1024;
+const DIGEST_LEN: usize = 32;
+
+type PeerClient = VmServiceClient<SigningChannel<Channel>>;
+
+/// How this node talks to its peers.
+#[derive(Clone, Default)]
+pub struct ReplicationConfig {
+    /// Stamped on the replicas, see HostService.GetNodeIdentity.
+    pub node_id: String,
+    /// The API token requests to peers are signed with.
+    pub token: Option<Vec<u8>>,
+    /// The certificate of the CA that issues the certificates of peers
+    /// serving their API with TLS.
+    pub ca_cert: Option<Vec<u8>>,
+    /// The PEM files of the certificate this node got from the CA and its
+    /// key, presented to those peers. Read on every connection, as they are
+    /// renewed.
+    pub client_cert: Option<PathBuf>,
+    /// The key of `client_cert`.
+    pub client_key: Option<PathBuf>,
+}
+
+/// What the sender remembers about the replica of a disk on a peer.
+#[derive(Serialize, Deserialize)]
+struct SentState {
+    generation: String,
+    size_bytes: u64,
+}
+
+/// What the receiver keeps next to a replica.
+#[derive(Serialize, Deserialize)]
+struct ReplicaMetadata {
+    vm_id: String,
+    device_id: String,
+    source_node_id: String,
+    size_bytes: u64,
+    format: i32,
+    generation: String,
+    updated_at_ms: i64,
+}
+
+/// The outcome of reading a disk for a replication.
+struct ReadBlocks {
+    sums: Vec<u8>,
+    blocks_sent: u64,
+    bytes_sent: u64,
+}
+
+/// Marks a replication, sent or received, as running until it is dropped.
+struct Busy {
+    paths: Arc<Mutex<HashSet<PathBuf>>>,
+    path: PathBuf,
+}
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        self.paths.lock().unwrap().remove(&self.path);
+    }
+}
+
+#[derive(Clone)]
+pub struct Replication {
+    config: Arc<ReplicationConfig>,
+    state_dir: PathBuf,
+    replica_dir: PathBuf,
+    busy: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl Replication {
+    pub fn new(config: ReplicationConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            state_dir: PathBuf::from(VM_REPLICATION_DIR),
+            replica_dir: PathBuf::from(VM_REPLICA_DIR),
+            busy: Arc::default(),
+        }
+    }
+
+    fn claim(&self, path: &Path) -> Result<Busy, VmServiceError> {
+        if !self.busy.lock().unwrap().insert(path.to_path_buf()) {
+            return Err(VmServiceError::InvalidState(format!(
+                "A replication of {} is already in progress",
+                path.display()
+            )));
+        }
+        Ok(Busy {
+            paths: self.busy.clone(),
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Replicates the disks of a VM to `peer`. `disks` are the `(device_id,
+    /// path)` pairs of the disks to replicate; a running VM is paused while
+    /// they are copied.
+    pub async fn replicate(
+        self,
+        record: VmRecord,
+        peer: String,
+        disks: Vec<(String, PathBuf)>,
+        hypervisor: Arc<dyn Hypervisor>,
+    ) -> Result<ReplicateVmDisksResponse, VmServiceError> {
+        let vm_id = record.vm_id.to_string();
+        let dir = self.state_dir.join(&vm_id).join(peer_dir_name(&peer));
+        let _busy = self.claim(&dir)?;
+        let mut client = self.connect(&peer).await?;
+
+        let staging = dir.join("staging");
+        fs::create_dir_all(&staging).await.map_err(|e| {
+            VmServiceError::Replication(format!("Cannot create {}: {e}", staging.display()))
+        })?;
+        let result = async {
+            let pause = record.status.state == VmState::Running;
+            let staged = stage_disks(&vm_id, &disks, &staging, pause, hypervisor.as_ref()).await?;
+            let mut results = Vec::with_capacity(staged.len());
+            for (device_id, path) in staged {
+                let result = self
+                    .replicate_disk(&mut client, &vm_id, &device_id, &path, &dir)
+                    .await?;
+                info!(
+                    "Replication ({vm_id}): Sent {} blocks ({} bytes) of disk '{device_id}' to {peer}",
+                    result.blocks_sent, result.bytes_sent
+                );
+                results.push(result);
+            }
+            Ok(ReplicateVmDisksResponse { disks: results })
+        }
+        .await;
+        if let Err(e) = fs::remove_dir_all(&staging).await {
+            warn!("Replication: Failed to remove {}: {e}", staging.display());
+        }
+        result
+    }
+
+    async fn connect(&self, peer: &str) -> Result<PeerClient, VmServiceError> {
+        let mut endpoint = Endpoint::from_shared(peer.to_string()).map_err(|e| {
+            VmServiceError::InvalidArgument(format!("Invalid peer address '{peer}': {e}"))
+        })?;
+        if peer.starts_with("https://") {
+            let Some(ca_cert) = &self.config.ca_cert else {
+                return Err(VmServiceError::InvalidArgument(format!(
+                    "Cannot verify the certificate of {peer} without the certificate of the CA"
+                )));
+            };
+            let mut tls = ClientTlsConfig::new().ca_certificate(Certificate::from_pem(ca_cert));
+            if let Some(identity) = self.client_identity().await? {
+                tls = tls.identity(identity);
+            }
+            endpoint = endpoint.tls_config(tls).map_err(|e| {
+                VmServiceError::Replication(format!("Invalid TLS configuration: {e}"))
+            })?;
+        }
+        let channel = endpoint.connect().await.map_err(|e| {
+            VmServiceError::Replication(format!("Cannot connect to peer {peer}: {e}"))
+        })?;
+        Ok(VmServiceClient::new(SigningChannel::new(
+            channel,
+            self.config.token.clone(),
+        )))
+    }
+
+    /// The certificate from the CA to present to peers, if there is one.
+    async fn client_identity(&self) -> Result<Option<Identity>, VmServiceError> {
+        let (Some(cert_path), Some(key_path)) = (&self.config.client_cert, &self.config.client_key)
+        else {
+            return Ok(None);
+        };
+        Ok(Some(Identity::from_pem(
+            read_pem(cert_path).await?,
+            read_pem(key_path).await?,
+        )))
+    }
+
+    /// Sends the blocks of a staged disk that changed since the last
+    /// replication, or all of them if the peer does not have that replica.
+    async fn replicate_disk(
+        &self,
+        client: &mut PeerClient,
+        vm_id: &str,
+        device_id: &str,
+        path: &Path,
+        dir: &Path,
+    ) -> Result<DiskReplicationResult, VmServiceError> {
+        let name = flatten_device_id(device_id);
+        let state_path = dir.join(format!("{name}.json"));
+        let sums_path = dir.join(format!("{name}.sums"));
+        let size_bytes = fs::metadata(path)
+            .await
+            .map_err(|e| {
+                VmServiceError::Replication(format!("Cannot access {}: {e}", path.display()))
+            })?
+            .len();
+        let format = disk_image::inspect(path).await?.format;
+
+        let mut previous = load_sent_state(&state_path, &sums_path).await;
+        let generation = Uuid::new_v4().to_string();
+        let (read, full) = loop {
+            let base_generation = previous
+                .as_ref()
+                .map(|(state, _)| state.generation.clone())
+                .unwrap_or_default();
+            let header = DiskReplicaHeader {
+                vm_id: vm_id.to_string(),
+                device_id: device_id.to_string(),
+                source_node_id: self.config.node_id.clone(),
+                size_bytes,
+                format: format as i32,
+                base_generation,
+                generation: generation.clone(),
+            };
+            let sums = previous.as_ref().map(|(_, sums)| sums.clone());
+            match send_disk(client, header, path, sums).await? {
+                Ok(read) => break (read, previous.is_none()),
+                Err(status) if status.code() == Code::FailedPrecondition && previous.is_some() => {
+                    info!(
+                        "Replication ({vm_id}): The peer does not have the last replica of disk '{device_id}', sending all blocks: {}",
+                        status.message()
+                    );
+                    previous = None;
+                }
+                Err(status) => {
+                    return Err(VmServiceError::Replication(format!(
+                        "The peer did not take disk '{device_id}': {}",
+                        status.message()
+                    )))
+                }
+            }
+        };
+
+        let state = SentState {
+            generation,
+            size_bytes,
+        };
+        let write_state = async {
+            fs::write(&sums_path, &read.sums).await?;
+            write_json(&state_path, &state).await
+        };
+        write_state.await.map_err(|e| {
+            VmServiceError::Replication(format!(
+                "Cannot keep the replication state of disk '{device_id}': {e}"
+            ))
+        })?;
+        Ok(DiskReplicationResult {
+            device_id: device_id.to_string(),
+            size_bytes,
+            blocks_sent: read.blocks_sent,
+            bytes_sent: read.bytes_sent,
+            full,
+        })
+    }
+
+    /// Writes the blocks of a disk replicated by a peer into a copy of its
+    /// replica and replaces the replica with it.
+    pub async fn receive(
+        self,
+        mut stream: Streaming<ReceiveDiskReplicaRequest>,
+    ) -> Result<ReceiveDiskReplicaResponse, VmServiceError> {
+        let header = match stream.message().await.map_err(stream_error)? {
+            Some(ReceiveDiskReplicaRequest {
+                payload: Some(Payload::Header(header)),
+            }) => header,
+            _ => {
+                return Err(VmServiceError::InvalidArgument(
+                    "The first message must be a header".to_string(),
+                ))
+            }
+        };
+        let vm_id = Uuid::parse_str(&header.vm_id)
+            .map_err(|_| VmServiceError::InvalidArgument("Invalid VM ID format.".to_string()))?;
+        if header.device_id.is_empty() || header.generation.is_empty() {
+            return Err(VmServiceError::InvalidArgument(
+                "The header must name the disk and the generation of the replica".to_string(),
+            ));
+        }
+
+        let dir = self.replica_dir.join(vm_id.to_string());
+        let name = flatten_device_id(&header.device_id);
+        let image = dir.join(format!("{name}.img"));
+        let metadata_path = dir.join(format!("{name}.json"));
+        let _busy = self.claim(&image)?;
+
+        let current = read_json::<ReplicaMetadata>(&metadata_path).await;
+        if !header.base_generation.is_empty() {
+            let current_generation = current.as_ref().map(|m| m.generation.as_str());
+            if current_generation != Some(header.base_generation.as_str()) {
+                return Err(VmServiceError::InvalidState(format!(
+                    "The replica of disk '{}' is not at generation {}",
+                    header.device_id, header.base_generation
+                )));
+            }
+        }
+
+        fs::create_dir_all(&dir).await.map_err(|e| {
+            VmServiceError::Replication(format!("Cannot create {}: {e}", dir.display()))
+        })?;
+        let partial = dir.join(format!("{name}.partial"));
+        let written = match write_replica(&header, &image, &partial, &mut stream).await {
+            Ok(written) => written,
+            Err(e) => {
+                fs::remove_file(&partial).await.ok();
+                return Err(e);
+            }
+        };
+        fs::rename(&partial, &image).await.map_err(|e| {
+            VmServiceError::Replication(format!("Cannot replace {}: {e}", image.display()))
+        })?;
+        let metadata = ReplicaMetadata {
+            vm_id: vm_id.to_string(),
+            device_id: header.device_id.clone(),
+            source_node_id: header.source_node_id.clone(),
+            size_bytes: header.size_bytes,
+            format: header.format,
+            generation: header.generation.clone(),
+            updated_at_ms: now_ms(),
+        };
+        write_json(&metadata_path, &metadata).await.map_err(|e| {
+            VmServiceError::Replication(format!("Cannot write {}: {e}", metadata_path.display()))
+        })?;
+        info!(
+            "Replication ({vm_id}): Received {written} bytes of disk '{}' from node {}",
+            header.device_id, header.source_node_id
+        );
+        Ok(ReceiveDiskReplicaResponse {
+            bytes_written: written,
+        })
+    }
+
+    /// The replicas this node keeps, of the disks of `vm_id` or of all VMs.
+    pub async fn list(&self, vm_id: Option<&str>) -> Result<Vec<DiskReplica>, VmServiceError> {
+        let vm_dirs = match vm_id {
+            Some(vm_id) => vec![self.replica_dir.join(parse_vm_id(vm_id)?.to_string())],
+            None => list_dir(&self.replica_dir).await,
+        };
+        let mut replicas = Vec::new();
+        for dir in vm_dirs {
+            for path in list_dir(&dir).await {
+                if path.extension().is_none_or(|extension| extension != "json") {
+                    continue;
+                }
+                let Some(metadata) = read_json::<ReplicaMetadata>(&path).await else {
+                    warn!("Replication: Ignoring unreadable {}", path.display());
+                    continue;
+                };
+                replicas.push(DiskReplica {
+                    vm_id: metadata.vm_id,
+                    device_id: metadata.device_id,
+                    source_node_id: metadata.source_node_id,
+                    size_bytes: metadata.size_bytes,
+                    format: metadata.format,
+                    generation: metadata.generation,
+                    path: path.with_extension("img").to_string_lossy().into_owned(),
+                    updated_at: Some(prost_types::Timestamp {
+                        seconds: metadata.updated_at_ms.div_euclid(1000),
+                        nanos: (metadata.updated_at_ms.rem_euclid(1000) * 1_000_000) as i32,
+                    }),
+                });
+            }
+        }
+        replicas.sort_by(|a, b| (&a.vm_id, &a.device_id).cmp(&(&b.vm_id, &b.device_id)));
+        Ok(replicas)
+    }
+
+    /// Deletes the replicas of the disks of `vm_id`.
+    pub async fn delete(&self, vm_id: &str) -> Result<(), VmServiceError> {
+        let dir = self.replica_dir.join(parse_vm_id(vm_id)?.to_string());
+        if self
+            .busy
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|path| path.starts_with(&dir))
+        {
+            return Err(VmServiceError::InvalidState(format!(
+                "A disk of VM {vm_id} is being replicated"
+            )));
+        }
+        match fs::remove_dir_all(&dir).await {
+            Ok(()) => {
+                info!("Replication ({vm_id}): Deleted the replicas of its disks");
+                Ok(())
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(VmServiceError::Replication(format!(
+                "Cannot delete {}: {e}",
+                dir.display()
+            ))),
+        }
+    }
+}
+
+fn parse_vm_id(vm_id: &str) -> Result<Uuid, VmServiceError> {
+    Uuid::parse_str(vm_id)
+        .map_err(|_| VmServiceError::InvalidArgument("Invalid VM ID format.".to_string()))
+}
+
+async fn read_pem(path: &Path) -> Result<Vec<u8>, VmServiceError> {
+    fs::read(path).await.map_err(|e| {
+        VmServiceError::Replication(format!(
+            "Cannot read the client certificate {}: {e}",
+            path.display()
+        ))
+    })
+}
+
+/// Where the state of the replication to a peer is kept. Peers are told
+/// apart by a hash of their address, which is no valid file name.
+fn peer_dir_name(peer: &str) -> String {
+    hex_digest(peer.as_bytes())[..16].to_string()
+}
+
+fn hex_digest(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+fn stream_error(status: Status) -> VmServiceError {
+    VmServiceError::Replication(format!(
+        "Receiving from the peer failed: {}",
+        status.message()
+    ))
+}
+
+/// Copies the disks into `staging`, pausing the VM meanwhile if `pause` is
+/// set, so the copies are consistent with each other.
+async fn stage_disks(
+    vm_id: &str,
+    disks: &[(String, PathBuf)],
+    staging: &Path,
+    pause: bool,
+    hypervisor: &dyn Hypervisor,
+) -> Result<Vec<(String, PathBuf)>, VmServiceError> {
+    if pause {
+        hypervisor
+            .pause_vm(PauseVmRequest {
+                vm_id: vm_id.to_string(),
+            })
+            .await?;
+    }
+    let mut staged = Vec::with_capacity(disks.len());
+    let mut result = Ok(());
+    for (device_id, path) in disks {
+        let copy = staging.join(format!("{}.img", flatten_device_id(device_id)));
+        if let Err(e) = snapshot::copy_disk(path, &copy).await {
+            result = Err(e);
+            break;
+        }
+        staged.push((device_id.clone(), copy));
+    }
+    if pause {
+        if let Err(e) = hypervisor
+            .resume_vm(ResumeVmRequest {
+                vm_id: vm_id.to_string(),
+            })
+            .await
+        {
+            warn!("Replication ({vm_id}): Failed to resume the VM: {e}");
+            result = result.and(Err(e.into()));
+        }
+    }
+    result.map(|()| staged)
+}
+
+/// The state of the last replication of a disk, if it was completed.
+async fn load_sent_state(state_path: &Path, sums_path: &Path) -> Option<(SentState, Vec<u8>)> {
+    let state = read_json::<SentState>(state_path).await?;
+    let sums = fs::read(sums_path).await.ok()?;
+    let blocks = state.size_bytes.div_ceil(BLOCK_SIZE as u64) as usize;
+    (sums.len() == blocks * DIGEST_LEN).then_some((state, sums))
+}
+
+/// Streams the blocks of `path` to the peer. The outer error is a local one,
+/// the inner the answer of the peer.
+async fn send_disk(
+    client: &mut PeerClient,
+    header: DiskReplicaHeader,
+    path: &Path,
+    previous: Option<Vec<u8>>,
+) -> Result<Result<ReadBlocks, Status>, VmServiceError> {
+    let (tx, rx) = mpsc::channel(4);
+    tx.send(ReceiveDiskReplicaRequest {
+        payload: Some(Payload::Header(header)),
+    })
+    .await
+    .ok();
+    let source = path.to_path_buf();
+    let mut reader =
+        tokio::task::spawn_blocking(move || read_blocks(&source, previous.as_deref(), &tx));
+    let call = client.receive_disk_replica(ReceiverStream::new(rx));
+    tokio::pin!(call);
+
+    // A failed read drops the call, which the peer sees as an aborted stream,
+    // rather than ending the stream, which would complete the replica.
+    let mut response = None;
+    let read = tokio::select! {
+        read = &mut reader => read,
+        result = &mut call => {
+            response = Some(result);
+            (&mut reader).await
+        }
+    };
+    let read = match read
+        .map_err(|e| VmServiceError::Replication(format!("Reading the disk failed: {e}")))?
+    {
+        Ok(read) => Some(read),
+        // The peer stopped receiving, its answer tells why.
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => None,
+        Err(e) => {
+            return Err(VmServiceError::Replication(format!(
+                "Cannot read {}: {e}",
+                path.display()
+            )))
+        }
+    };
+    let response = match response {
+        Some(response) => response,
+        None => call.await,
+    };
+    match (response, read) {
+        (Err(status), _) => Ok(Err(status)),
+        (Ok(_), Some(read)) => Ok(Ok(read)),
+        (Ok(_), None) => Err(VmServiceError::Replication(
+            "The peer answered before it received all blocks".to_string(),
+        )),
+    }
+}
+
+/// Whether a block has to be sent: if it changed since the last replication,
+/// or, for blocks the replica does not have yet, if it is not zero.
+fn needs_sending(block: &[u8], digest: &[u8], previous: Option<&[u8]>) -> bool {
+    match previous {
+        Some(previous) => previous != digest,
+        None => block.iter().any(|byte| *byte != 0),
+    }
+}
+
+/// Reads the disk at `path` block by block, sends the blocks that need
+/// sending to `tx` and returns the digests of all blocks.
+fn read_blocks(
+    path: &Path,
+    previous: Option<&[u8]>,
+    tx: &mpsc::Sender<ReceiveDiskReplicaRequest>,
+) -> std::io::Result<ReadBlocks> {
+    let mut file = std::fs::File::open(path)?;
+    let mut read = ReadBlocks {
+        sums: Vec::new(),
+        blocks_sent: 0,
+        bytes_sent: 0,
+    };
+    let mut offset = 0;
+    for index in 0.. {
+        let mut block = Vec::with_capacity(BLOCK_SIZE);
+        (&mut file)
+            .take(BLOCK_SIZE as u64)
+            .read_to_end(&mut block)?;
+        if block.is_empty() {
+            break;
+        }
+        let digest = Sha256::digest(&block);
+        let previous_digest =
+            previous.and_then(|sums| sums.get(index * DIGEST_LEN..(index + 1) * DIGEST_LEN));
+        read.sums.extend_from_slice(&digest);
+        let len = block.len();
+        if needs_sending(&block, &digest, previous_digest) {
+            read.blocks_sent += 1;
+            read.bytes_sent += len as u64;
+            tx.blocking_send(ReceiveDiskReplicaRequest {
+                payload: Some(Payload::Block(DiskReplicaBlock {
+                    offset,
+                    data: block,
+                })),
+            })
+            .map_err(|_| {
+                std::io::Error::new(ErrorKind::BrokenPipe, "the peer stopped receiving")
+            })?;
+        }
+        offset += len as u64;
+        if len < BLOCK_SIZE {
+            break;
+        }
+    }
+    Ok(read)
+}
+
+/// Writes the blocks of the stream into `partial`, a copy of `image` for
+/// blocks that apply to the replica or an empty image otherwise.
+async fn write_replica(
+    header: &DiskReplicaHeader,
+    image: &Path,
+    partial: &Path,
+    stream: &mut Streaming<ReceiveDiskReplicaRequest>,
+) -> Result<u64, VmServiceError> {
+    let io_error =
+        |e: std::io::Error| VmServiceError::Replication(format!("{}: {e}", partial.display()));
+    if header.base_generation.is_empty() {
+        fs::File::create(partial).await.map_err(io_error)?;
+    } else {
+        snapshot::copy_disk(image, partial).await?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .open(partial)
+        .await
+        .map_err(io_error)?;
+    file.set_len(header.size_bytes).await.map_err(io_error)?;
+
+    let mut written = 0;
+    while let Some(request) = stream.message().await.map_err(stream_error)? {
+        let Some(Payload::Block(block)) = request.payload else {
+            return Err(VmServiceError::InvalidArgument(
+                "Only the first message may be a header".to_string(),
+            ));
+        };
+        let end = block.offset.checked_add(block.data.len() as u64);
+        if end.is_none_or(|end| end > header.size_bytes) {
+            return Err(VmServiceError::InvalidArgument(format!(
+                "Block at {} is beyond the end of the disk",
+                block.offset
+            )));
+        }
+        file.seek(SeekFrom::Start(block.offset))
+            .await
+            .map_err(io_error)?;
+        file.write_all(&block.data).await.map_err(io_error)?;
+        written += block.data.len() as u64;
+    }
+    file.sync_all().await.map_err(io_error)?;
+    Ok(written)
+}
+
+async fn list_dir(dir: &Path) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    let Ok(mut entries) = fs::read_dir(dir).await else {
+        return paths;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        paths.push(entry.path());
+    }
+    paths
+}
+
+async fn read_json<T: for<'de> Deserialize<'de>>(path: &Path) -> Option<T> {
+    let data = fs::read(path).await.ok()?;
+    serde_json::from_slice(&data).ok()
+}
+
+async fn write_json<T: Serialize>(path: &Path, value: &T) -> std::io::Result<()> {
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, serde_json::to_vec(value)?).await?;
+    fs::rename(&temp_path, path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocks_of(rx: &mut mpsc::Receiver<ReceiveDiskReplicaRequest>) -> Vec<u64> {
+        let mut offsets = Vec::new();
+        while let Ok(request) = rx.try_recv() {
+            if let Some(Payload::Block(block)) = request.payload {
+                offsets.push(block.offset);
+            }
+        }
+        offsets
+    }
+
+    #[test]
+    fn only_changed_blocks_are_sent() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("disk.img");
+        let mut data = vec![0u8; 3 * BLOCK_SIZE + 512];
+        data[BLOCK_SIZE + 7] = 1;
+        data[3 * BLOCK_SIZE] = 2;
+        std::fs::write(&path, &data).unwrap();
+
+        let (tx, mut rx) = mpsc::channel(16);
+        let full = read_blocks(&path, None, &tx).unwrap();
+        assert_eq!(
+            blocks_of(&mut rx),
+            vec![BLOCK_SIZE as u64, 3 * BLOCK_SIZE as u64]
+        );
+        assert_eq!(full.bytes_sent, BLOCK_SIZE as u64 + 512);
+        assert_eq!(full.sums.len(), 4 * DIGEST_LEN);
+
+        data[7] = 3;
+        data[BLOCK_SIZE + 7] = 0;
+        std::fs::write(&path, &data).unwrap();
+        let incremental = read_blocks(&path, Some(&full.sums), &tx).unwrap();
+        assert_eq!(blocks_of(&mut rx), vec![0, BLOCK_SIZE as u64]);
+        assert_eq!(incremental.blocks_sent, 2);
+    }
+
+    #[test]
+    fn new_blocks_are_sent_unless_zero() {
+        let zero = [0u8; 16];
+        let digest = Sha256::digest(zero);
+        assert!(!needs_sending(&zero, &digest, None));
+        assert!(needs_sending(&[1u8; 16], &digest, None));
+        assert!(!needs_sending(&zero, &digest, Some(&digest)));
+        assert!(needs_sending(&zero, &digest, Some(&[0u8; DIGEST_LEN])));
+    }
+}
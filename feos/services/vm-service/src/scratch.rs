@@ -0,0 +1,147 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{error::VmServiceError, storage_daemon::flatten_device_id, VM_SCRATCH_DIR};
+use feos_proto::vm_service::EphemeralDiskConfig;
+use log::{info, warn};
+use nix::errno::Errno;
+use nix::mount::{mount, umount2, MntFlags, MsFlags};
+use std::path::{Path, PathBuf};
+use tokio::fs::{self, OpenOptions};
+
+const MEMINFO_PATH: &str = "/proc/meminfo";
+const DISK_FILE: &str = "disk.img";
+const SECTOR_SIZE: u64 = 512;
+
+/// Every scratch disk lives on its own tmpfs sized to the disk, so a guest
+/// can never take more host memory than the disk's capacity.
+fn mount_dir(vm_id: &str, device_id: &str) -> PathBuf {
+    Path::new(VM_SCRATCH_DIR).join(format!("{vm_id}-{}", flatten_device_id(device_id)))
+}
+
+pub fn validate(config: &EphemeralDiskConfig) -> Result<(), VmServiceError> {
+    if config.size_bytes == 0 || config.size_bytes % SECTOR_SIZE != 0 {
+        return Err(VmServiceError::InvalidArgument(format!(
+            "Ephemeral disk size must be a non-zero multiple of {SECTOR_SIZE} bytes, got {}",
+            config.size_bytes
+        )));
+    }
+    Ok(())
+}
+
+fn parse_mem_available(meminfo: &str) -> Option<u64> {
+    meminfo.lines().find_map(|line| {
+        let kib = line
+            .strip_prefix("MemAvailable:")?
+            .trim()
+            .strip_suffix("kB")?;
+        kib.trim().parse::<u64>().ok().map(|kib| kib * 1024)
+    })
+}
+
+/// Fails unless the host has `required_bytes` of memory available.
+pub async fn check_memory(required_bytes: u64) -> Result<(), VmServiceError> {
+    let meminfo = fs::read_to_string(MEMINFO_PATH)
+        .await
+        .map_err(|e| VmServiceError::Scratch(format!("Failed to read {MEMINFO_PATH}: {e}")))?;
+    let available = parse_mem_available(&meminfo).ok_or_else(|| {
+        VmServiceError::Scratch(format!("MemAvailable missing from {MEMINFO_PATH}"))
+    })?;
+    if required_bytes > available {
+        return Err(VmServiceError::InsufficientMemory(format!(
+            "{required_bytes} bytes of memory requested, but only {available} bytes are available"
+        )));
+    }
+    Ok(())
+}
+
+/// Mounts the tmpfs of a scratch disk and creates the sparse disk file in
+/// it. Returns the path of the file.
+pub async fn create(
+    vm_id: &str,
+    device_id: &str,
+    config: &EphemeralDiskConfig,
+) -> Result<PathBuf, VmServiceError> {
+    let dir = mount_dir(vm_id, device_id);
+    fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| VmServiceError::Scratch(format!("Failed to create {}: {e}", dir.display())))?;
+
+    let options = format!("size={},mode=0700", config.size_bytes);
+    mount(
+        Some("tmpfs"),
+        &dir,
+        Some("tmpfs"),
+        MsFlags::MS_NODEV | MsFlags::MS_NOSUID | MsFlags::MS_NOEXEC,
+        Some(options.as_str()),
+    )
+    .map_err(|e| {
+        VmServiceError::Scratch(format!("Failed to mount tmpfs on {}: {e}", dir.display()))
+    })?;
+
+    let path = dir.join(DISK_FILE);
+    let result = async {
+        fs::File::create(&path)
+            .await?
+            .set_len(config.size_bytes)
+            .await
+    }
+    .await;
+    if let Err(e) = result {
+        remove(vm_id, device_id).await;
+        return Err(VmServiceError::Scratch(format!(
+            "Failed to create {}: {e}",
+            path.display()
+        )));
+    }
+    info!(
+        "Scratch: Created {} byte disk '{device_id}' for VM {vm_id}",
+        config.size_bytes
+    );
+    Ok(path)
+}
+
+/// Drops everything the guest wrote to a scratch disk and returns the memory
+/// to the host. The disk keeps its size.
+pub async fn wipe(vm_id: &str, device_id: &str, config: &EphemeralDiskConfig) {
+    let path = mount_dir(vm_id, device_id).join(DISK_FILE);
+    let result = async {
+        let file = OpenOptions::new().write(true).open(&path).await?;
+        file.set_len(0).await?;
+        file.set_len(config.size_bytes).await
+    }
+    .await;
+    match result {
+        Ok(()) => info!("Scratch: Wiped disk '{device_id}' of VM {vm_id}"),
+        Err(e) => warn!("Scratch: Failed to wipe {}: {e}", path.display()),
+    }
+}
+
+/// Unmounts the tmpfs of a scratch disk, freeing its memory.
+pub async fn remove(vm_id: &str, device_id: &str) {
+    let dir = mount_dir(vm_id, device_id);
+    match umount2(&dir, MntFlags::MNT_DETACH) {
+        Ok(()) | Err(Errno::EINVAL) | Err(Errno::ENOENT) => {}
+        Err(e) => {
+            warn!("Scratch: Failed to unmount {}: {e}", dir.display());
+            return;
+        }
+    }
+    if let Err(e) = fs::remove_dir(&dir).await {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            warn!("Scratch: Failed to remove {}: {e}", dir.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn available_memory_is_read_in_bytes() {
+        let meminfo = "MemTotal:       16318412 kB\nMemFree:         1092644 kB\nMemAvailable:    9563132 kB\n";
+        assert_eq!(parse_mem_available(meminfo), Some(9563132 * 1024));
+        assert_eq!(parse_mem_available("MemTotal: 16318412 kB\n"), None);
+    }
+}
@@ -0,0 +1,184 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! SLAAC for tap NICs. While a VM runs, FeOS announces the first /64 of the
+//! prefix delegated to the host on the tap device of each of its SLAAC NICs
+//! and routes the address the guest forms from the MAC of the NIC to it. The
+//! address follows from the prefix and the MAC alone, so the guests need no
+//! DHCP server and FeOS no leases.
+
+use crate::error::VmServiceError;
+use feos_proto::vm_service::{net_config, NetConfig, VmConfig};
+use feos_utils::network::macvtap::parse_mac;
+use feos_utils::network::router_advert::{self, Advertisement};
+use feos_utils::network::utils::{delegated_prefix, dns_servers};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::net::Ipv6Addr;
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Duration, Instant};
+
+/// How long a hotplugged NIC may take to get its tap device.
+const TAP_TIMEOUT: Duration = Duration::from_secs(10);
+const TAP_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The tap device of a NIC addressed by SLAAC.
+fn slaac_tap(nic: &NetConfig) -> Option<&str> {
+    match &nic.backend {
+        Some(net_config::Backend::Tap(tap)) if tap.slaac => Some(tap.tap_name.as_str()),
+        _ => None,
+    }
+}
+
+pub fn uses_slaac(nic: &NetConfig) -> bool {
+    slaac_tap(nic).is_some()
+}
+
+/// Checks that FeOS can announce a prefix to the NIC if it uses SLAAC.
+pub fn validate_nic(nic: &NetConfig) -> Result<(), VmServiceError> {
+    if slaac_tap(nic).is_some_and(str::is_empty) {
+        return Err(VmServiceError::InvalidArgument(format!(
+            "SLAAC NIC '{}' needs a tap name",
+            nic.device_id
+        )));
+    }
+    Ok(())
+}
+
+pub fn validate(config: &VmConfig) -> Result<(), VmServiceError> {
+    config.net.iter().try_for_each(validate_nic)
+}
+
+/// The address the guest forms on a SLAAC NIC in `prefix`.
+pub fn guest_address(prefix: Ipv6Addr, nic: &NetConfig) -> Option<Ipv6Addr> {
+    let mac = parse_mac(&nic.mac_address).ok()?;
+    Some(router_advert::eui64_address(prefix, mac))
+}
+
+async fn wait_for_tap(tap: &str) -> bool {
+    let deadline = Instant::now() + TAP_TIMEOUT;
+    let device = Path::new("/sys/class/net").join(tap);
+    while !tokio::fs::try_exists(&device).await.unwrap_or(false) {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        sleep(TAP_POLL_INTERVAL).await;
+    }
+    true
+}
+
+async fn advertise(vm_id: String, nic: NetConfig) {
+    let Some(tap) = slaac_tap(&nic) else {
+        return;
+    };
+    let Some((prefix, _)) = delegated_prefix().await else {
+        warn!("Slaac ({vm_id}): The host has no delegated prefix, not announcing one on {tap}.");
+        return;
+    };
+    let prefix = router_advert::first_subnet(prefix);
+    let Some(address) = guest_address(prefix, &nic) else {
+        warn!(
+            "Slaac ({vm_id}): NIC '{}' has no valid MAC address, not announcing a prefix.",
+            nic.device_id
+        );
+        return;
+    };
+    if !wait_for_tap(tap).await {
+        warn!("Slaac ({vm_id}): Tap device {tap} did not appear.");
+        return;
+    }
+    if let Err(e) = router_advert::route_to_guest(tap, address).await {
+        warn!("Slaac ({vm_id}): Failed to route {address} to {tap}: {e}");
+        return;
+    }
+    info!("Slaac ({vm_id}): Routing {address} to {tap}.");
+
+    let advertisement = Advertisement {
+        prefix,
+        dns_servers: dns_servers().await,
+    };
+    if let Err(e) = router_advert::serve(tap, advertisement).await {
+        warn!("Slaac ({vm_id}): Router advertisements on {tap} stopped: {e}");
+    }
+}
+
+/// The router advertisements for the SLAAC NICs of the running VMs, by VM
+/// and device ID.
+#[derive(Clone, Default)]
+pub struct SlaacAdvertisers {
+    advertisers: Arc<Mutex<HashMap<String, HashMap<String, JoinHandle<()>>>>>,
+}
+
+impl SlaacAdvertisers {
+    fn advertisers(
+        &self,
+    ) -> std::sync::MutexGuard<'_, HashMap<String, HashMap<String, JoinHandle<()>>>> {
+        self.advertisers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Starts advertising to the SLAAC NICs of the VM that are not served
+    /// yet, e.g. when it starts or a NIC was attached.
+    pub fn start(&self, vm_id: &str, config: &VmConfig) {
+        let mut advertisers = self.advertisers();
+        let vm_advertisers = advertisers.entry(vm_id.to_string()).or_default();
+        for nic in config.net.iter().filter(|nic| slaac_tap(nic).is_some()) {
+            if vm_advertisers
+                .get(&nic.device_id)
+                .is_some_and(|advertiser| !advertiser.is_finished())
+            {
+                continue;
+            }
+            let advertiser = tokio::spawn(advertise(vm_id.to_string(), nic.clone()));
+            vm_advertisers.insert(nic.device_id.clone(), advertiser);
+        }
+    }
+
+    /// Stops advertising to the NICs of the VM, once it no longer runs.
+    pub fn stop(&self, vm_id: &str) {
+        if let Some(vm_advertisers) = self.advertisers().remove(vm_id) {
+            for advertiser in vm_advertisers.into_values() {
+                advertiser.abort();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feos_proto::vm_service::TapConfig;
+
+    fn tap_nic(tap_name: &str, slaac: bool) -> NetConfig {
+        NetConfig {
+            device_id: "net0".to_string(),
+            backend: Some(net_config::Backend::Tap(TapConfig {
+                tap_name: tap_name.to_string(),
+                slaac,
+            })),
+            mac_address: "52:54:00:ab:cd:01".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn slaac_nics_need_a_tap_name_and_get_their_eui64_address() {
+        assert!(validate_nic(&tap_nic("tap0", true)).is_ok());
+        assert!(validate_nic(&tap_nic("", false)).is_ok());
+        assert!(validate_nic(&tap_nic("", true)).is_err());
+
+        let prefix = "2001:db8:12::".parse().unwrap();
+        assert_eq!(
+            guest_address(prefix, &tap_nic("tap0", true)),
+            Some("2001:db8:12:0:5054:ff:feab:cd01".parse().unwrap())
+        );
+        let no_mac = NetConfig {
+            mac_address: String::new(),
+            ..tap_nic("tap0", true)
+        };
+        assert_eq!(guest_address(prefix, &no_mac), None);
+    }
+}
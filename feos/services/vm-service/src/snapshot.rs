@@ -0,0 +1,188 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{error::VmServiceError, overlay, persistence::VmRecord, IMAGE_DIR, VM_SNAPSHOT_DIR};
+use feos_proto::vm_service::{disk_config, DiskSnapshot};
+use log::{info, warn};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::process::Command as TokioCommand;
+
+/// The device ID under which snapshots refer to a VM's root filesystem.
+pub const ROOTFS_DEVICE_ID: &str = "rootfs";
+
+/// The file-backed disks of a VM as `(device_id, path)` pairs, starting with
+/// the root filesystem. Overlays are snapshotted without their base. Disks
+/// passed through via VFIO cannot be snapshotted and are skipped.
+pub fn vm_disks(record: &VmRecord) -> Vec<(String, PathBuf)> {
+    let rootfs = PathBuf::from(IMAGE_DIR)
+        .join(record.image_uuid.to_string())
+        .join("disk.image");
+    let mut disks = vec![(ROOTFS_DEVICE_ID.to_string(), rootfs)];
+    for disk in &record.config.disks {
+        match &disk.backend {
+            Some(disk_config::Backend::Path(path)) => {
+                let device_id = if disk.device_id.is_empty() {
+                    path.clone()
+                } else {
+                    disk.device_id.clone()
+                };
+                disks.push((device_id, PathBuf::from(path)));
+            }
+            Some(disk_config::Backend::Overlay(_)) => {
+                let path = overlay::overlay_path(&record.vm_id.to_string(), &disk.device_id);
+                disks.push((disk.device_id.clone(), path));
+            }
+            _ => {}
+        }
+    }
+    disks
+}
+
+/// Picks the disks named in `device_ids`, or all of them if it is empty.
+pub fn select_disks<T: Clone>(
+    disks: &[T],
+    device_ids: &[String],
+    device_id: impl Fn(&T) -> &str,
+) -> Result<Vec<T>, VmServiceError> {
+    if device_ids.is_empty() {
+        return Ok(disks.to_vec());
+    }
+    device_ids
+        .iter()
+        .map(|id| {
+            disks
+                .iter()
+                .find(|disk| device_id(disk) == id)
+                .cloned()
+                .ok_or_else(|| VmServiceError::InvalidArgument(format!("Unknown disk '{id}'")))
+        })
+        .collect()
+}
+
+pub fn snapshot_dir(vm_id: &str, snapshot_id: &str) -> PathBuf {
+    PathBuf::from(VM_SNAPSHOT_DIR).join(vm_id).join(snapshot_id)
+}
+
+/// Where the hypervisor keeps the memory and device state of a snapshot.
+pub fn memory_dir(snapshot_dir: &Path) -> PathBuf {
+    snapshot_dir.join("memory")
+}
+
+/// Copies a disk image and returns its size. Data blocks are shared with the
+/// source on filesystems that support reflinks (btrfs, XFS), so snapshots are
+/// instant and only take up space as the disks diverge. Elsewhere the image is
+/// copied with its holes preserved.
+pub async fn copy_disk(source: &Path, destination: &Path) -> Result<u64, VmServiceError> {
+    let metadata = fs::metadata(source).await.map_err(|e| {
+        VmServiceError::Snapshot(format!("Cannot access disk {}: {e}", source.display()))
+    })?;
+    if !metadata.is_file() {
+        return Err(VmServiceError::Snapshot(format!(
+            "Disk {} is not an image file and cannot be snapshotted",
+            source.display()
+        )));
+    }
+
+    let output = TokioCommand::new("cp")
+        .arg("--reflink=auto")
+        .arg("--sparse=always")
+        .arg(source)
+        .arg(destination)
+        .output()
+        .await
+        .map_err(|e| VmServiceError::Snapshot(format!("Failed to run cp: {e}")))?;
+    if !output.status.success() {
+        return Err(VmServiceError::Snapshot(format!(
+            "Copying {} to {} failed: {}",
+            source.display(),
+            destination.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(metadata.len())
+}
+
+/// Overwrites the disks of a VM with their copies from a snapshot.
+pub async fn revert_disks(disks: &[DiskSnapshot]) -> Result<(), VmServiceError> {
+    for disk in disks {
+        info!(
+            "Snapshot: Reverting disk '{}' at {}",
+            disk.device_id, disk.source_path
+        );
+        copy_disk(Path::new(&disk.snapshot_path), Path::new(&disk.source_path)).await?;
+    }
+    Ok(())
+}
+
+pub async fn remove_snapshot_dir(path: &Path) {
+    if let Err(e) = fs::remove_dir_all(path).await {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            warn!("Snapshot: Failed to remove {}: {e}", path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence::VmStatus;
+    use feos_proto::vm_service::{DiskConfig, VfioPciConfig, VmConfig, VmState};
+    use uuid::Uuid;
+
+    fn record_with_disks(disks: Vec<DiskConfig>) -> VmRecord {
+        VmRecord {
+            vm_id: Uuid::new_v4(),
+            image_uuid: Uuid::nil(),
+            status: VmStatus {
+                state: VmState::Stopped,
+                last_msg: String::new(),
+                process_id: None,
+            },
+            config: VmConfig {
+                disks,
+                ..Default::default()
+            },
+            namespace: "default".to_string(),
+            name: None,
+        }
+    }
+
+    #[test]
+    fn vm_disks_skip_passthrough_devices() {
+        let record = record_with_disks(vec![
+            DiskConfig {
+                device_id: "data".to_string(),
+                backend: Some(disk_config::Backend::Path("/srv/data.img".to_string())),
+                ..Default::default()
+            },
+            DiskConfig {
+                device_id: "nvme".to_string(),
+                backend: Some(disk_config::Backend::VfioPci(VfioPciConfig {
+                    bdf: "0000:03:00.0".to_string(),
+                })),
+                ..Default::default()
+            },
+            DiskConfig {
+                device_id: String::new(),
+                backend: Some(disk_config::Backend::Path("/srv/scratch.img".to_string())),
+                ..Default::default()
+            },
+        ]);
+
+        let ids: Vec<_> = vm_disks(&record).into_iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, ["rootfs", "data", "/srv/scratch.img"]);
+    }
+
+    #[test]
+    fn select_disks_by_device_id() {
+        let disks = vec![("rootfs", 1), ("data", 2)];
+        let all = select_disks(&disks, &[], |disk| disk.0).unwrap();
+        assert_eq!(all, disks);
+
+        let data = select_disks(&disks, &["data".to_string()], |disk| disk.0).unwrap();
+        assert_eq!(data, [("data", 2)]);
+
+        assert!(select_disks(&disks, &["swap".to_string()], |disk| disk.0).is_err());
+    }
+}
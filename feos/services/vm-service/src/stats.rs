@@ -0,0 +1,286 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Usage of the devices assigned to VMs, returned by GetVmStats and exported
+//! in the Prometheus text format.
+
+use crate::{persistence::VmRecord, Command};
+use feos_proto::vm_service::{
+    GetVmStatsRequest, GetVmStatsResponse, GpuConfig, VmGpuStats, VmStats,
+};
+use feos_utils::host::gpu::GpuPartition;
+use feos_utils::host::gpu_metrics::{GpuMetrics, GpuVendor};
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use log::{debug, info, warn};
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::io;
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, oneshot};
+
+const METRICS_PATH: &str = "/metrics";
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// The gauges of a GPU, with their help text and value.
+type GpuGauge = (&'static str, &'static str, fn(&VmGpuStats) -> Option<u64>);
+
+const GPU_GAUGES: [GpuGauge; 4] = [
+    (
+        "feos_vm_gpu_utilization_percent",
+        "Utilization of the GPU a device of the VM is on.",
+        |gpu| gpu.utilization_percent.map(u64::from),
+    ),
+    (
+        "feos_vm_gpu_memory_used_bytes",
+        "Used memory of the GPU a device of the VM is on.",
+        |gpu| gpu.memory_used_bytes,
+    ),
+    (
+        "feos_vm_gpu_memory_total_bytes",
+        "Total memory of the GPU a device of the VM is on.",
+        |gpu| gpu.memory_total_bytes,
+    ),
+    (
+        "feos_vm_gpu_temperature_celsius",
+        "Temperature of the GPU a device of the VM is on.",
+        |gpu| gpu.temperature_celsius.map(u64::from),
+    ),
+];
+
+fn vendor_name(vendor: GpuVendor) -> &'static str {
+    match vendor {
+        GpuVendor::Nvidia => "nvidia",
+        GpuVendor::Amd => "amd",
+    }
+}
+
+/// The stats of a GPU of a VM. Partitions report the values of the GPU they
+/// are created on, which is the device the vendor tools know.
+fn gpu_stats(
+    config: &GpuConfig,
+    partitions: &[GpuPartition],
+    metrics: &[GpuMetrics],
+) -> VmGpuStats {
+    let gpu_address = partitions
+        .iter()
+        .find(|partition| partition.pci_address == config.pci_address)
+        .map_or(config.pci_address.as_str(), |partition| {
+            partition.gpu_address.as_str()
+        });
+    let sample = metrics
+        .iter()
+        .find(|sample| sample.pci_address == config.pci_address)
+        .or_else(|| {
+            metrics
+                .iter()
+                .find(|sample| sample.pci_address == gpu_address)
+        });
+    VmGpuStats {
+        pci_address: config.pci_address.clone(),
+        gpu_address: gpu_address.to_string(),
+        profile: config.profile.clone(),
+        vendor: sample
+            .map(|sample| vendor_name(sample.vendor).to_string())
+            .unwrap_or_default(),
+        utilization_percent: sample.and_then(|sample| sample.utilization_percent),
+        memory_used_bytes: sample.and_then(|sample| sample.memory_used_bytes),
+        memory_total_bytes: sample.and_then(|sample| sample.memory_total_bytes),
+        temperature_celsius: sample.and_then(|sample| sample.temperature_celsius),
+    }
+}
+
+pub fn vm_stats(
+    records: &[VmRecord],
+    partitions: &[GpuPartition],
+    metrics: &[GpuMetrics],
+) -> Vec<VmStats> {
+    records
+        .iter()
+        .map(|record| VmStats {
+            vm_id: record.vm_id.to_string(),
+            namespace: record.namespace.clone(),
+            gpus: record
+                .config
+                .gpus
+                .iter()
+                .map(|gpu| gpu_stats(gpu, partitions, metrics))
+                .collect(),
+        })
+        .collect()
+}
+
+/// Escapes a label value of the Prometheus text format.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+/// Renders the stats of the node `node_id` in the Prometheus text exposition
+/// format. Values that are not known are left out.
+pub fn render_prometheus(node_id: &str, stats: &[VmStats]) -> String {
+    let mut out = String::new();
+    for (name, help, value) in GPU_GAUGES {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} gauge");
+        for vm in stats {
+            for gpu in &vm.gpus {
+                let Some(value) = value(gpu) else {
+                    continue;
+                };
+                let labels: [(&str, &str); 7] = [
+                    ("node_id", node_id),
+                    ("vm_id", &vm.vm_id),
+                    ("namespace", &vm.namespace),
+                    ("pci_address", &gpu.pci_address),
+                    ("gpu_address", &gpu.gpu_address),
+                    ("profile", &gpu.profile),
+                    ("vendor", &gpu.vendor),
+                ];
+                let labels = labels
+                    .map(|(label, value)| format!("{label}=\"{}\"", escape_label(value)))
+                    .join(",");
+                let _ = writeln!(out, "{name}{{{labels}}} {value}");
+            }
+        }
+    }
+    out
+}
+
+fn text_response(status: StatusCode, body: String) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::from(body)));
+    *response.status_mut() = status;
+    response
+}
+
+async fn handle(
+    request: Request<Incoming>,
+    vm_tx: mpsc::Sender<Command>,
+    node_id: &str,
+) -> Response<Full<Bytes>> {
+    if request.method() != Method::GET || request.uri().path() != METRICS_PATH {
+        return text_response(StatusCode::NOT_FOUND, "Not found\n".to_string());
+    }
+    let (resp_tx, resp_rx) = oneshot::channel();
+    if vm_tx
+        .send(Command::GetVmStats(GetVmStatsRequest::default(), resp_tx))
+        .await
+        .is_err()
+    {
+        return text_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "VM service is not running\n".to_string(),
+        );
+    }
+    match resp_rx.await {
+        Ok(Ok(GetVmStatsResponse { stats, .. })) => {
+            let mut response = text_response(StatusCode::OK, render_prometheus(node_id, &stats));
+            response.headers_mut().insert(
+                CONTENT_TYPE,
+                HeaderValue::from_static(PROMETHEUS_CONTENT_TYPE),
+            );
+            response
+        }
+        Ok(Err(e)) => text_response(StatusCode::INTERNAL_SERVER_ERROR, format!("{e}\n")),
+        Err(_) => text_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "VM service dropped the request\n".to_string(),
+        ),
+    }
+}
+
+/// Serves the stats of the VMs for Prometheus on `addr`, under `/metrics`,
+/// until listening fails. Every series is labeled with `node_id`.
+pub async fn serve(
+    addr: SocketAddr,
+    vm_tx: mpsc::Sender<Command>,
+    node_id: String,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("VmMetrics: Listening on {addr}");
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("VmMetrics: Failed to accept connection: {e}");
+                continue;
+            }
+        };
+        let vm_tx = vm_tx.clone();
+        let node_id = node_id.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |request| {
+                let vm_tx = vm_tx.clone();
+                let node_id = node_id.clone();
+                async move { Ok::<_, Infallible>(handle(request, vm_tx, &node_id).await) }
+            });
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                debug!("VmMetrics: Connection from {peer} failed: {e}");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partitions_report_their_gpu_and_render_for_prometheus() {
+        let partitions = [GpuPartition {
+            pci_address: "0000:41:00.4".to_string(),
+            gpu_address: "0000:41:00.0".to_string(),
+            profile: "A100-4C".to_string(),
+        }];
+        let metrics = [GpuMetrics {
+            pci_address: "0000:41:00.0".to_string(),
+            vendor: GpuVendor::Nvidia,
+            utilization_percent: Some(37),
+            memory_used_bytes: Some(1024),
+            memory_total_bytes: None,
+            temperature_celsius: Some(54),
+        }];
+        let partition = GpuConfig {
+            profile: "A100-4C".to_string(),
+            pci_address: "0000:41:00.4".to_string(),
+        };
+        let unknown = GpuConfig {
+            profile: "A100-4C".to_string(),
+            pci_address: "0000:81:00.4".to_string(),
+        };
+
+        let stats = gpu_stats(&partition, &partitions, &metrics);
+        assert_eq!(stats.gpu_address, "0000:41:00.0");
+        assert_eq!(stats.vendor, "nvidia");
+        assert_eq!(stats.utilization_percent, Some(37));
+        let unreported = gpu_stats(&unknown, &partitions, &metrics);
+        assert_eq!(unreported.gpu_address, "0000:81:00.4");
+        assert_eq!(unreported.utilization_percent, None);
+
+        let text = render_prometheus(
+            "3f9a",
+            &[VmStats {
+                vm_id: "vm-1".to_string(),
+                namespace: "ai\"team".to_string(),
+                gpus: vec![stats, unreported],
+            }],
+        );
+        assert!(text.contains("# TYPE feos_vm_gpu_utilization_percent gauge\n"));
+        assert!(text.contains(
+            "feos_vm_gpu_utilization_percent{node_id=\"3f9a\",vm_id=\"vm-1\",namespace=\"ai\\\"team\",pci_address=\"0000:41:00.4\",gpu_address=\"0000:41:00.0\",profile=\"A100-4C\",vendor=\"nvidia\"} 37\n"
+        ));
+        assert!(!text.contains("feos_vm_gpu_memory_total_bytes{"));
+        assert!(!text.contains("0000:81:00.4"));
+    }
+}
@@ -0,0 +1,289 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{disk_image, error::VmServiceError, VM_EXPORT_DIR};
+use feos_proto::vm_service::{disk_config, DiskConfig, DiskFormat};
+use feos_utils::filesystem::PathWatcher;
+use log::{info, warn};
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::fs::{self, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command as TokioCommand;
+use tokio::time::Duration;
+
+const STORAGE_DAEMON_BIN: &str = "qemu-storage-daemon";
+const EXPORT_NODE: &str = "export";
+const SOCKET_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The ID under which the key passed to `start_export` can be referenced from
+/// the block device options.
+pub const KEY_SECRET_ID: &str = "key";
+
+/// Whether the disk is served to the VM by a storage daemon. RBD images always
+/// are, and so are qcow2 overlays, which are not told apart here. Local
+/// images, scratch disks, iSCSI LUNs and NVMe-oF namespaces only need one to
+/// pass discard through.
+pub fn is_exported(disk: &DiskConfig) -> bool {
+    match disk.backend {
+        Some(disk_config::Backend::Rbd(_)) => true,
+        Some(
+            disk_config::Backend::Path(_)
+            | disk_config::Backend::Iscsi(_)
+            | disk_config::Backend::Nvmeof(_)
+            | disk_config::Backend::Ephemeral(_)
+            | disk_config::Backend::Overlay(_),
+        ) => disk.discard,
+        _ => false,
+    }
+}
+
+/// Block device options for an image file or a host block device in
+/// `format`, which is detected from its header if unspecified.
+pub async fn local_blockdev(path: &Path, format: DiskFormat) -> Result<Value, VmServiceError> {
+    let image = disk_image::inspect(path).await?;
+    let format = match format {
+        DiskFormat::Unspecified => image.format,
+        format => format,
+    };
+    Ok(image_blockdev(path, image.block_device, format))
+}
+
+/// Block device options for a qcow2 overlay FeOS created. Its backing file
+/// is opened along with it.
+pub fn overlay_blockdev(path: &Path) -> Value {
+    image_blockdev(path, false, DiskFormat::Qcow2)
+}
+
+fn image_blockdev(path: &Path, block_device: bool, format: DiskFormat) -> Value {
+    let protocol = if block_device { "host_device" } else { "file" };
+    let file = json!({
+        "driver": protocol,
+        "filename": path,
+        "discard": "unmap",
+        "cache": { "direct": true },
+    });
+    if format == DiskFormat::Qcow2 {
+        json!({ "driver": "qcow2", "file": file })
+    } else {
+        file
+    }
+}
+
+/// Files of the storage daemon serving one disk of a VM. Device IDs may be
+/// paths, so they are flattened into a single file name.
+/// Turns a device ID, which is often a host path, into a file name.
+pub(crate) fn flatten_device_id(device_id: &str) -> String {
+    device_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn export_file(vm_id: &str, device_id: &str, extension: &str) -> PathBuf {
+    let device = flatten_device_id(device_id);
+    Path::new(VM_EXPORT_DIR).join(format!("{vm_id}-{device}.{extension}"))
+}
+
+async fn remove_file(path: &Path) {
+    if let Err(e) = fs::remove_file(path).await {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            warn!("StorageDaemon: Failed to remove {}: {e}", path.display());
+        }
+    }
+}
+
+/// Starts a storage daemon serving `blockdev` over vhost-user-blk and returns
+/// the path of its socket. Guest discards are passed on to the block device
+/// if `discard` is set, so thin and sparse backends release the space.
+pub async fn start_export(
+    vm_id: &str,
+    device_id: &str,
+    mut blockdev: Value,
+    key: Option<&str>,
+    readonly: bool,
+    discard: bool,
+) -> Result<PathBuf, VmServiceError> {
+    let socket_path = export_file(vm_id, device_id, "sock");
+    let pid_path = export_file(vm_id, device_id, "pid");
+    let key_path = export_file(vm_id, device_id, "key");
+    fs::create_dir_all(VM_EXPORT_DIR).await.map_err(|e| {
+        VmServiceError::StorageDaemon(format!("Failed to create {VM_EXPORT_DIR}: {e}"))
+    })?;
+    remove_file(&socket_path).await;
+
+    blockdev["node-name"] = json!(EXPORT_NODE);
+    if discard {
+        blockdev["discard"] = json!("unmap");
+        blockdev["detect-zeroes"] = json!("unmap");
+    }
+
+    let mut args = Vec::new();
+    if let Some(key) = key {
+        // The daemon reads the key once at startup, so it is only on disk
+        // until the export is up.
+        let mut key_file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&key_path)
+            .await
+            .map_err(|e| VmServiceError::StorageDaemon(format!("Failed to write key: {e}")))?;
+        key_file
+            .write_all(key.as_bytes())
+            .await
+            .map_err(|e| VmServiceError::StorageDaemon(format!("Failed to write key: {e}")))?;
+        drop(key_file);
+        args.push("--object".to_string());
+        args.push(format!(
+            "secret,id={KEY_SECRET_ID},format=base64,file={}",
+            key_path.display()
+        ));
+    }
+    args.push("--blockdev".to_string());
+    args.push(blockdev.to_string());
+    args.push("--export".to_string());
+    args.push(format!(
+        "type=vhost-user-blk,id=export,node-name={EXPORT_NODE},addr.type=unix,addr.path={},writable={}",
+        socket_path.display(),
+        if readonly { "off" } else { "on" }
+    ));
+
+    let result = spawn_daemon(&args, &socket_path, &pid_path).await;
+    if key.is_some() {
+        remove_file(&key_path).await;
+    }
+    result?;
+
+    info!(
+        "StorageDaemon: Exporting disk '{device_id}' of VM {vm_id} at {}",
+        socket_path.display()
+    );
+    Ok(socket_path)
+}
+
+async fn spawn_daemon(
+    args: &[String],
+    socket_path: &Path,
+    pid_path: &Path,
+) -> Result<(), VmServiceError> {
+    let mut watcher = PathWatcher::new(socket_path);
+    let mut child = TokioCommand::new(STORAGE_DAEMON_BIN)
+        .args(args)
+        .arg("--pidfile")
+        .arg(pid_path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            VmServiceError::StorageDaemon(format!("Failed to start {STORAGE_DAEMON_BIN}: {e}"))
+        })?;
+
+    tokio::select! {
+        ready = watcher.wait(SOCKET_TIMEOUT) => {
+            if let Err(e) = ready {
+                let _ = child.kill().await;
+                return Err(VmServiceError::StorageDaemon(format!(
+                    "Failed waiting for {STORAGE_DAEMON_BIN} to create {}: {e}",
+                    socket_path.display()
+                )));
+            }
+        }
+        exited = child.wait() => {
+            let status = exited.map_err(|e| {
+                VmServiceError::StorageDaemon(format!("Failed to check storage daemon: {e}"))
+            })?;
+            let mut stderr = String::new();
+            if let Some(mut pipe) = child.stderr.take() {
+                let _ = pipe.read_to_string(&mut stderr).await;
+            }
+            return Err(VmServiceError::StorageDaemon(format!(
+                "{STORAGE_DAEMON_BIN} exited with {status}: {}",
+                stderr.trim()
+            )));
+        }
+    }
+
+    let socket = socket_path.display().to_string();
+    tokio::spawn(async move {
+        match child.wait_with_output().await {
+            Ok(output) if !output.status.success() => warn!(
+                "StorageDaemon: Daemon for {socket} exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+            Ok(_) => info!("StorageDaemon: Daemon for {socket} exited"),
+            Err(e) => warn!("StorageDaemon: Failed to wait for daemon of {socket}: {e}"),
+        }
+    });
+    Ok(())
+}
+
+/// Stops the storage daemon serving a disk. Only call this once the VM no
+/// longer uses the export.
+pub async fn stop_export(vm_id: &str, device_id: &str) {
+    let pid_path = export_file(vm_id, device_id, "pid");
+    match fs::read_to_string(&pid_path).await {
+        Ok(pid) => match pid.trim().parse::<i32>() {
+            Ok(pid) => match kill(Pid::from_raw(pid), Signal::SIGTERM) {
+                Ok(()) => {
+                    info!("StorageDaemon: Stopped daemon for disk '{device_id}' of VM {vm_id}")
+                }
+                Err(e) => warn!("StorageDaemon: Failed to stop daemon {pid}: {e}"),
+            },
+            Err(_) => warn!("StorageDaemon: Invalid pid file {}", pid_path.display()),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => warn!("StorageDaemon: Failed to read {}: {e}", pid_path.display()),
+    }
+    remove_file(&pid_path).await;
+    remove_file(&export_file(vm_id, device_id, "sock")).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feos_proto::vm_service::{RbdConfig, VfioPciConfig};
+
+    fn disk(backend: disk_config::Backend, discard: bool) -> DiskConfig {
+        DiskConfig {
+            backend: Some(backend),
+            discard,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn only_rbd_and_discarding_disks_are_exported() {
+        let path = disk_config::Backend::Path("/srv/data.qcow2".to_string());
+        assert!(!is_exported(&disk(path.clone(), false)));
+        assert!(is_exported(&disk(path, true)));
+        assert!(is_exported(&disk(
+            disk_config::Backend::Rbd(RbdConfig::default()),
+            false
+        )));
+        assert!(!is_exported(&disk(
+            disk_config::Backend::VfioPci(VfioPciConfig::default()),
+            true
+        )));
+    }
+
+    #[test]
+    fn export_files_flatten_device_paths() {
+        assert_eq!(
+            export_file("vm", "/srv/data.img", "sock"),
+            Path::new(VM_EXPORT_DIR).join("vm-_srv_data.img.sock")
+        );
+    }
+}
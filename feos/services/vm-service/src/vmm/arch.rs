@@ -0,0 +1,82 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::VmmError;
+use feos_proto::vm_service::VmConfig;
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+compile_error!("VMs can only be run on x86_64 and aarch64 hosts");
+
+/// The firmware VMs boot. Guests find the serial port of the VM as a 16550
+/// UART.
+#[cfg(target_arch = "x86_64")]
+pub const FIRMWARE_PATH: &str = "/usr/share/cloud-hypervisor/hypervisor-fw";
+
+/// The firmware VMs boot, the EDK2 build for Cloud Hypervisor. It hands the
+/// guest the ACPI tables describing the GIC and the PL011 UART the VMM
+/// emulates, so guests find the serial port of the VM as ttyAMA0.
+#[cfg(target_arch = "aarch64")]
+pub const FIRMWARE_PATH: &str = "/usr/share/cloud-hypervisor/CLOUDHV_EFI.fd";
+
+/// The firmware VMs booting from the network boot, EDK2 with its network
+/// stack, which does PXE and HTTP boot over virtio-net.
+#[cfg(target_arch = "x86_64")]
+pub const NETBOOT_FIRMWARE_PATH: &str = "/usr/share/cloud-hypervisor/CLOUDHV.fd";
+
+#[cfg(target_arch = "aarch64")]
+pub const NETBOOT_FIRMWARE_PATH: &str = FIRMWARE_PATH;
+
+/// Whether the hypervisor can expose Hyper-V enlightenments to guests.
+pub const HYPERV_SUPPORTED: bool = cfg!(target_arch = "x86_64");
+
+/// Whether the hypervisor can pass OEM strings in the SMBIOS tables, which
+/// is how the ignition config reaches the guest.
+pub const OEM_STRINGS_SUPPORTED: bool = cfg!(target_arch = "x86_64");
+
+/// Rejects the options of a VM that the architecture of the host cannot
+/// provide, before anything is set up for the VM.
+pub fn check_config(config: &VmConfig) -> Result<(), VmmError> {
+    let arch = std::env::consts::ARCH;
+    if !HYPERV_SUPPORTED && config.clock.as_ref().is_some_and(|clock| clock.hyperv) {
+        return Err(VmmError::InvalidConfig(format!(
+            "The Hyper-V clock is not available on {arch} hosts"
+        )));
+    }
+    if !OEM_STRINGS_SUPPORTED
+        && config
+            .ignition
+            .as_ref()
+            .is_some_and(|data| !data.is_empty())
+    {
+        return Err(VmmError::InvalidConfig(format!(
+            "Ignition is passed in SMBIOS OEM strings, which are not available on {arch} hosts"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feos_proto::vm_service::ClockConfig;
+
+    #[test]
+    fn x86_only_options_are_checked() {
+        assert!(check_config(&VmConfig::default()).is_ok());
+
+        let hyperv = VmConfig {
+            clock: Some(ClockConfig {
+                hyperv: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(check_config(&hyperv).is_ok(), HYPERV_SUPPORTED);
+
+        let ignition = VmConfig {
+            ignition: Some("{}".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(check_config(&ignition).is_ok(), OEM_STRINGS_SUPPORTED);
+    }
+}
@@ -1,8 +1,14 @@
 // SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use super::{Hypervisor, VmmError};
-use crate::{VmEventWrapper, IMAGE_DIR, VM_API_SOCKET_DIR, VM_CONSOLE_DIR};
+use super::arch;
+use super::ch_events::ChEventMonitor;
+use super::ch_fds;
+use super::{CreatedVm, Hypervisor, VmmError};
+use crate::{
+    cgroup, VmEventWrapper, IMAGE_DIR, VM_API_SOCKET_DIR, VM_CONSOLE_DIR, VM_GUEST_CID,
+    VM_VSOCK_DIR,
+};
 use cloud_hypervisor_client::{
     apis::{configuration::Configuration, DefaultApi, DefaultApiClient},
     models::{
@@ -11,40 +17,79 @@ use cloud_hypervisor_client::{
     },
 };
 use feos_proto::vm_service::{
-    net_config, AttachDiskRequest, AttachDiskResponse, AttachNicRequest, AttachNicResponse,
-    CreateVmRequest, DeleteVmRequest, DeleteVmResponse, DetachDiskRequest, DetachDiskResponse,
-    DetachNicRequest, DetachNicResponse, GetVmRequest, PauseVmRequest, PauseVmResponse,
-    PingVmRequest, PingVmResponse, ResumeVmRequest, ResumeVmResponse, ShutdownVmRequest,
-    ShutdownVmResponse, StartVmRequest, StartVmResponse, VmConfig, VmInfo, VmState,
+    disk_config, net_config, AttachDiskRequest, AttachDiskResponse, AttachNicRequest,
+    AttachNicResponse, AttachPciDeviceRequest, AttachPciDeviceResponse, CreateVmRequest,
+    DeleteVmRequest, DeleteVmResponse, DetachDiskRequest, DetachDiskResponse, DetachNicRequest,
+    DetachNicResponse, DetachPciDeviceRequest, DetachPciDeviceResponse, DiskBus, DiskConfig,
+    GetVmRequest, MacvtapConfig, MacvtapMode, NetRateLimit, PauseVmRequest, PauseVmResponse,
+    PciDeviceConfig, PingVmRequest, PingVmResponse, ResizeVmRequest, ResizeVmResponse,
+    ResumeVmRequest, ResumeVmResponse, ShutdownVmRequest, ShutdownVmResponse, StartVmRequest,
+    StartVmResponse, VmConfig, VmInfo, VmState,
 };
+use feos_utils::filesystem::wait_for_path;
+use feos_utils::host::serial;
+use feos_utils::network::macvtap;
 use hyper_util::client::legacy::Client;
 use hyperlocal::{UnixClientExt, UnixConnector, Uri as HyperlocalUri};
 use log::{error, info, warn};
 use nix::sys::signal::{kill, Signal};
 use nix::unistd::{self, Pid};
+use std::collections::HashMap;
 use std::io;
+use std::os::fd::AsRawFd;
 use std::path::{Path, PathBuf};
+use std::process::Stdio;
 use std::sync::Arc;
+use std::time::SystemTime;
 use tokio::process::Command as TokioCommand;
 use tokio::sync::{broadcast, mpsc};
-use tokio::time::{self, timeout, Duration};
+use tokio::time::{self, Duration};
 use uuid::Uuid;
 
 #[derive(Debug)]
 pub enum ChNetworkDevice {
     Net(Box<models::NetConfig>),
     Device(models::DeviceConfig),
+    /// A NIC whose macvtap device is opened by FeOS and handed to the VMM.
+    Macvtap(Box<models::NetConfig>, MacvtapConfig),
 }
 
 impl ChNetworkDevice {
     pub fn id(&self) -> Option<String> {
         match self {
-            ChNetworkDevice::Net(config) => config.id.clone(),
+            ChNetworkDevice::Net(config) | ChNetworkDevice::Macvtap(config, _) => config.id.clone(),
             ChNetworkDevice::Device(config) => config.id.clone(),
         }
     }
 }
 
+fn token_bucket(per_second: u64, burst: u64) -> Option<models::TokenBucket> {
+    (per_second > 0).then(|| models::TokenBucket {
+        size: per_second as i64,
+        one_time_burst: (burst > 0).then_some(burst as i64),
+        refill_time: 1000,
+    })
+}
+
+fn convert_rate_limit_to_ch(limit: &NetRateLimit) -> models::RateLimiterConfig {
+    models::RateLimiterConfig {
+        bandwidth: token_bucket(
+            limit.bandwidth_bytes_per_second,
+            limit.bandwidth_burst_bytes,
+        ),
+        ops: token_bucket(limit.packets_per_second, limit.packets_burst),
+    }
+}
+
+fn macvtap_mode(mode: MacvtapMode) -> macvtap::MacvtapMode {
+    match mode {
+        MacvtapMode::Unspecified | MacvtapMode::Bridge => macvtap::MacvtapMode::Bridge,
+        MacvtapMode::Vepa => macvtap::MacvtapMode::Vepa,
+        MacvtapMode::Private => macvtap::MacvtapMode::Private,
+        MacvtapMode::Passthru => macvtap::MacvtapMode::Passthru,
+    }
+}
+
 fn convert_net_config_to_ch(
     nic: &feos_proto::vm_service::NetConfig,
 ) -> Result<ChNetworkDevice, VmmError> {
@@ -66,10 +111,46 @@ fn convert_net_config_to_ch(
                 tap: Some(tap.tap_name.clone()),
                 mac,
                 id,
+                rate_limiter_config: nic.rate_limit.as_ref().map(convert_rate_limit_to_ch),
                 ..Default::default()
             };
             Ok(ChNetworkDevice::Net(Box::new(ch_net_config)))
         }
+        Some(net_config::Backend::Macvtap(macvtap)) => {
+            if macvtap.parent.is_empty() || macvtap.name.is_empty() {
+                return Err(VmmError::InvalidConfig(
+                    "A macvtap NIC needs a parent interface and a name".to_string(),
+                ));
+            }
+            if nic.mac_address.is_empty() {
+                return Err(VmmError::InvalidConfig(
+                    "A macvtap NIC needs a MAC address".to_string(),
+                ));
+            }
+            let id = if !nic.device_id.is_empty() {
+                Some(nic.device_id.clone())
+            } else {
+                Some(macvtap.name.clone())
+            };
+
+            let ch_net_config = models::NetConfig {
+                mac: Some(nic.mac_address.clone()),
+                id,
+                rate_limiter_config: nic.rate_limit.as_ref().map(convert_rate_limit_to_ch),
+                ..Default::default()
+            };
+            Ok(ChNetworkDevice::Macvtap(
+                Box::new(ch_net_config),
+                macvtap.clone(),
+            ))
+        }
+        Some(net_config::Backend::VfioPci(_) | net_config::Backend::VhostUser(_))
+            if nic.rate_limit.is_some() =>
+        {
+            Err(VmmError::InvalidConfig(
+                "Rate limits are only supported for tap and macvtap NICs".to_string(),
+            ))
+        }
         Some(net_config::Backend::VfioPci(vfio_pci)) => {
             let device_path = format!("/sys/bus/pci/devices/{}", vfio_pci.bdf);
             let id = if !nic.device_id.is_empty() {
@@ -85,12 +166,123 @@ fn convert_net_config_to_ch(
             };
             Ok(ChNetworkDevice::Device(ch_device_config))
         }
+        Some(net_config::Backend::VhostUser(vhost_user)) => {
+            let id = if !nic.device_id.is_empty() {
+                Some(nic.device_id.clone())
+            } else {
+                Some(vhost_user.socket_path.clone())
+            };
+
+            let mac = if nic.mac_address.is_empty() {
+                None
+            } else {
+                Some(nic.mac_address.clone())
+            };
+
+            let vhost_mode = if vhost_user.server {
+                "Server"
+            } else {
+                "Client"
+            };
+            let ch_net_config = models::NetConfig {
+                vhost_user: Some(true),
+                vhost_socket: Some(vhost_user.socket_path.clone()),
+                vhost_mode: Some(vhost_mode.to_string()),
+                mac,
+                id,
+                ..Default::default()
+            };
+            Ok(ChNetworkDevice::Net(Box::new(ch_net_config)))
+        }
+        None => Err(VmmError::InvalidConfig(
+            "NetConfig backend (tap, vfio_pci, vhost_user or macvtap) is required".to_string(),
+        )),
+    }
+}
+
+/// The reason VMs that power off by themselves are reported stopped with.
+const GUEST_SHUTDOWN_REASON: &str = "Guest-initiated shutdown";
+
+fn event_monitor_path(vm_id: &str) -> PathBuf {
+    PathBuf::from(VM_API_SOCKET_DIR).join(format!("{vm_id}.events"))
+}
+
+#[derive(Debug)]
+pub enum ChDiskDevice {
+    Disk(Box<models::DiskConfig>),
+    Device(models::DeviceConfig),
+}
+
+fn convert_disk_config_to_ch(disk: &DiskConfig) -> Result<ChDiskDevice, VmmError> {
+    if disk.bus() == DiskBus::VirtioScsi {
+        return Err(VmmError::InvalidConfig(format!(
+            "Disk '{}' requests virtio-scsi, but Cloud Hypervisor only provides virtio-blk",
+            disk.device_id
+        )));
+    }
+    let id = |default: &str| {
+        if disk.device_id.is_empty() {
+            Some(default.to_string())
+        } else {
+            Some(disk.device_id.clone())
+        }
+    };
+
+    match &disk.backend {
+        Some(disk_config::Backend::Path(path)) => {
+            let ch_disk_config = models::DiskConfig {
+                path: Some(path.clone()),
+                readonly: Some(disk.readonly),
+                id: id(path),
+                ..Default::default()
+            };
+            Ok(ChDiskDevice::Disk(Box::new(ch_disk_config)))
+        }
+        // Discard reaches the image through the vhost-user-blk backend, which
+        // advertises it to the guest.
+        Some(disk_config::Backend::VhostUserBlk(vhost_user_blk)) => {
+            let ch_disk_config = models::DiskConfig {
+                vhost_user: Some(true),
+                vhost_socket: Some(vhost_user_blk.socket_path.clone()),
+                readonly: Some(disk.readonly),
+                id: id(&vhost_user_blk.socket_path),
+                ..Default::default()
+            };
+            Ok(ChDiskDevice::Disk(Box::new(ch_disk_config)))
+        }
+        Some(disk_config::Backend::VfioPci(vfio_pci)) => {
+            let device_path = format!("/sys/bus/pci/devices/{}", vfio_pci.bdf);
+            let ch_device_config = models::DeviceConfig {
+                id: id(&device_path),
+                path: device_path,
+                ..Default::default()
+            };
+            Ok(ChDiskDevice::Device(ch_device_config))
+        }
+        Some(
+            disk_config::Backend::Iscsi(_)
+            | disk_config::Backend::Nvmeof(_)
+            | disk_config::Backend::Rbd(_)
+            | disk_config::Backend::Ephemeral(_)
+            | disk_config::Backend::Overlay(_),
+        ) => Err(VmmError::InvalidConfig(
+            "iSCSI, NVMe-oF, RBD, ephemeral and overlay disks must be attached through their host device or export"
+                .to_string(),
+        )),
         None => Err(VmmError::InvalidConfig(
-            "NetConfig backend (tap or vfio_pci) is required".to_string(),
+            "DiskConfig backend is required".to_string(),
         )),
     }
 }
 
+fn pci_device_config_to_ch(device: &PciDeviceConfig) -> models::DeviceConfig {
+    models::DeviceConfig {
+        path: format!("/sys/bus/pci/devices/{}", device.pci_address),
+        id: (!device.device_id.is_empty()).then(|| device.device_id.clone()),
+        ..Default::default()
+    }
+}
+
 pub struct CloudHypervisorAdapter {
     ch_binary_path: PathBuf,
 }
@@ -126,34 +318,34 @@ impl CloudHypervisorAdapter {
         config: VmConfig,
         image_uuid: String,
         api_socket_path: &Path,
-    ) -> Result<(), VmmError> {
-        let wait_for_socket = async {
-            while !api_socket_path.exists() {
-                tokio::time::sleep(Duration::from_millis(50)).await;
-            }
-        };
-
-        if timeout(Duration::from_secs(5), wait_for_socket)
-            .await
-            .is_err()
-        {
-            return Err(VmmError::ApiConnectionFailed(
-                "Timed out waiting for API socket".to_string(),
-            ));
+    ) -> Result<SystemTime, VmmError> {
+        if let Err(e) = wait_for_path(api_socket_path, Duration::from_secs(5)).await {
+            return Err(VmmError::ApiConnectionFailed(format!(
+                "Failed waiting for API socket: {e}"
+            )));
         }
+        let vmm_ready_at = SystemTime::now();
         info!("CloudHypervisorAdapter ({vm_id}): API socket is available.");
 
         let client = self.get_ch_api_client(vm_id)?;
         tokio::fs::create_dir_all(VM_CONSOLE_DIR)
             .await
             .map_err(|e| VmmError::Internal(format!("Failed to create console dir: {e}")))?;
+        tokio::fs::create_dir_all(VM_VSOCK_DIR)
+            .await
+            .map_err(|e| VmmError::Internal(format!("Failed to create vsock dir: {e}")))?;
 
         let rootfs_path_str = format!("{IMAGE_DIR}/{image_uuid}/disk.image");
         let console_socket_path = format!("{VM_CONSOLE_DIR}/{vm_id}.console");
+        let vsock_socket_path = format!("{VM_VSOCK_DIR}/{vm_id}.vsock");
 
         let mut ch_vm_config = models::VmConfig {
             payload: models::PayloadConfig {
-                firmware: Some("/usr/share/cloud-hypervisor/hypervisor-fw".to_string()),
+                firmware: Some(if config.netboot.is_some() {
+                    arch::NETBOOT_FIRMWARE_PATH.to_string()
+                } else {
+                    arch::FIRMWARE_PATH.to_string()
+                }),
                 ..Default::default()
             },
             disks: Some(vec![models::DiskConfig {
@@ -165,32 +357,60 @@ impl CloudHypervisorAdapter {
                 mode: ConsoleMode::Socket,
                 ..Default::default()
             }),
+            // The hypervisor's stdio is the serial port of the VM, if any.
             console: Some(models::ConsoleConfig {
-                mode: ConsoleMode::Off,
+                mode: if config.serial_port.is_some() {
+                    ConsoleMode::Tty
+                } else {
+                    ConsoleMode::Off
+                },
                 ..Default::default()
             }),
+            vsock: Some(models::VsockConfig::new(VM_GUEST_CID, vsock_socket_path)),
             ..Default::default()
         };
 
+        arch::check_config(&config)?;
+        let hyperv_clock = config.clock.as_ref().is_some_and(|clock| clock.hyperv);
         if let Some(cpus) = config.cpus {
             ch_vm_config.cpus = Some(models::CpusConfig {
                 boot_vcpus: cpus.boot_vcpus as i32,
                 max_vcpus: cpus.max_vcpus as i32,
+                kvm_hyperv: hyperv_clock.then_some(true),
                 ..Default::default()
             });
         }
 
         if let Some(mem) = config.memory {
+            let hotplug = mem.hotplug_size_mib > 0;
             ch_vm_config.memory = Some(models::MemoryConfig {
                 size: mem.size_mib as i64 * 1024 * 1024,
                 shared: Some(true),
                 hugepages: Some(mem.hugepages),
+                hotplug_method: hotplug.then(|| "VirtioMem".to_string()),
+                hotplug_size: hotplug.then_some(mem.hotplug_size_mib as i64 * 1024 * 1024),
+                hotplugged_size: (hotplug && mem.hotplugged_size_mib > 0)
+                    .then_some(mem.hotplugged_size_mib as i64 * 1024 * 1024),
                 ..Default::default()
             });
         }
 
         let mut ch_net_configs: Vec<models::NetConfig> = Vec::new();
         let mut ch_device_configs: Vec<models::DeviceConfig> = Vec::new();
+        let mut macvtap_nets = Vec::new();
+
+        for disk in &config.disks {
+            match convert_disk_config_to_ch(disk)? {
+                ChDiskDevice::Disk(disk_config) => {
+                    if let Some(disks) = ch_vm_config.disks.as_mut() {
+                        disks.push(*disk_config);
+                    }
+                }
+                ChDiskDevice::Device(device_config) => {
+                    ch_device_configs.push(device_config);
+                }
+            }
+        }
 
         for nc in config.net {
             match convert_net_config_to_ch(&nc)? {
@@ -200,9 +420,38 @@ impl CloudHypervisorAdapter {
                 ChNetworkDevice::Device(device_config) => {
                     ch_device_configs.push(device_config);
                 }
+                ChNetworkDevice::Macvtap(net_config, macvtap) => {
+                    macvtap_nets.push((net_config, macvtap));
+                }
             }
         }
 
+        for (i, gpu) in config.gpus.iter().enumerate() {
+            if gpu.pci_address.is_empty() {
+                return Err(VmmError::InvalidConfig(format!(
+                    "No GPU partition was assigned for profile '{}'",
+                    gpu.profile
+                )));
+            }
+            ch_device_configs.push(models::DeviceConfig {
+                path: format!("/sys/bus/pci/devices/{}", gpu.pci_address),
+                id: Some(format!("gpu{i}")),
+                ..Default::default()
+            });
+        }
+
+        for device in &config.pci_devices {
+            ch_device_configs.push(pci_device_config_to_ch(device));
+        }
+
+        for (i, mdev) in config.mdevs.iter().enumerate() {
+            ch_device_configs.push(models::DeviceConfig {
+                path: format!("/sys/bus/mdev/devices/{}", mdev.uuid),
+                id: Some(format!("mdev{i}")),
+                ..Default::default()
+            });
+        }
+
         if !ch_net_configs.is_empty() {
             ch_vm_config.net = Some(ch_net_configs);
         }
@@ -228,7 +477,60 @@ impl CloudHypervisorAdapter {
 
         info!("CloudHypervisorAdapter ({vm_id}): vm.create API call successful.");
 
-        Ok::<(), VmmError>(())
+        // The devices of macvtap NICs can only be handed over with vm.add-net,
+        // the VMM adds them to the VM before it boots.
+        for (net_config, macvtap) in macvtap_nets {
+            self.add_macvtap_net(vm_id, *net_config, &macvtap).await?;
+        }
+
+        Ok(vmm_ready_at)
+    }
+
+    /// Creates the macvtap device of a NIC if needed and adds the NIC to the
+    /// VM with the device opened.
+    async fn add_macvtap_net(
+        &self,
+        vm_id: &str,
+        net_config: models::NetConfig,
+        config: &MacvtapConfig,
+    ) -> Result<(), VmmError> {
+        let mac = net_config.mac.clone().unwrap_or_default();
+        let index = macvtap::ensure(
+            &config.name,
+            &config.parent,
+            macvtap_mode(config.mode()),
+            &mac,
+        )
+        .await
+        .map_err(|e| {
+            VmmError::Internal(format!(
+                "Failed to create macvtap device {} on {}: {e}",
+                config.name, config.parent
+            ))
+        })?;
+        let device_path = macvtap::device_path(index);
+        wait_for_path(&device_path, Duration::from_secs(5))
+            .await
+            .map_err(|e| {
+                VmmError::Internal(format!("Failed waiting for {}: {e}", device_path.display()))
+            })?;
+        let tap = macvtap::open(index).map_err(|e| {
+            VmmError::Internal(format!("Failed to open {}: {e}", device_path.display()))
+        })?;
+        let body = serde_json::to_vec(&net_config)
+            .map_err(|e| VmmError::Internal(format!("Failed to encode NIC config: {e}")))?;
+        let socket_path = PathBuf::from(VM_API_SOCKET_DIR).join(vm_id);
+        tokio::task::spawn_blocking(move || {
+            ch_fds::put_with_fds(&socket_path, "vm.add-net", &body, &[tap.as_raw_fd()])
+        })
+        .await
+        .map_err(|e| VmmError::Internal(e.to_string()))?
+        .map_err(|e| VmmError::ApiOperationFailed(format!("vm.add-net failed: {e}")))?;
+        info!(
+            "CloudHypervisorAdapter ({vm_id}): Added macvtap NIC {} on {}",
+            config.name, config.parent
+        );
+        Ok(())
     }
 
     async fn cleanup_socket_file(&self, vm_id: &str, socket_path: &Path, socket_type: &str) {
@@ -248,6 +550,32 @@ impl CloudHypervisorAdapter {
     }
 }
 
+/// Whether the VMM reported that the guest powered off.
+async fn guest_shut_down(vm_id: &str, events: &mut ChEventMonitor) -> bool {
+    match events.read_events().await {
+        Ok(events) => events.iter().any(|event| event.is_guest_shutdown()),
+        Err(e) => {
+            warn!("CloudHypervisorAdapter ({vm_id}): Failed to read hypervisor events: {e}");
+            false
+        }
+    }
+}
+
+async fn report_guest_shutdown(vm_id: &str, broadcast_tx: &mpsc::Sender<VmEventWrapper>) {
+    info!("CloudHypervisorAdapter ({vm_id}): Guest powered off, VM is stopped.");
+    super::broadcast_state_change_event(
+        broadcast_tx,
+        vm_id,
+        "vm-health-monitor",
+        feos_proto::vm_service::VmStateChangedEvent {
+            new_state: VmState::Stopped as i32,
+            reason: GUEST_SHUTDOWN_REASON.to_string(),
+        },
+        None,
+    )
+    .await;
+}
+
 #[tonic::async_trait]
 impl Hypervisor for CloudHypervisorAdapter {
     async fn create_vm(
@@ -255,7 +583,7 @@ impl Hypervisor for CloudHypervisorAdapter {
         vm_id: &str,
         req: CreateVmRequest,
         image_uuid: String,
-    ) -> Result<Option<i64>, VmmError> {
+    ) -> Result<CreatedVm, VmmError> {
         info!("CloudHypervisorAdapter: Creating VM with provided ID: {vm_id}");
 
         let config = req
@@ -264,33 +592,94 @@ impl Hypervisor for CloudHypervisorAdapter {
 
         let api_socket_path = PathBuf::from(VM_API_SOCKET_DIR).join(vm_id);
 
+        // Cloud Hypervisor appends to the file, events of an earlier VM with
+        // the same ID must not be read again.
+        let event_path = event_monitor_path(vm_id);
+        if let Err(e) = tokio::fs::remove_file(&event_path).await {
+            if e.kind() != io::ErrorKind::NotFound {
+                return Err(VmmError::Internal(format!(
+                    "Failed to remove stale event file {}: {e}",
+                    event_path.display()
+                )));
+            }
+        }
+
+        let mut command = TokioCommand::new(&self.ch_binary_path);
+        command.arg("--api-socket").arg(&api_socket_path);
+        command
+            .arg("--event-monitor")
+            .arg(format!("path={}", event_path.display()));
+        if let Some(config) = &config.serial_port {
+            let port = serial::serial_port(&config.path)
+                .and_then(|port| serial::open_serial_port(&port, config.baud_rate))
+                .map_err(|e| {
+                    VmmError::InvalidConfig(format!(
+                        "Failed to open serial port {}: {e}",
+                        config.path
+                    ))
+                })?;
+            let output = port
+                .try_clone()
+                .map_err(|e| VmmError::Internal(format!("Failed to duplicate serial port: {e}")))?;
+            command.stdin(Stdio::from(port)).stdout(Stdio::from(output));
+            info!(
+                "CloudHypervisorAdapter ({vm_id}): Connecting serial port {} to the VM console",
+                config.path
+            );
+        }
+
         info!("CloudHypervisorAdapter ({vm_id}): Spawning cloud-hypervisor process...");
         let mut child = unsafe {
-            TokioCommand::new(&self.ch_binary_path)
-                .arg("--api-socket")
-                .arg(&api_socket_path)
+            command
                 .pre_exec(|| unistd::setsid().map(|_pid| ()).map_err(io::Error::other))
                 .spawn()
         }
         .map_err(|e| VmmError::ProcessSpawnFailed(e.to_string()))?;
         let pid = child.id().map(|id| id as i64);
 
+        let swap_max = config.memory.as_ref().and_then(|m| m.swap_max_bytes);
+        if let (Some(swap_max), Some(pid)) = (swap_max, pid) {
+            if let Err(e) = cgroup::limit_swap(vm_id, pid, swap_max).await {
+                let _ = child.kill().await;
+                cgroup::remove(vm_id).await;
+                return Err(VmmError::ProcessSpawnFailed(format!(
+                    "Failed to limit swap of the hypervisor process: {e}"
+                )));
+            }
+        }
+
+        let numa_node = config
+            .placement
+            .as_ref()
+            .and_then(|placement| placement.assigned_numa_node);
+        if let (Some(node), Some(pid)) = (numa_node, pid) {
+            if let Err(e) = cgroup::bind_to_node(vm_id, pid, node).await {
+                let _ = child.kill().await;
+                cgroup::remove(vm_id).await;
+                return Err(VmmError::ProcessSpawnFailed(format!(
+                    "Failed to bind the hypervisor process to NUMA node {node}: {e}"
+                )));
+            }
+        }
+
         let vm_creation = self.perform_vm_creation(vm_id, config, image_uuid, &api_socket_path);
 
         tokio::select! {
             biased;
             exit_status_res = child.wait() => {
+                cgroup::remove(vm_id).await;
                 let status = exit_status_res.map_err(|e| VmmError::ProcessSpawnFailed(format!("Failed to wait for child process: {e}")))?;
                 Err(VmmError::ProcessSpawnFailed(format!("Process exited prematurely with status: {status}")))
             }
             creation_result = vm_creation => {
                 match creation_result {
-                    Ok(_) => Ok(pid),
+                    Ok(vmm_ready_at) => Ok(CreatedVm { process_id: pid, vmm_ready_at }),
                     Err(e) => {
                         if let Err(kill_err) = child.kill().await {
                              warn!("CloudHypervisorAdapter ({vm_id}): Failed to kill child process after creation failure: {kill_err}");
                         }
                         let _ = child.wait().await;
+                        cgroup::remove(vm_id).await;
                         Err(e)
                     }
                 }
@@ -316,6 +705,10 @@ impl Hypervisor for CloudHypervisorAdapter {
     ) {
         info!("CloudHypervisorAdapter ({vm_id}): Starting healthcheck monitoring.");
         let mut interval = time::interval(Duration::from_secs(10));
+        // Events are checked more often than the VMM is pinged, so a guest that
+        // powers off is reported stopped right away.
+        let mut event_interval = time::interval(Duration::from_secs(1));
+        let mut events = ChEventMonitor::new(event_monitor_path(&vm_id));
         let vm_id_uuid = match Uuid::parse_str(&vm_id) {
             Ok(id) => id,
             Err(e) => {
@@ -333,6 +726,12 @@ impl Hypervisor for CloudHypervisorAdapter {
                     };
 
                     if let Err(e) = self.ping_vm(req).await {
+                        // The VMM exits when the guest powers off, which must
+                        // not be taken for a crash.
+                        if guest_shut_down(&vm_id, &mut events).await {
+                            report_guest_shutdown(&vm_id, &broadcast_tx).await;
+                            break;
+                        }
                         warn!("CloudHypervisorAdapter ({vm_id}): Healthcheck failed: {e}. VM is considered unhealthy.");
                         super::broadcast_state_change_event(
                             &broadcast_tx,
@@ -350,6 +749,12 @@ impl Hypervisor for CloudHypervisorAdapter {
                         log::debug!("CloudHypervisorAdapter ({vm_id}): Healthcheck ping successful.");
                     }
                 }
+                _ = event_interval.tick() => {
+                    if guest_shut_down(&vm_id, &mut events).await {
+                        report_guest_shutdown(&vm_id, &broadcast_tx).await;
+                        break;
+                    }
+                }
                 Ok(cancelled_vm_id) = cancel_bus.recv() => {
                     if cancelled_vm_id == vm_id_uuid {
                         info!("CloudHypervisorAdapter ({vm_id}): Received cancellation signal. Stopping healthcheck.");
@@ -383,6 +788,9 @@ impl Hypervisor for CloudHypervisorAdapter {
             vm_id: req.vm_id,
             state: state as i32,
             config: None,
+            boot_timings: None,
+            guest_addresses: Vec::new(),
+            ..Default::default()
         })
     }
 
@@ -427,6 +835,8 @@ impl Hypervisor for CloudHypervisorAdapter {
             }
         }
 
+        cgroup::remove(&req.vm_id).await;
+
         let api_socket_path = PathBuf::from(VM_API_SOCKET_DIR).join(&req.vm_id);
         self.cleanup_socket_file(&req.vm_id, &api_socket_path, "API")
             .await;
@@ -436,6 +846,19 @@ impl Hypervisor for CloudHypervisorAdapter {
         self.cleanup_socket_file(&req.vm_id, &console_socket_path, "console")
             .await;
 
+        let vsock_socket_path = PathBuf::from(VM_VSOCK_DIR).join(format!("{}.vsock", req.vm_id));
+        self.cleanup_socket_file(&req.vm_id, &vsock_socket_path, "vsock")
+            .await;
+
+        if let Err(e) = tokio::fs::remove_file(event_monitor_path(&req.vm_id)).await {
+            if e.kind() != io::ErrorKind::NotFound {
+                warn!(
+                    "CloudHypervisorAdapter ({vm_id}): Failed to remove event file: {e}",
+                    vm_id = req.vm_id
+                );
+            }
+        }
+
         Ok(DeleteVmResponse {})
     }
 
@@ -451,6 +874,18 @@ impl Hypervisor for CloudHypervisorAdapter {
         }
     }
 
+    async fn get_vsock_socket_path(&self, vm_id: &str) -> Result<PathBuf, VmmError> {
+        let socket_path = PathBuf::from(VM_VSOCK_DIR).join(format!("{vm_id}.vsock"));
+        if tokio::fs::try_exists(&socket_path)
+            .await
+            .map_err(|e| VmmError::Internal(e.to_string()))?
+        {
+            Ok(socket_path)
+        } else {
+            Err(VmmError::VmNotFound(vm_id.to_string()))
+        }
+    }
+
     async fn ping_vm(&self, req: PingVmRequest) -> Result<PingVmResponse, VmmError> {
         let api_client = self.get_ch_api_client(&req.vm_id)?;
         let ch_ping: ChPingResponse = api_client
@@ -475,6 +910,14 @@ impl Hypervisor for CloudHypervisorAdapter {
         Ok(ShutdownVmResponse {})
     }
 
+    async fn power_button_vm(&self, vm_id: &str) -> Result<(), VmmError> {
+        let api_client = self.get_ch_api_client(vm_id)?;
+        api_client
+            .power_button_vm()
+            .await
+            .map_err(|e| VmmError::ApiOperationFailed(e.to_string()))
+    }
+
     async fn pause_vm(&self, req: PauseVmRequest) -> Result<PauseVmResponse, VmmError> {
         let api_client = self.get_ch_api_client(&req.vm_id)?;
         api_client
@@ -493,16 +936,37 @@ impl Hypervisor for CloudHypervisorAdapter {
         Ok(ResumeVmResponse {})
     }
 
-    async fn attach_disk(&self, _req: AttachDiskRequest) -> Result<AttachDiskResponse, VmmError> {
-        Err(VmmError::Internal(
-            "AttachDisk not implemented for CloudHypervisorAdapter".to_string(),
-        ))
+    async fn attach_disk(&self, req: AttachDiskRequest) -> Result<AttachDiskResponse, VmmError> {
+        let api_client = self.get_ch_api_client(&req.vm_id)?;
+        let disk = req
+            .disk
+            .ok_or_else(|| VmmError::InvalidConfig("DiskConfig is required".to_string()))?;
+        let device_info = match convert_disk_config_to_ch(&disk)? {
+            ChDiskDevice::Disk(ch_disk_config) => api_client
+                .vm_add_disk_put(*ch_disk_config)
+                .await
+                .map_err(|e| VmmError::ApiOperationFailed(format!("vm.add-disk failed: {e}")))?,
+            ChDiskDevice::Device(ch_device_config) => api_client
+                .vm_add_device_put(ch_device_config)
+                .await
+                .map_err(|e| VmmError::ApiOperationFailed(format!("vm.add-device failed: {e}")))?,
+        };
+
+        Ok(AttachDiskResponse {
+            device_id: device_info.id,
+        })
     }
 
-    async fn detach_disk(&self, _req: DetachDiskRequest) -> Result<DetachDiskResponse, VmmError> {
-        Err(VmmError::Internal(
-            "DetachDisk not implemented for CloudHypervisorAdapter".to_string(),
-        ))
+    async fn detach_disk(&self, req: DetachDiskRequest) -> Result<DetachDiskResponse, VmmError> {
+        let api_client = self.get_ch_api_client(&req.vm_id)?;
+        let device_to_remove = models::VmRemoveDevice {
+            id: Some(req.device_id),
+        };
+        api_client
+            .vm_remove_device_put(device_to_remove)
+            .await
+            .map_err(|e| VmmError::ApiOperationFailed(format!("vm.remove-device failed: {e}")))?;
+        Ok(DetachDiskResponse {})
     }
 
     async fn attach_nic(&self, req: AttachNicRequest) -> Result<AttachNicResponse, VmmError> {
@@ -529,6 +993,10 @@ impl Hypervisor for CloudHypervisorAdapter {
                         VmmError::ApiOperationFailed(format!("vm.add-device failed: {e}"))
                     })?;
             }
+            ChNetworkDevice::Macvtap(ch_net_config, macvtap) => {
+                self.add_macvtap_net(&req.vm_id, *ch_net_config, &macvtap)
+                    .await?;
+            }
         }
 
         Ok(AttachNicResponse {
@@ -547,4 +1015,105 @@ impl Hypervisor for CloudHypervisorAdapter {
             .map_err(|e| VmmError::ApiOperationFailed(format!("vm.remove-device failed: {e}")))?;
         Ok(DetachNicResponse {})
     }
+
+    async fn attach_pci_device(
+        &self,
+        req: AttachPciDeviceRequest,
+    ) -> Result<AttachPciDeviceResponse, VmmError> {
+        let api_client = self.get_ch_api_client(&req.vm_id)?;
+        let device = req
+            .device
+            .ok_or_else(|| VmmError::InvalidConfig("PciDeviceConfig is required".to_string()))?;
+        let device_info = api_client
+            .vm_add_device_put(pci_device_config_to_ch(&device))
+            .await
+            .map_err(|e| VmmError::ApiOperationFailed(format!("vm.add-device failed: {e}")))?;
+        Ok(AttachPciDeviceResponse {
+            device_id: device_info.id,
+        })
+    }
+
+    async fn detach_pci_device(
+        &self,
+        req: DetachPciDeviceRequest,
+    ) -> Result<DetachPciDeviceResponse, VmmError> {
+        let api_client = self.get_ch_api_client(&req.vm_id)?;
+        let device_to_remove = models::VmRemoveDevice {
+            id: Some(req.device_id),
+        };
+        api_client
+            .vm_remove_device_put(device_to_remove)
+            .await
+            .map_err(|e| VmmError::ApiOperationFailed(format!("vm.remove-device failed: {e}")))?;
+        Ok(DetachPciDeviceResponse {})
+    }
+
+    async fn resize_vm(&self, req: ResizeVmRequest) -> Result<ResizeVmResponse, VmmError> {
+        let api_client = self.get_ch_api_client(&req.vm_id)?;
+        let resize = models::VmResize {
+            desired_vcpus: req.vcpus.map(|vcpus| vcpus as i32),
+            desired_ram: req.memory_mib.map(|memory| memory as i64 * 1024 * 1024),
+            desired_balloon: None,
+        };
+        api_client
+            .vm_resize_put(resize)
+            .await
+            .map_err(|e| VmmError::ApiOperationFailed(format!("vm.resize failed: {e}")))?;
+        Ok(ResizeVmResponse {})
+    }
+
+    async fn snapshot_vm(&self, vm_id: &str, destination: &Path) -> Result<(), VmmError> {
+        let api_client = self.get_ch_api_client(vm_id)?;
+        let snapshot_config = models::VmSnapshotConfig {
+            destination_url: Some(format!("file://{}", destination.display())),
+        };
+        api_client
+            .vm_snapshot_put(snapshot_config)
+            .await
+            .map_err(|e| VmmError::ApiOperationFailed(format!("vm.snapshot failed: {e}")))?;
+        info!(
+            "CloudHypervisorAdapter ({vm_id}): Saved VM state to {}",
+            destination.display()
+        );
+        Ok(())
+    }
+
+    async fn restore_vm(&self, vm_id: &str, source: &Path) -> Result<(), VmmError> {
+        let api_client = self.get_ch_api_client(vm_id)?;
+        // A VMM can only restore into an empty slot, so the current VM goes first. The
+        // restored VM recreates its console and vsock sockets at the same paths.
+        api_client
+            .delete_vm()
+            .await
+            .map_err(|e| VmmError::ApiOperationFailed(format!("vm.delete failed: {e}")))?;
+
+        let console_socket_path = PathBuf::from(VM_CONSOLE_DIR).join(format!("{vm_id}.console"));
+        self.cleanup_socket_file(vm_id, &console_socket_path, "console")
+            .await;
+        let vsock_socket_path = PathBuf::from(VM_VSOCK_DIR).join(format!("{vm_id}.vsock"));
+        self.cleanup_socket_file(vm_id, &vsock_socket_path, "vsock")
+            .await;
+
+        let restore_config = models::RestoreConfig::new(format!("file://{}", source.display()));
+        api_client
+            .vm_restore_put(restore_config)
+            .await
+            .map_err(|e| VmmError::ApiOperationFailed(format!("vm.restore failed: {e}")))?;
+        info!(
+            "CloudHypervisorAdapter ({vm_id}): Restored VM state from {}",
+            source.display()
+        );
+        Ok(())
+    }
+
+    async fn vm_counters(
+        &self,
+        vm_id: &str,
+    ) -> Result<HashMap<String, HashMap<String, i64>>, VmmError> {
+        let api_client = self.get_ch_api_client(vm_id)?;
+        api_client
+            .vm_counters_get()
+            .await
+            .map_err(|e| VmmError::ApiOperationFailed(format!("vm.counters failed: {e}")))
+    }
 }
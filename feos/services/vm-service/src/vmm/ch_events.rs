@@ -0,0 +1,129 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use serde::Deserialize;
+use std::io::{self, SeekFrom};
+use std::path::PathBuf;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// An event Cloud Hypervisor writes to its `--event-monitor` file.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ChEvent {
+    /// What the event is about, e.g. `vmm`, `vm` or `guest`.
+    pub source: String,
+    /// What happened, e.g. `booted` or `shutdown`.
+    pub event: String,
+}
+
+impl ChEvent {
+    /// The VMM only shuts down by itself when the guest powered off, e.g. by
+    /// an ACPI shutdown. Shutting down the VM through the API keeps the VMM.
+    pub fn is_guest_shutdown(&self) -> bool {
+        self.source == "vmm" && self.event == "shutdown"
+    }
+}
+
+/// Follows the event monitor file of a Cloud Hypervisor process.
+pub struct ChEventMonitor {
+    path: PathBuf,
+    offset: u64,
+}
+
+impl ChEventMonitor {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, offset: 0 }
+    }
+
+    /// Reads the events written since the last call. An event that is only
+    /// partially written yet is read by the next call.
+    pub async fn read_events(&mut self) -> io::Result<Vec<ChEvent>> {
+        let mut file = match tokio::fs::File::open(&self.path).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        file.seek(SeekFrom::Start(self.offset)).await?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).await?;
+        let (events, consumed) = parse_events(&data)?;
+        self.offset += consumed as u64;
+        Ok(events)
+    }
+}
+
+/// Parses the pretty-printed JSON events Cloud Hypervisor writes one after
+/// another. Returns the events and how many bytes they took.
+fn parse_events(data: &[u8]) -> io::Result<(Vec<ChEvent>, usize)> {
+    let mut stream = serde_json::Deserializer::from_slice(data).into_iter::<ChEvent>();
+    let mut events = Vec::new();
+    let mut consumed = 0;
+    loop {
+        match stream.next() {
+            Some(Ok(event)) => {
+                events.push(event);
+                consumed = stream.byte_offset();
+            }
+            Some(Err(e)) if e.is_eof() => break,
+            Some(Err(e)) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+            None => {
+                consumed = data.len();
+                break;
+            }
+        }
+    }
+    Ok((events, consumed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EVENTS: &str = r#"{
+  "timestamp": {
+    "secs": 0,
+    "nanos": 21000
+  },
+  "source": "vmm",
+  "event": "starting",
+  "properties": null
+}
+
+{
+  "timestamp": {
+    "secs": 12,
+    "nanos": 5000
+  },
+  "source": "vm",
+  "event": "shutdown",
+  "properties": null
+}
+
+{
+  "timestamp": {
+    "secs": 12,
+    "nanos": 9000
+  },
+  "source": "vmm",
+  "event": "shutdown",
+  "properties": null
+}
+
+"#;
+
+    #[test]
+    fn partially_written_events_are_read_later() {
+        let split = EVENTS.find(r#""source": "vm","#).unwrap();
+        let (events, consumed) = parse_events(&EVENTS.as_bytes()[..split]).unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(!events[0].is_guest_shutdown());
+
+        let (events, _) = parse_events(&EVENTS.as_bytes()[consumed..]).unwrap();
+        assert_eq!(
+            events.iter().map(|e| e.event.as_str()).collect::<Vec<_>>(),
+            ["shutdown", "shutdown"]
+        );
+        assert!(!events[0].is_guest_shutdown());
+        assert!(events[1].is_guest_shutdown());
+        assert!(parse_events(b"{\"source\": 1}").is_err());
+    }
+}
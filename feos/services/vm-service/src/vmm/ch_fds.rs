@@ -0,0 +1,113 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Cloud Hypervisor API requests that hand file descriptors to the VMM, which
+//! the generated client cannot do. The VMM takes the descriptors that come
+//! with a request as SCM_RIGHTS, e.g. the tap devices of `vm.add-net`.
+
+use nix::sys::socket::{sendmsg, ControlMessage, MsgFlags};
+use std::io::{self, IoSlice, Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The status code and body of an HTTP/1.1 response. The VMM keeps the
+/// connection open, so the body is read by its Content-Length.
+fn read_response(reader: &mut impl Read) -> io::Result<(u16, Vec<u8>)> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let mut read_more = |buf: &mut Vec<u8>| {
+        let n = reader.read(&mut chunk)?;
+        if n == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        Ok::<_, io::Error>(())
+    };
+    let head_end = loop {
+        if let Some(end) = buf.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end;
+        }
+        read_more(&mut buf)?;
+    };
+    let head = std::str::from_utf8(&buf[..head_end]).map_err(|_| invalid("header is not UTF-8"))?;
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| invalid("malformed status line"))?;
+    let length = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .map(|(_, value)| value.trim().parse::<usize>())
+        .transpose()
+        .map_err(|_| invalid("malformed Content-Length"))?
+        .unwrap_or(0);
+    let body_start = head_end + 4;
+    while buf.len() < body_start + length {
+        read_more(&mut buf)?;
+    }
+    Ok((status, buf[body_start..body_start + length].to_vec()))
+}
+
+/// Sends a PUT request with a JSON body to `endpoint`, e.g. `vm.add-net`, and
+/// `fds` along with it. Fails unless the VMM answers with a success status.
+pub(super) fn put_with_fds(
+    socket_path: &Path,
+    endpoint: &str,
+    body: &[u8],
+    fds: &[RawFd],
+) -> io::Result<Vec<u8>> {
+    let mut stream = UnixStream::connect(socket_path)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut request = format!(
+        "PUT /api/v1/{endpoint} HTTP/1.1\r\nHost: localhost\r\nAccept: application/json\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+        body.len()
+    )
+    .into_bytes();
+    request.extend_from_slice(body);
+    let sent = sendmsg::<()>(
+        stream.as_raw_fd(),
+        &[IoSlice::new(&request)],
+        &[ControlMessage::ScmRights(fds)],
+        MsgFlags::empty(),
+        None,
+    )?;
+    stream.write_all(&request[sent..])?;
+    let (status, body) = read_response(&mut stream)?;
+    if !(200..300).contains(&status) {
+        return Err(io::Error::other(format!(
+            "{endpoint} failed with status {status}: {}",
+            String::from_utf8_lossy(&body).trim()
+        )));
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn responses_are_read_by_content_length() {
+        let mut response: &[u8] =
+            b"HTTP/1.1 200 \r\ncontent-length: 5\r\nContent-Type: application/json\r\n\r\n{\"a\"}HTTP/1.1";
+        assert_eq!(
+            read_response(&mut response).unwrap(),
+            (200, b"{\"a\"}".to_vec())
+        );
+        let mut no_content: &[u8] = b"HTTP/1.1 204 No Content\r\n\r\n";
+        assert_eq!(read_response(&mut no_content).unwrap(), (204, Vec::new()));
+        let mut truncated: &[u8] = b"HTTP/1.1 500 \r\nContent-Length: 10\r\n\r\nshort";
+        assert_eq!(
+            read_response(&mut truncated).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+}
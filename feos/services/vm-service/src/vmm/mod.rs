@@ -3,20 +3,27 @@
 
 use crate::VmEventWrapper;
 use feos_proto::vm_service::{
-    AttachDiskRequest, AttachDiskResponse, AttachNicRequest, AttachNicResponse, CreateVmRequest,
-    DeleteVmRequest, DeleteVmResponse, DetachDiskRequest, DetachDiskResponse, DetachNicRequest,
-    DetachNicResponse, GetVmRequest, PauseVmRequest, PauseVmResponse, PingVmRequest,
-    PingVmResponse, ResumeVmRequest, ResumeVmResponse, ShutdownVmRequest, ShutdownVmResponse,
-    StartVmRequest, StartVmResponse, VmEvent, VmInfo, VmStateChangedEvent,
+    AttachDiskRequest, AttachDiskResponse, AttachNicRequest, AttachNicResponse,
+    AttachPciDeviceRequest, AttachPciDeviceResponse, CreateVmRequest, DeleteVmRequest,
+    DeleteVmResponse, DetachDiskRequest, DetachDiskResponse, DetachNicRequest, DetachNicResponse,
+    DetachPciDeviceRequest, DetachPciDeviceResponse, GetVmRequest, PauseVmRequest, PauseVmResponse,
+    PingVmRequest, PingVmResponse, ResizeVmRequest, ResizeVmResponse, ResumeVmRequest,
+    ResumeVmResponse, ShutdownVmRequest, ShutdownVmResponse, StartVmRequest, StartVmResponse,
+    VmBootPhase, VmBootPhaseEvent, VmEvent, VmInfo, VmStateChangedEvent,
 };
 use prost::Message;
 use prost_types::Any;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 use tokio::sync::{broadcast, mpsc};
 use tonic::Status;
 use uuid::Uuid;
 
+pub mod arch;
 pub mod ch_adapter;
+pub mod ch_events;
+mod ch_fds;
 
 #[derive(Debug, thiserror::Error)]
 pub enum VmmError {
@@ -57,6 +64,14 @@ impl From<VmmError> for Status {
     }
 }
 
+/// A VM whose hypervisor process is running and configured.
+#[derive(Debug)]
+pub struct CreatedVm {
+    pub process_id: Option<i64>,
+    /// When the hypervisor process started answering on its API socket.
+    pub vmm_ready_at: SystemTime,
+}
+
 #[tonic::async_trait]
 pub trait Hypervisor: Send + Sync {
     async fn create_vm(
@@ -64,7 +79,7 @@ pub trait Hypervisor: Send + Sync {
         vm_id: &str,
         req: CreateVmRequest,
         image_uuid: String,
-    ) -> Result<Option<i64>, VmmError>;
+    ) -> Result<CreatedVm, VmmError>;
 
     async fn start_vm(&self, req: StartVmRequest) -> Result<StartVmResponse, VmmError>;
 
@@ -85,14 +100,43 @@ pub trait Hypervisor: Send + Sync {
 
     async fn get_console_socket_path(&self, vm_id: &str) -> Result<PathBuf, VmmError>;
 
+    async fn get_vsock_socket_path(&self, vm_id: &str) -> Result<PathBuf, VmmError>;
+
     async fn ping_vm(&self, req: PingVmRequest) -> Result<PingVmResponse, VmmError>;
     async fn shutdown_vm(&self, req: ShutdownVmRequest) -> Result<ShutdownVmResponse, VmmError>;
+    /// Asks the guest to shut down, as pressing the power button does. The
+    /// VM keeps running until the guest powers off.
+    async fn power_button_vm(&self, vm_id: &str) -> Result<(), VmmError>;
     async fn pause_vm(&self, req: PauseVmRequest) -> Result<PauseVmResponse, VmmError>;
     async fn resume_vm(&self, req: ResumeVmRequest) -> Result<ResumeVmResponse, VmmError>;
     async fn attach_disk(&self, req: AttachDiskRequest) -> Result<AttachDiskResponse, VmmError>;
     async fn detach_disk(&self, req: DetachDiskRequest) -> Result<DetachDiskResponse, VmmError>;
     async fn attach_nic(&self, req: AttachNicRequest) -> Result<AttachNicResponse, VmmError>;
     async fn detach_nic(&self, req: DetachNicRequest) -> Result<DetachNicResponse, VmmError>;
+    /// Hot-plugs a PCI device of the host, which must be bound to vfio-pci.
+    async fn attach_pci_device(
+        &self,
+        req: AttachPciDeviceRequest,
+    ) -> Result<AttachPciDeviceResponse, VmmError>;
+    async fn detach_pci_device(
+        &self,
+        req: DetachPciDeviceRequest,
+    ) -> Result<DetachPciDeviceResponse, VmmError>;
+    /// Hotplugs vCPUs and virtio-mem memory into a running VM, or unplugs them.
+    async fn resize_vm(&self, req: ResizeVmRequest) -> Result<ResizeVmResponse, VmmError>;
+
+    /// Saves the memory and device state of a paused VM into the `destination` directory.
+    async fn snapshot_vm(&self, vm_id: &str, destination: &Path) -> Result<(), VmmError>;
+
+    /// Replaces the VM with the state saved by `snapshot_vm`. The restored VM is paused.
+    async fn restore_vm(&self, vm_id: &str, source: &Path) -> Result<(), VmmError>;
+
+    /// The counters of the devices of a VM, by device ID and counter name, e.g.
+    /// `read_bytes` of a disk or `rx_frames` of a NIC.
+    async fn vm_counters(
+        &self,
+        vm_id: &str,
+    ) -> Result<HashMap<String, HashMap<String, i64>>, VmmError>;
 }
 
 pub async fn broadcast_state_change_event(
@@ -110,6 +154,7 @@ pub async fn broadcast_state_change_event(
             type_url: "type.googleapis.com/feos.vm.vmm.api.v1.VmStateChangedEvent".to_string(),
             value: data.encode_to_vec(),
         }),
+        node_id: String::new(),
     };
 
     if broadcast_tx
@@ -121,6 +166,39 @@ pub async fn broadcast_state_change_event(
     }
 }
 
+pub async fn broadcast_boot_phase_event(
+    broadcast_tx: &mpsc::Sender<VmEventWrapper>,
+    vm_id: &str,
+    phase: VmBootPhase,
+    at: SystemTime,
+) {
+    let data = VmBootPhaseEvent {
+        phase: phase as i32,
+        timestamp: Some(at.into()),
+    };
+    let event = VmEvent {
+        vm_id: vm_id.to_string(),
+        id: Uuid::new_v4().to_string(),
+        component_id: "vm-service".to_string(),
+        data: Some(Any {
+            type_url: "type.googleapis.com/feos.vm.vmm.api.v1.VmBootPhaseEvent".to_string(),
+            value: data.encode_to_vec(),
+        }),
+        node_id: String::new(),
+    };
+
+    if broadcast_tx
+        .send(VmEventWrapper {
+            event,
+            process_id: None,
+        })
+        .await
+        .is_err()
+    {
+        log::warn!("Failed to broadcast boot phase event for VM '{vm_id}': channel closed.");
+    }
+}
+
 pub enum VmmType {
     CloudHypervisor,
 }
@@ -0,0 +1,87 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Connections to vsock ports of guests. Cloud Hypervisor offers the vsock
+//! device of a VM as a unix socket on the host, a "hybrid vsock": the host
+//! side writes `CONNECT <port>` and the socket carries the stream once the
+//! guest accepted it.
+
+use std::io;
+use std::path::Path;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+};
+
+/// Connects to `port` of the guest behind the hybrid vsock socket at
+/// `socket_path`. Cloud Hypervisor answers `OK <port>` when the guest
+/// accepted the connection.
+pub async fn connect(socket_path: &Path, port: u32) -> io::Result<UnixStream> {
+    let mut socket = UnixStream::connect(socket_path).await?;
+    socket
+        .write_all(format!("CONNECT {port}\n").as_bytes())
+        .await?;
+
+    // Read the reply byte by byte so that no forwarded payload is consumed.
+    let mut reply = Vec::new();
+    let mut byte = [0u8; 1];
+    loop {
+        socket.read_exact(&mut byte).await?;
+        if byte[0] == b'\n' {
+            break;
+        }
+        reply.push(byte[0]);
+        if reply.len() > 64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "vsock handshake reply too long",
+            ));
+        }
+    }
+
+    let reply = String::from_utf8_lossy(&reply);
+    if reply.starts_with("OK ") {
+        Ok(socket)
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("unexpected vsock handshake reply '{reply}'"),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+    use tokio::net::UnixListener;
+
+    /// Plays Cloud Hypervisor: accepts the connection to port 1025 and
+    /// refuses any other port.
+    async fn fake_hybrid_vsock(listener: UnixListener) {
+        loop {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut socket = BufReader::new(socket);
+            let mut line = String::new();
+            socket.read_line(&mut line).await.unwrap();
+            if line == "CONNECT 1025\n" {
+                socket.write_all(b"OK 1073741824\npayload").await.unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn connect_completes_the_handshake_and_keeps_the_payload() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("vm.vsock");
+        tokio::spawn(fake_hybrid_vsock(UnixListener::bind(&path).unwrap()));
+
+        let mut socket = connect(&path, 1025).await.unwrap();
+        let mut payload = String::new();
+        socket.read_to_string(&mut payload).await.unwrap();
+        assert_eq!(payload, "payload");
+
+        assert!(connect(&path, 22).await.is_err());
+    }
+}
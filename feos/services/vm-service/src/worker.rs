@@ -2,23 +2,50 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    dispatcher_handlers::get_image_service_client, error::VmServiceError, vmm::Hypervisor,
-    VmEventWrapper,
+    cloud_init,
+    console::{ConsoleAttachment, ConsoleEvent, ConsoleManager},
+    device_manager, disk_image,
+    dispatcher_handlers::{get_image_service_client, CreateVmLimits, CreateVmSaga},
+    drain,
+    error::VmServiceError,
+    guest_agent::{self, GuestAgent},
+    iscsi, nvmeof, overlay,
+    persistence::{repository::VmRepository, VmRecord},
+    rbd, scratch, snapshot, storage_daemon,
+    vmm::{broadcast_boot_phase_event, Hypervisor},
+    vsock, VmEventWrapper,
 };
 use feos_proto::{
     image_service::{ImageState as OciImageState, WatchImageStatusRequest},
     vm_service::{
-        stream_vm_console_request as console_input, AttachDiskRequest, AttachDiskResponse,
-        AttachNicRequest, AttachNicResponse, ConsoleData, CreateVmRequest, CreateVmResponse,
-        DeleteVmRequest, DeleteVmResponse, DetachDiskRequest, DetachDiskResponse, DetachNicRequest,
-        DetachNicResponse, GetVmRequest, PauseVmRequest, PauseVmResponse, PingVmRequest,
-        PingVmResponse, ResumeVmRequest, ResumeVmResponse, ShutdownVmRequest, ShutdownVmResponse,
-        StartVmRequest, StartVmResponse, StreamVmConsoleRequest, StreamVmConsoleResponse,
-        StreamVmEventsRequest, VmEvent, VmInfo, VmState, VmStateChangedEvent,
+        disk_config, port_forward_request, stream_vm_console_request as console_input,
+        AttachConsoleMessage, AttachDiskRequest, AttachDiskResponse, AttachNicRequest,
+        AttachNicResponse, AttachPciDeviceRequest, AttachPciDeviceResponse, ConsoleData,
+        CreateVmRequest, CreateVmSnapshotResponse, DeleteVmRequest, DeleteVmResponse,
+        DetachDiskRequest, DetachDiskResponse, DetachNicRequest, DetachNicResponse,
+        DetachPciDeviceRequest, DetachPciDeviceResponse, DiskConfig, DiskFormat, DiskSnapshot,
+        ExecInGuestRequest, ExecInGuestResponse, GetGuestInfoRequest, GetGuestInfoResponse,
+        GetVmRequest, GuestFileChunk, NetConfig, PauseVmRequest, PauseVmResponse, PingVmRequest,
+        PingVmResponse, PortForwardRequest, PortForwardResponse, PortForwardStart,
+        PullGuestFileRequest, PushGuestFileRequest, PushGuestFileResponse, PushGuestFileStart,
+        ResizeVmRequest, ResizeVmResponse, ResumeVmRequest, ResumeVmResponse,
+        RevertVmSnapshotResponse, ShutdownVmRequest, ShutdownVmResponse, StartVmRequest,
+        StartVmResponse, StreamVmConsoleRequest, StreamVmConsoleResponse, StreamVmEventsRequest,
+        VhostUserBlkConfig, VmBootPhase, VmConfig, VmEvent, VmInfo, VmSnapshotInfo, VmState,
+        VmStateChangedEvent,
     },
 };
+use feos_utils::host::admission::{AdmissionController, Resources, WorkloadKind};
+use feos_utils::host::startup::{StartupOrder, WorkloadRef, DEFAULT_DEPENDENCY_TIMEOUT};
+use feos_utils::network::macvtap;
 use log::{error, info, warn};
-use std::{path::PathBuf, sync::Arc};
+use secret_service::store::SecretStore;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::UnixStream,
@@ -66,24 +93,32 @@ async fn wait_for_image_ready(image_uuid: &str, image_ref: &str) -> Result<(), V
     )))
 }
 
+/// Resolves once `vm_id` is deleted, as announced on the cancel bus.
+async fn deleted(vm_id: Uuid, cancel_bus: &mut broadcast::Receiver<Uuid>) {
+    loop {
+        match cancel_bus.recv().await {
+            Ok(deleted_vm_id) if deleted_vm_id == vm_id => return,
+            Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+            Err(broadcast::error::RecvError::Closed) => std::future::pending().await,
+        }
+    }
+}
+
+/// Creates a VM after its client was told its ID. Deleting the VM meanwhile
+/// cancels the creation wherever it is, waiting for the image or in a call
+/// to the hypervisor, and undoes what it set up.
 pub async fn handle_create_vm(
-    vm_id: String,
-    req: CreateVmRequest,
+    mut req: CreateVmRequest,
     image_uuid: String,
-    responder: oneshot::Sender<Result<CreateVmResponse, VmServiceError>>,
     hypervisor: Arc<dyn Hypervisor>,
     broadcast_tx: mpsc::Sender<VmEventWrapper>,
+    limits: CreateVmLimits,
+    mut saga: CreateVmSaga,
+    mut cancel_bus: broadcast::Receiver<Uuid>,
 ) {
-    if responder
-        .send(Ok(CreateVmResponse {
-            vm_id: vm_id.clone(),
-        }))
-        .is_err()
-    {
-        error!("VmWorker ({vm_id}): Client disconnected before immediate response could be sent. Aborting creation.");
-        return;
-    }
-
+    let vm_uuid = saga.vm_id();
+    let vm_id = vm_uuid.to_string();
+    let create_requested_at = SystemTime::now();
     info!("VmWorker ({vm_id}): Starting creation process.");
     crate::vmm::broadcast_state_change_event(
         &broadcast_tx,
@@ -96,6 +131,13 @@ pub async fn handle_create_vm(
         None,
     )
     .await;
+    broadcast_boot_phase_event(
+        &broadcast_tx,
+        &vm_id,
+        VmBootPhase::CreateRequested,
+        create_requested_at,
+    )
+    .await;
 
     let image_ref = req
         .config
@@ -106,59 +148,138 @@ pub async fn handle_create_vm(
     info!(
         "VmWorker ({vm_id}): Waiting for image '{image_ref}' (uuid: {image_uuid}) to be ready..."
     );
-    if let Err(e) = wait_for_image_ready(&image_uuid, &image_ref).await {
-        let error_msg = e.to_string();
-        error!("VmWorker ({vm_id}): {error_msg}");
-        crate::vmm::broadcast_state_change_event(
-            &broadcast_tx,
-            &vm_id,
-            "vm-service",
-            VmStateChangedEvent {
-                new_state: VmState::Crashed as i32,
-                reason: error_msg,
-            },
-            None,
-        )
-        .await;
-        return;
+    let ready = tokio::select! {
+        ready = wait_for_image_ready(&image_uuid, &image_ref) => Some(ready),
+        () = deleted(vm_uuid, &mut cancel_bus) => None,
+    };
+    match ready {
+        Some(Ok(())) => {}
+        Some(Err(e)) => {
+            fail_vm_creation(&vm_id, e, &broadcast_tx, hypervisor.as_ref(), saga).await;
+            return;
+        }
+        None => {
+            cancel_vm_creation(&vm_id, hypervisor.as_ref(), saga).await;
+            return;
+        }
     }
     info!("VmWorker ({vm_id}): Image '{image_ref}' (uuid: {image_uuid}) is ready.");
+    broadcast_boot_phase_event(
+        &broadcast_tx,
+        &vm_id,
+        VmBootPhase::ImageReady,
+        SystemTime::now(),
+    )
+    .await;
 
-    let result = hypervisor.create_vm(&vm_id, req, image_uuid).await;
+    let creation = async {
+        // Only a limited number of VMs spawn and configure their hypervisor
+        // at once, so a burst of creations cannot overload the host.
+        let _permit = match limits.create_permits.try_acquire() {
+            Ok(permit) => permit,
+            Err(_) => {
+                info!("VmWorker ({vm_id}): Waiting for a free creation slot...");
+                limits
+                    .create_permits
+                    .acquire()
+                    .await
+                    .map_err(|e| crate::vmm::VmmError::Internal(e.to_string()))?
+            }
+        };
+        if let Some(config) = req.config.as_mut() {
+            for address in device_manager::vm_pci_devices(config) {
+                saga.claim_pci_device(&address).await?;
+            }
+            for disk in &mut config.disks {
+                saga.disk_backend(disk);
+                prepare_disk_backend(saga.repository(), &vm_id, disk).await?;
+            }
+            add_cloud_init_seed(
+                &vm_id,
+                &req.namespace,
+                req.name.as_deref(),
+                config,
+                &limits.secrets,
+            )
+            .await?;
+        }
+        saga.hypervisor();
+        Ok::<_, VmServiceError>(hypervisor.create_vm(&vm_id, req, image_uuid).await?)
+    };
+    let result = tokio::select! {
+        result = creation => Some(result),
+        () = deleted(vm_uuid, &mut cancel_bus) => None,
+    };
 
     match result {
-        Ok(pid) => {
+        Some(Ok(created)) => {
+            saga.complete();
             info!("VmWorker ({vm_id}): Background creation process completed successfully.");
-            crate::vmm::broadcast_state_change_event(
+            broadcast_boot_phase_event(
                 &broadcast_tx,
                 &vm_id,
-                "vm-service",
-                VmStateChangedEvent {
-                    new_state: VmState::Created as i32,
-                    reason: "Hypervisor process started and VM configured".to_string(),
-                },
-                pid,
+                VmBootPhase::VmmSpawned,
+                created.vmm_ready_at,
+            )
+            .await;
+            broadcast_boot_phase_event(
+                &broadcast_tx,
+                &vm_id,
+                VmBootPhase::Created,
+                SystemTime::now(),
             )
             .await;
-        }
-        Err(e) => {
-            let error_msg = e.to_string();
-            error!("VmWorker ({vm_id}): Background creation process failed: {error_msg}");
             crate::vmm::broadcast_state_change_event(
                 &broadcast_tx,
                 &vm_id,
                 "vm-service",
                 VmStateChangedEvent {
-                    new_state: VmState::Crashed as i32,
-                    reason: error_msg,
+                    new_state: VmState::Created as i32,
+                    reason: "Hypervisor process started and VM configured".to_string(),
                 },
-                None,
+                created.process_id,
             )
             .await;
         }
+        Some(Err(e)) => fail_vm_creation(&vm_id, e, &broadcast_tx, hypervisor.as_ref(), saga).await,
+        None => cancel_vm_creation(&vm_id, hypervisor.as_ref(), saga).await,
     }
 }
 
+/// Undoes what the creation of a VM set up when the VM was deleted before
+/// it was created. Its deletion reported it gone already.
+async fn cancel_vm_creation(vm_id: &str, hypervisor: &dyn Hypervisor, saga: CreateVmSaga) {
+    warn!("VmWorker ({vm_id}): The VM was deleted while it was being created. Cancelling the creation.");
+    saga.roll_back(hypervisor).await;
+    cloud_init::remove_seed(vm_id).await;
+}
+
+/// Reports why the creation of a VM failed and undoes what it set up. The
+/// event keeps the reason in the VM's event history after it is gone.
+async fn fail_vm_creation(
+    vm_id: &str,
+    e: VmServiceError,
+    broadcast_tx: &mpsc::Sender<VmEventWrapper>,
+    hypervisor: &dyn Hypervisor,
+    saga: CreateVmSaga,
+) {
+    let error_msg = e.to_string();
+    error!("VmWorker ({vm_id}): Background creation process failed: {error_msg}");
+    crate::vmm::broadcast_state_change_event(
+        broadcast_tx,
+        vm_id,
+        "vm-service",
+        VmStateChangedEvent {
+            new_state: VmState::Crashed as i32,
+            reason: format!("{error_msg}. The VM was removed."),
+        },
+        None,
+    )
+    .await;
+    saga.roll_back(hypervisor).await;
+    cloud_init::remove_seed(vm_id).await;
+}
+
 pub fn start_healthcheck_monitor(
     vm_id: String,
     hypervisor: Arc<dyn Hypervisor>,
@@ -174,6 +295,139 @@ pub fn start_healthcheck_monitor(
     });
 }
 
+/// Brings back a VM whose hypervisor is gone, e.g. after a reboot of the
+/// host, once the network is up and the workloads it is started after are
+/// running. The VM is booted again if it was running or paused. Failures
+/// leave it crashed with the reason.
+pub(crate) async fn recover_vm(
+    record: VmRecord,
+    repository: VmRepository,
+    startup: StartupOrder,
+    secrets: SecretStore,
+    hypervisor: Arc<dyn Hypervisor>,
+    broadcast_tx: mpsc::Sender<VmEventWrapper>,
+    cancel_bus: broadcast::Receiver<Uuid>,
+) {
+    let vm_id = record.vm_id.to_string();
+    let boot = matches!(record.status.state, VmState::Running | VmState::Paused);
+    let timeout = record
+        .config
+        .startup
+        .as_ref()
+        .and_then(|startup| startup.dependency_timeout_seconds)
+        .map_or(DEFAULT_DEPENDENCY_TIMEOUT, |secs| {
+            Duration::from_secs(secs.into())
+        });
+    crate::vmm::broadcast_state_change_event(
+        &broadcast_tx,
+        &vm_id,
+        "vm-service",
+        VmStateChangedEvent {
+            new_state: VmState::Creating as i32,
+            reason: "Auto-start waiting for the network and startup dependencies".to_string(),
+        },
+        None,
+    )
+    .await;
+
+    let result = async {
+        startup.wait_for_network().await;
+        startup
+            .wait_for_dependencies(&WorkloadRef::new(WorkloadKind::Vm, &vm_id), timeout)
+            .await
+            .map_err(|e| VmServiceError::InvalidState(e.to_string()))?;
+        info!("VmWorker ({vm_id}): Startup dependencies are running, recreating VM.");
+
+        // A socket left behind by the previous hypervisor blocks the new one.
+        let api_socket_path = PathBuf::from(crate::VM_API_SOCKET_DIR).join(&vm_id);
+        let _ = tokio::fs::remove_file(&api_socket_path).await;
+        let mut config = record.config;
+        for disk in &mut config.disks {
+            prepare_disk_backend(&repository, &vm_id, disk).await?;
+        }
+        add_cloud_init_seed(
+            &vm_id,
+            &record.namespace,
+            record.name.as_deref(),
+            &mut config,
+            &secrets,
+        )
+        .await?;
+        let req = CreateVmRequest {
+            config: Some(config),
+            vm_id: Some(vm_id.clone()),
+            namespace: record.namespace,
+            name: record.name,
+        };
+        Ok::<_, VmServiceError>(
+            hypervisor
+                .create_vm(&vm_id, req, record.image_uuid.to_string())
+                .await?,
+        )
+    }
+    .await;
+
+    let created = match result {
+        Ok(created) => created,
+        Err(e) => {
+            error!("VmWorker ({vm_id}): Auto-start failed: {e}");
+            crate::vmm::broadcast_state_change_event(
+                &broadcast_tx,
+                &vm_id,
+                "vm-service",
+                VmStateChangedEvent {
+                    new_state: VmState::Crashed as i32,
+                    reason: format!("Auto-start failed: {e}"),
+                },
+                None,
+            )
+            .await;
+            return;
+        }
+    };
+    crate::vmm::broadcast_state_change_event(
+        &broadcast_tx,
+        &vm_id,
+        "vm-service",
+        VmStateChangedEvent {
+            new_state: VmState::Created as i32,
+            reason: "Hypervisor process auto-started and VM configured".to_string(),
+        },
+        created.process_id,
+    )
+    .await;
+    if !boot {
+        return;
+    }
+
+    let (responder, response) = oneshot::channel();
+    handle_start_vm(
+        StartVmRequest {
+            vm_id: vm_id.clone(),
+        },
+        responder,
+        hypervisor,
+        broadcast_tx.clone(),
+        Some(cancel_bus),
+    )
+    .await;
+    if let Ok(Err(e)) = response.await {
+        error!("VmWorker ({vm_id}): Auto-start failed to boot the VM: {e}");
+        // The hypervisor is up, so the VM stays created.
+        crate::vmm::broadcast_state_change_event(
+            &broadcast_tx,
+            &vm_id,
+            "vm-service",
+            VmStateChangedEvent {
+                new_state: VmState::Created as i32,
+                reason: format!("Auto-start failed to boot the VM: {e}"),
+            },
+            None,
+        )
+        .await;
+    }
+}
+
 pub async fn handle_start_vm(
     req: StartVmRequest,
     responder: oneshot::Sender<Result<StartVmResponse, VmServiceError>>,
@@ -182,9 +436,24 @@ pub async fn handle_start_vm(
     cancel_bus: Option<broadcast::Receiver<Uuid>>,
 ) {
     let vm_id = req.vm_id.clone();
+    let boot_requested_at = SystemTime::now();
     let result = hypervisor.start_vm(req).await;
 
     if result.is_ok() {
+        broadcast_boot_phase_event(
+            &broadcast_tx,
+            &vm_id,
+            VmBootPhase::BootRequested,
+            boot_requested_at,
+        )
+        .await;
+        broadcast_boot_phase_event(
+            &broadcast_tx,
+            &vm_id,
+            VmBootPhase::Booted,
+            SystemTime::now(),
+        )
+        .await;
         crate::vmm::broadcast_state_change_event(
             &broadcast_tx,
             &vm_id,
@@ -218,13 +487,52 @@ pub async fn handle_get_vm(
     }
 }
 
+/// Whether the VMs events are about belong to a namespace. The namespace of
+/// a VM never changes, so it is looked up once per VM.
+struct NamespaceFilter {
+    namespace: String,
+    repository: VmRepository,
+    members: HashMap<String, bool>,
+}
+
+impl NamespaceFilter {
+    async fn matches(&mut self, vm_id: &str) -> bool {
+        if let Some(member) = self.members.get(vm_id) {
+            return *member;
+        }
+        let record = match Uuid::parse_str(vm_id) {
+            Ok(id) => self.repository.get_vm(id).await,
+            Err(_) => Ok(None),
+        };
+        match record {
+            Ok(Some(record)) => {
+                let member = record.namespace == self.namespace;
+                self.members.insert(vm_id.to_string(), member);
+                member
+            }
+            // Without a record the VM is gone, so are further events of it.
+            Ok(None) => false,
+            Err(e) => {
+                warn!("VmWorker (Stream): Failed to look up the namespace of VM {vm_id}: {e}");
+                false
+            }
+        }
+    }
+}
+
 pub async fn handle_stream_vm_events(
     req: StreamVmEventsRequest,
     stream_tx: mpsc::Sender<Result<VmEvent, Status>>,
     broadcast_tx: broadcast::Sender<VmEventWrapper>,
+    repository: VmRepository,
 ) {
     let mut broadcast_rx = broadcast_tx.subscribe();
     let vm_id_to_watch = req.vm_id;
+    let mut namespace_filter = req.namespace.map(|namespace| NamespaceFilter {
+        namespace,
+        repository,
+        members: HashMap::new(),
+    });
 
     let watcher_desc = vm_id_to_watch
         .clone()
@@ -233,9 +541,15 @@ pub async fn handle_stream_vm_events(
     loop {
         match broadcast_rx.recv().await {
             Ok(VmEventWrapper { event, .. }) => {
-                if vm_id_to_watch.as_ref().is_none_or(|id| event.vm_id == *id)
-                    && stream_tx.send(Ok(event)).await.is_err()
-                {
+                if !vm_id_to_watch.as_ref().is_none_or(|id| event.vm_id == *id) {
+                    continue;
+                }
+                if let Some(filter) = namespace_filter.as_mut() {
+                    if !filter.matches(&event.vm_id).await {
+                        continue;
+                    }
+                }
+                if stream_tx.send(Ok(event)).await.is_err() {
                     info!("VmWorker (Stream): Client for '{watcher_desc}' disconnected.");
                     break;
                 }
@@ -259,6 +573,8 @@ pub async fn handle_delete_vm(
     req: DeleteVmRequest,
     image_uuid: String,
     process_id: Option<i64>,
+    released_disks: Vec<DiskRelease>,
+    repository: VmRepository,
     responder: oneshot::Sender<Result<DeleteVmResponse, VmServiceError>>,
     hypervisor: Arc<dyn Hypervisor>,
     _broadcast_tx: mpsc::Sender<VmEventWrapper>,
@@ -266,6 +582,16 @@ pub async fn handle_delete_vm(
     let vm_id = req.vm_id.clone();
     let result = hypervisor.delete_vm(req, process_id).await;
 
+    for release in &released_disks {
+        release_disk_backend(&repository, &vm_id, release).await;
+    }
+    cloud_init::remove_seed(&vm_id).await;
+    // The devices go back to the host once the hypervisor let go of them.
+    if let Ok(uuid) = Uuid::parse_str(&vm_id) {
+        device_manager::release_all(&repository, uuid).await;
+        overlay::remove_all(&repository, uuid).await;
+    }
+
     if !image_uuid.is_empty() {
         info!("VmWorker ({vm_id}): Attempting to delete associated image with UUID: {image_uuid}");
         match get_image_service_client().await {
@@ -298,12 +624,13 @@ pub async fn handle_delete_vm(
 }
 
 pub async fn spawn_console_bridge(
-    vm_id: String,
+    attach: AttachConsoleMessage,
     input_stream: Streaming<StreamVmConsoleRequest>,
     output_tx: mpsc::Sender<Result<StreamVmConsoleResponse, Status>>,
     hypervisor: Arc<dyn Hypervisor>,
+    consoles: ConsoleManager,
 ) {
-    let socket_path = match hypervisor.get_console_socket_path(&vm_id).await {
+    let socket_path = match hypervisor.get_console_socket_path(&attach.vm_id).await {
         Ok(path) => path,
         Err(e) => {
             let _ = output_tx.send(Err(e.into())).await;
@@ -311,7 +638,141 @@ pub async fn spawn_console_bridge(
         }
     };
 
-    bridge_console_streams(socket_path, input_stream, output_tx).await;
+    let console = match consoles
+        .attach(
+            &attach.vm_id,
+            &socket_path,
+            attach.read_only,
+            attach.takeover,
+        )
+        .await
+    {
+        Ok(console) => console,
+        Err(e) => {
+            let err_msg = format!("Failed to connect to console socket at {socket_path:?}: {e}");
+            let _ = output_tx.send(Err(Status::unavailable(err_msg))).await;
+            return;
+        }
+    };
+
+    bridge_console_streams(attach.vm_id, console, input_stream, output_tx).await;
+}
+
+pub async fn spawn_port_forward_bridge(
+    start: PortForwardStart,
+    input_stream: Streaming<PortForwardRequest>,
+    output_tx: mpsc::Sender<Result<PortForwardResponse, Status>>,
+    hypervisor: Arc<dyn Hypervisor>,
+) {
+    let socket_path = match hypervisor.get_vsock_socket_path(&start.vm_id).await {
+        Ok(path) => path,
+        Err(e) => {
+            let _ = output_tx.send(Err(e.into())).await;
+            return;
+        }
+    };
+
+    let socket = match vsock::connect(&socket_path, start.port).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            let err_msg = format!(
+                "Failed to connect to vsock port {} of VM {}: {e}",
+                start.port, start.vm_id
+            );
+            let _ = output_tx.send(Err(Status::unavailable(err_msg))).await;
+            return;
+        }
+    };
+
+    info!(
+        "VmmHelper (PortForward {}): Connected to guest vsock port {}.",
+        start.vm_id, start.port
+    );
+    bridge_port_forward_streams(start.vm_id, socket, input_stream, output_tx).await;
+}
+
+pub async fn handle_exec_in_guest(
+    req: ExecInGuestRequest,
+    responder: oneshot::Sender<Result<ExecInGuestResponse, VmServiceError>>,
+    hypervisor: Arc<dyn Hypervisor>,
+) {
+    let result = match GuestAgent::connect(&req.vm_id, hypervisor.as_ref()).await {
+        Ok(mut agent) => agent.exec(req).await,
+        Err(e) => Err(e),
+    };
+    if responder.send(result).is_err() {
+        error!("VmWorker: Failed to send response for ExecInGuest.");
+    }
+}
+
+pub async fn handle_push_guest_file(
+    start: PushGuestFileStart,
+    input_stream: Streaming<PushGuestFileRequest>,
+    responder: oneshot::Sender<Result<PushGuestFileResponse, VmServiceError>>,
+    hypervisor: Arc<dyn Hypervisor>,
+) {
+    let vm_id = start.vm_id.clone();
+    let path = start.path.clone();
+    let result = match GuestAgent::connect(&vm_id, hypervisor.as_ref()).await {
+        Ok(mut agent) => agent.write_file(start, input_stream).await,
+        Err(e) => Err(e),
+    };
+    if let Ok(bytes_written) = &result {
+        info!("VmWorker ({vm_id}): Wrote {bytes_written} bytes to {path} in the guest");
+    }
+    let result = result.map(|bytes_written| PushGuestFileResponse { bytes_written });
+    if responder.send(result).is_err() {
+        error!("VmWorker: Failed to send response for PushGuestFile.");
+    }
+}
+
+pub async fn handle_pull_guest_file(
+    req: PullGuestFileRequest,
+    output_tx: mpsc::Sender<Result<GuestFileChunk, Status>>,
+    hypervisor: Arc<dyn Hypervisor>,
+) {
+    let chunks = match GuestAgent::connect(&req.vm_id, hypervisor.as_ref()).await {
+        Ok(mut agent) => agent.read_file(req.path).await,
+        Err(e) => Err(e),
+    };
+    let mut chunks = match chunks {
+        Ok(chunks) => chunks,
+        Err(e) => {
+            let _ = output_tx.send(Err(e.into())).await;
+            return;
+        }
+    };
+    while let Some(chunk) = chunks.next().await {
+        let chunk: Result<GuestFileChunk, Status> = chunk
+            .map(|chunk| GuestFileChunk { data: chunk.data })
+            .map_err(|status| guest_agent::agent_error(status).into());
+        let failed = chunk.is_err();
+        if output_tx.send(chunk).await.is_err() {
+            info!(
+                "VmWorker ({}): Client went away while pulling a file from the guest.",
+                req.vm_id
+            );
+            return;
+        }
+        if failed {
+            return;
+        }
+    }
+}
+
+pub async fn handle_get_guest_info(
+    req: GetGuestInfoRequest,
+    nics: Vec<NetConfig>,
+    responder: oneshot::Sender<Result<GetGuestInfoResponse, VmServiceError>>,
+    hypervisor: Arc<dyn Hypervisor>,
+) {
+    let result = match GuestAgent::connect(&req.vm_id, hypervisor.as_ref()).await {
+        Ok(mut agent) => agent.info(&nics).await,
+        Err(e) => Err(e),
+    };
+    if responder.send(result).is_err() {
+        error!("VmWorker: Failed to send response for GetGuestInfo.");
+    }
 }
 
 pub async fn handle_ping_vm(
@@ -325,25 +786,35 @@ pub async fn handle_ping_vm(
     }
 }
 
+/// How long a graceful ShutdownVm waits for the guest by default.
+const DEFAULT_SHUTDOWN_GRACE_PERIOD_SECONDS: u32 = 60;
+
 pub async fn handle_shutdown_vm(
     req: ShutdownVmRequest,
+    ephemeral_disks: Vec<DiskConfig>,
     responder: oneshot::Sender<Result<ShutdownVmResponse, VmServiceError>>,
     hypervisor: Arc<dyn Hypervisor>,
     broadcast_tx: mpsc::Sender<VmEventWrapper>,
 ) {
     let vm_id = req.vm_id.clone();
-    let result = hypervisor.shutdown_vm(req).await;
+    let result = if req.graceful {
+        let grace_period = Duration::from_secs(u64::from(
+            req.grace_period_seconds
+                .unwrap_or(DEFAULT_SHUTDOWN_GRACE_PERIOD_SECONDS),
+        ));
+        drain::stop_vm(&vm_id, true, grace_period, hypervisor.as_ref())
+            .await
+            .map(|_| ShutdownVmResponse {})
+    } else {
+        hypervisor.shutdown_vm(req).await
+    };
 
     if result.is_ok() {
-        crate::vmm::broadcast_state_change_event(
-            &broadcast_tx,
+        finish_shutdown(
             &vm_id,
-            "vm-service",
-            VmStateChangedEvent {
-                new_state: VmState::Stopped as i32,
-                reason: "Shutdown command successful".to_string(),
-            },
-            None,
+            &ephemeral_disks,
+            &broadcast_tx,
+            "Shutdown command successful",
         )
         .await;
     }
@@ -353,6 +824,31 @@ pub async fn handle_shutdown_vm(
     }
 }
 
+/// Wipes the ephemeral disks of a VM that stopped and reports it stopped.
+pub(crate) async fn finish_shutdown(
+    vm_id: &str,
+    ephemeral_disks: &[DiskConfig],
+    broadcast_tx: &mpsc::Sender<VmEventWrapper>,
+    reason: &str,
+) {
+    for disk in ephemeral_disks {
+        if let Some(disk_config::Backend::Ephemeral(config)) = &disk.backend {
+            scratch::wipe(vm_id, &disk.device_id, config).await;
+        }
+    }
+    crate::vmm::broadcast_state_change_event(
+        broadcast_tx,
+        vm_id,
+        "vm-service",
+        VmStateChangedEvent {
+            new_state: VmState::Stopped as i32,
+            reason: reason.to_string(),
+        },
+        None,
+    )
+    .await;
+}
+
 pub async fn handle_pause_vm(
     req: PauseVmRequest,
     responder: oneshot::Sender<Result<PauseVmResponse, VmServiceError>>,
@@ -409,23 +905,226 @@ pub async fn handle_resume_vm(
     }
 }
 
+/// A disk whose host side is torn down once the VM no longer uses it.
+#[derive(Debug, Clone)]
+pub struct DiskRelease {
+    pub disk: DiskConfig,
+    /// Whether to log out of the disk's iSCSI target or disconnect from its
+    /// NVMe-oF subsystem. Other disks may still use the connection.
+    pub disconnect: bool,
+}
+
+/// Sets up the host side of a disk and points the disk at what the
+/// hypervisor attaches: the block device of an iSCSI LUN or NVMe-oF
+/// namespace, the file of a scratch disk, or the socket of a storage daemon
+/// for RBD images, qcow2 overlays and disks that pass discard through. Images
+/// given by path have to still be in the format kept with the VM, overlays
+/// in the one they were created in.
+async fn prepare_disk_backend(
+    repository: &VmRepository,
+    vm_id: &str,
+    disk: &mut DiskConfig,
+) -> Result<(), VmServiceError> {
+    let device = match &disk.backend {
+        Some(disk_config::Backend::Rbd(image)) => {
+            let socket_path =
+                rbd::start_export(vm_id, &disk.device_id, image, disk.readonly, disk.discard)
+                    .await?;
+            disk.backend = Some(disk_config::Backend::VhostUserBlk(VhostUserBlkConfig {
+                socket_path: socket_path.to_string_lossy().into_owned(),
+            }));
+            return Ok(());
+        }
+        Some(disk_config::Backend::Iscsi(target)) => {
+            let device = iscsi::login(target).await?;
+            info!(
+                "VmWorker ({vm_id}): Attaching LUN {} of {} as {}",
+                target.lun,
+                target.target_iqn,
+                device.display()
+            );
+            device
+        }
+        Some(disk_config::Backend::Nvmeof(subsystem)) => {
+            let device = nvmeof::connect(subsystem).await?;
+            info!(
+                "VmWorker ({vm_id}): Attaching namespace {} of {} as {}",
+                subsystem.nsid,
+                subsystem.subsystem_nqn,
+                device.display()
+            );
+            device
+        }
+        Some(disk_config::Backend::Ephemeral(config)) => {
+            scratch::create(vm_id, &disk.device_id, config).await?
+        }
+        Some(disk_config::Backend::Path(path)) => {
+            let path = PathBuf::from(path);
+            disk_image::check_format(&path, disk.format()).await?;
+            path
+        }
+        Some(disk_config::Backend::Overlay(config)) => {
+            let vm_uuid = Uuid::parse_str(vm_id).map_err(|_| {
+                VmServiceError::InvalidArgument("Invalid VM ID format.".to_string())
+            })?;
+            let overlay = overlay::create(repository, vm_uuid, &disk.device_id, config).await?;
+            let path = PathBuf::from(&overlay.overlay_path);
+            // Cloud Hypervisor does not open qcow2 backing files.
+            if overlay.format == DiskFormat::Qcow2 {
+                let socket_path = storage_daemon::start_export(
+                    vm_id,
+                    &disk.device_id,
+                    storage_daemon::overlay_blockdev(&path),
+                    None,
+                    disk.readonly,
+                    disk.discard,
+                )
+                .await?;
+                disk.backend = Some(disk_config::Backend::VhostUserBlk(VhostUserBlkConfig {
+                    socket_path: socket_path.to_string_lossy().into_owned(),
+                }));
+                return Ok(());
+            }
+            disk_image::check_format(&path, overlay.format).await?;
+            disk.set_format(overlay.format);
+            path
+        }
+        _ => return Ok(()),
+    };
+
+    disk.backend = if storage_daemon::is_exported(disk) {
+        // Only images given by path and overlays have a format, network
+        // block devices and scratch disks are served raw.
+        let format = if matches!(
+            disk.backend,
+            Some(disk_config::Backend::Path(_) | disk_config::Backend::Overlay(_))
+        ) {
+            disk.format()
+        } else {
+            DiskFormat::Raw
+        };
+        let blockdev = storage_daemon::local_blockdev(&device, format).await?;
+        let socket_path = storage_daemon::start_export(
+            vm_id,
+            &disk.device_id,
+            blockdev,
+            None,
+            disk.readonly,
+            true,
+        )
+        .await?;
+        Some(disk_config::Backend::VhostUserBlk(VhostUserBlkConfig {
+            socket_path: socket_path.to_string_lossy().into_owned(),
+        }))
+    } else {
+        Some(disk_config::Backend::Path(
+            device.to_string_lossy().into_owned(),
+        ))
+    };
+    Ok(())
+}
+
+/// Writes the NoCloud seed of a VM with cloud-init data and adds it to the
+/// disks the hypervisor attaches. The seed is not part of the stored config,
+/// it is written again whenever the VM is created, with the current values
+/// of the secrets of `namespace` it references.
+async fn add_cloud_init_seed(
+    vm_id: &str,
+    namespace: &str,
+    name: Option<&str>,
+    config: &mut VmConfig,
+    secrets: &SecretStore,
+) -> Result<(), VmServiceError> {
+    if let Some(cloud_init) = &config.cloud_init {
+        let mut values = Vec::with_capacity(cloud_init.secrets.len());
+        for secret in &cloud_init.secrets {
+            let value = secrets.get(namespace, &secret.name).await?;
+            values.push((secret.path.clone(), value));
+        }
+        let seed = cloud_init::create_seed(vm_id, name, cloud_init, &values).await?;
+        config.disks.push(seed);
+    }
+    Ok(())
+}
+
+/// Tears down what `prepare_disk_backend` set up for a disk. Overlays are
+/// deleted, as the disk is gone from the VM.
+pub(crate) async fn release_disk_backend(
+    repository: &VmRepository,
+    vm_id: &str,
+    release: &DiskRelease,
+) {
+    let disk = &release.disk;
+    if storage_daemon::is_exported(disk) {
+        storage_daemon::stop_export(vm_id, &disk.device_id).await;
+    }
+    match &disk.backend {
+        Some(disk_config::Backend::Iscsi(target)) if release.disconnect => {
+            iscsi::logout(target).await;
+        }
+        Some(disk_config::Backend::Nvmeof(subsystem)) if release.disconnect => {
+            nvmeof::disconnect(subsystem).await;
+        }
+        Some(disk_config::Backend::Ephemeral(_)) => {
+            scratch::remove(vm_id, &disk.device_id).await;
+        }
+        Some(disk_config::Backend::Overlay(_)) => {
+            // qcow2 overlays are exported whether or not they pass discard.
+            storage_daemon::stop_export(vm_id, &disk.device_id).await;
+            match Uuid::parse_str(vm_id) {
+                Ok(vm_uuid) => overlay::remove(repository, vm_uuid, &disk.device_id).await,
+                Err(_) => warn!(
+                    "VmWorker ({vm_id}): Invalid VM ID, keeping overlay of disk '{}'",
+                    disk.device_id
+                ),
+            }
+        }
+        _ => {}
+    }
+}
+
 pub async fn handle_attach_disk(
-    req: AttachDiskRequest,
+    mut req: AttachDiskRequest,
+    release_on_failure: Option<DiskRelease>,
+    repository: VmRepository,
     responder: oneshot::Sender<Result<AttachDiskResponse, VmServiceError>>,
     hypervisor: Arc<dyn Hypervisor>,
 ) {
-    let result = hypervisor.attach_disk(req).await;
-    if responder.send(result.map_err(Into::into)).is_err() {
+    let vm_id = req.vm_id.clone();
+
+    let result = async {
+        if let Some(disk) = req.disk.as_mut() {
+            prepare_disk_backend(&repository, &vm_id, disk).await?;
+        }
+        Ok::<_, VmServiceError>(hypervisor.attach_disk(req).await?)
+    }
+    .await;
+
+    if result.is_err() {
+        if let Some(release) = &release_on_failure {
+            release_disk_backend(&repository, &vm_id, release).await;
+        }
+    }
+
+    if responder.send(result).is_err() {
         error!("VmWorker: Failed to send response for AttachDisk.");
     }
 }
 
 pub async fn handle_detach_disk(
     req: DetachDiskRequest,
+    release: Option<DiskRelease>,
+    repository: VmRepository,
     responder: oneshot::Sender<Result<DetachDiskResponse, VmServiceError>>,
     hypervisor: Arc<dyn Hypervisor>,
 ) {
+    let vm_id = req.vm_id.clone();
     let result = hypervisor.detach_disk(req).await;
+    if result.is_ok() {
+        if let Some(release) = &release {
+            release_disk_backend(&repository, &vm_id, release).await;
+        }
+    }
     if responder.send(result.map_err(Into::into)).is_err() {
         error!("VmWorker: Failed to send response for DetachDisk.");
     }
@@ -442,64 +1141,402 @@ pub async fn handle_attach_nic(
     }
 }
 
+/// Detaches a NIC and removes its macvtap device, if it has one, once the
+/// guest no longer uses it.
 pub async fn handle_detach_nic(
     req: DetachNicRequest,
+    macvtap: Option<String>,
     responder: oneshot::Sender<Result<DetachNicResponse, VmServiceError>>,
     hypervisor: Arc<dyn Hypervisor>,
 ) {
+    let vm_id = req.vm_id.clone();
     let result = hypervisor.detach_nic(req).await;
+    if let (Ok(_), Some(name)) = (&result, macvtap) {
+        if let Err(e) = macvtap::delete(&name).await {
+            warn!("VmWorker ({vm_id}): Failed to remove macvtap device {name}: {e}");
+        }
+    }
     if responder.send(result.map_err(Into::into)).is_err() {
         error!("VmWorker: Failed to send response for DetachNic.");
     }
 }
 
+pub async fn handle_attach_pci_device(
+    req: AttachPciDeviceRequest,
+    repository: VmRepository,
+    vm_id: Uuid,
+    address: String,
+    responder: oneshot::Sender<Result<AttachPciDeviceResponse, VmServiceError>>,
+    hypervisor: Arc<dyn Hypervisor>,
+) {
+    let result = hypervisor.attach_pci_device(req).await;
+    if let Err(e) = &result {
+        warn!("VmWorker ({vm_id}): Failed to attach PCI device {address}: {e}");
+        remove_pci_device(&repository, vm_id, &address).await;
+        device_manager::release(&repository, vm_id, &address).await;
+    }
+    if responder.send(result.map_err(Into::into)).is_err() {
+        error!("VmWorker: Failed to send response for AttachPciDevice.");
+    }
+}
+
+/// Removes a PCI device the hypervisor did not attach from the config of a
+/// VM again.
+async fn remove_pci_device(repository: &VmRepository, vm_id: Uuid, address: &str) {
+    match repository.get_vm(vm_id).await {
+        Ok(Some(mut record)) => {
+            record
+                .config
+                .pci_devices
+                .retain(|device| device.pci_address != address);
+            if let Err(e) = repository.save_vm(&record).await {
+                warn!("VmWorker ({vm_id}): Failed to remove PCI device {address} from the config: {e}");
+            }
+        }
+        Ok(None) => {}
+        Err(e) => warn!(
+            "VmWorker ({vm_id}): Failed to get the record to remove PCI device {address}: {e}"
+        ),
+    }
+}
+
+/// Detaches a PCI device and gives it back to the host once the guest no
+/// longer uses it.
+pub async fn handle_detach_pci_device(
+    req: DetachPciDeviceRequest,
+    repository: VmRepository,
+    vm_id: Uuid,
+    address: String,
+    responder: oneshot::Sender<Result<DetachPciDeviceResponse, VmServiceError>>,
+    hypervisor: Arc<dyn Hypervisor>,
+) {
+    let result = hypervisor.detach_pci_device(req).await;
+    if result.is_ok() {
+        device_manager::release(&repository, vm_id, &address).await;
+    }
+    if responder.send(result.map_err(Into::into)).is_err() {
+        error!("VmWorker: Failed to send response for DetachPciDevice.");
+    }
+}
+
+/// Removes the macvtap devices of a deleted VM.
+pub async fn remove_macvtaps(vm_id: Uuid, names: Vec<String>) {
+    for name in names {
+        match macvtap::delete(&name).await {
+            Ok(()) => info!("VmWorker ({vm_id}): Removed macvtap device {name}"),
+            Err(e) => warn!("VmWorker ({vm_id}): Failed to remove macvtap device {name}: {e}"),
+        }
+    }
+}
+
+/// Resizes a running VM to `config` and keeps the new sizes in its record.
+/// `previous` is what was committed to the VM before, it is committed again
+/// if the hypervisor fails to resize the VM.
+pub async fn handle_resize_vm(
+    req: ResizeVmRequest,
+    config: VmConfig,
+    previous: Resources,
+    repository: VmRepository,
+    admission: AdmissionController,
+    responder: oneshot::Sender<Result<ResizeVmResponse, VmServiceError>>,
+    hypervisor: Arc<dyn Hypervisor>,
+) {
+    let vm_id = req.vm_id.clone();
+    let result = match hypervisor.resize_vm(req).await {
+        Ok(response) => save_resized_config(&repository, &vm_id, config)
+            .await
+            .map(|()| response),
+        Err(e) => {
+            admission.restore(WorkloadKind::Vm, &vm_id, previous);
+            Err(e.into())
+        }
+    };
+
+    if responder.send(result).is_err() {
+        error!("VmWorker: Failed to send response for ResizeVm.");
+    }
+}
+
+async fn save_resized_config(
+    repository: &VmRepository,
+    vm_id: &str,
+    config: VmConfig,
+) -> Result<(), VmServiceError> {
+    let vm_uuid = Uuid::parse_str(vm_id)
+        .map_err(|_| VmServiceError::InvalidArgument("Invalid VM ID format.".to_string()))?;
+    let Some(mut record) = repository.get_vm(vm_uuid).await? else {
+        return Err(crate::vmm::VmmError::VmNotFound(vm_id.to_string()).into());
+    };
+    record.config.cpus = config.cpus;
+    record.config.memory = config.memory;
+    repository.save_vm(&record).await?;
+    info!("VmWorker ({vm_id}): Resized VM.");
+    Ok(())
+}
+
+async fn take_snapshot(
+    snapshot: &mut VmSnapshotInfo,
+    pause: bool,
+    hypervisor: &dyn Hypervisor,
+) -> Result<(), VmServiceError> {
+    let vm_id = snapshot.vm_id.clone();
+    let dir = snapshot::snapshot_dir(&vm_id, &snapshot.snapshot_id);
+    tokio::fs::create_dir_all(&dir).await.map_err(|e| {
+        VmServiceError::Snapshot(format!("Failed to create {}: {e}", dir.display()))
+    })?;
+
+    if pause {
+        hypervisor
+            .pause_vm(PauseVmRequest {
+                vm_id: vm_id.clone(),
+            })
+            .await?;
+    }
+
+    let result = async {
+        for disk in &mut snapshot.disks {
+            disk.size_bytes =
+                snapshot::copy_disk(Path::new(&disk.source_path), Path::new(&disk.snapshot_path))
+                    .await?;
+        }
+        if snapshot.includes_memory {
+            let memory_dir = snapshot::memory_dir(&dir);
+            tokio::fs::create_dir_all(&memory_dir).await.map_err(|e| {
+                VmServiceError::Snapshot(format!("Failed to create {}: {e}", memory_dir.display()))
+            })?;
+            hypervisor.snapshot_vm(&vm_id, &memory_dir).await?;
+        }
+        Ok::<(), VmServiceError>(())
+    }
+    .await;
+
+    if pause {
+        if let Err(e) = hypervisor
+            .resume_vm(ResumeVmRequest {
+                vm_id: vm_id.clone(),
+            })
+            .await
+        {
+            error!("VmWorker ({vm_id}): Failed to resume VM after taking a snapshot: {e}");
+        }
+    }
+
+    result
+}
+
+pub async fn handle_create_vm_snapshot(
+    mut snapshot: VmSnapshotInfo,
+    pause: bool,
+    responder: oneshot::Sender<Result<CreateVmSnapshotResponse, VmServiceError>>,
+    hypervisor: Arc<dyn Hypervisor>,
+    repository: VmRepository,
+) {
+    let mut result = take_snapshot(&mut snapshot, pause, hypervisor.as_ref()).await;
+    // A client that gave up, e.g. because its deadline passed, never learns
+    // the ID of the snapshot, so it is not kept.
+    if result.is_ok() && responder.is_closed() {
+        warn!(
+            "VmWorker ({}): CreateVmSnapshot was cancelled by the client. Discarding snapshot '{}'.",
+            snapshot.vm_id, snapshot.name
+        );
+        result = Err(VmServiceError::Snapshot(
+            "Cancelled by the client".to_string(),
+        ));
+    }
+    if result.is_ok() {
+        result = repository
+            .save_vm_snapshot(&snapshot)
+            .await
+            .map_err(Into::into);
+    }
+
+    let result = match result {
+        Ok(()) => {
+            info!(
+                "VmWorker ({}): Created snapshot '{}' ({})",
+                snapshot.vm_id, snapshot.name, snapshot.snapshot_id
+            );
+            Ok(CreateVmSnapshotResponse {
+                snapshot_id: snapshot.snapshot_id,
+            })
+        }
+        Err(e) => {
+            snapshot::remove_snapshot_dir(&snapshot::snapshot_dir(
+                &snapshot.vm_id,
+                &snapshot.snapshot_id,
+            ))
+            .await;
+            Err(e)
+        }
+    };
+
+    if responder.send(result).is_err() {
+        error!("VmWorker: Failed to send response for CreateVmSnapshot.");
+    }
+}
+
+/// Reverts the selected disks of a snapshot. With `restore_memory`, the VM is then
+/// replaced by the one saved in the snapshot and ends up paused; `pause` stops a
+/// running guest from writing to its disks while they are reverted.
+pub async fn handle_revert_vm_snapshot(
+    snapshot: VmSnapshotInfo,
+    disks: Vec<DiskSnapshot>,
+    restore_memory: bool,
+    pause: bool,
+    responder: oneshot::Sender<Result<RevertVmSnapshotResponse, VmServiceError>>,
+    hypervisor: Arc<dyn Hypervisor>,
+    broadcast_tx: mpsc::Sender<VmEventWrapper>,
+) {
+    let vm_id = snapshot.vm_id.clone();
+    let result = async {
+        if pause {
+            hypervisor
+                .pause_vm(PauseVmRequest {
+                    vm_id: vm_id.clone(),
+                })
+                .await?;
+        }
+        snapshot::revert_disks(&disks).await?;
+        if restore_memory {
+            let dir = snapshot::snapshot_dir(&vm_id, &snapshot.snapshot_id);
+            if let Err(e) = hypervisor
+                .restore_vm(&vm_id, &snapshot::memory_dir(&dir))
+                .await
+            {
+                crate::vmm::broadcast_state_change_event(
+                    &broadcast_tx,
+                    &vm_id,
+                    "vm-service",
+                    VmStateChangedEvent {
+                        new_state: VmState::Crashed as i32,
+                        reason: format!("Reverting to snapshot '{}' failed: {e}", snapshot.name),
+                    },
+                    None,
+                )
+                .await;
+                return Err(e.into());
+            }
+            crate::vmm::broadcast_state_change_event(
+                &broadcast_tx,
+                &vm_id,
+                "vm-service",
+                VmStateChangedEvent {
+                    new_state: VmState::Paused as i32,
+                    reason: format!("Reverted to snapshot '{}'", snapshot.name),
+                },
+                None,
+            )
+            .await;
+        }
+        Ok::<_, VmServiceError>(RevertVmSnapshotResponse {})
+    }
+    .await;
+
+    if responder.send(result).is_err() {
+        error!("VmWorker: Failed to send response for RevertVmSnapshot.");
+    }
+}
+
 async fn bridge_console_streams(
-    socket_path: PathBuf,
+    vm_id: String,
+    mut console: ConsoleAttachment,
     mut grpc_input: Streaming<StreamVmConsoleRequest>,
     grpc_output: mpsc::Sender<Result<StreamVmConsoleResponse, Status>>,
 ) {
-    let vm_id = socket_path
-        .file_stem()
-        .unwrap()
-        .to_str()
-        .unwrap_or("unknown")
-        .to_string();
-
-    let socket = match UnixStream::connect(&socket_path).await {
-        Ok(s) => s,
-        Err(e) => {
-            let err_msg = format!("Failed to connect to console socket at {socket_path:?}: {e}");
-            let _ = grpc_output.send(Err(Status::unavailable(err_msg))).await;
-            return;
-        }
+    let attached = StreamVmConsoleResponse {
+        output: console.take_backlog(),
+        writable: Some(console.is_writer()),
     };
+    if grpc_output.send(Ok(attached)).await.is_err() {
+        return;
+    }
+
+    loop {
+        tokio::select! {
+            biased;
+            _ = grpc_output.closed() => {
+                info!("VmWorker (Console {vm_id}): gRPC client disconnected.");
+                break;
+            }
+            event = console.next_event() => {
+                let msg = match event {
+                    Some(ConsoleEvent::Output(output)) => StreamVmConsoleResponse { output, writable: None },
+                    Some(ConsoleEvent::Writable(writable)) => StreamVmConsoleResponse { output: Vec::new(), writable: Some(writable) },
+                    None => {
+                        info!("VmWorker (Console {vm_id}): Console closed.");
+                        break;
+                    }
+                };
+                if grpc_output.send(Ok(msg)).await.is_err() {
+                    break;
+                }
+            }
+            input = grpc_input.next() => match input {
+                Some(Ok(msg)) => match msg.payload {
+                    Some(console_input::Payload::Data(ConsoleData { input })) => {
+                        if let Err(e) = console.write(input).await {
+                            warn!("VmWorker (Console {vm_id}): Failed to write to console: {e}");
+                            break;
+                        }
+                    }
+                    Some(console_input::Payload::Attach(_)) => {
+                        let _ = grpc_output
+                            .send(Err(Status::invalid_argument(
+                                "Cannot send Attach message more than once.",
+                            )))
+                            .await;
+                        break;
+                    }
+                    None => {
+                        let _ = grpc_output
+                            .send(Err(Status::invalid_argument("Empty ConsoleInput payload.")))
+                            .await;
+                        break;
+                    }
+                },
+                Some(Err(e)) => {
+                    warn!("VmWorker (Console {vm_id}): Error reading from gRPC client stream: {e}");
+                    break;
+                }
+                None => break,
+            },
+        }
+    }
+}
 
-    let (mut socket_reader, mut socket_writer) = tokio::io::split(socket);
+async fn bridge_port_forward_streams(
+    vm_id: String,
+    socket: UnixStream,
+    mut grpc_input: Streaming<PortForwardRequest>,
+    grpc_output: mpsc::Sender<Result<PortForwardResponse, Status>>,
+) {
+    let (mut socket_reader, mut socket_writer) = socket.into_split();
     let grpc_output_clone = grpc_output.clone();
     let read_task_vm_id = vm_id.clone();
 
     let read_task = tokio::spawn(async move {
-        let mut buf = vec![0; 4096];
+        let mut buf = vec![0; 16384];
         loop {
             tokio::select! {
                 biased;
                 _ = grpc_output_clone.closed() => {
-                    info!("VmmHelper (Console {}): gRPC client disconnected, terminating read task.", &read_task_vm_id);
+                    info!("VmmHelper (PortForward {read_task_vm_id}): gRPC client disconnected, terminating read task.");
                     break;
                 }
                 read_result = socket_reader.read(&mut buf) => {
                     match read_result {
                         Ok(0) => {
-                            info!("VmmHelper (Console {}): Console socket closed (EOF).", &read_task_vm_id);
+                            info!("VmmHelper (PortForward {read_task_vm_id}): Guest closed the connection.");
                             break;
                         }
                         Ok(n) => {
-                            let output_msg = StreamVmConsoleResponse { output: buf[..n].to_vec() };
+                            let output_msg = PortForwardResponse { data: buf[..n].to_vec() };
                             if grpc_output_clone.send(Ok(output_msg)).await.is_err() {
+                                break;
                             }
                         }
                         Err(e) => {
-                            let err_msg = format!("Error reading from console socket: {e}");
+                            let err_msg = format!("Error reading from vsock socket: {e}");
                             let _ = grpc_output_clone.send(Err(Status::internal(err_msg))).await;
                             break;
                         }
@@ -509,45 +1546,43 @@ async fn bridge_console_streams(
         }
     });
 
-    let write_task_vm_id = vm_id.clone();
     let write_task = tokio::spawn(async move {
         while let Some(result) = grpc_input.next().await {
             match result {
                 Ok(msg) => match msg.payload {
-                    Some(console_input::Payload::Data(ConsoleData { input })) => {
-                        if let Err(e) = socket_writer.write_all(&input).await {
-                            warn!("VmmHelper (Console {}): Failed to write to socket: {}. VM may have shut down.", &write_task_vm_id, e);
+                    Some(port_forward_request::Payload::Data(data)) => {
+                        if let Err(e) = socket_writer.write_all(&data).await {
+                            warn!("VmmHelper (PortForward {vm_id}): Failed to write to vsock socket: {e}");
                             break;
                         }
                     }
-                    Some(console_input::Payload::Attach(_)) => {
+                    Some(port_forward_request::Payload::Start(_)) => {
                         let _ = grpc_output
                             .send(Err(Status::invalid_argument(
-                                "Cannot send Attach message more than once.",
+                                "Cannot send Start message more than once.",
                             )))
                             .await;
                         break;
                     }
                     None => {
                         let _ = grpc_output
-                            .send(Err(Status::invalid_argument("Empty ConsoleInput payload.")))
+                            .send(Err(Status::invalid_argument("Empty PortForward payload.")))
                             .await;
                         break;
                     }
                 },
                 Err(e) => {
-                    warn!(
-                        "VmmHelper (Console {}): Error reading from gRPC client stream: {}",
-                        &write_task_vm_id, e
-                    );
+                    warn!("VmmHelper (PortForward {vm_id}): Error reading from gRPC client stream: {e}");
                     break;
                 }
             }
         }
+        // Propagate the client's half-close so the guest sees EOF.
+        let _ = socket_writer.shutdown().await;
     });
 
-    tokio::select! {
-        _ = read_task => {},
-        _ = write_task => {},
-    }
+    // The connection lives as long as the guest keeps its side open; the client
+    // may half-close its stream earlier.
+    let _ = read_task.await;
+    write_task.abort();
 }
@@ -0,0 +1,579 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Audit log of the public API. Every call that changes the state of the
+//! node is recorded with the address of its caller, the identities it
+//! authenticated as, a summary of its request and its outcome, including
+//! calls the authentication rejects. Calls that only read state are not
+//! recorded.
+
+use audit_service::{persistence::AuditEntry, AuditRecorder};
+use feos_proto::auth::signs_body;
+use feos_proto::{container_service, schedule_service, secret_service, vm_service};
+use http_body::{Body as HttpBody, Frame, SizeHint};
+use http_body_util::{BodyExt, Full, Limited};
+use prost::Message;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::task::{ready, Context, Poll};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tonic::body::Body;
+use tonic::codegen::http::{Request, Response};
+use tonic::codegen::Bytes;
+use tonic::transport::server::{TcpConnectInfo, TlsConnectInfo};
+use tonic::{Code, Status};
+use tower::{Layer, Service};
+
+/// The calls that only read state. Listed one by one, new calls are audited
+/// until they are added here.
+const READ_ONLY_METHODS: &[&str] = &[
+    "ExportLogs",
+    "GetCPUInfo",
+    "GetCapabilities",
+    "GetClockInfo",
+    "GetContainer",
+    "GetDiskUsage",
+    "GetFirewall",
+    "GetGuestInfo",
+    "GetInfo",
+    "GetKernelStats",
+    "GetLogLevels",
+    "GetMemory",
+    "GetNetworkInfo",
+    "GetNicTuning",
+    "GetNodeIdentity",
+    "GetVersionInfo",
+    "GetVm",
+    "GetVmBootMetrics",
+    "GetVmStats",
+    "Hostname",
+    "InspectImage",
+    "ListContainerEvents",
+    "ListContainers",
+    "ListDiskReplicas",
+    "ListGpuPartitions",
+    "ListHostPciDevices",
+    "ListImages",
+    "ListMdevs",
+    "ListNvmeControllers",
+    "ListPinnedImages",
+    "ListPools",
+    "ListSchedules",
+    "ListSecrets",
+    "ListSwap",
+    "ListVmEvents",
+    "ListVmSnapshots",
+    "ListVms",
+    "ListVolumes",
+    "PingVm",
+    "PlanEvacuation",
+    "PullGuestFile",
+    "ReadFeOSLogs",
+    "ReplayContainerStateJournal",
+    "ReplayVmStateJournal",
+    "StreamAuditLog",
+    "StreamContainerEvents",
+    "StreamContainerLogs",
+    "StreamFeOSLogs",
+    "StreamKernelLogs",
+    "StreamVmEvents",
+    "StreamVmFlows",
+    "StreamVmMetrics",
+    "WatchDiskUsageAlerts",
+    "WatchImageStatus",
+];
+/// The same limit as for the signed bodies the authentication buffers.
+const MAX_SUMMARIZED_BODY_BYTES: usize = 16 * 1024 * 1024;
+/// The compressed flag and length in front of a gRPC message.
+const GRPC_PREFIX_LEN: usize = 5;
+
+fn method_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+fn is_audited(method: &str) -> bool {
+    !READ_ONLY_METHODS.contains(&method)
+}
+
+/// The API token a call was signed with, set by the authentication once it
+/// verified the signature. The authentication runs inside the audit, which
+/// hands it this in the request extensions.
+#[derive(Clone, Default)]
+pub(crate) struct VerifiedToken(Arc<OnceLock<String>>);
+
+impl VerifiedToken {
+    pub(crate) fn set(&self, token_id: String) {
+        let _ = self.0.set(token_id);
+    }
+}
+
+/// The remote address of the connection the request came in on.
+fn caller<B>(request: &Request<B>) -> String {
+    let extensions = request.extensions();
+    extensions
+        .get::<TcpConnectInfo>()
+        .or_else(|| {
+            extensions
+                .get::<TlsConnectInfo<TcpConnectInfo>>()
+                .map(TlsConnectInfo::get_ref)
+        })
+        .and_then(TcpConnectInfo::remote_addr)
+        .map_or_else(|| "unknown".to_string(), |addr| addr.to_string())
+}
+
+/// The subject of the client certificate of the connection, which the TLS
+/// handshake verified against the CA.
+fn client_cert_subject<B>(request: &Request<B>) -> Option<String> {
+    let certs = request
+        .extensions()
+        .get::<TlsConnectInfo<TcpConnectInfo>>()?
+        .peer_certs()?;
+    let (_, cert) = x509_parser::parse_x509_certificate(certs.first()?.as_ref()).ok()?;
+    Some(cert.subject().to_string())
+}
+
+/// The identities a caller authenticated as, e.g.
+/// `client-cert:CN=node-2 api-token:3f2a...`, or `none`.
+fn credential(client_cert: Option<&str>, token_id: Option<&str>) -> String {
+    let identities = [
+        client_cert.map(|subject| format!("client-cert:{subject}")),
+        token_id.map(|token_id| format!("api-token:{token_id}")),
+    ];
+    let credential = identities.into_iter().flatten().collect::<Vec<_>>();
+    if credential.is_empty() {
+        "none".to_string()
+    } else {
+        credential.join(" ")
+    }
+}
+
+fn pairs(fields: &[(&str, &str)]) -> String {
+    fields
+        .iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The identifying fields of the request of a call. Request bodies such as
+/// secret values, guest commands and cloud-init data are left out.
+fn summarize(method: &str, message: &[u8]) -> Option<String> {
+    use container_service as container;
+    use schedule_service as schedule;
+    use secret_service as secret;
+    use vm_service as vm;
+
+    let summary = match method {
+        "CreateVm" => {
+            let req = vm::CreateVmRequest::decode(message).ok()?;
+            let image_ref = req.config.map(|config| config.image_ref);
+            pairs(&[
+                ("vm_id", req.vm_id.as_deref().unwrap_or_default()),
+                ("namespace", &req.namespace),
+                ("name", req.name.as_deref().unwrap_or_default()),
+                ("image_ref", image_ref.as_deref().unwrap_or_default()),
+            ])
+        }
+        "StartVm" => pairs(&[("vm_id", &vm::StartVmRequest::decode(message).ok()?.vm_id)]),
+        "DeleteVm" => pairs(&[("vm_id", &vm::DeleteVmRequest::decode(message).ok()?.vm_id)]),
+        "ShutdownVm" => pairs(&[("vm_id", &vm::ShutdownVmRequest::decode(message).ok()?.vm_id)]),
+        "PauseVm" => pairs(&[("vm_id", &vm::PauseVmRequest::decode(message).ok()?.vm_id)]),
+        "ResumeVm" => pairs(&[("vm_id", &vm::ResumeVmRequest::decode(message).ok()?.vm_id)]),
+        "ResizeVm" => pairs(&[("vm_id", &vm::ResizeVmRequest::decode(message).ok()?.vm_id)]),
+        "ExecInGuest" => pairs(&[(
+            "vm_id",
+            &vm::ExecInGuestRequest::decode(message).ok()?.vm_id,
+        )]),
+        "AttachDisk" => {
+            let req = vm::AttachDiskRequest::decode(message).ok()?;
+            let device_id = req.disk.map(|disk| disk.device_id);
+            pairs(&[
+                ("vm_id", &req.vm_id),
+                ("device_id", device_id.as_deref().unwrap_or_default()),
+            ])
+        }
+        "DetachDisk" => {
+            let req = vm::DetachDiskRequest::decode(message).ok()?;
+            pairs(&[("vm_id", &req.vm_id), ("device_id", &req.device_id)])
+        }
+        "AttachNic" => {
+            let req = vm::AttachNicRequest::decode(message).ok()?;
+            let device_id = req.nic.map(|nic| nic.device_id);
+            pairs(&[
+                ("vm_id", &req.vm_id),
+                ("device_id", device_id.as_deref().unwrap_or_default()),
+            ])
+        }
+        "DetachNic" => {
+            let req = vm::DetachNicRequest::decode(message).ok()?;
+            pairs(&[("vm_id", &req.vm_id), ("device_id", &req.device_id)])
+        }
+        "AttachPciDevice" => pairs(&[(
+            "vm_id",
+            &vm::AttachPciDeviceRequest::decode(message).ok()?.vm_id,
+        )]),
+        "DetachPciDevice" => {
+            let req = vm::DetachPciDeviceRequest::decode(message).ok()?;
+            pairs(&[("vm_id", &req.vm_id), ("device_id", &req.device_id)])
+        }
+        "CreateVmSnapshot" => {
+            let req = vm::CreateVmSnapshotRequest::decode(message).ok()?;
+            pairs(&[("vm_id", &req.vm_id), ("name", &req.name)])
+        }
+        "RevertVmSnapshot" => {
+            let req = vm::RevertVmSnapshotRequest::decode(message).ok()?;
+            pairs(&[("vm_id", &req.vm_id), ("snapshot_id", &req.snapshot_id)])
+        }
+        "DeleteVmSnapshot" => {
+            let req = vm::DeleteVmSnapshotRequest::decode(message).ok()?;
+            pairs(&[("vm_id", &req.vm_id), ("snapshot_id", &req.snapshot_id)])
+        }
+        "ReplicateVmDisks" => {
+            let req = vm::ReplicateVmDisksRequest::decode(message).ok()?;
+            pairs(&[("vm_id", &req.vm_id), ("peer", &req.peer)])
+        }
+        "DeleteDiskReplicas" => pairs(&[(
+            "vm_id",
+            &vm::DeleteDiskReplicasRequest::decode(message).ok()?.vm_id,
+        )]),
+        "CreateContainer" => {
+            let req = container::CreateContainerRequest::decode(message).ok()?;
+            let image_ref = req.config.map(|config| config.image_ref);
+            pairs(&[
+                (
+                    "container_id",
+                    req.container_id.as_deref().unwrap_or_default(),
+                ),
+                ("namespace", &req.namespace),
+                ("name", req.name.as_deref().unwrap_or_default()),
+                ("image_ref", image_ref.as_deref().unwrap_or_default()),
+            ])
+        }
+        "StartContainer" => pairs(&[(
+            "container_id",
+            &container::StartContainerRequest::decode(message)
+                .ok()?
+                .container_id,
+        )]),
+        "StopContainer" => pairs(&[(
+            "container_id",
+            &container::StopContainerRequest::decode(message)
+                .ok()?
+                .container_id,
+        )]),
+        "DeleteContainer" => pairs(&[(
+            "container_id",
+            &container::DeleteContainerRequest::decode(message)
+                .ok()?
+                .container_id,
+        )]),
+        "PutSecret" => {
+            let req = secret::PutSecretRequest::decode(message).ok()?;
+            pairs(&[("namespace", &req.namespace), ("name", &req.name)])
+        }
+        "DeleteSecret" => {
+            let req = secret::DeleteSecretRequest::decode(message).ok()?;
+            pairs(&[("namespace", &req.namespace), ("name", &req.name)])
+        }
+        "CreateSchedule" => pairs(&[(
+            "name",
+            &schedule::CreateScheduleRequest::decode(message).ok()?.name,
+        )]),
+        "DeleteSchedule" => pairs(&[(
+            "schedule_id",
+            &schedule::DeleteScheduleRequest::decode(message)
+                .ok()?
+                .schedule_id,
+        )]),
+        "SetVmSnapshotPolicy" => pairs(&[(
+            "vm_id",
+            &schedule::SetVmSnapshotPolicyRequest::decode(message)
+                .ok()?
+                .vm_id,
+        )]),
+        "DeleteVmSnapshotPolicy" => pairs(&[(
+            "vm_id",
+            &schedule::DeleteVmSnapshotPolicyRequest::decode(message)
+                .ok()?
+                .vm_id,
+        )]),
+        _ => return None,
+    };
+    Some(summary)
+}
+
+/// The summary of a buffered unary request body, empty for compressed or
+/// malformed ones.
+fn summarize_body(method: &str, body: &[u8]) -> String {
+    match body.split_first() {
+        Some((0, rest)) if rest.len() >= GRPC_PREFIX_LEN - 1 => {
+            summarize(method, &body[GRPC_PREFIX_LEN..]).unwrap_or_default()
+        }
+        _ => String::new(),
+    }
+}
+
+/// A call in progress. Dropped before it finished, e.g. because the client
+/// went away, it is recorded as cancelled.
+struct PendingCall {
+    recorder: AuditRecorder,
+    entry: Option<AuditEntry>,
+    client_cert: Option<String>,
+    token: VerifiedToken,
+    started: Instant,
+}
+
+impl PendingCall {
+    fn finish(&mut self, status: &Status) {
+        if let Some(mut entry) = self.entry.take() {
+            entry.credential = credential(
+                self.client_cert.as_deref(),
+                self.token.0.get().map(String::as_str),
+            );
+            entry.code = status.code() as i32;
+            entry.message = status.message().to_string();
+            entry.duration_ms = self.started.elapsed().as_millis() as u64;
+            self.recorder.record(entry);
+        }
+    }
+}
+
+impl Drop for PendingCall {
+    fn drop(&mut self) {
+        self.finish(&Status::cancelled("The call was cancelled"));
+    }
+}
+
+/// The response body of an audited call, which records the call with the
+/// status in its trailers.
+struct AuditedBody {
+    inner: Body,
+    call: PendingCall,
+}
+
+impl HttpBody for AuditedBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = ready!(Pin::new(&mut self.inner).poll_frame(cx));
+        match &frame {
+            Some(Ok(frame)) => {
+                if let Some(status) = frame.trailers_ref().and_then(Status::from_header_map) {
+                    self.call.finish(&status);
+                }
+            }
+            Some(Err(status)) => self.call.finish(status),
+            None => self
+                .call
+                .finish(&Status::unknown("The response ended without a status")),
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Records the state-changing calls to the services it wraps.
+#[derive(Clone)]
+pub struct AuditLayer {
+    recorder: AuditRecorder,
+}
+
+impl AuditLayer {
+    pub fn new(recorder: AuditRecorder) -> Self {
+        Self { recorder }
+    }
+}
+
+impl<S> Layer<S> for AuditLayer {
+    type Service = Audit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Audit {
+            inner,
+            recorder: self.recorder.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Audit<S> {
+    inner: S,
+    recorder: AuditRecorder,
+}
+
+impl<S> Service<Request<Body>> for Audit<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<Body>) -> Self::Future {
+        let path = request.uri().path().to_string();
+        let method = method_name(&path).to_string();
+        if !is_audited(&method) {
+            return Box::pin(self.inner.call(request));
+        }
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let recorder = self.recorder.clone();
+        Box::pin(async move {
+            let started = Instant::now();
+            let timestamp_ms = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as i64;
+            let token = VerifiedToken::default();
+            request.extensions_mut().insert(token.clone());
+            let caller = caller(&request);
+            let client_cert = client_cert_subject(&request);
+
+            // Streamed requests are not buffered, their calls are recorded
+            // without a summary.
+            let (request, summary) = if signs_body(&path) {
+                let (parts, body) = request.into_parts();
+                match Limited::new(body, MAX_SUMMARIZED_BODY_BYTES)
+                    .collect()
+                    .await
+                {
+                    Ok(body) => {
+                        let body = body.to_bytes();
+                        let summary = summarize_body(&method, &body);
+                        (
+                            Request::from_parts(parts, Body::new(Full::new(body))),
+                            Ok(summary),
+                        )
+                    }
+                    Err(e) => (
+                        Request::from_parts(parts, Body::empty()),
+                        Err(Status::invalid_argument(format!(
+                            "Failed to read the request: {e}"
+                        ))),
+                    ),
+                }
+            } else {
+                (request, Ok(String::new()))
+            };
+
+            let mut call = PendingCall {
+                recorder,
+                entry: Some(AuditEntry {
+                    timestamp_ms,
+                    method: path,
+                    caller,
+                    credential: String::new(),
+                    summary: summary.as_ref().cloned().unwrap_or_default(),
+                    code: Code::Ok as i32,
+                    message: String::new(),
+                    duration_ms: 0,
+                }),
+                client_cert,
+                token,
+                started,
+            };
+            if let Err(status) = summary {
+                call.finish(&status);
+                return Ok(status.into_http());
+            }
+
+            let response = inner.call(request).await?;
+            // A trailers-only response carries its status in the headers.
+            if let Some(status) = Status::from_header_map(response.headers()) {
+                call.finish(&status);
+                return Ok(response);
+            }
+            Ok(response.map(|inner| Body::new(AuditedBody { inner, call })))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grpc_message(message: &impl Message) -> Vec<u8> {
+        let message = message.encode_to_vec();
+        let mut body = vec![0];
+        body.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        body.extend_from_slice(&message);
+        body
+    }
+
+    #[test]
+    fn only_state_changing_calls_are_audited() {
+        assert!(is_audited(method_name(
+            "/feos.vm.vmm.api.v1.VMService/CreateVm"
+        )));
+        assert!(is_audited(method_name(
+            "/feos.container.v1.ContainerService/DeleteContainer"
+        )));
+        assert!(is_audited(method_name("/feos.host.v1.HostService/Reboot")));
+        // Console keystrokes change the state of the guest.
+        assert!(is_audited(method_name(
+            "/feos.vm.vmm.api.v1.VMService/StreamVmConsole"
+        )));
+        assert!(!is_audited(method_name(
+            "/feos.vm.vmm.api.v1.VMService/GetVm"
+        )));
+        assert!(!is_audited(method_name(
+            "/feos.audit.v1.AuditService/StreamAuditLog"
+        )));
+        assert!(!is_audited(method_name(
+            "/feos.host.v1.HostService/Hostname"
+        )));
+    }
+
+    #[test]
+    fn credentials_name_the_verified_identities() {
+        assert_eq!(credential(None, None), "none");
+        assert_eq!(
+            credential(Some("CN=node-2"), Some("3f2a")),
+            "client-cert:CN=node-2 api-token:3f2a"
+        );
+    }
+
+    #[test]
+    fn summaries_leave_out_secret_values() {
+        let body = grpc_message(&secret_service::PutSecretRequest {
+            namespace: "tenant-a".to_string(),
+            name: "db-password".to_string(),
+            value: b"hunter2".to_vec(),
+        });
+        assert_eq!(
+            summarize_body("PutSecret", &body),
+            "namespace=tenant-a name=db-password"
+        );
+
+        let body = grpc_message(&vm_service::DetachDiskRequest {
+            vm_id: "vm-1".to_string(),
+            device_id: "data".to_string(),
+        });
+        assert_eq!(
+            summarize_body("DetachDisk", &body),
+            "vm_id=vm-1 device_id=data"
+        );
+        // Compressed messages are not decoded.
+        let mut body = body;
+        body[0] = 1;
+        assert_eq!(summarize_body("DetachDisk", &body), "");
+    }
+}
@@ -0,0 +1,363 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Token authentication of the public API. With a token configured, every
+//! request must be signed with it as described in `feos_proto::auth`, must
+//! be recent and must not have been seen before, so captured requests cannot
+//! be replayed. Streamed requests are only passed on once their first message
+//! matches their signature.
+
+use crate::audit::VerifiedToken;
+use feos_proto::auth::{
+    body_digest, read_first_message, signs_body, verify, FIRST_MESSAGE_HEADER, NONCE_HEADER,
+    SIGNATURE_HEADER, TIMESTAMP_HEADER,
+};
+use http_body_util::{BodyExt, Full, Limited};
+use log::warn;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tonic::body::Body;
+use tonic::codegen::http::{HeaderMap, Request, Response};
+use tonic::Status;
+use tower::{Layer, Service};
+
+/// How far the timestamp of a request may be off the clock of the host,
+/// which is how long its nonce is remembered.
+const REQUEST_WINDOW_SECONDS: u64 = 300;
+/// Request bodies, and the first message of streamed requests, are buffered
+/// to check their signature. Larger bodies must be sent with a streaming RPC.
+const MAX_SIGNED_BODY_BYTES: usize = 16 * 1024 * 1024;
+const MAX_NONCE_LEN: usize = 64;
+
+#[derive(Debug, PartialEq)]
+enum AuthError {
+    MissingHeader(&'static str),
+    InvalidHeader(&'static str),
+    Expired,
+    BadSignature,
+    BadFirstMessage,
+    Replayed,
+    Body(String),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingHeader(name) => write!(f, "the request is not signed, {name} is missing"),
+            Self::InvalidHeader(name) => write!(f, "{name} is invalid"),
+            Self::Expired => write!(
+                f,
+                "the request timestamp is more than {REQUEST_WINDOW_SECONDS}s off, is the clock of the client right?"
+            ),
+            Self::BadSignature => write!(f, "the request signature does not match the API token"),
+            Self::BadFirstMessage => {
+                write!(f, "the first message of the stream does not match the signature")
+            }
+            Self::Replayed => write!(f, "the request nonce was used before"),
+            Self::Body(e) => write!(f, "failed to read the request: {e}"),
+        }
+    }
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &'static str) -> Result<&'a str, AuthError> {
+    headers
+        .get(name)
+        .ok_or(AuthError::MissingHeader(name))?
+        .to_str()
+        .map_err(|_| AuthError::InvalidHeader(name))
+}
+
+/// The nonces of the requests within the window. Older requests are
+/// rejected by their timestamp, so their nonces are forgotten.
+#[derive(Default)]
+struct ReplayGuard {
+    seen: Mutex<HashMap<String, u64>>,
+}
+
+impl ReplayGuard {
+    fn check_timestamp(timestamp: u64, now: u64) -> Result<(), AuthError> {
+        if timestamp.abs_diff(now) > REQUEST_WINDOW_SECONDS {
+            return Err(AuthError::Expired);
+        }
+        Ok(())
+    }
+
+    fn remember(&self, nonce: &str, timestamp: u64, now: u64) -> Result<(), AuthError> {
+        let mut seen = self.seen.lock().unwrap();
+        seen.retain(|_, seen_at| seen_at.abs_diff(now) <= REQUEST_WINDOW_SECONDS);
+        if seen.contains_key(nonce) {
+            return Err(AuthError::Replayed);
+        }
+        seen.insert(nonce.to_string(), timestamp);
+        Ok(())
+    }
+}
+
+struct Authenticator {
+    token: Vec<u8>,
+    replays: ReplayGuard,
+}
+
+/// What the token is recorded as in the audit log, a prefix of its SHA-256
+/// digest, so the log tells tokens apart without revealing them.
+fn token_id(token: &[u8]) -> String {
+    hex::encode(Sha256::digest(token))[..16].to_string()
+}
+
+impl Authenticator {
+    async fn check(&self, request: Request<Body>, now: u64) -> Result<Request<Body>, AuthError> {
+        let (parts, body) = request.into_parts();
+        let path = parts.uri.path();
+        let timestamp = header(&parts.headers, TIMESTAMP_HEADER)?
+            .parse()
+            .map_err(|_| AuthError::InvalidHeader(TIMESTAMP_HEADER))?;
+        let nonce = header(&parts.headers, NONCE_HEADER)?;
+        if nonce.is_empty() || nonce.len() > MAX_NONCE_LEN {
+            return Err(AuthError::InvalidHeader(NONCE_HEADER));
+        }
+        let signature = header(&parts.headers, SIGNATURE_HEADER)?;
+        ReplayGuard::check_timestamp(timestamp, now)?;
+
+        let body = if signs_body(path) {
+            let body = Limited::new(body, MAX_SIGNED_BODY_BYTES)
+                .collect()
+                .await
+                .map_err(|e| AuthError::Body(e.to_string()))?
+                .to_bytes();
+            let digest = body_digest(&body);
+            if !verify(&self.token, path, timestamp, nonce, &digest, signature) {
+                return Err(AuthError::BadSignature);
+            }
+            Body::new(Full::new(body))
+        } else {
+            // The signature is checked before the stream is read, so only
+            // signed clients can make the host wait for their first message.
+            let digest = header(&parts.headers, FIRST_MESSAGE_HEADER)?;
+            if !verify(&self.token, path, timestamp, nonce, digest, signature) {
+                return Err(AuthError::BadSignature);
+            }
+            let (message, body) = read_first_message(body, MAX_SIGNED_BODY_BYTES)
+                .await
+                .map_err(|e| AuthError::Body(e.to_string()))?;
+            if body_digest(&message) != digest {
+                return Err(AuthError::BadFirstMessage);
+            }
+            body
+        };
+        // Only signed requests are remembered, so others cannot use up nonces.
+        self.replays.remember(nonce, timestamp, now)?;
+        Ok(Request::from_parts(parts, body))
+    }
+}
+
+/// Authenticates the requests to the services it wraps if there is a token.
+#[derive(Clone)]
+pub struct ApiAuthLayer {
+    authenticator: Option<Arc<Authenticator>>,
+}
+
+impl ApiAuthLayer {
+    pub fn new(token: Option<Vec<u8>>) -> Self {
+        Self {
+            authenticator: token.map(|token| {
+                Arc::new(Authenticator {
+                    token,
+                    replays: ReplayGuard::default(),
+                })
+            }),
+        }
+    }
+}
+
+impl<S> Layer<S> for ApiAuthLayer {
+    type Service = ApiAuth<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ApiAuth {
+            inner,
+            authenticator: self.authenticator.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ApiAuth<S> {
+    inner: S,
+    authenticator: Option<Arc<Authenticator>>,
+}
+
+impl<S> Service<Request<Body>> for ApiAuth<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let Some(authenticator) = self.authenticator.clone() else {
+            return Box::pin(self.inner.call(request));
+        };
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let path = request.uri().path().to_string();
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            match authenticator.check(request, now).await {
+                Ok(request) => {
+                    if let Some(verified) = request.extensions().get::<VerifiedToken>() {
+                        verified.set(token_id(&authenticator.token));
+                    }
+                    inner.call(request).await
+                }
+                Err(e) => {
+                    warn!("Main: Rejected a request to {path}: {e}");
+                    Ok(Status::unauthenticated(format!("Not authenticated: {e}")).into_http())
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use feos_proto::auth::sign;
+    use tonic::codegen::Bytes;
+
+    const TOKEN: &[u8] = b"secret";
+    const PATH: &str = "/feos.vm.vmm.api.v1.VMService/DeleteVm";
+    const NOW: u64 = 1_700_000_000;
+
+    fn signed_request(timestamp: u64, nonce: &str, body: &'static [u8]) -> Request<Body> {
+        let signature = sign(TOKEN, PATH, timestamp, nonce, &body_digest(body));
+        Request::builder()
+            .uri(PATH)
+            .header(TIMESTAMP_HEADER, timestamp)
+            .header(NONCE_HEADER, nonce)
+            .header(SIGNATURE_HEADER, signature)
+            .body(Body::new(Full::new(Bytes::from_static(body))))
+            .unwrap()
+    }
+
+    async fn check(authenticator: &Authenticator, request: Request<Body>) -> Result<(), AuthError> {
+        authenticator.check(request, NOW).await.map(|_| ())
+    }
+
+    #[tokio::test]
+    async fn signed_requests_are_accepted_once_within_the_window() {
+        let authenticator = Authenticator {
+            token: TOKEN.to_vec(),
+            replays: ReplayGuard::default(),
+        };
+
+        let request = authenticator
+            .check(signed_request(NOW - 10, "a", b"vm-1"), NOW)
+            .await
+            .unwrap();
+        let body = request.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body.as_ref(), b"vm-1");
+        // A captured request cannot be sent again.
+        assert_eq!(
+            check(&authenticator, signed_request(NOW - 10, "a", b"vm-1")).await,
+            Err(AuthError::Replayed)
+        );
+        assert_eq!(
+            check(&authenticator, signed_request(NOW - 301, "b", b"vm-1")).await,
+            Err(AuthError::Expired)
+        );
+        // Nor can its body be swapped.
+        let mut request = signed_request(NOW, "c", b"vm-1");
+        *request.body_mut() = Body::new(Full::new(Bytes::from_static(b"vm-2")));
+        assert_eq!(
+            check(&authenticator, request).await,
+            Err(AuthError::BadSignature)
+        );
+        // A rejected request does not use up its nonce.
+        check(&authenticator, signed_request(NOW, "c", b"vm-1"))
+            .await
+            .unwrap();
+
+        let mut request = signed_request(NOW, "d", b"vm-1");
+        request.headers_mut().remove(SIGNATURE_HEADER);
+        assert_eq!(
+            check(&authenticator, request).await,
+            Err(AuthError::MissingHeader(SIGNATURE_HEADER))
+        );
+    }
+
+    #[tokio::test]
+    async fn streams_are_rejected_unless_their_first_message_is_signed() {
+        const STREAM_PATH: &str = "/feos.container.v1.ContainerService/ExecContainer";
+        let streamed_request = |nonce: &str, first: &'static [u8]| {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            let digest = body_digest(b"\0\0\0\0\x05start");
+            let signature = sign(TOKEN, STREAM_PATH, timestamp, nonce, &digest);
+            let mut stream = first.to_vec();
+            stream.extend_from_slice(b"\0\0\0\0\x05stdin");
+            Request::builder()
+                .uri(STREAM_PATH)
+                .header(TIMESTAMP_HEADER, timestamp)
+                .header(NONCE_HEADER, nonce)
+                .header(SIGNATURE_HEADER, signature)
+                .header(FIRST_MESSAGE_HEADER, digest)
+                .body(Body::new(Full::new(Bytes::from(stream))))
+                .unwrap()
+        };
+        // Echoes the stream it was passed.
+        let echo = tower::service_fn(|request: Request<Body>| async move {
+            let body = request.into_body().collect().await.unwrap().to_bytes();
+            Ok::<_, std::convert::Infallible>(Response::new(Body::new(Full::new(body))))
+        });
+        let mut service = ApiAuthLayer::new(Some(TOKEN.to_vec())).layer(echo);
+
+        let response = service
+            .call(streamed_request("a", b"\0\0\0\0\x05start"))
+            .await
+            .unwrap();
+        assert!(Status::from_header_map(response.headers()).is_none());
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body.as_ref(), b"\0\0\0\0\x05start\0\0\0\0\x05stdin");
+
+        // Captured headers cannot be sent with another stream.
+        let response = service
+            .call(streamed_request("b", b"\0\0\0\0\x05other"))
+            .await
+            .unwrap();
+        let status = Status::from_header_map(response.headers()).unwrap();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+    }
+
+    #[test]
+    fn nonces_are_forgotten_once_their_requests_expire() {
+        let guard = ReplayGuard::default();
+        guard.remember("a", NOW, NOW).unwrap();
+        guard.remember("b", NOW + 200, NOW).unwrap();
+        assert_eq!(
+            guard.remember("a", NOW, NOW + 300),
+            Err(AuthError::Replayed)
+        );
+        guard.remember("c", NOW + 301, NOW + 301).unwrap();
+        let seen = guard.seen.lock().unwrap();
+        let mut nonces: Vec<&str> = seen.keys().map(String::as_str).collect();
+        nonces.sort();
+        assert_eq!(nonces, ["b", "c"]);
+    }
+}
@@ -0,0 +1,426 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! The certificate of the public API, from an internal CA speaking the
+//! protocol of `ca.proto`. The daemon enrolls with a token of the CA, then
+//! renews the certificate with a new key once two thirds of its lifetime
+//! have passed, authenticating with the certificate it has. The certificate
+//! of new TLS handshakes is swapped on renewal, so connections are neither
+//! dropped nor refused while it rotates. It is valid for client
+//! authentication too, peers are called with it and the client certificates
+//! of peers are verified against the CA.
+
+use anyhow::{anyhow, bail, Context, Result};
+use feos_proto::ca::{
+    certificate_authority_client::CertificateAuthorityClient, SignCertificateRequest,
+};
+use log::{debug, error, info, warn};
+use rcgen::{CertificateParams, DnType, ExtendedKeyUsagePurpose, KeyPair};
+use rustls::crypto::ring;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::danger::ClientCertVerifier;
+use rustls::server::{ClientHello, ResolvesServerCert, ServerConfig, WebPkiClientVerifier};
+use rustls::sign::CertifiedKey;
+use rustls::RootCertStore;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::fs;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::{Certificate, ClientTlsConfig, Endpoint, Identity};
+
+pub(crate) const DEFAULT_CERT_DIR: &str = "/var/lib/feos/certs";
+pub(crate) const KEY_FILE: &str = "key.pem";
+pub(crate) const CERT_FILE: &str = "cert.pem";
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long accepting pauses after it failed, e.g. because the daemon ran
+/// out of file descriptors, which would fail again right away.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// How to get certificates from the CA.
+pub(crate) struct CaConfig {
+    /// e.g. `https://ca.example.com:9443`
+    pub(crate) endpoint: String,
+    /// PEM-encoded certificate the CA is verified with.
+    pub(crate) ca_cert: Vec<u8>,
+    /// Needed unless the daemon has a valid certificate.
+    pub(crate) enrollment_token: Option<String>,
+    /// Where the key and certificate are kept across restarts.
+    pub(crate) dir: PathBuf,
+    /// The DNS names and addresses the certificate is for.
+    pub(crate) names: Vec<String>,
+    /// Whether clients must present a certificate of the CA.
+    pub(crate) require_client_cert: bool,
+}
+
+/// A key and the certificate chain the CA issued for it, PEM-encoded.
+struct Issued {
+    key_pem: String,
+    chain_pem: String,
+}
+
+impl Issued {
+    fn certified_key(&self) -> Result<CertifiedKey> {
+        let chain = CertificateDer::pem_slice_iter(self.chain_pem.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .context("Invalid certificate chain")?;
+        if chain.is_empty() {
+            bail!("The certificate chain is empty");
+        }
+        let key = PrivateKeyDer::from_pem_slice(self.key_pem.as_bytes())
+            .context("Invalid private key")?;
+        let key = ring::sign::any_supported_type(&key).context("Unsupported private key")?;
+        Ok(CertifiedKey::new(chain, key))
+    }
+
+    /// When the certificate becomes and stops being valid.
+    fn validity(&self) -> Result<(SystemTime, SystemTime)> {
+        let (_, pem) = x509_parser::pem::parse_x509_pem(self.chain_pem.as_bytes())
+            .map_err(|e| anyhow!("Invalid certificate: {e}"))?;
+        let cert = pem
+            .parse_x509()
+            .map_err(|e| anyhow!("Invalid certificate: {e}"))?;
+        let time = |timestamp: i64| {
+            UNIX_EPOCH + Duration::from_secs(u64::try_from(timestamp).unwrap_or_default())
+        };
+        let validity = cert.validity();
+        Ok((
+            time(validity.not_before.timestamp()),
+            time(validity.not_after.timestamp()),
+        ))
+    }
+
+    fn is_valid_at(&self, now: SystemTime) -> bool {
+        matches!(self.validity(), Ok((not_before, not_after)) if not_before <= now && now < not_after)
+    }
+}
+
+/// When to renew a certificate, two thirds into its lifetime, which leaves
+/// time to retry while the CA is unavailable.
+fn renewal_time(not_before: SystemTime, not_after: SystemTime) -> SystemTime {
+    let lifetime = not_after.duration_since(not_before).unwrap_or_default();
+    not_before + lifetime * 2 / 3
+}
+
+/// The certificate new TLS handshakes are done with.
+#[derive(Debug)]
+pub(crate) struct CertResolver {
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl CertResolver {
+    fn new(key: CertifiedKey) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(key)),
+        }
+    }
+
+    fn swap(&self, key: CertifiedKey) {
+        *self.current.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(key);
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(
+            self.current
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone(),
+        )
+    }
+}
+
+async fn load(dir: &Path) -> Option<Issued> {
+    let key_pem = fs::read_to_string(dir.join(KEY_FILE)).await.ok()?;
+    let chain_pem = fs::read_to_string(dir.join(CERT_FILE)).await.ok()?;
+    Some(Issued { key_pem, chain_pem })
+}
+
+async fn write_file(path: &Path, content: &str, mode: u32) -> io::Result<()> {
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, content).await?;
+    fs::set_permissions(&temp_path, std::fs::Permissions::from_mode(mode)).await?;
+    fs::rename(temp_path, path).await
+}
+
+async fn store(dir: &Path, issued: &Issued) -> io::Result<()> {
+    fs::create_dir_all(dir).await?;
+    write_file(&dir.join(KEY_FILE), &issued.key_pem, 0o600).await?;
+    write_file(&dir.join(CERT_FILE), &issued.chain_pem, 0o644).await
+}
+
+/// Has the CA sign a certificate for a new key, authenticating with the
+/// current certificate if it is valid and with the enrollment token if not.
+async fn request(config: &CaConfig, current: Option<&Issued>) -> Result<Issued> {
+    let key = KeyPair::generate().context("Failed to generate a key")?;
+    let mut params = CertificateParams::new(config.names.clone())
+        .context("Invalid names for the certificate")?;
+    params
+        .distinguished_name
+        .push(DnType::CommonName, config.names[0].clone());
+    params.extended_key_usages = vec![
+        ExtendedKeyUsagePurpose::ServerAuth,
+        ExtendedKeyUsagePurpose::ClientAuth,
+    ];
+    let csr_pem = params
+        .serialize_request(&key)
+        .and_then(|csr| csr.pem())
+        .context("Failed to create a certificate signing request")?;
+
+    let mut tls = ClientTlsConfig::new().ca_certificate(Certificate::from_pem(&config.ca_cert));
+    let mut enrollment_token = String::new();
+    match current.filter(|issued| issued.is_valid_at(SystemTime::now())) {
+        Some(issued) => {
+            tls = tls.identity(Identity::from_pem(&issued.chain_pem, &issued.key_pem));
+        }
+        None => {
+            enrollment_token = config.enrollment_token.clone().context(
+                "No valid certificate to renew and no enrollment token, set FEOS_CA_ENROLLMENT_TOKEN_FILE",
+            )?;
+        }
+    }
+    let channel = Endpoint::from_shared(config.endpoint.clone())
+        .context("Invalid CA endpoint")?
+        .tls_config(tls)?
+        .connect()
+        .await
+        .with_context(|| format!("Failed to connect to the CA at {}", config.endpoint))?;
+    let response = CertificateAuthorityClient::new(channel)
+        .sign_certificate(SignCertificateRequest {
+            csr_pem,
+            enrollment_token,
+        })
+        .await
+        .map_err(|status| anyhow!("The CA did not sign the certificate: {}", status.message()))?
+        .into_inner();
+
+    let issued = Issued {
+        key_pem: key.serialize_pem(),
+        chain_pem: response.certificate_chain_pem,
+    };
+    issued.certified_key()?;
+    issued.validity()?;
+    Ok(issued)
+}
+
+/// Renews the certificate whenever it is due, for as long as the daemon runs.
+async fn rotate(config: CaConfig, resolver: Arc<CertResolver>, mut issued: Issued) {
+    loop {
+        let renew_at = match issued.validity() {
+            Ok((not_before, not_after)) => renewal_time(not_before, not_after),
+            Err(e) => {
+                error!("Certs: Cannot tell when the certificate expires: {e}");
+                SystemTime::now()
+            }
+        };
+        if let Ok(wait) = renew_at.duration_since(SystemTime::now()) {
+            info!("Certs: Renewing the certificate in {}s.", wait.as_secs());
+            tokio::time::sleep(wait).await;
+        }
+        match renew(&config, &resolver, &issued).await {
+            Ok(renewed) => {
+                info!("Certs: Renewed the certificate.");
+                issued = renewed;
+            }
+            Err(e) => {
+                warn!(
+                    "Certs: Failed to renew the certificate, retrying in {}s: {e:#}",
+                    RETRY_INTERVAL.as_secs()
+                );
+                tokio::time::sleep(RETRY_INTERVAL).await;
+            }
+        }
+    }
+}
+
+async fn renew(config: &CaConfig, resolver: &CertResolver, current: &Issued) -> Result<Issued> {
+    let issued = request(config, Some(current)).await?;
+    resolver.swap(issued.certified_key()?);
+    if let Err(e) = store(&config.dir, &issued).await {
+        warn!(
+            "Certs: Failed to keep the certificate in {}, it is requested again after a restart: {e}",
+            config.dir.display()
+        );
+    }
+    Ok(issued)
+}
+
+/// Gets a certificate, the one kept from before if it is valid, and keeps it
+/// renewed. Waits for the CA until it issues one, the API is not served
+/// without TLS once a CA is configured.
+pub(crate) async fn start(config: CaConfig) -> Arc<CertResolver> {
+    let now = SystemTime::now();
+    let kept = load(&config.dir)
+        .await
+        .filter(|issued| issued.is_valid_at(now) && issued.certified_key().is_ok());
+    let issued = match kept {
+        Some(issued) => {
+            info!("Certs: Using the certificate in {}.", config.dir.display());
+            issued
+        }
+        None => loop {
+            match request(&config, None).await {
+                Ok(issued) => {
+                    info!("Certs: Enrolled with the CA at {}.", config.endpoint);
+                    if let Err(e) = store(&config.dir, &issued).await {
+                        warn!(
+                            "Certs: Failed to keep the certificate in {}: {e}",
+                            config.dir.display()
+                        );
+                    }
+                    break issued;
+                }
+                Err(e) => error!(
+                    "Certs: Failed to enroll, retrying in {}s: {e:#}",
+                    RETRY_INTERVAL.as_secs()
+                ),
+            }
+            tokio::time::sleep(RETRY_INTERVAL).await;
+        },
+    };
+    let key = issued
+        .certified_key()
+        .expect("The certificate was checked when it was issued or loaded");
+    let resolver = Arc::new(CertResolver::new(key));
+    tokio::spawn(rotate(config, resolver.clone(), issued));
+    resolver
+}
+
+/// Verifies client certificates against the CA in `ca_cert`. Unless they
+/// are `required`, clients without one are let through, e.g. the CLI signing
+/// its requests with the API token, or nothing at all if there is no token.
+/// The audit log records the latter with the credential `none`.
+pub(crate) fn client_verifier(
+    ca_cert: &[u8],
+    required: bool,
+) -> Result<Arc<dyn ClientCertVerifier>> {
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_slice_iter(ca_cert) {
+        roots
+            .add(cert.context("Invalid CA certificate")?)
+            .context("Invalid CA certificate")?;
+    }
+    let builder = WebPkiClientVerifier::builder_with_provider(
+        Arc::new(roots),
+        Arc::new(ring::default_provider()),
+    );
+    let builder = if required {
+        builder
+    } else {
+        builder.allow_unauthenticated()
+    };
+    builder
+        .build()
+        .context("Cannot verify client certificates with the CA certificate")
+}
+
+/// The TLS connections to `listener`, done with the current certificate of
+/// `resolver`. Handshakes run on their own, a slow client holds up nobody.
+pub(crate) fn tls_incoming(
+    listener: TcpListener,
+    resolver: Arc<CertResolver>,
+    client_verifier: Arc<dyn ClientCertVerifier>,
+) -> ReceiverStream<io::Result<TlsStream<TcpStream>>> {
+    let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .expect("The ring provider supports the default protocol versions")
+        .with_client_cert_verifier(client_verifier)
+        .with_cert_resolver(resolver);
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+
+    let (tx, rx) = mpsc::channel(32);
+    tokio::spawn(async move {
+        while !tx.is_closed() {
+            let (stream, peer) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    warn!("Certs: Failed to accept a connection: {e}");
+                    tokio::time::sleep(ACCEPT_BACKOFF).await;
+                    continue;
+                }
+            };
+            let acceptor = acceptor.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => {
+                        let _ = tx.send(Ok(stream)).await;
+                    }
+                    Ok(Err(e)) => debug!("Certs: TLS handshake with {peer} failed: {e}"),
+                    Err(_) => debug!("Certs: TLS handshake with {peer} timed out"),
+                }
+            });
+        }
+    });
+    ReceiverStream::new(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn self_signed(not_before: (i32, u8, u8), not_after: (i32, u8, u8)) -> Issued {
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(vec!["feos.example.com".to_string()]).unwrap();
+        params.not_before = rcgen::date_time_ymd(not_before.0, not_before.1, not_before.2);
+        params.not_after = rcgen::date_time_ymd(not_after.0, not_after.1, not_after.2);
+        Issued {
+            key_pem: key.serialize_pem(),
+            chain_pem: params.self_signed(&key).unwrap().pem(),
+        }
+    }
+
+    #[test]
+    fn certificates_are_renewed_two_thirds_into_their_lifetime() {
+        let issued = self_signed((2026, 1, 1), (2026, 4, 1));
+        let (not_before, not_after) = issued.validity().unwrap();
+        assert_eq!(
+            not_after.duration_since(not_before).unwrap(),
+            Duration::from_secs(90 * 24 * 3600)
+        );
+        assert_eq!(
+            renewal_time(not_before, not_after),
+            not_before + Duration::from_secs(60 * 24 * 3600)
+        );
+        assert!(issued.is_valid_at(not_before));
+        assert!(!issued.is_valid_at(not_after));
+        assert!(issued.certified_key().is_ok());
+    }
+
+    #[test]
+    fn clients_without_a_certificate_are_let_through_unless_required() {
+        let ca = self_signed((2026, 1, 1), (2026, 4, 1));
+        let verifier = client_verifier(ca.chain_pem.as_bytes(), false).unwrap();
+        assert!(verifier.offer_client_auth());
+        assert!(!verifier.client_auth_mandatory());
+        let verifier = client_verifier(ca.chain_pem.as_bytes(), true).unwrap();
+        assert!(verifier.client_auth_mandatory());
+        assert!(client_verifier(b"", false).is_err());
+    }
+
+    #[test]
+    fn handshakes_use_the_current_certificate() {
+        let first = self_signed((2026, 1, 1), (2026, 4, 1));
+        let second = self_signed((2026, 3, 1), (2026, 6, 1));
+        let resolver = CertResolver::new(first.certified_key().unwrap());
+        let current = |resolver: &CertResolver| resolver.current.read().unwrap().cert[0].to_vec();
+        assert_eq!(
+            current(&resolver),
+            first.certified_key().unwrap().cert[0].to_vec()
+        );
+        resolver.swap(second.certified_key().unwrap());
+        assert_eq!(
+            current(&resolver),
+            second.certified_key().unwrap().cert[0].to_vec()
+        );
+    }
+}
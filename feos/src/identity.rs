@@ -0,0 +1,109 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! The identity of the node, an Ed25519 key pair generated at the first boot
+//! of FeOS and kept like an SSH host key. Its ID, the fingerprint of the
+//! public key, stays the same across restarts, upgrades and reinstalls that
+//! keep the identity directory, unlike the hostname or addresses of the node.
+
+use anyhow::{Context, Result};
+use feos_proto::host_service::GetNodeIdentityResponse;
+use log::info;
+use rcgen::{KeyPair, PublicKeyData, PKCS_ED25519};
+use sha2::{Digest, Sha256};
+use std::io::ErrorKind;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use tokio::fs;
+
+pub(crate) const DEFAULT_IDENTITY_DIR: &str = "/var/lib/feos/identity";
+const KEY_FILE: &str = "node_key.pem";
+const ALGORITHM: &str = "ed25519";
+
+/// The ID of the node with the key pair, the SHA-256 fingerprint of its
+/// public key.
+fn node_id(key: &KeyPair) -> String {
+    hex::encode(Sha256::digest(key.subject_public_key_info()))
+}
+
+fn identity(key: &KeyPair) -> GetNodeIdentityResponse {
+    GetNodeIdentityResponse {
+        node_id: node_id(key),
+        algorithm: ALGORITHM.to_string(),
+        public_key_pem: key.public_key_pem(),
+    }
+}
+
+/// Loads the key pair of the node from `dir`, generating it if there is none
+/// yet. A key file that cannot be read is an error rather than a reason to
+/// generate a new identity, which controllers would take for another node.
+pub(crate) async fn load_or_generate(dir: &Path) -> Result<GetNodeIdentityResponse> {
+    let path = dir.join(KEY_FILE);
+    match fs::read_to_string(&path).await {
+        Ok(pem) => {
+            let key = KeyPair::from_pem(&pem)
+                .with_context(|| format!("Invalid node key in '{}'", path.display()))?;
+            let identity = identity(&key);
+            info!("Identity: Node ID is {}.", identity.node_id);
+            Ok(identity)
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            let key =
+                KeyPair::generate_for(&PKCS_ED25519).context("Failed to generate a node key")?;
+            fs::create_dir_all(dir)
+                .await
+                .with_context(|| format!("Failed to create '{}'", dir.display()))?;
+            let temp_path = path.with_extension("tmp");
+            fs::write(&temp_path, key.serialize_pem()).await?;
+            fs::set_permissions(&temp_path, std::fs::Permissions::from_mode(0o600)).await?;
+            fs::rename(&temp_path, &path)
+                .await
+                .with_context(|| format!("Failed to keep the node key in '{}'", path.display()))?;
+            let identity = identity(&key);
+            info!(
+                "Identity: Generated the node key in '{}', node ID is {}.",
+                path.display(),
+                identity.node_id
+            );
+            Ok(identity)
+        }
+        Err(e) => {
+            Err(e).with_context(|| format!("Failed to read the node key '{}'", path.display()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn the_generated_identity_is_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let generated = load_or_generate(dir.path()).await.unwrap();
+        assert_eq!(generated.algorithm, "ed25519");
+        assert_eq!(generated.node_id.len(), 64);
+        assert!(generated
+            .public_key_pem
+            .starts_with("-----BEGIN PUBLIC KEY-----"));
+        let mode = std::fs::metadata(dir.path().join(KEY_FILE))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let loaded = load_or_generate(dir.path()).await.unwrap();
+        assert_eq!(loaded, generated);
+    }
+
+    #[tokio::test]
+    async fn an_unreadable_key_is_not_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(KEY_FILE), "not a key").unwrap();
+        assert!(load_or_generate(dir.path()).await.is_err());
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join(KEY_FILE)).unwrap(),
+            "not a key"
+        );
+    }
+}
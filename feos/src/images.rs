@@ -0,0 +1,62 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#[cfg(feature = "container")]
+use container_service::persistence::repository::ContainerRepository;
+use image_service::reclaimer::ImageReferences;
+use std::collections::HashSet;
+#[cfg(feature = "vm")]
+use vm_service::{overlay, persistence::repository::VmRepository};
+
+/// The images the VMs and containers of the host use, and those disks of
+/// VMs are overlays of, as their services recorded them.
+pub(crate) struct FeosImageReferences {
+    #[cfg(feature = "vm")]
+    pub(crate) vms: VmRepository,
+    #[cfg(feature = "container")]
+    pub(crate) containers: ContainerRepository,
+}
+
+#[tonic::async_trait]
+impl ImageReferences for FeosImageReferences {
+    async fn referenced_images(&self) -> Result<HashSet<String>, String> {
+        let mut referenced = HashSet::new();
+        #[cfg(feature = "vm")]
+        referenced.extend(
+            self.vms
+                .list_all_vms()
+                .await
+                .map_err(|e| format!("Failed to list VMs: {e}"))?
+                .into_iter()
+                .map(|record| record.image_uuid.to_string()),
+        );
+        #[cfg(feature = "container")]
+        referenced.extend(
+            self.containers
+                .list_all_containers()
+                .await
+                .map_err(|e| format!("Failed to list containers: {e}"))?
+                .into_iter()
+                .map(|record| record.image_uuid.to_string()),
+        );
+        Ok(referenced)
+    }
+
+    #[cfg(feature = "vm")]
+    async fn base_images(&self) -> Result<HashSet<String>, String> {
+        Ok(self
+            .vms
+            .list_disk_overlays(None)
+            .await
+            .map_err(|e| format!("Failed to list disk overlays: {e}"))?
+            .iter()
+            .filter_map(|overlay| overlay::base_image(&overlay.base_path))
+            .map(|image_uuid| image_uuid.to_string())
+            .collect())
+    }
+
+    #[cfg(not(feature = "vm"))]
+    async fn base_images(&self) -> Result<HashSet<String>, String> {
+        Ok(HashSet::new())
+    }
+}
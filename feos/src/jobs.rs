@@ -0,0 +1,258 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use chrono::TimeDelta;
+use feos_proto::host_service::ExportLogsRequest;
+use feos_proto::schedule_service::{
+    schedule_action::Action, DatabaseBackupAction, LogExportAction,
+};
+#[cfg(feature = "vm")]
+use feos_proto::{
+    schedule_service::{VmDiskReplicationAction, VmSnapshotAction},
+    vm_service::{
+        CreateVmSnapshotRequest, DeleteVmSnapshotRequest, ListVmSnapshotsRequest,
+        ReplicateVmDisksRequest,
+    },
+};
+use host_service::Command as HostCommand;
+use image_service::FileCommand;
+use prost_types::Timestamp;
+use schedule_service::{
+    backup::{self, file_timestamp},
+    JobRun, JobRunner,
+};
+use std::path::Path;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{mpsc, oneshot};
+#[cfg(feature = "vm")]
+use vm_service::Command as VmCommand;
+
+/// The prefix of the archives scheduled log exports write.
+const LOG_EXPORT_PREFIX: &str = "feos-logs-";
+
+/// Runs the actions of schedules by sending commands to the other services.
+pub(crate) struct FeosJobRunner {
+    pub(crate) image_gc_tx: mpsc::Sender<FileCommand>,
+    pub(crate) host_tx: mpsc::Sender<HostCommand>,
+    #[cfg(feature = "vm")]
+    pub(crate) vm_tx: mpsc::Sender<VmCommand>,
+    /// The databases a backup copies.
+    pub(crate) database_urls: Vec<String>,
+}
+
+/// Sends a command and waits for its response, like the API handlers do.
+async fn request<C, T, E: std::fmt::Display>(
+    tx: &mpsc::Sender<C>,
+    command_constructor: impl FnOnce(oneshot::Sender<Result<T, E>>) -> C,
+) -> Result<T, String> {
+    let (resp_tx, resp_rx) = oneshot::channel();
+    tx.send(command_constructor(resp_tx))
+        .await
+        .map_err(|_| "The service is not running".to_string())?;
+    resp_rx
+        .await
+        .map_err(|_| "The service dropped the request".to_string())?
+        .map_err(|e| e.to_string())
+}
+
+impl FeosJobRunner {
+    /// Snapshots taken by a schedule are named after it, so that it only
+    /// prunes its own. Those of snapshot policies are named alike, so that a
+    /// policy also prunes the snapshots of the one it replaced.
+    #[cfg(feature = "vm")]
+    async fn snapshot_vm(&self, run: &JobRun, action: &VmSnapshotAction) -> Result<String, String> {
+        let prefix = if run.vm_snapshot_policy {
+            "snapshot-policy-".to_string()
+        } else {
+            format!("schedule-{}-", &run.schedule_id.to_string()[..8])
+        };
+        let name = format!("{prefix}{}", file_timestamp(run.started_at));
+        let created = request(&self.vm_tx, |resp_tx| {
+            VmCommand::CreateVmSnapshot(
+                CreateVmSnapshotRequest {
+                    vm_id: action.vm_id.clone(),
+                    name: name.clone(),
+                    device_ids: Vec::new(),
+                    include_memory: action.include_memory,
+                },
+                resp_tx,
+            )
+        })
+        .await?;
+
+        let mut message = format!("Created snapshot '{name}' ({})", created.snapshot_id);
+        if action.keep == 0 {
+            return Ok(message);
+        }
+        let listed = request(&self.vm_tx, |resp_tx| {
+            VmCommand::ListVmSnapshots(
+                ListVmSnapshotsRequest {
+                    vm_id: action.vm_id.clone(),
+                },
+                resp_tx,
+            )
+        })
+        .await?;
+        let mut snapshots: Vec<_> = listed
+            .snapshots
+            .into_iter()
+            .filter(|snapshot| snapshot.name.starts_with(&prefix))
+            .collect();
+        snapshots.sort_by(|a, b| (a.created_at, &a.name).cmp(&(b.created_at, &b.name)));
+        let excess = snapshots.len().saturating_sub(action.keep as usize);
+        for snapshot in &snapshots[..excess] {
+            request(&self.vm_tx, |resp_tx| {
+                VmCommand::DeleteVmSnapshot(
+                    DeleteVmSnapshotRequest {
+                        vm_id: action.vm_id.clone(),
+                        snapshot_id: snapshot.snapshot_id.clone(),
+                    },
+                    resp_tx,
+                )
+            })
+            .await?;
+        }
+        if excess > 0 {
+            message.push_str(&format!(", deleted {excess} older snapshots"));
+        }
+        Ok(message)
+    }
+
+    #[cfg(feature = "vm")]
+    async fn replicate_vm_disks(&self, action: &VmDiskReplicationAction) -> Result<String, String> {
+        let replicated = request(&self.vm_tx, |resp_tx| {
+            VmCommand::ReplicateVmDisks(
+                ReplicateVmDisksRequest {
+                    vm_id: action.vm_id.clone(),
+                    peer: action.peer.clone(),
+                    device_ids: Vec::new(),
+                },
+                resp_tx,
+            )
+        })
+        .await?;
+        let bytes_sent: u64 = replicated.disks.iter().map(|disk| disk.bytes_sent).sum();
+        Ok(format!(
+            "Replicated {} disks to {}, sent {bytes_sent} bytes",
+            replicated.disks.len(),
+            action.peer
+        ))
+    }
+
+    async fn collect_image_garbage(&self) -> Result<String, String> {
+        let (responder, done) = oneshot::channel();
+        self.image_gc_tx
+            .send(FileCommand::CollectGarbage { responder })
+            .await
+            .map_err(|_| "The image service is not running".to_string())?;
+        done.await
+            .map_err(|_| "The image service dropped the request".to_string())?;
+        Ok("Collected unused image blobs and layers".to_string())
+    }
+
+    async fn backup_databases(
+        &self,
+        run: &JobRun,
+        action: &DatabaseBackupAction,
+    ) -> Result<String, String> {
+        let backup_dir = backup::backup_databases(
+            &self.database_urls,
+            Path::new(&action.directory),
+            run.started_at,
+            action.keep,
+        )
+        .await
+        .map_err(|e| format!("Failed to back up the databases: {e}"))?;
+        Ok(format!(
+            "Backed up the databases to {}",
+            backup_dir.display()
+        ))
+    }
+
+    async fn export_logs(&self, run: &JobRun, action: &LogExportAction) -> Result<String, String> {
+        let directory = Path::new(&action.directory);
+        let name = format!(
+            "{LOG_EXPORT_PREFIX}{}.tar.gz",
+            file_timestamp(run.started_at)
+        );
+        let path = directory.join(&name);
+        let partial_path = directory.join(format!(".{name}.partial"));
+        let start_time = (action.window_seconds > 0).then(|| {
+            let start = run.started_at - TimeDelta::seconds(action.window_seconds as i64);
+            Timestamp {
+                seconds: start.timestamp(),
+                nanos: 0,
+            }
+        });
+
+        let (chunk_tx, mut chunk_rx) = mpsc::channel(16);
+        self.host_tx
+            .send(HostCommand::ExportLogs(
+                ExportLogsRequest {
+                    start_time,
+                    end_time: None,
+                    compression: 0,
+                },
+                chunk_tx,
+            ))
+            .await
+            .map_err(|_| "The host service is not running".to_string())?;
+
+        let result = async {
+            tokio::fs::create_dir_all(directory)
+                .await
+                .map_err(|e| e.to_string())?;
+            let mut file = tokio::fs::File::create(&partial_path)
+                .await
+                .map_err(|e| e.to_string())?;
+            while let Some(chunk) = chunk_rx.recv().await {
+                let chunk = chunk.map_err(|status| status.message().to_string())?;
+                file.write_all(&chunk.data)
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+            file.sync_all().await.map_err(|e| e.to_string())?;
+            tokio::fs::rename(&partial_path, &path)
+                .await
+                .map_err(|e| e.to_string())
+        }
+        .await;
+        if let Err(e) = result {
+            let _ = tokio::fs::remove_file(&partial_path).await;
+            return Err(format!("Failed to export the logs: {e}"));
+        }
+
+        backup::prune(directory, LOG_EXPORT_PREFIX, action.keep)
+            .await
+            .map_err(|e| format!("Failed to remove old log exports: {e}"))?;
+        Ok(format!("Exported the logs to {}", path.display()))
+    }
+}
+
+#[tonic::async_trait]
+impl JobRunner for FeosJobRunner {
+    fn check(&self, action: &Action) -> Result<(), String> {
+        if cfg!(not(feature = "vm"))
+            && matches!(action, Action::VmSnapshot(_) | Action::VmDiskReplication(_))
+        {
+            return Err("This FeOS is built without VM support".to_string());
+        }
+        Ok(())
+    }
+
+    async fn run(&self, run: &JobRun) -> Result<String, String> {
+        match &run.action {
+            #[cfg(feature = "vm")]
+            Action::VmSnapshot(action) => self.snapshot_vm(run, action).await,
+            #[cfg(feature = "vm")]
+            Action::VmDiskReplication(action) => self.replicate_vm_disks(action).await,
+            #[cfg(not(feature = "vm"))]
+            Action::VmSnapshot(_) | Action::VmDiskReplication(_) => {
+                Err("This FeOS is built without VM support".to_string())
+            }
+            Action::ImageGc(_) => self.collect_image_garbage().await,
+            Action::DatabaseBackup(action) => self.backup_databases(run, action).await,
+            Action::LogExport(action) => self.export_logs(run, action).await,
+        }
+    }
+}
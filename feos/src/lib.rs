@@ -1,20 +1,59 @@
 // SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
 // SPDX-License-Identifier: Apache-2.0
 
+mod audit;
+mod auth;
+mod certs;
+mod identity;
+mod images;
+mod jobs;
 mod setup;
 
+#[cfg(not(any(feature = "vm", feature = "container")))]
+compile_error!("FeOS needs at least one of the `vm` and `container` features");
+
 use anyhow::Result;
+use audit::AuditLayer;
+use auth::ApiAuthLayer;
+#[cfg(feature = "container")]
+use container_service::persistence::repository::ContainerRepository;
+use feos_utils::host::maintenance::Maintenance;
+use feos_utils::host::startup::StartupOrder;
+use futures::future::Either;
 use host_service::RestartSignal;
 use image_service::IMAGE_SERVICE_SOCKET;
+use images::FeosImageReferences;
+use jobs::FeosJobRunner;
 use log::{error, info, warn};
 use nix::unistd::Uid;
 use setup::*;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+#[cfg(feature = "container")]
 use task_service::TASK_SERVICE_SOCKET;
-use tokio::{fs, net::UnixListener, sync::mpsc};
+use tokio::{
+    fs,
+    net::{TcpListener, UnixListener},
+    sync::mpsc,
+};
 use tokio_stream::wrappers::UnixListenerStream;
 use tonic::transport::Server;
+#[cfg(feature = "vm")]
+use vm_service::persistence::repository::VmRepository;
+
+/// How the daemon was started.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ServerOptions {
+    /// The daemon executed a new binary of itself, the host is set up.
+    pub restarted_after_upgrade: bool,
+    /// Wait for the primary daemon of the host to go away instead of failing
+    /// if there is one.
+    pub standby: bool,
+    /// The primary lock held by the daemon before the upgrade.
+    pub primary_lock_fd: Option<i32>,
+}
 
-pub async fn run_server(restarted_after_upgrade: bool) -> Result<()> {
+pub async fn run_server(options: ServerOptions) -> Result<()> {
     println!(
         "
     ███████╗███████╗ ██████╗ ███████╗
@@ -38,9 +77,12 @@ pub async fn run_server(restarted_after_upgrade: bool) -> Result<()> {
         warn!("Not running as root! (uid: {})", Uid::current());
     }
 
+    // Held until the daemon exits, a standby takes over then.
+    let primary_lock = acquire_primary_lock(options.standby, options.primary_lock_fd).await?;
+
     let mut ntp_servers = Vec::new();
 
-    if !restarted_after_upgrade {
+    if !options.restarted_after_upgrade {
         if std::process::id() == 1 {
             ntp_servers = perform_first_boot_initialization().await?;
         }
@@ -48,24 +90,132 @@ pub async fn run_server(restarted_after_upgrade: bool) -> Result<()> {
         info!("Main: Skipping one-time initialization on restart after upgrade.");
     }
 
+    attach_log_journal(&log_handle).await;
+
+    dotenvy::dotenv().ok();
+    // The databases of all services, whose disk usage the storage service
+    // reports and which scheduled database backups copy.
+    let mut database_urls = Vec::new();
+
+    #[cfg(feature = "vm")]
     let vm_db_url = setup_database().await?;
+    #[cfg(feature = "vm")]
+    database_urls.push(vm_db_url.clone());
+    #[cfg(feature = "container")]
+    let container_db_url = container_db_url();
+    #[cfg(feature = "container")]
+    database_urls.push(container_db_url.clone());
+    let storage_db_url = storage_db_url();
+    database_urls.push(storage_db_url.clone());
+    let schedule_db_url = schedule_db_url();
+    database_urls.push(schedule_db_url.clone());
+    let secret_db_url = secret_db_url();
+    database_urls.push(secret_db_url.clone());
+    let audit_db_url = audit_db_url();
+    database_urls.push(audit_db_url.clone());
+
+    // Before the VM service, which may start VMs using them by itself.
+    #[cfg(feature = "vm")]
+    host_service::worker::recreate_mdevs();
 
     let (restart_tx, mut restart_rx) = mpsc::channel::<RestartSignal>(1);
 
-    let vm_service = initialize_vm_service(&vm_db_url).await?;
-    let container_service = initialize_container_service().await?;
+    let admission = initialize_admission_controller();
+    let maintenance = Maintenance::default();
+    let startup = StartupOrder::default();
+    tokio::spawn(wait_for_network(startup.clone()));
+    // Before the services, whose events and metrics carry the node ID.
+    let identity = load_node_identity().await?;
+    // Before the VM service, which signs its requests to peers with it.
+    let api_token = load_api_token()?;
+    let (audit_service, audit_recorder) =
+        initialize_audit_service(&audit_db_url, &identity.node_id).await?;
+    // Before the workload services, which hand secrets to their workloads.
+    let (secret_service, secrets) = initialize_secret_service(&secret_db_url).await?;
+    #[cfg(feature = "vm")]
+    let (vm_service, vm_tx) = initialize_vm_service(
+        &vm_db_url,
+        admission.clone(),
+        maintenance.clone(),
+        startup.clone(),
+        secrets.clone(),
+        &identity.node_id,
+        api_token.clone(),
+    )
+    .await?;
+    #[cfg(feature = "container")]
+    let container_service = initialize_container_service(
+        &container_db_url,
+        admission.clone(),
+        maintenance.clone(),
+        startup.clone(),
+        secrets,
+        &identity.node_id,
+    )
+    .await?;
+    // The image service keeps the images these refer to.
+    let image_references = FeosImageReferences {
+        #[cfg(feature = "vm")]
+        vms: VmRepository::connect(&vm_db_url).await?,
+        #[cfg(feature = "container")]
+        containers: ContainerRepository::connect(&container_db_url).await?,
+    };
+    let (image_service, image_filestore_tx) =
+        initialize_image_service(Arc::new(image_references)).await?;
+    let storage_service =
+        initialize_storage_service(&storage_db_url, &database_urls, image_filestore_tx.clone())
+            .await?;
 
-    let host_service = initialize_host_service(restart_tx.clone(), log_handle, ntp_servers);
+    let firewall = setup_host_firewall().await;
+    let (host_service, host_tx) = initialize_host_service(
+        restart_tx.clone(),
+        log_handle,
+        ntp_servers,
+        maintenance,
+        firewall,
+        identity,
+    );
 
-    let image_service = initialize_image_service().await?;
-    let task_service = initialize_task_service().await?;
+    let job_runner = FeosJobRunner {
+        image_gc_tx: image_filestore_tx,
+        host_tx,
+        #[cfg(feature = "vm")]
+        vm_tx,
+        database_urls,
+    };
+    let schedule_service =
+        initialize_schedule_service(&schedule_db_url, Arc::new(job_runner)).await?;
 
-    let tcp_addr = "[::]:1337".parse().unwrap();
+    let tcp_addr = SocketAddr::from((Ipv6Addr::UNSPECIFIED, API_PORT));
     let tcp_server = Server::builder()
-        .add_service(vm_service)
-        .add_service(container_service)
+        // Outside of the authentication, so rejected calls are recorded too.
+        .layer(AuditLayer::new(audit_recorder))
+        .layer(ApiAuthLayer::new(api_token))
+        .add_service(storage_service)
         .add_service(host_service)
-        .serve(tcp_addr);
+        .add_service(schedule_service)
+        .add_service(secret_service)
+        .add_service(audit_service);
+    #[cfg(feature = "vm")]
+    let tcp_server = tcp_server.add_service(vm_service);
+    #[cfg(feature = "container")]
+    let tcp_server = tcp_server.add_service(container_service);
+    let tcp_server = match load_ca_config()? {
+        // Enrolling waits for the CA, the internal services are served
+        // meanwhile.
+        Some(ca_config) => {
+            let client_verifier =
+                certs::client_verifier(&ca_config.ca_cert, ca_config.require_client_cert)?;
+            let listener = TcpListener::bind(tcp_addr).await?;
+            Either::Left(async move {
+                let resolver = certs::start(ca_config).await;
+                tcp_server
+                    .serve_with_incoming(certs::tls_incoming(listener, resolver, client_verifier))
+                    .await
+            })
+        }
+        None => Either::Right(tcp_server.serve(tcp_addr)),
+    };
 
     fs::remove_file(IMAGE_SERVICE_SOCKET).await.ok();
     let image_uds = UnixListener::bind(IMAGE_SERVICE_SOCKET)?;
@@ -74,15 +224,23 @@ pub async fn run_server(restarted_after_upgrade: bool) -> Result<()> {
         .add_service(image_service)
         .serve_with_incoming(image_uds_stream);
 
-    fs::remove_file(TASK_SERVICE_SOCKET).await.ok();
-    let task_uds = UnixListener::bind(TASK_SERVICE_SOCKET)?;
-    let task_uds_stream = UnixListenerStream::new(task_uds);
-    let task_unix_socket_server = Server::builder()
-        .add_service(task_service)
-        .serve_with_incoming(task_uds_stream);
+    // The task service runs the processes of containers only.
+    #[cfg(feature = "container")]
+    let task_unix_socket_server = {
+        let task_service = initialize_task_service().await?;
+        fs::remove_file(TASK_SERVICE_SOCKET).await.ok();
+        let task_uds = UnixListener::bind(TASK_SERVICE_SOCKET)?;
+        let task_uds_stream = UnixListenerStream::new(task_uds);
+        Server::builder()
+            .add_service(task_service)
+            .serve_with_incoming(task_uds_stream)
+    };
+    #[cfg(not(feature = "container"))]
+    let task_unix_socket_server = std::future::pending::<Result<(), tonic::transport::Error>>();
 
     info!("Main: Public gRPC Server listening on {tcp_addr}");
     info!("Main: Internal ImageService listening on Unix socket {IMAGE_SERVICE_SOCKET}");
+    #[cfg(feature = "container")]
     info!("Main: Internal TaskService listening on Unix socket {TASK_SERVICE_SOCKET}");
 
     tokio::select! {
@@ -102,7 +260,7 @@ pub async fn run_server(restarted_after_upgrade: bool) -> Result<()> {
             }
         },
         Some(RestartSignal(new_binary_path)) = restart_rx.recv() => {
-            if let Err(e) = handle_upgrade(&new_binary_path) {
+            if let Err(e) = handle_upgrade(&new_binary_path, &primary_lock) {
                 error!("Upgrade failed: {e}");
             }
         }
@@ -4,7 +4,7 @@
 use anyhow::Result;
 use clap::Parser;
 use feos_utils::filesystem::{get_root_fstype, move_root};
-use main_server::run_server;
+use main_server::{run_server, ServerOptions};
 use nix::sys::prctl;
 use nix::unistd::execv;
 use std::env;
@@ -15,6 +15,13 @@ use std::ffi::CString;
 struct ServerArgs {
     #[arg(long, hide = true)]
     restarted_after_upgrade: bool,
+    /// Run as a cold standby that starts up and takes over the API and the
+    /// workloads when the primary FeOS daemon of the host exits or crashes.
+    #[arg(long)]
+    standby: bool,
+    /// The primary lock inherited across an upgrade.
+    #[arg(long, hide = true)]
+    primary_lock_fd: Option<i32>,
 }
 
 #[tokio::main]
@@ -22,6 +29,11 @@ async fn main() -> Result<()> {
     let args = ServerArgs::parse();
 
     if std::process::id() == 1 {
+        if args.standby {
+            return Err(anyhow::anyhow!(
+                "[feos] A standby cannot run as PID 1, the host goes down with it"
+            ));
+        }
         let root_fstype = get_root_fstype().unwrap_or_else(|e| {
             eprintln!("[feos] Failed to get root fstype: {e}");
             String::new()
@@ -46,5 +58,10 @@ async fn main() -> Result<()> {
         })?;
     }
 
-    run_server(args.restarted_after_upgrade).await
+    run_server(ServerOptions {
+        restarted_after_upgrade: args.restarted_after_upgrade,
+        standby: args.standby,
+        primary_lock_fd: args.primary_lock_fd,
+    })
+    .await
 }
@@ -1,50 +1,177 @@
 // SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::certs::{CaConfig, CERT_FILE, DEFAULT_CERT_DIR, KEY_FILE};
+use crate::identity::{self, DEFAULT_IDENTITY_DIR};
 use anyhow::Result;
+use audit_service::{
+    api::AuditApiHandler, dispatcher::Dispatcher as AuditDispatcher, AuditRecorder,
+    Command as AuditCommand, DEFAULT_AUDIT_DB_URL,
+};
+#[cfg(feature = "container")]
 use container_service::{
-    api::ContainerApiHandler, dispatcher::Dispatcher as ContainerDispatcher,
-    Command as ContainerCommand, DEFAULT_CONTAINER_DB_URL,
+    api::ContainerApiHandler,
+    dispatcher::Dispatcher as ContainerDispatcher,
+    runtime::snapshotter::{self, DEFAULT_SNAPSHOTTER},
+    Command as ContainerCommand, CONTAINER_DIR, DEFAULT_CONTAINER_DB_URL,
 };
+#[cfg(feature = "vm")]
+use feos_proto::vm_service::vm_service_server::{self, VmServiceServer};
 use feos_proto::{
-    container_service::container_service_server::ContainerServiceServer,
-    host_service::host_service_server::HostServiceServer,
+    audit_service::audit_service_server::{self, AuditServiceServer},
+    host_service::{
+        host_service_server::{self, HostServiceServer},
+        GetCapabilitiesResponse, GetNodeIdentityResponse,
+    },
     image_service::image_service_server::ImageServiceServer,
+    schedule_service::schedule_service_server::{self, ScheduleServiceServer},
+    secret_service::secret_service_server::{self, SecretServiceServer},
+    storage_service::{
+        storage_service_server::{self, StorageServiceServer},
+        UsageCategory,
+    },
+};
+#[cfg(feature = "container")]
+use feos_proto::{
+    container_service::container_service_server::{self, ContainerServiceServer},
     task_service::task_service_server::TaskServiceServer,
-    vm_service::vm_service_server::VmServiceServer,
+};
+use feos_utils::feos_logger::{
+    JournalConfig, LogHandle, DEFAULT_JOURNAL_DIR, DEFAULT_JOURNAL_MAX_BYTES,
 };
 use feos_utils::filesystem::mount_virtual_filesystems;
+use feos_utils::host::admission::{
+    host_capacity, AdmissionController, AdmissionPolicy, DEFAULT_CPU_OVERCOMMIT_RATIO,
+    DEFAULT_MEMORY_OVERCOMMIT_RATIO, DEFAULT_RESERVED_MEMORY_BYTES, DEFAULT_RESERVED_MILLI_CPUS,
+};
 use feos_utils::host::info::is_running_on_vm;
+use feos_utils::host::maintenance::Maintenance;
 use feos_utils::host::memory::configure_hugepages;
-use feos_utils::network::{configure_network_devices, configure_sriov};
+use feos_utils::host::primary::{primary_pid, PrimaryLock, PRIMARY_LOCK_PATH};
+use feos_utils::host::startup::StartupOrder;
+use feos_utils::network::firewall::{self, Profile as FirewallProfile};
+use feos_utils::network::utils::{has_default_route, has_ipv4_default_route, INTERFACE_NAME};
+use feos_utils::network::{configure_network_devices, configure_sriov, nat64};
 use host_service::{
     api::HostApiHandler, dispatcher::HostServiceDispatcher, worker::TimeSyncWorker,
     Command as HostCommand, RestartSignal,
 };
 use image_service::{
-    api::ImageApiHandler, dispatcher::ImageServiceDispatcher, filestore::FileStore,
-    worker::Orchestrator, IMAGE_DIR,
+    api::ImageApiHandler,
+    dispatcher::ImageServiceDispatcher,
+    filestore::FileStore,
+    mirror::{self, RegistryMirror},
+    reclaimer::{self, ImageReferences, Reclaimer},
+    worker::Orchestrator,
+    FileCommand, IMAGE_DIR, IMAGE_LAYER_DIR, IMAGE_MIRROR_DIR,
 };
 use log::{error, info, warn};
 use nix::libc;
+use schedule_service::{
+    api::ScheduleApiHandler, dispatcher::Dispatcher as ScheduleDispatcher,
+    Command as ScheduleCommand, JobRunner, DEFAULT_SCHEDULE_DB_URL,
+};
+use secret_service::{
+    api::SecretApiHandler, dispatcher::Dispatcher as SecretDispatcher,
+    persistence::repository::SecretRepository, store::SecretStore, Command as SecretCommand,
+    DEFAULT_SECRET_DB_URL,
+};
 use std::env;
 use std::ffi::CString;
-use std::net::Ipv6Addr;
+use std::fmt::Display;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::os::fd::RawFd;
 use std::os::unix::ffi::OsStringExt;
 use std::os::unix::fs::PermissionsExt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use storage_service::{
+    api::StorageApiHandler,
+    dispatcher::Dispatcher as StorageDispatcher,
+    usage::{
+        UsageConfig, UsageSource, UsageThresholds, DEFAULT_CRITICAL_PERCENT,
+        DEFAULT_WARNING_PERCENT,
+    },
+    Command as StorageCommand, DEFAULT_STORAGE_DB_URL,
+};
+#[cfg(feature = "container")]
 use task_service::{api::TaskApiHandler, dispatcher::Dispatcher, Command as TaskCommand};
 use tokio::fs::{self, File};
 use tokio::sync::mpsc;
+use tokio::time::Instant;
+#[cfg(feature = "vm")]
 use vm_service::{
-    api::VmApiHandler, dispatcher::VmServiceDispatcher, Command as VmCommand, DEFAULT_VM_DB_URL,
-    VM_API_SOCKET_DIR, VM_CONSOLE_DIR,
+    api::VmApiHandler, dispatcher::VmServiceDispatcher, replication::ReplicationConfig, stats,
+    Command as VmCommand, DEFAULT_VM_CREATE_CONCURRENCY, DEFAULT_VM_DB_URL, VM_API_SOCKET_DIR,
+    VM_CONSOLE_DIR, VM_SNAPSHOT_DIR, VM_VSOCK_DIR,
 };
 
 pub(crate) const VFS_NUM: u32 = 125;
+/// The port of the public API, on all addresses.
+pub(crate) const API_PORT: u16 = 1337;
 pub(crate) const HUGEPAGES_NUM: u32 = 1024;
+/// How long auto-started workloads wait for a default route before they are
+/// started without one.
+const NETWORK_READY_TIMEOUT: Duration = Duration::from_secs(120);
+const NETWORK_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+fn env_or_default<T>(name: &str, default: T, valid: impl Fn(&T) -> bool) -> T
+where
+    T: FromStr + Display,
+{
+    match env::var(name) {
+        Ok(value) => value.parse().ok().filter(valid).unwrap_or_else(|| {
+            warn!("Main: Invalid {name} '{value}', using default {default}");
+            default
+        }),
+        Err(_) => {
+            info!("Main: {name} not set, using default {default}");
+            default
+        }
+    }
+}
 
-pub(crate) async fn initialize_vm_service(db_url: &str) -> Result<VmServiceServer<VmApiHandler>> {
+/// Sets up the CPU and memory overcommit policy that VMs and containers are
+/// admitted by.
+pub(crate) fn initialize_admission_controller() -> AdmissionController {
+    let is_ratio = |ratio: &f64| ratio.is_finite() && *ratio > 0.0;
+    let policy = AdmissionPolicy {
+        cpu_overcommit_ratio: env_or_default(
+            "FEOS_CPU_OVERCOMMIT_RATIO",
+            DEFAULT_CPU_OVERCOMMIT_RATIO,
+            is_ratio,
+        ),
+        memory_overcommit_ratio: env_or_default(
+            "FEOS_MEMORY_OVERCOMMIT_RATIO",
+            DEFAULT_MEMORY_OVERCOMMIT_RATIO,
+            is_ratio,
+        ),
+        reserved_milli_cpus: env_or_default(
+            "FEOS_RESERVED_MILLI_CPUS",
+            DEFAULT_RESERVED_MILLI_CPUS,
+            |_| true,
+        ),
+        reserved_memory_bytes: env_or_default(
+            "FEOS_RESERVED_MEMORY_MIB",
+            DEFAULT_RESERVED_MEMORY_BYTES >> 20,
+            |_: &u64| true,
+        ) << 20,
+    };
+    AdmissionController::new(&policy, host_capacity())
+}
+
+#[cfg(feature = "vm")]
+pub(crate) async fn initialize_vm_service(
+    db_url: &str,
+    admission: AdmissionController,
+    maintenance: Maintenance,
+    startup: StartupOrder,
+    secrets: SecretStore,
+    node_id: &str,
+    api_token: Option<Vec<u8>>,
+) -> Result<(VmServiceServer<VmApiHandler>, mpsc::Sender<VmCommand>)> {
     info!("Main: Ensuring VM socket directory '{VM_API_SOCKET_DIR}' exists...");
     fs::create_dir_all(VM_API_SOCKET_DIR).await?;
     info!("Main: Directory check complete. Path '{VM_API_SOCKET_DIR}' is ready.");
@@ -53,26 +180,106 @@ pub(crate) async fn initialize_vm_service(db_url: &str) -> Result<VmServiceServe
     fs::create_dir_all(VM_CONSOLE_DIR).await?;
     info!("Main: Directory check complete. Path '{VM_CONSOLE_DIR}' is ready.");
 
+    info!("Main: Ensuring VM vsock directory '{VM_VSOCK_DIR}' exists...");
+    fs::create_dir_all(VM_VSOCK_DIR).await?;
+    info!("Main: Directory check complete. Path '{VM_VSOCK_DIR}' is ready.");
+
+    let create_concurrency = match env::var("VM_CREATE_CONCURRENCY") {
+        Ok(value) => value.parse().ok().filter(|n| *n > 0).unwrap_or_else(|| {
+            warn!(
+                "Main: Invalid VM_CREATE_CONCURRENCY '{value}', using default {DEFAULT_VM_CREATE_CONCURRENCY}"
+            );
+            DEFAULT_VM_CREATE_CONCURRENCY
+        }),
+        Err(_) => {
+            info!(
+                "Main: VM_CREATE_CONCURRENCY not set, using default {DEFAULT_VM_CREATE_CONCURRENCY}"
+            );
+            DEFAULT_VM_CREATE_CONCURRENCY
+        }
+    };
+
     let (vm_tx, vm_rx) = mpsc::channel::<VmCommand>(32);
-    let vm_dispatcher = VmServiceDispatcher::new(vm_rx, db_url).await?;
+    let vm_dispatcher = VmServiceDispatcher::new(
+        vm_rx,
+        db_url,
+        create_concurrency,
+        admission,
+        maintenance,
+        startup,
+        secrets,
+    )
+    .await?
+    .with_replication(load_replication_config(node_id, api_token)?);
     tokio::spawn(async move {
         vm_dispatcher.run().await;
     });
-    let vm_api_handler = VmApiHandler::new(vm_tx);
+    if let Ok(listen) = env::var("FEOS_METRICS_LISTEN") {
+        match listen.parse::<SocketAddr>() {
+            Ok(addr) => {
+                let metrics_tx = vm_tx.clone();
+                let node_id = node_id.to_string();
+                tokio::spawn(async move {
+                    if let Err(e) = stats::serve(addr, metrics_tx, node_id).await {
+                        error!("Main: VM metrics endpoint on {addr} failed: {e}");
+                    }
+                });
+            }
+            Err(e) => warn!("Main: Invalid FEOS_METRICS_LISTEN '{listen}': {e}"),
+        }
+    }
+    let vm_api_handler = VmApiHandler::new(vm_tx.clone(), node_id.to_string());
     let vm_service = VmServiceServer::new(vm_api_handler);
     info!("Main: VM Service is configured.");
 
-    Ok(vm_service)
+    Ok((vm_service, vm_tx))
 }
 
+#[cfg(feature = "container")]
+pub(crate) fn container_db_url() -> String {
+    env::var("CONTAINER_DATABASE_URL").unwrap_or_else(|_| {
+        info!("Main: CONTAINER_DATABASE_URL not set, using default '{DEFAULT_CONTAINER_DB_URL}'");
+        DEFAULT_CONTAINER_DB_URL.to_string()
+    })
+}
+
+/// How disks are replicated to peers. Requests to peers are signed with the
+/// API token of this node, whose peers share it, and peers serving their API
+/// with TLS are verified with the certificate in FEOS_CA_CERT_FILE. With a
+/// CA in FEOS_CA_ENDPOINT, the certificate it issued is presented to them.
+#[cfg(feature = "vm")]
+fn load_replication_config(node_id: &str, api_token: Option<Vec<u8>>) -> Result<ReplicationConfig> {
+    let ca_cert = match env::var("FEOS_CA_CERT_FILE") {
+        Ok(path) => Some(
+            std::fs::read(&path)
+                .map_err(|e| anyhow::anyhow!("Failed to read CA certificate '{path}': {e}"))?,
+        ),
+        Err(_) => None,
+    };
+    let cert_dir = env::var("FEOS_CA_ENDPOINT")
+        .ok()
+        .filter(|endpoint| !endpoint.is_empty())
+        .map(|_| cert_dir());
+    Ok(ReplicationConfig {
+        node_id: node_id.to_string(),
+        token: api_token,
+        ca_cert,
+        client_cert: cert_dir.as_ref().map(|dir| dir.join(CERT_FILE)),
+        client_key: cert_dir.as_ref().map(|dir| dir.join(KEY_FILE)),
+    })
+}
+
+#[cfg(feature = "container")]
 pub(crate) async fn initialize_container_service(
+    db_url: &str,
+    admission: AdmissionController,
+    maintenance: Maintenance,
+    startup: StartupOrder,
+    secrets: SecretStore,
+    node_id: &str,
 ) -> Result<ContainerServiceServer<ContainerApiHandler>> {
     info!("Main: Initializing Container Service...");
 
-    let db_url = env::var("CONTAINER_DATABASE_URL").unwrap_or_else(|_| {
-        info!("Main: CONTAINER_DATABASE_URL not set, using default '{DEFAULT_CONTAINER_DB_URL}'");
-        DEFAULT_CONTAINER_DB_URL.to_string()
-    });
     if let Some(db_path_str) = db_url.strip_prefix("sqlite:") {
         let db_path = Path::new(db_path_str);
         if let Some(db_dir) = db_path.parent() {
@@ -83,25 +290,457 @@ pub(crate) async fn initialize_container_service(
         }
     }
 
+    let snapshotter_name = env::var("CONTAINER_SNAPSHOTTER").unwrap_or_else(|_| {
+        info!("Main: CONTAINER_SNAPSHOTTER not set, using default '{DEFAULT_SNAPSHOTTER}'");
+        DEFAULT_SNAPSHOTTER.to_string()
+    });
+    let snapshotter = snapshotter::from_name(&snapshotter_name)?;
+
     let (container_tx, container_rx) = mpsc::channel::<ContainerCommand>(32);
-    let container_dispatcher = ContainerDispatcher::new(container_rx, &db_url).await?;
+    let container_dispatcher = ContainerDispatcher::new(
+        container_rx,
+        db_url,
+        snapshotter,
+        admission,
+        maintenance,
+        startup,
+        secrets,
+    )
+    .await?;
     tokio::spawn(async move {
         container_dispatcher.run().await;
     });
-    let container_api_handler = ContainerApiHandler::new(container_tx);
+    let container_api_handler = ContainerApiHandler::new(container_tx, node_id.to_string());
     let container_service = ContainerServiceServer::new(container_api_handler);
     info!("Main: Container Service is configured.");
 
     Ok(container_service)
 }
 
+fn usage_percent_from_env(name: &str, default: u32) -> u32 {
+    match env::var(name) {
+        Ok(value) => value.parse().unwrap_or_else(|_| {
+            warn!("Main: Invalid {name} '{value}', using default {default}");
+            default
+        }),
+        Err(_) => {
+            info!("Main: {name} not set, using default {default}");
+            default
+        }
+    }
+}
+
+/// Where the services keep their data, for disk usage reporting.
+fn usage_sources(database_urls: &[String]) -> Vec<UsageSource> {
+    let mut sources = vec![
+        UsageSource::new(UsageCategory::ImageCache, IMAGE_DIR).excluding(IMAGE_LAYER_DIR),
+        UsageSource::new(UsageCategory::ContainerLayers, IMAGE_LAYER_DIR),
+    ];
+    #[cfg(feature = "container")]
+    sources.push(UsageSource::new(
+        UsageCategory::ContainerLayers,
+        CONTAINER_DIR,
+    ));
+    #[cfg(feature = "vm")]
+    sources.push(UsageSource::new(UsageCategory::VmDisks, VM_SNAPSHOT_DIR));
+    for db_path in database_urls
+        .iter()
+        .filter_map(|url| url.strip_prefix("sqlite:"))
+    {
+        sources.push(UsageSource::new(UsageCategory::Databases, db_path));
+        sources.push(UsageSource::new(
+            UsageCategory::Databases,
+            format!("{db_path}-wal"),
+        ));
+    }
+    sources
+}
+
+pub(crate) fn storage_db_url() -> String {
+    env::var("STORAGE_DATABASE_URL").unwrap_or_else(|_| {
+        info!("Main: STORAGE_DATABASE_URL not set, using default '{DEFAULT_STORAGE_DB_URL}'");
+        DEFAULT_STORAGE_DB_URL.to_string()
+    })
+}
+
+/// `database_urls` are the databases of all services, whose disk usage is
+/// reported.
+pub(crate) async fn initialize_storage_service(
+    db_url: &str,
+    database_urls: &[String],
+    image_gc_tx: mpsc::Sender<FileCommand>,
+) -> Result<StorageServiceServer<StorageApiHandler>> {
+    info!("Main: Initializing Storage Service...");
+
+    if let Some(db_path_str) = db_url.strip_prefix("sqlite:") {
+        let db_path = Path::new(db_path_str);
+        if let Some(db_dir) = db_path.parent() {
+            fs::create_dir_all(db_dir).await?;
+        }
+        if !db_path.exists() {
+            File::create(db_path).await?;
+        }
+    }
+
+    let thresholds = UsageThresholds {
+        warning_percent: usage_percent_from_env(
+            "STORAGE_USAGE_WARNING_PERCENT",
+            DEFAULT_WARNING_PERCENT,
+        ),
+        critical_percent: usage_percent_from_env(
+            "STORAGE_USAGE_CRITICAL_PERCENT",
+            DEFAULT_CRITICAL_PERCENT,
+        ),
+    };
+    thresholds.validate().map_err(anyhow::Error::msg)?;
+    let image_gc = env::var("STORAGE_USAGE_IMAGE_GC").is_ok_and(|value| value == "true");
+    info!(
+        "Main: Garbage collection of images on critical disk usage is {}",
+        if image_gc { "enabled" } else { "disabled" }
+    );
+    let usage_config = UsageConfig {
+        sources: usage_sources(database_urls),
+        thresholds,
+        image_gc: image_gc.then_some(image_gc_tx),
+    };
+
+    let (storage_tx, storage_rx) = mpsc::channel::<StorageCommand>(32);
+    let storage_dispatcher = StorageDispatcher::new(storage_rx, db_url, usage_config).await?;
+    tokio::spawn(async move {
+        storage_dispatcher.run().await;
+    });
+    let storage_api_handler = StorageApiHandler::new(storage_tx);
+    let storage_service = StorageServiceServer::new(storage_api_handler);
+    info!("Main: Storage Service is configured.");
+
+    Ok(storage_service)
+}
+
+pub(crate) fn schedule_db_url() -> String {
+    env::var("SCHEDULE_DATABASE_URL").unwrap_or_else(|_| {
+        info!("Main: SCHEDULE_DATABASE_URL not set, using default '{DEFAULT_SCHEDULE_DB_URL}'");
+        DEFAULT_SCHEDULE_DB_URL.to_string()
+    })
+}
+
+pub(crate) async fn initialize_schedule_service(
+    db_url: &str,
+    runner: Arc<dyn JobRunner>,
+) -> Result<ScheduleServiceServer<ScheduleApiHandler>> {
+    info!("Main: Initializing Schedule Service...");
+
+    if let Some(db_path_str) = db_url.strip_prefix("sqlite:") {
+        let db_path = Path::new(db_path_str);
+        if let Some(db_dir) = db_path.parent() {
+            fs::create_dir_all(db_dir).await?;
+        }
+        if !db_path.exists() {
+            File::create(db_path).await?;
+        }
+    }
+
+    let (schedule_tx, schedule_rx) = mpsc::channel::<ScheduleCommand>(32);
+    let schedule_dispatcher = ScheduleDispatcher::new(schedule_rx, db_url, runner).await?;
+    tokio::spawn(async move {
+        schedule_dispatcher.run().await;
+    });
+    let schedule_api_handler = ScheduleApiHandler::new(schedule_tx);
+    let schedule_service = ScheduleServiceServer::new(schedule_api_handler);
+    info!("Main: Schedule Service is configured.");
+
+    Ok(schedule_service)
+}
+
+pub(crate) fn secret_db_url() -> String {
+    env::var("SECRET_DATABASE_URL").unwrap_or_else(|_| {
+        info!("Main: SECRET_DATABASE_URL not set, using default '{DEFAULT_SECRET_DB_URL}'");
+        DEFAULT_SECRET_DB_URL.to_string()
+    })
+}
+
+pub(crate) fn audit_db_url() -> String {
+    env::var("AUDIT_DATABASE_URL").unwrap_or_else(|_| {
+        info!("Main: AUDIT_DATABASE_URL not set, using default '{DEFAULT_AUDIT_DB_URL}'");
+        DEFAULT_AUDIT_DB_URL.to_string()
+    })
+}
+
+/// Opens the audit log, returning the recorder the API layer hands the
+/// state-changing calls to.
+pub(crate) async fn initialize_audit_service(
+    db_url: &str,
+    node_id: &str,
+) -> Result<(AuditServiceServer<AuditApiHandler>, AuditRecorder)> {
+    info!("Main: Initializing Audit Service...");
+
+    if let Some(db_path_str) = db_url.strip_prefix("sqlite:") {
+        let db_path = Path::new(db_path_str);
+        if let Some(db_dir) = db_path.parent() {
+            fs::create_dir_all(db_dir).await?;
+        }
+        if !db_path.exists() {
+            File::create(db_path).await?;
+        }
+    }
+
+    let (recorder, entries_rx) = AuditRecorder::channel();
+    let (audit_tx, audit_rx) = mpsc::channel::<AuditCommand>(32);
+    let audit_dispatcher =
+        AuditDispatcher::new(audit_rx, entries_rx, db_url, node_id.to_string()).await?;
+    tokio::spawn(async move {
+        audit_dispatcher.run().await;
+    });
+    let audit_api_handler = AuditApiHandler::new(audit_tx);
+    let audit_service = AuditServiceServer::new(audit_api_handler);
+    info!("Main: Audit Service is configured.");
+
+    Ok((audit_service, recorder))
+}
+
+/// Opens the secrets of the host, which are encrypted with the key derived
+/// from the file in FEOS_SECRETS_KEY_FILE or, without one, with a key sealed
+/// to the host TPM. A host without a usable key still starts, but its
+/// workloads cannot use secrets.
+pub(crate) async fn initialize_secret_service(
+    db_url: &str,
+) -> Result<(SecretServiceServer<SecretApiHandler>, SecretStore)> {
+    info!("Main: Initializing Secret Service...");
+
+    if let Some(db_path_str) = db_url.strip_prefix("sqlite:") {
+        let db_path = Path::new(db_path_str);
+        if let Some(db_dir) = db_path.parent() {
+            fs::create_dir_all(db_dir).await?;
+        }
+        if !db_path.exists() {
+            File::create(db_path).await?;
+        }
+    }
+
+    let repository = SecretRepository::connect(db_url).await?;
+    let key_file = env::var("FEOS_SECRETS_KEY_FILE").ok().map(PathBuf::from);
+    let key = match secret_service::key::load(&repository, key_file.as_deref()).await {
+        Ok(key) => {
+            match &key_file {
+                Some(path) => info!(
+                    "Main: Secrets are encrypted with the key in '{}'.",
+                    path.display()
+                ),
+                None => info!("Main: Secrets are encrypted with the key sealed to the TPM."),
+            }
+            Ok(key)
+        }
+        Err(e) => {
+            warn!("Main: Secrets are unavailable, no key could be loaded: {e}");
+            Err(format!("The host has no key for secrets: {e}"))
+        }
+    };
+    let store = SecretStore::new(repository, key);
+
+    let (secret_tx, secret_rx) = mpsc::channel::<SecretCommand>(32);
+    let secret_dispatcher = SecretDispatcher::new(secret_rx, store.clone());
+    tokio::spawn(async move {
+        secret_dispatcher.run().await;
+    });
+    let secret_api_handler = SecretApiHandler::new(secret_tx);
+    let secret_service = SecretServiceServer::new(secret_api_handler);
+    info!("Main: Secret Service is configured.");
+
+    Ok((secret_service, store))
+}
+
+/// The token clients sign their requests to the public API with, read from
+/// the file in FEOS_API_TOKEN_FILE. Without one the API is not authenticated,
+/// so it should then only be reachable from a trusted network.
+pub(crate) fn load_api_token() -> Result<Option<Vec<u8>>> {
+    let Ok(path) = env::var("FEOS_API_TOKEN_FILE") else {
+        warn!("Main: FEOS_API_TOKEN_FILE not set, the public API is not authenticated.");
+        return Ok(None);
+    };
+    let token = std::fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("Failed to read API token file '{path}': {e}"))?;
+    let token = token.trim();
+    if token.is_empty() {
+        anyhow::bail!("API token file '{path}' is empty");
+    }
+    info!("Main: Public API requests must be signed with the token in '{path}'.");
+    Ok(Some(token.as_bytes().to_vec()))
+}
+
+/// The identity of the node, kept in FEOS_IDENTITY_DIR. It should be on a
+/// disk that survives reinstalls for the node to keep its ID.
+pub(crate) async fn load_node_identity() -> Result<GetNodeIdentityResponse> {
+    let dir = env::var("FEOS_IDENTITY_DIR").unwrap_or_else(|_| DEFAULT_IDENTITY_DIR.to_string());
+    identity::load_or_generate(Path::new(&dir)).await
+}
+
+/// The CA the certificate of the public API is requested from, in
+/// FEOS_CA_ENDPOINT. Without one the API is served without TLS. The CA is
+/// verified with the certificate in FEOS_CA_CERT_FILE, and the daemon
+/// enrolls with the token in FEOS_CA_ENROLLMENT_TOKEN_FILE. The certificate
+/// is for the names in FEOS_CERT_NAMES, by default the hostname. Clients must
+/// present a certificate of the CA if FEOS_REQUIRE_CLIENT_CERT is true.
+pub(crate) fn load_ca_config() -> Result<Option<CaConfig>> {
+    let Some(endpoint) = env::var("FEOS_CA_ENDPOINT").ok().filter(|e| !e.is_empty()) else {
+        warn!("Main: FEOS_CA_ENDPOINT not set, the public API is served without TLS.");
+        return Ok(None);
+    };
+    let ca_cert_file = env::var("FEOS_CA_CERT_FILE")
+        .map_err(|_| anyhow::anyhow!("FEOS_CA_CERT_FILE must be set with FEOS_CA_ENDPOINT"))?;
+    let ca_cert = std::fs::read(&ca_cert_file)
+        .map_err(|e| anyhow::anyhow!("Failed to read CA certificate '{ca_cert_file}': {e}"))?;
+    let enrollment_token = match env::var("FEOS_CA_ENROLLMENT_TOKEN_FILE") {
+        Ok(path) => {
+            let token = std::fs::read_to_string(&path).map_err(|e| {
+                anyhow::anyhow!("Failed to read enrollment token file '{path}': {e}")
+            })?;
+            Some(token.trim().to_string()).filter(|token| !token.is_empty())
+        }
+        Err(_) => None,
+    };
+    let names: Vec<String> = match env::var("FEOS_CERT_NAMES") {
+        Ok(names) => names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(String::from)
+            .collect(),
+        Err(_) => vec![nix::unistd::gethostname()?.to_string_lossy().into_owned()],
+    };
+    if names.is_empty() {
+        anyhow::bail!("FEOS_CERT_NAMES has no names for the certificate");
+    }
+    info!(
+        "Main: The public API is served with TLS, with a certificate for {} from the CA at {endpoint}.",
+        names.join(", ")
+    );
+    let require_client_cert = env_or_default("FEOS_REQUIRE_CLIENT_CERT", false, |_| true);
+    if !require_client_cert {
+        info!("Main: Clients without a certificate are let through, set FEOS_REQUIRE_CLIENT_CERT to reject them.");
+    }
+    Ok(Some(CaConfig {
+        endpoint,
+        ca_cert,
+        enrollment_token,
+        dir: cert_dir(),
+        names,
+        require_client_cert,
+    }))
+}
+
+/// Where the certificate from the CA is kept, FEOS_CERT_DIR.
+fn cert_dir() -> PathBuf {
+    PathBuf::from(env::var("FEOS_CERT_DIR").unwrap_or_else(|_| DEFAULT_CERT_DIR.to_string()))
+}
+
+/// Applies the default profile of the host firewall, unless FEOS_HOST_FIREWALL
+/// is false. It filters the interfaces in FEOS_HOST_FIREWALL_INTERFACES, the
+/// uplink by default, and lets SSH through if FEOS_HOST_FIREWALL_SSH is true.
+/// Other listeners, e.g. the metrics endpoint, need a firewall exception.
+pub(crate) async fn setup_host_firewall() -> FirewallProfile {
+    let is_interface_list = |interfaces: &String| {
+        interfaces.split(',').all(|interface| {
+            !interface.is_empty()
+                && interface.len() < 16
+                && interface
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        })
+    };
+    let interfaces = env_or_default(
+        "FEOS_HOST_FIREWALL_INTERFACES",
+        INTERFACE_NAME.to_string(),
+        is_interface_list,
+    );
+    let profile = FirewallProfile {
+        interfaces: interfaces.split(',').map(String::from).collect(),
+        api_port: API_PORT,
+        ssh: env_or_default("FEOS_HOST_FIREWALL_SSH", false, |_| true),
+    };
+    if !env_or_default("FEOS_HOST_FIREWALL", true, |_| true) {
+        warn!("Main: Host firewall is disabled, the host accepts traffic on every port.");
+        return profile;
+    }
+    match firewall::apply(&profile).await {
+        Ok(()) => info!(
+            "Main: Host firewall applied on {}, SSH is {}.",
+            interfaces,
+            if profile.ssh { "allowed" } else { "blocked" }
+        ),
+        Err(e) => error!("Main: Failed to apply the host firewall: {e}"),
+    }
+    profile
+}
+
+/// Persists the daemon logs so they survive restarts and reboots. An empty
+/// FEOS_LOG_JOURNAL_DIR keeps them in memory only.
+pub(crate) async fn attach_log_journal(log_handle: &LogHandle) {
+    let dir = env::var("FEOS_LOG_JOURNAL_DIR").unwrap_or_else(|_| {
+        info!("Main: FEOS_LOG_JOURNAL_DIR not set, using default '{DEFAULT_JOURNAL_DIR}'");
+        DEFAULT_JOURNAL_DIR.to_string()
+    });
+    if dir.is_empty() {
+        info!("Main: Log journal is disabled, logs are kept in memory only.");
+        return;
+    }
+    let max_bytes = match env::var("FEOS_LOG_JOURNAL_MAX_BYTES") {
+        Ok(value) => value.parse().unwrap_or_else(|_| {
+            warn!(
+                "Main: Invalid FEOS_LOG_JOURNAL_MAX_BYTES '{value}', using default {DEFAULT_JOURNAL_MAX_BYTES}"
+            );
+            DEFAULT_JOURNAL_MAX_BYTES
+        }),
+        Err(_) => DEFAULT_JOURNAL_MAX_BYTES,
+    };
+
+    let config = JournalConfig {
+        dir: PathBuf::from(&dir),
+        max_bytes,
+    };
+    match log_handle.attach_journal(config).await {
+        Ok(()) => info!("Main: Writing logs to journal in '{dir}' (up to {max_bytes} bytes)."),
+        Err(e) => {
+            warn!("Main: Failed to open log journal in '{dir}', keeping logs in memory only: {e}")
+        }
+    }
+}
+
+/// The services served on the public endpoint, for GetCapabilities.
+fn capabilities() -> GetCapabilitiesResponse {
+    let mut services = vec![
+        host_service_server::SERVICE_NAME,
+        storage_service_server::SERVICE_NAME,
+        schedule_service_server::SERVICE_NAME,
+        secret_service_server::SERVICE_NAME,
+        audit_service_server::SERVICE_NAME,
+    ];
+    #[cfg(feature = "vm")]
+    services.push(vm_service_server::SERVICE_NAME);
+    #[cfg(feature = "container")]
+    services.push(container_service_server::SERVICE_NAME);
+    GetCapabilitiesResponse {
+        services: services.into_iter().map(String::from).collect(),
+    }
+}
+
 pub(crate) fn initialize_host_service(
     restart_tx: mpsc::Sender<RestartSignal>,
-    log_handle: feos_utils::feos_logger::LogHandle,
+    log_handle: LogHandle,
     ntp_servers: Vec<Ipv6Addr>,
-) -> HostServiceServer<HostApiHandler> {
+    maintenance: Maintenance,
+    firewall: FirewallProfile,
+    identity: GetNodeIdentityResponse,
+) -> (HostServiceServer<HostApiHandler>, mpsc::Sender<HostCommand>) {
     let (host_tx, host_rx) = mpsc::channel::<HostCommand>(32);
-    let host_dispatcher = HostServiceDispatcher::new(host_rx, restart_tx, log_handle);
+    let node_id = identity.node_id.clone();
+    let host_dispatcher = HostServiceDispatcher::new(
+        host_rx,
+        restart_tx,
+        log_handle,
+        maintenance,
+        capabilities(),
+        firewall,
+        identity,
+    );
     tokio::spawn(async move {
         host_dispatcher.run().await;
     });
@@ -111,14 +750,19 @@ pub(crate) fn initialize_host_service(
         time_worker.run().await;
     });
 
-    let host_api_handler = HostApiHandler::new(host_tx);
+    let host_api_handler = HostApiHandler::new(host_tx.clone(), node_id);
     let host_service = HostServiceServer::new(host_api_handler);
     info!("Main: Host Service is configured.");
 
-    host_service
+    (host_service, host_tx)
 }
 
-pub(crate) async fn initialize_image_service() -> Result<ImageServiceServer<ImageApiHandler>> {
+pub(crate) async fn initialize_image_service(
+    references: Arc<dyn ImageReferences>,
+) -> Result<(
+    ImageServiceServer<ImageApiHandler>,
+    mpsc::Sender<FileCommand>,
+)> {
     info!("Main: Ensuring image directory '{IMAGE_DIR}' exists...");
     fs::create_dir_all(IMAGE_DIR).await?;
     info!("Main: Directory check complete. Path '{IMAGE_DIR}' is ready.");
@@ -130,12 +774,65 @@ pub(crate) async fn initialize_image_service() -> Result<ImageServiceServer<Imag
     });
     info!("Main: FileStore actor for Image Service has been started.");
 
-    let orchestrator_actor = Orchestrator::new(filestore_tx);
+    // Hosts of a rack can share the images one of them pulled, see
+    // `RegistryMirror`.
+    let mirror = env::var("FEOS_REGISTRY_MIRROR")
+        .ok()
+        .filter(|mirror| !mirror.is_empty());
+    if let Some(mirror) = &mirror {
+        info!("Main: Pulling images through registry mirror {mirror}.");
+    }
+    if let Ok(listen) = env::var("FEOS_REGISTRY_MIRROR_LISTEN") {
+        match listen.parse::<SocketAddr>() {
+            Ok(addr) => {
+                tokio::spawn(async move {
+                    let registry_mirror = RegistryMirror::new(IMAGE_MIRROR_DIR);
+                    if let Err(e) = mirror::serve(addr, registry_mirror).await {
+                        error!("Main: Registry mirror on {addr} failed: {e}");
+                    }
+                });
+            }
+            Err(e) => warn!("Main: Invalid FEOS_REGISTRY_MIRROR_LISTEN '{listen}': {e}"),
+        }
+    }
+
+    // Credentials of private registries, in the format of Docker's
+    // config.json. The file is read on every pull.
+    let auth_file = env::var("FEOS_REGISTRY_AUTH_FILE")
+        .ok()
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from);
+    if let Some(auth_file) = &auth_file {
+        info!(
+            "Main: Reading registry credentials from {}.",
+            auth_file.display()
+        );
+    }
+
+    // Images no workload used for the grace period are deleted.
+    let grace_period = env_or_default(
+        "FEOS_IMAGE_GC_GRACE_PERIOD_SECONDS",
+        reclaimer::DEFAULT_GRACE_PERIOD.as_secs(),
+        |_| true,
+    );
+    let gc_interval = env_or_default(
+        "FEOS_IMAGE_GC_INTERVAL_SECONDS",
+        reclaimer::DEFAULT_INTERVAL.as_secs(),
+        |seconds| *seconds > 0,
+    );
+    let image_reclaimer = Reclaimer::new(references, Duration::from_secs(grace_period));
+
+    let orchestrator_actor =
+        Orchestrator::new(filestore_tx.clone(), mirror, auth_file, image_reclaimer);
     let orchestrator_tx = orchestrator_actor.get_command_sender();
     tokio::spawn(async move {
         orchestrator_actor.run().await;
     });
     info!("Main: Orchestrator actor for Image Service has been started.");
+    tokio::spawn(reclaimer::run(
+        orchestrator_tx.clone(),
+        Duration::from_secs(gc_interval),
+    ));
 
     let grpc_dispatcher = ImageServiceDispatcher::new(orchestrator_tx);
     let grpc_dispatcher_tx = grpc_dispatcher.get_command_sender();
@@ -148,9 +845,10 @@ pub(crate) async fn initialize_image_service() -> Result<ImageServiceServer<Imag
     let image_service = ImageServiceServer::new(image_api_handler);
     info!("Main: Image Service is configured.");
 
-    Ok(image_service)
+    Ok((image_service, filestore_tx))
 }
 
+#[cfg(feature = "container")]
 pub(crate) async fn initialize_task_service() -> Result<TaskServiceServer<TaskApiHandler>> {
     info!("Main: Starting Task Service...");
 
@@ -203,9 +901,41 @@ pub(crate) async fn perform_first_boot_initialization() -> Result<Vec<Ipv6Addr>>
     Ok(ntp_servers)
 }
 
-pub(crate) async fn setup_database() -> Result<String> {
-    dotenvy::dotenv().ok();
+/// Lets the workloads that start by themselves come up once the host has a
+/// default route, or after `NETWORK_READY_TIMEOUT` if it never gets one.
+pub(crate) async fn wait_for_network(startup: StartupOrder) {
+    let deadline = Instant::now() + NETWORK_READY_TIMEOUT;
+    while !has_default_route().await {
+        if Instant::now() >= deadline {
+            warn!(
+                "Main: No default route after {NETWORK_READY_TIMEOUT:?}, auto-starting workloads anyway."
+            );
+            break;
+        }
+        tokio::time::sleep(NETWORK_POLL_INTERVAL).await;
+    }
+    info!("Main: Network is ready, auto-starting workloads.");
+    startup.set_network_ready();
+    log_nat64().await;
+}
+
+/// Tells how IPv4-only registries and servers are reached from IPv6-only
+/// hosts.
+async fn log_nat64() {
+    if has_ipv4_default_route().await {
+        return;
+    }
+    match nat64::discover_prefix().await {
+        Some(prefix) => info!("Main: IPv6-only host, DNS64 resolver uses NAT64 prefix {prefix}"),
+        None => warn!(
+            "Main: IPv6-only host without a DNS64 resolver, IPv4-only names are unreachable. IPv4 addresses are mapped into {}",
+            nat64::Nat64Prefix::WELL_KNOWN
+        ),
+    }
+}
 
+#[cfg(feature = "vm")]
+pub(crate) async fn setup_database() -> Result<String> {
     let db_url = env::var("DATABASE_URL").unwrap_or_else(|_| {
         info!("Main: DATABASE_URL not set, using default '{DEFAULT_VM_DB_URL}'");
         DEFAULT_VM_DB_URL.to_string()
@@ -232,7 +962,36 @@ pub(crate) async fn setup_database() -> Result<String> {
     Ok(db_url)
 }
 
-pub(crate) fn handle_upgrade(new_binary_path: &Path) -> Result<()> {
+/// Makes this daemon the primary of the host. A standby waits until the
+/// primary is gone, any other daemon gives up if there is one. Nothing is
+/// initialized before, so a standby starts up cold when it takes over.
+pub(crate) async fn acquire_primary_lock(
+    standby: bool,
+    inherited_fd: Option<RawFd>,
+) -> Result<PrimaryLock> {
+    if let Some(fd) = inherited_fd {
+        // SAFETY: handle_upgrade passes the descriptor of the lock it kept
+        // open across exec, nothing else uses it.
+        return Ok(unsafe { PrimaryLock::from_inherited_fd(fd) }?);
+    }
+    let path = Path::new(PRIMARY_LOCK_PATH);
+    if let Some(lock) = PrimaryLock::try_acquire(path)? {
+        info!("Main: This daemon is the primary of the host.");
+        return Ok(lock);
+    }
+    let primary = primary_pid(path).map_or_else(|| "unknown".to_string(), |pid| pid.to_string());
+    if !standby {
+        anyhow::bail!(
+            "The FeOS daemon with PID {primary} is the primary of this host, start with --standby to take over when it is gone"
+        );
+    }
+    info!("Main: Standing by for the primary FeOS daemon (PID {primary}).");
+    let lock = tokio::task::spawn_blocking(move || PrimaryLock::acquire(path)).await??;
+    warn!("Main: The primary FeOS daemon (PID {primary}) is gone, taking over its workloads.");
+    Ok(lock)
+}
+
+pub(crate) fn handle_upgrade(new_binary_path: &Path, primary_lock: &PrimaryLock) -> Result<()> {
     info!("Main: Upgrade signal received. New binary at {new_binary_path:?}. Preparing to execv.");
 
     let current_exe = match std::env::current_exe() {
@@ -294,6 +1053,14 @@ pub(crate) fn handle_upgrade(new_binary_path: &Path) -> Result<()> {
     if !args.contains(&restart_flag.to_string()) {
         args.push(restart_flag.to_string());
     }
+    // The new binary holds on to the lock, so no standby takes over while it
+    // starts.
+    let lock_fd_flag = "--primary-lock-fd=";
+    args.retain(|arg| !arg.starts_with(lock_fd_flag));
+    match primary_lock.keep_across_exec() {
+        Ok(fd) => args.push(format!("{lock_fd_flag}{fd}")),
+        Err(e) => warn!("Main: Failed to keep the primary lock across the upgrade: {e}"),
+    }
 
     let cstr_args: Vec<CString> = args
         .into_iter()
@@ -54,11 +54,25 @@ async fn test_create_and_start_container() -> Result<()> {
         image_ref,
         command: vec![],
         env: Default::default(),
+        disk_limit_bytes: 0,
+        swap_max_bytes: None,
+        memory_limit_bytes: 0,
+        milli_cpus: 0,
+        drain_policy: 0,
+        startup: None,
+        stop_signal: None,
+        stop_timeout_seconds: None,
+        cpu_weight: None,
+        cpuset: String::new(),
+        scheduling: None,
+        serial_ports: vec![],
+        secrets: vec![],
     };
 
     let create_req = CreateContainerRequest {
         config: Some(container_config),
         container_id: None,
+        ..Default::default()
     };
 
     info!("Sending CreateContainer request");
@@ -3,9 +3,12 @@
 
 use super::{ensure_server, get_public_clients};
 use anyhow::{Context, Result};
+use feos_proto::container_service::container_service_server;
 use feos_proto::host_service::{
-    GetCpuInfoRequest, GetNetworkInfoRequest, HostnameRequest, MemoryRequest,
+    host_service_server, GetCapabilitiesRequest, GetCpuInfoRequest, GetNetworkInfoRequest,
+    HostnameRequest, MemoryRequest,
 };
+use feos_proto::vm_service::vm_service_server;
 use log::info;
 use nix::unistd;
 use std::fs::File;
@@ -219,3 +222,28 @@ async fn test_get_kernel_stats() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_get_capabilities() -> Result<()> {
+    ensure_server().await;
+    let (_, mut host_client, _) = get_public_clients().await?;
+
+    let services = host_client
+        .get_capabilities(GetCapabilitiesRequest {})
+        .await?
+        .into_inner()
+        .services;
+    info!("Services from API: {services:?}");
+    for service in [
+        host_service_server::SERVICE_NAME,
+        vm_service_server::SERVICE_NAME,
+        container_service_server::SERVICE_NAME,
+    ] {
+        assert!(
+            services.iter().any(|s| s == service),
+            "{service} should be listed in {services:?}"
+        );
+    }
+
+    Ok(())
+}
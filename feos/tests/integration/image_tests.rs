@@ -7,7 +7,7 @@ use feos_proto::image_service::{
     DeleteImageRequest, ImageState, ImageStatusResponse, ListImagesRequest, PullImageRequest,
     WatchImageStatusRequest,
 };
-use image_service::IMAGE_DIR;
+use image_service::{filestore::rootfs_layers, IMAGE_DIR};
 use log::info;
 use std::path::Path;
 use std::time::Duration;
@@ -131,9 +131,11 @@ async fn test_container_image_lifecycle() -> Result<()> {
         image_path.display()
     );
     assert!(image_path.exists(), "Image directory should exist");
+    let layers = rootfs_layers(&image_path).await?;
+    assert!(!layers.is_empty(), "container image should have layers");
     assert!(
-        image_path.join("rootfs").exists(),
-        "rootfs directory should exist for container image"
+        layers.iter().all(|layer| layer.exists()),
+        "layers of the container image should be unpacked"
     );
     assert!(
         image_path.join("config.json").exists(),
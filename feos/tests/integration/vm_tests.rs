@@ -35,15 +35,29 @@ async fn test_create_and_start_vm() -> Result<()> {
         memory: Some(MemoryConfig {
             size_mib: 2048,
             hugepages: false,
+            swap_max_bytes: None,
+            hotplug_size_mib: 0,
+            hotplugged_size_mib: 0,
         }),
         image_ref,
         disks: vec![],
         net: vec![],
         ignition: None,
+        clock: None,
+        drain_policy: 0,
+        startup: None,
+        gpus: vec![],
+        mdevs: vec![],
+        serial_port: None,
+        netboot: None,
+        placement: None,
+        pci_devices: vec![],
+        cloud_init: None,
     };
     let create_req = CreateVmRequest {
         config: Some(vm_config),
         vm_id: None,
+        ..Default::default()
     };
 
     info!("Sending CreateVm request");
@@ -92,6 +106,8 @@ async fn test_create_and_start_vm() -> Result<()> {
     info!("Sending ShutdownVm request for vm_id: {}", &vm_id);
     let shutdown_req = ShutdownVmRequest {
         vm_id: vm_id.clone(),
+        graceful: false,
+        grace_period_seconds: None,
     };
     vm_client.shutdown_vm(shutdown_req).await?;
 
@@ -122,6 +138,8 @@ async fn test_create_and_start_vm() -> Result<()> {
 
     let attach_payload = console_input::Payload::Attach(AttachConsoleMessage {
         vm_id: vm_id.clone(),
+        read_only: false,
+        takeover: false,
     });
     let attach_input = StreamVmConsoleRequest {
         payload: Some(attach_payload),
@@ -231,6 +249,7 @@ async fn test_create_and_start_vm() -> Result<()> {
             device_id: "test".to_string(),
             backend: Some(net_config::Backend::Tap(TapConfig {
                 tap_name: "test".to_string(),
+                ..Default::default()
             })),
             ..Default::default()
         }),
@@ -316,15 +335,29 @@ async fn test_vm_healthcheck_and_crash_recovery() -> Result<()> {
         memory: Some(MemoryConfig {
             size_mib: 1024,
             hugepages: false,
+            swap_max_bytes: None,
+            hotplug_size_mib: 0,
+            hotplugged_size_mib: 0,
         }),
         image_ref,
         disks: vec![],
         net: vec![],
         ignition: None,
+        clock: None,
+        drain_policy: 0,
+        startup: None,
+        gpus: vec![],
+        mdevs: vec![],
+        serial_port: None,
+        netboot: None,
+        placement: None,
+        pci_devices: vec![],
+        cloud_init: None,
     };
     let create_req = CreateVmRequest {
         config: Some(vm_config),
         vm_id: None,
+        ..Default::default()
     };
 
     info!("Sending CreateVm request for healthcheck test");
@@ -404,6 +437,8 @@ async fn test_vm_healthcheck_and_crash_recovery() -> Result<()> {
 
     let shutdown_req = ShutdownVmRequest {
         vm_id: vm_id.clone(),
+        graceful: false,
+        grace_period_seconds: None,
     };
     assert!(
         vm_client.shutdown_vm(shutdown_req).await.is_err(),
@@ -0,0 +1,137 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use log::LevelFilter;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// The subsystem a log entry belongs to. Each one has its own history buffer
+/// and log level, so verbose logging in one does not crowd out the others.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Component {
+    Vm,
+    Container,
+    Network,
+    Api,
+    /// Everything else: host management, storage, images and startup.
+    System,
+}
+
+impl Component {
+    pub const ALL: [Component; 5] = [
+        Component::Vm,
+        Component::Container,
+        Component::Network,
+        Component::Api,
+        Component::System,
+    ];
+
+    /// Maps a log target, i.e. the module path of the log call, to the
+    /// subsystem it belongs to.
+    pub fn from_target(target: &str) -> Self {
+        let mut path = target.split("::");
+        let krate = path.next().unwrap_or(target);
+        // The gRPC handlers of every service live in its `api` module.
+        if path.next() == Some("api") {
+            return Component::Api;
+        }
+        match krate {
+            "vm_service" => Component::Vm,
+            "container_service" | "task_service" => Component::Container,
+            "tonic" | "h2" | "hyper" | "hyper_util" | "tower" => Component::Api,
+            "rtnetlink" | "netlink_proto" | "netlink_sys" => Component::Network,
+            _ if target.starts_with("feos_utils::network") => Component::Network,
+            _ => Component::System,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Component::Vm => "vm",
+            Component::Container => "container",
+            Component::Network => "network",
+            Component::Api => "api",
+            Component::System => "system",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for Component {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Component {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Component::ALL
+            .into_iter()
+            .find(|component| component.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown log component '{s}'"))
+    }
+}
+
+/// The log level of every component, shared between the `log` frontend and
+/// the `LogHandle`s so it can be changed while FeOS is running.
+#[derive(Clone)]
+pub(crate) struct ComponentLevels {
+    levels: Arc<[AtomicUsize; Component::ALL.len()]>,
+}
+
+impl ComponentLevels {
+    pub(crate) fn new(default: LevelFilter) -> Self {
+        Self {
+            levels: Arc::new(std::array::from_fn(|_| AtomicUsize::new(default as usize))),
+        }
+    }
+
+    pub(crate) fn get(&self, component: Component) -> LevelFilter {
+        let level = self.levels[component.index()].load(Ordering::Relaxed);
+        LevelFilter::iter().nth(level).unwrap_or(LevelFilter::Trace)
+    }
+
+    /// Sets the level of a component and raises or lowers the global `log`
+    /// maximum to the most verbose level still in use.
+    pub(crate) fn set(&self, component: Component, level: LevelFilter) {
+        self.levels[component.index()].store(level as usize, Ordering::Relaxed);
+        log::set_max_level(self.max());
+    }
+
+    pub(crate) fn max(&self) -> LevelFilter {
+        Component::ALL
+            .into_iter()
+            .map(|component| self.get(component))
+            .max()
+            .unwrap_or(LevelFilter::Off)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn targets_map_to_components() {
+        assert_eq!(Component::from_target("vm_service::worker"), Component::Vm);
+        assert_eq!(
+            Component::from_target("task_service::worker"),
+            Component::Container
+        );
+        assert_eq!(
+            Component::from_target("feos_utils::network::dhcpv6"),
+            Component::Network
+        );
+        assert_eq!(Component::from_target("vm_service::api"), Component::Api);
+        assert_eq!(Component::from_target("h2::codec"), Component::Api);
+        assert_eq!(Component::from_target("feos::setup"), Component::System);
+        assert_eq!("Network".parse::<Component>(), Ok(Component::Network));
+    }
+}
@@ -0,0 +1,347 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{Component, LogEntry};
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_JOURNAL_DIR: &str = "/var/lib/feos/logs";
+pub const DEFAULT_JOURNAL_MAX_BYTES: u64 = 64 << 20;
+
+const SEGMENT_EXTENSION: &str = "log";
+/// The journal keeps at least this many segments, so dropping the oldest one
+/// never discards more than a fraction of the history.
+const MIN_SEGMENTS: u64 = 8;
+
+/// Where and how much of the log history is kept on disk.
+#[derive(Clone, Debug)]
+pub struct JournalConfig {
+    pub dir: PathBuf,
+    pub max_bytes: u64,
+}
+
+impl Default for JournalConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from(DEFAULT_JOURNAL_DIR),
+            max_bytes: DEFAULT_JOURNAL_MAX_BYTES,
+        }
+    }
+}
+
+/// Selects log entries by sequence number and time. All bounds are
+/// inclusive, unset bounds are open.
+#[derive(Clone, Debug, Default)]
+pub struct LogQuery {
+    pub start_seq: Option<u64>,
+    pub end_seq: Option<u64>,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+    /// Stop after this many entries, oldest first. 0 means no limit.
+    pub limit: usize,
+    pub component: Option<Component>,
+}
+
+impl LogQuery {
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        self.start_seq.is_none_or(|seq| entry.seq >= seq)
+            && self.end_seq.is_none_or(|seq| entry.seq <= seq)
+            && self.start_time.is_none_or(|time| entry.timestamp >= time)
+            && self.end_time.is_none_or(|time| entry.timestamp <= time)
+            && self
+                .component
+                .is_none_or(|component| entry.component() == component)
+    }
+
+    pub(crate) fn is_full(&self, found: usize) -> bool {
+        self.limit > 0 && found >= self.limit
+    }
+}
+
+struct Segment {
+    first_seq: u64,
+    path: PathBuf,
+    size: u64,
+}
+
+/// A circular on-disk log made of segment files, one entry per line. The
+/// oldest segment is deleted once the journal outgrows its size, so the
+/// newest `max_bytes` of history survive restarts and reboots.
+pub(crate) struct Journal {
+    dir: PathBuf,
+    max_bytes: u64,
+    segment_bytes: u64,
+    segments: VecDeque<Segment>,
+    file: Option<File>,
+}
+
+fn segment_path(dir: &Path, first_seq: u64) -> PathBuf {
+    dir.join(format!("{first_seq:020}.{SEGMENT_EXTENSION}"))
+}
+
+impl Journal {
+    /// Opens the journal in `config.dir`, creating it if needed. Also returns
+    /// the sequence number of the newest entry on disk.
+    pub(crate) fn open(config: &JournalConfig) -> io::Result<(Self, Option<u64>)> {
+        fs::create_dir_all(&config.dir)?;
+        let mut segments = Vec::new();
+        for dir_entry in fs::read_dir(&config.dir)? {
+            let path = dir_entry?.path();
+            if path.extension().is_none_or(|ext| ext != SEGMENT_EXTENSION) {
+                continue;
+            }
+            let Some(first_seq) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse().ok())
+            else {
+                continue;
+            };
+            let size = fs::metadata(&path)?.len();
+            segments.push(Segment {
+                first_seq,
+                path,
+                size,
+            });
+        }
+        segments.sort_by_key(|segment| segment.first_seq);
+
+        let mut last_seq = None;
+        for segment in segments.iter().rev() {
+            last_seq = last_entry(&segment.path)?.map(|entry| entry.seq);
+            if last_seq.is_some() {
+                break;
+            }
+        }
+
+        let journal = Self {
+            dir: config.dir.clone(),
+            max_bytes: config.max_bytes,
+            segment_bytes: (config.max_bytes / MIN_SEGMENTS).max(1),
+            segments: segments.into(),
+            file: None,
+        };
+        Ok((journal, last_seq))
+    }
+
+    pub(crate) fn append(&mut self, entry: &LogEntry) -> io::Result<()> {
+        let line = encode(entry);
+        let needs_segment = match self.segments.back() {
+            Some(segment) => segment.size + line.len() as u64 > self.segment_bytes,
+            None => true,
+        };
+        if needs_segment {
+            self.start_segment(entry.seq)?;
+        }
+
+        let Some(segment) = self.segments.back_mut() else {
+            return Ok(());
+        };
+        if self.file.is_none() {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&segment.path)?;
+            // A crash can leave a partial line behind, keep it separate.
+            if ends_with_partial_line(&segment.path)? {
+                file.write_all(b"\n")?;
+                segment.size += 1;
+            }
+            self.file = Some(file);
+        }
+        if let Some(file) = self.file.as_mut() {
+            file.write_all(line.as_bytes())?;
+            segment.size += line.len() as u64;
+        }
+        Ok(())
+    }
+
+    fn start_segment(&mut self, first_seq: u64) -> io::Result<()> {
+        if let Some(file) = self.file.take() {
+            file.sync_data()?;
+        }
+        let path = segment_path(&self.dir, first_seq);
+        self.segments.push_back(Segment {
+            first_seq,
+            path,
+            size: 0,
+        });
+
+        let mut total: u64 = self.segments.iter().map(|segment| segment.size).sum();
+        while total > self.max_bytes.saturating_sub(self.segment_bytes) && self.segments.len() > 1 {
+            let Some(oldest) = self.segments.pop_front() else {
+                break;
+            };
+            total -= oldest.size;
+            if let Err(e) = fs::remove_file(&oldest.path) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The segment files oldest first, with the first sequence number each
+    /// of them holds.
+    pub(crate) fn segments(&self) -> Vec<(u64, PathBuf)> {
+        self.segments
+            .iter()
+            .map(|segment| (segment.first_seq, segment.path.clone()))
+            .collect()
+    }
+}
+
+fn ends_with_partial_line(path: &Path) -> io::Result<bool> {
+    let mut file = File::open(path)?;
+    if file.metadata()?.len() == 0 {
+        return Ok(false);
+    }
+    file.seek(SeekFrom::End(-1))?;
+    let mut last = [0u8; 1];
+    file.read_exact(&mut last)?;
+    Ok(last[0] != b'\n')
+}
+
+fn last_entry(path: &Path) -> io::Result<Option<LogEntry>> {
+    let mut last = None;
+    for line in BufReader::new(File::open(path)?).split(b'\n') {
+        if let Some(entry) = decode(&String::from_utf8_lossy(&line?)) {
+            last = Some(entry);
+        }
+    }
+    Ok(last)
+}
+
+/// Reads the entries matching `query` from the given segments. Segments that
+/// were rotated away in the meantime are skipped.
+pub(crate) fn read_segments(
+    segments: &[(u64, PathBuf)],
+    query: &LogQuery,
+) -> io::Result<Vec<LogEntry>> {
+    let mut entries = Vec::new();
+    for (i, (_, path)) in segments.iter().enumerate() {
+        // Everything in this segment precedes the next one.
+        let next_first_seq = segments.get(i + 1).map(|(seq, _)| *seq);
+        if let (Some(start), Some(next)) = (query.start_seq, next_first_seq) {
+            if next <= start {
+                continue;
+            }
+        }
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        for line in BufReader::new(file).split(b'\n') {
+            // Lines torn by a crash may not even be valid UTF-8.
+            let Some(entry) = decode(&String::from_utf8_lossy(&line?)) else {
+                continue;
+            };
+            if query.end_seq.is_some_and(|end| entry.seq > end) {
+                return Ok(entries);
+            }
+            if query.matches(&entry) {
+                entries.push(entry);
+                if query.is_full(entries.len()) {
+                    return Ok(entries);
+                }
+            }
+        }
+    }
+    Ok(entries)
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('t') => unescaped.push('\t'),
+            Some(c) => unescaped.push(c),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+/// Serializes an entry as a tab-separated line.
+fn encode(entry: &LogEntry) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\t{}\n",
+        entry.seq,
+        entry.timestamp.to_rfc3339_opts(SecondsFormat::Nanos, true),
+        entry.level,
+        escape(&entry.target),
+        escape(&entry.message)
+    )
+}
+
+fn decode(line: &str) -> Option<LogEntry> {
+    let mut fields = line.splitn(5, '\t');
+    let seq = fields.next()?.parse().ok()?;
+    let timestamp = DateTime::parse_from_rfc3339(fields.next()?)
+        .ok()?
+        .with_timezone(&Utc);
+    let level = fields.next()?.parse().ok()?;
+    let target = unescape(fields.next()?);
+    let message = unescape(fields.next()?);
+    Some(LogEntry {
+        seq,
+        timestamp,
+        level,
+        target,
+        message,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+
+    #[test]
+    fn entries_survive_encoding() {
+        let entry = LogEntry {
+            seq: 42,
+            timestamp: Utc::now(),
+            level: Level::Warn,
+            target: "vm_service::worker".to_string(),
+            message: "line one\nline\ttwo \\ three".to_string(),
+        };
+        let line = encode(&entry);
+        assert_eq!(line.matches('\n').count(), 1);
+
+        let decoded = decode(line.trim_end_matches('\n')).unwrap();
+        assert_eq!(decoded.seq, entry.seq);
+        assert_eq!(decoded.timestamp, entry.timestamp);
+        assert_eq!(decoded.level, entry.level);
+        assert_eq!(decoded.target, entry.target);
+        assert_eq!(decoded.message, entry.message);
+
+        assert!(decode("17\t2025-01-01T00:00:00Z\tINFO").is_none());
+    }
+}
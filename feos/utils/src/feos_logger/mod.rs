@@ -3,12 +3,21 @@
 
 use chrono::{DateTime, Utc};
 use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::fmt;
-use std::io::Write;
+use std::io::{self, Write};
+use std::path::PathBuf;
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 use tokio::sync::{broadcast, mpsc, oneshot};
 
+mod component;
+mod journal;
+
+pub use component::Component;
+use component::ComponentLevels;
+use journal::Journal;
+pub use journal::{JournalConfig, LogQuery, DEFAULT_JOURNAL_DIR, DEFAULT_JOURNAL_MAX_BYTES};
+
 #[derive(Clone, Debug)]
 pub struct LogEntry {
     pub seq: u64,
@@ -18,6 +27,12 @@ pub struct LogEntry {
     pub message: String,
 }
 
+impl LogEntry {
+    pub fn component(&self) -> Component {
+        Component::from_target(&self.target)
+    }
+}
+
 impl fmt::Display for LogEntry {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -34,7 +49,9 @@ impl fmt::Display for LogEntry {
 #[derive(Clone)]
 pub struct LogHandle {
     history_requester: mpsc::Sender<HistoryRequest>,
+    journal_requester: mpsc::Sender<JournalRequest>,
     broadcast_sender: broadcast::Sender<LogEntry>,
+    levels: ComponentLevels,
 }
 
 pub struct LogReader {
@@ -44,6 +61,7 @@ pub struct LogReader {
 
 pub struct Builder {
     filter: LevelFilter,
+    component_filters: Vec<(Component, LevelFilter)>,
     max_history: usize,
     broadcast_capacity: usize,
     mpsc_capacity: usize,
@@ -54,6 +72,7 @@ impl Default for Builder {
     fn default() -> Self {
         Self {
             filter: LevelFilter::Info,
+            component_filters: Vec::new(),
             max_history: 1000,
             broadcast_capacity: 1024,
             mpsc_capacity: 4096,
@@ -72,6 +91,13 @@ impl Builder {
         self
     }
 
+    /// Overrides the level set by `filter_level` for one component.
+    pub fn component_level(mut self, component: Component, level: LevelFilter) -> Self {
+        self.component_filters.push((component, level));
+        self
+    }
+
+    /// The number of entries kept in memory for each component.
     pub fn max_history(mut self, size: usize) -> Self {
         self.max_history = size;
         self
@@ -85,20 +111,28 @@ impl Builder {
     pub fn init(self) -> Result<LogHandle, SetLoggerError> {
         let (log_tx, log_rx) = mpsc::channel::<LogMessage>(self.mpsc_capacity);
         let (history_tx, history_rx) = mpsc::channel(32);
+        let (journal_tx, journal_rx) = mpsc::channel(32);
         let (broadcast_tx, _) = broadcast::channel(self.broadcast_capacity);
 
+        let levels = ComponentLevels::new(self.filter);
+        for (component, level) in self.component_filters {
+            levels.set(component, level);
+        }
+
         let logger_frontend = FeosLogger {
             sender: log_tx,
-            filter: self.filter,
+            levels: levels.clone(),
         };
 
         let actor = LoggerActor {
             log_receiver: log_rx,
             history_requester: history_rx,
+            journal_requester: journal_rx,
             broadcast_sender: broadcast_tx.clone(),
-            history: VecDeque::with_capacity(self.max_history),
+            history: HashMap::new(),
             max_history: self.max_history,
             seq_counter: 0,
+            journal: None,
             log_to_stdout: self.log_to_stdout,
             stdout_writer: StandardStream::stdout(ColorChoice::Auto),
         };
@@ -107,11 +141,13 @@ impl Builder {
 
         let handle = LogHandle {
             history_requester: history_tx,
+            journal_requester: journal_tx,
             broadcast_sender: broadcast_tx,
+            levels: levels.clone(),
         };
 
         log::set_boxed_logger(Box::new(logger_frontend))?;
-        log::set_max_level(self.filter);
+        log::set_max_level(levels.max());
 
         Ok(handle)
     }
@@ -136,6 +172,76 @@ impl LogHandle {
             receiver,
         })
     }
+
+    /// Returns the buffered log history without subscribing to new entries.
+    pub async fn history(&self) -> Result<Vec<LogEntry>, &'static str> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        if self.history_requester.send(resp_tx).await.is_err() {
+            return Err("Logger actor has shut down");
+        }
+
+        match resp_rx.await {
+            Ok(history) => Ok(history.into()),
+            Err(_) => Err("Failed to receive history from logger actor"),
+        }
+    }
+
+    pub fn level(&self, component: Component) -> LevelFilter {
+        self.levels.get(component)
+    }
+
+    /// Changes the log level of a component at runtime.
+    pub fn set_level(&self, component: Component, level: LevelFilter) {
+        self.levels.set(component, level);
+    }
+
+    /// Starts persisting log entries to the journal in `config.dir`. The
+    /// buffered history is written to it first and renumbered to continue
+    /// the sequence of the entries already on disk.
+    pub async fn attach_journal(&self, config: JournalConfig) -> io::Result<()> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.journal_requester
+            .send(JournalRequest::Attach(config, resp_tx))
+            .await
+            .map_err(|_| io::Error::other("Logger actor has shut down"))?;
+        resp_rx
+            .await
+            .map_err(|_| io::Error::other("Logger actor dropped the journal request"))?
+    }
+
+    /// Returns the entries matching `query`, oldest first. Reads the journal
+    /// if one is attached and the buffered history otherwise.
+    pub async fn query(&self, query: LogQuery) -> io::Result<Vec<LogEntry>> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.journal_requester
+            .send(JournalRequest::Segments(resp_tx))
+            .await
+            .map_err(|_| io::Error::other("Logger actor has shut down"))?;
+        let segments = resp_rx
+            .await
+            .map_err(|_| io::Error::other("Logger actor dropped the journal request"))?;
+
+        match segments {
+            Some(segments) => {
+                tokio::task::spawn_blocking(move || journal::read_segments(&segments, &query))
+                    .await
+                    .map_err(io::Error::other)?
+            }
+            None => {
+                let history = self.history().await.map_err(io::Error::other)?;
+                let mut entries = Vec::new();
+                for entry in history {
+                    if query.matches(&entry) {
+                        entries.push(entry);
+                        if query.is_full(entries.len()) {
+                            break;
+                        }
+                    }
+                }
+                Ok(entries)
+            }
+        }
+    }
 }
 
 impl LogReader {
@@ -159,6 +265,12 @@ impl LogReader {
 
 type HistoryRequest = oneshot::Sender<VecDeque<LogEntry>>;
 
+enum JournalRequest {
+    Attach(JournalConfig, oneshot::Sender<io::Result<()>>),
+    /// Answered with `None` while no journal is attached.
+    Segments(oneshot::Sender<Option<Vec<(u64, PathBuf)>>>),
+}
+
 struct LogMessage {
     level: Level,
     target: String,
@@ -167,12 +279,12 @@ struct LogMessage {
 
 struct FeosLogger {
     sender: mpsc::Sender<LogMessage>,
-    filter: LevelFilter,
+    levels: ComponentLevels,
 }
 
 impl Log for FeosLogger {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= self.filter
+        metadata.level() <= self.levels.get(Component::from_target(metadata.target()))
     }
 
     fn log(&self, record: &Record) {
@@ -197,10 +309,12 @@ impl Log for FeosLogger {
 struct LoggerActor {
     log_receiver: mpsc::Receiver<LogMessage>,
     history_requester: mpsc::Receiver<HistoryRequest>,
+    journal_requester: mpsc::Receiver<JournalRequest>,
     broadcast_sender: broadcast::Sender<LogEntry>,
-    history: VecDeque<LogEntry>,
+    history: HashMap<Component, VecDeque<LogEntry>>,
     max_history: usize,
     seq_counter: u64,
+    journal: Option<Journal>,
     log_to_stdout: bool,
     stdout_writer: StandardStream,
 }
@@ -223,17 +337,28 @@ impl LoggerActor {
                     if self.log_to_stdout {
                         let _ = self.write_log_entry_to_stdout(&entry);
                     }
+                    self.write_log_entry_to_journal(&entry);
 
-                    self.history.push_back(entry.clone());
-                    if self.history.len() > self.max_history {
-                        self.history.pop_front();
+                    let history = self.history.entry(entry.component()).or_default();
+                    history.push_back(entry.clone());
+                    if history.len() > self.max_history {
+                        history.pop_front();
                     }
 
                     let _ = self.broadcast_sender.send(entry);
                 },
 
                 Some(responder) = self.history_requester.recv() => {
-                    let _ = responder.send(self.history.clone());
+                    let _ = responder.send(self.merged_history());
+                },
+
+                Some(request) = self.journal_requester.recv() => match request {
+                    JournalRequest::Attach(config, responder) => {
+                        let _ = responder.send(self.attach_journal(&config));
+                    }
+                    JournalRequest::Segments(responder) => {
+                        let _ = responder.send(self.journal.as_ref().map(Journal::segments));
+                    }
                 },
 
                 else => { break; }
@@ -241,6 +366,40 @@ impl LoggerActor {
         }
     }
 
+    fn attach_journal(&mut self, config: &JournalConfig) -> io::Result<()> {
+        let (journal, last_seq) = Journal::open(config)?;
+        self.journal = Some(journal);
+
+        let offset = last_seq.unwrap_or(0);
+        for entry in self.history.values_mut().flatten() {
+            entry.seq += offset;
+        }
+        self.seq_counter += offset;
+        for entry in self.merged_history() {
+            self.write_log_entry_to_journal(&entry);
+        }
+        Ok(())
+    }
+
+    /// The history of all components, oldest first.
+    fn merged_history(&self) -> VecDeque<LogEntry> {
+        let mut entries: Vec<LogEntry> = self.history.values().flatten().cloned().collect();
+        entries.sort_by_key(|entry| entry.seq);
+        entries.into()
+    }
+
+    /// Stops persisting entries after the first write error, so a full or
+    /// failing disk does not turn every log line into an error.
+    fn write_log_entry_to_journal(&mut self, entry: &LogEntry) {
+        let Some(journal) = self.journal.as_mut() else {
+            return;
+        };
+        if let Err(e) = journal.append(entry) {
+            eprintln!("[LOGGER WARNING] Failed to write log journal, disabling it: {e}");
+            self.journal = None;
+        }
+    }
+
     fn write_log_entry_to_stdout(&mut self, entry: &LogEntry) -> std::io::Result<()> {
         let mut level_spec = ColorSpec::new();
         match entry.level {
@@ -4,6 +4,8 @@
 mod fsmount;
 mod mount;
 mod r#move;
+mod watch;
 
 pub use mount::mount_virtual_filesystems;
 pub use r#move::{get_root_fstype, move_root};
+pub use watch::{wait_for_path, PathWatcher};
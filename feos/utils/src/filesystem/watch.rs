@@ -0,0 +1,179 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use log::debug;
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::unix::AsyncFd;
+use tokio::time::{sleep, timeout_at, Instant};
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(10);
+/// Inotify wakes the watcher as soon as the path shows up, the backoff only
+/// catches what it cannot see, so it may back off far.
+const MAX_BACKOFF_WATCHED: Duration = Duration::from_secs(2);
+const MAX_BACKOFF_UNWATCHED: Duration = Duration::from_millis(500);
+
+struct InotifyFd(Inotify);
+
+impl AsRawFd for InotifyFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_fd().as_raw_fd()
+    }
+}
+
+/// Exponential backoff with up to 50% random jitter, so waiters started
+/// together do not wake up in lockstep.
+struct Backoff {
+    next: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    fn new(max: Duration) -> Self {
+        Self {
+            next: INITIAL_BACKOFF.min(max),
+            max,
+        }
+    }
+
+    fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(self.max);
+        let jitter = RandomState::new().build_hasher().finish() % 512;
+        delay + delay * jitter as u32 / 1024
+    }
+}
+
+/// Wakes up when an entry is created in the directory of `path`. Where the
+/// directory cannot be watched, e.g. because it does not exist yet, it falls
+/// back to waking up with exponential backoff.
+pub struct PathWatcher {
+    path: PathBuf,
+    inotify: Option<AsyncFd<InotifyFd>>,
+    backoff: Backoff,
+}
+
+impl PathWatcher {
+    /// Starts watching. Create the watcher before checking for the path the
+    /// first time, so its creation in between is not missed.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let inotify = match watch_parent(&path) {
+            Ok(inotify) => Some(inotify),
+            Err(e) => {
+                debug!(
+                    "PathWatcher: Cannot watch the directory of {}, polling instead: {e}",
+                    path.display()
+                );
+                None
+            }
+        };
+        let max_backoff = if inotify.is_some() {
+            MAX_BACKOFF_WATCHED
+        } else {
+            MAX_BACKOFF_UNWATCHED
+        };
+        Self {
+            path,
+            inotify,
+            backoff: Backoff::new(max_backoff),
+        }
+    }
+
+    /// Waits until the directory of the path changed or the backoff expired.
+    /// Callers check their condition again after each call.
+    pub async fn changed(&mut self) {
+        let delay = self.backoff.next_delay();
+        let Some(inotify) = self.inotify.as_ref() else {
+            sleep(delay).await;
+            return;
+        };
+        tokio::select! {
+            guard = inotify.readable() => {
+                if let Ok(mut guard) = guard {
+                    // Drain the events, only the wake-up matters.
+                    let _ = guard.try_io(|fd| {
+                        fd.get_ref().0.read_events().map_err(io::Error::from)
+                    });
+                }
+            }
+            _ = sleep(delay) => {}
+        }
+    }
+
+    /// Waits for the path to exist, for at most `wait`.
+    pub async fn wait(&mut self, wait: Duration) -> io::Result<()> {
+        let deadline = Instant::now() + wait;
+        loop {
+            if tokio::fs::try_exists(&self.path).await? {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("timed out waiting for {}", self.path.display()),
+                ));
+            }
+            let _ = timeout_at(deadline, self.changed()).await;
+        }
+    }
+}
+
+fn watch_parent(path: &Path) -> io::Result<AsyncFd<InotifyFd>> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)?;
+    inotify.add_watch(
+        parent,
+        AddWatchFlags::IN_CREATE | AddWatchFlags::IN_MOVED_TO | AddWatchFlags::IN_ATTRIB,
+    )?;
+    AsyncFd::new(InotifyFd(inotify))
+}
+
+/// Waits for `path` to be created, for at most `wait`.
+pub async fn wait_for_path(path: impl Into<PathBuf>, wait: Duration) -> io::Result<()> {
+    PathWatcher::new(path).wait(wait).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn wakes_up_when_the_path_is_created() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("api.sock");
+        let mut watcher = PathWatcher::new(&path);
+        assert!(watcher.inotify.is_some());
+
+        let create = path.clone();
+        tokio::spawn(async move {
+            sleep(Duration::from_millis(20)).await;
+            std::fs::write(create, b"").unwrap();
+        });
+        watcher.wait(Duration::from_secs(5)).await.unwrap();
+
+        let missing = dir.path().join("missing.sock");
+        let err = wait_for_path(missing, Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn backoff_grows_up_to_its_limit() {
+        let max = Duration::from_millis(100);
+        let mut backoff = Backoff::new(max);
+        let delays: Vec<Duration> = (0..8).map(|_| backoff.next_delay()).collect();
+        assert!(delays[0] >= INITIAL_BACKOFF && delays[0] < INITIAL_BACKOFF * 2);
+        assert!(delays.iter().all(|delay| *delay < max * 3 / 2));
+        assert!(delays[7] >= max);
+    }
+}
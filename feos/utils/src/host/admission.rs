@@ -0,0 +1,359 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use log::info;
+use nix::sys::sysinfo::sysinfo;
+use nix::unistd::{sysconf, SysconfVar};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+
+pub const DEFAULT_CPU_OVERCOMMIT_RATIO: f64 = 4.0;
+pub const DEFAULT_MEMORY_OVERCOMMIT_RATIO: f64 = 1.0;
+pub const DEFAULT_RESERVED_MILLI_CPUS: u64 = 0;
+pub const DEFAULT_RESERVED_MEMORY_BYTES: u64 = 512 << 20;
+
+/// The CPU and memory a workload is sized for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Resources {
+    pub milli_cpus: u64,
+    pub memory_bytes: u64,
+}
+
+impl Resources {
+    fn saturating_add(self, other: Resources) -> Resources {
+        Resources {
+            milli_cpus: self.milli_cpus.saturating_add(other.milli_cpus),
+            memory_bytes: self.memory_bytes.saturating_add(other.memory_bytes),
+        }
+    }
+
+    fn saturating_sub(self, other: Resources) -> Resources {
+        Resources {
+            milli_cpus: self.milli_cpus.saturating_sub(other.milli_cpus),
+            memory_bytes: self.memory_bytes.saturating_sub(other.memory_bytes),
+        }
+    }
+}
+
+impl fmt::Display for Resources {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}m CPU, {} MiB memory",
+            self.milli_cpus,
+            self.memory_bytes >> 20
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WorkloadKind {
+    Vm,
+    Container,
+}
+
+impl fmt::Display for WorkloadKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorkloadKind::Vm => f.write_str("VM"),
+            WorkloadKind::Container => f.write_str("container"),
+        }
+    }
+}
+
+/// How far the host may be oversubscribed. The reservations are taken off
+/// the host capacity for FeOS and the host itself before the ratios apply.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdmissionPolicy {
+    pub cpu_overcommit_ratio: f64,
+    pub memory_overcommit_ratio: f64,
+    pub reserved_milli_cpus: u64,
+    pub reserved_memory_bytes: u64,
+}
+
+impl Default for AdmissionPolicy {
+    fn default() -> Self {
+        Self {
+            cpu_overcommit_ratio: DEFAULT_CPU_OVERCOMMIT_RATIO,
+            memory_overcommit_ratio: DEFAULT_MEMORY_OVERCOMMIT_RATIO,
+            reserved_milli_cpus: DEFAULT_RESERVED_MILLI_CPUS,
+            reserved_memory_bytes: DEFAULT_RESERVED_MEMORY_BYTES,
+        }
+    }
+}
+
+impl AdmissionPolicy {
+    /// What workloads may commit in total on a host of the given capacity.
+    pub fn allocatable(&self, capacity: Resources) -> Resources {
+        let scale = |amount: u64, ratio: f64| (amount as f64 * ratio.max(0.0)) as u64;
+        let available = capacity.saturating_sub(Resources {
+            milli_cpus: self.reserved_milli_cpus,
+            memory_bytes: self.reserved_memory_bytes,
+        });
+        Resources {
+            milli_cpus: scale(available.milli_cpus, self.cpu_overcommit_ratio),
+            memory_bytes: scale(available.memory_bytes, self.memory_overcommit_ratio),
+        }
+    }
+}
+
+/// The online CPUs and the physical memory of the host.
+pub fn host_capacity() -> Resources {
+    let cpus = match sysconf(SysconfVar::_NPROCESSORS_ONLN) {
+        Ok(Some(cpus)) => u64::try_from(cpus).unwrap_or(0),
+        _ => 0,
+    };
+    let memory_bytes = sysinfo().map(|info| info.ram_total()).unwrap_or(0);
+    Resources {
+        milli_cpus: cpus * 1000,
+        memory_bytes,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdmissionError {
+    pub requested: Resources,
+    pub committed: Resources,
+    pub allocatable: Resources,
+}
+
+impl fmt::Display for AdmissionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "requested {} with {} already committed exceeds the {} the overcommit policy allows",
+            self.requested, self.committed, self.allocatable
+        )
+    }
+}
+
+impl std::error::Error for AdmissionError {}
+
+type WorkloadKey = (WorkloadKind, String);
+
+struct Ledger {
+    allocatable: Resources,
+    committed: Resources,
+    workloads: HashMap<WorkloadKey, Resources>,
+}
+
+impl Ledger {
+    fn insert(&mut self, key: WorkloadKey, resources: Resources) {
+        if let Some(previous) = self.workloads.insert(key, resources) {
+            self.committed = self.committed.saturating_sub(previous);
+        }
+        self.committed = self.committed.saturating_add(resources);
+    }
+}
+
+/// Tracks the resources committed to VMs and containers against what the
+/// host can give, and turns away workloads that do not fit. It is shared by
+/// all services creating workloads.
+#[derive(Clone)]
+pub struct AdmissionController {
+    ledger: Arc<Mutex<Ledger>>,
+}
+
+impl AdmissionController {
+    pub fn new(policy: &AdmissionPolicy, capacity: Resources) -> Self {
+        let allocatable = policy.allocatable(capacity);
+        info!("AdmissionController: Host has {capacity}, workloads may commit {allocatable}");
+        Self {
+            ledger: Arc::new(Mutex::new(Ledger {
+                allocatable,
+                committed: Resources::default(),
+                workloads: HashMap::new(),
+            })),
+        }
+    }
+
+    fn ledger(&self) -> std::sync::MutexGuard<'_, Ledger> {
+        self.ledger.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn committed(&self) -> Resources {
+        self.ledger().committed
+    }
+
+    /// Commits `resources` to a new workload if they fit. The commitment is
+    /// withdrawn when the returned `Admission` is dropped without `keep`.
+    pub fn admit(
+        &self,
+        kind: WorkloadKind,
+        id: &str,
+        resources: Resources,
+    ) -> Result<Admission, AdmissionError> {
+        let mut ledger = self.ledger();
+        let total = ledger.committed.saturating_add(resources);
+        if total.milli_cpus > ledger.allocatable.milli_cpus
+            || total.memory_bytes > ledger.allocatable.memory_bytes
+        {
+            return Err(AdmissionError {
+                requested: resources,
+                committed: ledger.committed,
+                allocatable: ledger.allocatable,
+            });
+        }
+        ledger.insert((kind, id.to_string()), resources);
+        Ok(Admission {
+            controller: self.clone(),
+            key: Some((kind, id.to_string())),
+        })
+    }
+
+    /// Records a workload that already exists, e.g. one found in a database
+    /// at startup, without checking the policy.
+    pub fn restore(&self, kind: WorkloadKind, id: &str, resources: Resources) {
+        self.ledger().insert((kind, id.to_string()), resources);
+    }
+
+    /// Changes the resources committed to an existing workload if the new
+    /// size fits, e.g. for a VM that gets vCPUs or memory hotplugged. Returns
+    /// the previous commitment, which `restore` puts back if resizing the
+    /// workload itself fails.
+    pub fn resize(
+        &self,
+        kind: WorkloadKind,
+        id: &str,
+        resources: Resources,
+    ) -> Result<Resources, AdmissionError> {
+        let mut ledger = self.ledger();
+        let key = (kind, id.to_string());
+        let previous = ledger.workloads.get(&key).copied().unwrap_or_default();
+        let total = ledger
+            .committed
+            .saturating_sub(previous)
+            .saturating_add(resources);
+        if total.milli_cpus > ledger.allocatable.milli_cpus
+            || total.memory_bytes > ledger.allocatable.memory_bytes
+        {
+            return Err(AdmissionError {
+                requested: resources,
+                committed: ledger.committed,
+                allocatable: ledger.allocatable,
+            });
+        }
+        ledger.insert(key, resources);
+        Ok(previous)
+    }
+
+    /// Gives the resources of a removed workload back.
+    pub fn release(&self, kind: WorkloadKind, id: &str) {
+        let mut ledger = self.ledger();
+        if let Some(resources) = ledger.workloads.remove(&(kind, id.to_string())) {
+            ledger.committed = ledger.committed.saturating_sub(resources);
+        }
+    }
+}
+
+/// The commitment of a workload that is still being created.
+pub struct Admission {
+    controller: AdmissionController,
+    key: Option<WorkloadKey>,
+}
+
+impl Admission {
+    /// Keeps the resources committed once the workload exists, until it is
+    /// released.
+    pub fn keep(mut self) {
+        self.key = None;
+    }
+}
+
+impl Drop for Admission {
+    fn drop(&mut self) {
+        if let Some((kind, id)) = self.key.take() {
+            self.controller.release(kind, &id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIB: u64 = 1 << 30;
+
+    fn controller() -> AdmissionController {
+        let policy = AdmissionPolicy {
+            cpu_overcommit_ratio: 2.0,
+            memory_overcommit_ratio: 1.0,
+            reserved_milli_cpus: 1000,
+            reserved_memory_bytes: GIB,
+        };
+        let capacity = Resources {
+            milli_cpus: 4000,
+            memory_bytes: 9 * GIB,
+        };
+        AdmissionController::new(&policy, capacity)
+    }
+
+    fn sized(milli_cpus: u64, memory_bytes: u64) -> Resources {
+        Resources {
+            milli_cpus,
+            memory_bytes,
+        }
+    }
+
+    #[test]
+    fn workloads_beyond_the_policy_are_rejected() {
+        let controller = controller();
+        controller
+            .admit(WorkloadKind::Vm, "a", sized(4000, 4 * GIB))
+            .unwrap()
+            .keep();
+        // 6 CPUs and 8 GiB are allocatable.
+        let err = controller
+            .admit(WorkloadKind::Vm, "b", sized(4000, GIB))
+            .err()
+            .unwrap();
+        assert_eq!(err.committed, sized(4000, 4 * GIB));
+        assert!(controller
+            .admit(WorkloadKind::Container, "c", sized(1000, 5 * GIB))
+            .is_err());
+        controller
+            .admit(WorkloadKind::Container, "c", sized(2000, 4 * GIB))
+            .unwrap()
+            .keep();
+        assert_eq!(controller.committed(), sized(6000, 8 * GIB));
+
+        controller.release(WorkloadKind::Vm, "a");
+        assert_eq!(controller.committed(), sized(2000, 4 * GIB));
+    }
+
+    #[test]
+    fn dropped_admissions_are_withdrawn() {
+        let controller = controller();
+        let admission = controller
+            .admit(WorkloadKind::Vm, "a", sized(1000, GIB))
+            .unwrap();
+        assert_eq!(controller.committed(), sized(1000, GIB));
+        drop(admission);
+        assert_eq!(controller.committed(), Resources::default());
+
+        controller.restore(WorkloadKind::Vm, "a", sized(8000, 16 * GIB));
+        assert_eq!(controller.committed(), sized(8000, 16 * GIB));
+    }
+
+    #[test]
+    fn resized_workloads_only_count_their_new_size() {
+        let controller = controller();
+        controller
+            .admit(WorkloadKind::Vm, "a", sized(4000, 4 * GIB))
+            .unwrap()
+            .keep();
+        let previous = controller
+            .resize(WorkloadKind::Vm, "a", sized(6000, 8 * GIB))
+            .unwrap();
+        assert_eq!(previous, sized(4000, 4 * GIB));
+        assert_eq!(controller.committed(), sized(6000, 8 * GIB));
+        assert!(controller
+            .resize(WorkloadKind::Vm, "a", sized(6000, 9 * GIB))
+            .is_err());
+        assert_eq!(controller.committed(), sized(6000, 8 * GIB));
+
+        controller.restore(WorkloadKind::Vm, "a", previous);
+        assert_eq!(controller.committed(), sized(4000, 4 * GIB));
+    }
+}
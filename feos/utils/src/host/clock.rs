@@ -0,0 +1,144 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const CLOCKSOURCE_DIR: &str = "/sys/devices/system/clocksource/clocksource0";
+const PTP_CLASS_DIR: &str = "/sys/class/ptp";
+const KVM_DEVICE: &str = "/dev/kvm";
+/// KVM only pairs guest clock readings with the host clock, as the ptp_kvm
+/// driver of guests asks for, while the host runs on the TSC.
+#[cfg(not(target_arch = "aarch64"))]
+pub const PTP_KVM_CLOCKSOURCE: &str = "tsc";
+/// On aarch64 the host has to run on the architected timer instead.
+#[cfg(target_arch = "aarch64")]
+pub const PTP_KVM_CLOCKSOURCE: &str = "arch_sys_counter";
+
+/// A PTP hardware clock of the host, e.g. the one of a NIC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PtpClock {
+    /// The device name, e.g. `ptp0` for `/dev/ptp0`.
+    pub device: String,
+    /// The name the driver gives the clock.
+    pub name: String,
+}
+
+/// How well the kernel considers the system clock to be synchronized, as
+/// reported by adjtimex(2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KernelClockStatus {
+    pub synchronized: bool,
+    pub offset_ns: i64,
+    pub estimated_error_us: i64,
+    pub max_error_us: i64,
+}
+
+pub fn current_clocksource() -> io::Result<String> {
+    let source = fs::read_to_string(Path::new(CLOCKSOURCE_DIR).join("current_clocksource"))?;
+    Ok(source.trim().to_string())
+}
+
+pub fn available_clocksources() -> io::Result<Vec<String>> {
+    let sources = fs::read_to_string(Path::new(CLOCKSOURCE_DIR).join("available_clocksource"))?;
+    Ok(sources.split_whitespace().map(str::to_string).collect())
+}
+
+/// Switches the host to another of the available clocksources.
+pub fn set_clocksource(source: &str) -> io::Result<()> {
+    if !available_clocksources()?.iter().any(|s| s == source) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("clocksource '{source}' is not available"),
+        ));
+    }
+    fs::write(
+        Path::new(CLOCKSOURCE_DIR).join("current_clocksource"),
+        source,
+    )
+}
+
+pub fn ptp_clocks() -> io::Result<Vec<PtpClock>> {
+    ptp_clocks_in(Path::new(PTP_CLASS_DIR))
+}
+
+fn ptp_clocks_in(class_dir: &Path) -> io::Result<Vec<PtpClock>> {
+    let entries = match fs::read_dir(class_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut clocks = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = fs::read_to_string(entry.path().join("clock_name")).unwrap_or_default();
+        clocks.push(PtpClock {
+            device: entry.file_name().to_string_lossy().into_owned(),
+            name: name.trim().to_string(),
+        });
+    }
+    clocks.sort_by(|a, b| a.device.cmp(&b.device));
+    Ok(clocks)
+}
+
+/// Whether guests can synchronize their clock to the host with the ptp_kvm
+/// driver.
+pub fn ptp_kvm_supported() -> bool {
+    Path::new(KVM_DEVICE).exists()
+        && current_clocksource().is_ok_and(|source| source == PTP_KVM_CLOCKSOURCE)
+}
+
+pub fn kernel_clock_status() -> io::Result<KernelClockStatus> {
+    // SAFETY: a zeroed timex has no modes set, so adjtimex only reads the
+    // kernel clock state into it.
+    let mut timex: libc::timex = unsafe { std::mem::zeroed() };
+    let state = unsafe { libc::adjtimex(&mut timex) };
+    if state == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // The fields are C longs, 32 bit wide on some targets.
+    let offset = timex.offset as i64;
+    let offset_ns = if timex.status & libc::STA_NANO != 0 {
+        offset
+    } else {
+        offset * 1000
+    };
+    Ok(KernelClockStatus {
+        synchronized: state != libc::TIME_ERROR,
+        offset_ns,
+        estimated_error_us: timex.esterror as i64,
+        max_error_us: timex.maxerror as i64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ptp_clocks_are_listed_by_device() {
+        let dir = tempfile::tempdir().unwrap();
+        for (device, name) in [("ptp1", "mlx5_ptp\n"), ("ptp0", "ptp_kvm\n")] {
+            fs::create_dir(dir.path().join(device)).unwrap();
+            fs::write(dir.path().join(device).join("clock_name"), name).unwrap();
+        }
+        let clocks = ptp_clocks_in(dir.path()).unwrap();
+        assert_eq!(
+            clocks,
+            vec![
+                PtpClock {
+                    device: "ptp0".to_string(),
+                    name: "ptp_kvm".to_string(),
+                },
+                PtpClock {
+                    device: "ptp1".to_string(),
+                    name: "mlx5_ptp".to_string(),
+                },
+            ]
+        );
+        assert!(ptp_clocks_in(&dir.path().join("missing"))
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,335 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{host_root, PCI_DEVICES_DIR};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const NVIDIA_VENDOR_ID: &str = "0x10de";
+/// PCI class of display controllers, e.g. `0x030000` or `0x030200`.
+const DISPLAY_CLASS_PREFIX: &str = "0x03";
+
+/// A GPU of the host that can be partitioned into vGPUs, one per SR-IOV
+/// virtual function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gpu {
+    pub pci_address: String,
+    /// How many partitions the GPU can have at most.
+    pub max_partitions: u32,
+    /// The vGPU profiles a new partition can have. Profiles of MIG-backed
+    /// vGPUs are offered once MIG mode is enabled on the GPU.
+    pub creatable_profiles: Vec<String>,
+}
+
+/// A virtual function of a GPU set up as a vGPU, which is passed through to
+/// a VM like any other PCI device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GpuPartition {
+    pub pci_address: String,
+    pub gpu_address: String,
+    pub profile: String,
+}
+
+/// A vGPU type as listed by the NVIDIA driver, e.g. `557 : NVIDIA A100-4C`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct VgpuType {
+    id: u32,
+    name: String,
+}
+
+/// Parses the `ID : Name` table of `creatable_vgpu_types`. The header line
+/// and anything else without a numeric ID is skipped.
+fn parse_vgpu_types(content: &str) -> Vec<VgpuType> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (id, name) = line.split_once(':')?;
+            Some(VgpuType {
+                id: id.trim().parse().ok()?,
+                name: name.trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+fn read_trimmed(path: &Path) -> io::Result<String> {
+    Ok(fs::read_to_string(path)?.trim().to_string())
+}
+
+fn vgpu_types(vf: &Path, file: &str) -> Vec<VgpuType> {
+    fs::read_to_string(vf.join("nvidia").join(file))
+        .map(|content| parse_vgpu_types(&content))
+        .unwrap_or_default()
+}
+
+/// The ID of the vGPU type a virtual function is set up as, 0 if none.
+fn current_vgpu_type(vf: &Path) -> io::Result<u32> {
+    let current = read_trimmed(&vf.join("nvidia/current_vgpu_type"))?;
+    current.parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected current_vgpu_type '{current}'"),
+        )
+    })
+}
+
+fn is_partitionable_gpu(device: &Path) -> bool {
+    let vendor = read_trimmed(&device.join("vendor")).unwrap_or_default();
+    let class = read_trimmed(&device.join("class")).unwrap_or_default();
+    vendor == NVIDIA_VENDOR_ID
+        && class.starts_with(DISPLAY_CLASS_PREFIX)
+        && device.join("sriov_totalvfs").exists()
+}
+
+/// The PCI addresses and paths in `devices_dir` of the virtual functions of
+/// a GPU, in order.
+fn virtual_functions(devices_dir: &Path, gpu: &str) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut functions = Vec::new();
+    for entry in fs::read_dir(devices_dir.join(gpu))? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Some(index) = name
+            .strip_prefix("virtfn")
+            .and_then(|index| index.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        let target = fs::read_link(entry.path())?;
+        let Some(address) = target.file_name() else {
+            continue;
+        };
+        let address = address.to_string_lossy().into_owned();
+        functions.push((index, address));
+    }
+    functions.sort();
+    Ok(functions
+        .into_iter()
+        .map(|(_, address)| {
+            let path = devices_dir.join(&address);
+            (address, path)
+        })
+        .collect())
+}
+
+pub fn gpus() -> io::Result<Vec<Gpu>> {
+    gpus_in(&host_root().join(PCI_DEVICES_DIR))
+}
+
+fn gpus_in(devices_dir: &Path) -> io::Result<Vec<Gpu>> {
+    let entries = match fs::read_dir(devices_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut gpus = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if !is_partitionable_gpu(&entry.path()) {
+            continue;
+        }
+        let pci_address = entry.file_name().to_string_lossy().into_owned();
+        let max_partitions = read_trimmed(&entry.path().join("sriov_totalvfs"))?
+            .parse()
+            .unwrap_or(0);
+        let mut creatable_profiles = Vec::new();
+        for (_, vf) in virtual_functions(devices_dir, &pci_address)? {
+            for vgpu_type in vgpu_types(&vf, "creatable_vgpu_types") {
+                if !creatable_profiles.contains(&vgpu_type.name) {
+                    creatable_profiles.push(vgpu_type.name);
+                }
+            }
+        }
+        gpus.push(Gpu {
+            pci_address,
+            max_partitions,
+            creatable_profiles,
+        });
+    }
+    gpus.sort_by(|a, b| a.pci_address.cmp(&b.pci_address));
+    Ok(gpus)
+}
+
+pub fn partitions() -> io::Result<Vec<GpuPartition>> {
+    partitions_in(&host_root().join(PCI_DEVICES_DIR))
+}
+
+fn partitions_in(devices_dir: &Path) -> io::Result<Vec<GpuPartition>> {
+    let mut partitions = Vec::new();
+    for gpu in gpus_in(devices_dir)? {
+        for (pci_address, vf) in virtual_functions(devices_dir, &gpu.pci_address)? {
+            let id = current_vgpu_type(&vf)?;
+            if id == 0 {
+                continue;
+            }
+            // Once set up, the type is no longer creatable on the function.
+            let profile = vgpu_types(&vf, "supported_vgpu_types")
+                .into_iter()
+                .find(|vgpu_type| vgpu_type.id == id)
+                .map_or_else(|| id.to_string(), |vgpu_type| vgpu_type.name);
+            partitions.push(GpuPartition {
+                pci_address,
+                gpu_address: gpu.pci_address.clone(),
+                profile,
+            });
+        }
+    }
+    Ok(partitions)
+}
+
+/// Sets up a free virtual function of `gpu` as a vGPU of `profile`. The
+/// virtual functions of the GPU are enabled first if they are not yet.
+pub fn create_partition(gpu: &str, profile: &str) -> io::Result<GpuPartition> {
+    create_partition_in(&host_root().join(PCI_DEVICES_DIR), gpu, profile)
+}
+
+fn create_partition_in(devices_dir: &Path, gpu: &str, profile: &str) -> io::Result<GpuPartition> {
+    let device = devices_dir.join(gpu);
+    if !is_partitionable_gpu(&device) {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{gpu} is not a GPU with SR-IOV vGPU support"),
+        ));
+    }
+    if read_trimmed(&device.join("sriov_numvfs"))? == "0" {
+        let total = read_trimmed(&device.join("sriov_totalvfs"))?;
+        fs::write(device.join("sriov_numvfs"), total)?;
+    }
+
+    let mut offered = false;
+    for (pci_address, vf) in virtual_functions(devices_dir, gpu)? {
+        let Some(vgpu_type) = vgpu_types(&vf, "creatable_vgpu_types")
+            .into_iter()
+            .find(|vgpu_type| vgpu_type.name == profile)
+        else {
+            continue;
+        };
+        offered = true;
+        if current_vgpu_type(&vf)? != 0 {
+            continue;
+        }
+        fs::write(
+            vf.join("nvidia/current_vgpu_type"),
+            vgpu_type.id.to_string(),
+        )?;
+        return Ok(GpuPartition {
+            pci_address,
+            gpu_address: gpu.to_string(),
+            profile: vgpu_type.name,
+        });
+    }
+    let reason = if offered {
+        format!("{gpu} has no free virtual function left for profile '{profile}'")
+    } else {
+        format!("{gpu} cannot create vGPUs of profile '{profile}'")
+    };
+    Err(io::Error::new(io::ErrorKind::InvalidInput, reason))
+}
+
+/// Tears down the vGPU of a virtual function. It must not be in use by a VM.
+pub fn destroy_partition(pci_address: &str) -> io::Result<()> {
+    destroy_partition_in(&host_root().join(PCI_DEVICES_DIR), pci_address)
+}
+
+fn destroy_partition_in(devices_dir: &Path, pci_address: &str) -> io::Result<()> {
+    let vf = devices_dir.join(pci_address);
+    let is_partition = vf
+        .join("physfn")
+        .canonicalize()
+        .is_ok_and(|gpu| is_partitionable_gpu(&gpu))
+        && current_vgpu_type(&vf).is_ok_and(|id| id != 0);
+    if !is_partition {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{pci_address} is not a GPU partition"),
+        ));
+    }
+    fs::write(vf.join("nvidia/current_vgpu_type"), "0")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    const GPU: &str = "0000:41:00.0";
+    const CREATABLE: &str = "ID    : vGPU Name\n557   : NVIDIA A100-4C\n558   : NVIDIA A100-5C\n";
+
+    /// Builds a GPU with two virtual functions, the second of them set up
+    /// as an A100-5C.
+    fn fake_gpu(dir: &Path) {
+        let gpu = dir.join(GPU);
+        fs::create_dir_all(&gpu).unwrap();
+        fs::write(gpu.join("vendor"), "0x10de\n").unwrap();
+        fs::write(gpu.join("class"), "0x030200\n").unwrap();
+        fs::write(gpu.join("sriov_totalvfs"), "2\n").unwrap();
+        fs::write(gpu.join("sriov_numvfs"), "2\n").unwrap();
+        for (index, (vf, current)) in [("0000:41:00.4", "0"), ("0000:41:00.5", "558")]
+            .into_iter()
+            .enumerate()
+        {
+            let nvidia = dir.join(vf).join("nvidia");
+            fs::create_dir_all(&nvidia).unwrap();
+            fs::write(nvidia.join("creatable_vgpu_types"), CREATABLE).unwrap();
+            fs::write(nvidia.join("supported_vgpu_types"), CREATABLE).unwrap();
+            fs::write(nvidia.join("current_vgpu_type"), format!("{current}\n")).unwrap();
+            symlink(format!("../{vf}"), gpu.join(format!("virtfn{index}"))).unwrap();
+            symlink(format!("../{GPU}"), dir.join(vf).join("physfn")).unwrap();
+        }
+    }
+
+    #[test]
+    fn vgpu_types_are_parsed_from_the_driver_table() {
+        assert_eq!(
+            parse_vgpu_types(CREATABLE),
+            vec![
+                VgpuType {
+                    id: 557,
+                    name: "NVIDIA A100-4C".to_string()
+                },
+                VgpuType {
+                    id: 558,
+                    name: "NVIDIA A100-5C".to_string()
+                },
+            ]
+        );
+        assert!(parse_vgpu_types("").is_empty());
+    }
+
+    #[test]
+    fn partitions_are_created_on_free_virtual_functions() {
+        let dir = tempfile::tempdir().unwrap();
+        fake_gpu(dir.path());
+
+        let gpus = gpus_in(dir.path()).unwrap();
+        assert_eq!(gpus.len(), 1);
+        assert_eq!(gpus[0].max_partitions, 2);
+        assert_eq!(
+            gpus[0].creatable_profiles,
+            vec!["NVIDIA A100-4C", "NVIDIA A100-5C"]
+        );
+        assert_eq!(
+            partitions_in(dir.path()).unwrap(),
+            vec![GpuPartition {
+                pci_address: "0000:41:00.5".to_string(),
+                gpu_address: GPU.to_string(),
+                profile: "NVIDIA A100-5C".to_string(),
+            }]
+        );
+
+        let partition = create_partition_in(dir.path(), GPU, "NVIDIA A100-4C").unwrap();
+        assert_eq!(partition.pci_address, "0000:41:00.4");
+        assert_eq!(
+            read_trimmed(&dir.path().join("0000:41:00.4/nvidia/current_vgpu_type")).unwrap(),
+            "557"
+        );
+        let err = create_partition_in(dir.path(), GPU, "NVIDIA A100-4C").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(create_partition_in(dir.path(), GPU, "NVIDIA H100-1C").is_err());
+
+        destroy_partition_in(dir.path(), "0000:41:00.4").unwrap();
+        assert_eq!(partitions_in(dir.path()).unwrap().len(), 1);
+        assert!(destroy_partition_in(dir.path(), "0000:41:00.4").is_err());
+        assert!(destroy_partition_in(dir.path(), GPU).is_err());
+    }
+}
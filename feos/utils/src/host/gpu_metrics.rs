@@ -0,0 +1,223 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Usage of the GPUs the host drivers manage, read with the management tools
+//! of the vendors, `nvidia-smi` (NVML) and `amd-smi`. GPUs passed through
+//! whole are bound to vfio-pci and not visible to the tools, but the GPUs
+//! that partitions are created on are.
+
+use log::warn;
+use serde_json::Value;
+use std::io;
+use tokio::process::Command;
+
+const NVIDIA_SMI_BIN: &str = "nvidia-smi";
+const AMD_SMI_BIN: &str = "amd-smi";
+const NVIDIA_QUERY: &str =
+    "--query-gpu=pci.bus_id,utilization.gpu,memory.used,memory.total,temperature.gpu";
+const MIB: u64 = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GpuVendor {
+    Nvidia,
+    Amd,
+}
+
+/// A sample of a GPU. Values the tool reports as not available are `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GpuMetrics {
+    /// e.g. `0000:41:00.0`
+    pub pci_address: String,
+    pub vendor: GpuVendor,
+    pub utilization_percent: Option<u32>,
+    pub memory_used_bytes: Option<u64>,
+    pub memory_total_bytes: Option<u64>,
+    pub temperature_celsius: Option<u32>,
+}
+
+/// Turns the PCI addresses of the tools, e.g. `00000000:41:00.0`, into the
+/// form sysfs uses.
+fn normalize_pci_address(address: &str) -> String {
+    let address = address.trim().to_ascii_lowercase();
+    match address.split_once(':') {
+        Some((domain, rest)) if domain.len() > 4 => {
+            format!("{}:{rest}", &domain[domain.len() - 4..])
+        }
+        _ => address,
+    }
+}
+
+/// Parses the CSV `nvidia-smi` prints for `NVIDIA_QUERY` without header and
+/// units. Memory is reported in MiB.
+fn parse_nvidia_smi(csv: &str) -> Vec<GpuMetrics> {
+    csv.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let [address, utilization, used, total, temperature] = fields[..] else {
+                return None;
+            };
+            Some(GpuMetrics {
+                pci_address: normalize_pci_address(address),
+                vendor: GpuVendor::Nvidia,
+                utilization_percent: utilization.parse().ok(),
+                memory_used_bytes: used.parse::<u64>().ok().map(|mib| mib * MIB),
+                memory_total_bytes: total.parse::<u64>().ok().map(|mib| mib * MIB),
+                temperature_celsius: temperature.parse().ok(),
+            })
+        })
+        .collect()
+}
+
+/// A value of `amd-smi`, which is either a plain number or an object with
+/// the number and its unit, depending on the version.
+fn amd_value(value: &Value) -> Option<u64> {
+    let value = value.get("value").unwrap_or(value);
+    value
+        .as_u64()
+        .or_else(|| value.as_f64().map(|value| value as u64))
+}
+
+fn amd_bytes(value: &Value) -> Option<u64> {
+    let factor = match value.get("unit").and_then(Value::as_str) {
+        Some("B") => 1,
+        Some("KB") => 1 << 10,
+        Some("GB") => 1 << 30,
+        _ => MIB,
+    };
+    amd_value(value).map(|value| value * factor)
+}
+
+/// Parses `amd-smi metric --json`, taking the PCI addresses of the GPUs
+/// from `amd-smi list --json`.
+fn parse_amd_smi(metrics: &str, list: &str) -> Vec<GpuMetrics> {
+    let (Ok(Value::Array(metrics)), Ok(Value::Array(list))) = (
+        serde_json::from_str::<Value>(metrics),
+        serde_json::from_str::<Value>(list),
+    ) else {
+        return Vec::new();
+    };
+    metrics
+        .iter()
+        .filter_map(|gpu| {
+            let index = gpu.get("gpu")?;
+            let address = list
+                .iter()
+                .find(|entry| entry.get("gpu") == Some(index))?
+                .get("bdf")?
+                .as_str()?;
+            let usage = &gpu["usage"];
+            let memory = &gpu["mem_usage"];
+            let temperature = &gpu["temperature"];
+            Some(GpuMetrics {
+                pci_address: normalize_pci_address(address),
+                vendor: GpuVendor::Amd,
+                utilization_percent: amd_value(&usage["gfx_activity"]).map(|v| v as u32),
+                memory_used_bytes: amd_bytes(&memory["used_vram"]),
+                memory_total_bytes: amd_bytes(&memory["total_vram"]),
+                temperature_celsius: amd_value(&temperature["edge"])
+                    .or_else(|| amd_value(&temperature["hotspot"]))
+                    .map(|v| v as u32),
+            })
+        })
+        .collect()
+}
+
+/// The standard output of a tool, none if the tool is not installed.
+async fn run(bin: &str, args: &[&str]) -> io::Result<Option<String>> {
+    let output = match Command::new(bin).args(args).output().await {
+        Ok(output) => output,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "{bin} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()))
+}
+
+/// Samples all GPUs the installed tools see. A tool that fails is logged
+/// and its GPUs are left out.
+pub async fn collect() -> Vec<GpuMetrics> {
+    let mut metrics = Vec::new();
+    match run(
+        NVIDIA_SMI_BIN,
+        &[NVIDIA_QUERY, "--format=csv,noheader,nounits"],
+    )
+    .await
+    {
+        Ok(Some(csv)) => metrics.extend(parse_nvidia_smi(&csv)),
+        Ok(None) => {}
+        Err(e) => warn!("GpuMetrics: {e}"),
+    }
+    let amd = async {
+        let Some(list) = run(AMD_SMI_BIN, &["list", "--json"]).await? else {
+            return Ok(None);
+        };
+        let args = [
+            "metric",
+            "--usage",
+            "--mem-usage",
+            "--temperature",
+            "--json",
+        ];
+        Ok::<_, io::Error>(run(AMD_SMI_BIN, &args).await?.map(|m| (m, list)))
+    };
+    match amd.await {
+        Ok(Some((samples, list))) => metrics.extend(parse_amd_smi(&samples, &list)),
+        Ok(None) => {}
+        Err(e) => warn!("GpuMetrics: {e}"),
+    }
+    metrics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nvidia_smi_csv_is_parsed() {
+        let csv = "00000000:41:00.0, 37, 10240, 40960, 54\n00000000:C1:00.0, [N/A], 0, 40960, 31\n";
+        let metrics = parse_nvidia_smi(csv);
+        assert_eq!(
+            metrics[0],
+            GpuMetrics {
+                pci_address: "0000:41:00.0".to_string(),
+                vendor: GpuVendor::Nvidia,
+                utilization_percent: Some(37),
+                memory_used_bytes: Some(10 << 30),
+                memory_total_bytes: Some(40 << 30),
+                temperature_celsius: Some(54),
+            }
+        );
+        assert_eq!(metrics[1].pci_address, "0000:c1:00.0");
+        assert_eq!(metrics[1].utilization_percent, None);
+    }
+
+    #[test]
+    fn amd_smi_json_is_parsed() {
+        let list = r#"[{"gpu": 0, "bdf": "0000:0c:00.0", "uuid": "x"}]"#;
+        let metrics = r#"[{
+            "gpu": 0,
+            "usage": {"gfx_activity": {"value": 12, "unit": "%"}},
+            "mem_usage": {
+                "total_vram": {"value": 196592, "unit": "MB"},
+                "used_vram": {"value": 283, "unit": "MB"}
+            },
+            "temperature": {"edge": "N/A", "hotspot": {"value": 41, "unit": "C"}}
+        }]"#;
+        assert_eq!(
+            parse_amd_smi(metrics, list),
+            [GpuMetrics {
+                pci_address: "0000:0c:00.0".to_string(),
+                vendor: GpuVendor::Amd,
+                utilization_percent: Some(12),
+                memory_used_bytes: Some(283 * MIB),
+                memory_total_bytes: Some(196592 * MIB),
+                temperature_celsius: Some(41),
+            }]
+        );
+    }
+}
@@ -26,6 +26,16 @@ pub struct Interface {
     pub mac_address: Option<String>,
 }
 
+/// The architecture of the host as OCI images name it, e.g. `amd64` or
+/// `arm64`.
+pub fn oci_architecture() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        arch => arch,
+    }
+}
+
 fn get_pci_address(interface_name: &str) -> Option<String> {
     let path = format!("/sys/class/net/{interface_name}/device");
     if let Ok(device_path) = fs::read_link(path) {
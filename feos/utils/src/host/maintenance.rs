@@ -0,0 +1,231 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::host::admission::WorkloadKind;
+use log::{info, warn};
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+const DRAIN_EVENT_CAPACITY: usize = 32;
+
+/// Returned to creates while the host is cordoned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cordoned(pub WorkloadKind);
+
+impl fmt::Display for Cordoned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "host is cordoned for maintenance and does not accept new {} workloads",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for Cordoned {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrainInProgress;
+
+impl fmt::Display for DrainInProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the host is already being drained")
+    }
+}
+
+impl std::error::Error for DrainInProgress {}
+
+/// What happened to a workload during a drain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrainOutcome {
+    /// The workload shut down within the grace period.
+    Stopped,
+    /// The workload did not shut down in time and was stopped forcibly.
+    ForceStopped,
+    /// The workload asked to be left running.
+    Kept,
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DrainedWorkload {
+    pub kind: WorkloadKind,
+    pub id: String,
+    pub outcome: DrainOutcome,
+    pub message: String,
+}
+
+/// Reported by the services draining their workloads.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DrainEvent {
+    /// The number of workloads a service is about to drain, sent before any
+    /// of them is `Drained`.
+    Planned(usize),
+    Drained(DrainedWorkload),
+}
+
+/// Asks a service to drain its workloads. The service is done once it drops
+/// `events`.
+#[derive(Debug)]
+pub struct DrainJob {
+    /// How long workloads may take to shut down before they are stopped
+    /// forcibly.
+    pub grace_period: Duration,
+    pub events: mpsc::Sender<DrainEvent>,
+}
+
+#[derive(Default)]
+struct State {
+    cordoned: bool,
+    draining: bool,
+    drainers: Vec<mpsc::Sender<DrainJob>>,
+}
+
+/// Whether the host takes new workloads, and the services that drain the
+/// existing ones for host maintenance. It is shared by the host service and
+/// all services creating workloads.
+#[derive(Clone, Default)]
+pub struct Maintenance {
+    state: Arc<Mutex<State>>,
+}
+
+impl Maintenance {
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Registers a service whose workloads are drained. It receives a
+    /// `DrainJob` on every drain.
+    pub fn register_drainer(&self) -> mpsc::Receiver<DrainJob> {
+        let (tx, rx) = mpsc::channel(1);
+        self.state().drainers.push(tx);
+        rx
+    }
+
+    pub fn is_cordoned(&self) -> bool {
+        self.state().cordoned
+    }
+
+    /// Fails if the host does not accept new workloads.
+    pub fn check_schedulable(&self, kind: WorkloadKind) -> Result<(), Cordoned> {
+        if self.is_cordoned() {
+            return Err(Cordoned(kind));
+        }
+        Ok(())
+    }
+
+    pub fn cordon(&self) {
+        let mut state = self.state();
+        if !state.cordoned {
+            info!("Maintenance: Host cordoned, new workloads are rejected.");
+            state.cordoned = true;
+        }
+    }
+
+    pub fn uncordon(&self) {
+        let mut state = self.state();
+        if state.cordoned {
+            info!("Maintenance: Host uncordoned, new workloads are accepted again.");
+            state.cordoned = false;
+        }
+    }
+
+    /// Cordons the host and has every registered service drain its
+    /// workloads. The events of all services arrive on the returned `Drain`,
+    /// which ends once all are done.
+    pub fn drain(&self, grace_period: Duration) -> Result<Drain, DrainInProgress> {
+        let drainers = {
+            let mut state = self.state();
+            if state.draining {
+                return Err(DrainInProgress);
+            }
+            state.draining = true;
+            state.drainers.clone()
+        };
+        self.cordon();
+
+        let (events_tx, events) = mpsc::channel(DRAIN_EVENT_CAPACITY);
+        for drainer in drainers {
+            let job = DrainJob {
+                grace_period,
+                events: events_tx.clone(),
+            };
+            if drainer.try_send(job).is_err() {
+                warn!("Maintenance: A service is not ready to drain its workloads.");
+            }
+        }
+        Ok(Drain {
+            maintenance: self.clone(),
+            events,
+        })
+    }
+}
+
+/// A drain in progress. Dropping it allows the next drain, the host stays
+/// cordoned until `Maintenance::uncordon`.
+pub struct Drain {
+    maintenance: Maintenance,
+    events: mpsc::Receiver<DrainEvent>,
+}
+
+impl Drain {
+    /// The next event of any service, `None` once all services are done.
+    pub async fn next_event(&mut self) -> Option<DrainEvent> {
+        self.events.recv().await
+    }
+}
+
+impl Drop for Drain {
+    fn drop(&mut self) {
+        self.maintenance.state().draining = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn drains_cordon_the_host_and_collect_all_services() {
+        let maintenance = Maintenance::default();
+        let mut vms = maintenance.register_drainer();
+        let mut containers = maintenance.register_drainer();
+        assert!(maintenance.check_schedulable(WorkloadKind::Vm).is_ok());
+
+        let mut drain = maintenance.drain(Duration::from_secs(30)).unwrap();
+        assert_eq!(
+            maintenance.check_schedulable(WorkloadKind::Container),
+            Err(Cordoned(WorkloadKind::Container))
+        );
+        assert!(maintenance.drain(Duration::from_secs(30)).is_err());
+
+        let vm_job = vms.recv().await.unwrap();
+        assert_eq!(vm_job.grace_period, Duration::from_secs(30));
+        vm_job.events.send(DrainEvent::Planned(1)).await.unwrap();
+        let drained = DrainedWorkload {
+            kind: WorkloadKind::Vm,
+            id: "vm".to_string(),
+            outcome: DrainOutcome::Stopped,
+            message: String::new(),
+        };
+        vm_job
+            .events
+            .send(DrainEvent::Drained(drained.clone()))
+            .await
+            .unwrap();
+        drop(vm_job);
+        drop(containers.recv().await.unwrap());
+
+        assert_eq!(drain.next_event().await, Some(DrainEvent::Planned(1)));
+        assert_eq!(drain.next_event().await, Some(DrainEvent::Drained(drained)));
+        assert_eq!(drain.next_event().await, None);
+
+        drop(drain);
+        assert!(maintenance.is_cordoned());
+        maintenance.uncordon();
+        assert!(maintenance.check_schedulable(WorkloadKind::Vm).is_ok());
+        assert!(maintenance.drain(Duration::from_secs(30)).is_ok());
+    }
+}
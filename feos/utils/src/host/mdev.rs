@@ -0,0 +1,334 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{host_root, MDEV_BUS_DIR, MDEV_DEVICES_DIR};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The mdevs created through FeOS as `<uuid> <parent> <type>` lines, so they
+/// are recreated after a reboot of the host. Relative to the root directory
+/// like the sysfs paths.
+const MDEV_STATE_FILE: &str = "var/lib/feos/mdevs";
+
+/// A type of mediated device a parent device offers, e.g. a vGPU profile or
+/// a vfio-ap matrix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MdevType {
+    /// Address of the parent device, e.g. `0000:3b:00.0` or `matrix`.
+    pub parent: String,
+    /// ID of the type, e.g. `nvidia-63`.
+    pub type_id: String,
+    pub name: String,
+    /// The VFIO device API of the type, e.g. `vfio-pci` or `vfio-ap`.
+    pub device_api: String,
+    pub available_instances: u32,
+    pub description: String,
+}
+
+/// A mediated device, which is passed through to a VM by its UUID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mdev {
+    pub uuid: String,
+    pub parent: String,
+    pub type_id: String,
+}
+
+fn read_trimmed(path: &Path) -> io::Result<String> {
+    Ok(fs::read_to_string(path)?.trim().to_string())
+}
+
+fn read_dir_names(dir: &Path) -> io::Result<Vec<String>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut names = entries
+        .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+        .collect::<io::Result<Vec<_>>>()?;
+    names.sort();
+    Ok(names)
+}
+
+fn type_dir(root: &Path, parent: &str, type_id: &str) -> PathBuf {
+    root.join(MDEV_BUS_DIR)
+        .join(parent)
+        .join("mdev_supported_types")
+        .join(type_id)
+}
+
+/// Checks for the canonical `8-4-4-4-12` hex form the kernel expects.
+fn validate_uuid(uuid: &str) -> io::Result<()> {
+    let groups: Vec<&str> = uuid.split('-').collect();
+    let valid = groups.iter().map(|group| group.len()).eq([8, 4, 4, 4, 12])
+        && groups
+            .iter()
+            .all(|group| group.chars().all(|c| c.is_ascii_hexdigit()));
+    if !valid {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("'{uuid}' is not a UUID"),
+        ));
+    }
+    Ok(())
+}
+
+pub fn mdev_types() -> io::Result<Vec<MdevType>> {
+    mdev_types_in(host_root())
+}
+
+fn mdev_types_in(root: &Path) -> io::Result<Vec<MdevType>> {
+    let mut types = Vec::new();
+    for parent in read_dir_names(&root.join(MDEV_BUS_DIR))? {
+        let types_dir = root
+            .join(MDEV_BUS_DIR)
+            .join(&parent)
+            .join("mdev_supported_types");
+        for type_id in read_dir_names(&types_dir)? {
+            let dir = types_dir.join(&type_id);
+            types.push(MdevType {
+                name: read_trimmed(&dir.join("name")).unwrap_or_default(),
+                device_api: read_trimmed(&dir.join("device_api"))?,
+                available_instances: read_trimmed(&dir.join("available_instances"))?
+                    .parse()
+                    .unwrap_or(0),
+                description: read_trimmed(&dir.join("description")).unwrap_or_default(),
+                parent: parent.clone(),
+                type_id,
+            });
+        }
+    }
+    Ok(types)
+}
+
+pub fn mdevs() -> io::Result<Vec<Mdev>> {
+    mdevs_in(host_root())
+}
+
+fn mdevs_in(root: &Path) -> io::Result<Vec<Mdev>> {
+    let devices_dir = root.join(MDEV_DEVICES_DIR);
+    let mut mdevs = Vec::new();
+    for uuid in read_dir_names(&devices_dir)? {
+        // The type links to `<parent>/mdev_supported_types/<type>`.
+        let type_path = fs::canonicalize(devices_dir.join(&uuid).join("mdev_type"))?;
+        let name = |path: Option<&Path>| {
+            path.and_then(Path::file_name)
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+        mdevs.push(Mdev {
+            parent: name(type_path.ancestors().nth(2)),
+            type_id: name(Some(&type_path)),
+            uuid,
+        });
+    }
+    Ok(mdevs)
+}
+
+fn read_state(root: &Path) -> io::Result<Vec<Mdev>> {
+    let content = match fs::read_to_string(root.join(MDEV_STATE_FILE)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    Ok(content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            Some(Mdev {
+                uuid: fields.next()?.to_string(),
+                parent: fields.next()?.to_string(),
+                type_id: fields.next()?.to_string(),
+            })
+        })
+        .collect())
+}
+
+fn write_state(root: &Path, mdevs: &[Mdev]) -> io::Result<()> {
+    let path = root.join(MDEV_STATE_FILE);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let content: String = mdevs
+        .iter()
+        .map(|mdev| format!("{} {} {}\n", mdev.uuid, mdev.parent, mdev.type_id))
+        .collect();
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, content)?;
+    fs::rename(temp_path, path)
+}
+
+fn create_in_sysfs(root: &Path, mdev: &Mdev) -> io::Result<()> {
+    let dir = type_dir(root, &mdev.parent, &mdev.type_id);
+    if !dir.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} offers no mdev type {}", mdev.parent, mdev.type_id),
+        ));
+    }
+    if read_trimmed(&dir.join("available_instances"))? == "0" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} has no instances of mdev type {} left",
+                mdev.parent, mdev.type_id
+            ),
+        ));
+    }
+    fs::write(dir.join("create"), &mdev.uuid)
+}
+
+/// Creates an mdev of a type offered by `parent` and records it, so it is
+/// recreated by `recreate_mdevs` after a reboot.
+pub fn create_mdev(parent: &str, type_id: &str, uuid: &str) -> io::Result<Mdev> {
+    create_mdev_in(host_root(), parent, type_id, uuid)
+}
+
+fn create_mdev_in(root: &Path, parent: &str, type_id: &str, uuid: &str) -> io::Result<Mdev> {
+    validate_uuid(uuid)?;
+    if root.join(MDEV_DEVICES_DIR).join(uuid).exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("mdev {uuid} already exists"),
+        ));
+    }
+    let mdev = Mdev {
+        uuid: uuid.to_string(),
+        parent: parent.to_string(),
+        type_id: type_id.to_string(),
+    };
+    create_in_sysfs(root, &mdev)?;
+    let mut state = read_state(root)?;
+    state.retain(|recorded| recorded.uuid != mdev.uuid);
+    state.push(mdev.clone());
+    write_state(root, &state)?;
+    Ok(mdev)
+}
+
+/// Removes an mdev and forgets it. It must not be in use by a VM.
+pub fn remove_mdev(uuid: &str) -> io::Result<()> {
+    remove_mdev_in(host_root(), uuid)
+}
+
+fn remove_mdev_in(root: &Path, uuid: &str) -> io::Result<()> {
+    validate_uuid(uuid)?;
+    let mut state = read_state(root)?;
+    let recorded = state.len();
+    state.retain(|mdev| mdev.uuid != uuid);
+    let device = root.join(MDEV_DEVICES_DIR).join(uuid);
+    if device.exists() {
+        fs::write(device.join("remove"), "1")?;
+    } else if state.len() == recorded {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("mdev {uuid} not found"),
+        ));
+    }
+    write_state(root, &state)
+}
+
+/// Creates the recorded mdevs that do not exist, e.g. after a reboot of the
+/// host. Returns the mdevs that could not be created with the reason.
+pub fn recreate_mdevs() -> io::Result<Vec<(Mdev, io::Error)>> {
+    recreate_mdevs_in(host_root())
+}
+
+fn recreate_mdevs_in(root: &Path) -> io::Result<Vec<(Mdev, io::Error)>> {
+    let mut failed = Vec::new();
+    for mdev in read_state(root)? {
+        if root.join(MDEV_DEVICES_DIR).join(&mdev.uuid).exists() {
+            continue;
+        }
+        if let Err(e) = create_in_sysfs(root, &mdev) {
+            failed.push((mdev, e));
+        }
+    }
+    Ok(failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host::FakeRoot;
+
+    const PARENT: &str = "0000:3b:00.0";
+    const UUID: &str = "83b8f4f2-509f-382f-3c1e-e6bfe0fa1001";
+
+    fn fake_type(root: &FakeRoot, available_instances: u32) -> PathBuf {
+        let dir = type_dir(root.path(), PARENT, "nvidia-63");
+        root.write(dir.join("name"), "GRID T4-1Q\n")
+            .write(dir.join("device_api"), "vfio-pci\n")
+            .write(
+                dir.join("available_instances"),
+                format!("{available_instances}\n"),
+            );
+        dir
+    }
+
+    /// Does what the kernel does when the UUID is written to `create`.
+    fn fake_mdev(root: &FakeRoot, type_dir: &Path, uuid: &str) {
+        root.link(
+            Path::new(MDEV_DEVICES_DIR).join(uuid).join("mdev_type"),
+            type_dir,
+        );
+    }
+
+    #[test]
+    fn mdevs_are_recorded_and_recreated() {
+        let fake = FakeRoot::new();
+        let root = fake.path();
+        let dir = fake_type(&fake, 4);
+
+        let types = mdev_types_in(root).unwrap();
+        assert_eq!(types.len(), 1);
+        assert_eq!(types[0].parent, PARENT);
+        assert_eq!(types[0].name, "GRID T4-1Q");
+        assert_eq!(types[0].available_instances, 4);
+
+        create_mdev_in(root, PARENT, "nvidia-63", UUID).unwrap();
+        assert_eq!(read_trimmed(&dir.join("create")).unwrap(), UUID);
+        fake_mdev(&fake, &dir, UUID);
+        assert_eq!(
+            mdevs_in(root).unwrap(),
+            vec![Mdev {
+                uuid: UUID.to_string(),
+                parent: PARENT.to_string(),
+                type_id: "nvidia-63".to_string(),
+            }]
+        );
+        let err = create_mdev_in(root, PARENT, "nvidia-63", UUID).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert!(recreate_mdevs_in(root).unwrap().is_empty());
+
+        // After a reboot, the mdev is gone but still recorded.
+        fs::remove_dir_all(root.join(MDEV_DEVICES_DIR).join(UUID)).unwrap();
+        fs::remove_file(dir.join("create")).unwrap();
+        assert!(recreate_mdevs_in(root).unwrap().is_empty());
+        assert_eq!(read_trimmed(&dir.join("create")).unwrap(), UUID);
+        fake_mdev(&fake, &dir, UUID);
+
+        remove_mdev_in(root, UUID).unwrap();
+        let device = root.join(MDEV_DEVICES_DIR).join(UUID);
+        assert_eq!(read_trimmed(&device.join("remove")).unwrap(), "1");
+        assert!(read_state(root).unwrap().is_empty());
+        fs::remove_dir_all(device).unwrap();
+        let err = remove_mdev_in(root, UUID).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn mdevs_are_only_created_from_available_types() {
+        let fake = FakeRoot::new();
+        let root = fake.path();
+        fake_type(&fake, 0);
+
+        let err = create_mdev_in(root, PARENT, "nvidia-63", UUID).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = create_mdev_in(root, PARENT, "nvidia-64", UUID).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        let err = create_mdev_in(root, PARENT, "nvidia-63", "not-a-uuid").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(read_state(root).unwrap().is_empty());
+    }
+}
@@ -1,6 +1,84 @@
 // SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
 // SPDX-License-Identifier: Apache-2.0
 
+pub mod admission;
+pub mod clock;
+pub mod gpu;
+pub mod gpu_metrics;
 pub mod info;
+pub mod maintenance;
+pub mod mdev;
 pub mod memory;
+pub mod nic;
+pub mod numa;
+pub mod nvme;
+pub mod pci;
 pub mod power;
+pub mod primary;
+pub mod process;
+pub mod serial;
+pub mod startup;
+
+use std::path::Path;
+
+// Paths of sysfs and procfs, relative to the root directory so tests can use
+// a fake one.
+const NET_CLASS_DIR: &str = "sys/class/net";
+const NODE_DIR: &str = "sys/devices/system/node";
+const CPU_DIR: &str = "sys/devices/system/cpu";
+const PCI_DEVICES_DIR: &str = "sys/bus/pci/devices";
+const PCI_DRIVERS_DIR: &str = "sys/bus/pci/drivers";
+const PCI_DRIVERS_PROBE: &str = "sys/bus/pci/drivers_probe";
+const MDEV_BUS_DIR: &str = "sys/class/mdev_bus";
+const MDEV_DEVICES_DIR: &str = "sys/bus/mdev/devices";
+const NVME_CLASS_DIR: &str = "sys/class/nvme";
+const IRQ_DIR: &str = "proc/irq";
+const DEV_DIR: &str = "dev";
+
+/// The root directory of the host, which the paths above are relative to.
+fn host_root() -> &'static Path {
+    Path::new("/")
+}
+
+/// A fake root directory for tests, with the sysfs files they need.
+#[cfg(test)]
+struct FakeRoot {
+    dir: tempfile::TempDir,
+}
+
+#[cfg(test)]
+impl FakeRoot {
+    fn new() -> Self {
+        Self {
+            dir: tempfile::tempdir().unwrap(),
+        }
+    }
+
+    fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Writes a file, creating the directories it is in.
+    fn write(&self, path: impl AsRef<Path>, content: impl AsRef<[u8]>) -> &Self {
+        let path = self.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+        self
+    }
+
+    fn dir(&self, path: impl AsRef<Path>) -> &Self {
+        std::fs::create_dir_all(self.path().join(path)).unwrap();
+        self
+    }
+
+    /// Links `path` to `target`, both within the root, as sysfs links
+    /// devices to their drivers and groups.
+    fn link(&self, path: impl AsRef<Path>, target: impl AsRef<Path>) -> &Self {
+        let path = self.path().join(path);
+        let target = self.path().join(target);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::create_dir_all(&target).unwrap();
+        std::os::unix::fs::symlink(target, path).unwrap();
+        self
+    }
+}
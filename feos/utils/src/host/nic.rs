@@ -0,0 +1,428 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{host_root, CPU_DIR, IRQ_DIR, NET_CLASS_DIR};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::path::{Path, PathBuf};
+
+const ETHTOOL_GCHANNELS: u32 = 0x3c;
+const ETHTOOL_SCHANNELS: u32 = 0x3d;
+
+/// `struct ethtool_channels` of `linux/ethtool.h`.
+#[repr(C)]
+#[derive(Default)]
+struct EthtoolChannels {
+    cmd: u32,
+    max_rx: u32,
+    max_tx: u32,
+    max_other: u32,
+    max_combined: u32,
+    rx_count: u32,
+    tx_count: u32,
+    other_count: u32,
+    combined_count: u32,
+}
+
+/// Queue counts of a NIC. RSS spreads received packets over the rx and
+/// combined queues.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueueCounts {
+    pub rx: u32,
+    pub tx: u32,
+    pub other: u32,
+    pub combined: u32,
+}
+
+/// The queues a NIC has and the most it supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NicQueues {
+    pub current: QueueCounts,
+    pub max: QueueCounts,
+}
+
+/// An interrupt of a NIC and the CPUs it is delivered to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NicIrq {
+    pub irq: u32,
+    pub cpus: Vec<u32>,
+}
+
+/// The RSS queues and interrupts of a NIC backed by a device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NicTuning {
+    pub interface: String,
+    /// `None` if the driver cannot report its queues.
+    pub queues: Option<NicQueues>,
+    pub irqs: Vec<NicIrq>,
+    /// The CPUs of the NUMA node the NIC is attached to.
+    pub local_cpus: Vec<u32>,
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// Parses a kernel CPU list like `0-3,8,10-11`.
+pub fn parse_cpu_list(list: &str) -> io::Result<Vec<u32>> {
+    let invalid = || invalid_input(format!("'{list}' is not a CPU list"));
+    let mut cpus = BTreeSet::new();
+    for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+        let (first, last) = range.split_once('-').unwrap_or((range, range));
+        let first: u32 = first.trim().parse().map_err(|_| invalid())?;
+        let last: u32 = last.trim().parse().map_err(|_| invalid())?;
+        if first > last {
+            return Err(invalid());
+        }
+        cpus.extend(first..=last);
+    }
+    Ok(cpus.into_iter().collect())
+}
+
+/// Formats sorted CPUs as a kernel CPU list like `0-3,8`.
+pub fn format_cpu_list(cpus: &[u32]) -> String {
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for &cpu in cpus {
+        match ranges.last_mut() {
+            Some((_, last)) if *last + 1 == cpu => *last = cpu,
+            _ => ranges.push((cpu, cpu)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|&(first, last)| {
+            if first == last {
+                first.to_string()
+            } else {
+                format!("{first}-{last}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn read_cpu_list(path: &Path) -> io::Result<Vec<u32>> {
+    match fs::read_to_string(path) {
+        Ok(list) => parse_cpu_list(&list),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// CPUs set aside for workloads with `isolcpus` or `nohz_full`, which should
+/// not serve interrupts.
+pub fn isolated_cpus() -> io::Result<Vec<u32>> {
+    isolated_cpus_in(host_root())
+}
+
+fn isolated_cpus_in(root: &Path) -> io::Result<Vec<u32>> {
+    let cpu_dir = root.join(CPU_DIR);
+    let mut cpus: BTreeSet<u32> = read_cpu_list(&cpu_dir.join("isolated"))?
+        .into_iter()
+        .collect();
+    cpus.extend(read_cpu_list(&cpu_dir.join("nohz_full"))?);
+    Ok(cpus.into_iter().collect())
+}
+
+fn device_dir(root: &Path, interface: &str) -> io::Result<PathBuf> {
+    if interface.is_empty()
+        || interface.len() >= libc::IFNAMSIZ
+        || interface.contains('/')
+        || interface.starts_with('.')
+    {
+        return Err(invalid_input(format!(
+            "'{interface}' is not an interface name"
+        )));
+    }
+    let interface_dir = root.join(NET_CLASS_DIR).join(interface);
+    if !interface_dir.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Interface '{interface}' not found"),
+        ));
+    }
+    let device_dir = interface_dir.join("device");
+    if !device_dir.exists() {
+        return Err(invalid_input(format!(
+            "Interface '{interface}' is not backed by a device"
+        )));
+    }
+    Ok(device_dir)
+}
+
+fn nic_irqs_in(root: &Path, interface: &str) -> io::Result<Vec<NicIrq>> {
+    let msi_dir = device_dir(root, interface)?.join("msi_irqs");
+    let entries = match fs::read_dir(&msi_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut irqs = Vec::new();
+    for entry in entries {
+        let Ok(irq) = entry?.file_name().to_string_lossy().parse::<u32>() else {
+            continue;
+        };
+        let cpus = read_cpu_list(
+            &root
+                .join(IRQ_DIR)
+                .join(irq.to_string())
+                .join("smp_affinity_list"),
+        )?;
+        irqs.push(NicIrq { irq, cpus });
+    }
+    irqs.sort_by_key(|irq| irq.irq);
+    Ok(irqs)
+}
+
+fn ethtool_socket() -> io::Result<OwnedFd> {
+    nix::sys::socket::socket(
+        nix::sys::socket::AddressFamily::Inet,
+        nix::sys::socket::SockType::Datagram,
+        nix::sys::socket::SockFlag::SOCK_CLOEXEC,
+        None,
+    )
+    .map_err(io::Error::from)
+}
+
+fn ethtool_channels(interface: &str, channels: &mut EthtoolChannels) -> io::Result<()> {
+    let socket = ethtool_socket()?;
+    // SAFETY: ifreq is plain old data, for which all zeroes are valid.
+    let mut ifr: libc::ifreq = unsafe { std::mem::zeroed() };
+    for (dst, src) in ifr.ifr_name.iter_mut().zip(interface.bytes()) {
+        *dst = src as libc::c_char;
+    }
+    ifr.ifr_ifru.ifru_data = (channels as *mut EthtoolChannels).cast();
+    // SAFETY: ifr points to `channels`, which outlives the call, and the
+    // name is NUL terminated as it is shorter than IFNAMSIZ.
+    let ret = unsafe { libc::ioctl(socket.as_raw_fd(), libc::SIOCETHTOOL, &mut ifr) };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn nic_queues(interface: &str) -> io::Result<Option<NicQueues>> {
+    let mut channels = EthtoolChannels {
+        cmd: ETHTOOL_GCHANNELS,
+        ..Default::default()
+    };
+    match ethtool_channels(interface, &mut channels) {
+        Ok(()) => Ok(Some(NicQueues {
+            current: QueueCounts {
+                rx: channels.rx_count,
+                tx: channels.tx_count,
+                other: channels.other_count,
+                combined: channels.combined_count,
+            },
+            max: QueueCounts {
+                rx: channels.max_rx,
+                tx: channels.max_tx,
+                other: channels.max_other,
+                combined: channels.max_combined,
+            },
+        })),
+        Err(e) if e.raw_os_error() == Some(libc::EOPNOTSUPP) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn set_combined_queues(interface: &str, combined: u32) -> io::Result<()> {
+    let Some(queues) = nic_queues(interface)? else {
+        return Err(invalid_input(format!(
+            "The driver of '{interface}' cannot change its queues"
+        )));
+    };
+    if combined > queues.max.combined {
+        return Err(invalid_input(format!(
+            "'{interface}' supports at most {} combined queues",
+            queues.max.combined
+        )));
+    }
+    let mut channels = EthtoolChannels {
+        cmd: ETHTOOL_SCHANNELS,
+        rx_count: queues.current.rx,
+        tx_count: queues.current.tx,
+        other_count: queues.current.other,
+        combined_count: combined,
+        ..Default::default()
+    };
+    ethtool_channels(interface, &mut channels)
+}
+
+fn nic_tuning_in(root: &Path, interface: &str) -> io::Result<NicTuning> {
+    let irqs = nic_irqs_in(root, interface)?;
+    let local_cpus = read_cpu_list(&device_dir(root, interface)?.join("local_cpulist"))?;
+    Ok(NicTuning {
+        interface: interface.to_string(),
+        queues: nic_queues(interface)?,
+        irqs,
+        local_cpus,
+    })
+}
+
+/// The tuning of every NIC of the host that is backed by a device.
+pub fn nic_tunings() -> io::Result<Vec<NicTuning>> {
+    let root = host_root();
+    let mut interfaces = fs::read_dir(root.join(NET_CLASS_DIR))?
+        .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+        .collect::<io::Result<Vec<_>>>()?;
+    interfaces.sort();
+    interfaces
+        .iter()
+        .filter(|interface| {
+            root.join(NET_CLASS_DIR)
+                .join(interface)
+                .join("device")
+                .exists()
+        })
+        .map(|interface| nic_tuning_in(root, interface))
+        .collect()
+}
+
+pub fn nic_tuning(interface: &str) -> io::Result<NicTuning> {
+    nic_tuning_in(host_root(), interface)
+}
+
+/// The CPUs the interrupts of a NIC may use: all online CPUs that are not
+/// isolated, narrowed to the NIC's NUMA node if it has any of them. Requested
+/// CPUs must be online and not isolated.
+fn irq_cpus_in(root: &Path, interface: &str, requested: &[u32]) -> io::Result<Vec<u32>> {
+    let online = read_cpu_list(&root.join(CPU_DIR).join("online"))?;
+    let isolated = isolated_cpus_in(root)?;
+    if !requested.is_empty() {
+        if let Some(cpu) = requested.iter().find(|cpu| isolated.contains(cpu)) {
+            return Err(invalid_input(format!(
+                "CPU {cpu} is isolated for workloads and cannot serve interrupts"
+            )));
+        }
+        if let Some(cpu) = requested.iter().find(|cpu| !online.contains(cpu)) {
+            return Err(invalid_input(format!("CPU {cpu} is not online")));
+        }
+        return Ok(requested.to_vec());
+    }
+    let housekeeping: Vec<u32> = online
+        .into_iter()
+        .filter(|cpu| !isolated.contains(cpu))
+        .collect();
+    let local_cpus = read_cpu_list(&device_dir(root, interface)?.join("local_cpulist"))?;
+    let local: Vec<u32> = housekeeping
+        .iter()
+        .copied()
+        .filter(|cpu| local_cpus.contains(cpu))
+        .collect();
+    match (local.is_empty(), housekeeping.is_empty()) {
+        (false, _) => Ok(local),
+        (true, false) => Ok(housekeeping),
+        (true, true) => Err(invalid_input(
+            "All online CPUs are isolated, none can serve interrupts".to_string(),
+        )),
+    }
+}
+
+/// Spreads the interrupts of a NIC over `cpus`, one CPU per queue. Kernel
+/// managed interrupts, whose affinity cannot be changed, are left as they are.
+fn set_irq_affinity_in(root: &Path, interface: &str, cpus: &[u32]) -> io::Result<()> {
+    let cpus = irq_cpus_in(root, interface, cpus)?;
+    for (irq, cpu) in nic_irqs_in(root, interface)?
+        .iter()
+        .zip(cpus.iter().cycle())
+    {
+        let path = root
+            .join(IRQ_DIR)
+            .join(irq.irq.to_string())
+            .join("smp_affinity_list");
+        match fs::write(&path, cpu.to_string()) {
+            Ok(()) => {}
+            Err(e) if e.raw_os_error() == Some(libc::EIO) => {}
+            Err(e) => {
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("Failed to set the affinity of IRQ {}: {e}", irq.irq),
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sets the number of combined RSS queues of a NIC, unless `combined_queues`
+/// is 0, then spreads its interrupts over `irq_cpus`. Without CPUs, the
+/// interrupts are kept off isolated CPUs and on the NIC's NUMA node.
+pub fn tune_nic(interface: &str, combined_queues: u32, irq_cpus: &[u32]) -> io::Result<NicTuning> {
+    let root = host_root();
+    device_dir(root, interface)?;
+    if combined_queues > 0 {
+        set_combined_queues(interface, combined_queues)?;
+    }
+    // Changing the queues can change the interrupts of the NIC.
+    set_irq_affinity_in(root, interface, irq_cpus)?;
+    nic_tuning_in(root, interface)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host::FakeRoot;
+
+    fn fake_nic(root: &FakeRoot, interface: &str, local_cpus: &str, irqs: &[u32]) {
+        let device = Path::new(NET_CLASS_DIR).join(interface).join("device");
+        root.write(device.join("local_cpulist"), local_cpus);
+        for irq in irqs {
+            let irq = irq.to_string();
+            root.write(device.join("msi_irqs").join(&irq), "msix")
+                .write(
+                    Path::new(IRQ_DIR).join(&irq).join("smp_affinity_list"),
+                    "0-7\n",
+                );
+        }
+    }
+
+    #[test]
+    fn cpu_lists_round_trip() {
+        assert_eq!(
+            parse_cpu_list("0-3,8,10-11\n").unwrap(),
+            [0, 1, 2, 3, 8, 10, 11]
+        );
+        assert_eq!(parse_cpu_list("\n").unwrap(), Vec::<u32>::new());
+        assert_eq!(format_cpu_list(&[0, 1, 2, 3, 8, 10, 11]), "0-3,8,10-11");
+        assert!(parse_cpu_list("3-1").is_err());
+        assert!(parse_cpu_list("a").is_err());
+    }
+
+    #[test]
+    fn irqs_avoid_isolated_cpus() {
+        let fake = FakeRoot::new();
+        let cpu_dir = Path::new(CPU_DIR);
+        fake.write(cpu_dir.join("online"), "0-7\n")
+            .write(cpu_dir.join("isolated"), "2-3\n")
+            .write(cpu_dir.join("nohz_full"), "3-4\n");
+        fake_nic(&fake, "eth0", "0-3", &[40, 41, 42]);
+        fake_nic(&fake, "eth1", "2-3", &[50]);
+        let root = fake.path();
+
+        assert_eq!(isolated_cpus_in(root).unwrap(), [2, 3, 4]);
+        set_irq_affinity_in(root, "eth0", &[]).unwrap();
+        let cpus: Vec<Vec<u32>> = nic_irqs_in(root, "eth0")
+            .unwrap()
+            .into_iter()
+            .map(|irq| irq.cpus)
+            .collect();
+        assert_eq!(cpus, [vec![0], vec![1], vec![0]]);
+
+        // No housekeeping CPU is local to eth1, so any of them is used.
+        assert_eq!(irq_cpus_in(root, "eth1", &[]).unwrap(), [0, 1, 5, 6, 7]);
+        assert_eq!(
+            set_irq_affinity_in(root, "eth0", &[1, 3])
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidInput
+        );
+        assert!(irq_cpus_in(root, "eth0", &[9]).is_err());
+        assert_eq!(
+            nic_irqs_in(root, "eth9").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+}
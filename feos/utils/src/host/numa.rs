@@ -0,0 +1,123 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! The NUMA nodes of the host and the nodes devices are attached to.
+
+use super::nic::parse_cpu_list;
+use super::{host_root, NET_CLASS_DIR, NODE_DIR, PCI_DEVICES_DIR};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumaNode {
+    pub id: u32,
+    pub cpus: Vec<u32>,
+}
+
+/// The nodes of the host by ID. Nodes without CPUs, e.g. of CXL memory, are
+/// left out, nothing can run on them.
+pub fn nodes() -> io::Result<Vec<NumaNode>> {
+    nodes_in(host_root())
+}
+
+fn nodes_in(root: &Path) -> io::Result<Vec<NumaNode>> {
+    let mut nodes = Vec::new();
+    let entries = match fs::read_dir(root.join(NODE_DIR)) {
+        Ok(entries) => entries,
+        // Kernels without NUMA support have no node directory.
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(nodes),
+        Err(e) => return Err(e),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let Some(id) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.strip_prefix("node"))
+            .and_then(|id| id.parse().ok())
+        else {
+            continue;
+        };
+        let cpus = parse_cpu_list(&fs::read_to_string(entry.path().join("cpulist"))?)?;
+        if !cpus.is_empty() {
+            nodes.push(NumaNode { id, cpus });
+        }
+    }
+    nodes.sort_by_key(|node| node.id);
+    Ok(nodes)
+}
+
+/// The node in a `numa_node` file of sysfs, none for devices the firmware
+/// does not tie to a node.
+fn read_node(path: &Path) -> io::Result<Option<u32>> {
+    let value = fs::read_to_string(path)?;
+    let node: i32 = value.trim().parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} has no node but '{}'", path.display(), value.trim()),
+        )
+    })?;
+    Ok(u32::try_from(node).ok())
+}
+
+/// The node of a PCI device, e.g. `0000:41:00.0`.
+pub fn pci_device_node(address: &str) -> io::Result<Option<u32>> {
+    pci_device_node_in(host_root(), address)
+}
+
+fn pci_device_node_in(root: &Path, address: &str) -> io::Result<Option<u32>> {
+    read_node(&root.join(PCI_DEVICES_DIR).join(address).join("numa_node"))
+}
+
+/// The node of the device behind a network interface. Virtual interfaces
+/// have no node.
+pub fn interface_node(interface: &str) -> io::Result<Option<u32>> {
+    interface_node_in(host_root(), interface)
+}
+
+fn interface_node_in(root: &Path, interface: &str) -> io::Result<Option<u32>> {
+    let path = root
+        .join(NET_CLASS_DIR)
+        .join(interface)
+        .join("device/numa_node");
+    match read_node(&path) {
+        Err(e)
+            if e.kind() == io::ErrorKind::NotFound
+                && root.join(NET_CLASS_DIR).join(interface).exists() =>
+        {
+            Ok(None)
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host::FakeRoot;
+
+    #[test]
+    fn nodes_and_device_nodes_are_read() {
+        let root = FakeRoot::new();
+        root.write("sys/devices/system/node/node1/cpulist", "8-15\n")
+            .write("sys/devices/system/node/node0/cpulist", "0-7\n")
+            .write("sys/devices/system/node/node2/cpulist", "\n")
+            .write("sys/devices/system/node/possible", "0-2\n")
+            .write("sys/bus/pci/devices/0000:41:00.0/numa_node", "1\n")
+            .write("sys/bus/pci/devices/0000:03:00.0/numa_node", "-1\n")
+            .write("sys/class/net/eth0/device/numa_node", "0\n")
+            .dir("sys/class/net/br0");
+        let root = root.path();
+
+        let nodes = nodes_in(root).unwrap();
+        assert_eq!(nodes.iter().map(|node| node.id).collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(nodes[1].cpus, (8..16).collect::<Vec<_>>());
+        assert_eq!(pci_device_node_in(root, "0000:41:00.0").unwrap(), Some(1));
+        assert_eq!(pci_device_node_in(root, "0000:03:00.0").unwrap(), None);
+        assert!(pci_device_node_in(root, "0000:81:00.0").is_err());
+        assert_eq!(interface_node_in(root, "eth0").unwrap(), Some(0));
+        assert_eq!(interface_node_in(root, "br0").unwrap(), None);
+        assert!(interface_node_in(root, "eth9").is_err());
+    }
+}
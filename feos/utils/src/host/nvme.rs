@@ -0,0 +1,758 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! NVMe controllers of the host and their namespaces. Attached namespaces are
+//! read from sysfs, everything else is done with admin commands sent through
+//! the character device of the controller, as `nvme-cli` does.
+
+use super::{host_root, DEV_DIR, NVME_CLASS_DIR};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// `_IOWR('N', 0x41, struct nvme_passthru_cmd)`
+const NVME_IOCTL_ADMIN_CMD: libc::c_ulong = 0xc048_4e41;
+/// `_IO('N', 0x46)`, rescans the namespaces of a controller.
+const NVME_IOCTL_RESCAN: libc::c_ulong = 0x4e46;
+
+const OPCODE_IDENTIFY: u8 = 0x06;
+const OPCODE_NAMESPACE_MANAGEMENT: u8 = 0x0d;
+const OPCODE_NAMESPACE_ATTACHMENT: u8 = 0x15;
+const OPCODE_FORMAT_NVM: u8 = 0x80;
+const CNS_NAMESPACE: u32 = 0x00;
+const CNS_CONTROLLER: u32 = 0x01;
+const CNS_ALLOCATED_NAMESPACES: u32 = 0x10;
+/// Identifies the capabilities common to all namespaces.
+const NSID_ALL: u32 = 0xffff_ffff;
+const SELECT_CREATE: u32 = 0;
+const SELECT_DELETE: u32 = 1;
+const SELECT_ATTACH: u32 = 0;
+const SELECT_DETACH: u32 = 1;
+const IDENTIFY_LEN: usize = 4096;
+/// The Namespace Management bit of OACS in the controller data.
+const OACS_NAMESPACE_MANAGEMENT: u16 = 1 << 3;
+const STATUS_INVALID_FIELD: u16 = 0x002;
+const STATUS_INVALID_NAMESPACE: u16 = 0x00b;
+const STATUS_INSUFFICIENT_CAPACITY: u16 = 0x115;
+const STATUS_NAMESPACE_NOT_ATTACHED: u16 = 0x11a;
+/// Formatting with secure erase can take long on large namespaces.
+const FORMAT_TIMEOUT_MS: u32 = 30 * 60 * 1000;
+/// How long the kernel may take to show a namespace after a rescan.
+const RESCAN_TIMEOUT: Duration = Duration::from_secs(10);
+const SECTOR_SIZE: u64 = 512;
+
+/// `struct nvme_passthru_cmd` of `linux/nvme_ioctl.h`.
+#[repr(C)]
+#[derive(Debug, Default)]
+struct PassthruCmd {
+    opcode: u8,
+    flags: u8,
+    rsvd1: u16,
+    nsid: u32,
+    cdw2: u32,
+    cdw3: u32,
+    metadata: u64,
+    addr: u64,
+    metadata_len: u32,
+    data_len: u32,
+    cdw10: u32,
+    cdw11: u32,
+    cdw12: u32,
+    cdw13: u32,
+    cdw14: u32,
+    cdw15: u32,
+    timeout_ms: u32,
+    result: u32,
+}
+
+/// A block size a namespace can be formatted with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LbaFormat {
+    pub index: u8,
+    pub block_size: u32,
+    pub metadata_size: u16,
+    /// 0 is the best performance the controller offers, 3 the worst.
+    pub relative_performance: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NvmeNamespace {
+    pub nsid: u32,
+    /// The block device, e.g. `nvme0n1`. Empty if the namespace is not
+    /// attached to the controller.
+    pub device: String,
+    pub size_bytes: u64,
+    pub block_size: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NvmeController {
+    /// e.g. `nvme0`
+    pub name: String,
+    /// The PCI address for PCIe controllers, e.g. `0000:81:00.0`.
+    pub address: String,
+    pub transport: String,
+    pub model: String,
+    pub serial: String,
+    pub firmware: String,
+    /// Whether namespaces can be created and deleted.
+    pub namespace_management: bool,
+    pub total_capacity_bytes: u64,
+    pub unallocated_capacity_bytes: u64,
+    pub max_namespaces: u32,
+    pub lba_formats: Vec<LbaFormat>,
+    pub namespaces: Vec<NvmeNamespace>,
+}
+
+/// How a namespace is erased when it is formatted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecureErase {
+    None = 0,
+    UserData = 1,
+    Cryptographic = 2,
+}
+
+/// What the Identify Controller data tells about namespaces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ControllerData {
+    controller_id: u16,
+    namespace_management: bool,
+    total_capacity_bytes: u64,
+    unallocated_capacity_bytes: u64,
+    max_namespaces: u32,
+}
+
+/// What the Identify Namespace data tells about the formats.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct NamespaceData {
+    size_blocks: u64,
+    current_format: u8,
+    lba_formats: Vec<LbaFormat>,
+}
+
+impl NamespaceData {
+    fn block_size(&self) -> u32 {
+        self.lba_formats
+            .iter()
+            .find(|format| format.index == self.current_format)
+            .map_or(0, |format| format.block_size)
+    }
+}
+
+/// A command the controller completed with an error status.
+#[derive(Debug)]
+struct CommandStatus {
+    opcode: u8,
+    status: u16,
+}
+
+impl fmt::Display for CommandStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "NVMe admin command {:#04x} failed with status {:#05x}",
+            self.opcode, self.status
+        )
+    }
+}
+
+impl std::error::Error for CommandStatus {}
+
+fn command_status(e: &io::Error) -> Option<u16> {
+    e.get_ref()?
+        .downcast_ref::<CommandStatus>()
+        .map(|status| status.status)
+}
+
+fn le_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn le_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap_or_default())
+}
+
+fn le_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap_or_default())
+}
+
+/// The capacities are 128 bit, but no device holds more than 2^64 bytes.
+fn le_capacity(data: &[u8], offset: usize) -> u64 {
+    u64::try_from(u128::from_le_bytes(
+        data[offset..offset + 16].try_into().unwrap_or_default(),
+    ))
+    .unwrap_or(u64::MAX)
+}
+
+fn parse_controller_data(data: &[u8; IDENTIFY_LEN]) -> ControllerData {
+    ControllerData {
+        controller_id: le_u16(data, 78),
+        namespace_management: le_u16(data, 256) & OACS_NAMESPACE_MANAGEMENT != 0,
+        total_capacity_bytes: le_capacity(data, 280),
+        unallocated_capacity_bytes: le_capacity(data, 296),
+        max_namespaces: le_u32(data, 516),
+    }
+}
+
+fn parse_namespace_data(data: &[u8; IDENTIFY_LEN]) -> NamespaceData {
+    let formats = usize::from(data[25]) + 1;
+    let flbas = data[26];
+    NamespaceData {
+        size_blocks: le_u64(data, 0),
+        current_format: (flbas & 0x0f) | ((flbas >> 5) & 0x03) << 4,
+        lba_formats: (0..formats.min(64))
+            .filter_map(|index| {
+                let lbaf = le_u32(data, 128 + index * 4);
+                // An LBA data size below 9 means the format is not supported.
+                let lbads = (lbaf >> 16) & 0xff;
+                (9..32).contains(&lbads).then(|| LbaFormat {
+                    index: index as u8,
+                    block_size: 1 << lbads,
+                    metadata_size: (lbaf & 0xffff) as u16,
+                    relative_performance: ((lbaf >> 24) & 0x03) as u8,
+                })
+            })
+            .collect(),
+    }
+}
+
+/// The format without metadata of `block_size`. 0 picks the format the
+/// controller rates fastest.
+fn pick_format(formats: &[LbaFormat], block_size: u32) -> io::Result<&LbaFormat> {
+    formats
+        .iter()
+        .filter(|format| format.metadata_size == 0)
+        .filter(|format| block_size == 0 || format.block_size == block_size)
+        .min_by_key(|format| (format.relative_performance, format.index))
+        .ok_or_else(|| {
+            let sizes: Vec<String> = formats
+                .iter()
+                .filter(|format| format.metadata_size == 0)
+                .map(|format| format.block_size.to_string())
+                .collect();
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Block size {block_size} is not supported, only {}",
+                    sizes.join(", ")
+                ),
+            )
+        })
+}
+
+/// The FLBAS field, which splits the format index into bits 0-3 and 5-6.
+fn flbas(index: u8) -> u32 {
+    u32::from(index & 0x0f) | u32::from(index >> 4 & 0x03) << 5
+}
+
+fn read_trimmed(path: &Path) -> String {
+    fs::read_to_string(path)
+        .map(|value| value.trim().to_string())
+        .unwrap_or_default()
+}
+
+fn is_controller_name(name: &str) -> bool {
+    name.strip_prefix("nvme")
+        .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+}
+
+fn validate_controller(root: &Path, controller: &str) -> io::Result<()> {
+    if !is_controller_name(controller) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("'{controller}' is not an NVMe controller name like nvme0"),
+        ));
+    }
+    if !root.join(NVME_CLASS_DIR).join(controller).exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("NVMe controller {controller} not found"),
+        ));
+    }
+    Ok(())
+}
+
+/// The block device of a namespace directory of a controller. With native
+/// multipath the controller has the hidden path `nvme0c0n1` of the device
+/// `nvme0n1`, which assumes the subsystem is numbered like its controller,
+/// as it is for local PCIe drives.
+fn namespace_device(controller: &str, entry: &str) -> Option<String> {
+    let rest = entry.strip_prefix(controller)?;
+    let instance = match rest.strip_prefix('c') {
+        Some(path) => path.split_once('n')?.1,
+        None => rest.strip_prefix('n')?,
+    };
+    (!instance.is_empty() && instance.chars().all(|c| c.is_ascii_digit()))
+        .then(|| format!("{controller}n{instance}"))
+}
+
+fn attached_namespaces_in(root: &Path, controller: &str) -> io::Result<Vec<NvmeNamespace>> {
+    let dir = root.join(NVME_CLASS_DIR).join(controller);
+    let mut namespaces = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Some(device) = namespace_device(controller, &name) else {
+            continue;
+        };
+        let path = entry.path();
+        let Ok(nsid) = read_trimmed(&path.join("nsid")).parse() else {
+            continue;
+        };
+        let sectors: u64 = read_trimmed(&path.join("size")).parse().unwrap_or(0);
+        namespaces.push(NvmeNamespace {
+            nsid,
+            device,
+            size_bytes: sectors * SECTOR_SIZE,
+            block_size: read_trimmed(&path.join("queue/logical_block_size"))
+                .parse()
+                .unwrap_or(0),
+        });
+    }
+    namespaces.sort_by_key(|namespace| namespace.nsid);
+    Ok(namespaces)
+}
+
+/// The controllers as sysfs shows them, without what only the controller
+/// itself tells.
+fn controllers_in(root: &Path) -> io::Result<Vec<NvmeController>> {
+    let class_dir = root.join(NVME_CLASS_DIR);
+    let entries = match fs::read_dir(&class_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut controllers = Vec::new();
+    for entry in entries {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if !is_controller_name(&name) {
+            continue;
+        }
+        let dir = class_dir.join(&name);
+        controllers.push(NvmeController {
+            address: read_trimmed(&dir.join("address")),
+            transport: read_trimmed(&dir.join("transport")),
+            model: read_trimmed(&dir.join("model")),
+            serial: read_trimmed(&dir.join("serial")),
+            firmware: read_trimmed(&dir.join("firmware_rev")),
+            namespaces: attached_namespaces_in(root, &name)?,
+            name,
+            ..Default::default()
+        });
+    }
+    controllers.sort_by_key(|controller| controller.name[4..].parse::<u32>().unwrap_or(u32::MAX));
+    Ok(controllers)
+}
+
+fn status_error(opcode: u8, status: u16) -> io::Error {
+    let kind = match status {
+        STATUS_INVALID_FIELD | STATUS_INSUFFICIENT_CAPACITY => io::ErrorKind::InvalidInput,
+        STATUS_INVALID_NAMESPACE => io::ErrorKind::NotFound,
+        _ => io::ErrorKind::Other,
+    };
+    io::Error::new(kind, CommandStatus { opcode, status })
+}
+
+/// Sends an admin command and returns the result dword of the completion.
+fn admin_command(device: &File, cmd: &mut PassthruCmd) -> io::Result<u32> {
+    // SAFETY: cmd matches the kernel's struct and its data buffer, if any,
+    // outlives the call with at least data_len bytes.
+    let ret = unsafe { libc::ioctl(device.as_raw_fd(), NVME_IOCTL_ADMIN_CMD, cmd as *mut _) };
+    match ret {
+        0 => Ok(cmd.result),
+        ret if ret < 0 => Err(io::Error::last_os_error()),
+        status => Err(status_error(cmd.opcode, (status & 0x7ff) as u16)),
+    }
+}
+
+fn identify(device: &File, cns: u32, nsid: u32) -> io::Result<Box<[u8; IDENTIFY_LEN]>> {
+    let mut data = Box::new([0u8; IDENTIFY_LEN]);
+    admin_command(
+        device,
+        &mut PassthruCmd {
+            opcode: OPCODE_IDENTIFY,
+            nsid,
+            addr: data.as_mut_ptr() as u64,
+            data_len: IDENTIFY_LEN as u32,
+            cdw10: cns,
+            ..Default::default()
+        },
+    )?;
+    Ok(data)
+}
+
+fn open_controller(root: &Path, controller: &str) -> io::Result<File> {
+    validate_controller(root, controller)?;
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(root.join(DEV_DIR).join(controller))
+}
+
+fn managed_controller(device: &File, controller: &str) -> io::Result<ControllerData> {
+    let data = parse_controller_data(&*identify(device, CNS_CONTROLLER, 0)?);
+    if !data.namespace_management {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{controller} does not support namespace management"),
+        ));
+    }
+    Ok(data)
+}
+
+/// The namespaces allocated on the controller, attached or not.
+fn allocated_nsids(device: &File) -> io::Result<Vec<u32>> {
+    let data = identify(device, CNS_ALLOCATED_NAMESPACES, 0)?;
+    Ok((0..IDENTIFY_LEN / 4)
+        .map(|i| le_u32(&data[..], i * 4))
+        .take_while(|&nsid| nsid != 0)
+        .collect())
+}
+
+/// The controller list of a namespace attachment: its length, then the IDs.
+fn controller_list(controller_id: u16) -> Box<[u8; IDENTIFY_LEN]> {
+    let mut list = Box::new([0u8; IDENTIFY_LEN]);
+    list[0..2].copy_from_slice(&1u16.to_le_bytes());
+    list[2..4].copy_from_slice(&controller_id.to_le_bytes());
+    list
+}
+
+fn rescan(device: &File) -> io::Result<()> {
+    // SAFETY: The rescan ioctl takes no argument.
+    if unsafe { libc::ioctl(device.as_raw_fd(), NVME_IOCTL_RESCAN) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Fails if the block device of the namespace is mounted or held, e.g. by
+/// device mapper or an open exclusive handle.
+fn check_unused(root: &Path, namespace: &NvmeNamespace) -> io::Result<()> {
+    if namespace.device.is_empty() {
+        return Ok(());
+    }
+    let path = root.join(DEV_DIR).join(&namespace.device);
+    match OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_EXCL)
+        .open(&path)
+    {
+        Ok(_) => Ok(()),
+        Err(e) if e.raw_os_error() == Some(libc::EBUSY) => Err(io::Error::new(
+            io::ErrorKind::ResourceBusy,
+            format!("{} is in use", namespace.device),
+        )),
+        Err(e) => Err(e),
+    }
+}
+
+/// Waits for the kernel to show the namespace after a rescan.
+fn wait_for_namespace(root: &Path, controller: &str, nsid: u32) -> io::Result<NvmeNamespace> {
+    let deadline = Instant::now() + RESCAN_TIMEOUT;
+    loop {
+        if let Some(namespace) = attached_namespaces_in(root, controller)?
+            .into_iter()
+            .find(|namespace| namespace.nsid == nsid && namespace.size_bytes > 0)
+        {
+            return Ok(namespace);
+        }
+        if Instant::now() >= deadline {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("Namespace {nsid} of {controller} did not show up"),
+            ));
+        }
+        sleep(Duration::from_millis(100));
+    }
+}
+
+fn namespace_of(root: &Path, controller: &str, nsid: u32) -> io::Result<NvmeNamespace> {
+    Ok(attached_namespaces_in(root, controller)?
+        .into_iter()
+        .find(|namespace| namespace.nsid == nsid)
+        .unwrap_or(NvmeNamespace {
+            nsid,
+            device: String::new(),
+            size_bytes: 0,
+            block_size: 0,
+        }))
+}
+
+/// Lists the NVMe controllers of the host with their namespaces. What only
+/// the controller tells, e.g. the capacity, is left out for controllers that
+/// do not answer.
+pub fn controllers() -> io::Result<Vec<NvmeController>> {
+    let root = host_root();
+    let mut controllers = controllers_in(root)?;
+    for controller in &mut controllers {
+        let Ok(device) = File::open(root.join(DEV_DIR).join(&controller.name)) else {
+            continue;
+        };
+        let Ok(data) = identify(&device, CNS_CONTROLLER, 0) else {
+            continue;
+        };
+        let data = parse_controller_data(&data);
+        controller.namespace_management = data.namespace_management;
+        controller.total_capacity_bytes = data.total_capacity_bytes;
+        controller.unallocated_capacity_bytes = data.unallocated_capacity_bytes;
+        controller.max_namespaces = data.max_namespaces;
+        if let Ok(common) = identify(&device, CNS_NAMESPACE, NSID_ALL) {
+            controller.lba_formats = parse_namespace_data(&common).lba_formats;
+        }
+        if !data.namespace_management {
+            continue;
+        }
+        for nsid in allocated_nsids(&device).unwrap_or_default() {
+            if controller.namespaces.iter().any(|ns| ns.nsid == nsid) {
+                continue;
+            }
+            let data = identify(&device, CNS_NAMESPACE, nsid)
+                .map(|data| parse_namespace_data(&data))
+                .ok();
+            controller.namespaces.push(NvmeNamespace {
+                nsid,
+                device: String::new(),
+                size_bytes: data
+                    .as_ref()
+                    .map_or(0, |data| data.size_blocks * u64::from(data.block_size())),
+                block_size: data.as_ref().map_or(0, NamespaceData::block_size),
+            });
+        }
+        controller
+            .namespaces
+            .sort_by_key(|namespace| namespace.nsid);
+    }
+    Ok(controllers)
+}
+
+/// Creates a namespace of `size_bytes` with blocks of `block_size`, see
+/// `pick_format`, and attaches it to the controller.
+pub fn create_namespace(
+    controller: &str,
+    size_bytes: u64,
+    block_size: u32,
+) -> io::Result<NvmeNamespace> {
+    let root = host_root();
+    let device = open_controller(root, controller)?;
+    let data = managed_controller(&device, controller)?;
+    let common = parse_namespace_data(&*identify(&device, CNS_NAMESPACE, NSID_ALL)?);
+    let format = pick_format(&common.lba_formats, block_size)?;
+    let block_size = u64::from(format.block_size);
+    if size_bytes == 0 || !size_bytes.is_multiple_of(block_size) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Size must be a positive multiple of the block size {block_size}"),
+        ));
+    }
+
+    let blocks = size_bytes / block_size;
+    let mut buffer = Box::new([0u8; IDENTIFY_LEN]);
+    buffer[0..8].copy_from_slice(&blocks.to_le_bytes());
+    buffer[8..16].copy_from_slice(&blocks.to_le_bytes());
+    buffer[26] = flbas(format.index) as u8;
+    let nsid = admin_command(
+        &device,
+        &mut PassthruCmd {
+            opcode: OPCODE_NAMESPACE_MANAGEMENT,
+            addr: buffer.as_mut_ptr() as u64,
+            data_len: IDENTIFY_LEN as u32,
+            cdw10: SELECT_CREATE,
+            ..Default::default()
+        },
+    )?;
+
+    let mut controllers = controller_list(data.controller_id);
+    admin_command(
+        &device,
+        &mut PassthruCmd {
+            opcode: OPCODE_NAMESPACE_ATTACHMENT,
+            nsid,
+            addr: controllers.as_mut_ptr() as u64,
+            data_len: IDENTIFY_LEN as u32,
+            cdw10: SELECT_ATTACH,
+            ..Default::default()
+        },
+    )?;
+    rescan(&device)?;
+    wait_for_namespace(root, controller, nsid)
+}
+
+/// Detaches and deletes a namespace. Its block device must not be in use.
+pub fn delete_namespace(controller: &str, nsid: u32) -> io::Result<()> {
+    let root = host_root();
+    let device = open_controller(root, controller)?;
+    let data = managed_controller(&device, controller)?;
+    check_unused(root, &namespace_of(root, controller, nsid)?)?;
+
+    let mut controllers = controller_list(data.controller_id);
+    let detached = admin_command(
+        &device,
+        &mut PassthruCmd {
+            opcode: OPCODE_NAMESPACE_ATTACHMENT,
+            nsid,
+            addr: controllers.as_mut_ptr() as u64,
+            data_len: IDENTIFY_LEN as u32,
+            cdw10: SELECT_DETACH,
+            ..Default::default()
+        },
+    );
+    match detached {
+        Err(e) if command_status(&e) != Some(STATUS_NAMESPACE_NOT_ATTACHED) => return Err(e),
+        _ => {}
+    }
+    admin_command(
+        &device,
+        &mut PassthruCmd {
+            opcode: OPCODE_NAMESPACE_MANAGEMENT,
+            nsid,
+            cdw10: SELECT_DELETE,
+            ..Default::default()
+        },
+    )?;
+    rescan(&device)
+}
+
+/// Formats a namespace with blocks of `block_size`, 0 keeping the current
+/// size, and erases it as asked. Its block device must not be in use.
+pub fn format_namespace(
+    controller: &str,
+    nsid: u32,
+    block_size: u32,
+    erase: SecureErase,
+) -> io::Result<NvmeNamespace> {
+    let root = host_root();
+    let device = open_controller(root, controller)?;
+    let namespace = namespace_of(root, controller, nsid)?;
+    check_unused(root, &namespace)?;
+    let data = parse_namespace_data(&*identify(&device, CNS_NAMESPACE, nsid)?);
+    let index = if block_size == 0 {
+        data.current_format
+    } else {
+        pick_format(&data.lba_formats, block_size)?.index
+    };
+
+    let lbaf = u32::from(index & 0x0f) | u32::from(index >> 4 & 0x03) << 12;
+    admin_command(
+        &device,
+        &mut PassthruCmd {
+            opcode: OPCODE_FORMAT_NVM,
+            nsid,
+            cdw10: lbaf | (erase as u32) << 9,
+            timeout_ms: FORMAT_TIMEOUT_MS,
+            ..Default::default()
+        },
+    )?;
+    rescan(&device)?;
+    if namespace.device.is_empty() {
+        return Ok(namespace);
+    }
+    wait_for_namespace(root, controller, nsid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host::FakeRoot;
+
+    fn fake_namespace(root: &FakeRoot, controller: &str, entry: &str, nsid: u32, sectors: u64) {
+        let dir = Path::new(NVME_CLASS_DIR).join(controller).join(entry);
+        root.write(dir.join("nsid"), format!("{nsid}\n"))
+            .write(dir.join("size"), format!("{sectors}\n"))
+            .write(dir.join("queue/logical_block_size"), "4096\n");
+    }
+
+    #[test]
+    fn controllers_and_attached_namespaces_are_read_from_sysfs() {
+        let fake = FakeRoot::new();
+        let class_dir = Path::new(NVME_CLASS_DIR);
+        fake.write(class_dir.join("nvme10/model"), "SAMSUNG MZQL23T8HCLS   \n")
+            .write(class_dir.join("nvme10/address"), "0000:81:00.0\n")
+            .dir(class_dir.join("nvme2/power"))
+            .dir(class_dir.join("nvme-fabrics"));
+        fake_namespace(&fake, "nvme10", "nvme10c10n2", 2, 2048);
+        fake_namespace(&fake, "nvme10", "nvme10n1", 1, 1024);
+        let root = fake.path();
+
+        let controllers = controllers_in(root).unwrap();
+        let names: Vec<&str> = controllers.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["nvme2", "nvme10"]);
+        let nvme10 = &controllers[1];
+        assert_eq!(nvme10.model, "SAMSUNG MZQL23T8HCLS");
+        assert_eq!(nvme10.address, "0000:81:00.0");
+        assert_eq!(
+            nvme10.namespaces,
+            [
+                NvmeNamespace {
+                    nsid: 1,
+                    device: "nvme10n1".to_string(),
+                    size_bytes: 1024 * 512,
+                    block_size: 4096,
+                },
+                NvmeNamespace {
+                    nsid: 2,
+                    device: "nvme10n2".to_string(),
+                    size_bytes: 2048 * 512,
+                    block_size: 4096,
+                },
+            ]
+        );
+        assert!(controllers[0].namespaces.is_empty());
+
+        let err = validate_controller(root, "nvme3").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        let err = validate_controller(root, "../nvme10").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn identify_data_is_parsed_and_formats_are_picked() {
+        let mut controller = [0u8; IDENTIFY_LEN];
+        controller[78..80].copy_from_slice(&7u16.to_le_bytes());
+        controller[256] = 0x0e;
+        controller[280..288].copy_from_slice(&(4u64 << 40).to_le_bytes());
+        controller[296..304].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        controller[516..520].copy_from_slice(&32u32.to_le_bytes());
+        assert_eq!(
+            parse_controller_data(&controller),
+            ControllerData {
+                controller_id: 7,
+                namespace_management: true,
+                total_capacity_bytes: 4 << 40,
+                unallocated_capacity_bytes: 1 << 40,
+                max_namespaces: 32,
+            }
+        );
+
+        let mut namespace = [0u8; IDENTIFY_LEN];
+        namespace[0..8].copy_from_slice(&1000u64.to_le_bytes());
+        namespace[25] = 2;
+        namespace[26] = 1;
+        // 512 bytes, good performance; 4096 bytes, best; 4096 + 8 metadata.
+        namespace[128..132].copy_from_slice(&(9u32 << 16 | 2 << 24).to_le_bytes());
+        namespace[132..136].copy_from_slice(&(12u32 << 16).to_le_bytes());
+        namespace[136..140].copy_from_slice(&(12u32 << 16 | 8).to_le_bytes());
+        let data = parse_namespace_data(&namespace);
+        assert_eq!(data.size_blocks, 1000);
+        assert_eq!(data.current_format, 1);
+        assert_eq!(data.lba_formats.len(), 3);
+        assert_eq!(data.block_size(), 4096);
+
+        assert_eq!(pick_format(&data.lba_formats, 0).unwrap().index, 1);
+        assert_eq!(pick_format(&data.lba_formats, 512).unwrap().index, 0);
+        let err = pick_format(&data.lba_formats, 8192).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(flbas(17), 0x21);
+
+        let err = status_error(OPCODE_NAMESPACE_ATTACHMENT, STATUS_NAMESPACE_NOT_ATTACHED);
+        assert_eq!(command_status(&err), Some(STATUS_NAMESPACE_NOT_ATTACHED));
+        assert_eq!(
+            err.to_string(),
+            "NVMe admin command 0x15 failed with status 0x11a"
+        );
+    }
+}
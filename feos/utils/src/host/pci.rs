@@ -0,0 +1,242 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! The PCI devices of the host and their binding to vfio-pci, which VMs
+//! need to get a device passed through.
+
+use super::{host_root, PCI_DEVICES_DIR, PCI_DRIVERS_DIR, PCI_DRIVERS_PROBE};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const VFIO_PCI_DRIVER: &str = "vfio-pci";
+/// PCI class of bridges, which are never passed through.
+const BRIDGE_CLASS: u32 = 0x06;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PciDevice {
+    /// e.g. `0000:41:00.0`
+    pub address: String,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    /// The class, subclass and programming interface, e.g. `0x020000`.
+    pub class: u32,
+    /// The driver bound to the device, none if it is unbound.
+    pub driver: Option<String>,
+    /// Devices without an IOMMU group cannot be passed through.
+    pub iommu_group: Option<u32>,
+    pub numa_node: Option<u32>,
+}
+
+impl PciDevice {
+    /// Whether the device can be bound to vfio-pci and passed through.
+    pub fn vfio_capable(&self) -> bool {
+        self.iommu_group.is_some() && self.class >> 16 != BRIDGE_CLASS
+    }
+}
+
+fn read_trimmed(path: &Path) -> io::Result<String> {
+    Ok(fs::read_to_string(path)?.trim().to_string())
+}
+
+fn read_hex(path: &Path) -> io::Result<u32> {
+    let value = read_trimmed(path)?;
+    u32::from_str_radix(value.trim_start_matches("0x"), 16).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is not hexadecimal but '{value}'", path.display()),
+        )
+    })
+}
+
+/// The name a sysfs link points to, none if there is no link.
+fn link_name(path: &Path) -> io::Result<Option<String>> {
+    match fs::read_link(path) {
+        Ok(target) => Ok(target
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn device_dir(root: &Path, address: &str) -> io::Result<PathBuf> {
+    let dir = root.join(PCI_DEVICES_DIR).join(address);
+    if address.contains('/') || !dir.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("PCI device {address} not found"),
+        ));
+    }
+    Ok(dir)
+}
+
+fn read_device(dir: &Path, address: &str) -> io::Result<PciDevice> {
+    let numa_node = read_trimmed(&dir.join("numa_node"))
+        .ok()
+        .and_then(|node| node.parse::<u32>().ok());
+    Ok(PciDevice {
+        address: address.to_string(),
+        vendor_id: read_hex(&dir.join("vendor"))? as u16,
+        device_id: read_hex(&dir.join("device"))? as u16,
+        class: read_hex(&dir.join("class"))?,
+        driver: link_name(&dir.join("driver"))?,
+        iommu_group: link_name(&dir.join("iommu_group"))?.and_then(|group| group.parse().ok()),
+        numa_node,
+    })
+}
+
+/// The PCI devices of the host by address.
+pub fn devices() -> io::Result<Vec<PciDevice>> {
+    devices_in(host_root())
+}
+
+fn devices_in(root: &Path) -> io::Result<Vec<PciDevice>> {
+    let mut devices = Vec::new();
+    for entry in fs::read_dir(root.join(PCI_DEVICES_DIR))? {
+        let entry = entry?;
+        let address = entry.file_name().to_string_lossy().into_owned();
+        devices.push(read_device(&entry.path(), &address)?);
+    }
+    devices.sort_by(|a, b| a.address.cmp(&b.address));
+    Ok(devices)
+}
+
+/// The PCI device at `address`, e.g. `0000:41:00.0`.
+pub fn device(address: &str) -> io::Result<PciDevice> {
+    device_in(host_root(), address)
+}
+
+fn device_in(root: &Path, address: &str) -> io::Result<PciDevice> {
+    read_device(&device_dir(root, address)?, address)
+}
+
+/// Binds a device to vfio-pci and returns the driver it was bound to before.
+/// A device that is already bound to vfio-pci is left as it is.
+pub fn bind_vfio(address: &str) -> io::Result<Option<String>> {
+    bind_vfio_in(host_root(), address)
+}
+
+fn bind_vfio_in(root: &Path, address: &str) -> io::Result<Option<String>> {
+    let device = device_in(root, address)?;
+    if device.driver.as_deref() == Some(VFIO_PCI_DRIVER) {
+        return Ok(None);
+    }
+    if !device.vfio_capable() {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("PCI device {address} cannot be passed through, it is a bridge or has no IOMMU group"),
+        ));
+    }
+    let dir = device_dir(root, address)?;
+    // The override makes the probe below pick vfio-pci over the driver the
+    // kernel would match.
+    fs::write(dir.join("driver_override"), VFIO_PCI_DRIVER)?;
+    if device.driver.is_some() {
+        fs::write(dir.join("driver/unbind"), address)?;
+    }
+    fs::write(root.join(PCI_DRIVERS_PROBE), address)?;
+    let driver = link_name(&dir.join("driver"))?;
+    if driver.as_deref() != Some(VFIO_PCI_DRIVER) {
+        return Err(io::Error::other(format!(
+            "PCI device {address} is bound to {} instead of {VFIO_PCI_DRIVER}, is the module loaded?",
+            driver.as_deref().unwrap_or("no driver")
+        )));
+    }
+    Ok(device.driver)
+}
+
+/// Unbinds a device from vfio-pci and binds it to `driver` again, as it was
+/// before `bind_vfio`.
+pub fn unbind_vfio(address: &str, driver: &str) -> io::Result<()> {
+    unbind_vfio_in(host_root(), address, driver)
+}
+
+fn unbind_vfio_in(root: &Path, address: &str, driver: &str) -> io::Result<()> {
+    let dir = device_dir(root, address)?;
+    // An empty override lets the kernel match drivers by ID again.
+    fs::write(dir.join("driver_override"), "\n")?;
+    if link_name(&dir.join("driver"))?.as_deref() == Some(VFIO_PCI_DRIVER) {
+        fs::write(dir.join("driver/unbind"), address)?;
+    }
+    fs::write(
+        root.join(PCI_DRIVERS_DIR).join(driver).join("bind"),
+        address,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host::FakeRoot;
+
+    fn fake_device(
+        root: &FakeRoot,
+        address: &str,
+        class: &str,
+        driver: Option<&str>,
+        group: Option<u32>,
+    ) {
+        let dir = Path::new(PCI_DEVICES_DIR).join(address);
+        root.write(dir.join("vendor"), "0x8086\n")
+            .write(dir.join("device"), "0x159b\n")
+            .write(dir.join("class"), format!("{class}\n"))
+            .write(dir.join("numa_node"), "-1\n");
+        if let Some(driver) = driver {
+            root.link(dir.join("driver"), Path::new(PCI_DRIVERS_DIR).join(driver));
+        }
+        if let Some(group) = group {
+            root.link(
+                dir.join("iommu_group"),
+                format!("sys/kernel/iommu_groups/{group}"),
+            );
+        }
+    }
+
+    #[test]
+    fn devices_are_read_with_their_driver_and_iommu_group() {
+        let fake = FakeRoot::new();
+        fake_device(&fake, "0000:41:00.1", "0x020000", Some("ice"), Some(37));
+        fake_device(
+            &fake,
+            "0000:41:00.0",
+            "0x020000",
+            Some(VFIO_PCI_DRIVER),
+            Some(36),
+        );
+        fake_device(&fake, "0000:00:01.0", "0x060400", Some("pcieport"), Some(2));
+        fake_device(&fake, "0000:03:00.0", "0x010802", None, None);
+        let root = fake.path();
+
+        let devices = devices_in(root).unwrap();
+        let addresses: Vec<&str> = devices.iter().map(|d| d.address.as_str()).collect();
+        assert_eq!(
+            addresses,
+            [
+                "0000:00:01.0",
+                "0000:03:00.0",
+                "0000:41:00.0",
+                "0000:41:00.1"
+            ]
+        );
+        let nic = &devices[3];
+        assert_eq!((nic.vendor_id, nic.device_id), (0x8086, 0x159b));
+        assert_eq!(nic.driver.as_deref(), Some("ice"));
+        assert_eq!(nic.iommu_group, Some(37));
+        assert_eq!(nic.numa_node, None);
+        let capable: Vec<bool> = devices.iter().map(PciDevice::vfio_capable).collect();
+        assert_eq!(capable, [false, false, true, true]);
+
+        // Devices on vfio-pci already are not touched.
+        assert_eq!(bind_vfio_in(root, "0000:41:00.0").unwrap(), None);
+        assert_eq!(
+            bind_vfio_in(root, "0000:00:01.0").unwrap_err().kind(),
+            io::ErrorKind::Unsupported
+        );
+        assert_eq!(
+            device_in(root, "0000:99:00.0").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        assert!(device_in(root, "../devices").is_err());
+    }
+}
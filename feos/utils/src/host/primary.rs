@@ -0,0 +1,152 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! One FeOS daemon of a host serves the API and manages the workloads, the
+//! primary. It holds an exclusive lock on a file for as long as it runs. A
+//! standby daemon waits for the lock and takes over when the primary exits
+//! or crashes, as the kernel drops the lock with the process holding it.
+//!
+//! The standby is a cold one: it initializes nothing before it has the lock,
+//! as e.g. migrating the databases would change them under the primary. The
+//! workloads are processes of their own that don't hold the lock, so they
+//! keep running while the primary is gone and the standby starts up.
+
+use nix::errno::Errno;
+use nix::fcntl::{fcntl, FcntlArg, FdFlag, Flock, FlockArg};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+use std::path::Path;
+
+pub const PRIMARY_LOCK_PATH: &str = "/var/lib/feos/primary.lock";
+
+/// The lock of the primary daemon, held until it is dropped.
+#[derive(Debug)]
+pub struct PrimaryLock(Flock<File>);
+
+fn open(path: &Path) -> io::Result<File> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+}
+
+impl PrimaryLock {
+    /// Takes the lock, none if another daemon holds it.
+    pub fn try_acquire(path: &Path) -> io::Result<Option<Self>> {
+        match Flock::lock(open(path)?, FlockArg::LockExclusiveNonblock) {
+            Ok(lock) => Self::record_pid(lock).map(Some),
+            Err((_, Errno::EWOULDBLOCK)) => Ok(None),
+            Err((_, errno)) => Err(errno.into()),
+        }
+    }
+
+    /// Waits until no other daemon holds the lock and takes it. This blocks
+    /// the thread for as long as the primary runs.
+    pub fn acquire(path: &Path) -> io::Result<Self> {
+        let lock = Flock::lock(open(path)?, FlockArg::LockExclusive)
+            .map_err(|(_, errno)| io::Error::from(errno))?;
+        Self::record_pid(lock)
+    }
+
+    /// Takes over the lock of the daemon this process was before it
+    /// executed a new binary, see `keep_across_exec`.
+    ///
+    /// # Safety
+    ///
+    /// `fd` must be the open lock file inherited across exec and must not be
+    /// used otherwise.
+    pub unsafe fn from_inherited_fd(fd: RawFd) -> io::Result<Self> {
+        let file = File::from_raw_fd(fd);
+        fcntl(&file, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))?;
+        // Locking a file description that holds the lock already succeeds.
+        match Flock::lock(file, FlockArg::LockExclusiveNonblock) {
+            Ok(lock) => Ok(Self(lock)),
+            Err((_, errno)) => Err(errno.into()),
+        }
+    }
+
+    /// Keeps the lock held across exec and returns the descriptor the new
+    /// binary takes it over from. Without, a standby might take over while
+    /// the primary restarts.
+    pub fn keep_across_exec(&self) -> io::Result<RawFd> {
+        fcntl(&*self.0, FcntlArg::F_SETFD(FdFlag::empty()))?;
+        Ok(self.0.as_raw_fd())
+    }
+
+    fn record_pid(lock: Flock<File>) -> io::Result<Self> {
+        let mut file: &File = &lock;
+        file.set_len(0)?;
+        file.write_all(format!("{}\n", std::process::id()).as_bytes())?;
+        Ok(Self(lock))
+    }
+}
+
+/// The PID of the daemon that holds or last held the lock.
+pub fn primary_pid(path: &Path) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[test]
+    fn only_one_daemon_is_primary_at_a_time() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("feos/primary.lock");
+
+        let primary = PrimaryLock::try_acquire(&path).unwrap().unwrap();
+        assert_eq!(primary_pid(&path), Some(std::process::id()));
+        // A lock on another file description of the same process conflicts
+        // as it would for another process.
+        assert!(PrimaryLock::try_acquire(&path).unwrap().is_none());
+
+        // Exec leaves the descriptor open without running drop.
+        let fd = primary.keep_across_exec().unwrap();
+        std::mem::forget(primary);
+        let primary = unsafe { PrimaryLock::from_inherited_fd(fd) }.unwrap();
+        assert!(PrimaryLock::try_acquire(&path).unwrap().is_none());
+
+        drop(primary);
+        assert!(PrimaryLock::acquire(&path).is_ok());
+    }
+
+    #[test]
+    fn a_standby_takes_over_while_the_workloads_keep_running() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("primary.lock");
+
+        let primary = PrimaryLock::try_acquire(&path).unwrap().unwrap();
+        // A VM of the primary, whose VMM runs in a session of its own.
+        let mut vm = Command::new("sleep")
+            .arg("60")
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let standby = std::thread::spawn({
+            let path = path.clone();
+            move || PrimaryLock::acquire(&path)
+        });
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(!standby.is_finished());
+
+        // The primary crashes. Its VM does not hold the lock.
+        drop(primary);
+        let _standby = standby.join().unwrap().unwrap();
+        assert_eq!(primary_pid(&path), Some(std::process::id()));
+        assert!(vm.try_wait().unwrap().is_none());
+
+        vm.kill().unwrap();
+        vm.wait().unwrap();
+    }
+}
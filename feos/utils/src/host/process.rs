@@ -0,0 +1,96 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Usage of host processes, e.g. of the hypervisors of VMs, as `/proc` has
+//! it. Values are of all threads of a process.
+
+use nix::unistd::{sysconf, SysconfVar};
+use std::io;
+use std::time::Duration;
+use tokio::fs;
+
+/// Clock ticks per second of the CPU times in `/proc` if sysconf does not
+/// tell, the value on all architectures FeOS runs on.
+const DEFAULT_CLOCK_TICKS: u64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessStats {
+    /// CPU time in user and kernel mode since the process started.
+    pub cpu_time: Duration,
+    /// Resident memory.
+    pub rss_bytes: u64,
+}
+
+fn clock_ticks() -> u64 {
+    match sysconf(SysconfVar::CLK_TCK) {
+        Ok(Some(ticks)) if ticks > 0 => ticks as u64,
+        _ => DEFAULT_CLOCK_TICKS,
+    }
+}
+
+fn invalid(pid: i64, file: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unexpected format of /proc/{pid}/{file}"),
+    )
+}
+
+/// The CPU time of `/proc/<pid>/stat`. The name of the command comes second
+/// and may contain spaces and parentheses, so the fields are counted from
+/// the last `)`.
+fn parse_cpu_time(stat: &str, clock_ticks: u64) -> Option<Duration> {
+    let (_, fields) = stat.rsplit_once(')')?;
+    let mut fields = fields.split_whitespace().skip(11);
+    let utime: u64 = fields.next()?.parse().ok()?;
+    let stime: u64 = fields.next()?.parse().ok()?;
+    let ticks = utime + stime;
+    Some(
+        Duration::from_secs(ticks / clock_ticks)
+            + Duration::from_nanos((ticks % clock_ticks) * 1_000_000_000 / clock_ticks),
+    )
+}
+
+/// The resident memory of `/proc/<pid>/status`. Kernel threads have none.
+fn parse_rss_bytes(status: &str) -> Option<u64> {
+    let Some(line) = status.lines().find(|line| line.starts_with("VmRSS:")) else {
+        return Some(0);
+    };
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// The usage of the process `pid`. Fails with `NotFound` once it is gone.
+pub async fn stats(pid: i64) -> io::Result<ProcessStats> {
+    let stat = fs::read_to_string(format!("/proc/{pid}/stat")).await?;
+    let status = fs::read_to_string(format!("/proc/{pid}/status")).await?;
+    Ok(ProcessStats {
+        cpu_time: parse_cpu_time(&stat, clock_ticks()).ok_or_else(|| invalid(pid, "stat"))?,
+        rss_bytes: parse_rss_bytes(&status).ok_or_else(|| invalid(pid, "status"))?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_time_and_memory_are_parsed_from_proc() {
+        let stat = "4242 (cloud-hyper (v)) S 1 4242 4242 0 -1 4194560 12345 0 0 0 \
+                    1250 375 0 0 20 0 6 0 98765 2147483648 262144 18446744073709551615";
+        assert_eq!(
+            parse_cpu_time(stat, 100),
+            Some(Duration::from_millis(16_250))
+        );
+        assert_eq!(
+            parse_cpu_time(stat, 1000),
+            Some(Duration::from_millis(1625))
+        );
+        assert_eq!(parse_cpu_time("4242 (cloud-hypervisor) S 1", 100), None);
+
+        let status =
+            "Name:\tcloud-hyperviso\nVmPeak:\t 2100000 kB\nVmRSS:\t 1048576 kB\nThreads:\t6\n";
+        assert_eq!(parse_rss_bytes(status), Some(1 << 30));
+        assert_eq!(parse_rss_bytes("Name:\tkthreadd\n"), Some(0));
+        assert_eq!(parse_rss_bytes("VmRSS:\tmany kB\n"), None);
+    }
+}
@@ -0,0 +1,134 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use nix::sys::stat::{major, minor};
+use nix::sys::termios::{self, BaudRate, ControlFlags, SetArg};
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::unix::fs::{FileTypeExt, MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+/// Kernel names of the serial ports that can be passed through to
+/// workloads: UARTs, USB serial adapters and modems, and ARM PL011 UARTs.
+const SERIAL_PORT_PREFIXES: [&str; 4] = ["ttyS", "ttyUSB", "ttyACM", "ttyAMA"];
+
+/// A serial port of the host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerialPort {
+    /// The device node, with links like `/dev/serial/by-id/...` resolved.
+    pub path: PathBuf,
+    pub major: u64,
+    pub minor: u64,
+}
+
+fn is_serial_port_name(name: &str) -> bool {
+    SERIAL_PORT_PREFIXES.iter().any(|prefix| {
+        name.strip_prefix(prefix)
+            .is_some_and(|index| !index.is_empty() && index.chars().all(|c| c.is_ascii_digit()))
+    })
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// Looks up the serial port at `path`, e.g. `/dev/ttyUSB0`.
+pub fn serial_port(path: &str) -> io::Result<SerialPort> {
+    if !path.starts_with("/dev/") {
+        return Err(invalid_input(format!(
+            "'{path}' is not a device node under /dev"
+        )));
+    }
+    let resolved = fs::canonicalize(path)?;
+    let name = resolved
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let metadata = fs::metadata(&resolved)?;
+    if resolved.parent() != Some(Path::new("/dev"))
+        || !is_serial_port_name(&name)
+        || !metadata.file_type().is_char_device()
+    {
+        return Err(invalid_input(format!("'{path}' is not a serial port")));
+    }
+    Ok(SerialPort {
+        major: major(metadata.rdev()),
+        minor: minor(metadata.rdev()),
+        path: resolved,
+    })
+}
+
+fn baud_rate(rate: u32) -> io::Result<BaudRate> {
+    Ok(match rate {
+        1200 => BaudRate::B1200,
+        2400 => BaudRate::B2400,
+        4800 => BaudRate::B4800,
+        9600 => BaudRate::B9600,
+        19200 => BaudRate::B19200,
+        38400 => BaudRate::B38400,
+        57600 => BaudRate::B57600,
+        115200 => BaudRate::B115200,
+        230400 => BaudRate::B230400,
+        460800 => BaudRate::B460800,
+        921600 => BaudRate::B921600,
+        _ => return Err(invalid_input(format!("Unsupported baud rate {rate}"))),
+    })
+}
+
+/// Checks a baud rate before a serial port is opened with it. 0 keeps the
+/// rate the port has.
+pub fn validate_baud_rate(rate: u32) -> io::Result<()> {
+    if rate != 0 {
+        baud_rate(rate)?;
+    }
+    Ok(())
+}
+
+/// Opens a serial port in raw mode, so bytes pass through unaltered. A
+/// `baud_rate` of 0 keeps the rate the port has. The port does not wait for
+/// a carrier and does not become the controlling terminal of FeOS.
+pub fn open_serial_port(port: &SerialPort, rate: u32) -> io::Result<File> {
+    // Without O_NONBLOCK, opening a port without carrier would block until
+    // CLOCAL is set.
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NOCTTY | libc::O_NONBLOCK)
+        .open(&port.path)?;
+    let mut attributes = termios::tcgetattr(&file)?;
+    termios::cfmakeraw(&mut attributes);
+    attributes.control_flags |= ControlFlags::CLOCAL | ControlFlags::CREAD;
+    if rate != 0 {
+        termios::cfsetspeed(&mut attributes, baud_rate(rate)?)?;
+    }
+    termios::tcsetattr(&file, SetArg::TCSANOW, &attributes)?;
+    fcntl(&file, FcntlArg::F_SETFL(OFlag::empty()))?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_serial_ports_are_passed_through() {
+        for name in ["ttyS0", "ttyS12", "ttyUSB0", "ttyACM1", "ttyAMA0"] {
+            assert!(is_serial_port_name(name), "{name}");
+        }
+        for name in ["tty0", "ttyS", "ttyUSBa", "ttyS0p", "sda", "null"] {
+            assert!(!is_serial_port_name(name), "{name}");
+        }
+        assert_eq!(
+            serial_port("/tmp/ttyS0").unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            serial_port("/dev/null").unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        assert!(validate_baud_rate(115200).is_ok());
+        assert!(validate_baud_rate(0).is_ok());
+        assert!(validate_baud_rate(12345).is_err());
+    }
+}
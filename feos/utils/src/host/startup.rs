@@ -0,0 +1,302 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::host::admission::WorkloadKind;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// How long automatic starts wait for dependencies that do not say.
+pub const DEFAULT_DEPENDENCY_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// A VM or container other workloads can be started after.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WorkloadRef {
+    pub kind: WorkloadKind,
+    pub id: String,
+}
+
+impl WorkloadRef {
+    pub fn new(kind: WorkloadKind, id: impl Into<String>) -> Self {
+        Self {
+            kind,
+            id: id.into(),
+        }
+    }
+}
+
+impl fmt::Display for WorkloadRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.kind, self.id)
+    }
+}
+
+fn join(workloads: &[WorkloadRef], separator: &str) -> String {
+    workloads
+        .iter()
+        .map(WorkloadRef::to_string)
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StartupError {
+    UnknownDependency(WorkloadRef),
+    /// The workloads, each to be started after the next one and the last
+    /// after the first.
+    Cycle(Vec<WorkloadRef>),
+    /// The dependencies that were not running in time.
+    Timeout(Vec<WorkloadRef>),
+}
+
+impl fmt::Display for StartupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StartupError::UnknownDependency(dependency) => {
+                write!(f, "dependency {dependency} does not exist")
+            }
+            StartupError::Cycle(cycle) => {
+                write!(
+                    f,
+                    "dependency cycle: {} -> {}",
+                    join(cycle, " -> "),
+                    cycle[0]
+                )
+            }
+            StartupError::Timeout(missing) => {
+                write!(f, "timed out waiting for {}", join(missing, ", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for StartupError {}
+
+/// The dependencies of all workloads and which of them are running, shared
+/// by the services that start workloads by themselves, e.g. at boot.
+#[derive(Clone)]
+pub struct StartupOrder {
+    dependencies: Arc<Mutex<HashMap<WorkloadRef, Vec<WorkloadRef>>>>,
+    running: watch::Sender<HashSet<WorkloadRef>>,
+    network_ready: watch::Sender<bool>,
+}
+
+impl Default for StartupOrder {
+    fn default() -> Self {
+        Self {
+            dependencies: Arc::default(),
+            running: watch::Sender::new(HashSet::new()),
+            network_ready: watch::Sender::new(false),
+        }
+    }
+}
+
+/// The path from `from` over its dependencies back to `to`, if any.
+fn path_to(
+    dependencies: &HashMap<WorkloadRef, Vec<WorkloadRef>>,
+    from: &WorkloadRef,
+    to: &WorkloadRef,
+    visited: &mut HashSet<WorkloadRef>,
+) -> Option<Vec<WorkloadRef>> {
+    if from == to {
+        return Some(vec![from.clone()]);
+    }
+    if !visited.insert(from.clone()) {
+        return None;
+    }
+    for next in dependencies.get(from).into_iter().flatten() {
+        if let Some(mut path) = path_to(dependencies, next, to, visited) {
+            path.insert(0, from.clone());
+            return Some(path);
+        }
+    }
+    None
+}
+
+fn find_cycle(
+    dependencies: &HashMap<WorkloadRef, Vec<WorkloadRef>>,
+    workload: &WorkloadRef,
+    after: &[WorkloadRef],
+) -> Option<Vec<WorkloadRef>> {
+    let mut visited = HashSet::new();
+    after.iter().find_map(|dependency| {
+        let mut cycle = path_to(dependencies, dependency, workload, &mut visited)?;
+        cycle.pop();
+        cycle.insert(0, workload.clone());
+        Some(cycle)
+    })
+}
+
+impl StartupOrder {
+    fn dependencies(&self) -> std::sync::MutexGuard<'_, HashMap<WorkloadRef, Vec<WorkloadRef>>> {
+        self.dependencies
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Adds a new workload to be started after `after`, which must exist and
+    /// must not be started after the workload themselves.
+    pub fn register(
+        &self,
+        workload: WorkloadRef,
+        after: Vec<WorkloadRef>,
+    ) -> Result<(), StartupError> {
+        let mut dependencies = self.dependencies();
+        if let Some(unknown) = after.iter().find(|d| !dependencies.contains_key(*d)) {
+            return Err(StartupError::UnknownDependency(unknown.clone()));
+        }
+        if let Some(cycle) = find_cycle(&dependencies, &workload, &after) {
+            return Err(StartupError::Cycle(cycle));
+        }
+        dependencies.insert(workload, after);
+        Ok(())
+    }
+
+    /// Adds a workload that existed before FeOS started. Its dependencies
+    /// are checked when it is started.
+    pub fn restore(&self, workload: WorkloadRef, after: Vec<WorkloadRef>) {
+        self.dependencies().insert(workload, after);
+    }
+
+    pub fn remove(&self, workload: &WorkloadRef) {
+        self.dependencies().remove(workload);
+        self.set_running(workload, false);
+    }
+
+    pub fn set_running(&self, workload: &WorkloadRef, running: bool) {
+        self.running.send_if_modified(|set| {
+            if running {
+                set.insert(workload.clone())
+            } else {
+                set.remove(workload)
+            }
+        });
+    }
+
+    /// Lets the workloads that wait for the network start.
+    pub fn set_network_ready(&self) {
+        self.network_ready.send_replace(true);
+    }
+
+    /// Waits until the host network is up, which workloads started at boot
+    /// need before anything else.
+    pub async fn wait_for_network(&self) {
+        let mut ready = self.network_ready.subscribe();
+        // The sender lives in `self`, so the wait cannot fail.
+        let _ = ready.wait_for(|ready| *ready).await;
+    }
+
+    /// Waits until all dependencies of `workload` are running.
+    pub async fn wait_for_dependencies(
+        &self,
+        workload: &WorkloadRef,
+        timeout: Duration,
+    ) -> Result<(), StartupError> {
+        let after = {
+            let dependencies = self.dependencies();
+            let after = dependencies.get(workload).cloned().unwrap_or_default();
+            if let Some(cycle) = find_cycle(&dependencies, workload, &after) {
+                return Err(StartupError::Cycle(cycle));
+            }
+            after
+        };
+        if after.is_empty() {
+            return Ok(());
+        }
+
+        let mut running = self.running.subscribe();
+        let wait = running.wait_for(|set| after.iter().all(|d| set.contains(d)));
+        if matches!(tokio::time::timeout(timeout, wait).await, Ok(Ok(_))) {
+            return Ok(());
+        }
+        let set = self.running.borrow();
+        Err(StartupError::Timeout(
+            after.into_iter().filter(|d| !set.contains(d)).collect(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vm(id: &str) -> WorkloadRef {
+        WorkloadRef::new(WorkloadKind::Vm, id)
+    }
+
+    fn container(id: &str) -> WorkloadRef {
+        WorkloadRef::new(WorkloadKind::Container, id)
+    }
+
+    #[test]
+    fn dependencies_must_exist_and_must_not_form_cycles() {
+        let order = StartupOrder::default();
+        order.register(container("db"), vec![]).unwrap();
+        order.register(vm("app"), vec![container("db")]).unwrap();
+        assert_eq!(
+            order.register(vm("web"), vec![vm("cache")]),
+            Err(StartupError::UnknownDependency(vm("cache")))
+        );
+
+        // Recreating the container to be started after the VM closes a loop.
+        order.remove(&container("db"));
+        let err = order
+            .register(container("db"), vec![vm("app")])
+            .unwrap_err();
+        assert_eq!(err, StartupError::Cycle(vec![container("db"), vm("app")]));
+        assert_eq!(
+            err.to_string(),
+            "dependency cycle: container db -> VM app -> container db"
+        );
+        assert_eq!(
+            order.register(vm("self"), vec![vm("self")]),
+            Err(StartupError::UnknownDependency(vm("self")))
+        );
+    }
+
+    #[tokio::test]
+    async fn workloads_wait_for_their_dependencies_to_run() {
+        let order = StartupOrder::default();
+        order.restore(vm("app"), vec![container("db"), vm("dns")]);
+        order.restore(container("db"), vec![]);
+        order.restore(vm("dns"), vec![]);
+        order.set_running(&vm("dns"), true);
+
+        assert_eq!(
+            order
+                .wait_for_dependencies(&vm("app"), Duration::from_millis(10))
+                .await,
+            Err(StartupError::Timeout(vec![container("db")]))
+        );
+
+        let waiter = {
+            let order = order.clone();
+            tokio::spawn(async move {
+                order
+                    .wait_for_dependencies(&vm("app"), Duration::from_secs(10))
+                    .await
+            })
+        };
+        order.set_running(&container("db"), true);
+        assert_eq!(waiter.await.unwrap(), Ok(()));
+
+        let network = {
+            let order = order.clone();
+            tokio::spawn(async move { order.wait_for_network().await })
+        };
+        tokio::task::yield_now().await;
+        assert!(!network.is_finished());
+        order.set_network_ready();
+        network.await.unwrap();
+
+        order.restore(vm("dns"), vec![vm("app")]);
+        assert!(matches!(
+            order
+                .wait_for_dependencies(&vm("app"), Duration::from_secs(10))
+                .await,
+            Err(StartupError::Cycle(_))
+        ));
+    }
+}
@@ -4,5 +4,7 @@
 pub mod feos_logger;
 pub mod filesystem;
 pub mod host;
+pub mod namespace;
 pub mod network;
+pub mod sqlite;
 pub mod version;
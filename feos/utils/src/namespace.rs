@@ -0,0 +1,87 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt;
+
+/// The namespace of resources created without one.
+pub const DEFAULT_NAMESPACE: &str = "default";
+const MAX_LABEL_LEN: usize = 63;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidName {
+    kind: &'static str,
+    value: String,
+}
+
+impl fmt::Display for InvalidName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid {} '{}': must be at most {MAX_LABEL_LEN} lowercase letters, digits and '-', starting and ending with a letter or digit",
+            self.kind, self.value
+        )
+    }
+}
+
+impl std::error::Error for InvalidName {}
+
+/// Whether `value` is a DNS label like `tenant-a`, the form namespaces and
+/// names take so they can be used in host names and paths.
+fn is_label(value: &str) -> bool {
+    let alphanumeric = |b: u8| b.is_ascii_lowercase() || b.is_ascii_digit();
+    let bytes = value.as_bytes();
+    match (bytes.first(), bytes.last()) {
+        (Some(&first), Some(&last)) => {
+            bytes.len() <= MAX_LABEL_LEN
+                && alphanumeric(first)
+                && alphanumeric(last)
+                && bytes.iter().all(|&b| alphanumeric(b) || b == b'-')
+        }
+        _ => false,
+    }
+}
+
+/// The namespace a request refers to, the default one if it is empty.
+pub fn namespace_or_default(namespace: &str) -> Result<String, InvalidName> {
+    if namespace.is_empty() {
+        return Ok(DEFAULT_NAMESPACE.to_string());
+    }
+    if !is_label(namespace) {
+        return Err(InvalidName {
+            kind: "namespace",
+            value: namespace.to_string(),
+        });
+    }
+    Ok(namespace.to_string())
+}
+
+/// Checks the name of a resource, which only has to be unique within its
+/// namespace.
+pub fn validate_name(name: &str) -> Result<(), InvalidName> {
+    if !is_label(name) {
+        return Err(InvalidName {
+            kind: "name",
+            value: name.to_string(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn namespaces_and_names_are_dns_labels() {
+        assert_eq!(namespace_or_default("").unwrap(), DEFAULT_NAMESPACE);
+        assert_eq!(namespace_or_default("tenant-a").unwrap(), "tenant-a");
+        assert!(namespace_or_default("Tenant").is_err());
+        assert!(namespace_or_default("-tenant").is_err());
+        assert!(namespace_or_default("tenant/a").is_err());
+
+        assert!(validate_name("web-1").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("web-").is_err());
+        assert!(validate_name(&"a".repeat(64)).is_err());
+    }
+}
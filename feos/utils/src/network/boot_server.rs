@@ -0,0 +1,267 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! A DHCPv6 server for the tap device of a single guest, which hands out the
+//! boot file URL and parameters the guest firmware netboots from (RFC 5970).
+
+use super::dhcpv6::create_multicast_socket;
+use dhcproto::v6::*;
+use dhcproto::{Decodable, Decoder, Encodable};
+use log::{debug, info, warn};
+use nix::net::if_::if_nametoindex;
+use std::io;
+use std::net::Ipv6Addr;
+
+const DHCPV6_SERVER_PORT: u16 = 547;
+/// How long the guest may keep its boot address. The firmware only needs it
+/// until the operating system configures the network itself.
+const LEASE_SECONDS: u32 = 3600;
+/// The vendor class UEFI firmware sends when doing HTTP boot. Offers without
+/// it are taken for plain address assignment.
+const HTTP_CLIENT_CLASS: &[u8] = b"HTTPClient";
+const DUID_LL: u16 = 3;
+const HTYPE_ETHERNET: u16 = 1;
+
+/// What a guest is told when it netboots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BootOffer {
+    /// The address the guest uses while booting.
+    pub address: Ipv6Addr,
+    /// Where the firmware fetches the boot file from, e.g.
+    /// `http://[2001:db8::1]/ipxe.efi` or `tftp://[2001:db8::1]/pxelinux.0`.
+    pub boot_url: String,
+    /// Passed to the boot file, e.g. an iPXE script URL or kernel arguments.
+    pub boot_params: Vec<String>,
+}
+
+/// Answers the DHCPv6 messages of the guest behind `interface` with `offer`
+/// until the socket fails, e.g. because the device went away.
+pub async fn serve(interface: &str, offer: BootOffer) -> io::Result<()> {
+    let index = if_nametoindex(interface)?;
+    let socket = create_multicast_socket(interface, index, DHCPV6_SERVER_PORT)
+        .map_err(|e| io::Error::other(format!("Failed to listen on {interface}: {e}")))?;
+    let server_id = server_duid(interface).await?;
+    info!(
+        "BootServer ({interface}): Offering {} with {}",
+        offer.address, offer.boot_url
+    );
+
+    let mut buf = vec![0u8; 1500];
+    loop {
+        let (len, client) = socket.recv_from(&mut buf).await?;
+        let request = match Message::decode(&mut Decoder::new(&buf[..len])) {
+            Ok(request) => request,
+            Err(e) => {
+                debug!("BootServer ({interface}): Ignoring malformed message from {client}: {e}");
+                continue;
+            }
+        };
+        let Some(reply) = answer(&request, &offer, &server_id) else {
+            continue;
+        };
+        let bytes = match reply.to_vec() {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                warn!("BootServer ({interface}): Failed to encode reply: {e}");
+                continue;
+            }
+        };
+        debug!(
+            "BootServer ({interface}): {:?} from {client}, answering with {:?}",
+            request.msg_type(),
+            reply.msg_type()
+        );
+        socket.send_to(&bytes, client).await?;
+    }
+}
+
+/// The DUID-LL of the server, made of the MAC address of the tap device.
+async fn server_duid(interface: &str) -> io::Result<Vec<u8>> {
+    let address = tokio::fs::read_to_string(format!("/sys/class/net/{interface}/address")).await?;
+    let mac = address
+        .trim()
+        .split(':')
+        .map(|octet| u8::from_str_radix(octet, 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let mut duid = Vec::with_capacity(4 + mac.len());
+    duid.extend(DUID_LL.to_be_bytes());
+    duid.extend(HTYPE_ETHERNET.to_be_bytes());
+    duid.extend(mac);
+    Ok(duid)
+}
+
+/// The reply to `request`, if the server answers it at all.
+pub fn answer(request: &Message, offer: &BootOffer, server_id: &[u8]) -> Option<Message> {
+    let opts = request.opts();
+    let Some(DhcpOption::ClientId(client_id)) = opts.get(OptionCode::ClientId) else {
+        return None;
+    };
+    if let Some(DhcpOption::ServerId(id)) = opts.get(OptionCode::ServerId) {
+        if id != server_id {
+            return None;
+        }
+    }
+    let rapid_commit = opts.get(OptionCode::RapidCommit).is_some();
+    let (msg_type, assign) = match request.msg_type() {
+        MessageType::Solicit if rapid_commit => (MessageType::Reply, true),
+        MessageType::Solicit => (MessageType::Advertise, true),
+        MessageType::Request | MessageType::Renew | MessageType::Rebind => {
+            (MessageType::Reply, true)
+        }
+        MessageType::InformationRequest => (MessageType::Reply, false),
+        _ => return None,
+    };
+
+    let mut reply = Message::new_with_id(msg_type, request.xid());
+    let reply_opts = reply.opts_mut();
+    reply_opts.insert(DhcpOption::ClientId(client_id.clone()));
+    reply_opts.insert(DhcpOption::ServerId(server_id.to_vec()));
+    if msg_type == MessageType::Reply && request.msg_type() == MessageType::Solicit {
+        reply_opts.insert(DhcpOption::RapidCommit);
+    }
+    if assign {
+        if let Some(DhcpOption::IANA(iana)) = opts.get(OptionCode::IANA) {
+            let mut addresses = DhcpOptions::new();
+            addresses.insert(DhcpOption::IAAddr(IAAddr {
+                addr: offer.address,
+                preferred_life: LEASE_SECONDS,
+                valid_life: LEASE_SECONDS,
+                opts: DhcpOptions::new(),
+            }));
+            reply_opts.insert(DhcpOption::IANA(IANA {
+                id: iana.id,
+                t1: LEASE_SECONDS / 2,
+                t2: LEASE_SECONDS / 5 * 4,
+                opts: addresses,
+            }));
+        }
+    }
+    if let Some(DhcpOption::VendorClass(class)) = opts.get(OptionCode::VendorClass) {
+        if class
+            .data
+            .iter()
+            .any(|data| data.starts_with(HTTP_CLIENT_CLASS))
+        {
+            // Encoded by hand, dhcproto gets the length of the option wrong.
+            let mut data = class.num.to_be_bytes().to_vec();
+            data.extend(length_prefixed(HTTP_CLIENT_CLASS));
+            reply_opts.insert(DhcpOption::Unknown(UnknownOption::new(
+                OptionCode::VendorClass,
+                data,
+            )));
+        }
+    }
+    reply_opts.insert(DhcpOption::Unknown(UnknownOption::new(
+        OptionCode::OptBootfileUrl,
+        offer.boot_url.clone().into_bytes(),
+    )));
+    if !offer.boot_params.is_empty() {
+        let params = offer
+            .boot_params
+            .iter()
+            .flat_map(|param| length_prefixed(param.as_bytes()))
+            .collect();
+        reply_opts.insert(DhcpOption::Unknown(UnknownOption::new(
+            OptionCode::OptBootfileParam,
+            params,
+        )));
+    }
+    Some(reply)
+}
+
+/// `data` preceded by its length, as the items of vendor classes and boot
+/// file parameters are.
+fn length_prefixed(data: &[u8]) -> Vec<u8> {
+    let mut item = (data.len() as u16).to_be_bytes().to_vec();
+    item.extend(data);
+    item
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVER_ID: &[u8] = &[0, 3, 0, 1, 0x02, 0, 0, 0, 0, 1];
+
+    fn offer() -> BootOffer {
+        BootOffer {
+            address: "2001:db8::10".parse().unwrap(),
+            boot_url: "http://[2001:db8::1]/ipxe.efi".to_string(),
+            boot_params: vec!["dhcp".to_string(), "chain boot.ipxe".to_string()],
+        }
+    }
+
+    fn solicit() -> Message {
+        let mut solicit = Message::new_with_id(MessageType::Solicit, [1, 2, 3]);
+        let opts = solicit.opts_mut();
+        opts.insert(DhcpOption::ClientId(vec![
+            0, 3, 0, 1, 0x52, 0x54, 0, 0, 0, 1,
+        ]));
+        opts.insert(DhcpOption::IANA(IANA {
+            id: 7,
+            t1: 0,
+            t2: 0,
+            opts: DhcpOptions::new(),
+        }));
+        opts.insert(DhcpOption::VendorClass(VendorClass {
+            num: 343,
+            data: vec![b"HTTPClient:Arch:00016:UNDI:003001".to_vec()],
+        }));
+        solicit
+    }
+
+    #[test]
+    fn http_boot_clients_get_an_address_and_the_boot_file() {
+        let advertise = answer(&solicit(), &offer(), SERVER_ID).unwrap();
+        assert_eq!(advertise.msg_type(), MessageType::Advertise);
+        assert_eq!(advertise.xid(), [1, 2, 3]);
+
+        // The reply has to survive the wire, as the firmware sees it.
+        let bytes = advertise.to_vec().unwrap();
+        let decoded = Message::decode(&mut Decoder::new(&bytes)).unwrap();
+        let opts = decoded.opts();
+        let Some(DhcpOption::IANA(iana)) = opts.get(OptionCode::IANA) else {
+            panic!("No IA_NA in {decoded:?}");
+        };
+        assert_eq!(iana.id, 7);
+        assert!(matches!(
+            iana.opts.get(OptionCode::IAAddr),
+            Some(DhcpOption::IAAddr(address)) if address.addr == offer().address
+        ));
+        assert!(matches!(
+            opts.get(OptionCode::VendorClass),
+            Some(DhcpOption::VendorClass(class)) if class.data == [HTTP_CLIENT_CLASS]
+        ));
+        let Some(DhcpOption::Unknown(url)) = opts.get(OptionCode::OptBootfileUrl) else {
+            panic!("No boot file URL in {decoded:?}");
+        };
+        assert_eq!(url.data(), offer().boot_url.as_bytes());
+        let Some(DhcpOption::Unknown(params)) = opts.get(OptionCode::OptBootfileParam) else {
+            panic!("No boot file parameters in {decoded:?}");
+        };
+        assert_eq!(&params.data()[..6], b"\0\x04dhcp");
+
+        let mut request = solicit();
+        request.set_msg_type(MessageType::Request);
+        request
+            .opts_mut()
+            .insert(DhcpOption::ServerId(SERVER_ID.to_vec()));
+        let reply = answer(&request, &offer(), SERVER_ID).unwrap();
+        assert_eq!(reply.msg_type(), MessageType::Reply);
+    }
+
+    #[test]
+    fn messages_for_other_servers_are_ignored() {
+        let mut request = solicit();
+        request.set_msg_type(MessageType::Request);
+        request
+            .opts_mut()
+            .insert(DhcpOption::ServerId(vec![0, 3, 0, 1, 0x02, 0, 0, 0, 0, 2]));
+        assert!(answer(&request, &offer(), SERVER_ID).is_none());
+
+        let mut release = solicit();
+        release.set_msg_type(MessageType::Release);
+        assert!(answer(&release, &offer(), SERVER_ID).is_none());
+    }
+}
@@ -0,0 +1,169 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! The connections the kernel tracks, as listed in `/proc/net/nf_conntrack`.
+
+use std::io;
+use std::net::IpAddr;
+
+const CONNTRACK_TABLE: &str = "/proc/net/nf_conntrack";
+/// Without accounting, entries have no packet and byte counters.
+const CONNTRACK_ACCOUNTING: &str = "/proc/sys/net/netfilter/nf_conntrack_acct";
+
+/// One direction of a connection, as the kernel saw its first packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tuple {
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    /// Ports for TCP, UDP and SCTP, zero for other protocols.
+    pub sport: u16,
+    pub dport: u16,
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Connection {
+    /// E.g. `tcp`, `udp` or `icmpv6`.
+    pub protocol: String,
+    /// The TCP state, e.g. `ESTABLISHED`, empty for other protocols.
+    pub state: String,
+    /// The direction of the packet that set up the connection.
+    pub original: Tuple,
+    /// The direction of the answers, with addresses after NAT.
+    pub reply: Tuple,
+}
+
+#[derive(Default)]
+struct PartialTuple {
+    src: Option<IpAddr>,
+    dst: Option<IpAddr>,
+    sport: u16,
+    dport: u16,
+    packets: u64,
+    bytes: u64,
+}
+
+impl PartialTuple {
+    fn finish(self) -> Option<Tuple> {
+        Some(Tuple {
+            src: self.src?,
+            dst: self.dst?,
+            sport: self.sport,
+            dport: self.dport,
+            packets: self.packets,
+            bytes: self.bytes,
+        })
+    }
+}
+
+/// Parses a line of `/proc/net/nf_conntrack`, e.g.
+/// `ipv4 2 tcp 6 431999 ESTABLISHED src=10.0.0.2 dst=10.0.0.1 sport=40000
+/// dport=443 packets=3 bytes=180 src=10.0.0.1 dst=10.0.0.2 sport=443
+/// dport=40000 packets=2 bytes=120 [ASSURED] mark=0 zone=0 use=2`.
+pub fn parse_connection(line: &str) -> Option<Connection> {
+    let mut fields = line.split_whitespace();
+    let protocol = fields.nth(2)?.to_string();
+    // The protocol number and the seconds until the entry expires.
+    fields.nth(1)?;
+
+    let mut state = String::new();
+    let mut tuples = [PartialTuple::default(), PartialTuple::default()];
+    let mut current = 0;
+    for field in fields {
+        let Some((key, value)) = field.split_once('=') else {
+            if !field.starts_with('[') && current == 0 && tuples[0].src.is_none() {
+                state = field.to_string();
+            }
+            continue;
+        };
+        match key {
+            // The second tuple starts with its source.
+            "src" if tuples[current].src.is_some() => {
+                current += 1;
+                if current == tuples.len() {
+                    break;
+                }
+                tuples[current].src = value.parse().ok();
+            }
+            "src" => tuples[current].src = value.parse().ok(),
+            "dst" => tuples[current].dst = value.parse().ok(),
+            "sport" => tuples[current].sport = value.parse().ok()?,
+            "dport" => tuples[current].dport = value.parse().ok()?,
+            "packets" => tuples[current].packets = value.parse().ok()?,
+            "bytes" => tuples[current].bytes = value.parse().ok()?,
+            _ => {}
+        }
+    }
+    let [original, reply] = tuples;
+    Some(Connection {
+        protocol,
+        state,
+        original: original.finish()?,
+        reply: reply.finish()?,
+    })
+}
+
+/// The connections the kernel tracks. Fails if the kernel has no connection
+/// tracking or does not list it in procfs.
+pub async fn connections() -> io::Result<Vec<Connection>> {
+    let content = tokio::fs::read_to_string(CONNTRACK_TABLE).await?;
+    Ok(content.lines().filter_map(parse_connection).collect())
+}
+
+/// Makes the kernel count the packets and bytes of the connections set up
+/// from now on.
+pub async fn enable_accounting() -> io::Result<()> {
+    tokio::fs::write(CONNTRACK_ACCOUNTING, b"1").await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connections_are_parsed_with_both_directions() {
+        let tcp = parse_connection(
+            "ipv4     2 tcp      6 431999 ESTABLISHED src=10.0.0.2 dst=10.0.0.1 sport=40000 \
+             dport=443 packets=3 bytes=180 src=10.0.0.1 dst=10.0.0.2 sport=443 dport=40000 \
+             packets=2 bytes=120 [ASSURED] mark=0 zone=0 use=2",
+        )
+        .unwrap();
+        assert_eq!(tcp.protocol, "tcp");
+        assert_eq!(tcp.state, "ESTABLISHED");
+        assert_eq!(
+            tcp.original,
+            Tuple {
+                src: "10.0.0.2".parse().unwrap(),
+                dst: "10.0.0.1".parse().unwrap(),
+                sport: 40000,
+                dport: 443,
+                packets: 3,
+                bytes: 180,
+            }
+        );
+        assert_eq!(tcp.reply.sport, 443);
+        assert_eq!(tcp.reply.bytes, 120);
+
+        let udp = parse_connection(
+            "ipv6     10 udp      17 29 src=2001:0db8:0000:0000:0000:0000:0000:0002 \
+             dst=2001:0db8:0000:0000:0000:0000:0000:0053 sport=5353 dport=53 [UNREPLIED] \
+             src=2001:0db8:0000:0000:0000:0000:0000:0053 \
+             dst=2001:0db8:0000:0000:0000:0000:0000:0002 sport=53 dport=5353 mark=0 zone=0 use=2",
+        )
+        .unwrap();
+        assert_eq!(udp.state, "");
+        assert_eq!(udp.original.src, "2001:db8::2".parse::<IpAddr>().unwrap());
+        assert_eq!(udp.reply.src, "2001:db8::53".parse::<IpAddr>().unwrap());
+        assert_eq!(udp.original.bytes, 0);
+
+        let icmp = parse_connection(
+            "ipv4     2 icmp     1 29 src=10.0.0.2 dst=10.0.0.1 type=8 code=0 id=7 \
+             src=10.0.0.1 dst=10.0.0.2 type=0 code=0 id=7 mark=0 zone=0 use=2",
+        )
+        .unwrap();
+        assert_eq!((icmp.original.sport, icmp.reply.dport), (0, 0));
+
+        assert_eq!(parse_connection("ipv4 2 tcp 6"), None);
+    }
+}
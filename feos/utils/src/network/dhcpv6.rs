@@ -210,6 +210,7 @@ pub struct Dhcpv6Result {
     pub address: Ipv6Addr,
     pub prefix: Option<PrefixInfo>,
     pub ntp_servers: Vec<Ipv6Addr>,
+    pub dns_servers: Vec<Ipv6Addr>,
 }
 
 pub async fn run_dhcpv6_client(
@@ -223,6 +224,7 @@ pub async fn run_dhcpv6_client(
     let mut ia_addr_confirm: Option<DhcpOption> = None;
     let mut ia_pd_confirm: Option<IAPrefix> = None;
     let mut ntp_servers: Vec<Ipv6Addr> = Vec::new();
+    let mut dns_servers: Vec<Ipv6Addr> = Vec::new();
 
     let interface_index = get_interface_index(interface_name.clone()).await?;
     let socket = create_multicast_socket(&interface_name, interface_index, 546)?;
@@ -368,8 +370,9 @@ pub async fn run_dhcpv6_client(
                         .insert(DhcpOption::IAPD(iapd_instance));
                 }
 
-                // Pass through ORO again to ensure we get NTP in Reply
+                // Pass through ORO again to ensure we get DNS and NTP in Reply
                 let mut oro = ORO { opts: Vec::new() };
+                oro.opts.push(OptionCode::DomainNameServers);
                 oro.opts.push(OptionCode::NtpServer); // Option 56 (RFC 5908 NTP)
                 request_msg.opts_mut().insert(DhcpOption::ORO(oro));
 
@@ -402,6 +405,13 @@ pub async fn run_dhcpv6_client(
                     }
                 }
 
+                if let Some(DhcpOption::DomainNameServers(servers)) =
+                    response.opts().get(OptionCode::DomainNameServers)
+                {
+                    dns_servers.clone_from(servers);
+                    info!("Received DNS servers from DHCP: {dns_servers:?}");
+                }
+
                 if !ntp_servers.is_empty() {
                     info!("Received NTP servers from DHCP: {ntp_servers:?}");
                 } else {
@@ -451,6 +461,7 @@ pub async fn run_dhcpv6_client(
             address: ia_a.addr,
             prefix: prefix_info,
             ntp_servers,
+            dns_servers,
         });
     }
 
@@ -486,7 +497,7 @@ pub async fn get_interface_index(interface_name: String) -> io::Result<u32> {
     .await?
 }
 
-fn create_multicast_socket(
+pub(crate) fn create_multicast_socket(
     interface_name: &str,
     interface_index: u32,
     lport: u16,
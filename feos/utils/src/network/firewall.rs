@@ -0,0 +1,415 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! The host firewall, an nftables table filtering what arrives on the host
+//! interfaces for the host itself. Its profile lets through answers to
+//! connections of the host, the ICMP and DHCPv6 the host configures its
+//! network with, the API, SSH if enabled and the exceptions, and drops the
+//! rest. Traffic of workloads, which arrives on their taps or is forwarded,
+//! is not filtered.
+
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+const NFT_BIN: &str = "nft";
+const TABLE: &str = "inet feos_host";
+pub const SSH_PORT: u16 = 22;
+const DHCPV6_SERVER_PORT: u16 = 547;
+const DHCPV6_CLIENT_PORT: u16 = 546;
+const MAX_NAME_LEN: usize = 32;
+/// The exceptions as `<name> <protocol> <port> <sources>` lines, with the
+/// sources separated by commas or `-` for any source, so they are applied
+/// again after a reboot of the host. Relative to the root directory, so
+/// tests can use a fake one.
+const EXCEPTIONS_FILE: &str = "var/lib/feos/firewall-exceptions";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl Protocol {
+    fn as_str(self) -> &'static str {
+        match self {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+        }
+    }
+
+    fn parse(protocol: &str) -> Option<Self> {
+        match protocol {
+            "tcp" => Some(Protocol::Tcp),
+            "udp" => Some(Protocol::Udp),
+            _ => None,
+        }
+    }
+}
+
+/// What the firewall lets through besides its exceptions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    /// The interfaces filtered, e.g. the uplink.
+    pub interfaces: Vec<String>,
+    pub api_port: u16,
+    pub ssh: bool,
+}
+
+/// Lets traffic to a port of the host through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exception {
+    pub name: String,
+    pub protocol: Protocol,
+    pub port: u16,
+    /// Addresses or networks, e.g. `2001:db8::/48`. Any source if empty.
+    pub sources: Vec<String>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// The address of a source like `10.0.0.0/8` or `2001:db8::1`. Networks
+/// must not have host bits set, nftables rejects them.
+fn parse_source(source: &str) -> io::Result<IpAddr> {
+    let (address, prefix_len) = match source.split_once('/') {
+        Some((address, prefix_len)) => (address, Some(prefix_len)),
+        None => (source, None),
+    };
+    let address: IpAddr = address
+        .parse()
+        .map_err(|_| invalid(format!("invalid source address '{source}'")))?;
+    let Some(prefix_len) = prefix_len else {
+        return Ok(address);
+    };
+    let prefix_len: u32 = prefix_len
+        .parse()
+        .map_err(|_| invalid(format!("invalid prefix length in source '{source}'")))?;
+    let host_bits = match address {
+        IpAddr::V4(v4) if prefix_len <= 32 => {
+            u128::from(u32::from(v4) & u32::MAX.checked_shr(prefix_len).unwrap_or(0))
+        }
+        IpAddr::V6(v6) if prefix_len <= 128 => {
+            u128::from(v6) & u128::MAX.checked_shr(prefix_len).unwrap_or(0)
+        }
+        _ => {
+            return Err(invalid(format!(
+                "prefix length of source '{source}' is too long"
+            )))
+        }
+    };
+    if host_bits != 0 {
+        return Err(invalid(format!("source '{source}' has host bits set")));
+    }
+    Ok(address)
+}
+
+pub fn validate(exception: &Exception) -> io::Result<()> {
+    let name = &exception.name;
+    if name.is_empty()
+        || name.len() > MAX_NAME_LEN
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(invalid(format!(
+            "invalid exception name '{name}', use up to {MAX_NAME_LEN} letters, digits, '-' and '_'"
+        )));
+    }
+    if exception.port == 0 {
+        return Err(invalid(format!("exception '{name}' has no port")));
+    }
+    for source in &exception.sources {
+        parse_source(source)?;
+    }
+    Ok(())
+}
+
+/// The rules accepting the traffic of `exceptions`, one per address family
+/// of their sources.
+fn exception_rules(exceptions: &[Exception]) -> Vec<String> {
+    let mut rules = Vec::new();
+    for exception in exceptions {
+        let accept = format!(
+            "{} dport {} accept comment \"{}\"",
+            exception.protocol.as_str(),
+            exception.port,
+            exception.name
+        );
+        if exception.sources.is_empty() {
+            rules.push(accept);
+            continue;
+        }
+        let (v4, v6): (Vec<&String>, Vec<&String>) = exception
+            .sources
+            .iter()
+            .partition(|source| matches!(parse_source(source), Ok(IpAddr::V4(_))));
+        for (family, sources) in [("ip", v4), ("ip6", v6)] {
+            if sources.is_empty() {
+                continue;
+            }
+            let sources: Vec<&str> = sources.iter().map(|source| source.as_str()).collect();
+            rules.push(format!(
+                "{family} saddr {{ {} }} {accept}",
+                sources.join(", ")
+            ));
+        }
+    }
+    rules
+}
+
+fn chain(name: &str, rules: &[String]) -> String {
+    let rules: String = rules.iter().map(|rule| format!("\t\t{rule}\n")).collect();
+    format!("\tchain {name} {{\n{rules}\t}}\n")
+}
+
+/// The nftables script replacing the table of the firewall.
+pub fn ruleset(profile: &Profile, exceptions: &[Exception]) -> String {
+    let interfaces: Vec<String> = profile
+        .interfaces
+        .iter()
+        .map(|interface| format!("\"{interface}\""))
+        .collect();
+    let input = [
+        "type filter hook input priority filter; policy accept;".to_string(),
+        format!("iifname {{ {} }} jump host_input", interfaces.join(", ")),
+    ];
+    let mut host_input = vec![
+        "ct state established,related accept".to_string(),
+        "ct state invalid drop".to_string(),
+        "meta l4proto { icmp, ipv6-icmp } accept".to_string(),
+        format!("udp sport {DHCPV6_SERVER_PORT} udp dport {DHCPV6_CLIENT_PORT} accept"),
+        format!("tcp dport {} accept", profile.api_port),
+    ];
+    if profile.ssh {
+        host_input.push(format!("tcp dport {SSH_PORT} accept"));
+    }
+    host_input.push("jump exceptions".to_string());
+    host_input.push("drop".to_string());
+    format!(
+        "add table {TABLE}\ndelete table {TABLE}\ntable {TABLE} {{\n{}{}{}}}\n",
+        chain("input", &input),
+        chain("host_input", &host_input),
+        chain("exceptions", &exception_rules(exceptions)),
+    )
+}
+
+/// The nftables script replacing the exceptions only, which keeps the rest
+/// of the table as it was applied.
+fn exceptions_script(exceptions: &[Exception]) -> String {
+    let mut script = format!(
+        "add table {TABLE}\nadd chain {TABLE} exceptions\nflush chain {TABLE} exceptions\n"
+    );
+    for rule in exception_rules(exceptions) {
+        script.push_str(&format!("add rule {TABLE} exceptions {rule}\n"));
+    }
+    script
+}
+
+async fn nft(args: &[&str], script: Option<&str>) -> io::Result<()> {
+    let mut child = Command::new(NFT_BIN)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| io::Error::other(format!("failed to run {NFT_BIN}: {e}")))?;
+    if let (Some(script), Some(mut stdin)) = (script, child.stdin.take()) {
+        stdin.write_all(script.as_bytes()).await?;
+    }
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "{NFT_BIN} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+fn read_exceptions(root: &Path) -> io::Result<Vec<Exception>> {
+    let content = match fs::read_to_string(root.join(EXCEPTIONS_FILE)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    Ok(content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let exception = Exception {
+                name: fields.next()?.to_string(),
+                protocol: Protocol::parse(fields.next()?)?,
+                port: fields.next()?.parse().ok()?,
+                sources: match fields.next()? {
+                    "-" => Vec::new(),
+                    sources => sources.split(',').map(String::from).collect(),
+                },
+            };
+            validate(&exception).ok()?;
+            Some(exception)
+        })
+        .collect())
+}
+
+fn write_exceptions(root: &Path, exceptions: &[Exception]) -> io::Result<()> {
+    let path = root.join(EXCEPTIONS_FILE);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let content: String = exceptions
+        .iter()
+        .map(|exception| {
+            let sources = if exception.sources.is_empty() {
+                "-".to_string()
+            } else {
+                exception.sources.join(",")
+            };
+            format!(
+                "{} {} {} {sources}\n",
+                exception.name,
+                exception.protocol.as_str(),
+                exception.port
+            )
+        })
+        .collect();
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, content)?;
+    fs::rename(temp_path, path)
+}
+
+/// The recorded exceptions.
+pub fn exceptions() -> io::Result<Vec<Exception>> {
+    read_exceptions(Path::new("/"))
+}
+
+/// Applies the profile with the recorded exceptions, replacing the firewall
+/// applied before, e.g. by the daemon before an upgrade.
+pub async fn apply(profile: &Profile) -> io::Result<()> {
+    let exceptions = exceptions()?;
+    nft(&["-f", "-"], Some(&ruleset(profile, &exceptions))).await
+}
+
+/// Whether the profile is applied.
+pub async fn is_applied() -> bool {
+    nft(&[&format!("list chain {TABLE} input")], None)
+        .await
+        .is_ok()
+}
+
+/// Applies the recorded exceptions with `exception` added, or replacing the
+/// one of the same name, and records them.
+pub async fn add_exception(exception: Exception) -> io::Result<Vec<Exception>> {
+    validate(&exception)?;
+    let mut exceptions = exceptions()?;
+    match exceptions
+        .iter_mut()
+        .find(|recorded| recorded.name == exception.name)
+    {
+        Some(recorded) => *recorded = exception,
+        None => exceptions.push(exception),
+    }
+    nft(&["-f", "-"], Some(&exceptions_script(&exceptions))).await?;
+    write_exceptions(Path::new("/"), &exceptions)?;
+    Ok(exceptions)
+}
+
+/// Applies the recorded exceptions without the one named `name`, and
+/// forgets it.
+pub async fn remove_exception(name: &str) -> io::Result<Vec<Exception>> {
+    let mut exceptions = exceptions()?;
+    let recorded = exceptions.len();
+    exceptions.retain(|exception| exception.name != name);
+    if exceptions.len() == recorded {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("firewall exception '{name}' not found"),
+        ));
+    }
+    nft(&["-f", "-"], Some(&exceptions_script(&exceptions))).await?;
+    write_exceptions(Path::new("/"), &exceptions)?;
+    Ok(exceptions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exception(name: &str, port: u16, sources: &[&str]) -> Exception {
+        Exception {
+            name: name.to_string(),
+            protocol: Protocol::Tcp,
+            port,
+            sources: sources.iter().map(|source| source.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn only_the_profile_and_exceptions_are_let_through() {
+        let profile = Profile {
+            interfaces: vec!["eth0".to_string()],
+            api_port: 1337,
+            ssh: false,
+        };
+        let exceptions = [
+            exception("metrics", 9100, &["10.0.0.0/8", "2001:db8::/48"]),
+            exception("mirror", 5000, &[]),
+        ];
+        let ruleset = ruleset(&profile, &exceptions);
+        assert!(ruleset.starts_with("add table inet feos_host\ndelete table inet feos_host\n"));
+        assert!(ruleset.contains("\t\tiifname { \"eth0\" } jump host_input\n"));
+        assert!(ruleset.contains("\t\ttcp dport 1337 accept\n\t\tjump exceptions\n\t\tdrop\n"));
+        assert!(!ruleset.contains("dport 22 "));
+        assert!(ruleset.contains(
+            "\t\tip saddr { 10.0.0.0/8 } tcp dport 9100 accept comment \"metrics\"\n\
+             \t\tip6 saddr { 2001:db8::/48 } tcp dport 9100 accept comment \"metrics\"\n\
+             \t\ttcp dport 5000 accept comment \"mirror\"\n"
+        ));
+
+        let with_ssh = Profile {
+            ssh: true,
+            ..profile
+        };
+        assert!(super::ruleset(&with_ssh, &[]).contains("\t\ttcp dport 22 accept\n"));
+        assert_eq!(
+            exceptions_script(&exceptions[1..]),
+            "add table inet feos_host\nadd chain inet feos_host exceptions\n\
+             flush chain inet feos_host exceptions\n\
+             add rule inet feos_host exceptions tcp dport 5000 accept comment \"mirror\"\n"
+        );
+    }
+
+    #[test]
+    fn exceptions_are_validated_and_recorded() {
+        assert!(validate(&exception("metrics", 9100, &["10.1.2.3", "::1/128"])).is_ok());
+        for invalid in [
+            exception("", 9100, &[]),
+            exception("no spaces", 9100, &[]),
+            exception("metrics", 0, &[]),
+            exception("metrics", 9100, &["10.1.2.3/8"]),
+            exception("metrics", 9100, &["2001:db8::/129"]),
+            exception("metrics", 9100, &["example.com"]),
+        ] {
+            let err = validate(&invalid).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{invalid:?}");
+        }
+
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        assert!(read_exceptions(root).unwrap().is_empty());
+        let exceptions = vec![
+            exception("metrics", 9100, &["10.0.0.0/8", "2001:db8::/48"]),
+            Exception {
+                protocol: Protocol::Udp,
+                ..exception("syslog", 514, &[])
+            },
+        ];
+        write_exceptions(root, &exceptions).unwrap();
+        assert_eq!(read_exceptions(root).unwrap(), exceptions);
+    }
+}
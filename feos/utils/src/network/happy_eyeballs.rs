@@ -0,0 +1,133 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Connection setup as described in RFC 8305. Addresses are tried in turn,
+//! IPv6 first, and a slow attempt does not hold up the next one.
+
+use super::nat64;
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::collections::HashSet;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use tokio::net::{lookup_host, TcpStream};
+use tokio::time::{sleep, Duration};
+
+/// How long an attempt runs alone before the next address is tried as well.
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Orders addresses alternating between IPv6 and IPv4, IPv6 first, keeping
+/// the order of the resolver within each family.
+pub fn interleave(addresses: impl IntoIterator<Item = SocketAddr>) -> Vec<SocketAddr> {
+    let (ipv6, ipv4): (Vec<_>, Vec<_>) = addresses.into_iter().partition(SocketAddr::is_ipv6);
+    let mut ipv6 = ipv6.into_iter();
+    let mut ipv4 = ipv4.into_iter();
+    let mut ordered = Vec::new();
+    loop {
+        match (ipv6.next(), ipv4.next()) {
+            (None, None) => return ordered,
+            (first, second) => ordered.extend(first.into_iter().chain(second)),
+        }
+    }
+}
+
+/// The addresses of `host` in the order they are tried. On IPv6-only hosts,
+/// IPv4 addresses are replaced by their NAT64 addresses.
+pub async fn resolve(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+    let addresses: Vec<SocketAddr> = lookup_host((host, port)).await?.collect();
+    let needs_nat64 = addresses
+        .iter()
+        .any(|address| matches!(address.ip(), IpAddr::V4(ip) if !ip.is_loopback()));
+    let prefix = if needs_nat64 {
+        nat64::ipv6_only_prefix().await
+    } else {
+        None
+    };
+    let addresses = addresses
+        .into_iter()
+        .map(|address| match (address.ip(), prefix) {
+            (IpAddr::V4(ip), Some(prefix)) if !ip.is_loopback() => {
+                SocketAddr::new(IpAddr::V6(prefix.embed(ip)), address.port())
+            }
+            _ => address,
+        });
+    let mut ordered = interleave(addresses);
+    // DNS64 may have made up the NAT64 addresses already.
+    let mut seen = HashSet::new();
+    ordered.retain(|address| seen.insert(*address));
+    Ok(ordered)
+}
+
+/// Connects to `host`, see `resolve` and `connect_addresses`.
+pub async fn connect(host: &str, port: u16) -> io::Result<TcpStream> {
+    connect_addresses(&resolve(host, port).await?).await
+}
+
+/// Connects to the first of `addresses` that accepts. The next address is
+/// tried once an attempt failed or after `CONNECTION_ATTEMPT_DELAY`, while
+/// the earlier attempts keep running.
+pub async fn connect_addresses(addresses: &[SocketAddr]) -> io::Result<TcpStream> {
+    let mut remaining = addresses.iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_error = None;
+    loop {
+        if attempts.is_empty() {
+            match remaining.next() {
+                Some(address) => attempts.push(TcpStream::connect(*address)),
+                None => {
+                    return Err(last_error.unwrap_or_else(|| {
+                        io::Error::new(io::ErrorKind::NotFound, "No address to connect to")
+                    }))
+                }
+            }
+        }
+        tokio::select! {
+            Some(result) = attempts.next() => match result {
+                Ok(stream) => return Ok(stream),
+                Err(e) => {
+                    last_error = Some(e);
+                    if let Some(address) = remaining.next() {
+                        attempts.push(TcpStream::connect(*address));
+                    }
+                }
+            },
+            _ = sleep(CONNECTION_ATTEMPT_DELAY), if remaining.len() > 0 => {
+                if let Some(address) = remaining.next() {
+                    attempts.push(TcpStream::connect(*address));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn families_alternate_starting_with_ipv6() {
+        let addresses: Vec<SocketAddr> = ["192.0.2.1:80", "192.0.2.2:80", "[2001:db8::1]:80"]
+            .iter()
+            .map(|address| address.parse().unwrap())
+            .collect();
+        assert_eq!(
+            interleave(addresses.clone()),
+            [addresses[2], addresses[0], addresses[1]]
+        );
+    }
+
+    #[tokio::test]
+    async fn refused_addresses_are_skipped() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let open = listener.local_addr().unwrap();
+        let closed = {
+            let unused = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            unused.local_addr().unwrap()
+        };
+
+        let stream = connect_addresses(&[closed, open]).await.unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), open);
+        assert!(connect_addresses(&[closed]).await.is_err());
+        assert!(connect_addresses(&[]).await.is_err());
+    }
+}
@@ -0,0 +1,236 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! macvtap devices, which put a VM NIC directly on a host interface. The VMM
+//! reads and writes the frames of the guest through `/dev/tap<ifindex>`.
+
+use futures::stream::TryStreamExt;
+use netlink_packet_route::link::{
+    InfoData, InfoKind, InfoMacVtap, LinkAttribute, LinkFlags, LinkInfo, LinkMessage,
+    MacVtapMode as NetlinkMode,
+};
+use rtnetlink::{new_connection, Handle};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MacvtapMode {
+    Bridge,
+    Vepa,
+    Private,
+    Passthru,
+}
+
+impl From<MacvtapMode> for NetlinkMode {
+    fn from(mode: MacvtapMode) -> Self {
+        match mode {
+            MacvtapMode::Bridge => NetlinkMode::Bridge,
+            MacvtapMode::Vepa => NetlinkMode::Vepa,
+            MacvtapMode::Private => NetlinkMode::Private,
+            MacvtapMode::Passthru => NetlinkMode::Passthrough,
+        }
+    }
+}
+
+fn netlink_error(e: rtnetlink::Error) -> io::Error {
+    match e {
+        rtnetlink::Error::NetlinkError(msg) => msg.to_io(),
+        e => io::Error::other(e.to_string()),
+    }
+}
+
+/// Parses a MAC address like `52:54:00:12:34:56`.
+pub fn parse_mac(mac: &str) -> io::Result<[u8; 6]> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("'{mac}' is not a MAC address"),
+        )
+    };
+    let mut bytes = [0u8; 6];
+    let mut parts = mac.split(':');
+    for byte in &mut bytes {
+        let part = parts
+            .next()
+            .filter(|part| part.len() == 2)
+            .ok_or_else(invalid)?;
+        *byte = u8::from_str_radix(part, 16).map_err(|_| invalid())?;
+    }
+    if parts.next().is_some() {
+        return Err(invalid());
+    }
+    Ok(bytes)
+}
+
+/// The request that creates `name` on the interface with index `parent`,
+/// up right away and with the MAC of the guest, which it receives frames for.
+fn link_message(name: &str, parent: u32, mode: MacvtapMode, mac: [u8; 6]) -> LinkMessage {
+    let mut msg = LinkMessage::default();
+    msg.header.flags = LinkFlags::Up;
+    msg.header.change_mask = LinkFlags::Up;
+    msg.attributes = vec![
+        LinkAttribute::IfName(name.to_string()),
+        LinkAttribute::Link(parent),
+        LinkAttribute::Address(mac.to_vec()),
+        LinkAttribute::LinkInfo(vec![
+            LinkInfo::Kind(InfoKind::MacVtap),
+            LinkInfo::Data(InfoData::MacVtap(vec![InfoMacVtap::Mode(mode.into())])),
+        ]),
+    ];
+    msg
+}
+
+fn is_macvtap(link: &LinkMessage) -> bool {
+    link.attributes.iter().any(|attribute| {
+        matches!(attribute, LinkAttribute::LinkInfo(infos)
+            if infos.contains(&LinkInfo::Kind(InfoKind::MacVtap)))
+    })
+}
+
+/// The link called `name`, none if there is no such link.
+async fn find_link(handle: &Handle, name: &str) -> io::Result<Option<LinkMessage>> {
+    match handle
+        .link()
+        .get()
+        .match_name(name.to_string())
+        .execute()
+        .try_next()
+        .await
+    {
+        Ok(link) => Ok(link),
+        Err(rtnetlink::Error::NetlinkError(msg)) if msg.raw_code() == -libc::ENODEV => Ok(None),
+        Err(e) => Err(netlink_error(e)),
+    }
+}
+
+async fn ensure_with(
+    handle: &Handle,
+    name: &str,
+    parent: &str,
+    mode: MacvtapMode,
+    mac: [u8; 6],
+) -> io::Result<u32> {
+    if let Some(link) = find_link(handle, name).await? {
+        if !is_macvtap(&link) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{name} exists and is not a macvtap device"),
+            ));
+        }
+        return Ok(link.header.index);
+    }
+    let parent_index = find_link(handle, parent)
+        .await?
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Parent interface {parent} not found"),
+            )
+        })?
+        .header
+        .index;
+    handle
+        .link()
+        .add(link_message(name, parent_index, mode, mac))
+        .execute()
+        .await
+        .map_err(netlink_error)?;
+    find_link(handle, name)
+        .await?
+        .map(|link| link.header.index)
+        .ok_or_else(|| io::Error::other(format!("{name} is gone after creating it")))
+}
+
+/// Creates the macvtap device `name` on `parent` for a NIC with the MAC
+/// address `mac` and returns its interface index. An existing macvtap device
+/// of that name is kept, e.g. when a VM is created again after a restart.
+pub async fn ensure(name: &str, parent: &str, mode: MacvtapMode, mac: &str) -> io::Result<u32> {
+    let mac = parse_mac(mac)?;
+    let (connection, handle, _) = new_connection()?;
+    let connection = tokio::spawn(connection);
+    let result = ensure_with(&handle, name, parent, mode, mac).await;
+    connection.abort();
+    result
+}
+
+/// Removes the macvtap device `name`. A device that is already gone is not
+/// an error.
+pub async fn delete(name: &str) -> io::Result<()> {
+    let (connection, handle, _) = new_connection()?;
+    let connection = tokio::spawn(connection);
+    let result = async {
+        match find_link(&handle, name).await? {
+            Some(link) if is_macvtap(&link) => handle
+                .link()
+                .del(link.header.index)
+                .execute()
+                .await
+                .map_err(netlink_error),
+            Some(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{name} is not a macvtap device"),
+            )),
+            None => Ok(()),
+        }
+    }
+    .await;
+    connection.abort();
+    result
+}
+
+/// The character device of the macvtap device with the given index.
+pub fn device_path(index: u32) -> PathBuf {
+    PathBuf::from(format!("/dev/tap{index}"))
+}
+
+/// Opens the character device of a macvtap device for a VMM.
+pub fn open(index: u32) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(device_path(index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn macs_are_parsed_strictly() {
+        assert_eq!(
+            parse_mac("52:54:00:AB:cd:0f").unwrap(),
+            [0x52, 0x54, 0x00, 0xab, 0xcd, 0x0f]
+        );
+        for mac in [
+            "52:54:00:ab:cd",
+            "52:54:00:ab:cd:0f:11",
+            "52-54-00-ab-cd-0f",
+            "5:54:00:ab:cd:0f",
+        ] {
+            assert_eq!(
+                parse_mac(mac).unwrap_err().kind(),
+                io::ErrorKind::InvalidInput,
+                "{mac}"
+            );
+        }
+    }
+
+    #[test]
+    fn link_message_creates_an_up_macvtap_on_the_parent() {
+        let mac = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+        let msg = link_message("mvt0", 7, MacvtapMode::Private, mac);
+        assert!(msg.header.flags.contains(LinkFlags::Up));
+        assert!(msg.attributes.contains(&LinkAttribute::Link(7)));
+        assert!(msg
+            .attributes
+            .contains(&LinkAttribute::Address(mac.to_vec())));
+        assert!(msg.attributes.contains(&LinkAttribute::LinkInfo(vec![
+            LinkInfo::Kind(InfoKind::MacVtap),
+            LinkInfo::Data(InfoData::MacVtap(vec![InfoMacVtap::Mode(
+                NetlinkMode::Private
+            )])),
+        ])));
+        assert!(is_macvtap(&msg));
+    }
+}
@@ -1,7 +1,15 @@
 // SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
 // SPDX-License-Identifier: Apache-2.0
 
+pub mod boot_server;
+pub mod conntrack;
 pub mod dhcpv6;
+pub mod firewall;
+pub mod happy_eyeballs;
+pub mod macvtap;
+pub mod nat64;
+pub mod neighbours;
+pub mod router_advert;
 pub mod utils;
 
 pub use utils::configure_network_devices;
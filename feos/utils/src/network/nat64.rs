@@ -0,0 +1,197 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! NAT64 prefixes, which let IPv6-only hosts reach IPv4 addresses. DNS64
+//! resolvers answer names with only IPv4 addresses with addresses in the
+//! prefix, but IPv4 literals, e.g. of a registry mirror, have to be mapped
+//! by FeOS.
+
+use super::utils::has_ipv4_default_route;
+use log::{debug, info};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::net::lookup_host;
+
+/// The name RFC 7050 reserves for discovering the NAT64 prefix. It only has
+/// the IPv4 addresses below, so its IPv6 addresses are made up by DNS64.
+const IPV4ONLY_ARPA: &str = "ipv4only.arpa";
+const IPV4ONLY_ADDRESSES: [Ipv4Addr; 2] =
+    [Ipv4Addr::new(192, 0, 0, 170), Ipv4Addr::new(192, 0, 0, 171)];
+/// The prefix lengths RFC 6052 defines, longest first.
+const PREFIX_LENGTHS: [u8; 6] = [96, 64, 56, 48, 40, 32];
+/// Bits 64 to 71 of a NAT64 address are always zero.
+const RESERVED_BYTE: usize = 8;
+
+/// A NAT64 prefix, e.g. the well-known `64:ff9b::/96`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Nat64Prefix {
+    prefix: Ipv6Addr,
+    length: u8,
+}
+
+impl Nat64Prefix {
+    pub const WELL_KNOWN: Self = Self {
+        prefix: Ipv6Addr::new(0x64, 0xff9b, 0, 0, 0, 0, 0, 0),
+        length: 96,
+    };
+
+    /// The prefix of `length` bits of `address`, if RFC 6052 allows the
+    /// length.
+    pub fn new(address: Ipv6Addr, length: u8) -> Option<Self> {
+        if !PREFIX_LENGTHS.contains(&length) {
+            return None;
+        }
+        let mut octets = address.octets();
+        octets[usize::from(length / 8)..].fill(0);
+        Some(Self {
+            prefix: Ipv6Addr::from(octets),
+            length,
+        })
+    }
+
+    /// The bytes of an address in the prefix that hold the IPv4 address.
+    fn ipv4_bytes(&self) -> impl Iterator<Item = usize> {
+        (usize::from(self.length / 8)..16)
+            .filter(|&i| i != RESERVED_BYTE)
+            .take(4)
+    }
+
+    /// The address through which the NAT64 gateway reaches `ipv4`.
+    pub fn embed(&self, ipv4: Ipv4Addr) -> Ipv6Addr {
+        let mut octets = self.prefix.octets();
+        for (i, byte) in self.ipv4_bytes().zip(ipv4.octets()) {
+            octets[i] = byte;
+        }
+        Ipv6Addr::from(octets)
+    }
+
+    /// The IPv4 address embedded in `address`, if it is in the prefix.
+    pub fn extract(&self, address: Ipv6Addr) -> Option<Ipv4Addr> {
+        let octets = address.octets();
+        let prefix_bytes = usize::from(self.length / 8);
+        if octets[..prefix_bytes] != self.prefix.octets()[..prefix_bytes] {
+            return None;
+        }
+        let mut ipv4 = [0; 4];
+        for (byte, i) in ipv4.iter_mut().zip(self.ipv4_bytes()) {
+            *byte = octets[i];
+        }
+        Some(Ipv4Addr::from(ipv4))
+    }
+
+    /// The prefix a DNS64 resolver used for an address of `ipv4only.arpa`.
+    fn from_ipv4only_address(address: Ipv6Addr) -> Option<Self> {
+        PREFIX_LENGTHS.iter().find_map(|&length| {
+            let prefix = Self::new(address, length)?;
+            let ipv4 = prefix.extract(address)?;
+            (IPV4ONLY_ADDRESSES.contains(&ipv4) && prefix.embed(ipv4) == address).then_some(prefix)
+        })
+    }
+}
+
+impl fmt::Display for Nat64Prefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.prefix, self.length)
+    }
+}
+
+/// Asks the resolver for the NAT64 prefix as described in RFC 7050. None if
+/// the resolver does not do DNS64.
+pub async fn discover_prefix() -> Option<Nat64Prefix> {
+    let addresses = match lookup_host((IPV4ONLY_ARPA, 0)).await {
+        Ok(addresses) => addresses,
+        Err(e) => {
+            debug!("Nat64: Failed to resolve {IPV4ONLY_ARPA}: {e}");
+            return None;
+        }
+    };
+    addresses
+        .into_iter()
+        .find_map(|address| match address.ip() {
+            IpAddr::V6(ip) => Nat64Prefix::from_ipv4only_address(ip),
+            IpAddr::V4(_) => None,
+        })
+}
+
+/// The NAT64 prefix IPv4 destinations are reached through, if the host has
+/// no IPv4 route of its own. Falls back to the well-known prefix if the
+/// resolver does not tell one.
+pub async fn ipv6_only_prefix() -> Option<Nat64Prefix> {
+    if has_ipv4_default_route().await {
+        return None;
+    }
+    Some(discover_prefix().await.unwrap_or(Nat64Prefix::WELL_KNOWN))
+}
+
+/// Maps a `host:port` with an IPv4 address to the NAT64 address of the host
+/// on IPv6-only hosts. Names and IPv6 addresses are returned as they are,
+/// DNS64 takes care of names.
+pub async fn reachable_host(host: &str) -> String {
+    let (ipv4, port) = match host.parse::<SocketAddr>() {
+        Ok(SocketAddr::V4(addr)) => (*addr.ip(), Some(addr.port())),
+        Err(_) => match host.parse::<Ipv4Addr>() {
+            Ok(ipv4) => (ipv4, None),
+            Err(_) => return host.to_string(),
+        },
+        Ok(SocketAddr::V6(_)) => return host.to_string(),
+    };
+    if ipv4.is_loopback() {
+        return host.to_string();
+    }
+    let Some(prefix) = ipv6_only_prefix().await else {
+        return host.to_string();
+    };
+    let ipv6 = prefix.embed(ipv4);
+    info!("Nat64: Reaching {host} through {ipv6}, the host has no IPv4 route");
+    match port {
+        Some(port) => SocketAddr::new(IpAddr::V6(ipv6), port).to_string(),
+        None => format!("[{ipv6}]"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ipv4_addresses_are_embedded_as_in_rfc_6052() {
+        let ipv4 = Ipv4Addr::new(192, 0, 2, 33);
+        let cases = [
+            ("2001:db8::", 32, "2001:db8:c000:221::"),
+            ("2001:db8:100::", 40, "2001:db8:1c0:2:21::"),
+            ("2001:db8:122::", 48, "2001:db8:122:c000:2:2100::"),
+            ("2001:db8:122:300::", 56, "2001:db8:122:3c0:0:221::"),
+            ("2001:db8:122:344::", 64, "2001:db8:122:344:c0:2:2100:0"),
+            ("2001:db8:122:344::", 96, "2001:db8:122:344::192.0.2.33"),
+        ];
+        for (prefix, length, expected) in cases {
+            let prefix = Nat64Prefix::new(prefix.parse().unwrap(), length).unwrap();
+            let expected: Ipv6Addr = expected.parse().unwrap();
+            assert_eq!(prefix.embed(ipv4), expected, "{prefix}");
+            assert_eq!(prefix.extract(expected), Some(ipv4), "{prefix}");
+        }
+        assert_eq!(
+            Nat64Prefix::WELL_KNOWN.extract("2001:db8::1".parse().unwrap()),
+            None
+        );
+        assert!(Nat64Prefix::new(Ipv6Addr::UNSPECIFIED, 80).is_none());
+    }
+
+    #[test]
+    fn prefixes_are_found_in_ipv4only_arpa_addresses() {
+        let well_known = "64:ff9b::c000:aa".parse().unwrap();
+        assert_eq!(
+            Nat64Prefix::from_ipv4only_address(well_known),
+            Some(Nat64Prefix::WELL_KNOWN)
+        );
+        let network_specific = "2001:db8:122:344:c0:0:aa00:0".parse().unwrap();
+        assert_eq!(
+            Nat64Prefix::from_ipv4only_address(network_specific),
+            Nat64Prefix::new("2001:db8:122:344::".parse().unwrap(), 64)
+        );
+        assert_eq!(
+            Nat64Prefix::from_ipv4only_address("2001:db8::1".parse().unwrap()),
+            None
+        );
+    }
+}
@@ -0,0 +1,71 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use futures::stream::TryStreamExt;
+use netlink_packet_route::neighbour::{
+    NeighbourAddress, NeighbourAttribute, NeighbourMessage, NeighbourState,
+};
+use rtnetlink::new_connection;
+use std::collections::HashMap;
+use std::io;
+use std::net::IpAddr;
+
+/// Formats a link layer address the way MAC addresses are configured, e.g.
+/// `52:54:00:12:34:56`.
+pub fn format_mac(mac: &[u8]) -> String {
+    mac.iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+fn neighbour_entry(msg: &NeighbourMessage) -> Option<(String, IpAddr)> {
+    // Entries that never or no longer resolved say nothing about the guest.
+    if matches!(
+        msg.header.state,
+        NeighbourState::Incomplete | NeighbourState::Failed | NeighbourState::None
+    ) {
+        return None;
+    }
+    let mut mac = None;
+    let mut ip = None;
+    for attribute in &msg.attributes {
+        match attribute {
+            NeighbourAttribute::LinkLocalAddress(lladdr) => mac = Some(format_mac(lladdr)),
+            NeighbourAttribute::Destination(NeighbourAddress::Inet(addr)) => {
+                ip = Some(IpAddr::V4(*addr))
+            }
+            NeighbourAttribute::Destination(NeighbourAddress::Inet6(addr)) => {
+                ip = Some(IpAddr::V6(*addr))
+            }
+            _ => {}
+        }
+    }
+    Some((mac?, ip?))
+}
+
+/// The IP addresses the host has seen in ARP and NDP traffic, by the MAC
+/// address that uses them.
+pub async fn neighbour_addresses() -> io::Result<HashMap<String, Vec<IpAddr>>> {
+    let (connection, handle, _) = new_connection()?;
+    let connection = tokio::spawn(connection);
+
+    let mut neighbours = handle.neighbours().get().execute();
+    let mut addresses: HashMap<String, Vec<IpAddr>> = HashMap::new();
+    let result = loop {
+        match neighbours.try_next().await {
+            Ok(Some(msg)) => {
+                if let Some((mac, ip)) = neighbour_entry(&msg) {
+                    let ips = addresses.entry(mac).or_default();
+                    if !ips.contains(&ip) {
+                        ips.push(ip);
+                    }
+                }
+            }
+            Ok(None) => break Ok(addresses),
+            Err(e) => break Err(io::Error::other(e.to_string())),
+        }
+    };
+    connection.abort();
+    result
+}
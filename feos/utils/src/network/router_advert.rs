@@ -0,0 +1,228 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Router advertisements on the tap device of a single guest, which announce
+//! a prefix for SLAAC and the name servers of the host (RFC 4861, RFC 8106).
+//! The prefix is not on-link, so the guest sends all traffic to the host,
+//! which routes each guest address to its tap device.
+
+use super::dhcpv6::add_ipv6_route;
+use super::macvtap::parse_mac;
+use log::{debug, info};
+use netlink_packet_route::route::RouteType;
+use nix::net::if_::if_nametoindex;
+use rtnetlink::new_connection;
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::io::{self, Read};
+use std::net::{Ipv6Addr, SocketAddrV6};
+use tokio::io::unix::AsyncFd;
+use tokio::time::{interval, Duration};
+
+const ROUTER_SOLICITATION: u8 = 133;
+const ROUTER_ADVERTISEMENT: u8 = 134;
+const OPTION_SOURCE_LINK_LAYER_ADDRESS: u8 = 1;
+const OPTION_PREFIX_INFORMATION: u8 = 3;
+const OPTION_RDNSS: u8 = 25;
+/// Addresses are formed from the prefix, but it is not on-link.
+const PREFIX_FLAG_AUTONOMOUS: u8 = 0x40;
+const ALL_NODES: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+const ALL_ROUTERS: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 2);
+/// Unsolicited advertisements are sent this often, guests that come up ask
+/// for one right away.
+const ADVERTISEMENT_INTERVAL: Duration = Duration::from_secs(200);
+const ROUTER_LIFETIME_SECONDS: u16 = 1800;
+const VALID_LIFETIME_SECONDS: u32 = 86400;
+const PREFERRED_LIFETIME_SECONDS: u32 = 14400;
+/// Three times the advertisement interval, as RFC 8106 recommends.
+const RDNSS_LIFETIME_SECONDS: u32 = 600;
+
+/// What a guest is told in router advertisements.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Advertisement {
+    /// The /64 the guest forms its address in.
+    pub prefix: Ipv6Addr,
+    pub dns_servers: Vec<Ipv6Addr>,
+}
+
+/// The first /64 of `prefix`.
+pub fn first_subnet(prefix: Ipv6Addr) -> Ipv6Addr {
+    Ipv6Addr::from(u128::from(prefix) & !(u64::MAX as u128))
+}
+
+/// The address a guest forms with SLAAC in `prefix` from the MAC address of
+/// its NIC, with an interface ID in modified EUI-64 format.
+pub fn eui64_address(prefix: Ipv6Addr, mac: [u8; 6]) -> Ipv6Addr {
+    let mut octets = first_subnet(prefix).octets();
+    octets[8] = mac[0] ^ 0b0000_0010;
+    octets[9] = mac[1];
+    octets[10] = mac[2];
+    octets[11] = 0xff;
+    octets[12] = 0xfe;
+    octets[13] = mac[3];
+    octets[14] = mac[4];
+    octets[15] = mac[5];
+    Ipv6Addr::from(octets)
+}
+
+/// A router advertisement from a router with MAC address `mac`. The kernel
+/// fills in the checksum.
+pub fn encode(advertisement: &Advertisement, mac: [u8; 6]) -> Vec<u8> {
+    let mut message = vec![ROUTER_ADVERTISEMENT, 0, 0, 0, 64, 0];
+    message.extend(ROUTER_LIFETIME_SECONDS.to_be_bytes());
+    // Reachable time and retransmission timer are left to the guest.
+    message.extend([0u8; 8]);
+
+    message.extend([OPTION_SOURCE_LINK_LAYER_ADDRESS, 1]);
+    message.extend(mac);
+
+    message.extend([OPTION_PREFIX_INFORMATION, 4, 64, PREFIX_FLAG_AUTONOMOUS]);
+    message.extend(VALID_LIFETIME_SECONDS.to_be_bytes());
+    message.extend(PREFERRED_LIFETIME_SECONDS.to_be_bytes());
+    message.extend([0u8; 4]);
+    message.extend(first_subnet(advertisement.prefix).octets());
+
+    if !advertisement.dns_servers.is_empty() {
+        let length = 1 + 2 * advertisement.dns_servers.len();
+        message.extend([OPTION_RDNSS, length as u8, 0, 0]);
+        message.extend(RDNSS_LIFETIME_SECONDS.to_be_bytes());
+        for server in &advertisement.dns_servers {
+            message.extend(server.octets());
+        }
+    }
+    message
+}
+
+async fn interface_mac(interface: &str) -> io::Result<[u8; 6]> {
+    let address = tokio::fs::read_to_string(format!("/sys/class/net/{interface}/address")).await?;
+    parse_mac(address.trim())
+}
+
+/// Routes `address` to the guest behind `interface`. The route goes away
+/// with the device.
+pub async fn route_to_guest(interface: &str, address: Ipv6Addr) -> io::Result<()> {
+    let (connection, handle, _) = new_connection()?;
+    let connection = tokio::spawn(connection);
+    let result = add_ipv6_route(
+        &handle,
+        interface,
+        address,
+        128,
+        None,
+        1024,
+        RouteType::Unicast,
+    )
+    .await;
+    connection.abort();
+    match result {
+        Ok(()) => Ok(()),
+        // Left from before FeOS restarted.
+        Err(rtnetlink::Error::NetlinkError(msg)) if msg.raw_code() == -libc::EEXIST => Ok(()),
+        Err(rtnetlink::Error::NetlinkError(msg)) => Err(msg.to_io()),
+        Err(e) => Err(io::Error::other(e.to_string())),
+    }
+}
+
+fn icmpv6_socket(interface: &str, index: u32) -> io::Result<Socket> {
+    let socket = Socket::new(Domain::IPV6, Type::RAW, Some(Protocol::ICMPV6))?;
+    socket.bind_device(Some(interface.as_bytes()))?;
+    socket.set_multicast_if_v6(index)?;
+    // Guests drop neighbor discovery messages that crossed a router.
+    socket.set_multicast_hops_v6(255)?;
+    socket.set_unicast_hops_v6(255)?;
+    socket.join_multicast_v6(&ALL_ROUTERS, index)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+async fn receive(socket: &AsyncFd<Socket>, buf: &mut [u8]) -> io::Result<usize> {
+    loop {
+        let mut guard = socket.readable().await?;
+        if let Ok(result) = guard.try_io(|socket| {
+            let mut socket = socket.get_ref();
+            socket.read(buf)
+        }) {
+            return result;
+        }
+    }
+}
+
+/// Advertises to the guest behind `interface`, periodically and whenever it
+/// solicits, until the socket fails, e.g. because the device went away.
+pub async fn serve(interface: &str, advertisement: Advertisement) -> io::Result<()> {
+    let index = if_nametoindex(interface)?;
+    let mac = interface_mac(interface).await?;
+    let socket = AsyncFd::new(icmpv6_socket(interface, index)?)?;
+    let message = encode(&advertisement, mac);
+    let all_nodes = SockAddr::from(SocketAddrV6::new(ALL_NODES, 0, 0, index));
+    info!(
+        "RouterAdvert ({interface}): Announcing {}/64",
+        first_subnet(advertisement.prefix)
+    );
+
+    let mut ticks = interval(ADVERTISEMENT_INTERVAL);
+    let mut buf = vec![0u8; 1500];
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {}
+            received = receive(&socket, &mut buf) => {
+                let len = received?;
+                if len == 0 || buf[0] != ROUTER_SOLICITATION {
+                    continue;
+                }
+                debug!("RouterAdvert ({interface}): Answering router solicitation");
+            }
+        }
+        match socket.get_ref().send_to(&message, &all_nodes) {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x52, 0x54, 0x00, 0xab, 0xcd, 0x01];
+
+    #[test]
+    fn guest_addresses_are_formed_from_the_mac() {
+        assert_eq!(
+            eui64_address("2001:db8:12::".parse().unwrap(), MAC),
+            "2001:db8:12:0:5054:ff:feab:cd01"
+                .parse::<Ipv6Addr>()
+                .unwrap()
+        );
+        assert_eq!(
+            first_subnet("2001:db8:12:0:1::".parse().unwrap()),
+            "2001:db8:12::".parse::<Ipv6Addr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn advertisements_announce_the_prefix_and_name_servers() {
+        let advertisement = Advertisement {
+            prefix: "2001:db8:12::".parse().unwrap(),
+            dns_servers: vec!["2001:db8::53".parse().unwrap()],
+        };
+        let message = encode(&advertisement, MAC);
+        assert_eq!(message.len(), 16 + 8 + 32 + 24);
+        assert_eq!(message[0], ROUTER_ADVERTISEMENT);
+        assert_eq!(&message[6..8], &ROUTER_LIFETIME_SECONDS.to_be_bytes());
+        assert_eq!(
+            &message[16..24],
+            &[1, 1, 0x52, 0x54, 0x00, 0xab, 0xcd, 0x01]
+        );
+        assert_eq!(&message[24..28], &[3, 4, 64, PREFIX_FLAG_AUTONOMOUS]);
+        assert_eq!(&message[40..56], &advertisement.prefix.octets());
+        assert_eq!(&message[56..58], &[OPTION_RDNSS, 3]);
+        assert_eq!(&message[64..80], &advertisement.dns_servers[0].octets());
+
+        let without_servers = Advertisement {
+            dns_servers: Vec::new(),
+            ..advertisement
+        };
+        assert_eq!(encode(&without_servers, MAC).len(), 16 + 8 + 32);
+    }
+}
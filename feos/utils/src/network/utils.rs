@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use super::dhcpv6::*;
+use crate::host::pci;
 use futures::stream::TryStreamExt;
 use log::{error, info, warn};
 use netlink_packet_route::link::{LinkAttribute, LinkFlags, LinkMessage};
@@ -16,6 +17,9 @@ use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::time::{sleep, Duration};
 
 pub const INTERFACE_NAME: &str = "eth0";
+/// Ships with a public DNS64 resolver, which the servers announced by DHCPv6
+/// replace.
+const RESOLV_CONF: &str = "/etc/resolv.conf";
 
 pub async fn configure_sriov(num_vfs: u32) -> Result<(), String> {
     let base_path = format!("/sys/class/net/{INTERFACE_NAME}/device");
@@ -121,15 +125,7 @@ fn format_pci_address(address: (u16, u8, u8, u8)) -> String {
 }
 
 async fn bind_vf_to_vfio(pci_address: &str) -> Result<(), io::Error> {
-    let override_path = format!("/sys/bus/pci/devices/{pci_address}/driver_override");
-    let mut override_file = OpenOptions::new().write(true).open(&override_path).await?;
-    override_file.write_all(b"vfio-pci").await?;
-
-    let bind_path = "/sys/bus/pci/drivers/vfio-pci/bind";
-    let mut bind_file = OpenOptions::new().write(true).open(bind_path).await?;
-    bind_file.write_all(pci_address.as_bytes()).await?;
-
-    Ok(())
+    pci::bind_vfio(pci_address).map(|_| ())
 }
 
 async fn get_device_information(pci: &str, field: &str) -> Result<String, io::Error> {
@@ -196,6 +192,11 @@ pub async fn configure_network_devices() -> Result<Option<(Ipv6Addr, u8, Vec<Ipv
         match run_dhcpv6_client(interface_name.clone()).await {
             Ok(result) => {
                 send_neigh_solicitation(interface_name.clone(), &ipv6_gateway, &result.address);
+                if !result.dns_servers.is_empty() {
+                    if let Err(e) = write_resolv_conf(RESOLV_CONF, &result.dns_servers).await {
+                        warn!("Failed to write {RESOLV_CONF}: {e}");
+                    }
+                }
                 if let Some(prefix_info) = result.prefix {
                     let delegated_prefix = prefix_info.prefix;
                     let prefix_length = prefix_info.prefix_length;
@@ -231,6 +232,100 @@ pub async fn configure_network_devices() -> Result<Option<(Ipv6Addr, u8, Vec<Ipv
     Ok(result_option)
 }
 
+/// Points the resolver at `servers`. On IPv6-only networks they are expected
+/// to do DNS64, see `nat64`.
+async fn write_resolv_conf(path: &str, servers: &[Ipv6Addr]) -> Result<(), io::Error> {
+    let content: String = servers
+        .iter()
+        .map(|server| format!("nameserver {server}\n"))
+        .collect();
+    tokio::fs::write(path, content).await?;
+    info!("Configured DNS servers {servers:?} in {path}");
+    Ok(())
+}
+
+/// Whether a routing table from `/proc/net/route` or `/proc/net/ipv6_route`
+/// has a default route over an interface other than loopback.
+fn has_default_route_in(table: &str) -> bool {
+    table.lines().any(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields[..] {
+            // IPv4: Iface Destination Gateway Flags RefCnt Use Metric Mask ...
+            [iface, "00000000", _, _, _, _, _, "00000000", ..] => iface != "lo",
+            // IPv6: Destination PrefixLen Source SourcePrefixLen NextHop Metric
+            // RefCnt Use Flags Iface
+            [dest, "00", _, _, _, _, _, _, _, iface] => {
+                iface != "lo" && dest.bytes().all(|b| b == b'0')
+            }
+            _ => false,
+        }
+    })
+}
+
+/// Whether the host has a default route, i.e. can reach other networks.
+pub async fn has_default_route() -> bool {
+    for table in ["/proc/net/route", "/proc/net/ipv6_route"] {
+        if let Ok(content) = tokio::fs::read_to_string(table).await {
+            if has_default_route_in(&content) {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Whether the host has an IPv4 default route. Without one, IPv4 addresses
+/// are only reachable through NAT64.
+pub async fn has_ipv4_default_route() -> bool {
+    tokio::fs::read_to_string("/proc/net/route")
+        .await
+        .is_ok_and(|content| has_default_route_in(&content))
+}
+
+/// Marks unreachable routes in `/proc/net/ipv6_route`.
+const RTF_REJECT: u32 = 0x0200;
+
+/// The delegated prefix in `/proc/net/ipv6_route`, which
+/// `configure_network_devices` installs as an unreachable route so that only
+/// the parts of it routed to guests are reachable.
+fn delegated_prefix_in(table: &str) -> Option<(Ipv6Addr, u8)> {
+    table.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [dest, prefix_length, _, _, _, _, _, _, flags, _] = fields[..] else {
+            return None;
+        };
+        let prefix = Ipv6Addr::from(u128::from_str_radix(dest, 16).ok()?);
+        let prefix_length = u8::from_str_radix(prefix_length, 16).ok()?;
+        let flags = u32::from_str_radix(flags, 16).ok()?;
+        // Global unicast addresses are in 2000::/3.
+        let global = prefix.octets()[0] & 0xe0 == 0x20;
+        (flags & RTF_REJECT != 0 && global && (1..=64).contains(&prefix_length))
+            .then_some((prefix, prefix_length))
+    })
+}
+
+/// The prefix delegated to the host, if it got one. It is read from the
+/// routing table, so it is also known after FeOS restarted without
+/// configuring the network again.
+pub async fn delegated_prefix() -> Option<(Ipv6Addr, u8)> {
+    let table = tokio::fs::read_to_string("/proc/net/ipv6_route")
+        .await
+        .ok()?;
+    delegated_prefix_in(&table)
+}
+
+/// The IPv6 name servers the host uses.
+pub async fn dns_servers() -> Vec<Ipv6Addr> {
+    let content = tokio::fs::read_to_string(RESOLV_CONF)
+        .await
+        .unwrap_or_default();
+    content
+        .lines()
+        .filter_map(|line| line.strip_prefix("nameserver"))
+        .filter_map(|server| server.trim().parse().ok())
+        .collect()
+}
+
 pub fn enable_ipv6_forwarding() -> Result<(), std::io::Error> {
     File::create("/proc/sys/net/ipv6/conf/all/forwarding")?.write_all(b"1")?;
     Ok(())
@@ -243,3 +338,47 @@ fn format_mac(bytes: Vec<u8>) -> String {
         .collect::<Vec<String>>()
         .join(":")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_routes_are_found_in_both_tables() {
+        let ipv4 =
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+                    eth0\t000200C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0\n";
+        assert!(!has_default_route_in(ipv4));
+        let ipv4 = format!("{ipv4}eth0\t00000000\t010200C0\t0003\t0\t0\t0\t00000000\t0\t0\t0\n");
+        assert!(has_default_route_in(&ipv4));
+
+        let zero = "00000000000000000000000000000000";
+        let ipv6 = format!(
+            "fe800000000000000000000000000000 40 {zero} 00 {zero} 00000100 00000002 00000000 00000001     eth0\n\
+             {zero} 00 {zero} 00 {zero} ffffffff 00000001 00000000 00200200       lo\n"
+        );
+        assert!(!has_default_route_in(&ipv6));
+        let ipv6 = format!(
+            "{ipv6}{zero} 00 {zero} 00 fd000000000000000000000000000001 00000400 00000001 00000000 00000003     eth0\n"
+        );
+        assert!(has_default_route_in(&ipv6));
+    }
+
+    #[test]
+    fn the_delegated_prefix_is_its_unreachable_route() {
+        let zero = "00000000000000000000000000000000";
+        let table = format!(
+            "{zero} 00 {zero} 00 {zero} ffffffff 00000001 00000000 00200200       lo\n\
+             fd000000000000000000000000000000 30 {zero} 00 {zero} 00000400 00000001 00000000 00000201       lo\n\
+             20010db8000100000000000000000000 40 {zero} 00 {zero} 00000100 00000001 00000000 00000001     eth0\n"
+        );
+        assert_eq!(delegated_prefix_in(&table), None);
+        let table = format!(
+            "{table}20010db8001200000000000000000000 38 {zero} 00 {zero} 00000400 00000001 00000000 00000201       lo\n"
+        );
+        assert_eq!(
+            delegated_prefix_in(&table),
+            Some(("2001:db8:12::".parse().unwrap(), 56))
+        );
+    }
+}
@@ -0,0 +1,131 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Opening and writing the SQLite databases of the services.
+
+use log::warn;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions};
+use std::future::Future;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How long a query waits for another connection to finish writing before
+/// it fails with "database is locked".
+pub const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+/// In WAL mode, readers do not block each other or the writer.
+const MAX_CONNECTIONS: u32 = 4;
+/// How often a write that still finds the database locked after the busy
+/// timeout is tried again, and how long it backs off before the first retry.
+const BUSY_RETRIES: u32 = 3;
+const BUSY_BACKOFF: Duration = Duration::from_millis(100);
+
+/// SQLite's primary result codes for a database locked by another
+/// connection and a table locked within the same one.
+const SQLITE_BUSY: i32 = 5;
+const SQLITE_LOCKED: i32 = 6;
+
+/// Opens a pool on the database in WAL mode, so the dispatcher's writes do
+/// not lock out concurrent reads.
+pub async fn connect_pool(db_url: &str) -> Result<SqlitePool, sqlx::Error> {
+    let options = SqliteConnectOptions::from_str(db_url)?
+        .journal_mode(SqliteJournalMode::Wal)
+        .busy_timeout(BUSY_TIMEOUT);
+    SqlitePoolOptions::new()
+        .max_connections(MAX_CONNECTIONS)
+        .connect_with(options)
+        .await
+}
+
+fn is_busy(e: &sqlx::Error) -> bool {
+    // Extended result codes keep the primary one in the low byte.
+    e.as_database_error()
+        .and_then(|e| e.code())
+        .and_then(|code| code.parse::<i32>().ok())
+        .is_some_and(|code| matches!(code & 0xff, SQLITE_BUSY | SQLITE_LOCKED))
+}
+
+/// Runs a write, again with backoff if the database stayed locked beyond
+/// the busy timeout.
+pub async fn retry_busy<T, F, Fut>(mut query: F) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    let mut retries = 0;
+    loop {
+        match query().await {
+            Err(e) if is_busy(&e) && retries < BUSY_RETRIES => {
+                retries += 1;
+                warn!("SQLite: Database is locked, retrying ({retries}/{BUSY_RETRIES}): {e}");
+                tokio::time::sleep(BUSY_BACKOFF * retries).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::Connection;
+
+    #[tokio::test]
+    async fn writes_wait_for_a_locked_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("test.db");
+        std::fs::File::create(&db_path).unwrap();
+        let db_url = format!("sqlite:{}", db_path.display());
+        let pool = connect_pool(&db_url).await.unwrap();
+        let (mode,): (String,) = sqlx::query_as("PRAGMA journal_mode")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(mode, "wal");
+        sqlx::query("CREATE TABLE t (v INTEGER)")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let mut locker = sqlx::SqliteConnection::connect(&db_url).await.unwrap();
+        sqlx::query("BEGIN IMMEDIATE")
+            .execute(&mut locker)
+            .await
+            .unwrap();
+
+        let impatient = SqliteConnectOptions::from_str(&db_url)
+            .unwrap()
+            .busy_timeout(Duration::ZERO);
+        let mut impatient = sqlx::SqliteConnection::connect_with(&impatient)
+            .await
+            .unwrap();
+        let err = sqlx::query("INSERT INTO t VALUES (0)")
+            .execute(&mut impatient)
+            .await
+            .unwrap_err();
+        assert!(is_busy(&err), "{err}");
+
+        let writes: Vec<_> = (1..=8)
+            .map(|v| {
+                let pool = pool.clone();
+                tokio::spawn(async move {
+                    retry_busy(|| {
+                        sqlx::query("INSERT INTO t VALUES (?1)")
+                            .bind(v)
+                            .execute(&pool)
+                    })
+                    .await
+                })
+            })
+            .collect();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        sqlx::query("COMMIT").execute(&mut locker).await.unwrap();
+        for write in writes {
+            write.await.unwrap().unwrap();
+        }
+        let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM t")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 8);
+    }
+}
@@ -0,0 +1,158 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::app::View;
+use chrono::{DateTime, Local};
+use feos_proto::container_service::{ContainerEvent, ContainerState, ContainerStateChangedEvent};
+use feos_proto::vm_service::{VmEvent, VmState, VmStateChangedEvent};
+use prost::Message;
+use ratatui::widgets::ListState;
+
+const MAX_ALERTS: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// The workload an alert is about, so the panel can jump to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceRef {
+    pub view: View,
+    pub id: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub severity: Severity,
+    pub message: String,
+    pub resource: Option<ResourceRef>,
+    pub time: DateTime<Local>,
+}
+
+impl Alert {
+    pub fn new(severity: Severity, message: String, resource: Option<ResourceRef>) -> Self {
+        Self {
+            severity,
+            message,
+            resource,
+            time: Local::now(),
+        }
+    }
+}
+
+/// Raises an error alert when a VM enters the crashed state.
+pub fn from_vm_event(event: &VmEvent) -> Option<Alert> {
+    let data = event.data.as_ref()?;
+    if !data.type_url.ends_with("VmStateChangedEvent") {
+        return None;
+    }
+    let change = VmStateChangedEvent::decode(&*data.value).ok()?;
+    if change.new_state != VmState::Crashed as i32 {
+        return None;
+    }
+    let mut message = format!("VM {} crashed", event.vm_id);
+    if !change.reason.is_empty() {
+        message.push_str(&format!(": {}", change.reason));
+    }
+    Some(Alert::new(
+        Severity::Error,
+        message,
+        Some(ResourceRef {
+            view: View::Vms,
+            id: event.vm_id.clone(),
+        }),
+    ))
+}
+
+/// Raises an error alert when a container is stopped by the OOM killer. The
+/// container service reports the cause only in the free-form reason.
+pub fn from_container_event(event: &ContainerEvent) -> Option<Alert> {
+    let data = event.data.as_ref()?;
+    if !data.type_url.ends_with("ContainerStateChangedEvent") {
+        return None;
+    }
+    let change = ContainerStateChangedEvent::decode(&*data.value).ok()?;
+    let oom = change.reason.to_lowercase().contains("oom");
+    if change.new_state != ContainerState::Stopped as i32 || !oom {
+        return None;
+    }
+    Some(Alert::new(
+        Severity::Error,
+        format!(
+            "Container {} was OOM-killed: {}",
+            event.container_id, change.reason
+        ),
+        Some(ResourceRef {
+            view: View::Containers,
+            id: event.container_id.clone(),
+        }),
+    ))
+}
+
+/// Alerts not yet dismissed, newest first.
+#[derive(Default)]
+pub struct Alerts {
+    pub items: Vec<Alert>,
+    pub state: ListState,
+}
+
+impl Alerts {
+    /// Adds an alert unless an identical one is still open, e.g. when a
+    /// resubscribed event stream replays the current state.
+    pub fn push(&mut self, alert: Alert) {
+        let duplicate = self
+            .items
+            .iter()
+            .any(|open| open.message == alert.message && open.resource == alert.resource);
+        if duplicate {
+            return;
+        }
+        self.items.insert(0, alert);
+        self.items.truncate(MAX_ALERTS);
+        if let Some(selected) = self.state.selected() {
+            self.state
+                .select(Some((selected + 1).min(self.items.len() - 1)));
+        }
+    }
+
+    pub fn selected(&self) -> Option<&Alert> {
+        self.items.get(self.state.selected()?)
+    }
+
+    pub fn move_selection(&mut self, delta: isize) {
+        if self.items.is_empty() {
+            self.state.select(None);
+            return;
+        }
+        let current = self.state.selected().unwrap_or(0) as isize;
+        let next = current
+            .saturating_add(delta)
+            .clamp(0, self.items.len() as isize - 1);
+        self.state.select(Some(next as usize));
+    }
+
+    pub fn dismiss_selected(&mut self) {
+        let Some(index) = self.state.selected() else {
+            return;
+        };
+        if index < self.items.len() {
+            self.items.remove(index);
+        }
+        if self.items.is_empty() {
+            self.state.select(None);
+        } else {
+            self.state.select(Some(index.min(self.items.len() - 1)));
+        }
+    }
+
+    pub fn dismiss_all(&mut self) {
+        self.items.clear();
+        self.state.select(None);
+    }
+
+    pub fn highest_severity(&self) -> Option<Severity> {
+        self.items.iter().map(|alert| alert.severity).max()
+    }
+}
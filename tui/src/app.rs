@@ -0,0 +1,1140 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::alerts::{Alert, Alerts, Severity};
+use crate::config::{KeyBindings, TuiConfig};
+use crate::console::{self, ConsoleState};
+use crate::export;
+use crate::metrics::{HostMetrics, HostSample};
+use crate::palette::{self, Kind, PaletteCommand, Verb};
+use crate::theme::Theme;
+use feos_client_config::{ClientConfig, HostContext};
+use feos_proto::container_service::{ContainerInfo, ContainerState};
+use feos_proto::vm_service::{VmInfo, VmMetrics, VmMetricsSample, VmState};
+use ratatui::crossterm::event::{
+    KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use ratatui::layout::{Position, Rect};
+use ratatui::widgets::{ListState, TableState};
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+const TOAST_TTL: Duration = Duration::from_secs(4);
+const CONSOLE_MIN_PERCENT: u16 = 20;
+const CONSOLE_MAX_PERCENT: u16 = 80;
+const MEMORY_ALERT_PERCENT: u64 = 90;
+/// Memory usage must drop below this before another memory alert is raised.
+const MEMORY_ALERT_CLEAR_PERCENT: u64 = 85;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum View {
+    Vms,
+    Containers,
+    Dashboard,
+}
+
+impl View {
+    pub const ALL: [View; 3] = [View::Vms, View::Containers, View::Dashboard];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            View::Vms => "VMs",
+            View::Containers => "Containers",
+            View::Dashboard => "Dashboard",
+        }
+    }
+
+    fn next(self) -> View {
+        let index = View::ALL.iter().position(|v| *v == self).unwrap_or(0);
+        View::ALL[(index + 1) % View::ALL.len()]
+    }
+
+    fn previous(self) -> View {
+        let index = View::ALL.iter().position(|v| *v == self).unwrap_or(0);
+        View::ALL[(index + View::ALL.len() - 1) % View::ALL.len()]
+    }
+}
+
+/// A lifecycle operation the user triggered on the selected resource.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    StartVm(String),
+    StopVm(String),
+    PauseVm(String),
+    ResumeVm(String),
+    RestartVm(String),
+    DeleteVm(String),
+    StartContainer(String),
+    StopContainer(String),
+    DeleteContainer(String),
+}
+
+impl Action {
+    pub fn description(&self) -> String {
+        match self {
+            Action::StartVm(id) => format!("start VM {id}"),
+            Action::StopVm(id) => format!("stop VM {id}"),
+            Action::PauseVm(id) => format!("pause VM {id}"),
+            Action::ResumeVm(id) => format!("resume VM {id}"),
+            Action::RestartVm(id) => format!("restart VM {id}"),
+            Action::DeleteVm(id) => format!("delete VM {id}"),
+            Action::StartContainer(id) => format!("start container {id}"),
+            Action::StopContainer(id) => format!("stop container {id}"),
+            Action::DeleteContainer(id) => format!("delete container {id}"),
+        }
+    }
+
+    fn verb(&self) -> &'static str {
+        match self {
+            Action::StartVm(_) | Action::StartContainer(_) => "start",
+            Action::StopVm(_) | Action::StopContainer(_) => "stop",
+            Action::PauseVm(_) => "pause",
+            Action::ResumeVm(_) => "resume",
+            Action::RestartVm(_) => "restart",
+            Action::DeleteVm(_) | Action::DeleteContainer(_) => "delete",
+        }
+    }
+
+    fn is_vm(&self) -> bool {
+        !matches!(
+            self,
+            Action::StartContainer(_) | Action::StopContainer(_) | Action::DeleteContainer(_)
+        )
+    }
+
+    /// Actions that interrupt or destroy a running workload are confirmed first.
+    pub fn needs_confirmation(&self) -> bool {
+        matches!(
+            self,
+            Action::StopVm(_)
+                | Action::RestartVm(_)
+                | Action::DeleteVm(_)
+                | Action::StopContainer(_)
+                | Action::DeleteContainer(_)
+        )
+    }
+}
+
+/// Describes a batch of actions for prompts and toasts, e.g. "stop 3 VMs".
+/// Batches are built from one table, so they never mix VMs and containers.
+pub fn describe(actions: &[Action]) -> String {
+    match actions {
+        [] => String::new(),
+        [action] => action.description(),
+        [first, ..] => {
+            let mut verbs: Vec<&str> = Vec::new();
+            for action in actions {
+                if !verbs.contains(&action.verb()) {
+                    verbs.push(action.verb());
+                }
+            }
+            let noun = if first.is_vm() { "VMs" } else { "containers" };
+            format!("{} {} {noun}", verbs.join("/"), actions.len())
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mode {
+    Normal,
+    Confirm(Vec<Action>),
+    /// Editing the filter; every keystroke narrows the table immediately.
+    Search,
+    /// Typing a `:` palette command.
+    Command(String),
+    /// The alerts panel is open and has keyboard focus.
+    Alerts,
+    /// Choosing a context to connect to.
+    Hosts,
+}
+
+/// Work a key press asks the main loop to start in the background.
+pub enum Command {
+    Run(Vec<Action>),
+    AttachConsole {
+        vm_id: String,
+        input: mpsc::UnboundedReceiver<Vec<u8>>,
+    },
+    /// Reconnect to another FeOS host.
+    SwitchHost(HostContext),
+}
+
+/// Results of background tasks, delivered to the main loop.
+pub enum AppMessage {
+    Vms(Result<Vec<VmInfo>, String>),
+    Containers(Result<Vec<ContainerInfo>, String>),
+    Host(Result<HostSample, String>),
+    VmMetrics(VmMetricsSample),
+    Alert(Alert),
+    /// Round-trip time of the last poll, or `None` if the host did not answer.
+    Latency(Option<Duration>),
+    /// Outcome of each action of a `Command::Run`.
+    ActionsDone(Vec<(Action, Result<(), String>)>),
+    ConsoleOutput {
+        vm_id: String,
+        output: Vec<u8>,
+    },
+    ConsoleClosed {
+        vm_id: String,
+        reason: String,
+    },
+}
+
+/// API round-trip time shown in the status line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Latency {
+    /// No poll has finished since connecting.
+    Unknown,
+    Measured(Duration),
+    Unreachable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToastLevel {
+    Info,
+    Error,
+}
+
+pub struct Toast {
+    pub message: String,
+    pub level: ToastLevel,
+    created: Instant,
+}
+
+/// Screen regions from the last draw, used to map mouse events to widgets.
+#[derive(Debug, Default)]
+pub struct ScreenAreas {
+    pub tabs: Vec<(View, Rect)>,
+    /// Area of the body below the tabs, shared by the table and the console pane.
+    pub body: Rect,
+    pub table: Rect,
+    pub console: Option<Rect>,
+}
+
+pub struct App {
+    /// The host the TUI is connected to.
+    pub target: HostContext,
+    pub latency: Latency,
+    /// Contexts offered by the host switcher, read when it is opened.
+    pub contexts: Vec<HostContext>,
+    pub host_picker: ListState,
+    pub config: TuiConfig,
+    pub theme: Theme,
+    config_path: PathBuf,
+    pub view: View,
+    pub mode: Mode,
+    pub vms: Vec<VmInfo>,
+    pub containers: Vec<ContainerInfo>,
+    pub vm_table: TableState,
+    pub container_table: TableState,
+    /// IDs marked for bulk actions, kept across filter changes.
+    pub marked_vms: BTreeSet<String>,
+    pub marked_containers: BTreeSet<String>,
+    /// Case-insensitive filter matched against ID, image and state.
+    pub filter: String,
+    pub console: Option<ConsoleState>,
+    pub host: HostMetrics,
+    /// Last error from polling host metrics, shown on the dashboard.
+    pub host_error: Option<String>,
+    /// Latest usage of the running VMs, by VM ID.
+    pub vm_metrics: HashMap<String, VmMetrics>,
+    pub alerts: Alerts,
+    memory_alert_raised: bool,
+    /// Share of the body height given to the console pane, in percent.
+    pub console_percent: u16,
+    dragging_split: bool,
+    pub areas: ScreenAreas,
+    pub toasts: Vec<Toast>,
+    pub should_quit: bool,
+}
+
+impl App {
+    pub fn new(target: HostContext, config: TuiConfig, config_path: PathBuf) -> Self {
+        let history = config.metrics_history_len();
+        Self {
+            target,
+            latency: Latency::Unknown,
+            contexts: Vec::new(),
+            host_picker: ListState::default(),
+            view: config.default_view,
+            theme: Theme::new(config.theme),
+            config,
+            config_path,
+            mode: Mode::Normal,
+            vms: Vec::new(),
+            containers: Vec::new(),
+            vm_table: TableState::default(),
+            container_table: TableState::default(),
+            marked_vms: BTreeSet::new(),
+            marked_containers: BTreeSet::new(),
+            filter: String::new(),
+            console: None,
+            host: HostMetrics::new(history),
+            host_error: None,
+            vm_metrics: HashMap::new(),
+            alerts: Alerts::default(),
+            memory_alert_raised: false,
+            console_percent: 60,
+            dragging_split: false,
+            areas: ScreenAreas::default(),
+            toasts: Vec::new(),
+            should_quit: false,
+        }
+    }
+
+    pub fn push_toast(&mut self, level: ToastLevel, message: impl Into<String>) {
+        self.toasts.push(Toast {
+            message: message.into(),
+            level,
+            created: Instant::now(),
+        });
+    }
+
+    /// Re-reads the config file. On error the current settings stay in effect.
+    pub fn reload_config(&mut self) {
+        match TuiConfig::load_from(&self.config_path) {
+            Ok(config) => {
+                self.theme = Theme::new(config.theme);
+                self.host.set_capacity(config.metrics_history_len());
+                self.config = config;
+                self.push_toast(ToastLevel::Info, "Configuration reloaded");
+            }
+            Err(e) => self.push_toast(ToastLevel::Error, format!("{e:#}")),
+        }
+    }
+
+    pub fn prune_toasts(&mut self) {
+        self.toasts
+            .retain(|toast| toast.created.elapsed() < TOAST_TTL);
+    }
+
+    pub fn handle_message(&mut self, message: AppMessage) {
+        match message {
+            AppMessage::Vms(Ok(mut vms)) => {
+                vms.sort_by(|a, b| a.vm_id.cmp(&b.vm_id));
+                self.marked_vms
+                    .retain(|id| vms.iter().any(|vm| &vm.vm_id == id));
+                self.vms = vms;
+                self.clamp_selections();
+            }
+            AppMessage::Containers(Ok(mut containers)) => {
+                containers.sort_by(|a, b| a.container_id.cmp(&b.container_id));
+                self.marked_containers
+                    .retain(|id| containers.iter().any(|c| &c.container_id == id));
+                self.containers = containers;
+                self.clamp_selections();
+            }
+            AppMessage::Vms(Err(e)) => self.push_toast(ToastLevel::Error, format!("List VMs: {e}")),
+            AppMessage::Containers(Err(e)) => {
+                self.push_toast(ToastLevel::Error, format!("List containers: {e}"))
+            }
+            AppMessage::Host(Ok(sample)) => {
+                self.host.push(sample);
+                self.host_error = None;
+                self.check_memory_alert();
+            }
+            AppMessage::VmMetrics(sample) => {
+                self.vm_metrics = sample
+                    .vms
+                    .into_iter()
+                    .map(|vm| (vm.vm_id.clone(), vm))
+                    .collect();
+            }
+            AppMessage::Alert(alert) => self.alerts.push(alert),
+            AppMessage::Latency(latency) => {
+                self.latency = latency.map_or(Latency::Unreachable, Latency::Measured)
+            }
+            AppMessage::Host(Err(e)) => self.host_error = Some(e),
+            AppMessage::ActionsDone(results) => {
+                let mut requested = Vec::new();
+                for (action, result) in results {
+                    match result {
+                        Ok(()) => requested.push(action),
+                        Err(e) => self.push_toast(
+                            ToastLevel::Error,
+                            format!("Failed to {}: {e}", action.description()),
+                        ),
+                    }
+                }
+                if !requested.is_empty() {
+                    self.push_toast(
+                        ToastLevel::Info,
+                        format!("Requested {}", describe(&requested)),
+                    );
+                }
+            }
+            AppMessage::ConsoleOutput { vm_id, output } => {
+                if let Some(console) = self.console_for(&vm_id) {
+                    console.push_output(&output);
+                }
+            }
+            AppMessage::ConsoleClosed { vm_id, reason } => {
+                if let Some(console) = self.console_for(&vm_id) {
+                    console.closed = Some(reason);
+                }
+            }
+        }
+    }
+
+    /// The open console, if it belongs to `vm_id`. Messages from a console that
+    /// was detached in the meantime are dropped.
+    fn console_for(&mut self, vm_id: &str) -> Option<&mut ConsoleState> {
+        self.console
+            .as_mut()
+            .filter(|console| console.vm_id == vm_id)
+    }
+
+    fn check_memory_alert(&mut self) {
+        let Some(&used) = self.host.memory.back() else {
+            return;
+        };
+        if used >= MEMORY_ALERT_PERCENT && !self.memory_alert_raised {
+            self.memory_alert_raised = true;
+            self.alerts.push(Alert::new(
+                Severity::Warning,
+                format!("Host memory usage at {used}%"),
+                None,
+            ));
+        } else if used < MEMORY_ALERT_CLEAR_PERCENT {
+            self.memory_alert_raised = false;
+        }
+    }
+
+    fn handle_alerts_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => self.mode = Mode::Normal,
+            KeyCode::Char(c) if c == self.config.keys.alerts => self.mode = Mode::Normal,
+            KeyCode::Down | KeyCode::Char('j') => self.alerts.move_selection(1),
+            KeyCode::Up | KeyCode::Char('k') => self.alerts.move_selection(-1),
+            KeyCode::Char('x') | KeyCode::Delete => self.alerts.dismiss_selected(),
+            KeyCode::Char('X') => self.alerts.dismiss_all(),
+            KeyCode::Enter => self.jump_to_alert(),
+            _ => {}
+        }
+    }
+
+    fn open_host_picker(&mut self) {
+        let config = match ClientConfig::load() {
+            Ok(config) => config,
+            Err(e) => {
+                self.push_toast(ToastLevel::Error, format!("{e:#}"));
+                return;
+            }
+        };
+        if config.contexts.is_empty() {
+            self.push_toast(
+                ToastLevel::Error,
+                "No contexts configured, add one with 'feos-cli context add'",
+            );
+            return;
+        }
+        let current = config
+            .contexts
+            .iter()
+            .position(|ctx| ctx.name == self.target.name);
+        self.contexts = config.contexts;
+        self.host_picker.select(Some(current.unwrap_or(0)));
+        self.mode = Mode::Hosts;
+    }
+
+    fn handle_hosts_key(&mut self, key: KeyEvent) -> Option<Command> {
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => self.mode = Mode::Normal,
+            KeyCode::Char(c) if c == self.config.keys.hosts => self.mode = Mode::Normal,
+            KeyCode::Down | KeyCode::Char('j') => self.host_picker.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => self.host_picker.select_previous(),
+            KeyCode::Enter => {
+                let index = self.host_picker.selected()?;
+                let target = self.contexts.get(index)?.clone();
+                self.mode = Mode::Normal;
+                return Some(Command::SwitchHost(target));
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn switch_to_context(&mut self, name: &str) -> Option<Command> {
+        let config = match ClientConfig::load() {
+            Ok(config) => config,
+            Err(e) => {
+                self.push_toast(ToastLevel::Error, format!("{e:#}"));
+                return None;
+            }
+        };
+        match config.get(name) {
+            Some(target) => Some(Command::SwitchHost(target.clone())),
+            None => {
+                self.push_toast(ToastLevel::Error, format!("Context '{name}' not found"));
+                None
+            }
+        }
+    }
+
+    /// Forgets everything shown for the previous host after the main loop has
+    /// reconnected to `target`.
+    pub fn connected_to(&mut self, target: HostContext) {
+        self.push_toast(
+            ToastLevel::Info,
+            format!("Connected to {} ({})", target.name, target.address),
+        );
+        self.target = target;
+        self.latency = Latency::Unknown;
+        self.vms.clear();
+        self.containers.clear();
+        self.marked_vms.clear();
+        self.marked_containers.clear();
+        self.clamp_selections();
+        self.console = None;
+        self.host = HostMetrics::new(self.config.metrics_history_len());
+        self.host_error = None;
+        self.vm_metrics.clear();
+        self.alerts = Alerts::default();
+        self.memory_alert_raised = false;
+    }
+
+    /// Switches to the view of the selected alert's resource and selects it.
+    fn jump_to_alert(&mut self) {
+        let Some(resource) = self.alerts.selected().and_then(|a| a.resource.clone()) else {
+            return;
+        };
+        self.mode = Mode::Normal;
+        self.view = resource.view;
+        self.filter.clear();
+        let index = match resource.view {
+            View::Vms => self.vms.iter().position(|vm| vm.vm_id == resource.id),
+            View::Containers => self
+                .containers
+                .iter()
+                .position(|c| c.container_id == resource.id),
+            View::Dashboard => None,
+        };
+        let selected = index.and_then(|index| {
+            let (table, _) = self.current_table()?;
+            table.select(Some(index));
+            Some(())
+        });
+        if selected.is_none() {
+            self.push_toast(
+                ToastLevel::Error,
+                format!("{} no longer exists", resource.id),
+            );
+        }
+    }
+
+    /// VMs matching the current filter, in table order.
+    pub fn visible_vms(&self) -> Vec<&VmInfo> {
+        self.vms
+            .iter()
+            .filter(|vm| {
+                let image = vm.config.as_ref().map_or("", |c| c.image_ref.as_str());
+                matches_filter(&self.filter, &[&vm.vm_id, image, vm_state_name(vm.state)])
+            })
+            .collect()
+    }
+
+    /// Containers matching the current filter, in table order.
+    pub fn visible_containers(&self) -> Vec<&ContainerInfo> {
+        self.containers
+            .iter()
+            .filter(|container| {
+                let image = container
+                    .config
+                    .as_ref()
+                    .map_or("", |c| c.image_ref.as_str());
+                matches_filter(
+                    &self.filter,
+                    &[
+                        &container.container_id,
+                        image,
+                        container_state_name(container.state),
+                    ],
+                )
+            })
+            .collect()
+    }
+
+    fn clamp_selections(&mut self) {
+        let vms = self.visible_vms().len();
+        let containers = self.visible_containers().len();
+        clamp_selection(&mut self.vm_table, vms);
+        clamp_selection(&mut self.container_table, containers);
+    }
+
+    fn handle_search_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Enter => self.mode = Mode::Normal,
+            KeyCode::Esc => {
+                self.filter.clear();
+                self.mode = Mode::Normal;
+            }
+            KeyCode::Backspace => {
+                self.filter.pop();
+            }
+            KeyCode::Char(c) => self.filter.push(c),
+            _ => return,
+        }
+        self.vm_table.select(Some(0));
+        self.container_table.select(Some(0));
+        self.clamp_selections();
+    }
+
+    /// Handles a key press and returns the command to run, if the key triggered one.
+    pub fn handle_key(&mut self, key: KeyEvent) -> Option<Command> {
+        if console::is_detach_key(&key) {
+            self.console = None;
+            return None;
+        }
+        if let Some(console) = &mut self.console {
+            // While scrolled back, vim motions move through the scrollback
+            // instead of being typed into the guest.
+            let scrolled = console.scroll > 0;
+            match key.code {
+                KeyCode::Char('g') if scrolled => console.scroll_to_top(),
+                KeyCode::Char('G') | KeyCode::Char('q') | KeyCode::Esc if scrolled => {
+                    console.scroll = 0
+                }
+                KeyCode::Char('k') if scrolled => console.scroll_up(1),
+                KeyCode::Char('j') if scrolled => console.scroll_down(1),
+                KeyCode::PageUp => console.scroll_up(10),
+                KeyCode::PageDown => console.scroll_down(10),
+                KeyCode::F(2) => self.save_console(false),
+                KeyCode::F(3) => self.save_console(true),
+                KeyCode::F(4) => self.copy_console_line(),
+                _ => console.send_key(key),
+            }
+            return None;
+        }
+
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.should_quit = true;
+            return None;
+        }
+
+        if let Mode::Confirm(actions) = &self.mode {
+            let actions = actions.clone();
+            return match key.code {
+                KeyCode::Char('y') | KeyCode::Enter => {
+                    self.mode = Mode::Normal;
+                    self.marked_vms.clear();
+                    self.marked_containers.clear();
+                    Some(Command::Run(actions))
+                }
+                KeyCode::Char('n') | KeyCode::Esc => {
+                    self.mode = Mode::Normal;
+                    None
+                }
+                _ => None,
+            };
+        }
+
+        if self.mode == Mode::Search {
+            self.handle_search_key(key);
+            return None;
+        }
+
+        if self.mode == Mode::Alerts {
+            self.handle_alerts_key(key);
+            return None;
+        }
+
+        if self.mode == Mode::Hosts {
+            return self.handle_hosts_key(key);
+        }
+
+        if let Mode::Command(input) = &mut self.mode {
+            match key.code {
+                KeyCode::Enter => {
+                    let input = std::mem::take(input);
+                    self.mode = Mode::Normal;
+                    return self.run_palette(&input);
+                }
+                KeyCode::Esc => self.mode = Mode::Normal,
+                KeyCode::Backspace if input.is_empty() => self.mode = Mode::Normal,
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char(c) => input.push(c),
+                _ => {}
+            }
+            return None;
+        }
+
+        let keys = self.config.keys;
+        match key.code {
+            KeyCode::Char(c) if c == keys.quit => self.should_quit = true,
+            KeyCode::Char(c) if c == keys.filter => self.mode = Mode::Search,
+            KeyCode::Char(c) if c == keys.reload => self.reload_config(),
+            KeyCode::Esc if !self.filter.is_empty() => {
+                self.filter.clear();
+                self.clamp_selections();
+            }
+            KeyCode::Esc => {
+                self.marked_vms.clear();
+                self.marked_containers.clear();
+            }
+            KeyCode::Char(' ') => self.toggle_mark(),
+            KeyCode::Char(':') => self.mode = Mode::Command(String::new()),
+            KeyCode::Char(c) if c == keys.alerts => {
+                if self.alerts.state.selected().is_none() {
+                    self.alerts.move_selection(0);
+                }
+                self.mode = Mode::Alerts;
+            }
+            KeyCode::Char(c) if c == keys.hosts => self.open_host_picker(),
+            KeyCode::Tab | KeyCode::Right | KeyCode::Char('l') => self.view = self.view.next(),
+            KeyCode::BackTab | KeyCode::Left | KeyCode::Char('h') => {
+                self.view = self.view.previous()
+            }
+            KeyCode::Char(c @ '1'..='3') => {
+                self.view = View::ALL[c as usize - '1' as usize];
+            }
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::Home | KeyCode::Char('g') => self.move_selection(isize::MIN),
+            KeyCode::End | KeyCode::Char('G') => self.move_selection(isize::MAX),
+            KeyCode::Char(c) if c == keys.console && self.view == View::Vms => {
+                return self.open_console()
+            }
+            KeyCode::Char(c) => {
+                let actions = self.actions_for_key(c);
+                let first = actions.first()?;
+                // Bulk actions are always confirmed, since they are easy to
+                // trigger with rows marked that have scrolled out of sight.
+                if actions.len() > 1 || first.needs_confirmation() {
+                    self.mode = Mode::Confirm(actions);
+                    return None;
+                }
+                return Some(Command::Run(actions));
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// Lines of console output the pane currently has room for.
+    fn console_height(&self) -> usize {
+        self.areas
+            .console
+            .map_or(0, |area| area.height.saturating_sub(2) as usize)
+    }
+
+    /// Saves the console output shown in the pane, or the whole scrollback if
+    /// `full` is set.
+    fn save_console(&mut self, full: bool) {
+        let height = self.console_height();
+        let Some(console) = &self.console else {
+            return;
+        };
+        let range = if full {
+            0..console.lines().len()
+        } else {
+            console.visible_range(height)
+        };
+        let content = console.text(range);
+        let prefix = format!("feos-console-{}", console.vm_id);
+        match export::write_log(&prefix, &content) {
+            Ok(path) => self.push_toast(ToastLevel::Info, format!("Saved {}", path.display())),
+            Err(e) => self.push_toast(ToastLevel::Error, format!("Failed to save log: {e}")),
+        }
+    }
+
+    fn copy_console_line(&mut self) {
+        let Some(line) = self.console.as_ref().and_then(|c| c.selected_line()) else {
+            self.push_toast(ToastLevel::Error, "Click a console line to select it first");
+            return;
+        };
+        match export::copy_to_clipboard(line) {
+            Ok(()) => self.push_toast(ToastLevel::Info, "Copied line to clipboard"),
+            Err(e) => self.push_toast(ToastLevel::Error, format!("Failed to copy: {e}")),
+        }
+    }
+
+    pub fn handle_mouse(&mut self, event: MouseEvent) {
+        let position = Position::new(event.column, event.row);
+        let in_console = self
+            .areas
+            .console
+            .is_some_and(|area| area.contains(position));
+        match event.kind {
+            MouseEventKind::ScrollUp if in_console => {
+                if let Some(console) = &mut self.console {
+                    console.scroll_up(3);
+                }
+            }
+            MouseEventKind::ScrollDown if in_console => {
+                if let Some(console) = &mut self.console {
+                    console.scroll_down(3);
+                }
+            }
+            MouseEventKind::ScrollUp if self.areas.table.contains(position) => {
+                self.move_selection(-1)
+            }
+            MouseEventKind::ScrollDown if self.areas.table.contains(position) => {
+                self.move_selection(1)
+            }
+            MouseEventKind::Down(MouseButton::Left) => {
+                // The console pane's top border doubles as the split handle.
+                if self.areas.console.is_some_and(|area| area.y == event.row) {
+                    self.dragging_split = true;
+                } else if in_console {
+                    let height = self.console_height();
+                    let top = self.areas.console.map_or(0, |area| area.y + 1);
+                    if let Some(console) = &mut self.console {
+                        console.select_row(event.row.saturating_sub(top) as usize, height);
+                    }
+                } else if let Some((view, _)) = self
+                    .areas
+                    .tabs
+                    .iter()
+                    .find(|(_, area)| area.contains(position))
+                {
+                    self.view = *view;
+                } else if self.areas.table.contains(position) {
+                    self.select_row_at(event.row);
+                }
+            }
+            MouseEventKind::Drag(MouseButton::Left) if self.dragging_split => {
+                let body = self.areas.body;
+                if body.height > 0 {
+                    let below = body.bottom().saturating_sub(event.row);
+                    let percent = (below as u32 * 100 / body.height as u32) as u16;
+                    self.console_percent = percent.clamp(CONSOLE_MIN_PERCENT, CONSOLE_MAX_PERCENT);
+                }
+            }
+            MouseEventKind::Up(MouseButton::Left) => self.dragging_split = false,
+            _ => {}
+        }
+    }
+
+    fn select_row_at(&mut self, row: u16) {
+        // Rows start below the table's top border and header line.
+        let first_row = self.areas.table.y + 2;
+        if row < first_row {
+            return;
+        }
+        let Some((table, len)) = self.current_table() else {
+            return;
+        };
+        let index = table.offset() + (row - first_row) as usize;
+        if index < len {
+            table.select(Some(index));
+        }
+    }
+
+    fn run_palette(&mut self, input: &str) -> Option<Command> {
+        let command = match palette::parse(input) {
+            Ok(command) => command,
+            Err(e) => {
+                self.push_toast(ToastLevel::Error, e);
+                return None;
+            }
+        };
+        match command {
+            PaletteCommand::Lifecycle { verb, kind, id } => {
+                let action = match self.resolve_action(verb, kind, &id) {
+                    Ok(action) => action,
+                    Err(e) => {
+                        self.push_toast(ToastLevel::Error, e);
+                        return None;
+                    }
+                };
+                if action.needs_confirmation() {
+                    self.mode = Mode::Confirm(vec![action]);
+                    return None;
+                }
+                return Some(Command::Run(vec![action]));
+            }
+            PaletteCommand::View(view) => self.view = view,
+            PaletteCommand::Console(id) => {
+                match resolve_id(self.vms.iter().map(|vm| &vm.vm_id), &id) {
+                    Some(vm_id) => return Some(self.open_console_for(vm_id)),
+                    None => self.push_toast(ToastLevel::Error, format!("No VM matches '{id}'")),
+                }
+            }
+            PaletteCommand::Filter(filter) => {
+                self.filter = filter;
+                self.clamp_selections();
+            }
+            PaletteCommand::Context(Some(name)) => return self.switch_to_context(&name),
+            PaletteCommand::Context(None) => self.open_host_picker(),
+            PaletteCommand::Reload => self.reload_config(),
+            PaletteCommand::Quit => self.should_quit = true,
+        }
+        None
+    }
+
+    /// Maps a palette verb to an action on the VM or container whose ID equals
+    /// or uniquely starts with `id`.
+    fn resolve_action(&self, verb: Verb, kind: Option<Kind>, id: &str) -> Result<Action, String> {
+        let vm = match kind {
+            Some(Kind::Container) => None,
+            _ => resolve_id(self.vms.iter().map(|vm| &vm.vm_id), id),
+        };
+        if let Some(vm_id) = vm {
+            return Ok(match verb {
+                Verb::Start => Action::StartVm(vm_id),
+                Verb::Stop => Action::StopVm(vm_id),
+                Verb::Pause => Action::PauseVm(vm_id),
+                Verb::Resume => Action::ResumeVm(vm_id),
+                Verb::Restart => Action::RestartVm(vm_id),
+                Verb::Delete => Action::DeleteVm(vm_id),
+            });
+        }
+        let container = match kind {
+            Some(Kind::Vm) => None,
+            _ => resolve_id(self.containers.iter().map(|c| &c.container_id), id),
+        };
+        let Some(container_id) = container else {
+            return Err(format!("No workload matches '{id}'"));
+        };
+        match verb {
+            Verb::Start => Ok(Action::StartContainer(container_id)),
+            Verb::Stop => Ok(Action::StopContainer(container_id)),
+            Verb::Delete => Ok(Action::DeleteContainer(container_id)),
+            Verb::Pause | Verb::Resume | Verb::Restart => {
+                Err("Containers can only be started, stopped or deleted".to_string())
+            }
+        }
+    }
+
+    fn open_console(&mut self) -> Option<Command> {
+        let vm_id = self
+            .visible_vms()
+            .get(self.vm_table.selected()?)?
+            .vm_id
+            .clone();
+        Some(self.open_console_for(vm_id))
+    }
+
+    fn open_console_for(&mut self, vm_id: String) -> Command {
+        let (input_tx, input_rx) = mpsc::unbounded_channel();
+        self.console = Some(ConsoleState::new(vm_id.clone(), input_tx));
+        Command::AttachConsole {
+            vm_id,
+            input: input_rx,
+        }
+    }
+
+    /// Marks or unmarks the selected row and moves to the next one.
+    fn toggle_mark(&mut self) {
+        let (id, marked) = match self.view {
+            View::Vms => {
+                let Some(vm) = self
+                    .vm_table
+                    .selected()
+                    .and_then(|i| self.visible_vms().get(i).copied())
+                else {
+                    return;
+                };
+                (vm.vm_id.clone(), &mut self.marked_vms)
+            }
+            View::Containers => {
+                let Some(container) = self
+                    .container_table
+                    .selected()
+                    .and_then(|i| self.visible_containers().get(i).copied())
+                else {
+                    return;
+                };
+                (container.container_id.clone(), &mut self.marked_containers)
+            }
+            View::Dashboard => return,
+        };
+        if !marked.remove(&id) {
+            marked.insert(id);
+        }
+        self.move_selection(1);
+    }
+
+    /// The actions an action key triggers: one per marked row, or for the
+    /// selected row if none are marked.
+    fn actions_for_key(&self, key: char) -> Vec<Action> {
+        let keys = &self.config.keys;
+        match self.view {
+            View::Vms if self.marked_vms.is_empty() => self
+                .vm_table
+                .selected()
+                .and_then(|i| self.visible_vms().get(i).copied())
+                .and_then(|vm| vm_action(vm, key, keys))
+                .into_iter()
+                .collect(),
+            View::Vms => self
+                .vms
+                .iter()
+                .filter(|vm| self.marked_vms.contains(&vm.vm_id))
+                .filter_map(|vm| vm_action(vm, key, keys))
+                .collect(),
+            View::Containers if self.marked_containers.is_empty() => self
+                .container_table
+                .selected()
+                .and_then(|i| self.visible_containers().get(i).copied())
+                .and_then(|container| container_action(container, key, keys))
+                .into_iter()
+                .collect(),
+            View::Containers => self
+                .containers
+                .iter()
+                .filter(|c| self.marked_containers.contains(&c.container_id))
+                .filter_map(|container| container_action(container, key, keys))
+                .collect(),
+            View::Dashboard => Vec::new(),
+        }
+    }
+
+    /// The table of the current view and its number of visible rows.
+    fn current_table(&mut self) -> Option<(&mut TableState, usize)> {
+        match self.view {
+            View::Vms => {
+                let len = self.visible_vms().len();
+                Some((&mut self.vm_table, len))
+            }
+            View::Containers => {
+                let len = self.visible_containers().len();
+                Some((&mut self.container_table, len))
+            }
+            View::Dashboard => None,
+        }
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let Some((table, len)) = self.current_table() else {
+            return;
+        };
+        if len == 0 {
+            return;
+        }
+        let current = table.selected().unwrap_or(0) as isize;
+        let next = current.saturating_add(delta).clamp(0, len as isize - 1);
+        table.select(Some(next as usize));
+    }
+}
+
+fn resolve_id<'a>(mut ids: impl Iterator<Item = &'a String> + Clone, id: &str) -> Option<String> {
+    if let Some(exact) = ids.clone().find(|candidate| *candidate == id) {
+        return Some(exact.clone());
+    }
+    let first = ids.find(|candidate| candidate.starts_with(id))?;
+    // Ambiguous prefixes must not pick an arbitrary workload.
+    if ids.any(|candidate| candidate.starts_with(id)) {
+        None
+    } else {
+        Some(first.clone())
+    }
+}
+
+fn vm_action(vm: &VmInfo, key: char, keys: &KeyBindings) -> Option<Action> {
+    let id = vm.vm_id.clone();
+    let paused = vm.state == VmState::Paused as i32;
+    let action = if (key == keys.start || key == keys.pause) && paused {
+        Action::ResumeVm(id)
+    } else if key == keys.start {
+        Action::StartVm(id)
+    } else if key == keys.stop {
+        Action::StopVm(id)
+    } else if key == keys.pause {
+        Action::PauseVm(id)
+    } else if key == keys.restart {
+        Action::RestartVm(id)
+    } else if key == keys.delete {
+        Action::DeleteVm(id)
+    } else {
+        return None;
+    };
+    Some(action)
+}
+
+fn container_action(container: &ContainerInfo, key: char, keys: &KeyBindings) -> Option<Action> {
+    let id = container.container_id.clone();
+    let action = if key == keys.start {
+        Action::StartContainer(id)
+    } else if key == keys.stop {
+        Action::StopContainer(id)
+    } else if key == keys.delete {
+        Action::DeleteContainer(id)
+    } else {
+        return None;
+    };
+    Some(action)
+}
+
+fn clamp_selection(table: &mut TableState, len: usize) {
+    match (table.selected(), len) {
+        (_, 0) => table.select(None),
+        (None, _) => table.select(Some(0)),
+        (Some(i), _) if i >= len => table.select(Some(len - 1)),
+        _ => {}
+    }
+}
+
+fn matches_filter(filter: &str, fields: &[&str]) -> bool {
+    if filter.is_empty() {
+        return true;
+    }
+    let filter = filter.to_lowercase();
+    fields
+        .iter()
+        .any(|field| field.to_lowercase().contains(&filter))
+}
+
+pub fn vm_state_name(state: i32) -> &'static str {
+    match VmState::try_from(state) {
+        Ok(VmState::Creating) => "Creating",
+        Ok(VmState::Created) => "Created",
+        Ok(VmState::Running) => "Running",
+        Ok(VmState::Paused) => "Paused",
+        Ok(VmState::Stopped) => "Stopped",
+        Ok(VmState::Crashed) => "Crashed",
+        _ => "Unknown",
+    }
+}
+
+pub fn container_state_name(state: i32) -> &'static str {
+    match ContainerState::try_from(state) {
+        Ok(ContainerState::PullingImage) => "PullingImage",
+        Ok(ContainerState::Created) => "Created",
+        Ok(ContainerState::Running) => "Running",
+        Ok(ContainerState::Stopped) => "Stopped",
+        Ok(ContainerState::Failed) => "Failed",
+        _ => "Unknown",
+    }
+}
+
+/// How the process of a container ended, e.g. `0` or `signal 9`, empty if
+/// it did not.
+pub fn container_exit_status(container: &ContainerInfo) -> String {
+    match (container.exit_signal, container.exit_code) {
+        (Some(signal), _) => format!("signal {signal}"),
+        (None, Some(code)) => code.to_string(),
+        (None, None) => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describe_batches() {
+        assert_eq!(describe(&[Action::StopVm("a".to_string())]), "stop VM a");
+        assert_eq!(
+            describe(&[
+                Action::PauseVm("a".to_string()),
+                Action::ResumeVm("b".to_string()),
+                Action::PauseVm("c".to_string()),
+            ]),
+            "pause/resume 3 VMs"
+        );
+        assert_eq!(
+            describe(&[
+                Action::DeleteContainer("a".to_string()),
+                Action::DeleteContainer("b".to_string()),
+            ]),
+            "delete 2 containers"
+        );
+    }
+}
@@ -0,0 +1,345 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::alerts;
+use crate::app::{Action, AppMessage};
+use crate::metrics::HostSample;
+use anyhow::Result;
+use feos_client_config::HostContext;
+use feos_proto::auth::SigningChannel;
+use feos_proto::container_service::{
+    container_service_client::ContainerServiceClient, ContainerInfo, DeleteContainerRequest,
+    ListContainersRequest, StartContainerRequest, StopContainerRequest,
+    StreamContainerEventsRequest,
+};
+use feos_proto::host_service::{
+    host_service_client::HostServiceClient, GetKernelStatsRequest, MemoryRequest,
+};
+use feos_proto::vm_service::{
+    stream_vm_console_request as console_input, vm_service_client::VmServiceClient,
+    AttachConsoleMessage, ConsoleData, DeleteVmRequest, GetVmRequest, ListVmsRequest,
+    PauseVmRequest, ResumeVmRequest, ShutdownVmRequest, StartVmRequest, StreamVmConsoleRequest,
+    StreamVmEventsRequest, StreamVmMetricsRequest, VmInfo, VmState,
+};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tonic::{Code, Status};
+
+type Channel = SigningChannel<tonic::transport::Channel>;
+
+const RESTART_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const RESTART_TIMEOUT: Duration = Duration::from_secs(60);
+const EVENT_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// gRPC clients for the services shown in the TUI, sharing one channel.
+#[derive(Clone)]
+pub struct FeosClient {
+    vms: VmServiceClient<Channel>,
+    containers: ContainerServiceClient<Channel>,
+    host: HostServiceClient<Channel>,
+}
+
+impl FeosClient {
+    /// Creates a client that connects on first use, so the TUI starts even if the
+    /// daemon is not reachable yet.
+    pub fn connect_lazy(target: &HostContext) -> Result<Self> {
+        let channel = SigningChannel::new(target.endpoint()?.connect_lazy(), target.token()?);
+        Ok(Self {
+            vms: VmServiceClient::new(channel.clone()),
+            containers: ContainerServiceClient::new(channel.clone()),
+            host: HostServiceClient::new(channel),
+        })
+    }
+
+    /// A host built without VM support has no VMs to list.
+    pub async fn list_vms(&mut self) -> Result<Vec<VmInfo>, Status> {
+        match self.vms.list_vms(ListVmsRequest::default()).await {
+            Ok(response) => Ok(response.into_inner().vms),
+            Err(status) if status.code() == Code::Unimplemented => Ok(Vec::new()),
+            Err(status) => Err(status),
+        }
+    }
+
+    /// A host built without container support has no containers to list.
+    pub async fn list_containers(&mut self) -> Result<Vec<ContainerInfo>, Status> {
+        match self
+            .containers
+            .list_containers(ListContainersRequest::default())
+            .await
+        {
+            Ok(response) => Ok(response.into_inner().containers),
+            Err(status) if status.code() == Code::Unimplemented => Ok(Vec::new()),
+            Err(status) => Err(status),
+        }
+    }
+
+    pub async fn host_sample(&mut self) -> Result<HostSample, Status> {
+        let cpu = self
+            .host
+            .get_kernel_stats(GetKernelStatsRequest {})
+            .await?
+            .into_inner()
+            .stats
+            .and_then(|stats| stats.total)
+            .ok_or_else(|| Status::internal("Kernel stats without CPU totals"))?;
+        let mem_info = self
+            .host
+            .get_memory(MemoryRequest {})
+            .await?
+            .into_inner()
+            .mem_info
+            .unwrap_or_default();
+        Ok(HostSample {
+            cpu,
+            mem_total_kb: mem_info.memtotal,
+            mem_available_kb: mem_info.memavailable,
+        })
+    }
+
+    pub async fn run(&mut self, action: &Action) -> Result<(), Status> {
+        match action {
+            Action::StartVm(vm_id) => {
+                self.vms
+                    .start_vm(StartVmRequest {
+                        vm_id: vm_id.clone(),
+                    })
+                    .await?;
+            }
+            Action::StopVm(vm_id) => {
+                self.vms
+                    .shutdown_vm(ShutdownVmRequest {
+                        vm_id: vm_id.clone(),
+                        graceful: false,
+                        grace_period_seconds: None,
+                    })
+                    .await?;
+            }
+            Action::PauseVm(vm_id) => {
+                self.vms
+                    .pause_vm(PauseVmRequest {
+                        vm_id: vm_id.clone(),
+                    })
+                    .await?;
+            }
+            Action::ResumeVm(vm_id) => {
+                self.vms
+                    .resume_vm(ResumeVmRequest {
+                        vm_id: vm_id.clone(),
+                    })
+                    .await?;
+            }
+            Action::RestartVm(vm_id) => self.restart_vm(vm_id).await?,
+            Action::DeleteVm(vm_id) => {
+                self.vms
+                    .delete_vm(DeleteVmRequest {
+                        vm_id: vm_id.clone(),
+                    })
+                    .await?;
+            }
+            Action::StartContainer(container_id) => {
+                self.containers
+                    .start_container(StartContainerRequest {
+                        container_id: container_id.clone(),
+                    })
+                    .await?;
+            }
+            Action::StopContainer(container_id) => {
+                self.containers
+                    .stop_container(StopContainerRequest {
+                        container_id: container_id.clone(),
+                        ..Default::default()
+                    })
+                    .await?;
+            }
+            Action::DeleteContainer(container_id) => {
+                self.containers
+                    .delete_container(DeleteContainerRequest {
+                        container_id: container_id.clone(),
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// There is no reboot RPC, so a restart is a shutdown followed by a start
+    /// once the VM has reached the stopped state.
+    async fn restart_vm(&mut self, vm_id: &str) -> Result<(), Status> {
+        self.vms
+            .shutdown_vm(ShutdownVmRequest {
+                vm_id: vm_id.to_string(),
+                graceful: false,
+                grace_period_seconds: None,
+            })
+            .await?;
+
+        let wait_for_stop = async {
+            loop {
+                let vm = self
+                    .vms
+                    .get_vm(GetVmRequest {
+                        vm_id: vm_id.to_string(),
+                    })
+                    .await?
+                    .into_inner();
+                if vm.state == VmState::Stopped as i32 {
+                    return Ok::<(), Status>(());
+                }
+                tokio::time::sleep(RESTART_POLL_INTERVAL).await;
+            }
+        };
+        tokio::time::timeout(RESTART_TIMEOUT, wait_for_stop)
+            .await
+            .map_err(|_| Status::deadline_exceeded("VM did not stop in time"))??;
+
+        self.vms
+            .start_vm(StartVmRequest {
+                vm_id: vm_id.to_string(),
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Relays keystrokes from `input` to the VM console and its output to the
+    /// main loop until either side goes away. Dropping the input sender detaches.
+    pub async fn stream_console(
+        &mut self,
+        vm_id: String,
+        mut input: mpsc::UnboundedReceiver<Vec<u8>>,
+        msg_tx: mpsc::UnboundedSender<AppMessage>,
+    ) {
+        let closed = |reason: String| AppMessage::ConsoleClosed {
+            vm_id: vm_id.clone(),
+            reason,
+        };
+
+        let (request_tx, request_rx) = mpsc::channel(16);
+        let attach = StreamVmConsoleRequest {
+            payload: Some(console_input::Payload::Attach(AttachConsoleMessage {
+                vm_id: vm_id.clone(),
+                read_only: false,
+                takeover: false,
+            })),
+        };
+        if request_tx.send(attach).await.is_err() {
+            return;
+        }
+        let mut output = match self
+            .vms
+            .stream_vm_console(ReceiverStream::new(request_rx))
+            .await
+        {
+            Ok(response) => response.into_inner(),
+            Err(status) => {
+                let _ = msg_tx.send(closed(status.message().to_string()));
+                return;
+            }
+        };
+
+        loop {
+            tokio::select! {
+                data = input.recv() => {
+                    let Some(data) = data else {
+                        return;
+                    };
+                    let request = StreamVmConsoleRequest {
+                        payload: Some(console_input::Payload::Data(ConsoleData { input: data })),
+                    };
+                    if request_tx.send(request).await.is_err() {
+                        let _ = msg_tx.send(closed("console stream closed".to_string()));
+                        return;
+                    }
+                }
+                message = output.next() => {
+                    let message = match message {
+                        Some(Ok(response)) => AppMessage::ConsoleOutput {
+                            vm_id: vm_id.clone(),
+                            output: response.output,
+                        },
+                        Some(Err(status)) => closed(status.message().to_string()),
+                        None => closed("console stream ended".to_string()),
+                    };
+                    let done = matches!(message, AppMessage::ConsoleClosed { .. });
+                    if msg_tx.send(message).is_err() || done {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Subscribes to VM and container events and forwards the ones worth an
+    /// alert. Subscriptions are re-established while the daemon is unreachable.
+    pub async fn watch_alerts(self, msg_tx: mpsc::UnboundedSender<AppMessage>) {
+        tokio::join!(
+            self.clone().watch_vm_events(msg_tx.clone()),
+            self.watch_container_events(msg_tx)
+        );
+    }
+
+    async fn watch_vm_events(mut self, msg_tx: mpsc::UnboundedSender<AppMessage>) {
+        while !msg_tx.is_closed() {
+            if let Ok(response) = self
+                .vms
+                .stream_vm_events(StreamVmEventsRequest::default())
+                .await
+            {
+                let mut events = response.into_inner();
+                while let Some(Ok(event)) = events.next().await {
+                    if let Some(alert) = alerts::from_vm_event(&event) {
+                        let _ = msg_tx.send(AppMessage::Alert(alert));
+                    }
+                }
+            }
+            tokio::time::sleep(EVENT_RETRY_INTERVAL).await;
+        }
+    }
+
+    /// Forwards the usage of the VMs, sampled every `interval`, and
+    /// subscribes again while the daemon is unreachable. A host built without
+    /// VM support has none.
+    pub async fn watch_vm_metrics(
+        mut self,
+        interval: Duration,
+        msg_tx: mpsc::UnboundedSender<AppMessage>,
+    ) {
+        let request = StreamVmMetricsRequest {
+            vm_ids: Vec::new(),
+            interval_seconds: interval.as_secs().clamp(1, u32::MAX.into()) as u32,
+        };
+        while !msg_tx.is_closed() {
+            match self.vms.stream_vm_metrics(request.clone()).await {
+                Ok(response) => {
+                    let mut samples = response.into_inner();
+                    while let Some(Ok(sample)) = samples.next().await {
+                        if msg_tx.send(AppMessage::VmMetrics(sample)).is_err() {
+                            return;
+                        }
+                    }
+                }
+                Err(status) if status.code() == Code::Unimplemented => return,
+                Err(_) => {}
+            }
+            tokio::time::sleep(EVENT_RETRY_INTERVAL).await;
+        }
+    }
+
+    async fn watch_container_events(mut self, msg_tx: mpsc::UnboundedSender<AppMessage>) {
+        while !msg_tx.is_closed() {
+            if let Ok(response) = self
+                .containers
+                .stream_container_events(StreamContainerEventsRequest::default())
+                .await
+            {
+                let mut events = response.into_inner();
+                while let Some(Ok(event)) = events.next().await {
+                    if let Some(alert) = alerts::from_container_event(&event) {
+                        let _ = msg_tx.send(AppMessage::Alert(alert));
+                    }
+                }
+            }
+            tokio::time::sleep(EVENT_RETRY_INTERVAL).await;
+        }
+    }
+}
@@ -0,0 +1,176 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::app::View;
+use crate::theme::ThemeName;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const MIN_REFRESH_INTERVAL_MS: u64 = 250;
+
+/// TUI settings stored in `$FEOS_TUI_CONFIG`, `$XDG_CONFIG_HOME/feos/tui.yaml` or
+/// `~/.config/feos/tui.yaml`. Every field is optional.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct TuiConfig {
+    pub refresh_interval_ms: u64,
+    /// Time span covered by the dashboard sparklines.
+    pub metrics_history_secs: u64,
+    pub default_view: View,
+    pub theme: ThemeName,
+    pub keys: KeyBindings,
+}
+
+impl Default for TuiConfig {
+    fn default() -> Self {
+        Self {
+            refresh_interval_ms: 2000,
+            metrics_history_secs: 300,
+            default_view: View::Vms,
+            theme: ThemeName::default(),
+            keys: KeyBindings::default(),
+        }
+    }
+}
+
+/// Single-character bindings for the table views. Navigation, Tab, Esc and the
+/// confirm dialog keys are fixed.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct KeyBindings {
+    pub start: char,
+    pub stop: char,
+    pub pause: char,
+    pub restart: char,
+    pub delete: char,
+    pub console: char,
+    pub alerts: char,
+    pub hosts: char,
+    pub filter: char,
+    pub reload: char,
+    pub quit: char,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            start: 's',
+            stop: 't',
+            pause: 'p',
+            restart: 'r',
+            delete: 'd',
+            console: 'c',
+            alerts: 'a',
+            hosts: 'H',
+            filter: '/',
+            reload: 'R',
+            quit: 'q',
+        }
+    }
+}
+
+impl KeyBindings {
+    fn all(&self) -> [(&'static str, char); 11] {
+        [
+            ("start", self.start),
+            ("stop", self.stop),
+            ("pause", self.pause),
+            ("restart", self.restart),
+            ("delete", self.delete),
+            ("console", self.console),
+            ("alerts", self.alerts),
+            ("hosts", self.hosts),
+            ("filter", self.filter),
+            ("reload", self.reload),
+            ("quit", self.quit),
+        ]
+    }
+
+    fn validate(&self) -> Result<()> {
+        let bindings = self.all();
+        for (i, &(name, key)) in bindings.iter().enumerate() {
+            if matches!(
+                key,
+                'h' | 'j' | 'k' | 'l' | 'g' | 'G' | ':' | ' ' | '1'..='3'
+            ) {
+                bail!("Key '{key}' for '{name}' is reserved for navigation");
+            }
+            if let Some((other, _)) = bindings[i + 1..].iter().find(|(_, k)| *k == key) {
+                bail!("Key '{key}' is bound to both '{name}' and '{other}'");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TuiConfig {
+    pub fn path() -> Result<PathBuf> {
+        if let Some(path) = std::env::var_os("FEOS_TUI_CONFIG") {
+            return Ok(PathBuf::from(path));
+        }
+        let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => std::env::var_os("HOME")
+                .map(|home| PathBuf::from(home).join(".config"))
+                .ok_or_else(|| {
+                    anyhow!("Neither FEOS_TUI_CONFIG, XDG_CONFIG_HOME nor HOME is set")
+                })?,
+        };
+        Ok(config_dir.join("feos").join("tui.yaml"))
+    }
+
+    /// Loads the TUI configuration. A missing file yields the defaults.
+    pub fn load_from(path: &Path) -> Result<Self> {
+        let config: Self = match std::fs::read_to_string(path) {
+            Ok(content) if content.trim().is_empty() => Self::default(),
+            Ok(content) => serde_yaml::from_str(&content)
+                .with_context(|| format!("Failed to parse TUI config {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read TUI config {}", path.display()))
+            }
+        };
+        config
+            .keys
+            .validate()
+            .with_context(|| format!("Invalid key bindings in {}", path.display()))?;
+        Ok(config)
+    }
+
+    pub fn refresh_interval(&self) -> Duration {
+        Duration::from_millis(self.refresh_interval_ms.max(MIN_REFRESH_INTERVAL_MS))
+    }
+
+    /// Number of refresh samples kept for the dashboard sparklines.
+    pub fn metrics_history_len(&self) -> usize {
+        let interval_ms = self.refresh_interval().as_millis() as u64;
+        (self.metrics_history_secs * 1000 / interval_ms) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_config_keeps_defaults() {
+        let config: TuiConfig =
+            serde_yaml::from_str("theme: colorblind\nkeys:\n  stop: x\n").unwrap();
+        assert_eq!(config.theme, ThemeName::Colorblind);
+        assert_eq!(config.keys.stop, 'x');
+        assert_eq!(config.keys.start, 's');
+        assert_eq!(config.default_view, View::Vms);
+    }
+
+    #[test]
+    fn duplicate_bindings_are_rejected() {
+        let keys = KeyBindings {
+            stop: 's',
+            ..Default::default()
+        };
+        assert!(keys.validate().is_err());
+    }
+}
@@ -0,0 +1,182 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::VecDeque;
+use std::ops::Range;
+use tokio::sync::mpsc;
+
+const SCROLLBACK_LINES: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Escape {
+    None,
+    Esc,
+    Csi,
+}
+
+/// An attached serial console of a VM: the scrollback received so far and the
+/// sender feeding keystrokes to the console stream.
+pub struct ConsoleState {
+    pub vm_id: String,
+    lines: VecDeque<String>,
+    escape: Escape,
+    /// Number of lines scrolled up from the bottom.
+    pub scroll: usize,
+    /// Index into the scrollback of the line picked with the mouse.
+    pub selected: Option<usize>,
+    pub closed: Option<String>,
+    input: mpsc::UnboundedSender<Vec<u8>>,
+}
+
+impl ConsoleState {
+    pub fn new(vm_id: String, input: mpsc::UnboundedSender<Vec<u8>>) -> Self {
+        Self {
+            vm_id,
+            lines: VecDeque::from([String::new()]),
+            escape: Escape::None,
+            scroll: 0,
+            selected: None,
+            closed: None,
+            input,
+        }
+    }
+
+    pub fn lines(&self) -> &VecDeque<String> {
+        &self.lines
+    }
+
+    /// Scrollback indices shown in a pane with room for `height` lines.
+    pub fn visible_range(&self, height: usize) -> Range<usize> {
+        let end = self.lines.len().saturating_sub(self.scroll);
+        end.saturating_sub(height)..end
+    }
+
+    /// Selects the line at `row` of a pane with room for `height` lines.
+    pub fn select_row(&mut self, row: usize, height: usize) {
+        let range = self.visible_range(height);
+        let index = range.start + row;
+        if range.contains(&index) {
+            self.selected = Some(index);
+        }
+    }
+
+    pub fn selected_line(&self) -> Option<&str> {
+        self.lines.get(self.selected?).map(String::as_str)
+    }
+
+    /// The lines in `range` joined with newlines, for exporting.
+    pub fn text(&self, range: Range<usize>) -> String {
+        let mut text = String::new();
+        for line in self.lines.range(range) {
+            text.push_str(line);
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Appends console output. Terminal control sequences are dropped since the
+    /// pane renders plain text only.
+    pub fn push_output(&mut self, output: &[u8]) {
+        for c in String::from_utf8_lossy(output).chars() {
+            match (self.escape, c) {
+                (Escape::Esc, '[') => self.escape = Escape::Csi,
+                (Escape::Esc, _) => self.escape = Escape::None,
+                (Escape::Csi, '\x40'..='\x7e') => self.escape = Escape::None,
+                (Escape::Csi, _) => {}
+                (Escape::None, '\x1b') => self.escape = Escape::Esc,
+                (Escape::None, '\n') => self.new_line(),
+                (Escape::None, '\x08') => {
+                    self.current_line().pop();
+                }
+                (Escape::None, '\t') => self.current_line().push_str("    "),
+                (Escape::None, c) if c.is_control() => {}
+                (Escape::None, c) => self.current_line().push(c),
+            }
+        }
+    }
+
+    fn current_line(&mut self) -> &mut String {
+        self.lines.back_mut().expect("console always has a line")
+    }
+
+    fn new_line(&mut self) {
+        self.lines.push_back(String::new());
+        if self.lines.len() > SCROLLBACK_LINES {
+            self.lines.pop_front();
+            self.selected = self.selected.and_then(|i| i.checked_sub(1));
+        } else if self.scroll > 0 {
+            // Keep the viewed region in place while output arrives.
+            self.scroll += 1;
+        }
+    }
+
+    pub fn scroll_up(&mut self, lines: usize) {
+        self.scroll = (self.scroll + lines).min(self.lines.len().saturating_sub(1));
+    }
+
+    pub fn scroll_to_top(&mut self) {
+        self.scroll = self.lines.len().saturating_sub(1);
+    }
+
+    pub fn scroll_down(&mut self, lines: usize) {
+        self.scroll = self.scroll.saturating_sub(lines);
+    }
+
+    /// Forwards a key press to the guest. Keys without a terminal encoding are
+    /// ignored.
+    pub fn send_key(&mut self, key: KeyEvent) {
+        let Some(bytes) = key_to_bytes(key) else {
+            return;
+        };
+        self.scroll = 0;
+        // A closed console keeps its scrollback visible until detached.
+        let _ = self.input.send(bytes);
+    }
+}
+
+/// Ctrl+] detaches, as in `feos-cli vm console`. Terminals report it either as
+/// `]` or as `5` with the control modifier.
+pub fn is_detach_key(key: &KeyEvent) -> bool {
+    key.modifiers.contains(KeyModifiers::CONTROL)
+        && matches!(key.code, KeyCode::Char(']') | KeyCode::Char('5'))
+}
+
+fn key_to_bytes(key: KeyEvent) -> Option<Vec<u8>> {
+    let bytes = match key.code {
+        KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            if !c.is_ascii() {
+                return None;
+            }
+            vec![(c.to_ascii_lowercase() as u8) & 0x1f]
+        }
+        KeyCode::Char(c) => c.to_string().into_bytes(),
+        KeyCode::Enter => b"\r".to_vec(),
+        KeyCode::Backspace => vec![0x7f],
+        KeyCode::Tab => b"\t".to_vec(),
+        KeyCode::Esc => vec![0x1b],
+        KeyCode::Up => b"\x1b[A".to_vec(),
+        KeyCode::Down => b"\x1b[B".to_vec(),
+        KeyCode::Right => b"\x1b[C".to_vec(),
+        KeyCode::Left => b"\x1b[D".to_vec(),
+        KeyCode::Home => b"\x1b[H".to_vec(),
+        KeyCode::End => b"\x1b[F".to_vec(),
+        KeyCode::Delete => b"\x1b[3~".to_vec(),
+        _ => return None,
+    };
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_output_strips_control_sequences() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let mut console = ConsoleState::new("vm".to_string(), tx);
+        console.push_output(b"\x1b[1;32mlogin:\x1b[0m \r\nroot");
+        console.push_output(b"x\x08\n");
+        assert_eq!(console.lines(), &["login: ", "root", ""]);
+    }
+}
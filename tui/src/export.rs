@@ -0,0 +1,26 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Writes `content` to `<prefix>-<timestamp>.log` in the current directory and
+/// returns the path.
+pub fn write_log(prefix: &str, content: &str) -> std::io::Result<PathBuf> {
+    let path = PathBuf::from(format!(
+        "{prefix}-{}.log",
+        chrono::Local::now().format("%Y%m%d-%H%M%S")
+    ));
+    std::fs::write(&path, content)?;
+    Ok(path)
+}
+
+/// Copies `text` to the clipboard with an OSC 52 escape sequence. This is
+/// handled by the terminal emulator, so it also works over SSH, but some
+/// terminals ignore it or require it to be enabled.
+pub fn copy_to_clipboard(text: &str) -> std::io::Result<()> {
+    let mut stdout = std::io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", STANDARD.encode(text))?;
+    stdout.flush()
+}
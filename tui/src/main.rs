@@ -0,0 +1,213 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::Result;
+use app::{App, AppMessage, Command, ToastLevel};
+use clap::Parser;
+use client::FeosClient;
+use config::TuiConfig;
+use ratatui::crossterm::event::{
+    self, DisableMouseCapture, EnableMouseCapture, Event, KeyEventKind,
+};
+use ratatui::crossterm::execute;
+use ratatui::DefaultTerminal;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+
+mod alerts;
+mod app;
+mod client;
+mod config;
+mod console;
+mod export;
+mod metrics;
+mod palette;
+mod theme;
+mod ui;
+
+const TICK_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    #[arg(
+        short,
+        long,
+        env = "FEOS_ADDRESS",
+        help = "FeOS API address, overriding the context's address (default: http://[::1]:1337)"
+    )]
+    address: Option<String>,
+
+    #[arg(
+        long,
+        env = "FEOS_CONTEXT",
+        help = "Context from the feos-cli config to connect to (default: the current context)"
+    )]
+    context: Option<String>,
+
+    #[arg(
+        long,
+        env = "FEOS_TUI_CONFIG",
+        help = "Path to the TUI config file (default: ~/.config/feos/tui.yaml)"
+    )]
+    config: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let target = feos_client_config::resolve(cli.address.as_deref(), cli.context.as_deref())?;
+    let client = FeosClient::connect_lazy(&target)?;
+    let config_path = match cli.config {
+        Some(path) => path,
+        None => TuiConfig::path()?,
+    };
+    let config = TuiConfig::load_from(&config_path)?;
+
+    let mut terminal = ratatui::init();
+    let app = App::new(target, config, config_path);
+    let result = match execute!(std::io::stdout(), EnableMouseCapture) {
+        Ok(()) => run(&mut terminal, app, client).await,
+        Err(e) => Err(e.into()),
+    };
+    let _ = execute!(std::io::stdout(), DisableMouseCapture);
+    ratatui::restore();
+    result
+}
+
+async fn run(terminal: &mut DefaultTerminal, mut app: App, mut client: FeosClient) -> Result<()> {
+    let (input_tx, mut input_rx) = mpsc::unbounded_channel();
+    // crossterm's blocking reader would stall the runtime, so it gets its own thread.
+    std::thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            if input_tx.send(event).is_err() {
+                break;
+            }
+        }
+    });
+
+    let (mut msg_tx, mut msg_rx) = mpsc::unbounded_channel();
+    let mut refresh_interval = app.config.refresh_interval();
+    let mut refresh = tokio::time::interval(refresh_interval);
+    let mut tick = tokio::time::interval(TICK_INTERVAL);
+    let mut watcher = tokio::spawn(client.clone().watch_alerts(msg_tx.clone()));
+    let mut metrics_watcher = tokio::spawn(
+        client
+            .clone()
+            .watch_vm_metrics(refresh_interval, msg_tx.clone()),
+    );
+
+    while !app.should_quit {
+        if app.config.refresh_interval() != refresh_interval {
+            refresh_interval = app.config.refresh_interval();
+            refresh = tokio::time::interval(refresh_interval);
+            metrics_watcher.abort();
+            metrics_watcher = tokio::spawn(
+                client
+                    .clone()
+                    .watch_vm_metrics(refresh_interval, msg_tx.clone()),
+            );
+        }
+        terminal.draw(|frame| ui::draw(frame, &mut app))?;
+
+        tokio::select! {
+            Some(event) = input_rx.recv() => {
+                match event {
+                    Event::Key(key) if key.kind == KeyEventKind::Press => {
+                        match app.handle_key(key) {
+                            Some(Command::SwitchHost(target)) => {
+                                match FeosClient::connect_lazy(&target) {
+                                    Ok(new_client) => {
+                                        // Results still in flight for the previous host are
+                                        // sent to the old channel and dropped with it.
+                                        watcher.abort();
+                                        metrics_watcher.abort();
+                                        client = new_client;
+                                        (msg_tx, msg_rx) = mpsc::unbounded_channel();
+                                        watcher = tokio::spawn(
+                                            client.clone().watch_alerts(msg_tx.clone()),
+                                        );
+                                        metrics_watcher = tokio::spawn(
+                                            client
+                                                .clone()
+                                                .watch_vm_metrics(refresh_interval, msg_tx.clone()),
+                                        );
+                                        app.connected_to(target);
+                                        refresh.reset_immediately();
+                                    }
+                                    Err(e) => app.push_toast(ToastLevel::Error, format!("{e:#}")),
+                                }
+                            }
+                            Some(command) => spawn_command(command, client.clone(), msg_tx.clone()),
+                            None => {}
+                        }
+                    }
+                    Event::Mouse(mouse) => app.handle_mouse(mouse),
+                    _ => {}
+                }
+            }
+            Some(message) = msg_rx.recv() => app.handle_message(message),
+            _ = refresh.tick() => {
+                tokio::spawn(refresh_all(client.clone(), msg_tx.clone()));
+            }
+            _ = tick.tick() => app.prune_toasts(),
+        }
+    }
+    Ok(())
+}
+
+fn spawn_command(
+    command: Command,
+    mut client: FeosClient,
+    msg_tx: mpsc::UnboundedSender<AppMessage>,
+) {
+    match command {
+        Command::Run(actions) => {
+            tokio::spawn(async move {
+                let mut tasks = JoinSet::new();
+                for action in actions {
+                    let mut client = client.clone();
+                    tasks.spawn(async move {
+                        let result = client
+                            .run(&action)
+                            .await
+                            .map_err(|s| s.message().to_string());
+                        (action, result)
+                    });
+                }
+                let mut results = Vec::new();
+                while let Some(joined) = tasks.join_next().await {
+                    if let Ok(outcome) = joined {
+                        results.push(outcome);
+                    }
+                }
+                let _ = msg_tx.send(AppMessage::ActionsDone(results));
+                refresh_all(client, msg_tx).await;
+            });
+        }
+        Command::AttachConsole { vm_id, input } => {
+            tokio::spawn(async move { client.stream_console(vm_id, input, msg_tx).await });
+        }
+        Command::SwitchHost(_) => unreachable!("host switches are handled by the main loop"),
+    }
+}
+
+async fn refresh_all(mut client: FeosClient, msg_tx: mpsc::UnboundedSender<AppMessage>) {
+    let started = Instant::now();
+    let vms = client.list_vms().await.map_err(|s| s.message().to_string());
+    let latency = vms.is_ok().then(|| started.elapsed());
+    let _ = msg_tx.send(AppMessage::Latency(latency));
+    let _ = msg_tx.send(AppMessage::Vms(vms));
+    let containers = client
+        .list_containers()
+        .await
+        .map_err(|s| s.message().to_string());
+    let _ = msg_tx.send(AppMessage::Containers(containers));
+    let host = client
+        .host_sample()
+        .await
+        .map_err(|s| s.message().to_string());
+    let _ = msg_tx.send(AppMessage::Host(host));
+}
@@ -0,0 +1,109 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use feos_proto::host_service::CpuTime;
+use std::collections::VecDeque;
+
+/// One poll of the host's CPU counters and memory usage.
+pub struct HostSample {
+    pub cpu: CpuTime,
+    pub mem_total_kb: u64,
+    pub mem_available_kb: u64,
+}
+
+/// Rolling history of host utilization for the dashboard sparklines, in percent.
+pub struct HostMetrics {
+    capacity: usize,
+    pub cpu: VecDeque<u64>,
+    pub memory: VecDeque<u64>,
+    pub mem_total_kb: u64,
+    pub mem_used_kb: u64,
+    last_cpu: Option<CpuTime>,
+}
+
+impl HostMetrics {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(2),
+            cpu: VecDeque::new(),
+            memory: VecDeque::new(),
+            mem_total_kb: 0,
+            mem_used_kb: 0,
+            last_cpu: None,
+        }
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(2);
+        truncate_front(&mut self.cpu, self.capacity);
+        truncate_front(&mut self.memory, self.capacity);
+    }
+
+    pub fn push(&mut self, sample: HostSample) {
+        // CPU counters are cumulative, so utilization needs two samples.
+        if let Some(last) = &self.last_cpu {
+            if let Some(busy) = busy_percent(last, &sample.cpu) {
+                self.cpu.push_back(busy);
+                truncate_front(&mut self.cpu, self.capacity);
+            }
+        }
+        self.last_cpu = Some(sample.cpu);
+
+        self.mem_total_kb = sample.mem_total_kb;
+        self.mem_used_kb = sample.mem_total_kb.saturating_sub(sample.mem_available_kb);
+        if sample.mem_total_kb > 0 {
+            self.memory
+                .push_back(self.mem_used_kb * 100 / sample.mem_total_kb);
+            truncate_front(&mut self.memory, self.capacity);
+        }
+    }
+}
+
+fn truncate_front(values: &mut VecDeque<u64>, capacity: usize) {
+    while values.len() > capacity {
+        values.pop_front();
+    }
+}
+
+fn idle_and_total(cpu: &CpuTime) -> (u64, u64) {
+    // guest and guest_nice are already accounted for in user and nice.
+    let idle = cpu.idle + cpu.iowait;
+    let total = cpu.user + cpu.nice + cpu.system + idle + cpu.irq + cpu.softirq + cpu.steal;
+    (idle, total)
+}
+
+fn busy_percent(previous: &CpuTime, current: &CpuTime) -> Option<u64> {
+    let (prev_idle, prev_total) = idle_and_total(previous);
+    let (idle, total) = idle_and_total(current);
+    let total_delta = total.checked_sub(prev_total).filter(|delta| *delta > 0)?;
+    let idle_delta = idle.saturating_sub(prev_idle).min(total_delta);
+    Some((total_delta - idle_delta) * 100 / total_delta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cpu(user: u64, idle: u64) -> CpuTime {
+        CpuTime {
+            name: "cpu".to_string(),
+            user,
+            idle,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn cpu_utilization_is_computed_from_deltas() {
+        let mut metrics = HostMetrics::new(3);
+        for (user, idle) in [(0, 0), (25, 75), (125, 75), (150, 150), (150, 250)] {
+            metrics.push(HostSample {
+                cpu: cpu(user, idle),
+                mem_total_kb: 1000,
+                mem_available_kb: 250,
+            });
+        }
+        assert_eq!(metrics.cpu, [100, 25, 0]);
+        assert_eq!(metrics.memory.back(), Some(&75));
+    }
+}
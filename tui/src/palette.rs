@@ -0,0 +1,133 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::app::View;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verb {
+    Start,
+    Stop,
+    Pause,
+    Resume,
+    Restart,
+    Delete,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Vm,
+    Container,
+}
+
+/// A command entered in the `:` palette.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaletteCommand {
+    /// Lifecycle verb on a workload. Without an explicit kind the ID is looked
+    /// up among VMs first, then containers.
+    Lifecycle {
+        verb: Verb,
+        kind: Option<Kind>,
+        id: String,
+    },
+    View(View),
+    Console(String),
+    Filter(String),
+    /// Connect to the named context, or pick one when no name is given.
+    Context(Option<String>),
+    Reload,
+    Quit,
+}
+
+pub const USAGE: &str = "start|stop|pause|resume|restart|delete [vm|container] <id>, \
+                         view vms|containers|dashboard, console <vm-id>, filter [text], context [name], reload, quit";
+
+pub fn parse(input: &str) -> Result<PaletteCommand, String> {
+    let mut words = input.split_whitespace();
+    let Some(command) = words.next() else {
+        return Err(format!("Usage: {USAGE}"));
+    };
+    let args: Vec<&str> = words.collect();
+
+    let verb = match command {
+        "start" => Some(Verb::Start),
+        "stop" => Some(Verb::Stop),
+        "pause" => Some(Verb::Pause),
+        "resume" => Some(Verb::Resume),
+        "restart" => Some(Verb::Restart),
+        "delete" | "rm" => Some(Verb::Delete),
+        _ => None,
+    };
+    if let Some(verb) = verb {
+        let (kind, id) = match args.as_slice() {
+            [id] => (None, id),
+            ["vm", id] => (Some(Kind::Vm), id),
+            ["container", id] => (Some(Kind::Container), id),
+            _ => return Err(format!("Usage: {command} [vm|container] <id>")),
+        };
+        return Ok(PaletteCommand::Lifecycle {
+            verb,
+            kind,
+            id: id.to_string(),
+        });
+    }
+
+    match (command, args.as_slice()) {
+        ("view", [name]) => {
+            let view = match *name {
+                "vms" | "vm" => View::Vms,
+                "containers" | "container" => View::Containers,
+                "dashboard" | "metrics" => View::Dashboard,
+                _ => return Err(format!("Unknown view '{name}'")),
+            };
+            Ok(PaletteCommand::View(view))
+        }
+        ("console", [id]) => Ok(PaletteCommand::Console(id.to_string())),
+        ("filter", args) => Ok(PaletteCommand::Filter(args.join(" "))),
+        ("context" | "ctx", []) => Ok(PaletteCommand::Context(None)),
+        ("context" | "ctx", [name]) => Ok(PaletteCommand::Context(Some(name.to_string()))),
+        ("reload", []) => Ok(PaletteCommand::Reload),
+        ("q" | "quit", []) => Ok(PaletteCommand::Quit),
+        _ => Err(format!("Unknown command '{input}'. Usage: {USAGE}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_lifecycle_commands() {
+        assert_eq!(
+            parse("stop vm-001"),
+            Ok(PaletteCommand::Lifecycle {
+                verb: Verb::Stop,
+                kind: None,
+                id: "vm-001".to_string()
+            })
+        );
+        assert_eq!(
+            parse("delete container abc"),
+            Ok(PaletteCommand::Lifecycle {
+                verb: Verb::Delete,
+                kind: Some(Kind::Container),
+                id: "abc".to_string()
+            })
+        );
+        assert!(parse("stop").is_err());
+    }
+
+    #[test]
+    fn parse_other_commands() {
+        assert_eq!(
+            parse("view dashboard"),
+            Ok(PaletteCommand::View(View::Dashboard))
+        );
+        assert_eq!(parse("filter"), Ok(PaletteCommand::Filter(String::new())));
+        assert_eq!(parse(" q "), Ok(PaletteCommand::Quit));
+        assert_eq!(
+            parse("context lab"),
+            Ok(PaletteCommand::Context(Some("lab".to_string())))
+        );
+        assert!(parse("view pods").is_err());
+    }
+}
@@ -0,0 +1,87 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use ratatui::style::{Color, Modifier, Style};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemeName {
+    #[default]
+    Dark,
+    Light,
+    /// Okabe-Ito palette, distinguishable with the common forms of color blindness.
+    Colorblind,
+}
+
+/// Colors used across the TUI. State colors are looked up by state name so VMs
+/// and containers share one palette.
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    pub running: Color,
+    pub paused: Color,
+    pub failed: Color,
+    pub stopped: Color,
+    pub pending: Color,
+    pub info: Color,
+    pub error: Color,
+    pub warning: Color,
+    pub muted: Color,
+}
+
+impl Theme {
+    pub fn new(name: ThemeName) -> Self {
+        match name {
+            ThemeName::Dark => Self {
+                running: Color::Green,
+                paused: Color::Yellow,
+                failed: Color::Red,
+                stopped: Color::DarkGray,
+                pending: Color::Cyan,
+                info: Color::Green,
+                error: Color::Red,
+                warning: Color::Yellow,
+                muted: Color::DarkGray,
+            },
+            // The bright ANSI colors are hard to read on white backgrounds.
+            ThemeName::Light => Self {
+                running: Color::Rgb(0, 110, 0),
+                paused: Color::Rgb(160, 90, 0),
+                failed: Color::Rgb(180, 0, 0),
+                stopped: Color::Gray,
+                pending: Color::Rgb(0, 80, 160),
+                info: Color::Rgb(0, 110, 0),
+                error: Color::Rgb(180, 0, 0),
+                warning: Color::Rgb(160, 90, 0),
+                muted: Color::Gray,
+            },
+            ThemeName::Colorblind => Self {
+                running: Color::Rgb(0, 114, 178),
+                paused: Color::Rgb(230, 159, 0),
+                failed: Color::Rgb(213, 94, 0),
+                stopped: Color::Gray,
+                pending: Color::Rgb(86, 180, 233),
+                info: Color::Rgb(0, 114, 178),
+                error: Color::Rgb(213, 94, 0),
+                warning: Color::Rgb(230, 159, 0),
+                muted: Color::Gray,
+            },
+        }
+    }
+
+    pub fn state(&self, state: &str) -> Style {
+        let color = match state {
+            "Running" => self.running,
+            "Paused" => self.paused,
+            "Crashed" | "Failed" => self.failed,
+            "Stopped" => self.stopped,
+            "Creating" | "Created" | "PullingImage" => self.pending,
+            _ => return Style::new(),
+        };
+        Style::new().fg(color)
+    }
+
+    pub fn highlight(&self) -> Style {
+        Style::new().add_modifier(Modifier::REVERSED)
+    }
+}
@@ -0,0 +1,552 @@
+// SPDX-FileCopyrightText: 2023 SAP SE or an SAP affiliate company and IronCore contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::alerts::Severity;
+use crate::app::{
+    container_exit_status, container_state_name, describe, vm_state_name, App, Latency, Mode,
+    ToastLevel, View,
+};
+use crate::console::ConsoleState;
+use crate::theme::Theme;
+use feos_proto::vm_service::VmState;
+use ratatui::layout::{Constraint, Flex, Layout, Rect};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{
+    Block, Clear, Gauge, List, ListItem, Paragraph, Row, Sparkline, Table, Tabs, Wrap,
+};
+use ratatui::Frame;
+
+pub fn draw(frame: &mut Frame, app: &mut App) {
+    let show_filter = matches!(app.mode, Mode::Search | Mode::Command(_)) || !app.filter.is_empty();
+    let [header, body, filter, footer] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Min(0),
+        Constraint::Length(u16::from(show_filter)),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    draw_header(frame, app, header);
+    app.areas.body = body;
+    app.areas.console = None;
+    let body = match &app.console {
+        Some(console) => {
+            let [table, pane] = Layout::vertical([
+                Constraint::Percentage(100 - app.console_percent),
+                Constraint::Percentage(app.console_percent),
+            ])
+            .areas(body);
+            draw_console(frame, &app.theme, console, pane);
+            app.areas.console = Some(pane);
+            table
+        }
+        None => body,
+    };
+    app.areas.table = body;
+    match app.view {
+        View::Vms => draw_vms(frame, app, body),
+        View::Containers => draw_containers(frame, app, body),
+        View::Dashboard => draw_dashboard(frame, app, body),
+    }
+    if show_filter {
+        draw_filter(frame, app, filter);
+    }
+    draw_help(frame, app, footer);
+
+    if app.mode == Mode::Alerts {
+        draw_alerts(frame, app, body);
+    }
+    if app.mode == Mode::Hosts {
+        draw_hosts(frame, app);
+    }
+    if let Mode::Confirm(actions) = &app.mode {
+        draw_confirm(frame, &app.theme, &describe(actions));
+    }
+    draw_toasts(frame, app);
+}
+
+fn draw_header(frame: &mut Frame, app: &mut App, area: Rect) {
+    let status = status_line(app);
+    let [tabs_area, endpoint_area] = Layout::horizontal([
+        Constraint::Min(0),
+        Constraint::Length(status.width() as u16 + 1),
+    ])
+    .areas(area);
+    let selected = View::ALL.iter().position(|v| *v == app.view).unwrap_or(0);
+    let titles: Vec<String> = View::ALL
+        .iter()
+        .enumerate()
+        .map(|(i, view)| format!("{} {}", i + 1, view.title()))
+        .collect();
+
+    // Tabs pads each title with one space on both sides and separates them with
+    // a one-column divider.
+    app.areas.tabs.clear();
+    let mut x = tabs_area.x;
+    for (view, title) in View::ALL.iter().zip(&titles) {
+        let width = title.chars().count() as u16 + 2;
+        let tab = Rect::new(x, tabs_area.y, width, 1).intersection(tabs_area);
+        app.areas.tabs.push((*view, tab));
+        x = x.saturating_add(width + 1);
+    }
+
+    let tabs = Tabs::new(titles)
+        .select(selected)
+        .highlight_style(Style::new().bold().reversed());
+    frame.render_widget(tabs, tabs_area);
+    frame.render_widget(Paragraph::new(status), endpoint_area);
+
+    if let Some(severity) = app.alerts.highest_severity() {
+        let badge = format!(" {} alerts ", app.alerts.items.len());
+        let width = badge.len() as u16;
+        let area = Rect::new(endpoint_area.x.saturating_sub(width + 1), area.y, width, 1)
+            .intersection(tabs_area);
+        let color = severity_color(&app.theme, severity);
+        frame.render_widget(
+            Paragraph::new(badge).style(Style::new().fg(color).reversed()),
+            area,
+        );
+    }
+}
+
+/// Context name, endpoint and the latency of the last poll.
+fn status_line(app: &App) -> Line<'static> {
+    let latency = match app.latency {
+        Latency::Unknown => Span::raw("connecting").fg(app.theme.muted),
+        Latency::Measured(latency) => Span::raw(format!("{} ms", latency.as_millis())),
+        Latency::Unreachable => Span::raw("unreachable").fg(app.theme.error),
+    };
+    let scheme = if app.target.tls.is_some() {
+        "tls"
+    } else {
+        "plain"
+    };
+    Line::from(vec![
+        Span::raw(format!("{} ", app.target.name)).bold(),
+        Span::raw(format!("{} ({scheme}) ", app.target.address)).fg(app.theme.muted),
+        latency,
+    ])
+}
+
+fn draw_hosts(frame: &mut Frame, app: &mut App) {
+    let width = app
+        .contexts
+        .iter()
+        .map(|ctx| ctx.name.len() + ctx.address.len() + 8)
+        .max()
+        .unwrap_or(0)
+        .clamp(40, 100) as u16;
+    let height = app.contexts.len() as u16 + 2;
+    let area = centered(frame.area(), width, height).intersection(frame.area());
+    let items: Vec<ListItem> = app
+        .contexts
+        .iter()
+        .map(|ctx| {
+            let marker = if ctx.name == app.target.name {
+                "* "
+            } else {
+                "  "
+            };
+            ListItem::new(Line::from(vec![
+                Span::raw(format!("{marker}{} ", ctx.name)),
+                Span::raw(ctx.address.clone()).fg(app.theme.muted),
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(Block::bordered().title(" Connect to "))
+        .highlight_style(app.theme.highlight());
+    frame.render_widget(Clear, area);
+    frame.render_stateful_widget(list, area, &mut app.host_picker);
+}
+
+fn severity_color(theme: &Theme, severity: Severity) -> Color {
+    match severity {
+        Severity::Warning => theme.warning,
+        Severity::Error => theme.error,
+    }
+}
+
+fn draw_alerts(frame: &mut Frame, app: &mut App, area: Rect) {
+    let width = (area.width * 3 / 5).max(40).min(area.width);
+    let area = Rect::new(area.right() - width, area.y, width, area.height);
+    let items: Vec<ListItem> = app
+        .alerts
+        .items
+        .iter()
+        .map(|alert| {
+            let label = match alert.severity {
+                Severity::Warning => "WARN ",
+                Severity::Error => "ERROR",
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(
+                    label,
+                    Style::new().fg(severity_color(&app.theme, alert.severity)),
+                ),
+                Span::raw(format!(" {} ", alert.time.format("%H:%M:%S"))),
+                Span::raw(alert.message.clone()),
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(Block::bordered().title(format!(" Alerts ({}) ", app.alerts.items.len())))
+        .highlight_style(app.theme.highlight());
+    frame.render_widget(Clear, area);
+    frame.render_stateful_widget(list, area, &mut app.alerts.state);
+}
+
+fn draw_vms(frame: &mut Frame, app: &mut App, area: Rect) {
+    let vms = app.visible_vms();
+    let title = table_title("VMs", vms.len(), app.vms.len(), app.marked_vms.len());
+    let rows = vms.into_iter().map(|vm| {
+        let marked = app.marked_vms.contains(&vm.vm_id);
+        let state = vm_state_name(vm.state);
+        let (vcpus, memory, image) = match &vm.config {
+            Some(config) => (
+                config
+                    .cpus
+                    .as_ref()
+                    .map(|c| c.boot_vcpus.to_string())
+                    .unwrap_or_default(),
+                config
+                    .memory
+                    .as_ref()
+                    .map(|m| format!("{} MiB", m.size_mib + m.hotplugged_size_mib))
+                    .unwrap_or_default(),
+                config.image_ref.clone(),
+            ),
+            None => Default::default(),
+        };
+        let (cpu, used) = match app.vm_metrics.get(&vm.vm_id) {
+            Some(metrics) => (
+                metrics
+                    .cpu_percent
+                    .map(|cpu| format!("{cpu:.0}%"))
+                    .unwrap_or_default(),
+                format!("{} MiB", metrics.memory_rss_bytes >> 20),
+            ),
+            None => Default::default(),
+        };
+        Row::new(vec![
+            mark_cell(&app.theme, marked),
+            Span::raw(vm.vm_id.clone()),
+            Span::styled(state, app.theme.state(state)),
+            Span::raw(vcpus),
+            Span::raw(memory),
+            Span::raw(cpu),
+            Span::raw(used),
+            Span::raw(image),
+        ])
+    });
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(1),
+            Constraint::Length(36),
+            Constraint::Length(10),
+            Constraint::Length(6),
+            Constraint::Length(12),
+            Constraint::Length(6),
+            Constraint::Length(12),
+            Constraint::Min(10),
+        ],
+    )
+    .header(Row::new(["", "ID", "STATE", "VCPUS", "MEMORY", "CPU", "USED", "IMAGE"]).bold())
+    .block(Block::bordered().title(title))
+    .row_highlight_style(app.theme.highlight());
+    frame.render_stateful_widget(table, area, &mut app.vm_table);
+}
+
+fn draw_containers(frame: &mut Frame, app: &mut App, area: Rect) {
+    let containers = app.visible_containers();
+    let title = table_title(
+        "Containers",
+        containers.len(),
+        app.containers.len(),
+        app.marked_containers.len(),
+    );
+    let rows = containers.into_iter().map(|container| {
+        let marked = app.marked_containers.contains(&container.container_id);
+        let state = container_state_name(container.state);
+        let (image, command) = match &container.config {
+            Some(config) => (config.image_ref.clone(), config.command.join(" ")),
+            None => Default::default(),
+        };
+        Row::new(vec![
+            mark_cell(&app.theme, marked),
+            Span::raw(container.container_id.clone()),
+            Span::styled(state, app.theme.state(state)),
+            Span::raw(container_exit_status(container)),
+            Span::raw(image),
+            Span::raw(command),
+        ])
+    });
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(1),
+            Constraint::Length(36),
+            Constraint::Length(12),
+            Constraint::Length(10),
+            Constraint::Percentage(40),
+            Constraint::Min(10),
+        ],
+    )
+    .header(Row::new(["", "ID", "STATE", "EXIT", "IMAGE", "COMMAND"]).bold())
+    .block(Block::bordered().title(title))
+    .row_highlight_style(app.theme.highlight());
+    frame.render_stateful_widget(table, area, &mut app.container_table);
+}
+
+fn draw_dashboard(frame: &mut Frame, app: &App, area: Rect) {
+    let [cpu_area, memory_area, workloads_area] = Layout::vertical([
+        Constraint::Length(7),
+        Constraint::Length(7),
+        Constraint::Min(0),
+    ])
+    .areas(area);
+
+    let host = &app.host;
+    let cpu_title = match host.cpu.back() {
+        Some(cpu) => format!(" CPU {cpu}% "),
+        None => " CPU ".to_string(),
+    };
+    let memory_title = format!(
+        " Memory {} / {} MiB ",
+        host.mem_used_kb / 1024,
+        host.mem_total_kb / 1024
+    );
+    for (title, data, color, area) in [
+        (cpu_title, &host.cpu, app.theme.running, cpu_area),
+        (memory_title, &host.memory, app.theme.pending, memory_area),
+    ] {
+        let mut block = Block::bordered().title(title);
+        if let Some(error) = &app.host_error {
+            block = block.title_bottom(Line::from(format!(" {error} ")).fg(app.theme.error));
+        }
+        let sparkline = Sparkline::default()
+            .block(block)
+            .data(data)
+            .max(100)
+            .style(Style::new().fg(color));
+        frame.render_widget(sparkline, area);
+    }
+
+    draw_workload_gauges(frame, app, workloads_area);
+}
+
+/// Memory each running or paused VM uses relative to the host's total memory,
+/// as the resident memory of its hypervisor.
+fn draw_workload_gauges(frame: &mut Frame, app: &App, area: Rect) {
+    let block = Block::bordered().title(" VM memory usage ");
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let host_total_mib = app.host.mem_total_kb / 1024;
+    let vms: Vec<_> = app
+        .vms
+        .iter()
+        .filter(|vm| vm.state == VmState::Running as i32 || vm.state == VmState::Paused as i32)
+        .filter_map(|vm| {
+            let metrics = app.vm_metrics.get(&vm.vm_id)?;
+            Some((vm.vm_id.as_str(), metrics.memory_rss_bytes >> 20))
+        })
+        .take(inner.height as usize)
+        .collect();
+    if vms.is_empty() || host_total_mib == 0 {
+        frame.render_widget(Paragraph::new("No running VMs").fg(app.theme.muted), inner);
+        return;
+    }
+
+    let rows = Layout::vertical(vec![Constraint::Length(1); vms.len()]).split(inner);
+    for ((vm_id, size_mib), row) in vms.into_iter().zip(rows.iter()) {
+        let ratio = (size_mib as f64 / host_total_mib as f64).min(1.0);
+        let gauge = Gauge::default()
+            .ratio(ratio)
+            .label(format!("{vm_id}  {size_mib} MiB ({:.0}%)", ratio * 100.0))
+            .gauge_style(Style::new().fg(app.theme.running));
+        frame.render_widget(gauge, *row);
+    }
+}
+
+fn table_title(name: &str, shown: usize, total: usize, marked: usize) -> String {
+    let mut title = if shown == total {
+        format!(" {name} ({total}) ")
+    } else {
+        format!(" {name} ({shown}/{total}) ")
+    };
+    if marked > 0 {
+        title.push_str(&format!("[{marked} marked] "));
+    }
+    title
+}
+
+fn mark_cell(theme: &Theme, marked: bool) -> Span<'static> {
+    if marked {
+        Span::styled("●", Style::new().fg(theme.info))
+    } else {
+        Span::raw(" ")
+    }
+}
+
+fn draw_filter(frame: &mut Frame, app: &App, area: Rect) {
+    let (prompt, text) = match &app.mode {
+        Mode::Command(input) => (":", input.as_str()),
+        _ => ("/", app.filter.as_str()),
+    };
+    let mut spans = vec![Span::styled(prompt, Style::new().bold()), Span::raw(text)];
+    if matches!(app.mode, Mode::Search | Mode::Command(_)) {
+        spans.push(Span::styled(" ", Style::new().reversed()));
+    }
+    frame.render_widget(Line::from(spans), area);
+}
+
+fn draw_console(frame: &mut Frame, theme: &Theme, console: &ConsoleState, area: Rect) {
+    let range = console.visible_range(area.height.saturating_sub(2) as usize);
+    let text: Vec<Line> = range
+        .clone()
+        .zip(console.lines().range(range))
+        .map(|(index, line)| {
+            let line = Line::raw(line.as_str());
+            if console.selected == Some(index) {
+                line.style(theme.highlight())
+            } else {
+                line
+            }
+        })
+        .collect();
+
+    let mut title = format!(" Console: {} ", console.vm_id);
+    if console.scroll > 0 {
+        title.push_str(&format!("[-{}] ", console.scroll));
+    }
+    let mut block = Block::bordered().title(title);
+    if let Some(reason) = &console.closed {
+        block = block
+            .title_bottom(Line::from(format!(" closed: {reason} ")).fg(theme.error))
+            .border_style(Style::new().fg(theme.muted));
+    }
+    frame.render_widget(Paragraph::new(text).block(block), area);
+}
+
+fn draw_help(frame: &mut Frame, app: &App, area: Rect) {
+    let keys = app.config.keys;
+    let bindings: Vec<(String, &str)> = if app.console.is_some() {
+        vec![
+            ("ctrl+]".to_string(), "detach"),
+            ("pgup/pgdn".to_string(), "scroll"),
+            ("f2".to_string(), "save shown"),
+            ("f3".to_string(), "save all"),
+            ("f4".to_string(), "copy line"),
+            ("g/G".to_string(), "top/bottom"),
+        ]
+    } else {
+        match (&app.mode, app.view) {
+            (Mode::Confirm(_), _) => {
+                vec![("y".to_string(), "confirm"), ("n".to_string(), "cancel")]
+            }
+            (Mode::Search, _) => vec![("enter".to_string(), "apply"), ("esc".to_string(), "clear")],
+            (Mode::Command(_), _) => {
+                vec![("enter".to_string(), "run"), ("esc".to_string(), "cancel")]
+            }
+            (Mode::Alerts, _) => vec![
+                ("enter".to_string(), "jump to resource"),
+                ("x".to_string(), "dismiss"),
+                ("X".to_string(), "dismiss all"),
+                ("esc".to_string(), "close"),
+            ],
+            (Mode::Hosts, _) => vec![
+                ("enter".to_string(), "connect"),
+                ("esc".to_string(), "cancel"),
+            ],
+            (Mode::Normal, View::Vms) => vec![
+                (keys.start.to_string(), "start"),
+                (keys.stop.to_string(), "stop"),
+                (keys.pause.to_string(), "pause/resume"),
+                (keys.restart.to_string(), "restart"),
+                (keys.delete.to_string(), "delete"),
+                (keys.console.to_string(), "console"),
+                ("space".to_string(), "mark"),
+                (keys.filter.to_string(), "filter"),
+                (keys.alerts.to_string(), "alerts"),
+                ("tab".to_string(), "switch view"),
+                (keys.quit.to_string(), "quit"),
+            ],
+            (Mode::Normal, View::Containers) => vec![
+                (keys.start.to_string(), "start"),
+                (keys.stop.to_string(), "stop"),
+                (keys.delete.to_string(), "delete"),
+                ("space".to_string(), "mark"),
+                (keys.filter.to_string(), "filter"),
+                (keys.alerts.to_string(), "alerts"),
+                ("tab".to_string(), "switch view"),
+                (keys.quit.to_string(), "quit"),
+            ],
+            (Mode::Normal, View::Dashboard) => vec![
+                (keys.alerts.to_string(), "alerts"),
+                (keys.hosts.to_string(), "hosts"),
+                ("tab".to_string(), "switch view"),
+                (keys.reload.to_string(), "reload config"),
+                (keys.quit.to_string(), "quit"),
+            ],
+        }
+    };
+    frame.render_widget(Line::from(help_spans(&bindings)), area);
+}
+
+fn help_spans(keys: &[(String, &str)]) -> Vec<Span<'static>> {
+    keys.iter()
+        .flat_map(|(key, label)| {
+            [
+                Span::styled(format!(" {key} "), Style::new().reversed()),
+                Span::raw(format!(" {label}  ")),
+            ]
+        })
+        .collect()
+}
+
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let [area] = Layout::horizontal([Constraint::Length(width)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [area] = Layout::vertical([Constraint::Length(height)])
+        .flex(Flex::Center)
+        .areas(area);
+    area
+}
+
+fn draw_confirm(frame: &mut Frame, theme: &Theme, description: &str) {
+    let area = centered(frame.area(), 60, 5);
+    let dialog = Paragraph::new(vec![
+        Line::from(format!("Really {description}?")),
+        Line::from(""),
+        Line::from("[y] confirm   [n] cancel").fg(theme.muted),
+    ])
+    .wrap(Wrap { trim: true })
+    .block(Block::bordered().title(" Confirm ").fg(theme.warning));
+    frame.render_widget(Clear, area);
+    frame.render_widget(dialog, area);
+}
+
+fn draw_toasts(frame: &mut Frame, app: &App) {
+    let screen = frame.area();
+    let width = screen.width.min(60);
+    let mut bottom = screen.height.saturating_sub(1);
+    for toast in app.toasts.iter().rev() {
+        if bottom < 3 {
+            break;
+        }
+        let area = Rect::new(screen.width - width, bottom - 3, width, 3);
+        let color = match toast.level {
+            ToastLevel::Info => app.theme.info,
+            ToastLevel::Error => app.theme.error,
+        };
+        let widget = Paragraph::new(toast.message.as_str())
+            .block(Block::bordered().border_style(Style::new().fg(color)));
+        frame.render_widget(Clear, area);
+        frame.render_widget(widget, area);
+        bottom -= 3;
+    }
+}